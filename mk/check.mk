@@ -26,6 +26,21 @@ reftest: $(S)src/reftest/reftest.rs servo
 contenttest: $(S)src/contenttest/contenttest.rs servo
 	$(RUSTC) $(RFLAGS_servo) -o $@ $< -L .
 
+layouttest: $(S)src/layouttest/layouttest.rs servo
+	$(RUSTC) $(RFLAGS_servo) -o $@ $< -L .
+
+compattest: $(S)src/testing/compat.rs servo
+	$(RUSTC) $(RFLAGS_servo) -o $@ $< -L .
+
+fuzz-cow-scope: $(S)src/fuzz/cow_scope.rs $(S)src/servo/dom/cow.rs $(S)src/servo/dom/arena.rs
+	$(RUSTC) $(RFLAGS_servo) -o $@ $<
+
+fuzz-html-parser: $(S)src/fuzz/html_parser.rs servo
+	$(RUSTC) $(RFLAGS_servo) -o $@ $< -L .
+
+fuzz-css-selector: $(S)src/fuzz/css_selector.rs servo
+	$(RUSTC) $(RFLAGS_servo) -o $@ $< -L .
+
 .PHONY: check $(DEPS_CHECK)
 
 check: $(DEPS_CHECK) check-servo
@@ -38,3 +53,27 @@ check-ref: reftest
 
 check-content: contenttest
 	./contenttest --source-dir=$(S)/src/test/content $(TESTNAME)
+
+# Needs a servo built with --enable-debug, for window.__servoDebug
+check-layout: layouttest
+	./layouttest --source-dir=$(S)/src/test/layout $(TESTNAME)
+
+# WPT_DIR is a web-platform-tests checkout. Its dom/ and resources/ are
+# copied into the build directory with our testharnessreport.js, so a
+# wptserve given as WPT_URL has to serve that copy. With COMPAT_BASELINE set
+# to a previous compat-report.json this fails if more than 5 tests regressed
+COMPAT_WPT_DIR = $(B)src/test/compat/wpt
+
+check-compat: compattest servo
+	rm -rf $(COMPAT_WPT_DIR)
+	mkdir -p $(COMPAT_WPT_DIR)
+	cp -R $(WPT_DIR)/dom $(WPT_DIR)/resources $(COMPAT_WPT_DIR)/
+	cp $(S)src/testing/testharnessreport.js $(COMPAT_WPT_DIR)/resources/testharnessreport.js
+	./compattest --wpt-dir=$(COMPAT_WPT_DIR) --servo=$(B)servo --report=compat-report.json \
+		$(if $(WPT_URL),--wpt-url=$(WPT_URL)) \
+		$(if $(COMPAT_BASELINE),--baseline=$(COMPAT_BASELINE)) $(TESTNAME)
+
+check-fuzz: fuzz-cow-scope fuzz-html-parser fuzz-css-selector
+	./fuzz-cow-scope
+	./fuzz-html-parser
+	./fuzz-css-selector
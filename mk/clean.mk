@@ -17,4 +17,4 @@ $(eval $(call DEF_SUBMODULE_CLEAN_RULES,$(submodule))))
 clean: $(DEPS_CLEAN) clean-servo
 
 clean-servo:
-	rm -f servo servo-test
+	rm -f servo servo-test compattest compat-report.json fuzz-cow-scope fuzz-html-parser fuzz-css-selector
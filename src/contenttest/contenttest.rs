@@ -52,15 +52,45 @@ fn find_tests(config: Config) -> ~[TestDesc] {
 fn make_test(config: Config, file: ~str) -> TestDesc {
     {
         name: file,
+        ignore: needs_missing_feature(file),
         testfn: fn~() { run_test(config, file) },
-        ignore: false,
         should_fail: false
     }
 }
 
+// Whether `file` tests something servo was built without. contenttest is
+// built with servo's cfgs, so it knows which those are
+fn needs_missing_feature(file: &str) -> bool {
+    (!weak_refs() && file.ends_with("test_weakref.html")) ||
+        (!sqlite() && (file.ends_with("test_indexeddb.html") ||
+                       file.ends_with("test_cache_storage.html")))
+}
+
+#[cfg(weak_refs)]
+fn weak_refs() -> bool { true }
+
+#[cfg(not(weak_refs))]
+fn weak_refs() -> bool { false }
+
+#[cfg(sqlite)]
+fn sqlite() -> bool { true }
+
+#[cfg(not(sqlite))]
+fn sqlite() -> bool { false }
+
+// The flags `file` needs besides the ones every test gets
+fn extra_flags(file: &str) -> ~[~str] {
+    if file.ends_with("test_notification.html") {
+        ~[~"--permissions", ~"grant", ~"--enable-notification-debug"]
+    } else {
+        ~[]
+    }
+}
+
 fn run_test(config: Config, file: ~str) {
     let infile = ~"file://" + os::make_absolute(&Path(file)).to_str();
-    let res = run::program_output("./servo", ~[infile]);
+    let args = ~[~"--expose-gc"] + extra_flags(file) + ~[infile];
+    let res = run::program_output("./servo", args);
     io::print(res.out);
     do str::split_char_each(res.out, '\n') |line| {
         if line.contains("TEST-UNEXPECTED-FAIL") {
@@ -0,0 +1,179 @@
+/*!
+A fuzzer for the copy-on-write DOM scope in `servo/dom/cow.rs`.
+
+Each input is a string of bytes that is decoded into a sequence of
+scope operations (`fork`, `write`, `join`, `read`, `handle`, `free`).
+The operations run against a `Scope<u64, u64>` and a simple model of
+what the reader and writer should see, and the scope is checked after
+every step:
+
+ * the writer always sees the latest writes;
+ * the reader sees the values from when it was forked until it is joined;
+ * the dirty chain is acyclic and only holds copied handles.
+
+With no arguments, random inputs are generated; otherwise each argument
+is a file to replay, e.g. a crashing input saved from an earlier run.
+
+    ./fuzz-cow-scope [--iterations=N] [FILE...]
+*/
+
+extern mod std;
+
+use dvec::DVec;
+use std::getopts::{getopts, optopt, opt_maybe_str, fail_str};
+
+#[path = "../servo/dom/cow.rs"]
+mod cow;
+
+// cow.rs takes its arenas from `dom::arena`, as in servo
+#[path = "../servo/dom"]
+mod dom {
+    pub mod arena;
+}
+
+use cow::{Handle, Scope};
+
+const DEFAULT_ITERATIONS: uint = 1000000;
+const MAX_INPUT_LEN: uint = 256;
+
+type TestScope = Scope<u64, u64>;
+
+// What the scope should contain, per live handle
+struct Model {
+    handle: Handle<u64, u64>,
+    mut writer: u64,
+    mut reader: u64,
+}
+
+fn main() {
+    let args = os::args().tail();
+    let matches = match getopts(args, ~[optopt(~"iterations")]) {
+      Ok(m) => m,
+      Err(f) => fail fail_str(f)
+    };
+
+    if matches.free.is_not_empty() {
+        for matches.free.each |file| {
+            match io::read_whole_file(&Path(*file)) {
+                Ok(move input) => run_input(input),
+                Err(move e) => fail fmt!("couldn't read %s: %s", *file, e)
+            }
+        }
+        return;
+    }
+
+    let iterations = match opt_maybe_str(matches, ~"iterations") {
+        Some(move n) => uint::from_str(n).expect(~"--iterations must be a number"),
+        None => DEFAULT_ITERATIONS
+    };
+
+    let rng = rand::Rng();
+    for uint::range(0, iterations) |i| {
+        let len = rng.gen_uint_range(1, MAX_INPUT_LEN);
+        let input = rng.gen_bytes(len);
+        // Log the input first, so a failure can be replayed from the log
+        debug!("iteration %u: %?", i, input);
+        run_input(input);
+    }
+    io::println(fmt!("fuzz-cow-scope: %u inputs ok", iterations));
+}
+
+fn run_input(input: &[u8]) unsafe {
+    let scope: TestScope = Scope();
+    let models: DVec<@Model> = DVec();
+    let mut pos = 0u;
+
+    // reads the next input byte, or 0 once the input is used up
+    let next = || {
+        let b = if pos < input.len() { input[pos] } else { 0u8 };
+        pos += 1;
+        b
+    };
+
+    while pos < input.len() {
+        match next() % 6u8 {
+            0u8 => { // fork
+                if !scope.is_reader_forked() {
+                    scope.reader_forked();
+                    for models.each |m| { m.reader = m.writer; }
+                }
+            }
+            1u8 => { // write
+                if models.len() > 0 {
+                    let m = models[next() as uint % models.len()];
+                    let value = m.writer + (next() as u64) + 1u64;
+                    do scope.write(&m.handle) |v| {
+                        // Scope<u64> has no mutable fields to write through,
+                        // so poke the writer's copy directly
+                        let p: *mut u64 = cast::transmute(v);
+                        *p = value;
+                    }
+                    m.writer = value;
+                }
+            }
+            2u8 => { // join
+                if scope.is_reader_forked() {
+                    scope.reader_joined();
+                    for models.each |m| { m.reader = m.writer; }
+                }
+            }
+            3u8 => { // read
+                if models.len() > 0 {
+                    let m = models[next() as uint % models.len()];
+                    assert scope.read(&m.handle, |v| *v) == m.writer;
+                    assert m.handle.read(|v| *v) == m.reader;
+                }
+            }
+            4u8 => { // handle
+                let value = next() as u64;
+                let h = scope.handle(&value);
+                models.push(@Model { handle: h, writer: value, reader: value });
+            }
+            _ => { // free
+                // Only allowed while no reader is active
+                if models.len() > 0 && !scope.is_reader_forked() {
+                    let i = next() as uint % models.len();
+                    let m = models[i];
+                    let arc = scope.upgrade_to_arc(m.handle);
+                    assert *std::arc::get(&arc) == m.writer;
+                    let rest = do vec::filter(models.get()) |other| { other.handle != m.handle };
+                    models.set(move rest);
+                }
+            }
+        }
+
+        check_invariants(scope, &models);
+    }
+
+    if scope.is_reader_forked() {
+        scope.reader_joined();
+        check_invariants(scope, &models);
+    }
+}
+
+fn check_invariants(scope: TestScope, models: &DVec<@Model>) unsafe {
+    // Every live handle is owned by the scope
+    assert scope.d.free_list.len() == models.len();
+
+    // The dirty chain can't be longer than the number of handles; if it
+    // is, it loops back on itself
+    let mut h = scope.d.first_dirty;
+    let mut dirty = 0u;
+    while h.is_not_null() {
+        dirty += 1;
+        assert dirty <= models.len();
+        assert scope.is_reader_forked();
+        assert ptr::const_offset(h.read_ptr(), 0) != ptr::const_offset(h.write_ptr(), 0);
+        h = h.next_dirty();
+    }
+
+    for models.each |m| {
+        if !scope.is_reader_forked() {
+            assert m.reader == m.writer;
+            assert ptr::const_offset(m.handle.read_ptr(), 0) ==
+                ptr::const_offset(m.handle.write_ptr(), 0);
+        }
+        assert scope.read(&m.handle, |v| *v) == m.writer;
+        assert m.handle.read(|v| *v) == m.reader;
+    }
+}
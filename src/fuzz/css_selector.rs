@@ -0,0 +1,166 @@
+/*!
+A fuzzer for CSS selector parsing and matching (`servo/css/matching.rs`).
+
+Each iteration makes a random selector string, parses it as the selector
+of a one-rule stylesheet, and runs `query_selector_all` for each parsed
+selector against a random DOM tree. It checks that:
+
+ * parsing and matching never fail, however malformed the selector;
+ * the results are nodes of the tree, in document order, with no repeats;
+ * matching is deterministic: a second tree built from the same seed
+   gives the same results.
+
+    ./fuzz-css-selector [--iterations=N]
+*/
+
+extern mod std;
+extern mod servo;
+extern mod newcss (name = "css");
+
+use std::cell::Cell;
+use std::getopts::{getopts, optopt, opt_maybe_str, fail_str};
+use std::net::url;
+use newcss::parser::parse_stylesheet;
+use newcss::util::{DataStream, DataStreamFactory};
+use newcss::values::Selector;
+use servo::css::matching::MatchingMethods;
+use servo::dom::element::{Attr, ElementData, HTMLDivElement};
+use servo::dom::node::{Element, Node, NodeScope, NodeScopeExtensions, Text};
+use servo::util::tree::WriteMethods;
+
+const DEFAULT_ITERATIONS: uint = 500000;
+const MAX_TREE_SIZE: uint = 64;
+
+fn tags() -> ~[~str] { ~[~"div", ~"span", ~"p", ~"img", ~"a", ~"ul", ~"li"] }
+fn attr_names() -> ~[~str] { ~[~"id", ~"class", ~"lang", ~"flag"] }
+fn attr_values() -> ~[~str] { ~[~"", ~"a", ~"b", ~"a b", ~"en", ~"en-us", ~"x-y-z"] }
+
+// Bits of selector syntax to build selectors from, including some that
+// aren't valid
+fn fragments() -> ~[~str] {
+    tags() + attr_names() + attr_values() +
+    ~[~"*", ~".", ~"#", ~"[", ~"]", ~"=", ~"~=", ~"|=", ~"^=", ~"$=", ~"*=", ~"\"", ~"'",
+      ~" ", ~">", ~"+", ~"~", ~",", ~":", ~"::", ~"(", ~")", ~"first-child", ~"not",
+      ~"\\", ~"{", ~"}", ~";", ~"/*", ~"*/", ~"@media", ~"\n", ~"\x00"]
+}
+
+fn main() {
+    let args = os::args().tail();
+    let matches = match getopts(args, ~[optopt(~"iterations")]) {
+      Ok(m) => m,
+      Err(f) => fail fail_str(f)
+    };
+    let iterations = match opt_maybe_str(matches, ~"iterations") {
+        Some(move n) => uint::from_str(n).expect(~"--iterations must be a number"),
+        None => DEFAULT_ITERATIONS
+    };
+
+    let rng = rand::Rng();
+    let fragments = fragments();
+    for uint::range(0, iterations) |i| {
+        let selector = random_selector(rng, fragments);
+        let seed = rand::seed();
+        // Log the input first, so a failure can be reproduced from the log
+        debug!("iteration %u: selector %? tree seed %?", i, selector, seed);
+        run_input(move selector, seed);
+    }
+    io::println(fmt!("fuzz-css-selector: %u inputs ok", iterations));
+}
+
+fn random_selector(rng: rand::Rng, fragments: &[~str]) -> ~str {
+    let mut selector = ~"";
+    for rng.gen_uint_range(1, 16).times {
+        if rng.gen_uint_range(0, 8) == 0 {
+            str::push_char(&mut selector, rng.gen_char_from("!$%&-_0123456789é"));
+        } else {
+            selector += rng.choose(fragments);
+        }
+    }
+    move selector
+}
+
+fn run_input(selector: ~str, seed: ~[u8]) {
+    let scope = NodeScope();
+    let (root, nodes) = random_tree(scope, rand::seeded_rng(&seed));
+    let (other_root, other_nodes) = random_tree(scope, rand::seeded_rng(&seed));
+    assert nodes.len() == other_nodes.len();
+
+    for parse_selectors(move selector).each |sel| {
+        let result = positions(root.query_selector_all(*sel), nodes);
+        let other_result = positions(other_root.query_selector_all(*sel), other_nodes);
+        assert result == other_result;
+    }
+}
+
+// Parses `selector` as the selector of a stylesheet rule
+fn parse_selectors(selector: ~str) -> ~[~Selector] {
+    let css = str::to_bytes(selector + ~" { color: red }");
+    let url = url::from_str(~"fuzz://input/style.css").get();
+    let factory: DataStreamFactory = |move css| {
+        let data = Cell(copy css);
+        let stream: DataStream = || {
+            if data.is_empty() { None } else { Some(data.take()) }
+        };
+        move stream
+    };
+
+    let sheet = parse_stylesheet(move url, move factory);
+    let mut selectors = ~[];
+    for sheet.each |rule| {
+        let (rule_selectors, _) = copy **rule;
+        selectors += rule_selectors;
+    }
+    move selectors
+}
+
+// Builds a random tree and returns its root and all its nodes, in document
+// order
+fn random_tree(scope: NodeScope, rng: rand::Rng) -> (Node, ~[Node]) {
+    let size = rng.gen_uint_range(1, MAX_TREE_SIZE);
+    let root = random_element(scope, rng);
+    let mut parents = ~[root];
+    for (size - 1).times {
+        let parent = rng.choose(parents);
+        if rng.gen_uint_range(0, 5) == 0 {
+            scope.add_child(parent, scope.new_node(Text(~"text")));
+        } else {
+            let child = random_element(scope, rng);
+            scope.add_child(parent, child);
+            parents.push(child);
+        }
+    }
+
+    let mut nodes = ~[];
+    do root.traverse_preorder |node| {
+        nodes.push(node);
+    }
+    (root, move nodes)
+}
+
+fn random_element(scope: NodeScope, rng: rand::Rng) -> Node {
+    let element = ElementData(rng.choose(tags()), ~HTMLDivElement);
+    let names = attr_names();
+    for names.each |name| {
+        if rng.gen_bool() {
+            element.attrs.push(~Attr(copy *name, rng.choose(attr_values())));
+        }
+    }
+    scope.new_node(Element(move element))
+}
+
+// Maps matched nodes to their document order positions, checking that
+// they're all in the tree, in order and not repeated
+fn positions(matched: &[Node], nodes: &[Node]) -> ~[uint] {
+    let mut result = ~[];
+    let mut next = 0u;
+    for matched.each |node| {
+        match vec::position_between(nodes, next, nodes.len(), |n| n == node) {
+            Some(i) => {
+                result.push(i);
+                next = i + 1;
+            }
+            None => fail ~"matched a node that's not in the tree, or not in document order"
+        }
+    }
+    move result
+}
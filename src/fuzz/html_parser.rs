@@ -0,0 +1,210 @@
+/*!
+A fuzzer for the HTML parser in `servo/html/hubbub_html_parser.rs`.
+
+Each input is fed to `parse_html` as the page. Any stylesheets, scripts
+and images that the page links to get the same bytes, so the CSS parser
+is exercised too. After parsing, the DOM is checked:
+
+ * every child's parent pointer is the node it hangs off;
+ * the sibling links agree in both directions, and `last_child` is the
+   last of them;
+ * walking the tree visits no more nodes than were created (no cycles).
+
+The tree is then serialized back to HTML.
+
+Each input runs in its own task. If one fails, it is saved as
+`html-crash-N.html` in the current directory and fuzzing carries on.
+Out-of-memory aborts the whole process, so inputs are capped at
+`MAX_INPUT_LEN` bytes; the last input is logged at debug level before it
+runs.
+
+With no arguments, random inputs are generated; otherwise each argument
+is a file to replay.
+
+    ./fuzz-html-parser [--iterations=N] [FILE...]
+*/
+
+extern mod std;
+extern mod servo;
+
+use std::getopts::{getopts, optopt, opt_maybe_str, fail_str};
+use std::net::url;
+use servo::dom::element::ElementData;
+use servo::dom::node::{Comment, Doctype, Element, Node, NodeScope, Text};
+use servo::html::hubbub_html_parser::parse_html;
+use servo::resource::image_cache_task::{ImageCacheTask, ImageCacheTaskClient};
+use servo::resource::resource_task::{Done, Exit, LoaderTaskFactory, Payload,
+                                     create_resource_task_with_loaders};
+use servo::util::tree;
+
+const DEFAULT_ITERATIONS: uint = 100000;
+const MAX_INPUT_LEN: uint = 4096;
+
+// Bits of HTML to splice into random inputs, so the tree builder gets
+// further than it would with bytes alone
+fn fragments() -> ~[~str] {
+    ~[~"<", ~">", ~"</", ~"/>", ~"=", ~"\"", ~"'", ~" ", ~"\n", ~"&", ~"&amp;", ~"&#x",
+      ~"<!--", ~"-->", ~"<!DOCTYPE html>", ~"<![CDATA[",
+      ~"<html>", ~"<head>", ~"<body>", ~"<div>", ~"</div>", ~"<p>", ~"</p>", ~"<span>",
+      ~"<table>", ~"<tr>", ~"<td>", ~"</table>", ~"<form>", ~"<select>", ~"<option>",
+      ~"<ul>", ~"<li>", ~"<img src=", ~"<link rel=stylesheet href=", ~"<script>",
+      ~"</script>", ~"<script src=", ~"<style>", ~"</style>", ~"<title>", ~"<br>",
+      ~"loading=lazy", ~"{", ~"}", ~";", ~":", ~"color", ~"red"]
+}
+
+fn main() {
+    let args = os::args().tail();
+    let matches = match getopts(args, ~[optopt(~"iterations")]) {
+      Ok(m) => m,
+      Err(f) => fail fail_str(f)
+    };
+
+    if matches.free.is_not_empty() {
+        for matches.free.each |file| {
+            match io::read_whole_file(&Path(*file)) {
+                Ok(move input) => run_input(move input),
+                Err(move e) => fail fmt!("couldn't read %s: %s", *file, e)
+            }
+        }
+        return;
+    }
+
+    let iterations = match opt_maybe_str(matches, ~"iterations") {
+        Some(move n) => uint::from_str(n).expect(~"--iterations must be a number"),
+        None => DEFAULT_ITERATIONS
+    };
+
+    let rng = rand::Rng();
+    let fragments = fragments();
+    let mut crashes = 0u;
+    for uint::range(0, iterations) |i| {
+        let input = random_input(rng, fragments);
+        debug!("iteration %u: %?", i, input);
+
+        let result = do task::try |copy input| {
+            run_input(copy input);
+        };
+        if result.is_err() {
+            let path = Path(fmt!("html-crash-%u.html", crashes));
+            io::println(fmt!("fuzz-html-parser: input %u failed, saved as %s",
+                             i, path.to_str()));
+            match io::file_writer(&path, ~[io::Create, io::Truncate]) {
+                Ok(writer) => writer.write(input),
+                Err(move e) => io::println(fmt!("couldn't save input: %s", e))
+            }
+            crashes += 1;
+        }
+    }
+
+    io::println(fmt!("fuzz-html-parser: %u inputs, %u failures", iterations, crashes));
+    if crashes > 0 {
+        os::set_exit_status(1);
+    }
+}
+
+fn random_input(rng: rand::Rng, fragments: &[~str]) -> ~[u8] {
+    let mut input = ~[];
+    while input.len() < MAX_INPUT_LEN {
+        if rng.gen_uint_range(0, 3) == 0 {
+            input += rng.gen_bytes(rng.gen_uint_range(1, 16));
+        } else {
+            input += str::to_bytes(rng.choose(fragments));
+        }
+        if rng.gen_uint_range(0, 64) == 0 {
+            break;
+        }
+    }
+    vec::slice(input, 0, uint::min(input.len(), MAX_INPUT_LEN))
+}
+
+fn run_input(input: ~[u8]) {
+    // Every URL loads as the fuzz input
+    let loader: LoaderTaskFactory = |_url, progress_chan, move input| {
+        progress_chan.send(Payload(copy input));
+        progress_chan.send(Done(Ok(())));
+    };
+    let resource_task = create_resource_task_with_loaders(~[(~"fuzz", move loader)]);
+    let image_cache_task = ImageCacheTask(resource_task);
+    let scope = NodeScope();
+
+    let url = url::from_str(~"fuzz://input/index.html").get();
+    let result = parse_html(scope, move url, resource_task, image_cache_task.clone());
+    // Wait for the CSS and JS tasks too
+    result.style_port.recv();
+    result.js_port.recv();
+
+    check_tree(scope, result.root);
+    let html = serialize(scope, result.root);
+    debug!("serialized: %s", html);
+
+    image_cache_task.exit();
+    resource_task.send(Exit);
+}
+
+fn check_tree(scope: NodeScope, root: Node) {
+    assert tree::parent(&scope, &root).is_none();
+
+    let node_count = scope.d.free_list.len();
+    let mut visited = 0u;
+    let mut stack = ~[root];
+    while stack.is_not_empty() {
+        let node = stack.pop();
+        visited += 1;
+        assert visited <= node_count;
+
+        let mut prev = None;
+        for tree::each_child(&scope, &node) |child| {
+            assert tree::parent(&scope, child) == Some(node);
+            assert tree::prev_sibling(&scope, child) == prev;
+            prev = Some(*child);
+            stack.push(*child);
+            true
+        }
+        assert tree::last_child(&scope, &node) == prev;
+    }
+}
+
+fn serialize(scope: NodeScope, node: Node) -> ~str {
+    let mut html = ~"";
+    let mut children = ~"";
+    for tree::each_child(&scope, &node) |child| {
+        children += serialize(scope, *child);
+        true
+    }
+
+    do scope.read(&node) |n| {
+        match *n.kind {
+            Element(ref element) => {
+                html += ~"<" + element.tag_name + serialize_attrs(element) + ~">";
+                html += children;
+                html += ~"</" + element.tag_name + ~">";
+            }
+            Text(ref text) => html += escape(*text),
+            Comment(ref text) => html += ~"<!--" + *text + ~"-->",
+            Doctype(ref doctype) => html += ~"<!DOCTYPE " + doctype.name + ~">"
+        }
+    }
+    move html
+}
+
+fn serialize_attrs(element: &ElementData) -> ~str {
+    let mut attrs = ~"";
+    for element.attrs.each |attr| {
+        attrs += fmt!(" %s=\"%s\"", attr.name, escape(attr.value));
+    }
+    move attrs
+}
+
+fn escape(s: &str) -> ~str {
+    let mut escaped = ~"";
+    for str::each_char(s) |c| {
+        match c {
+            '&' => escaped += ~"&amp;",
+            '<' => escaped += ~"&lt;",
+            '>' => escaped += ~"&gt;",
+            '"' => escaped += ~"&quot;",
+            _ => str::push_char(&mut escaped, c)
+        }
+    }
+    move escaped
+}
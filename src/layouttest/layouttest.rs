@@ -0,0 +1,114 @@
+/*!
+Layout tests: each `foo.html` in the source directory prints its layout with
+`window.__servoDebug.dumpLayout()` (see `dump.js`), and the test passes if
+that matches the golden text in `foo.txt`. Servo must be built with
+`--enable-debug` for pages to have `__servoDebug`.
+*/
+
+extern mod std;
+extern mod servo;
+
+use std::test::{TestOpts, run_tests_console, TestDesc};
+use std::getopts::{getopts, reqopt, opt_str, fail_str};
+use os::list_dir_path;
+use servo::layout::debug::diff_layout_dumps;
+
+struct Config {
+    source_dir: ~str,
+    filter: Option<~str>
+}
+
+fn main() {
+    let args = os::args();
+    let config = parse_config(args);
+    let opts = test_options(config);
+    let tests = find_tests(config);
+    run_tests_console(&opts, tests);
+}
+
+fn parse_config(args: ~[~str]) -> Config {
+    let args = args.tail();
+    let opts = ~[reqopt(~"source-dir")];
+    let matches = match getopts(args, opts) {
+      Ok(m) => m,
+      Err(f) => fail fail_str(f)
+    };
+
+    Config {
+        source_dir: opt_str(matches, ~"source-dir"),
+        filter: if matches.free.is_empty() {
+            None
+        } else {
+            Some(matches.free.head())
+        }
+    }
+}
+
+fn test_options(config: Config) -> TestOpts {
+    {
+        filter: config.filter,
+        run_ignored: false,
+        logfile: None
+    }
+}
+
+fn find_tests(config: Config) -> ~[TestDesc] {
+    let all_files = list_dir_path(&Path(config.source_dir));
+    let html_files = all_files.filter( |file| file.to_str().ends_with(".html") );
+    return html_files.map(|file| make_test((*file).to_str()) );
+}
+
+fn make_test(file: ~str) -> TestDesc {
+    {
+        name: file,
+        testfn: fn~() { run_test(file) },
+        ignore: false,
+        should_fail: false
+    }
+}
+
+// The dump the page alerted: from the `ALERT: ` line up to the blank line
+// the dump's trailing newline leaves
+fn find_dump(output: &str) -> Option<~str> {
+    let mut dump = None;
+    for str::lines(output).each |line| {
+        match dump {
+            None if line.starts_with("ALERT: ") => {
+                dump = Some(line.slice(7, line.len()) + "\n");
+            }
+            None => (),
+            Some(ref mut text) => {
+                if line.is_empty() {
+                    break;
+                }
+                *text += *line + "\n";
+            }
+        }
+    }
+    move dump
+}
+
+fn run_test(file: ~str) {
+    let infile = ~"file://" + os::make_absolute(&Path(file)).to_str();
+    let golden_file = file.slice(0, file.len() - 5) + ".txt";
+    let golden = match io::read_whole_file_str(&Path(golden_file)) {
+        Ok(move golden) => move golden,
+        Err(e) => fail fmt!("unable to load %s: %s", golden_file, e)
+    };
+
+    let res = run::program_output("./servo", ~[infile]);
+    let dump = match find_dump(res.out) {
+        Some(move dump) => move dump,
+        None => {
+            io::print(res.out);
+            fail ~"the page printed no layout dump";
+        }
+    };
+    match diff_layout_dumps(golden, dump) {
+        Some(difference) => {
+            io::print(dump);
+            fail difference;
+        }
+        None => ()
+    }
+}
@@ -0,0 +1,687 @@
+/*!
+The accessibility tree that screen readers see: one `AXNode` for each
+rendered element or run of text, with the role and name assistive
+technologies announce.
+*/
+
+use core::send_map::linear::LinearMap;
+use core::to_bytes::{Cb, IterBytes};
+use dom::aria::*;
+use dom::element::*;
+use dom::html::input;
+use dom::node::{Node, NodeScope, Element, Text, Comment, Doctype};
+use util::tree;
+
+pub enum AXRole {
+    AXWebArea,
+    AXGeneric,
+    AXStaticText,
+    AXLink,
+    AXButton,
+    AXCheckBox,
+    AXRadio,
+    AXTextBox,
+    AXSpinButton,
+    AXSlider,
+    AXScrollBar,
+    AXProgressIndicator,
+    AXMeter,
+    AXComboBox,
+    AXListBox,
+    AXOption,
+    AXTree,
+    AXTreeItem,
+    AXGroup,
+    AXArticle,
+    AXImg,
+    AXHeading,
+    AXParagraph,
+    AXList,
+    AXListItem,
+    AXTable,
+    AXRowGroup,
+    AXRow,
+    AXCell,
+    AXForm,
+    AXRegion,
+    AXComplementary,
+    AXNavigation,
+    AXMain,
+    AXBanner,
+    AXContentInfo,
+    AXSeparator,
+    AXDialog,
+    AXAlert,
+    AXTab,
+    AXTabList,
+    AXTabPanel,
+    AXMenu,
+    AXMenuItem,
+    AXPresentation,
+}
+
+impl AXRole : cmp::Eq {
+    pure fn eq(&self, other: &AXRole) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &AXRole) -> bool {
+        !self.eq(other)
+    }
+}
+
+impl AXRole {
+    /// The role an element with the ARIA role `role` has.
+    static fn from_aria(role: AriaRole) -> AXRole {
+        match role {
+            RoleGeneric | RoleBlockquote | RoleCaption | RoleCode | RoleDefinition |
+            RoleDeletion | RoleEmphasis | RoleInsertion | RoleStrong | RoleSubscript |
+            RoleSuperscript | RoleTerm | RoleTime | RoleMath | RoleNote | RoleFigure |
+            RoleToolbar | RoleTooltip | RoleApplication | RoleDocument | RoleFeed |
+            RoleDirectory | RoleTreeGrid | RoleDocAbstract | RoleDocColophon | RoleDocCover |
+            RoleDocCredit | RoleDocDedication | RoleDocEpigraph | RoleDocExample |
+            RoleDocFootnote | RoleDocNotice | RoleDocPageFooter | RoleDocPageHeader |
+            RoleDocPullquote | RoleDocQna | RoleDocSubtitle | RoleDocTip |
+            RoleDocBiblioEntry | RoleDocEndnote => AXGeneric,
+            RoleLink | RoleDocBacklink | RoleDocBiblioRef | RoleDocGlossRef |
+            RoleDocNoteRef => AXLink,
+            RoleButton => AXButton,
+            RoleCheckbox | RoleSwitch | RoleMenuItemCheckbox => AXCheckBox,
+            RoleRadio | RoleMenuItemRadio => AXRadio,
+            RoleTextbox | RoleSearchbox => AXTextBox,
+            RoleSpinbutton => AXSpinButton,
+            RoleSlider => AXSlider,
+            RoleScrollbar => AXScrollBar,
+            RoleProgressbar => AXProgressIndicator,
+            RoleMeter => AXMeter,
+            RoleCombobox => AXComboBox,
+            RoleListbox => AXListBox,
+            RoleOption => AXOption,
+            RoleTree => AXTree,
+            RoleTreeItem => AXTreeItem,
+            RoleGroup | RoleRadioGroup => AXGroup,
+            RoleArticle => AXArticle,
+            RoleImg => AXImg,
+            RoleHeading => AXHeading,
+            RoleParagraph => AXParagraph,
+            RoleList | RoleDocPageList => AXList,
+            RoleListItem => AXListItem,
+            RoleTable | RoleGrid => AXTable,
+            RoleRowGroup => AXRowGroup,
+            RoleRow => AXRow,
+            RoleCell | RoleGridCell | RoleColumnHeader | RoleRowHeader => AXCell,
+            RoleForm => AXForm,
+            RoleRegion | RoleSearch | RoleLog | RoleMarquee | RoleTimer | RoleStatus |
+            RoleDocAcknowledgments | RoleDocAfterword | RoleDocAppendix |
+            RoleDocBibliography | RoleDocChapter | RoleDocConclusion | RoleDocCredits |
+            RoleDocEndnotes | RoleDocEpilogue | RoleDocErrata | RoleDocForeword |
+            RoleDocGlossary | RoleDocIndex | RoleDocIntroduction | RoleDocPart |
+            RoleDocPreface | RoleDocPrologue => AXRegion,
+            RoleComplementary => AXComplementary,
+            RoleNavigation | RoleDocToc => AXNavigation,
+            RoleMain => AXMain,
+            RoleBanner => AXBanner,
+            RoleContentInfo => AXContentInfo,
+            RoleSeparator | RoleDocPageBreak => AXSeparator,
+            RoleDialog | RoleAlertDialog => AXDialog,
+            RoleAlert => AXAlert,
+            RoleTab => AXTab,
+            RoleTabList => AXTabList,
+            RoleTabPanel => AXTabPanel,
+            RoleMenu | RoleMenubar => AXMenu,
+            RoleMenuItem => AXMenuItem,
+            RoleNone | RolePresentation => AXPresentation
+        }
+    }
+
+    /// Whether an element with this role takes its name from its contents.
+    pure fn name_from_content(&self) -> bool {
+        match *self {
+            AXLink | AXButton | AXCheckBox | AXRadio | AXOption | AXTreeItem | AXHeading |
+            AXCell | AXTab | AXMenuItem | AXStaticText => true,
+            _ => false
+        }
+    }
+}
+
+pub enum AXProperty {
+    AXLevel,
+    AXUrl,
+    AXChecked,
+    AXDisabled,
+    AXExpanded,
+    AXSelected,
+}
+
+impl AXProperty : cmp::Eq {
+    pure fn eq(&self, other: &AXProperty) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &AXProperty) -> bool {
+        !self.eq(other)
+    }
+}
+
+impl AXProperty : IterBytes {
+    pure fn iter_bytes(lsb0: bool, f: Cb) {
+        (self as uint).iter_bytes(lsb0, f);
+    }
+}
+
+pub enum AXValue {
+    AXBool(bool),
+    AXInt(int),
+    AXString(~str),
+}
+
+pub struct AXNode {
+    role: AXRole,
+    name: ~str,
+    description: ~str,
+    children: ~[AXNode],
+    properties: LinearMap<AXProperty, AXValue>,
+}
+
+/// Builds the accessibility tree for the document rooted at `root`.
+pub fn build_ax_tree(scope: &NodeScope, root: Node) -> AXNode {
+    let mut tree = AXNode {
+        role: AXWebArea,
+        name: title_of(scope, root),
+        description: ~"",
+        children: ~[],
+        properties: LinearMap(),
+    };
+    append_children(scope, root, &mut tree.children);
+    move tree
+}
+
+// Appends the AXNodes for the children of `node`. Presentational and
+// generic elements don't get a node of their own; their children are
+// hoisted into the parent.
+fn append_children(scope: &NodeScope, node: Node, out: &mut ~[AXNode]) {
+    for scope.each_child(&node) |child| {
+        match build_node(scope, *child) {
+            Some(move ax_node) => {
+                if ax_node.role == AXPresentation ||
+                   (ax_node.role == AXGeneric && ax_node.properties.is_empty()) {
+                    for vec::each_mut(ax_node.children) |grandchild| {
+                        let mut moved = None;
+                        moved <-> Some(move *grandchild);
+                        out.push(option::unwrap(move moved));
+                    }
+                } else {
+                    out.push(move ax_node);
+                }
+            }
+            None => ()
+        }
+    }
+}
+
+fn build_node(scope: &NodeScope, node: Node) -> Option<AXNode> {
+    let role = match role_of(scope, node) {
+        Some(role) => role,
+        None => return None
+    };
+
+    if role == AXStaticText {
+        let text = collapse_whitespace(text_content(scope, node));
+        if text.is_empty() {
+            return None;
+        }
+        return Some(AXNode {
+            role: AXStaticText,
+            name: move text,
+            description: ~"",
+            children: ~[],
+            properties: LinearMap(),
+        });
+    }
+
+    let mut ax_node = AXNode {
+        role: role,
+        name: compute_accessible_name(scope, &node),
+        description: ~"",
+        children: ~[],
+        properties: properties_of(scope, node),
+    };
+    // The title is the description, unless it was used as the name
+    match get_attr(scope, node, "title") {
+        Some(move title) if title != ax_node.name => ax_node.description = move title,
+        _ => ()
+    }
+    if role != AXImg {
+        append_children(scope, node, &mut ax_node.children);
+    }
+    Some(move ax_node)
+}
+
+// The role of `node`, or None if it isn't rendered or is hidden with
+// `aria-hidden="true"`. An ARIA `role` attribute overrides the element's
+// implicit role. Elements with `display: contents` have no box but are
+// still rendered, through their children, so they keep their role.
+fn role_of(scope: &NodeScope, node: Node) -> Option<AXRole> {
+    do scope.read(&node) |n| {
+        match n.kind {
+            ~Text(*) => Some(AXStaticText),
+            ~Doctype(*) | ~Comment(*) => None,
+            ~Element(ref e) => match e.kind {
+                // Never rendered, as layout's UA display rules say
+                ~HTMLHeadElement | ~HTMLScriptElement | ~HTMLStyleElement |
+                ~HTMLTitleElement | ~HTMLMetaElement | ~HTMLLinkElement => None,
+                _ if is_aria_hidden(e) => None,
+                _ => match e.get_attr("role").chain(|value| parse_aria_role(value)) {
+                    Some(role) => Some(AXRole::from_aria(role)),
+                    None => Some(implicit_role(e))
+                }
+            }
+        }
+    }
+}
+
+fn is_aria_hidden(element: &ElementData) -> bool {
+    match element.get_attr("aria-hidden") {
+        Some(ref value) => match parse_aria_property("aria-hidden", *value) {
+            Some(AriaHidden(hidden)) => hidden,
+            _ => false
+        },
+        None => false
+    }
+}
+
+/// The role an element has without an ARIA `role` attribute.
+pub fn implicit_role(element: &ElementData) -> AXRole {
+    match element.kind {
+        ~HTMLAnchorElement if element.get_attr("href").is_some() => AXLink,
+        ~HTMLAsideElement => AXComplementary,
+        ~HTMLFormElement => AXForm,
+        ~HTMLButtonElement => AXButton,
+        ~HTMLHRElement => AXSeparator,
+        ~HTMLHeadingElement(*) => AXHeading,
+        ~HTMLImageElement(*) => match element.get_attr("alt") {
+            // An empty alt marks the image as decorative
+            Some(ref alt) if alt.is_empty() => AXPresentation,
+            _ => AXImg
+        },
+        ~HTMLInputElement(*) => match element.get_attr("type").map(|t| str::to_lower(*t)) {
+            Some(~"checkbox") => AXCheckBox,
+            Some(~"radio") => AXRadio,
+            Some(~"button") | Some(~"submit") | Some(~"reset") => AXButton,
+            Some(~"hidden") => AXPresentation,
+            Some(~"range") => AXSlider,
+            Some(~"number") => AXSpinButton,
+            _ => AXTextBox
+        },
+        ~HTMLListItemElement => AXListItem,
+        ~HTMLOListElement | ~HTMLUListElement => AXList,
+        ~HTMLOptionElement => AXOption,
+        ~HTMLParagraphElement => AXParagraph,
+        ~HTMLSectionElement if element.get_attr("aria-label").is_some() ||
+                               element.get_attr("aria-labelledby").is_some() => AXRegion,
+        ~HTMLSelectElement => match element.get_attr("multiple") {
+            Some(_) => AXListBox,
+            None => AXComboBox
+        },
+        ~HTMLTableElement => AXTable,
+        ~HTMLTableBodyElement => AXRowGroup,
+        ~HTMLTableRowElement => AXRow,
+        ~HTMLTableCellElement => AXCell,
+        ~HTMLTextAreaElement => AXTextBox,
+        _ => AXGeneric
+    }
+}
+
+fn properties_of(scope: &NodeScope, node: Node) -> LinearMap<AXProperty, AXValue> {
+    let mut properties = LinearMap();
+    do scope.read(&node) |n| {
+        match n.kind {
+            ~Element(ref e) => {
+                match e.kind {
+                    ~HTMLHeadingElement(level) => {
+                        properties.insert(AXLevel, AXInt(level as int + 1));
+                    }
+                    ~HTMLAnchorElement => match e.get_attr("href") {
+                        Some(move href) => { properties.insert(AXUrl, AXString(move href)); }
+                        None => ()
+                    },
+                    ~HTMLInputElement(*) => {
+                        if e.get_attr("disabled").is_some() {
+                            properties.insert(AXDisabled, AXBool(true));
+                        }
+                        match e.get_attr("type").map(|t| str::to_lower(*t)) {
+                            Some(~"checkbox") | Some(~"radio") => {
+                                properties.insert(AXChecked, AXBool(input::checked(e)));
+                            }
+                            _ => ()
+                        }
+                    }
+                    ~HTMLOptionElement => {
+                        properties.insert(AXSelected, AXBool(e.get_attr("selected").is_some()));
+                    }
+                    _ => ()
+                }
+                // ARIA states override the native ones
+                for e.attrs.each |attr| {
+                    match parse_aria_property(attr.name, attr.value) {
+                        Some(AriaExpanded(b)) => { properties.insert(AXExpanded, AXBool(b)); }
+                        Some(AriaDisabled(b)) => { properties.insert(AXDisabled, AXBool(b)); }
+                        Some(AriaSelected(b)) => { properties.insert(AXSelected, AXBool(b)); }
+                        Some(AriaChecked(TristateMixed)) | Some(AriaPressed(TristateMixed)) => {
+                            properties.insert(AXChecked, AXString(~"mixed"));
+                        }
+                        Some(AriaChecked(t)) | Some(AriaPressed(t)) => {
+                            properties.insert(AXChecked, AXBool(t == TristateTrue));
+                        }
+                        Some(AriaLevel(level)) => {
+                            properties.insert(AXLevel, AXInt(level as int));
+                        }
+                        _ => ()
+                    }
+                }
+            }
+            _ => ()
+        }
+    }
+    move properties
+}
+
+/**
+The accessible name of `node`, following the accessible name computation:
+the elements `aria-labelledby` points at, then `aria-label`, then the
+element's own labelling attributes like `alt`, then its text if its role
+takes a name from content, then its `title`, and for a text field, its
+`placeholder` as a last resort.
+*/
+pub fn compute_accessible_name(scope: &NodeScope, node: &Node) -> ~str {
+    match get_attr(scope, *node, "aria-labelledby") {
+        Some(ref ids) => {
+            let root = root_of(scope, *node);
+            let names = do str::words(*ids).filter_map |id| {
+                find_by_id(scope, root, *id).map(|label| {
+                    collapse_whitespace(text_content(scope, *label))
+                })
+            };
+            let name = str::connect(names.filter(|name| !name.is_empty()), " ");
+            if !name.is_empty() {
+                return move name;
+            }
+        }
+        None => ()
+    }
+
+    match get_attr(scope, *node, "aria-label") {
+        Some(ref label) if !str::trim(*label).is_empty() => return str::trim(*label),
+        _ => ()
+    }
+
+    let is_image = do scope.read(node) |n| {
+        match n.kind {
+            ~Element(ref e) => match e.kind {
+                ~HTMLImageElement(*) => true,
+                _ => false
+            },
+            _ => false
+        }
+    };
+    if is_image {
+        match get_attr(scope, *node, "alt") {
+            Some(ref alt) if !alt.is_empty() => return copy *alt,
+            _ => ()
+        }
+    }
+
+    match role_of(scope, *node) {
+        Some(role) if role.name_from_content() => {
+            let text = collapse_whitespace(text_content(scope, *node));
+            if !text.is_empty() {
+                return move text;
+            }
+        }
+        _ => ()
+    }
+
+    match get_attr(scope, *node, "title") {
+        Some(move title) => return move title,
+        None => ()
+    }
+
+    let is_text_field = do scope.read(node) |n| {
+        match n.kind {
+            ~Element(ref e) => match e.kind {
+                ~HTMLTextAreaElement => true,
+                ~HTMLInputElement(*) => {
+                    let role = implicit_role(e);
+                    role == AXTextBox || role == AXSpinButton
+                }
+                _ => false
+            },
+            _ => false
+        }
+    };
+    if is_text_field {
+        match get_attr(scope, *node, "placeholder") {
+            Some(move placeholder) => return move placeholder,
+            None => ()
+        }
+    }
+    ~""
+}
+
+fn get_attr(scope: &NodeScope, node: Node, name: &str) -> Option<~str> {
+    do scope.read(&node) |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_attr(name),
+            _ => None
+        }
+    }
+}
+
+// The concatenated text of the rendered text nodes under `node`
+fn text_content(scope: &NodeScope, node: Node) -> ~str {
+    let text = do scope.read(&node) |n| {
+        match n.kind {
+            ~Text(ref s) => Some(copy *s),
+            _ => None
+        }
+    };
+    match text {
+        Some(move s) => move s,
+        None => {
+            let mut s = ~"";
+            for scope.each_child(&node) |child| {
+                if role_of(scope, *child).is_some() {
+                    s += text_content(scope, *child);
+                }
+            }
+            move s
+        }
+    }
+}
+
+fn collapse_whitespace(s: &str) -> ~str {
+    str::connect(str::words(s), " ")
+}
+
+fn root_of(scope: &NodeScope, node: Node) -> Node {
+    let mut node = node;
+    loop {
+        match tree::parent(scope, &node) {
+            Some(parent) => node = parent,
+            None => return node
+        }
+    }
+}
+
+fn find_by_id(scope: &NodeScope, node: Node, id: &str) -> Option<Node> {
+    if get_attr(scope, node, "id") == Some(id.to_str()) {
+        return Some(node);
+    }
+    for scope.each_child(&node) |child| {
+        match find_by_id(scope, *child, id) {
+            Some(found) => return Some(found),
+            None => ()
+        }
+    }
+    None
+}
+
+// The name of the document, from its <title>
+fn title_of(scope: &NodeScope, node: Node) -> ~str {
+    let is_title = do scope.read(&node) |n| {
+        match n.kind {
+            ~Element(ref e) => match e.kind {
+                ~HTMLTitleElement => true,
+                _ => false
+            },
+            _ => false
+        }
+    };
+    if is_title {
+        return collapse_whitespace(text_content_unfiltered(scope, node));
+    }
+    for scope.each_child(&node) |child| {
+        let title = title_of(scope, *child);
+        if !title.is_empty() {
+            return move title;
+        }
+    }
+    ~""
+}
+
+// Like text_content, but for elements that aren't rendered themselves
+fn text_content_unfiltered(scope: &NodeScope, node: Node) -> ~str {
+    let mut s = ~"";
+    for scope.each_child(&node) |child| {
+        s += text_content(scope, *child);
+    }
+    move s
+}
+
+#[cfg(test)]
+mod ax_tree_tests {
+    use dom::node::NodeScopeExtensions;
+
+    fn element(scope: &NodeScope, tag: ~str, kind: ~ElementKind,
+               attrs: &[(~str, ~str)]) -> Node {
+        let data = ElementData(move tag, move kind);
+        for attrs.each |attr| {
+            let (name, value) = copy *attr;
+            data.attrs.push(~Attr(move name, move value));
+        }
+        scope.new_node(Element(move data))
+    }
+
+    #[test]
+    fn test_aria_role_overrides_implicit_role() {
+        let scope = NodeScope();
+        let root = element(&scope, ~"body", ~HTMLBodyElement, ~[]);
+        let div = element(&scope, ~"div", ~HTMLDivElement, ~[(~"role", ~"bogus button")]);
+        let para = element(&scope, ~"p", ~HTMLParagraphElement, ~[(~"role", ~"presentation")]);
+        let text = scope.new_node(Text(~" Click  me "));
+        scope.add_child(root, div);
+        scope.add_child(root, para);
+        scope.add_child(div, text);
+
+        let tree = build_ax_tree(&scope, root);
+        assert tree.children.len() == 1;
+        assert tree.children[0].role == AXButton;
+        assert tree.children[0].name == ~"Click me";
+    }
+
+    #[test]
+    fn test_widget_roles_kept() {
+        let scope = NodeScope();
+        let root = element(&scope, ~"body", ~HTMLBodyElement, ~[]);
+        let roles = ~[~"progressbar", ~"slider", ~"meter", ~"spinbutton", ~"article", ~"group",
+                      ~"treeitem"];
+        for roles.each |role| {
+            let div = element(&scope, ~"div", ~HTMLDivElement, ~[(~"role", copy *role)]);
+            scope.add_child(root, div);
+        }
+        let range = element(&scope, ~"input", ~HTMLInputElement(HTMLInputData()),
+                            ~[(~"type", ~"range")]);
+        scope.add_child(root, range);
+
+        let tree = build_ax_tree(&scope, root);
+        assert tree.children.map(|child| child.role) ==
+            ~[AXProgressIndicator, AXSlider, AXMeter, AXSpinButton, AXArticle, AXGroup,
+              AXTreeItem, AXSlider];
+    }
+
+    #[test]
+    fn test_aria_hidden_hides_subtree() {
+        let scope = NodeScope();
+        let root = element(&scope, ~"body", ~HTMLBodyElement, ~[]);
+        let hidden = element(&scope, ~"div", ~HTMLDivElement, ~[(~"aria-hidden", ~"true")]);
+        let button = element(&scope, ~"div", ~HTMLDivElement, ~[(~"role", ~"button"),
+                                                                (~"aria-expanded", ~"true")]);
+        let shown = element(&scope, ~"p", ~HTMLParagraphElement, ~[(~"aria-hidden", ~"maybe")]);
+        scope.add_child(root, hidden);
+        scope.add_child(hidden, button);
+        scope.add_child(root, shown);
+
+        let tree = build_ax_tree(&scope, root);
+        assert tree.children.len() == 1;
+        assert tree.children[0].role == AXParagraph;
+
+        do scope.write(&hidden) |n| {
+            match n.kind {
+                ~Element(ref e) => e.set_attr("aria-hidden", ~"false"),
+                _ => fail
+            }
+        }
+        let tree = build_ax_tree(&scope, root);
+        assert tree.children.len() == 2;
+        assert tree.children[0].role == AXButton;
+        match tree.children[0].properties.find(&AXExpanded) {
+            Some(AXBool(true)) => (),
+            _ => fail
+        }
+    }
+
+    #[test]
+    fn test_display_contents_keeps_role() {
+        let scope = NodeScope();
+        let root = element(&scope, ~"body", ~HTMLBodyElement, ~[]);
+        let list = element(&scope, ~"ul", ~HTMLUListElement, ~[(~"style", ~"display: contents")]);
+        let item = element(&scope, ~"li", ~HTMLListItemElement, ~[]);
+        scope.add_child(root, list);
+        scope.add_child(list, item);
+        scope.add_child(item, scope.new_node(Text(~"Item")));
+
+        let tree = build_ax_tree(&scope, root);
+        assert tree.children.len() == 1;
+        assert tree.children[0].role == AXList;
+        assert tree.children[0].children[0].role == AXListItem;
+    }
+
+    #[test]
+    fn test_accessible_name_order() {
+        let scope = NodeScope();
+        let root = element(&scope, ~"body", ~HTMLBodyElement, ~[]);
+        let label = element(&scope, ~"span", ~HTMLSpanElement, ~[(~"id", ~"l")]);
+        scope.add_child(label, scope.new_node(Text(~"Labelled")));
+        let img = element(&scope, ~"img", ~HTMLImageElement(HTMLImageData()),
+                          ~[(~"alt", ~"Alt"), (~"title", ~"Title")]);
+        let labelled = element(&scope, ~"img", ~HTMLImageElement(HTMLImageData()),
+                               ~[(~"aria-labelledby", ~"l"), (~"aria-label", ~"Label")]);
+        let titled = element(&scope, ~"div", ~HTMLDivElement, ~[(~"title", ~"Title")]);
+        scope.add_child(root, label);
+        scope.add_child(root, img);
+        scope.add_child(root, labelled);
+        scope.add_child(root, titled);
+
+        assert compute_accessible_name(&scope, &img) == ~"Alt";
+        assert compute_accessible_name(&scope, &labelled) == ~"Labelled";
+        assert compute_accessible_name(&scope, &titled) == ~"Title";
+
+        let field = element(&scope, ~"input", ~HTMLInputElement(HTMLInputData()),
+                            ~[(~"placeholder", ~"Search")]);
+        let titled_field = element(&scope, ~"input", ~HTMLInputElement(HTMLInputData()),
+                                   ~[(~"placeholder", ~"Search"), (~"title", ~"Query")]);
+        let placeholder_div = element(&scope, ~"div", ~HTMLDivElement,
+                                      ~[(~"placeholder", ~"Search")]);
+        assert compute_accessible_name(&scope, &field) == ~"Search";
+        assert compute_accessible_name(&scope, &titled_field) == ~"Query";
+        assert compute_accessible_name(&scope, &placeholder_div) == ~"";
+    }
+}
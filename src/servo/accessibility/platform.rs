@@ -0,0 +1,57 @@
+/*!
+Hands the accessibility tree to the platform's accessibility API, where
+screen readers can find it.
+*/
+
+use ax_tree::AXNode;
+
+pub trait AXBridge {
+    /// Replaces the tree exposed to assistive technologies.
+    fn update(&self, tree: &AXNode);
+}
+
+/**
+Where the tree goes: the platform's bridge if there is one, or with
+`--enable-accessibility-debug`, the debug log.
+*/
+pub fn bridge(debug: bool) -> Option<@AXBridge> {
+    match platform_bridge() {
+        Some(bridge) => Some(bridge),
+        None if debug => Some(@DebugBridge as @AXBridge),
+        None => None
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn platform_bridge() -> Option<@AXBridge> {
+    //TODO: expose the tree through NSAccessibility
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn platform_bridge() -> Option<@AXBridge> {
+    //TODO: expose the tree through AT-SPI
+    None
+}
+
+/// Dumps each new tree to the debug log, with indentation.
+struct DebugBridge;
+
+impl DebugBridge : AXBridge {
+    fn update(&self, tree: &AXNode) {
+        dump_indent(tree, 0);
+    }
+}
+
+fn dump_indent(node: &AXNode, indent: uint) {
+    let mut s = ~"";
+    for uint::range(0, indent) |_i| {
+        s += ~"    ";
+    }
+    s += fmt!("%? \"%s\"", node.role, node.name);
+    debug!("%s", s);
+
+    for node.children.each |child| {
+        dump_indent(child, indent + 1);
+    }
+}
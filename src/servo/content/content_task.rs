@@ -4,24 +4,64 @@ tasks.
 */
 
 export Content, ContentTask;
-export ControlMsg, ExecuteMsg, ParseMsg, ExitMsg, Timer;
+export ControlMsg, ExecuteMsg, ParseMsg, PostMsg, ExitMsg, Timer, FireEvent, Callback,
+       SettlePromise, CollectGarbage, CollectCycles, AttachDevtools, DevtoolsCommand,
+       DetachDevtools, DeliverPerformanceEntries, StylesheetsParsed, Print, WakeUp;
 export PingMsg, PongMsg;
 export task_from_context;
 
 use core::util::replace;
 use std::arc::{ARC, clone};
 use comm::{Port, Chan, listen, select2};
-use task::{spawn, spawn_listener};
+use task::{spawn, spawn_listener, spawn_unlinked};
 use io::{read_whole_file, println};
 
 use dom::document::Document;
-use dom::node::{Node, NodeScope, define_bindings};
-use dom::event::{Event, ResizeEvent, ReflowEvent};
+use dom::node::{Node, NodeScope, Element, define_bindings};
+use dom::event::{Event, ResizeEvent, ReflowEvent, ScrollEvent, KeyEvent, PointerInputEvent,
+                 PaintedEvent};
+use dom::events::pointer_event::{PointerInput, PointerDown, PointerUp, PointerCancel};
+use dom::element::{HTMLInputElement, HTMLImageElement};
+use dom::html::input;
 use dom::window::Window;
+use dom::resize_observer::{BoxSizes, empty_box_sizes};
+use dom::bindings::resize_observer;
+use dom::bindings::node;
+use dom::bindings::element;
+use dom::bindings::notification;
+use dom::bindings::promise;
+use dom::bindings::error_reporter;
+use dom::bindings::finalization;
+use dom::bindings::module_script;
+use dom::bindings::module_script::ModuleMap;
+use dom::bindings::performance::entry_list_to_jsval;
+use dom::performance_observer::{PerformanceEntry, NavigationEntry};
+use dom::bindings::proxy;
+use dom::bindings::rooting::{NodeWrappers, RootedValues};
+use dom::bindings::pointer_event::{new_pointer_event, post_capture_event};
+use dom::bindings::utils::{new_event, new_input_event, new_wheel_event,
+                           STOP_IMMEDIATE_PROPERTY};
+use dom::bindings::event_target::target_id;
+use dom::event_target::{NodeTarget, ObjectTarget, NoPhase, dispatch_steps};
+use dom::scroll::{clamp_offset, scroll_container_for};
+use dom::service_worker::{ServiceWorkerRegistry, Registration, intercept};
+use geom::point::Point2D;
 use geom::size::Size2D;
 use layout::layout_task;
-use layout_task::{LayoutTask, BuildMsg, BuildData, AddStylesheet};
-use resource::image_cache_task::ImageCacheTask;
+use layout_task::{LayoutTask, BuildMsg, BuildData, PrintMsg, PrintData, AddStylesheet};
+use layout::print::PrintedPage;
+use layout::paint_timing::ContentfulPaint;
+use resource::image_cache_task::{ImageCacheTask, ImageCacheTaskClient};
+use opts::Opts;
+use content::cpu_throttle::{CpuThrottle, CpuTicker};
+use content::font_loader::load_web_fonts;
+use content::promise_queue::PromiseQueue;
+use event_loop::scheduler::{Scheduler, Priority, UserVisible, Normal, Background};
+use content::devtools::DevtoolsClient;
+use CpuTickerExitMsg = content::cpu_throttle::ExitMsg;
+use accessibility::ax_tree::build_ax_tree;
+use accessibility::platform::AXBridge;
+use memory::cycle_collector;
 
 use newcss::values::Stylesheet;
 
@@ -36,26 +76,102 @@ use js::rust::compartment;
 
 use resource::resource_task;
 use resource_task::{ResourceTask};
+use resource::buffer_pool::BufferPool;
 
 use std::net::url::Url;
-use url_to_str = std::net::url::to_str;
+use html::hubbub_html_parser::{HtmlParserResult, JSResult, ClassicScript, ModuleScript};
+use html::cssparse::WebFontFace;
+use resource::resource_task::TimedFetch;
+use util::url::url_to_str;
 use util::url::make_url;
 use task::{task, SingleThreaded};
 use std::cell::Cell;
-
-use js::glue::bindgen::RUST_JSVAL_TO_OBJECT;
-use js::JSVAL_NULL;
-use js::jsapi::{JSContext, JSVal};
-use js::jsapi::bindgen::{JS_CallFunctionValue, JS_GetContextPrivate};
+use std::time::precise_time_ns;
+
+use js::glue::bindgen::{RUST_JSVAL_TO_OBJECT, RUST_OBJECT_TO_JSVAL, RUST_BOOLEAN_TO_JSVAL,
+                        RUST_INT_TO_JSVAL};
+use js::{JSVAL_NULL, JSTYPE_FUNCTION};
+use js::jsapi::{JSContext, JSVal, JSBool, JSObject};
+use js::jsapi::bindgen::{JS_CallFunctionValue, JS_GetContextPrivate, JS_GetProperty,
+                         JS_SetProperty, JS_TypeOfValue, JS_MaybeGC, JS_SetOperationCallback,
+                         JS_EvaluateScript, JS_ClearPendingException};
+use libc::c_int;
+use util::tree;
 use ptr::null;
 
 pub enum ControlMsg {
     ParseMsg(Url),
+    // Loads the page a form POSTs a body, of a Content-Type, to
+    PostMsg(Url, ~[u8], ~str),
     ExecuteMsg(Url),
     Timer(~dom::window::TimerData),
+    // Fires an event ("show", "popstate", ...) at a JS object. The target
+    // and the event are given by their keys in `posted`
+    FireEvent(uint, ~str, uint),
+    // Calls a JS function with one argument, by their keys in `posted`
+    Callback(uint, uint),
+    // Settles a promise, by its key in `pending_promises`, with the outcome
+    // of work done on another task, made into JS values here
+    SettlePromise(uint, fn~(*JSContext) -> Result<JSVal, JSVal>),
+    // Sent when memory is running low
+    CollectGarbage,
+    // Sent after a GC, to break the cycles between nodes and their listeners
+    CollectCycles,
+    // A devtools client connected, and where its messages go
+    AttachDevtools(pipes::SharedChan<~str>),
+    // A CDP message from the devtools client
+    DevtoolsCommand(~str),
+    DetachDevtools,
+    // Calls back the PerformanceObservers that have entries buffered
+    DeliverPerformanceEntries,
+    // The style sheets of the page a ParseMsg is loading, by its load id,
+    // and the fonts they ask for
+    StylesheetsParsed(uint, Stylesheet, ~[WebFontFace]),
+    // Prints the page, sending the printed pages back at a resolution
+    Print(uint, pipes::Chan<~[PrintedPage]>),
+    // Sent when the soonest held back timer, due at a time in ns, may run
+    WakeUp(u64),
+    // Samples the transitions of registered custom properties for a frame
+    SampleTransitions,
     ExitMsg
 }
 
+// A message the content task has received and scheduled, but not handled
+enum Runnable {
+    ControlRunnable(ControlMsg),
+    EventRunnable(Event),
+}
+
+// Timers and callbacks from other tasks wait behind input and relayout;
+// work nobody is waiting on waits behind everything. Events and callbacks
+// posted by script go with input, so that they keep their order with it.
+fn control_msg_priority(msg: &ControlMsg) -> Priority {
+    match *msg {
+        CollectGarbage | CollectCycles | DeliverPerformanceEntries => Background,
+        FireEvent(*) | Callback(*) | WakeUp(*) => UserVisible,
+        AttachDevtools(*) | DevtoolsCommand(*) | DetachDevtools => UserVisible,
+        // The page isn't shown until its style sheets are in
+        StylesheetsParsed(*) => UserVisible,
+        _ => Normal
+    }
+}
+
+// How often transitions of registered custom properties are sampled, in ns
+const TRANSITION_FRAME_NS: u64 = 16666667;
+
+// A page that has been parsed, waiting for its style sheets
+struct PendingLoad {
+    id: uint,
+    url: Url,
+    navigation_start: u64,
+    root: Node,
+    js_port: comm::Port<JSResult>,
+    timing_port: comm::Port<TimedFetch>,
+    // Print requests that came while it loaded, at their dpi, to be
+    // answered once it's been laid out and its scripts have run
+    mut prints: ~[(uint, pipes::Chan<~[PrintedPage]>)],
+}
+
 pub enum PingMsg {
     PongMsg
 }
@@ -63,6 +179,7 @@ pub enum PingMsg {
 pub type ContentTask = pipes::SharedChan<ControlMsg>;
 
 fn ContentTask(layout_task: LayoutTask,
+               opts: Opts,
                dom_event_port: pipes::Port<Event>,
                dom_event_chan: pipes::SharedChan<Event>,
                resource_task: ResourceTask,
@@ -79,8 +196,9 @@ fn ContentTask(layout_task: LayoutTask,
     do task().sched_mode(SingleThreaded).spawn |move layout_task, move control_port,
                                                 move control_chan_copy, move resource_task,
                                                 move img_cache_task, move dom_event_port,
-                                                move dom_event_chan| {
-        let content = Content(layout_task, control_port.take(), control_chan_copy.clone(),
+                                                move dom_event_chan, move opts| {
+        let content = Content(layout_task, copy opts,
+                              control_port.take(), control_chan_copy.clone(),
                               resource_task, img_cache_task.clone(),
                               dom_event_port.take(), dom_event_chan.take());
         content.start();
@@ -91,6 +209,7 @@ fn ContentTask(layout_task: LayoutTask,
 
 struct Content {
     layout_task: LayoutTask,
+    opts: Opts,
     mut layout_join_port: Option<pipes::Port<()>>,
 
     image_cache_task: ImageCacheTask,
@@ -109,11 +228,81 @@ struct Content {
     mut window_size: Size2D<uint>,
 
     resource_task: ResourceTask,
+    // Where the bodies of scripts are kept while they wait to be run
+    buffer_pool: BufferPool,
 
     compartment: Option<compartment>,
+
+    // Set with --cpu-limit
+    cpu_throttle: Option<CpuThrottle>,
+    cpu_ticker: CpuTicker,
+    // Whether an ExitMsg has come in, after which scripts are stopped
+    mut exiting: bool,
+
+    // Where the accessibility tree goes, on platforms that have somewhere
+    ax_bridge: Option<@AXBridge>,
+
+    // Whether the listener running now is passive, so can't cancel its event
+    mut in_passive_listener: bool,
+
+    // Promise jobs waiting for the current task to finish
+    microtasks: PromiseQueue,
+    // The promises for work going on off this task, rooted until they're
+    // settled
+    pending_promises: RootedValues,
+    // The values of the FireEvent and Callback messages on their way here,
+    // rooted until they've been handled
+    posted: RootedValues,
+    // The messages waiting to be handled, by priority
+    scheduler: Scheduler<Runnable>,
+    // When the WakeUp on its way is due, if one is
+    mut wake_up_at: Option<u64>,
+
+    // The page's ES modules, each compiled once
+    modules: ModuleMap,
+
+    // The traps of the proxies bindings make
+    proxy_handler: *libc::c_void,
+
+    // The wrapper of each node script has seen, kept rooted
+    node_wrappers: NodeWrappers,
+    // Whether a CollectCycles is on its way
+    mut cycle_collection_pending: bool,
+    // Whether a SampleTransitions is waiting for the next frame
+    mut transition_frame_pending: bool,
+    // When cycles were last collected, in ns
+    mut last_cycle_collection: Option<u64>,
+
+    // Rejected promises with no handler yet, logged after the next
+    // microtask checkpoint if they still have none
+    unhandled_rejections: RootedValues,
+    // Uncaught errors waiting for script to return, to be given to
+    // window.onerror then
+    mut pending_errors: ~[error_reporter::PendingError],
+
+    // Whether window.onerror is running, so errors it throws are only logged
+    mut in_onerror: bool,
+
+    // The devtools client debugging the page, if one is connected
+    mut devtools: Option<@DevtoolsClient>,
+
+    // The images of the page that have resource entries
+    mut timed_images: ~[~str],
+
+    // The page being loaded, while its style sheets are parsed
+    mut pending_load: Option<PendingLoad>,
+    mut next_load_id: uint,
+
+    // The service workers the task's pages have registered, the one that
+    // controls the current page, and the resource task that sends its
+    // loads to that worker
+    service_workers: ServiceWorkerRegistry,
+    mut controller: Option<@Registration>,
+    mut intercepting: Option<ResourceTask>,
 }
 
-fn Content(layout_task: LayoutTask, 
+fn Content(layout_task: LayoutTask,
+           opts: Opts,
            control_port: pipes::Port<ControlMsg>,
            control_chan: pipes::SharedChan<ControlMsg>,
            resource_task: ResourceTask,
@@ -125,15 +314,23 @@ fn Content(layout_task: LayoutTask,
     let cx = jsrt.cx();
 
     cx.set_default_options_and_version();
-    cx.set_logging_error_reporter();
 
+    finalization::enable_weak_refs(cx.ptr);
     let compartment = match cx.new_compartment(global_class) {
           Ok(c) => Some(c),
           Err(()) => None
     };
 
+    let cpu_throttle = opts.cpu_limit.map(|percent| CpuThrottle(*percent));
+    let ax_bridge = accessibility::platform::bridge(opts.enable_accessibility_debug);
+    // Always ticking, as the operation callback also stops scripts once the
+    // task is told to exit
+    JS_SetOperationCallback(cx.ptr, operation_callback);
+    let cpu_ticker = CpuTicker(jsrt.ptr);
+
     let content = @Content {
         layout_task : move layout_task,
+        opts : move opts,
         layout_join_port : None,
         image_cache_task : move img_cache_task,
         control_port : move control_port,
@@ -151,10 +348,54 @@ fn Content(layout_task: LayoutTask,
         window_size : Size2D(800u, 600u),
 
         resource_task : resource_task,
-        compartment : compartment
+        buffer_pool : BufferPool(),
+        compartment : compartment,
+
+        cpu_throttle : move cpu_throttle,
+        cpu_ticker : move cpu_ticker,
+        exiting : false,
+
+        ax_bridge : move ax_bridge,
+
+        in_passive_listener : false,
+
+        microtasks : PromiseQueue(cx.ptr),
+        pending_promises : RootedValues(cx.ptr),
+        posted : RootedValues(cx.ptr),
+        scheduler : Scheduler(),
+        wake_up_at : None,
+
+        modules : ModuleMap(cx.ptr),
+
+        proxy_handler : proxy::new_proxy_traps_handler(),
+
+        node_wrappers : NodeWrappers(cx.ptr),
+        cycle_collection_pending : false,
+        transition_frame_pending : false,
+        last_cycle_collection : None,
+
+        unhandled_rejections : RootedValues(cx.ptr),
+        pending_errors : ~[],
+        in_onerror : false,
+
+        devtools : None,
+
+        timed_images : ~[],
+
+        pending_load : None,
+        next_load_id : 0,
+
+        service_workers : ServiceWorkerRegistry(),
+        controller : None,
+        intercepting : None
     };
 
     cx.set_cx_private(ptr::to_unsafe_ptr(&*content) as *());
+    promise::init(cx.ptr);
+    error_reporter::init(cx.ptr);
+    finalization::init(cx.ptr, ptr::to_unsafe_ptr(&*content));
+    cycle_collector::init(cx.ptr, ptr::to_unsafe_ptr(&*content));
+    module_script::init(cx.ptr);
 
     content
 }
@@ -163,64 +404,361 @@ fn task_from_context(cx: *JSContext) -> *Content unsafe {
     cast::reinterpret_cast(&JS_GetContextPrivate(cx))
 }
 
+// Called every so often while scripts run, when the CpuTicker asks for it.
+// A script that never returns would keep the task from seeing an ExitMsg,
+// as when the engine tears down a renderer that's out of memory, so the
+// messages that have come in are scheduled here, and once one says to exit
+// the script is stopped.
+extern fn operation_callback(cx: *JSContext) -> JSBool unsafe {
+    let content = task_from_context(cx);
+    while (*content).control_port.peek() {
+        (*content).schedule_control_msg((*content).control_port.recv());
+    }
+    if (*content).exiting {
+        return 0;
+    }
+    for (*content).cpu_throttle.each |throttle| {
+        throttle.throttle();
+    }
+    1
+}
+
 #[allow(non_implicitly_copyable_typarams)]
 impl Content {
 
     fn start() {
         while self.handle_msg() {
-            // Go on ...
+            self.perform_microtask_checkpoint();
+        }
+    }
+
+    /**
+    Runs the promise jobs queued during the task that just finished, and any
+    those queue, before the next task starts. Relayouts if there were any,
+    as they may have changed the document. The errors script didn't catch
+    go to window.onerror first, and after each job.
+    */
+    fn perform_microtask_checkpoint() {
+        let compartment = match copy self.compartment {
+            Some(compartment) => compartment,
+            None => return
+        };
+        error_reporter::dispatch_pending_errors(self.cx.ptr);
+        let ran = do self.microtasks.drain |job| {
+            let rval = JSVAL_NULL;
+            JS_CallFunctionValue(self.cx.ptr, compartment.global_obj.ptr, job,
+                                 0, null(), ptr::to_unsafe_ptr(&rval));
+            error_reporter::dispatch_pending_errors(self.cx.ptr);
+        };
+        finalization::clear_kept_objects(self.cx.ptr);
+        error_reporter::report_unhandled_rejections(self.cx.ptr);
+        if ran > 0 {
+            match copy self.document {
+                Some(document) => self.relayout(document, &self.doc_url.get()),
+                None => ()
+            }
         }
     }
 
+    /**
+    Handles only the devtools client's messages while it has script stopped
+    at a breakpoint. Other messages wait until script goes on, and are then
+    handled in the order they came.
+    */
+    fn wait_while_paused() {
+        let mut waiting = ~[];
+        while self.devtools.map_default(false, |client| client.is_paused()) {
+            match self.control_port.recv() {
+                DevtoolsCommand(move message) => {
+                    for self.devtools.each |client| {
+                        client.handle_message(copy message);
+                    }
+                }
+                DetachDevtools => {
+                    for self.devtools.each |client| {
+                        client.detach();
+                    }
+                    self.devtools = None;
+                }
+                move msg => waiting.push(move msg)
+            }
+        }
+        do vec::consume(move waiting) |_i, msg| {
+            self.control_chan.send(move msg);
+        }
+    }
+
+    // Where the page's loads go: its service worker, if it has one
+    fn page_resource_task() -> ResourceTask {
+        self.intercepting.get_default(self.resource_task)
+    }
+
+    fn run_module(url: Url) {
+        module_script::run_module(self.cx.ptr, &self.modules, move url,
+                                  self.page_resource_task());
+    }
+
+    /**
+    Schedules every message that has come in, waiting for one if none that's
+    queued is ready to run, then handles the most urgent. Input and relayouts are handled
+    at UserVisible priority, ahead of timers and network callbacks. The
+    microtask checkpoint after each task is part of it, so promise jobs
+    always run before the next task, whatever its priority.
+    */
     fn handle_msg() -> bool {
-        match pipes::select2i(&self.control_port, &self.event_port) {
-            either::Left(*) => self.handle_control_msg(self.control_port.recv()),
-            either::Right(*) => self.handle_event(self.event_port.recv())
+        if !self.scheduler.has_ready(precise_time_ns()) {
+            self.schedule_wake_up();
+            match pipes::select2i(&self.control_port, &self.event_port) {
+                either::Left(*) => self.schedule_control_msg(self.control_port.recv()),
+                either::Right(*) => self.scheduler.post(UserVisible,
+                                                        EventRunnable(self.event_port.recv()))
+            }
+        }
+        while self.control_port.peek() {
+            self.schedule_control_msg(self.control_port.recv());
+        }
+        while self.event_port.peek() {
+            self.scheduler.post(UserVisible, EventRunnable(self.event_port.recv()));
         }
+
+        let start = precise_time_ns();
+        let mut keep_going = true;
+        let ran = do self.scheduler.run_one_task |runnable| {
+            keep_going = match move runnable {
+                ControlRunnable(move msg) => self.handle_control_msg(move msg),
+                EventRunnable(move event) => self.handle_event(move event)
+            };
+        };
+        if !ran {
+            // Only timers that aren't due yet are queued; the next turn waits
+            // for them, or for whatever comes in first
+            return true;
+        }
+        for self.window.each |window| {
+            window.note_task(start);
+        }
+        keep_going
     }
 
-    fn handle_control_msg(control_msg: ControlMsg) -> bool {
-        match move control_msg {
-          ParseMsg(move url) => {
-            debug!("content: Received url `%s` to parse", url_to_str(copy url));
+    // Has a WakeUp sent when the soonest held back timer is due, unless one
+    // that's as soon is already on its way. The content task waits on its
+    // ports in the meantime, rather than sleeping through other messages.
+    fn schedule_wake_up() {
+        match self.scheduler.next_due() {
+            Some(due) if self.wake_up_at.map_default(true, |at| due < *at) => {
+                self.wake_up_at = Some(due);
+                let now = precise_time_ns();
+                let ms = if due > now { ((due - now) / 1000000 + 1) as uint } else { 0 };
+                let chan = self.control_chan.clone();
+                // Unlinked, as the content task may have gone when it sends
+                do spawn_unlinked |move chan| {
+                    std::timer::sleep(std::uv_global_loop::get(), ms);
+                    chan.send(WakeUp(due));
+                }
+            }
+            _ => ()
+        }
+    }
 
-            // Note: we can parse the next document in parallel
-            // with any previous documents.
+    // Timers are held back until their delay has passed, by the clock
+    // rather than by when the timer task sent them. Once an ExitMsg is in,
+    // scripts are stopped until it's handled.
+    fn schedule_control_msg(msg: ControlMsg) {
+        match msg {
+            ExitMsg => self.exiting = true,
+            _ => ()
+        }
+        let priority = control_msg_priority(&msg);
+        let fire_at = match msg {
+            Timer(ref data) => data.fire_at,
+            _ => 0
+        };
+        self.scheduler.post_at(priority, ControlRunnable(move msg), fire_at);
+    }
 
-            let result = html::hubbub_html_parser::parse_html(self.scope,
-                                                              copy url,
-                                                              self.resource_task,
-                                                              self.image_cache_task.clone());
+    /**
+    Sets up the page that `load` parsed, once its style sheets are ready:
+    lays it out, and runs its scripts.
+    */
+    fn finish_load(load: PendingLoad) {
+        let PendingLoad { url: move url, navigation_start: navigation_start, root: root,
+                          js_port: move js_port, timing_port: move timing_port,
+                          prints: move prints, _ } = move load;
+
+        let js_scripts = js_port.recv();
+        debug!("js_scripts: %?", js_scripts);
+
+        let document = Document(root, self.scope);
+        let window   = Window(self.control_chan.clone(), self.opts.permission_prompt,
+                              copy url, navigation_start, self.cx.ptr);
+        self.settle_largest_contentful_paint();
+        self.relayout(&document, &url);
+        for self.window.each |old_window| {
+            old_window.unroot_all();
+        }
+        // The old document's nodes are going, and their wrappers with them
+        self.node_wrappers.unroot_all();
+        self.document = Some(@move document);
+        self.window   = Some(@move window);
+        self.doc_url = Some(move url);
+
+        let compartment = option::expect(self.compartment, ~"TODO error checking");
+        compartment.define_functions(debug_fns);
+        define_bindings(compartment,
+                        option::get(self.document),
+                        option::get(self.window));
+        if self.opts.expose_gc {
+            finalization::define_gc(compartment);
+        }
+        if self.opts.enable_accessibility_debug {
+            element::define_accessibility_debug(compartment);
+        }
+        if self.opts.enable_notification_debug {
+            notification::define_notification_debug(compartment);
+        }
 
-            let root = result.root;
+        // The scripts and style sheets have all loaded, so their timings
+        // have all been sent
+        let window = self.window.get();
+        while timing_port.peek() {
+            let fetch = timing_port.recv();
+            let entry = window.performance.resource_entry(&fetch.url, copy fetch.initiator_type,
+                                                          &fetch.timing, &self.doc_url.get());
+            window.record_performance_entry(move entry);
+        }
+        self.timed_images = ~[];
+
+        if self.opts.javascript_enabled {
+            // Module scripts are deferred, so run after the classic ones
+            let mut module_urls = ~[];
+            // Classic scripts don't keep their own URLs yet, so they're
+            // all named after the page, for errors and breakpoints
+            let page_url = url_to_str(self.doc_url.get());
+            do vec::consume(move js_scripts) |_i, script| {
+                match move script {
+                    ClassicScript(move bytes) => {
+                        self.evaluate_pooled_script(compartment, move bytes, page_url);
+                    }
+                    ModuleScript(move url) => module_urls.push(move url)
+                }
+            }
+            do vec::consume(move module_urls) |_i, url| {
+                self.run_module(move url);
+            }
+        }
 
-              // Send stylesheets over to layout
-              // FIXME: Need these should be streamed to layout as they are parsed
-              // and do not need to stop here in the content task
-              // FIXME: This currently expects exactly one stylesheet
-              let sheet = result.style_port.recv();
-              self.layout_task.send(AddStylesheet(move sheet));
+        window.record_performance_entry(PerformanceEntry(url_to_str(self.doc_url.get()),
+                                                         NavigationEntry, 0.0,
+                                                         window.performance.now()));
+        self.time_load_paint();
 
-            let js_scripts = result.js_port.recv();
-            debug!("js_scripts: %?", js_scripts);
+        let document = self.document.get();
+        do vec::consume(move prints) |_i, print| {
+            let (dpi, pages_chan) = move print;
+            self.print(document, &self.doc_url.get(), dpi, move pages_chan);
+        }
+    }
 
-            let document = Document(root, self.scope);
-            let window   = Window(self.control_chan.clone());
-            self.relayout(&document, &url);
-            self.document = Some(@move document);
-            self.window   = Some(@move window);
-            self.doc_url = Some(move url);
+    // Runs a classic script whose body came from the buffer pool, then
+    // gives the buffer back
+    fn evaluate_pooled_script(compartment: compartment, bytes: ~[u8], filename: &str) {
+        let evaluated = do vec::as_imm_buf(bytes) |bytes_ptr, bytes_len| {
+            do str::as_c_str(filename) |filename_cstr| {
+                let rval = JSVAL_NULL;
+                JS_EvaluateScript(self.cx.ptr, compartment.global_obj.ptr,
+                                  bytes_ptr as *libc::c_char, bytes_len as libc::c_uint,
+                                  filename_cstr, 1, ptr::to_unsafe_ptr(&rval))
+            }
+        };
+        if evaluated == 0 {
+            println(fmt!("Error running a script in %s", filename));
+            JS_ClearPendingException(self.cx.ptr);
+        }
+        self.buffer_pool.release(move bytes);
+    }
 
-            let compartment = option::expect(self.compartment, ~"TODO error checking");
-            compartment.define_functions(debug_fns);
-            define_bindings(compartment,
-                            option::get(self.document),
-                            option::get(self.window));
+    /**
+    Starts loading the page at `url`, with a POST of `body` if there is
+    one. The page is set up by `finish_load` once its style sheets are in.
+    */
+    fn load_page(url: Url, body: Option<(~[u8], ~str)>) {
+        let navigation_start = precise_time_ns();
 
-            do vec::consume(move js_scripts) |_i, bytes| {
-                self.cx.evaluate_script(compartment.global_obj, move bytes, ~"???", 1u);
+        // A page is controlled by the worker whose scope it's in, as
+        // of when it's loaded
+        for self.intercepting.each |proxy| {
+            proxy.send(resource_task::Exit);
+        }
+        self.controller = self.service_workers.controller_for(url_to_str(copy url));
+        self.intercepting = self.controller.map(|registration| {
+            intercept(self.resource_task, registration.worker)
+        });
+
+        // Note: we can parse the next document in parallel
+        // with any previous documents.
+
+        let result = html::hubbub_html_parser::parse_html(self.scope,
+                                                          copy url,
+                                                          move body,
+                                                          self.page_resource_task(),
+                                                          self.image_cache_task.clone(),
+                                                          self.buffer_pool.clone());
+        let HtmlParserResult { root: root, style_port: move style_port,
+                               js_port: move js_port, timing_port: move timing_port } =
+            move result;
+
+        // The style sheets are parsed on tasks of their own. The page
+        // can't be laid out or run its scripts until they're done, but
+        // other messages are handled while it waits.
+        self.next_load_id += 1;
+        let load_id = self.next_load_id;
+        let control_chan = self.control_chan.clone();
+        do spawn |move style_port, move control_chan| {
+            let (move sheet, move faces) = style_port.recv();
+            control_chan.send(StylesheetsParsed(load_id, move sheet, move faces));
+        }
+        // Printing waits for whichever page ends up loaded
+        let prints = match replace(&mut self.pending_load, None) {
+            Some(move load) => {
+                let PendingLoad { prints: move prints, _ } = move load;
+                move prints
             }
+            None => ~[]
+        };
+        self.pending_load = Some(PendingLoad {
+            id: load_id,
+            url: move url,
+            navigation_start: navigation_start,
+            root: root,
+            js_port: move js_port,
+            timing_port: move timing_port,
+            prints: move prints,
+        });
+    }
+
+    fn handle_control_msg(control_msg: ControlMsg) -> bool {
+        match move control_msg {
+          ParseMsg(move url) => {
+            debug!("content: Received url `%s` to parse", url_to_str(copy url));
+            self.load_page(move url, None);
+            return true;
+          }
+
+          PostMsg(move url, move body, move content_type) => {
+            debug!("content: Received url `%s` to post to", url_to_str(copy url));
+            self.load_page(move url, Some((move body, move content_type)));
+            return true;
+          }
 
+          StylesheetsParsed(load_id, move sheet, move faces) => {
+            // A later navigation may have replaced the load they were for
+            if self.pending_load.map_default(false, |load| load.id == load_id) {
+                let load = option::unwrap(replace(&mut self.pending_load, None));
+                self.layout_task.send(AddStylesheet(move sheet));
+                load_web_fonts(move faces, self.page_resource_task(), self.layout_task,
+                               self.event_chan.clone());
+                self.finish_load(move load);
+            }
             return true;
           }
 
@@ -239,10 +777,143 @@ impl Content {
             return true;
           }
 
+          FireEvent(target_key, move kind, event_key) => {
+            self.dispatch_event(self.posted.get(target_key), kind, self.posted.get(event_key));
+            self.posted.remove(target_key);
+            self.posted.remove(event_key);
+            return true;
+          }
+
+          Callback(funval_key, arg_key) => {
+            let compartment = option::expect(self.compartment, ~"TODO error checking");
+            let arg = self.posted.get(arg_key);
+            let rval = JSVAL_NULL;
+            JS_CallFunctionValue(self.cx.ptr, compartment.global_obj.ptr,
+                                 self.posted.get(funval_key), 1, ptr::to_unsafe_ptr(&arg),
+                                 ptr::to_unsafe_ptr(&rval));
+            self.posted.remove(funval_key);
+            self.posted.remove(arg_key);
+            self.relayout(self.document.get(), &self.doc_url.get());
+            return true;
+          }
+
+          SettlePromise(key, move settlement) => {
+            let promise = self.pending_promises.get(key);
+            let outcome = settlement(self.cx.ptr);
+            unsafe {
+                promise::settle_promise(self.cx.ptr, RUST_JSVAL_TO_OBJECT(promise), outcome);
+            }
+            self.pending_promises.remove(key);
+            return true;
+          }
+
+          DeliverPerformanceEntries => {
+            let window = self.window.get();
+            window.performance.delivered();
+            let compartment = option::expect(self.compartment, ~"TODO error checking");
+            // a callback may observe or disconnect, changing the list
+            for window.performance.observers.get().each |observer| {
+                let records = observer.take_records();
+                if records.is_empty() {
+                    loop;
+                }
+                let argv = ~[entry_list_to_jsval(self.cx.ptr, records), observer.obj];
+                let rval = JSVAL_NULL;
+                do vec::as_imm_buf(argv) |argv, argc| {
+                    JS_CallFunctionValue(self.cx.ptr, compartment.global_obj.ptr,
+                                         observer.callback, argc as libc::c_uint, argv,
+                                         ptr::to_unsafe_ptr(&rval));
+                }
+            }
+            self.relayout(self.document.get(), &self.doc_url.get());
+            return true;
+          }
+
+          CollectGarbage => {
+            JS_MaybeGC(self.cx.ptr);
+            return true;
+          }
+
+          WakeUp(due) => {
+            if self.wake_up_at == Some(due) {
+                self.wake_up_at = None;
+            }
+            return true;
+          }
+
+          SampleTransitions => {
+            self.transition_frame_pending = false;
+            self.sample_property_transitions();
+            return true;
+          }
+
+          CollectCycles => {
+            self.cycle_collection_pending = false;
+            match (copy self.document, copy self.window) {
+                (Some(document), Some(window)) => {
+                    cycle_collector::detect_cycles(document, window, &self.node_wrappers,
+                                                   self.cx.ptr);
+                }
+                _ => ()
+            }
+            self.last_cycle_collection = Some(precise_time_ns());
+            return true;
+          }
+
+          Print(dpi, move pages_chan) => {
+            // A page that's still loading is printed once it's ready
+            match self.pending_load {
+                Some(ref load) => {
+                    load.prints.push((dpi, move pages_chan));
+                    return true;
+                }
+                None => ()
+            }
+            match copy self.document {
+                Some(document) => self.print(document, &self.doc_url.get(), dpi,
+                                             move pages_chan),
+                None => pages_chan.send(~[])
+            }
+            return true;
+          }
+
+          AttachDevtools(move chan) => {
+            let compartment = option::expect(self.compartment, ~"TODO error checking");
+            for self.devtools.each |client| {
+                client.detach();
+            }
+            self.devtools = DevtoolsClient(self.cx, &compartment, move chan);
+            return true;
+          }
+
+          DevtoolsCommand(move message) => {
+            for self.devtools.each |client| {
+                client.handle_message(copy message);
+            }
+            // Evaluating script may have changed the document
+            match copy self.document {
+                Some(document) => self.relayout(document, &self.doc_url.get()),
+                None => ()
+            }
+            return true;
+          }
+
+          DetachDevtools => {
+            for self.devtools.each |client| {
+                client.detach();
+            }
+            self.devtools = None;
+            return true;
+          }
 
           ExecuteMsg(url) => {
             debug!("content: Received url `%s` to execute", url_to_str(copy url));
 
+            if !self.opts.javascript_enabled {
+                debug!("content: JavaScript is disabled, not executing");
+                return true;
+            }
+
             match read_whole_file(&Path(url.path)) {
               Err(msg) => {
                 println(fmt!("Error opening %s: %s", url_to_str(copy url), msg));
@@ -250,6 +921,9 @@ impl Content {
               Ok(move bytes) => {
                 let compartment = option::expect(self.compartment, ~"TODO error checking");
                 compartment.define_functions(debug_fns);
+                if self.opts.expose_gc {
+                    finalization::define_gc(compartment);
+                }
                 self.cx.evaluate_script(compartment.global_obj, move bytes, copy url.path, 1u);
               }
             }
@@ -257,6 +931,22 @@ impl Content {
           }
 
           ExitMsg => {
+            self.settle_largest_contentful_paint();
+            self.cpu_ticker.send(CpuTickerExitMsg);
+            for self.intercepting.each |proxy| {
+                proxy.send(resource_task::Exit);
+            }
+            self.service_workers.stop_all();
+            // Before the context goes, which the roots need
+            self.node_wrappers.unroot_all();
+            self.microtasks.clear();
+            self.pending_promises.clear();
+            self.posted.clear();
+            self.unhandled_rejections.clear();
+            self.modules.clear();
+            for self.window.each |window| {
+                window.unroot_all();
+            }
             self.layout_task.send(layout_task::ExitMsg);
             return false;
           }
@@ -312,6 +1002,9 @@ impl Content {
             url: copy *doc_url,
             dom_event_chan: self.event_chan.clone(),
             window_size: self.window_size,
+            scroll_offset: self.window.map_default(Point2D(0, 0), |w| w.scroll.viewport),
+            element_scroll_offsets: self.window.map_default(~[], |w| w.scroll.element_offsets()),
+            report_paint: self.window.map_default(false, |w| w.performance.is_timing_paint()),
             content_join_chan: move join_chan
         };
 
@@ -322,6 +1015,204 @@ impl Content {
         self.scope.reader_forked();
 
         debug!("content: layout forked");
+
+        self.notify_resize_observers();
+        self.time_images();
+
+        self.update_accessibility_tree();
+    }
+
+    /**
+       Has layout print the document, as relayout has it lay it out for the
+       screen. Layout sends the pages to `pages_chan` itself.
+    */
+    fn print(document: &Document, doc_url: &Url, dpi: uint,
+             pages_chan: pipes::Chan<~[PrintedPage]>) {
+        debug!("content: printing");
+        self.join_layout();
+
+        let (join_chan, join_port) = pipes::stream();
+        self.layout_join_port = move Some(move join_port);
+
+        let data = PrintData {
+            node: document.root,
+            url: copy *doc_url,
+            dom_event_chan: self.event_chan.clone(),
+            dpi: dpi,
+            pages_chan: move pages_chan,
+            content_join_chan: move join_chan
+        };
+        self.layout_task.send(PrintMsg(move data));
+        self.scope.reader_forked();
+    }
+
+    /**
+       Records a frame layout showed for paint timing: the first contentful
+       paint, and the largest so far. Layout sends a PaintedEvent for each
+       frame while the page is timing paint.
+    */
+    fn time_paint(paint: &ContentfulPaint) {
+        for self.window.each |window| {
+            let largest = paint.largest.map(|e| (e.node, e.area));
+            let now = window.performance.now();
+            for window.performance.painted(now, paint.contentful, largest).each |entry| {
+                window.record_performance_entry(copy *entry);
+            }
+        }
+    }
+
+    /**
+       Times the frame shown as the page loads, which was laid out before
+       there was a window, and reports the largest contentful paint so far.
+       This waits for layout, but only once per page.
+    */
+    fn time_load_paint() {
+        let window = self.window.get();
+        if !window.performance.is_timing_paint() {
+            return;
+        }
+
+        self.join_layout();
+        let response_port = Port();
+        self.layout_task.send(layout_task::QueryMsg(layout_task::Contentful,
+                                                    response_port.chan()));
+        match response_port.recv() {
+            Ok(layout_task::PaintedContent(ref paint)) => self.time_paint(paint),
+            _ => ()
+        }
+        for window.performance.report_largest_contentful_paint().each |entry| {
+            window.record_performance_entry(copy *entry);
+        }
+    }
+
+    /**
+       Records a resource entry for each image of the page whose fetch has
+       finished since the last layout. Images are fetched by the image
+       cache, for layout, so it's asked how long each one took.
+    */
+    fn time_images() {
+        let (window, document) = match (self.window, self.document) {
+            (Some(window), Some(document)) => (window, document),
+            _ => return
+        };
+        let mut urls = ~[];
+        do document.root.traverse_preorder |node| {
+            do node.read |n| {
+                match n.kind {
+                    ~Element(ref e) => match e.kind {
+                        ~HTMLImageElement(ref d) => {
+                            for d.image.each |url| { urls.push(copy *url); }
+                        }
+                        _ => ()
+                    },
+                    _ => ()
+                }
+            }
+        }
+        for urls.each |url| {
+            let name = url_to_str(copy *url);
+            if self.timed_images.contains(&name) {
+                loop;
+            }
+            match self.image_cache_task.timing(copy *url) {
+                Some(ref timing) => {
+                    let entry = window.performance.resource_entry(url, ~"img", timing,
+                                                                  &self.doc_url.get());
+                    window.record_performance_entry(move entry);
+                    self.timed_images.push(move name);
+                }
+                None => ()
+            }
+        }
+    }
+
+    /// Settles the largest contentful paint on the user's first input, or
+    /// when the page goes away.
+    fn settle_largest_contentful_paint() {
+        for self.window.each |window| {
+            for window.performance.finalize_largest_contentful_paint().each |entry| {
+                window.record_performance_entry(copy *entry);
+            }
+        }
+    }
+
+    /// Has the transitions of registered custom properties sampled a frame
+    /// from now, unless that's already on its way.
+    fn schedule_transition_frame() {
+        if !self.transition_frame_pending {
+            self.transition_frame_pending = true;
+            self.scheduler.post_at(UserVisible, ControlRunnable(SampleTransitions),
+                                   precise_time_ns() + TRANSITION_FRAME_NS);
+        }
+    }
+
+    /**
+    Gives the elements whose registered custom properties are transitioning
+    their values for this frame, and lays the page out with them, until the
+    transitions have all finished.
+    */
+    fn sample_property_transitions() {
+        let (document, window) = match (copy self.document, copy self.window) {
+            (Some(document), Some(window)) => (document, window),
+            _ => return
+        };
+        let running = do window.property_registry.sample(precise_time_ns()) |node, name, value| {
+            do self.scope.write(&node) |n| {
+                match n.kind {
+                    ~Element(ref e) => e.set_animated_property(name, value.map(|v| v.to_css())),
+                    _ => ()
+                }
+            }
+        };
+        self.relayout(document, &self.doc_url.get());
+        if running {
+            self.schedule_transition_frame();
+        }
+    }
+
+    /// Rebuilds the accessibility tree and hands it to the platform, after
+    /// layout or when scripts change ARIA attributes.
+    fn update_accessibility_tree() {
+        for self.ax_bridge.each |bridge| {
+            for self.document.each |document| {
+                bridge.update(&build_ax_tree(&self.scope, document.root));
+            }
+        }
+    }
+
+    /**
+       Compares the sizes of the elements ResizeObservers are watching with
+       the layout that was just started, and queues a call to each observer
+       that has entries. This waits for layout, so only when something is
+       being observed.
+    */
+    fn notify_resize_observers() {
+        let window = match self.window {
+            Some(window) => window,
+            None => return
+        };
+        if !window.resize_observers.any(|observer| observer.is_observing()) {
+            return;
+        }
+
+        self.join_layout();
+        for window.resize_observers.each |observer| {
+            let entries = do observer.gather_entries |node| {
+                let response_port = Port();
+                self.layout_task.send(layout_task::QueryMsg(layout_task::Boxes(node),
+                                                            response_port.chan()));
+                match response_port.recv() {
+                    Ok(layout_task::NodeBoxes(content_box, border_box)) => {
+                        BoxSizes(content_box, border_box)
+                    }
+                    _ => empty_box_sizes()
+                }
+            };
+            if !entries.is_empty() {
+                window.post_callback(observer.callback,
+                                     resize_observer::entries_to_jsval(self.cx.ptr, entries));
+            }
+        }
     }
 
      fn query_layout(query: layout_task::LayoutQuery) -> layout_task::LayoutQueryResponse {
@@ -333,6 +1224,316 @@ impl Content {
          return response_port.recv()
     }
 
+    /**
+       Scrolls the viewport to `offset`, as far as the page allows, and
+       queues a `scroll` event at the window if it moved.
+    */
+    fn scroll_viewport_to(offset: Point2D<int>) unsafe {
+        let (document, window) = match (self.document, self.window) {
+            (Some(document), Some(window)) => (document, window),
+            _ => return
+        };
+        let content_size = match self.query_layout(layout_task::ContentBox(document.root)) {
+            Ok(layout_task::ContentSize(size)) => size,
+            _ => Size2D(0, 0)
+        };
+        let viewport_size = Size2D(self.window_size.width as int,
+                                   self.window_size.height as int);
+        let offset = clamp_offset(offset, content_size, viewport_size);
+        if offset == window.scroll.viewport {
+            return;
+        }
+        window.scroll.viewport = offset;
+        self.relayout(document, &self.doc_url.get());
+
+        let target = self.window_object();
+        let event = new_event(self.cx.ptr, "scroll", target);
+        window.post_event(target, ~"scroll", RUST_OBJECT_TO_JSVAL(event));
+    }
+
+    /// Scrolls the element `node`, whose JS object is `obj`, to `offset`,
+    /// and lays the page out again if it moved.
+    fn scroll_element_to(node: Node, obj: JSVal, offset: Point2D<int>) unsafe {
+        let window = self.window.get();
+        if window.scroll_element_to(self.cx.ptr, node, obj, offset) {
+            self.relayout(self.document.get(), &self.doc_url.get());
+        }
+    }
+
+    /**
+       Dispatches `event` at `target`. It goes down from the window through
+       the target's ancestors, firing capture listeners, then fires the
+       target's own listeners, then, if it bubbles, goes back up firing the
+       others. Each target's `onfoo` handler runs before its non-capture
+       listeners. `stopPropagation()` lets the listeners of the current
+       target finish, but stops there; `stopImmediatePropagation()` stops
+       at once. Returns false if a listener cancelled the event with
+       `preventDefault()`.
+    */
+    fn dispatch_event(target: JSVal, kind: &str, event: JSVal) -> bool unsafe {
+        let event_obj = RUST_JSVAL_TO_OBJECT(event);
+        let path = self.event_path(target);
+        let mut called = false;
+        let bubbles = self.event_flag(event_obj, "bubbles");
+        for dispatch_steps(path.len(), bubbles).each |step| {
+            let (i, phase, capture) = *step;
+            if self.event_flag(event_obj, "cancelBubble") {
+                break;
+            }
+            let current = RUST_JSVAL_TO_OBJECT(path[i]);
+            let callbacks = self.callbacks(current, kind, capture);
+            if callbacks.is_empty() {
+                loop;
+            }
+
+            self.set_event_value(event_obj, "eventPhase", RUST_INT_TO_JSVAL(phase as c_int));
+            self.set_event_value(event_obj, "currentTarget", path[i]);
+            for callbacks.each |callback| {
+                let (callback, passive) = *callback;
+                self.in_passive_listener = passive;
+                let rval = JSVAL_NULL;
+                JS_CallFunctionValue(self.cx.ptr, current, callback,
+                                     1, ptr::to_unsafe_ptr(&event), ptr::to_unsafe_ptr(&rval));
+                self.in_passive_listener = false;
+                called = true;
+                if self.event_flag(event_obj, STOP_IMMEDIATE_PROPERTY) {
+                    break;
+                }
+            }
+        }
+        self.set_event_value(event_obj, "eventPhase", RUST_INT_TO_JSVAL(NoPhase as c_int));
+        self.set_event_value(event_obj, "currentTarget", JSVAL_NULL);
+
+        if called {
+            self.relayout(self.document.get(), &self.doc_url.get());
+        }
+        !self.event_flag(event_obj, "defaultPrevented")
+    }
+
+    // The targets an event at `target` goes through, from the window down.
+    // Nodes are wrapped afresh, so ancestors' listeners see new objects.
+    priv fn event_path(target: JSVal) -> ~[JSVal] unsafe {
+        let window = self.window_object();
+        let document = self.global_property("document");
+        let mut path = ~[];
+        match target_id(RUST_JSVAL_TO_OBJECT(target)) {
+            NodeTarget(node) => {
+                let mut ancestor = tree::get_parent(&self.scope, &node);
+                while ancestor.is_some() {
+                    let parent = ancestor.get();
+                    path.push(RUST_OBJECT_TO_JSVAL(node::create(self.cx.ptr, parent,
+                                                                self.scope)));
+                    ancestor = tree::get_parent(&self.scope, &parent);
+                }
+                path.push(document);
+                path.push(window);
+            }
+            ObjectTarget(_) if target == document => path.push(window),
+            ObjectTarget(_) => ()
+        }
+        vec::reverse(path);
+        path.push(target);
+        move path
+    }
+
+    // The callbacks for `kind` events at `obj` in one phase, with whether
+    // each is passive. Listeners added with `once` are removed.
+    priv fn callbacks(obj: *JSObject, kind: &str, capture: bool) -> ~[(JSVal, bool)] unsafe {
+        let mut callbacks = ~[];
+        if !capture {
+            let handler = JSVAL_NULL;
+            let found = do str::as_c_str(~"on" + kind) |name| {
+                JS_GetProperty(self.cx.ptr, obj, name, ptr::to_unsafe_ptr(&handler))
+            };
+            if found == 1 && JS_TypeOfValue(self.cx.ptr, handler) == JSTYPE_FUNCTION {
+                callbacks.push((handler, false));
+            }
+        }
+        for self.window.each |window| {
+            let id = target_id(obj);
+            for window.event_listeners.phase_listeners(id, kind, capture).each |listener| {
+                if listener.once {
+                    window.event_listeners.remove(id, kind, listener.callback, listener.capture);
+                }
+                callbacks.push((listener.callback, listener.passive));
+            }
+        }
+        move callbacks
+    }
+
+    priv fn event_flag(event: *JSObject, name: &str) -> bool unsafe {
+        let val = JSVAL_NULL;
+        do str::as_c_str(name) |name| {
+            JS_GetProperty(self.cx.ptr, event, name, ptr::to_unsafe_ptr(&val));
+        }
+        val == RUST_BOOLEAN_TO_JSVAL(1)
+    }
+
+    priv fn set_event_value(event: *JSObject, name: &str, val: JSVal) unsafe {
+        do str::as_c_str(name) |name| {
+            JS_SetProperty(self.cx.ptr, event, name, ptr::to_unsafe_ptr(&val));
+        }
+    }
+
+    // A property of the global object
+    priv fn global_property(name: &str) -> JSVal {
+        let compartment = option::expect(self.compartment, ~"TODO error checking");
+        let val = JSVAL_NULL;
+        do str::as_c_str(name) |name| {
+            JS_GetProperty(self.cx.ptr, compartment.global_obj.ptr, name,
+                           ptr::to_unsafe_ptr(&val));
+        }
+        val
+    }
+
+    // The JS object scripts see as `window`
+    priv fn window_object() -> JSVal {
+        self.global_property("window")
+    }
+
+    /**
+       Scrolls whatever is under `point` in the window: its nearest scroll
+       container, or else the viewport. A `wheel` event goes first; if any
+       listener for it (or for touches) isn't passive, the scroll waits for
+       it to be dispatched and doesn't happen if it's cancelled. Otherwise
+       it's queued to run after the scroll.
+    */
+    fn handle_scroll(point: Point2D<int>, delta: Point2D<int>) unsafe {
+        let (document, window) = match (self.document, self.window) {
+            (Some(document), Some(window)) => (document, window),
+            _ => return
+        };
+        let viewport = window.scroll.viewport;
+        let page_point = Point2D(point.x + viewport.x, point.y + viewport.y);
+        let hit = match self.query_layout(layout_task::HitTest(document.root, page_point)) {
+            Ok(layout_task::HitNode(node)) => Some(node),
+            _ => None
+        };
+
+        let wheel_target = match hit {
+            Some(node) => RUST_OBJECT_TO_JSVAL(node::create(self.cx.ptr, node, self.scope)),
+            None => self.window_object()
+        };
+        let wheel = RUST_OBJECT_TO_JSVAL(new_wheel_event(self.cx.ptr, wheel_target, delta));
+        if window.event_listeners.has_blocking_listener() {
+            if !self.dispatch_event(wheel_target, "wheel", wheel) {
+                return;
+            }
+        } else {
+            window.post_event(wheel_target, ~"wheel", wheel);
+        }
+        match hit.chain(|node| scroll_container_for(&self.scope, node)) {
+            Some(container) => {
+                let current = window.scroll.element_offset(container);
+                let obj = node::create(self.cx.ptr, container, self.scope);
+                self.scroll_element_to(container, RUST_OBJECT_TO_JSVAL(obj),
+                                       Point2D(current.x + delta.x, current.y + delta.y));
+            }
+            None => {
+                self.scroll_viewport_to(Point2D(viewport.x + delta.x, viewport.y + delta.y))
+            }
+        }
+    }
+
+    /**
+       Types `key` into the focused element. Text inputs take characters and
+       backspace, and fire `input`; the space bar toggles checkboxes and
+       radio buttons, which fire `input` and then `change`. Tab moves focus.
+    */
+    fn handle_key(key: char) unsafe {
+        let (document, window) = match (self.document, self.window) {
+            (Some(document), Some(window)) => (document, window),
+            _ => return
+        };
+        if key == '\t' {
+            window.focus_next(self.cx.ptr, document);
+            return;
+        }
+        let focused = match window.focused {
+            Some(focused) => focused,
+            None => return
+        };
+        let node = focused.node;
+        let is_input = do self.scope.read(&node) |n| {
+            match n.kind {
+                ~Element(ref e) => match e.kind {
+                    ~HTMLInputElement(*) => true,
+                    _ => false
+                },
+                _ => false
+            }
+        };
+        if !is_input {
+            return;
+        }
+
+        let typed = do self.scope.read(&node) |n| {
+            match n.kind {
+                ~Element(ref e) => input::typed_value(e, key),
+                _ => None
+            }
+        };
+        match move typed {
+            Some(move value) => {
+                input::set_value(&self.scope, node, move value);
+                // Deletions have no data
+                let data = if key >= ' ' && key != '\x7f' {
+                    Some(str::from_char(key))
+                } else {
+                    None
+                };
+                let event = new_input_event(self.cx.ptr, focused.obj, move data);
+                window.post_event(focused.obj, ~"input", RUST_OBJECT_TO_JSVAL(event));
+            }
+            None if key == ' ' && input::activate(document, node) => {
+                let event = new_input_event(self.cx.ptr, focused.obj, None);
+                window.post_event(focused.obj, ~"input", RUST_OBJECT_TO_JSVAL(event));
+                let event = new_event(self.cx.ptr, "change", focused.obj);
+                window.post_event(focused.obj, ~"change", RUST_OBJECT_TO_JSVAL(event));
+                self.update_accessibility_tree();
+            }
+            None => ()
+        }
+    }
+
+    /**
+       Fires a pointer event at the element under the pointer, or at the
+       element that's captured it. Lifting the pointer releases the capture.
+    */
+    fn handle_pointer(input: PointerInput) unsafe {
+        let (document, window) = match (self.document, self.window) {
+            (Some(document), Some(window)) => (document, window),
+            _ => return
+        };
+        let pointer_id = input.sample.pointer_id;
+        if input.phase == PointerDown {
+            window.pointers.pointer_down(pointer_id);
+        }
+        let target = match window.pointers.target(pointer_id) {
+            Some(node) => node,
+            None => {
+                let viewport = window.scroll.viewport;
+                let point = input.sample.point;
+                let page_point = Point2D(point.x + viewport.x, point.y + viewport.y);
+                match self.query_layout(layout_task::HitTest(document.root, page_point)) {
+                    Ok(layout_task::HitNode(node)) => node,
+                    _ => document.root
+                }
+            }
+        };
+        let target_obj = RUST_OBJECT_TO_JSVAL(node::create(self.cx.ptr, target, self.scope));
+        let kind = input.phase.event_type();
+        let event = new_pointer_event(self.cx.ptr, kind, target_obj, &input);
+        window.post_event(target_obj, move kind, RUST_OBJECT_TO_JSVAL(event));
+
+        if input.phase == PointerUp || input.phase == PointerCancel {
+            for window.pointers.pointer_up(pointer_id).each |node| {
+                post_capture_event(self.cx.ptr, window, self.scope, *node, "lostpointercapture",
+                                   pointer_id);
+            }
+        }
+    }
+
     /**
        This is the main entry point for receiving and dispatching DOM events.
     */
@@ -367,6 +1568,30 @@ impl Content {
             }
             return true;
           }
+          ScrollEvent(point, delta) => {
+            debug!("content got scroll event: %? at %?", delta, point);
+            self.settle_largest_contentful_paint();
+            self.handle_scroll(point, delta);
+            return true;
+          }
+          KeyEvent(key) => {
+            debug!("content got key event: %?", key);
+            self.settle_largest_contentful_paint();
+            self.handle_key(key);
+            return true;
+          }
+          PointerInputEvent(move input) => {
+            debug!("content got pointer event: %s", input.phase.event_type());
+            if input.phase == PointerDown {
+                self.settle_largest_contentful_paint();
+            }
+            self.handle_pointer(move input);
+            return true;
+          }
+          PaintedEvent(ref paint) => {
+            self.time_paint(paint);
+            return true;
+          }
         }
     }
 }
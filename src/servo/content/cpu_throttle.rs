@@ -0,0 +1,121 @@
+/*!
+Keeps scripts under the `--cpu-limit`. A ticker task interrupts the content
+task's JS runtime once a second; each interruption compares how much CPU
+time the process used over the last second with the budget, and sleeps off
+the excess before letting the script carry on.
+
+The ticker runs without a limit too, as the content task's operation
+callback is also where scripts are stopped once it's told to exit.
+*/
+
+use comm::{Port, Chan};
+use task::spawn_listener;
+use std::timer::recv_timeout;
+use std::uv_global_loop;
+use std::time::precise_time_ns;
+use js::jsapi::JSRuntime;
+use js::jsapi::bindgen::JS_TriggerOperationCallback;
+
+const TICK_MS: uint = 1000;
+
+pub struct CpuThrottle {
+    // The share of one CPU scripts may use, from 0 to 1
+    budget: float,
+    mut last_wall_ns: u64,
+    mut last_cpu_ns: u64,
+}
+
+/// A throttle allowing `percent` percent of one CPU.
+pub fn CpuThrottle(percent: uint) -> CpuThrottle {
+    CpuThrottle {
+        budget: (percent as float) / 100.0,
+        last_wall_ns: precise_time_ns(),
+        last_cpu_ns: process_cpu_time_ns(),
+    }
+}
+
+impl CpuThrottle {
+    /// Sleeps for as long as the process went over budget since the last
+    /// check.
+    fn throttle(&self) {
+        let ms = self.sample(precise_time_ns(), process_cpu_time_ns());
+        if ms > 0 {
+            debug!("cpu throttle: sleeping for %u ms", ms);
+            std::timer::sleep(uv_global_loop::get(), ms);
+            // The sleep doesn't count against the next second
+            self.last_wall_ns = precise_time_ns();
+        }
+    }
+
+    /**
+    Records the wall clock and CPU times, returning how many milliseconds to
+    sleep to make up for any CPU used over the budget since the last sample.
+    Each sample starts a new bucket, so overuse isn't carried forward.
+    */
+    fn sample(&self, wall_ns: u64, cpu_ns: u64) -> uint {
+        let wall = (wall_ns - self.last_wall_ns) as float;
+        let cpu = (cpu_ns - self.last_cpu_ns) as float;
+        self.last_wall_ns = wall_ns;
+        self.last_cpu_ns = cpu_ns;
+        if wall <= 0.0 {
+            return 0;
+        }
+        let actual = cpu / wall;
+        if actual <= self.budget {
+            0
+        } else {
+            ((actual - self.budget) * (TICK_MS as float)) as uint
+        }
+    }
+}
+
+pub enum TickerMsg {
+    ExitMsg
+}
+
+pub type CpuTicker = Chan<TickerMsg>;
+
+/// Interrupts the scripts running in `rt` once a second, so that the
+/// runtime's operation callback can throttle or stop them.
+pub fn CpuTicker(rt: *JSRuntime) -> CpuTicker {
+    // Raw pointers can't be sent, but triggering the callback from
+    // another thread is what it's for
+    let rt_addr = rt as uint;
+    do spawn_listener |port: Port<TickerMsg>| {
+        loop {
+            match recv_timeout(uv_global_loop::get(), TICK_MS, &port) {
+                Some(ExitMsg) => break,
+                None => JS_TriggerOperationCallback(rt_addr as *JSRuntime)
+            }
+        }
+    }
+}
+
+/// The CPU time used by this process so far, in nanoseconds.
+pub fn process_cpu_time_ns() -> u64 {
+    // POSIX requires CLOCKS_PER_SEC to be a million
+    (unsafe { clock() } as u64) * 1000
+}
+
+extern {
+    fn clock() -> libc::c_long;
+}
+
+#[test]
+fn test_sample_under_budget() {
+    let throttle = CpuThrottle(50);
+    throttle.last_wall_ns = 0;
+    throttle.last_cpu_ns = 0;
+    assert throttle.sample(1000000000, 400000000) == 0;
+}
+
+#[test]
+fn test_sample_over_budget() {
+    let throttle = CpuThrottle(25);
+    throttle.last_wall_ns = 0;
+    throttle.last_cpu_ns = 0;
+    // 75% used against a 25% budget: sleep off the extra half second
+    assert throttle.sample(1000000000, 750000000) == 500;
+    // The next bucket starts empty
+    assert throttle.sample(2000000000, 750000000) == 0;
+}
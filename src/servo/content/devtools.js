@@ -0,0 +1,470 @@
+/*
+The debugger side of devtools, run in a compartment of its own. It answers
+Chrome DevTools Protocol messages with SpiderMonkey's Debugger API. Rust
+gives it:
+
+  debuggee  the page's global
+  send(s)   sends the CDP message s to the client
+  pause()   handles the client's messages until `paused` is false again
+
+and calls receive() with each message from the client, and consoleMessage()
+when the page logs something.
+*/
+
+var dbg = new Debugger();
+var global = dbg.addDebuggee(debuggee);
+var enabled = false;
+var consoleEnabled = false;
+
+// Set while stopped at a breakpoint or step; `resumeAction` is how to go on
+var paused = false;
+var resumeAction = null;
+var pausedFrames = [];
+
+// Debugger.Objects and Environments the client has ids for, until the
+// next resume
+var objects = {};
+var nextObjectId = 1;
+
+var scriptIds = new Map();
+var scriptsById = {};
+var nextScriptId = 1;
+
+var breakpoints = [];
+var nextBreakpointId = 1;
+
+// Frames with step handlers set, to clear when the step is done
+var steppingFrames = [];
+
+function ProtocolError(code, message) {
+  this.code = code;
+  this.message = message;
+}
+
+function event(method, params) {
+  send(JSON.stringify({ method: method, params: params }));
+}
+
+function scriptId(script) {
+  if (!script) {
+    return "0";
+  }
+  if (!scriptIds.has(script)) {
+    var id = String(nextScriptId++);
+    scriptIds.set(script, id);
+    scriptsById[id] = script;
+  }
+  return scriptIds.get(script);
+}
+
+// A script and the functions in it
+function allScripts(script) {
+  var scripts = [script];
+  script.getChildScripts().forEach(function(child) {
+    scripts = scripts.concat(allScripts(child));
+  });
+  return scripts;
+}
+
+function location(script, offset) {
+  var loc = script.getOffsetLocation(offset);
+  return { scriptId: scriptId(script), lineNumber: loc.lineNumber - 1,
+           columnNumber: loc.columnNumber };
+}
+
+function objectId(value) {
+  var id = String(nextObjectId++);
+  objects[id] = value;
+  return id;
+}
+
+// A debuggee value as a CDP RemoteObject
+function remote(value) {
+  if (value === undefined) {
+    return { type: "undefined" };
+  }
+  if (value === null) {
+    return { type: "object", subtype: "null", value: null };
+  }
+  if (value instanceof Debugger.Object) {
+    if (value.callable) {
+      return { type: "function", className: "Function", objectId: objectId(value),
+               description: "function " + (value.name || "") + "()" };
+    }
+    var result = { type: "object", className: value.class, description: value.class,
+                   objectId: objectId(value) };
+    if (value.class == "Array") {
+      result.subtype = "array";
+    } else if (value.class == "Error") {
+      result.subtype = "error";
+    }
+    return result;
+  }
+  if (typeof value == "symbol") {
+    return { type: "symbol", description: value.toString() };
+  }
+  if (typeof value == "number" && (!isFinite(value) || (value === 0 && 1 / value < 0))) {
+    return { type: "number", unserializableValue: String(value),
+             description: Object.is(value, -0) ? "-0" : String(value) };
+  }
+  return { type: typeof value, value: value, description: String(value) };
+}
+
+// A completion value, from eval, as Runtime.evaluate's result
+function evaluationResult(completion) {
+  if (completion === null) {
+    return { result: { type: "undefined" },
+             exceptionDetails: { exceptionId: 0, text: "Script terminated", lineNumber: 0,
+                                 columnNumber: 0 } };
+  }
+  if ("throw" in completion) {
+    return { result: remote(completion.throw),
+             exceptionDetails: { exceptionId: 0, text: "Uncaught", lineNumber: 0,
+                                 columnNumber: 0, exception: remote(completion.throw) } };
+  }
+  return { result: remote(completion.return) };
+}
+
+function scopeType(env, frame) {
+  if (env.type == "with") {
+    return "with";
+  }
+  if (env.type == "object") {
+    return env.parent ? "with" : "global";
+  }
+  return env === frame.environment ? "local" : "closure";
+}
+
+function callFrame(frame, index) {
+  var scopeChain = [];
+  for (var env = frame.environment; env; env = env.parent) {
+    var type = scopeType(env, frame);
+    scopeChain.push({ type: type,
+                      object: { type: "object", className: "Object", description: type,
+                                objectId: objectId(env) } });
+  }
+  return {
+    callFrameId: String(index),
+    functionName: frame.callee ? (frame.callee.displayName || "") : "",
+    location: frame.script ? location(frame.script, frame.offset)
+                           : { scriptId: "0", lineNumber: 0, columnNumber: 0 },
+    url: frame.script ? frame.script.url : "",
+    scopeChain: scopeChain,
+    this: remote(frame.this)
+  };
+}
+
+function currentLine(frame) {
+  return frame.script.getOffsetLocation(frame.offset).lineNumber;
+}
+
+function clearSteps() {
+  dbg.onEnterFrame = undefined;
+  steppingFrames.forEach(function(frame) {
+    if (frame.live) {
+      frame.onStep = undefined;
+      frame.onPop = undefined;
+    }
+  });
+  steppingFrames = [];
+}
+
+// Stops in the next line run in `frame`, or in its caller once it returns
+function stepOver(frame) {
+  if (!frame.script) {
+    return;
+  }
+  var line = currentLine(frame);
+  steppingFrames.push(frame);
+  frame.onStep = function() {
+    if (currentLine(this) != line) {
+      clearSteps();
+      return pauseIn(this, "other", []);
+    }
+    return undefined;
+  };
+  frame.onPop = function(completion) {
+    var older = this.older;
+    clearSteps();
+    if (older && older.script) {
+      steppingFrames.push(older);
+      older.onStep = function() {
+        clearSteps();
+        return pauseIn(this, "other", []);
+      };
+    }
+    return undefined;
+  };
+}
+
+// As stepOver, but also stops in any function called first
+function stepInto(frame) {
+  stepOver(frame);
+  dbg.onEnterFrame = function(entered) {
+    clearSteps();
+    return pauseIn(entered, "other", []);
+  };
+}
+
+// Tells the client where script stopped, and waits for it to go on
+function pauseIn(frame, reason, hitBreakpoints) {
+  pausedFrames = [];
+  for (var f = frame; f; f = f.older) {
+    pausedFrames.push(f);
+  }
+  event("Debugger.paused", { callFrames: pausedFrames.map(callFrame), reason: reason,
+                             hitBreakpoints: hitBreakpoints });
+  paused = true;
+  resumeAction = "resume";
+  pause();
+  pausedFrames = [];
+  objects = {};
+  event("Debugger.resumed", {});
+  if (frame.live) {
+    if (resumeAction == "stepOver") {
+      stepOver(frame);
+    } else if (resumeAction == "stepInto") {
+      stepInto(frame);
+    }
+  }
+  return undefined;
+}
+
+function resume(action) {
+  if (!paused) {
+    throw new ProtocolError(-32000, "Can only perform operation while paused.");
+  }
+  paused = false;
+  resumeAction = action;
+  return {};
+}
+
+// Sets `bp` in `script` if it has code on its line, returning where
+function setBreakpointIn(script, bp) {
+  var offsets = script.getLineOffsets(bp.lineNumber + 1);
+  if (offsets.length == 0) {
+    return [];
+  }
+  script.setBreakpoint(offsets[0], bp.handler);
+  return [location(script, offsets[0])];
+}
+
+function onNewScript(script) {
+  event("Debugger.scriptParsed", {
+    scriptId: scriptId(script),
+    url: script.url || "",
+    startLine: script.startLine - 1,
+    startColumn: 0,
+    endLine: script.startLine + script.lineCount - 2,
+    endColumn: 0,
+    executionContextId: 1,
+    hash: ""
+  });
+  breakpoints.forEach(function(bp) {
+    if (bp.url != script.url) {
+      return;
+    }
+    allScripts(script).forEach(function(s) {
+      setBreakpointIn(s, bp).forEach(function(loc) {
+        event("Debugger.breakpointResolved", { breakpointId: bp.id, location: loc });
+      });
+    });
+  });
+}
+
+function requireEnabled() {
+  if (!enabled) {
+    throw new ProtocolError(-32000, "Debugger agent is not enabled");
+  }
+}
+
+function getProperties(params) {
+  var target = objects[params.objectId];
+  if (!target) {
+    throw new ProtocolError(-32000, "Could not find object with given id");
+  }
+  var result = [];
+  if (target instanceof Debugger.Environment) {
+    target.names().forEach(function(name) {
+      var value = target.getVariable(name);
+      if (value && (value.optimizedOut || value.uninitialized || value.missingArguments)) {
+        return;
+      }
+      result.push({ name: name, value: remote(value), writable: true, configurable: false,
+                    enumerable: true, isOwn: true });
+    });
+    return { result: result };
+  }
+  target.getOwnPropertyNames().forEach(function(name) {
+    var desc = target.getOwnPropertyDescriptor(name);
+    var prop = { name: name, configurable: desc.configurable, enumerable: desc.enumerable,
+                 isOwn: true };
+    if ("value" in desc) {
+      prop.value = remote(desc.value);
+      prop.writable = desc.writable;
+    } else {
+      if (desc.get) {
+        prop.get = remote(desc.get);
+      }
+      if (desc.set) {
+        prop.set = remote(desc.set);
+      }
+    }
+    result.push(prop);
+  });
+  if (!params.ownProperties && target.proto) {
+    result.push({ name: "__proto__", value: remote(target.proto), writable: true,
+                  configurable: true, enumerable: false, isOwn: true });
+  }
+  return { result: result };
+}
+
+var handlers = {
+  "Debugger.enable": function() {
+    if (!enabled) {
+      enabled = true;
+      dbg.onNewScript = onNewScript;
+      dbg.findScripts().forEach(function(script) {
+        if (script.url) {
+          onNewScript(script);
+        }
+      });
+    }
+    return { debuggerId: "servo" };
+  },
+
+  "Debugger.disable": function() {
+    enabled = false;
+    dbg.onNewScript = undefined;
+    clearSteps();
+    return {};
+  },
+
+  "Debugger.setBreakpointByUrl": function(params) {
+    requireEnabled();
+    if (params.url === undefined) {
+      throw new ProtocolError(-32602, "Either url or urlRegex must be specified.");
+    }
+    var bp = { id: String(nextBreakpointId++) + ":" + params.lineNumber + ":" + params.url,
+               url: params.url, lineNumber: params.lineNumber };
+    bp.handler = {
+      hit: function(frame) {
+        if (params.condition) {
+          var completion = frame.eval(params.condition);
+          if (completion && "return" in completion && !completion.return) {
+            return undefined;
+          }
+        }
+        clearSteps();
+        return pauseIn(frame, "other", [bp.id]);
+      }
+    };
+    breakpoints.push(bp);
+    var locations = [];
+    dbg.findScripts({ url: params.url, line: params.lineNumber + 1 }).forEach(function(s) {
+      locations = locations.concat(setBreakpointIn(s, bp));
+    });
+    return { breakpointId: bp.id, locations: locations };
+  },
+
+  "Debugger.removeBreakpoint": function(params) {
+    breakpoints = breakpoints.filter(function(bp) {
+      if (bp.id != params.breakpointId) {
+        return true;
+      }
+      dbg.findScripts({ url: bp.url }).forEach(function(s) {
+        s.clearBreakpoint(bp.handler);
+      });
+      return false;
+    });
+    return {};
+  },
+
+  "Debugger.resume": function() { return resume("resume"); },
+  "Debugger.stepOver": function() { return resume("stepOver"); },
+  "Debugger.stepInto": function() { return resume("stepInto"); },
+
+  "Debugger.getScriptSource": function(params) {
+    var script = scriptsById[params.scriptId];
+    if (!script) {
+      throw new ProtocolError(-32000, "No script for id: " + params.scriptId);
+    }
+    return { scriptSource: script.source.text };
+  },
+
+  "Debugger.evaluateOnCallFrame": function(params) {
+    var frame = pausedFrames[Number(params.callFrameId)];
+    if (!frame) {
+      throw new ProtocolError(-32000, "Could not find call frame with given id");
+    }
+    return evaluationResult(frame.eval(params.expression));
+  },
+
+  "Runtime.enable": function() {
+    event("Runtime.executionContextCreated", {
+      context: { id: 1, origin: "", name: "", auxData: { isDefault: true } }
+    });
+    return {};
+  },
+
+  "Runtime.evaluate": function(params) {
+    return evaluationResult(global.executeInGlobal(params.expression, { url: "devtools" }));
+  },
+
+  "Runtime.getProperties": getProperties,
+
+  "Console.enable": function() {
+    consoleEnabled = true;
+    return {};
+  },
+
+  "Console.disable": function() {
+    consoleEnabled = false;
+    return {};
+  }
+};
+
+// Answers one message from the client
+function receive(text) {
+  var message;
+  try {
+    message = JSON.parse(text);
+  } catch (e) {
+    return JSON.stringify({ error: { code: -32700, message: "Message must be valid JSON" } });
+  }
+  try {
+    var handler = handlers[message.method];
+    if (!handler) {
+      throw new ProtocolError(-32601, "'" + message.method + "' wasn't found");
+    }
+    return JSON.stringify({ id: message.id, result: handler(message.params || {}) });
+  } catch (e) {
+    var error = e instanceof ProtocolError ? { code: e.code, message: e.message }
+                                           : { code: -32000, message: String(e) };
+    return JSON.stringify({ id: message.id, error: error });
+  }
+}
+
+// Something the page logged, with console.log or by throwing
+function consoleMessage(source, level, text, url, line) {
+  if (consoleEnabled) {
+    event("Console.messageAdded", {
+      message: { source: source, level: level, text: text, url: url, line: Number(line) }
+    });
+  }
+}
+
+// The client went away
+function detach() {
+  clearSteps();
+  breakpoints.forEach(function(bp) {
+    dbg.findScripts({ url: bp.url }).forEach(function(s) {
+      s.clearBreakpoint(bp.handler);
+    });
+  });
+  breakpoints = [];
+  dbg.onNewScript = undefined;
+  dbg.removeAllDebuggees();
+  paused = false;
+  resumeAction = "resume";
+}
@@ -0,0 +1,165 @@
+/*!
+The content task's end of devtools. A connected client's CDP messages are
+answered by `devtools.js`, which runs in a compartment of its own with
+SpiderMonkey's `Debugger` watching the page's global. While script is
+stopped at a breakpoint, the content task handles only the client's
+messages, until it's told to go on.
+*/
+
+use js::rust::{cx, compartment, methods};
+use js::global::global_class;
+use js::{JS_ARGV, JS_SET_RVAL, JSVAL_VOID, JSVAL_NULL};
+use js::jsapi::{JSContext, JSVal, JSBool};
+use js::jsapi::bindgen::{JS_DefineDebuggerObject, JS_WrapObject, JS_EnterCompartment,
+                         JS_LeaveCompartment, JS_SetProperty, JS_GetProperty,
+                         JS_CallFunctionValue, JS_DefineFunctions, JS_ClearPendingException};
+use js::glue::bindgen::{RUST_OBJECT_TO_JSVAL, RUST_BOOLEAN_TO_JSVAL};
+use libc::c_uint;
+use ptr::null;
+
+use content::content_task::task_from_context;
+use dom::bindings::utils::{domstring_to_jsval, jsval_to_str, str};
+
+const DEVTOOLS_JS: &static/str = include_str!("devtools.js");
+
+pub struct DevtoolsClient {
+    cx: cx,
+    // Where devtools.js runs
+    compartment: compartment,
+    // Where the client's messages go
+    chan: pipes::SharedChan<~str>,
+}
+
+// send(message)
+extern fn send(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+    if argc < 1 {
+        return 0;
+    }
+    let message = match jsval_to_str(cx, *argv) {
+        Ok(move message) => move message,
+        Err(()) => return 0
+    };
+    for (*task_from_context(cx)).devtools.each |client| {
+        client.chan.send(copy message);
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    1
+}
+
+// pause(): returns once the client says to go on
+extern fn pause(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    (*task_from_context(cx)).wait_while_paused();
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    1
+}
+
+/**
+Starts debugging `debuggee`, the page's compartment, for a client whose
+messages go to `chan`. None if SpiderMonkey can't make the debugger's
+compartment.
+*/
+pub fn DevtoolsClient(cx: cx, debuggee: &compartment,
+                      chan: pipes::SharedChan<~str>) -> Option<@DevtoolsClient> {
+    let compartment = match cx.new_compartment(global_class) {
+        Ok(move compartment) => move compartment,
+        Err(()) => return None
+    };
+    let global = compartment.global_obj.ptr;
+    unsafe {
+        let old = JS_EnterCompartment(cx.ptr, global);
+        JS_DefineDebuggerObject(cx.ptr, global);
+
+        // The page's global, as this compartment sees it
+        let wrapped = debuggee.global_obj.ptr;
+        JS_WrapObject(cx.ptr, ptr::to_unsafe_ptr(&wrapped));
+        let val = RUST_OBJECT_TO_JSVAL(wrapped);
+        do str::as_c_str("debuggee") |s| {
+            JS_SetProperty(cx.ptr, global, s, ptr::to_unsafe_ptr(&val));
+        }
+
+        let methods = ~[{name: compartment.add_name(~"send"),
+                         call: {op: send, info: null()},
+                         nargs: 1,
+                         flags: 0,
+                         selfHostedName: null()},
+                        {name: compartment.add_name(~"pause"),
+                         call: {op: pause, info: null()},
+                         nargs: 0,
+                         flags: 0,
+                         selfHostedName: null()}];
+        vec::as_imm_buf(methods, |fns, _len| {
+            JS_DefineFunctions(cx.ptr, global, fns);
+        });
+        JS_LeaveCompartment(cx.ptr, old);
+    }
+
+    let client = @DevtoolsClient { cx: cx, compartment: move compartment, chan: move chan };
+    cx.evaluate_script(client.compartment.global_obj, str::to_bytes(DEVTOOLS_JS),
+                       ~"devtools.js", 1u);
+    Some(client)
+}
+
+impl DevtoolsClient {
+    // Calls a function of devtools.js with string arguments, giving back
+    // what it returned as a string
+    priv fn call(name: &str, args: ~[~str]) -> Option<~str> unsafe {
+        let cx = self.cx.ptr;
+        let global = self.compartment.global_obj.ptr;
+        let old = JS_EnterCompartment(cx, global);
+        let fun = JSVAL_NULL;
+        do str::as_c_str(name) |s| {
+            JS_GetProperty(cx, global, s, ptr::to_unsafe_ptr(&fun));
+        }
+        let argv = args.map(|arg| domstring_to_jsval(cx, &str(copy *arg)));
+        let rval = JSVAL_VOID;
+        let ok = do vec::as_imm_buf(argv) |argv, argc| {
+            JS_CallFunctionValue(cx, global, fun, argc as c_uint, argv,
+                                 ptr::to_unsafe_ptr(&rval))
+        };
+        let result = if ok == 0 {
+            JS_ClearPendingException(cx);
+            None
+        } else {
+            match jsval_to_str(cx, rval) {
+                Ok(move s) => Some(move s),
+                Err(()) => None
+            }
+        };
+        JS_LeaveCompartment(cx, old);
+        result
+    }
+
+    /// Answers a message from the client.
+    fn handle_message(message: ~str) {
+        match self.call("receive", ~[move message]) {
+            Some(move response) => self.chan.send(move response),
+            None => #error("devtools: couldn't answer a message")
+        }
+    }
+
+    /// Tells the client of something the page logged. `source` is
+    /// `console-api` for console.log and friends, `javascript` for errors.
+    fn console_message(source: &str, level: &str, text: &str, url: &str, line: uint) {
+        self.call("consoleMessage", ~[source.to_str(), level.to_str(), text.to_str(),
+                                      url.to_str(), line.to_str()]);
+    }
+
+    /// Whether script is stopped, waiting for the client.
+    fn is_paused() -> bool unsafe {
+        let cx = self.cx.ptr;
+        let global = self.compartment.global_obj.ptr;
+        let old = JS_EnterCompartment(cx, global);
+        let paused = JSVAL_NULL;
+        do str::as_c_str("paused") |s| {
+            JS_GetProperty(cx, global, s, ptr::to_unsafe_ptr(&paused));
+        }
+        JS_LeaveCompartment(cx, old);
+        paused == RUST_BOOLEAN_TO_JSVAL(1)
+    }
+
+    /// Stops debugging, letting script go on if it's stopped.
+    fn detach() {
+        self.call("detach", ~[]);
+    }
+}
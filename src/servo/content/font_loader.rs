@@ -0,0 +1,49 @@
+/*!
+Loading the fonts of a page's `@font-face` rules. Each font is fetched on
+a task of its own and handed to layout, and then the page is laid out
+again so that text can be shaped with it. Until then, layout draws the
+text as the face's `font-display` says.
+*/
+
+use comm::Port;
+use dom::event::{Event, ReflowEvent};
+use html::cssparse::WebFontFace;
+use layout::layout_task::{LayoutTask, WebFontRequested, WebFontLoaded};
+use resource::resource_task;
+use resource::resource_task::ResourceTask;
+use std::net::url::Url;
+use util::url::url_to_str;
+
+/// Fetches the font of each face, for layout to add to its font cache.
+pub fn load_web_fonts(faces: ~[WebFontFace], resource_task: ResourceTask,
+                      layout_task: LayoutTask, event_chan: pipes::SharedChan<Event>) {
+    for vec::consume(move faces) |_i, face| {
+        // Its `font-display` periods start now
+        layout_task.send(WebFontRequested(copy face.family, face.display));
+        let event_chan = event_chan.clone();
+        do task::spawn |move face, copy resource_task, move event_chan| {
+            let data = fetch(&face.url, resource_task);
+            layout_task.send(WebFontLoaded(copy face.family, move data));
+            event_chan.send(ReflowEvent);
+        }
+    }
+}
+
+fn fetch(url: &Url, resource_task: ResourceTask) -> Result<~[u8], ()> {
+    let response_port = Port();
+    resource_task.send(resource_task::Load(copy *url, response_port.chan()));
+
+    let mut data = ~[];
+    loop {
+        match response_port.recv() {
+            resource_task::Payload(move chunk) => data.push_all_move(move chunk),
+            resource_task::Done(Ok(*)) => return Ok(move data),
+            resource_task::Done(Err(*)) => {
+                #debug("font_loader: couldn't load %s", url_to_str(copy *url));
+                return Err(());
+            }
+            resource_task::ContentType(*) | resource_task::Header(*) |
+            resource_task::Timing(*) => ()
+        }
+    }
+}
@@ -0,0 +1,193 @@
+/*!
+Finding and fetching ES modules. An `import` names its module with a
+specifier, which is resolved against the URL of the module that imports
+it, then fetched through the resource task. There are no import maps, so
+a bare name like `import "lodash"` can't be resolved.
+*/
+
+use comm::Port;
+use std::future;
+use std::future::Future;
+use std::net::url;
+use std::net::url::Url;
+use util::url::{url_host, url_to_str};
+use resource::resource_task;
+use resource::resource_task::{ResourceTask, FetchTiming};
+
+pub enum ModuleError {
+    // Neither a URL nor a path starting with "/", "./" or "../"
+    BareSpecifier(~str),
+    FetchFailed(~str),
+    NotUtf8(~str),
+    // The source didn't compile, or the module didn't link
+    InvalidModule(~str),
+}
+
+impl ModuleError : cmp::Eq {
+    pure fn eq(&self, other: &ModuleError) -> bool {
+        match (copy *self, copy *other) {
+            (BareSpecifier(a), BareSpecifier(b)) => a == b,
+            (FetchFailed(a), FetchFailed(b)) => a == b,
+            (NotUtf8(a), NotUtf8(b)) => a == b,
+            (InvalidModule(a), InvalidModule(b)) => a == b,
+            _ => false
+        }
+    }
+    pure fn ne(&self, other: &ModuleError) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+impl ModuleError {
+    fn message(&self) -> ~str {
+        match *self {
+            BareSpecifier(ref specifier) => {
+                fmt!("module specifier \"%s\" doesn't start with \"/\", \"./\" or \"../\"",
+                     *specifier)
+            }
+            FetchFailed(ref url) => fmt!("couldn't fetch module %s", *url),
+            NotUtf8(ref url) => fmt!("module %s isn't UTF-8", *url),
+            InvalidModule(ref url) => fmt!("module %s is invalid", *url)
+        }
+    }
+}
+
+/// `path` without its "." and ".." segments, as RFC 3986 has them removed.
+pub fn remove_dot_segments(path: &str) -> ~str {
+    let segments = str::split_char(path, '/');
+    let mut output: ~[~str] = ~[];
+    for segments.eachi |i, segment| {
+        if i == 0 {
+            // Before the leading slash
+            loop;
+        }
+        let last = i == segments.len() - 1;
+        match *segment {
+            ~"." => (),
+            ~".." => {
+                if !output.is_empty() {
+                    output.pop();
+                }
+            }
+            _ => {
+                output.push(copy *segment);
+                loop;
+            }
+        }
+        // A path that ends in a dot segment names a directory
+        if last {
+            output.push(~"");
+        }
+    }
+    ~"/" + str::connect(output, "/")
+}
+
+/**
+The URL a module specifier names, coming from the module (or document) at
+`referrer_url`. A specifier is either a whole URL or a path, and paths
+must start with "/", "./" or "../" so that bare names are kept for import
+maps.
+*/
+pub fn resolve_module(specifier: &str, referrer_url: &Url) -> Result<Url, ModuleError> {
+    let relative = specifier.starts_with("/") || specifier.starts_with("./") ||
+                   specifier.starts_with("../");
+    if !relative {
+        return match url::get_scheme(specifier) {
+            Ok(*) => match url::from_str(specifier) {
+                Ok(move url) => Ok(move url),
+                Err(*) => Err(BareSpecifier(str::from_slice(specifier)))
+            },
+            Err(*) => Err(BareSpecifier(str::from_slice(specifier)))
+        };
+    }
+
+    let path = if specifier.starts_with("//") {
+        // Scheme-relative
+        return match url::from_str(referrer_url.scheme + ":" + specifier) {
+            Ok(move url) => Ok(move url),
+            Err(*) => Err(BareSpecifier(str::from_slice(specifier)))
+        };
+    } else if specifier.starts_with("/") {
+        str::from_slice(specifier)
+    } else {
+        let directory = match str::rfind_char(referrer_url.path, '/') {
+            Some(i) => referrer_url.path.slice(0, i + 1),
+            None => ~"/"
+        };
+        directory + specifier
+    };
+
+    // The query and fragment aren't part of the path
+    let (path, rest) = match str::find(path, |c| c == '?' || c == '#') {
+        Some(i) => (path.slice(0, i), path.slice(i, path.len())),
+        None => (copy path, ~"")
+    };
+    let port = match referrer_url.port {
+        Some(ref port) => ~":" + *port,
+        None => ~""
+    };
+    let resolved = fmt!("%s://%s%s%s%s", referrer_url.scheme, url_host(referrer_url.host), port,
+                        remove_dot_segments(path), rest);
+    match url::from_str(resolved) {
+        Ok(move url) => Ok(move url),
+        Err(*) => Err(BareSpecifier(str::from_slice(specifier)))
+    }
+}
+
+/// Fetches the source of the module at `url` in the background, with how
+/// long that took.
+pub fn fetch_module(url: Url, resource_task: ResourceTask)
+    -> Future<Result<(~str, Option<FetchTiming>), ModuleError>> {
+    do future::spawn |move url, move resource_task| {
+        let response_port = Port();
+        resource_task.send(resource_task::Load(copy url, response_port.chan()));
+
+        let mut source = ~[];
+        let mut timing = None;
+        loop {
+            match response_port.recv() {
+                resource_task::ContentType(*) | resource_task::Header(*) => (),
+                resource_task::Payload(data) => source += data,
+                resource_task::Timing(move t) => timing = Some(move t),
+                resource_task::Done(Ok(*)) => break,
+                resource_task::Done(Err(*)) => return Err(FetchFailed(url_to_str(copy url)))
+            }
+        }
+        if str::is_utf8(source) {
+            Ok((str::from_bytes(source), move timing))
+        } else {
+            Err(NotUtf8(url_to_str(copy url)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod module_loader_tests {
+    fn referrer() -> Url {
+        url::from_str("http://example.com:8000/app/js/main.js?v=2").get()
+    }
+
+    fn resolve(specifier: &str) -> ~str {
+        url_to_str(resolve_module(specifier, &referrer()).get())
+    }
+
+    #[test]
+    fn test_remove_dot_segments() {
+        assert remove_dot_segments("/a/b/c/./../../g") == ~"/a/g";
+        assert remove_dot_segments("/a/b/..") == ~"/a/";
+        assert remove_dot_segments("/../a") == ~"/a";
+        assert remove_dot_segments("/a/b") == ~"/a/b";
+    }
+
+    #[test]
+    fn test_resolve_module() {
+        assert resolve("./util.js") == ~"http://example.com:8000/app/js/util.js";
+        assert resolve("../lib/dom.js") == ~"http://example.com:8000/app/lib/dom.js";
+        assert resolve("/vendor/x.js") == ~"http://example.com:8000/vendor/x.js";
+        assert resolve("https://cdn.example.net/m.js") == ~"https://cdn.example.net/m.js";
+        assert resolve("./a/../b.js?x=1") == ~"http://example.com:8000/app/js/b.js?x=1";
+
+        assert resolve_module("lodash", &referrer()).get_err() == BareSpecifier(~"lodash");
+        assert resolve_module("util.js", &referrer()).get_err() == BareSpecifier(~"util.js");
+    }
+}
@@ -0,0 +1,86 @@
+/*!
+The microtask queue. SpiderMonkey hands the content task a job each time a
+promise settles with reactions waiting on it; the jobs wait here until the
+task that queued them has finished, then all run before the next task
+starts. Jobs can queue more jobs, which run in the same checkpoint.
+
+A job is rooted from when it's queued until it has run, as nothing else
+holds on to it in the meantime.
+*/
+
+use core::dlist::DList;
+use js::jsapi::{JSContext, JSVal};
+use dom::bindings::rooting::RootedValues;
+
+pub struct PromiseQueue {
+    // The keys of the job functions in `roots`, oldest first
+    priv jobs: DList<uint>,
+    priv roots: RootedValues,
+}
+
+pub fn PromiseQueue(cx: *JSContext) -> PromiseQueue {
+    PromiseQueue { jobs: DList(), roots: RootedValues(cx) }
+}
+
+impl PromiseQueue {
+    fn enqueue(&self, job: JSVal) {
+        self.jobs.push(self.roots.add(job));
+    }
+
+    fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    fn len(&self) -> uint {
+        self.jobs.len()
+    }
+
+    /// Drops the queued jobs without running them.
+    fn clear(&self) {
+        while self.jobs.pop().is_some() {}
+        self.roots.clear();
+    }
+
+    /**
+    Runs the queued jobs in order with `run` until none are left, including
+    any that are queued while it's draining. Returns how many ran.
+    */
+    fn drain(&self, run: fn(JSVal)) -> uint {
+        let mut ran = 0;
+        loop {
+            match self.jobs.pop() {
+                Some(key) => {
+                    // Still rooted while it runs
+                    run(self.roots.get(key));
+                    self.roots.remove(key);
+                    ran += 1;
+                }
+                None => break
+            }
+        }
+        ran
+    }
+}
+
+#[test]
+fn test_drain() {
+    let queue = PromiseQueue(ptr::null());
+    assert queue.drain(|_job| fail) == 0;
+
+    queue.enqueue(1);
+    queue.enqueue(2);
+    assert queue.len() == 2;
+
+    // Jobs queued while draining run after the ones already there
+    let mut order = ~[];
+    let ran = do queue.drain |job| {
+        order.push(job);
+        if job == 1 {
+            queue.enqueue(3);
+        }
+    };
+    assert ran == 3;
+    assert order == ~[1, 2, 3];
+    assert queue.is_empty();
+    assert queue.roots.len() == 0;
+}
@@ -0,0 +1,90 @@
+/*!
+Forced colors mode: as each node is styled, replaces the colors the page
+gave with the user's system colors, so that everything is drawn in their
+high contrast palette. Elements with `forced-color-adjust: none`, and
+everything under them, keep their own colors.
+*/
+
+use css::values::forced_colors::{SystemColor, Canvas, CanvasText, LinkText, ButtonFace,
+                                 ButtonText, ButtonBorder, Field, FieldText, system_color};
+use dom::element::{HTMLAnchorElement, HTMLButtonElement, HTMLInputElement, HTMLSelectElement,
+                   HTMLTextAreaElement};
+use dom::node::{Element, Node};
+use newcss::color::rgba;
+use newcss::values::{Specified, BgColor, BdrColor, TextColor};
+
+// The system colors an element's background, text and border are drawn in
+fn palette_for(node: Node) -> Option<(SystemColor, SystemColor, SystemColor)> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => Some(match e.kind {
+                ~HTMLButtonElement => (ButtonFace, ButtonText, ButtonBorder),
+                ~HTMLInputElement(*) | ~HTMLSelectElement | ~HTMLTextAreaElement => {
+                    (Field, FieldText, ButtonBorder)
+                }
+                ~HTMLAnchorElement => (Canvas, LinkText, LinkText),
+                _ => (Canvas, CanvasText, CanvasText)
+            }),
+            _ => None
+        }
+    }
+}
+
+// Whether the element opts its subtree out of forced colors
+fn keeps_own_colors(node: Node) -> bool {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => match e.get_style_property("forced-color-adjust") {
+                Some(ref value) => str::trim(*value) == ~"none",
+                None => false
+            },
+            _ => false
+        }
+    }
+}
+
+/**
+Forces the colors of `node`, once it's been styled, unless it or an
+element above it keeps its own colors. A background keeps the
+transparency the page gave it, so that what's behind still shows where it
+did; the root's background is always drawn, as it's the canvas. Text
+takes the palette's text color even where the page gave none.
+*/
+pub fn force_node_colors(node: Node) {
+    // forced-color-adjust is inherited
+    let mut cur = Some(node);
+    loop {
+        match cur {
+            Some(n) if keeps_own_colors(n) => return,
+            Some(n) => cur = n.read(|n| n.tree.parent),
+            None => break
+        }
+    }
+    let is_root = node.read(|n| n.tree.parent).is_none();
+    match palette_for(node) {
+        Some((background, text, border)) => do node.aux |layout| {
+            let bg = system_color(background);
+            match layout.style.background_color {
+                Specified(BgColor(c)) => {
+                    layout.style.background_color =
+                        Specified(BgColor(rgba(bg.red, bg.green, bg.blue, c.alpha)));
+                }
+                // form controls are filled even without a background of
+                // their own, so they mustn't keep their scheme's color
+                _ if is_root || background == Field => {
+                    layout.style.background_color = Specified(BgColor(bg));
+                }
+                _ => ()
+            }
+            // the default black could be lost against the forced background
+            layout.style.text_color = Specified(TextColor(system_color(text)));
+            match layout.style.border_color {
+                Specified(BdrColor(_)) => {
+                    layout.style.border_color = Specified(BdrColor(system_color(border)));
+                }
+                _ => ()
+            }
+        },
+        None => ()
+    }
+}
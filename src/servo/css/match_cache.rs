@@ -0,0 +1,246 @@
+/*!
+Remembers which rules of the style sheet matched an element, so that the
+many elements of a page that look the same to the style sheet only go
+through selector matching once.
+
+Elements look the same when they have the same fingerprint: tag name, id,
+class list and parent's tag name. A rule is only looked up in the cache if
+its selectors can't see anything else about the element; rules with
+descendant or sibling selectors, or that test other attributes, are always
+matched afresh. So entries never go stale when the page changes. Changing
+an element's id or class changes its fingerprint, and changing any other
+attribute only affects rules that aren't cached. Only a new style sheet
+empties the cache.
+*/
+
+use dom::node::{Element, Node};
+use newcss::values::{Stylesheet, Selector, Attr, Exists, Exact, Includes, StartsWith};
+use std::map::HashMap;
+
+/// What the cacheable selectors can see of an element.
+pub struct Fingerprint {
+    tag: ~str,
+    id: Option<~str>,
+    // The distinct class names, sorted; None without a class attribute
+    classes: Option<~[~str]>,
+    parent_tag: Option<~str>,
+}
+
+impl Fingerprint {
+    // One string per fingerprint, for the cache's keys. Control characters
+    // keep the parts apart, as they can't appear in names.
+    fn key(&self) -> ~str {
+        let part = |value: &Option<~str>| match *value {
+            Some(ref value) => ~"\x01" + *value,
+            None => ~"\x02"
+        };
+        let classes = match self.classes {
+            Some(ref classes) => Some(str::connect(*classes, "\x03")),
+            None => None
+        };
+        str::connect(~[copy self.tag, part(&self.id), part(&classes), part(&self.parent_tag)],
+                     "\x04")
+    }
+}
+
+fn tag_name(node: Node) -> Option<~str> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => Some(copy e.tag_name),
+            _ => None
+        }
+    }
+}
+
+/// The fingerprint of `node`, if it's an element.
+pub fn fingerprint(node: Node) -> Option<Fingerprint> {
+    let parent_tag = match node.read(|n| n.tree.parent) {
+        Some(parent) => tag_name(parent),
+        None => None
+    };
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => {
+                let classes = do e.get_attr("class").map |value| {
+                    let mut names = ~[];
+                    for str::split_char(*value, ' ').each |name| {
+                        if !name.is_empty() && !vec::contains(names, name) {
+                            names.push(copy *name);
+                        }
+                    }
+                    sort::quick_sort3(names);
+                    move names
+                };
+                Some(Fingerprint {
+                    tag: copy e.tag_name,
+                    id: e.get_attr("id"),
+                    classes: move classes,
+                    parent_tag: copy parent_tag
+                })
+            }
+            _ => None
+        }
+    }
+}
+
+// Whether the fingerprint decides if `attr` matches
+fn attr_is_cacheable(attr: &Attr) -> bool {
+    match *attr {
+        Exists(ref name) => *name == ~"id" || *name == ~"class",
+        Exact(ref name, _) => *name == ~"id",
+        Includes(ref name, _) => *name == ~"class",
+        StartsWith(*) => false
+    }
+}
+
+// Whether the fingerprint decides if `sel` matches. A child selector is,
+// as long as the parent is only picked by tag.
+fn selector_is_cacheable(sel: &Selector) -> bool {
+    match *sel {
+        newcss::values::Element(_, ref attrs) => vec::all(*attrs, |attr| attr_is_cacheable(attr)),
+        newcss::values::Child(ref parent, ref child) => {
+            let parent_by_tag = match **parent {
+                newcss::values::Element(_, ref attrs) => attrs.is_empty(),
+                _ => false
+            };
+            parent_by_tag && selector_is_cacheable(*child)
+        }
+        newcss::values::Descendant(*) | newcss::values::Sibling(*) => false
+    }
+}
+
+/// Whether each rule of `styles` can be looked up in the cache.
+pub fn cacheable_rules(styles: &Stylesheet) -> ~[bool] {
+    do styles.map |rule| {
+        let (ref selectors, _) = **rule;
+        vec::all(*selectors, |sel| selector_is_cacheable(*sel))
+    }
+}
+
+pub struct MatchCache {
+    // The indices of the cacheable rules that matched, in order, by
+    // fingerprint key
+    priv entries: HashMap<~str, @~[uint]>,
+    // `cacheable_rules` of the style sheet, worked out on first use
+    priv mut cacheable: Option<@~[bool]>,
+    priv mut hits: uint,
+    priv mut misses: uint,
+}
+
+pub fn MatchCache() -> MatchCache {
+    MatchCache {
+        entries: HashMap(),
+        cacheable: None,
+        hits: 0,
+        misses: 0
+    }
+}
+
+impl MatchCache {
+    /// Which rules of `styles` can be looked up in the cache.
+    fn cacheable_rules(&self, styles: &Stylesheet) -> @~[bool] {
+        match self.cacheable {
+            Some(cacheable) => cacheable,
+            None => {
+                let cacheable = @cacheable_rules(styles);
+                self.cacheable = Some(cacheable);
+                cacheable
+            }
+        }
+    }
+
+    /// The cacheable rules an element with this fingerprint matched.
+    fn find(&self, fingerprint: &Fingerprint) -> Option<@~[uint]> {
+        let found = self.entries.find(fingerprint.key());
+        if found.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        found
+    }
+
+    fn insert(&self, fingerprint: &Fingerprint, rules: ~[uint]) {
+        self.entries.insert(fingerprint.key(), @move rules);
+    }
+
+    /// Forgets everything, for when the style sheet changes.
+    fn clear(&self) {
+        self.entries.clear();
+        self.cacheable = None;
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    fn len(&self) -> uint {
+        self.entries.size()
+    }
+
+    /// How many lookups found an entry, and how many didn't.
+    fn stats(&self) -> (uint, uint) {
+        (self.hits, self.misses)
+    }
+}
+
+#[cfg(test)]
+mod match_cache_tests {
+    use dom::element::{Attr, ElementData, HTMLDivElement};
+    use dom::node::NodeScope;
+
+    #[allow(non_implicitly_copyable_typarams)]
+    fn new_element(scope: &NodeScope, attrs: ~[(~str, ~str)]) -> Node {
+        let elmt = ElementData(~"div", ~HTMLDivElement);
+        for attrs.each |attr| {
+            let (ref name, ref value) = *attr;
+            elmt.attrs.push(~Attr(copy *name, copy *value));
+        }
+        scope.new_node(dom::node::Element(move elmt))
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let scope = NodeScope();
+        let parent = new_element(&scope, ~[]);
+        let a = new_element(&scope, ~[(~"class", ~"b a  b")]);
+        let b = new_element(&scope, ~[(~"class", ~"a b")]);
+        let c = new_element(&scope, ~[(~"class", ~"a b"), (~"id", ~"c")]);
+        scope.add_child(parent, a);
+        scope.add_child(parent, b);
+
+        let key = |node| fingerprint(node).get().key();
+        assert fingerprint(a).get().classes == Some(~[~"a", ~"b"]);
+        assert key(a) == key(b);
+        assert key(b) != key(c);
+        // c has no parent
+        assert fingerprint(c).get().parent_tag.is_none();
+    }
+
+    #[test]
+    fn test_cacheable_selectors() {
+        let by_class = newcss::values::Element(~"div", ~[Includes(~"class", ~"a")]);
+        let by_lang = newcss::values::Element(~"*", ~[Exists(~"lang")]);
+        assert selector_is_cacheable(&by_class);
+        assert !selector_is_cacheable(&by_lang);
+        assert selector_is_cacheable(&newcss::values::Child(~newcss::values::Element(~"p", ~[]),
+                                                            ~copy by_class));
+        assert !selector_is_cacheable(&newcss::values::Descendant(
+            ~newcss::values::Element(~"p", ~[]), ~move by_class));
+    }
+
+    #[test]
+    fn test_find_and_clear() {
+        let scope = NodeScope();
+        let a = new_element(&scope, ~[(~"id", ~"x")]);
+        let cache = MatchCache();
+        let print = fingerprint(a).get();
+
+        assert cache.find(&print).is_none();
+        cache.insert(&print, ~[0, 2]);
+        assert *cache.find(&print).get() == ~[0, 2];
+        assert cache.stats() == (1, 1);
+
+        cache.clear();
+        assert cache.len() == 0;
+        assert cache.find(&print).is_none();
+    }
+}
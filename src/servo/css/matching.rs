@@ -2,8 +2,9 @@
    Performs CSS selector matching.
 */
 
-use dom::node::{LayoutData, Node, Text};
+use dom::node::{LayoutData, Node, NodeTree, Text};
 use dom::element::ElementData;
+use css::match_cache::{MatchCache, fingerprint};
 
 use newcss::values::*;
 use styles::{SpecifiedStyle};
@@ -195,7 +196,8 @@ impl Node : PrivStyleMethods {
 }
 
 trait MatchingMethods {
-    fn match_css_style(styles : &Stylesheet);
+    fn match_css_style(styles : &Stylesheet, cache : &MatchCache);
+    fn query_selector_all(sel : &Selector) -> ~[Node];
 }
 
 impl Node : MatchingMethods {
@@ -203,26 +205,64 @@ impl Node : MatchingMethods {
     Compare an html element to a list of css rules and update its
     style according to the rules matching it.
     */
-    fn match_css_style(styles : &Stylesheet) {
+    fn match_css_style(styles : &Stylesheet, cache : &MatchCache) {
         // Loop over each rule, see if our node matches what is
         // described in the rule. If it matches, update its style. As
         // we don't currently have priorities of style information,
         // the latest rule takes precedence over the others. So we
         // just overwrite style information as we go.
-
-        for styles.each |sty| {
+        //
+        // Rules the cache can answer for are looked up by the node's
+        // fingerprint; the first node with a fingerprint fills it in.
+
+        let cacheable = cache.cacheable_rules(styles);
+        let print = fingerprint(self);
+        let cached = match print {
+            Some(ref print) => cache.find(print),
+            None => None
+        };
+        let mut matched = ~[];
+
+        for styles.eachi |i, sty| {
             let (selectors, decls) = copy **sty;
-            for selectors.each |sel| {
-                if self.matches_selector(*sel) {
-                    for decls.each |decl| {
-                        self.update_style(*decl);
-                    }
+            let matches = match cached {
+                Some(rules) if cacheable[i] => vec::contains(*rules, &i),
+                _ => selectors.any(|sel| self.matches_selector(*sel))
+            };
+            if matches {
+                if cacheable[i] {
+                    matched.push(i);
+                }
+                for decls.each |decl| {
+                    self.update_style(*decl);
                 }
             }
         }
+
+        match (cached, print) {
+            (None, Some(ref print)) => cache.insert(print, move matched),
+            _ => ()
+        }
         
         self.aux(|a| debug!("Changed the style to: %?", copy *a.style));
     }
+
+    /**
+    Returns the elements in this subtree, including this node, that match
+    the selector, in document order.
+    */
+    fn query_selector_all(sel : &Selector) -> ~[Node] {
+        let mut result = ~[];
+        // Only descendants can match, so the walk starts at the first child
+        for NodeTree.each_child(&self) |child| {
+            do child.traverse_preorder |node| {
+                if node.matches_selector(sel) {
+                    result.push(node);
+                }
+            }
+        }
+        move result
+    }
 }
 
 #[cfg(test)]
@@ -359,4 +399,24 @@ mod test {
         assert ggchild.matches_selector(~copy sel4);
         assert gggchild.matches_selector(~move sel4);
     }
+
+    #[test]
+    fn test_query_selector_all() {
+        let scope = NodeScope();
+
+        let root = new_node_from_attr(&scope, ~"class", ~"blue");
+        let child1 = new_node_from_attr(&scope, ~"flag", ~"black");
+        let child2 = new_node_from_attr(&scope, ~"id", ~"green");
+        let gchild = new_node_from_attr(&scope, ~"flag", ~"grey");
+
+        scope.add_child(root, child1);
+        scope.add_child(root, child2);
+        scope.add_child(child1, gchild);
+
+        let sel = Element(~"div", ~[Exists(~"flag")]);
+        assert root.query_selector_all(~copy sel) == ~[child1, gchild];
+        assert child2.query_selector_all(~copy sel).is_empty();
+        // the node queried from isn't among the results, even if it matches
+        assert child1.query_selector_all(~move sel) == ~[gchild];
+    }
 }
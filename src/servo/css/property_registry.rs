@@ -0,0 +1,523 @@
+/*!
+Registered custom properties, from `CSS.registerProperty`. Registering a
+`--name` gives it a syntax, which says what values it takes and how they
+interpolate, so changes to it can be transitioned instead of jumping.
+
+While a property transitions, the content task samples it each frame and
+gives the element its value, which `var()` references to it in the
+element's `style` attribute are replaced with.
+
+TODO: the style system doesn't know custom properties, so registered ones
+are only seen in `style` attributes, and only by the properties layout
+reads from there. `@property` rules aren't parsed, and transitions are
+linear whatever their timing function.
+*/
+
+use dvec::DVec;
+use dom::node::Node;
+
+/// One of the types a registered property's syntax can name.
+pub enum SyntaxComponent {
+    LengthSyntax,
+    NumberSyntax,
+    IntegerSyntax,
+    PercentageSyntax,
+    ColorSyntax,
+}
+
+impl SyntaxComponent : cmp::Eq {
+    pure fn eq(&self, other: &SyntaxComponent) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &SyntaxComponent) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// What values a registered property takes.
+pub enum PropertySyntax {
+    // `*`: any value, which can't be interpolated
+    UniversalSyntax,
+    // `<length> | <color>`: any one of these types
+    Components(~[SyntaxComponent]),
+}
+
+/// A value of a registered property, parsed by its syntax.
+pub enum TypedValue {
+    // In px
+    LengthValue(float),
+    NumberValue(float),
+    IntegerValue(int),
+    PercentageValue(float),
+    // Red, green and blue from 0 to 255, and alpha from 0 to 1
+    ColorValue(float, float, float, float),
+    // A value of the universal syntax, as written
+    TokenValue(~str),
+}
+
+impl TypedValue : cmp::Eq {
+    pure fn eq(&self, other: &TypedValue) -> bool {
+        match (copy *self, copy *other) {
+            (LengthValue(a), LengthValue(b)) => a == b,
+            (NumberValue(a), NumberValue(b)) => a == b,
+            (IntegerValue(a), IntegerValue(b)) => a == b,
+            (PercentageValue(a), PercentageValue(b)) => a == b,
+            (ColorValue(r1, g1, b1, a1), ColorValue(r2, g2, b2, a2)) => {
+                r1 == r2 && g1 == g2 && b1 == b2 && a1 == a2
+            }
+            (TokenValue(a), TokenValue(b)) => a == b,
+            _ => false
+        }
+    }
+    pure fn ne(&self, other: &TypedValue) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+impl TypedValue {
+    /// The value as CSS text, as `var()` references to it are replaced with.
+    fn to_css(&self) -> ~str {
+        match *self {
+            LengthValue(px) => float::to_str(px, 3) + "px",
+            NumberValue(n) => float::to_str(n, 3),
+            IntegerValue(n) => int::str(n),
+            PercentageValue(p) => float::to_str(p, 3) + "%",
+            ColorValue(r, g, b, a) => {
+                fmt!("rgba(%d, %d, %d, %s)", float::round(r) as int, float::round(g) as int,
+                     float::round(b) as int, float::to_str(a, 3))
+            }
+            TokenValue(ref text) => copy *text
+        }
+    }
+}
+
+pub struct PropertyDefinition {
+    name: ~str,
+    syntax: PropertySyntax,
+    inherits: bool,
+    initial_value: Option<TypedValue>,
+}
+
+/// Why registering a property failed. `CSS.registerProperty` throws a
+/// SyntaxError for all but `AlreadyRegistered`.
+pub enum RegisterError {
+    InvalidName,
+    InvalidSyntax,
+    // Missing, unparseable, or depending on context, like `em` lengths
+    InvalidInitialValue,
+    AlreadyRegistered,
+}
+
+impl RegisterError : cmp::Eq {
+    pure fn eq(&self, other: &RegisterError) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &RegisterError) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+pub fn parse_syntax(value: &str) -> Option<PropertySyntax> {
+    let value = str::trim(value);
+    if value == "*" {
+        return Some(UniversalSyntax);
+    }
+    let mut components = ~[];
+    for str::split_char(value, '|').each |part| {
+        let component = match str::trim(*part) {
+            ~"<length>" => LengthSyntax,
+            ~"<number>" => NumberSyntax,
+            ~"<integer>" => IntegerSyntax,
+            ~"<percentage>" => PercentageSyntax,
+            ~"<color>" => ColorSyntax,
+            _ => return None
+        };
+        components.push(component);
+    }
+    Some(Components(move components))
+}
+
+fn parse_number(value: &str) -> Option<float> {
+    if value.is_empty() {
+        return None;
+    }
+    float::from_str(value)
+}
+
+// A color given as #rgb, #rrggbb, rgb(), rgba() or one of a few names
+fn parse_color(value: &str) -> Option<TypedValue> {
+    let hex = |s: &str| uint::from_str_radix(s, 16).map(|v| *v as float);
+    match value.to_str() {
+        ~"transparent" => return Some(ColorValue(0.0, 0.0, 0.0, 0.0)),
+        ~"black" => return Some(ColorValue(0.0, 0.0, 0.0, 1.0)),
+        ~"white" => return Some(ColorValue(255.0, 255.0, 255.0, 1.0)),
+        ~"red" => return Some(ColorValue(255.0, 0.0, 0.0, 1.0)),
+        ~"green" => return Some(ColorValue(0.0, 128.0, 0.0, 1.0)),
+        ~"blue" => return Some(ColorValue(0.0, 0.0, 255.0, 1.0)),
+        _ => ()
+    }
+    if value.starts_with("#") && value.len() == 7 {
+        return match (hex(value.slice(1, 3)), hex(value.slice(3, 5)), hex(value.slice(5, 7))) {
+            (Some(r), Some(g), Some(b)) => Some(ColorValue(r, g, b, 1.0)),
+            _ => None
+        };
+    }
+    if value.starts_with("#") && value.len() == 4 {
+        return match (hex(value.slice(1, 2)), hex(value.slice(2, 3)), hex(value.slice(3, 4))) {
+            (Some(r), Some(g), Some(b)) => Some(ColorValue(r * 17.0, g * 17.0, b * 17.0, 1.0)),
+            _ => None
+        };
+    }
+    let args = if value.starts_with("rgba(") && value.ends_with(")") {
+        value.slice(5, value.len() - 1)
+    } else if value.starts_with("rgb(") && value.ends_with(")") {
+        value.slice(4, value.len() - 1)
+    } else {
+        return None;
+    };
+    let parts = str::split_char(args, ',').map(|part| parse_number(str::trim(*part)));
+    if parts.any(|part| part.is_none()) {
+        return None;
+    }
+    let parts = parts.map(|part| part.get());
+    match parts.len() {
+        3 => Some(ColorValue(parts[0], parts[1], parts[2], 1.0)),
+        4 => Some(ColorValue(parts[0], parts[1], parts[2], parts[3])),
+        _ => None
+    }
+}
+
+fn parse_component(component: SyntaxComponent, value: &str) -> Option<TypedValue> {
+    match component {
+        LengthSyntax if value == "0" => Some(LengthValue(0.0)),
+        LengthSyntax if value.ends_with("px") => {
+            parse_number(value.slice(0, value.len() - 2)).map(|px| LengthValue(*px))
+        }
+        LengthSyntax => None,
+        NumberSyntax => parse_number(value).map(|n| NumberValue(*n)),
+        IntegerSyntax => int::from_str(value).map(|n| IntegerValue(*n)),
+        PercentageSyntax if value.ends_with("%") => {
+            parse_number(value.slice(0, value.len() - 1)).map(|p| PercentageValue(*p))
+        }
+        PercentageSyntax => None,
+        ColorSyntax => parse_color(value)
+    }
+}
+
+/// Parses `value` by `syntax`, trying its types in order.
+pub fn parse_value(syntax: &PropertySyntax, value: &str) -> Option<TypedValue> {
+    let value = str::trim(value);
+    match *syntax {
+        UniversalSyntax if value.is_empty() => None,
+        UniversalSyntax => Some(TokenValue(value.to_str())),
+        Components(ref components) => {
+            for components.each |component| {
+                match parse_component(*component, value) {
+                    Some(move parsed) => return Some(move parsed),
+                    None => ()
+                }
+            }
+            None
+        }
+    }
+}
+
+fn lerp(from: float, to: float, progress: float) -> float {
+    from + (to - from) * progress
+}
+
+/**
+The value `progress` of the way from `from` to `to`. Lengths, numbers and
+percentages interpolate linearly, integers round to the nearest, and
+colors interpolate each sRGB channel, premultiplied by alpha. Values of
+different types, or of the universal syntax, flip halfway.
+*/
+pub fn interpolate(from: &TypedValue, to: &TypedValue, progress: float) -> TypedValue {
+    match (copy *from, copy *to) {
+        (LengthValue(a), LengthValue(b)) => LengthValue(lerp(a, b, progress)),
+        (NumberValue(a), NumberValue(b)) => NumberValue(lerp(a, b, progress)),
+        (IntegerValue(a), IntegerValue(b)) => {
+            IntegerValue(float::round(lerp(a as float, b as float, progress)) as int)
+        }
+        (PercentageValue(a), PercentageValue(b)) => PercentageValue(lerp(a, b, progress)),
+        (ColorValue(r1, g1, b1, a1), ColorValue(r2, g2, b2, a2)) => {
+            let alpha = lerp(a1, a2, progress);
+            if alpha == 0.0 {
+                return ColorValue(0.0, 0.0, 0.0, 0.0);
+            }
+            let channel = |c1: float, c2: float| lerp(c1 * a1, c2 * a2, progress) / alpha;
+            ColorValue(channel(r1, r2), channel(g1, g2), channel(b1, b2), alpha)
+        }
+        _ if progress < 0.5 => copy *from,
+        _ => copy *to
+    }
+}
+
+/**
+How long changes to the custom property `name` transition for, in ns, by
+an element's `transition` value: a list of a property, or `all`, and a
+duration in `s` or `ms`, with an optional delay after it. The last entry
+for the property wins.
+*/
+pub fn transition_duration(transition: &str, name: &str) -> Option<(u64, u64)> {
+    let mut found = None;
+    for str::split_char(transition, ',').each |entry| {
+        let words = str::split_char_nonempty(str::trim(*entry), ' ');
+        if words.is_empty() || words[0] != name.to_str() && words[0] != ~"all" {
+            loop;
+        }
+        let times = vec::filter_map(words.tail(), |word| parse_time(*word));
+        if times.len() == 1 {
+            found = Some((times[0], 0));
+        } else if times.len() > 1 {
+            found = Some((times[0], times[1]));
+        }
+    }
+    match found {
+        Some((0, _)) => None,
+        found => found
+    }
+}
+
+// A time in `s` or `ms`, in ns
+fn parse_time(value: &str) -> Option<u64> {
+    let ms = if value.ends_with("ms") {
+        parse_number(value.slice(0, value.len() - 2))
+    } else if value.ends_with("s") {
+        parse_number(value.slice(0, value.len() - 1)).map(|s| *s * 1000.0)
+    } else {
+        None
+    };
+    match ms {
+        Some(ms) if ms >= 0.0 => Some((ms * 1000000.0) as u64),
+        _ => None
+    }
+}
+
+struct PropertyTransition {
+    node: Node,
+    name: ~str,
+    from: TypedValue,
+    to: TypedValue,
+    // Monotonic times in ns
+    start: u64,
+    duration: u64,
+}
+
+/// The custom properties a document has registered, and the transitions
+/// running on them.
+pub struct PropertyRegistry {
+    priv definitions: DVec<PropertyDefinition>,
+    priv transitions: DVec<PropertyTransition>,
+}
+
+pub fn PropertyRegistry() -> PropertyRegistry {
+    PropertyRegistry { definitions: DVec(), transitions: DVec() }
+}
+
+impl PropertyRegistry {
+    /**
+    Registers `name`. Its initial value may only be left out with the
+    universal syntax, and only lengths in px are computationally
+    independent here.
+    */
+    fn register(&self, name: ~str, syntax: &str, inherits: bool,
+                initial_value: Option<~str>) -> Result<(), RegisterError> {
+        if !name.starts_with("--") || name.len() < 3 {
+            return Err(InvalidName);
+        }
+        let syntax = match parse_syntax(syntax) {
+            Some(move syntax) => move syntax,
+            None => return Err(InvalidSyntax)
+        };
+        let initial_value = match initial_value {
+            Some(ref value) => match parse_value(&syntax, *value) {
+                Some(move value) => Some(move value),
+                None => return Err(InvalidInitialValue)
+            },
+            None => match syntax {
+                UniversalSyntax => None,
+                _ => return Err(InvalidInitialValue)
+            }
+        };
+        if self.find(name).is_some() {
+            return Err(AlreadyRegistered);
+        }
+        self.definitions.push(PropertyDefinition {
+            name: move name,
+            syntax: move syntax,
+            inherits: inherits,
+            initial_value: move initial_value,
+        });
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Option<PropertyDefinition> {
+        do self.definitions.find |definition| { definition.name == name.to_str() }
+    }
+
+    fn each_name(&self, f: fn(&str) -> bool) {
+        for self.definitions.each |definition| {
+            if !f(definition.name) { break; }
+        }
+    }
+
+    /**
+    Notes that the registered property `name` of `node` changed from `old`
+    to `new` at `now`, and starts a transition between them if the
+    element's `transition` asks for one. A value the syntax doesn't take
+    is the initial value. Replaces any running transition, starting from
+    where it got to.
+    */
+    fn value_changed(&self, node: Node, name: &str, old: Option<~str>, new: Option<~str>,
+                     transition: Option<~str>, now: u64) {
+        let definition = match self.find(name) {
+            Some(move definition) => move definition,
+            None => return
+        };
+        let value = |text: Option<~str>| {
+            match text.chain(|text| parse_value(&definition.syntax, text)) {
+                Some(move value) => Some(move value),
+                None => copy definition.initial_value
+            }
+        };
+        let from = match self.animated_value(node, name, now) {
+            Some(move current) => Some(move current),
+            None => value(old)
+        };
+        let to = value(new);
+        self.stop(node, name);
+
+        let timing = transition.chain(|t| transition_duration(t, name));
+        match (move from, move to, timing) {
+            (Some(move from), Some(move to), Some((duration, delay))) if from != to => {
+                self.transitions.push(PropertyTransition {
+                    node: node,
+                    name: name.to_str(),
+                    from: move from,
+                    to: move to,
+                    start: now + delay,
+                    duration: duration,
+                });
+            }
+            _ => ()
+        }
+    }
+
+    /// The value of `name` on `node` at `now`, while it's transitioning.
+    fn animated_value(&self, node: Node, name: &str, now: u64) -> Option<TypedValue> {
+        for self.transitions.each |t| {
+            if t.node == node && t.name == name.to_str() {
+                if now <= t.start {
+                    return Some(copy t.from);
+                }
+                if now >= t.start + t.duration {
+                    return Some(copy t.to);
+                }
+                let progress = ((now - t.start) as float) / (t.duration as float);
+                return Some(interpolate(&t.from, &t.to, progress));
+            }
+        }
+        None
+    }
+
+    /// Whether any transitions are running, or waiting to be sampled.
+    fn is_transitioning(&self) -> bool {
+        !self.transitions.is_empty()
+    }
+
+    /**
+    Calls `f` with the node, property and value at `now` of each transition,
+    and with None for those that have finished, which are dropped. Returns
+    whether any are still running.
+    */
+    fn sample(&self, now: u64, f: fn(Node, &str, Option<TypedValue>)) -> bool {
+        let transitions = self.transitions.get();
+        for transitions.each |t| {
+            if now < t.start + t.duration {
+                f(t.node, t.name, self.animated_value(t.node, t.name, now));
+            } else {
+                f(t.node, t.name, None);
+            }
+        }
+        let running = do transitions.filter |t| { now < t.start + t.duration };
+        self.transitions.set(move running);
+        !self.transitions.is_empty()
+    }
+
+    priv fn stop(&self, node: Node, name: &str) {
+        let others = do self.transitions.get().filter |t| {
+            !(t.node == node && t.name == name.to_str())
+        };
+        self.transitions.set(move others);
+    }
+}
+
+#[cfg(test)]
+mod property_registry_tests {
+    #[test]
+    fn test_register() {
+        let registry = PropertyRegistry();
+        assert registry.register(~"--x", "<length>", false, Some(~"10px")) == Ok(());
+        assert registry.register(~"--x", "<number>", false, Some(~"1")) == Err(AlreadyRegistered);
+        assert registry.register(~"x", "*", true, None) == Err(InvalidName);
+        assert registry.register(~"--y", "<lenght>", true, None) == Err(InvalidSyntax);
+        assert registry.register(~"--y", "<color>", true, None) == Err(InvalidInitialValue);
+        assert registry.register(~"--y", "<length>", true, Some(~"2em")) ==
+            Err(InvalidInitialValue);
+        assert registry.register(~"--y", "*", true, None) == Ok(());
+        assert registry.find("--x").get().initial_value == Some(LengthValue(10.0));
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let syntax = parse_syntax("<length> | <color>").get();
+        let black = parse_value(&syntax, "#000").get();
+        let red = parse_value(&syntax, "rgba(255, 0, 0, 1)").get();
+        assert interpolate(&black, &red, 0.5) == ColorValue(127.5, 0.0, 0.0, 1.0);
+        // a transparent end doesn't darken the other
+        let clear = parse_value(&syntax, "transparent").get();
+        assert interpolate(&clear, &red, 0.5) == ColorValue(255.0, 0.0, 0.0, 0.5);
+
+        let short = parse_value(&syntax, "10px").get();
+        assert interpolate(&short, &LengthValue(20.0), 0.25) == LengthValue(12.5);
+        assert interpolate(&IntegerValue(1), &IntegerValue(4), 0.5) == IntegerValue(3);
+        // mismatched types flip halfway
+        assert interpolate(&short, &red, 0.4) == short;
+        assert interpolate(&short, &red, 0.5) == red;
+    }
+
+    #[test]
+    fn test_sample() {
+        let registry = PropertyRegistry();
+        assert registry.register(~"--size", "<length>", false, Some(~"0px")) == Ok(());
+        let scope = dom::node::NodeScope();
+        let node = scope.new_node(dom::node::Text(~""));
+        registry.value_changed(node, "--size", Some(~"0px"), Some(~"100px"),
+                               Some(~"--size 1s"), 0);
+
+        let sampled = DVec();
+        assert registry.sample(250000000, |_node, name, value| {
+            sampled.push((name.to_str(), value));
+        });
+        assert sampled.get() == ~[(~"--size", Some(LengthValue(25.0)))];
+        assert LengthValue(25.0).to_css() == ~"25px";
+
+        let finished = DVec();
+        assert !registry.sample(1000000000, |_node, name, value| {
+            finished.push((name.to_str(), value));
+        });
+        assert finished.get() == ~[(~"--size", None)];
+        assert registry.animated_value(node, "--size", 1000000000).is_none();
+    }
+
+    #[test]
+    fn test_transition_duration() {
+        assert transition_duration("--x 1s", "--x") == Some((1000000000, 0));
+        assert transition_duration("color 1s, --x 200ms 50ms", "--x") ==
+            Some((200000000, 50000000));
+        assert transition_duration("all 2s", "--x") == Some((2000000000, 0));
+        assert transition_duration("--y 1s", "--x").is_none();
+        assert transition_duration("--x 0s", "--x").is_none();
+    }
+}
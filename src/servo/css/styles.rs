@@ -8,10 +8,12 @@ use newcss::values::*;
 use newcss::values::Stylesheet;
 use dom::element::{HTMLDivElement, HTMLHeadElement, HTMLImageElement, UnknownElement, HTMLScriptElement};
 use dom::node::{Comment, Doctype, Element, Text,
-                Node, NodeKind, NodeTree, LayoutData};
+                Node, NodeKind, LayoutData};
 use newcss::color::{Color, rgb};
 use newcss::color::css_colors::{white, black};
 use layout::context::LayoutContext;
+use css::forced_colors::force_node_colors;
+use css::match_cache::MatchCache;
 
 #[allow(non_implicitly_copyable_typarams)]
 type SpecifiedStyle = {mut background_color : CSSValue<CSSBackgroundColor>,
@@ -107,7 +109,6 @@ trait StyleMethods {
 
     fn style() -> SpecifiedStyle;
     fn initialize_style_for_subtree(ctx: &LayoutContext, refs: &DVec<@LayoutData>);
-    fn recompute_style_for_subtree(ctx: &LayoutContext, styles : &Stylesheet);
 }
 
 impl Node : StyleMethods {
@@ -120,7 +121,8 @@ impl Node : StyleMethods {
                 let node_kind = self.read(|n| copy *n.kind);
                 let data = @LayoutData({
                     mut style : ~empty_style_for_node_kind(&node_kind),
-                    mut flow  : None
+                    mut flow  : None,
+                    mut dirty : true
                 });
                 self.set_aux(data); Some(data)
             },
@@ -143,33 +145,76 @@ impl Node : StyleMethods {
     /**
      * Initializes layout data and styles for a Node tree, if any nodes do not have
      * this data already. Append created layout data to the task's GC roots.
+     *
+     * Every node's style is marked out of date, as content doesn't say
+     * which nodes it changed; the Styler matches them again as layout gets
+     * to them.
      */
     fn initialize_style_for_subtree(_ctx: &LayoutContext, refs: &DVec<@LayoutData>) {
         do self.traverse_preorder |n| {
             match n.initialize_layout_data() {
                 Some(r) => refs.push(r),
-                None => {}
+                None => n.aux(|data| data.dirty = true)
             }
         }
     }
+}
 
-    /**
-     * Performs CSS selector matching on a subtree.
+/**
+Styles nodes when layout first looks at them, rather than the whole tree
+up front, so that nodes layout never gets to, like everything under a
+`display: none` element, are never matched against the style sheet.
+*/
+pub struct Styler {
+    priv mut stylesheet: Option<@Stylesheet>,
+    priv match_cache: MatchCache,
+    // Whether styled nodes get the high contrast palette
+    priv mut forced_colors: bool,
+}
 
-     * This is, importantly, the function that updates the layout data for
-     * the node (the reader-auxiliary box in the COW model) with the
-     * computed style.
-     */
-    fn recompute_style_for_subtree(ctx: &LayoutContext, styles : &Stylesheet) {
-        let mut i = 0u;
-        
-        // Compute the styles of each of our children in parallel
-        for NodeTree.each_child(&self) |kid| {
-            i = i + 1u;
-            kid.recompute_style_for_subtree(ctx, styles); 
+pub fn Styler() -> Styler {
+    Styler {
+        stylesheet: None,
+        match_cache: MatchCache(),
+        forced_colors: false
+    }
+}
+
+impl Styler {
+    fn set_stylesheet(&self, sheet: Stylesheet) {
+        assert self.stylesheet.is_none(); // FIXME: Support multiple sheets
+        self.stylesheet = Some(@move sheet);
+        self.match_cache.clear();
+    }
+
+    fn set_forced_colors(&self, forced_colors: bool) {
+        self.forced_colors = forced_colors;
+    }
+
+    /// Styles `node`, if its style is out of date.
+    fn ensure_style(&self, node: Node) {
+        if !node.has_aux() || !node.aux(|data| data.dirty) {
+            return;
         }
+        // Start over, so that rules that no longer match leave nothing behind
+        let node_kind = node.read(|n| copy *n.kind);
+        do node.aux |data| {
+            data.style = ~empty_style_for_node_kind(&node_kind);
+            data.dirty = false;
+        }
+        match self.stylesheet {
+            Some(sheet) => node.match_css_style(sheet, &self.match_cache),
+            None => ()
+        }
+        if self.forced_colors {
+            force_node_colors(node);
+        }
+    }
 
-        self.match_css_style(styles);
+    /// The match cache's size, hits and misses, for debugging.
+    fn match_cache_stats(&self) -> (uint, uint, uint) {
+        let (hits, misses) = self.match_cache.stats();
+        (self.match_cache.len(), hits, misses)
     }
 }
 
@@ -0,0 +1,99 @@
+/*!
+`aspect-ratio` values: the ratio of width to height a box keeps when only
+one of them is given.
+*/
+
+use au = gfx::geometry;
+use gfx::geometry::Au;
+
+pub struct AspectRatio {
+    // width over height, if a ratio was given
+    ratio: Option<float>,
+    // `auto`: a replaced element's natural ratio wins over `ratio`
+    auto: bool,
+}
+
+impl AspectRatio {
+    /// The ratio to size a box by, given its natural ratio if it has one.
+    pure fn used_ratio(natural: Option<float>) -> Option<float> {
+        match (self.auto, natural) {
+            (true, Some(natural)) => Some(natural),
+            _ => self.ratio
+        }
+    }
+}
+
+pub pure fn height_for_width(width: Au, ratio: float) -> Au {
+    Au(float::floor(*width as float / ratio + 0.5) as i32)
+}
+
+pub pure fn width_for_height(height: Au, ratio: float) -> Au {
+    Au(float::floor(*height as float * ratio + 0.5) as i32)
+}
+
+// `<number> [ / <number> ]?`, as width over height. A ratio with a zero
+// in it is degenerate, and treated as if there weren't one.
+fn parse_ratio(value: &str) -> Option<Option<float>> {
+    let parts = str::split_char(value, '/');
+    let numbers = vec::filter_map(parts, |p| float::from_str(str::trim(*p)));
+    if numbers.len() != parts.len() || numbers.any(|n| *n < 0.0) {
+        return None;
+    }
+    match numbers.len() {
+        1 => Some(if numbers[0] > 0.0 { Some(numbers[0]) } else { None }),
+        2 if numbers[0] > 0.0 && numbers[1] > 0.0 => Some(Some(numbers[0] / numbers[1])),
+        2 => Some(None),
+        _ => None
+    }
+}
+
+/// Parses an `aspect-ratio` value: `auto`, a ratio like `16 / 9`, or both.
+pub fn parse_aspect_ratio(value: &str) -> Option<AspectRatio> {
+    let value = str::trim(value);
+    if value == "auto" {
+        return Some(AspectRatio { ratio: None, auto: true });
+    }
+    let (ratio, auto) = if value.starts_with("auto ") {
+        (value.slice(5, value.len()), true)
+    } else if value.ends_with(" auto") {
+        (value.slice(0, value.len() - 5), true)
+    } else {
+        (value.to_str(), false)
+    };
+    parse_ratio(ratio).map(|r| AspectRatio { ratio: *r, auto: auto })
+}
+
+#[cfg(test)]
+mod aspect_ratio_tests {
+    #[test]
+    fn test_parse_aspect_ratio() {
+        let ratio = parse_aspect_ratio("16 / 9").get();
+        assert ratio.ratio == Some(16.0 / 9.0) && !ratio.auto;
+        assert parse_aspect_ratio("2/1").get().ratio == Some(2.0);
+        assert parse_aspect_ratio("1.5").get().ratio == Some(1.5);
+
+        let auto = parse_aspect_ratio("auto").get();
+        assert auto.ratio.is_none() && auto.auto;
+        let both = parse_aspect_ratio("auto 4 / 3").get();
+        assert both.ratio == Some(4.0 / 3.0) && both.auto;
+        assert parse_aspect_ratio("4/3 auto").get().auto;
+
+        // degenerate
+        assert parse_aspect_ratio("0 / 1").get().ratio.is_none();
+        assert parse_aspect_ratio("wide").is_none();
+        assert parse_aspect_ratio("-1 / 2").is_none();
+        assert parse_aspect_ratio("1 / 2 / 3").is_none();
+    }
+
+    #[test]
+    fn test_used_ratio() {
+        let fixed = AspectRatio { ratio: Some(2.0), auto: false };
+        assert fixed.used_ratio(Some(1.0)) == Some(2.0);
+        let auto = AspectRatio { ratio: Some(2.0), auto: true };
+        assert auto.used_ratio(Some(1.0)) == Some(1.0);
+        assert auto.used_ratio(None) == Some(2.0);
+
+        assert height_for_width(au::from_px(160), 16.0 / 9.0) == au::from_px(90);
+        assert width_for_height(au::from_px(90), 16.0 / 9.0) == au::from_px(160);
+    }
+}
@@ -0,0 +1,248 @@
+/*!
+`shape-outside` values: the shape that lines wrap around beside a float,
+instead of its box. This is only the parsing; layout::shape_outside works
+out where the shapes are.
+*/
+
+use au = gfx::geometry;
+use gfx::geometry::Au;
+
+/// A length, or a percentage of the reference box's width or height.
+pub enum ShapeLength {
+    ShapeAu(Au),
+    ShapePercent(float),
+}
+
+impl ShapeLength {
+    pure fn resolve(reference: Au) -> Au {
+        match self {
+            ShapeAu(au) => au,
+            ShapePercent(percent) => au::from_frac_px(au::to_frac_px(reference) * percent / 100.0)
+        }
+    }
+}
+
+pub enum ShapeRadius {
+    RadiusLength(ShapeLength),
+    ClosestSide,
+    FarthestSide,
+}
+
+pub struct ShapePosition {
+    x: ShapeLength,
+    y: ShapeLength,
+}
+
+pub enum BasicShape {
+    Circle(ShapeRadius, ShapePosition),
+    Ellipse(ShapeRadius, ShapeRadius, ShapePosition),
+    // top, right, bottom and left
+    Inset(ShapeLength, ShapeLength, ShapeLength, ShapeLength),
+    Polygon(~[(ShapeLength, ShapeLength)]),
+}
+
+pub enum ShapeOutside {
+    ShapeNone,
+    ShapeBasic(BasicShape),
+    // the image's url, unresolved
+    ShapeImage(~str),
+}
+
+/**
+Parses a `shape-outside` value, as written, since the case of a URL
+matters. Anything not understood is `none`, so that the float's box is
+used. Rounded corners on `inset()`, the fill rule of `polygon()` and the
+box after a URL are ignored.
+*/
+pub fn parse_shape_outside(value: &str) -> ShapeOutside {
+    match url_token(str::trim(value)) {
+        Some(move url) => return ShapeImage(move url),
+        None => ()
+    }
+    let value = str::to_lower(str::trim(value));
+    let shape = match function_args(value, "circle") {
+        Some(args) => parse_circle(args),
+        None => match function_args(value, "ellipse") {
+            Some(args) => parse_ellipse(args),
+            None => match function_args(value, "inset") {
+                Some(args) => parse_inset(args),
+                None => match function_args(value, "polygon") {
+                    Some(args) => parse_polygon(args),
+                    None => None
+                }
+            }
+        }
+    };
+    match move shape {
+        Some(move shape) => ShapeBasic(move shape),
+        None => ShapeNone
+    }
+}
+
+// What's between the parentheses of `name(...)`
+fn function_args(value: &str, name: &str) -> Option<~str> {
+    let prefix = fmt!("%s(", name);
+    if value.starts_with(prefix) && value.ends_with(")") {
+        Some(str::trim(value.slice(prefix.len(), value.len() - 1)))
+    } else {
+        None
+    }
+}
+
+/**
+The URL of a `url(...)` at the start of `value`, in any case, and quoted or
+not. It ends at its closing parenthesis, or quote, so parentheses and
+semicolons inside a quoted URL, like a `data:` one, are part of it.
+*/
+fn url_token(value: &str) -> Option<~str> {
+    if value.len() < 4 || str::to_lower(value.slice(0, 4)) != ~"url(" {
+        return None;
+    }
+    let rest = str::trim_left(value.slice(4, value.len()));
+    if rest.starts_with("\"") || rest.starts_with("'") {
+        let quote = rest.char_at(0);
+        return match str::find_char_from(rest, quote, 1) {
+            Some(end) if str::trim_left(rest.slice(end + 1, rest.len())).starts_with(")") => {
+                Some(rest.slice(1, end))
+            }
+            _ => None
+        };
+    }
+    match str::find_char(rest, ')') {
+        Some(end) => Some(str::trim(rest.slice(0, end))),
+        None => None
+    }
+}
+
+fn parse_length(value: &str) -> Option<ShapeLength> {
+    if value == "0" {
+        Some(ShapeAu(Au(0)))
+    } else if value.ends_with("px") {
+        float::from_str(value.slice(0, value.len() - 2)).map(|px| ShapeAu(au::from_frac_px(*px)))
+    } else if value.ends_with("%") {
+        float::from_str(value.slice(0, value.len() - 1)).map(|percent| ShapePercent(*percent))
+    } else {
+        None
+    }
+}
+
+fn parse_radius(value: &str) -> Option<ShapeRadius> {
+    match value.to_str() {
+        ~"closest-side" => Some(ClosestSide),
+        ~"farthest-side" => Some(FarthestSide),
+        _ => parse_length(value).map(|length| RadiusLength(*length))
+    }
+}
+
+// A keyword, and whether it's for the vertical position
+fn position_keyword(value: &str) -> Option<(ShapeLength, bool)> {
+    match value.to_str() {
+        ~"left" => Some((ShapePercent(0.0), false)),
+        ~"right" => Some((ShapePercent(100.0), false)),
+        ~"top" => Some((ShapePercent(0.0), true)),
+        ~"bottom" => Some((ShapePercent(100.0), true)),
+        ~"center" => Some((ShapePercent(50.0), false)),
+        _ => None
+    }
+}
+
+/// A position of one or two words, which default to the center.
+fn parse_position(words: &[~str]) -> Option<ShapePosition> {
+    let center = ShapePercent(50.0);
+    let component = |word: &~str| match position_keyword(*word) {
+        Some(keyword) => Some(keyword),
+        None => parse_length(*word).map(|length| (*length, false))
+    };
+    match words.len() {
+        0 => Some(ShapePosition { x: center, y: center }),
+        1 => match component(&words[0]) {
+            Some((length, true)) => Some(ShapePosition { x: center, y: length }),
+            Some((length, false)) => Some(ShapePosition { x: length, y: center }),
+            None => None
+        },
+        2 => match (component(&words[0]), component(&words[1])) {
+            // `top left` gives the vertical position first
+            (Some((y, true)), Some((x, _))) => Some(ShapePosition { x: x, y: y }),
+            (Some((x, _)), Some((y, _))) => Some(ShapePosition { x: x, y: y }),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+// The words before `at`, and the position after it
+fn split_at_position(args: &str) -> Option<(~[~str], ShapePosition)> {
+    let words = str::split_char_nonempty(args, ' ');
+    match vec::position(words, |w| *w == ~"at") {
+        Some(i) => parse_position(vec::slice(words, i + 1, words.len()))
+                       .map(|position| (vec::slice(words, 0, i), *position)),
+        None => parse_position([]).map(|position| (copy words, *position))
+    }
+}
+
+fn parse_circle(args: &str) -> Option<BasicShape> {
+    match split_at_position(args) {
+        Some((radius, position)) => match radius.len() {
+            0 => Some(Circle(ClosestSide, position)),
+            1 => parse_radius(radius[0]).map(|r| Circle(*r, position)),
+            _ => None
+        },
+        None => None
+    }
+}
+
+fn parse_ellipse(args: &str) -> Option<BasicShape> {
+    match split_at_position(args) {
+        Some((radii, position)) => match radii.len() {
+            0 => Some(Ellipse(ClosestSide, ClosestSide, position)),
+            2 => match (parse_radius(radii[0]), parse_radius(radii[1])) {
+                (Some(rx), Some(ry)) => Some(Ellipse(rx, ry, position)),
+                _ => None
+            },
+            _ => None
+        },
+        None => None
+    }
+}
+
+fn parse_inset(args: &str) -> Option<BasicShape> {
+    let words = str::split_char_nonempty(args, ' ');
+    let words = match vec::position(words, |w| *w == ~"round") {
+        Some(i) => vec::slice(words, 0, i),
+        None => move words
+    };
+    let lengths = vec::filter_map(words, |w| parse_length(*w));
+    if lengths.len() != words.len() {
+        return None;
+    }
+    // as with margin and padding
+    match lengths.len() {
+        1 => Some(Inset(lengths[0], lengths[0], lengths[0], lengths[0])),
+        2 => Some(Inset(lengths[0], lengths[1], lengths[0], lengths[1])),
+        3 => Some(Inset(lengths[0], lengths[1], lengths[2], lengths[1])),
+        4 => Some(Inset(lengths[0], lengths[1], lengths[2], lengths[3])),
+        _ => None
+    }
+}
+
+fn parse_polygon(args: &str) -> Option<BasicShape> {
+    let mut points = ~[];
+    for str::split_char(args, ',').each |point| {
+        let words = str::split_char_nonempty(*point, ' ');
+        if words.len() == 1 && (words[0] == ~"nonzero" || words[0] == ~"evenodd") {
+            loop;
+        }
+        if words.len() != 2 {
+            return None;
+        }
+        match (parse_length(words[0]), parse_length(words[1])) {
+            (Some(x), Some(y)) => points.push((x, y)),
+            _ => return None
+        }
+    }
+    if points.len() < 3 {
+        None
+    } else {
+        Some(Polygon(move points))
+    }
+}
@@ -0,0 +1,107 @@
+/*!
+`break-before`, `break-after` and `break-inside`, which say where pages
+may, must or mustn't end, and the older `page-break-*` properties they
+replace. Only page breaks are kept: column and region breaks read as
+`auto`, as there are no columns or regions to break.
+*/
+
+/// A break before or after an element.
+pub enum BreakBetween {
+    BreakAuto,
+    // Don't end the page here if it can be helped
+    BreakAvoid,
+    // End the page here. `left`, `right`, `recto` and `verso` are too, as
+    // pages aren't told apart by side
+    BreakPage,
+}
+
+impl BreakBetween : cmp::Eq {
+    pure fn eq(&self, other: &BreakBetween) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &BreakBetween) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// A break inside an element.
+pub enum BreakInside {
+    InsideAuto,
+    // Keep the element on one page, if it fits on one
+    InsideAvoid,
+}
+
+impl BreakInside : cmp::Eq {
+    pure fn eq(&self, other: &BreakInside) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &BreakInside) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// Parses `break-before` or `break-after`.
+pub fn parse_break_between(value: &str) -> Option<BreakBetween> {
+    match str::to_lower(str::trim(value)) {
+        ~"auto" | ~"avoid-column" | ~"column" | ~"avoid-region" | ~"region" => Some(BreakAuto),
+        ~"avoid" | ~"avoid-page" => Some(BreakAvoid),
+        ~"always" | ~"all" | ~"page" | ~"left" | ~"right" | ~"recto" | ~"verso" => {
+            Some(BreakPage)
+        }
+        _ => None
+    }
+}
+
+/// Parses `page-break-before` or `page-break-after`.
+pub fn parse_page_break_between(value: &str) -> Option<BreakBetween> {
+    match str::to_lower(str::trim(value)) {
+        ~"auto" => Some(BreakAuto),
+        ~"avoid" => Some(BreakAvoid),
+        ~"always" | ~"left" | ~"right" => Some(BreakPage),
+        _ => None
+    }
+}
+
+/// Parses `break-inside`.
+pub fn parse_break_inside(value: &str) -> Option<BreakInside> {
+    match str::to_lower(str::trim(value)) {
+        ~"auto" | ~"avoid-column" | ~"avoid-region" => Some(InsideAuto),
+        ~"avoid" | ~"avoid-page" => Some(InsideAvoid),
+        _ => None
+    }
+}
+
+/// Parses `page-break-inside`.
+pub fn parse_page_break_inside(value: &str) -> Option<BreakInside> {
+    match str::to_lower(str::trim(value)) {
+        ~"auto" => Some(InsideAuto),
+        ~"avoid" => Some(InsideAvoid),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod breaks_tests {
+    #[test]
+    fn test_parse_break_between() {
+        assert parse_break_between("page") == Some(BreakPage);
+        assert parse_break_between(" Right ") == Some(BreakPage);
+        assert parse_break_between("avoid-page") == Some(BreakAvoid);
+        assert parse_break_between("column") == Some(BreakAuto);
+        assert parse_break_between("sometimes").is_none();
+
+        assert parse_page_break_between("always") == Some(BreakPage);
+        assert parse_page_break_between("avoid") == Some(BreakAvoid);
+        // Only the newer property has `page`
+        assert parse_page_break_between("page").is_none();
+    }
+
+    #[test]
+    fn test_parse_break_inside() {
+        assert parse_break_inside("avoid") == Some(InsideAvoid);
+        assert parse_break_inside("avoid-column") == Some(InsideAuto);
+        assert parse_break_inside("always").is_none();
+        assert parse_page_break_inside("avoid") == Some(InsideAvoid);
+        assert parse_page_break_inside("avoid-page").is_none();
+    }
+}
@@ -0,0 +1,111 @@
+/*!
+`color-scheme`, and the `<meta name="color-scheme">` that sets it for the
+whole page, which say whether an element can be drawn in light colors,
+dark ones or either. Form controls and other things the browser draws
+itself follow the scheme that gets picked.
+*/
+
+use newcss::color::{Color, rgb};
+
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+impl ColorScheme : cmp::Eq {
+    pure fn eq(&self, other: &ColorScheme) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &ColorScheme) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// The schemes an element supports, in the order the author gave them.
+pub struct ColorSchemes {
+    // Empty for `normal`
+    schemes: ~[ColorScheme],
+    // `only`: don't let the browser recolor the page to another scheme
+    only: bool,
+}
+
+/**
+Parses a `color-scheme` value, which is also what the meta tag's `content`
+holds. Keywords other than `light` and `dark` are kept for future schemes
+and ignored, but there must be at least one keyword.
+*/
+pub fn parse_color_scheme(value: &str) -> Option<ColorSchemes> {
+    let words = str::words(str::to_lower(value));
+    if words.is_empty() {
+        return None;
+    }
+    if words.len() == 1 && words[0] == ~"normal" {
+        return Some(ColorSchemes { schemes: ~[], only: false });
+    }
+    let mut schemes = ~[];
+    let mut only = false;
+    for words.eachi |i, word| {
+        match *word {
+            ~"light" => if !vec::contains(schemes, &Light) { schemes.push(Light) },
+            ~"dark" => if !vec::contains(schemes, &Dark) { schemes.push(Dark) },
+            // `only` may come first or last, not in between
+            ~"only" if !only && (i == 0 || i == words.len() - 1) => only = true,
+            ~"normal" | ~"only" => return None,
+            _ => ()
+        }
+    }
+    if only && words.len() == 1 {
+        return None;
+    }
+    Some(ColorSchemes { schemes: move schemes, only: only })
+}
+
+/**
+The scheme to draw in: the one the user prefers if the element supports
+it, otherwise the first one the element supports. Pages that say nothing
+are light.
+*/
+pub fn resolve(schemes: &ColorSchemes, preferred: ColorScheme) -> ColorScheme {
+    if vec::contains(schemes.schemes, &preferred) {
+        preferred
+    } else if !schemes.schemes.is_empty() {
+        schemes.schemes[0]
+    } else {
+        Light
+    }
+}
+
+/// The background form controls get when the page doesn't give one.
+pub fn control_background(scheme: ColorScheme) -> Color {
+    match scheme {
+        Light => rgb(255, 255, 255),
+        Dark => rgb(59, 59, 59)
+    }
+}
+
+#[cfg(test)]
+mod color_scheme_tests {
+    #[test]
+    fn test_parse_color_scheme() {
+        let schemes = parse_color_scheme("dark light").get();
+        assert schemes.schemes == ~[Dark, Light];
+        assert !schemes.only;
+
+        assert parse_color_scheme("normal").get().schemes.is_empty();
+        assert parse_color_scheme("only light").get().only;
+        assert parse_color_scheme("Light Sepia").get().schemes == ~[Light];
+        assert parse_color_scheme("").is_none();
+        assert parse_color_scheme("only").is_none();
+        assert parse_color_scheme("light normal").is_none();
+        assert parse_color_scheme("light only dark").is_none();
+    }
+
+    #[test]
+    fn test_resolve() {
+        let either = parse_color_scheme("light dark").get();
+        assert resolve(&either, Dark) == Dark;
+        assert resolve(&either, Light) == Light;
+        assert resolve(&parse_color_scheme("dark").get(), Light) == Dark;
+        assert resolve(&parse_color_scheme("normal").get(), Dark) == Light;
+    }
+}
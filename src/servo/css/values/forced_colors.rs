@@ -0,0 +1,101 @@
+/*!
+The system colors of forced colors mode, where the user's high contrast
+palette replaces the page's colors, and the `forced-colors` media feature
+that tells pages it's on.
+*/
+
+use newcss::color::{Color, rgb};
+
+pub enum SystemColor {
+    Canvas,
+    CanvasText,
+    LinkText,
+    VisitedText,
+    ActiveText,
+    ButtonFace,
+    ButtonText,
+    ButtonBorder,
+    Field,
+    FieldText,
+    Highlight,
+    HighlightText,
+    GrayText,
+}
+
+impl SystemColor : cmp::Eq {
+    pure fn eq(&self, other: &SystemColor) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &SystemColor) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+pub fn parse_system_color(value: &str) -> Option<SystemColor> {
+    match str::to_lower(str::trim(value)) {
+        ~"canvas" => Some(Canvas),
+        ~"canvastext" => Some(CanvasText),
+        ~"linktext" => Some(LinkText),
+        ~"visitedtext" => Some(VisitedText),
+        ~"activetext" => Some(ActiveText),
+        ~"buttonface" => Some(ButtonFace),
+        ~"buttontext" => Some(ButtonText),
+        ~"buttonborder" => Some(ButtonBorder),
+        ~"field" => Some(Field),
+        ~"fieldtext" => Some(FieldText),
+        ~"highlight" => Some(Highlight),
+        ~"highlighttext" => Some(HighlightText),
+        ~"graytext" => Some(GrayText),
+        _ => None
+    }
+}
+
+/**
+The color a system color stands for. Until the platform's palette can be
+read, it's that of the usual high contrast theme: light text on black,
+with yellow links.
+*/
+pub fn system_color(color: SystemColor) -> Color {
+    match color {
+        Canvas | ButtonFace | Field => rgb(0, 0, 0),
+        CanvasText | ButtonText | ButtonBorder | FieldText | HighlightText => rgb(255, 255, 255),
+        LinkText => rgb(255, 255, 0),
+        VisitedText => rgb(128, 128, 255),
+        ActiveText | Highlight => rgb(0, 255, 255),
+        GrayText => rgb(63, 243, 63)
+    }
+}
+
+/**
+Evaluates `forced-colors`, given the part of the media feature after the
+colon, or nothing when it's used on its own. None if the value isn't one
+the feature takes, which makes the query false.
+*/
+pub fn evaluate_forced_colors(value: Option<&str>, active: bool) -> Option<bool> {
+    match value {
+        None => Some(active),
+        Some(value) => match str::to_lower(str::trim(value)) {
+            ~"active" => Some(active),
+            ~"none" => Some(!active),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod forced_colors_tests {
+    #[test]
+    fn test_parse_system_color() {
+        assert parse_system_color("ButtonFace") == Some(ButtonFace);
+        assert parse_system_color(" linktext ") == Some(LinkText);
+        assert parse_system_color("WindowFrame").is_none();
+    }
+
+    #[test]
+    fn test_evaluate_forced_colors() {
+        assert evaluate_forced_colors(None, true) == Some(true);
+        assert evaluate_forced_colors(Some("active"), false) == Some(false);
+        assert evaluate_forced_colors(Some(" none"), false) == Some(true);
+        assert evaluate_forced_colors(Some("on"), true).is_none();
+    }
+}
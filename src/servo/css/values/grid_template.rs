@@ -0,0 +1,83 @@
+/*!
+`grid-template-columns` and `grid-template-rows` values: a list of track
+sizes, or `subgrid`, for a nested grid that uses its parent's tracks.
+*/
+
+use au = gfx::geometry;
+use gfx::geometry::Au;
+
+pub enum TrackSize {
+    FixedTrack(Au),
+    // `<number>fr`, a share of the space left over
+    FlexTrack(float),
+    // `auto`: sized by the items in it
+    AutoTrack,
+}
+
+pub enum TrackList {
+    Tracks(~[TrackSize]),
+    Subgrid,
+}
+
+fn parse_track_size(value: &str) -> Option<TrackSize> {
+    if value == "auto" {
+        Some(AutoTrack)
+    } else if value == "0" {
+        Some(FixedTrack(Au(0)))
+    } else if value.ends_with("px") {
+        match float::from_str(value.slice(0, value.len() - 2)) {
+            Some(px) if px >= 0.0 => Some(FixedTrack(au::from_frac_px(px))),
+            _ => None
+        }
+    } else if value.ends_with("fr") {
+        match float::from_str(value.slice(0, value.len() - 2)) {
+            Some(fr) if fr >= 0.0 => Some(FlexTrack(fr)),
+            _ => None
+        }
+    } else {
+        None
+    }
+}
+
+/**
+Parses a `grid-template-columns` or `grid-template-rows` value: `subgrid`,
+or track sizes separated by spaces, like `100px 1fr auto`. Line names,
+`repeat()` and `minmax()` aren't supported; a list with any is None.
+*/
+pub fn parse_track_list(value: &str) -> Option<TrackList> {
+    let value = str::trim(value);
+    if value == "subgrid" {
+        return Some(Subgrid);
+    }
+    let words = str::words(value);
+    if words.is_empty() {
+        return None;
+    }
+    let tracks = vec::filter_map(words, |w| parse_track_size(*w));
+    if tracks.len() == words.len() {
+        Some(Tracks(move tracks))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod grid_template_tests {
+    #[test]
+    fn test_parse_track_list() {
+        match parse_track_list("100px 1fr auto 2.5fr") {
+            Some(Tracks(tracks)) => {
+                assert tracks.len() == 4;
+                match tracks[0] { FixedTrack(au) => assert au == au::from_px(100), _ => fail }
+                match tracks[1] { FlexTrack(fr) => assert fr == 1.0, _ => fail }
+                match tracks[2] { AutoTrack => (), _ => fail }
+                match tracks[3] { FlexTrack(fr) => assert fr == 2.5, _ => fail }
+            }
+            _ => fail
+        }
+        match parse_track_list(" subgrid ") { Some(Subgrid) => (), _ => fail }
+        assert parse_track_list("").is_none();
+        assert parse_track_list("100px repeat(2, 1fr)").is_none();
+        assert parse_track_list("-1fr").is_none();
+    }
+}
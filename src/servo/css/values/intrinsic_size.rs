@@ -0,0 +1,91 @@
+/*!
+The intrinsic sizing keywords of `width`: `min-content`, `max-content`,
+`fit-content` and `fit-content(<length-percentage>)`. Layout turns them
+into an `AvailableSpace` to size the box's content in.
+*/
+
+use au = gfx::geometry;
+use gfx::geometry::Au;
+
+/// The limit of `fit-content()`: a length, or a percentage of the width
+/// of the containing block.
+pub enum FitContentLimit {
+    LimitAu(Au),
+    LimitPercent(float),
+}
+
+impl FitContentLimit {
+    pure fn resolve(containing_width: Au) -> Au {
+        match self {
+            LimitAu(au) => au,
+            LimitPercent(percent) => {
+                Au(float::floor(*containing_width as float * percent / 100.0 + 0.5) as i32)
+            }
+        }
+    }
+}
+
+pub enum IntrinsicSize {
+    MinContent,
+    MaxContent,
+    FitContent(FitContentLimit),
+}
+
+fn parse_limit(value: &str) -> Option<FitContentLimit> {
+    let value = str::trim(value);
+    let limit = if value == "0" {
+        Some(LimitAu(Au(0)))
+    } else if value.ends_with("px") {
+        float::from_str(value.slice(0, value.len() - 2)).map(|px| LimitAu(au::from_frac_px(*px)))
+    } else if value.ends_with("%") {
+        float::from_str(value.slice(0, value.len() - 1)).map(|percent| LimitPercent(*percent))
+    } else {
+        None
+    };
+    match limit {
+        Some(LimitAu(au)) if au < Au(0) => None,
+        Some(LimitPercent(percent)) if percent < 0.0 => None,
+        _ => limit
+    }
+}
+
+/// Parses a `width` that's an intrinsic sizing keyword. Other widths,
+/// like lengths and `auto`, are None.
+pub fn parse_intrinsic_size(value: &str) -> Option<IntrinsicSize> {
+    let value = str::trim(value);
+    match value.to_str() {
+        ~"min-content" => Some(MinContent),
+        ~"max-content" => Some(MaxContent),
+        // As wide as the containing block allows
+        ~"fit-content" => Some(FitContent(LimitPercent(100.0))),
+        _ if value.starts_with("fit-content(") && value.ends_with(")") => {
+            parse_limit(value.slice(12, value.len() - 1)).map(|limit| FitContent(*limit))
+        }
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod intrinsic_size_tests {
+    #[test]
+    fn test_parse_intrinsic_size() {
+        match parse_intrinsic_size("min-content") { Some(MinContent) => (), _ => fail }
+        match parse_intrinsic_size(" max-content ") { Some(MaxContent) => (), _ => fail }
+        match parse_intrinsic_size("fit-content(150px)") {
+            Some(FitContent(LimitAu(au))) => assert au == au::from_px(150),
+            _ => fail
+        }
+        match parse_intrinsic_size("fit-content(50%)") {
+            Some(FitContent(limit)) => assert limit.resolve(au::from_px(300)) == au::from_px(150),
+            _ => fail
+        }
+        match parse_intrinsic_size("fit-content") {
+            Some(FitContent(limit)) => assert limit.resolve(au::from_px(300)) == au::from_px(300),
+            _ => fail
+        }
+        assert parse_intrinsic_size("auto").is_none();
+        assert parse_intrinsic_size("100px").is_none();
+        assert parse_intrinsic_size("fit-content(wide)").is_none();
+        assert parse_intrinsic_size("fit-content(-5px)").is_none();
+    }
+}
@@ -0,0 +1,165 @@
+/*!
+`list-style-type`, `list-style-position` and `list-style-image`, and the
+`list-style` shorthand for them, which say how a list item's marker looks
+and where it goes.
+*/
+
+pub enum ListStyleType {
+    Disc,
+    Circle,
+    Square,
+    Decimal,
+    DecimalLeadingZero,
+    LowerAlpha,
+    UpperAlpha,
+    LowerGreek,
+    LowerRoman,
+    UpperRoman,
+    NoListStyle,
+}
+
+impl ListStyleType : cmp::Eq {
+    pure fn eq(&self, other: &ListStyleType) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &ListStyleType) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+pub enum ListStylePosition {
+    Outside,
+    Inside,
+}
+
+impl ListStylePosition : cmp::Eq {
+    pure fn eq(&self, other: &ListStylePosition) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &ListStylePosition) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// What a `list-style` shorthand sets. Parts it leaves out are None.
+pub struct ListStyle {
+    style_type: Option<ListStyleType>,
+    position: Option<ListStylePosition>,
+    // Some(None) for `none`; the url is unresolved
+    image: Option<Option<~str>>,
+}
+
+pub fn parse_list_style_type(value: &str) -> Option<ListStyleType> {
+    match str::trim(value) {
+        ~"disc" => Some(Disc),
+        ~"circle" => Some(Circle),
+        ~"square" => Some(Square),
+        ~"decimal" => Some(Decimal),
+        ~"decimal-leading-zero" => Some(DecimalLeadingZero),
+        ~"lower-alpha" | ~"lower-latin" => Some(LowerAlpha),
+        ~"upper-alpha" | ~"upper-latin" => Some(UpperAlpha),
+        ~"lower-greek" => Some(LowerGreek),
+        ~"lower-roman" => Some(LowerRoman),
+        ~"upper-roman" => Some(UpperRoman),
+        ~"none" => Some(NoListStyle),
+        _ => None
+    }
+}
+
+/**
+The list style an HTML `type` attribute asks for. On `<ol>` and `<li>`
+the numbering ones are case sensitive, so `a` and `A` differ.
+*/
+pub fn list_style_type_for_attr(value: &str) -> Option<ListStyleType> {
+    match str::trim(value) {
+        ~"1" => Some(Decimal),
+        ~"a" => Some(LowerAlpha),
+        ~"A" => Some(UpperAlpha),
+        ~"i" => Some(LowerRoman),
+        ~"I" => Some(UpperRoman),
+        v => parse_list_style_type(str::to_lower(v))
+    }
+}
+
+pub fn parse_list_style_position(value: &str) -> Option<ListStylePosition> {
+    match str::trim(value) {
+        ~"outside" => Some(Outside),
+        ~"inside" => Some(Inside),
+        _ => None
+    }
+}
+
+/// Parses a `list-style-image`: Some(None) for `none`, or the url.
+pub fn parse_list_style_image(value: &str) -> Option<Option<~str>> {
+    let value = str::trim(value);
+    if value == "none" {
+        return Some(None);
+    }
+    if value.starts_with("url(") && value.ends_with(")") {
+        let url = str::trim(value.slice(4, value.len() - 1));
+        let url = if url.len() >= 2 && (url.starts_with("\"") && url.ends_with("\"") ||
+                                        url.starts_with("'") && url.ends_with("'")) {
+            url.slice(1, url.len() - 1)
+        } else {
+            url
+        };
+        return Some(Some(url));
+    }
+    None
+}
+
+/**
+Parses the `list-style` shorthand: a type, a position and an image, in any
+order. A lone `none` sets both the type and the image to none; with a type
+or an image beside it, it's the other one.
+*/
+pub fn parse_list_style(value: &str) -> Option<ListStyle> {
+    let mut style = ListStyle { style_type: None, position: None, image: None };
+    let mut nones = 0;
+    for str::split_char_nonempty(str::trim(value), ' ').each |word| {
+        if *word == ~"none" {
+            nones += 1;
+        } else if style.position.is_none() && parse_list_style_position(*word).is_some() {
+            style.position = parse_list_style_position(*word);
+        } else if style.style_type.is_none() && parse_list_style_type(*word).is_some() {
+            style.style_type = parse_list_style_type(*word);
+        } else if style.image.is_none() && parse_list_style_image(*word).is_some() {
+            style.image = parse_list_style_image(*word);
+        } else {
+            return None;
+        }
+    }
+    for nones.times {
+        if style.style_type.is_none() {
+            style.style_type = Some(NoListStyle);
+        } else if style.image.is_none() {
+            style.image = Some(None);
+        } else {
+            return None;
+        }
+    }
+    Some(move style)
+}
+
+#[cfg(test)]
+mod list_style_tests {
+    #[test]
+    fn test_parse_list_style() {
+        assert parse_list_style_type("lower-latin") == Some(LowerAlpha);
+        assert parse_list_style_type("dots").is_none();
+        assert list_style_type_for_attr("A") == Some(UpperAlpha);
+        assert list_style_type_for_attr("SQUARE") == Some(Square);
+        assert parse_list_style_image("url('dot.png')") == Some(Some(~"dot.png"));
+
+        let style = parse_list_style("square inside").get();
+        assert style.style_type == Some(Square) && style.position == Some(Inside);
+        assert style.image.is_none();
+
+        let none = parse_list_style("none").get();
+        assert none.style_type == Some(NoListStyle) && none.image == Some(None);
+        let image = parse_list_style("url(dot.png) none").get();
+        assert image.style_type == Some(NoListStyle) && image.image == Some(Some(~"dot.png"));
+
+        assert parse_list_style("square circle").is_none();
+    }
+}
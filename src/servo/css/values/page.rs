@@ -0,0 +1,273 @@
+/*!
+`@page` rules, which set the paper size and margins pages are printed
+with, and the `orphans` and `widows` lines kept together at page breaks.
+
+The style sheet parser doesn't hand at-rules on, so they're read from the
+text of the page's style sheets. Only plain `@page` rules are; ones for
+`:first`, `:left`, `:right` or named pages are skipped, as are the margin
+boxes inside a rule.
+*/
+
+use au = gfx::geometry;
+use au::Au;
+use geom::size::Size2D;
+
+/// What the `@page` rules of a page set. Anything not set is None.
+pub struct PageRule {
+    size: Option<Size2D<Au>>,
+    // Top, right, bottom and left
+    margins: Option<(Au, Au, Au, Au)>,
+    orphans: Option<uint>,
+    widows: Option<uint>,
+}
+
+pub fn PageRule() -> PageRule {
+    PageRule { size: None, margins: None, orphans: None, widows: None }
+}
+
+const PX_PER_IN: float = 96.0;
+
+/// A length in absolute units, which are all a page has to go by.
+pub fn parse_page_length(value: &str) -> Option<Au> {
+    let value = str::trim(value);
+    if value == ~"0" {
+        return Some(Au(0));
+    }
+    let units = [("px", 1.0), ("in", PX_PER_IN), ("cm", PX_PER_IN / 2.54),
+                 ("mm", PX_PER_IN / 25.4), ("pt", PX_PER_IN / 72.0), ("pc", PX_PER_IN / 6.0)];
+    for units.each |unit| {
+        let (suffix, px_per_unit) = *unit;
+        if value.ends_with(suffix) {
+            let number = str::slice(value, 0, value.len() - suffix.len());
+            return match float::from_str(number) {
+                Some(n) if n >= 0.0 => Some(au::from_frac_px(n * px_per_unit)),
+                _ => None
+            };
+        }
+    }
+    None
+}
+
+// The portrait size of a named paper size, in mm
+fn named_size(name: &str) -> Option<(float, float)> {
+    match str::to_lower(name) {
+        ~"a5" => Some((148.0, 210.0)),
+        ~"a4" => Some((210.0, 297.0)),
+        ~"a3" => Some((297.0, 420.0)),
+        ~"b5" => Some((176.0, 250.0)),
+        ~"b4" => Some((250.0, 353.0)),
+        ~"letter" => Some((215.9, 279.4)),
+        ~"legal" => Some((215.9, 355.6)),
+        ~"ledger" => Some((279.4, 431.8)),
+        _ => None
+    }
+}
+
+fn mm(n: float) -> Au {
+    au::from_frac_px(n * PX_PER_IN / 25.4)
+}
+
+/**
+Parses `size`: one or two lengths, or a paper size, an orientation or
+both. None for `auto`, which leaves the size to the printer, and for
+values it can't read.
+*/
+pub fn parse_page_size(value: &str) -> Option<Size2D<Au>> {
+    let words = str::words(value);
+    if words.is_empty() || words.len() > 2 {
+        return None;
+    }
+    let lengths = words.map(|w| parse_page_length(*w));
+    if lengths.all(|l| l.is_some()) {
+        let width = lengths[0].get();
+        let height = if lengths.len() == 2 { lengths[1].get() } else { width };
+        return Some(Size2D(width, height));
+    }
+
+    let mut paper = None;
+    let mut landscape = None;
+    for words.each |word| {
+        match str::to_lower(*word) {
+            ~"portrait" if landscape.is_none() => landscape = Some(false),
+            ~"landscape" if landscape.is_none() => landscape = Some(true),
+            w => match named_size(w) {
+                Some(size) if paper.is_none() => paper = Some(size),
+                _ => return None
+            }
+        }
+    }
+    // An orientation on its own turns the default paper
+    let (width, height) = paper.get_default((215.9, 279.4));
+    if landscape.get_default(false) {
+        Some(Size2D(mm(height), mm(width)))
+    } else {
+        Some(Size2D(mm(width), mm(height)))
+    }
+}
+
+// One to four lengths, going round clockwise from the top as for `margin`
+fn parse_margins(value: &str) -> Option<(Au, Au, Au, Au)> {
+    let lengths = str::words(value).map(|w| parse_page_length(*w));
+    if !lengths.all(|l| l.is_some()) {
+        return None;
+    }
+    let l = lengths.map(|l| l.get());
+    match l.len() {
+        1 => Some((l[0], l[0], l[0], l[0])),
+        2 => Some((l[0], l[1], l[0], l[1])),
+        3 => Some((l[0], l[1], l[2], l[1])),
+        4 => Some((l[0], l[1], l[2], l[3])),
+        _ => None
+    }
+}
+
+fn parse_line_count(value: &str) -> Option<uint> {
+    match uint::from_str(str::trim(value)) {
+        Some(n) if n > 0 => Some(n),
+        _ => None
+    }
+}
+
+// `css` without its comments
+fn strip_comments(css: &str) -> ~str {
+    let mut result = ~"";
+    let mut rest = css.to_str();
+    loop {
+        match str::find_str(rest, "/*") {
+            Some(start) => {
+                result += str::slice(rest, 0, start);
+                rest = match str::find_str_from(rest, "*/", start + 2) {
+                    Some(end) => str::slice(rest, end + 2, rest.len()),
+                    None => ~""
+                };
+            }
+            None => {
+                result += rest;
+                return move result;
+            }
+        }
+    }
+}
+
+// The declarations of each plain `@page` rule in `css`, with any blocks
+// nested inside them left out
+fn page_rule_bodies(css: &str) -> ~[~str] {
+    let mut bodies = ~[];
+    let mut from = 0;
+    loop {
+        let at = match str::find_str_from(css, "@page", from) {
+            Some(at) => at,
+            None => return move bodies
+        };
+        let open = match str::find_char_from(css, '{', at) {
+            Some(open) => open,
+            None => return move bodies
+        };
+        let plain = str::trim(str::slice(css, at + 5, open)).is_empty();
+        let mut body = ~"";
+        let mut depth = 1;
+        let mut i = open + 1;
+        while i < css.len() && depth > 0 {
+            let c = css[i] as char;
+            if c == '{' {
+                depth += 1;
+            } else if c == '}' {
+                depth -= 1;
+            } else if depth == 1 {
+                str::push_char(&mut body, c);
+            }
+            i += 1;
+        }
+        if plain {
+            bodies.push(move body);
+        }
+        from = i;
+    }
+}
+
+/// Reads the `@page` rules in `css`. Later declarations win.
+pub fn parse_page_rules(css: &str, rule: &mut PageRule) {
+    for page_rule_bodies(strip_comments(css)).each |body| {
+        for str::split_char(*body, ';').each |declaration| {
+            let colon = str::find_char(*declaration, ':');
+            if colon.is_none() {
+                loop;
+            }
+            let colon = colon.get();
+            let name = str::to_lower(str::trim(str::slice(*declaration, 0, colon)));
+            let value = str::trim(str::slice(*declaration, colon + 1, declaration.len()));
+            match name {
+                ~"size" => match parse_page_size(value) {
+                    Some(size) => rule.size = Some(size),
+                    None => ()
+                },
+                ~"margin" => match parse_margins(value) {
+                    Some(margins) => rule.margins = Some(margins),
+                    None => ()
+                },
+                ~"margin-top" | ~"margin-right" | ~"margin-bottom" | ~"margin-left" => {
+                    match parse_page_length(value) {
+                        Some(length) => {
+                            let (t, r, b, l) = rule.margins.get_default((Au(0), Au(0), Au(0),
+                                                                         Au(0)));
+                            rule.margins = Some(match name {
+                                ~"margin-top" => (length, r, b, l),
+                                ~"margin-right" => (t, length, b, l),
+                                ~"margin-bottom" => (t, r, length, l),
+                                _ => (t, r, b, length)
+                            });
+                        }
+                        None => ()
+                    }
+                }
+                ~"orphans" => match parse_line_count(value) {
+                    Some(n) => rule.orphans = Some(n),
+                    None => ()
+                },
+                ~"widows" => match parse_line_count(value) {
+                    Some(n) => rule.widows = Some(n),
+                    None => ()
+                },
+                _ => ()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod page_tests {
+    #[test]
+    fn test_parse_page_length() {
+        assert parse_page_length("1in") == Some(au::from_px(96));
+        assert parse_page_length("72pt") == Some(au::from_px(96));
+        assert parse_page_length("0") == Some(Au(0));
+        assert parse_page_length("2em").is_none();
+        assert parse_page_length("-1px").is_none();
+    }
+
+    #[test]
+    fn test_parse_page_size() {
+        let a4 = parse_page_size("A4").get();
+        assert a4 == Size2D(mm(210.0), mm(297.0));
+        assert parse_page_size("a4 landscape").get() == Size2D(mm(297.0), mm(210.0));
+        assert parse_page_size("landscape").get() == Size2D(mm(279.4), mm(215.9));
+        assert parse_page_size("4in 6in").get() == Size2D(au::from_px(384), au::from_px(576));
+        assert parse_page_size("5in").get() == Size2D(au::from_px(480), au::from_px(480));
+        assert parse_page_size("auto").is_none();
+        assert parse_page_size("a4 letter").is_none();
+    }
+
+    #[test]
+    fn test_parse_page_rules() {
+        let mut rule = PageRule();
+        parse_page_rules("p { margin: 3in } @page { size: letter; margin: 1in 0.5in; \
+                          orphans: 3 } @page :first { margin: 0 } \
+                          @page { /* widows: 9; */ widows: 4; margin-top: 2in; \
+                          @top-center { content: 'x' } }", &mut rule);
+        let (inch, half) = (au::from_px(96), au::from_px(48));
+        assert rule.size == Some(Size2D(mm(215.9), mm(279.4)));
+        assert rule.margins == Some((au::from_px(192), half, inch, half));
+        assert rule.orphans == Some(3);
+        assert rule.widows == Some(4);
+    }
+}
@@ -0,0 +1,194 @@
+/*!
+The `--devtools-port` server, which speaks the Chrome DevTools Protocol
+(CDP) so that Chrome's devtools or VS Code can debug the page's scripts.
+
+Clients find the page at `http://127.0.0.1:<port>/json/list` and connect to
+its `webSocketDebuggerUrl`. CDP messages are JSON, one per WebSocket text
+message; this task only carries them between the client and the content
+task, which does what they ask (see `content::devtools`). Each connection
+is answered on a task of its own, so one that stalls doesn't hold up the
+rest. One client debugs the page at a time; others that connect meanwhile
+are turned away. Every 100 ms the task checks whether it's been told to
+exit.
+
+Only requests from this machine, by the loopback interface's name and from
+no web page, are answered: a client can run script in the page.
+*/
+
+use comm::{Port, Chan};
+use engine::{EngineTask, AttachDevtoolsMsg, DevtoolsCommandMsg, DetachDevtoolsMsg};
+use devtools::websocket::{TcpStream, WebSocket, listen, read_request, write_response, upgrade};
+use ipc::unix_socket::ConnectionClosed;
+use task::{task, SingleThreaded};
+
+const POLL_INTERVAL_MS: uint = 100;
+// The one page there is
+const PAGE_ID: &static/str = "servo";
+
+pub enum Msg {
+    ExitMsg
+}
+
+pub type CdpServer = Chan<Msg>;
+
+// A connected client, and the messages for it from the content task
+struct Client {
+    socket: WebSocket,
+    messages: pipes::Port<~str>,
+}
+
+/// What `/json/list` says of the page.
+pub fn target_list(port: uint) -> ~str {
+    fmt!("[{\"id\":\"%s\",\"type\":\"page\",\"title\":\"Servo\",\"url\":\"\",\
+          \"webSocketDebuggerUrl\":\"ws://127.0.0.1:%u/devtools/page/%s\"}]",
+         PAGE_ID, port, PAGE_ID)
+}
+
+/// What `/json/version` says of the browser.
+pub fn version_info(port: uint) -> ~str {
+    fmt!("{\"Browser\":\"Servo\",\"Protocol-Version\":\"1.3\",\
+          \"webSocketDebuggerUrl\":\"ws://127.0.0.1:%u/devtools/page/%s\"}", port, PAGE_ID)
+}
+
+// Answers an HTTP request on a new connection. Returns the socket if it
+// was upgraded to a WebSocket; other requests are answered and closed.
+fn serve(stream: TcpStream, port: uint) -> Option<WebSocket> {
+    let request = match read_request(&stream) {
+        Ok(move request) => move request,
+        Err(_) => {
+            write_response(&stream, "400 Bad Request", "text/plain", "bad request");
+            return None;
+        }
+    };
+    if !request.is_trusted() {
+        write_response(&stream, "403 Forbidden", "text/plain", "forbidden");
+        return None;
+    }
+    if request.is_upgrade() {
+        if request.path != fmt!("/devtools/page/%s", PAGE_ID) {
+            write_response(&stream, "404 Not Found", "text/plain", "no such page");
+            return None;
+        }
+        return match upgrade(move stream, &request) {
+            Ok(move socket) => Some(move socket),
+            Err(_) => {
+                #error("devtools: can't upgrade a connection to a WebSocket");
+                None
+            }
+        };
+    }
+    match request.path {
+        ~"/json" | ~"/json/list" => {
+            write_response(&stream, "200 OK", "application/json", target_list(port));
+        }
+        ~"/json/version" => {
+            write_response(&stream, "200 OK", "application/json", version_info(port));
+        }
+        _ => {
+            write_response(&stream, "404 Not Found", "text/plain", "not found");
+        }
+    }
+    None
+}
+
+// Answers a new connection on a task of its own, sending the socket to
+// `sockets` if it was upgraded to a WebSocket
+fn answer(stream: TcpStream, port: uint, sockets: Chan<WebSocket>) {
+    // Reading the request blocks
+    do task().sched_mode(SingleThreaded).spawn |move stream| {
+        match serve(move stream, port) {
+            Some(move socket) => sockets.send(move socket),
+            None => ()
+        }
+    }
+}
+
+// Passes a client's messages to the content task and the content task's to
+// the client. Returns false once the client has gone.
+fn relay(client: &Client, engine: EngineTask) -> bool {
+    while client.messages.peek() {
+        match client.messages.try_recv() {
+            Some(move message) => {
+                if client.socket.send_text(message).is_err() {
+                    return false;
+                }
+            }
+            None => break
+        }
+    }
+    if !client.socket.poll(POLL_INTERVAL_MS) {
+        return true;
+    }
+    match client.socket.recv() {
+        Ok(Some(move message)) => {
+            engine.send(DevtoolsCommandMsg(move message));
+            true
+        }
+        Ok(None) => true,
+        Err(ConnectionClosed) => false,
+        Err(_) => {
+            #error("devtools: bad message, dropping the connection");
+            false
+        }
+    }
+}
+
+/// Listens on `port` of 127.0.0.1 for devtools clients, attaching the one
+/// connected to the page `engine` shows. Fails if the port can't be used.
+pub fn CdpServer(port: uint, engine: EngineTask) -> CdpServer {
+    let listener = match listen(port) {
+        Ok(move listener) => move listener,
+        Err(_) => fail fmt!("can't listen on --devtools-port %u", port)
+    };
+    let control_port = Port();
+    let control_chan = Chan(&control_port);
+    // Polling the socket blocks, so the task gets a thread of its own
+    do task().sched_mode(SingleThreaded).spawn |move listener, move control_port| {
+        // The WebSockets connections were upgraded to
+        let sockets: Port<WebSocket> = Port();
+        let sockets_chan = Chan(&sockets);
+        let mut client: Option<Client> = None;
+        while !control_port.peek() {
+            // While there's a client, it's what's waited on
+            let wait_ms = if client.is_some() { 0 } else { POLL_INTERVAL_MS };
+            if listener.poll(wait_ms) {
+                match listener.accept() {
+                    Ok(move stream) => answer(move stream, port, sockets_chan),
+                    Err(_) => #error("devtools: can't accept a connection")
+                }
+            }
+            while sockets.peek() {
+                let socket = sockets.recv();
+                if client.is_some() {
+                    // Dropping the socket closes it
+                    #error("devtools: a client is already attached, turning another away");
+                    loop;
+                }
+                let (chan, messages) = pipes::stream();
+                engine.send(AttachDevtoolsMsg(pipes::SharedChan(move chan)));
+                client = Some(Client { socket: move socket, messages: move messages });
+            }
+            let closed = match client {
+                Some(ref client) => !relay(client, engine),
+                None => false
+            };
+            if closed {
+                #debug("devtools: client went away");
+                engine.send(DetachDevtoolsMsg);
+                client = None;
+            }
+        }
+        if client.is_some() {
+            engine.send(DetachDevtoolsMsg);
+        }
+    }
+    move control_chan
+}
+
+#[test]
+fn test_target_list() {
+    let list = target_list(9222);
+    assert str::contains(list, "ws://127.0.0.1:9222/devtools/page/servo");
+    assert std::json::from_str(list).is_ok();
+    assert std::json::from_str(version_info(9222)).is_ok();
+}
@@ -0,0 +1,531 @@
+/*!
+Just enough of HTTP and WebSocket (RFC 6455) for devtools clients: a TCP
+listener on the loopback interface, the request line and headers of an
+HTTP request, the upgrade handshake, and text frames.
+*/
+
+use ipc::unix_socket::{IpcError, ConnectionClosed, MalformedMessage, OsError, write_all,
+                       read_exact, poll_readable};
+use libc::{c_int, c_void};
+use std::base64::ToBase64;
+
+const AF_INET: c_int = 2;
+const SOCK_STREAM: c_int = 1;
+const EINTR: int = 4;
+const INADDR_LOOPBACK: u32 = 0x7f000001;
+// Longer requests and frames are taken to be garbage
+const MAX_HEADER_LEN: uint = 8192;
+const MAX_FRAME_LEN: uint = 16 * 1024 * 1024;
+// Appended to a client's key to make the accept key
+const WEBSOCKET_GUID: &static/str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub const OPCODE_CONTINUATION: u8 = 0;
+pub const OPCODE_TEXT: u8 = 1;
+pub const OPCODE_BINARY: u8 = 2;
+pub const OPCODE_CLOSE: u8 = 8;
+pub const OPCODE_PING: u8 = 9;
+pub const OPCODE_PONG: u8 = 10;
+
+/// A socket connected to a client; closed when dropped.
+pub struct TcpStream {
+    priv fd: c_int,
+
+    drop {
+        unsafe { libc::close(self.fd); }
+    }
+}
+
+impl TcpStream {
+    fn write(&self, bytes: &[u8]) -> Result<(), IpcError> {
+        write_all(self.fd, bytes)
+    }
+
+    fn read(&self, len: uint) -> Result<~[u8], IpcError> {
+        read_exact(self.fd, len)
+    }
+
+    /// Whether there's something to read within `ms` milliseconds.
+    fn poll(&self, ms: uint) -> bool {
+        poll_readable(self.fd, ms)
+    }
+}
+
+/// A TCP socket waiting for connections.
+pub struct TcpListener {
+    priv fd: c_int,
+
+    drop {
+        unsafe { libc::close(self.fd); }
+    }
+}
+
+impl TcpListener {
+    fn accept(&self) -> Result<TcpStream, IpcError> {
+        loop {
+            let fd = c_accept(self.fd, ptr::null(), ptr::null());
+            if fd >= 0 {
+                return Ok(TcpStream { fd: fd });
+            }
+            if os::errno() != EINTR {
+                return Err(OsError(os::errno()));
+            }
+        }
+    }
+
+    /// Whether a connection arrives within `ms` milliseconds.
+    fn poll(&self, ms: uint) -> bool {
+        poll_readable(self.fd, ms)
+    }
+}
+
+/// Listens on `port` of 127.0.0.1. Only this machine can connect: whoever
+/// does can run script in the page.
+pub fn listen(port: uint) -> Result<TcpListener, IpcError> {
+    let fd = socket(AF_INET, SOCK_STREAM, 0);
+    if fd < 0 {
+        return Err(OsError(os::errno()));
+    }
+    let listener = TcpListener { fd: fd };
+    // So that a restarted servo can listen again at once
+    let on = 1 as c_int;
+    setsockopt(fd, SOL_SOCKET, SO_REUSEADDR, ptr::to_unsafe_ptr(&on) as *c_void,
+               sys::size_of::<c_int>() as u32);
+    let addr = sockaddr(htons(port as u16), htonl(INADDR_LOOPBACK));
+    let len = sys::size_of::<sockaddr_in>() as u32;
+    if bind(fd, ptr::to_unsafe_ptr(&addr), len) != 0 || c_listen(fd, 8) != 0 {
+        return Err(OsError(os::errno()));
+    }
+    Ok(move listener)
+}
+
+/// An HTTP request's path and headers. The body, if any, isn't read.
+pub struct HttpRequest {
+    method: ~str,
+    path: ~str,
+    headers: ~[(~str, ~str)],
+}
+
+impl HttpRequest {
+    /// The value of a header, whatever the case of its name.
+    fn header(&self, name: &str) -> Option<~str> {
+        for self.headers.each |header| {
+            let (ref key, ref value) = *header;
+            if str::to_lower(*key) == str::to_lower(name) {
+                return Some(copy *value);
+            }
+        }
+        None
+    }
+
+    /**
+    Whether the request may be answered. Its Host has to name the loopback
+    interface, so that a page whose domain has been rebound to 127.0.0.1
+    can't reach the server. If it has an Origin, that has to be a devtools
+    front-end's, so that pages a browser shows can't connect either. Tools
+    that aren't web pages, like VS Code, send no Origin.
+    */
+    fn is_trusted(&self) -> bool {
+        let host_ok = match self.header("host") {
+            Some(move host) => is_loopback_host(host),
+            None => false
+        };
+        let origin_ok = match self.header("origin") {
+            Some(move origin) => origin.is_empty() || is_devtools_origin(origin),
+            None => true
+        };
+        host_ok && origin_ok
+    }
+
+    /// Whether this asks to become a WebSocket.
+    fn is_upgrade(&self) -> bool {
+        match self.header("upgrade") {
+            Some(move upgrade) => str::to_lower(upgrade) == ~"websocket",
+            None => false
+        }
+    }
+}
+
+/// Whether `host`, a Host header, names the loopback interface, with or
+/// without a port.
+pub fn is_loopback_host(host: &str) -> bool {
+    let name = match str::rfind_char(host, ':') {
+        Some(i) => host.slice(0, i),
+        None => host.to_str()
+    };
+    match str::to_lower(name) {
+        ~"localhost" | ~"127.0.0.1" => true,
+        _ => false
+    }
+}
+
+/// Whether `origin` is that of a devtools front-end built into a browser.
+pub fn is_devtools_origin(origin: &str) -> bool {
+    match str::to_lower(origin) {
+        ~"devtools://devtools" | ~"chrome-devtools://devtools" => true,
+        _ => false
+    }
+}
+
+/// Parses the head of an HTTP request, up to the blank line after it.
+pub fn parse_request(head: &str) -> Option<HttpRequest> {
+    let lines = str::split_str(head, "\r\n");
+    if lines.is_empty() {
+        return None;
+    }
+    let request_line = str::split_char(lines[0], ' ');
+    if request_line.len() != 3 || !request_line[2].starts_with("HTTP/") {
+        return None;
+    }
+    let mut headers = ~[];
+    for vec::view(lines, 1, lines.len()).each |line| {
+        if line.is_empty() {
+            loop;
+        }
+        match str::find_char(*line, ':') {
+            Some(i) => headers.push((str::trim(line.slice(0, i)),
+                                     str::trim(line.slice(i + 1, line.len())))),
+            None => return None
+        }
+    }
+    Some(HttpRequest {
+        method: copy request_line[0],
+        path: copy request_line[1],
+        headers: move headers
+    })
+}
+
+/// Reads the head of an HTTP request from `stream`.
+pub fn read_request(stream: &TcpStream) -> Result<HttpRequest, IpcError> {
+    let mut head = ~[];
+    while !head.ends_with(&[13u8, 10, 13, 10]) {
+        if head.len() > MAX_HEADER_LEN {
+            return Err(MalformedMessage);
+        }
+        match stream.read(1) {
+            Ok(move byte) => head.push_all(byte),
+            Err(e) => return Err(e)
+        }
+    }
+    let head = vec::view(head, 0, head.len() - 4);
+    if !str::is_utf8(head) {
+        return Err(MalformedMessage);
+    }
+    match parse_request(str::from_bytes(head)) {
+        Some(move request) => Ok(move request),
+        None => Err(MalformedMessage)
+    }
+}
+
+/// Writes a whole HTTP response with a body of `content_type`.
+pub fn write_response(stream: &TcpStream, status: &str, content_type: &str,
+                      body: &str) -> Result<(), IpcError> {
+    let response = fmt!("HTTP/1.1 %s\r\nContent-Type: %s\r\nContent-Length: %u\r\n\
+                         Connection: close\r\n\r\n%s", status, content_type, body.len(), body);
+    stream.write(str::to_bytes(response))
+}
+
+/// The `Sec-WebSocket-Accept` for a client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> ~str {
+    let sha1 = std::sha1::sha1();
+    sha1.input_str(key);
+    sha1.input_str(WEBSOCKET_GUID);
+    sha1.result().to_base64()
+}
+
+/// One frame, unmasked.
+pub struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: ~[u8],
+}
+
+/// A frame as a server sends it: whole, and without a mask.
+pub fn encode_frame(opcode: u8, payload: &[u8]) -> ~[u8] {
+    let mut bytes = vec::with_capacity(payload.len() + 10);
+    bytes.push(0x80 | opcode);
+    let len = payload.len();
+    if len < 126 {
+        bytes.push(len as u8);
+    } else if len < 65536 {
+        bytes.push(126);
+        bytes.push((len >> 8) as u8);
+        bytes.push(len as u8);
+    } else {
+        bytes.push(127);
+        for [56u64, 48, 40, 32, 24, 16, 8, 0].each |shift| {
+            bytes.push((len as u64 >> *shift) as u8);
+        }
+    }
+    bytes.push_all(payload);
+    move bytes
+}
+
+/**
+Reads a frame, taking its bytes from `read`, which reads exactly as many
+as it's asked for. Clients must mask what they send, so unmasked frames are
+malformed.
+*/
+pub fn read_frame(read: fn(uint) -> Result<~[u8], IpcError>) -> Result<Frame, IpcError> {
+    let header = match read(2) {
+        Ok(move header) => move header,
+        Err(e) => return Err(e)
+    };
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0f;
+    if header[1] & 0x80 == 0 {
+        return Err(MalformedMessage);
+    }
+    let len = match header[1] & 0x7f {
+        126 => match read(2) {
+            Ok(move bytes) => (bytes[0] as uint << 8) | bytes[1] as uint,
+            Err(e) => return Err(e)
+        },
+        127 => match read(8) {
+            Ok(move bytes) => {
+                let mut len = 0u64;
+                for bytes.each |b| { len = (len << 8) | *b as u64; }
+                if len > MAX_FRAME_LEN as u64 {
+                    return Err(MalformedMessage);
+                }
+                len as uint
+            }
+            Err(e) => return Err(e)
+        },
+        len => len as uint
+    };
+    if len > MAX_FRAME_LEN {
+        return Err(MalformedMessage);
+    }
+    let mask = match read(4) {
+        Ok(move mask) => move mask,
+        Err(e) => return Err(e)
+    };
+    let mut payload = if len == 0 {
+        ~[]
+    } else {
+        match read(len) {
+            Ok(move payload) => move payload,
+            Err(e) => return Err(e)
+        }
+    };
+    for uint::range(0, payload.len()) |i| {
+        payload[i] ^= mask[i % 4];
+    }
+    Ok(Frame { fin: fin, opcode: opcode, payload: move payload })
+}
+
+/// The server's end of a WebSocket connection, which carries text.
+pub struct WebSocket {
+    priv stream: TcpStream,
+}
+
+impl WebSocket {
+    fn send_text(&self, text: &str) -> Result<(), IpcError> {
+        self.stream.write(encode_frame(OPCODE_TEXT, str::to_bytes(text)))
+    }
+
+    /**
+    Waits for the next message. Pings are answered on the way; None is a
+    message that wasn't text, and ConnectionClosed means the client closed
+    the connection.
+    */
+    fn recv(&self) -> Result<Option<~str>, IpcError> {
+        let mut message = ~[];
+        let mut opcode = OPCODE_CONTINUATION;
+        loop {
+            let frame = match read_frame(|len| self.stream.read(len)) {
+                Ok(move frame) => move frame,
+                Err(e) => return Err(e)
+            };
+            match frame.opcode {
+                OPCODE_PING => {
+                    match self.stream.write(encode_frame(OPCODE_PONG, frame.payload)) {
+                        Ok(()) => loop,
+                        Err(e) => return Err(e)
+                    }
+                }
+                OPCODE_PONG => loop,
+                OPCODE_CLOSE => {
+                    self.stream.write(encode_frame(OPCODE_CLOSE, ~[]));
+                    return Err(ConnectionClosed);
+                }
+                OPCODE_CONTINUATION => (),
+                first => opcode = first
+            }
+            message.push_all(frame.payload);
+            if message.len() > MAX_FRAME_LEN {
+                return Err(MalformedMessage);
+            }
+            if frame.fin {
+                break;
+            }
+        }
+        if opcode != OPCODE_TEXT {
+            return Ok(None);
+        }
+        if !str::is_utf8(message) {
+            return Err(MalformedMessage);
+        }
+        Ok(Some(str::from_bytes(message)))
+    }
+
+    /// Whether there's something to receive within `ms` milliseconds.
+    fn poll(&self, ms: uint) -> bool {
+        self.stream.poll(ms)
+    }
+}
+
+/// Answers `request`, which asked to upgrade `stream`, and makes it a
+/// WebSocket.
+pub fn upgrade(stream: TcpStream, request: &HttpRequest) -> Result<WebSocket, IpcError> {
+    let key = match request.header("sec-websocket-key") {
+        Some(move key) => move key,
+        None => return Err(MalformedMessage)
+    };
+    let response = fmt!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                         Connection: Upgrade\r\nSec-WebSocket-Accept: %s\r\n\r\n",
+                        accept_key(key));
+    match stream.write(str::to_bytes(response)) {
+        Ok(()) => Ok(WebSocket { stream: move stream }),
+        Err(e) => Err(e)
+    }
+}
+
+#[cfg(target_os = "linux")]
+const SOL_SOCKET: c_int = 1;
+#[cfg(target_os = "linux")]
+const SO_REUSEADDR: c_int = 2;
+
+#[cfg(target_os = "linux")]
+struct sockaddr_in {
+    sin_family: u16,
+    sin_port: u16,
+    sin_addr: u32,
+    sin_zero: [u8 * 8],
+}
+
+#[cfg(target_os = "linux")]
+fn sockaddr(port: u16, addr: u32) -> sockaddr_in {
+    sockaddr_in { sin_family: AF_INET as u16, sin_port: port, sin_addr: addr, sin_zero: [0, ..8] }
+}
+
+#[cfg(target_os = "macos")]
+const SOL_SOCKET: c_int = 0xffff;
+#[cfg(target_os = "macos")]
+const SO_REUSEADDR: c_int = 4;
+
+#[cfg(target_os = "macos")]
+struct sockaddr_in {
+    sin_len: u8,
+    sin_family: u8,
+    sin_port: u16,
+    sin_addr: u32,
+    sin_zero: [u8 * 8],
+}
+
+#[cfg(target_os = "macos")]
+fn sockaddr(port: u16, addr: u32) -> sockaddr_in {
+    sockaddr_in {
+        sin_len: sys::size_of::<sockaddr_in>() as u8,
+        sin_family: AF_INET as u8,
+        sin_port: port,
+        sin_addr: addr,
+        sin_zero: [0, ..8]
+    }
+}
+
+extern {
+    fn socket(domain: c_int, ty: c_int, protocol: c_int) -> c_int;
+    fn setsockopt(fd: c_int, level: c_int, name: c_int, value: *c_void, len: u32) -> c_int;
+    fn bind(fd: c_int, addr: *sockaddr_in, len: u32) -> c_int;
+    fn htons(n: u16) -> u16;
+    fn htonl(n: u32) -> u32;
+    #[link_name = "listen"]
+    fn c_listen(fd: c_int, backlog: c_int) -> c_int;
+    #[link_name = "accept"]
+    fn c_accept(fd: c_int, addr: *sockaddr_in, len: *u32) -> c_int;
+}
+
+#[cfg(test)]
+mod websocket_tests {
+    // Reads from `bytes` as a socket would
+    fn reader(bytes: ~[u8]) -> fn@(uint) -> Result<~[u8], IpcError> {
+        let pos = @mut 0u;
+        |len| {
+            if *pos + len > bytes.len() {
+                Err(ConnectionClosed)
+            } else {
+                let read = vec::slice(bytes, *pos, *pos + len);
+                *pos += len;
+                Ok(move read)
+            }
+        }
+    }
+
+    // A frame as a client sends it, masked
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> ~[u8] {
+        let mask = [0x12u8, 0x34, 0x56, 0x78];
+        let mut bytes = encode_frame(opcode, payload);
+        let offset = if payload.len() < 126 { 2 } else if payload.len() < 65536 { 4 } else { 10 };
+        if !fin {
+            bytes[0] &= 0x7f;
+        }
+        bytes[1] |= 0x80;
+        let mut masked = vec::slice(bytes, 0, offset);
+        masked.push_all(mask);
+        for payload.eachi |i, b| { masked.push(*b ^ mask[i % 4]); }
+        move masked
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // The example from RFC 6455
+        assert accept_key("dGhlIHNhbXBsZSBub25jZQ==") == ~"s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let request = parse_request("GET /devtools/page/servo HTTP/1.1\r\nHost: localhost:9222\r\n\
+                                     Upgrade: websocket\r\nSec-WebSocket-Key: abc==").get();
+        assert request.method == ~"GET";
+        assert request.path == ~"/devtools/page/servo";
+        assert request.header("host") == Some(~"localhost:9222");
+        assert request.header("Sec-Websocket-Key") == Some(~"abc==");
+        assert request.is_upgrade();
+
+        assert !parse_request("GET /json HTTP/1.1").get().is_upgrade();
+        assert parse_request("hello").is_none();
+        assert parse_request("GET / HTTP/1.1\r\nnot a header").is_none();
+    }
+
+    #[test]
+    fn test_trusted() {
+        let request = |head: &str| parse_request(~"GET /json HTTP/1.1\r\n" + head).get();
+        assert request("Host: localhost:9222").is_trusted();
+        assert request("Host: 127.0.0.1:9222\r\nOrigin: devtools://devtools").is_trusted();
+        // A page from elsewhere, or one whose name resolves to 127.0.0.1
+        assert !request("Host: 127.0.0.1:9222\r\nOrigin: http://example.com").is_trusted();
+        assert !request("Host: rebound.example.com:9222").is_trusted();
+        assert !request("Origin: devtools://devtools").is_trusted();
+    }
+
+    #[test]
+    fn test_frames() {
+        let frame = read_frame(reader(client_frame(true, OPCODE_TEXT,
+                                                   str::to_bytes("hello")))).get();
+        assert frame.fin && frame.opcode == OPCODE_TEXT;
+        assert frame.payload == str::to_bytes("hello");
+
+        let long = vec::from_elem(300, 7u8);
+        let frame = read_frame(reader(client_frame(false, OPCODE_BINARY, long))).get();
+        assert !frame.fin && frame.payload == long;
+
+        let encoded = encode_frame(OPCODE_TEXT, long);
+        assert encoded[1] == 126 && encoded.len() == 304;
+
+        // Servers don't take unmasked frames
+        assert read_frame(reader(encode_frame(OPCODE_TEXT, ~[1, 2]))).get_err() ==
+            MalformedMessage;
+        assert read_frame(reader(~[0x81])).get_err() == ConnectionClosed;
+    }
+}
@@ -0,0 +1,58 @@
+/*!
+`AbortController` and `AbortSignal`: a way for scripts to call off work
+that's under way. For now that's only script's own work, as nothing takes a
+signal yet: a signal can be aborted, listened to and checked with
+`throwIfAborted()`. The reason it's aborted with is kept on its JS object,
+so that the GC sees it.
+*/
+
+pub struct AbortSignal {
+    mut aborted: bool,
+}
+
+pub fn AbortSignal() -> AbortSignal {
+    AbortSignal { aborted: false }
+}
+
+impl AbortSignal {
+    /**
+    Marks the signal aborted. Returns false if it was aborted already, in
+    which case nothing happens; otherwise the caller keeps the reason and
+    fires `abort` at it.
+    */
+    fn signal_abort(&self) -> bool {
+        if self.aborted {
+            return false;
+        }
+        self.aborted = true;
+        true
+    }
+}
+
+pub struct AbortController {
+    signal: @AbortSignal,
+}
+
+pub fn AbortController() -> AbortController {
+    AbortController { signal: @AbortSignal() }
+}
+
+impl AbortController {
+    /// Aborts the controller's signal. See `AbortSignal::signal_abort`.
+    fn abort(&self) -> bool {
+        self.signal.signal_abort()
+    }
+}
+
+#[test]
+fn test_abort() {
+    let controller = AbortController();
+    assert !controller.signal.aborted;
+
+    assert controller.abort();
+    assert controller.signal.aborted;
+
+    // Aborting again does nothing
+    assert !controller.abort();
+    assert controller.signal.aborted;
+}
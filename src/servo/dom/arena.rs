@@ -0,0 +1,174 @@
+/*!
+A typed arena for DOM node data. Nodes are allocated and freed often, so
+rather than going to malloc for each one, the COW scope takes them from
+large slabs, and slots that are given back are reused before the slabs
+grow. Each type of value has an arena of its own, and so a free list of
+its own.
+
+Slots are handed out zeroed, as the COW scope's copies expect, and the
+arena never runs drop glue: whoever releases a slot must have dropped
+its value first. The slabs are freed with the arena.
+*/
+
+use core::libc::types::os::arch::c95::size_t;
+use core::libc::c_void;
+
+// Slots per slab
+pub const DEFAULT_SLAB_LEN: uint = 256;
+
+pub struct Arena<T> {
+    priv mut slabs: ~[*mut T],
+    priv slab_len: uint,
+    // How many slots of the newest slab have been handed out
+    priv mut used: uint,
+    priv mut free_list: ~[*mut T],
+
+    drop unsafe {
+        for self.slabs.each |slab| {
+            libc::free(*slab as *c_void);
+        }
+    }
+}
+
+pub fn Arena<T>(slab_len: uint) -> Arena<T> {
+    assert slab_len > 0;
+    Arena {
+        slabs: ~[],
+        slab_len: slab_len,
+        used: 0,
+        free_list: ~[]
+    }
+}
+
+impl<T> Arena<T> {
+    /// A zeroed slot for a T, reusing a released one if there is one.
+    unsafe fn alloc(&self) -> *mut T {
+        let size = sys::size_of::<T>();
+        let slot = if !self.free_list.is_empty() {
+            self.free_list.pop()
+        } else {
+            if self.slabs.is_empty() || self.used == self.slab_len {
+                let slab: *mut T = cast::reinterpret_cast(
+                    &libc::calloc(self.slab_len as size_t, size as size_t));
+                assert slab.is_not_null();
+                self.slabs.push(slab);
+                self.used = 0;
+            }
+            let slot = ptr::mut_offset(self.slabs[self.slabs.len() - 1], self.used);
+            self.used += 1;
+            slot
+        };
+        libc::memset(slot as *c_void, 0, size as size_t);
+        slot
+    }
+
+    /// Gives back a slot, whose value has already been dropped.
+    unsafe fn release(&self, slot: *mut T) {
+        assert self.contains(slot as *T);
+        self.free_list.push(slot);
+    }
+
+    /// Whether `p` points at a slot of this arena.
+    fn contains(&self, p: *T) -> bool {
+        let size = sys::size_of::<T>();
+        let addr = p as uint;
+        for self.slabs.each |slab| {
+            let start = *slab as uint;
+            if addr >= start && addr < start + self.slab_len * size {
+                return (addr - start) % size == 0;
+            }
+        }
+        false
+    }
+
+    /// How many slots are in use.
+    fn len(&self) -> uint {
+        if self.slabs.is_empty() {
+            return 0;
+        }
+        self.capacity() - self.slab_len + self.used - self.free_list.len()
+    }
+
+    /// How many slots the slabs hold.
+    fn capacity(&self) -> uint {
+        self.slabs.len() * self.slab_len
+    }
+
+    /**
+    Calls `f` with each slot in use, so that a collector can scan them,
+    until it returns false.
+    */
+    unsafe fn each_live(&self, f: fn(*T) -> bool) {
+        let mut free = self.free_list.map(|slot| *slot as uint);
+        sort::quick_sort3(free);
+        for self.slabs.eachi |i, slab| {
+            let in_use = if i == self.slabs.len() - 1 { self.used } else { self.slab_len };
+            for uint::range(0, in_use) |j| {
+                let slot = ptr::mut_offset(*slab, j);
+                if !is_in_sorted(free, slot as uint) && !f(slot as *T) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// Binary search, for addresses on the free list
+fn is_in_sorted(v: &[uint], x: uint) -> bool {
+    let mut low = 0;
+    let mut high = v.len();
+    while low < high {
+        let mid = (low + high) / 2;
+        if v[mid] == x {
+            return true;
+        } else if v[mid] < x {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod arena_tests {
+    #[test]
+    fn test_alloc_and_reuse() unsafe {
+        let arena: Arena<(uint, uint)> = Arena(2);
+        let a = arena.alloc();
+        *a = (1, 2);
+        let b = arena.alloc();
+        let c = arena.alloc();
+        assert *c == (0, 0);
+        assert arena.capacity() == 4;
+        assert arena.len() == 3;
+        assert arena.contains(b as *(uint, uint));
+        assert !arena.contains(ptr::null());
+
+        // A released slot is handed out again, zeroed
+        arena.release(a);
+        assert arena.len() == 2;
+        let d = arena.alloc();
+        assert d == a;
+        assert *d == (0, 0);
+        assert arena.capacity() == 4;
+    }
+
+    #[test]
+    fn test_each_live() unsafe {
+        let arena: Arena<uint> = Arena(2);
+        let slots = vec::from_fn(5, |i| {
+            let slot = arena.alloc();
+            *slot = i;
+            slot
+        });
+        arena.release(slots[1]);
+        arena.release(slots[3]);
+
+        let mut seen = ~[];
+        for arena.each_live |slot| {
+            seen.push(*slot);
+        }
+        assert seen == ~[0, 2, 4];
+    }
+}
@@ -0,0 +1,430 @@
+/*!
+ARIA: the `role` and `aria-*` attributes pages use to describe their
+elements to assistive technologies. These parse the attribute values; the
+accessibility tree decides what they mean.
+*/
+
+/// The WAI-ARIA and DPUB-ARIA roles. Abstract roles, which authors may not
+/// use, aren't included.
+pub enum AriaRole {
+    RoleAlert,
+    RoleAlertDialog,
+    RoleApplication,
+    RoleArticle,
+    RoleBanner,
+    RoleBlockquote,
+    RoleButton,
+    RoleCaption,
+    RoleCell,
+    RoleCheckbox,
+    RoleCode,
+    RoleColumnHeader,
+    RoleCombobox,
+    RoleComplementary,
+    RoleContentInfo,
+    RoleDefinition,
+    RoleDeletion,
+    RoleDialog,
+    RoleDirectory,
+    RoleDocument,
+    RoleEmphasis,
+    RoleFeed,
+    RoleFigure,
+    RoleForm,
+    RoleGeneric,
+    RoleGrid,
+    RoleGridCell,
+    RoleGroup,
+    RoleHeading,
+    RoleImg,
+    RoleInsertion,
+    RoleLink,
+    RoleList,
+    RoleListbox,
+    RoleListItem,
+    RoleLog,
+    RoleMain,
+    RoleMarquee,
+    RoleMath,
+    RoleMenu,
+    RoleMenubar,
+    RoleMenuItem,
+    RoleMenuItemCheckbox,
+    RoleMenuItemRadio,
+    RoleMeter,
+    RoleNavigation,
+    RoleNone,
+    RoleNote,
+    RoleOption,
+    RoleParagraph,
+    RolePresentation,
+    RoleProgressbar,
+    RoleRadio,
+    RoleRadioGroup,
+    RoleRegion,
+    RoleRow,
+    RoleRowGroup,
+    RoleRowHeader,
+    RoleScrollbar,
+    RoleSearch,
+    RoleSearchbox,
+    RoleSeparator,
+    RoleSlider,
+    RoleSpinbutton,
+    RoleStatus,
+    RoleStrong,
+    RoleSubscript,
+    RoleSuperscript,
+    RoleSwitch,
+    RoleTab,
+    RoleTable,
+    RoleTabList,
+    RoleTabPanel,
+    RoleTerm,
+    RoleTextbox,
+    RoleTime,
+    RoleTimer,
+    RoleToolbar,
+    RoleTooltip,
+    RoleTree,
+    RoleTreeGrid,
+    RoleTreeItem,
+
+    // DPUB-ARIA
+    RoleDocAbstract,
+    RoleDocAcknowledgments,
+    RoleDocAfterword,
+    RoleDocAppendix,
+    RoleDocBacklink,
+    RoleDocBiblioEntry,
+    RoleDocBibliography,
+    RoleDocBiblioRef,
+    RoleDocChapter,
+    RoleDocColophon,
+    RoleDocConclusion,
+    RoleDocCover,
+    RoleDocCredit,
+    RoleDocCredits,
+    RoleDocDedication,
+    RoleDocEndnote,
+    RoleDocEndnotes,
+    RoleDocEpigraph,
+    RoleDocEpilogue,
+    RoleDocErrata,
+    RoleDocExample,
+    RoleDocFootnote,
+    RoleDocForeword,
+    RoleDocGlossary,
+    RoleDocGlossRef,
+    RoleDocIndex,
+    RoleDocIntroduction,
+    RoleDocNoteRef,
+    RoleDocNotice,
+    RoleDocPageBreak,
+    RoleDocPageFooter,
+    RoleDocPageHeader,
+    RoleDocPageList,
+    RoleDocPart,
+    RoleDocPreface,
+    RoleDocPrologue,
+    RoleDocPullquote,
+    RoleDocQna,
+    RoleDocSubtitle,
+    RoleDocTip,
+    RoleDocToc,
+}
+
+impl AriaRole : cmp::Eq {
+    pure fn eq(&self, other: &AriaRole) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &AriaRole) -> bool {
+        !self.eq(other)
+    }
+}
+
+/**
+Parses a `role` attribute. The value is a list of roles, and the first one
+this knows about wins, so that pages can fall back from newer roles.
+*/
+pub fn parse_aria_role(value: &str) -> Option<AriaRole> {
+    for str::words(value).each |word| {
+        match parse_single_role(str::to_lower(*word)) {
+            Some(role) => return Some(role),
+            None => ()
+        }
+    }
+    None
+}
+
+fn parse_single_role(role: &str) -> Option<AriaRole> {
+    Some(match role {
+        "alert" => RoleAlert,
+        "alertdialog" => RoleAlertDialog,
+        "application" => RoleApplication,
+        "article" => RoleArticle,
+        "banner" => RoleBanner,
+        "blockquote" => RoleBlockquote,
+        "button" => RoleButton,
+        "caption" => RoleCaption,
+        "cell" => RoleCell,
+        "checkbox" => RoleCheckbox,
+        "code" => RoleCode,
+        "columnheader" => RoleColumnHeader,
+        "combobox" => RoleCombobox,
+        "complementary" => RoleComplementary,
+        "contentinfo" => RoleContentInfo,
+        "definition" => RoleDefinition,
+        "deletion" => RoleDeletion,
+        "dialog" => RoleDialog,
+        "directory" => RoleDirectory,
+        "document" => RoleDocument,
+        "emphasis" => RoleEmphasis,
+        "feed" => RoleFeed,
+        "figure" => RoleFigure,
+        "form" => RoleForm,
+        "generic" => RoleGeneric,
+        "grid" => RoleGrid,
+        "gridcell" => RoleGridCell,
+        "group" => RoleGroup,
+        "heading" => RoleHeading,
+        "img" | "image" => RoleImg,
+        "insertion" => RoleInsertion,
+        "link" => RoleLink,
+        "list" => RoleList,
+        "listbox" => RoleListbox,
+        "listitem" => RoleListItem,
+        "log" => RoleLog,
+        "main" => RoleMain,
+        "marquee" => RoleMarquee,
+        "math" => RoleMath,
+        "menu" => RoleMenu,
+        "menubar" => RoleMenubar,
+        "menuitem" => RoleMenuItem,
+        "menuitemcheckbox" => RoleMenuItemCheckbox,
+        "menuitemradio" => RoleMenuItemRadio,
+        "meter" => RoleMeter,
+        "navigation" => RoleNavigation,
+        "none" => RoleNone,
+        "note" => RoleNote,
+        "option" => RoleOption,
+        "paragraph" => RoleParagraph,
+        "presentation" => RolePresentation,
+        "progressbar" => RoleProgressbar,
+        "radio" => RoleRadio,
+        "radiogroup" => RoleRadioGroup,
+        "region" => RoleRegion,
+        "row" => RoleRow,
+        "rowgroup" => RoleRowGroup,
+        "rowheader" => RoleRowHeader,
+        "scrollbar" => RoleScrollbar,
+        "search" => RoleSearch,
+        "searchbox" => RoleSearchbox,
+        "separator" => RoleSeparator,
+        "slider" => RoleSlider,
+        "spinbutton" => RoleSpinbutton,
+        "status" => RoleStatus,
+        "strong" => RoleStrong,
+        "subscript" => RoleSubscript,
+        "superscript" => RoleSuperscript,
+        "switch" => RoleSwitch,
+        "tab" => RoleTab,
+        "table" => RoleTable,
+        "tablist" => RoleTabList,
+        "tabpanel" => RoleTabPanel,
+        "term" => RoleTerm,
+        "textbox" => RoleTextbox,
+        "time" => RoleTime,
+        "timer" => RoleTimer,
+        "toolbar" => RoleToolbar,
+        "tooltip" => RoleTooltip,
+        "tree" => RoleTree,
+        "treegrid" => RoleTreeGrid,
+        "treeitem" => RoleTreeItem,
+        "doc-abstract" => RoleDocAbstract,
+        "doc-acknowledgments" => RoleDocAcknowledgments,
+        "doc-afterword" => RoleDocAfterword,
+        "doc-appendix" => RoleDocAppendix,
+        "doc-backlink" => RoleDocBacklink,
+        "doc-biblioentry" => RoleDocBiblioEntry,
+        "doc-bibliography" => RoleDocBibliography,
+        "doc-biblioref" => RoleDocBiblioRef,
+        "doc-chapter" => RoleDocChapter,
+        "doc-colophon" => RoleDocColophon,
+        "doc-conclusion" => RoleDocConclusion,
+        "doc-cover" => RoleDocCover,
+        "doc-credit" => RoleDocCredit,
+        "doc-credits" => RoleDocCredits,
+        "doc-dedication" => RoleDocDedication,
+        "doc-endnote" => RoleDocEndnote,
+        "doc-endnotes" => RoleDocEndnotes,
+        "doc-epigraph" => RoleDocEpigraph,
+        "doc-epilogue" => RoleDocEpilogue,
+        "doc-errata" => RoleDocErrata,
+        "doc-example" => RoleDocExample,
+        "doc-footnote" => RoleDocFootnote,
+        "doc-foreword" => RoleDocForeword,
+        "doc-glossary" => RoleDocGlossary,
+        "doc-glossref" => RoleDocGlossRef,
+        "doc-index" => RoleDocIndex,
+        "doc-introduction" => RoleDocIntroduction,
+        "doc-noteref" => RoleDocNoteRef,
+        "doc-notice" => RoleDocNotice,
+        "doc-pagebreak" => RoleDocPageBreak,
+        "doc-pagefooter" => RoleDocPageFooter,
+        "doc-pageheader" => RoleDocPageHeader,
+        "doc-pagelist" => RoleDocPageList,
+        "doc-part" => RoleDocPart,
+        "doc-preface" => RoleDocPreface,
+        "doc-prologue" => RoleDocPrologue,
+        "doc-pullquote" => RoleDocPullquote,
+        "doc-qna" => RoleDocQna,
+        "doc-subtitle" => RoleDocSubtitle,
+        "doc-tip" => RoleDocTip,
+        "doc-toc" => RoleDocToc,
+        _ => return None
+    })
+}
+
+/// The values of `aria-checked` and `aria-pressed`.
+pub enum AriaTristate {
+    TristateFalse,
+    TristateTrue,
+    TristateMixed
+}
+
+impl AriaTristate : cmp::Eq {
+    pure fn eq(&self, other: &AriaTristate) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &AriaTristate) -> bool {
+        !self.eq(other)
+    }
+}
+
+/// The values of `aria-live`.
+pub enum AriaLive {
+    LiveOff,
+    LivePolite,
+    LiveAssertive
+}
+
+pub enum AriaProperty {
+    AriaLabel(~str),
+    AriaLabelledBy(~[~str]),
+    AriaDescribedBy(~[~str]),
+    AriaDescription(~str),
+    AriaHidden(bool),
+    AriaExpanded(bool),
+    AriaDisabled(bool),
+    AriaSelected(bool),
+    AriaChecked(AriaTristate),
+    AriaPressed(AriaTristate),
+    AriaRequired(bool),
+    AriaReadOnly(bool),
+    AriaModal(bool),
+    AriaBusy(bool),
+    AriaAtomic(bool),
+    AriaLive(AriaLive),
+    AriaLevel(uint),
+    AriaValueNow(float),
+    AriaValueMin(float),
+    AriaValueMax(float),
+    AriaValueText(~str),
+}
+
+/**
+Parses an `aria-*` attribute. Returns None for attributes that aren't ARIA
+properties this knows about, and for invalid values, which the spec says
+to treat as if the attribute were missing.
+*/
+pub fn parse_aria_property(name: &str, value: &str) -> Option<AriaProperty> {
+    let value = str::trim(value);
+    match str::to_lower(name) {
+        ~"aria-label" => Some(AriaLabel(move value)),
+        ~"aria-labelledby" => Some(AriaLabelledBy(id_list(value))),
+        ~"aria-describedby" => Some(AriaDescribedBy(id_list(value))),
+        ~"aria-description" => Some(AriaDescription(move value)),
+        ~"aria-hidden" => parse_bool(value).map(|b| AriaHidden(*b)),
+        ~"aria-expanded" => parse_bool(value).map(|b| AriaExpanded(*b)),
+        ~"aria-disabled" => parse_bool(value).map(|b| AriaDisabled(*b)),
+        ~"aria-selected" => parse_bool(value).map(|b| AriaSelected(*b)),
+        ~"aria-required" => parse_bool(value).map(|b| AriaRequired(*b)),
+        ~"aria-readonly" => parse_bool(value).map(|b| AriaReadOnly(*b)),
+        ~"aria-modal" => parse_bool(value).map(|b| AriaModal(*b)),
+        ~"aria-busy" => parse_bool(value).map(|b| AriaBusy(*b)),
+        ~"aria-atomic" => parse_bool(value).map(|b| AriaAtomic(*b)),
+        ~"aria-checked" => parse_tristate(value).map(|t| AriaChecked(*t)),
+        ~"aria-pressed" => parse_tristate(value).map(|t| AriaPressed(*t)),
+        ~"aria-live" => match str::to_lower(value) {
+            ~"off" => Some(AriaLive(LiveOff)),
+            ~"polite" => Some(AriaLive(LivePolite)),
+            ~"assertive" => Some(AriaLive(LiveAssertive)),
+            _ => None
+        },
+        ~"aria-level" => match uint::from_str(value) {
+            Some(level) if level > 0 => Some(AriaLevel(level)),
+            _ => None
+        },
+        ~"aria-valuenow" => float::from_str(value).map(|n| AriaValueNow(*n)),
+        ~"aria-valuemin" => float::from_str(value).map(|n| AriaValueMin(*n)),
+        ~"aria-valuemax" => float::from_str(value).map(|n| AriaValueMax(*n)),
+        ~"aria-valuetext" => Some(AriaValueText(move value)),
+        _ => None
+    }
+}
+
+/// Whether changing the attribute `name` can change the accessibility tree.
+pub pure fn is_aria_attribute(name: &str) -> bool {
+    name == "role" || str::starts_with(name, "aria-")
+}
+
+fn id_list(value: &str) -> ~[~str] {
+    str::words(value)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match str::to_lower(value) {
+        ~"true" => Some(true),
+        ~"false" => Some(false),
+        _ => None
+    }
+}
+
+fn parse_tristate(value: &str) -> Option<AriaTristate> {
+    match str::to_lower(value) {
+        ~"true" => Some(TristateTrue),
+        ~"false" => Some(TristateFalse),
+        ~"mixed" => Some(TristateMixed),
+        _ => None
+    }
+}
+
+#[test]
+fn test_parse_aria_role() {
+    assert parse_aria_role("button") == Some(RoleButton);
+    assert parse_aria_role("Doc-Chapter") == Some(RoleDocChapter);
+    // The first role that's known wins
+    assert parse_aria_role("switch-v2 checkbox link") == Some(RoleCheckbox);
+    // Abstract roles can't be used
+    assert parse_aria_role("widget").is_none();
+}
+
+#[test]
+fn test_parse_aria_property() {
+    match parse_aria_property("aria-hidden", " TRUE ") {
+        Some(AriaHidden(true)) => (),
+        _ => fail
+    }
+    match parse_aria_property("aria-labelledby", "a  b") {
+        Some(AriaLabelledBy(ids)) => assert ids == ~[~"a", ~"b"],
+        _ => fail
+    }
+    match parse_aria_property("aria-checked", "mixed") {
+        Some(AriaChecked(TristateMixed)) => (),
+        _ => fail
+    }
+    assert parse_aria_property("aria-expanded", "yes").is_none();
+    assert parse_aria_property("aria-level", "0").is_none();
+    assert parse_aria_property("data-foo", "1").is_none();
+}
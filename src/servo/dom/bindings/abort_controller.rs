@@ -0,0 +1,232 @@
+use js::rust::{bare_compartment, methods};
+use js::{JS_ARGV, JSPROP_ENUMERATE, JSPROP_SHARED, JSPROP_READONLY, JSVAL_NULL, JSVAL_VOID,
+            JS_THIS_OBJECT, JS_SET_RVAL, JSPROP_NATIVE_ACCESSORS};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp};
+use js::jsapi::bindgen::{JS_GetReservedSlot, JS_SetReservedSlot, JS_DefineFunctions,
+                            JS_DefineProperty, JS_DefineProperties, JS_GetProperty,
+                            JS_SetProperty, JS_SetPendingException};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use ptr::null;
+use libc::c_uint;
+use utils::{rust_box, squirrel_away, get_compartment, new_error};
+use content::content_task::task_from_context;
+use dom::abort_controller::{AbortController, AbortSignal};
+
+// Where a signal keeps the reason it was aborted with, so that it lives as
+// long as the signal does
+const REASON_PROPERTY: &static/str = "__reason";
+
+unsafe fn define_readonly(cx: *JSContext, obj: *JSObject, name: &str, val: JSVal) {
+    do str::as_c_str(name) |s| {
+        JS_DefineProperty(cx, obj, s, val,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE | JSPROP_READONLY);
+    }
+}
+
+// Keeps `reason` on the signal's object, once it's been aborted
+unsafe fn set_reason(cx: *JSContext, signal: *JSObject, reason: JSVal) {
+    // Not enumerable, as it's only there for the reason getter
+    do str::as_c_str(REASON_PROPERTY) |s| {
+        JS_DefineProperty(cx, signal, s, reason,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_READONLY);
+    }
+}
+
+/// The JS object for `signal`.
+pub unsafe fn new_signal(cx: *JSContext, signal: @AbortSignal) -> *JSObject {
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"AbortSignalInstance", ~"AbortSignal",
+                                          compartment.global_obj.ptr));
+    let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(signal));
+    JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+    obj.ptr
+}
+
+// The reason passed to abort(), or an AbortError if there isn't one
+unsafe fn reason_arg(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSVal {
+    let argv = JS_ARGV(cx, vp);
+    if argc > 0 && RUST_JSVAL_IS_VOID(*argv) == 0 {
+        *argv
+    } else {
+        new_error(cx, "AbortError", "The operation was aborted.")
+    }
+}
+
+extern fn AbortController_constructor(cx: *JSContext, _argc: c_uint,
+                                      vp: *JSVal) -> JSBool unsafe {
+    let controller = @AbortController();
+
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"AbortControllerInstance", ~"AbortController",
+                                          compartment.global_obj.ptr));
+    define_readonly(cx, obj.ptr, "signal",
+                    RUST_OBJECT_TO_JSVAL(new_signal(cx, controller.signal)));
+
+    let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(controller));
+    JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(obj.ptr));
+    return 1;
+}
+
+extern fn abort(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    if (*unwrap_controller(obj)).payload.abort() {
+        let signal = JSVAL_NULL;
+        do str::as_c_str("signal") |s| {
+            JS_GetProperty(cx, obj, s, ptr::to_unsafe_ptr(&signal));
+        }
+        set_reason(cx, RUST_JSVAL_TO_OBJECT(signal), reason_arg(cx, argc, vp));
+        let event = utils::new_event(cx, "abort", signal);
+        let not_cancelable = RUST_BOOLEAN_TO_JSVAL(0);
+        do str::as_c_str("cancelable") |s| {
+            JS_SetProperty(cx, event, s, ptr::to_unsafe_ptr(&not_cancelable));
+        }
+        (*task_from_context(cx)).dispatch_event(signal, "abort", RUST_OBJECT_TO_JSVAL(event));
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+// A signal that's aborted already, without firing anything
+extern fn AbortSignal_abort(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let signal = @AbortSignal();
+    signal.signal_abort();
+    let obj = new_signal(cx, signal);
+    set_reason(cx, obj, reason_arg(cx, argc, vp));
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(obj));
+    return 1;
+}
+
+extern fn throwIfAborted(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    if (*unwrap_signal(obj)).payload.aborted {
+        JS_SetPendingException(cx, get_reason(cx, obj));
+        return 0;
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn getAborted(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = RUST_BOOLEAN_TO_JSVAL((*unwrap_signal(obj)).payload.aborted as JSBool);
+    return 1;
+}
+
+extern fn getReason(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = get_reason(cx, obj);
+    return 1;
+}
+
+// The reason `signal` was aborted with; undefined if it hasn't been
+unsafe fn get_reason(cx: *JSContext, signal: *JSObject) -> JSVal {
+    let reason = JSVAL_VOID;
+    do str::as_c_str(REASON_PROPERTY) |s| {
+        JS_GetProperty(cx, signal, s, ptr::to_unsafe_ptr(&reason));
+    }
+    reason
+}
+
+unsafe fn unwrap_controller(obj: *JSObject) -> *rust_box<AbortController> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+unsafe fn unwrap_signal(obj: *JSObject) -> *rust_box<AbortSignal> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+extern fn finalize_controller(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("abort controller finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @AbortController = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+extern fn finalize_signal(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("abort signal finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @AbortSignal = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+pub fn init(compartment: &bare_compartment) {
+    let controller = utils::define_constructor(~"AbortController", None,
+                                               AbortController_constructor, compartment);
+    let methods = ~[{name: compartment.add_name(~"abort"),
+                     call: {op: abort, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, controller.ptr, fns);
+    });
+    compartment.register_class(utils::instance_jsclass(~"AbortControllerInstance",
+                                                       finalize_controller));
+
+    // Signals only come from controllers and AbortSignal.abort()
+    let signal = utils::define_empty_prototype(~"AbortSignal", None, compartment);
+
+    //TODO: abort should only be on the constructor, but it's the same object
+    //      as the prototype here.
+    let methods = ~[{name: compartment.add_name(~"throwIfAborted"),
+                     call: {op: throwIfAborted, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"abort"),
+                     call: {op: AbortSignal_abort, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, signal.ptr, fns);
+    });
+
+    let attrs = @~[
+        {name: compartment.add_name(~"aborted"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getAborted, info: null()},
+         setter: {op: null(), info: null()}},
+
+        {name: compartment.add_name(~"reason"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getReason, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        assert JS_DefineProperties(compartment.cx.ptr, signal.ptr, specs) == 1;
+    });
+
+    // addEventListener("abort", ...) and onabort both work through the
+    // usual dispatch
+    bindings::event_target::init(compartment, signal.ptr);
+    compartment.register_class(utils::instance_jsclass(~"AbortSignalInstance",
+                                                       finalize_signal));
+}
@@ -0,0 +1,407 @@
+use js::rust::{bare_compartment, methods, jsobj};
+use js::{JS_ARGV, JSPROP_ENUMERATE, JSPROP_SHARED, JSVAL_NULL, JSVAL_VOID, JS_THIS_OBJECT,
+            JS_SET_RVAL, JSPROP_NATIVE_ACCESSORS};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp};
+use js::jsapi::bindgen::{JS_GetReservedSlot, JS_SetReservedSlot, JS_DefineFunctions,
+                            JS_DefineProperties, JS_GetProperty, JS_SetProperty, JS_GetClass,
+                            JS_GetArrayLength, JS_GetElement, JS_IsArrayObject,
+                            JS_NewNumberValue, JS_ReportError};
+use js::glue::bindgen::*;
+use ptr::null;
+use libc::c_uint;
+use utils::{domstring_to_jsval, rust_box, squirrel_away, jsval_to_str, str, get_compartment};
+use typed_array::{is_buffer_source, buffer_source_bytes, create_array_buffer};
+use content::content_task::task_from_context;
+use dom::blob::{Blob, BlobPart, ArrayBufferPart, StringPart, NestedBlobPart};
+use dom::file::File;
+use dom::file;
+use dom::file_reader::{ReadyState, Empty, Loading, Done, read_as_text, read_as_data_url};
+use dom::window::Window;
+
+/// What's behind a Blob or File JS object.
+pub enum BlobObject {
+    BlobObj(Blob),
+    FileObj(File)
+}
+
+impl BlobObject {
+    fn blob(&self) -> &self/Blob {
+        match *self {
+            BlobObj(ref blob) => blob,
+            FileObj(ref file) => &file.blob
+        }
+    }
+}
+
+unsafe fn unwrap(obj: *JSObject) -> *rust_box<BlobObject> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+/// The Blob or File behind a JS value, if it is one.
+pub unsafe fn unwrap_blob(cx: *JSContext, val: JSVal) -> Option<@BlobObject> {
+    if RUST_JSVAL_IS_OBJECT(val) == 0 || RUST_JSVAL_IS_NULL(val) == 1 {
+        return None;
+    }
+    let obj = RUST_JSVAL_TO_OBJECT(val);
+    let class_name = str::raw::from_c_str((*JS_GetClass(obj)).name);
+    if class_name == ~"BlobInstance" || class_name == ~"FileInstance" {
+        let boxed: @BlobObject = cast::reinterpret_cast(&unwrap(obj));
+        let blob = copy boxed;
+        cast::forget(move boxed);
+        Some(blob)
+    } else {
+        None
+    }
+}
+
+fn wrap(cx: *JSContext, class: ~str, proto: ~str, payload: BlobObject) -> JSVal unsafe {
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(move class, move proto, compartment.global_obj.ptr));
+    let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(@move payload));
+    JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+    RUST_OBJECT_TO_JSVAL(obj.ptr)
+}
+
+pub fn new_blob_object(cx: *JSContext, blob: Blob) -> JSVal {
+    wrap(cx, ~"BlobInstance", ~"Blob", BlobObj(move blob))
+}
+
+pub fn new_file_object(cx: *JSContext, file: File) -> JSVal {
+    wrap(cx, ~"FileInstance", ~"File", FileObj(move file))
+}
+
+unsafe fn get_property(cx: *JSContext, obj: *JSObject, name: &str) -> Option<JSVal> {
+    if obj.is_null() {
+        return None;
+    }
+    let val = JSVAL_VOID;
+    let found = do str::as_c_str(name) |s| {
+        JS_GetProperty(cx, obj, s, ptr::to_unsafe_ptr(&val))
+    };
+    if found == 0 || RUST_JSVAL_IS_VOID(val) == 1 { None } else { Some(val) }
+}
+
+// The `blobParts` argument: an array of ArrayBuffers, typed arrays, Blobs
+// and strings
+unsafe fn get_parts(cx: *JSContext, val: JSVal) -> Result<~[BlobPart], ()> {
+    if RUST_JSVAL_IS_VOID(val) == 1 {
+        return Ok(~[]);
+    }
+    let array = if RUST_JSVAL_IS_OBJECT(val) == 1 { RUST_JSVAL_TO_OBJECT(val) } else { null() };
+    if array.is_null() || JS_IsArrayObject(cx, array) == 0 {
+        do str::as_c_str(~"blob parts must be an array") |s| {
+            JS_ReportError(cx, s);
+        }
+        return Err(());
+    }
+
+    let len = 0u32;
+    JS_GetArrayLength(cx, array, ptr::to_unsafe_ptr(&len));
+    let mut parts = ~[];
+    for uint::range(0, len as uint) |i| {
+        let elem = JSVAL_VOID;
+        JS_GetElement(cx, array, i as u32, ptr::to_unsafe_ptr(&elem));
+        match unwrap_blob(cx, elem) {
+            Some(blob) => parts.push(NestedBlobPart(blob.blob().clone())),
+            None if RUST_JSVAL_IS_OBJECT(elem) == 1 &&
+                    is_buffer_source(cx, RUST_JSVAL_TO_OBJECT(elem)) => {
+                let bytes = buffer_source_bytes(cx, RUST_JSVAL_TO_OBJECT(elem)).get();
+                parts.push(ArrayBufferPart(move bytes));
+            }
+            None => match jsval_to_str(cx, elem) {
+                Ok(move s) => parts.push(StringPart(move s)),
+                Err(()) => return Err(())
+            }
+        }
+    }
+    Ok(move parts)
+}
+
+// The `type` member of the options argument
+unsafe fn get_type(cx: *JSContext, options: *JSObject) -> ~str {
+    match get_property(cx, options, "type") {
+        Some(val) => jsval_to_str(cx, val).get_default(~""),
+        None => ~""
+    }
+}
+
+unsafe fn options_object(argc: c_uint, argv: *JSVal, i: uint) -> *JSObject {
+    if (argc as uint) > i && RUST_JSVAL_IS_OBJECT(*ptr::offset(argv, i)) == 1 {
+        RUST_JSVAL_TO_OBJECT(*ptr::offset(argv, i))
+    } else {
+        null()
+    }
+}
+
+extern fn Blob_constructor(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+    let parts = match get_parts(cx, if argc > 0 { *argv } else { JSVAL_VOID }) {
+        Ok(move parts) => move parts,
+        Err(()) => return 0
+    };
+    let content_type = get_type(cx, options_object(argc, argv, 1));
+    JS_SET_RVAL(cx, vp, new_blob_object(cx, Blob::from_parts(move parts, content_type)));
+    return 1;
+}
+
+extern fn File_constructor(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+    if argc < 2 {
+        do str::as_c_str(~"File needs parts and a name") |s| {
+            JS_ReportError(cx, s);
+        }
+        return 0;
+    }
+    let parts = match get_parts(cx, *argv) {
+        Ok(move parts) => move parts,
+        Err(()) => return 0
+    };
+    let name = match jsval_to_str(cx, *ptr::offset(argv, 1)) {
+        Ok(move name) => move name,
+        Err(()) => return 0
+    };
+    let options = options_object(argc, argv, 2);
+    let last_modified = match get_property(cx, options, "lastModified") {
+        Some(val) if RUST_JSVAL_IS_INT(val) == 1 => RUST_JSVAL_TO_INT(val) as i64,
+        Some(val) if RUST_JSVAL_IS_DOUBLE(val) == 1 => RUST_JSVAL_TO_DOUBLE(val) as i64,
+        _ => file::now()
+    };
+    let blob = Blob::from_parts(move parts, get_type(cx, options));
+    JS_SET_RVAL(cx, vp, new_file_object(cx, File(move blob, move name, last_modified)));
+    return 1;
+}
+
+unsafe fn int_arg(argc: c_uint, argv: *JSVal, i: uint) -> Option<int> {
+    if (argc as uint) > i && RUST_JSVAL_IS_INT(*ptr::offset(argv, i)) == 1 {
+        Some(RUST_JSVAL_TO_INT(*ptr::offset(argv, i)) as int)
+    } else {
+        None
+    }
+}
+
+extern fn slice(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let argv = JS_ARGV(cx, vp);
+    let content_type = if argc > 2 {
+        match jsval_to_str(cx, *ptr::offset(argv, 2)) {
+            Ok(move t) => Some(move t),
+            Err(()) => return 0
+        }
+    } else {
+        None
+    };
+    let sliced = (*unwrap(obj)).payload.blob().slice(int_arg(argc, argv, 0),
+                                                     int_arg(argc, argv, 1),
+                                                     move content_type);
+    JS_SET_RVAL(cx, vp, new_blob_object(cx, move sliced));
+    return 1;
+}
+
+unsafe fn new_number(cx: *JSContext, n: float) -> JSVal {
+    let val = JSVAL_NULL;
+    JS_NewNumberValue(cx, n as libc::c_double, ptr::to_unsafe_ptr(&val));
+    val
+}
+
+extern fn getSize(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = new_number(cx, (*unwrap(obj)).payload.blob().size() as float);
+    return 1;
+}
+
+extern fn getType(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = domstring_to_jsval(cx, &str(copy (*unwrap(obj)).payload.blob().content_type));
+    return 1;
+}
+
+extern fn getName(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = match (*unwrap(obj)).payload {
+        FileObj(ref file) => domstring_to_jsval(cx, &str(copy file.name)),
+        BlobObj(*) => JSVAL_VOID
+    };
+    return 1;
+}
+
+extern fn getLastModified(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = match (*unwrap(obj)).payload {
+        FileObj(ref file) => new_number(cx, file.last_modified as float),
+        BlobObj(*) => JSVAL_VOID
+    };
+    return 1;
+}
+
+extern fn finalize(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("blob finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @BlobObject = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+/* FileReader */
+
+enum ReadFormat {
+    ReadText,
+    ReadArrayBuffer,
+    ReadDataURL
+}
+
+unsafe fn set_property(cx: *JSContext, obj: *JSObject, name: &str, val: JSVal) {
+    do str::as_c_str(name) |s| {
+        JS_SetProperty(cx, obj, s, ptr::to_unsafe_ptr(&val));
+    }
+}
+
+unsafe fn set_ready_state(cx: *JSContext, obj: *JSObject, state: ReadyState) {
+    set_property(cx, obj, "readyState", RUST_INT_TO_JSVAL(state as libc::c_int));
+}
+
+// The blob is read straight away, but the events are queued to run after
+// the current script, as if the read had been asynchronous
+unsafe fn read(cx: *JSContext, argc: c_uint, vp: *JSVal, format: ReadFormat) -> JSBool {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let blob = match if argc > 0 { unwrap_blob(cx, *JS_ARGV(cx, vp)) } else { None } {
+        Some(move blob) => move blob,
+        None => {
+            do str::as_c_str(~"FileReader can only read Blobs") |s| {
+                JS_ReportError(cx, s);
+            }
+            return 0;
+        }
+    };
+
+    set_ready_state(cx, obj, Loading);
+    let result = match format {
+        ReadText => domstring_to_jsval(cx, &str(read_as_text(blob.blob()))),
+        ReadDataURL => domstring_to_jsval(cx, &str(read_as_data_url(blob.blob()))),
+        ReadArrayBuffer => do blob.blob().with_bytes |bytes| {
+            create_array_buffer(cx, bytes)
+        }
+    };
+    set_property(cx, obj, "result", result);
+    set_ready_state(cx, obj, Done);
+
+    let win: @Window = (*task_from_context(cx)).window.expect(~"FileReader needs a window");
+    let target = RUST_OBJECT_TO_JSVAL(obj);
+    for [~"loadstart", ~"load", ~"loadend"].each |kind| {
+        let event = RUST_OBJECT_TO_JSVAL(utils::new_event(cx, *kind, target));
+        win.post_event(target, copy *kind, event);
+    }
+
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn readAsText(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    read(cx, argc, vp, ReadText)
+}
+
+extern fn readAsArrayBuffer(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    read(cx, argc, vp, ReadArrayBuffer)
+}
+
+extern fn readAsDataURL(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    read(cx, argc, vp, ReadDataURL)
+}
+
+extern fn FileReader_constructor(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"FileReaderInstance", ~"FileReader",
+                                          compartment.global_obj.ptr));
+    set_ready_state(cx, obj.ptr, Empty);
+    set_property(cx, obj.ptr, "result", JSVAL_NULL);
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(obj.ptr));
+    return 1;
+}
+
+pub fn init(compartment: &bare_compartment) {
+    let obj = utils::define_constructor(~"Blob", None, Blob_constructor, compartment);
+    let methods = ~[{name: compartment.add_name(~"slice"),
+                     call: {op: slice, info: null()},
+                     nargs: 3,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj.ptr, fns);
+    });
+    let attrs = @~[
+        {name: compartment.add_name(~"size"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getSize, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"type"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getType, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        assert JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs) == 1;
+    });
+    compartment.register_class(utils::instance_jsclass(~"BlobInstance", finalize));
+
+    let obj = utils::define_constructor(~"File", Some(~"Blob"), File_constructor, compartment);
+    let attrs = @~[
+        {name: compartment.add_name(~"name"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getName, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"lastModified"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getLastModified, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        assert JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs) == 1;
+    });
+    compartment.register_class(utils::instance_jsclass(~"FileInstance", finalize));
+
+    let obj = utils::define_constructor(~"FileReader", None, FileReader_constructor,
+                                        compartment);
+    let methods = ~[{name: compartment.add_name(~"readAsText"),
+                     call: {op: readAsText, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"readAsArrayBuffer"),
+                     call: {op: readAsArrayBuffer, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"readAsDataURL"),
+                     call: {op: readAsDataURL, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj.ptr, fns);
+    });
+    compartment.register_class(utils::instance_jsclass(~"FileReaderInstance", null()));
+}
@@ -0,0 +1,598 @@
+/*!
+The Cache API: `caches`, the `Cache`s it opens, and the `Request`s and
+`Response`s they keep. Every method returns a promise. Its work is done on
+a task of its own, with a connection of its own to the origin's cache
+database, and the promise is settled on the content task once it's done.
+
+There's no `fetch` yet, so requests and responses are only made with their
+constructors, and `cache.add` and `addAll` are missing. A response's body
+is read with `text()` or `arrayBuffer()`, and its headers are a plain
+object.
+*/
+
+use js::rust::{bare_compartment, methods};
+use js::{JS_ARGV, JSPROP_ENUMERATE, JSPROP_READONLY, JSVAL_VOID, JS_THIS_OBJECT, JS_SET_RVAL};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp};
+use js::jsapi::bindgen::{JS_GetReservedSlot, JS_SetReservedSlot, JS_DefineFunctions,
+                         JS_DefineProperty, JS_GetProperty, JS_GetPropertyById, JS_NewObject,
+                         JS_GetClass, JS_NewArrayObject,
+                         JS_Enumerate, JS_IdArrayLength, JS_IdArrayGet, JS_DestroyIdArray,
+                         JS_IdToValue};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use ptr::null;
+use libc::c_uint;
+use std::future;
+use util::url::url_to_str;
+use utils::{rust_box, squirrel_away, get_compartment, new_error, throw_error, unicode_to_jsval,
+            jsval_to_unicode, string_arg};
+use bindings::promise::{future_to_promise, resolved_promise, rejected_promise};
+use bindings::typed_array::{buffer_source_bytes, create_array_buffer};
+use content::content_task::task_from_context;
+use dom::cache_storage::{CacheStorage, Cache, CachedRequest, CachedResponse};
+use dom::text_decoder::TextDecoder;
+use util::url::make_url;
+
+unsafe fn unwrap<T>(obj: *JSObject) -> *rust_box<T> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+// A new `class` object, keeping `payload` in its reserved slot
+unsafe fn wrap<T>(cx: *JSContext, class: ~str, proto: ~str, payload: @T) -> *JSObject {
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(compartment.new_object_with_proto(move class, move proto,
+                                                               compartment.global_obj.ptr));
+    let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(payload));
+    JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+    obj.ptr
+}
+
+// A copy of what `val` keeps, if it's an object of `class`
+unsafe fn unwrap_instance<T: Copy>(val: JSVal, class: &str) -> Option<T> {
+    if !is_object(val) {
+        return None;
+    }
+    let obj = RUST_JSVAL_TO_OBJECT(val);
+    if str::raw::from_c_str((*JS_GetClass(obj)).name) == class.to_str() {
+        Some(copy (*unwrap::<T>(obj)).payload)
+    } else {
+        None
+    }
+}
+
+unsafe fn define_value(cx: *JSContext, obj: *JSObject, name: &str, val: JSVal) {
+    do str::as_c_str(name) |s| {
+        JS_DefineProperty(cx, obj, s, val,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE | JSPROP_READONLY);
+    }
+}
+
+unsafe fn get_value(cx: *JSContext, obj: *JSObject, name: &str) -> JSVal {
+    let val = JSVAL_VOID;
+    do str::as_c_str(name) |s| {
+        JS_GetProperty(cx, obj, s, ptr::to_unsafe_ptr(&val));
+    }
+    val
+}
+
+unsafe fn is_object(val: JSVal) -> bool {
+    RUST_JSVAL_IS_OBJECT(val) == 1 && RUST_JSVAL_IS_NULL(val) == 0
+}
+
+// Returns a promise rejected with a `name` error, for arguments that won't do
+unsafe fn reject(cx: *JSContext, vp: *JSVal, name: &str, message: &str) -> JSBool {
+    rejected_promise(cx, vp, new_error(cx, name, message))
+}
+
+unsafe fn object_arg(cx: *JSContext, argc: c_uint, vp: *JSVal, i: uint) -> *JSObject {
+    if argc as uint > i && is_object(*ptr::offset(JS_ARGV(cx, vp), i)) {
+        RUST_JSVAL_TO_OBJECT(*ptr::offset(JS_ARGV(cx, vp), i))
+    } else {
+        null()
+    }
+}
+
+fn array_to_jsval(cx: *JSContext, vals: &[JSVal]) -> JSVal unsafe {
+    do vec::as_imm_buf(vals) |buf, len| {
+        RUST_OBJECT_TO_JSVAL(JS_NewArrayObject(cx, len as libc::c_int, buf))
+    }
+}
+
+fn undefined_to_jsval(_cx: *JSContext, _nothing: ()) -> JSVal {
+    JSVAL_VOID
+}
+
+fn bool_to_jsval(_cx: *JSContext, b: bool) -> JSVal {
+    RUST_BOOLEAN_TO_JSVAL(b as JSBool)
+}
+
+fn strings_to_jsval(cx: *JSContext, strings: ~[~str]) -> JSVal {
+    array_to_jsval(cx, strings.map(|s| unicode_to_jsval(cx, *s)))
+}
+
+/**
+Returns a promise for what `work` gives, once it's been done on a task of
+its own. `to_js` makes the JS value the promise is resolved with; if the
+work fails, it's rejected with an `UnknownError`.
+*/
+unsafe fn promise_for<T: Copy Send>(cx: *JSContext, vp: *JSVal,
+                                    work: fn~() -> Result<T, ~str>,
+                                    to_js: fn~(*JSContext, T) -> JSVal) -> JSBool {
+    let settle = fn~(cx: *JSContext, result: Result<T, ~str>, move to_js)
+        -> Result<JSVal, JSVal> {
+        match move result {
+            Ok(move value) => Ok(to_js(cx, move value)),
+            Err(move e) => Err(new_error(cx, "UnknownError", e))
+        }
+    };
+    future_to_promise(cx, vp, future::spawn(move work), move settle)
+}
+
+// The page's caches, or None if its origin can't have any
+unsafe fn storage(cx: *JSContext) -> Option<CacheStorage> {
+    match utils::storage_location(cx) {
+        Some((ref dir, ref origin)) => Some(CacheStorage(dir, *origin)),
+        None => None
+    }
+}
+
+// The methods fetch upper cases; others are left as they're given
+fn normalize_method(method: &str) -> ~str {
+    let upper = str::to_upper(method);
+    let common = ~["DELETE", "GET", "HEAD", "OPTIONS", "POST", "PUT"];
+    if common.any(|m| str::eq_slice(*m, upper)) {
+        move upper
+    } else {
+        method.to_str()
+    }
+}
+
+// The request `val` stands for: a Request, or a URL to GET, which is
+// resolved against the page's
+unsafe fn request_arg(cx: *JSContext, argc: c_uint, vp: *JSVal, i: uint)
+    -> Option<CachedRequest> {
+    if argc as uint <= i {
+        return None;
+    }
+    let val = *ptr::offset(JS_ARGV(cx, vp), i);
+    match unwrap_instance::<CachedRequest>(val, "RequestInstance") {
+        Some(move request) => Some(move request),
+        None => match jsval_to_unicode(cx, val) {
+            Some(move url) => {
+                let url = make_url(move url, copy (*task_from_context(cx)).doc_url);
+                Some(CachedRequest("GET", url_to_str(move url)))
+            }
+            None => None
+        }
+    }
+}
+
+fn new_request(cx: *JSContext, request: CachedRequest) -> JSVal unsafe {
+    let obj = wrap(cx, ~"RequestInstance", ~"Request", @copy request);
+    define_value(cx, obj, "method", unicode_to_jsval(cx, request.method));
+    define_value(cx, obj, "url", unicode_to_jsval(cx, request.url));
+    RUST_OBJECT_TO_JSVAL(obj)
+}
+
+extern fn Request_constructor(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let request = match request_arg(cx, argc, vp, 0) {
+        Some(move request) => move request,
+        None => return throw_error(cx, "TypeError", "Request needs a URL")
+    };
+    let init = object_arg(cx, argc, vp, 1);
+    let method = if init.is_null() {
+        None
+    } else {
+        let val = get_value(cx, init, "method");
+        if RUST_JSVAL_IS_VOID(val) == 1 { None } else { jsval_to_unicode(cx, val) }
+    };
+    let request = match move method {
+        Some(move method) => CachedRequest(normalize_method(method), request.url),
+        None => move request
+    };
+    JS_SET_RVAL(cx, vp, new_request(cx, move request));
+    return 1;
+}
+
+// The bytes of a response body: binary data's own, or a string's UTF-8
+unsafe fn body_arg(cx: *JSContext, argc: c_uint, vp: *JSVal) -> Option<~[u8]> {
+    if argc < 1 {
+        return Some(~[]);
+    }
+    let val = *JS_ARGV(cx, vp);
+    if RUST_JSVAL_IS_VOID(val) == 1 || RUST_JSVAL_IS_NULL(val) == 1 {
+        return Some(~[]);
+    }
+    if is_object(val) {
+        match buffer_source_bytes(cx, RUST_JSVAL_TO_OBJECT(val)) {
+            Some(move bytes) => return Some(move bytes),
+            None => ()
+        }
+    }
+    jsval_to_unicode(cx, val).map(|s| str::to_bytes(*s))
+}
+
+// The status in a response's init, which has to be from 200 to 599
+unsafe fn status_arg(cx: *JSContext, init: *JSObject) -> Option<uint> {
+    if init.is_null() {
+        return Some(200);
+    }
+    let val = get_value(cx, init, "status");
+    let n = if RUST_JSVAL_IS_VOID(val) == 1 {
+        200.0
+    } else if RUST_JSVAL_IS_INT(val) == 1 {
+        RUST_JSVAL_TO_INT(val) as float
+    } else if RUST_JSVAL_IS_DOUBLE(val) == 1 {
+        RUST_JSVAL_TO_DOUBLE(val) as float
+    } else {
+        0.0
+    };
+    if n >= 200.0 && n <= 599.0 && float::floor(n) == n { Some(n as uint) } else { None }
+}
+
+// The headers in a response's init, as a plain object. Their names are
+// lower cased, as fetch would have them.
+unsafe fn headers_arg(cx: *JSContext, init: *JSObject) -> Option<~[(~str, ~str)]> {
+    if init.is_null() || !is_object(get_value(cx, init, "headers")) {
+        return Some(~[]);
+    }
+    let obj = RUST_JSVAL_TO_OBJECT(get_value(cx, init, "headers"));
+    let ids = JS_Enumerate(cx, obj);
+    if ids.is_null() {
+        return None;
+    }
+    let mut headers = ~[];
+    let mut failed = false;
+    for uint::range(0, JS_IdArrayLength(cx, ids) as uint) |i| {
+        let id = JS_IdArrayGet(cx, ids, i as c_uint);
+        let name = JSVAL_VOID;
+        let value = JSVAL_VOID;
+        if JS_IdToValue(cx, id, ptr::to_unsafe_ptr(&name)) == 0 ||
+           JS_GetPropertyById(cx, obj, id, ptr::to_unsafe_ptr(&value)) == 0 {
+            failed = true;
+            break;
+        }
+        match (jsval_to_unicode(cx, name), jsval_to_unicode(cx, value)) {
+            (Some(move name), Some(move value)) => headers.push((str::to_lower(name), move value)),
+            _ => {
+                failed = true;
+                break;
+            }
+        }
+    }
+    JS_DestroyIdArray(cx, ids);
+    if failed { None } else { Some(move headers) }
+}
+
+/// Keeps `response` in `obj`, a new `ResponseInstance`, and defines the
+/// fields script reads from it.
+pub unsafe fn define_response(cx: *JSContext, obj: *JSObject, response: CachedResponse) {
+    let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(@copy response));
+    JS_SetReservedSlot(obj, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+    define_value(cx, obj, "status", RUST_INT_TO_JSVAL(response.status as libc::c_int));
+    let ok = response.status >= 200 && response.status < 300;
+    define_value(cx, obj, "ok", RUST_BOOLEAN_TO_JSVAL(ok as JSBool));
+    let headers = JS_NewObject(cx, null(), null(), null());
+    for response.headers.each |header| {
+        let (ref name, ref value) = *header;
+        define_value(cx, headers, *name, unicode_to_jsval(cx, *value));
+    }
+    define_value(cx, obj, "headers", RUST_OBJECT_TO_JSVAL(headers));
+}
+
+fn new_response(cx: *JSContext, response: CachedResponse) -> JSVal unsafe {
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(compartment.new_object_with_proto(~"ResponseInstance", ~"Response",
+                                                               compartment.global_obj.ptr));
+    define_response(cx, obj.ptr, move response);
+    RUST_OBJECT_TO_JSVAL(obj.ptr)
+}
+
+/// What `val` keeps, if it's a `Response`.
+pub unsafe fn unwrap_response(val: JSVal) -> Option<CachedResponse> {
+    unwrap_instance(val, "ResponseInstance")
+}
+
+fn response_to_jsval(cx: *JSContext, response: Option<CachedResponse>) -> JSVal {
+    match move response {
+        Some(move response) => new_response(cx, move response),
+        None => JSVAL_VOID
+    }
+}
+
+/**
+The response `new Response(body, init)` makes from its arguments, or `None`
+with an exception pending if they won't do.
+*/
+pub unsafe fn response_arg(cx: *JSContext, argc: c_uint, vp: *JSVal) -> Option<CachedResponse> {
+    let body = match body_arg(cx, argc, vp) {
+        Some(move body) => move body,
+        None => return None
+    };
+    let init = object_arg(cx, argc, vp, 1);
+    let status = match status_arg(cx, init) {
+        Some(status) => status,
+        None => {
+            throw_error(cx, "RangeError", "The status has to be from 200 to 599");
+            return None;
+        }
+    };
+    match headers_arg(cx, init) {
+        Some(move headers) => Some(CachedResponse { status: status, headers: move headers,
+                                                    body: move body }),
+        None => None
+    }
+}
+
+extern fn Response_constructor(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match response_arg(cx, argc, vp) {
+        Some(move response) => {
+            JS_SET_RVAL(cx, vp, new_response(cx, move response));
+            1
+        }
+        None => 0
+    }
+}
+
+pub extern fn text(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    // Malformed UTF-8 is replaced, so decoding can't fail
+    let decoder = TextDecoder("utf-8", false, false).get();
+    let text = decoder.decode((*unwrap::<CachedResponse>(obj)).payload.body).get();
+    JS_SET_RVAL(cx, vp, resolved_promise(cx, unicode_to_jsval(cx, text)));
+    return 1;
+}
+
+pub extern fn arrayBuffer(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let buffer = create_array_buffer(cx, (*unwrap::<CachedResponse>(obj)).payload.body);
+    JS_SET_RVAL(cx, vp, resolved_promise(cx, buffer));
+    return 1;
+}
+
+fn new_cache(cx: *JSContext, cache: Cache) -> JSVal unsafe {
+    RUST_OBJECT_TO_JSVAL(wrap(cx, ~"CacheInstance", ~"Cache", @move cache))
+}
+
+unsafe fn this_cache(cx: *JSContext, vp: *JSVal) -> Option<Cache> {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() { None } else { Some(copy (*unwrap::<Cache>(obj)).payload) }
+}
+
+extern fn put(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let cache = match this_cache(cx, vp) {
+        Some(move cache) => move cache,
+        None => return 0
+    };
+    let request = match request_arg(cx, argc, vp, 0) {
+        Some(move request) => move request,
+        None => return reject(cx, vp, "TypeError", "put needs a request")
+    };
+    if request.method != ~"GET" {
+        return reject(cx, vp, "TypeError", "Only GET requests can be cached");
+    }
+    let response = if argc > 1 {
+        unwrap_response(*ptr::offset(JS_ARGV(cx, vp), 1))
+    } else {
+        None
+    };
+    let response = match move response {
+        Some(move response) => move response,
+        None => return reject(cx, vp, "TypeError", "put needs a Response")
+    };
+    if response.status == 206 {
+        return reject(cx, vp, "TypeError", "Partial responses can't be cached");
+    }
+    let varies_on_everything = do response.headers.any |header| {
+        let (ref name, ref value) = *header;
+        *name == ~"vary" && str::split_char(*value, ',').any(|v| str::trim(*v) == ~"*")
+    };
+    if varies_on_everything {
+        return reject(cx, vp, "TypeError", "Responses that vary on * can't be cached");
+    }
+    promise_for(cx, vp, fn~(move cache, move request, move response) -> Result<(), ~str> {
+        cache.put(&request, &response)
+    }, undefined_to_jsval)
+}
+
+extern fn cache_match(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let cache = match this_cache(cx, vp) {
+        Some(move cache) => move cache,
+        None => return 0
+    };
+    let request = match request_arg(cx, argc, vp, 0) {
+        Some(move request) => move request,
+        None => return reject(cx, vp, "TypeError", "match needs a request")
+    };
+    // Only GETs are cached, so nothing else can match
+    if request.method != ~"GET" {
+        JS_SET_RVAL(cx, vp, resolved_promise(cx, JSVAL_VOID));
+        return 1;
+    }
+    promise_for(cx, vp, fn~(move cache, move request)
+                -> Result<Option<CachedResponse>, ~str> {
+        cache.match_request(&request)
+    }, response_to_jsval)
+}
+
+extern fn cache_delete(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let cache = match this_cache(cx, vp) {
+        Some(move cache) => move cache,
+        None => return 0
+    };
+    let request = match request_arg(cx, argc, vp, 0) {
+        Some(move request) => move request,
+        None => return reject(cx, vp, "TypeError", "delete needs a request")
+    };
+    if request.method != ~"GET" {
+        JS_SET_RVAL(cx, vp, resolved_promise(cx, RUST_BOOLEAN_TO_JSVAL(0)));
+        return 1;
+    }
+    promise_for(cx, vp, fn~(move cache, move request) -> Result<bool, ~str> {
+        cache.delete(&request)
+    }, bool_to_jsval)
+}
+
+extern fn cache_keys(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let cache = match this_cache(cx, vp) {
+        Some(move cache) => move cache,
+        None => return 0
+    };
+    promise_for(cx, vp, fn~(move cache) -> Result<~[CachedRequest], ~str> {
+        cache.keys()
+    }, |cx, requests| array_to_jsval(cx, requests.map(|r| new_request(cx, copy *r))))
+}
+
+extern fn open(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let storage = match storage(cx) {
+        Some(move storage) => move storage,
+        None => return reject(cx, vp, "SecurityError", "This page's origin can't keep caches")
+    };
+    let name = match string_arg(cx, argc, vp, 0) {
+        Some(move name) => move name,
+        None => return reject(cx, vp, "TypeError", "open needs a cache name")
+    };
+    promise_for(cx, vp, fn~(move storage, move name) -> Result<Cache, ~str> {
+        storage.open(name)
+    }, new_cache)
+}
+
+extern fn has(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let storage = match storage(cx) {
+        Some(move storage) => move storage,
+        None => return reject(cx, vp, "SecurityError", "This page's origin can't keep caches")
+    };
+    let name = match string_arg(cx, argc, vp, 0) {
+        Some(move name) => move name,
+        None => return reject(cx, vp, "TypeError", "has needs a cache name")
+    };
+    promise_for(cx, vp, fn~(move storage, move name) -> Result<bool, ~str> {
+        storage.has(name)
+    }, bool_to_jsval)
+}
+
+extern fn delete(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let storage = match storage(cx) {
+        Some(move storage) => move storage,
+        None => return reject(cx, vp, "SecurityError", "This page's origin can't keep caches")
+    };
+    let name = match string_arg(cx, argc, vp, 0) {
+        Some(move name) => move name,
+        None => return reject(cx, vp, "TypeError", "delete needs a cache name")
+    };
+    promise_for(cx, vp, fn~(move storage, move name) -> Result<bool, ~str> {
+        storage.delete(name)
+    }, bool_to_jsval)
+}
+
+extern fn keys(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let storage = match storage(cx) {
+        Some(move storage) => move storage,
+        None => return reject(cx, vp, "SecurityError", "This page's origin can't keep caches")
+    };
+    promise_for(cx, vp, fn~(move storage) -> Result<~[~str], ~str> {
+        storage.keys()
+    }, strings_to_jsval)
+}
+
+// Looks in every cache, oldest first
+extern fn storage_match(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let storage = match storage(cx) {
+        Some(move storage) => move storage,
+        None => return reject(cx, vp, "SecurityError", "This page's origin can't keep caches")
+    };
+    let request = match request_arg(cx, argc, vp, 0) {
+        Some(move request) => move request,
+        None => return reject(cx, vp, "TypeError", "match needs a request")
+    };
+    if request.method != ~"GET" {
+        JS_SET_RVAL(cx, vp, resolved_promise(cx, JSVAL_VOID));
+        return 1;
+    }
+    promise_for(cx, vp, fn~(move storage, move request)
+                -> Result<Option<CachedResponse>, ~str> {
+        storage.match_request(&request)
+    }, response_to_jsval)
+}
+
+extern fn finalize_cache(_fop: *JSFreeOp, obj: *JSObject) {
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @Cache = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+extern fn finalize_request(_fop: *JSFreeOp, obj: *JSObject) {
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @CachedRequest = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+pub extern fn finalize_response(_fop: *JSFreeOp, obj: *JSObject) {
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @CachedResponse = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+fn define_methods(compartment: &bare_compartment, obj: *JSObject,
+                  methods: &[(~str, *u8, u16)]) {
+    let specs = do methods.map |method| {
+        let (ref name, op, nargs) = *method;
+        {name: compartment.add_name(copy *name),
+         call: {op: op, info: null()},
+         nargs: nargs,
+         flags: 0,
+         selfHostedName: null()}
+    };
+    vec::as_imm_buf(specs, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj, fns);
+    });
+}
+
+pub fn init(compartment: &bare_compartment) {
+    utils::define_constructor(~"Request", None, Request_constructor, compartment);
+    compartment.register_class(utils::instance_jsclass(~"RequestInstance", finalize_request));
+
+    let response = utils::define_constructor(~"Response", None, Response_constructor,
+                                             compartment);
+    define_methods(compartment, response.ptr,
+                   ~[(~"text", text as *u8, 0),
+                     (~"arrayBuffer", arrayBuffer as *u8, 0)]);
+    compartment.register_class(utils::instance_jsclass(~"ResponseInstance", finalize_response));
+
+    let cache = utils::define_empty_prototype(~"Cache", None, compartment);
+    define_methods(compartment, cache.ptr,
+                   ~[(~"put", put as *u8, 2),
+                     (~"match", cache_match as *u8, 1),
+                     (~"delete", cache_delete as *u8, 1),
+                     (~"keys", cache_keys as *u8, 0)]);
+    compartment.register_class(utils::instance_jsclass(~"CacheInstance", finalize_cache));
+
+    let storage = utils::define_empty_prototype(~"CacheStorage", None, compartment);
+    define_methods(compartment, storage.ptr,
+                   ~[(~"open", open as *u8, 1),
+                     (~"has", has as *u8, 1),
+                     (~"delete", delete as *u8, 1),
+                     (~"keys", keys as *u8, 0),
+                     (~"match", storage_match as *u8, 1)]);
+    compartment.register_class(utils::instance_jsclass(~"CacheStorageInstance", null()));
+
+    let caches = result::unwrap(
+        compartment.new_object_with_proto(~"CacheStorageInstance", ~"CacheStorage",
+                                          compartment.global_obj.ptr));
+    compartment.define_property(~"caches", RUST_OBJECT_TO_JSVAL(caches.ptr),
+                                GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                                GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                                JSPROP_ENUMERATE);
+}
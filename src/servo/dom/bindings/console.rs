@@ -0,0 +1,107 @@
+/*!
+`console.log` and friends. Messages are printed, and passed on to a
+connected devtools client.
+*/
+
+use js::rust::{bare_compartment, methods};
+use js::{JS_ARGV, JS_SET_RVAL, JSVAL_VOID, JSPROP_ENUMERATE};
+use js::jsapi::{JSContext, JSVal, JSBool};
+use js::jsapi::bindgen::{JS_NewObject, JS_DefineFunctions};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use libc::c_uint;
+use ptr::null;
+
+use bindings::debug::caller_location;
+use content::content_task::task_from_context;
+use utils::jsval_to_str;
+
+// Prints the arguments, separated by spaces, as a message of `level`
+unsafe fn log_message(cx: *JSContext, level: &str, argc: c_uint, vp: *JSVal) -> JSBool {
+    let argv = JS_ARGV(cx, vp);
+    let mut parts = ~[];
+    for uint::range(0, argc as uint) |i| {
+        match jsval_to_str(cx, *ptr::offset(argv, i)) {
+            Ok(move part) => parts.push(move part),
+            Err(()) => return 0
+        }
+    }
+    let text = str::connect(parts, " ");
+    io::println(fmt!("console.%s: %s", level, text));
+
+    let (url, line) = caller_location(cx).get_default((~"", 0));
+    for (*task_from_context(cx)).devtools.each |client| {
+        client.console_message("console-api", level, text, url, line);
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    1
+}
+
+/// Warns about something the page's script did that the engine ignored,
+/// as if with `console.warn`.
+pub unsafe fn report_warning(cx: *JSContext, text: &str) {
+    warn!("%s", text);
+    let (url, line) = caller_location(cx).get_default((~"", 0));
+    for (*task_from_context(cx)).devtools.each |client| {
+        client.console_message("javascript", "warning", text, url, line);
+    }
+}
+
+extern fn log(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    log_message(cx, "log", argc, vp)
+}
+
+extern fn info(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    log_message(cx, "info", argc, vp)
+}
+
+extern fn warn(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    log_message(cx, "warning", argc, vp)
+}
+
+extern fn error(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    log_message(cx, "error", argc, vp)
+}
+
+extern fn debug(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    log_message(cx, "debug", argc, vp)
+}
+
+pub fn init(compartment: &bare_compartment) {
+    let cx = compartment.cx.ptr;
+    let console = JS_NewObject(cx, null(), null(), compartment.global_obj.ptr);
+
+    let methods = ~[{name: compartment.add_name(~"log"),
+                     call: {op: log, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"info"),
+                     call: {op: info, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"warn"),
+                     call: {op: warn, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"error"),
+                     call: {op: error, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"debug"),
+                     call: {op: debug, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(cx, console, fns);
+    });
+
+    compartment.define_property(~"console", RUST_OBJECT_TO_JSVAL(console),
+                                GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                                GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                                JSPROP_ENUMERATE);
+}
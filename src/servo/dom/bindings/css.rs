@@ -0,0 +1,95 @@
+/*!
+The `CSS` namespace. Only `CSS.registerProperty` is here so far.
+*/
+
+use js::rust::bare_compartment;
+use js::{JS_ARGV, JS_SET_RVAL, JSVAL_VOID, JSPROP_ENUMERATE};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool};
+use js::jsapi::bindgen::{JS_NewObject, JS_DefineFunctions, JS_GetProperty, JS_ValueToBoolean};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use libc::c_uint;
+use ptr::null;
+
+use css::property_registry::{RegisterError, InvalidName, InvalidSyntax, InvalidInitialValue,
+                             AlreadyRegistered};
+use content::content_task::task_from_context;
+use utils::{jsval_to_str, str, throw_error};
+
+unsafe fn get_member(cx: *JSContext, dict: *JSObject, name: &str) -> JSVal {
+    let val = JSVAL_VOID;
+    do str::as_c_str(name) |s| {
+        JS_GetProperty(cx, dict, s, ptr::to_unsafe_ptr(&val));
+    }
+    val
+}
+
+// A string member of the dictionary, if it's there
+unsafe fn string_member(cx: *JSContext, dict: *JSObject, name: &str) -> Option<~str> {
+    let val = get_member(cx, dict, name);
+    if RUST_JSVAL_IS_VOID(val) == 1 {
+        return None;
+    }
+    jsval_to_str(cx, val).ok()
+}
+
+fn error_message(err: RegisterError) -> (&static/str, &static/str) {
+    match err {
+        InvalidName => ("SyntaxError", "a custom property's name starts with --"),
+        InvalidSyntax => ("SyntaxError", "the syntax isn't one registerProperty supports"),
+        InvalidInitialValue => ("SyntaxError", "the initial value doesn't match the syntax"),
+        AlreadyRegistered => ("InvalidModificationError", "the property is already registered")
+    }
+}
+
+extern fn registerProperty(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+    if argc < 1 || RUST_JSVAL_IS_OBJECT(*argv) == 0 || RUST_JSVAL_IS_NULL(*argv) == 1 {
+        return throw_error(cx, "TypeError", "registerProperty needs a property definition");
+    }
+    let definition = RUST_JSVAL_TO_OBJECT(*argv);
+    let name = match string_member(cx, definition, "name") {
+        Some(move name) => move name,
+        None => return throw_error(cx, "TypeError", "the property definition needs a name")
+    };
+    let inherits_val = get_member(cx, definition, "inherits");
+    if RUST_JSVAL_IS_VOID(inherits_val) == 1 {
+        return throw_error(cx, "TypeError", "the property definition needs inherits");
+    }
+    let inherits = 0;
+    JS_ValueToBoolean(cx, inherits_val, ptr::to_unsafe_ptr(&inherits));
+    let syntax = string_member(cx, definition, "syntax").get_default(~"*");
+    let initial_value = string_member(cx, definition, "initialValue");
+
+    let win = (*task_from_context(cx)).window.expect(~"CSS needs a window");
+    match win.property_registry.register(move name, syntax, inherits == 1,
+                                         move initial_value) {
+        Ok(()) => {
+            JS_SET_RVAL(cx, vp, JSVAL_VOID);
+            1
+        }
+        Err(err) => {
+            let (kind, message) = error_message(err);
+            throw_error(cx, kind, message)
+        }
+    }
+}
+
+pub fn init(compartment: &bare_compartment) {
+    let cx = compartment.cx.ptr;
+    let css = JS_NewObject(cx, null(), null(), compartment.global_obj.ptr);
+
+    let methods = ~[{name: compartment.add_name(~"registerProperty"),
+                     call: {op: registerProperty, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(cx, css, fns);
+    });
+
+    compartment.define_property(~"CSS", RUST_OBJECT_TO_JSVAL(css),
+                                GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                                GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                                JSPROP_ENUMERATE);
+}
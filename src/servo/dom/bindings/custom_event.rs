@@ -0,0 +1,91 @@
+use js::rust::{bare_compartment, methods};
+use js::{JS_ARGV, JSVAL_NULL, JSVAL_VOID, JS_SET_RVAL};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool};
+use js::jsapi::bindgen::{JS_GetProperty, JS_SetProperty, JS_ValueToBoolean, JS_ReportError};
+use js::glue::bindgen::*;
+use libc::c_uint;
+use ptr::null;
+
+use dom::events::custom_event::{CustomEvent, EventInit};
+use bindings::structured_clone::{structured_clone, throw_data_clone_error};
+use utils::{get_compartment, init_event, jsval_to_str};
+
+unsafe fn get_member(cx: *JSContext, dict: *JSObject, name: &str) -> JSVal {
+    let val = JSVAL_VOID;
+    if !dict.is_null() {
+        do str::as_c_str(name) |s| {
+            JS_GetProperty(cx, dict, s, ptr::to_unsafe_ptr(&val));
+        }
+    }
+    val
+}
+
+unsafe fn bool_member(cx: *JSContext, dict: *JSObject, name: &str) -> bool {
+    let b = 0;
+    JS_ValueToBoolean(cx, get_member(cx, dict, name), ptr::to_unsafe_ptr(&b));
+    b == 1
+}
+
+unsafe fn set_property(cx: *JSContext, obj: *JSObject, name: &str, val: JSVal) {
+    do str::as_c_str(name) |s| {
+        JS_SetProperty(cx, obj, s, ptr::to_unsafe_ptr(&val));
+    }
+}
+
+/// The JS object for `event`, before it's dispatched anywhere.
+pub unsafe fn new_custom_event(cx: *JSContext, event: &CustomEvent) -> *JSObject {
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"CustomEventInstance", ~"CustomEvent",
+                                          compartment.global_obj.ptr)).ptr;
+    init_event(cx, obj, event.kind, JSVAL_NULL);
+    set_property(cx, obj, "bubbles", RUST_BOOLEAN_TO_JSVAL(event.init.bubbles as JSBool));
+    set_property(cx, obj, "cancelable", RUST_BOOLEAN_TO_JSVAL(event.init.cancelable as JSBool));
+    set_property(cx, obj, "detail", event.detail);
+    obj
+}
+
+extern fn CustomEvent_constructor(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+    if argc < 1 {
+        do str::as_c_str(~"CustomEvent needs a type") |s| {
+            JS_ReportError(cx, s);
+        }
+        return 0;
+    }
+    let kind = match jsval_to_str(cx, *argv) {
+        Ok(move kind) => move kind,
+        Err(()) => return 0
+    };
+    let dict = if argc > 1 && RUST_JSVAL_IS_OBJECT(*ptr::offset(argv, 1)) == 1 {
+        RUST_JSVAL_TO_OBJECT(*ptr::offset(argv, 1))
+    } else {
+        null()
+    };
+
+    let init = EventInit {
+        bubbles: bool_member(cx, dict, "bubbles"),
+        cancelable: bool_member(cx, dict, "cancelable")
+    };
+    // The detail is cloned, so the listeners can't change what the script
+    // that fired the event still holds
+    let detail = match get_member(cx, dict, "detail") {
+        val if RUST_JSVAL_IS_VOID(val) == 1 => JSVAL_NULL,
+        val => match structured_clone(cx, val) {
+            Ok(clone) => clone,
+            Err(err) => {
+                throw_data_clone_error(cx, err);
+                return 0;
+            }
+        }
+    };
+
+    let event = CustomEvent(move kind, init, detail);
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(new_custom_event(cx, &event)));
+    return 1;
+}
+
+pub fn init(compartment: &bare_compartment) {
+    utils::define_constructor(~"CustomEvent", None, CustomEvent_constructor, compartment);
+    compartment.register_class(utils::instance_jsclass(~"CustomEventInstance", null()));
+}
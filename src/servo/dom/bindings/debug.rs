@@ -0,0 +1,254 @@
+/*!
+Stack traces. SpiderMonkey records where each `Error` was made; this turns
+that record, or the stack where script is running now, into the lines V8
+gives, like
+
+    TypeError: x is null
+        at draw (http://example.com/app.js:12:5)
+        at http://example.com/app.js:30:1
+
+which is what `error.stack` holds. `Error.captureStackTrace(obj)` puts one
+on any object, as libraries written for V8 expect.
+*/
+
+use js::rust::bare_compartment;
+use js::{JS_ARGV, JS_THIS_OBJECT, JS_SET_RVAL, JSVAL_VOID, JSVAL_NULL, JSPROP_SHARED,
+         JSPROP_NATIVE_ACCESSORS};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSString};
+use js::jsapi::bindgen::{JS_CaptureCurrentStack, JS_ExceptionStackOrNull, JS_GetSavedFrameSource,
+                         JS_GetSavedFrameLine, JS_GetSavedFrameColumn,
+                         JS_GetSavedFrameFunctionDisplayName, JS_GetSavedFrameParent,
+                         JS_GetProperty, JS_DefineProperty, JS_DefineProperties,
+                         JS_DefineFunctions, JS_ReportError};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use libc::c_uint;
+use ptr::null;
+
+use utils::{domstring_to_jsval, jsval_to_str, str};
+
+// How many frames a trace has at most
+const MAX_FRAMES: c_uint = 50;
+
+/// One call on a stack.
+pub struct StackFrame {
+    // None for top-level script and anonymous functions
+    function: Option<~str>,
+    filename: ~str,
+    line: uint,
+    column: uint,
+}
+
+/// A frame as a line of a trace: `at function_name (filename:line:column)`.
+pub fn format_frame(frame: &StackFrame) -> ~str {
+    match frame.function {
+        Some(ref function) => fmt!("at %s (%s:%u:%u)", *function, frame.filename, frame.line,
+                                   frame.column),
+        None => fmt!("at %s:%u:%u", frame.filename, frame.line, frame.column)
+    }
+}
+
+unsafe fn jsstring_to_str(cx: *JSContext, s: *JSString) -> Option<~str> {
+    if s.is_null() {
+        return None;
+    }
+    match jsval_to_str(cx, RUST_STRING_TO_JSVAL(s)) {
+        Ok(move s) => Some(move s),
+        Err(()) => None
+    }
+}
+
+// The frames of a SpiderMonkey SavedFrame stack, innermost first
+unsafe fn frames_of(cx: *JSContext, stack: *JSObject) -> ~[StackFrame] {
+    let mut frames = ~[];
+    let mut frame = stack;
+    while !frame.is_null() && frames.len() < MAX_FRAMES as uint {
+        let source: *JSString = null();
+        let function: *JSString = null();
+        let line = 0u32;
+        let column = 0u32;
+        JS_GetSavedFrameSource(cx, frame, ptr::to_unsafe_ptr(&source));
+        JS_GetSavedFrameFunctionDisplayName(cx, frame, ptr::to_unsafe_ptr(&function));
+        JS_GetSavedFrameLine(cx, frame, ptr::to_unsafe_ptr(&line));
+        JS_GetSavedFrameColumn(cx, frame, ptr::to_unsafe_ptr(&column));
+        frames.push(StackFrame {
+            function: jsstring_to_str(cx, function),
+            filename: jsstring_to_str(cx, source).get_default(~"<unknown>"),
+            line: line as uint,
+            column: column as uint
+        });
+
+        let parent: *JSObject = null();
+        JS_GetSavedFrameParent(cx, frame, ptr::to_unsafe_ptr(&parent));
+        frame = parent;
+    }
+    move frames
+}
+
+unsafe fn current_frames(cx: *JSContext) -> ~[StackFrame] {
+    let stack: *JSObject = null();
+    if JS_CaptureCurrentStack(cx, ptr::to_unsafe_ptr(&stack), MAX_FRAMES) == 0 {
+        return ~[];
+    }
+    frames_of(cx, stack)
+}
+
+// A trace's lines, indented as V8 indents them
+fn format_frames(frames: &[StackFrame]) -> ~str {
+    str::connect(frames.map(|frame| ~"    " + format_frame(frame)), "\n")
+}
+
+/**
+The stack where script is running now, a frame to a line. Empty when no
+script is running, so the error reporter only adds it when an error is
+reported from inside script.
+*/
+pub fn capture_stack_trace(cx: *JSContext) -> ~str unsafe {
+    format_frames(current_frames(cx))
+}
+
+/// Where the script running now is: its file and line.
+pub fn caller_location(cx: *JSContext) -> Option<(~str, uint)> unsafe {
+    let frames = current_frames(cx);
+    if frames.is_empty() {
+        None
+    } else {
+        Some((copy frames[0].filename, frames[0].line))
+    }
+}
+
+// `obj` as the first line of its trace, like `TypeError: x is null`
+unsafe fn header(cx: *JSContext, obj: *JSObject) -> ~str {
+    match jsval_to_str(cx, RUST_OBJECT_TO_JSVAL(obj)) {
+        Ok(move header) => move header,
+        Err(()) => ~"Error"
+    }
+}
+
+unsafe fn stack_string(cx: *JSContext, obj: *JSObject, frames: &[StackFrame]) -> ~str {
+    if frames.is_empty() {
+        header(cx, obj)
+    } else {
+        header(cx, obj) + "\n" + format_frames(frames)
+    }
+}
+
+// Gives `obj` an own `stack` that isn't enumerable, as errors' are
+unsafe fn set_stack(cx: *JSContext, obj: *JSObject, stack: JSVal) -> JSBool {
+    do str::as_c_str("stack") |s| {
+        JS_DefineProperty(cx, obj, s, stack,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8, 0)
+    }
+}
+
+unsafe fn function_name(cx: *JSContext, fun: JSVal) -> Option<~str> {
+    if RUST_JSVAL_IS_OBJECT(fun) == 0 || fun == JSVAL_NULL {
+        return None;
+    }
+    let name = JSVAL_VOID;
+    do str::as_c_str("name") |s| {
+        JS_GetProperty(cx, RUST_JSVAL_TO_OBJECT(fun), s, ptr::to_unsafe_ptr(&name));
+    }
+    match jsval_to_str(cx, name) {
+        Ok(move name) if name.len() > 0 => Some(move name),
+        _ => None
+    }
+}
+
+// Error.captureStackTrace(obj[, constructorOpt]): the frames above the
+// newest call of constructorOpt are left out, so that a trace made in a
+// constructor starts where it was called
+extern fn captureStackTrace(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+    if argc < 1 || RUST_JSVAL_IS_OBJECT(*argv) == 0 || *argv == JSVAL_NULL {
+        do str::as_c_str("Error.captureStackTrace needs an object") |s| {
+            JS_ReportError(cx, s);
+        }
+        return 0;
+    }
+    let obj = RUST_JSVAL_TO_OBJECT(*argv);
+    let mut frames = current_frames(cx);
+    if argc > 1 {
+        match function_name(cx, *ptr::offset(argv, 1)) {
+            Some(ref name) => match frames.position(|f| f.function == Some(copy *name)) {
+                Some(i) => frames = vec::slice(frames, i + 1, frames.len()),
+                None => ()
+            },
+            None => ()
+        }
+    }
+    let stack = domstring_to_jsval(cx, &str(stack_string(cx, obj, frames)));
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    set_stack(cx, obj, stack)
+}
+
+// Error.prototype.stack, from where the error was made
+extern fn getStack(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    let stack = JS_ExceptionStackOrNull(obj);
+    *vp = if stack.is_null() {
+        JSVAL_VOID
+    } else {
+        domstring_to_jsval(cx, &str(stack_string(cx, obj, frames_of(cx, stack))))
+    };
+    1
+}
+
+// Assigning to `stack` replaces it on that error only
+extern fn setStack(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    let argv = JS_ARGV(cx, cast::reinterpret_cast(&vp));
+    set_stack(cx, obj, *argv)
+}
+
+unsafe fn get_object(cx: *JSContext, obj: *JSObject, name: &str) -> Option<*JSObject> {
+    let val = JSVAL_VOID;
+    do str::as_c_str(name) |s| {
+        JS_GetProperty(cx, obj, s, ptr::to_unsafe_ptr(&val));
+    }
+    if RUST_JSVAL_IS_OBJECT(val) == 1 && val != JSVAL_NULL {
+        Some(RUST_JSVAL_TO_OBJECT(val))
+    } else {
+        None
+    }
+}
+
+pub fn init(compartment: &bare_compartment) {
+    let cx = compartment.cx.ptr;
+    let error = match unsafe { get_object(cx, compartment.global_obj.ptr, "Error") } {
+        Some(error) => error,
+        None => return
+    };
+
+    let methods = ~[{name: compartment.add_name(~"captureStackTrace"),
+                     call: {op: captureStackTrace, info: null()},
+                     nargs: 2,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(cx, error, fns);
+    });
+
+    // SpiderMonkey's own stack has a format of its own; this one is V8's
+    let proto = match unsafe { get_object(cx, error, "prototype") } {
+        Some(proto) => proto,
+        None => return
+    };
+    let attrs = @~[
+        {name: compartment.add_name(~"stack"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getStack, info: null()},
+         setter: {op: setStack, info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        JS_DefineProperties(cx, proto, specs);
+    });
+}
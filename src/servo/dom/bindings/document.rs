@@ -10,10 +10,13 @@ use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
 use js::crust::{JS_PropertyStub, JS_StrictPropertyStub, JS_EnumerateStub, JS_ConvertStub, JS_ResolveStub};
 use ptr::null;
 use libc::c_uint;
-use utils::{DOMString, domstring_to_jsval, rust_box, squirrel_away, str};
+use utils::{DOMString, domstring_to_jsval, jsval_to_str, rust_box, squirrel_away, str};
 use bindings::node::create;
 
 use dom::document::Document;
+use dom::element::ElementData;
+use dom::node::{Element, NodeScopeExtensions, Text};
+use html::hubbub_html_parser::build_element_kind;
 
 enum DOMException {
     INVALID_CHARACTER_ERR
@@ -70,7 +73,53 @@ extern fn getDocumentElement(cx: *JSContext, _argc: c_uint, vp: *mut JSVal)
     let box = unwrap(obj);
     let node = (*box).payload.root;
     let scope = (*box).payload.scope;
-    *vp = RUST_OBJECT_TO_JSVAL(node::create(cx, node, scope).ptr);
+    *vp = RUST_OBJECT_TO_JSVAL(node::create(cx, node, scope));
+    return 1;
+}
+
+// The string argument of a Document method, or None if there isn't one
+unsafe fn string_arg(cx: *JSContext, argc: c_uint, vp: *JSVal) -> Option<~str> {
+    if argc < 1 {
+        return None;
+    }
+    match jsval_to_str(cx, *JS_ARGV(cx, vp)) {
+        Ok(move s) => Some(move s),
+        Err(()) => None
+    }
+}
+
+extern fn createElement(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let tag = match string_arg(cx, argc, vp) {
+        Some(move tag) => str::to_lower(tag),
+        None => return 0
+    };
+
+    let box = unwrap(obj);
+    let scope = (*box).payload.scope;
+    let kind = build_element_kind(tag);
+    let node = scope.new_node(Element(ElementData(move tag, move kind)));
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(node::create(cx, node, scope)));
+    return 1;
+}
+
+extern fn createTextNode(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let data = match string_arg(cx, argc, vp) {
+        Some(move data) => move data,
+        None => return 0
+    };
+
+    let box = unwrap(obj);
+    let scope = (*box).payload.scope;
+    let node = scope.new_node(Text(move data));
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(node::create(cx, node, scope)));
     return 1;
 }
 
@@ -102,6 +151,23 @@ pub fn init(compartment: &bare_compartment, doc: @Document) {
         assert JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs) == 1;
     });
 
+    // Nodes made here come from the arenas of the document's scope, as
+    // the parser's do
+    let methods = ~[{name: compartment.add_name(~"createElement"),
+                     call: {op: createElement, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"createTextNode"),
+                     call: {op: createTextNode, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj.ptr, fns);
+    });
+    bindings::event_target::init(compartment, obj.ptr);
+
     compartment.register_class(utils::instance_jsclass(~"DocumentInstance", finalize));
 
     let instance : jsobj = result::unwrap(
@@ -1,6 +1,6 @@
 use au = gfx::geometry;
 use au::au;
-use js::rust::{bare_compartment, methods, jsobj};
+use js::rust::{bare_compartment, methods};
 use js::{JS_ARGV, JSCLASS_HAS_RESERVED_SLOTS, JSPROP_ENUMERATE, JSPROP_SHARED, JSVAL_NULL,
             JS_THIS_OBJECT, JS_SET_RVAL, JSPROP_NATIVE_ACCESSORS};
 use js::jsapi::{JSContext, JSVal, JSObject, JSBool, jsid, JSClass, JSFreeOp, JSPropertySpec};
@@ -13,10 +13,18 @@ use js::crust::{JS_PropertyStub, JS_StrictPropertyStub, JS_EnumerateStub, JS_Con
 
 use content::content_task::{Content, task_from_context};
 use layout::layout_task;
+use resource::image_cache_task::ImageCacheTaskClient;
 use dom::node::{Node, NodeScope, Element};
 use dom::element::*;
 use node::NodeBundle;
-use utils::{rust_box, squirrel_away_unique, get_compartment, domstring_to_jsval, str};
+use dom::aria::is_aria_attribute;
+use accessibility::ax_tree::compute_accessible_name;
+use dom::focus::{FocusedElement, is_focusable};
+use dom::bindings::pointer_event::post_capture_event;
+use geom::point::Point2D;
+use std::time::precise_time_ns;
+use utils::{rust_box, squirrel_away_unique, get_compartment, domstring_to_jsval, jsval_to_str,
+            str, jsval_to_px};
 use libc::c_uint;
 use ptr::null;
 use node::unwrap;
@@ -37,12 +45,61 @@ pub fn init(compartment: &bare_compartment) {
          tinyid: 0,
          flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
          getter: {op: getTagName, info: null()},
-         setter: {op: null(), info: null()}}];
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"scrollTop"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getScrollTop, info: null()},
+         setter: {op: setScrollTop, info: null()}},
+        {name: compartment.add_name(~"scrollLeft"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getScrollLeft, info: null()},
+         setter: {op: setScrollLeft, info: null()}}];
     vec::push(&mut compartment.global_props, attrs);
     vec::as_imm_buf(*attrs, |specs, _len| {
         JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs);
     });
 
+    let methods = ~[{name: compartment.add_name(~"getAttribute"),
+                     call: {op: getAttribute, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"setAttribute"),
+                     call: {op: setAttribute, info: null()},
+                     nargs: 2,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"focus"),
+                     call: {op: focus, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"blur"),
+                     call: {op: blur, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"setPointerCapture"),
+                     call: {op: setPointerCapture, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"releasePointerCapture"),
+                     call: {op: releasePointerCapture, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"hasPointerCapture"),
+                     call: {op: hasPointerCapture, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj.ptr, fns);
+    });
+
     compartment.register_class(utils::instance_jsclass(~"GenericElementInstance",
                                                        finalize));
 
@@ -57,7 +114,12 @@ pub fn init(compartment: &bare_compartment) {
          tinyid: 0,
          flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
          getter: {op: HTMLImageElement_getWidth, info: null()},
-         setter: {op: HTMLImageElement_setWidth, info: null()}}];
+         setter: {op: HTMLImageElement_setWidth, info: null()}},
+        {name: compartment.add_name(~"complete"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: HTMLImageElement_getComplete, info: null()},
+         setter: {op: null(), info: null()}}];
     vec::push(&mut compartment.global_props, attrs);
     vec::as_imm_buf(*attrs, |specs, _len| {
         JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs);
@@ -98,6 +160,33 @@ extern fn HTMLImageElement_getWidth(cx: *JSContext, _argc: c_uint, vp: *mut JSVa
     return 1;
 }
 
+// An image is complete once it's decoded, or has failed to be. One with
+// no source is complete too.
+extern fn HTMLImageElement_getComplete(cx: *JSContext, _argc: c_uint, vp: *mut JSVal)
+    -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+
+    let bundle = unwrap(obj);
+    let url = do (*bundle).payload.scope.read(&(*bundle).payload.node) |nd| {
+        match nd.kind {
+            ~Element(ref ed) => match ed.kind {
+                ~HTMLImageElement(ref d) => copy d.image,
+                _ => fail ~"why is this not an image element?"
+            },
+            _ => fail ~"why is this not an element?"
+        }
+    };
+    let complete = match move url {
+        Some(move url) => (*task_from_context(cx)).image_cache_task.is_complete(move url),
+        None => true
+    };
+    *vp = RUST_BOOLEAN_TO_JSVAL(if complete { 1 } else { 0 });
+    return 1;
+}
+
 #[allow(non_implicitly_copyable_typarams)]
 extern fn HTMLImageElement_setWidth(cx: *JSContext, _argc: c_uint, vp: *mut JSVal)
     -> JSBool unsafe {
@@ -150,15 +239,318 @@ extern fn getTagName(cx: *JSContext, _argc: c_uint, vp: *mut JSVal)
     return 1;
 }
 
+// The string arguments of an Element method, or None if there are too few
+unsafe fn string_args(cx: *JSContext, argc: c_uint, vp: *JSVal, n: uint) -> Option<~[~str]> {
+    if (argc as uint) < n {
+        return None;
+    }
+    let mut args = ~[];
+    for uint::range(0, n) |i| {
+        match jsval_to_str(cx, *ptr::offset(JS_ARGV(cx, vp), i)) {
+            Ok(move s) => args.push(move s),
+            Err(()) => return None
+        }
+    }
+    Some(move args)
+}
+
+#[allow(non_implicitly_copyable_typarams)]
+extern fn getAttribute(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let name = match string_args(cx, argc, vp, 1) {
+        Some(move args) => copy args[0],
+        None => return 0
+    };
+
+    let bundle = unwrap(obj);
+    let value = do (*bundle).payload.scope.read(&(*bundle).payload.node) |nd| {
+        match nd.kind {
+          ~Element(ref ed) => ed.get_attr(name),
+          _ => fail ~"why is this not an element?"
+        }
+    };
+    match move value {
+      Some(move value) => JS_SET_RVAL(cx, vp, domstring_to_jsval(cx, &str(move value))),
+      None => JS_SET_RVAL(cx, vp, JSVAL_NULL)
+    }
+    return 1;
+}
+
+// A property in the `style` attribute of `node`
+fn style_property(scope: &NodeScope, node: Node, name: &str) -> Option<~str> {
+    do scope.read(&node) |nd| {
+        match nd.kind {
+            ~Element(ref ed) => ed.get_style_property(name),
+            _ => None
+        }
+    }
+}
+
+#[allow(non_implicitly_copyable_typarams)]
+extern fn setAttribute(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let (name, value) = match string_args(cx, argc, vp, 2) {
+        Some(move args) => (str::to_lower(args[0]), copy args[1]),
+        None => return 0
+    };
+
+    let bundle = unwrap(obj);
+    let scope = &(*bundle).payload.scope;
+    let node = (*bundle).payload.node;
+
+    // Registered custom properties may transition when the style changes
+    let registry = (*task_from_context(cx)).window.map(|win| win.property_registry);
+    let mut old_values = ~[];
+    if name == ~"style" {
+        for registry.each |registry| {
+            for registry.each_name |property| {
+                old_values.push((property.to_str(), style_property(scope, node, property)));
+            }
+        }
+    }
+
+    do scope.write(&node) |nd| {
+        match nd.kind {
+          ~Element(ref ed) => ed.set_attr(name, copy value),
+          _ => fail ~"why is this not an element?"
+        }
+    };
+    for registry.each |registry| {
+        let now = precise_time_ns();
+        for old_values.each |pair| {
+            let (ref property, ref old) = *pair;
+            registry.value_changed(node, *property, copy *old,
+                                   style_property(scope, node, *property),
+                                   style_property(scope, node, "transition"), now);
+        }
+        if registry.is_transitioning() {
+            (*task_from_context(cx)).schedule_transition_frame();
+        }
+    }
+    if is_aria_attribute(name) {
+        (*task_from_context(cx)).update_accessibility_tree();
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn computedAccessibleName(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let bundle = unwrap(obj);
+    let name = compute_accessible_name(&(*bundle).payload.scope, &(*bundle).payload.node);
+    JS_SET_RVAL(cx, vp, domstring_to_jsval(cx, &str(move name)));
+    return 1;
+}
+
+/**
+Defines `element.computedAccessibleName()`, which isn't standard, for
+seeing what name the accessibility tree gives an element. Only with
+--enable-accessibility-debug, after the other bindings are defined.
+*/
+pub fn define_accessibility_debug(compartment: &bare_compartment) unsafe {
+    let cx = compartment.cx.ptr;
+    let proto = JSVAL_NULL;
+    do str::as_c_str(~"Element") |s| {
+        JS_GetProperty(cx, compartment.global_obj.ptr, s, ptr::to_unsafe_ptr(&proto));
+    }
+    let methods = ~[{name: compartment.add_name(~"computedAccessibleName"),
+                     call: {op: computedAccessibleName, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(cx, RUST_JSVAL_TO_OBJECT(proto), fns);
+    });
+}
+
+extern fn focus(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let bundle = unwrap(obj);
+    let node = (*bundle).payload.node;
+    // Focusing something that can't take focus does nothing
+    if is_focusable(&(*bundle).payload.scope, node) {
+        let win = (*task_from_context(cx)).window.expect(~"focus needs a window");
+        win.set_focus(cx, Some(FocusedElement { node: node, obj: RUST_OBJECT_TO_JSVAL(obj) }));
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn blur(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let node = (*unwrap(obj)).payload.node;
+    let win = (*task_from_context(cx)).window.expect(~"blur needs a window");
+    match win.focused {
+        Some(ref focused) if focused.node == node => win.set_focus(cx, None),
+        _ => ()
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+// The pointer id given as the first argument
+unsafe fn pointer_id_arg(cx: *JSContext, argc: c_uint, vp: *JSVal) -> Option<i32> {
+    if argc < 1 || RUST_JSVAL_IS_INT(*JS_ARGV(cx, vp)) == 0 {
+        do str::as_c_str(~"a pointer id is needed") |s| {
+            JS_ReportError(cx, s);
+        }
+        return None;
+    }
+    Some(RUST_JSVAL_TO_INT(*JS_ARGV(cx, vp)) as i32)
+}
+
+extern fn setPointerCapture(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let pointer_id = match pointer_id_arg(cx, argc, vp) {
+        Some(id) => id,
+        None => return 0
+    };
+    let bundle = unwrap(obj);
+    let node = (*bundle).payload.node;
+    let scope = (*bundle).payload.scope;
+    let win = (*task_from_context(cx)).window.expect(~"pointer capture needs a window");
+    let had_capture = win.pointers.target(pointer_id) == Some(node);
+    match win.pointers.set(pointer_id, node) {
+        Ok(lost) => {
+            for lost.each |old| {
+                post_capture_event(cx, win, scope, *old, "lostpointercapture", pointer_id);
+            }
+            if !had_capture {
+                post_capture_event(cx, win, scope, node, "gotpointercapture", pointer_id);
+            }
+        }
+        Err(()) => {
+            do str::as_c_str(~"NotFoundError: that pointer isn't active") |s| {
+                JS_ReportError(cx, s);
+            }
+            return 0;
+        }
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn releasePointerCapture(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let pointer_id = match pointer_id_arg(cx, argc, vp) {
+        Some(id) => id,
+        None => return 0
+    };
+    let bundle = unwrap(obj);
+    let node = (*bundle).payload.node;
+    let win = (*task_from_context(cx)).window.expect(~"pointer capture needs a window");
+    if win.pointers.release(pointer_id, node) {
+        post_capture_event(cx, win, (*bundle).payload.scope, node, "lostpointercapture",
+                           pointer_id);
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn hasPointerCapture(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let pointer_id = match pointer_id_arg(cx, argc, vp) {
+        Some(id) => id,
+        None => return 0
+    };
+    let node = (*unwrap(obj)).payload.node;
+    let win = (*task_from_context(cx)).window.expect(~"pointer capture needs a window");
+    let captured = win.pointers.target(pointer_id) == Some(node);
+    JS_SET_RVAL(cx, vp, RUST_BOOLEAN_TO_JSVAL(captured as JSBool));
+    return 1;
+}
+
+// The element's scroll offset, in px
+unsafe fn scroll_offset(cx: *JSContext, obj: *JSObject) -> Point2D<int> {
+    let win = (*task_from_context(cx)).window.expect(~"scrolling needs a window");
+    win.scroll.element_offset((*unwrap(obj)).payload.node)
+}
+
+// Scrolls the element, setting the offset `f` makes from the current one.
+// False if converting the value threw.
+unsafe fn set_scroll_offset(cx: *JSContext, obj: *JSObject, vp: *mut JSVal,
+                            f: fn(Point2D<int>, int) -> Point2D<int>) -> bool {
+    let win = (*task_from_context(cx)).window.expect(~"scrolling needs a window");
+    let node = (*unwrap(obj)).payload.node;
+    let arg = ptr::offset(JS_ARGV(cx, cast::reinterpret_cast(&vp)), 0);
+    let value = match jsval_to_px(cx, *arg) {
+        Some(value) => value,
+        None => return false
+    };
+    let offset = f(win.scroll.element_offset(node), value);
+    (*task_from_context(cx)).scroll_element_to(node, RUST_OBJECT_TO_JSVAL(obj), offset);
+    true
+}
+
+extern fn getScrollTop(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = RUST_INT_TO_JSVAL(scroll_offset(cx, obj).y as libc::c_int);
+    return 1;
+}
+
+extern fn setScrollTop(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    return set_scroll_offset(cx, obj, vp, |offset, top| Point2D(offset.x, top)) as JSBool;
+}
+
+extern fn getScrollLeft(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = RUST_INT_TO_JSVAL(scroll_offset(cx, obj).x as libc::c_int);
+    return 1;
+}
+
+extern fn setScrollLeft(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    return set_scroll_offset(cx, obj, vp, |offset, left| Point2D(left, offset.y)) as JSBool;
+}
+
 #[allow(non_implicitly_copyable_typarams)]
-pub fn create(cx: *JSContext, node: Node, scope: NodeScope) -> jsobj unsafe {
-    let proto = scope.write(&node, |nd| {
+pub fn create(cx: *JSContext, node: Node, scope: NodeScope) -> *JSObject unsafe {
+    let proto = scope.read(&node, |nd| {
         match nd.kind {
           ~Element(ed) => {
             match ed.kind {
               ~HTMLDivElement(*) => ~"HTMLDivElement",
+              ~HTMLFormElement(*) => ~"HTMLFormElement",
               ~HTMLHeadElement(*) => ~"HTMLHeadElement",
               ~HTMLImageElement(*) => ~"HTMLImageElement",
+              ~HTMLInputElement(*) => ~"HTMLInputElement",
               ~HTMLScriptElement(*) => ~"HTMLScriptElement",
               _ => ~"HTMLElement"
             }
@@ -179,5 +571,7 @@ pub fn create(cx: *JSContext, node: Node, scope: NodeScope) -> jsobj unsafe {
             cast::reinterpret_cast(&squirrel_away_unique(~NodeBundle(node, scope)));
         JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
     }
-    return obj;
+    // Rooted until the content task lets go of its nodes
+    (*task_from_context(cx)).node_wrappers.insert(node, obj.ptr);
+    return obj.ptr;
 }
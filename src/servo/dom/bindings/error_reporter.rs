@@ -0,0 +1,168 @@
+/*!
+Where script errors go. An uncaught exception is given to `window.onerror`
+if the page set it, as a string like `file.js:3:7: TypeError: x is null`,
+and then logged with `error!` unless the handler returned `true`. The
+engine reports errors from the middle of a call into it, where script
+can't run, so they wait on the content task until the script has returned.
+
+A promise rejected with no handler is logged too, once the microtask
+checkpoint after it has passed without one being added.
+*/
+
+use js::{JSVAL_NULL, JSTYPE_FUNCTION};
+use js::jsapi::{JSContext, JSVal, JSObject, JSErrorReport};
+use js::jsapi::bindgen::{JS_SetErrorReporter, JS_GetProperty, JS_CallFunctionValue,
+                         JS_TypeOfValue, JS_ClearPendingException};
+use js::glue::bindgen::{RUST_OBJECT_TO_JSVAL, RUST_JSVAL_TO_OBJECT, RUST_JSVAL_IS_OBJECT,
+                        RUST_JSVAL_TO_BOOLEAN, RUST_JSVAL_IS_BOOLEAN};
+use libc::{c_char, c_int, c_void};
+
+use content::content_task::task_from_context;
+use bindings::debug::capture_stack_trace;
+use bindings::promise::{get_promise_result, set_rejection_tracker};
+use utils::{domstring_to_jsval, jsval_to_str, str};
+
+// JS::PromiseRejectionHandlingState
+const REJECTION_UNHANDLED: c_int = 0;
+
+/// An uncaught error waiting to go to window.onerror: the error, and the
+/// stack it was thrown from, if any.
+pub type PendingError = (~str, ~str);
+
+/// An error as `window.onerror` and the log get it.
+pub fn format_error(message: &str, filename: &str, line: uint, column: uint) -> ~str {
+    fmt!("%s:%u:%u: %s", filename, line, column, message)
+}
+
+unsafe fn property(cx: *JSContext, obj: *JSObject, name: &str) -> JSVal {
+    let val = JSVAL_NULL;
+    do str::as_c_str(name) |name| {
+        JS_GetProperty(cx, obj, name, ptr::to_unsafe_ptr(&val));
+    }
+    val
+}
+
+// Gives `error` to window.onerror, and says whether it returned true to
+// keep the error from being logged
+unsafe fn call_onerror(cx: *JSContext, error: &str) -> bool {
+    let content = task_from_context(cx);
+    let global = match (*content).compartment {
+        Some(ref compartment) => compartment.global_obj.ptr,
+        None => return false
+    };
+    let window = property(cx, global, "window");
+    if RUST_JSVAL_IS_OBJECT(window) == 0 || window == JSVAL_NULL {
+        return false;
+    }
+    let window = RUST_JSVAL_TO_OBJECT(window);
+    let handler = property(cx, window, "onerror");
+    if JS_TypeOfValue(cx, handler) != JSTYPE_FUNCTION {
+        return false;
+    }
+
+    let arg = domstring_to_jsval(cx, &str(str::from_slice(error)));
+    let rval = JSVAL_NULL;
+    (*content).in_onerror = true;
+    let ok = JS_CallFunctionValue(cx, window, handler, 1, ptr::to_unsafe_ptr(&arg),
+                                  ptr::to_unsafe_ptr(&rval));
+    (*content).in_onerror = false;
+    if ok == 0 {
+        JS_ClearPendingException(cx);
+        return false;
+    }
+    RUST_JSVAL_IS_BOOLEAN(rval) == 1 && RUST_JSVAL_TO_BOOLEAN(rval) == 1
+}
+
+extern fn report_error(cx: *JSContext, message: *c_char, report: *JSErrorReport) unsafe {
+    let message = if message.is_null() { ~"unknown error" } else { str::raw::from_c_str(message) };
+    let (filename, line) = if report.is_null() {
+        (~"", 0)
+    } else if (*report).filename.is_null() {
+        (~"<unknown>", (*report).lineno as uint)
+    } else {
+        (str::raw::from_c_str((*report).filename), (*report).lineno as uint)
+    };
+    let error = if report.is_null() {
+        copy message
+    } else {
+        format_error(message, filename, line, (*report).column as uint)
+    };
+    let content = task_from_context(cx);
+    for (*content).devtools.each |client| {
+        client.console_message("javascript", "error", message, filename, line);
+    }
+    // Errors reported from inside script, rather than uncaught at the top,
+    // have a stack to show
+    let trace = capture_stack_trace(cx);
+    if (*content).in_onerror {
+        // An error thrown by the handler itself is only logged
+        log_error(error, trace);
+    } else {
+        (*content).pending_errors.push((move error, move trace));
+    }
+}
+
+fn log_error(error: &str, trace: &str) {
+    if trace.is_empty() {
+        error!("[JS] %s", error);
+    } else {
+        error!("[JS] %s\n%s", error, trace);
+    }
+}
+
+/// Gives the errors reported since script last returned to window.onerror,
+/// and logs the ones it doesn't handle. Called when no script is running.
+pub fn dispatch_pending_errors(cx: *JSContext) unsafe {
+    let content = task_from_context(cx);
+    while !(*content).pending_errors.is_empty() {
+        let (error, trace) = vec::shift(&mut (*content).pending_errors);
+        if !call_onerror(cx, error) {
+            log_error(error, trace);
+        }
+    }
+}
+
+extern fn track_rejection(cx: *JSContext, promise: *JSObject, state: c_int,
+                          _data: *c_void) unsafe {
+    let content = task_from_context(cx);
+    let promise = RUST_OBJECT_TO_JSVAL(promise);
+    if state == REJECTION_UNHANDLED {
+        (*content).unhandled_rejections.add(promise);
+    } else {
+        // A handler was added after all
+        match (*content).unhandled_rejections.key_of(promise) {
+            Some(key) => { (*content).unhandled_rejections.remove(key); }
+            None => ()
+        }
+    }
+}
+
+/// Logs the rejected promises that still have no handler. Called at the
+/// end of each microtask checkpoint.
+pub fn report_unhandled_rejections(cx: *JSContext) unsafe {
+    let rejections = &(*task_from_context(cx)).unhandled_rejections;
+    // Stringifying a reason runs script, which may reject more promises;
+    // those wait for the next checkpoint
+    for rejections.keys().each |key| {
+        if !rejections.contains(*key) {
+            loop;
+        }
+        let promise = rejections.get(*key);
+        let reason = get_promise_result(cx, RUST_JSVAL_TO_OBJECT(promise));
+        let reason = match jsval_to_str(cx, reason) {
+            Ok(move reason) => move reason,
+            Err(()) => {
+                JS_ClearPendingException(cx);
+                ~"<unprintable>"
+            }
+        };
+        error!("[JS] Uncaught (in promise) %s", reason);
+        rejections.remove(*key);
+    }
+}
+
+/// Sends the errors of `cx` to `window.onerror` and the log.
+pub fn init(cx: *JSContext) {
+    JS_SetErrorReporter(cx, report_error);
+    set_rejection_tracker(cx, track_rejection);
+}
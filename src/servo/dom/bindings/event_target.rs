@@ -0,0 +1,221 @@
+use js::rust::bare_compartment;
+use js::{JS_ARGV, JSVAL_VOID, JS_THIS_OBJECT, JS_SET_RVAL, JSTYPE_FUNCTION};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool};
+use js::jsapi::bindgen::{JS_DefineFunctions, JS_GetProperty, JS_SetProperty, JS_GetClass,
+                            JS_ReportError, JS_TypeOfValue, JS_ValueToBoolean};
+use js::glue::bindgen::*;
+use libc::c_uint;
+use ptr::null;
+
+use content::content_task::task_from_context;
+use dom::element::HTMLBodyElement;
+use dom::event_target::{EventTargetId, NodeTarget, ObjectTarget, EventListener,
+                        EventListenerOptions, default_passive};
+use dom::node::Element;
+use utils::{jsval_to_str, reset_propagation};
+
+/// Defines `addEventListener`, `removeEventListener` and `dispatchEvent` on
+/// a prototype.
+pub fn init(compartment: &bare_compartment, proto: *JSObject) {
+    let methods = ~[{name: compartment.add_name(~"addEventListener"),
+                     call: {op: addEventListener, info: null()},
+                     nargs: 3,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"removeEventListener"),
+                     call: {op: removeEventListener, info: null()},
+                     nargs: 3,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"dispatchEvent"),
+                     call: {op: dispatchEvent, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, proto, fns);
+    });
+}
+
+unsafe fn class_name(obj: *JSObject) -> ~str {
+    str::raw::from_c_str((*JS_GetClass(obj)).name)
+}
+
+/// Which target a JS object is, as far as its listeners are concerned.
+pub unsafe fn target_id(obj: *JSObject) -> EventTargetId {
+    if class_name(obj) == ~"GenericElementInstance" {
+        NodeTarget((*node::unwrap(obj)).payload.node)
+    } else {
+        ObjectTarget(obj)
+    }
+}
+
+// Whether a target is the window, the document or the body, where
+// scroll-blocking listeners are passive unless they say otherwise
+unsafe fn is_top_level(obj: *JSObject) -> bool {
+    match class_name(obj) {
+        ~"WindowInstance" | ~"DocumentInstance" => true,
+        ~"GenericElementInstance" => {
+            let bundle = node::unwrap(obj);
+            do (*bundle).payload.scope.read(&(*bundle).payload.node) |nd| {
+                match nd.kind {
+                    ~Element(ref ed) => match ed.kind {
+                        ~HTMLBodyElement => true,
+                        _ => false
+                    },
+                    _ => false
+                }
+            }
+        }
+        _ => false
+    }
+}
+
+unsafe fn to_bool(cx: *JSContext, val: JSVal) -> bool {
+    let b = 0;
+    JS_ValueToBoolean(cx, val, ptr::to_unsafe_ptr(&b));
+    b == 1
+}
+
+// A member of the options dictionary, if it's there
+unsafe fn bool_member(cx: *JSContext, options: *JSObject, name: &str) -> Option<bool> {
+    let val = JSVAL_VOID;
+    do str::as_c_str(name) |s| {
+        JS_GetProperty(cx, options, s, ptr::to_unsafe_ptr(&val));
+    }
+    if RUST_JSVAL_IS_VOID(val) == 1 {
+        None
+    } else {
+        Some(to_bool(cx, val))
+    }
+}
+
+// The third argument: `capture` on its own, or a dictionary
+unsafe fn options_arg(cx: *JSContext, argc: c_uint, vp: *JSVal) -> EventListenerOptions {
+    let mut options = EventListenerOptions();
+    if argc < 3 {
+        return options;
+    }
+    let arg = *ptr::offset(JS_ARGV(cx, vp), 2);
+    if RUST_JSVAL_IS_OBJECT(arg) == 1 && RUST_JSVAL_IS_NULL(arg) == 0 {
+        let dict = RUST_JSVAL_TO_OBJECT(arg);
+        options.capture = bool_member(cx, dict, "capture").get_default(false);
+        options.passive = bool_member(cx, dict, "passive");
+        options.once = bool_member(cx, dict, "once").get_default(false);
+    } else {
+        options.capture = to_bool(cx, arg);
+    }
+    options
+}
+
+// The type and callback arguments. A null callback is allowed, and does
+// nothing.
+unsafe fn listener_args(cx: *JSContext, argc: c_uint, vp: *JSVal)
+    -> Result<Option<(~str, JSVal)>, ()> {
+    if argc < 2 {
+        do str::as_c_str(~"an event type and a listener are needed") |s| {
+            JS_ReportError(cx, s);
+        }
+        return Err(());
+    }
+    let kind = match jsval_to_str(cx, *JS_ARGV(cx, vp)) {
+        Ok(move kind) => move kind,
+        Err(()) => return Err(())
+    };
+    let callback = *ptr::offset(JS_ARGV(cx, vp), 1);
+    if RUST_JSVAL_IS_NULL(callback) == 1 {
+        return Ok(None);
+    }
+    //TODO: objects with a handleEvent method can be listeners too
+    if JS_TypeOfValue(cx, callback) != JSTYPE_FUNCTION {
+        do str::as_c_str(~"event listeners must be functions") |s| {
+            JS_ReportError(cx, s);
+        }
+        return Err(());
+    }
+    Ok(Some((move kind, callback)))
+}
+
+extern fn addEventListener(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let (kind, callback) = match listener_args(cx, argc, vp) {
+        Ok(Some(move args)) => move args,
+        Ok(None) => {
+            JS_SET_RVAL(cx, vp, JSVAL_VOID);
+            return 1;
+        }
+        Err(()) => return 0
+    };
+    let options = options_arg(cx, argc, vp);
+    let passive = match options.passive {
+        Some(passive) => passive,
+        None => default_passive(kind, is_top_level(obj))
+    };
+    let win = (*task_from_context(cx)).window.expect(~"listeners need a window");
+    win.event_listeners.add(target_id(obj), EventListener {
+        kind: move kind,
+        callback: callback,
+        capture: options.capture,
+        passive: passive,
+        once: options.once
+    });
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    return 1;
+}
+
+extern fn removeEventListener(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    match listener_args(cx, argc, vp) {
+        Ok(Some((move kind, callback))) => {
+            let capture = options_arg(cx, argc, vp).capture;
+            let win = (*task_from_context(cx)).window.expect(~"listeners need a window");
+            win.event_listeners.remove(target_id(obj), kind, callback, capture);
+        }
+        Ok(None) => (),
+        Err(()) => return 0
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    return 1;
+}
+
+// Dispatches an event a script made, straight away, returning whether no
+// listener cancelled it
+extern fn dispatchEvent(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let event = if argc > 0 { *JS_ARGV(cx, vp) } else { JSVAL_VOID };
+    let kind_val = JSVAL_VOID;
+    if RUST_JSVAL_IS_OBJECT(event) == 1 && RUST_JSVAL_IS_NULL(event) == 0 {
+        do str::as_c_str("type") |s| {
+            JS_GetProperty(cx, RUST_JSVAL_TO_OBJECT(event), s, ptr::to_unsafe_ptr(&kind_val));
+        }
+    }
+    if RUST_JSVAL_IS_VOID(kind_val) == 1 {
+        do str::as_c_str(~"dispatchEvent needs an event") |s| {
+            JS_ReportError(cx, s);
+        }
+        return 0;
+    }
+    let kind = match jsval_to_str(cx, kind_val) {
+        Ok(move kind) => move kind,
+        Err(()) => return 0
+    };
+
+    let target = RUST_OBJECT_TO_JSVAL(obj);
+    do str::as_c_str("target") |s| {
+        JS_SetProperty(cx, RUST_JSVAL_TO_OBJECT(event), s, ptr::to_unsafe_ptr(&target));
+    }
+    // A listener may have stopped the event the last time it was dispatched
+    reset_propagation(cx, RUST_JSVAL_TO_OBJECT(event));
+    let not_cancelled = (*task_from_context(cx)).dispatch_event(target, kind, event);
+    JS_SET_RVAL(cx, vp, RUST_BOOLEAN_TO_JSVAL(not_cancelled as JSBool));
+    return 1;
+}
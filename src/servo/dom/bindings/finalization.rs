@@ -0,0 +1,95 @@
+/*!
+`WeakRef` and `FinalizationRegistry` are SpiderMonkey's own, once weak
+references are turned on. What's left to us is running the cleanup
+callbacks of a registry whose targets have been collected, which the
+engine asks for and which run as a task of their own; and letting go of
+the targets `deref()` has kept alive once the microtask checkpoint is over.
+
+The APIs for this are newer than the SpiderMonkey we pin, so they're
+only used when built with `--cfg weak_refs` (configure's
+--enable-weak-refs); without it, the functions here do nothing and
+pages have no `WeakRef` or `FinalizationRegistry`.
+
+With --expose-gc, there's a global `gc()` for tests that need to see
+something collected.
+*/
+
+use js::rust::bare_compartment;
+use js::{JSVAL_VOID, JS_SET_RVAL};
+use js::jsapi::{JSContext, JSVal, JSBool};
+#[cfg(weak_refs)]
+use js::jsapi::{JSObject, JSFunction};
+#[cfg(weak_refs)]
+use js::jsapi::bindgen::{JS_SetWeakRefsEnabled, JS_SetHostCleanupFinalizationRegistryCallback,
+                         JS_ClearKeptObjects, JS_GetFunctionObject};
+use js::jsapi::bindgen::{JS_GC, JS_GetRuntime, JS_DefineFunctions};
+#[cfg(weak_refs)]
+use js::glue::bindgen::RUST_OBJECT_TO_JSVAL;
+use libc::c_uint;
+#[cfg(weak_refs)]
+use libc::c_void;
+use ptr::null;
+
+use content::content_task::Content;
+#[cfg(weak_refs)]
+use content::content_task::Callback;
+
+// Called when a registry has cleanup to do. There's no context to find the
+// content task from, so it's passed in `data`. The cleanup function is kept
+// rooted until it has run.
+#[cfg(weak_refs)]
+extern fn queue_cleanup(do_cleanup: *JSFunction, _incumbent_global: *JSObject,
+                        data: *c_void) unsafe {
+    let content: *Content = cast::reinterpret_cast(&data);
+    let cleanup = RUST_OBJECT_TO_JSVAL(JS_GetFunctionObject(do_cleanup));
+    let posted = &(*content).posted;
+    (*content).control_chan.send(Callback(posted.add(cleanup), posted.add(JSVAL_VOID)));
+}
+
+/// Turns on weak references. This has to come before the global is made.
+#[cfg(weak_refs)]
+pub fn enable_weak_refs(cx: *JSContext) {
+    JS_SetWeakRefsEnabled(cx, 1);
+}
+
+#[cfg(not(weak_refs))]
+pub fn enable_weak_refs(_cx: *JSContext) {
+}
+
+/// Runs cleanup callbacks on `content`, the content task of `cx`.
+#[cfg(weak_refs)]
+pub fn init(cx: *JSContext, content: *Content) {
+    JS_SetHostCleanupFinalizationRegistryCallback(cx, queue_cleanup, content as *c_void);
+}
+
+#[cfg(not(weak_refs))]
+pub fn init(_cx: *JSContext, _content: *Content) {
+}
+
+/// Lets the targets of weak references that were dereferenced be collected
+/// again, as the end of each microtask checkpoint does.
+#[cfg(weak_refs)]
+pub fn clear_kept_objects(cx: *JSContext) {
+    JS_ClearKeptObjects(cx);
+}
+
+#[cfg(not(weak_refs))]
+pub fn clear_kept_objects(_cx: *JSContext) {
+}
+
+extern fn gc(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    JS_GC(JS_GetRuntime(cx));
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    1
+}
+
+pub fn define_gc(compartment: &bare_compartment) {
+    let methods = ~[{name: compartment.add_name(~"gc"),
+                     call: {op: gc, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, compartment.global_obj.ptr, fns);
+    });
+}
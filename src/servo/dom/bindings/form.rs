@@ -0,0 +1,207 @@
+use js::rust::bare_compartment;
+use js::{JSPROP_ENUMERATE, JSPROP_SHARED, JSVAL_NULL, JS_THIS_OBJECT, JS_SET_RVAL,
+         JSPROP_NATIVE_ACCESSORS};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool};
+use js::jsapi::bindgen::JS_DefineFunctions;
+use js::jsapi::bindgen::*;
+use js::glue::bindgen::*;
+
+use content::content_task::task_from_context;
+use dom::html::form::{FormAttributes, MethodGet, MethodPost, MethodDialog, UrlEncoded,
+                      Multipart, TextPlain, form_attributes, invalid_controls,
+                      construct_entry_list, plan_submission};
+use dom::node::Element;
+use utils::{domstring_to_jsval, new_event, str};
+use libc::c_uint;
+use ptr::null;
+use node::unwrap;
+
+pub fn init(compartment: &bare_compartment) {
+    let obj = utils::define_empty_prototype(~"HTMLFormElement", Some(~"HTMLElement"),
+                                            compartment);
+    let attrs = @~[
+        {name: compartment.add_name(~"action"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getAction, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"method"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getMethod, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"enctype"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getEnctype, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"target"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getTarget, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"noValidate"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getNoValidate, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs);
+    });
+
+    let methods = ~[{name: compartment.add_name(~"submit"),
+                     call: {op: submit, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"requestSubmit"),
+                     call: {op: requestSubmit, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"checkValidity"),
+                     call: {op: checkValidity, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj.ptr, fns);
+    });
+}
+
+unsafe fn attributes_of(obj: *JSObject) -> FormAttributes {
+    let bundle = unwrap(obj);
+    do (*bundle).payload.scope.read(&(*bundle).payload.node) |nd| {
+        match nd.kind {
+          ~Element(ref ed) => form_attributes(ed),
+          _ => fail ~"why is this not an element?"
+        }
+    }
+}
+
+// Gets a string from the form's attributes
+unsafe fn get_string(cx: *JSContext, vp: *mut JSVal, f: fn(&FormAttributes) -> ~str)
+    -> JSBool {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = domstring_to_jsval(cx, &str(f(&attributes_of(obj))));
+    return 1;
+}
+
+extern fn getAction(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    get_string(cx, vp, |attrs| copy attrs.action)
+}
+
+extern fn getMethod(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    do get_string(cx, vp) |attrs| {
+        match attrs.method {
+            MethodGet => ~"get",
+            MethodPost => ~"post",
+            MethodDialog => ~"dialog"
+        }
+    }
+}
+
+extern fn getEnctype(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    do get_string(cx, vp) |attrs| {
+        match attrs.enctype {
+            UrlEncoded => ~"application/x-www-form-urlencoded",
+            Multipart => ~"multipart/form-data",
+            TextPlain => ~"text/plain"
+        }
+    }
+}
+
+extern fn getTarget(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    get_string(cx, vp, |attrs| copy attrs.target)
+}
+
+extern fn getNoValidate(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = RUST_BOOLEAN_TO_JSVAL(attributes_of(obj).novalidate as JSBool);
+    return 1;
+}
+
+// Fires `invalid` at each of the form's controls that fails its constraints.
+// Returns whether there were none.
+unsafe fn validate(cx: *JSContext, obj: *JSObject) -> bool {
+    let content = task_from_context(cx);
+    let win = (*content).window.expect(~"forms need a window");
+    let document = (*content).document.expect(~"forms need a document");
+    let invalid = invalid_controls(document, (*unwrap(obj)).payload.node);
+    for invalid.each |control| {
+        let target = RUST_OBJECT_TO_JSVAL(node::create(cx, *control, document.scope));
+        let event = new_event(cx, "invalid", target);
+        win.post_event(target, ~"invalid", RUST_OBJECT_TO_JSVAL(event));
+    }
+    invalid.is_empty()
+}
+
+// Submits the form: its entries are encoded and the window navigates to
+// where it says. `submit()` skips validation and the `submit` event, as in
+// the spec; `requestSubmit()` and the submit button go through both.
+unsafe fn submit_form(cx: *JSContext, obj: *JSObject, interactive: bool) {
+    let content = task_from_context(cx);
+    let win = (*content).window.expect(~"forms need a window");
+    let document = (*content).document.expect(~"forms need a document");
+    let attrs = attributes_of(obj);
+    if interactive {
+        if !attrs.novalidate && !validate(cx, obj) {
+            return;
+        }
+        // A listener can cancel the submission
+        let target = RUST_OBJECT_TO_JSVAL(obj);
+        let event = new_event(cx, "submit", target);
+        if !(*content).dispatch_event(target, "submit", RUST_OBJECT_TO_JSVAL(event)) {
+            return;
+        }
+    }
+
+    let data = construct_entry_list(document, (*unwrap(obj)).payload.node);
+    let FormSubmission { url: move url, method: method, body: move body } =
+        plan_submission(&attrs, &(*content).doc_url.get(), &data);
+    match (method, move body) {
+        (MethodGet, _) => win.navigate(move url),
+        (MethodPost, Some((move body, move content_type))) => {
+            win.navigate_post(move url, move body, move content_type)
+        }
+        // Closing a dialog needs <dialog>
+        _ => ()
+    }
+}
+
+extern fn submit(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    submit_form(cx, obj, false);
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn requestSubmit(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    submit_form(cx, obj, true);
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn checkValidity(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let valid = validate(cx, obj);
+    JS_SET_RVAL(cx, vp, RUST_BOOLEAN_TO_JSVAL(valid as JSBool));
+    return 1;
+}
@@ -0,0 +1,295 @@
+use js::rust::{bare_compartment, methods, jsobj};
+use js::{JS_ARGV, JSVAL_NULL, JS_THIS_OBJECT, JS_SET_RVAL};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp};
+use js::jsapi::bindgen::{JS_GetReservedSlot, JS_SetReservedSlot, JS_DefineFunctions,
+                            JS_NewArrayObject, JS_ReportError};
+use js::glue::bindgen::*;
+use ptr::null;
+use libc::c_uint;
+use utils::{domstring_to_jsval, rust_box, squirrel_away, jsval_to_str, str, get_compartment};
+use dom::form_data::{FormData, FormDataEntryValue, StringValue, FileValue};
+use dom::file::File;
+use bindings::blob::{unwrap_blob, new_file_object, BlobObj, FileObj};
+use bindings::symbol;
+
+unsafe fn unwrap(obj: *JSObject) -> *rust_box<FormData> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+unsafe fn entry_value_to_jsval(cx: *JSContext, value: &FormDataEntryValue) -> JSVal {
+    match *value {
+        StringValue(ref s) => domstring_to_jsval(cx, &str(copy *s)),
+        FileValue(ref file) => new_file_object(cx, file.clone())
+    }
+}
+
+unsafe fn new_array(cx: *JSContext, values: &[JSVal]) -> JSVal {
+    do vec::as_imm_buf(values) |buf, len| {
+        RUST_OBJECT_TO_JSVAL(JS_NewArrayObject(cx, len as libc::c_int, buf))
+    }
+}
+
+// Gets `this` and the first `nstrings` arguments as strings, reporting an
+// error if there are fewer than `nargs` arguments
+unsafe fn this_and_args(cx: *JSContext, argc: c_uint, vp: *JSVal, nstrings: uint, nargs: uint,
+                        name: &str) -> Option<(*JSObject, ~[~str])> {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return None;
+    }
+    if (argc as uint) < nargs {
+        do str::as_c_str(fmt!("FormData.%s needs %u arguments", name, nargs)) |s| {
+            JS_ReportError(cx, s);
+        }
+        return None;
+    }
+
+    let argv = JS_ARGV(cx, vp);
+    let mut args = ~[];
+    for uint::range(0, nstrings) |i| {
+        match jsval_to_str(cx, *ptr::offset(argv, i)) {
+            Ok(move s) => args.push(move s),
+            Err(()) => return None
+        }
+    }
+    Some((obj, move args))
+}
+
+// The value argument of append() and set(): a string, or a Blob with an
+// optional file name
+unsafe fn entry_value(cx: *JSContext, argc: c_uint, vp: *JSVal) -> Option<FormDataEntryValue> {
+    let argv = JS_ARGV(cx, vp);
+    let val = *ptr::offset(argv, 1);
+    let filename = if argc > 2 {
+        match jsval_to_str(cx, *ptr::offset(argv, 2)) {
+            Ok(move name) => Some(move name),
+            Err(()) => return None
+        }
+    } else {
+        None
+    };
+    match unwrap_blob(cx, val) {
+        Some(blob) => {
+            let file = match *blob {
+                BlobObj(ref blob) => File(blob.clone(), ~"blob", 0),
+                FileObj(ref file) => file.clone()
+            };
+            Some(FileValue(match move filename {
+                Some(move name) => File(file.blob.clone(), move name, file.last_modified),
+                None => move file
+            }))
+        }
+        None => match jsval_to_str(cx, val) {
+            Ok(move s) => Some(StringValue(move s)),
+            Err(()) => None
+        }
+    }
+}
+
+extern fn append(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match this_and_args(cx, argc, vp, 1, 2, "append") {
+        Some((obj, move args)) => match entry_value(cx, argc, vp) {
+            Some(move value) => {
+                (*unwrap(obj)).payload.append(copy args[0], move value);
+                JS_SET_RVAL(cx, vp, JSVAL_NULL);
+                1
+            }
+            None => 0
+        },
+        None => 0
+    }
+}
+
+extern fn set(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match this_and_args(cx, argc, vp, 1, 2, "set") {
+        Some((obj, move args)) => match entry_value(cx, argc, vp) {
+            Some(move value) => {
+                (*unwrap(obj)).payload.set(copy args[0], move value);
+                JS_SET_RVAL(cx, vp, JSVAL_NULL);
+                1
+            }
+            None => 0
+        },
+        None => 0
+    }
+}
+
+extern fn delete(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match this_and_args(cx, argc, vp, 1, 1, "delete") {
+        Some((obj, move args)) => {
+            (*unwrap(obj)).payload.delete(args[0]);
+            JS_SET_RVAL(cx, vp, JSVAL_NULL);
+            1
+        }
+        None => 0
+    }
+}
+
+extern fn get(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match this_and_args(cx, argc, vp, 1, 1, "get") {
+        Some((obj, move args)) => {
+            let val = match (*unwrap(obj)).payload.get(args[0]) {
+                Some(ref value) => entry_value_to_jsval(cx, value),
+                None => JSVAL_NULL
+            };
+            JS_SET_RVAL(cx, vp, val);
+            1
+        }
+        None => 0
+    }
+}
+
+extern fn getAll(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match this_and_args(cx, argc, vp, 1, 1, "getAll") {
+        Some((obj, move args)) => {
+            let values = (*unwrap(obj)).payload.get_all(args[0]);
+            let vals = do values.map |value| { entry_value_to_jsval(cx, value) };
+            JS_SET_RVAL(cx, vp, new_array(cx, vals));
+            1
+        }
+        None => 0
+    }
+}
+
+extern fn has(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match this_and_args(cx, argc, vp, 1, 1, "has") {
+        Some((obj, move args)) => {
+            let found = (*unwrap(obj)).payload.has(args[0]);
+            JS_SET_RVAL(cx, vp, RUST_BOOLEAN_TO_JSVAL(found as JSBool));
+            1
+        }
+        None => 0
+    }
+}
+
+unsafe fn entries_array(cx: *JSContext, obj: *JSObject) -> JSVal {
+    let entries = (*unwrap(obj)).payload.entries();
+    let vals = do entries.map |entry| {
+        let (ref name, ref value) = *entry;
+        new_array(cx, ~[domstring_to_jsval(cx, &str(copy *name)),
+                        entry_value_to_jsval(cx, value)])
+    };
+    new_array(cx, vals)
+}
+
+// entries(), keys() and values() return arrays; only for-of gets an iterator
+extern fn entries(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match this_and_args(cx, argc, vp, 0, 0, "entries") {
+        Some((obj, _)) => {
+            JS_SET_RVAL(cx, vp, entries_array(cx, obj));
+            1
+        }
+        None => 0
+    }
+}
+
+// [Symbol.iterator], so that for-of gives [name, value] pairs
+extern fn iterator(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match this_and_args(cx, argc, vp, 0, 0, "[Symbol.iterator]") {
+        Some((obj, _)) => symbol::iterate_array(cx, entries_array(cx, obj), vp),
+        None => 0
+    }
+}
+
+extern fn keys(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match this_and_args(cx, argc, vp, 0, 0, "keys") {
+        Some((obj, _)) => {
+            let keys = (*unwrap(obj)).payload.keys();
+            let vals = do keys.map |key| { domstring_to_jsval(cx, &str(copy *key)) };
+            JS_SET_RVAL(cx, vp, new_array(cx, vals));
+            1
+        }
+        None => 0
+    }
+}
+
+extern fn values(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match this_and_args(cx, argc, vp, 0, 0, "values") {
+        Some((obj, _)) => {
+            let values = (*unwrap(obj)).payload.values();
+            let vals = do values.map |value| { entry_value_to_jsval(cx, value) };
+            JS_SET_RVAL(cx, vp, new_array(cx, vals));
+            1
+        }
+        None => 0
+    }
+}
+
+//TODO: new FormData(form) should start with the form's entries
+extern fn constructor(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"FormDataInstance", ~"FormData",
+                                          compartment.global_obj.ptr));
+    let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(@FormData()));
+    JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(obj.ptr));
+    return 1;
+}
+
+extern fn finalize(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("form data finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @FormData = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+pub fn init(compartment: &bare_compartment) {
+    let obj = utils::define_constructor(~"FormData", None, constructor, compartment);
+
+    let methods = ~[{name: compartment.add_name(~"append"),
+                     call: {op: append, info: null()},
+                     nargs: 2,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"delete"),
+                     call: {op: delete, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"get"),
+                     call: {op: get, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"getAll"),
+                     call: {op: getAll, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"has"),
+                     call: {op: has, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"set"),
+                     call: {op: set, info: null()},
+                     nargs: 2,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"entries"),
+                     call: {op: entries, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"keys"),
+                     call: {op: keys, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"values"),
+                     call: {op: values, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj.ptr, fns);
+    });
+    symbol::define_symbol_method(compartment.cx.ptr, obj.ptr, symbol::SymbolIterator,
+                                 "[Symbol.iterator]", iterator, 0);
+
+    compartment.register_class(utils::instance_jsclass(~"FormDataInstance", finalize));
+}
@@ -0,0 +1,261 @@
+use js::rust::{bare_compartment, methods, jsobj};
+use js::{JS_ARGV, JSPROP_ENUMERATE, JSPROP_SHARED, JSPROP_READONLY, JSVAL_NULL,
+            JS_THIS_OBJECT, JS_SET_RVAL, JSPROP_NATIVE_ACCESSORS};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp};
+use js::jsapi::bindgen::{JS_GetReservedSlot, JS_SetReservedSlot, JS_DefineFunctions,
+                            JS_DefineProperty, JS_DefineProperties, JS_GetProperty,
+                            JS_ReportError};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use ptr::null;
+use libc::c_uint;
+use utils::{domstring_to_jsval, rust_box, squirrel_away, jsval_to_str, str, get_compartment};
+use content::content_task::task_from_context;
+use bindings::structured_clone::{structured_clone, throw_data_clone_error};
+use dom::history::History;
+use dom::window::Window;
+
+unsafe fn window_from_context(cx: *JSContext) -> @Window {
+    (*task_from_context(cx)).window.expect(~"history needs a window")
+}
+
+unsafe fn unwrap(obj: *JSObject) -> *rust_box<@History> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+// pushState(state, title, url) and replaceState(state, title, url)
+unsafe fn update_state(cx: *JSContext, argc: c_uint, vp: *JSVal, push: bool) -> JSBool {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let argv = JS_ARGV(cx, vp);
+    if argc < 2 {
+        do str::as_c_str(~"pushState and replaceState need a state and a title") |s| {
+            JS_ReportError(cx, s);
+        }
+        return 0;
+    }
+
+    let state = match structured_clone(cx, *argv) {
+        Ok(state) => state,
+        Err(err) => {
+            throw_data_clone_error(cx, err);
+            return 0;
+        }
+    };
+    let title = match jsval_to_str(cx, *ptr::offset(argv, 1)) {
+        Ok(move s) => move s,
+        Err(()) => return 0
+    };
+    let url = if argc > 2 && RUST_JSVAL_IS_NULL(*ptr::offset(argv, 2)) == 0 &&
+            RUST_JSVAL_IS_VOID(*ptr::offset(argv, 2)) == 0 {
+        match jsval_to_str(cx, *ptr::offset(argv, 2)) {
+            Ok(move s) => Some(move s),
+            Err(()) => return 0
+        }
+    } else {
+        None
+    };
+
+    let history = (*unwrap(obj)).payload;
+    let url = match history.resolve_url(move url) {
+        Ok(move url) => move url,
+        Err(()) => {
+            //TODO: throw a SecurityError DOMException
+            do str::as_c_str(~"history URLs must have the same origin as the document") |s| {
+                JS_ReportError(cx, s);
+            }
+            return 0;
+        }
+    };
+
+    if push {
+        history.push_state(state, move title, move url);
+    } else {
+        history.replace_state(state, move title, move url);
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn pushState(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    update_state(cx, argc, vp, true)
+}
+
+extern fn replaceState(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    update_state(cx, argc, vp, false)
+}
+
+// Moves through the history, firing popstate at the window if we moved
+unsafe fn go_by(cx: *JSContext, vp: *JSVal, delta: int) -> JSBool {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let history = (*unwrap(obj)).payload;
+    if history.go(delta) {
+        let compartment = get_compartment(cx);
+        let window_obj = JSVAL_NULL;
+        do str::as_c_str(~"window") |s| {
+            JS_GetProperty(cx, compartment.global_obj.ptr, s, ptr::to_unsafe_ptr(&window_obj));
+        }
+
+        let event = utils::new_event(cx, "popstate", window_obj);
+        do str::as_c_str(~"state") |s| {
+            JS_DefineProperty(cx, event, s, history.state(),
+                              GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                              GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                              JSPROP_ENUMERATE);
+        }
+        window_from_context(cx).post_event(window_obj, ~"popstate", RUST_OBJECT_TO_JSVAL(event));
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn back(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    go_by(cx, vp, -1)
+}
+
+extern fn forward(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    go_by(cx, vp, 1)
+}
+
+extern fn go(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+    //TODO: go() and go(0) should reload the page
+    let delta = if argc > 0 && RUST_JSVAL_IS_INT(*argv) == 1 {
+        RUST_JSVAL_TO_INT(*argv) as int
+    } else {
+        0
+    };
+    go_by(cx, vp, delta)
+}
+
+extern fn getLength(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = RUST_INT_TO_JSVAL((*unwrap(obj)).payload.len() as libc::c_int);
+    return 1;
+}
+
+extern fn getState(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = (*unwrap(obj)).payload.state();
+    return 1;
+}
+
+extern fn getHref(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = domstring_to_jsval(cx, &str((*unwrap(obj)).payload.href()));
+    return 1;
+}
+
+extern fn finalize(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("history finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @@History = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+fn new_instance(compartment: &bare_compartment, class: ~str, proto: ~str,
+                history: @History) -> jsobj {
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(move class, move proto, compartment.global_obj.ptr));
+    unsafe {
+        let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(@history));
+        JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+    }
+    obj
+}
+
+/// Defines `history` and `location` on the window object.
+pub fn init(compartment: &bare_compartment, win: @Window, window_obj: *JSObject) {
+    let proto = utils::define_empty_prototype(~"History", None, compartment);
+    let methods = ~[{name: compartment.add_name(~"pushState"),
+                     call: {op: pushState, info: null()},
+                     nargs: 3,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"replaceState"),
+                     call: {op: replaceState, info: null()},
+                     nargs: 3,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"back"),
+                     call: {op: back, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"forward"),
+                     call: {op: forward, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"go"),
+                     call: {op: go, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, proto.ptr, fns);
+    });
+
+    let attrs = @~[
+        {name: compartment.add_name(~"length"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getLength, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"state"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getState, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        assert JS_DefineProperties(compartment.cx.ptr, proto.ptr, specs) == 1;
+    });
+    compartment.register_class(utils::instance_jsclass(~"HistoryInstance", finalize));
+
+    //TODO: setting href, and the rest of Location
+    let proto = utils::define_empty_prototype(~"Location", None, compartment);
+    let attrs = @~[
+        {name: compartment.add_name(~"href"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getHref, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        assert JS_DefineProperties(compartment.cx.ptr, proto.ptr, specs) == 1;
+    });
+    compartment.register_class(utils::instance_jsclass(~"LocationInstance", finalize));
+
+    let history = new_instance(compartment, ~"HistoryInstance", ~"History", win.history);
+    let location = new_instance(compartment, ~"LocationInstance", ~"Location", win.history);
+
+    define_readonly(compartment, window_obj, "history", history.ptr);
+    define_readonly(compartment, window_obj, "location", location.ptr);
+}
+
+fn define_readonly(compartment: &bare_compartment, obj: *JSObject, name: &str,
+                   value: *JSObject) {
+    do str::as_c_str(name) |s| {
+        JS_DefineProperty(compartment.cx.ptr, obj, s, RUST_OBJECT_TO_JSVAL(value),
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE | JSPROP_READONLY);
+    }
+}
@@ -0,0 +1,888 @@
+/*!
+`window.indexedDB` and the objects it leads to. Each keeps its part of
+`dom::idb` in its reserved slot, except an IDBRequest, whose fields are
+plain properties.
+
+Requests run one at a time on the database's storage task. Each step of a
+transaction is a native callback the window calls, first once the script
+that made it has finished, then each time the storage task is done. A
+transaction's JS object roots itself, its database and its requests until
+it finishes, so that their events can still be fired when script has
+dropped them.
+*/
+
+use js::rust::{bare_compartment, methods};
+use js::{JS_ARGV, JSPROP_ENUMERATE, JSPROP_SHARED, JSPROP_READONLY, JSVAL_NULL, JSVAL_VOID,
+         JS_THIS_OBJECT, JS_SET_RVAL, JSPROP_NATIVE_ACCESSORS};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp, JSNative};
+use js::jsapi::bindgen::{JS_GetReservedSlot, JS_SetReservedSlot, JS_DefineFunctions,
+                         JS_DefineProperties, JS_DefineProperty, JS_GetProperty, JS_NewObject,
+                         JS_NewArrayObject, JS_IsArrayObject, JS_GetArrayLength, JS_GetElement,
+                         JS_SetPendingException, JS_ValueToBoolean,
+                         JS_NewNumberValue, JS_NewFunction, JS_GetFunctionObject};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use ptr::null;
+use libc::c_uint;
+use utils::{domstring_to_jsval, rust_box, squirrel_away, str, get_compartment, throw_error,
+            unicode_to_jsval, jsval_to_unicode, string_arg};
+use bindings::rooting::RootedVec;
+use bindings::structured_clone::{serialize, deserialize};
+use content::content_task::task_from_context;
+use dom::idb;
+use dom::idb::{IDBFactory, IDBDatabase, IDBTransaction, IDBObjectStore, IDBIndex, Key,
+               NumberKey, StringKey, IdbError, DataError, VersionError, InvalidStateError,
+               AbortError, SecurityError, Outcome, KeyOutcome, RecordOutcome, RecordsOutcome,
+               DoneOutcome, ReadOnly, VersionChange, Running, Committing, Finished};
+use dom::text_decoder::decode_utf16_units;
+use dom::window::Window;
+
+// What a transaction's JS object keeps
+struct TransactionObject {
+    tx: @IDBTransaction,
+    // The transaction's object, its database's, its requests' and the
+    // callbacks that process it, until it finishes
+    roots: RootedVec,
+}
+
+// What an open request's JS object keeps, until it has opened the database
+struct OpenRequest {
+    name: ~str,
+    version: Option<u64>,
+    roots: RootedVec,
+}
+
+unsafe fn unwrap<T>(obj: *JSObject) -> *rust_box<T> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+// A new `class` object, keeping `payload` in its reserved slot
+unsafe fn wrap<T>(cx: *JSContext, class: ~str, proto: ~str, payload: @T) -> *JSObject {
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(compartment.new_object_with_proto(move class, move proto,
+                                                               compartment.global_obj.ptr));
+    let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(payload));
+    JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+    obj.ptr
+}
+
+unsafe fn window_from_context(cx: *JSContext) -> @Window {
+    (*task_from_context(cx)).window.expect(~"indexedDB needs a window")
+}
+
+unsafe fn define_value(cx: *JSContext, obj: *JSObject, name: &str, val: JSVal) {
+    do str::as_c_str(name) |s| {
+        JS_DefineProperty(cx, obj, s, val,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE | JSPROP_READONLY);
+    }
+}
+
+unsafe fn get_value(cx: *JSContext, obj: *JSObject, name: &str) -> JSVal {
+    let val = JSVAL_VOID;
+    do str::as_c_str(name) |s| {
+        JS_GetProperty(cx, obj, s, ptr::to_unsafe_ptr(&val));
+    }
+    val
+}
+
+unsafe fn is_object(val: JSVal) -> bool {
+    RUST_JSVAL_IS_OBJECT(val) == 1 && RUST_JSVAL_IS_NULL(val) == 0
+}
+
+unsafe fn new_exception(cx: *JSContext, error: &IdbError) -> JSVal {
+    let exception = JS_NewObject(cx, null(), null(), null());
+    define_value(cx, exception, "name", domstring_to_jsval(cx, &str(error.name())));
+    define_value(cx, exception, "message", domstring_to_jsval(cx, &str(error.message())));
+    RUST_OBJECT_TO_JSVAL(exception)
+}
+
+unsafe fn throw_idb_error(cx: *JSContext, error: IdbError) -> JSBool {
+    JS_SetPendingException(cx, new_exception(cx, &error));
+    0
+}
+
+unsafe fn number_to_jsval(cx: *JSContext, n: float) -> JSVal {
+    let val = JSVAL_NULL;
+    JS_NewNumberValue(cx, n as libc::c_double, ptr::to_unsafe_ptr(&val));
+    val
+}
+
+unsafe fn jsval_to_key(cx: *JSContext, val: JSVal) -> Option<Key> {
+    if RUST_JSVAL_IS_INT(val) == 1 {
+        Some(NumberKey(RUST_JSVAL_TO_INT(val) as float))
+    } else if RUST_JSVAL_IS_DOUBLE(val) == 1 {
+        let n = RUST_JSVAL_TO_DOUBLE(val) as float;
+        if float::is_NaN(n) { None } else { Some(NumberKey(n)) }
+    } else if RUST_JSVAL_IS_STRING(val) == 1 {
+        jsval_to_unicode(cx, val).map(|s| StringKey(copy *s))
+    } else {
+        None
+    }
+}
+
+unsafe fn key_to_jsval(cx: *JSContext, key: &Key) -> JSVal {
+    match *key {
+        NumberKey(n) => number_to_jsval(cx, n),
+        StringKey(ref s) => unicode_to_jsval(cx, *s)
+    }
+}
+
+// The key `key_path` finds in `value`, if there's a valid one
+unsafe fn evaluate_key_path(cx: *JSContext, value: JSVal, key_path: &str) -> Option<Key> {
+    let mut current = value;
+    if !key_path.is_empty() {
+        for str::split_char(key_path, '.').each |name| {
+            if !is_object(current) {
+                return None;
+            }
+            current = get_value(cx, RUST_JSVAL_TO_OBJECT(current), *name);
+        }
+    }
+    jsval_to_key(cx, current)
+}
+
+// A stored value, from its structured clone data
+unsafe fn value_to_jsval(cx: *JSContext, bytes: &[u8]) -> JSVal {
+    match deserialize(cx, bytes) {
+        Ok(value) => value,
+        Err(_) => JSVAL_VOID
+    }
+}
+
+unsafe fn outcome_to_jsval(cx: *JSContext, outcome: &Outcome) -> JSVal {
+    match *outcome {
+        KeyOutcome(ref key) => key_to_jsval(cx, key),
+        RecordOutcome(Some((_, ref bytes))) => value_to_jsval(cx, *bytes),
+        RecordOutcome(None) | DoneOutcome => JSVAL_VOID,
+        RecordsOutcome(ref records) => {
+            let values = do records.map |record| {
+                match *record {
+                    (_, ref bytes) => value_to_jsval(cx, *bytes)
+                }
+            };
+            do vec::as_imm_buf(values) |buf, len| {
+                RUST_OBJECT_TO_JSVAL(JS_NewArrayObject(cx, len as libc::c_int, buf))
+            }
+        }
+    }
+}
+
+// A JS function for `op`, kept in `roots`
+unsafe fn native_function(cx: *JSContext, op: JSNative, roots: &RootedVec) -> JSVal {
+    let fun = do str::as_c_str("indexedDB") |name| { JS_NewFunction(cx, op, 1, 0, null(), name) };
+    let fun_obj = JS_GetFunctionObject(fun);
+    roots.push(fun_obj);
+    RUST_OBJECT_TO_JSVAL(fun_obj)
+}
+
+// Calls `op` with `arg` once the script that's running now has finished.
+// The function is kept in `roots` until then
+unsafe fn post_native(cx: *JSContext, op: JSNative, arg: JSVal, roots: &RootedVec) {
+    window_from_context(cx).post_callback(native_function(cx, op, roots), arg);
+}
+
+/* IDBRequest */
+
+unsafe fn fire(cx: *JSContext, target: JSVal, kind: &str) -> bool {
+    let event = utils::new_event(cx, kind, target);
+    (*task_from_context(cx)).dispatch_event(target, kind, RUST_OBJECT_TO_JSVAL(event))
+}
+
+// A pending request on the store `source`
+unsafe fn new_request(cx: *JSContext, source: *JSObject, tx: @IDBTransaction) -> *JSObject {
+    let compartment = get_compartment(cx);
+    let request = result::unwrap(
+        compartment.new_object_with_proto(~"IDBRequestInstance", ~"IDBRequest",
+                                          compartment.global_obj.ptr)).ptr;
+    define_value(cx, request, "readyState", domstring_to_jsval(cx, &str(~"pending")));
+    define_value(cx, request, "result", JSVAL_VOID);
+    define_value(cx, request, "error", JSVAL_NULL);
+    define_value(cx, request, "source", RUST_OBJECT_TO_JSVAL(source));
+    define_value(cx, request, "transaction", tx.obj);
+    request
+}
+
+// Returns the request `queued` was made for, or throws why it couldn't be
+unsafe fn return_request(cx: *JSContext, vp: *JSVal, tx: @IDBTransaction, request: *JSObject,
+                         queued: Result<(), IdbError>) -> JSBool {
+    match move queued {
+        Ok(()) => {
+            (*transaction_object(tx)).payload.roots.push(request);
+            JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(request));
+            1
+        }
+        Err(move e) => throw_idb_error(cx, move e)
+    }
+}
+
+// Sets a request's result, or its error, as it's done
+unsafe fn complete_request(cx: *JSContext, request: *JSObject, result: JSVal, error: JSVal) {
+    define_value(cx, request, "readyState", domstring_to_jsval(cx, &str(~"done")));
+    define_value(cx, request, "result", result);
+    define_value(cx, request, "error", error);
+}
+
+/* IDBTransaction */
+
+unsafe fn transaction_object(tx: @IDBTransaction) -> *rust_box<TransactionObject> {
+    unwrap(RUST_JSVAL_TO_OBJECT(tx.obj))
+}
+
+// The JS object for a new transaction. Once the script that's running now
+// has finished, it's inactive, and if it has started, processed
+unsafe fn new_transaction(cx: *JSContext, tx: @IDBTransaction, db_obj: *JSObject)
+    -> *JSObject {
+    let obj = wrap(cx, ~"IDBTransactionInstance", ~"IDBTransaction",
+                   @TransactionObject { tx: tx, roots: RootedVec(cx) });
+    tx.obj = RUST_OBJECT_TO_JSVAL(obj);
+    let roots = &(*transaction_object(tx)).payload.roots;
+    roots.push(obj);
+    roots.push(db_obj);
+    define_value(cx, obj, "db", RUST_OBJECT_TO_JSVAL(db_obj));
+    define_value(cx, obj, "mode", domstring_to_jsval(cx, &str(tx.mode.name())));
+    define_value(cx, obj, "error", JSVAL_NULL);
+    schedule(cx, tx);
+    obj
+}
+
+unsafe fn schedule(cx: *JSContext, tx: @IDBTransaction) {
+    post_native(cx, process_transaction, tx.obj, &(*transaction_object(tx)).payload.roots);
+}
+
+// What the storage task calls once it has run a transaction's request or
+// commit, to have the transaction processed again
+unsafe fn when_done(cx: *JSContext, tx: @IDBTransaction) -> fn~() {
+    let fun = native_function(cx, process_transaction, &(*transaction_object(tx)).payload.roots);
+    window_from_context(cx).callback_sender(fun, tx.obj)
+}
+
+/**
+One step of a transaction: fires the event of the request the storage task
+has run, if there is one, then sends it the next request, or if there are
+none left, the commit. Once that's done, this is called again.
+*/
+extern fn process_transaction(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    let tx = (*unwrap::<TransactionObject>(RUST_JSVAL_TO_OBJECT(*JS_ARGV(cx, vp)))).payload.tx;
+    // Whatever script made requests has finished
+    tx.active = false;
+    if tx.state == Committing {
+        match tx.finish_commit() {
+            Ok(()) => complete_transaction(cx, tx),
+            Err(move error) => abort_transaction(cx, tx, move error)
+        }
+        return 1;
+    }
+    if tx.state != Running {
+        return 1;
+    }
+
+    match tx.finish_request() {
+        Some((request, move result)) => {
+            let request_obj = RUST_JSVAL_TO_OBJECT(request);
+            match move result {
+                Ok(move outcome) => {
+                    complete_request(cx, request_obj, outcome_to_jsval(cx, &outcome), JSVAL_NULL);
+                    // Handlers can make more requests
+                    tx.active = true;
+                    fire(cx, request, "success");
+                    tx.active = false;
+                }
+                Err(move error) => {
+                    complete_request(cx, request_obj, JSVAL_VOID, new_exception(cx, &error));
+                    tx.active = true;
+                    let not_cancelled = fire(cx, request, "error");
+                    tx.active = false;
+                    // preventDefault() lets the transaction go on
+                    if not_cancelled {
+                        abort_transaction(cx, tx, move error);
+                    }
+                }
+            }
+            // A handler may have aborted it
+            if tx.state != Running {
+                return 1;
+            }
+        }
+        None => ()
+    }
+    match copy tx.error {
+        Some(move error) => {
+            abort_transaction(cx, tx, move error);
+            return 1;
+        }
+        None => ()
+    }
+
+    if tx.has_requests() {
+        tx.run_next(when_done(cx, tx));
+    } else {
+        tx.commit(when_done(cx, tx));
+    }
+    1
+}
+
+unsafe fn complete_transaction(cx: *JSContext, tx: @IDBTransaction) {
+    fire(cx, tx.obj, "complete");
+    if tx.mode == VersionChange {
+        // The upgrade is done, so the open request succeeds
+        define_value(cx, RUST_JSVAL_TO_OBJECT(tx.open_request), "transaction", JSVAL_NULL);
+        fire(cx, tx.open_request, "success");
+    }
+    finish_transaction(cx, tx, true);
+}
+
+/**
+Undoes a transaction. The requests that hadn't run fail with an
+AbortError, and if it was upgrading the database, the open request fails
+too and the connection is closed.
+*/
+unsafe fn abort_transaction(cx: *JSContext, tx: @IDBTransaction, error: IdbError) {
+    if tx.state == Finished {
+        return;
+    }
+    let was_running = tx.state == Running || tx.state == Committing;
+    define_value(cx, RUST_JSVAL_TO_OBJECT(tx.obj), "error", new_exception(cx, &error));
+    let requests = tx.abort(move error);
+    for requests.each |request| {
+        complete_request(cx, RUST_JSVAL_TO_OBJECT(*request), JSVAL_VOID,
+                         new_exception(cx, &AbortError));
+        fire(cx, *request, "error");
+    }
+    fire(cx, tx.obj, "abort");
+    if tx.mode == VersionChange {
+        tx.db.closed = true;
+        let request = RUST_JSVAL_TO_OBJECT(tx.open_request);
+        complete_request(cx, request, JSVAL_VOID, new_exception(cx, &AbortError));
+        define_value(cx, request, "transaction", JSVAL_NULL);
+        fire(cx, tx.open_request, "error");
+    }
+    finish_transaction(cx, tx, was_running);
+}
+
+// Lets go of a finished transaction's objects, and starts the next one if
+// it was the one running
+unsafe fn finish_transaction(cx: *JSContext, tx: @IDBTransaction, was_running: bool) {
+    (*transaction_object(tx)).payload.roots.clear();
+    if was_running {
+        match tx.db.start_next() {
+            Some(next) => schedule(cx, next),
+            None => ()
+        }
+    }
+}
+
+extern fn objectStore(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let tx = (*unwrap::<TransactionObject>(obj)).payload.tx;
+    let name = match string_arg(cx, argc, vp, 0) {
+        Some(move name) => move name,
+        None => return throw_error(cx, "TypeError", "objectStore needs a store name")
+    };
+    match idb::object_store(tx, name) {
+        Ok(move store) => {
+            JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(new_store(cx, move store)));
+            1
+        }
+        Err(move e) => throw_idb_error(cx, move e)
+    }
+}
+
+/* indexedDB and IDBDatabase */
+
+unsafe fn get_bool(cx: *JSContext, obj: *JSObject, name: &str) -> bool {
+    if obj.is_null() {
+        return false;
+    }
+    let b = 0;
+    JS_ValueToBoolean(cx, get_value(cx, obj, name), ptr::to_unsafe_ptr(&b));
+    b != 0
+}
+
+// The options object at argument `i`, or null
+unsafe fn options_arg(cx: *JSContext, argc: c_uint, vp: *JSVal, i: uint) -> *JSObject {
+    if argc as uint > i && is_object(*ptr::offset(JS_ARGV(cx, vp), i)) {
+        RUST_JSVAL_TO_OBJECT(*ptr::offset(JS_ARGV(cx, vp), i))
+    } else {
+        null()
+    }
+}
+
+/// Where the page keeps its databases, or None if its origin can't have any.
+unsafe fn factory(cx: *JSContext) -> Option<IDBFactory> {
+    match utils::storage_location(cx) {
+        Some((ref dir, ref origin)) => Some(IDBFactory(dir, *origin)),
+        None => None
+    }
+}
+
+extern fn open(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let name = match string_arg(cx, argc, vp, 0) {
+        Some(move name) => move name,
+        None => return throw_error(cx, "TypeError", "open needs a database name")
+    };
+    let version = if argc > 1 && RUST_JSVAL_IS_VOID(*ptr::offset(JS_ARGV(cx, vp), 1)) == 0 {
+        let val = *ptr::offset(JS_ARGV(cx, vp), 1);
+        let n = if RUST_JSVAL_IS_INT(val) == 1 {
+            RUST_JSVAL_TO_INT(val) as float
+        } else if RUST_JSVAL_IS_DOUBLE(val) == 1 {
+            RUST_JSVAL_TO_DOUBLE(val) as float
+        } else {
+            0.0
+        };
+        if !(n >= 1.0 && n <= 9007199254740991.0 && float::floor(n) == n) {
+            return throw_error(cx, "TypeError", "The version has to be a whole number from 1");
+        }
+        Some(n as u64)
+    } else {
+        None
+    };
+    if factory(cx).is_none() {
+        return throw_idb_error(cx, SecurityError);
+    }
+
+    let request = wrap(cx, ~"IDBOpenDBRequestInstance", ~"IDBOpenDBRequest",
+                       @OpenRequest { name: move name, version: version, roots: RootedVec(cx) });
+    define_value(cx, request, "readyState", domstring_to_jsval(cx, &str(~"pending")));
+    define_value(cx, request, "result", JSVAL_VOID);
+    define_value(cx, request, "error", JSVAL_NULL);
+    define_value(cx, request, "source", JSVAL_NULL);
+    define_value(cx, request, "transaction", JSVAL_NULL);
+    let roots = &(*unwrap::<OpenRequest>(request)).payload.roots;
+    roots.push(request);
+    post_native(cx, open_database, RUST_OBJECT_TO_JSVAL(request), roots);
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(request));
+    1
+}
+
+unsafe fn fail_open(cx: *JSContext, request: JSVal, error: &IdbError) {
+    complete_request(cx, RUST_JSVAL_TO_OBJECT(request), JSVAL_VOID, new_exception(cx, error));
+    fire(cx, request, "error");
+}
+
+/**
+Opens the database an open request asked for. If it's at a lower version
+than the request's, "upgradeneeded" fires with a versionchange transaction
+that "success" waits for; otherwise "success" fires now.
+*/
+extern fn open_database(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    let request = *JS_ARGV(cx, vp);
+    let request_obj = RUST_JSVAL_TO_OBJECT(request);
+    let open_request = &(*unwrap::<OpenRequest>(request_obj)).payload;
+    let opened = match factory(cx) {
+        Some(ref factory) => factory.open(open_request.name),
+        None => Err(SecurityError)
+    };
+    match move opened {
+        Ok(db) => {
+            let version = match open_request.version {
+                Some(version) => version,
+                None => if db.version > 0 { db.version } else { 1 }
+            };
+            if version < db.version {
+                fail_open(cx, request, &VersionError);
+            } else {
+                let db_obj = new_database(cx, db);
+                complete_request(cx, request_obj, RUST_OBJECT_TO_JSVAL(db_obj), JSVAL_NULL);
+                if version > db.version {
+                    let old_version = db.version;
+                    let tx = idb::upgrade(db, version);
+                    let tx_obj = new_transaction(cx, tx, db_obj);
+                    tx.open_request = request;
+                    (*transaction_object(tx)).payload.roots.push(request_obj);
+                    define_value(cx, request_obj, "transaction", RUST_OBJECT_TO_JSVAL(tx_obj));
+
+                    let event = utils::new_event(cx, "upgradeneeded", request);
+                    define_value(cx, event, "oldVersion",
+                                 number_to_jsval(cx, old_version as float));
+                    define_value(cx, event, "newVersion", number_to_jsval(cx, version as float));
+                    // Stores and indexes can be made while it's handled
+                    tx.active = true;
+                    (*task_from_context(cx)).dispatch_event(request, "upgradeneeded",
+                                                            RUST_OBJECT_TO_JSVAL(event));
+                    tx.active = false;
+                } else {
+                    fire(cx, request, "success");
+                }
+            }
+        }
+        Err(move error) => fail_open(cx, request, &error)
+    }
+    open_request.roots.clear();
+    1
+}
+
+unsafe fn new_database(cx: *JSContext, db: @IDBDatabase) -> *JSObject {
+    let obj = wrap(cx, ~"IDBDatabaseInstance", ~"IDBDatabase", @db);
+    define_value(cx, obj, "name", unicode_to_jsval(cx, db.name));
+    obj
+}
+
+unsafe fn unwrap_database(obj: *JSObject) -> @IDBDatabase {
+    (*unwrap::<@IDBDatabase>(obj)).payload
+}
+
+extern fn getVersion(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = number_to_jsval(cx, unwrap_database(obj).version as float);
+    1
+}
+
+extern fn createObjectStore(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let db = unwrap_database(obj);
+    let name = match string_arg(cx, argc, vp, 0) {
+        Some(move name) => move name,
+        None => return throw_error(cx, "TypeError", "createObjectStore needs a store name")
+    };
+    let options = options_arg(cx, argc, vp, 1);
+    let key_path = if options.is_null() { JSVAL_VOID } else { get_value(cx, options, "keyPath") };
+    let key_path = if RUST_JSVAL_IS_VOID(key_path) == 1 || RUST_JSVAL_IS_NULL(key_path) == 1 {
+        None
+    } else if RUST_JSVAL_IS_STRING(key_path) == 1 {
+        jsval_to_unicode(cx, key_path)
+    } else {
+        return throw_error(cx, "TypeError", "Only string key paths are supported");
+    };
+    let tx = match db.upgrade_transaction() {
+        Some(tx) => tx,
+        None => return throw_idb_error(cx, InvalidStateError)
+    };
+    let created = db.create_object_store(tx, name, move key_path);
+    match created.chain(|_| idb::object_store(tx, name)) {
+        Ok(move store) => {
+            JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(new_store(cx, move store)));
+            1
+        }
+        Err(move e) => throw_idb_error(cx, move e)
+    }
+}
+
+extern fn transaction(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let names = if argc > 0 { *JS_ARGV(cx, vp) } else { JSVAL_VOID };
+    // One store's name, or an array of them
+    let scope = if is_object(names) && JS_IsArrayObject(cx, RUST_JSVAL_TO_OBJECT(names)) == 1 {
+        let array = RUST_JSVAL_TO_OBJECT(names);
+        let len = 0u32;
+        JS_GetArrayLength(cx, array, ptr::to_unsafe_ptr(&len));
+        let mut scope = ~[];
+        for uint::range(0, len as uint) |i| {
+            let elem = JSVAL_VOID;
+            JS_GetElement(cx, array, i as u32, ptr::to_unsafe_ptr(&elem));
+            match jsval_to_unicode(cx, elem) {
+                Some(move name) => scope.push(move name),
+                None => return 0
+            }
+        }
+        move scope
+    } else {
+        match string_arg(cx, argc, vp, 0) {
+            Some(move name) => ~[move name],
+            None => return throw_error(cx, "TypeError", "transaction needs the names of its stores")
+        }
+    };
+    let mode = match string_arg(cx, argc, vp, 1) {
+        None => ReadOnly,
+        Some(move name) => match idb::mode_for_name(name) {
+            Some(mode) => mode,
+            None => return throw_error(cx, "TypeError", "The mode has to be readonly or readwrite")
+        }
+    };
+    match idb::transaction(unwrap_database(obj), move scope, mode) {
+        Ok(tx) => {
+            JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(new_transaction(cx, tx, obj)));
+            1
+        }
+        Err(move e) => throw_idb_error(cx, move e)
+    }
+}
+
+/* IDBObjectStore and IDBIndex */
+
+unsafe fn new_store(cx: *JSContext, store: IDBObjectStore) -> *JSObject {
+    let name = unicode_to_jsval(cx, store.info.name);
+    let key_path = match store.info.key_path {
+        Some(ref key_path) => unicode_to_jsval(cx, *key_path),
+        None => JSVAL_NULL
+    };
+    let tx_obj = store.transaction.obj;
+    let obj = wrap(cx, ~"IDBObjectStoreInstance", ~"IDBObjectStore", @move store);
+    define_value(cx, obj, "name", name);
+    define_value(cx, obj, "keyPath", key_path);
+    // Which keeps the transaction's object alive as long as the store's
+    define_value(cx, obj, "transaction", tx_obj);
+    obj
+}
+
+unsafe fn new_index(cx: *JSContext, store_obj: *JSObject, index: IDBIndex) -> *JSObject {
+    let name = unicode_to_jsval(cx, index.info.name);
+    let key_path = unicode_to_jsval(cx, index.info.key_path);
+    let unique = RUST_BOOLEAN_TO_JSVAL(index.info.unique as JSBool);
+    let obj = wrap(cx, ~"IDBIndexInstance", ~"IDBIndex", @move index);
+    define_value(cx, obj, "name", name);
+    define_value(cx, obj, "keyPath", key_path);
+    define_value(cx, obj, "unique", unique);
+    define_value(cx, obj, "objectStore", RUST_OBJECT_TO_JSVAL(store_obj));
+    obj
+}
+
+// The key argument at `i`
+unsafe fn key_arg(cx: *JSContext, argc: c_uint, vp: *JSVal, i: uint) -> Option<Key> {
+    if argc as uint > i {
+        jsval_to_key(cx, *ptr::offset(JS_ARGV(cx, vp), i))
+    } else {
+        None
+    }
+}
+
+unsafe fn put_value(cx: *JSContext, argc: c_uint, vp: *JSVal, overwrite: bool) -> JSBool {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let store = &(*unwrap::<IDBObjectStore>(obj)).payload;
+    let value = if argc > 0 { *JS_ARGV(cx, vp) } else { JSVAL_VOID };
+    let has_key = argc > 1 && RUST_JSVAL_IS_VOID(*ptr::offset(JS_ARGV(cx, vp), 1)) == 0;
+    // A store with a key path finds keys in the values; one without
+    // needs them given. There's no key generator to make them
+    let key = match store.info.key_path {
+        Some(_) if has_key => None,
+        Some(ref key_path) => evaluate_key_path(cx, value, *key_path),
+        None => key_arg(cx, argc, vp, 1)
+    };
+    let key = match move key {
+        Some(move key) => move key,
+        None => return throw_idb_error(cx, DataError)
+    };
+    let bytes = match serialize(cx, value) {
+        Ok(move bytes) => move bytes,
+        Err(e) => return throw_error(cx, "DataCloneError", e.message())
+    };
+    let request = new_request(cx, obj, store.transaction);
+    let queued = store.put(RUST_OBJECT_TO_JSVAL(request), move key, move bytes, overwrite);
+    return_request(cx, vp, store.transaction, request, move queued)
+}
+
+extern fn add(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    put_value(cx, argc, vp, false)
+}
+
+extern fn put(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    put_value(cx, argc, vp, true)
+}
+
+extern fn get(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let store = &(*unwrap::<IDBObjectStore>(obj)).payload;
+    let key = match key_arg(cx, argc, vp, 0) {
+        Some(move key) => move key,
+        None => return throw_idb_error(cx, DataError)
+    };
+    let request = new_request(cx, obj, store.transaction);
+    let queued = store.get(RUST_OBJECT_TO_JSVAL(request), move key);
+    return_request(cx, vp, store.transaction, request, move queued)
+}
+
+extern fn getAll(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let store = &(*unwrap::<IDBObjectStore>(obj)).payload;
+    let request = new_request(cx, obj, store.transaction);
+    let queued = store.get_all(RUST_OBJECT_TO_JSVAL(request));
+    return_request(cx, vp, store.transaction, request, move queued)
+}
+
+extern fn delete(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let store = &(*unwrap::<IDBObjectStore>(obj)).payload;
+    let key = match key_arg(cx, argc, vp, 0) {
+        Some(move key) => move key,
+        None => return throw_idb_error(cx, DataError)
+    };
+    let request = new_request(cx, obj, store.transaction);
+    let queued = store.delete(RUST_OBJECT_TO_JSVAL(request), move key);
+    return_request(cx, vp, store.transaction, request, move queued)
+}
+
+extern fn clear(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let store = &(*unwrap::<IDBObjectStore>(obj)).payload;
+    let request = new_request(cx, obj, store.transaction);
+    let queued = store.clear(RUST_OBJECT_TO_JSVAL(request));
+    return_request(cx, vp, store.transaction, request, move queued)
+}
+
+/**
+Makes an index. It's only recorded for now: the store's records aren't
+filed in it, so it can't be read from.
+*/
+extern fn createIndex(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let store = &(*unwrap::<IDBObjectStore>(obj)).payload;
+    let (name, key_path) = match (string_arg(cx, argc, vp, 0), string_arg(cx, argc, vp, 1)) {
+        (Some(move name), Some(move key_path)) => (move name, move key_path),
+        _ => return throw_error(cx, "TypeError", "createIndex needs a name and a key path")
+    };
+    let unique = get_bool(cx, options_arg(cx, argc, vp, 2), "unique");
+    match store.create_index(name, key_path, unique) {
+        Ok(move index) => {
+            JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(new_index(cx, obj, move index)));
+            1
+        }
+        Err(move e) => throw_idb_error(cx, move e)
+    }
+}
+
+extern fn finalize_open_request(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("idb open request finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @OpenRequest = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+extern fn finalize_database(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("idb database finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @@IDBDatabase = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+extern fn finalize_transaction(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("idb transaction finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @TransactionObject = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+extern fn finalize_store(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("idb object store finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @IDBObjectStore = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+extern fn finalize_index(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("idb index finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @IDBIndex = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+fn define_methods(compartment: &bare_compartment, obj: *JSObject,
+                  methods: &[(~str, *u8, u16)]) {
+    let specs = do methods.map |method| {
+        let (ref name, op, nargs) = *method;
+        {name: compartment.add_name(copy *name),
+         call: {op: op, info: null()},
+         nargs: nargs,
+         flags: 0,
+         selfHostedName: null()}
+    };
+    vec::as_imm_buf(specs, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj, fns);
+    });
+}
+
+fn define_getters(compartment: &bare_compartment, obj: *JSObject, getters: &[(~str, *u8)]) {
+    let attrs = @do getters.map |getter| {
+        let (ref name, op) = *getter;
+        {name: compartment.add_name(copy *name),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: op, info: null()},
+         setter: {op: null(), info: null()}}
+    };
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        assert JS_DefineProperties(compartment.cx.ptr, obj, specs) == 1;
+    });
+}
+
+pub fn init(compartment: &bare_compartment) {
+    let factory = utils::define_empty_prototype(~"IDBFactory", None, compartment);
+    define_methods(compartment, factory.ptr, ~[(~"open", open as *u8, 2)]);
+    compartment.register_class(utils::instance_jsclass(~"IDBFactoryInstance", null()));
+
+    // Requests' fields are plain properties, set as they change
+    let request = utils::define_empty_prototype(~"IDBRequest", None, compartment);
+    bindings::event_target::init(compartment, request.ptr);
+    compartment.register_class(utils::instance_jsclass(~"IDBRequestInstance", null()));
+    utils::define_empty_prototype(~"IDBOpenDBRequest", Some(~"IDBRequest"), compartment);
+    compartment.register_class(utils::instance_jsclass(~"IDBOpenDBRequestInstance",
+                                                       finalize_open_request));
+
+    let database = utils::define_empty_prototype(~"IDBDatabase", None, compartment);
+    define_methods(compartment, database.ptr,
+                   ~[(~"createObjectStore", createObjectStore as *u8, 2),
+                     (~"transaction", transaction as *u8, 2)]);
+    define_getters(compartment, database.ptr, ~[(~"version", getVersion as *u8)]);
+    bindings::event_target::init(compartment, database.ptr);
+    compartment.register_class(utils::instance_jsclass(~"IDBDatabaseInstance",
+                                                       finalize_database));
+
+    let transaction = utils::define_empty_prototype(~"IDBTransaction", None, compartment);
+    define_methods(compartment, transaction.ptr,
+                   ~[(~"objectStore", objectStore as *u8, 1)]);
+    bindings::event_target::init(compartment, transaction.ptr);
+    compartment.register_class(utils::instance_jsclass(~"IDBTransactionInstance",
+                                                       finalize_transaction));
+
+    let store = utils::define_empty_prototype(~"IDBObjectStore", None, compartment);
+    define_methods(compartment, store.ptr,
+                   ~[(~"add", add as *u8, 2),
+                     (~"put", put as *u8, 2),
+                     (~"get", get as *u8, 1),
+                     (~"getAll", getAll as *u8, 0),
+                     (~"delete", delete as *u8, 1),
+                     (~"clear", clear as *u8, 0),
+                     (~"createIndex", createIndex as *u8, 3)]);
+    compartment.register_class(utils::instance_jsclass(~"IDBObjectStoreInstance",
+                                                       finalize_store));
+
+    utils::define_empty_prototype(~"IDBIndex", None, compartment);
+    compartment.register_class(utils::instance_jsclass(~"IDBIndexInstance", finalize_index));
+
+    let indexed_db = result::unwrap(
+        compartment.new_object_with_proto(~"IDBFactoryInstance", ~"IDBFactory",
+                                          compartment.global_obj.ptr));
+    compartment.define_property(~"indexedDB", RUST_OBJECT_TO_JSVAL(indexed_db.ptr),
+                                GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                                GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                                JSPROP_ENUMERATE);
+}
@@ -0,0 +1,149 @@
+use js::rust::bare_compartment;
+use js::{JS_ARGV, JSPROP_ENUMERATE, JSPROP_SHARED, JSPROP_NATIVE_ACCESSORS, JS_THIS_OBJECT};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool};
+use js::jsapi::bindgen::*;
+use js::glue::bindgen::*;
+
+use content::content_task::task_from_context;
+use dom::element::ElementData;
+use dom::html::input;
+use dom::node::Element;
+use utils::{domstring_to_jsval, jsval_to_str, str};
+use libc::c_uint;
+use ptr::null;
+use node::unwrap;
+
+pub fn init(compartment: &bare_compartment) {
+    let obj = utils::define_empty_prototype(~"HTMLInputElement", Some(~"HTMLElement"),
+                                            compartment);
+    let attrs = @~[
+        {name: compartment.add_name(~"type"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getType, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"value"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getValue, info: null()},
+         setter: {op: setValue, info: null()}},
+        {name: compartment.add_name(~"defaultValue"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getDefaultValue, info: null()},
+         setter: {op: setDefaultValue, info: null()}},
+        {name: compartment.add_name(~"checked"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getChecked, info: null()},
+         setter: {op: setChecked, info: null()}},
+        {name: compartment.add_name(~"defaultChecked"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getDefaultChecked, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs);
+    });
+}
+
+unsafe fn with_input<R>(obj: *JSObject, f: fn(&ElementData) -> R) -> R {
+    let bundle = unwrap(obj);
+    do (*bundle).payload.scope.read(&(*bundle).payload.node) |nd| {
+        match nd.kind {
+          ~Element(ref ed) => f(ed),
+          _ => fail ~"why is this not an element?"
+        }
+    }
+}
+
+// Gets a string from the input
+unsafe fn get_string(cx: *JSContext, vp: *mut JSVal, f: fn(&ElementData) -> ~str) -> JSBool {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = domstring_to_jsval(cx, &str(with_input(obj, f)));
+    return 1;
+}
+
+// The value being assigned to a property
+unsafe fn setter_arg(cx: *JSContext, vp: *mut JSVal) -> JSVal {
+    *ptr::offset(JS_ARGV(cx, cast::reinterpret_cast(&vp)), 0)
+}
+
+extern fn getType(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    get_string(cx, vp, input::input_type)
+}
+
+extern fn getValue(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    get_string(cx, vp, input::value)
+}
+
+extern fn setValue(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    match jsval_to_str(cx, setter_arg(cx, vp)) {
+        // Unlike typing, this doesn't fire `input`
+        Ok(move value) => {
+            let bundle = unwrap(obj);
+            input::set_value(&(*bundle).payload.scope, (*bundle).payload.node, move value);
+            return 1;
+        }
+        Err(()) => return 0
+    }
+}
+
+extern fn getDefaultValue(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    get_string(cx, vp, |input| input.get_attr("value").get_default(~""))
+}
+
+extern fn setDefaultValue(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    match jsval_to_str(cx, setter_arg(cx, vp)) {
+        Ok(move value) => {
+            with_input(obj, |input| input.set_attr("value", copy value));
+            return 1;
+        }
+        Err(()) => return 0
+    }
+}
+
+extern fn getChecked(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = RUST_BOOLEAN_TO_JSVAL(with_input(obj, input::checked) as JSBool);
+    return 1;
+}
+
+extern fn setChecked(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    let checked = 0;
+    JS_ValueToBoolean(cx, setter_arg(cx, vp), ptr::to_unsafe_ptr(&checked));
+    let content = task_from_context(cx);
+    let document = (*content).document.expect(~"inputs need a document");
+    input::set_checked(document, (*unwrap(obj)).payload.node, checked == 1);
+    (*content).update_accessibility_tree();
+    return 1;
+}
+
+extern fn getDefaultChecked(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    let checked = with_input(obj, |input| input.get_attr("checked").is_some());
+    *vp = RUST_BOOLEAN_TO_JSVAL(checked as JSBool);
+    return 1;
+}
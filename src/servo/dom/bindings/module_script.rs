@@ -0,0 +1,229 @@
+/*!
+Compiling, linking and running ES modules. A module's whole graph is
+fetched and compiled before any of it runs: SpiderMonkey asks the resolve
+hook for each import while it links, and that has to answer straight away,
+so every import must be in the content task's module map by then.
+
+Errors loading a module go to the error reporter, like those a module
+throws, and so to `window.onerror`.
+*/
+
+use js::JSPROP_ENUMERATE;
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSString};
+use js::jsapi::bindgen::{JS_CompileModule, JS_ModuleInstantiate, JS_ModuleEvaluate,
+                         JS_GetRequestedModules, JS_GetRequestedModuleSpecifier,
+                         JS_SetModulePrivate, JS_SetModuleResolveHook,
+                         JS_SetModuleMetadataHook, JS_GetRuntime, JS_GetArrayLength,
+                         JS_GetElement, JS_DefineProperty, JS_ReportError};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use js::JSVAL_VOID;
+use libc::{c_char, size_t};
+use ptr::null;
+use std::future;
+use std::net::url;
+use std::net::url::Url;
+use util::url::url_to_str;
+
+use content::content_task::task_from_context;
+use content::module_loader::{ModuleError, InvalidModule, resolve_module, fetch_module};
+use resource::resource_task::{ResourceTask, FetchTiming};
+use util::url::{UrlMap, url_map};
+use utils::{domstring_to_jsval, jsval_to_str, str, throw_error};
+use bindings::rooting::RootedVec;
+
+pub struct Module {
+    url: Url,
+    record: *JSObject,
+}
+
+/**
+The compiled modules of a page, by URL. A module is only fetched and run
+once, however many times it's imported. The map keeps each module's record
+rooted, as nothing else holds on to it between imports.
+*/
+pub struct ModuleMap {
+    priv modules: UrlMap<@Module>,
+    priv records: RootedVec,
+}
+
+pub fn ModuleMap(cx: *JSContext) -> ModuleMap {
+    ModuleMap { modules: url_map(), records: RootedVec(cx) }
+}
+
+impl ModuleMap {
+    fn contains(&self, url: &Url) -> bool {
+        self.modules.contains_key(copy *url)
+    }
+
+    fn find(&self, url: &Url) -> Option<@Module> {
+        self.modules.find(copy *url)
+    }
+
+    priv fn insert(&self, url: &Url, record: *JSObject) {
+        self.records.push(record);
+        self.modules.insert(copy *url, @Module { url: copy *url, record: record });
+    }
+
+    /// Forgets every module, and unroots their records.
+    fn clear(&self) {
+        self.modules.clear();
+        self.records.clear();
+    }
+}
+
+unsafe fn compile(cx: *JSContext, url: &Url, source: &str) -> Result<*JSObject, ModuleError> {
+    let url_str = url_to_str(copy *url);
+    let record = do str::as_c_str(url_str) |filename| {
+        do str::as_buf(source) |buf, _len| {
+            JS_CompileModule(cx, filename, buf as *c_char, source.len() as size_t)
+        }
+    };
+    if record.is_null() {
+        return Err(InvalidModule(move url_str));
+    }
+    // The module keeps its URL, to resolve its imports against and for
+    // import.meta.url
+    JS_SetModulePrivate(record, domstring_to_jsval(cx, &str(move url_str)));
+    Ok(record)
+}
+
+unsafe fn requested_specifiers(cx: *JSContext, record: *JSObject) -> ~[~str] {
+    let requested = JS_GetRequestedModules(cx, record);
+    let len = 0u32;
+    JS_GetArrayLength(cx, requested, ptr::to_unsafe_ptr(&len));
+    let mut specifiers = ~[];
+    for uint::range(0, len as uint) |i| {
+        let elem = JSVAL_VOID;
+        JS_GetElement(cx, requested, i as u32, ptr::to_unsafe_ptr(&elem));
+        let specifier = JS_GetRequestedModuleSpecifier(cx, elem);
+        match jsval_to_str(cx, RUST_STRING_TO_JSVAL(specifier)) {
+            Ok(move specifier) => specifiers.push(move specifier),
+            Err(()) => ()
+        }
+    }
+    move specifiers
+}
+
+/**
+Fetches and compiles the module at `url` and everything it imports, leaving
+out what's in `modules` already. The imports found in one round of fetches
+are all fetched at once in the next.
+*/
+pub fn load_module_graph(cx: *JSContext, modules: &ModuleMap, url: Url,
+                         resource_task: ResourceTask) -> Result<@Module, ModuleError> unsafe {
+    let mut pending = if modules.contains(&url) { ~[] } else { ~[copy url] };
+    while !pending.is_empty() {
+        let fetches = do pending.map |url| {
+            (copy *url, fetch_module(copy *url, resource_task))
+        };
+        pending = ~[];
+        for fetches.each |fetch| {
+            let (ref module_url, ref source) = *fetch;
+            let source = match future::get(source) {
+                Ok((move source, move timing)) => {
+                    for timing.each |timing| {
+                        record_fetch(cx, module_url, timing);
+                    }
+                    move source
+                }
+                Err(move err) => return Err(move err)
+            };
+            let record = match compile(cx, module_url, source) {
+                Ok(record) => record,
+                Err(move err) => return Err(move err)
+            };
+            modules.insert(module_url, record);
+
+            for requested_specifiers(cx, record).each |specifier| {
+                match resolve_module(*specifier, module_url) {
+                    Ok(move import) => {
+                        if !modules.contains(&import) && !vec::contains(pending, &import) {
+                            pending.push(move import);
+                        }
+                    }
+                    Err(move err) => return Err(move err)
+                }
+            }
+        }
+    }
+    Ok(modules.find(&url).get())
+}
+
+// Adds a resource entry to the page's timeline for the fetch of `url`
+unsafe fn record_fetch(cx: *JSContext, url: &Url, timing: &FetchTiming) {
+    let content = task_from_context(cx);
+    for (*content).window.each |window| {
+        let entry = window.performance.resource_entry(url, ~"script", timing,
+                                                      &(*content).doc_url.get());
+        window.record_performance_entry(move entry);
+    }
+}
+
+/// Loads the module at `url` with its imports, then links and runs it. A
+/// module that throws reports its own exception; a module that can't be
+/// loaded or linked is reported here.
+pub fn run_module(cx: *JSContext, modules: &ModuleMap, url: Url,
+                  resource_task: ResourceTask) unsafe {
+    let module = match load_module_graph(cx, modules, copy url, resource_task) {
+        Ok(module) => module,
+        Err(move err) => return report_error(cx, err.message())
+    };
+    if JS_ModuleInstantiate(cx, module.record) == 0 {
+        return report_error(cx, InvalidModule(url_to_str(move url)).message());
+    }
+    JS_ModuleEvaluate(cx, module.record);
+}
+
+unsafe fn report_error(cx: *JSContext, message: &str) {
+    do str::as_c_str(message) |s| {
+        JS_ReportError(cx, s);
+    }
+}
+
+// Finds the module an import names, among those the graph loaded
+extern fn resolve_hook(cx: *JSContext, referrer: JSVal, specifier: *JSString) -> *JSObject unsafe {
+    let referrer_url = match jsval_to_str(cx, referrer) {
+        Ok(move s) => match url::from_str(s) {
+            Ok(move url) => move url,
+            Err(_) => {
+                throw_error(cx, "TypeError", fmt!("%s isn't a module URL", s));
+                return null();
+            }
+        },
+        Err(()) => return null()
+    };
+    let specifier = match jsval_to_str(cx, RUST_STRING_TO_JSVAL(specifier)) {
+        Ok(move specifier) => move specifier,
+        Err(()) => return null()
+    };
+    match resolve_module(specifier, &referrer_url) {
+        Ok(move url) => match (*task_from_context(cx)).modules.find(&url) {
+            Some(module) => module.record,
+            None => {
+                report_error(cx, fmt!("module %s wasn't loaded", url_to_str(move url)));
+                null()
+            }
+        },
+        Err(move err) => {
+            report_error(cx, err.message());
+            null()
+        }
+    }
+}
+
+// Fills in import.meta
+extern fn metadata_hook(cx: *JSContext, private: JSVal, meta: *JSObject) -> JSBool unsafe {
+    do str::as_c_str("url") |s| {
+        JS_DefineProperty(cx, meta, s, private,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE)
+    }
+}
+
+pub fn init(cx: *JSContext) {
+    let rt = JS_GetRuntime(cx);
+    JS_SetModuleResolveHook(rt, resolve_hook);
+    JS_SetModuleMetadataHook(rt, metadata_hook);
+}
@@ -0,0 +1,174 @@
+use js::rust::{bare_compartment, methods, jsobj};
+use js::{JS_ARGV, JSPROP_ENUMERATE, JSPROP_READONLY, JSVAL_NULL, JS_THIS_OBJECT, JS_SET_RVAL,
+            JSTYPE_FUNCTION};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp};
+use js::jsapi::bindgen::{JS_GetReservedSlot, JS_SetReservedSlot, JS_DefineFunctions,
+                            JS_DefineProperty, JS_NewObject, JS_NewNumberValue,
+                            JS_TypeOfValue};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use ptr::null;
+use libc::c_uint;
+use utils::{domstring_to_jsval, rust_box, squirrel_away, str};
+use content::content_task::task_from_context;
+use bindings::rooting::RootedVec;
+use dom::geolocation::{Geolocation, Position, PositionError};
+use dom::window::Window;
+
+unsafe fn define_value(cx: *JSContext, obj: *JSObject, name: &str, val: JSVal) {
+    do str::as_c_str(name) |s| {
+        JS_DefineProperty(cx, obj, s, val,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE | JSPROP_READONLY);
+    }
+}
+
+unsafe fn define_number(cx: *JSContext, obj: *JSObject, name: &str, n: float) {
+    let val = JSVAL_NULL;
+    JS_NewNumberValue(cx, n as libc::c_double, ptr::to_unsafe_ptr(&val));
+    define_value(cx, obj, name, val);
+}
+
+// The objects are rooted while they're built, as each number and string
+// defined on them can GC
+unsafe fn position_to_jsval(cx: *JSContext, position: &Position) -> JSVal {
+    let roots = RootedVec(cx);
+    let obj = JS_NewObject(cx, null(), null(), null());
+    roots.push(obj);
+    let coords = JS_NewObject(cx, null(), null(), null());
+    roots.push(coords);
+    define_number(cx, coords, "latitude", position.coords.latitude);
+    define_number(cx, coords, "longitude", position.coords.longitude);
+    define_number(cx, coords, "accuracy", position.coords.accuracy);
+
+    define_value(cx, obj, "coords", RUST_OBJECT_TO_JSVAL(coords));
+    define_number(cx, obj, "timestamp", position.timestamp as float);
+    RUST_OBJECT_TO_JSVAL(obj)
+}
+
+unsafe fn error_to_jsval(cx: *JSContext, error: PositionError) -> JSVal {
+    let roots = RootedVec(cx);
+    let obj = JS_NewObject(cx, null(), null(), null());
+    roots.push(obj);
+    define_value(cx, obj, "code", RUST_INT_TO_JSVAL(error as libc::c_int));
+    define_value(cx, obj, "message", domstring_to_jsval(cx, &str(error.message())));
+    RUST_OBJECT_TO_JSVAL(obj)
+}
+
+// Queues the success or error callback for the current position. Callbacks
+// that aren't functions are ignored.
+unsafe fn report_position(cx: *JSContext, geolocation: @Geolocation, argc: c_uint, argv: *JSVal) {
+    let win: @Window = (*task_from_context(cx)).window.expect(~"geolocation needs a window");
+    match geolocation.current_position() {
+        Ok(ref position) => {
+            if argc > 0 && JS_TypeOfValue(cx, *argv) == JSTYPE_FUNCTION {
+                win.post_callback(*argv, position_to_jsval(cx, position));
+            }
+        }
+        Err(error) => {
+            if argc > 1 && JS_TypeOfValue(cx, *ptr::offset(argv, 1)) == JSTYPE_FUNCTION {
+                win.post_callback(*ptr::offset(argv, 1), error_to_jsval(cx, error));
+            }
+        }
+    }
+}
+
+//TODO: the options argument (enableHighAccuracy, timeout, maximumAge) is ignored
+extern fn getCurrentPosition(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    report_position(cx, (*unwrap(obj)).payload, argc, JS_ARGV(cx, vp));
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+//TODO: there are no position updates to send yet, so a watch only reports
+//      the first position
+extern fn watchPosition(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let geolocation = (*unwrap(obj)).payload;
+    let id = geolocation.watch();
+    report_position(cx, geolocation, argc, JS_ARGV(cx, vp));
+    JS_SET_RVAL(cx, vp, RUST_INT_TO_JSVAL(id as libc::c_int));
+    return 1;
+}
+
+extern fn clearWatch(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let argv = JS_ARGV(cx, vp);
+    if argc > 0 && RUST_JSVAL_IS_INT(*argv) == 1 {
+        (*unwrap(obj)).payload.clear_watch(RUST_JSVAL_TO_INT(*argv) as uint);
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+unsafe fn unwrap(obj: *JSObject) -> *rust_box<@Geolocation> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+extern fn finalize(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("geolocation finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @@Geolocation = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+pub fn init(compartment: &bare_compartment, win: @Window) {
+    let _ = utils::define_empty_prototype(~"Navigator", None, compartment);
+    compartment.register_class(utils::instance_jsclass(~"NavigatorInstance", null()));
+    let navigator: jsobj = result::unwrap(
+        compartment.new_object_with_proto(~"NavigatorInstance", ~"Navigator",
+                                          compartment.global_obj.ptr));
+
+    let proto = utils::define_empty_prototype(~"Geolocation", None, compartment);
+    let methods = ~[{name: compartment.add_name(~"getCurrentPosition"),
+                     call: {op: getCurrentPosition, info: null()},
+                     nargs: 3,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"watchPosition"),
+                     call: {op: watchPosition, info: null()},
+                     nargs: 3,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"clearWatch"),
+                     call: {op: clearWatch, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, proto.ptr, fns);
+    });
+
+    compartment.register_class(utils::instance_jsclass(~"GeolocationInstance", finalize));
+    let geolocation: jsobj = result::unwrap(
+        compartment.new_object_with_proto(~"GeolocationInstance", ~"Geolocation",
+                                          compartment.global_obj.ptr));
+
+    unsafe {
+        let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(@win.geolocation));
+        JS_SetReservedSlot(geolocation.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+
+        define_value(compartment.cx.ptr, navigator.ptr, "geolocation",
+                     RUST_OBJECT_TO_JSVAL(geolocation.ptr));
+        define_value(compartment.cx.ptr, navigator.ptr, "serviceWorker",
+                     bindings::service_worker::new_container(compartment));
+    }
+
+    compartment.define_property(~"navigator", RUST_OBJECT_TO_JSVAL(navigator.ptr),
+                                GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                                GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                                JSPROP_ENUMERATE);
+}
@@ -1,4 +1,4 @@
-use js::rust::{bare_compartment, methods, jsobj};
+use js::rust::{bare_compartment, methods};
 use js::{JS_ARGV, JSCLASS_HAS_RESERVED_SLOTS, JSPROP_ENUMERATE, JSPROP_SHARED, JSVAL_NULL,
             JS_THIS_OBJECT, JS_SET_RVAL, JSPROP_NATIVE_ACCESSORS};
 use js::jsapi::{JSContext, JSVal, JSObject, JSBool, jsid, JSClass, JSFreeOp, JSPropertySpec};
@@ -9,6 +9,7 @@ use js::jsval::{INT_TO_JSVAL, JSVAL_TO_PRIVATE};
 use js::jsapi::bindgen::*;
 use js::glue::bindgen::*;
 
+use content::content_task::task_from_context;
 use dom::node::{Node, NodeScope, Text, Doctype, Comment, Element};
 use utils::{rust_box, squirrel_away_unique, get_compartment, domstring_to_jsval, str};
 use libc::c_uint;
@@ -39,28 +40,70 @@ pub fn init(compartment: &bare_compartment) {
     vec::as_imm_buf(*attrs, |specs, _len| {
         JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs);
     });
+
+    bindings::event_target::init(compartment, obj.ptr);
+
+    let obj = utils::define_empty_prototype(~"Text", Some(~"Node"), compartment);
+    let attrs = @~[
+        {name: compartment.add_name(~"data"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getData, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs);
+    });
+
+    compartment.register_class(utils::instance_jsclass(~"TextInstance", finalize));
+}
+
+extern fn finalize(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("text finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _node: ~NodeBundle = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
 }
 
+/// The wrapper of `node`, made the first time it's asked for.
 #[allow(non_implicitly_copyable_typarams)]
-pub fn create(cx: *JSContext, node: Node, scope: NodeScope) -> jsobj unsafe {
-    do scope.write(&node) |nd| {
+pub fn create(cx: *JSContext, node: Node, scope: NodeScope) -> *JSObject unsafe {
+    match (*task_from_context(cx)).node_wrappers.find(node) {
+        Some(obj) => return obj,
+        None => ()
+    }
+    // element::create looks at the node itself, so it's called once we're done
+    let is_text = do scope.read(&node) |nd| {
         match nd.kind {
-            ~Element(*) => {
-              element::create(cx, node, scope)
-            }
-            ~Text(*) => {
-              fail ~"no text node bindings yet";
-            }
-            ~Comment(*) => {
-              fail ~"no comment node bindings yet";
-            }
-            ~Doctype(*) => {
-              fail ~"no doctype node bindings yet";
-            }
+            ~Element(*) => false,
+            ~Text(*) => true,
+            ~Comment(*) => fail ~"no comment node bindings yet",
+            ~Doctype(*) => fail ~"no doctype node bindings yet"
         }
+    };
+    if is_text {
+        create_text(cx, node, scope)
+    } else {
+        element::create(cx, node, scope)
     }
 }
 
+#[allow(non_implicitly_copyable_typarams)]
+fn create_text(cx: *JSContext, node: Node, scope: NodeScope) -> *JSObject unsafe {
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"TextInstance", ~"Text",
+                                          compartment.global_obj.ptr));
+
+    let raw_ptr: *libc::c_void =
+        cast::reinterpret_cast(&squirrel_away_unique(~NodeBundle(node, scope)));
+    JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+    // Rooted until the content task lets go of its nodes, as elements are
+    (*task_from_context(cx)).node_wrappers.insert(node, obj.ptr);
+    return obj.ptr;
+}
+
 struct NodeBundle {
     node: Node,
     scope: NodeScope,
@@ -90,7 +133,7 @@ extern fn getFirstChild(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool
         do (*bundle).payload.scope.write(&(*bundle).payload.node) |nd| {
             match nd.tree.first_child {
               Some(n) => {
-                let obj = create(cx, n, (*bundle).payload.scope).ptr;
+                let obj = create(cx, n, (*bundle).payload.scope);
                 *vp = RUST_OBJECT_TO_JSVAL(obj);
               }
               None => {
@@ -114,7 +157,7 @@ extern fn getNextSibling(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBoo
         do (*bundle).payload.scope.write(&(*bundle).payload.node) |nd| {
             match nd.tree.next_sibling {
               Some(n) => {
-                let obj = create(cx, n, (*bundle).payload.scope).ptr;
+                let obj = create(cx, n, (*bundle).payload.scope);
                 *vp = RUST_OBJECT_TO_JSVAL(obj);
               }
               None => {
@@ -139,6 +182,26 @@ impl NodeBundle {
     }
 }
 
+#[allow(non_implicitly_copyable_typarams)]
+extern fn getData(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool {
+    unsafe {
+        let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+        if obj.is_null() {
+            return 0;
+        }
+
+        let bundle = unwrap(obj);
+        let data = do (*bundle).payload.scope.read(&(*bundle).payload.node) |nd| {
+            match nd.kind {
+                ~Text(ref data) => copy *data,
+                _ => fail ~"why is this not a text node?"
+            }
+        };
+        *vp = domstring_to_jsval(cx, &str(move data));
+    }
+    return 1;
+}
+
 extern fn getNodeType(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool {
     unsafe {
         let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
@@ -0,0 +1,293 @@
+use js::rust::{bare_compartment, methods, jsobj};
+use js::{JS_ARGV, JSPROP_ENUMERATE, JSPROP_SHARED, JSPROP_READONLY, JSVAL_NULL, JSVAL_VOID,
+            JS_THIS_OBJECT, JS_SET_RVAL, JSPROP_NATIVE_ACCESSORS, JSTYPE_FUNCTION};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp};
+use js::jsapi::bindgen::{JS_GetReservedSlot, JS_SetReservedSlot, JS_DefineFunctions,
+                            JS_DefineProperty, JS_DefineProperties, JS_GetProperty,
+                            JS_GetArrayLength, JS_GetElement, JS_ValueToECMAUint32,
+                            JS_ValueToBoolean, JS_TypeOfValue, JS_CallFunctionValue,
+                            JS_ReportError};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use ptr::null;
+use libc::c_uint;
+use utils::{domstring_to_jsval, rust_box, squirrel_away, jsval_to_str, str, null_string,
+            get_compartment};
+use content::content_task::task_from_context;
+use dom::bindings::promise::resolved_promise;
+use dom::notification::{Notification, NotificationOptions, PermissionGranted};
+use dom::window::Window;
+
+unsafe fn window_from_context(cx: *JSContext) -> @Window {
+    (*task_from_context(cx)).window.expect(~"notifications need a window")
+}
+
+unsafe fn post_event(cx: *JSContext, win: @Window, target: JSVal, kind: ~str) {
+    let event = RUST_OBJECT_TO_JSVAL(utils::new_event(cx, kind, target));
+    win.post_event(target, move kind, event);
+}
+
+// Returns None if the property is missing or undefined
+unsafe fn get_property(cx: *JSContext, obj: *JSObject, name: &str) -> Option<JSVal> {
+    let val = JSVAL_VOID;
+    let found = do str::as_c_str(name) |s| {
+        JS_GetProperty(cx, obj, s, ptr::to_unsafe_ptr(&val))
+    };
+    if found == 0 || RUST_JSVAL_IS_VOID(val) == 1 {
+        None
+    } else {
+        Some(val)
+    }
+}
+
+unsafe fn get_str_property(cx: *JSContext, obj: *JSObject, name: &str) -> Option<~str> {
+    match get_property(cx, obj, name) {
+        Some(val) => match jsval_to_str(cx, val) {
+            Ok(move s) => Some(move s),
+            Err(()) => None
+        },
+        None => None
+    }
+}
+
+unsafe fn get_vibrate_pattern(cx: *JSContext, val: JSVal) -> ~[uint] {
+    // A single number is a pattern of one vibration
+    if RUST_JSVAL_IS_OBJECT(val) == 0 {
+        let n = 0u32;
+        JS_ValueToECMAUint32(cx, val, ptr::to_unsafe_ptr(&n));
+        return ~[n as uint];
+    }
+
+    let array = RUST_JSVAL_TO_OBJECT(val);
+    let len = 0u32;
+    if JS_GetArrayLength(cx, array, ptr::to_unsafe_ptr(&len)) == 0 {
+        return ~[];
+    }
+    let mut pattern = ~[];
+    for uint::range(0, len as uint) |i| {
+        let elem = JSVAL_VOID;
+        let n = 0u32;
+        JS_GetElement(cx, array, i as u32, ptr::to_unsafe_ptr(&elem));
+        JS_ValueToECMAUint32(cx, elem, ptr::to_unsafe_ptr(&n));
+        pattern.push(n as uint);
+    }
+    move pattern
+}
+
+unsafe fn get_options(cx: *JSContext, obj: *JSObject) -> NotificationOptions {
+    let mut options = NotificationOptions();
+    if obj.is_null() {
+        return move options;
+    }
+
+    options.body = get_str_property(cx, obj, "body").get_default(~"");
+    options.icon = get_str_property(cx, obj, "icon");
+    options.tag = get_str_property(cx, obj, "tag").get_default(~"");
+    options.badge = get_str_property(cx, obj, "badge");
+    match get_property(cx, obj, "vibrate") {
+        Some(val) => options.vibrate = get_vibrate_pattern(cx, val),
+        None => ()
+    }
+    match get_property(cx, obj, "silent") {
+        Some(val) => {
+            let silent = 0;
+            JS_ValueToBoolean(cx, val, ptr::to_unsafe_ptr(&silent));
+            options.silent = silent == 1;
+        }
+        None => ()
+    }
+    move options
+}
+
+unsafe fn define_readonly(cx: *JSContext, obj: *JSObject, name: &str, val: JSVal) {
+    do str::as_c_str(name) |s| {
+        JS_DefineProperty(cx, obj, s, val,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE | JSPROP_READONLY);
+    }
+}
+
+unsafe fn optional_string(cx: *JSContext, s: &Option<~str>) -> JSVal {
+    match *s {
+        Some(ref s) => domstring_to_jsval(cx, &str(copy *s)),
+        None => domstring_to_jsval(cx, &null_string)
+    }
+}
+
+extern fn constructor(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+    if argc < 1 {
+        do str::as_c_str(~"Notification constructor needs a title") |s| {
+            JS_ReportError(cx, s);
+        }
+        return 0;
+    }
+
+    let title = match jsval_to_str(cx, *ptr::offset(argv, 0)) {
+        Ok(move s) => move s,
+        Err(()) => return 0
+    };
+    let options_obj = if argc > 1 && RUST_JSVAL_IS_OBJECT(*ptr::offset(argv, 1)) == 1 {
+        RUST_JSVAL_TO_OBJECT(*ptr::offset(argv, 1))
+    } else {
+        null()
+    };
+    let notification = @Notification(cx, move title, get_options(cx, options_obj));
+
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"NotificationInstance", ~"Notification",
+                                          compartment.global_obj.ptr));
+
+    define_readonly(cx, obj.ptr, "title", domstring_to_jsval(cx, &str(copy notification.title)));
+    define_readonly(cx, obj.ptr, "body",
+                    domstring_to_jsval(cx, &str(copy notification.options.body)));
+    define_readonly(cx, obj.ptr, "icon", optional_string(cx, &notification.options.icon));
+    define_readonly(cx, obj.ptr, "tag", domstring_to_jsval(cx, &str(copy notification.options.tag)));
+    define_readonly(cx, obj.ptr, "badge", optional_string(cx, &notification.options.badge));
+    define_readonly(cx, obj.ptr, "silent", RUST_BOOLEAN_TO_JSVAL(notification.options.silent as JSBool));
+    let data = if options_obj.is_null() {
+        JSVAL_NULL
+    } else {
+        get_property(cx, options_obj, "data").get_default(JSVAL_NULL)
+    };
+    define_readonly(cx, obj.ptr, "data", data);
+
+    let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(@notification));
+    JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+
+    let win = window_from_context(cx);
+    let val = RUST_OBJECT_TO_JSVAL(obj.ptr);
+    if win.notification_permission == PermissionGranted {
+        notification.show(val);
+        win.notifications.push(notification);
+        post_event(cx, win, val, ~"show");
+    } else {
+        post_event(cx, win, val, ~"error");
+    }
+
+    JS_SET_RVAL(cx, vp, val);
+    return 1;
+}
+
+extern fn close(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let notification = (*unwrap(obj)).payload;
+    if notification.close() {
+        let win = window_from_context(cx);
+        do win.notifications.swap |notifications| {
+            vec::filter(notifications, |n| !core::box::ptr_eq(*n, notification))
+        }
+        post_event(cx, win, RUST_OBJECT_TO_JSVAL(obj), ~"close");
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+// What the platform does when the user clicks a notification
+extern fn debugClick(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    if (*unwrap(obj)).payload.click() {
+        post_event(cx, window_from_context(cx), RUST_OBJECT_TO_JSVAL(obj), ~"click");
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    return 1;
+}
+
+// Returns a promise for the permission, which is also passed to the
+// (deprecated) callback argument
+extern fn requestPermission(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+    let permission = window_from_context(cx).request_notification_permission();
+    let result = domstring_to_jsval(cx, &str(permission.to_str()));
+
+    if argc > 0 && JS_TypeOfValue(cx, *argv) == JSTYPE_FUNCTION {
+        let rval = JSVAL_NULL;
+        let compartment = get_compartment(cx);
+        JS_CallFunctionValue(cx, compartment.global_obj.ptr, *argv,
+                             1, ptr::to_unsafe_ptr(&result), ptr::to_unsafe_ptr(&rval));
+    }
+
+    JS_SET_RVAL(cx, vp, resolved_promise(cx, result));
+    return 1;
+}
+
+extern fn getPermission(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let permission = window_from_context(cx).notification_permission;
+    *vp = domstring_to_jsval(cx, &str(permission.to_str()));
+    return 1;
+}
+
+unsafe fn unwrap(obj: *JSObject) -> *rust_box<@Notification> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+extern fn finalize(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("notification finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @@Notification = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+pub fn init(compartment: &bare_compartment) {
+    let obj = utils::define_constructor(~"Notification", None, constructor, compartment);
+
+    //TODO: requestPermission and permission should only be on the constructor,
+    //      but it's the same object as the prototype here.
+    let methods = ~[{name: compartment.add_name(~"close"),
+                     call: {op: close, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"requestPermission"),
+                     call: {op: requestPermission, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj.ptr, fns);
+    });
+
+    let attrs = @~[
+        {name: compartment.add_name(~"permission"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getPermission, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        assert JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs) == 1;
+    });
+
+    compartment.register_class(utils::instance_jsclass(~"NotificationInstance", finalize));
+}
+
+/**
+Defines `notification.debugClick()`, which isn't standard, for clicking a
+notification when the console is all that shows it. Only with
+--enable-notification-debug, after the other bindings are defined.
+*/
+pub fn define_notification_debug(compartment: &bare_compartment) unsafe {
+    let cx = compartment.cx.ptr;
+    let proto = JSVAL_NULL;
+    do str::as_c_str(~"Notification") |s| {
+        JS_GetProperty(cx, compartment.global_obj.ptr, s, ptr::to_unsafe_ptr(&proto));
+    }
+    let methods = ~[{name: compartment.add_name(~"debugClick"),
+                     call: {op: debugClick, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(cx, RUST_JSVAL_TO_OBJECT(proto), fns);
+    });
+}
@@ -0,0 +1,441 @@
+/*!
+`performance`, its entries and `PerformanceObserver`. Entries are made into
+JS objects as they're handed out, so a script only ever sees copies.
+*/
+
+use js::rust::{bare_compartment, methods, jsobj};
+use js::{JS_ARGV, JSPROP_ENUMERATE, JSPROP_READONLY, JSVAL_NULL, JSVAL_VOID, JS_THIS_OBJECT,
+            JS_SET_RVAL, JSTYPE_FUNCTION};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp};
+use js::jsapi::bindgen::{JS_GetReservedSlot, JS_SetReservedSlot, JS_DefineFunctions,
+                            JS_DefineProperty, JS_GetProperty, JS_NewObject, JS_NewArrayObject,
+                            JS_TypeOfValue, JS_ReportError, JS_NewNumberValue, JS_IsArrayObject,
+                            JS_GetArrayLength, JS_GetElement};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use ptr::null;
+use libc::c_uint;
+use utils::{rust_box, squirrel_away, jsval_to_str, domstring_to_jsval, str, get_compartment,
+            string_arg};
+use content::content_task::task_from_context;
+use dom::performance_observer::{Performance, PerformanceObserver, PerformanceEntry, EntryType,
+                                MarkEntry, MeasureEntry, ResourceEntry, PaintEntry,
+                                LargestContentfulPaintEntry};
+use dom::bindings::node;
+use dom::window::Window;
+
+unsafe fn unwrap(obj: *JSObject) -> *rust_box<@PerformanceObserver> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+unsafe fn report_error(cx: *JSContext, msg: &str) -> JSBool {
+    do str::as_c_str(msg) |s| {
+        JS_ReportError(cx, s);
+    }
+    0
+}
+
+unsafe fn window(cx: *JSContext) -> @Window {
+    (*task_from_context(cx)).window.expect(~"performance needs a window")
+}
+
+unsafe fn define_value(cx: *JSContext, obj: *JSObject, name: &str, val: JSVal) {
+    do str::as_c_str(name) |s| {
+        JS_DefineProperty(cx, obj, s, val,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE | JSPROP_READONLY);
+    }
+}
+
+unsafe fn number(cx: *JSContext, n: float) -> JSVal {
+    let val = JSVAL_NULL;
+    JS_NewNumberValue(cx, n as libc::c_double, ptr::to_unsafe_ptr(&val));
+    val
+}
+
+unsafe fn new_array(cx: *JSContext, values: &[JSVal]) -> JSVal {
+    do vec::as_imm_buf(values) |buf, len| {
+        RUST_OBJECT_TO_JSVAL(JS_NewArrayObject(cx, len as libc::c_int, buf))
+    }
+}
+
+/// A PerformanceMark, PerformanceMeasure, PerformanceResourceTiming,
+/// PerformancePaintTiming, LargestContentfulPaint or plain PerformanceEntry
+/// for `entry`.
+pub unsafe fn entry_to_jsval(cx: *JSContext, entry: &PerformanceEntry) -> JSVal {
+    let compartment = get_compartment(cx);
+    let (instance, proto) = match entry.entry_type {
+        MarkEntry => (~"PerformanceMarkInstance", ~"PerformanceMark"),
+        MeasureEntry => (~"PerformanceMeasureInstance", ~"PerformanceMeasure"),
+        ResourceEntry => (~"PerformanceResourceTimingInstance", ~"PerformanceResourceTiming"),
+        PaintEntry => (~"PerformancePaintTimingInstance", ~"PerformancePaintTiming"),
+        LargestContentfulPaintEntry => (~"LargestContentfulPaintInstance",
+                                        ~"LargestContentfulPaint"),
+        _ => (~"PerformanceEntryInstance", ~"PerformanceEntry")
+    };
+    let obj = result::unwrap(compartment.new_object_with_proto(move instance, move proto,
+                                                               compartment.global_obj.ptr));
+    define_value(cx, obj.ptr, "name", domstring_to_jsval(cx, &str(copy entry.name)));
+    define_value(cx, obj.ptr, "entryType",
+                 domstring_to_jsval(cx, &str(entry.entry_type.to_str())));
+    define_value(cx, obj.ptr, "startTime", number(cx, entry.start_time));
+    define_value(cx, obj.ptr, "duration", number(cx, entry.duration));
+    for entry.resource.each |resource| {
+        define_value(cx, obj.ptr, "initiatorType",
+                     domstring_to_jsval(cx, &str(copy resource.initiator_type)));
+        let times = [("redirectStart", resource.redirect_start),
+                     ("redirectEnd", resource.redirect_end),
+                     ("fetchStart", resource.fetch_start),
+                     ("domainLookupStart", resource.domain_lookup_start),
+                     ("domainLookupEnd", resource.domain_lookup_end),
+                     ("connectStart", resource.connect_start),
+                     ("connectEnd", resource.connect_end),
+                     ("secureConnectionStart", resource.secure_connection_start),
+                     ("requestStart", resource.request_start),
+                     ("responseStart", resource.response_start),
+                     ("responseEnd", resource.response_end)];
+        for times.each |time| {
+            let (name, value) = *time;
+            define_value(cx, obj.ptr, name, number(cx, value));
+        }
+        let sizes = [("transferSize", resource.transfer_size),
+                     ("encodedBodySize", resource.encoded_body_size),
+                     ("decodedBodySize", resource.decoded_body_size)];
+        for sizes.each |size| {
+            let (name, value) = *size;
+            define_value(cx, obj.ptr, name, RUST_INT_TO_JSVAL(value as libc::c_int));
+        }
+    }
+    for entry.largest.each |largest| {
+        let scope = (*task_from_context(cx)).scope;
+        define_value(cx, obj.ptr, "renderTime", number(cx, entry.start_time));
+        define_value(cx, obj.ptr, "size", RUST_INT_TO_JSVAL(largest.size as libc::c_int));
+        define_value(cx, obj.ptr, "element",
+                     RUST_OBJECT_TO_JSVAL(node::create(cx, largest.element, scope)));
+    }
+    RUST_OBJECT_TO_JSVAL(obj.ptr)
+}
+
+unsafe fn entries_to_jsval(cx: *JSContext, entries: &[PerformanceEntry]) -> JSVal {
+    let vals = do entries.map |entry| { entry_to_jsval(cx, entry) };
+    new_array(cx, vals)
+}
+
+// The entry type named by argument `i`, or Err if it names none
+unsafe fn entry_type_arg(cx: *JSContext, argc: c_uint, vp: *JSVal,
+                         i: uint) -> Result<Option<EntryType>, ()> {
+    match string_arg(cx, argc, vp, i) {
+        Some(move name) => match EntryType::from_str(name) {
+            Some(entry_type) => Ok(Some(entry_type)),
+            None => Err(())
+        },
+        None => Ok(None)
+    }
+}
+
+extern fn now(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    JS_SET_RVAL(cx, vp, number(cx, window(cx).performance.now()));
+    1
+}
+
+extern fn mark(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let name = match string_arg(cx, argc, vp, 0) {
+        Some(move name) => move name,
+        None => return report_error(cx, "performance.mark needs a name")
+    };
+    let win = window(cx);
+    let entry = PerformanceEntry(move name, MarkEntry, win.performance.now(), 0.0);
+    JS_SET_RVAL(cx, vp, entry_to_jsval(cx, &entry));
+    win.record_performance_entry(move entry);
+    1
+}
+
+extern fn measure(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let name = match string_arg(cx, argc, vp, 0) {
+        Some(move name) => move name,
+        None => return report_error(cx, "performance.measure needs a name")
+    };
+    let win = window(cx);
+    match win.performance.measure(move name, string_arg(cx, argc, vp, 1),
+                                  string_arg(cx, argc, vp, 2)) {
+        Ok(move entry) => {
+            JS_SET_RVAL(cx, vp, entry_to_jsval(cx, &entry));
+            win.record_performance_entry(move entry);
+            1
+        }
+        Err(move mark) => report_error(cx, fmt!("there's no mark named '%s'", mark))
+    }
+}
+
+extern fn getEntries(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    JS_SET_RVAL(cx, vp, entries_to_jsval(cx, window(cx).performance.get_entries(None, None)));
+    1
+}
+
+extern fn getEntriesByType(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let entries = match entry_type_arg(cx, argc, vp, 0) {
+        Ok(Some(entry_type)) => window(cx).performance.get_entries(Some(entry_type), None),
+        _ => ~[]
+    };
+    JS_SET_RVAL(cx, vp, entries_to_jsval(cx, entries));
+    1
+}
+
+extern fn getEntriesByName(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let name = string_arg(cx, argc, vp, 0);
+    let entries = match entry_type_arg(cx, argc, vp, 1) {
+        Ok(entry_type) if name.is_some() => {
+            window(cx).performance.get_entries(entry_type, move name)
+        }
+        _ => ~[]
+    };
+    JS_SET_RVAL(cx, vp, entries_to_jsval(cx, entries));
+    1
+}
+
+extern fn clearMarks(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    window(cx).performance.clear(MarkEntry, string_arg(cx, argc, vp, 0));
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    1
+}
+
+extern fn clearMeasures(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    window(cx).performance.clear(MeasureEntry, string_arg(cx, argc, vp, 0));
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    1
+}
+
+// The entryTypes of observe's options. Types this doesn't know are left
+// out, as the spec asks.
+unsafe fn entry_types_option(cx: *JSContext, argc: c_uint, vp: *JSVal) -> Option<~[EntryType]> {
+    if argc < 1 || RUST_JSVAL_IS_OBJECT(*JS_ARGV(cx, vp)) == 0 ||
+       RUST_JSVAL_IS_NULL(*JS_ARGV(cx, vp)) == 1 {
+        return None;
+    }
+    let options = RUST_JSVAL_TO_OBJECT(*JS_ARGV(cx, vp));
+    let val = JSVAL_VOID;
+    do str::as_c_str(~"entryTypes") |s| {
+        JS_GetProperty(cx, options, s, ptr::to_unsafe_ptr(&val));
+    }
+    if RUST_JSVAL_IS_OBJECT(val) == 0 || RUST_JSVAL_IS_NULL(val) == 1 ||
+       JS_IsArrayObject(cx, RUST_JSVAL_TO_OBJECT(val)) == 0 {
+        return None;
+    }
+    let array = RUST_JSVAL_TO_OBJECT(val);
+    let len = 0u32;
+    JS_GetArrayLength(cx, array, ptr::to_unsafe_ptr(&len));
+    let mut entry_types = ~[];
+    for uint::range(0, len as uint) |i| {
+        let elem = JSVAL_VOID;
+        JS_GetElement(cx, array, i as u32, ptr::to_unsafe_ptr(&elem));
+        match jsval_to_str(cx, elem).ok().chain(|name| EntryType::from_str(name)) {
+            Some(entry_type) => entry_types.push(entry_type),
+            None => ()
+        }
+    }
+    Some(move entry_types)
+}
+
+extern fn observe(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    match entry_types_option(cx, argc, vp) {
+        Some(move entry_types) => (*unwrap(obj)).payload.observe(move entry_types),
+        None => return report_error(cx, "PerformanceObserver.observe needs an entryTypes array")
+    }
+    window(cx).performance.update_observer((*unwrap(obj)).payload);
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    1
+}
+
+extern fn disconnect(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    (*unwrap(obj)).payload.disconnect();
+    window(cx).performance.update_observer((*unwrap(obj)).payload);
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    1
+}
+
+extern fn takeRecords(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    JS_SET_RVAL(cx, vp, entries_to_jsval(cx, (*unwrap(obj)).payload.take_records()));
+    1
+}
+
+extern fn constructor(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    if argc < 1 || JS_TypeOfValue(cx, *JS_ARGV(cx, vp)) != JSTYPE_FUNCTION {
+        return report_error(cx, "PerformanceObserver needs a callback");
+    }
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"PerformanceObserverInstance", ~"PerformanceObserver",
+                                          compartment.global_obj.ptr));
+    let observer = @PerformanceObserver(cx, *JS_ARGV(cx, vp), RUST_OBJECT_TO_JSVAL(obj.ptr));
+
+    let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(@observer));
+    JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(obj.ptr));
+    1
+}
+
+extern fn finalize(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("performance observer finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @@PerformanceObserver = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+// The entries of the PerformanceObserverEntryList `this`, kept in its
+// `__entries` property
+unsafe fn list_entries(cx: *JSContext, vp: *JSVal) -> ~[JSVal] {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    let entries = JSVAL_VOID;
+    if obj.is_null() {
+        return ~[];
+    }
+    do str::as_c_str(~"__entries") |s| {
+        JS_GetProperty(cx, obj, s, ptr::to_unsafe_ptr(&entries));
+    }
+    if RUST_JSVAL_IS_OBJECT(entries) == 0 || RUST_JSVAL_IS_NULL(entries) == 1 {
+        return ~[];
+    }
+    let array = RUST_JSVAL_TO_OBJECT(entries);
+    let len = 0u32;
+    JS_GetArrayLength(cx, array, ptr::to_unsafe_ptr(&len));
+    do vec::from_fn(len as uint) |i| {
+        let elem = JSVAL_VOID;
+        JS_GetElement(cx, array, i as u32, ptr::to_unsafe_ptr(&elem));
+        elem
+    }
+}
+
+// The string property `name` of the entry `entry`
+unsafe fn entry_field(cx: *JSContext, entry: JSVal, name: &str) -> Option<~str> {
+    let val = JSVAL_VOID;
+    do str::as_c_str(name) |s| {
+        JS_GetProperty(cx, RUST_JSVAL_TO_OBJECT(entry), s, ptr::to_unsafe_ptr(&val));
+    }
+    jsval_to_str(cx, val).ok()
+}
+
+extern fn list_getEntries(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    JS_SET_RVAL(cx, vp, new_array(cx, list_entries(cx, vp)));
+    1
+}
+
+extern fn list_getEntriesByType(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let entry_type = string_arg(cx, argc, vp, 0);
+    let entries = do list_entries(cx, vp).filter |entry| {
+        entry_type.is_some() && entry_field(cx, *entry, "entryType") == entry_type
+    };
+    JS_SET_RVAL(cx, vp, new_array(cx, entries));
+    1
+}
+
+extern fn list_getEntriesByName(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let name = string_arg(cx, argc, vp, 0);
+    let entry_type = string_arg(cx, argc, vp, 1);
+    let entries = do list_entries(cx, vp).filter |entry| {
+        name.is_some() && entry_field(cx, *entry, "name") == name &&
+            (entry_type.is_none() || entry_field(cx, *entry, "entryType") == entry_type)
+    };
+    JS_SET_RVAL(cx, vp, new_array(cx, entries));
+    1
+}
+
+/// The PerformanceObserverEntryList passed to an observer's callback.
+pub fn entry_list_to_jsval(cx: *JSContext, entries: &[PerformanceEntry]) -> JSVal unsafe {
+    let compartment = get_compartment(cx);
+    let list = result::unwrap(
+        compartment.new_object_with_proto(~"PerformanceObserverEntryListInstance",
+                                          ~"PerformanceObserverEntryList",
+                                          compartment.global_obj.ptr));
+    do str::as_c_str(~"__entries") |s| {
+        JS_DefineProperty(cx, list.ptr, s, entries_to_jsval(cx, entries),
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_READONLY);
+    }
+    RUST_OBJECT_TO_JSVAL(list.ptr)
+}
+
+fn define_methods(compartment: &bare_compartment, obj: *JSObject,
+                  methods: &[(~str, *u8, u16)]) {
+    let specs = do methods.map |method| {
+        let (ref name, op, nargs) = *method;
+        {name: compartment.add_name(copy *name),
+         call: {op: op, info: null()},
+         nargs: nargs,
+         flags: 0,
+         selfHostedName: null()}
+    };
+    vec::as_imm_buf(specs, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj, fns);
+    });
+}
+
+pub fn init(compartment: &bare_compartment, win: @Window) {
+    let cx = compartment.cx.ptr;
+
+    // The entry types, and the classes of their objects
+    utils::define_empty_prototype(~"PerformanceEntry", None, compartment);
+    for [~"PerformanceMark", ~"PerformanceMeasure", ~"PerformanceResourceTiming",
+         ~"PerformancePaintTiming", ~"LargestContentfulPaint"].each |name| {
+        utils::define_empty_prototype(copy *name, Some(~"PerformanceEntry"), compartment);
+    }
+    for [~"PerformanceEntry", ~"PerformanceMark", ~"PerformanceMeasure",
+         ~"PerformanceResourceTiming", ~"PerformancePaintTiming", ~"LargestContentfulPaint",
+         ~"PerformanceObserverEntryList"].each |name| {
+        compartment.register_class(utils::instance_jsclass(*name + ~"Instance", null()));
+    }
+
+    let list = utils::define_empty_prototype(~"PerformanceObserverEntryList", None, compartment);
+    define_methods(compartment, list.ptr,
+                   ~[(~"getEntries", list_getEntries as *u8, 0),
+                     (~"getEntriesByType", list_getEntriesByType as *u8, 1),
+                     (~"getEntriesByName", list_getEntriesByName as *u8, 2)]);
+
+    let observer = utils::define_constructor(~"PerformanceObserver", None, constructor,
+                                             compartment);
+    define_methods(compartment, observer.ptr,
+                   ~[(~"observe", observe as *u8, 1),
+                     (~"disconnect", disconnect as *u8, 0),
+                     (~"takeRecords", takeRecords as *u8, 0)]);
+    compartment.register_class(utils::instance_jsclass(~"PerformanceObserverInstance",
+                                                       finalize));
+
+    let performance = JS_NewObject(cx, null(), null(), compartment.global_obj.ptr);
+    define_methods(compartment, performance,
+                   ~[(~"now", now as *u8, 0),
+                     (~"mark", mark as *u8, 1),
+                     (~"measure", measure as *u8, 3),
+                     (~"getEntries", getEntries as *u8, 0),
+                     (~"getEntriesByType", getEntriesByType as *u8, 1),
+                     (~"getEntriesByName", getEntriesByName as *u8, 2),
+                     (~"clearMarks", clearMarks as *u8, 1),
+                     (~"clearMeasures", clearMeasures as *u8, 1)]);
+    unsafe {
+        // The time origin, as ms since the epoch
+        let origin = std::time::get_time();
+        let origin_ms = origin.sec as float * 1000.0 + origin.nsec as float / 1000000.0 -
+            win.performance.now();
+        define_value(cx, performance, "timeOrigin", number(cx, origin_ms));
+    }
+
+    compartment.define_property(~"performance", RUST_OBJECT_TO_JSVAL(performance),
+                                GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                                GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                                JSPROP_ENUMERATE);
+}
@@ -0,0 +1,117 @@
+use js::{JSPROP_ENUMERATE, JSPROP_READONLY, JSVAL_NULL, JS_THIS_OBJECT, JS_SET_RVAL};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool};
+use js::jsapi::bindgen::{JS_DefineProperty, JS_DefineFunction, JS_GetProperty,
+                            JS_NewArrayObject, JS_NewNumberValue};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use libc::c_uint;
+
+use dom::events::pointer_event::{PointerInput, PointerSample};
+use dom::node::{Node, NodeScope};
+use dom::window::Window;
+use utils::{domstring_to_jsval, new_event, str};
+
+unsafe fn define_value(cx: *JSContext, obj: *JSObject, name: &str, val: JSVal) {
+    do str::as_c_str(name) |s| {
+        JS_DefineProperty(cx, obj, s, val,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE | JSPROP_READONLY);
+    }
+}
+
+unsafe fn define_int(cx: *JSContext, obj: *JSObject, name: &str, n: int) {
+    define_value(cx, obj, name, RUST_INT_TO_JSVAL(n as libc::c_int));
+}
+
+unsafe fn define_number(cx: *JSContext, obj: *JSObject, name: &str, n: f64) {
+    let val = JSVAL_NULL;
+    JS_NewNumberValue(cx, n as libc::c_double, ptr::to_unsafe_ptr(&val));
+    define_value(cx, obj, name, val);
+}
+
+/// A MouseEvent for `sample`. There's no screen position to be had, so
+/// `screenX` and `screenY` are the window's.
+pub unsafe fn new_mouse_event(cx: *JSContext, kind: &str, target: JSVal,
+                              sample: &PointerSample) -> *JSObject {
+    let event = new_event(cx, kind, target);
+    for [~"clientX", ~"screenX"].each |name| {
+        define_int(cx, event, *name, sample.point.x);
+    }
+    for [~"clientY", ~"screenY"].each |name| {
+        define_int(cx, event, *name, sample.point.y);
+    }
+    define_int(cx, event, "button", sample.button as int);
+    define_int(cx, event, "buttons", sample.buttons as int);
+    event
+}
+
+unsafe fn define_pointer_fields(cx: *JSContext, event: *JSObject, sample: &PointerSample) {
+    define_int(cx, event, "pointerId", sample.pointer_id as int);
+    define_number(cx, event, "width", sample.width);
+    define_number(cx, event, "height", sample.height);
+    define_number(cx, event, "pressure", sample.pressure);
+    define_number(cx, event, "tangentialPressure", sample.tangential_pressure);
+    define_int(cx, event, "tiltX", sample.tilt_x as int);
+    define_int(cx, event, "tiltY", sample.tilt_y as int);
+    define_int(cx, event, "twist", sample.twist as int);
+    define_value(cx, event, "pointerType",
+                 domstring_to_jsval(cx, &str(sample.pointer_type.to_str())));
+    define_value(cx, event, "isPrimary", RUST_BOOLEAN_TO_JSVAL(sample.is_primary as JSBool));
+}
+
+// Where getCoalescedEvents() finds its array
+const COALESCED_PROPERTY: &static/str = "__coalescedEvents";
+
+/**
+A PointerEvent: a MouseEvent with the pointer's id, contact geometry, pen
+angles and type. Moves that were coalesced answer `getCoalescedEvents()`
+with an event for each sample.
+*/
+pub unsafe fn new_pointer_event(cx: *JSContext, kind: &str, target: JSVal,
+                                input: &PointerInput) -> *JSObject {
+    let event = new_mouse_event(cx, kind, target, &input.sample);
+    define_pointer_fields(cx, event, &input.sample);
+
+    let coalesced = do input.coalesced.map |sample| {
+        let e = new_mouse_event(cx, kind, target, sample);
+        define_pointer_fields(cx, e, sample);
+        RUST_OBJECT_TO_JSVAL(e)
+    };
+    let array = do vec::as_imm_buf(coalesced) |buf, len| {
+        RUST_OBJECT_TO_JSVAL(JS_NewArrayObject(cx, len as libc::c_int, buf))
+    };
+    // Not enumerable, as it's only there for getCoalescedEvents()
+    do str::as_c_str(COALESCED_PROPERTY) |s| {
+        JS_DefineProperty(cx, event, s, array,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_READONLY);
+    }
+    do str::as_c_str("getCoalescedEvents") |s| {
+        JS_DefineFunction(cx, event, s, getCoalescedEvents, 0, 0);
+    }
+    event
+}
+
+extern fn getCoalescedEvents(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let array = JSVAL_NULL;
+    do str::as_c_str(COALESCED_PROPERTY) |s| {
+        JS_GetProperty(cx, obj, s, ptr::to_unsafe_ptr(&array));
+    }
+    JS_SET_RVAL(cx, vp, array);
+    return 1;
+}
+
+/// Queues `gotpointercapture` or `lostpointercapture` at `node`.
+pub unsafe fn post_capture_event(cx: *JSContext, win: @Window, scope: NodeScope, node: Node,
+                                 kind: &str, pointer_id: i32) {
+    let target = RUST_OBJECT_TO_JSVAL(node::create(cx, node, scope));
+    let event = new_event(cx, kind, target);
+    define_int(cx, event, "pointerId", pointer_id as int);
+    win.post_event(target, kind.to_str(), RUST_OBJECT_TO_JSVAL(event));
+}
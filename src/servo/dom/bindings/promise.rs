@@ -0,0 +1,259 @@
+/*!
+Promises for the DOM's async APIs. The `Promise` global, with `resolve`,
+`reject`, `all`, `allSettled`, `race` and `any`, is SpiderMonkey's own, as
+are async functions; what the engine needs is a way to make promises and
+settle them from Rust, and to run the jobs their reactions queue on the
+content task's microtask queue. An `await` resumes from one of those jobs.
+
+Work done off the content task settles its promise through
+`future_to_promise`, so that each API needn't wire that up for itself.
+
+The engine calls this needs came with SpiderMonkey 52, so they're only
+built with `--cfg promise_jobs` (`configure --enable-promise-jobs`).
+Without it no promise can be made from Rust, and nothing is ever a
+promise. The DOM's async APIs then give an already-resolved value as it
+is, and throw where they would have rejected or waited; pages never get
+null for a promise.
+*/
+
+use js::JS_SET_RVAL;
+use js::glue::bindgen::RUST_OBJECT_TO_JSVAL;
+#[cfg(promise_jobs)]
+use js::glue::bindgen::{RUST_JSVAL_IS_OBJECT, RUST_JSVAL_TO_OBJECT};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool};
+#[cfg(promise_jobs)]
+use js::jsapi::bindgen::{JS_NewPromiseObject, JS_ResolvePromise, JS_RejectPromise,
+                         JS_SetEnqueuePromiseJobCallback, JS_IsPromiseObject,
+                         JS_GetPromiseState, JS_GetPromiseResult,
+                         JS_SetPromiseRejectionTrackerCallback};
+#[cfg(not(promise_jobs))]
+use js::JSVAL_VOID;
+#[cfg(not(promise_jobs))]
+use js::jsapi::bindgen::JS_SetPendingException;
+#[cfg(not(promise_jobs))]
+use utils::throw_error;
+use libc::{c_int, c_void};
+use ptr::null;
+#[cfg(promise_jobs)]
+use std::future;
+use std::future::Future;
+
+use content::content_task::task_from_context;
+#[cfg(promise_jobs)]
+use content::content_task::SettlePromise;
+
+/// Where a promise has got to, as `JS::PromiseState` numbers them.
+pub enum PromiseState {
+    Pending = 0,
+    Fulfilled = 1,
+    Rejected = 2
+}
+
+impl PromiseState : cmp::Eq {
+    pure fn eq(&self, other: &PromiseState) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &PromiseState) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// The callback SpiderMonkey gives each promise job to, to be queued.
+pub type EnqueueJobCallback = extern fn(*JSContext, *JSObject, *JSObject, *JSObject,
+                                        *c_void) -> JSBool;
+
+/// The callback SpiderMonkey tells when a promise is rejected with no
+/// handler, or gets one after that.
+pub type RejectionTrackerCallback = extern fn(*JSContext, *JSObject, c_int, *c_void);
+
+/// Whether `val` is a promise: a Promise object itself, not just any
+/// thenable.
+#[cfg(promise_jobs)]
+pub unsafe fn is_promise(_cx: *JSContext, val: JSVal) -> bool {
+    RUST_JSVAL_IS_OBJECT(val) == 1 && JS_IsPromiseObject(RUST_JSVAL_TO_OBJECT(val)) == 1
+}
+
+#[cfg(not(promise_jobs))]
+pub unsafe fn is_promise(_cx: *JSContext, _val: JSVal) -> bool {
+    false
+}
+
+#[cfg(promise_jobs)]
+pub unsafe fn promise_state(_cx: *JSContext, promise: *JSObject) -> PromiseState {
+    match JS_GetPromiseState(promise) as c_int {
+        1 => Fulfilled,
+        2 => Rejected,
+        _ => Pending
+    }
+}
+
+#[cfg(not(promise_jobs))]
+pub unsafe fn promise_state(_cx: *JSContext, _promise: *JSObject) -> PromiseState {
+    Pending
+}
+
+/// The value `promise` was fulfilled with, or the reason it was rejected
+/// with. Undefined while it's pending.
+#[cfg(promise_jobs)]
+pub unsafe fn get_promise_result(_cx: *JSContext, promise: *JSObject) -> JSVal {
+    JS_GetPromiseResult(promise)
+}
+
+#[cfg(not(promise_jobs))]
+pub unsafe fn get_promise_result(_cx: *JSContext, _promise: *JSObject) -> JSVal {
+    JSVAL_VOID
+}
+
+/// A new pending promise.
+#[cfg(promise_jobs)]
+pub unsafe fn new_promise(cx: *JSContext) -> *JSObject {
+    JS_NewPromiseObject(cx, null(), null())
+}
+
+#[cfg(not(promise_jobs))]
+pub unsafe fn new_promise(_cx: *JSContext) -> *JSObject {
+    warn!("can't make a promise without SpiderMonkey's promise jobs");
+    null()
+}
+
+/// Resolves `promise` with `value`. Its reactions run at the next microtask
+/// checkpoint, not now.
+#[cfg(promise_jobs)]
+pub unsafe fn resolve_promise(cx: *JSContext, promise: *JSObject, value: JSVal) -> bool {
+    JS_ResolvePromise(cx, promise, value) == 1
+}
+
+#[cfg(not(promise_jobs))]
+pub unsafe fn resolve_promise(_cx: *JSContext, _promise: *JSObject, _value: JSVal) -> bool {
+    false
+}
+
+/// Rejects `promise` with `reason`.
+#[cfg(promise_jobs)]
+pub unsafe fn reject_promise(cx: *JSContext, promise: *JSObject, reason: JSVal) -> bool {
+    JS_RejectPromise(cx, promise, reason) == 1
+}
+
+#[cfg(not(promise_jobs))]
+pub unsafe fn reject_promise(_cx: *JSContext, _promise: *JSObject, _reason: JSVal) -> bool {
+    false
+}
+
+/// Has SpiderMonkey give the promise jobs of `cx` to `callback`.
+#[cfg(promise_jobs)]
+pub fn set_enqueue_job_callback(cx: *JSContext, callback: EnqueueJobCallback) {
+    JS_SetEnqueuePromiseJobCallback(cx, callback, null());
+}
+
+#[cfg(not(promise_jobs))]
+pub fn set_enqueue_job_callback(_cx: *JSContext, _callback: EnqueueJobCallback) {
+}
+
+/// Has SpiderMonkey tell `callback` about the rejections in `cx` that
+/// have no handler.
+#[cfg(promise_jobs)]
+pub fn set_rejection_tracker(cx: *JSContext, callback: RejectionTrackerCallback) {
+    JS_SetPromiseRejectionTrackerCallback(cx, callback, null());
+}
+
+#[cfg(not(promise_jobs))]
+pub fn set_rejection_tracker(_cx: *JSContext, _callback: RejectionTrackerCallback) {
+}
+
+/**
+Settles `promise` with the outcome of some work done in Rust: resolved with
+the value it gave, or rejected with its error. This is how an async
+function that awaits the promise gets to go on.
+*/
+pub unsafe fn settle_promise(cx: *JSContext, promise: *JSObject,
+                             result: Result<JSVal, JSVal>) -> bool {
+    match result {
+        Ok(value) => resolve_promise(cx, promise, value),
+        Err(reason) => reject_promise(cx, promise, reason)
+    }
+}
+
+/// A promise that's already resolved with `value`, as `Promise.resolve`
+/// would give. Without promise jobs, `value` itself; `await` takes either.
+#[cfg(promise_jobs)]
+pub unsafe fn resolved_promise(cx: *JSContext, value: JSVal) -> JSVal {
+    let promise = new_promise(cx);
+    resolve_promise(cx, promise, value);
+    RUST_OBJECT_TO_JSVAL(promise)
+}
+
+#[cfg(not(promise_jobs))]
+pub unsafe fn resolved_promise(_cx: *JSContext, value: JSVal) -> JSVal {
+    value
+}
+
+/**
+Has the native called with `vp` return a promise that's already rejected
+with `reason`. Without promise jobs, `reason` is thrown instead. Returns
+what the native should return.
+*/
+#[cfg(promise_jobs)]
+pub unsafe fn rejected_promise(cx: *JSContext, vp: *JSVal, reason: JSVal) -> JSBool {
+    let promise = new_promise(cx);
+    reject_promise(cx, promise, reason);
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(promise));
+    1
+}
+
+#[cfg(not(promise_jobs))]
+pub unsafe fn rejected_promise(cx: *JSContext, _vp: *JSVal, reason: JSVal) -> JSBool {
+    JS_SetPendingException(cx, reason);
+    0
+}
+
+/**
+Has the native called with `vp` return a promise for what `future` gives.
+The future is waited on in a task of its own; once it's ready, the content
+task settles the promise with the JS values `to_js` makes of the outcome
+there, as JS values can't be made on another task. Reactions to the
+promise run as microtasks after that. Returns what the native should
+return.
+
+The content task keeps the promise rooted until then, as the page may not.
+
+Without promise jobs there's nothing to give the page to wait on, so a
+TypeError is thrown and the future is dropped.
+*/
+#[cfg(promise_jobs)]
+pub unsafe fn future_to_promise<T: Copy Send>(cx: *JSContext, vp: *JSVal, future: Future<T>,
+                                              to_js: fn~(*JSContext, T) -> Result<JSVal, JSVal>)
+                                              -> JSBool {
+    let promise = RUST_OBJECT_TO_JSVAL(new_promise(cx));
+    let content = task_from_context(cx);
+    let key = (*content).pending_promises.add(promise);
+    let content_chan = (*content).control_chan.clone();
+    do task::spawn |move future, move to_js, move content_chan| {
+        let outcome = future::get(&future);
+        let settlement = fn~(cx: *JSContext, move to_js, move outcome) -> Result<JSVal, JSVal> {
+            to_js(cx, copy outcome)
+        };
+        content_chan.send(SettlePromise(key, move settlement));
+    }
+    JS_SET_RVAL(cx, vp, promise);
+    1
+}
+
+#[cfg(not(promise_jobs))]
+pub unsafe fn future_to_promise<T: Copy Send>(cx: *JSContext, _vp: *JSVal, _future: Future<T>,
+                                              _to_js: fn~(*JSContext, T)
+                                                  -> Result<JSVal, JSVal>)
+                                              -> JSBool {
+    throw_error(cx, "TypeError", "This needs promises, which servo was built without")
+}
+
+// Called by SpiderMonkey when a promise reaction needs to run
+extern fn enqueue_promise_job(cx: *JSContext, job: *JSObject, _allocation_site: *JSObject,
+                              _incumbent_global: *JSObject, _data: *c_void) -> JSBool unsafe {
+    (*task_from_context(cx)).microtasks.enqueue(RUST_OBJECT_TO_JSVAL(job));
+    1
+}
+
+/// Sends the promise jobs of `cx` to its content task's microtask queue.
+pub fn init(cx: *JSContext) {
+    set_enqueue_job_callback(cx, enqueue_promise_job);
+}
@@ -0,0 +1,251 @@
+/*!
+Proxies backed by Rust. Script gets SpiderMonkey's own `Proxy` with the
+standard classes; this is for bindings whose objects have properties that
+aren't known ahead of time, like a storage area's keys.
+
+A proxy made here has a target object and a `ProxyHandler`. Each trap asks
+the handler first and goes on to the target if the handler doesn't have
+the property, so methods on the target's prototype still work.
+*/
+
+use js::{JSVAL_VOID, JSPROP_ENUMERATE};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp, jsid, JSPropertyDescriptor,
+                AutoIdVector};
+use js::jsapi::bindgen::{JS_IdToValue, JS_ValueToId, JS_GetPropertyById, JS_SetPropertyById,
+                         JS_HasPropertyById, JS_AlreadyHasOwnPropertyById,
+                         JS_DeletePropertyById, JS_DefinePropertyById,
+                         JS_GetPropertyDescriptorById, JS_Enumerate, JS_IdArrayLength,
+                         JS_IdArrayGet, JS_DestroyIdArray};
+use js::glue::ProxyTraps;
+use js::glue::bindgen::*;
+use libc::{c_uint, c_void};
+use ptr::null;
+
+use content::content_task::task_from_context;
+use utils::{domstring_to_jsval, jsval_to_str, squirrel_away, str};
+
+/// What a Rust-backed proxy does with its properties. Each method says
+/// whether the handler dealt with the property; if it didn't, the target
+/// is used instead.
+pub trait ProxyHandler {
+    fn get(&self, cx: *JSContext, target: *JSObject, name: &str) -> Option<JSVal>;
+    fn set(&self, cx: *JSContext, target: *JSObject, name: &str, val: JSVal) -> bool;
+    fn has(&self, cx: *JSContext, target: *JSObject, name: &str) -> bool;
+    fn delete(&self, cx: *JSContext, target: *JSObject, name: &str) -> bool;
+    // Listed before the target's own enumerable properties
+    fn own_keys(&self, cx: *JSContext, target: *JSObject) -> ~[~str];
+    fn define_property(&self, cx: *JSContext, target: *JSObject, name: &str,
+                       val: JSVal) -> bool;
+}
+
+// A trait object is two words, so it's boxed to fit in a slot
+struct HandlerBox {
+    handler: @ProxyHandler,
+}
+
+unsafe fn handler(proxy: *JSObject) -> @ProxyHandler {
+    let val = GetProxyExtra(proxy, 0);
+    let boxed: @HandlerBox = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    let handler = boxed.handler;
+    cast::forget(move boxed);
+    handler
+}
+
+unsafe fn target(proxy: *JSObject) -> *JSObject {
+    RUST_JSVAL_TO_OBJECT(GetProxyPrivate(proxy))
+}
+
+unsafe fn id_to_str(cx: *JSContext, id: jsid) -> Option<~str> {
+    let val = JSVAL_VOID;
+    if JS_IdToValue(cx, id, ptr::to_unsafe_ptr(&val)) == 0 {
+        return None;
+    }
+    match jsval_to_str(cx, val) {
+        Ok(move name) => Some(move name),
+        Err(()) => None
+    }
+}
+
+extern fn get(cx: *JSContext, proxy: *JSObject, _receiver: *JSObject, id: jsid,
+              vp: *mut JSVal) -> JSBool unsafe {
+    let name = match id_to_str(cx, id) {
+        Some(move name) => move name,
+        None => return 0
+    };
+    match handler(proxy).get(cx, target(proxy), name) {
+        Some(val) => {
+            *vp = val;
+            1
+        }
+        None => JS_GetPropertyById(cx, target(proxy), id, vp as *JSVal)
+    }
+}
+
+extern fn set(cx: *JSContext, proxy: *JSObject, _receiver: *JSObject, id: jsid, _strict: JSBool,
+              vp: *mut JSVal) -> JSBool unsafe {
+    let name = match id_to_str(cx, id) {
+        Some(move name) => move name,
+        None => return 0
+    };
+    if handler(proxy).set(cx, target(proxy), name, *vp) {
+        1
+    } else {
+        JS_SetPropertyById(cx, target(proxy), id, vp as *JSVal)
+    }
+}
+
+extern fn has(cx: *JSContext, proxy: *JSObject, id: jsid, bp: *mut JSBool) -> JSBool unsafe {
+    let name = match id_to_str(cx, id) {
+        Some(move name) => move name,
+        None => return 0
+    };
+    if handler(proxy).has(cx, target(proxy), name) {
+        *bp = 1;
+        1
+    } else {
+        JS_HasPropertyById(cx, target(proxy), id, bp as *JSBool)
+    }
+}
+
+extern fn has_own(cx: *JSContext, proxy: *JSObject, id: jsid, bp: *mut JSBool) -> JSBool unsafe {
+    let name = match id_to_str(cx, id) {
+        Some(move name) => move name,
+        None => return 0
+    };
+    if handler(proxy).has(cx, target(proxy), name) {
+        *bp = 1;
+        1
+    } else {
+        JS_AlreadyHasOwnPropertyById(cx, target(proxy), id, bp as *JSBool)
+    }
+}
+
+extern fn delete_(cx: *JSContext, proxy: *JSObject, id: jsid, bp: *mut JSBool) -> JSBool unsafe {
+    let name = match id_to_str(cx, id) {
+        Some(move name) => move name,
+        None => return 0
+    };
+    *bp = 1;
+    if handler(proxy).delete(cx, target(proxy), name) {
+        1
+    } else {
+        JS_DeletePropertyById(cx, target(proxy), id)
+    }
+}
+
+extern fn define_property(cx: *JSContext, proxy: *JSObject, id: jsid,
+                          desc: *JSPropertyDescriptor) -> JSBool unsafe {
+    let name = match id_to_str(cx, id) {
+        Some(move name) => move name,
+        None => return 0
+    };
+    if handler(proxy).define_property(cx, target(proxy), name, (*desc).value) {
+        1
+    } else {
+        JS_DefinePropertyById(cx, target(proxy), id, (*desc).value, (*desc).getter,
+                              (*desc).setter, (*desc).attrs)
+    }
+}
+
+// The handler's properties are plain enumerable data properties
+extern fn get_property_descriptor(cx: *JSContext, proxy: *JSObject, id: jsid, _set: JSBool,
+                                  desc: *mut JSPropertyDescriptor) -> JSBool unsafe {
+    let name = match id_to_str(cx, id) {
+        Some(move name) => move name,
+        None => return 0
+    };
+    let handler = handler(proxy);
+    if handler.has(cx, target(proxy), name) {
+        (*desc).obj = proxy;
+        (*desc).attrs = JSPROP_ENUMERATE;
+        (*desc).shortid = 0;
+        (*desc).getter = null();
+        (*desc).setter = null();
+        (*desc).value = handler.get(cx, target(proxy), name).get_default(JSVAL_VOID);
+        1
+    } else {
+        JS_GetPropertyDescriptorById(cx, target(proxy), id, 0, desc as *JSPropertyDescriptor)
+    }
+}
+
+unsafe fn append_name(cx: *JSContext, props: *AutoIdVector, name: &str) -> bool {
+    let id = 0 as jsid;
+    let val = domstring_to_jsval(cx, &str(str::from_slice(name)));
+    JS_ValueToId(cx, val, ptr::to_unsafe_ptr(&id)) == 1 && AppendToAutoIdVector(props, id) == 1
+}
+
+extern fn own_property_names(cx: *JSContext, proxy: *JSObject,
+                             props: *AutoIdVector) -> JSBool unsafe {
+    for handler(proxy).own_keys(cx, target(proxy)).each |name| {
+        if !append_name(cx, props, *name) {
+            return 0;
+        }
+    }
+    let ids = JS_Enumerate(cx, target(proxy));
+    if ids.is_null() {
+        return 0;
+    }
+    for uint::range(0, JS_IdArrayLength(cx, ids) as uint) |i| {
+        if AppendToAutoIdVector(props, JS_IdArrayGet(cx, ids, i as c_uint)) == 0 {
+            JS_DestroyIdArray(cx, ids);
+            return 0;
+        }
+    }
+    JS_DestroyIdArray(cx, ids);
+    1
+}
+
+extern fn finalize(_fop: *JSFreeOp, proxy: *JSObject) {
+    #debug("proxy finalize!");
+    unsafe {
+        let val = GetProxyExtra(proxy, 0);
+        let _: @HandlerBox = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+/// The C++ handler whose traps call into `ProxyHandler`s. One is made per
+/// content task and shared by all its proxies.
+pub fn new_proxy_traps_handler() -> *c_void {
+    let traps = ProxyTraps {
+        getPropertyDescriptor: get_property_descriptor,
+        getOwnPropertyDescriptor: get_property_descriptor,
+        defineProperty: define_property,
+        getOwnPropertyNames: own_property_names,
+        delete_: delete_,
+        enumerate: own_property_names,
+        has: has,
+        hasOwn: has_own,
+        get: get,
+        set: set,
+        keys: own_property_names,
+        iterate: null(),
+        call: null(),
+        construct: null(),
+        nativeCall: null(),
+        hasInstance: null(),
+        typeOf: null(),
+        objectClassIs: null(),
+        obj_toString: null(),
+        fun_toString: null(),
+        defaultValue: null(),
+        finalize: finalize,
+        getElementIfPresent: null(),
+        getPrototypeOf: null(),
+        trace: null()
+    };
+    CreateProxyHandler(ptr::to_unsafe_ptr(&traps), null())
+}
+
+/// A proxy for `target` whose properties come from `handler` first.
+pub fn create_proxy(cx: *JSContext, target: *JSObject,
+                    handler: @ProxyHandler) -> *JSObject unsafe {
+    let traps_handler = (*task_from_context(cx)).proxy_handler;
+    let proxy = NewProxyObject(cx, traps_handler, RUST_OBJECT_TO_JSVAL(target), null(), null(),
+                               null(), null());
+    if proxy.is_null() {
+        return proxy;
+    }
+    let raw_ptr: *c_void = cast::reinterpret_cast(&squirrel_away(@HandlerBox { handler: handler }));
+    SetProxyExtra(proxy, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+    proxy
+}
@@ -0,0 +1,233 @@
+use js::rust::{bare_compartment, methods, jsobj};
+use js::{JS_ARGV, JSPROP_ENUMERATE, JSPROP_READONLY, JSVAL_NULL, JSVAL_VOID, JS_THIS_OBJECT,
+            JS_SET_RVAL, JSTYPE_FUNCTION};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp};
+use js::jsapi::bindgen::{JS_GetReservedSlot, JS_SetReservedSlot, JS_DefineFunctions,
+                            JS_DefineProperty, JS_GetProperty, JS_GetClass, JS_NewObject,
+                            JS_NewArrayObject, JS_TypeOfValue, JS_ReportError};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use ptr::null;
+use libc::c_uint;
+use geom::size::Size2D;
+use utils::{rust_box, squirrel_away, jsval_to_str, get_compartment};
+use unwrap_node = node::unwrap;
+use content::content_task::task_from_context;
+use dom::node::Node;
+use dom::resize_observer::{ResizeObserver, ResizeObserverEntry, ResizeObserverBox,
+                           ContentBox};
+
+unsafe fn unwrap(obj: *JSObject) -> *rust_box<@ResizeObserver> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+unsafe fn report_error(cx: *JSContext, msg: &str) -> JSBool {
+    do str::as_c_str(msg) |s| {
+        JS_ReportError(cx, s);
+    }
+    0
+}
+
+// Keeps the window's list of observers up to date after `observer` changed
+unsafe fn update_window(cx: *JSContext, observer: @ResizeObserver) {
+    let win = (*task_from_context(cx)).window.expect(~"ResizeObserver needs a window");
+    win.update_resize_observer(observer);
+}
+
+// The element behind the first argument
+unsafe fn target_arg(cx: *JSContext, argc: c_uint, vp: *JSVal) -> Option<(Node, JSVal)> {
+    if argc < 1 {
+        return None;
+    }
+    let val = *JS_ARGV(cx, vp);
+    if RUST_JSVAL_IS_OBJECT(val) == 0 || RUST_JSVAL_IS_NULL(val) == 1 {
+        return None;
+    }
+    let obj = RUST_JSVAL_TO_OBJECT(val);
+    if str::raw::from_c_str((*JS_GetClass(obj)).name) != ~"GenericElementInstance" {
+        return None;
+    }
+    Some(((*unwrap_node(obj)).payload.node, val))
+}
+
+// The `box` member of observe's options
+unsafe fn box_option(cx: *JSContext, argc: c_uint, vp: *JSVal) -> Result<ResizeObserverBox, ()> {
+    if argc < 2 || RUST_JSVAL_IS_OBJECT(*ptr::offset(JS_ARGV(cx, vp), 1)) == 0 {
+        return Ok(ContentBox);
+    }
+    let options = RUST_JSVAL_TO_OBJECT(*ptr::offset(JS_ARGV(cx, vp), 1));
+    if options.is_null() {
+        return Ok(ContentBox);
+    }
+    let val = JSVAL_VOID;
+    do str::as_c_str(~"box") |s| {
+        JS_GetProperty(cx, options, s, ptr::to_unsafe_ptr(&val));
+    }
+    if RUST_JSVAL_IS_VOID(val) == 1 {
+        return Ok(ContentBox);
+    }
+    match jsval_to_str(cx, val) {
+        Ok(move s) => match ResizeObserverBox::from_str(s) {
+            Some(observed_box) => Ok(observed_box),
+            None => {
+                report_error(cx, fmt!("'%s' isn't a box ResizeObserver can observe", s));
+                Err(())
+            }
+        },
+        Err(()) => Err(())
+    }
+}
+
+extern fn observe(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let (target, target_obj) = match target_arg(cx, argc, vp) {
+        Some(target) => target,
+        None => return report_error(cx, "ResizeObserver.observe needs an element")
+    };
+    let observed_box = match box_option(cx, argc, vp) {
+        Ok(observed_box) => observed_box,
+        Err(()) => return 0
+    };
+    let observer = (*unwrap(obj)).payload;
+    observer.observe(target, target_obj, observed_box);
+    update_window(cx, observer);
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn unobserve(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let observer = (*unwrap(obj)).payload;
+    match target_arg(cx, argc, vp) {
+        Some((target, _)) => observer.unobserve(target),
+        None => return report_error(cx, "ResizeObserver.unobserve needs an element")
+    }
+    update_window(cx, observer);
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn disconnect(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let observer = (*unwrap(obj)).payload;
+    observer.disconnect();
+    update_window(cx, observer);
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn constructor(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    if argc < 1 || JS_TypeOfValue(cx, *JS_ARGV(cx, vp)) != JSTYPE_FUNCTION {
+        return report_error(cx, "ResizeObserver needs a callback");
+    }
+    // The window only keeps it once it's observing something
+    let observer = @ResizeObserver(cx, *JS_ARGV(cx, vp));
+
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"ResizeObserverInstance", ~"ResizeObserver",
+                                          compartment.global_obj.ptr));
+    let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(@observer));
+    JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(obj.ptr));
+    return 1;
+}
+
+extern fn finalize(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("resize observer finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @@ResizeObserver = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+unsafe fn define_value(cx: *JSContext, obj: *JSObject, name: &str, val: JSVal) {
+    do str::as_c_str(name) |s| {
+        JS_DefineProperty(cx, obj, s, val,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE | JSPROP_READONLY);
+    }
+}
+
+unsafe fn define_int(cx: *JSContext, obj: *JSObject, name: &str, n: int) {
+    define_value(cx, obj, name, RUST_INT_TO_JSVAL(n as libc::c_int));
+}
+
+unsafe fn new_array(cx: *JSContext, values: &[JSVal]) -> JSVal {
+    do vec::as_imm_buf(values) |buf, len| {
+        RUST_OBJECT_TO_JSVAL(JS_NewArrayObject(cx, len as libc::c_int, buf))
+    }
+}
+
+// A one-element array of ResizeObserverSize, since there are no fragments
+unsafe fn sizes_to_jsval(cx: *JSContext, size: Size2D<int>) -> JSVal {
+    //TODO: swap these for vertical writing modes
+    let obj = JS_NewObject(cx, null(), null(), null());
+    define_int(cx, obj, "inlineSize", size.width);
+    define_int(cx, obj, "blockSize", size.height);
+    new_array(cx, ~[RUST_OBJECT_TO_JSVAL(obj)])
+}
+
+unsafe fn entry_to_jsval(cx: *JSContext, entry: &ResizeObserverEntry) -> JSVal {
+    let rect = entry.sizes.content_rect;
+    let content_rect = JS_NewObject(cx, null(), null(), null());
+    define_int(cx, content_rect, "x", rect.origin.x);
+    define_int(cx, content_rect, "y", rect.origin.y);
+    define_int(cx, content_rect, "width", rect.size.width);
+    define_int(cx, content_rect, "height", rect.size.height);
+    define_int(cx, content_rect, "top", rect.origin.y);
+    define_int(cx, content_rect, "left", rect.origin.x);
+    define_int(cx, content_rect, "bottom", rect.origin.y + rect.size.height);
+    define_int(cx, content_rect, "right", rect.origin.x + rect.size.width);
+
+    let obj = JS_NewObject(cx, null(), null(), null());
+    define_value(cx, obj, "target", entry.target);
+    define_value(cx, obj, "contentRect", RUST_OBJECT_TO_JSVAL(content_rect));
+    define_value(cx, obj, "borderBoxSize", sizes_to_jsval(cx, entry.sizes.border_box));
+    define_value(cx, obj, "contentBoxSize", sizes_to_jsval(cx, entry.sizes.content_box));
+    define_value(cx, obj, "devicePixelContentBoxSize",
+                 sizes_to_jsval(cx, entry.sizes.device_pixel_content_box));
+    RUST_OBJECT_TO_JSVAL(obj)
+}
+
+/// The array of ResizeObserverEntry objects passed to the callback.
+pub fn entries_to_jsval(cx: *JSContext, entries: &[ResizeObserverEntry]) -> JSVal unsafe {
+    let vals = do entries.map |entry| { entry_to_jsval(cx, entry) };
+    new_array(cx, vals)
+}
+
+pub fn init(compartment: &bare_compartment) {
+    let obj = utils::define_constructor(~"ResizeObserver", None, constructor, compartment);
+    let methods = ~[{name: compartment.add_name(~"observe"),
+                     call: {op: observe, info: null()},
+                     nargs: 2,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"unobserve"),
+                     call: {op: unobserve, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"disconnect"),
+                     call: {op: disconnect, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj.ptr, fns);
+    });
+
+    compartment.register_class(utils::instance_jsclass(~"ResizeObserverInstance", finalize));
+}
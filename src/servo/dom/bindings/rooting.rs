@@ -0,0 +1,232 @@
+/*!
+GC rooting for the wrappers of DOM nodes. A node gets one wrapper, the
+first time script sees it, and the wrapper is kept as a root until the
+document is replaced or the content task exits. Script gets the same
+object every time it asks for the node, and anything it set on the
+wrapper stays.
+
+Other JS values that Rust holds on to between calls into script, such as
+listener callbacks, are kept in a `RootedValues` for as long as they're
+held.
+*/
+
+use dom::cow;
+use dom::node::Node;
+use js::jsapi::{JSContext, JSObject, JSVal};
+use js::jsapi::bindgen::{JS_AddObjectRoot, JS_RemoveObjectRoot, JS_AddValueRoot,
+                         JS_RemoveValueRoot};
+use js::glue::bindgen::{RUST_JSVAL_IS_OBJECT, RUST_JSVAL_IS_NULL, RUST_JSVAL_IS_STRING,
+                        RUST_JSVAL_TO_OBJECT, RUST_OBJECT_TO_JSVAL};
+use std::map::HashMap;
+
+/**
+JS objects kept alive as GC roots. Each one is boxed, so that the address
+the engine is given for it stays the same as the vector grows.
+*/
+pub struct RootedVec {
+    priv cx: *JSContext,
+    priv mut roots: ~[~*JSObject],
+
+    drop {
+        self.clear();
+    }
+}
+
+pub fn RootedVec(cx: *JSContext) -> RootedVec {
+    RootedVec { cx: cx, roots: ~[] }
+}
+
+impl RootedVec {
+    /// Roots `obj`, and returns where it is.
+    fn push(&self, obj: *JSObject) -> uint {
+        let root = ~obj;
+        JS_AddObjectRoot(self.cx, ptr::to_unsafe_ptr(&*root));
+        self.roots.push(move root);
+        self.roots.len() - 1
+    }
+
+    fn get(&self, i: uint) -> *JSObject {
+        *self.roots[i]
+    }
+
+    fn len(&self) -> uint {
+        self.roots.len()
+    }
+
+    /// Unroots everything, so that the GC may collect it.
+    fn clear(&self) {
+        for self.roots.each |root| {
+            JS_RemoveObjectRoot(self.cx, ptr::to_unsafe_ptr(&**root));
+        }
+        self.roots = ~[];
+    }
+}
+
+/**
+JS values kept alive as GC roots, each under a key it's looked up and
+removed with. Only objects and strings are collected, so other values are
+kept without a root. Each value is boxed, like RootedVec's objects.
+*/
+pub struct RootedValues {
+    priv cx: *JSContext,
+    priv mut next_key: uint,
+    priv mut entries: ~[(uint, ~JSVal)],
+
+    drop {
+        self.clear();
+    }
+}
+
+pub fn RootedValues(cx: *JSContext) -> RootedValues {
+    RootedValues { cx: cx, next_key: 0, entries: ~[] }
+}
+
+fn is_gc_thing(val: JSVal) -> bool {
+    (RUST_JSVAL_IS_OBJECT(val) == 1 && RUST_JSVAL_IS_NULL(val) == 0) ||
+        RUST_JSVAL_IS_STRING(val) == 1
+}
+
+impl RootedValues {
+    /// Roots `val`, and returns the key it's kept under.
+    fn add(&self, val: JSVal) -> uint {
+        let root = ~val;
+        if is_gc_thing(val) {
+            JS_AddValueRoot(self.cx, ptr::to_unsafe_ptr(&*root));
+        }
+        let key = self.next_key;
+        self.next_key += 1;
+        self.entries.push((key, move root));
+        key
+    }
+
+    priv fn position(&self, key: uint) -> Option<uint> {
+        do self.entries.position |entry| {
+            match *entry { (k, _) => k == key }
+        }
+    }
+
+    fn contains(&self, key: uint) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// The key `val` is kept under, if it's here.
+    fn key_of(&self, val: JSVal) -> Option<uint> {
+        let i = do self.entries.position |entry| {
+            match *entry { (_, ref root) => **root == val }
+        };
+        match i {
+            Some(i) => match self.entries[i] { (k, _) => Some(k) },
+            None => None
+        }
+    }
+
+    /// The keys of the values kept, oldest first.
+    fn keys(&self) -> ~[uint] {
+        let keys = do self.entries.map |entry| {
+            match *entry { (k, _) => k }
+        };
+        std::sort::merge_sort(keys, |a, b| *a <= *b)
+    }
+
+    fn get(&self, key: uint) -> JSVal {
+        match self.entries[self.position(key).expect(~"No value is rooted under that key")] {
+            (_, ref root) => **root
+        }
+    }
+
+    /// Unroots the value kept under `key`, and returns it.
+    fn remove(&self, key: uint) -> JSVal {
+        let i = self.position(key).expect(~"No value is rooted under that key");
+        let mut entries = ~[];
+        entries <-> self.entries;
+        let (_, root) = vec::swap_remove(&mut entries, i);
+        self.entries <-> entries;
+        if is_gc_thing(*root) {
+            JS_RemoveValueRoot(self.cx, ptr::to_unsafe_ptr(&*root));
+        }
+        *root
+    }
+
+    fn len(&self) -> uint {
+        self.entries.len()
+    }
+
+    /// Unroots everything, so that the GC may collect it.
+    fn clear(&self) {
+        for self.entries.each |entry| {
+            match *entry {
+                (_, ref root) => if is_gc_thing(**root) {
+                    JS_RemoveValueRoot(self.cx, ptr::to_unsafe_ptr(&**root));
+                }
+            }
+        }
+        self.entries = ~[];
+    }
+}
+
+/// The wrapper of each node that script has seen.
+pub struct NodeWrappers {
+    priv roots: RootedValues,
+    // The key of each node's wrapper in `roots`, by the address of its handle
+    priv keys: HashMap<uint, uint>,
+    // Each wrapper's node, by the wrapper's address
+    priv nodes: HashMap<uint, Node>,
+}
+
+pub fn NodeWrappers(cx: *JSContext) -> NodeWrappers {
+    NodeWrappers { roots: RootedValues(cx), keys: HashMap(), nodes: HashMap() }
+}
+
+fn node_key(node: Node) -> uint unsafe {
+    cow::unwrap(node) as uint
+}
+
+impl NodeWrappers {
+    fn find(&self, node: Node) -> Option<*JSObject> {
+        self.keys.find(node_key(node)).map(|key| RUST_JSVAL_TO_OBJECT(self.roots.get(*key)))
+    }
+
+    /// Roots `obj` as the wrapper of `node`.
+    fn insert(&self, node: Node, obj: *JSObject) {
+        assert self.find(node).is_none();
+        self.keys.insert(node_key(node), self.roots.add(RUST_OBJECT_TO_JSVAL(obj)));
+        self.nodes.insert(obj as uint, node);
+    }
+
+    /// Unroots the wrapper of `node`, which the GC may then collect.
+    fn remove(&self, node: Node) {
+        match self.keys.find(node_key(node)) {
+            Some(key) => {
+                let obj = RUST_JSVAL_TO_OBJECT(self.roots.remove(key));
+                self.keys.remove(node_key(node));
+                self.nodes.remove(obj as uint);
+            }
+            None => ()
+        }
+    }
+
+    /// The node `obj` is the wrapper of, if it's one.
+    fn node_for(&self, obj: *JSObject) -> Option<Node> {
+        self.nodes.find(obj as uint)
+    }
+
+    /// The nodes that have wrappers.
+    fn nodes(&self) -> ~[Node] {
+        let mut nodes = ~[];
+        for self.nodes.each_value |node| {
+            nodes.push(node);
+        }
+        move nodes
+    }
+
+    fn len(&self) -> uint {
+        self.roots.len()
+    }
+
+    /// Lets all the wrappers be collected, as the nodes are going away.
+    fn unroot_all(&self) {
+        self.roots.clear();
+        self.keys.clear();
+        self.nodes.clear();
+    }
+}
@@ -0,0 +1,470 @@
+/*!
+Service workers, for script. The page gets `navigator.serviceWorker`, to
+register workers and find the one controlling it. The worker's own script
+runs in a global of its own, `WorkerGlobal`, in a runtime of its own on the
+worker's task. Page bindings all need a content task, so that global has
+only what a worker needs: `self`, `addEventListener`, `skipWaiting`, the
+events it's sent, and `Response`, to answer fetches with.
+
+Registrations and workers are plain objects for now, with a worker's
+`scriptURL` and `state`, and a registration's `scope` and `active` worker.
+*/
+
+use js::rust::{bare_compartment, methods, cx, compartment};
+use jsrt = js::rust::rt;
+use js::global::global_class;
+use js::{JSPROP_ENUMERATE, JSPROP_SHARED, JSPROP_NATIVE_ACCESSORS, JSVAL_NULL, JSVAL_VOID,
+         JS_ARGV, JS_SET_RVAL, JSTYPE_FUNCTION};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool};
+use js::jsapi::bindgen::{JS_GetContextPrivate, JS_EvaluateScript, JS_CallFunctionValue,
+                         JS_TypeOfValue, JS_ClearPendingException, JS_NewObject,
+                         JS_DefineProperties};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use ptr::null;
+use libc::{c_uint, c_void};
+use io::println;
+use std::net::url::Url;
+use util::url::url_to_str;
+use bindings::cache_storage::{define_value, get_value, is_object, reject, object_arg,
+                              define_methods, response_arg, define_response, unwrap_response,
+                              text, arrayBuffer, finalize_response};
+use utils::{new_error, throw_error, unicode_to_jsval, jsval_to_unicode, string_arg};
+use bindings::promise::{future_to_promise, resolved_promise, is_promise, promise_state,
+                        get_promise_result, set_enqueue_job_callback, Fulfilled};
+use bindings::rooting::RootedVec;
+use content::content_task::task_from_context;
+use content::promise_queue::PromiseQueue;
+use dom::service_worker;
+use dom::service_worker::{ServiceWorker, Registration, WorkerState, Activated, Redundant,
+                          FetchOutcome, FallThrough, Respond, NetworkError, default_scope,
+                          scope_allowed};
+use util::url::{make_url, same_origin};
+
+/// The global a service worker's script runs in, with the runtime it has
+/// to itself.
+pub struct WorkerGlobal {
+    priv jsrt: jsrt,
+    priv cx: cx,
+    priv compartment: compartment,
+
+    // Promise jobs, run once each event has been dispatched
+    priv jobs: PromiseQueue,
+
+    // The functions given to addEventListener, and the kind of event each
+    // is for
+    priv listeners: RootedVec,
+    priv mut listener_kinds: ~[~str],
+
+    // What the event being dispatched was given by waitUntil and
+    // respondWith, kept rooted until it's been handled
+    priv extensions: RootedVec,
+    priv mut response: Option<JSVal>,
+    priv mut dispatching: bool,
+
+    // Whether the script threw, so the worker can't be installed
+    priv mut failed: bool,
+}
+
+/// Runs `source`, the script at `script_url`, in a new worker global.
+pub fn WorkerGlobal(script_url: &Url, source: &str) -> @WorkerGlobal {
+    let jsrt = jsrt();
+    let cx = jsrt.cx();
+    cx.set_default_options_and_version();
+    let compartment = result::unwrap(cx.new_compartment(global_class));
+
+    let global = @WorkerGlobal {
+        jsrt: jsrt,
+        cx: cx,
+        compartment: compartment,
+        jobs: PromiseQueue(cx.ptr),
+        listeners: RootedVec(cx.ptr),
+        listener_kinds: ~[],
+        extensions: RootedVec(cx.ptr),
+        response: None,
+        dispatching: false,
+        failed: false
+    };
+    cx.set_cx_private(ptr::to_unsafe_ptr(&*global) as *());
+    set_enqueue_job_callback(cx.ptr, enqueue_job);
+    define_globals(compartment);
+
+    let filename = url_to_str(copy *script_url);
+    let bytes = str::to_bytes(source);
+    let evaluated = do vec::as_imm_buf(bytes) |bytes_ptr, bytes_len| {
+        do str::as_c_str(filename) |filename_cstr| {
+            let rval = JSVAL_NULL;
+            JS_EvaluateScript(cx.ptr, compartment.global_obj.ptr,
+                              bytes_ptr as *libc::c_char, bytes_len as c_uint,
+                              filename_cstr, 1, ptr::to_unsafe_ptr(&rval))
+        }
+    };
+    if evaluated == 0 {
+        println(fmt!("Error running the service worker at %s", filename));
+        JS_ClearPendingException(cx.ptr);
+        global.failed = true;
+    }
+    global.run_jobs();
+    global
+}
+
+fn global_from_context(cx: *JSContext) -> *WorkerGlobal unsafe {
+    cast::reinterpret_cast(&JS_GetContextPrivate(cx))
+}
+
+// Called by SpiderMonkey when a promise reaction needs to run
+extern fn enqueue_job(cx: *JSContext, job: *JSObject, _allocation_site: *JSObject,
+                      _incumbent_global: *JSObject, _data: *c_void) -> JSBool unsafe {
+    (*global_from_context(cx)).jobs.enqueue(RUST_OBJECT_TO_JSVAL(job));
+    1
+}
+
+impl WorkerGlobal {
+    /**
+    Dispatches `install`, and then `activate` if the worker installed. It
+    doesn't if the script threw, a listener threw, or a promise given to
+    `waitUntil` wasn't fulfilled once the jobs had run.
+    */
+    fn install(&self) -> WorkerState {
+        if self.failed {
+            return Redundant;
+        }
+        let event = self.new_event(~"ExtendableEventInstance", ~"ExtendableEvent", "install");
+        let dispatched = self.dispatch("install", event);
+        self.run_jobs();
+        let extended = self.extensions_fulfilled();
+        self.extensions.clear();
+        if !dispatched || !extended {
+            return Redundant;
+        }
+
+        let event = self.new_event(~"ExtendableEventInstance", ~"ExtendableEvent", "activate");
+        self.dispatch("activate", event);
+        self.run_jobs();
+        self.extensions.clear();
+        Activated
+    }
+
+    /**
+    Dispatches a `fetch` event for a load of `url`. If no listener called
+    `respondWith`, the load goes to the network. Otherwise it gets what it
+    was given, once the jobs have run: a Response, or a promise fulfilled
+    with one. Anything else is a network error.
+    */
+    fn fetch(&self, url: Url) -> FetchOutcome unsafe {
+        let cx = self.cx.ptr;
+        let request = JS_NewObject(cx, null(), null(), null());
+        define_value(cx, request, "url", unicode_to_jsval(cx, url_to_str(move url)));
+        define_value(cx, request, "method", unicode_to_jsval(cx, "GET"));
+        let event = self.new_event(~"FetchEventInstance", ~"FetchEvent", "fetch");
+        define_value(cx, event, "request", RUST_OBJECT_TO_JSVAL(request));
+
+        self.response = None;
+        self.dispatch("fetch", event);
+        self.run_jobs();
+        let outcome = match self.response {
+            None => FallThrough,
+            Some(response) => {
+                let response = match self.settled_value(response) {
+                    Some(value) => unwrap_response(value),
+                    None => None
+                };
+                match move response {
+                    Some(move response) => Respond(move response),
+                    None => NetworkError
+                }
+            }
+        };
+        self.response = None;
+        self.extensions.clear();
+        outcome
+    }
+
+    /// Unroots everything, before the runtime goes.
+    fn close(&self) {
+        self.listeners.clear();
+        self.listener_kinds = ~[];
+        self.extensions.clear();
+    }
+
+    fn new_event(&self, class: ~str, proto: ~str, kind: &str) -> *JSObject unsafe {
+        let event = result::unwrap(self.compartment.new_object_with_proto(
+            move class, move proto, self.compartment.global_obj.ptr));
+        define_value(self.cx.ptr, event.ptr, "type", unicode_to_jsval(self.cx.ptr, kind));
+        event.ptr
+    }
+
+    // Calls the listeners for `kind`, then the global's `on<kind>`. Returns
+    // false if any of them threw.
+    fn dispatch(&self, kind: &str, event: *JSObject) -> bool unsafe {
+        let event = RUST_OBJECT_TO_JSVAL(event);
+        let mut ok = true;
+        self.dispatching = true;
+        // Listeners added while it's dispatched don't get the event
+        for uint::range(0, self.listener_kinds.len()) |i| {
+            if str::eq_slice(self.listener_kinds[i], kind) {
+                ok = self.call(RUST_OBJECT_TO_JSVAL(self.listeners.get(i)), event) && ok;
+            }
+        }
+        let handler = get_value(self.cx.ptr, self.compartment.global_obj.ptr, ~"on" + kind);
+        if JS_TypeOfValue(self.cx.ptr, handler) == JSTYPE_FUNCTION {
+            ok = self.call(handler, event) && ok;
+        }
+        self.dispatching = false;
+        ok
+    }
+
+    fn call(&self, fun: JSVal, arg: JSVal) -> bool unsafe {
+        let rval = JSVAL_NULL;
+        if JS_CallFunctionValue(self.cx.ptr, self.compartment.global_obj.ptr, fun,
+                                1, ptr::to_unsafe_ptr(&arg), ptr::to_unsafe_ptr(&rval)) == 1 {
+            true
+        } else {
+            JS_ClearPendingException(self.cx.ptr);
+            false
+        }
+    }
+
+    fn run_jobs(&self) {
+        let _ = do self.jobs.drain |job| {
+            let rval = JSVAL_NULL;
+            JS_CallFunctionValue(self.cx.ptr, self.compartment.global_obj.ptr, job,
+                                 0, null(), ptr::to_unsafe_ptr(&rval));
+        };
+    }
+
+    // What `val` comes to: itself, or what it was fulfilled with if it's a
+    // promise. None if it's a promise that was rejected, or that there are
+    // no jobs left to settle.
+    fn settled_value(&self, val: JSVal) -> Option<JSVal> unsafe {
+        if !is_promise(self.cx.ptr, val) {
+            return Some(val);
+        }
+        let promise = RUST_JSVAL_TO_OBJECT(val);
+        if promise_state(self.cx.ptr, promise) == Fulfilled {
+            Some(get_promise_result(self.cx.ptr, promise))
+        } else {
+            None
+        }
+    }
+
+    fn extensions_fulfilled(&self) -> bool {
+        for uint::range(0, self.extensions.len()) |i| {
+            let extension = RUST_OBJECT_TO_JSVAL(self.extensions.get(i));
+            if self.settled_value(extension).is_none() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+extern fn addEventListener(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let global = global_from_context(cx);
+    let argv = JS_ARGV(cx, vp);
+    if argc >= 2 && JS_TypeOfValue(cx, *ptr::offset(argv, 1)) == JSTYPE_FUNCTION {
+        match jsval_to_unicode(cx, *argv) {
+            Some(move kind) => {
+                (*global).listeners.push(RUST_JSVAL_TO_OBJECT(*ptr::offset(argv, 1)));
+                (*global).listener_kinds.push(move kind);
+            }
+            None => return 0
+        }
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    return 1;
+}
+
+// Workers are activated as soon as they're installed anyway
+extern fn skipWaiting(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    JS_SET_RVAL(cx, vp, resolved_promise(cx, JSVAL_VOID));
+    return 1;
+}
+
+extern fn waitUntil(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let global = global_from_context(cx);
+    if !(*global).dispatching {
+        return throw_error(cx, "InvalidStateError",
+                           "waitUntil can only be called while the event is dispatched");
+    }
+    // Anything but a promise counts as one that's fulfilled
+    if argc > 0 && is_object(*JS_ARGV(cx, vp)) {
+        (*global).extensions.push(RUST_JSVAL_TO_OBJECT(*JS_ARGV(cx, vp)));
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    return 1;
+}
+
+extern fn respondWith(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let global = global_from_context(cx);
+    if !(*global).dispatching || (*global).response.is_some() {
+        return throw_error(cx, "InvalidStateError",
+                           "respondWith can only be called once, while the event is dispatched");
+    }
+    let response = if argc > 0 { *JS_ARGV(cx, vp) } else { JSVAL_VOID };
+    if is_object(response) {
+        (*global).extensions.push(RUST_JSVAL_TO_OBJECT(response));
+    }
+    (*global).response = Some(response);
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    return 1;
+}
+
+extern fn Response_constructor(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    match response_arg(cx, argc, vp) {
+        Some(move response) => {
+            let compartment = (*global_from_context(cx)).compartment;
+            let obj = result::unwrap(compartment.new_object_with_proto(
+                ~"ResponseInstance", ~"Response", compartment.global_obj.ptr));
+            define_response(cx, obj.ptr, move response);
+            JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(obj.ptr));
+            1
+        }
+        None => 0
+    }
+}
+
+fn define_globals(compartment: &bare_compartment) {
+    compartment.define_property(~"self", RUST_OBJECT_TO_JSVAL(compartment.global_obj.ptr),
+                                GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                                GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                                JSPROP_ENUMERATE);
+    define_methods(compartment, compartment.global_obj.ptr,
+                   ~[(~"addEventListener", addEventListener as *u8, 2),
+                     (~"skipWaiting", skipWaiting as *u8, 0)]);
+
+    let response = utils::define_constructor(~"Response", None, Response_constructor,
+                                             compartment);
+    define_methods(compartment, response.ptr,
+                   ~[(~"text", text as *u8, 0),
+                     (~"arrayBuffer", arrayBuffer as *u8, 0)]);
+    compartment.register_class(utils::instance_jsclass(~"ResponseInstance", finalize_response));
+
+    let event = utils::define_empty_prototype(~"ExtendableEvent", None, compartment);
+    define_methods(compartment, event.ptr, ~[(~"waitUntil", waitUntil as *u8, 1)]);
+    compartment.register_class(utils::instance_jsclass(~"ExtendableEventInstance", null()));
+
+    let fetch_event = utils::define_empty_prototype(~"FetchEvent", Some(~"ExtendableEvent"),
+                                                    compartment);
+    define_methods(compartment, fetch_event.ptr, ~[(~"respondWith", respondWith as *u8, 1)]);
+    compartment.register_class(utils::instance_jsclass(~"FetchEventInstance", null()));
+}
+
+// The worker of `registration`, as script sees it
+fn worker_to_jsval(cx: *JSContext, registration: &Registration) -> JSVal unsafe {
+    let worker = JS_NewObject(cx, null(), null(), null());
+    define_value(cx, worker, "scriptURL", unicode_to_jsval(cx, registration.script_url));
+    define_value(cx, worker, "state", unicode_to_jsval(cx, Activated.name()));
+    RUST_OBJECT_TO_JSVAL(worker)
+}
+
+fn registration_to_jsval(cx: *JSContext, registration: &Registration) -> JSVal unsafe {
+    let obj = JS_NewObject(cx, null(), null(), null());
+    define_value(cx, obj, "scope", unicode_to_jsval(cx, registration.scope));
+    define_value(cx, obj, "active", worker_to_jsval(cx, registration));
+    RUST_OBJECT_TO_JSVAL(obj)
+}
+
+// The scope in register's options, resolved against the page
+unsafe fn scope_arg(cx: *JSContext, argc: c_uint, vp: *JSVal, doc_url: &Url) -> Option<Url> {
+    let options = object_arg(cx, argc, vp, 1);
+    if options.is_null() {
+        return None;
+    }
+    let scope = get_value(cx, options, "scope");
+    if RUST_JSVAL_IS_VOID(scope) == 1 {
+        return None;
+    }
+    jsval_to_unicode(cx, scope).map(|s| make_url(copy *s, Some(copy *doc_url)))
+}
+
+/**
+`register(scriptURL, options)`: installs the worker at `scriptURL` for a
+scope, which is `options.scope` or the script's directory. The promise is
+resolved with the registration once the worker has been activated.
+*/
+extern fn register(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let content = task_from_context(cx);
+    let doc_url = match (*content).doc_url {
+        Some(ref url) => copy *url,
+        None => return reject(cx, vp, "InvalidStateError", "There's no page to register for")
+    };
+    let script_url = match string_arg(cx, argc, vp, 0) {
+        Some(move s) => make_url(move s, Some(copy doc_url)),
+        None => return reject(cx, vp, "TypeError", "register needs a script URL")
+    };
+    let scope_url = scope_arg(cx, argc, vp, &doc_url);
+    if !same_origin(&script_url, &doc_url) ||
+       scope_url.map_default(false, |url| !same_origin(url, &doc_url)) {
+        return reject(cx, vp, "SecurityError", "A service worker has to be from the page's origin");
+    }
+
+    let script = url_to_str(copy script_url);
+    let scope = match move scope_url {
+        Some(move url) => url_to_str(move url),
+        None => default_scope(script)
+    };
+    if !scope_allowed(scope, script) {
+        return reject(cx, vp, "SecurityError", "The scope is outside the script's directory");
+    }
+    match (*content).service_workers.get(scope) {
+        Some(registration) if registration.script_url == script => {
+            JS_SET_RVAL(cx, vp, resolved_promise(cx, registration_to_jsval(cx, registration)));
+            return 1;
+        }
+        _ => ()
+    }
+
+    let installed = service_worker::install(move script_url, (*content).resource_task);
+    let to_js = fn~(cx: *JSContext, result: Result<ServiceWorker, ~str>, move scope, move script)
+        -> Result<JSVal, JSVal> {
+        match move result {
+            Ok(worker) => {
+                let registration = @Registration {
+                    scope: copy scope,
+                    script_url: copy script,
+                    worker: worker
+                };
+                unsafe {
+                    (*task_from_context(cx)).service_workers.add(registration);
+                }
+                Ok(registration_to_jsval(cx, registration))
+            }
+            Err(move e) => Err(new_error(cx, "TypeError", e))
+        }
+    };
+    return future_to_promise(cx, vp, move installed, move to_js);
+}
+
+// The worker controlling the page, which was chosen when it was loaded
+extern fn getController(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    *vp = match (*task_from_context(cx)).controller {
+        Some(registration) => worker_to_jsval(cx, registration),
+        None => JSVAL_NULL
+    };
+    return 1;
+}
+
+/// A new `ServiceWorkerContainer`, for `navigator.serviceWorker`.
+pub fn new_container(compartment: &bare_compartment) -> JSVal {
+    let container = utils::define_empty_prototype(~"ServiceWorkerContainer", None, compartment);
+    define_methods(compartment, container.ptr, ~[(~"register", register as *u8, 2)]);
+    let attrs = @~[
+        {name: compartment.add_name(~"controller"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getController, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        assert JS_DefineProperties(compartment.cx.ptr, container.ptr, specs) == 1;
+    });
+    compartment.register_class(utils::instance_jsclass(~"ServiceWorkerContainerInstance",
+                                                       null()));
+
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"ServiceWorkerContainerInstance",
+                                          ~"ServiceWorkerContainer",
+                                          compartment.global_obj.ptr));
+    RUST_OBJECT_TO_JSVAL(obj.ptr)
+}
@@ -0,0 +1,99 @@
+/*!
+`window.__servoDebug`, hooks for debugging servo itself from a page. Only
+builds with `--cfg debug_assertions` (`configure --enable-debug`) have it.
+
+`__servoDebug.dumpLayout()` returns the page's flow tree as text; see
+`layout::debug::dump_layout_tree`. `__servoDebug.memoryReport()` returns the
+bytes each part of servo is using, by name; see `memory::reporter`.
+*/
+
+use js::rust::{bare_compartment, methods};
+use js::{JS_SET_RVAL, JSVAL_NULL, JSPROP_ENUMERATE};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool};
+use js::jsapi::bindgen::{JS_NewObject, JS_DefineFunctions, JS_DefineProperty,
+                         JS_NewNumberValue};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use layout::layout_task;
+use memory::reporter::collect_report;
+use libc::c_uint;
+use ptr::null;
+
+use content::content_task::task_from_context;
+use utils::{domstring_to_jsval, str};
+
+#[cfg(debug_assertions)]
+extern fn dumpLayout(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let content = task_from_context(cx);
+    let rval = match (*content).query_layout(layout_task::DumpLayout) {
+        Ok(layout_task::LayoutDump(move dump)) => domstring_to_jsval(cx, &str(move dump)),
+        _ => JSVAL_NULL
+    };
+    JS_SET_RVAL(cx, vp, rval);
+    1
+}
+
+#[cfg(debug_assertions)]
+extern fn memoryReport(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let content = task_from_context(cx);
+    let document = match (*content).document {
+        Some(document) => document,
+        None => {
+            JS_SET_RVAL(cx, vp, JSVAL_NULL);
+            return 1;
+        }
+    };
+    let report = collect_report(document, &*content);
+    let obj = JS_NewObject(cx, null(), null(), null());
+    let sizes = ~[("domNodes", report.dom_nodes),
+                  ("layoutData", report.layout_data),
+                  ("styleData", report.style_data),
+                  ("jsHeap", report.js_heap),
+                  ("imageCache", report.image_cache),
+                  ("networkCache", report.network_cache),
+                  ("fontCache", report.font_cache)];
+    for sizes.each |size| {
+        let (name, bytes) = *size;
+        let val = JSVAL_NULL;
+        JS_NewNumberValue(cx, bytes as libc::c_double, ptr::to_unsafe_ptr(&val));
+        do str::as_c_str(name) |s| {
+            JS_DefineProperty(cx, obj, s, val,
+                              GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                              GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                              JSPROP_ENUMERATE);
+        }
+    }
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(obj));
+    1
+}
+
+#[cfg(debug_assertions)]
+pub fn init(compartment: &bare_compartment, window: *JSObject) {
+    let cx = compartment.cx.ptr;
+    let debug = JS_NewObject(cx, null(), null(), compartment.global_obj.ptr);
+
+    let methods = ~[{name: compartment.add_name(~"dumpLayout"),
+                     call: {op: dumpLayout, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()},
+                   {name: compartment.add_name(~"memoryReport"),
+                     call: {op: memoryReport, info: null()},
+                     nargs: 0,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(cx, debug, fns);
+    });
+
+    do str::as_c_str("__servoDebug") |s| {
+        JS_DefineProperty(cx, window, s, RUST_OBJECT_TO_JSVAL(debug),
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE);
+    }
+}
+
+#[cfg(not(debug_assertions))]
+pub fn init(_compartment: &bare_compartment, _window: *JSObject) {
+}
@@ -0,0 +1,143 @@
+/*!
+Deep copies of JS values with SpiderMonkey's structured clone, as used by
+`structuredClone`, `history.pushState` and IndexedDB.
+*/
+
+use js::{JSVAL_NULL, JSVAL_VOID, JSPROP_ENUMERATE, JSPROP_READONLY};
+use js::jsapi::{JSContext, JSVal};
+use js::jsapi::bindgen::{JS_WriteStructuredClone, JS_ReadStructuredClone,
+                            JS_ClearStructuredClone, JS_ClearPendingException,
+                            JS_SetPendingException, JS_NewObject, JS_NewArrayObject,
+                            JS_DefineProperty};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use ptr::null;
+use libc::size_t;
+use utils::{domstring_to_jsval, str};
+use bindings::transfer::check_transfer_list;
+
+const JS_STRUCTURED_CLONE_VERSION: u32 = 1;
+
+pub enum StructuredCloneError {
+    // The value holds something that can't be cloned, like a function or a
+    // DOM node
+    UnsupportedType,
+    // Something in the transfer list isn't an ArrayBuffer
+    NotTransferable,
+    // The same ArrayBuffer is in the transfer list twice
+    DuplicateTransfer,
+    // An ArrayBuffer in the transfer list was already transferred
+    DetachedBuffer
+}
+
+impl StructuredCloneError {
+    pure fn message(&self) -> ~str {
+        match *self {
+            UnsupportedType => ~"The value could not be cloned",
+            NotTransferable => ~"Only ArrayBuffers can be transferred",
+            DuplicateTransfer => ~"An ArrayBuffer can only be transferred once",
+            DetachedBuffer => ~"A detached ArrayBuffer can't be transferred"
+        }
+    }
+}
+
+impl StructuredCloneError: cmp::Eq {
+    pure fn eq(&self, other: &StructuredCloneError) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &StructuredCloneError) -> bool {
+        !self.eq(other)
+    }
+}
+
+pub unsafe fn structured_clone(cx: *JSContext, val: JSVal)
+    -> Result<JSVal, StructuredCloneError> {
+    structured_clone_with_transfer(cx, val, ~[])
+}
+
+/**
+Clones `val`, moving the ArrayBuffers in `transfer` into the copy. The
+originals are detached, leaving them empty, as `transfer_array_buffer`
+leaves one.
+*/
+pub unsafe fn structured_clone_with_transfer(cx: *JSContext, val: JSVal, transfer: &[JSVal])
+    -> Result<JSVal, StructuredCloneError> {
+    match check_transfer_list(cx, transfer) {
+        Ok(()) => (),
+        Err(err) => return Err(err)
+    }
+    let transferable = if transfer.is_empty() {
+        JSVAL_VOID
+    } else {
+        do vec::as_imm_buf(transfer) |buf, len| {
+            RUST_OBJECT_TO_JSVAL(JS_NewArrayObject(cx, len as libc::c_int, buf))
+        }
+    };
+
+    let data: *u64 = null();
+    let nbytes: size_t = 0;
+    if JS_WriteStructuredClone(cx, val, ptr::to_unsafe_ptr(&data), ptr::to_unsafe_ptr(&nbytes),
+                               null(), null(), transferable) == 0 {
+        // SpiderMonkey reported its own error; ours replaces it
+        JS_ClearPendingException(cx);
+        return Err(UnsupportedType);
+    }
+
+    let clone = JSVAL_NULL;
+    let ok = JS_ReadStructuredClone(cx, data, nbytes, JS_STRUCTURED_CLONE_VERSION,
+                                    ptr::to_unsafe_ptr(&clone), null(), null());
+    JS_ClearStructuredClone(data, nbytes);
+    if ok == 0 {
+        JS_ClearPendingException(cx);
+        return Err(UnsupportedType);
+    }
+    Ok(clone)
+}
+
+/**
+`val` as structured clone data, for keeping and reading back with
+`deserialize` later, as IndexedDB keeps values. Nothing can be transferred,
+so the data points at nothing in the JS heap.
+*/
+pub unsafe fn serialize(cx: *JSContext, val: JSVal) -> Result<~[u8], StructuredCloneError> {
+    let data: *u64 = null();
+    let nbytes: size_t = 0;
+    if JS_WriteStructuredClone(cx, val, ptr::to_unsafe_ptr(&data), ptr::to_unsafe_ptr(&nbytes),
+                               null(), null(), JSVAL_VOID) == 0 {
+        JS_ClearPendingException(cx);
+        return Err(UnsupportedType);
+    }
+    let bytes = vec::from_buf(data as *u8, nbytes as uint);
+    JS_ClearStructuredClone(data, nbytes);
+    Ok(move bytes)
+}
+
+/// A new copy of the value `serialize` made `bytes` from.
+pub unsafe fn deserialize(cx: *JSContext, bytes: &[u8]) -> Result<JSVal, StructuredCloneError> {
+    // The engine reads the data a word at a time, so it has to be aligned
+    let words = vec::from_elem((bytes.len() + 7) / 8, 0u64);
+    ptr::memcpy(vec::raw::to_ptr(words) as *mut u8, vec::raw::to_ptr(bytes), bytes.len());
+    let clone = JSVAL_NULL;
+    if JS_ReadStructuredClone(cx, vec::raw::to_ptr(words), bytes.len() as size_t,
+                              JS_STRUCTURED_CLONE_VERSION, ptr::to_unsafe_ptr(&clone),
+                              null(), null()) == 0 {
+        JS_ClearPendingException(cx);
+        return Err(UnsupportedType);
+    }
+    Ok(clone)
+}
+
+/// Throws a `DataCloneError` for `err` in `cx`.
+pub unsafe fn throw_data_clone_error(cx: *JSContext, err: StructuredCloneError) {
+    let exception = JS_NewObject(cx, null(), null(), null());
+    for [(~"name", ~"DataCloneError"), (~"message", err.message())].each |pair| {
+        let (ref name, ref value) = *pair;
+        do str::as_c_str(*name) |s| {
+            JS_DefineProperty(cx, exception, s, domstring_to_jsval(cx, &str(copy *value)),
+                              GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                              GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                              JSPROP_ENUMERATE | JSPROP_READONLY);
+        }
+    }
+    JS_SetPendingException(cx, RUST_OBJECT_TO_JSVAL(exception));
+}
@@ -0,0 +1,68 @@
+/*!
+Symbols. `Symbol` and the well-known symbols are SpiderMonkey's, set up on
+the global with the standard classes; these are for bindings that make
+symbols or give their objects symbol-keyed methods, like
+`[Symbol.iterator]` so that `for-of` works on them.
+*/
+
+use js::JSVAL_VOID;
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSNative, jsid};
+use js::jsapi::bindgen::{JS_NewSymbol, JS_GetWellKnownSymbol, JS_NewStringCopyN,
+                         JS_NewFunction, JS_GetFunctionObject, JS_DefinePropertyById,
+                         JS_GetPropertyById, JS_CallFunctionValue};
+use js::glue::bindgen::*;
+use libc::c_uint;
+use ptr::null;
+
+/// The well-known symbols bindings use, by their `JS::SymbolCode`.
+pub enum WellKnownSymbol {
+    SymbolIterator = 0,
+    SymbolSpecies = 4,
+    SymbolHasInstance = 5,
+    SymbolToPrimitive = 7,
+    SymbolToStringTag = 8,
+    SymbolAsyncIterator = 10,
+}
+
+/// A new symbol, like `Symbol(description)`.
+pub unsafe fn js_symbol(cx: *JSContext, description: Option<&str>) -> JSVal {
+    let description = match description {
+        Some(d) => do str::as_buf(d) |buf, len| {
+            JS_NewStringCopyN(cx, cast::reinterpret_cast(&buf), len as libc::size_t)
+        },
+        None => null()
+    };
+    RUST_SYMBOL_TO_JSVAL(JS_NewSymbol(cx, description))
+}
+
+/// The property key of a well-known symbol.
+pub unsafe fn well_known_symbol_id(cx: *JSContext, which: WellKnownSymbol) -> jsid {
+    RUST_SYMBOL_TO_JSID(JS_GetWellKnownSymbol(cx, which as u32))
+}
+
+/// Defines a method on `obj` keyed by a well-known symbol. `name` is the
+/// function's name, like `[Symbol.iterator]`.
+pub unsafe fn define_symbol_method(cx: *JSContext, obj: *JSObject, which: WellKnownSymbol,
+                                   name: &str, op: JSNative, nargs: c_uint) -> bool {
+    let fun = do str::as_c_str(name) |name| { JS_NewFunction(cx, op, nargs, 0, null(), name) };
+    if fun.is_null() {
+        return false;
+    }
+    JS_DefinePropertyById(cx, obj, well_known_symbol_id(cx, which),
+                          RUST_OBJECT_TO_JSVAL(JS_GetFunctionObject(fun)), null(), null(), 0) == 1
+}
+
+/**
+Sets `rval` to an iterator over the elements of `array`, made by calling
+its own `[Symbol.iterator]`. Bindings that return arrays from `entries()`
+and the like use this for their iterators too.
+*/
+pub unsafe fn iterate_array(cx: *JSContext, array: JSVal, rval: *JSVal) -> JSBool {
+    let iterator_fn = JSVAL_VOID;
+    let array = RUST_JSVAL_TO_OBJECT(array);
+    if JS_GetPropertyById(cx, array, well_known_symbol_id(cx, SymbolIterator),
+                          ptr::to_unsafe_ptr(&iterator_fn)) == 0 {
+        return 0;
+    }
+    JS_CallFunctionValue(cx, array, iterator_fn, 0, null(), rval)
+}
@@ -0,0 +1,278 @@
+/*!
+The `TextDecoder` and `TextEncoder` constructors. A decoder keeps its
+`dom::text_decoder::TextDecoder` in its reserved slot; an encoder has
+nothing to keep, since it only ever writes UTF-8.
+*/
+
+use js::rust::{bare_compartment, methods};
+use js::{JS_ARGV, JSPROP_ENUMERATE, JSPROP_SHARED, JSVAL_VOID, JS_THIS_OBJECT,
+         JS_SET_RVAL, JSPROP_NATIVE_ACCESSORS};
+use js::jsapi::{JSContext, JSVal, JSObject, JSBool, JSFreeOp};
+use js::jsapi::bindgen::{JS_GetReservedSlot, JS_SetReservedSlot, JS_DefineFunctions,
+                         JS_DefineProperties, JS_DefineProperty, JS_GetProperty, JS_NewObject,
+                         JS_ValueToString, JS_GetStringCharsZAndLength, JS_ValueToBoolean,
+                         JS_IsUint8Array,
+                         JS_GetArrayBufferViewData, JS_GetArrayBufferViewByteLength};
+use js::glue::bindgen::*;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
+use ptr::null;
+use libc::c_uint;
+use utils::{domstring_to_jsval, rust_box, squirrel_away, jsval_to_str, str, get_compartment,
+            throw_error, unicode_to_jsval};
+use typed_array::{with_uint8_slice, create_uint8_array};
+use dom::text_decoder;
+use dom::text_decoder::TextDecoder;
+
+unsafe fn unwrap(obj: *JSObject) -> *rust_box<TextDecoder> {
+    let val = JS_GetReservedSlot(obj, 0);
+    cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
+}
+
+// The code units of a JS value, as a string
+unsafe fn string_units(cx: *JSContext, val: JSVal) -> Option<~[u16]> {
+    let jsstr = JS_ValueToString(cx, val);
+    if jsstr.is_null() {
+        return None;
+    }
+    let len = 0;
+    let chars = JS_GetStringCharsZAndLength(cx, jsstr, ptr::to_unsafe_ptr(&len));
+    if chars.is_null() {
+        return None;
+    }
+    Some(vec::raw::from_buf_raw(chars, len as uint))
+}
+
+unsafe fn get_bool(cx: *JSContext, obj: *JSObject, name: &str) -> bool {
+    if obj.is_null() {
+        return false;
+    }
+    let val = JSVAL_VOID;
+    do str::as_c_str(name) |s| {
+        JS_GetProperty(cx, obj, s, ptr::to_unsafe_ptr(&val));
+    }
+    let b = 0;
+    JS_ValueToBoolean(cx, val, ptr::to_unsafe_ptr(&b));
+    b != 0
+}
+
+extern fn TextDecoder_constructor(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+    let label = if argc > 0 && RUST_JSVAL_IS_VOID(*argv) == 0 {
+        match jsval_to_str(cx, *argv) {
+            Ok(move label) => move label,
+            Err(()) => return 0
+        }
+    } else {
+        ~"utf-8"
+    };
+    let options = if argc > 1 && RUST_JSVAL_IS_OBJECT(*ptr::offset(argv, 1)) == 1 {
+        RUST_JSVAL_TO_OBJECT(*ptr::offset(argv, 1))
+    } else {
+        null()
+    };
+    let decoder = match TextDecoder(label, get_bool(cx, options, "fatal"),
+                                    get_bool(cx, options, "ignoreBOM")) {
+        Some(move decoder) => move decoder,
+        None => return throw_error(cx, "RangeError",
+                                   fmt!("\"%s\" isn't an encoding TextDecoder knows", label))
+    };
+
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"TextDecoderInstance", ~"TextDecoder",
+                                          compartment.global_obj.ptr));
+    let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(@move decoder));
+    JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(obj.ptr));
+    return 1;
+}
+
+extern fn decode(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    let decoder = &(*unwrap(obj)).payload;
+    // No buffer decodes as no bytes
+    let input = if argc > 0 { *JS_ARGV(cx, vp) } else { JSVAL_VOID };
+    let decoded = if RUST_JSVAL_IS_VOID(input) == 1 {
+        decoder.decode([])
+    } else if RUST_JSVAL_IS_OBJECT(input) == 1 && RUST_JSVAL_IS_NULL(input) == 0 {
+        match with_uint8_slice(cx, RUST_JSVAL_TO_OBJECT(input), |bytes| decoder.decode(bytes)) {
+            Some(move decoded) => move decoded,
+            None => return throw_error(cx, "TypeError",
+                                       "decode needs an ArrayBuffer or a view of one")
+        }
+    } else {
+        return throw_error(cx, "TypeError", "decode needs an ArrayBuffer or a view of one");
+    };
+    match move decoded {
+        Ok(move text) => {
+            JS_SET_RVAL(cx, vp, unicode_to_jsval(cx, text));
+            1
+        }
+        Err(e) => throw_error(cx, "TypeError", e.message)
+    }
+}
+
+extern fn getDecoderEncoding(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = domstring_to_jsval(cx, &str((*unwrap(obj)).payload.encoding.name().to_str()));
+    return 1;
+}
+
+extern fn getFatal(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = RUST_BOOLEAN_TO_JSVAL((*unwrap(obj)).payload.fatal as JSBool);
+    return 1;
+}
+
+extern fn getIgnoreBOM(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = RUST_BOOLEAN_TO_JSVAL((*unwrap(obj)).payload.ignore_bom as JSBool);
+    return 1;
+}
+
+extern fn finalize(_fop: *JSFreeOp, obj: *JSObject) {
+    #debug("text decoder finalize!");
+    unsafe {
+        let val = JS_GetReservedSlot(obj, 0);
+        let _: @TextDecoder = cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val));
+    }
+}
+
+/* TextEncoder */
+
+extern fn TextEncoder_constructor(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let compartment = get_compartment(cx);
+    let obj = result::unwrap(
+        compartment.new_object_with_proto(~"TextEncoderInstance", ~"TextEncoder",
+                                          compartment.global_obj.ptr));
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(obj.ptr));
+    return 1;
+}
+
+// The first argument's code units; a missing one is the empty string
+unsafe fn input_units(cx: *JSContext, argc: c_uint, vp: *JSVal) -> Option<~[u16]> {
+    if argc > 0 && RUST_JSVAL_IS_VOID(*JS_ARGV(cx, vp)) == 0 {
+        string_units(cx, *JS_ARGV(cx, vp))
+    } else {
+        Some(~[])
+    }
+}
+
+extern fn encode(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let units = match input_units(cx, argc, vp) {
+        Some(move units) => move units,
+        None => return 0
+    };
+    JS_SET_RVAL(cx, vp, create_uint8_array(cx, text_decoder::encode(units)));
+    1
+}
+
+extern fn encodeInto(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let units = match input_units(cx, argc, vp) {
+        Some(move units) => move units,
+        None => return 0
+    };
+    let dest = if argc > 1 { *ptr::offset(JS_ARGV(cx, vp), 1) } else { JSVAL_VOID };
+    if RUST_JSVAL_IS_OBJECT(dest) == 0 || RUST_JSVAL_IS_NULL(dest) == 1 ||
+       JS_IsUint8Array(RUST_JSVAL_TO_OBJECT(dest), cx) == 0 {
+        return throw_error(cx, "TypeError", "encodeInto needs a Uint8Array to write to");
+    }
+    let array = RUST_JSVAL_TO_OBJECT(dest);
+    let mut bytes = vec::from_elem(JS_GetArrayBufferViewByteLength(array, cx) as uint, 0u8);
+    let (read, written) = text_decoder::encode_into(units, bytes);
+    // Only what was written is copied back; the rest of the array is left
+    // as it was
+    ptr::memcpy(JS_GetArrayBufferViewData(array, cx) as *mut u8, vec::raw::to_ptr(bytes),
+                written);
+
+    let result = JS_NewObject(cx, null(), null(), null());
+    for [(~"read", read), (~"written", written)].each |pair| {
+        let (ref key, n) = *pair;
+        do str::as_c_str(*key) |s| {
+            JS_DefineProperty(cx, result, s, RUST_INT_TO_JSVAL(n as libc::c_int),
+                              GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                              GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                              JSPROP_ENUMERATE);
+        }
+    }
+    JS_SET_RVAL(cx, vp, RUST_OBJECT_TO_JSVAL(result));
+    1
+}
+
+extern fn getEncoderEncoding(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    *vp = domstring_to_jsval(cx, &str(~"utf-8"));
+    return 1;
+}
+
+pub fn init(compartment: &bare_compartment) {
+    let obj = utils::define_constructor(~"TextDecoder", None, TextDecoder_constructor,
+                                        compartment);
+    let methods = ~[{name: compartment.add_name(~"decode"),
+                     call: {op: decode, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj.ptr, fns);
+    });
+    let attrs = @~[
+        {name: compartment.add_name(~"encoding"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getDecoderEncoding, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"fatal"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getFatal, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"ignoreBOM"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getIgnoreBOM, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        assert JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs) == 1;
+    });
+    compartment.register_class(utils::instance_jsclass(~"TextDecoderInstance", finalize));
+
+    let obj = utils::define_constructor(~"TextEncoder", None, TextEncoder_constructor,
+                                        compartment);
+    let methods = ~[{name: compartment.add_name(~"encode"),
+                     call: {op: encode, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"encodeInto"),
+                     call: {op: encodeInto, info: null()},
+                     nargs: 2,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj.ptr, fns);
+    });
+    let attrs = @~[
+        {name: compartment.add_name(~"encoding"),
+         tinyid: 0,
+         flags: (JSPROP_SHARED | JSPROP_ENUMERATE | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getEncoderEncoding, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        assert JS_DefineProperties(compartment.cx.ptr, obj.ptr, specs) == 1;
+    });
+    compartment.register_class(utils::instance_jsclass(~"TextEncoderInstance", null()));
+}
@@ -0,0 +1,61 @@
+/*!
+Moving ArrayBuffers from one owner to another, as a transfer list asks.
+The memory goes to a new buffer and the original is detached: its length
+becomes 0 and typed arrays over it see no elements.
+*/
+
+use js::jsapi::{JSContext, JSVal, JSObject};
+use js::jsapi::bindgen::{JS_IsArrayBufferObject, JS_IsDetachedArrayBufferObject,
+                         JS_GetArrayBufferByteLength, JS_StealArrayBufferContents,
+                         JS_NewArrayBufferWithContents, JS_ClearPendingException};
+use js::glue::bindgen::*;
+use bindings::structured_clone::{StructuredCloneError, NotTransferable, DuplicateTransfer,
+                                 DetachedBuffer};
+
+/**
+Checks that everything in `transfer` can be transferred: each has to be
+an ArrayBuffer that isn't detached, and none can be there twice.
+*/
+pub unsafe fn check_transfer_list(cx: *JSContext, transfer: &[JSVal])
+    -> Result<(), StructuredCloneError> {
+    for transfer.eachi |i, buffer| {
+        if RUST_JSVAL_IS_OBJECT(*buffer) == 0 || RUST_JSVAL_IS_NULL(*buffer) == 1 ||
+           JS_IsArrayBufferObject(RUST_JSVAL_TO_OBJECT(*buffer), cx) == 0 {
+            return Err(NotTransferable);
+        }
+        if JS_IsDetachedArrayBufferObject(RUST_JSVAL_TO_OBJECT(*buffer)) == 1 {
+            return Err(DetachedBuffer);
+        }
+        if vec::view(transfer, 0, i).contains(buffer) {
+            return Err(DuplicateTransfer);
+        }
+    }
+    Ok(())
+}
+
+/**
+A new ArrayBuffer with the memory of `buf`, which is left detached. No
+bytes are copied; the memory changes owner.
+*/
+pub unsafe fn transfer_array_buffer(cx: *JSContext, buf: *JSObject)
+    -> Result<*JSObject, StructuredCloneError> {
+    if JS_IsArrayBufferObject(buf, cx) == 0 {
+        return Err(NotTransferable);
+    }
+    if JS_IsDetachedArrayBufferObject(buf) == 1 {
+        return Err(DetachedBuffer);
+    }
+    let len = JS_GetArrayBufferByteLength(buf, cx);
+    // Stealing the contents detaches the buffer they came from
+    let contents = JS_StealArrayBufferContents(cx, buf);
+    if contents.is_null() {
+        JS_ClearPendingException(cx);
+        return Err(NotTransferable);
+    }
+    let moved = JS_NewArrayBufferWithContents(cx, len as libc::size_t, contents);
+    if moved.is_null() {
+        JS_ClearPendingException(cx);
+        return Err(NotTransferable);
+    }
+    Ok(moved)
+}
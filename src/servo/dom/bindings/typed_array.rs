@@ -0,0 +1,55 @@
+/*!
+Reading and making ArrayBuffers and typed arrays. The constructors
+themselves (`Uint8Array`, `Float32Array`, `DataView` and the rest) are
+SpiderMonkey's, set up on the global with the standard classes; these are
+for bindings that take binary data from script or hand it back.
+*/
+
+use js::jsapi::{JSContext, JSVal, JSObject};
+use js::jsapi::bindgen::{JS_IsArrayBufferObject, JS_GetArrayBufferByteLength,
+                         JS_GetArrayBufferData, JS_NewArrayBuffer,
+                         JS_IsArrayBufferViewObject, JS_GetArrayBufferViewByteLength,
+                         JS_GetArrayBufferViewData, JS_NewUint8Array, JS_GetUint8ArrayData};
+use js::glue::bindgen::*;
+
+/// Whether `obj` is binary data: an ArrayBuffer, a typed array or a DataView.
+pub unsafe fn is_buffer_source(cx: *JSContext, obj: *JSObject) -> bool {
+    JS_IsArrayBufferObject(obj, cx) == 1 || JS_IsArrayBufferViewObject(obj, cx) == 1
+}
+
+/**
+Calls `f` with the bytes of `obj`, if it's an ArrayBuffer or a view of one.
+A view gives only the bytes it covers, whatever its element type. The
+slice is only good during the call, since anything that runs script can
+detach the buffer.
+*/
+pub unsafe fn with_uint8_slice<R>(cx: *JSContext, obj: *JSObject,
+                                  f: fn(&[u8]) -> R) -> Option<R> {
+    let (data, len) = if JS_IsArrayBufferObject(obj, cx) == 1 {
+        (JS_GetArrayBufferData(obj, cx), JS_GetArrayBufferByteLength(obj, cx))
+    } else if JS_IsArrayBufferViewObject(obj, cx) == 1 {
+        (JS_GetArrayBufferViewData(obj, cx) as *u8, JS_GetArrayBufferViewByteLength(obj, cx))
+    } else {
+        return None;
+    };
+    Some(vec::raw::buf_as_slice(data, len as uint, f))
+}
+
+/// A copy of the bytes of `obj`, as `with_uint8_slice` gives them.
+pub unsafe fn buffer_source_bytes(cx: *JSContext, obj: *JSObject) -> Option<~[u8]> {
+    with_uint8_slice(cx, obj, |bytes| vec::from_slice(bytes))
+}
+
+/// A new ArrayBuffer holding a copy of `data`.
+pub unsafe fn create_array_buffer(cx: *JSContext, data: &[u8]) -> JSVal {
+    let buffer = JS_NewArrayBuffer(cx, data.len() as u32);
+    ptr::memcpy(JS_GetArrayBufferData(buffer, cx), vec::raw::to_ptr(data), data.len());
+    RUST_OBJECT_TO_JSVAL(buffer)
+}
+
+/// A new Uint8Array holding a copy of `data`.
+pub unsafe fn create_uint8_array(cx: *JSContext, data: &[u8]) -> JSVal {
+    let array = JS_NewUint8Array(cx, data.len() as u32);
+    ptr::memcpy(JS_GetUint8ArrayData(array, cx), vec::raw::to_ptr(data), data.len());
+    RUST_OBJECT_TO_JSVAL(array)
+}
@@ -0,0 +1,71 @@
+use js::rust::{bare_compartment, methods};
+use js::{JS_ARGV, JSVAL_NULL, JS_SET_RVAL};
+use js::jsapi::{JSContext, JSVal, JSBool};
+use js::jsapi::bindgen::{JS_DefineFunctions, JS_ReportError};
+use js::glue::bindgen::*;
+use ptr::null;
+use libc::c_uint;
+use utils::{domstring_to_jsval, jsval_to_str, str};
+use content::content_task::task_from_context;
+use bindings::blob::unwrap_blob;
+use resource::resource_task::{RegisterBlobURL, RevokeBlobURL};
+use resource::blob_url_store::new_blob_url;
+use util::url::origin;
+
+unsafe fn report_error(cx: *JSContext, msg: &str) -> JSBool {
+    do str::as_c_str(msg) |s| {
+        JS_ReportError(cx, s);
+    }
+    0
+}
+
+extern fn createObjectURL(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let blob = match if argc > 0 { unwrap_blob(cx, *JS_ARGV(cx, vp)) } else { None } {
+        Some(move blob) => move blob,
+        None => return report_error(cx, "URL.createObjectURL needs a Blob")
+    };
+
+    let content = task_from_context(cx);
+    let url = new_blob_url(match (*content).doc_url {
+        Some(ref doc_url) => origin(doc_url),
+        None => ~"null"
+    });
+    (*content).resource_task.send(RegisterBlobURL(copy url, blob.blob().clone()));
+
+    JS_SET_RVAL(cx, vp, domstring_to_jsval(cx, &str(move url)));
+    return 1;
+}
+
+extern fn revokeObjectURL(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    if argc < 1 {
+        return report_error(cx, "URL.revokeObjectURL needs a URL");
+    }
+    match jsval_to_str(cx, *JS_ARGV(cx, vp)) {
+        Ok(move url) => {
+            if url.starts_with("blob:") {
+                (*task_from_context(cx)).resource_task.send(RevokeBlobURL(move url));
+            }
+        }
+        Err(()) => return 0
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+//TODO: new URL(...)
+pub fn init(compartment: &bare_compartment) {
+    let obj = utils::define_empty_prototype(~"URL", None, compartment);
+    let methods = ~[{name: compartment.add_name(~"createObjectURL"),
+                     call: {op: createObjectURL, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"revokeObjectURL"),
+                     call: {op: revokeObjectURL, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()}];
+    vec::as_imm_buf(methods, |fns, _len| {
+        JS_DefineFunctions(compartment.cx.ptr, obj.ptr, fns);
+    });
+}
@@ -1,16 +1,23 @@
 use js::rust::{compartment, bare_compartment, methods};
-use js::{JS_ARGV, JSCLASS_HAS_RESERVED_SLOTS, JSPROP_ENUMERATE, JSPROP_SHARED, JSVAL_NULL,
-            JS_THIS_OBJECT, JS_SET_RVAL};
+use js::{JS_ARGV, JSCLASS_HAS_RESERVED_SLOTS, JSPROP_ENUMERATE, JSPROP_SHARED, JSPROP_READONLY,
+            JSVAL_NULL, JS_THIS_OBJECT, JS_SET_RVAL};
 use js::jsapi::{JSContext, JSVal, JSObject, JSBool, jsid, JSClass, JSFreeOp};
 use js::jsapi::bindgen::{JS_ValueToString, JS_GetStringCharsZAndLength, JS_ReportError,
                             JS_GetReservedSlot, JS_SetReservedSlot, JS_NewStringCopyN,
                             JS_DefineFunctions, JS_DefineProperty, JS_GetContextPrivate,
-                            JS_GetClass, JS_GetPrototype};
+                            JS_GetClass, JS_GetPrototype, JS_NewObject, JS_DefineFunction,
+                            JS_SetProperty, JS_GetProperty, JS_SetPendingException,
+                            JS_NewUCStringCopyN, JS_ValueToNumber};
 use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB, ENUMERATE_STUB, CONVERT_STUB,
                   RESOLVE_STUB};
 use js::glue::bindgen::*;
 use ptr::null;
+use libc::c_uint;
+use geom::point::Point2D;
 use content::content_task::{Content, task_from_context};
+use dom::event_target::{bubbles, cancelable};
+use dom::text_decoder::decode_utf16_units;
+use util::url::origin;
 
 enum DOMString {
     str(~str),
@@ -69,6 +76,270 @@ unsafe fn domstring_to_jsval(cx: *JSContext, str: &DOMString) -> JSVal {
     }
 }
 
+/// A JS string of `s`, which unlike `domstring_to_jsval` keeps what's
+/// outside Latin-1.
+pub unsafe fn unicode_to_jsval(cx: *JSContext, s: &str) -> JSVal {
+    let units = str::to_utf16(s);
+    do vec::as_imm_buf(units) |buf, len| {
+        RUST_STRING_TO_JSVAL(JS_NewUCStringCopyN(cx, buf, len as libc::size_t))
+    }
+}
+
+/// A JS value converted to a string, with lone surrogates replaced.
+pub unsafe fn jsval_to_unicode(cx: *JSContext, val: JSVal) -> Option<~str> {
+    let jsstr = JS_ValueToString(cx, val);
+    if jsstr.is_null() {
+        return None;
+    }
+    let len = 0;
+    let chars = JS_GetStringCharsZAndLength(cx, jsstr, ptr::to_unsafe_ptr(&len));
+    if chars.is_null() {
+        return None;
+    }
+    match decode_utf16_units(vec::raw::from_buf_raw(chars, len as uint), false) {
+        Ok(move s) => Some(move s),
+        Err(_) => None
+    }
+}
+
+/// The argument at `i` of a native as a string, if it was given and isn't
+/// undefined.
+pub unsafe fn string_arg(cx: *JSContext, argc: c_uint, vp: *JSVal, i: uint) -> Option<~str> {
+    if argc as uint > i && RUST_JSVAL_IS_VOID(*ptr::offset(JS_ARGV(cx, vp), i)) == 0 {
+        jsval_to_unicode(cx, *ptr::offset(JS_ARGV(cx, vp), i))
+    } else {
+        None
+    }
+}
+
+/// `v` as a whole number of px, for a scroll offset: NaN and infinities
+/// are 0. None if converting it threw.
+pub unsafe fn jsval_to_px(cx: *JSContext, v: JSVal) -> Option<int> {
+    let number = 0f64;
+    if JS_ValueToNumber(cx, v, ptr::to_unsafe_ptr(&number)) == 0 {
+        return None;
+    }
+    Some(if f64::is_finite(number) { number as int } else { 0 })
+}
+
+/// An error object, as a DOMException would be: one with a `name` and a
+/// `message`. The message can have anything in it, not just Latin-1.
+pub unsafe fn new_error(cx: *JSContext, name: &str, message: &str) -> JSVal {
+    let error = JS_NewObject(cx, null(), null(), null());
+    for [(~"name", name.to_str()), (~"message", message.to_str())].each |pair| {
+        let (ref key, ref value) = *pair;
+        do str::as_c_str(*key) |s| {
+            JS_DefineProperty(cx, error, s, unicode_to_jsval(cx, *value),
+                              GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                              GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                              JSPROP_ENUMERATE | JSPROP_READONLY);
+        }
+    }
+    RUST_OBJECT_TO_JSVAL(error)
+}
+
+/// Throws a `name` error. Returns 0, for the native that threw it to return.
+pub unsafe fn throw_error(cx: *JSContext, name: &str, message: &str) -> JSBool {
+    JS_SetPendingException(cx, new_error(cx, name, message));
+    0
+}
+
+unsafe fn define_event_value(cx: *JSContext, event: *JSObject, name: &str, val: JSVal) {
+    do str::as_c_str(name) |name| {
+        JS_DefineProperty(cx, event, name, val,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE);
+    }
+}
+
+/// A plain event object with `type` and `target` set. Dispatch keeps
+/// `eventPhase` and `currentTarget` up to date, and `cancelBubble` is set
+/// once propagation has been stopped.
+pub unsafe fn new_event(cx: *JSContext, kind: &str, target: JSVal) -> *JSObject {
+    let event = JS_NewObject(cx, null(), null(), null());
+    init_event(cx, event, kind, target);
+    event
+}
+
+/// Gives `event` the members and methods of an event. Events the engine
+/// fires bubble and can be cancelled as their type says.
+pub unsafe fn init_event(cx: *JSContext, event: *JSObject, kind: &str, target: JSVal) {
+    define_event_value(cx, event, "type", domstring_to_jsval(cx, &str(str::from_slice(kind))));
+    define_event_value(cx, event, "target", target);
+    define_event_value(cx, event, "currentTarget", JSVAL_NULL);
+    define_event_value(cx, event, "eventPhase", RUST_INT_TO_JSVAL(0));
+    define_event_value(cx, event, "bubbles", RUST_BOOLEAN_TO_JSVAL(bubbles(kind) as JSBool));
+    define_event_value(cx, event, "cancelable",
+                       RUST_BOOLEAN_TO_JSVAL(cancelable(kind) as JSBool));
+    define_event_value(cx, event, "defaultPrevented", RUST_BOOLEAN_TO_JSVAL(0));
+    define_event_value(cx, event, "cancelBubble", RUST_BOOLEAN_TO_JSVAL(0));
+    // Not enumerable, as it's only there for dispatch
+    do str::as_c_str(STOP_IMMEDIATE_PROPERTY) |name| {
+        JS_DefineProperty(cx, event, name, RUST_BOOLEAN_TO_JSVAL(0),
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          0);
+    }
+    do str::as_c_str("preventDefault") |name| {
+        JS_DefineFunction(cx, event, name, preventDefault, 0, 0);
+    }
+    do str::as_c_str("stopPropagation") |name| {
+        JS_DefineFunction(cx, event, name, stopPropagation, 0, 0);
+    }
+    do str::as_c_str("stopImmediatePropagation") |name| {
+        JS_DefineFunction(cx, event, name, stopImmediatePropagation, 0, 0);
+    }
+}
+
+/// Where an event remembers that `stopImmediatePropagation()` was called.
+pub const STOP_IMMEDIATE_PROPERTY: &static/str = "__stopImmediatePropagation";
+
+unsafe fn get_flag(cx: *JSContext, obj: *JSObject, name: &str) -> bool {
+    let val = JSVAL_NULL;
+    do str::as_c_str(name) |name| {
+        JS_GetProperty(cx, obj, name, ptr::to_unsafe_ptr(&val));
+    }
+    val == RUST_BOOLEAN_TO_JSVAL(1)
+}
+
+unsafe fn set_flag_to(cx: *JSContext, obj: *JSObject, name: &str, value: bool) {
+    let val = RUST_BOOLEAN_TO_JSVAL(value as JSBool);
+    do str::as_c_str(name) |name| {
+        JS_SetProperty(cx, obj, name, ptr::to_unsafe_ptr(&val));
+    }
+}
+
+unsafe fn set_flag(cx: *JSContext, obj: *JSObject, name: &str) {
+    set_flag_to(cx, obj, name, true);
+}
+
+/// Clears the flags `stopPropagation()` and `stopImmediatePropagation()`
+/// set, so that an event dispatched again reaches its listeners.
+pub unsafe fn reset_propagation(cx: *JSContext, event: *JSObject) {
+    set_flag_to(cx, event, "cancelBubble", false);
+    set_flag_to(cx, event, STOP_IMMEDIATE_PROPERTY, false);
+}
+
+extern fn stopPropagation(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    set_flag(cx, obj, "cancelBubble");
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn stopImmediatePropagation(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    set_flag(cx, obj, "cancelBubble");
+    set_flag(cx, obj, STOP_IMMEDIATE_PROPERTY);
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn preventDefault(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, vp);
+    if obj.is_null() {
+        return 0;
+    }
+    if (*task_from_context(cx)).in_passive_listener {
+        bindings::console::report_warning(cx,
+                                          "Ignoring preventDefault() in a passive event listener");
+    } else if get_flag(cx, obj, "cancelable") {
+        set_flag(cx, obj, "defaultPrevented");
+    }
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+/// A `wheel` event for a scroll by `delta` px.
+pub unsafe fn new_wheel_event(cx: *JSContext, target: JSVal, delta: Point2D<int>) -> *JSObject {
+    let event = new_event(cx, "wheel", target);
+    let members = [(~"deltaX", delta.x), (~"deltaY", delta.y), (~"deltaZ", 0),
+                   // DOM_DELTA_PIXEL
+                   (~"deltaMode", 0)];
+    for members.each |member| {
+        let (ref name, value) = *member;
+        do str::as_c_str(*name) |name| {
+            JS_DefineProperty(cx, event, name, RUST_INT_TO_JSVAL(value as libc::c_int),
+                              GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                              GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                              JSPROP_ENUMERATE);
+        }
+    }
+    event
+}
+
+/// Like new_event, with a `relatedTarget` too, as focus events have.
+pub unsafe fn new_related_event(cx: *JSContext, kind: &str, target: JSVal,
+                                related_target: JSVal) -> *JSObject {
+    let event = new_event(cx, kind, target);
+    do str::as_c_str("relatedTarget") |name| {
+        JS_DefineProperty(cx, event, name, related_target,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE);
+    }
+    event
+}
+
+/// An `input` event, with the text that was inserted, if any, as `data`.
+pub unsafe fn new_input_event(cx: *JSContext, target: JSVal, data: Option<~str>) -> *JSObject {
+    let event = new_event(cx, "input", target);
+    let data = match move data {
+        Some(move data) => domstring_to_jsval(cx, &str(move data)),
+        None => JSVAL_NULL
+    };
+    do str::as_c_str("data") |name| {
+        JS_DefineProperty(cx, event, name, data,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE);
+    }
+    event
+}
+
+/**
+Where the page keeps what it stores, IndexedDB's databases and the Cache
+API's responses, and the origin they're kept under. That's the storage
+directory, or `servo/storage` in the user's data directory if there isn't
+one, made so that only the user can get into it. Each `file:` page is an
+origin of its own, keyed by its path; other opaque origins get None, as
+they can't keep anything, and so does every page if there's nowhere to
+keep it.
+*/
+pub unsafe fn storage_location(cx: *JSContext) -> Option<(Path, ~str)> {
+    let content = task_from_context(cx);
+    let dir = match (*content).opts.storage_dir {
+        Some(ref dir) => Path(*dir),
+        None => match user_data_dir() {
+            Some(ref data_dir) => data_dir.push_many([~"servo", ~"storage"]),
+            None => return None
+        }
+    };
+    if !os::path_is_dir(&dir) && !os::mkdir_recursive(&dir, 0o700) {
+        return None;
+    }
+    match (*content).doc_url {
+        Some(ref url) if url.scheme == ~"file" => Some((move dir, ~"file://" + url.path)),
+        Some(ref url) if origin(url) != ~"null" => Some((move dir, origin(url))),
+        _ => None
+    }
+}
+
+// $XDG_DATA_HOME, or ~/.local/share
+fn user_data_dir() -> Option<Path> {
+    match os::getenv("XDG_DATA_HOME") {
+        Some(move dir) if !dir.is_empty() => Some(Path(dir)),
+        _ => os::homedir().map(|home| home.push_many([~".local", ~"share"]))
+    }
+}
+
 pub fn get_compartment(cx: *JSContext) -> compartment {
     unsafe {
         let content = task_from_context(cx);
@@ -96,6 +367,12 @@ extern fn has_instance(_cx: *JSContext, obj: **JSObject, v: *JSVal, bp: *mut JSB
 }
 
 pub fn prototype_jsclass(name: ~str) -> fn(compartment: &bare_compartment) -> JSClass {
+    constructor_jsclass(move name, null())
+}
+
+/// A prototype class whose objects can be called with `new`.
+pub fn constructor_jsclass(name: ~str, construct: *u8)
+    -> fn(compartment: &bare_compartment) -> JSClass {
     |compartment: &bare_compartment, move name| {
         {name: compartment.add_name(copy name),
          flags: 0,
@@ -110,7 +387,7 @@ pub fn prototype_jsclass(name: ~str) -> fn(compartment: &bare_compartment) -> JS
          checkAccess: null(),
          call: null(),
          hasInstance: has_instance,
-         construct: null(),
+         construct: construct,
          trace: null(),
          reserved: (null(), null(), null(), null(), null(),  // 05
                     null(), null(), null(), null(), null(),  // 10
@@ -155,7 +432,13 @@ pub fn instance_jsclass(name: ~str, finalize: *u8)
 // FIXME: A lot of string copies here
 pub fn define_empty_prototype(name: ~str, proto: Option<~str>, compartment: &bare_compartment)
     -> js::rust::jsobj {
-    compartment.register_class(utils::prototype_jsclass(copy name));
+    define_constructor(move name, proto, null(), compartment)
+}
+
+/// Like define_empty_prototype, but `new Name(...)` calls the `construct` hook.
+pub fn define_constructor(name: ~str, proto: Option<~str>, construct: *u8,
+                          compartment: &bare_compartment) -> js::rust::jsobj {
+    compartment.register_class(utils::constructor_jsclass(copy name, construct));
 
     //TODO error checking
     let obj = result::unwrap(
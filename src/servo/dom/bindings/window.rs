@@ -1,19 +1,26 @@
 use js::rust::{bare_compartment, methods};
 use js::{JS_ARGV, JSCLASS_HAS_RESERVED_SLOTS, JSPROP_ENUMERATE, JSPROP_SHARED, JSVAL_NULL,
-            JS_THIS_OBJECT, JS_SET_RVAL};
+            JSVAL_VOID, JS_THIS_OBJECT, JS_SET_RVAL, JSPROP_NATIVE_ACCESSORS,
+            JSTYPE_FUNCTION};
 use js::jsapi::{JSContext, JSVal, JSObject, JSBool, jsid, JSClass, JSFreeOp};
 use js::jsapi::bindgen::{JS_ValueToString, JS_GetStringCharsZAndLength, JS_ReportError,
                             JS_GetReservedSlot, JS_SetReservedSlot, JS_NewStringCopyN,
-    JS_DefineFunctions, JS_DefineProperty, JS_DefineProperties, JS_EncodeString, JS_free};
+    JS_DefineFunctions, JS_DefineProperty, JS_DefineProperties, JS_EncodeString, JS_free,
+    JS_GetProperty, JS_IsArrayObject, JS_GetArrayLength, JS_GetElement, JS_TypeOfValue};
 use js::glue::bindgen::*;
 use js::global::jsval_to_rust_str;
 use js::crust::{JS_PropertyStub, JS_StrictPropertyStub, JS_EnumerateStub, JS_ConvertStub, JS_ResolveStub};
 use js::glue::bindgen::RUST_JSVAL_TO_INT;
+use js::glue::{PROPERTY_STUB, STRICT_PROPERTY_STUB};
 use ptr::null;
 use libc::c_uint;
-use utils::{rust_box, squirrel_away, jsval_to_str};
+use utils::{rust_box, squirrel_away, jsval_to_str, str, throw_error, jsval_to_px};
+use util::base64;
 use bindings::node::create;
+use bindings::structured_clone::{structured_clone_with_transfer, throw_data_clone_error};
 use dom::window::{Window, TimerMessage_Fire};
+use content::content_task::task_from_context;
+use geom::point::Point2D;
 use dom::node::Node;
 use dvec::DVec;
 
@@ -51,6 +58,177 @@ extern fn close(cx: *JSContext, _argc: c_uint, vp: *JSVal) -> JSBool unsafe {
     return 1;
 }
 
+// The code units of the first argument, as a string
+unsafe fn string_units_arg(cx: *JSContext, argc: c_uint, vp: *JSVal) -> Option<~[u16]> {
+    let val = if argc > 0 { *JS_ARGV(cx, vp) } else { JSVAL_VOID };
+    let jsstr = JS_ValueToString(cx, val);
+    if jsstr.is_null() {
+        return None;
+    }
+    let len = 0;
+    let chars = JS_GetStringCharsZAndLength(cx, jsstr, ptr::to_unsafe_ptr(&len));
+    if chars.is_null() {
+        return None;
+    }
+    Some(vec::raw::from_buf_raw(chars, len as uint))
+}
+
+// A JS string with one code unit for each byte
+unsafe fn bytes_to_jsval(cx: *JSContext, bytes: &[u8]) -> JSVal {
+    do vec::as_imm_buf(bytes) |buf, len| {
+        RUST_STRING_TO_JSVAL(JS_NewStringCopyN(cx, buf as *libc::c_char, len as libc::size_t))
+    }
+}
+
+extern fn btoa(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let units = match string_units_arg(cx, argc, vp) {
+        Some(move units) => move units,
+        None => return 0
+    };
+    if units.any(|c| *c > 0xff) {
+        return throw_error(cx, "InvalidCharacterError",
+                           "btoa's string has a character outside Latin-1");
+    }
+    let encoded = base64::encode(units.map(|c| *c as u8));
+    JS_SET_RVAL(cx, vp, bytes_to_jsval(cx, str::to_bytes(encoded)));
+    1
+}
+
+extern fn atob(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let units = match string_units_arg(cx, argc, vp) {
+        Some(move units) => move units,
+        None => return 0
+    };
+    match base64::decode(units) {
+        Some(move bytes) => {
+            JS_SET_RVAL(cx, vp, bytes_to_jsval(cx, bytes));
+            1
+        }
+        None => throw_error(cx, "InvalidCharacterError", "atob's string isn't valid base64")
+    }
+}
+
+/**
+Queues the callback on the same microtask queue as promise jobs, so it
+runs once the current task's script has finished and before the next task,
+such as a timer, starts. A callback that queues another has it run in the
+same checkpoint. The queue keeps the callback rooted until then.
+*/
+extern fn queue_microtask(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    if argc < 1 || JS_TypeOfValue(cx, *JS_ARGV(cx, vp)) != JSTYPE_FUNCTION {
+        return throw_error(cx, "TypeError", "queueMicrotask needs a function");
+    }
+    (*task_from_context(cx)).microtasks.enqueue(*JS_ARGV(cx, vp));
+    JS_SET_RVAL(cx, vp, JSVAL_VOID);
+    1
+}
+
+// The `transfer` member of structuredClone's options, if there is one
+unsafe fn transfer_list(cx: *JSContext, options: JSVal) -> Result<~[JSVal], ()> {
+    if RUST_JSVAL_IS_OBJECT(options) == 0 || RUST_JSVAL_IS_NULL(options) == 1 {
+        return Ok(~[]);
+    }
+    let transfer = JSVAL_VOID;
+    do str::as_c_str(~"transfer") |s| {
+        JS_GetProperty(cx, RUST_JSVAL_TO_OBJECT(options), s, ptr::to_unsafe_ptr(&transfer));
+    }
+    if RUST_JSVAL_IS_VOID(transfer) == 1 {
+        return Ok(~[]);
+    }
+    if RUST_JSVAL_IS_OBJECT(transfer) == 0 || RUST_JSVAL_IS_NULL(transfer) == 1 ||
+       JS_IsArrayObject(cx, RUST_JSVAL_TO_OBJECT(transfer)) == 0 {
+        do str::as_c_str(~"structuredClone's transfer option must be an array") |s| {
+            JS_ReportError(cx, s);
+        }
+        return Err(());
+    }
+
+    let array = RUST_JSVAL_TO_OBJECT(transfer);
+    let len = 0u32;
+    JS_GetArrayLength(cx, array, ptr::to_unsafe_ptr(&len));
+    let mut values = ~[];
+    for uint::range(0, len as uint) |i| {
+        let val = JSVAL_VOID;
+        JS_GetElement(cx, array, i as u32, ptr::to_unsafe_ptr(&val));
+        values.push(val);
+    }
+    Ok(move values)
+}
+
+extern fn structuredClone(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let argv = JS_ARGV(cx, vp);
+    let value = if argc > 0 { *argv } else { JSVAL_VOID };
+    let transfer = match transfer_list(cx, if argc > 1 { *ptr::offset(argv, 1) }
+                                           else { JSVAL_VOID }) {
+        Ok(move transfer) => move transfer,
+        Err(()) => return 0
+    };
+
+    match structured_clone_with_transfer(cx, value, transfer) {
+        Ok(clone) => {
+            JS_SET_RVAL(cx, vp, clone);
+            1
+        }
+        Err(err) => {
+            throw_data_clone_error(cx, err);
+            0
+        }
+    }
+}
+
+extern fn getScrollX(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = RUST_INT_TO_JSVAL((*unwrap(obj)).payload.scroll.viewport.x as libc::c_int);
+    return 1;
+}
+
+extern fn getScrollY(cx: *JSContext, _argc: c_uint, vp: *mut JSVal) -> JSBool unsafe {
+    let obj = JS_THIS_OBJECT(cx, cast::reinterpret_cast(&vp));
+    if obj.is_null() {
+        return 0;
+    }
+    *vp = RUST_INT_TO_JSVAL((*unwrap(obj)).payload.scroll.viewport.y as libc::c_int);
+    return 1;
+}
+
+// The (x, y) arguments of scrollTo and scrollBy
+//TODO: support the ScrollToOptions dictionary form
+// The x and y arguments of scrollTo and scrollBy, or None if converting
+// them threw
+unsafe fn point_args(cx: *JSContext, argc: c_uint, argv: *JSVal) -> Option<Point2D<int>> {
+    let x = if argc > 0 { jsval_to_px(cx, *argv) } else { Some(0) };
+    let y = if argc > 1 { jsval_to_px(cx, *ptr::offset(argv, 1)) } else { Some(0) };
+    match (x, y) {
+        (Some(x), Some(y)) => Some(Point2D(x, y)),
+        _ => None
+    }
+}
+
+extern fn scrollTo(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let offset = match point_args(cx, argc, JS_ARGV(cx, vp)) {
+        Some(offset) => offset,
+        None => return 0
+    };
+    (*task_from_context(cx)).scroll_viewport_to(offset);
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
+extern fn scrollBy(cx: *JSContext, argc: c_uint, vp: *JSVal) -> JSBool unsafe {
+    let delta = match point_args(cx, argc, JS_ARGV(cx, vp)) {
+        Some(delta) => delta,
+        None => return 0
+    };
+    let current = (*unwrap(JS_THIS_OBJECT(cx, vp))).payload.scroll.viewport;
+    (*task_from_context(cx)).scroll_viewport_to(Point2D(current.x + delta.x,
+                                                        current.y + delta.y));
+    JS_SET_RVAL(cx, vp, JSVAL_NULL);
+    return 1;
+}
+
 unsafe fn unwrap(obj: *JSObject) -> *rust_box<Window> {
     let val = JS_GetReservedSlot(obj, 0);
     cast::reinterpret_cast(&RUST_JSVAL_TO_PRIVATE(val))
@@ -87,17 +265,75 @@ pub fn init(compartment: &bare_compartment, win: @Window) {
                      call: {op: close, info: null()},
                      nargs: 2,
                      flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"structuredClone"),
+                     call: {op: structuredClone, info: null()},
+                     nargs: 2,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"btoa"),
+                     call: {op: btoa, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"atob"),
+                     call: {op: atob, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"queueMicrotask"),
+                     call: {op: queue_microtask, info: null()},
+                     nargs: 1,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"scrollTo"),
+                     call: {op: scrollTo, info: null()},
+                     nargs: 2,
+                     flags: 0,
+                     selfHostedName: null()},
+                    {name: compartment.add_name(~"scrollBy"),
+                     call: {op: scrollBy, info: null()},
+                     nargs: 2,
+                     flags: 0,
                      selfHostedName: null()}];
 
     vec::as_imm_buf(methods, |fns, _len| {
         JS_DefineFunctions(compartment.cx.ptr, proto.ptr, fns);
     });
 
+    let attrs = @~[
+        {name: compartment.add_name(~"scrollX"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getScrollX, info: null()},
+         setter: {op: null(), info: null()}},
+        {name: compartment.add_name(~"scrollY"),
+         tinyid: 0,
+         flags: (JSPROP_ENUMERATE | JSPROP_SHARED | JSPROP_NATIVE_ACCESSORS) as u8,
+         getter: {op: getScrollY, info: null()},
+         setter: {op: null(), info: null()}}];
+    vec::push(&mut compartment.global_props, attrs);
+    vec::as_imm_buf(*attrs, |specs, _len| {
+        JS_DefineProperties(compartment.cx.ptr, proto.ptr, specs);
+    });
+    bindings::event_target::init(compartment, proto.ptr);
+
     unsafe {
         let raw_ptr: *libc::c_void = cast::reinterpret_cast(&squirrel_away(win));
         JS_SetReservedSlot(obj.ptr, 0, RUST_PRIVATE_TO_JSVAL(raw_ptr));
     }
 
+    bindings::history::init(compartment, win, obj.ptr);
+    bindings::servo_debug::init(compartment, obj.ptr);
+
+    // Set by the page to hear about uncaught errors; see error_reporter
+    do str::as_c_str("onerror") |s| {
+        JS_DefineProperty(compartment.cx.ptr, obj.ptr, s, JSVAL_NULL,
+                          GetJSClassHookStubPointer(PROPERTY_STUB) as *u8,
+                          GetJSClassHookStubPointer(STRICT_PROPERTY_STUB) as *u8,
+                          JSPROP_ENUMERATE);
+    }
+
     //TODO: All properties/methods on Window need to be available on the global
     //      object as well. We probably want a special JSClass with a resolve hook.
     compartment.define_property(~"window", RUST_OBJECT_TO_JSVAL(obj.ptr),
@@ -0,0 +1,130 @@
+/*!
+Immutable chunks of binary data, as used by `Blob` and `File`.
+*/
+
+use std::arc::{ARC, clone, get};
+
+/// One of the pieces a Blob is built from.
+pub enum BlobPart {
+    ArrayBufferPart(~[u8]),
+    // Encoded as UTF-8
+    StringPart(~str),
+    NestedBlobPart(Blob)
+}
+
+pub struct Blob {
+    // Shared, so that cloning or slicing a Blob doesn't copy its data
+    priv data: ARC<~[u8]>,
+    // The part of `data` that's in this Blob
+    priv start: uint,
+    priv end: uint,
+    // The MIME type, lowercased, or empty if it isn't known
+    content_type: ~str,
+}
+
+pub fn Blob(data: ~[u8], content_type: &str) -> Blob {
+    let len = data.len();
+    Blob {
+        data: ARC(move data),
+        start: 0,
+        end: len,
+        content_type: normalize_type(content_type),
+    }
+}
+
+impl Blob {
+    /// Concatenates the parts into a new Blob.
+    static fn from_parts(parts: ~[BlobPart], content_type: &str) -> Blob {
+        let mut data = ~[];
+        do vec::consume(move parts) |_i, part| {
+            match move part {
+                ArrayBufferPart(move bytes) => data += bytes,
+                StringPart(move s) => data += str::to_bytes(s),
+                NestedBlobPart(move blob) => do blob.with_bytes |bytes| { data += bytes; }
+            }
+        }
+        Blob(move data, content_type)
+    }
+
+    fn clone(&self) -> Blob {
+        Blob {
+            data: clone(&self.data),
+            start: self.start,
+            end: self.end,
+            content_type: copy self.content_type,
+        }
+    }
+
+    fn size(&self) -> uint {
+        self.end - self.start
+    }
+
+    fn with_bytes<R>(&self, f: fn(&[u8]) -> R) -> R {
+        f(vec::view(*get(&self.data), self.start, self.end))
+    }
+
+    /**
+    A Blob holding bytes `start` to `end` of this one. Negative offsets
+    count back from the end, and both are clamped to the size, as in
+    `Blob.prototype.slice`.
+    */
+    fn slice(&self, start: Option<int>, end: Option<int>, content_type: Option<~str>) -> Blob {
+        let start = relative_offset(start, 0, self.size());
+        let end = relative_offset(end, self.size(), self.size());
+        let (start, end) = (self.start + start, self.start + uint::max(start, end));
+        Blob {
+            data: clone(&self.data),
+            start: start,
+            end: end,
+            content_type: match content_type {
+                Some(ref t) => normalize_type(*t),
+                None => ~""
+            },
+        }
+    }
+}
+
+priv fn relative_offset(offset: Option<int>, default: uint, size: uint) -> uint {
+    match offset {
+        None => default,
+        Some(offset) if offset < 0 => size - uint::min(size, (-offset) as uint),
+        Some(offset) => uint::min(offset as uint, size)
+    }
+}
+
+// Types with anything other than printable ASCII are dropped
+priv fn normalize_type(content_type: &str) -> ~str {
+    if str::all(content_type, |c| c >= ' ' && c <= '~') {
+        str::to_lower(content_type)
+    } else {
+        ~""
+    }
+}
+
+#[cfg(test)]
+fn test_bytes(blob: &Blob) -> ~[u8] {
+    do blob.with_bytes |bytes| { vec::from_slice(bytes) }
+}
+
+#[test]
+fn test_from_parts() {
+    let inner = Blob(~[3u8], "");
+    let blob = Blob::from_parts(~[ArrayBufferPart(~[1u8, 2]), StringPart(~"a"),
+                                  NestedBlobPart(move inner)], "Text/Plain");
+    assert test_bytes(&blob) == ~[1u8, 2, 97, 3];
+    assert blob.content_type == ~"text/plain";
+}
+
+#[test]
+fn test_slice() {
+    let blob = Blob(~[0u8, 1, 2, 3, 4, 5], "text/plain");
+    assert test_bytes(&blob.slice(Some(1), Some(3), None)) == ~[1u8, 2];
+    assert test_bytes(&blob.slice(Some(-2), None, None)) == ~[4u8, 5];
+    assert test_bytes(&blob.slice(Some(4), Some(2), None)).is_empty();
+    assert test_bytes(&blob.slice(Some(-10), Some(10), None)).len() == 6;
+    assert blob.slice(None, None, None).content_type == ~"";
+
+    let sliced = blob.slice(Some(1), None, Some(~"a/b"));
+    assert test_bytes(&sliced.slice(Some(1), Some(2), None)) == ~[2u8];
+    assert sliced.content_type == ~"a/b";
+}
@@ -0,0 +1,444 @@
+/*!
+`window.caches`, and where it keeps its responses: a SQLite database per
+origin. The database lists the origin's caches in a `caches` table, and
+keeps each cache's request/response pairs in a table of its own. Every
+write happens in a transaction, so that two tasks (or processes) using the
+same origin's caches at once can't leave them half-written, and each waits
+its turn when the database is locked.
+*/
+
+use util::sqlite::Connection;
+
+/// A request as it's stored: what a response is looked up by.
+pub struct CachedRequest {
+    method: ~str,
+    url: ~str,
+}
+
+/// A request for `url` by `method`. Caches ignore the URL's fragment, so
+/// it's left out.
+pub fn CachedRequest(method: &str, url: &str) -> CachedRequest {
+    let url = match str::find_char(url, '#') {
+        Some(i) => url.slice(0, i),
+        None => url.to_str()
+    };
+    CachedRequest { method: method.to_str(), url: move url }
+}
+
+/// A response as it's stored.
+pub struct CachedResponse {
+    status: uint,
+    headers: ~[(~str, ~str)],
+    body: ~[u8],
+}
+
+/**
+An origin's caches, as `caches` gives them to script. The database is
+opened afresh for each call, so that the calls can be made on whichever
+task does the work; each connection waits for the others' writes.
+*/
+pub struct CacheStorage {
+    priv path: Path,
+}
+
+/// The caches of `origin`, which are kept in `dir`.
+pub fn CacheStorage(dir: &Path, origin: &str) -> CacheStorage {
+    CacheStorage { path: database_path(&dir.push("caches"), origin) }
+}
+
+impl CacheStorage {
+    /// The cache called `name`, which is made if there isn't one.
+    fn open(&self, name: &str) -> Result<Cache, ~str> {
+        do with_database(&self.path) |db| {
+            db.open_cache(name).map(|_| Cache { path: copy self.path, name: name.to_str() })
+        }
+    }
+
+    fn has(&self, name: &str) -> Result<bool, ~str> {
+        with_database(&self.path, |db| db.has_cache(name))
+    }
+
+    /// Deletes the cache called `name`, returning whether there was one.
+    fn delete(&self, name: &str) -> Result<bool, ~str> {
+        with_database(&self.path, |db| db.delete_cache(name))
+    }
+
+    /// The names of the caches, in the order they were made.
+    fn keys(&self) -> Result<~[~str], ~str> {
+        with_database(&self.path, |db| db.cache_names())
+    }
+
+    /// The response for `request` in the oldest cache that has one.
+    fn match_request(&self, request: &CachedRequest) -> Result<Option<CachedResponse>, ~str> {
+        do with_database(&self.path) |db| {
+            match db.cache_names() {
+                Ok(move names) => {
+                    let mut found = Ok(None);
+                    for names.each |name| {
+                        found = db.match_request(*name, request.method, request.url);
+                        match found {
+                            Ok(None) => (),
+                            _ => break
+                        }
+                    }
+                    move found
+                }
+                Err(move e) => Err(move e)
+            }
+        }
+    }
+}
+
+/// One of an origin's caches.
+pub struct Cache {
+    priv path: Path,
+    name: ~str,
+}
+
+impl Cache {
+    /// Stores `response` for `request`, replacing what was there for it.
+    fn put(&self, request: &CachedRequest, response: &CachedResponse) -> Result<(), ~str> {
+        with_database(&self.path, |db| db.put(self.name, request.method, request.url, response))
+    }
+
+    fn match_request(&self, request: &CachedRequest) -> Result<Option<CachedResponse>, ~str> {
+        with_database(&self.path, |db| db.match_request(self.name, request.method, request.url))
+    }
+
+    /// Deletes the response for `request`, returning whether there was one.
+    fn delete(&self, request: &CachedRequest) -> Result<bool, ~str> {
+        with_database(&self.path, |db| db.delete(self.name, request.method, request.url))
+    }
+
+    /// The requests the cache has responses for, oldest first.
+    fn keys(&self) -> Result<~[CachedRequest], ~str> {
+        do with_database(&self.path) |db| {
+            do db.keys(self.name).map |keys| {
+                do keys.map |key| {
+                    let (ref method, ref url) = *key;
+                    CachedRequest { method: copy *method, url: copy *url }
+                }
+            }
+        }
+    }
+}
+
+// Calls `f` with a new connection to the database at `path`, making its
+// directory first if need be
+fn with_database<T>(path: &Path, f: fn(&CacheDatabase) -> Result<T, ~str>)
+    -> Result<T, ~str> {
+    os::mkdir_recursive(&path.dir_path(), 0o700);
+    match CacheDatabase(path.to_str()) {
+        Ok(move db) => f(&db),
+        Err(move e) => Err(move e)
+    }
+}
+
+/// The database file for `origin`'s caches, in `dir`. Characters that
+/// can't go in a file name are replaced.
+pub fn database_path(dir: &Path, origin: &str) -> Path {
+    let name = do str::map(origin) |c| {
+        if char::is_alphanumeric(c) || c == '.' || c == '-' { c } else { '_' }
+    };
+    dir.push(name + ".sqlite")
+}
+
+pub struct CacheDatabase {
+    priv conn: Connection,
+}
+
+/// Opens (or creates) a cache database. ":memory:" opens one that's never
+/// written to disk.
+pub fn CacheDatabase(path: &str) -> Result<CacheDatabase, ~str> {
+    let database = match Connection(path) {
+        Ok(move conn) => CacheDatabase { conn: move conn },
+        Err(move e) => return Err(move e)
+    };
+    match database.conn.exec("CREATE TABLE IF NOT EXISTS caches \
+                              (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE)") {
+        Ok(()) => Ok(move database),
+        Err(move e) => Err(move e)
+    }
+}
+
+impl CacheDatabase {
+    /// The names of the caches, in the order they were made.
+    fn cache_names(&self) -> Result<~[~str], ~str> {
+        let stmt = match self.conn.prepare("SELECT name FROM caches ORDER BY id") {
+            Ok(move stmt) => move stmt,
+            Err(move e) => return Err(move e)
+        };
+        let mut names = ~[];
+        loop {
+            match stmt.step() {
+                Ok(true) => names.push(stmt.column_text(0)),
+                Ok(false) => return Ok(move names),
+                Err(move e) => return Err(move e)
+            }
+        }
+    }
+
+    fn has_cache(&self, name: &str) -> Result<bool, ~str> {
+        self.table(name).map(|table| table.is_some())
+    }
+
+    /// Makes the cache called `name`, unless there is one already.
+    fn open_cache(&self, name: &str) -> Result<(), ~str> {
+        do self.conn.transaction {
+            match self.table(name) {
+                Ok(Some(_)) => Ok(()),
+                Ok(None) => {
+                    let insert = self.conn.prepare("INSERT INTO caches (name) VALUES (?1)");
+                    do insert.chain |stmt| {
+                        stmt.bind_text(1, name);
+                        stmt.step().chain(|_| {
+                            let id = self.conn.last_insert_rowid();
+                            self.conn.exec(fmt!("CREATE TABLE cache_%d \
+                                                 (request_method TEXT NOT NULL, \
+                                                  request_url TEXT NOT NULL, \
+                                                  response_status INTEGER NOT NULL, \
+                                                  response_headers TEXT NOT NULL, \
+                                                  response_body_blob BLOB NOT NULL, \
+                                                  PRIMARY KEY (request_method, request_url))",
+                                                id as int))
+                        })
+                    }
+                }
+                Err(move e) => Err(move e)
+            }
+        }
+    }
+
+    /// Deletes the cache called `name`, returning whether there was one.
+    fn delete_cache(&self, name: &str) -> Result<bool, ~str> {
+        do self.conn.transaction {
+            match self.table(name) {
+                Ok(Some(move table)) => {
+                    let delete = self.conn.prepare("DELETE FROM caches WHERE name = ?1");
+                    do delete.chain |stmt| {
+                        stmt.bind_text(1, name);
+                        stmt.step().chain(|_| self.conn.exec(~"DROP TABLE " + table))
+                            .map(|_| true)
+                    }
+                }
+                Ok(None) => Ok(false),
+                Err(move e) => Err(move e)
+            }
+        }
+    }
+
+    /// Stores `response` for a request, replacing what was there for it.
+    fn put(&self, name: &str, method: &str, url: &str, response: &CachedResponse)
+        -> Result<(), ~str> {
+        do self.conn.transaction {
+            let table = match self.existing_table(name) {
+                Ok(move table) => move table,
+                Err(move e) => return Err(move e)
+            };
+            let insert = self.conn.prepare(fmt!("INSERT OR REPLACE INTO %s VALUES \
+                                                 (?1, ?2, ?3, ?4, ?5)", table));
+            do insert.chain |stmt| {
+                stmt.bind_text(1, method);
+                stmt.bind_text(2, url);
+                stmt.bind_int(3, response.status as int);
+                stmt.bind_text(4, format_headers(response.headers));
+                stmt.bind_blob(5, response.body);
+                stmt.step().map(|_| ())
+            }
+        }
+    }
+
+    /// The response stored for a request, if any.
+    fn match_request(&self, name: &str, method: &str, url: &str)
+        -> Result<Option<CachedResponse>, ~str> {
+        let table = match self.existing_table(name) {
+            Ok(move table) => move table,
+            Err(move e) => return Err(move e)
+        };
+        let select = self.conn.prepare(fmt!("SELECT response_status, response_headers, \
+                                             response_body_blob FROM %s \
+                                             WHERE request_method = ?1 AND request_url = ?2",
+                                            table));
+        do select.chain |stmt| {
+            stmt.bind_text(1, method);
+            stmt.bind_text(2, url);
+            do stmt.step().map |row| {
+                if *row {
+                    Some(CachedResponse {
+                        status: stmt.column_int(0) as uint,
+                        headers: parse_headers(stmt.column_text(1)),
+                        body: stmt.column_blob(2)
+                    })
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Deletes the response for a request, returning whether there was one.
+    fn delete(&self, name: &str, method: &str, url: &str) -> Result<bool, ~str> {
+        do self.conn.transaction {
+            let table = match self.existing_table(name) {
+                Ok(move table) => move table,
+                Err(move e) => return Err(move e)
+            };
+            let delete = self.conn.prepare(fmt!("DELETE FROM %s \
+                                                 WHERE request_method = ?1 AND request_url = ?2",
+                                                table));
+            do delete.chain |stmt| {
+                stmt.bind_text(1, method);
+                stmt.bind_text(2, url);
+                stmt.step().map(|_| self.conn.changes() > 0)
+            }
+        }
+    }
+
+    /// The method and URL of each request in a cache, oldest first.
+    fn keys(&self, name: &str) -> Result<~[(~str, ~str)], ~str> {
+        let table = match self.existing_table(name) {
+            Ok(move table) => move table,
+            Err(move e) => return Err(move e)
+        };
+        let stmt = match self.conn.prepare(fmt!("SELECT request_method, request_url FROM %s \
+                                                 ORDER BY rowid", table)) {
+            Ok(move stmt) => move stmt,
+            Err(move e) => return Err(move e)
+        };
+        let mut keys = ~[];
+        loop {
+            match stmt.step() {
+                Ok(true) => keys.push((stmt.column_text(0), stmt.column_text(1))),
+                Ok(false) => return Ok(move keys),
+                Err(move e) => return Err(move e)
+            }
+        }
+    }
+
+    // The table holding the cache called `name`, if there is one. Tables are
+    // named by id, so that cache names needn't be quoted.
+    priv fn table(&self, name: &str) -> Result<Option<~str>, ~str> {
+        let select = self.conn.prepare("SELECT id FROM caches WHERE name = ?1");
+        do select.chain |stmt| {
+            stmt.bind_text(1, name);
+            do stmt.step().map |row| {
+                if *row { Some(fmt!("cache_%d", stmt.column_int(0))) } else { None }
+            }
+        }
+    }
+
+    priv fn existing_table(&self, name: &str) -> Result<~str, ~str> {
+        match self.table(name) {
+            Ok(Some(move table)) => Ok(move table),
+            Ok(None) => Err(fmt!("there's no cache called %s", name)),
+            Err(move e) => Err(move e)
+        }
+    }
+}
+
+// Headers are stored a line each, as they'd be sent
+fn format_headers(headers: &[(~str, ~str)]) -> ~str {
+    let mut text = ~"";
+    for headers.each |header| {
+        let (ref name, ref value) = *header;
+        text += fmt!("%s: %s\r\n", *name, *value);
+    }
+    move text
+}
+
+fn parse_headers(text: &str) -> ~[(~str, ~str)] {
+    let mut headers = ~[];
+    for str::split_str(text, "\r\n").each |line| {
+        match str::find_str(*line, ": ") {
+            Some(i) => headers.push((line.slice(0, i), line.slice(i + 2, line.len()))),
+            None => ()
+        }
+    }
+    move headers
+}
+
+#[cfg(test)]
+mod cache_storage_tests {
+    fn response(status: uint, body: &str) -> CachedResponse {
+        CachedResponse {
+            status: status,
+            headers: ~[(~"Content-Type", ~"text/plain")],
+            body: str::to_bytes(body)
+        }
+    }
+
+    #[test]
+    #[ignore(cfg(not(sqlite)))]
+    fn test_caches() {
+        let db = CacheDatabase(":memory:").get();
+        assert db.cache_names().get().is_empty();
+        db.open_cache("v1").get();
+        db.open_cache("v2").get();
+        db.open_cache("v1").get();
+        assert db.cache_names().get() == ~[~"v1", ~"v2"];
+        assert db.delete_cache("v1").get();
+        assert !db.delete_cache("v1").get();
+        assert !db.has_cache("v1").get();
+        assert db.cache_names().get() == ~[~"v2"];
+    }
+
+    #[test]
+    #[ignore(cfg(not(sqlite)))]
+    fn test_put_match_delete() {
+        let db = CacheDatabase(":memory:").get();
+        db.open_cache("v1").get();
+        let url = "http://example.com/a";
+        db.put("v1", "GET", url, &response(200, "old")).get();
+        // A second put for the same request replaces the first
+        db.put("v1", "GET", url, &response(200, "new")).get();
+        db.put("v1", "GET", "http://example.com/b", &response(404, "")).get();
+
+        let found = db.match_request("v1", "GET", url).get().get();
+        assert found.status == 200;
+        assert found.body == str::to_bytes("new");
+        assert found.headers == ~[(~"Content-Type", ~"text/plain")];
+        assert db.match_request("v1", "POST", url).get().is_none();
+        assert db.keys("v1").get() == ~[(~"GET", url.to_str()),
+                                         (~"GET", ~"http://example.com/b")];
+
+        assert db.delete("v1", "GET", url).get();
+        assert !db.delete("v1", "GET", url).get();
+        assert db.match_request("v1", "GET", url).get().is_none();
+        // Nothing can be put in a cache that doesn't exist
+        assert db.put("v2", "GET", url, &response(200, "")).is_err();
+    }
+
+    #[test]
+    #[ignore(cfg(not(sqlite)))]
+    fn test_cache_storage() {
+        let dir = os::tmpdir().push(fmt!("servo-cache-storage-test-%d", os::getpid() as int));
+        let storage = CacheStorage(&dir, "http://example.com");
+        let v1 = storage.open("v1").get();
+        let v2 = storage.open("v2").get();
+        assert storage.keys().get() == ~[~"v1", ~"v2"];
+
+        // The fragment isn't part of the key
+        let request = CachedRequest("GET", "http://example.com/a#top");
+        assert request.url == ~"http://example.com/a";
+        v2.put(&request, &response(200, "second")).get();
+        assert storage.match_request(&request).get().get().body == str::to_bytes("second");
+        // The oldest cache with a response wins
+        v1.put(&CachedRequest("GET", "http://example.com/a"), &response(200, "first")).get();
+        assert storage.match_request(&request).get().get().body == str::to_bytes("first");
+        assert v2.keys().get().map(|r| copy r.url) == ~[~"http://example.com/a"];
+
+        assert v1.delete(&request).get();
+        assert v1.match_request(&request).get().is_none();
+        assert storage.delete("v2").get();
+        assert !storage.has("v2").get();
+        assert storage.match_request(&request).get().is_none();
+
+        os::remove_file(&database_path(&dir.push("caches"), "http://example.com"));
+    }
+
+    #[test]
+    fn test_database_path() {
+        assert database_path(&Path("/tmp"), "https://example.com:8443") ==
+            Path("/tmp/https___example.com_8443.sqlite");
+    }
+}
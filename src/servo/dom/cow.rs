@@ -57,23 +57,38 @@ main nodes.  This is convenient but dangerous: it is the reader's job
 to ensure that this data remains live independent of the COW nodes
 themselves.
 
+# Memory
+
+The reader and writer copies of the data come from the scope's arenas
+(see `dom::arena`), not from malloc, so they must be given back to them
+rather than freed. A scope can be made with several classes of handle,
+each with an arena and so a free list of its own, so that values of one
+kind are kept together; a handle's copies always come from its class's
+arena. The arenas' slabs go when the scope does, after the data in them
+has been dropped.
+
 */
 
 use core::libc::types::os::arch::c95::size_t;
+use dom::arena::{Arena, DEFAULT_SLAB_LEN};
 use ptr::Ptr;
+use std::arc::ARC;
 use vec::push;
 
 type ScopeData<T:Send,A> = {
     mut layout_active: bool,
     mut free_list: ~[Handle<T,A>],
-    mut first_dirty: Handle<T,A>
+    mut first_dirty: Handle<T,A>,
+    // Where the reader and writer copies live, one arena per class
+    arenas: ~[Arena<T>]
 };
 
 struct ScopeResource<T:Send,A> {
     d : ScopeData<T,A>,
 
     drop unsafe {
-        for self.d.free_list.each |h| { free_handle(*h); }
+        // Drop the data first; the arena frees its slabs after
+        for self.d.free_list.each |h| { free_handle(self.d.arenas, *h); }
     }
 }
 
@@ -86,7 +101,9 @@ pub type Scope<T:Send,A> = @ScopeResource<T,A>;
 type HandleData<T:Send,A> = {mut read_ptr: *T,
                              mut write_ptr: *mut T,
                              mut read_aux: *A,
-                             mut next_dirty: Handle<T,A>};
+                             mut next_dirty: Handle<T,A>,
+                             // The arena the copies come from
+                             mut class: uint};
 pub enum Handle<T:Send,A> {
     _Handle(*HandleData<T,A>)
 }
@@ -97,6 +114,7 @@ impl<T:Send,A> Handle<T,A> {
     fn write_ptr() -> *mut T unsafe       { (**self).write_ptr  }
     fn read_aux() -> *A unsafe            { (**self).read_aux   }
     fn next_dirty() -> Handle<T,A> unsafe { (**self).next_dirty }
+    fn class() -> uint unsafe             { (**self).class      }
 
     fn set_read_ptr(t: *T) unsafe             { (**self).read_ptr = t;   }
     fn set_write_ptr(t: *mut T) unsafe        { (**self).write_ptr = t;  }
@@ -140,12 +158,11 @@ impl <T: Send,A> Handle<T,A> : cmp::Eq {
 
 // Private methods
 impl<T: Copy Send,A> Scope<T,A> {
-    fn clone(v: *T) -> *T unsafe {
-        let n: *mut T =
-            cast::reinterpret_cast(&libc::calloc(sys::size_of::<T>() as size_t, 1u as size_t));
+    fn clone(v: *T, class: uint) -> *T unsafe {
+        let n: *mut T = self.d.arenas[class].alloc();
 
         // n.b.: this assignment will run the drop glue for <T,A>. *Hopefully* the fact that
-        // everything is initialized to NULL by calloc will make this ok.  We may have to make the
+        // the arena hands out zeroed slots will make this ok.  We may have to make the
         // take glue be tolerant of this.
         *n = unsafe{*v};
 
@@ -153,15 +170,19 @@ impl<T: Copy Send,A> Scope<T,A> {
     }
 }
 
-unsafe fn free<T:Send>(t: *T) {
-    let _x = move *cast::reinterpret_cast::<*T,*mut T>(&t);
-    libc::free(cast::reinterpret_cast(&t));
+// Drops the value at `t` and gives its slot back to the arena
+unsafe fn free<T:Send>(arena: &Arena<T>, t: *T) {
+    {
+        let _x = move *cast::reinterpret_cast::<*T,*mut T>(&t);
+    }
+    arena.release(cast::reinterpret_cast(&t));
 }
 
-unsafe fn free_handle<T:Send,A>(h: Handle<T,A>) {
-    free(h.read_ptr());
+unsafe fn free_handle<T:Send,A>(arenas: &[Arena<T>], h: Handle<T,A>) {
+    let arena = &arenas[h.class()];
+    free(arena, h.read_ptr());
     if h.write_ptr() != cast::reinterpret_cast(&h.read_ptr()) {
-        free(cast::reinterpret_cast::<*mut T,*T>(&h.write_ptr()));
+        free(arena, cast::reinterpret_cast::<*mut T,*T>(&h.write_ptr()));
     }
 }
 
@@ -178,9 +199,17 @@ fn null_handle<T:Send,A>() -> Handle<T,A> {
 }
 
 pub fn Scope<T:Send,A>() -> Scope<T,A> {
+    ScopeWithClasses(1)
+}
+
+/// A scope whose handles are in `classes` classes, each allocated from
+/// an arena of its own.
+pub fn ScopeWithClasses<T:Send,A>(classes: uint) -> Scope<T,A> {
+    assert classes > 0;
     @ScopeResource({mut layout_active: false,
                     mut free_list: ~[],
-                    mut first_dirty: null_handle()})
+                    mut first_dirty: null_handle(),
+                    arenas: vec::from_fn(classes, |_i| Arena(DEFAULT_SLAB_LEN))})
 }
 
 // Writer methods
@@ -201,7 +230,7 @@ impl<T:Copy Send,A> Scope<T,A> {
         if self.d.first_dirty.is_not_null() {
             let mut handle = self.d.first_dirty;
             while (*handle).is_not_null() {
-                free(handle.read_ptr());
+                free(&self.d.arenas[handle.class()], handle.read_ptr());
 
                 handle.set_read_ptr(cast::reinterpret_cast(&handle.write_ptr()));
                 let next_handle = handle.next_dirty();
@@ -215,6 +244,17 @@ impl<T:Copy Send,A> Scope<T,A> {
         self.d.layout_active = false;
     }
 
+    /// The bytes the scope has allocated: the arenas' slabs, and each
+    /// handle.
+    fn heap_size() -> uint {
+        let mut slots = 0;
+        for self.d.arenas.each |arena| {
+            slots += arena.capacity();
+        }
+        slots * sys::size_of::<T>() +
+            self.d.free_list.len() * sys::size_of::<HandleData<T,A>>()
+    }
+
     fn read<U>(h: &Handle<T,A>, f: fn(&T) -> U) -> U unsafe {
         // Use the write_ptr, which may be more up to date than the read_ptr or may not
         f(&*h.write_ptr())
@@ -225,21 +265,28 @@ impl<T:Copy Send,A> Scope<T,A> {
         let const_write_ptr = ptr::const_offset(h.write_ptr(), 0);
         if self.d.layout_active && const_read_ptr == const_write_ptr {
             #debug["marking handle %? as dirty", h];
-            h.set_write_ptr(cast::reinterpret_cast(&self.clone(h.read_ptr())));
+            h.set_write_ptr(cast::reinterpret_cast(&self.clone(h.read_ptr(), h.class())));
             h.set_next_dirty(self.d.first_dirty);
             self.d.first_dirty = *h;
         }
         f(&*h.write_ptr())
     }
 
+    fn handle(v: &T) -> Handle<T,A> {
+        self.handle_in_class(v, 0)
+    }
+
     // FIXME: This could avoid a deep copy by taking ownership of `v`
     #[allow(non_implicitly_copyable_typarams)]
-    fn handle(v: &T) -> Handle<T,A> unsafe {
+    fn handle_in_class(v: &T, class: uint) -> Handle<T,A> unsafe {
+        assert class < self.d.arenas.len();
+        // The handle itself is malloced; only the data goes in the arena
         debug!("vv: %?", *v);
         let d: *HandleData<T,A> =
             cast::reinterpret_cast(
                 &libc::malloc(sys::size_of::<HandleData<T,A>>() as size_t));
-        (*d).read_ptr = self.clone(ptr::to_unsafe_ptr(v));
+        (*d).class = class;
+        (*d).read_ptr = self.clone(ptr::to_unsafe_ptr(v), class);
         (*d).write_ptr = cast::reinterpret_cast(&(*d).read_ptr);
         (*d).read_aux = ptr::null();
         (*d).next_dirty = null_handle();
@@ -252,6 +299,33 @@ impl<T:Copy Send,A> Scope<T,A> {
     }
 }
 
+impl<T:Copy Const Send,A> Scope<T,A> {
+    /**
+    Moves the writer's view of `h` out of the scope and into an ARC, so
+    that it can outlive the scope and be sent to other tasks.
+
+    The scope stops managing the handle: its data is freed and both
+    pointers are nulled, so dropping the scope will not free them again.
+    The handle itself must not be read afterwards. No reader may be
+    active, since it could still be looking at the data.
+    */
+    fn upgrade_to_arc(h: Handle<T,A>) -> ARC<T> unsafe {
+        assert !self.d.layout_active;
+        assert h.is_not_null();
+        assert h.write_ptr().is_not_null();
+
+        let value = *h.write_ptr();
+        free_handle(self.d.arenas, h);
+        h.set_read_ptr(ptr::null());
+        h.set_write_ptr(ptr::mut_null());
+        h.set_read_aux(ptr::null());
+
+        self.d.free_list = vec::filter(self.d.free_list, |other| *other != h);
+
+        ARC(move value)
+    }
+}
+
 #[cfg(test)]
 #[allow(non_implicitly_copyable_typarams)]
 mod test {
@@ -333,4 +407,60 @@ mod test {
         assert henrietta.read(read_characteristic) == iter1 * iter2;
         assert ferdinand.read(read_characteristic) == iter1 * iter2;
     }
+
+    type sheep = {name: ~str, fleeces: uint};
+
+    #[test]
+    fn upgraded_value_outlives_scope() {
+        let arc = {
+            let s: Scope<sheep, processed> = Scope();
+            let dolly = s.handle(&{name: ~"dolly", fleeces: 1u});
+            s.handle(&{name: ~"shaun", fleeces: 2u});
+            s.write(&dolly, |_d| ());
+            let arc = s.upgrade_to_arc(dolly);
+            assert s.d.free_list.len() == 1u;
+            assert dolly.read_ptr().is_null();
+            move arc
+        };
+
+        let (chan, port) = pipes::stream();
+        do task::spawn |move arc, move chan| {
+            let dolly = std::arc::get(&arc);
+            chan.send((copy dolly.name, dolly.fleeces));
+        }
+        assert port.recv() == (~"dolly", 1u);
+    }
+
+    #[test]
+    fn heap_size_counts_slabs_and_handles() {
+        let s: Scope<sheep, processed> = Scope();
+        assert s.heap_size() == 0u;
+        s.handle(&{name: ~"dolly", fleeces: 1u});
+        let slab = DEFAULT_SLAB_LEN * sys::size_of::<sheep>();
+        let one = s.heap_size();
+        assert one > slab;
+        // The next node fits in the same slab, and only adds a handle
+        s.handle(&{name: ~"shaun", fleeces: 2u});
+        assert s.heap_size() == one + (one - slab);
+    }
+
+    #[test]
+    fn classes_have_arenas_of_their_own() {
+        let s: Scope<sheep, processed> = ScopeWithClasses(2);
+        let dolly = s.handle_in_class(&{name: ~"dolly", fleeces: 1u}, 0);
+        let shaun = s.handle_in_class(&{name: ~"shaun", fleeces: 2u}, 1);
+        assert s.d.arenas[0].contains(dolly.read_ptr());
+        assert s.d.arenas[1].contains(shaun.read_ptr());
+        assert s.d.arenas[0].len() == 1u;
+        assert s.d.arenas[1].len() == 1u;
+
+        // The writer's copy comes from the handle's own class, and goes
+        // back to it on the join
+        s.reader_forked();
+        s.write(&shaun, |_s| ());
+        assert s.d.arenas[0].len() == 1u;
+        assert s.d.arenas[1].len() == 2u;
+        s.reader_joined();
+        assert s.d.arenas[1].len() == 1u;
+    }
 }
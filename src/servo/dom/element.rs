@@ -8,6 +8,9 @@ struct ElementData {
     tag_name: ~str,
     kind: ~ElementKind,
     attrs: DVec<~Attr>,
+    // The values of the registered custom properties transitioning on the
+    // element, which `var()` references use instead of the declared ones
+    animated_properties: DVec<~Attr>,
 }
 
 #[allow(non_implicitly_copyable_typarams)]
@@ -24,9 +27,119 @@ impl ElementData {
         let idx = do self.attrs.position |attr| { name == attr.name };
         match idx {
             Some(idx) => self.attrs.set_elt(idx, ~Attr(name.to_str(), move value)),
-            None => {}
+            None => self.attrs.push(~Attr(name.to_str(), move value))
         }
     }
+
+    /// The lowercased value `name` is given in the `style` attribute, for
+    /// properties the style system doesn't know about yet. The last
+    /// declaration wins, and `var()` references in it are replaced.
+    fn get_style_property(name: &str) -> Option<~str> {
+        self.get_style_property_as_written(name).map(|value| str::to_lower(*value))
+    }
+
+    /// Like `get_style_property`, but keeping the case of the value, for
+    /// values like URLs where it matters.
+    fn get_style_property_as_written(name: &str) -> Option<~str> {
+        self.declared_style_property(name).map(|value| self.substitute_vars(*value))
+    }
+
+    // The value `name` is declared with in the `style` attribute, as written
+    priv fn declared_style_property(name: &str) -> Option<~str> {
+        match self.get_attr("style") {
+            Some(ref style) => {
+                let mut value = None;
+                for split_declarations(*style).each |declaration| {
+                    match str::find_char(*declaration, ':') {
+                        Some(i) => {
+                            let property = str::to_lower(str::trim(declaration.slice(0, i)));
+                            if property == name.to_str() {
+                                let v = declaration.slice(i + 1, declaration.len());
+                                value = Some(str::trim(v));
+                            }
+                        }
+                        None => ()
+                    }
+                }
+                move value
+            }
+            None => None
+        }
+    }
+
+    // `value`, with each `var(--name)` or `var(--name, fallback)` replaced
+    // by the custom property's animated or declared value
+    priv fn substitute_vars(value: &str) -> ~str {
+        let mut result = ~"";
+        let mut rest = value.to_str();
+        loop {
+            let start = match str::find_str(rest, "var(") {
+                Some(start) => start,
+                None => break
+            };
+            let end = match str::find_char_from(rest, ')', start) {
+                Some(end) => end,
+                None => break
+            };
+            let args = rest.slice(start + 4, end);
+            let (custom, fallback) = match str::find_char(args, ',') {
+                Some(i) => (str::trim(args.slice(0, i)),
+                            Some(str::trim(args.slice(i + 1, args.len())))),
+                None => (str::trim(args), None)
+            };
+            let animated = do self.animated_properties.find |attr| { attr.name == custom };
+            let replacement = match animated {
+                Some(attr) => Some(copy attr.value),
+                None => match self.declared_style_property(custom) {
+                    Some(move declared) if custom.starts_with("--") => Some(move declared),
+                    _ => fallback
+                }
+            };
+            result += rest.slice(0, start);
+            result += replacement.get_default(~"");
+            rest = rest.slice(end + 1, rest.len());
+        }
+        result + rest
+    }
+
+    /// Gives the registered custom property `name` its value in a frame of
+    /// a transition, or with None, goes back to the declared value.
+    fn set_animated_property(name: &str, value: Option<~str>) {
+        let others = do self.animated_properties.get().filter |attr| {
+            attr.name != name.to_str()
+        };
+        self.animated_properties.set(move others);
+        for value.each |value| {
+            self.animated_properties.push(~Attr(name.to_str(), copy *value));
+        }
+    }
+}
+
+// The declarations of a `style` attribute. A `;` in quotes or parentheses,
+// like those of a `data:` URL, doesn't end one.
+fn split_declarations(style: &str) -> ~[~str] {
+    let mut declarations = ~[];
+    let mut start = 0;
+    let mut depth = 0;
+    let mut quote = None;
+    for str::each_chari(style) |i, c| {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => (),
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ';' if depth == 0 => {
+                    declarations.push(style.slice(start, i));
+                    start = i + 1;
+                }
+                _ => ()
+            }
+        }
+    }
+    declarations.push(style.slice(start, style.len()));
+    move declarations
 }
 
 fn ElementData(tag_name: ~str, kind: ~ElementKind) -> ElementData {
@@ -34,6 +147,7 @@ fn ElementData(tag_name: ~str, kind: ~ElementKind) -> ElementData {
         tag_name : move tag_name,
         kind : move kind,
         attrs : DVec(),
+        animated_properties : DVec(),
     }
 }
 
@@ -51,12 +165,66 @@ fn Attr(name: ~str, value: ~str) -> Attr {
 
 fn HTMLImageData() -> HTMLImageData {
     HTMLImageData {
-        image: None
+        image: None,
+        loading: LoadingAuto
     }
 }
 
 struct HTMLImageData {
-    mut image: Option<Url>
+    mut image: Option<Url>,
+    mut loading: ImageLoading
+}
+
+fn HTMLInputData() -> HTMLInputData {
+    HTMLInputData {
+        value: None,
+        checked: None
+    }
+}
+
+// What the user or a script has done to an `<input>`. Until then, its value
+// and checkedness come from its `value` and `checked` attributes.
+struct HTMLInputData {
+    mut value: Option<~str>,
+    mut checked: Option<bool>
+}
+
+/// The value of the `loading` attribute on an `<img>`.
+enum ImageLoading {
+    LoadingEager,
+    LoadingLazy,
+    LoadingAuto,
+}
+
+impl ImageLoading {
+    /// Parses a `loading` attribute value. Missing and invalid values map to
+    /// `auto`, as the spec asks.
+    static pure fn from_attr(value: Option<~str>) -> ImageLoading {
+        match value {
+            Some(ref value) => match str::to_lower(*value) {
+                ~"lazy" => LoadingLazy,
+                ~"eager" => LoadingEager,
+                _ => LoadingAuto
+            },
+            None => LoadingAuto
+        }
+    }
+
+    pure fn is_lazy() -> bool {
+        match self {
+            LoadingLazy => true,
+            LoadingEager | LoadingAuto => false
+        }
+    }
+}
+
+impl ImageLoading : cmp::Eq {
+    pure fn eq(other: &ImageLoading) -> bool {
+        (self as uint) == (*other as uint)
+    }
+    pure fn ne(other: &ImageLoading) -> bool {
+        !self.eq(other)
+    }
 }
 
 enum HeadingLevel {
@@ -74,6 +242,7 @@ enum ElementKind {
     HTMLBRElement,
     HTMLBodyElement,
     HTMLBoldElement,
+    HTMLButtonElement,
     HTMLDivElement,
     HTMLFontElement,
     HTMLFormElement,
@@ -82,7 +251,7 @@ enum ElementKind {
     HTMLHeadingElement(HeadingLevel),
     HTMLHtmlElement,
     HTMLImageElement(HTMLImageData),
-    HTMLInputElement,
+    HTMLInputElement(HTMLInputData),
     HTMLItalicElement,
     HTMLLinkElement,
     HTMLListItemElement,
@@ -90,6 +259,10 @@ enum ElementKind {
     HTMLOListElement,
     HTMLOptionElement,
     HTMLParagraphElement,
+    HTMLRubyBaseElement,
+    HTMLRubyElement,
+    HTMLRubyParenthesisElement,
+    HTMLRubyTextElement,
     HTMLScriptElement,
     HTMLSectionElement,
     HTMLSelectElement,
@@ -100,7 +273,31 @@ enum ElementKind {
     HTMLTableCellElement,
     HTMLTableElement,
     HTMLTableRowElement,
+    HTMLTextAreaElement,
     HTMLTitleElement,
     HTMLUListElement,
     UnknownElement,
 }
+
+#[test]
+fn test_style_property_vars() {
+    let element = ElementData(~"div", ~HTMLDivElement);
+    element.set_attr("style", ~"--gap: 10px; column-gap: var(--gap); column-width: var(--w, 5px)");
+    assert element.get_style_property("column-gap") == Some(~"10px");
+    assert element.get_style_property("column-width") == Some(~"5px");
+
+    element.set_animated_property("--gap", Some(~"12.5px"));
+    assert element.get_style_property("column-gap") == Some(~"12.5px");
+    assert element.get_style_property("--gap") == Some(~"10px");
+    element.set_animated_property("--gap", None);
+    assert element.get_style_property("column-gap") == Some(~"10px");
+}
+
+#[test]
+fn test_style_property_keeps_urls_whole() {
+    let element = ElementData(~"div", ~HTMLDivElement);
+    element.set_attr("style", ~"float: LEFT; shape-outside: url('data:image/png;base64,AbC=')");
+    assert element.get_style_property("float") == Some(~"left");
+    assert element.get_style_property_as_written("shape-outside") ==
+        Some(~"url('data:image/png;base64,AbC=')");
+}
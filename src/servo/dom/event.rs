@@ -1,5 +1,17 @@
+use dom::events::pointer_event::PointerInput;
+use layout::paint_timing::ContentfulPaint;
+use geom::point::Point2D;
+
 enum Event {
     ResizeEvent(uint, uint, pipes::Chan<()>),
-    ReflowEvent        
+    ReflowEvent,
+    // A scroll gesture at a point in the window, by a delta in px
+    ScrollEvent(Point2D<int>, Point2D<int>),
+    // A key typed in the window
+    KeyEvent(char),
+    // A mouse, pen or finger pressed, moved or lifted in the window
+    PointerInputEvent(PointerInput),
+    // What the frame layout just built shows, for paint timing
+    PaintedEvent(ContentfulPaint)
 }
 
@@ -0,0 +1,382 @@
+/*!
+The listeners scripts add with `addEventListener`, kept per window. Elements
+are told apart by their node, since each lookup can make a new JS object for
+one; other targets (the window, file readers, ...) by their JS object.
+
+An event goes down from the window to its target, firing capture listeners
+on the way, then listeners at the target, then, if it bubbles, back up
+through non-capture listeners. `dispatch_steps` gives the order.
+
+A passive listener promises not to call `preventDefault()`, so the default
+action of its event needn't wait for it. Scrolling checks
+`has_blocking_listener` to decide whether it can go ahead before the
+`wheel` and `touchmove` listeners have run.
+*/
+
+use dom::node::Node;
+use dom::bindings::rooting::RootedValues;
+use dvec::DVec;
+use js::jsapi::{JSContext, JSObject, JSVal};
+
+pub enum EventTargetId {
+    NodeTarget(Node),
+    ObjectTarget(*JSObject)
+}
+
+impl EventTargetId : cmp::Eq {
+    pure fn eq(&self, other: &EventTargetId) -> bool {
+        match (*self, *other) {
+            (NodeTarget(a), NodeTarget(b)) => a == b,
+            (ObjectTarget(a), ObjectTarget(b)) => a == b,
+            _ => false
+        }
+    }
+    pure fn ne(&self, other: &EventTargetId) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// The third argument of `addEventListener`: a boolean for `capture`, or
+/// an options dictionary.
+pub struct EventListenerOptions {
+    capture: bool,
+    // None if the script didn't say, in which case it's `default_passive`
+    passive: Option<bool>,
+    once: bool,
+}
+
+pub fn EventListenerOptions() -> EventListenerOptions {
+    EventListenerOptions { capture: false, passive: None, once: false }
+}
+
+/// Whether the default action of `kind` events is to scroll.
+pub pure fn is_scroll_blocking(kind: &str) -> bool {
+    match kind {
+        "wheel" | "mousewheel" | "touchstart" | "touchmove" => true,
+        _ => false
+    }
+}
+
+/**
+Whether a listener is passive when the script didn't say. As in browsers,
+scroll-blocking listeners on the window, document or body are, since
+they're rarely meant to stop scrolling and would otherwise slow down every
+scroll on the page.
+*/
+pub fn default_passive(kind: &str, top_level: bool) -> bool {
+    top_level && is_scroll_blocking(kind)
+}
+
+/// The phase of dispatch, as `event.eventPhase` gives it.
+pub enum EventPhase {
+    NoPhase = 0,
+    CapturingPhase = 1,
+    AtTarget = 2,
+    BubblingPhase = 3
+}
+
+impl EventPhase : cmp::Eq {
+    pure fn eq(&self, other: &EventPhase) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &EventPhase) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// Whether events of type `kind` bubble. Most do; these are the ones
+/// that don't.
+pub pure fn bubbles(kind: &str) -> bool {
+    match kind {
+        "focus" | "blur" | "load" | "unload" | "error" | "abort" | "resize" |
+        "mouseenter" | "mouseleave" | "pointerenter" | "pointerleave" => false,
+        _ => true
+    }
+}
+
+/// Whether events of type `kind` fired by the engine can be cancelled
+/// with `preventDefault()`: those that have a default action to cancel.
+pub pure fn cancelable(kind: &str) -> bool {
+    match kind {
+        "click" | "dblclick" | "auxclick" | "contextmenu" | "mousedown" | "mouseup" |
+        "mousemove" | "mouseover" | "mouseout" | "pointerdown" | "pointerup" |
+        "pointermove" | "pointerover" | "pointerout" | "wheel" | "keydown" | "keypress" |
+        "keyup" | "touchstart" | "touchmove" | "touchend" | "beforeinput" | "submit" |
+        "invalid" | "beforeunload" | "dragstart" | "drag" | "dragenter" | "dragover" |
+        "drop" | "selectstart" => true,
+        _ => false
+    }
+}
+
+/**
+Where listeners run, in order, for an event dispatched along a path of
+`path_len` targets from the window down to the event's target. Each step is
+an index into the path, the phase, and whether it's the capture listeners
+there that run, rather than the others. Both kinds run at the target,
+capture listeners first.
+*/
+pub fn dispatch_steps(path_len: uint, bubbles: bool) -> ~[(uint, EventPhase, bool)] {
+    assert path_len > 0;
+    let target = path_len - 1;
+    let mut steps = ~[];
+    for uint::range(0, target) |i| {
+        steps.push((i, CapturingPhase, true));
+    }
+    steps.push((target, AtTarget, true));
+    steps.push((target, AtTarget, false));
+    if bubbles {
+        let mut i = target;
+        while i > 0 {
+            i -= 1;
+            steps.push((i, BubblingPhase, false));
+        }
+    }
+    move steps
+}
+
+pub struct EventListener {
+    kind: ~str,
+    callback: JSVal,
+    capture: bool,
+    passive: bool,
+    once: bool,
+}
+
+pub struct EventListeners {
+    // Each listener, with the key its callback is rooted under
+    priv entries: DVec<(EventTargetId, EventListener, uint)>,
+    priv callbacks: RootedValues,
+}
+
+pub fn EventListeners(cx: *JSContext) -> EventListeners {
+    EventListeners { entries: DVec(), callbacks: RootedValues(cx) }
+}
+
+impl EventListeners {
+    /**
+    Adds a listener to `target`. As the spec asks, a listener with the same
+    type, callback and capture as one already there is ignored; returns
+    whether it was added.
+    */
+    fn add(&self, target: EventTargetId, listener: EventListener) -> bool {
+        if self.find(target, listener.kind, listener.callback, listener.capture).is_some() {
+            return false;
+        }
+        let key = self.callbacks.add(listener.callback);
+        self.entries.push((target, move listener, key));
+        true
+    }
+
+    fn remove(&self, target: EventTargetId, kind: &str, callback: JSVal, capture: bool) {
+        match self.find(target, kind, callback, capture) {
+            Some(i) => {
+                let mut entries = self.entries.get();
+                let (_, _, key) = entries.remove(i);
+                self.callbacks.remove(key);
+                self.entries.set(move entries);
+            }
+            None => ()
+        }
+    }
+
+    /// The listeners for `kind` events at `target`, in the order they were
+    /// added.
+    fn listeners(&self, target: EventTargetId, kind: &str) -> ~[EventListener] {
+        self.filtered(target, kind, |_| true)
+    }
+
+    /// The capture listeners for `kind` events at `target` if `capture` is
+    /// set, or else the others.
+    fn phase_listeners(&self, target: EventTargetId, kind: &str, capture: bool)
+        -> ~[EventListener] {
+        self.filtered(target, kind, |listener| listener.capture == capture)
+    }
+
+    priv fn filtered(&self, target: EventTargetId, kind: &str,
+                     f: fn(&EventListener) -> bool) -> ~[EventListener] {
+        let mut listeners = ~[];
+        for self.entries.each |entry| {
+            let (t, ref listener, _) = *entry;
+            if t == target && str::eq_slice(listener.kind, kind) && f(listener) {
+                listeners.push(copy *listener);
+            }
+        }
+        move listeners
+    }
+
+    /// Everything that has listeners, once each.
+    fn targets(&self) -> ~[EventTargetId] {
+        let mut targets = ~[];
+        for self.entries.each |entry| {
+            let (t, _, _) = *entry;
+            if !targets.contains(&t) {
+                targets.push(t);
+            }
+        }
+        move targets
+    }
+
+    /// The callbacks of every listener at `target`.
+    fn callbacks(&self, target: EventTargetId) -> ~[JSVal] {
+        let mut callbacks = ~[];
+        for self.entries.each |entry| {
+            let (t, ref listener, _) = *entry;
+            if t == target {
+                callbacks.push(listener.callback);
+            }
+        }
+        move callbacks
+    }
+
+    /// Removes all the listeners at `target`.
+    fn remove_target(&self, target: EventTargetId) {
+        let (removed, kept) = vec::partition(self.entries.get(), |entry| {
+            let (t, _, _) = *entry;
+            t == target
+        });
+        for removed.each |entry| {
+            let (_, _, key) = *entry;
+            self.callbacks.remove(key);
+        }
+        self.entries.set(move kept);
+    }
+
+    /// Removes every listener, unrooting their callbacks. Done before the
+    /// context goes.
+    fn clear(&self) {
+        self.entries.set(~[]);
+        self.callbacks.clear();
+    }
+
+    /// Whether anything listens for a scroll-blocking event without being
+    /// passive, so that scrolling has to wait to see if it's cancelled.
+    fn has_blocking_listener(&self) -> bool {
+        do self.entries.get().any |entry| {
+            let (_, ref listener, _) = *entry;
+            !listener.passive && is_scroll_blocking(listener.kind)
+        }
+    }
+
+    priv fn find(&self, target: EventTargetId, kind: &str, callback: JSVal, capture: bool)
+        -> Option<uint> {
+        do self.entries.position |entry| {
+            let (t, ref listener, _) = *entry;
+            t == target && str::eq_slice(listener.kind, kind) &&
+                listener.callback == callback && listener.capture == capture
+        }
+    }
+}
+
+#[cfg(test)]
+mod event_target_tests {
+    use dom::element::{ElementData, UnknownElement};
+    use dom::node::{NodeScope, NodeScopeExtensions, Element};
+
+    fn listener(kind: &str, callback: JSVal, passive: bool) -> EventListener {
+        EventListener {
+            kind: kind.to_str(),
+            callback: callback,
+            capture: false,
+            passive: passive,
+            once: false
+        }
+    }
+
+    #[test]
+    fn test_cancelable() {
+        assert cancelable("click");
+        assert cancelable("submit");
+        assert cancelable("wheel");
+        assert !cancelable("scroll");
+        assert !cancelable("input");
+        assert !cancelable("load");
+        assert !cancelable("mouseenter");
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let scope = NodeScope();
+        let div = NodeTarget(scope.new_node(Element(ElementData(~"div", ~UnknownElement))));
+        let window = ObjectTarget(ptr::null());
+        let listeners = EventListeners(ptr::null());
+
+        assert listeners.add(div, listener("click", 1, false));
+        // The same listener again is ignored, but not one on another target
+        assert !listeners.add(div, listener("click", 1, true));
+        assert listeners.add(window, listener("click", 1, false));
+        assert listeners.add(div, listener("click", 2, false));
+        assert listeners.listeners(div, "click").map(|l| l.callback) == ~[1, 2];
+        assert listeners.listeners(div, "keydown").is_empty();
+
+        // Removing needs the capture flag to match
+        let mut capture = listener("click", 3, false);
+        capture.capture = true;
+        assert listeners.add(div, move capture);
+        listeners.remove(div, "click", 3, false);
+        assert listeners.listeners(div, "click").len() == 3;
+        listeners.remove(div, "click", 3, true);
+        listeners.remove(div, "click", 1, false);
+        assert listeners.listeners(div, "click").map(|l| l.callback) == ~[2];
+        assert listeners.listeners(window, "click").len() == 1;
+    }
+
+    #[test]
+    fn test_targets() {
+        let scope = NodeScope();
+        let div = NodeTarget(scope.new_node(Element(ElementData(~"div", ~UnknownElement))));
+        let window = ObjectTarget(ptr::null());
+        let listeners = EventListeners(ptr::null());
+        listeners.add(div, listener("click", 1, false));
+        listeners.add(window, listener("load", 2, false));
+        listeners.add(div, listener("keydown", 3, false));
+        assert listeners.targets() == ~[div, window];
+        assert listeners.callbacks(div) == ~[1, 3];
+
+        listeners.remove_target(div);
+        assert listeners.targets() == ~[window];
+        assert listeners.callbacks(div).is_empty();
+    }
+
+    #[test]
+    fn test_blocking_listeners() {
+        let listeners = EventListeners(ptr::null());
+        let window = ObjectTarget(ptr::null());
+        listeners.add(window, listener("scroll", 1, false));
+        listeners.add(window, listener("wheel", 2, true));
+        assert !listeners.has_blocking_listener();
+        listeners.add(window, listener("touchmove", 3, false));
+        assert listeners.has_blocking_listener();
+
+        assert default_passive("wheel", true);
+        assert !default_passive("wheel", false);
+        assert !default_passive("click", true);
+    }
+
+    #[test]
+    fn test_phase_listeners() {
+        let listeners = EventListeners(ptr::null());
+        let window = ObjectTarget(ptr::null());
+        let mut capture = listener("click", 1, false);
+        capture.capture = true;
+        listeners.add(window, move capture);
+        listeners.add(window, listener("click", 2, false));
+        assert listeners.phase_listeners(window, "click", true).map(|l| l.callback) == ~[1];
+        assert listeners.phase_listeners(window, "click", false).map(|l| l.callback) == ~[2];
+    }
+
+    #[test]
+    fn test_dispatch_steps() {
+        // The window, the document, a div and the target inside it
+        let steps = dispatch_steps(4, true);
+        assert steps == ~[(0, CapturingPhase, true), (1, CapturingPhase, true),
+                          (2, CapturingPhase, true),
+                          (3, AtTarget, true), (3, AtTarget, false),
+                          (2, BubblingPhase, false), (1, BubblingPhase, false),
+                          (0, BubblingPhase, false)];
+        assert dispatch_steps(4, false).len() == 5;
+        // A target that's on its own, like the window
+        assert dispatch_steps(1, true) == ~[(0, AtTarget, true), (0, AtTarget, false)];
+        assert !bubbles("focus");
+        assert bubbles("click");
+    }
+}
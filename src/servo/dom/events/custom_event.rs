@@ -0,0 +1,28 @@
+/*!
+`CustomEvent`: an event a script makes itself, carrying whatever it likes
+in `detail`. Unlike the events the engine fires, an event made by a script
+neither bubbles nor can be cancelled unless its init dictionary says so.
+*/
+
+use js::jsapi::JSVal;
+
+/// The members of `EventInit`.
+pub struct EventInit {
+    bubbles: bool,
+    cancelable: bool,
+}
+
+pub fn EventInit() -> EventInit {
+    EventInit { bubbles: false, cancelable: false }
+}
+
+pub struct CustomEvent {
+    kind: ~str,
+    init: EventInit,
+    // A structured clone of the `detail` the script passed, or null
+    detail: JSVal,
+}
+
+pub fn CustomEvent(kind: ~str, init: EventInit, detail: JSVal) -> CustomEvent {
+    CustomEvent { kind: move kind, init: init, detail: detail }
+}
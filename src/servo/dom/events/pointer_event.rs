@@ -0,0 +1,354 @@
+/*!
+Pointer events: what a mouse, pen or finger is doing, in the form
+`PointerEvent` gives to scripts, and which element each pointer has been
+captured by.
+
+Pointer moves can come much faster than frames, so the compositor queues
+them in a `PointerCoalescer` and sends them on once per frame; the moves of
+one pointer in between are merged into a single event that remembers each
+of them, for `getCoalescedEvents()`.
+*/
+
+use dom::node::Node;
+use dvec::DVec;
+use geom::point::Point2D;
+
+pub enum PointerType {
+    MousePointer,
+    PenPointer,
+    TouchPointer
+}
+
+impl PointerType {
+    /// The `pointerType` scripts see.
+    pure fn to_str() -> ~str {
+        match self {
+            MousePointer => ~"mouse",
+            PenPointer => ~"pen",
+            TouchPointer => ~"touch"
+        }
+    }
+}
+
+impl PointerType : cmp::Eq {
+    pure fn eq(&self, other: &PointerType) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &PointerType) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+pub enum PointerPhase {
+    PointerDown,
+    PointerMove,
+    PointerUp,
+    PointerCancel
+}
+
+impl PointerPhase {
+    /// The type of the event fired for this phase.
+    pure fn event_type() -> ~str {
+        match self {
+            PointerDown => ~"pointerdown",
+            PointerMove => ~"pointermove",
+            PointerUp => ~"pointerup",
+            PointerCancel => ~"pointercancel"
+        }
+    }
+}
+
+impl PointerPhase : cmp::Eq {
+    pure fn eq(&self, other: &PointerPhase) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &PointerPhase) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// The state of a pointer at one moment. Sizes and positions are in px;
+/// `point` is relative to the window.
+pub struct PointerSample {
+    pointer_id: i32,
+    pointer_type: PointerType,
+    is_primary: bool,
+    point: Point2D<int>,
+    // The button that changed, as in MouseEvent.button, or -1 for none
+    button: i16,
+    // The buttons held down, as in MouseEvent.buttons
+    buttons: u16,
+    width: f64,
+    height: f64,
+    pressure: f64,
+    tangential_pressure: f64,
+    tilt_x: i32,
+    tilt_y: i32,
+    twist: i32,
+}
+
+// The mouse is always pointer 1, and always the primary pointer
+const MOUSE_POINTER_ID: i32 = 1;
+
+/**
+The mouse as a pointer, at `point` with `buttons` held. As the spec asks
+for hardware that can't sense pressure, it's 0.5 while a button is held and
+0 otherwise.
+*/
+pub fn mouse_sample(point: Point2D<int>, button: i16, buttons: u16) -> PointerSample {
+    PointerSample {
+        pointer_id: MOUSE_POINTER_ID,
+        pointer_type: MousePointer,
+        is_primary: true,
+        point: point,
+        button: button,
+        buttons: buttons,
+        width: 1.0,
+        height: 1.0,
+        pressure: if buttons != 0 { 0.5 } else { 0.0 },
+        tangential_pressure: 0.0,
+        tilt_x: 0,
+        tilt_y: 0,
+        twist: 0
+    }
+}
+
+/// The bit a mouse button sets in `buttons`, given its `button` number.
+/// The middle and right buttons are numbered the other way round.
+pub pure fn button_bit(button: i16) -> u16 {
+    match button {
+        0 => 1,
+        1 => 4,
+        2 => 2,
+        b if b >= 0 && b < 16 => 1 << (b as u16),
+        _ => 0
+    }
+}
+
+/// A pointer event for the content task to fire. `coalesced` holds every
+/// sample a move was merged from, oldest first, ending with `sample`.
+pub struct PointerInput {
+    phase: PointerPhase,
+    sample: PointerSample,
+    coalesced: ~[PointerSample],
+}
+
+pub fn PointerInput(phase: PointerPhase, sample: PointerSample) -> PointerInput {
+    PointerInput {
+        phase: phase,
+        sample: sample,
+        coalesced: if phase == PointerMove { ~[sample] } else { ~[] }
+    }
+}
+
+/// Queues pointer inputs between frames, merging each pointer's moves.
+pub struct PointerCoalescer {
+    priv pending: DVec<PointerInput>,
+}
+
+pub fn PointerCoalescer() -> PointerCoalescer {
+    PointerCoalescer { pending: DVec() }
+}
+
+impl PointerCoalescer {
+    /**
+    Queues `input`. A move is merged into the last input queued for its
+    pointer if that's a move too; moves of other pointers in between don't
+    matter, but a press or release does.
+    */
+    fn push(&self, input: PointerInput) {
+        if input.phase == PointerMove {
+            let id = input.sample.pointer_id;
+            match self.pending.rposition(|p| p.sample.pointer_id == id) {
+                Some(i) if self.pending.get_elt(i).phase == PointerMove => {
+                    let mut merged = self.pending.get_elt(i);
+                    merged.coalesced.push(input.sample);
+                    merged.sample = input.sample;
+                    self.pending.set_elt(i, move merged);
+                    return;
+                }
+                _ => ()
+            }
+        }
+        self.pending.push(move input);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending.len() == 0
+    }
+
+    /// Takes everything queued, in order.
+    fn take(&self) -> ~[PointerInput] {
+        let mut inputs = ~[];
+        for self.pending.each |input| {
+            inputs.push(copy *input);
+        }
+        self.pending.set(~[]);
+        move inputs
+    }
+}
+
+/**
+Which pointers are down, and which element, if any, each has been captured
+by. A captured pointer's events go to its element wherever the pointer is.
+*/
+pub struct PointerCaptures {
+    priv active: DVec<i32>,
+    priv captures: DVec<(i32, Node)>,
+}
+
+pub fn PointerCaptures() -> PointerCaptures {
+    PointerCaptures {
+        active: DVec(),
+        captures: DVec(),
+    }
+}
+
+impl PointerCaptures {
+    /// Notes that a pointer has been pressed, before its event is fired.
+    fn pointer_down(&self, pointer_id: i32) {
+        if !self.is_active(pointer_id) {
+            self.active.push(pointer_id);
+        }
+    }
+
+    /**
+    Notes that a pointer has been lifted or cancelled, once its event has
+    been fired, releasing any capture. Returns the element that lost it.
+    */
+    fn pointer_up(&self, pointer_id: i32) -> Option<Node> {
+        self.active.set(vec::filter(self.active.get(), |id| *id != pointer_id));
+        self.release_any(pointer_id)
+    }
+
+    fn is_active(&self, pointer_id: i32) -> bool {
+        self.active.position(|id| *id == pointer_id).is_some()
+    }
+
+    /// The element `pointer_id` is captured by.
+    fn target(&self, pointer_id: i32) -> Option<Node> {
+        for self.captures.each |capture| {
+            let (id, node) = *capture;
+            if id == pointer_id {
+                return Some(node);
+            }
+        }
+        None
+    }
+
+    /**
+    Captures `pointer_id` to `node`, as `setPointerCapture()` does. Returns
+    Err if the pointer isn't down, which scripts see as a NotFoundError, and
+    otherwise the element that lost the capture, if it moved.
+    */
+    fn set(&self, pointer_id: i32, node: Node) -> Result<Option<Node>, ()> {
+        if !self.is_active(pointer_id) {
+            return Err(());
+        }
+        let old = self.release_any(pointer_id);
+        self.captures.push((pointer_id, node));
+        match old {
+            Some(old) if old != node => Ok(Some(old)),
+            _ => Ok(None)
+        }
+    }
+
+    /// Releases `pointer_id` if `node` has it, returning whether it did.
+    fn release(&self, pointer_id: i32, node: Node) -> bool {
+        if self.target(pointer_id) == Some(node) {
+            self.release_any(pointer_id);
+            true
+        } else {
+            false
+        }
+    }
+
+    priv fn release_any(&self, pointer_id: i32) -> Option<Node> {
+        let old = self.target(pointer_id);
+        self.captures.set(vec::filter(self.captures.get(), |capture| {
+            let (id, _) = *capture;
+            id != pointer_id
+        }));
+        old
+    }
+}
+
+#[cfg(test)]
+mod pointer_event_tests {
+    use dom::element::{ElementData, UnknownElement};
+    use dom::node::{NodeScope, NodeScopeExtensions, Element};
+
+    fn touch(id: i32, x: int) -> PointerSample {
+        PointerSample {
+            pointer_id: id,
+            pointer_type: TouchPointer,
+            is_primary: id == 2,
+            point: Point2D(x, 0),
+            button: -1,
+            buttons: 1,
+            width: 10.0,
+            height: 10.0,
+            pressure: 0.7,
+            tangential_pressure: 0.0,
+            tilt_x: 0,
+            tilt_y: 0,
+            twist: 0
+        }
+    }
+
+    #[test]
+    fn test_coalescing() {
+        let queue = PointerCoalescer();
+        queue.push(PointerInput(PointerDown, touch(2, 0)));
+        queue.push(PointerInput(PointerMove, touch(2, 1)));
+        queue.push(PointerInput(PointerMove, touch(3, 100)));
+        queue.push(PointerInput(PointerMove, touch(2, 2)));
+        queue.push(PointerInput(PointerMove, touch(2, 3)));
+        queue.push(PointerInput(PointerUp, touch(3, 100)));
+        queue.push(PointerInput(PointerMove, touch(3, 101)));
+
+        let inputs = queue.take();
+        assert queue.is_empty();
+        assert inputs.len() == 5;
+        assert inputs[0].phase == PointerDown;
+        assert inputs[0].coalesced.is_empty();
+        // Pointer 2's moves are merged into the first of them
+        assert inputs[1].sample.point == Point2D(3, 0);
+        assert inputs[1].coalesced.map(|s| s.point.x) == ~[1, 2, 3];
+        assert inputs[2].sample.pointer_id == 3;
+        // The move after pointer 3 lifts isn't merged into the one before
+        assert inputs[3].phase == PointerUp;
+        assert inputs[4].coalesced.len() == 1;
+    }
+
+    #[test]
+    fn test_captures() {
+        let scope = NodeScope();
+        let a = scope.new_node(Element(ElementData(~"div", ~UnknownElement)));
+        let b = scope.new_node(Element(ElementData(~"div", ~UnknownElement)));
+        let captures = PointerCaptures();
+
+        // Only pointers that are down can be captured
+        assert captures.set(1, a).is_err();
+        captures.pointer_down(1);
+        assert captures.set(1, a) == Ok(None);
+        assert captures.target(1) == Some(a);
+        assert captures.set(1, b) == Ok(Some(a));
+        assert !captures.release(1, a);
+        assert captures.target(1) == Some(b);
+        // Lifting the pointer releases it
+        assert captures.pointer_up(1) == Some(b);
+        assert captures.target(1).is_none();
+        assert !captures.is_active(1);
+    }
+
+    #[test]
+    fn test_mouse_sample() {
+        let pressed = mouse_sample(Point2D(5, 6), 0, button_bit(0));
+        assert pressed.pressure == 0.5;
+        assert pressed.pointer_type.to_str() == ~"mouse";
+        assert mouse_sample(Point2D(5, 6), -1, 0).pressure == 0.0;
+        assert button_bit(1) == 4;
+        assert button_bit(2) == 2;
+    }
+}
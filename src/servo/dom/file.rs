@@ -0,0 +1,37 @@
+/*!
+`File`: a Blob with a name and a modification time.
+*/
+
+use dom::blob::Blob;
+
+pub struct File {
+    blob: Blob,
+    name: ~str,
+    // Milliseconds since the epoch
+    last_modified: i64,
+}
+
+pub fn File(blob: Blob, name: ~str, last_modified: i64) -> File {
+    File {
+        blob: move blob,
+        name: move name,
+        last_modified: last_modified,
+    }
+}
+
+impl File {
+    fn clone(&self) -> File {
+        File {
+            blob: self.blob.clone(),
+            name: copy self.name,
+            last_modified: self.last_modified,
+        }
+    }
+}
+
+/// The current time in milliseconds since the epoch, the default for
+/// `last_modified`.
+pub fn now() -> i64 {
+    let time = std::time::get_time();
+    time.sec * 1000 + (time.nsec / 1000000) as i64
+}
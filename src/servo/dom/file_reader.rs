@@ -0,0 +1,66 @@
+/*!
+The conversions behind `FileReader`'s `readAsText` and `readAsDataURL`.
+*/
+
+use dom::blob::Blob;
+use std::base64::ToBase64;
+
+pub enum ReadyState {
+    Empty = 0,
+    Loading = 1,
+    Done = 2
+}
+
+/// Decodes UTF-8, replacing invalid sequences with U+FFFD.
+pub fn decode_utf8_lossy(bytes: &[u8]) -> ~str {
+    let mut s = ~"";
+    let mut i = 0;
+    while i < bytes.len() {
+        let width = str::utf8_char_width(bytes[i]);
+        if width > 0 && i + width <= bytes.len() && str::is_utf8(vec::view(bytes, i, i + width)) {
+            str::push_str(&mut s, str::from_bytes(vec::view(bytes, i, i + width)));
+            i += width;
+        } else {
+            str::push_char(&mut s, '�');
+            i += 1;
+        }
+    }
+    move s
+}
+
+pub fn read_as_text(blob: &Blob) -> ~str {
+    do blob.with_bytes |bytes| {
+        // Skip a byte order mark
+        if bytes.len() >= 3 && bytes[0] == 0xEF && bytes[1] == 0xBB && bytes[2] == 0xBF {
+            decode_utf8_lossy(vec::view(bytes, 3, bytes.len()))
+        } else {
+            decode_utf8_lossy(bytes)
+        }
+    }
+}
+
+pub fn read_as_data_url(blob: &Blob) -> ~str {
+    let content_type = if blob.content_type.is_empty() {
+        ~"application/octet-stream"
+    } else {
+        copy blob.content_type
+    };
+    do blob.with_bytes |bytes| {
+        fmt!("data:%s;base64,%s", content_type, bytes.to_base64())
+    }
+}
+
+#[test]
+fn test_decode_utf8_lossy() {
+    assert decode_utf8_lossy(str::to_bytes("café")) == ~"café";
+    assert decode_utf8_lossy(~[0x61u8, 0xFF, 0x62]) == ~"a�b";
+    // A truncated sequence at the end
+    assert decode_utf8_lossy(~[0x61u8, 0xC3]) == ~"a�";
+}
+
+#[test]
+fn test_read_as_data_url() {
+    assert read_as_data_url(&Blob(str::to_bytes("hi"), "text/plain")) ==
+        ~"data:text/plain;base64,aGk=";
+    assert read_as_data_url(&Blob(~[], "")) == ~"data:application/octet-stream;base64,";
+}
@@ -0,0 +1,159 @@
+/*!
+Which elements can take keyboard focus, and the order Tab moves through
+them in.
+*/
+
+use dom::document::Document;
+use dom::element::*;
+use dom::node::{Node, NodeScope, Element};
+use js::jsapi::JSVal;
+
+/// The focused element, and the JS object scripts know it by, which the
+/// window keeps rooted.
+pub struct FocusedElement {
+    node: Node,
+    obj: JSVal,
+}
+
+// The element's `tabindex`, or the default for elements that are focusable
+// without one
+fn tab_index(scope: &NodeScope, node: Node) -> Option<int> {
+    do scope.read(&node) |n| {
+        match n.kind {
+            ~Element(ref e) => {
+                let focusable_by_default = match e.kind {
+                    ~HTMLAnchorElement => e.get_attr("href").is_some(),
+                    ~HTMLInputElement(*) => e.get_attr("type") != Some(~"hidden"),
+                    ~HTMLButtonElement | ~HTMLSelectElement | ~HTMLTextAreaElement => true,
+                    _ => false
+                };
+                let disabled = match e.kind {
+                    ~HTMLButtonElement | ~HTMLInputElement(*) | ~HTMLSelectElement |
+                    ~HTMLOptionElement | ~HTMLTextAreaElement => {
+                        e.get_attr("disabled").is_some()
+                    }
+                    _ => false
+                };
+                if disabled {
+                    None
+                } else {
+                    match e.get_attr("tabindex").chain(|s| int::from_str(str::trim(s))) {
+                        Some(index) => Some(index),
+                        None if focusable_by_default => Some(0),
+                        None => None
+                    }
+                }
+            }
+            _ => None
+        }
+    }
+}
+
+/// Whether `element.focus()` can focus `node`. Elements with a negative
+/// `tabindex` can be, though Tab skips them.
+pub fn is_focusable(scope: &NodeScope, node: Node) -> bool {
+    tab_index(scope, node).is_some()
+}
+
+/**
+The elements Tab moves through: those with a positive `tabindex` in
+ascending order, ties broken by tree order, then those with a `tabindex` of
+0 (including links and form controls without one) in tree order.
+*/
+pub fn tab_order(document: &Document) -> ~[Node] {
+    let scope = &document.scope;
+    let mut positive = ~[];
+    let mut zero = ~[];
+    let mut stack = ~[document.root];
+    while !stack.is_empty() {
+        let node = stack.pop();
+        match tab_index(scope, node) {
+            Some(0) => zero.push(node),
+            Some(index) if index > 0 => positive.push((index, positive.len(), node)),
+            _ => ()
+        }
+        let mut children = ~[];
+        for scope.each_child(&node) |child| { children.push(*child); true; }
+        // Reversed, so that they're popped in tree order
+        stack.push_all_move(vec::reversed(children));
+    }
+
+    std::sort::quick_sort(positive, |a, b| {
+        let (index_a, pos_a, _) = *a;
+        let (index_b, pos_b, _) = *b;
+        index_a < index_b || (index_a == index_b && pos_a <= pos_b)
+    });
+    let mut order = positive.map(|entry| { let (_, _, node) = *entry; node });
+    order.push_all_move(move zero);
+    move order
+}
+
+/// The element after (or before) `current` in `order`, wrapping around at
+/// the ends. With nothing focused, it's the first (or last) element.
+pub fn next_focus(order: &[Node], current: Option<Node>, forward: bool) -> Option<Node> {
+    if order.is_empty() {
+        return None;
+    }
+    let len = order.len();
+    let position = current.chain(|node| vec::position(order, |n| *n == node));
+    Some(match (position, forward) {
+        (Some(i), true) => order[(i + 1) % len],
+        (Some(i), false) => order[(i + len - 1) % len],
+        (None, true) => order[0],
+        (None, false) => order[len - 1]
+    })
+}
+
+#[cfg(test)]
+mod focus_tests {
+    use dom::node::NodeScopeExtensions;
+
+    fn element(scope: &NodeScope, parent: Node, kind: ~ElementKind,
+               attrs: &[(~str, ~str)]) -> Node {
+        let data = ElementData(~"x", move kind);
+        for attrs.each |attr| {
+            let (name, value) = copy *attr;
+            data.attrs.push(~Attr(move name, move value));
+        }
+        let node = scope.new_node(Element(move data));
+        scope.add_child(parent, node);
+        node
+    }
+
+    #[test]
+    fn test_tab_order() {
+        let scope = NodeScope();
+        let root = scope.new_node(Element(ElementData(~"body", ~HTMLBodyElement)));
+        let link = element(&scope, root, ~HTMLAnchorElement, ~[(~"href", ~"#")]);
+        let second = element(&scope, root, ~HTMLDivElement, ~[(~"tabindex", ~"2")]);
+        let skipped = element(&scope, root, ~HTMLInputElement(HTMLInputData()),
+                              ~[(~"tabindex", ~"-1")]);
+        let disabled = element(&scope, root, ~HTMLInputElement(HTMLInputData()),
+                               ~[(~"disabled", ~"")]);
+        let div = element(&scope, root, ~HTMLDivElement, ~[]);
+        let input = element(&scope, div, ~HTMLInputElement(HTMLInputData()), ~[]);
+        let first = element(&scope, div, ~HTMLSpanElement, ~[(~"tabindex", ~"1")]);
+        let also_second = element(&scope, div, ~HTMLSpanElement, ~[(~"tabindex", ~"2")]);
+
+        let order = tab_order(&Document(root, scope));
+        assert order == ~[first, second, also_second, link, input];
+        assert is_focusable(&scope, skipped);
+        assert !is_focusable(&scope, disabled);
+        assert !is_focusable(&scope, div);
+    }
+
+    #[test]
+    fn test_next_focus_wraps() {
+        let scope = NodeScope();
+        let root = scope.new_node(Element(ElementData(~"body", ~HTMLBodyElement)));
+        let a = element(&scope, root, ~HTMLSelectElement, ~[]);
+        let b = element(&scope, root, ~HTMLSelectElement, ~[]);
+        let order = ~[a, b];
+
+        assert next_focus(order, None, true) == Some(a);
+        assert next_focus(order, None, false) == Some(b);
+        assert next_focus(order, Some(b), true) == Some(a);
+        assert next_focus(order, Some(a), false) == Some(b);
+        assert next_focus(~[], Some(a), true).is_none();
+    }
+}
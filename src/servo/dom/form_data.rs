@@ -0,0 +1,215 @@
+/*!
+`FormData`: an ordered list of name/value pairs to submit, and its
+`multipart/form-data` encoding.
+*/
+
+use dom::file::File;
+
+pub enum FormDataEntryValue {
+    StringValue(~str),
+    FileValue(File)
+}
+
+impl FormDataEntryValue {
+    fn clone(&self) -> FormDataEntryValue {
+        match *self {
+            StringValue(ref s) => StringValue(copy *s),
+            FileValue(ref file) => FileValue(file.clone())
+        }
+    }
+}
+
+pub struct FormData {
+    priv mut entries: ~[(~str, FormDataEntryValue)],
+}
+
+pub fn FormData() -> FormData {
+    FormData { entries: ~[] }
+}
+
+impl FormData {
+    fn append(&self, name: ~str, value: FormDataEntryValue) {
+        self.entries.push((move name, move value));
+    }
+
+    fn delete(&self, name: &str) {
+        let mut entries = ~[];
+        entries <-> self.entries;
+        do vec::consume(move entries) |_i, entry| {
+            let (entry_name, value) = move entry;
+            if entry_name != name.to_str() {
+                self.entries.push((move entry_name, move value));
+            }
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<FormDataEntryValue> {
+        for self.entries.each |entry| {
+            let (ref entry_name, ref value) = *entry;
+            if *entry_name == name.to_str() {
+                return Some(value.clone());
+            }
+        }
+        None
+    }
+
+    fn get_all(&self, name: &str) -> ~[FormDataEntryValue] {
+        let mut values = ~[];
+        for self.entries.each |entry| {
+            let (ref entry_name, ref value) = *entry;
+            if *entry_name == name.to_str() {
+                values.push(value.clone());
+            }
+        }
+        move values
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.entries.any(|entry| {
+            let (ref entry_name, _) = *entry;
+            *entry_name == name.to_str()
+        })
+    }
+
+    /// Replaces the first entry called `name` and removes the others, or
+    /// appends a new entry if there wasn't one.
+    fn set(&self, name: ~str, value: FormDataEntryValue) {
+        let mut entries = ~[];
+        let mut value = Some(move value);
+        entries <-> self.entries;
+        do vec::consume(move entries) |_i, entry| {
+            let (entry_name, entry_value) = move entry;
+            if entry_name != name {
+                self.entries.push((move entry_name, move entry_value));
+            } else if value.is_some() {
+                self.entries.push((move entry_name, option::swap_unwrap(&mut value)));
+            }
+        }
+        if value.is_some() {
+            self.entries.push((move name, option::swap_unwrap(&mut value)));
+        }
+    }
+
+    fn entries(&self) -> ~[(~str, FormDataEntryValue)] {
+        do self.entries.map |entry| {
+            let (ref name, ref value) = *entry;
+            (copy *name, value.clone())
+        }
+    }
+
+    fn keys(&self) -> ~[~str] {
+        do self.entries.map |entry| {
+            let (ref name, _) = *entry;
+            copy *name
+        }
+    }
+
+    fn values(&self) -> ~[FormDataEntryValue] {
+        do self.entries.map |entry| {
+            let (_, ref value) = *entry;
+            value.clone()
+        }
+    }
+}
+
+/**
+Encodes form data as a `multipart/form-data` body. Returns the body and
+the value of the Content-Type header, which carries the boundary.
+*/
+pub fn encode_multipart(data: &FormData) -> (~[u8], ~str) {
+    let rng = rand::Rng();
+    let boundary = ~"----ServoFormBoundary" +
+        str::from_chars(vec::from_fn(16, |_i| {
+            rng.gen_char_from("0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ")
+        }));
+    encode_multipart_with_boundary(data, boundary)
+}
+
+pub fn encode_multipart_with_boundary(data: &FormData, boundary: &str) -> (~[u8], ~str) {
+    let mut body = ~[];
+    for data.entries.each |entry| {
+        let (ref name, ref value) = *entry;
+        body += str::to_bytes(fmt!("--%s\r\nContent-Disposition: form-data; name=\"%s\"",
+                                   boundary, escape_name(*name)));
+        match *value {
+            StringValue(ref s) => {
+                body += str::to_bytes(~"\r\n\r\n");
+                body += str::to_bytes(*s);
+            }
+            FileValue(ref file) => {
+                let content_type = if file.blob.content_type.is_empty() {
+                    ~"application/octet-stream"
+                } else {
+                    copy file.blob.content_type
+                };
+                body += str::to_bytes(fmt!("; filename=\"%s\"\r\nContent-Type: %s\r\n\r\n",
+                                           escape_name(file.name), content_type));
+                do file.blob.with_bytes |bytes| { body += bytes; }
+            }
+        }
+        body += str::to_bytes(~"\r\n");
+    }
+    body += str::to_bytes(fmt!("--%s--\r\n", boundary));
+
+    (move body, fmt!("multipart/form-data; boundary=%s", boundary))
+}
+
+// Names and file names are quoted in the headers, so escape the quote and
+// the line breaks the way browsers do
+fn escape_name(name: &str) -> ~str {
+    let mut escaped = ~"";
+    for str::each_char(name) |c| {
+        match c {
+            '"' => escaped += ~"%22",
+            '\r' => escaped += ~"%0D",
+            '\n' => escaped += ~"%0A",
+            _ => str::push_char(&mut escaped, c)
+        }
+    }
+    move escaped
+}
+
+#[test]
+fn test_set_replaces_first_and_removes_rest() {
+    let data = FormData();
+    data.append(~"a", StringValue(~"1"));
+    data.append(~"b", StringValue(~"2"));
+    data.append(~"a", StringValue(~"3"));
+    data.set(~"a", StringValue(~"4"));
+
+    assert data.keys() == ~[~"a", ~"b"];
+    match data.get("a") {
+        Some(StringValue(move s)) => assert s == ~"4",
+        _ => fail
+    }
+    assert data.get_all("a").len() == 1;
+}
+
+#[test]
+fn test_delete() {
+    let data = FormData();
+    data.append(~"a", StringValue(~"1"));
+    data.append(~"b", StringValue(~"2"));
+    data.append(~"a", StringValue(~"3"));
+    data.delete("a");
+
+    assert !data.has("a");
+    assert data.has("b");
+    assert data.get("a").is_none();
+}
+
+#[test]
+fn test_encode_multipart() {
+    let data = FormData();
+    data.append(~"greeting", StringValue(~"hello"));
+    let file = File(dom::blob::Blob(str::to_bytes("abc"), "text/plain"), ~"a.txt", 0);
+    data.append(~"up\"load", FileValue(move file));
+    let (body, content_type) = encode_multipart_with_boundary(&data, "XYZ");
+
+    assert content_type == ~"multipart/form-data; boundary=XYZ";
+    assert str::from_bytes(body) ==
+        ~"--XYZ\r\nContent-Disposition: form-data; name=\"greeting\"\r\n\r\nhello\r\n" +
+        ~"--XYZ\r\nContent-Disposition: form-data; name=\"up%22load\"; filename=\"a.txt\"\r\n" +
+        ~"Content-Type: text/plain\r\n\r\nabc\r\n" +
+        ~"--XYZ--\r\n";
+}
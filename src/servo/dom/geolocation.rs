@@ -0,0 +1,143 @@
+/*!
+The Geolocation API, exposed to scripts as `navigator.geolocation`.
+
+Permission is asked for the first time a page wants a position, and the
+answer is remembered for the rest of the page. It's denied unless servo
+is run with `--permissions grant`.
+
+There is no location backend on any platform yet: `platform_position`
+always returns None, so even granted requests fail with
+`PositionUnavailable`.
+*/
+
+use dvec::DVec;
+use opts::PermissionPrompt;
+
+pub struct Coordinates {
+    latitude: float,
+    longitude: float,
+    // In meters
+    accuracy: float,
+}
+
+pub struct Position {
+    coords: Coordinates,
+    // Milliseconds since the epoch
+    timestamp: u64,
+}
+
+/// The `code` of a GeolocationPositionError.
+pub enum PositionError {
+    PermissionDenied = 1,
+    PositionUnavailable = 2,
+    Timeout = 3
+}
+
+impl PositionError {
+    pure fn message() -> ~str {
+        match self {
+            PermissionDenied => ~"User denied Geolocation",
+            PositionUnavailable => ~"Position unavailable",
+            Timeout => ~"Timeout expired"
+        }
+    }
+}
+
+impl PositionError : cmp::Eq {
+    pure fn eq(other: &PositionError) -> bool {
+        (self as uint) == (*other as uint)
+    }
+    pure fn ne(other: &PositionError) -> bool {
+        !self.eq(other)
+    }
+}
+
+pub struct Geolocation {
+    prompt: PermissionPrompt,
+    // None until the user has been asked
+    mut permission: Option<bool>,
+    mut next_watch_id: uint,
+    watches: DVec<uint>,
+}
+
+pub fn Geolocation(prompt: PermissionPrompt) -> Geolocation {
+    Geolocation {
+        prompt: prompt,
+        permission: None,
+        next_watch_id: 1,
+        watches: DVec(),
+    }
+}
+
+impl Geolocation {
+    fn request_permission() -> bool {
+        match self.permission {
+            Some(granted) => granted,
+            None => {
+                let granted = self.prompt.grants();
+                self.permission = Some(granted);
+                granted
+            }
+        }
+    }
+
+    fn current_position() -> Result<Position, PositionError> {
+        if !self.request_permission() {
+            return Err(PermissionDenied);
+        }
+        match platform_position() {
+            Some(move position) => Ok(move position),
+            None => Err(PositionUnavailable)
+        }
+    }
+
+    /// Starts a watch and returns its id. Ids are never zero.
+    fn watch() -> uint {
+        let id = self.next_watch_id;
+        self.next_watch_id += 1;
+        self.watches.push(id);
+        id
+    }
+
+    fn clear_watch(id: uint) {
+        let watches = do vec::filter(self.watches.get()) |w| { *w != id };
+        self.watches.set(move watches);
+    }
+
+    fn is_watching(id: uint) -> bool {
+        self.watches.get().contains(&id)
+    }
+}
+
+// Always None, as no platform's location service is hooked up
+// TODO: ask the OS (CoreLocation on Mac, GeoClue on Linux)
+priv fn platform_position() -> Option<Position> {
+    None
+}
+
+#[test]
+fn test_permission_is_remembered() {
+    use opts::{PromptGrant, PromptDeny};
+
+    let geolocation = Geolocation(PromptDeny);
+    assert geolocation.current_position().get_err() == PermissionDenied;
+    assert geolocation.permission == Some(false);
+
+    // there's no backend, so the position is never available
+    let geolocation = Geolocation(PromptGrant);
+    assert geolocation.current_position().get_err() == PositionUnavailable;
+    assert geolocation.permission == Some(true);
+}
+
+#[test]
+fn test_clear_watch() {
+    use opts::PromptGrant;
+
+    let geolocation = Geolocation(PromptGrant);
+    let a = geolocation.watch();
+    let b = geolocation.watch();
+    assert a != b;
+    geolocation.clear_watch(a);
+    assert !geolocation.is_watching(a);
+    assert geolocation.is_watching(b);
+}
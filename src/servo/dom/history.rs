@@ -0,0 +1,204 @@
+/*!
+The session history of a window, as seen through `window.history`.
+
+Only same-document entries made by `pushState` are supported: moving
+through the history changes the current entry and fires `popstate`, but
+never loads a page.
+
+Each entry's state is a structured clone of the one passed to `pushState`
+or `replaceState`, rooted for as long as the entry is in the history.
+*/
+
+use dom::bindings::rooting::RootedValues;
+use dvec::DVec;
+use js::JSVAL_NULL;
+use js::jsapi::{JSContext, JSVal};
+use std::net::url::Url;
+use util::url::url_to_str;
+use util::url::make_url;
+
+pub struct HistoryEntry {
+    // The key of the state in the history's roots
+    state_key: uint,
+    title: ~str,
+    url: Url,
+}
+
+pub struct History {
+    entries: DVec<HistoryEntry>,
+    mut index: uint,
+    priv states: RootedValues,
+}
+
+pub fn History(cx: *JSContext, url: Url) -> History {
+    let states = RootedValues(cx);
+    let entries = DVec();
+    entries.push(HistoryEntry { state_key: states.add(JSVAL_NULL), title: ~"", url: move url });
+    History {
+        entries: move entries,
+        index: 0,
+        states: move states,
+    }
+}
+
+#[allow(non_implicitly_copyable_typarams)]
+impl History {
+    fn len() -> uint {
+        self.entries.len()
+    }
+
+    fn current() -> HistoryEntry {
+        self.entries.get_elt(self.index)
+    }
+
+    /// The state of the current entry. It's null once the states are
+    /// unrooted.
+    fn state() -> JSVal {
+        let key = self.current().state_key;
+        if self.states.contains(key) {
+            self.states.get(key)
+        } else {
+            JSVAL_NULL
+        }
+    }
+
+    priv fn drop_state(key: uint) {
+        if self.states.contains(key) {
+            self.states.remove(key);
+        }
+    }
+
+    fn href() -> ~str {
+        url_to_str(copy self.current().url)
+    }
+
+    /// Resolves a `pushState` URL against the current one, which it has to be
+    /// able to replace without a load.
+    fn resolve_url(url: Option<~str>) -> Result<Url, ()> {
+        let current = self.current().url;
+        match move url {
+            None => Ok(move current),
+            Some(move url) => {
+                let resolved = make_url(move url, Some(copy current));
+                if can_rewrite_url(&resolved, &current) {
+                    Ok(move resolved)
+                } else {
+                    Err(())
+                }
+            }
+        }
+    }
+
+    /// Adds an entry after the current one, dropping any entries that were
+    /// forward of it.
+    fn push_state(state: JSVal, title: ~str, url: Url) {
+        let mut entries = self.entries.get();
+        for uint::range(self.index + 1, entries.len()) |i| {
+            self.drop_state(entries[i].state_key);
+        }
+        vec::truncate(&mut entries, self.index + 1);
+        entries.push(HistoryEntry { state_key: self.states.add(state), title: move title,
+                                    url: move url });
+        self.entries.set(move entries);
+        self.index += 1;
+    }
+
+    fn replace_state(state: JSVal, title: ~str, url: Url) {
+        self.drop_state(self.current().state_key);
+        self.entries.set_elt(self.index, HistoryEntry { state_key: self.states.add(state),
+                                                        title: move title, url: move url });
+    }
+
+    /// Unroots the states, as the window is going away.
+    fn unroot_states() {
+        self.states.clear();
+    }
+
+    /// Moves `delta` entries through the history. Returns false, without
+    /// moving, if that would go off either end.
+    fn go(delta: int) -> bool {
+        let index = self.index as int + delta;
+        if delta == 0 || index < 0 || index >= self.len() as int {
+            return false;
+        }
+        self.index = index as uint;
+        true
+    }
+
+    fn back() -> bool {
+        self.go(-1)
+    }
+
+    fn forward() -> bool {
+        self.go(1)
+    }
+}
+
+/**
+Whether a document at `current` can have `url` as its URL without loading
+it. It has to stay on the same scheme, host and port; one that isn't from
+HTTP, with an opaque origin, can only change its query and fragment.
+*/
+fn can_rewrite_url(url: &Url, current: &Url) -> bool {
+    if url.scheme != current.scheme || url.host != current.host || url.port != current.port {
+        return false;
+    }
+    url.scheme == ~"http" || url.scheme == ~"https" || url.path == current.path
+}
+
+#[cfg(test)]
+fn test_history() -> History {
+    History(ptr::null(), make_url(~"http://example.com/index.html", None))
+}
+
+#[test]
+fn test_push_and_go() {
+    let history = test_history();
+    history.push_state(JSVAL_NULL, ~"", history.resolve_url(Some(~"a.html")).get());
+    history.push_state(JSVAL_NULL, ~"", history.resolve_url(Some(~"b.html")).get());
+    assert history.len() == 3;
+    assert history.href() == ~"http://example.com/b.html";
+
+    assert history.go(-2);
+    assert history.href() == ~"http://example.com/index.html";
+    assert !history.back();
+    assert history.forward();
+    assert history.href() == ~"http://example.com/a.html";
+    assert !history.go(2);
+}
+
+#[test]
+fn test_push_drops_forward_entries() {
+    let history = test_history();
+    history.push_state(JSVAL_NULL, ~"", history.resolve_url(Some(~"a.html")).get());
+    assert history.back();
+    history.push_state(JSVAL_NULL, ~"", history.resolve_url(Some(~"b.html")).get());
+    assert history.len() == 2;
+    assert !history.forward();
+    // the dropped entry's state isn't kept
+    assert history.states.len() == 2;
+}
+
+#[test]
+fn test_replace_state() {
+    let history = test_history();
+    history.replace_state(JSVAL_NULL, ~"", history.resolve_url(Some(~"a.html")).get());
+    assert history.len() == 1;
+    assert history.href() == ~"http://example.com/a.html";
+    assert history.states.len() == 1;
+}
+
+#[test]
+fn test_cross_origin_url_is_rejected() {
+    let history = test_history();
+    assert history.resolve_url(Some(~"http://example.org/")).is_err();
+    assert history.resolve_url(None).is_ok();
+}
+
+#[test]
+fn test_file_url_keeps_its_path() {
+    let history = History(ptr::null(), make_url(~"file:///tmp/index.html", None));
+    assert history.resolve_url(Some(~"a.html")).is_err();
+    assert history.resolve_url(Some(~"?a")).is_ok();
+    assert history.resolve_url(Some(~"#a")).is_ok();
+}
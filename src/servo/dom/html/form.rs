@@ -0,0 +1,634 @@
+/*!
+`<form>`: which controls belong to a form, the constraints they're
+validated against, and how their values are encoded and submitted.
+*/
+
+use dom::document::Document;
+use dom::element::*;
+use dom::form_data::{FormData, StringValue, FileValue, encode_multipart};
+use dom::html::input;
+use dom::node::{Node, NodeScope, Element, Text};
+use std::net::url::Url;
+use util::pattern::Pattern;
+use util::tree;
+use util::url::make_url;
+
+pub enum FormMethod {
+    MethodGet,
+    MethodPost,
+    MethodDialog
+}
+
+impl FormMethod : cmp::Eq {
+    pure fn eq(&self, other: &FormMethod) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &FormMethod) -> bool {
+        !self.eq(other)
+    }
+}
+
+pub enum FormEnctype {
+    UrlEncoded,
+    Multipart,
+    TextPlain
+}
+
+impl FormEnctype : cmp::Eq {
+    pure fn eq(&self, other: &FormEnctype) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &FormEnctype) -> bool {
+        !self.eq(other)
+    }
+}
+
+/// The submission settings of an `HTMLFormElement`, from its attributes.
+pub struct FormAttributes {
+    // Relative to the document; empty means the document's own URL
+    action: ~str,
+    method: FormMethod,
+    enctype: FormEnctype,
+    target: ~str,
+    novalidate: bool,
+}
+
+/// Reads the attributes of a `<form>`. Missing and invalid values get their
+/// defaults.
+pub fn form_attributes(form: &ElementData) -> FormAttributes {
+    let method = match form.get_attr("method").map(|m| str::to_lower(*m)) {
+        Some(~"post") => MethodPost,
+        Some(~"dialog") => MethodDialog,
+        _ => MethodGet
+    };
+    let enctype = match form.get_attr("enctype").map(|e| str::to_lower(*e)) {
+        Some(~"multipart/form-data") => Multipart,
+        Some(~"text/plain") => TextPlain,
+        _ => UrlEncoded
+    };
+    FormAttributes {
+        action: form.get_attr("action").get_default(~""),
+        method: method,
+        enctype: enctype,
+        target: form.get_attr("target").get_default(~""),
+        novalidate: form.get_attr("novalidate").is_some(),
+    }
+}
+
+/// Why a control's value doesn't satisfy its constraints.
+pub enum ValidityError {
+    ValueMissing,
+    TypeMismatch,
+    PatternMismatch,
+    TooLong,
+    TooShort,
+    RangeUnderflow,
+    RangeOverflow
+}
+
+impl ValidityError : cmp::Eq {
+    pure fn eq(&self, other: &ValidityError) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &ValidityError) -> bool {
+        !self.eq(other)
+    }
+}
+
+fn is_form(scope: &NodeScope, node: Node) -> bool {
+    do scope.read(&node) |n| {
+        match n.kind {
+            ~Element(ref e) => match e.kind {
+                ~HTMLFormElement => true,
+                _ => false
+            },
+            _ => false
+        }
+    }
+}
+
+enum ControlKind {
+    // With the input's `type`, lowercased
+    InputControl(~str),
+    // With whether it takes several values
+    SelectControl(bool),
+    TextAreaControl,
+    ButtonControl
+}
+
+fn control_kind(element: &ElementData) -> Option<ControlKind> {
+    match element.kind {
+        ~HTMLInputElement(*) => Some(InputControl(input::input_type(element))),
+        ~HTMLSelectElement => Some(SelectControl(element.get_attr("multiple").is_some())),
+        ~HTMLTextAreaElement => Some(TextAreaControl),
+        ~HTMLButtonElement => Some(ButtonControl),
+        _ => None
+    }
+}
+
+fn control_kind_of(scope: &NodeScope, node: Node) -> Option<ControlKind> {
+    do scope.read(&node) |n| {
+        match n.kind {
+            ~Element(ref e) => control_kind(e),
+            _ => None
+        }
+    }
+}
+
+fn get_attr(scope: &NodeScope, node: Node, name: &str) -> Option<~str> {
+    do scope.read(&node) |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_attr(name),
+            _ => None
+        }
+    }
+}
+
+/// Calls `f` on `node` and each of its descendants, in tree order.
+pub fn each_node(scope: &NodeScope, node: Node, f: fn&(Node)) {
+    f(node);
+    for scope.each_child(&node) |child| {
+        each_node(scope, *child, f);
+        true;
+    }
+}
+
+// The concatenated text under `node`
+fn text_of(scope: &NodeScope, node: Node) -> ~str {
+    let mut s = do scope.read(&node) |n| {
+        match n.kind {
+            ~Text(ref text) => copy *text,
+            _ => ~""
+        }
+    };
+    for scope.each_child(&node) |child| {
+        s += text_of(scope, *child);
+        true;
+    }
+    move s
+}
+
+/// The form a control belongs to: the one its `form` attribute names, or
+/// else its nearest ancestor form.
+pub fn form_owner(document: &Document, control: Node) -> Option<Node> {
+    let scope = &document.scope;
+    match get_attr(scope, control, "form") {
+        Some(ref id) => {
+            let mut found = None;
+            do each_node(scope, document.root) |node| {
+                if found.is_none() && is_form(scope, node) &&
+                   get_attr(scope, node, "id") == Some(copy *id) {
+                    found = Some(node);
+                }
+            }
+            found
+        }
+        None => {
+            let mut current = tree::parent(scope, &control);
+            while current.is_some() {
+                let node = current.get();
+                if is_form(scope, node) {
+                    return Some(node);
+                }
+                current = tree::parent(scope, &node);
+            }
+            None
+        }
+    }
+}
+
+/// The controls that belong to `form`, in tree order.
+pub fn associated_controls(document: &Document, form: Node) -> ~[Node] {
+    let scope = &document.scope;
+    let mut controls = ~[];
+    do each_node(scope, document.root) |node| {
+        if control_kind_of(scope, node).is_some() && form_owner(document, node) == Some(form) {
+            controls.push(node);
+        }
+    }
+    move controls
+}
+
+// The `<option>`s under a `<select>`, with whether each is selected. When
+// none are and the select takes one value, the first option is.
+fn options_of(scope: &NodeScope, select: Node, multiple: bool) -> ~[(Node, bool)] {
+    let mut options = ~[];
+    do each_node(scope, select) |node| {
+        let selected = do scope.read(&node) |n| {
+            match n.kind {
+                ~Element(ref e) => match e.kind {
+                    ~HTMLOptionElement => Some(e.get_attr("selected").is_some()),
+                    _ => None
+                },
+                _ => None
+            }
+        };
+        match selected {
+            Some(selected) => options.push((node, selected)),
+            None => ()
+        }
+    }
+    if !multiple && !options.is_empty() && !options.any(|o| { let (_, s) = *o; s }) {
+        let (first, _) = options[0];
+        options[0] = (first, true);
+    }
+    move options
+}
+
+fn option_value(scope: &NodeScope, option: Node) -> ~str {
+    match get_attr(scope, option, "value") {
+        Some(move value) => move value,
+        None => str::connect(str::words(text_of(scope, option)), " ")
+    }
+}
+
+// The values a control submits under its name; checkboxes and radio buttons
+// that aren't checked, and buttons, submit none
+fn control_values(scope: &NodeScope, control: Node) -> ~[~str] {
+    match control_kind_of(scope, control) {
+        Some(InputControl(move input_type)) => {
+            let (value, checked) = do scope.read(&control) |n| {
+                match n.kind {
+                    ~Element(ref e) => (input::value(e), input::checked(e)),
+                    _ => fail ~"form controls are elements"
+                }
+            };
+            match input_type {
+                ~"checkbox" | ~"radio" if checked => ~[move value],
+                // Nothing is submitted for buttons until there's a submitter, or
+                // for files until there's a way to pick them
+                ~"checkbox" | ~"radio" | ~"submit" | ~"reset" | ~"button" | ~"image" |
+                ~"file" => ~[],
+                _ => ~[move value]
+            }
+        }
+        Some(SelectControl(multiple)) => {
+            do options_of(scope, control, multiple).filter_map |option| {
+                let (node, selected) = *option;
+                if selected { Some(option_value(scope, node)) } else { None }
+            }
+        }
+        Some(TextAreaControl) => ~[text_of(scope, control)],
+        Some(ButtonControl) | None => ~[]
+    }
+}
+
+/**
+The entries `form` submits: the name and value of each of its controls
+that has a name and isn't disabled, in tree order.
+*/
+pub fn construct_entry_list(document: &Document, form: Node) -> FormData {
+    let scope = &document.scope;
+    let data = FormData();
+    for associated_controls(document, form).each |control| {
+        let name = get_attr(scope, *control, "name").get_default(~"");
+        if name.is_empty() || get_attr(scope, *control, "disabled").is_some() {
+            loop;
+        }
+        for control_values(scope, *control).each |value| {
+            data.append(copy name, StringValue(copy *value));
+        }
+    }
+    move data
+}
+
+// Whether `s` is a valid e-mail address, by the HTML spec's definition
+fn is_valid_email(s: &str) -> bool {
+    let at = match str::find_char(s, '@') {
+        Some(at) => at,
+        None => return false
+    };
+    let local = s.slice(0, at);
+    let domain = s.slice(at + 1, s.len());
+    let local_ok = !local.is_empty() && str::all(local, |c| {
+        char::is_alphanumeric(c) && (c as uint) < 128 || str::contains_char(".!#$%&'*+/=?^_`{|}~-", c)
+    });
+    let labels = str::split_char(domain, '.');
+    let domain_ok = do labels.all |label| {
+        !label.is_empty() && label.len() <= 63 &&
+            !label.starts_with("-") && !label.ends_with("-") &&
+            str::all(*label, |c| char::is_alphanumeric(c) && (c as uint) < 128 || c == '-')
+    };
+    local_ok && domain_ok
+}
+
+// Whether `s` is an absolute URL
+fn is_valid_url(s: &str) -> bool {
+    match std::net::url::from_str(s) {
+        Ok(url) => !url.scheme.is_empty(),
+        Err(_) => false
+    }
+}
+
+/**
+Checks a control's value against its constraints: `required`, `pattern`,
+`minlength`, `maxlength`, `min`, `max`, and the formats of `type=email` and
+`type=url`. Disabled and read-only controls, and those that never submit a
+typed value, aren't validated.
+*/
+pub fn check_validity(scope: &NodeScope, control: Node) -> Option<ValidityError> {
+    let attr = |name: &str| get_attr(scope, control, name);
+    let input_type = match control_kind_of(scope, control) {
+        Some(InputControl(move input_type)) => match input_type {
+            ~"hidden" | ~"submit" | ~"reset" | ~"button" | ~"image" => return None,
+            _ => Some(move input_type)
+        },
+        Some(SelectControl(_)) | Some(TextAreaControl) => None,
+        Some(ButtonControl) | None => return None
+    };
+    if attr("disabled").is_some() || attr("readonly").is_some() {
+        return None;
+    }
+
+    let values = control_values(scope, control);
+    if values.is_empty() || values.all(|v| v.is_empty()) {
+        return if attr("required").is_some() { Some(ValueMissing) } else { None };
+    }
+    let value = copy values[0];
+
+    let input_type = match move input_type {
+        Some(move input_type) => move input_type,
+        // Of the rest, only <textarea> has constraints beyond `required`
+        None => return check_length(attr("minlength"), attr("maxlength"), value)
+    };
+
+    // Each address of an <input type=email multiple> is checked on its own
+    let values = if input_type == ~"email" && attr("multiple").is_some() {
+        str::split_char(value, ',').map(|v| str::trim(*v))
+    } else {
+        ~[copy value]
+    };
+    match input_type {
+        ~"email" if !values.all(|v| is_valid_email(*v)) => return Some(TypeMismatch),
+        ~"url" if !is_valid_url(value) => return Some(TypeMismatch),
+        _ => ()
+    }
+    match attr("pattern").map(|p| Pattern(*p)) {
+        // Patterns that can't be compiled are ignored
+        Some(Ok(ref pattern)) if !values.all(|v| pattern.matches(*v)) => {
+            return Some(PatternMismatch);
+        }
+        _ => ()
+    }
+    match check_length(attr("minlength"), attr("maxlength"), value) {
+        Some(error) => return Some(error),
+        None => ()
+    }
+    if input_type == ~"number" || input_type == ~"range" {
+        let number = match float::from_str(value) {
+            Some(number) => number,
+            None => return Some(TypeMismatch)
+        };
+        match attr("min").chain(|m| float::from_str(m)) {
+            Some(min) if number < min => return Some(RangeUnderflow),
+            _ => ()
+        }
+        match attr("max").chain(|m| float::from_str(m)) {
+            Some(max) if number > max => return Some(RangeOverflow),
+            _ => ()
+        }
+    }
+    None
+}
+
+fn check_length(minlength: Option<~str>, maxlength: Option<~str>, value: &str)
+    -> Option<ValidityError> {
+    let len = str::char_len(value);
+    match maxlength.chain(|m| uint::from_str(m)) {
+        Some(max) if len > max => return Some(TooLong),
+        _ => ()
+    }
+    match minlength.chain(|m| uint::from_str(m)) {
+        Some(min) if len < min => Some(TooShort),
+        _ => None
+    }
+}
+
+/// The controls of `form` whose values don't satisfy their constraints.
+pub fn invalid_controls(document: &Document, form: Node) -> ~[Node] {
+    do associated_controls(document, form).filter |control| {
+        check_validity(&document.scope, *control).is_some()
+    }
+}
+
+// Percent-encodes `s` for application/x-www-form-urlencoded
+fn urlencode_component(s: &str) -> ~str {
+    let mut encoded = ~"";
+    for str::each(s) |b| {
+        let c = b as char;
+        if char::is_alphanumeric(c) && b < 128 || c == '*' || c == '-' || c == '.' || c == '_' {
+            str::push_char(&mut encoded, c);
+        } else if c == ' ' {
+            str::push_char(&mut encoded, '+');
+        } else {
+            encoded += fmt!("%%%02X", b as uint);
+        }
+    }
+    move encoded
+}
+
+/// Encodes form data as `application/x-www-form-urlencoded`. Files are sent
+/// as their names.
+pub fn urlencode(data: &FormData) -> ~str {
+    let pairs = do data.entries().map |entry| {
+        let (ref name, ref value) = *entry;
+        let value = match *value {
+            StringValue(ref s) => copy *s,
+            FileValue(ref file) => copy file.name
+        };
+        fmt!("%s=%s", urlencode_component(*name), urlencode_component(value))
+    };
+    str::connect(pairs, "&")
+}
+
+// Encodes form data as `text/plain`: a `name=value` line for each entry
+fn encode_text_plain(data: &FormData) -> ~str {
+    let mut body = ~"";
+    for data.entries().each |entry| {
+        let (ref name, ref value) = *entry;
+        let value = match *value {
+            StringValue(ref s) => copy *s,
+            FileValue(ref file) => copy file.name
+        };
+        body += fmt!("%s=%s\r\n", *name, value);
+    }
+    move body
+}
+
+/// Where a submitted form goes, and what's sent there.
+pub struct FormSubmission {
+    url: Url,
+    method: FormMethod,
+    // For POST: the body and its Content-Type
+    body: Option<(~[u8], ~str)>,
+}
+
+/**
+Plans the submission of `data` from a form with `attrs`, in a document at
+`document_url`. GET submissions replace the query of the action URL with
+the entries; POST submissions encode them in the body, as the form's
+`enctype` says.
+*/
+pub fn plan_submission(attrs: &FormAttributes, document_url: &Url, data: &FormData)
+    -> FormSubmission {
+    let mut url = if attrs.action.is_empty() {
+        copy *document_url
+    } else {
+        make_url(copy attrs.action, Some(copy *document_url))
+    };
+    let body = match attrs.method {
+        MethodGet => {
+            url.query = do data.entries().map |entry| {
+                let (ref name, ref value) = *entry;
+                (copy *name, match *value {
+                    StringValue(ref s) => copy *s,
+                    FileValue(ref file) => copy file.name
+                })
+            };
+            url.fragment = None;
+            None
+        }
+        MethodPost => Some(match attrs.enctype {
+            UrlEncoded => (str::to_bytes(urlencode(data)),
+                           ~"application/x-www-form-urlencoded"),
+            Multipart => encode_multipart(data),
+            TextPlain => (str::to_bytes(encode_text_plain(data)), ~"text/plain")
+        }),
+        MethodDialog => None
+    };
+    FormSubmission {
+        url: move url,
+        method: attrs.method,
+        body: move body,
+    }
+}
+
+#[cfg(test)]
+mod form_tests {
+    use dom::node::NodeScopeExtensions;
+
+    fn element(scope: &NodeScope, parent: Option<Node>, kind: ~ElementKind,
+               attrs: &[(~str, ~str)]) -> Node {
+        let data = ElementData(~"x", move kind);
+        for attrs.each |attr| {
+            let (name, value) = copy *attr;
+            data.attrs.push(~Attr(move name, move value));
+        }
+        let node = scope.new_node(Element(move data));
+        for parent.each |parent| {
+            scope.add_child(*parent, node);
+        }
+        node
+    }
+
+    fn input(scope: &NodeScope, form: Node, attrs: &[(~str, ~str)]) -> Node {
+        element(scope, Some(form), ~HTMLInputElement(HTMLInputData()), attrs)
+    }
+
+    fn values(data: &FormData) -> ~[(~str, ~str)] {
+        do data.entries().map |entry| {
+            match *entry {
+                (ref name, StringValue(ref value)) => (copy *name, copy *value),
+                _ => fail
+            }
+        }
+    }
+
+    #[test]
+    fn test_form_attributes() {
+        let form = ElementData(~"form", ~HTMLFormElement);
+        form.set_attr("method", ~"POST");
+        form.set_attr("enctype", ~"multipart/form-data");
+        form.set_attr("novalidate", ~"");
+        let attrs = form_attributes(&form);
+        assert attrs.method == MethodPost;
+        assert attrs.enctype == Multipart;
+        assert attrs.novalidate;
+        assert attrs.action.is_empty();
+
+        let defaults = form_attributes(&ElementData(~"form", ~HTMLFormElement));
+        assert defaults.method == MethodGet && defaults.enctype == UrlEncoded;
+    }
+
+    #[test]
+    fn test_entry_list() {
+        let scope = NodeScope();
+        let body = element(&scope, None, ~HTMLBodyElement, ~[]);
+        let form = element(&scope, Some(body), ~HTMLFormElement, ~[(~"id", ~"f")]);
+        input(&scope, form, ~[(~"name", ~"q"), (~"value", ~"a b")]);
+        input(&scope, form, ~[(~"name", ~"off"), (~"type", ~"checkbox")]);
+        input(&scope, form, ~[(~"name", ~"on"), (~"type", ~"checkbox"), (~"checked", ~"")]);
+        input(&scope, form, ~[(~"name", ~"gone"), (~"value", ~"x"), (~"disabled", ~"")]);
+        input(&scope, form, ~[(~"value", ~"nameless")]);
+        input(&scope, form, ~[(~"name", ~"go"), (~"type", ~"submit")]);
+        let select = element(&scope, Some(form), ~HTMLSelectElement, ~[(~"name", ~"s")]);
+        let option = element(&scope, Some(select), ~HTMLOptionElement, ~[]);
+        scope.add_child(option, scope.new_node(Text(~" First ")));
+        element(&scope, Some(select), ~HTMLOptionElement, ~[(~"value", ~"2")]);
+        // Outside the form, but owned by it
+        input(&scope, body, ~[(~"name", ~"outside"), (~"value", ~"1"), (~"form", ~"f")]);
+
+        let data = construct_entry_list(&Document(body, scope), form);
+        assert values(&data) == ~[(~"q", ~"a b"), (~"on", ~"on"), (~"s", ~"First"),
+                                  (~"outside", ~"1")];
+        assert urlencode(&data) == ~"q=a+b&on=on&s=First&outside=1";
+    }
+
+    #[test]
+    fn test_urlencode_escapes() {
+        let data = FormData();
+        data.append(~"a&b", StringValue(~"1=2/é*"));
+        assert urlencode(&data) == ~"a%26b=1%3D2%2F%C3%A9*";
+    }
+
+    #[test]
+    fn test_validation() {
+        let scope = NodeScope();
+        let form = element(&scope, None, ~HTMLFormElement, ~[]);
+        let check = |attrs: &[(~str, ~str)]| check_validity(&scope, input(&scope, form, attrs));
+
+        assert check(~[(~"required", ~"")]) == Some(ValueMissing);
+        assert check(~[(~"required", ~""), (~"disabled", ~"")]).is_none();
+        assert check(~[(~"type", ~"checkbox"), (~"required", ~"")]) == Some(ValueMissing);
+        assert check(~[(~"pattern", ~"[0-9]{3}"), (~"value", ~"12a")]) == Some(PatternMismatch);
+        assert check(~[(~"pattern", ~"[0-9]{3}"), (~"value", ~"123")]).is_none();
+        // An empty value only fails `required`
+        assert check(~[(~"pattern", ~"[0-9]{3}")]).is_none();
+        assert check(~[(~"minlength", ~"3"), (~"value", ~"ab")]) == Some(TooShort);
+        assert check(~[(~"maxlength", ~"3"), (~"value", ~"abcd")]) == Some(TooLong);
+        assert check(~[(~"type", ~"number"), (~"min", ~"1"), (~"value", ~"0")]) ==
+            Some(RangeUnderflow);
+        assert check(~[(~"type", ~"range"), (~"max", ~"10"), (~"value", ~"11")]) ==
+            Some(RangeOverflow);
+        assert check(~[(~"type", ~"email"), (~"value", ~"user@example.com")]).is_none();
+        assert check(~[(~"type", ~"email"), (~"value", ~"user@-bad.com")]) == Some(TypeMismatch);
+        assert check(~[(~"type", ~"email"), (~"multiple", ~""),
+                       (~"value", ~"a@b.c, d@e.f")]).is_none();
+        assert check(~[(~"type", ~"url"), (~"value", ~"not a url")]) == Some(TypeMismatch);
+        assert check(~[(~"type", ~"url"), (~"value", ~"http://example.com/")]).is_none();
+    }
+
+    #[test]
+    fn test_plan_submission() {
+        let page = std::net::url::from_str("http://example.com/dir/page.html").get();
+        let data = FormData();
+        data.append(~"q", StringValue(~"servo"));
+
+        let form = ElementData(~"form", ~HTMLFormElement);
+        form.set_attr("action", ~"search");
+        let get = plan_submission(&form_attributes(&form), &page, &data);
+        assert get.url.host == ~"example.com" && get.url.path == ~"/dir/search";
+        assert get.url.query == ~[(~"q", ~"servo")];
+        assert get.body.is_none();
+
+        form.set_attr("method", ~"post");
+        let post = plan_submission(&form_attributes(&form), &page, &data);
+        match post.body {
+            Some((ref body, ref content_type)) => {
+                assert *body == str::to_bytes("q=servo");
+                assert *content_type == ~"application/x-www-form-urlencoded";
+            }
+            None => fail
+        }
+    }
+}
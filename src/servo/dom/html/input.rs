@@ -0,0 +1,293 @@
+/*!
+`<input>`: the value and checkedness the user or a script has given it, and
+the rules checkboxes, radio buttons and ranges apply to them.
+*/
+
+use dom::document::Document;
+use dom::element::*;
+use dom::html::form::{each_node, form_owner};
+use dom::node::{Node, NodeScope, Element};
+
+/// The `type` of an `<input>`, lowercased, defaulting to text.
+pub fn input_type(input: &ElementData) -> ~str {
+    input.get_attr("type").map_default(~"text", |t| str::to_lower(*t))
+}
+
+fn with_data<R>(input: &ElementData, f: fn(&HTMLInputData) -> R) -> R {
+    match input.kind {
+        ~HTMLInputElement(ref data) => f(data),
+        _ => fail ~"not an <input>"
+    }
+}
+
+fn with_input<R>(scope: &NodeScope, node: Node, f: fn(&ElementData) -> R) -> R {
+    do scope.write(&node) |n| {
+        match n.kind {
+            ~Element(ref e) => f(e),
+            _ => fail ~"not an <input>"
+        }
+    }
+}
+
+// Whether the user can type into an input of this type
+fn is_text_type(input_type: &str) -> bool {
+    match input_type {
+        "text" | "search" | "email" | "url" | "tel" | "password" | "number" => true,
+        _ => false
+    }
+}
+
+fn format_number(n: float) -> ~str {
+    if n == float::floor(n) {
+        int::str(n as int)
+    } else {
+        float::to_str(n, 6)
+    }
+}
+
+/**
+The value of a range: `value` parsed and clamped to `min` and `max`, then
+moved to the nearest step above `min`. Missing and invalid values get the
+spec's defaults: a range of 0 to 100 in steps of 1, starting halfway.
+*/
+pub fn sanitize_range(value: Option<~str>, min: Option<~str>, max: Option<~str>,
+                      step: Option<~str>) -> ~str {
+    let min = min.chain(|m| float::from_str(m)).get_default(0.0);
+    let max = float::fmax(min, max.chain(|m| float::from_str(m)).get_default(100.0));
+    let value = value.chain(|v| float::from_str(v)).get_default(min + (max - min) / 2.0);
+    let value = float::fmin(max, float::fmax(min, value));
+    let step = match step {
+        Some(ref s) if str::to_lower(*s) == ~"any" => None,
+        Some(move s) => match float::from_str(s) {
+            Some(step) if step > 0.0 => Some(step),
+            _ => Some(1.0)
+        },
+        None => Some(1.0)
+    };
+    match step {
+        Some(step) => {
+            let mut stepped = min + float::floor((value - min) / step + 0.5) * step;
+            if stepped > max {
+                stepped -= step;
+            }
+            format_number(stepped)
+        }
+        None => format_number(value)
+    }
+}
+
+/// The current value of an `<input>`: what was typed or set by script, or
+/// else its `value` attribute.
+pub fn value(input: &ElementData) -> ~str {
+    let default = input.get_attr("value");
+    let dirty = with_data(input, |data| copy data.value);
+    match input_type(input) {
+        ~"checkbox" | ~"radio" => default.get_default(~"on"),
+        ~"range" => {
+            sanitize_range(if dirty.is_some() { dirty } else { default }, input.get_attr("min"),
+                           input.get_attr("max"), input.get_attr("step"))
+        }
+        _ => match dirty {
+            Some(move value) => move value,
+            None => default.get_default(~"")
+        }
+    }
+}
+
+/// Whether a checkbox or radio button is checked.
+pub fn checked(input: &ElementData) -> bool {
+    match with_data(input, |data| data.checked) {
+        Some(checked) => checked,
+        None => input.get_attr("checked").is_some()
+    }
+}
+
+/**
+Sets the value of an `<input>`, as assigning to its `value` property does.
+Checkboxes and radio buttons have no value of their own, so their `value`
+attribute is set instead.
+*/
+pub fn set_value(scope: &NodeScope, node: Node, value: ~str) {
+    do with_input(scope, node) |input| {
+        match input_type(input) {
+            ~"checkbox" | ~"radio" => input.set_attr("value", copy value),
+            ~"range" => {
+                let value = sanitize_range(Some(copy value), input.get_attr("min"),
+                                           input.get_attr("max"), input.get_attr("step"));
+                with_data(input, |data| data.value = Some(copy value))
+            }
+            // Line breaks are stripped from single-line inputs
+            _ => {
+                let value = str::replace(str::replace(value, "\r", ""), "\n", "");
+                with_data(input, |data| data.value = Some(copy value))
+            }
+        }
+    }
+}
+
+/**
+Checks or unchecks a checkbox or radio button. Checking a radio button
+unchecks the others in its group: those with the same `name` and the same
+form owner.
+*/
+pub fn set_checked(document: &Document, node: Node, checked: bool) {
+    let scope = &document.scope;
+    let (radio, name) = do with_input(scope, node) |input| {
+        with_data(input, |data| data.checked = Some(checked));
+        (input_type(input) == ~"radio", input.get_attr("name").get_default(~""))
+    };
+    if !radio || !checked || name.is_empty() {
+        return;
+    }
+    let owner = form_owner(document, node);
+    do each_node(scope, document.root) |other| {
+        let in_group = do scope.read(&other) |n| {
+            match n.kind {
+                ~Element(ref e) => match e.kind {
+                    ~HTMLInputElement(*) => {
+                        input_type(e) == ~"radio" && e.get_attr("name") == Some(copy name)
+                    }
+                    _ => false
+                },
+                _ => false
+            }
+        };
+        if in_group && other != node && form_owner(document, other) == owner {
+            with_input(scope, other, |input| with_data(input, |data| data.checked = Some(false)));
+        }
+    }
+}
+
+/**
+What the user activating a checkbox or radio button (with a click or the
+space bar) does: checkboxes toggle and radio buttons are checked. Returns
+whether that changed anything.
+*/
+pub fn activate(document: &Document, node: Node) -> bool {
+    let (input_type, was_checked, disabled) = do with_input(&document.scope, node) |input| {
+        (input_type(input), checked(input), input.get_attr("disabled").is_some())
+    };
+    match input_type {
+        ~"checkbox" if !disabled => { set_checked(document, node, !was_checked); true }
+        ~"radio" if !disabled && !was_checked => { set_checked(document, node, true); true }
+        _ => false
+    }
+}
+
+/**
+The value a text input would have after the user typed `key`: a printable
+character is appended and backspace deletes the last character. Returns
+None if the key does nothing, including when the input is disabled or
+read-only, or at its `maxlength`.
+*/
+pub fn typed_value(input: &ElementData, key: char) -> Option<~str> {
+    if !is_text_type(input_type(input)) || input.get_attr("disabled").is_some() ||
+       input.get_attr("readonly").is_some() {
+        return None;
+    }
+    let current = value(input);
+    match key {
+        '\x08' | '\x7f' if current.is_empty() => None,
+        '\x08' | '\x7f' => {
+            let mut chars = str::chars(current);
+            chars.pop();
+            Some(str::from_chars(chars))
+        }
+        c if c >= ' ' => {
+            match input.get_attr("maxlength").chain(|m| uint::from_str(m)) {
+                Some(max) if str::char_len(current) >= max => None,
+                _ => {
+                    let mut value = move current;
+                    str::push_char(&mut value, c);
+                    Some(move value)
+                }
+            }
+        }
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod input_tests {
+    use dom::node::NodeScopeExtensions;
+
+    fn input(attrs: &[(~str, ~str)]) -> ElementData {
+        let data = ElementData(~"input", ~HTMLInputElement(HTMLInputData()));
+        for attrs.each |attr| {
+            let (name, value) = copy *attr;
+            data.set_attr(name, move value);
+        }
+        move data
+    }
+
+    fn radio(scope: &NodeScope, parent: Node, name: &str) -> Node {
+        let node = scope.new_node(Element(input(~[(~"type", ~"radio"),
+                                                  (~"name", name.to_str())])));
+        scope.add_child(parent, node);
+        node
+    }
+
+    fn is_checked(scope: &NodeScope, node: Node) -> bool {
+        do scope.read(&node) |n| {
+            match n.kind {
+                ~Element(ref e) => checked(e),
+                _ => fail
+            }
+        }
+    }
+
+    #[test]
+    fn test_value_and_default() {
+        let text = input(~[(~"value", ~"default")]);
+        assert value(&text) == ~"default";
+        with_data(&text, |data| data.value = Some(~"typed"));
+        assert value(&text) == ~"typed";
+        assert text.get_attr("value") == Some(~"default");
+
+        assert value(&input(~[(~"type", ~"checkbox")])) == ~"on";
+    }
+
+    #[test]
+    fn test_sanitize_range() {
+        assert sanitize_range(None, None, None, None) == ~"50";
+        assert sanitize_range(Some(~"150"), None, None, None) == ~"100";
+        assert sanitize_range(Some(~"-5"), Some(~"0"), Some(~"10"), None) == ~"0";
+        assert sanitize_range(Some(~"7"), Some(~"1"), Some(~"10"), Some(~"3")) == ~"7";
+        assert sanitize_range(Some(~"8"), Some(~"1"), Some(~"10"), Some(~"3")) == ~"7";
+        // The top step is below max
+        assert sanitize_range(Some(~"10"), Some(~"0"), Some(~"10"), Some(~"4")) == ~"8";
+        assert sanitize_range(Some(~"2.5"), None, None, Some(~"any")) == ~"2.5";
+        assert value(&input(~[(~"type", ~"range"), (~"value", ~"x")])) == ~"50";
+    }
+
+    #[test]
+    fn test_radio_group() {
+        let scope = NodeScope();
+        let root = scope.new_node(Element(ElementData(~"form", ~HTMLFormElement)));
+        let a = radio(&scope, root, "color");
+        let b = radio(&scope, root, "color");
+        let other = radio(&scope, root, "size");
+        let document = Document(root, scope);
+
+        assert activate(&document, a);
+        assert activate(&document, other);
+        assert activate(&document, b);
+        assert !is_checked(&document.scope, a);
+        assert is_checked(&document.scope, b);
+        assert is_checked(&document.scope, other);
+        // Activating a checked radio button does nothing
+        assert !activate(&document, b);
+    }
+
+    #[test]
+    fn test_typed_value() {
+        let text = input(~[(~"value", ~"ab"), (~"maxlength", ~"3")]);
+        assert typed_value(&text, 'c') == Some(~"abc");
+        assert typed_value(&text, '\x08') == Some(~"a");
+        with_data(&text, |data| data.value = Some(~"abc"));
+        assert typed_value(&text, 'd').is_none();
+        assert typed_value(&text, '\r').is_none();
+        assert typed_value(&input(~[(~"readonly", ~"")]), 'a').is_none();
+        assert typed_value(&input(~[(~"type", ~"checkbox")]), 'a').is_none();
+    }
+}
@@ -0,0 +1,1156 @@
+/*!
+IndexedDB: databases of JS values, each stored by key in an object store.
+An origin's databases share a SQLite file, where each value is kept as
+its structured clone data.
+
+A connection runs its transactions one at a time, in the order they were
+made, each inside a SQLite transaction of its own, so none sees another
+half-done. Readonly ones only take SQLite's shared lock, so connections
+from other pages can read alongside them; the others take the write lock
+up front, and wait for each other.
+
+Each request runs inside a savepoint, so one that fails leaves nothing
+behind even if its transaction goes on.
+
+A connection's SQLite work is done on a storage task of its own. Requests
+and commits run there while script goes on, and their results come back
+to it; what script needs at once, like making a store or reading the names
+of the stores, waits for the storage task.
+
+Only number and string keys are supported, and a key path is a single
+string like `"a.b"`. Indexes are only recorded: nothing is filed in them
+yet, so they can't be read and don't keep their keys unique. There's no
+key generator either, so every record needs a key.
+*/
+
+use js::jsapi::JSVal;
+use js::JSVAL_NULL;
+use dom::cache_storage::database_path;
+use util::sqlite::{Connection, Statement};
+use pipes::{Port, SharedChan};
+use task::{task, SingleThreaded};
+
+// Every number key sorts before every string key
+const NUMBER_TAG: u8 = 1;
+const STRING_TAG: u8 = 2;
+
+const SIGN_BIT: u64 = 0x8000000000000000;
+
+const SCHEMA: &static/str =
+    "CREATE TABLE IF NOT EXISTS databases \
+     (name TEXT PRIMARY KEY, version INTEGER NOT NULL); \
+     CREATE TABLE IF NOT EXISTS object_stores \
+     (id INTEGER PRIMARY KEY, database TEXT NOT NULL, name TEXT NOT NULL, key_path TEXT, \
+      UNIQUE (database, name)); \
+     CREATE TABLE IF NOT EXISTS indexes \
+     (id INTEGER PRIMARY KEY, store INTEGER NOT NULL, name TEXT NOT NULL, \
+      key_path TEXT NOT NULL, is_unique INTEGER NOT NULL, UNIQUE (store, name)); \
+     CREATE TABLE IF NOT EXISTS records \
+     (store INTEGER NOT NULL, key BLOB NOT NULL, value BLOB NOT NULL, \
+      PRIMARY KEY (store, key))";
+
+pub enum Key {
+    NumberKey(float),
+    StringKey(~str),
+}
+
+impl Key : cmp::Eq {
+    pure fn eq(&self, other: &Key) -> bool {
+        match (self, other) {
+            (&NumberKey(a), &NumberKey(b)) => a == b,
+            (&StringKey(ref a), &StringKey(ref b)) => *a == *b,
+            _ => false
+        }
+    }
+    pure fn ne(&self, other: &Key) -> bool {
+        !self.eq(other)
+    }
+}
+
+/**
+`key` as bytes that sort the way keys do, so that SQLite can order records
+by them. A number is its float's bits, flipped so that negative numbers
+come first; a string is its UTF-8.
+*/
+pub fn encode_key(key: &Key) -> ~[u8] {
+    match *key {
+        NumberKey(n) => {
+            // -0 is the same key as 0
+            let n = if n == 0.0 { 0f64 } else { n as f64 };
+            let bits: u64 = unsafe { cast::transmute(n) };
+            let bits = if bits & SIGN_BIT != 0 { !bits } else { bits | SIGN_BIT };
+            let mut bytes = ~[NUMBER_TAG];
+            for uint::range(0, 8) |i| {
+                bytes.push((bits >> (56 - 8 * i)) as u8);
+            }
+            move bytes
+        }
+        StringKey(ref s) => ~[STRING_TAG] + str::to_bytes(*s)
+    }
+}
+
+pub fn decode_key(bytes: &[u8]) -> Key {
+    if bytes[0] == NUMBER_TAG {
+        let mut bits = 0u64;
+        for uint::range(1, 9) |i| {
+            bits = (bits << 8) | bytes[i] as u64;
+        }
+        let bits = if bits & SIGN_BIT != 0 { bits & !SIGN_BIT } else { !bits };
+        let n: f64 = unsafe { cast::transmute(bits) };
+        NumberKey(n as float)
+    } else {
+        StringKey(str::from_bytes(vec::view(bytes, 1, bytes.len())))
+    }
+}
+
+pub enum IdbError {
+    // A record has the key already, or a store or index has the name
+    ConstraintError,
+    NotFoundError,
+    // A key is missing, or isn't one
+    DataError,
+    // The database's version is higher than the one asked for
+    VersionError,
+    InvalidStateError,
+    InvalidAccessError,
+    TransactionInactiveError,
+    ReadOnlyError,
+    AbortError,
+    // The page's origin can't keep databases
+    SecurityError,
+    // SQLite failed, with its message
+    UnknownError(~str),
+}
+
+impl IdbError {
+    /// The DOMException name script sees.
+    pure fn name(&self) -> ~str {
+        match *self {
+            ConstraintError => ~"ConstraintError",
+            NotFoundError => ~"NotFoundError",
+            DataError => ~"DataError",
+            VersionError => ~"VersionError",
+            InvalidStateError => ~"InvalidStateError",
+            InvalidAccessError => ~"InvalidAccessError",
+            TransactionInactiveError => ~"TransactionInactiveError",
+            ReadOnlyError => ~"ReadOnlyError",
+            AbortError => ~"AbortError",
+            SecurityError => ~"SecurityError",
+            UnknownError(*) => ~"UnknownError"
+        }
+    }
+
+    pure fn message(&self) -> ~str {
+        match *self {
+            ConstraintError => ~"A record with that key already exists",
+            NotFoundError => ~"No object store or index has that name",
+            DataError => ~"The key is missing or isn't a valid key",
+            VersionError => ~"The database already has a higher version",
+            InvalidStateError => ~"The object isn't in a state it can do that in",
+            InvalidAccessError => ~"The arguments can't be used together",
+            TransactionInactiveError => ~"The transaction isn't active",
+            ReadOnlyError => ~"The transaction is readonly",
+            AbortError => ~"The transaction was aborted",
+            SecurityError => ~"Pages from this origin can't use indexedDB",
+            UnknownError(ref message) => copy *message
+        }
+    }
+}
+
+impl IdbError : cmp::Eq {
+    pure fn eq(&self, other: &IdbError) -> bool {
+        self.name() == other.name()
+    }
+    pure fn ne(&self, other: &IdbError) -> bool {
+        !self.eq(other)
+    }
+}
+
+fn sql<T>(result: Result<T, ~str>) -> Result<T, IdbError> {
+    match move result {
+        Ok(move value) => Ok(move value),
+        Err(move e) => Err(UnknownError(move e))
+    }
+}
+
+// The first of `rows`, or `missing` if there are none
+fn first<T>(rows: Result<~[T], IdbError>, missing: IdbError) -> Result<T, IdbError> {
+    match move rows {
+        Ok(move rows) => {
+            let mut rows = move rows;
+            if rows.is_empty() { Err(move missing) } else { Ok(vec::shift(&mut rows)) }
+        }
+        Err(move e) => Err(move e)
+    }
+}
+
+pub struct StoreInfo {
+    id: int,
+    name: ~str,
+    key_path: Option<~str>,
+}
+
+pub struct IndexInfo {
+    id: int,
+    name: ~str,
+    key_path: ~str,
+    unique: bool,
+}
+
+fn store_row(stmt: &Statement) -> StoreInfo {
+    StoreInfo {
+        id: stmt.column_int(0),
+        name: stmt.column_text(1),
+        key_path: if stmt.column_is_null(2) { None } else { Some(stmt.column_text(2)) }
+    }
+}
+
+fn index_row(stmt: &Statement) -> IndexInfo {
+    IndexInfo {
+        id: stmt.column_int(0),
+        name: stmt.column_text(1),
+        key_path: stmt.column_text(2),
+        unique: stmt.column_int(3) != 0
+    }
+}
+
+fn record_row(stmt: &Statement) -> (Key, ~[u8]) {
+    (decode_key(stmt.column_blob(0)), stmt.column_blob(1))
+}
+
+/// `indexedDB`, for one origin.
+pub struct IDBFactory {
+    // The origin's SQLite file
+    path: Path,
+}
+
+/// The factory for `origin`, whose databases are kept in `dir`.
+pub fn IDBFactory(dir: &Path, origin: &str) -> IDBFactory {
+    IDBFactory { path: database_path(&dir.push("indexeddb"), origin) }
+}
+
+impl IDBFactory {
+    /// Opens a connection to the database called `name`, which is made,
+    /// at version 0, if there isn't one.
+    fn open(&self, name: &str) -> Result<@IDBDatabase, IdbError> {
+        os::mkdir_recursive(&self.path.dir_path(), 0o700);
+        open_database(self.path.to_str(), name)
+    }
+}
+
+/// Opens the database called `name` in the SQLite file at `path`.
+/// ":memory:" opens one that's never written to disk.
+pub fn open_database(path: &str, name: &str) -> Result<@IDBDatabase, IdbError> {
+    let (storage_chan, storage_port) = pipes::stream::<StorageMsg>();
+    let (opened_chan, opened_port) = pipes::stream();
+    let path = path.to_str();
+    let db_name = name.to_str();
+    // SQLite blocks, so the task gets a thread of its own
+    do task().sched_mode(SingleThreaded).spawn |move storage_port, move opened_chan, move path,
+                                                 move db_name| {
+        match IdbStorage(path, db_name) {
+            Ok(move storage) => {
+                opened_chan.send(storage.version());
+                // Until the connection's dropped
+                loop {
+                    match storage_port.try_recv() {
+                        Some(move msg) => msg(&storage),
+                        None => break
+                    }
+                }
+            }
+            Err(move e) => opened_chan.send(Err(move e))
+        }
+    }
+    match opened_port.recv() {
+        Ok(version) => Ok(@IDBDatabase {
+            name: name.to_str(),
+            version: version,
+            storage: SharedChan(move storage_chan),
+            running: None,
+            waiting: ~[],
+            closed: false
+        }),
+        Err(move e) => Err(move e)
+    }
+}
+
+// Work for a connection's storage task
+type StorageMsg = fn~(&IdbStorage);
+
+pub struct IDBDatabase {
+    name: ~str,
+    mut version: u64,
+    // Where the connection's SQLite work is sent
+    priv storage: SharedChan<StorageMsg>,
+    // The transaction that's running, and those waiting for it, oldest first
+    priv mut running: Option<@IDBTransaction>,
+    priv mut waiting: ~[@IDBTransaction],
+    // Set when its upgrade aborts, after which no more transactions can
+    // be made
+    mut closed: bool,
+}
+
+/// Makes a transaction over the stores called `scope`. It starts once
+/// the transactions made before it have finished.
+pub fn transaction(db: @IDBDatabase, scope: ~[~str], mode: TransactionMode)
+    -> Result<@IDBTransaction, IdbError> {
+    if db.closed || db.upgrade_transaction().is_some() {
+        return Err(InvalidStateError);
+    }
+    if scope.is_empty() {
+        return Err(InvalidAccessError);
+    }
+    for scope.each |name| {
+        match db.store_info(*name) {
+            Ok(_) => (),
+            Err(move e) => return Err(move e)
+        }
+    }
+    let tx = IDBTransaction(db, mode, move scope);
+    db.schedule(tx);
+    Ok(tx)
+}
+
+/**
+Makes the versionchange transaction that upgrades `db` to `version`. The
+database has the new version from now on, unless the transaction aborts.
+*/
+pub fn upgrade(db: @IDBDatabase, version: u64) -> @IDBTransaction {
+    let tx = IDBTransaction(db, VersionChange, ~[]);
+    tx.old_version = db.version;
+    db.version = version;
+    db.schedule(tx);
+    tx
+}
+
+impl IDBDatabase {
+    // Runs `f` on the storage task, without waiting for it
+    priv fn post(&self, f: StorageMsg) {
+        self.storage.send(move f);
+    }
+
+    // Runs `f` on the storage task, and waits for what it gives
+    priv fn call<T: Send>(&self, f: fn~(&IdbStorage) -> T) -> T {
+        let (result_chan, result_port) = pipes::stream();
+        do self.post |storage, move f, move result_chan| {
+            result_chan.send(f(storage));
+        }
+        result_port.recv()
+    }
+
+    priv fn schedule(&self, tx: @IDBTransaction) {
+        if self.running.is_none() {
+            self.start(tx);
+        } else {
+            self.waiting.push(tx);
+        }
+    }
+
+    // Begins `tx`'s SQLite transaction. If that fails, its requests and
+    // its commit fail with the error
+    priv fn start(&self, tx: @IDBTransaction) {
+        self.running = Some(tx);
+        tx.state = Running;
+        let mode = tx.mode;
+        let version = self.version;
+        do self.post |storage| {
+            storage.begin(mode, version);
+        }
+    }
+
+    /**
+    Once the running transaction has finished, starts the next one that
+    wasn't aborted while it waited, and returns it.
+    */
+    fn start_next(&self) -> Option<@IDBTransaction> {
+        self.running = None;
+        while !self.waiting.is_empty() {
+            let next = vec::shift(&mut self.waiting);
+            if next.state == Waiting {
+                self.start(next);
+                return Some(next);
+            }
+        }
+        None
+    }
+
+    /// The versionchange transaction, while it's running.
+    fn upgrade_transaction(&self) -> Option<@IDBTransaction> {
+        match self.running {
+            Some(tx) if tx.mode == VersionChange => Some(tx),
+            _ => None
+        }
+    }
+
+    fn store_info(&self, name: &str) -> Result<StoreInfo, IdbError> {
+        let name = name.to_str();
+        do self.call |storage, move name| { storage.store_info(name) }
+    }
+
+    // Only the versionchange transaction can change stores and indexes,
+    // and only while it's active
+    priv fn check_upgrading(&self, tx: &IDBTransaction) -> Result<(), IdbError> {
+        if tx.mode != VersionChange || tx.state != Running {
+            Err(InvalidStateError)
+        } else if !tx.active {
+            Err(TransactionInactiveError)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn create_object_store(&self, tx: &IDBTransaction, name: &str, key_path: Option<~str>)
+        -> Result<StoreInfo, IdbError> {
+        match self.check_upgrading(tx) {
+            Ok(()) => (),
+            Err(move e) => return Err(move e)
+        }
+        let name = name.to_str();
+        do self.call |storage, move name, move key_path| {
+            storage.create_object_store(name, copy key_path)
+        }
+    }
+
+    /// Makes an index on `store`. Only its name, key path and whether it's
+    /// unique are kept; records aren't filed in it.
+    fn create_index(&self, tx: &IDBTransaction, store: &StoreInfo, name: &str, key_path: &str,
+                    unique: bool) -> Result<IndexInfo, IdbError> {
+        match self.check_upgrading(tx) {
+            Ok(()) => (),
+            Err(move e) => return Err(move e)
+        }
+        let store = store.id;
+        let name = name.to_str();
+        let key_path = key_path.to_str();
+        do self.call |storage, move name, move key_path| {
+            storage.create_index(store, name, key_path, unique)
+        }
+    }
+
+}
+
+// A connection's SQLite database, on its storage task
+struct IdbStorage {
+    // The IndexedDB database it's a connection to
+    name: ~str,
+    conn: Connection,
+    // Why the running transaction couldn't begin, if it couldn't
+    mut begin_error: Option<IdbError>,
+}
+
+fn IdbStorage(path: &str, name: &str) -> Result<IdbStorage, IdbError> {
+    let conn = match sql(Connection(path)) {
+        Ok(move conn) => move conn,
+        Err(move e) => return Err(move e)
+    };
+    sql(conn.exec(SCHEMA)).map(|_| IdbStorage {
+        name: name.to_str(),
+        conn: conn,
+        begin_error: None
+    })
+}
+
+impl IdbStorage {
+    // The database's version, which is 0 until it's first upgraded
+    fn version(&self) -> Result<u64, IdbError> {
+        let versions = self.query("SELECT version FROM databases WHERE name = ?1",
+                                  |stmt| stmt.bind_text(1, self.name),
+                                  |stmt| stmt.column_int(0) as u64);
+        versions.map(|versions| if versions.is_empty() { 0 } else { versions[0] })
+    }
+
+    // Begins a transaction in `mode`. An upgrade sets the database's
+    // version to `version`
+    fn begin(&self, mode: TransactionMode, version: u64) {
+        let begun = match mode {
+            ReadOnly => sql(self.conn.exec("BEGIN")),
+            ReadWrite => sql(self.conn.exec("BEGIN IMMEDIATE")),
+            VersionChange => match sql(self.conn.exec("BEGIN IMMEDIATE")) {
+                Ok(()) => self.execute("INSERT OR REPLACE INTO databases VALUES (?1, ?2)", |stmt| {
+                    stmt.bind_text(1, self.name);
+                    stmt.bind_int(2, version as int);
+                }),
+                Err(move e) => Err(move e)
+            }
+        };
+        self.begin_error = match move begun {
+            Ok(()) => None,
+            Err(move e) => Some(move e)
+        };
+    }
+
+    // Runs a request in a savepoint of its own
+    fn run_request(&self, operation: &Operation) -> Result<Outcome, IdbError> {
+        match self.begin_error {
+            Some(ref e) => return Err(copy *e),
+            None => ()
+        }
+        do self.savepoint { self.run(operation) }
+    }
+
+    fn commit(&self) -> Result<(), IdbError> {
+        match self.begin_error {
+            Some(ref e) => Err(copy *e),
+            None => sql(self.conn.exec("COMMIT"))
+        }
+    }
+
+    fn rollback(&self) {
+        self.conn.exec("ROLLBACK");
+    }
+
+    fn store_info(&self, name: &str) -> Result<StoreInfo, IdbError> {
+        first(self.query("SELECT id, name, key_path FROM object_stores \
+                          WHERE database = ?1 AND name = ?2",
+                         |stmt| { stmt.bind_text(1, self.name); stmt.bind_text(2, name); },
+                         store_row),
+              NotFoundError)
+    }
+
+    fn create_object_store(&self, name: &str, key_path: Option<~str>)
+        -> Result<StoreInfo, IdbError> {
+        if self.store_info(name).is_ok() {
+            return Err(ConstraintError);
+        }
+        let inserted = self.execute("INSERT INTO object_stores (database, name, key_path) \
+                                     VALUES (?1, ?2, ?3)", |stmt| {
+            stmt.bind_text(1, self.name);
+            stmt.bind_text(2, name);
+            match key_path {
+                Some(ref path) => stmt.bind_text(3, *path),
+                None => stmt.bind_null(3)
+            }
+        });
+        match move inserted {
+            Ok(()) => self.store_info(name),
+            Err(move e) => Err(move e)
+        }
+    }
+
+    fn index_info(&self, store: int, name: &str) -> Result<IndexInfo, IdbError> {
+        first(self.query("SELECT id, name, key_path, is_unique FROM indexes \
+                          WHERE store = ?1 AND name = ?2",
+                         |stmt| { stmt.bind_int(1, store); stmt.bind_text(2, name); },
+                         index_row),
+              NotFoundError)
+    }
+
+    fn create_index(&self, store: int, name: &str, key_path: &str, unique: bool)
+        -> Result<IndexInfo, IdbError> {
+        if self.index_info(store, name).is_ok() {
+            return Err(ConstraintError);
+        }
+        let inserted = self.execute("INSERT INTO indexes (store, name, key_path, is_unique) \
+                                     VALUES (?1, ?2, ?3, ?4)", |stmt| {
+            stmt.bind_int(1, store);
+            stmt.bind_text(2, name);
+            stmt.bind_text(3, key_path);
+            stmt.bind_int(4, unique as int);
+        });
+        match move inserted {
+            Ok(()) => self.index_info(store, name),
+            Err(move e) => Err(move e)
+        }
+    }
+
+    // Runs a statement that returns no rows
+    priv fn execute(&self, sql: &str, bind: fn(&Statement)) -> Result<(), IdbError> {
+        let stmt = match self.conn.prepare(sql) {
+            Ok(move stmt) => move stmt,
+            Err(move e) => return Err(UnknownError(move e))
+        };
+        bind(&stmt);
+        match stmt.step() {
+            Ok(_) => Ok(()),
+            Err(move e) => Err(UnknownError(move e))
+        }
+    }
+
+    // The rows a query returns, each made into a T by `row`
+    priv fn query<T>(&self, sql: &str, bind: fn(&Statement), row: fn(&Statement) -> T)
+        -> Result<~[T], IdbError> {
+        let stmt = match self.conn.prepare(sql) {
+            Ok(move stmt) => move stmt,
+            Err(move e) => return Err(UnknownError(move e))
+        };
+        bind(&stmt);
+        let mut rows = ~[];
+        loop {
+            match stmt.step() {
+                Ok(true) => rows.push(row(&stmt)),
+                Ok(false) => return Ok(move rows),
+                Err(move e) => return Err(UnknownError(move e))
+            }
+        }
+    }
+
+    // Runs `f` in a savepoint, which is rolled back if it fails
+    priv fn savepoint<T>(&self, f: fn() -> Result<T, IdbError>) -> Result<T, IdbError> {
+        match sql(self.conn.exec("SAVEPOINT request")) {
+            Ok(()) => (),
+            Err(move e) => return Err(move e)
+        }
+        match f() {
+            Ok(move result) => sql(self.conn.exec("RELEASE request")).map(|_| move result),
+            Err(move e) => {
+                self.conn.exec("ROLLBACK TO request");
+                self.conn.exec("RELEASE request");
+                Err(move e)
+            }
+        }
+    }
+
+    priv fn run(&self, operation: &Operation) -> Result<Outcome, IdbError> {
+        match *operation {
+            PutOp(store, ref key, ref value, overwrite) => {
+                self.put(store, key, *value, overwrite).map(|_| KeyOutcome(copy *key))
+            }
+            GetOp(store, ref key) => self.get(store, key).map(|record| RecordOutcome(copy *record)),
+            GetAllOp(store) => self.get_all(store).map(|records| RecordsOutcome(copy *records)),
+            DeleteOp(store, ref key) => self.delete(store, key).map(|_| DoneOutcome),
+            ClearOp(store) => self.clear(store).map(|_| DoneOutcome)
+        }
+    }
+
+    priv fn put(&self, store: int, key: &Key, value: &[u8], overwrite: bool)
+        -> Result<(), IdbError> {
+        if !overwrite {
+            match self.get(store, key) {
+                Ok(Some(_)) => return Err(ConstraintError),
+                Ok(None) => (),
+                Err(move e) => return Err(move e)
+            }
+        }
+        self.execute("INSERT OR REPLACE INTO records VALUES (?1, ?2, ?3)", |stmt| {
+            stmt.bind_int(1, store);
+            stmt.bind_blob(2, encode_key(key));
+            stmt.bind_blob(3, value);
+        })
+    }
+
+    priv fn get(&self, store: int, key: &Key) -> Result<Option<(Key, ~[u8])>, IdbError> {
+        let records = self.query("SELECT key, value FROM records WHERE store = ?1 AND key = ?2",
+                                 |stmt| {
+                                     stmt.bind_int(1, store);
+                                     stmt.bind_blob(2, encode_key(key));
+                                 },
+                                 record_row);
+        match move records {
+            Ok(move records) => {
+                let mut records = move records;
+                Ok(if records.is_empty() { None } else { Some(vec::shift(&mut records)) })
+            }
+            Err(move e) => Err(move e)
+        }
+    }
+
+    priv fn get_all(&self, store: int) -> Result<~[(Key, ~[u8])], IdbError> {
+        self.query("SELECT key, value FROM records WHERE store = ?1 ORDER BY key",
+                   |stmt| stmt.bind_int(1, store),
+                   record_row)
+    }
+
+    priv fn delete(&self, store: int, key: &Key) -> Result<(), IdbError> {
+        self.execute("DELETE FROM records WHERE store = ?1 AND key = ?2", |stmt| {
+            stmt.bind_int(1, store);
+            stmt.bind_blob(2, encode_key(key));
+        })
+    }
+
+    priv fn clear(&self, store: int) -> Result<(), IdbError> {
+        self.execute("DELETE FROM records WHERE store = ?1", |stmt| stmt.bind_int(1, store))
+    }
+}
+
+pub enum TransactionMode {
+    ReadOnly,
+    ReadWrite,
+    // Upgrading the database; the only mode that can change its stores
+    VersionChange,
+}
+
+impl TransactionMode {
+    pure fn name(&self) -> ~str {
+        match *self {
+            ReadOnly => ~"readonly",
+            ReadWrite => ~"readwrite",
+            VersionChange => ~"versionchange"
+        }
+    }
+}
+
+impl TransactionMode : cmp::Eq {
+    pure fn eq(&self, other: &TransactionMode) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &TransactionMode) -> bool {
+        !self.eq(other)
+    }
+}
+
+/// The mode `transaction()` was asked for by name. Script can't ask for a
+/// versionchange transaction.
+pub fn mode_for_name(name: &str) -> Option<TransactionMode> {
+    match name {
+        "readonly" => Some(ReadOnly),
+        "readwrite" => Some(ReadWrite),
+        _ => None
+    }
+}
+
+pub enum TransactionState {
+    // Waiting for the transactions made before it
+    Waiting,
+    Running,
+    // Every request has run, and the storage task is committing it
+    Committing,
+    // Committed or aborted
+    Finished,
+}
+
+impl TransactionState : cmp::Eq {
+    pure fn eq(&self, other: &TransactionState) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &TransactionState) -> bool {
+        !self.eq(other)
+    }
+}
+
+/// What a request does, on the store given by its id.
+pub enum Operation {
+    // Stores a value under a key. The flag is whether a record already
+    // under the key is replaced
+    PutOp(int, Key, ~[u8], bool),
+    GetOp(int, Key),
+    GetAllOp(int),
+    DeleteOp(int, Key),
+    ClearOp(int),
+}
+
+impl Operation {
+    pure fn writes(&self) -> bool {
+        match *self {
+            PutOp(*) | DeleteOp(*) | ClearOp(*) => true,
+            _ => false
+        }
+    }
+}
+
+/// What a request that succeeded has for its result.
+pub enum Outcome {
+    // The key a value was stored under
+    KeyOutcome(Key),
+    // A record, if one was found
+    RecordOutcome(Option<(Key, ~[u8])>),
+    RecordsOutcome(~[(Key, ~[u8])]),
+    // Nothing, for delete() and clear()
+    DoneOutcome,
+}
+
+pub struct IDBTransaction {
+    db: @IDBDatabase,
+    mode: TransactionMode,
+    // The names of the stores it can use; a versionchange transaction can
+    // use them all
+    scope: ~[~str],
+    mut state: TransactionState,
+    // Whether requests can be made now: only while the script that made
+    // it, or a handler of one of its requests' events, is running
+    mut active: bool,
+    // Requests yet to run, with the JS objects their results go to
+    priv mut requests: ~[(JSVal, Operation)],
+    // The request on the storage task, and where its result comes back
+    priv mut running: Option<(JSVal, Port<Result<Outcome, IdbError>>)>,
+    // Where the commit's result comes back
+    priv mut committed: Option<Port<Result<(), IdbError>>>,
+    mut error: Option<IdbError>,
+    // The transaction's JS object, and for a versionchange transaction
+    // the open request that's waiting on it
+    mut obj: JSVal,
+    mut open_request: JSVal,
+    priv mut old_version: u64,
+}
+
+fn IDBTransaction(db: @IDBDatabase, mode: TransactionMode, scope: ~[~str]) -> @IDBTransaction {
+    @IDBTransaction {
+        db: db,
+        mode: mode,
+        scope: move scope,
+        state: Waiting,
+        active: true,
+        requests: ~[],
+        running: None,
+        committed: None,
+        error: None,
+        obj: JSVAL_NULL,
+        open_request: JSVAL_NULL,
+        old_version: 0
+    }
+}
+
+/// The store called `name`, as `tx` uses it.
+pub fn object_store(tx: @IDBTransaction, name: &str) -> Result<IDBObjectStore, IdbError> {
+    if tx.state == Finished {
+        return Err(InvalidStateError);
+    }
+    if !tx.in_scope(name) {
+        return Err(NotFoundError);
+    }
+    tx.db.store_info(name).map(|info| IDBObjectStore { transaction: tx, info: copy *info })
+}
+
+impl IDBTransaction {
+    /// Queues a request, whose result goes to the JS object `request`.
+    fn request(&self, request: JSVal, operation: Operation) -> Result<(), IdbError> {
+        if self.state == Finished || !self.active {
+            return Err(TransactionInactiveError);
+        }
+        if self.mode == ReadOnly && operation.writes() {
+            return Err(ReadOnlyError);
+        }
+        self.requests.push((request, move operation));
+        Ok(())
+    }
+
+    fn in_scope(&self, name: &str) -> bool {
+        self.mode == VersionChange || self.scope.any(|store| str::eq_slice(*store, name))
+    }
+
+    /// Whether there are requests that haven't been sent to run.
+    fn has_requests(&self) -> bool {
+        !self.requests.is_empty()
+    }
+
+    /**
+    Sends the oldest request to the storage task, which calls `done` once
+    it has run; `finish_request` then gives its result. A request that
+    fails changes nothing; whether the transaction goes on is up to the
+    caller.
+    */
+    fn run_next(&self, done: fn~()) {
+        assert self.state == Running && self.running.is_none() && self.has_requests();
+        let (request, operation) = vec::shift(&mut self.requests);
+        let (result_chan, result_port) = pipes::stream();
+        self.running = Some((request, move result_port));
+        do self.db.post |storage, move operation, move result_chan, move done| {
+            result_chan.send(storage.run_request(&operation));
+            done();
+        }
+    }
+
+    /// The JS object the request `run_next` sent has its result go to, and
+    /// the result. Waits for the storage task if it hasn't run it yet.
+    fn finish_request(&self) -> Option<(JSVal, Result<Outcome, IdbError>)> {
+        let mut running = None;
+        running <-> self.running;
+        match move running {
+            Some((request, move result_port)) => Some((request, result_port.recv())),
+            None => None
+        }
+    }
+
+    /// Commits on the storage task once every request has run, and it
+    /// calls `done` when it has; `finish_commit` then says whether that
+    /// worked. If it didn't, the caller should abort the transaction.
+    fn commit(&self, done: fn~()) {
+        assert self.state == Running && self.running.is_none() && !self.has_requests();
+        let (committed_chan, committed_port) = pipes::stream();
+        self.state = Committing;
+        self.committed = Some(move committed_port);
+        do self.db.post |storage, move committed_chan, move done| {
+            committed_chan.send(storage.commit());
+            done();
+        }
+    }
+
+    fn finish_commit(&self) -> Result<(), IdbError> {
+        assert self.state == Committing;
+        let mut committed = None;
+        committed <-> self.committed;
+        let result = option::unwrap(move committed).recv();
+        if result.is_ok() {
+            self.state = Finished;
+        }
+        move result
+    }
+
+    /**
+    Undoes everything the transaction did, and if it was upgrading the
+    database, puts the old version back. Returns the JS objects of the
+    requests that won't finish now.
+    */
+    fn abort(&self, error: IdbError) -> ~[JSVal] {
+        if self.state == Running || self.state == Committing {
+            // After the request that's running, if there is one
+            self.db.post(|storage| storage.rollback());
+        }
+        if self.mode == VersionChange {
+            self.db.version = self.old_version;
+        }
+        self.state = Finished;
+        self.error = Some(move error);
+        let mut requests = ~[];
+        match self.forget_running() {
+            Some(request) => requests.push(request),
+            None => ()
+        }
+        for self.requests.each |request| {
+            match *request { (obj, _) => requests.push(obj) }
+        }
+        self.requests = ~[];
+        self.committed = None;
+        move requests
+    }
+
+    // Forgets the request on the storage task, whose result won't be
+    // wanted now, and returns its JS object
+    priv fn forget_running(&self) -> Option<JSVal> {
+        let mut running = None;
+        running <-> self.running;
+        match move running {
+            Some((request, _)) => Some(request),
+            None => None
+        }
+    }
+}
+
+pub struct IDBObjectStore {
+    transaction: @IDBTransaction,
+    info: StoreInfo,
+}
+
+impl IDBObjectStore {
+    /// Queues storing `value`. `overwrite` is false for add(), which fails
+    /// if the key has a record.
+    fn put(&self, request: JSVal, key: Key, value: ~[u8], overwrite: bool)
+        -> Result<(), IdbError> {
+        self.transaction.request(request, PutOp(self.info.id, move key, move value, overwrite))
+    }
+
+    fn get(&self, request: JSVal, key: Key) -> Result<(), IdbError> {
+        self.transaction.request(request, GetOp(self.info.id, move key))
+    }
+
+    fn get_all(&self, request: JSVal) -> Result<(), IdbError> {
+        self.transaction.request(request, GetAllOp(self.info.id))
+    }
+
+    fn delete(&self, request: JSVal, key: Key) -> Result<(), IdbError> {
+        self.transaction.request(request, DeleteOp(self.info.id, move key))
+    }
+
+    fn clear(&self, request: JSVal) -> Result<(), IdbError> {
+        self.transaction.request(request, ClearOp(self.info.id))
+    }
+
+    fn create_index(&self, name: &str, key_path: &str, unique: bool) -> Result<IDBIndex, IdbError> {
+        do self.transaction.db.create_index(self.transaction, &self.info, name, key_path,
+                                            unique).map |info| {
+            IDBIndex { info: copy *info }
+        }
+    }
+}
+
+/// An index, which for now only knows what it was made with.
+pub struct IDBIndex {
+    info: IndexInfo,
+}
+
+#[cfg(test)]
+mod idb_tests {
+    use js::JSVAL_NULL;
+
+    fn open_upgraded() -> (@IDBDatabase, @IDBTransaction) {
+        let db = result::unwrap(open_database(":memory:", "test"));
+        let tx = upgrade(db, 1);
+        (db, tx)
+    }
+
+    // Runs every request, returning their results
+    fn run_all(tx: @IDBTransaction) -> ~[Result<Outcome, IdbError>] {
+        let mut results = ~[];
+        while tx.has_requests() {
+            tx.run_next(|| ());
+            match tx.finish_request() {
+                Some((_, move result)) => results.push(move result),
+                None => fail
+            }
+        }
+        move results
+    }
+
+    fn commit(tx: @IDBTransaction) -> Result<(), IdbError> {
+        tx.commit(|| ());
+        tx.finish_commit()
+    }
+
+    fn error_of(outcome: &Result<Outcome, IdbError>) -> Option<IdbError> {
+        match *outcome {
+            Err(ref e) => Some(copy *e),
+            Ok(_) => None
+        }
+    }
+
+    fn value_of(outcome: &Result<Outcome, IdbError>) -> Option<~[u8]> {
+        match *outcome {
+            Ok(RecordOutcome(Some((_, ref value)))) => Some(copy *value),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn test_keys_sort_in_key_order() {
+        let keys = ~[NumberKey(-1e300), NumberKey(-1.5), NumberKey(0.0), NumberKey(2.0),
+                     NumberKey(10.0), NumberKey(1e300), StringKey(~""), StringKey(~"a"),
+                     StringKey(~"ab"), StringKey(~"b")];
+        for uint::range(1, keys.len()) |i| {
+            assert encode_key(&keys[i - 1]) < encode_key(&keys[i]);
+        }
+        for keys.each |key| {
+            assert decode_key(encode_key(key)) == *key;
+        }
+        assert encode_key(&NumberKey(-0.0)) == encode_key(&NumberKey(0.0));
+    }
+
+    #[test]
+    #[ignore(cfg(not(sqlite)))]
+    fn test_put_get_delete() {
+        let (db, tx) = open_upgraded();
+        let info = result::unwrap(db.create_object_store(tx, "things", None));
+        let store = result::unwrap(object_store(tx, "things"));
+        assert store.info.id == info.id;
+
+        store.put(JSVAL_NULL, StringKey(~"b"), ~[2], false);
+        store.put(JSVAL_NULL, StringKey(~"a"), ~[1], false);
+        store.put(JSVAL_NULL, StringKey(~"a"), ~[3], false);
+        store.put(JSVAL_NULL, StringKey(~"b"), ~[4], true);
+        store.get(JSVAL_NULL, StringKey(~"a"));
+        store.get(JSVAL_NULL, StringKey(~"b"));
+        store.delete(JSVAL_NULL, StringKey(~"a"));
+        store.get(JSVAL_NULL, StringKey(~"a"));
+        let results = run_all(tx);
+        assert error_of(&results[2]) == Some(ConstraintError);
+        assert value_of(&results[4]) == Some(~[1]);
+        assert value_of(&results[5]) == Some(~[4]);
+        assert value_of(&results[7]).is_none();
+        assert commit(tx).is_ok();
+    }
+
+    #[test]
+    #[ignore(cfg(not(sqlite)))]
+    fn test_get_all_and_clear() {
+        let (db, tx) = open_upgraded();
+        db.create_object_store(tx, "things", None);
+        let store = result::unwrap(object_store(tx, "things"));
+        for [3.0, 1.0, 2.0].each |n| {
+            store.put(JSVAL_NULL, NumberKey(*n), ~[*n as u8], true);
+        }
+        store.get_all(JSVAL_NULL);
+        store.clear(JSVAL_NULL);
+        store.get_all(JSVAL_NULL);
+        let results = run_all(tx);
+        match results[3] {
+            Ok(RecordsOutcome(ref records)) => {
+                assert records.map(|r| match *r { (_, ref v) => copy *v }) == ~[~[1], ~[2], ~[3]];
+            }
+            _ => fail
+        }
+        match results[5] {
+            Ok(RecordsOutcome(ref records)) => assert records.is_empty(),
+            _ => fail
+        }
+    }
+
+    #[test]
+    #[ignore(cfg(not(sqlite)))]
+    fn test_create_index() {
+        let (db, tx) = open_upgraded();
+        db.create_object_store(tx, "people", Some(~"id"));
+        let store = result::unwrap(object_store(tx, "people"));
+        let index = result::unwrap(store.create_index("by_email", "email", true));
+        assert index.info.name == ~"by_email";
+        assert index.info.key_path == ~"email";
+        assert index.info.unique;
+        assert store.create_index("by_email", "name", false).is_err();
+        commit(tx);
+        db.start_next();
+
+        // Only an upgrade can make one
+        let tx = result::unwrap(transaction(db, ~[~"people"], ReadWrite));
+        let store = result::unwrap(object_store(tx, "people"));
+        assert store.create_index("by_name", "name", false).is_err();
+    }
+
+    #[test]
+    #[ignore(cfg(not(sqlite)))]
+    fn test_readonly_and_inactive() {
+        let (db, tx) = open_upgraded();
+        db.create_object_store(tx, "things", None);
+        commit(tx);
+        db.start_next();
+
+        let tx = result::unwrap(transaction(db, ~[~"things"], ReadOnly));
+        let store = result::unwrap(object_store(tx, "things"));
+        assert store.put(JSVAL_NULL, NumberKey(1.0), ~[], true) == Err(ReadOnlyError);
+        tx.active = false;
+        assert store.get(JSVAL_NULL, NumberKey(1.0)) == Err(TransactionInactiveError);
+        assert object_store(tx, "other").is_err();
+    }
+
+    #[test]
+    #[ignore(cfg(not(sqlite)))]
+    fn test_transactions_wait_their_turn() {
+        let (db, tx) = open_upgraded();
+        db.create_object_store(tx, "things", None);
+        commit(tx);
+        db.start_next();
+
+        let first = result::unwrap(transaction(db, ~[~"things"], ReadWrite));
+        let second = result::unwrap(transaction(db, ~[~"things"], ReadOnly));
+        assert first.state == Running;
+        assert second.state == Waiting;
+        result::unwrap(object_store(first, "things"))
+            .put(JSVAL_NULL, NumberKey(1.0), ~[7], true);
+        result::unwrap(object_store(second, "things")).get(JSVAL_NULL, NumberKey(1.0));
+        run_all(first);
+        commit(first);
+        assert db.start_next().is_some();
+        assert value_of(&run_all(second)[0]) == Some(~[7]);
+    }
+
+    #[test]
+    #[ignore(cfg(not(sqlite)))]
+    fn test_abort_undoes_everything() {
+        let (db, tx) = open_upgraded();
+        db.create_object_store(tx, "things", None);
+        commit(tx);
+        db.start_next();
+
+        let tx = result::unwrap(transaction(db, ~[~"things"], ReadWrite));
+        let store = result::unwrap(object_store(tx, "things"));
+        store.put(JSVAL_NULL, NumberKey(1.0), ~[1], true);
+        store.get(JSVAL_NULL, NumberKey(1.0));
+        run_all(tx);
+        assert tx.abort(AbortError).is_empty();
+        db.start_next();
+
+        let tx = result::unwrap(transaction(db, ~[~"things"], ReadOnly));
+        result::unwrap(object_store(tx, "things")).get(JSVAL_NULL, NumberKey(1.0));
+        assert value_of(&run_all(tx)[0]).is_none();
+    }
+
+    #[test]
+    #[ignore(cfg(not(sqlite)))]
+    fn test_aborted_upgrade_keeps_old_version() {
+        let db = result::unwrap(open_database(":memory:", "test"));
+        let tx = upgrade(db, 3);
+        assert db.version == 3;
+        db.create_object_store(tx, "things", None);
+        tx.abort(AbortError);
+        db.start_next();
+        assert db.version == 0;
+        assert db.store_info("things").is_err();
+    }
+}
@@ -101,9 +101,26 @@ fn DoctypeData(name: ~str, public_id: Option<~str>,
 fn define_bindings(compartment: &bare_compartment, doc: @Document,
                    win: @Window) {
     bindings::window::init(compartment, win);
+    bindings::navigator::init(compartment, win);
     bindings::document::init(compartment, doc);
     bindings::node::init(compartment);
     bindings::element::init(compartment);
+    bindings::form::init(compartment);
+    bindings::input::init(compartment);
+    bindings::notification::init(compartment);
+    bindings::performance::init(compartment, win);
+    bindings::blob::init(compartment);
+    bindings::form_data::init(compartment);
+    bindings::url::init(compartment);
+    bindings::text_coding::init(compartment);
+    bindings::idb::init(compartment);
+    bindings::cache_storage::init(compartment);
+    bindings::custom_event::init(compartment);
+    bindings::resize_observer::init(compartment);
+    bindings::abort_controller::init(compartment);
+    bindings::debug::init(compartment);
+    bindings::console::init(compartment);
+    bindings::css::init(compartment);
 }
 
 
@@ -114,15 +131,31 @@ fn define_bindings(compartment: &bare_compartment, doc: @Document,
    Note that there may be multiple boxes per DOM node. */
 enum LayoutData = {
     mut style: ~SpecifiedStyle,
-    mut flow:  Option<@FlowContext>
+    mut flow:  Option<@FlowContext>,
+    // The style is out of date, and is matched again when layout next
+    // looks at the node
+    mut dirty: bool
 };
 
 type Node = cow::Handle<NodeData, LayoutData>;
 
 type NodeScope = cow::Scope<NodeData, LayoutData>;
 
+// Each kind of node is allocated from an arena of its own
+const NODE_KIND_COUNT: uint = 4;
+
 fn NodeScope() -> NodeScope {
-    cow::Scope()
+    cow::ScopeWithClasses(NODE_KIND_COUNT)
+}
+
+// The arena a node of kind `k` is allocated from
+pure fn arena_class(k: &NodeKind) -> uint {
+    match *k {
+        Element(*) => 0,
+        Text(*) => 1,
+        Comment(*) => 2,
+        Doctype(*) => 3
+    }
 }
 
 trait NodeScopeExtensions {
@@ -132,7 +165,8 @@ trait NodeScopeExtensions {
 #[allow(non_implicitly_copyable_typarams)]
 impl NodeScope : NodeScopeExtensions {
     fn new_node(k: NodeKind) -> Node {
-        self.handle(&NodeData({tree: tree::empty(), kind: ~move k}))
+        let class = arena_class(&k);
+        self.handle_in_class(&NodeData({tree: tree::empty(), kind: ~move k}), class)
     }
 }
 
@@ -0,0 +1,146 @@
+/*!
+The Notifications API. There is no platform notification support yet, so
+notifications are shown by logging them, at the info level. Nothing can
+click one there; with --enable-notification-debug, script can, through
+`notification.debugClick()`.
+
+A notification's JS object is rooted while it's showing, so that its
+event handlers are there for the events the platform sends it.
+*/
+
+use dom::bindings::rooting::RootedValues;
+use js::jsapi::{JSContext, JSVal};
+
+pub enum NotificationPermission {
+    PermissionDefault,
+    PermissionGranted,
+    PermissionDenied
+}
+
+impl NotificationPermission {
+    pure fn to_str() -> ~str {
+        match self {
+            PermissionDefault => ~"default",
+            PermissionGranted => ~"granted",
+            PermissionDenied => ~"denied"
+        }
+    }
+}
+
+impl NotificationPermission : cmp::Eq {
+    pure fn eq(other: &NotificationPermission) -> bool {
+        (self as uint) == (*other as uint)
+    }
+    pure fn ne(other: &NotificationPermission) -> bool {
+        !self.eq(other)
+    }
+}
+
+/// The `options` dictionary passed to the Notification constructor. The
+/// `data` member is a JS value, so the bindings keep it on the JS object.
+pub struct NotificationOptions {
+    body: ~str,
+    icon: Option<~str>,
+    tag: ~str,
+    badge: Option<~str>,
+    vibrate: ~[uint],
+    silent: bool,
+}
+
+pub fn NotificationOptions() -> NotificationOptions {
+    NotificationOptions {
+        body: ~"",
+        icon: None,
+        tag: ~"",
+        badge: None,
+        vibrate: ~[],
+        silent: false,
+    }
+}
+
+pub struct Notification {
+    title: ~str,
+    options: NotificationOptions,
+    mut shown: bool,
+    mut closed: bool,
+    // The JS object, while the notification is showing
+    priv roots: RootedValues,
+}
+
+pub fn Notification(cx: *JSContext, title: ~str, options: NotificationOptions) -> Notification {
+    Notification {
+        title: move title,
+        options: move options,
+        shown: false,
+        closed: false,
+        roots: RootedValues(cx),
+    }
+}
+
+impl Notification {
+    /// Displays the notification, rooting `obj`, its JS object, until it's
+    /// closed. Callers must check for permission first.
+    fn show(obj: JSVal) {
+        assert !self.shown;
+        // TODO: hand this to the platform's notification service
+        if self.options.body.is_empty() {
+            info!("notification: %s", self.title);
+        } else {
+            info!("notification: %s: %s", self.title, self.options.body);
+        }
+        self.shown = true;
+        self.roots.add(obj);
+    }
+
+    fn is_showing() -> bool {
+        self.shown && !self.closed
+    }
+
+    /// Clicks the notification, as the platform would when the user does.
+    /// Returns false if it isn't showing, when there's nothing to click.
+    fn click() -> bool {
+        if !self.is_showing() {
+            return false;
+        }
+        info!("notification clicked: %s", self.title);
+        true
+    }
+
+    /// Dismisses the notification. Returns false if it was already closed.
+    fn close() -> bool {
+        if self.closed {
+            return false;
+        }
+        self.closed = true;
+        self.roots.clear();
+        true
+    }
+}
+
+#[cfg(test)]
+mod notification_tests {
+    use js::JSVAL_NULL;
+
+    #[test]
+    fn test_rooted_while_showing() {
+        let notification = Notification(ptr::null(), ~"title", NotificationOptions());
+        assert !notification.is_showing();
+        notification.show(JSVAL_NULL);
+        assert notification.is_showing();
+        assert notification.roots.len() == 1;
+        assert notification.close();
+        assert !notification.is_showing();
+        assert notification.roots.len() == 0;
+        assert !notification.close();
+    }
+
+    #[test]
+    fn test_only_clicked_while_showing() {
+        let notification = Notification(ptr::null(), ~"title", NotificationOptions());
+        assert !notification.click();
+        notification.show(JSVAL_NULL);
+        assert notification.click();
+        notification.close();
+        assert !notification.click();
+    }
+}
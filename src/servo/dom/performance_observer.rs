@@ -0,0 +1,636 @@
+/*!
+`performance` and `PerformanceObserver`. The page's timeline is a list of
+`PerformanceEntry`s, timed in ms from when navigation started: marks and
+measures made by scripts, the navigation itself, the resources fetched,
+paints and long tasks.
+
+Every script, style sheet, image and module the page fetches gets a
+`resource` entry from the timings the resource task takes. A cross-origin
+resource only shows its start, end and duration, unless its
+`Timing-Allow-Origin` header lets the page's origin see the rest.
+
+Paint timing follows the frames layout shows. The first with text or an
+image in it is the first contentful paint. The largest image or block of
+text shown so far is the largest contentful paint candidate, which is
+only recorded once the user first interacts with the page, as it won't
+change after that. Recording an entry hands it to the observers of its
+type, and once the running task is done each observer with entries is
+called back with them.
+*/
+
+use dom::bindings::rooting::RootedValues;
+use dom::node::Node;
+use js::jsapi::{JSContext, JSVal};
+use resource::resource_task::FetchTiming;
+use std::net::url::Url;
+use std::time::precise_time_ns;
+use util::url::url_to_str;
+use util::url::{origin, same_origin};
+use dvec::DVec;
+
+/// Tasks that take longer than this, in ms, are long tasks.
+pub const LONG_TASK_MS: float = 50.0;
+
+pub enum EntryType {
+    MarkEntry,
+    MeasureEntry,
+    NavigationEntry,
+    ResourceEntry,
+    PaintEntry,
+    LargestContentfulPaintEntry,
+    LongTaskEntry,
+}
+
+impl EntryType {
+    static fn from_str(s: &str) -> Option<EntryType> {
+        match s {
+            "mark" => Some(MarkEntry),
+            "measure" => Some(MeasureEntry),
+            "navigation" => Some(NavigationEntry),
+            "resource" => Some(ResourceEntry),
+            "paint" => Some(PaintEntry),
+            "largest-contentful-paint" => Some(LargestContentfulPaintEntry),
+            "longtask" => Some(LongTaskEntry),
+            _ => None
+        }
+    }
+
+    pure fn to_str() -> ~str {
+        match self {
+            MarkEntry => ~"mark",
+            MeasureEntry => ~"measure",
+            NavigationEntry => ~"navigation",
+            ResourceEntry => ~"resource",
+            PaintEntry => ~"paint",
+            LargestContentfulPaintEntry => ~"largest-contentful-paint",
+            LongTaskEntry => ~"longtask"
+        }
+    }
+}
+
+impl EntryType : cmp::Eq {
+    pure fn eq(&self, other: &EntryType) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &EntryType) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// What a `resource` entry says of the fetch, beyond its start and
+/// duration. Times are in ms from the time origin, or 0 for steps that
+/// didn't happen or that the page may not see.
+pub struct ResourceTiming {
+    // What fetched it: "script", "img", ...
+    initiator_type: ~str,
+    redirect_start: float,
+    redirect_end: float,
+    fetch_start: float,
+    domain_lookup_start: float,
+    domain_lookup_end: float,
+    connect_start: float,
+    connect_end: float,
+    secure_connection_start: float,
+    request_start: float,
+    response_start: float,
+    response_end: float,
+    // In bytes
+    transfer_size: uint,
+    encoded_body_size: uint,
+    decoded_body_size: uint,
+}
+
+/**
+Whether a page at `document` may see the detailed timing of a load of
+`resource`: it's from the same origin, or its `Timing-Allow-Origin` is `*`
+or lists the page's origin.
+*/
+pub fn timing_allowed(resource: &Url, document: &Url, timing_allow_origin: &Option<~str>) -> bool {
+    if same_origin(resource, document) {
+        return true;
+    }
+    let document_origin = origin(document);
+    match *timing_allow_origin {
+        Some(ref header) => do str::split_char(*header, ',').any |value| {
+            let value = str::trim(*value);
+            value == ~"*" || value == document_origin
+        },
+        None => false
+    }
+}
+
+/// The element a `largest-contentful-paint` entry is for, and how many
+/// square px of the viewport it covered.
+pub struct LargestContentfulPaint {
+    element: Node,
+    size: uint,
+}
+
+pub struct PerformanceEntry {
+    name: ~str,
+    entry_type: EntryType,
+    start_time: float,
+    duration: float,
+    resource: Option<ResourceTiming>,
+    largest: Option<LargestContentfulPaint>,
+}
+
+pub fn PerformanceEntry(name: ~str, entry_type: EntryType, start_time: float,
+                        duration: float) -> PerformanceEntry {
+    PerformanceEntry {
+        name: move name,
+        entry_type: entry_type,
+        start_time: start_time,
+        duration: duration,
+        resource: None,
+        largest: None,
+    }
+}
+
+pub struct PerformanceObserver {
+    callback: JSVal,
+    // The JS object for the observer, passed back to the callback
+    mut obj: JSVal,
+    priv mut entry_types: ~[EntryType],
+    // Entries recorded since the callback was last called
+    priv mut buffer: ~[PerformanceEntry],
+    // The callback and the JS object, while there are types observed
+    priv roots: RootedValues,
+}
+
+pub fn PerformanceObserver(cx: *JSContext, callback: JSVal, obj: JSVal) -> PerformanceObserver {
+    PerformanceObserver {
+        callback: callback,
+        obj: obj,
+        entry_types: ~[],
+        buffer: ~[],
+        roots: RootedValues(cx),
+    }
+}
+
+impl PerformanceObserver {
+    /// Starts observing entries of `entry_types`, in place of any observed
+    /// before, and roots the callback and the JS object until disconnected.
+    fn observe(&self, entry_types: ~[EntryType]) {
+        if entry_types.is_empty() {
+            return self.disconnect();
+        }
+        self.entry_types = move entry_types;
+        if self.roots.len() == 0 {
+            self.roots.add(self.callback);
+            self.roots.add(self.obj);
+        }
+    }
+
+    /// Stops observing, and unroots the callback and the JS object.
+    fn disconnect(&self) {
+        self.entry_types = ~[];
+        self.buffer = ~[];
+        self.roots.clear();
+    }
+
+    fn is_registered(&self) -> bool {
+        !self.entry_types.is_empty()
+    }
+
+    fn is_observing(&self, entry_type: EntryType) -> bool {
+        self.entry_types.contains(&entry_type)
+    }
+
+    /// Buffers `entry` if it's of an observed type. Returns whether it was.
+    fn queue(&self, entry: &PerformanceEntry) -> bool {
+        if self.is_observing(entry.entry_type) {
+            self.buffer.push(copy *entry);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The entries buffered for the callback, which are then forgotten.
+    fn take_records(&self) -> ~[PerformanceEntry] {
+        let mut records = ~[];
+        records <-> self.buffer;
+        move records
+    }
+}
+
+pub struct Performance {
+    // When navigation started, in ns
+    time_origin: u64,
+    priv mut entries: ~[PerformanceEntry],
+    observers: DVec<@PerformanceObserver>,
+    // Whether the observers have been queued a call since they were last
+    // called
+    priv mut delivery_queued: bool,
+    priv mut first_contentful_paint: bool,
+    // The largest contentful paint so far, until the user interacts
+    priv mut lcp_candidate: Option<PerformanceEntry>,
+    // Whether the candidate has been reported already
+    priv mut lcp_reported: bool,
+    priv mut lcp_final: bool,
+}
+
+pub fn Performance(time_origin: u64) -> Performance {
+    Performance {
+        time_origin: time_origin,
+        entries: ~[],
+        observers: DVec(),
+        delivery_queued: false,
+        first_contentful_paint: false,
+        lcp_candidate: None,
+        lcp_reported: false,
+        lcp_final: false,
+    }
+}
+
+impl Performance {
+    /// `time`, in ns, as ms since the time origin.
+    pure fn to_ms(time: u64) -> float {
+        if time < self.time_origin {
+            0.0
+        } else {
+            (time - self.time_origin) as float / 1000000.0
+        }
+    }
+
+    fn now() -> float {
+        self.to_ms(precise_time_ns())
+    }
+
+    /// Lists `observer` while it observes something, and forgets it once
+    /// it's disconnected.
+    fn update_observer(observer: @PerformanceObserver) {
+        let listed = self.observers.any(|o| core::box::ptr_eq(*o, observer));
+        if observer.is_registered() && !listed {
+            self.observers.push(observer);
+        } else if !observer.is_registered() && listed {
+            do self.observers.swap |observers| {
+                vec::filter(observers, |o| !core::box::ptr_eq(*o, observer))
+            }
+        }
+    }
+
+    /// Disconnects every observer, as when the page goes away.
+    fn disconnect_observers() {
+        for self.observers.each |observer| {
+            observer.disconnect();
+        }
+        self.observers.set(~[]);
+    }
+
+    /**
+    Adds `entry` to the timeline and buffers it for the observers of its
+    type. Returns true if that's the first entry for the observers since
+    they were last called, and so a call to them needs queueing.
+    */
+    fn record(entry: PerformanceEntry) -> bool {
+        let mut observed = false;
+        for self.observers.each |observer| {
+            if observer.queue(&entry) {
+                observed = true;
+            }
+        }
+        self.entries.push(move entry);
+        if observed && !self.delivery_queued {
+            self.delivery_queued = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Called when the observers are called back, so that the next entry
+    /// queues another call.
+    fn delivered() {
+        self.delivery_queued = false;
+    }
+
+    /**
+    The `resource` entry for the load of `url`, timed by `timing`, for a
+    page at `document`. Only the start, end and duration of a load the
+    page isn't allowed to see the timing of are given.
+    */
+    pure fn resource_entry(url: &Url, initiator_type: ~str, timing: &FetchTiming,
+                           document: &Url) -> PerformanceEntry {
+        let ms = |time: u64| if time == 0 { 0.0 } else { self.to_ms(time) };
+        let fetch_start = ms(timing.fetch_start);
+        let response_end = ms(timing.response_end);
+        let resource = if timing_allowed(url, document, &timing.timing_allow_origin) {
+            ResourceTiming {
+                initiator_type: move initiator_type,
+                redirect_start: ms(timing.redirect_start),
+                redirect_end: ms(timing.redirect_end),
+                fetch_start: fetch_start,
+                domain_lookup_start: ms(timing.domain_lookup_start),
+                domain_lookup_end: ms(timing.domain_lookup_end),
+                connect_start: ms(timing.connect_start),
+                connect_end: ms(timing.connect_end),
+                secure_connection_start: ms(timing.secure_connection_start),
+                request_start: ms(timing.request_start),
+                response_start: ms(timing.response_start),
+                response_end: response_end,
+                transfer_size: timing.transfer_size,
+                encoded_body_size: timing.encoded_body_size,
+                decoded_body_size: timing.decoded_body_size,
+            }
+        } else {
+            ResourceTiming {
+                initiator_type: move initiator_type,
+                redirect_start: 0.0,
+                redirect_end: 0.0,
+                fetch_start: fetch_start,
+                domain_lookup_start: 0.0,
+                domain_lookup_end: 0.0,
+                connect_start: 0.0,
+                connect_end: 0.0,
+                secure_connection_start: 0.0,
+                request_start: 0.0,
+                response_start: 0.0,
+                response_end: response_end,
+                transfer_size: 0,
+                encoded_body_size: 0,
+                decoded_body_size: 0,
+            }
+        };
+        // a load that was redirected starts with the first request
+        let start_time = if resource.redirect_start > 0.0 {
+            resource.redirect_start
+        } else {
+            fetch_start
+        };
+        let mut entry = PerformanceEntry(url_to_str(copy *url), ResourceEntry, start_time,
+                                         response_end - start_time);
+        entry.resource = Some(move resource);
+        move entry
+    }
+
+    /// Whether frames still need looking at for paint timing.
+    pure fn is_timing_paint() -> bool {
+        !self.lcp_final
+    }
+
+    /**
+    Notes a frame shown at `time`: whether it had any text or images, and
+    its largest image or text block, if any, with the area it covered.
+    Returns the `first-contentful-paint` entry if this is the first frame
+    with content.
+    */
+    fn painted(time: float, contentful: bool,
+               largest: Option<(Node, uint)>) -> Option<PerformanceEntry> {
+        if self.lcp_final {
+            return None;
+        }
+        match largest {
+            Some((element, size)) => {
+                let current = match self.lcp_candidate {
+                    Some(ref entry) => entry.largest.map_default(0, |l| l.size),
+                    None => 0
+                };
+                if size > current {
+                    let mut entry = PerformanceEntry(~"", LargestContentfulPaintEntry, time, 0.0);
+                    entry.largest = Some(LargestContentfulPaint { element: element, size: size });
+                    self.lcp_candidate = Some(move entry);
+                    self.lcp_reported = false;
+                }
+            }
+            None => ()
+        }
+        if contentful && !self.first_contentful_paint {
+            self.first_contentful_paint = true;
+            Some(PerformanceEntry(~"first-contentful-paint", PaintEntry, time, 0.0))
+        } else {
+            None
+        }
+    }
+
+    /// The largest contentful paint candidate, to be recorded, unless it
+    /// has been already. Called once the page has loaded.
+    fn report_largest_contentful_paint() -> Option<PerformanceEntry> {
+        if self.lcp_final || self.lcp_reported {
+            return None;
+        }
+        self.lcp_reported = true;
+        copy self.lcp_candidate
+    }
+
+    /// Called on the user's first input, or when the page goes away: the
+    /// largest contentful paint is settled, and returned to be recorded
+    /// unless it has been already.
+    fn finalize_largest_contentful_paint() -> Option<PerformanceEntry> {
+        let candidate = self.report_largest_contentful_paint();
+        self.lcp_final = true;
+        self.lcp_candidate = None;
+        move candidate
+    }
+
+    /// The start of the latest mark named `name`.
+    pure fn mark_time(name: &str) -> Option<float> {
+        let mut time = None;
+        for self.entries.each |entry| {
+            if entry.entry_type == MarkEntry && entry.name == name.to_str() {
+                time = Some(entry.start_time);
+            }
+        }
+        time
+    }
+
+    /**
+    The measure named `name` between two marks, from the time origin or to
+    now if either isn't given. Err names a mark that doesn't exist.
+    */
+    fn measure(name: ~str, start_mark: Option<~str>,
+               end_mark: Option<~str>) -> Result<PerformanceEntry, ~str> {
+        let start = match start_mark {
+            Some(ref mark) => match self.mark_time(*mark) {
+                Some(time) => time,
+                None => return Err(copy *mark)
+            },
+            None => 0.0
+        };
+        let end = match end_mark {
+            Some(ref mark) => match self.mark_time(*mark) {
+                Some(time) => time,
+                None => return Err(copy *mark)
+            },
+            None => self.now()
+        };
+        Ok(PerformanceEntry(move name, MeasureEntry, start, end - start))
+    }
+
+    /// The timeline, in the order entries were recorded, optionally only
+    /// those of a type or name.
+    fn get_entries(entry_type: Option<EntryType>, name: Option<~str>) -> ~[PerformanceEntry] {
+        do self.entries.filter |entry| {
+            entry_type.map_default(true, |t| entry.entry_type == *t) &&
+                name.map_default(true, |n| entry.name == *n)
+        }
+    }
+
+    /// Forgets the entries of `entry_type`, or only those named `name`.
+    fn clear(entry_type: EntryType, name: Option<~str>) {
+        self.entries = do self.entries.filter |entry| {
+            entry.entry_type != entry_type || !name.map_default(true, |n| entry.name == *n)
+        };
+    }
+}
+
+#[cfg(test)]
+mod performance_observer_tests {
+    use dom::element::{ElementData, HTMLDivElement};
+    use dom::node::NodeScope;
+    use js::JSVAL_NULL;
+
+    fn entry(name: &str, entry_type: EntryType, start_time: float) -> PerformanceEntry {
+        PerformanceEntry(name.to_str(), entry_type, start_time, 0.0)
+    }
+
+    #[test]
+    fn test_observers_get_their_types() {
+        let performance = Performance(0);
+        let marks = @PerformanceObserver(ptr::null(), JSVAL_NULL, JSVAL_NULL);
+        marks.observe(~[MarkEntry]);
+        let resources = @PerformanceObserver(ptr::null(), JSVAL_NULL, JSVAL_NULL);
+        resources.observe(~[ResourceEntry, PaintEntry]);
+        performance.update_observer(marks);
+        performance.update_observer(resources);
+        assert performance.observers.len() == 2;
+
+        // the first observed entry queues a call; later ones join it
+        assert performance.record(entry("a", MarkEntry, 1.0));
+        assert !performance.record(entry("b", MarkEntry, 2.0));
+        assert !performance.record(entry("c", LongTaskEntry, 3.0));
+        assert !performance.record(entry("d.js", ResourceEntry, 4.0));
+
+        assert marks.take_records().map(|e| copy e.name) == ~[~"a", ~"b"];
+        assert resources.take_records().map(|e| copy e.name) == ~[~"d.js"];
+        assert marks.take_records().is_empty();
+
+        // once called back, the next entry queues another call
+        performance.delivered();
+        assert performance.record(entry("e", MarkEntry, 5.0));
+        marks.disconnect();
+        assert marks.take_records().is_empty();
+        assert !performance.record(entry("f", MarkEntry, 6.0));
+
+        // disconnected observers are unrooted and dropped from the list
+        assert marks.roots.len() == 0;
+        assert resources.roots.len() == 2;
+        performance.update_observer(marks);
+        assert performance.observers.len() == 1;
+        performance.disconnect_observers();
+        assert resources.roots.len() == 0;
+        assert performance.observers.len() == 0;
+    }
+
+    #[test]
+    fn test_measure() {
+        let performance = Performance(0);
+        performance.record(entry("start", MarkEntry, 10.0));
+        performance.record(entry("end", MarkEntry, 25.0));
+        performance.record(entry("start", MarkEntry, 15.0));
+
+        // the latest mark of a name counts
+        let measure = performance.measure(~"m", Some(~"start"), Some(~"end")).get();
+        assert measure.start_time == 15.0 && measure.duration == 10.0;
+        assert measure.entry_type == MeasureEntry;
+        match performance.measure(~"m", Some(~"nope"), None) {
+            Err(mark) => assert mark == ~"nope",
+            Ok(_) => fail
+        }
+    }
+
+    #[test]
+    fn test_entries_and_clear() {
+        let performance = Performance(0);
+        performance.record(entry("a", MarkEntry, 1.0));
+        performance.record(entry("a", MeasureEntry, 2.0));
+        performance.record(entry("b", MarkEntry, 3.0));
+
+        assert performance.get_entries(None, None).len() == 3;
+        assert performance.get_entries(Some(MarkEntry), None).len() == 2;
+        assert performance.get_entries(None, Some(~"a")).len() == 2;
+        performance.clear(MarkEntry, Some(~"a"));
+        assert performance.get_entries(Some(MarkEntry), None).map(|e| copy e.name) == ~[~"b"];
+        performance.clear(MarkEntry, None);
+        assert performance.get_entries(None, None).len() == 1;
+    }
+
+    #[test]
+    fn test_paint_timing() {
+        let scope = NodeScope();
+        let small = scope.new_node(dom::node::Element(ElementData(~"p", ~HTMLDivElement)));
+        let big = scope.new_node(dom::node::Element(ElementData(~"div", ~HTMLDivElement)));
+        let performance = Performance(0);
+
+        // an empty frame isn't contentful
+        assert performance.painted(1.0, false, None).is_none();
+        let fcp = performance.painted(2.0, true, Some((small, 100))).get();
+        assert fcp.name == ~"first-contentful-paint" && fcp.start_time == 2.0;
+        assert fcp.entry_type == PaintEntry;
+        assert performance.painted(3.0, true, Some((big, 500))).is_none();
+        // a smaller element later doesn't replace the candidate
+        performance.painted(4.0, true, Some((small, 200)));
+
+        let lcp = performance.finalize_largest_contentful_paint().get();
+        assert lcp.entry_type == LargestContentfulPaintEntry && lcp.start_time == 3.0;
+        assert lcp.largest.get().element == big && lcp.largest.get().size == 500;
+        assert !performance.is_timing_paint();
+        performance.painted(5.0, true, Some((big, 1000)));
+        assert performance.finalize_largest_contentful_paint().is_none();
+    }
+
+    #[test]
+    fn test_lcp_reported_on_load() {
+        let scope = NodeScope();
+        let small = scope.new_node(dom::node::Element(ElementData(~"p", ~HTMLDivElement)));
+        let big = scope.new_node(dom::node::Element(ElementData(~"div", ~HTMLDivElement)));
+        let performance = Performance(0);
+
+        performance.painted(1.0, true, Some((small, 100)));
+        assert performance.report_largest_contentful_paint().get().start_time == 1.0;
+        // nothing new to settle
+        assert performance.report_largest_contentful_paint().is_none();
+        assert performance.is_timing_paint();
+
+        // a bigger element after load is reported when the page goes away
+        performance.painted(2.0, true, Some((big, 500)));
+        let lcp = performance.finalize_largest_contentful_paint().get();
+        assert lcp.start_time == 2.0 && lcp.largest.get().element == big;
+        assert !performance.is_timing_paint();
+    }
+
+    #[test]
+    fn test_resource_entry() {
+        let url = |s: &str| std::net::url::from_str(s).get();
+        let page = url("http://example.com/index.html");
+        let performance = Performance(1000000);
+        let mut timing = FetchTiming(3000000);
+        timing.response_start = 5000000;
+        timing.response_end = 9000000;
+        timing.encoded_body_size = 100;
+        timing.decoded_body_size = 100;
+        timing.transfer_size = 400;
+
+        let entry = performance.resource_entry(&url("http://example.com/a.js"), ~"script",
+                                               &timing, &page);
+        assert entry.name == ~"http://example.com/a.js" && entry.entry_type == ResourceEntry;
+        assert entry.start_time == 2.0 && entry.duration == 6.0;
+        let resource = entry.resource.get();
+        assert resource.initiator_type == ~"script";
+        assert resource.response_start == 4.0 && resource.request_start == 2.0;
+        assert resource.redirect_start == 0.0 && resource.transfer_size == 400;
+
+        // another origin only shows when it started and ended
+        let other = url("http://cdn.example.net/b.png");
+        let hidden = performance.resource_entry(&other, ~"img", &timing, &page).resource.get();
+        assert hidden.fetch_start == 2.0 && hidden.response_end == 8.0;
+        assert hidden.response_start == 0.0 && hidden.encoded_body_size == 0;
+
+        timing.timing_allow_origin = Some(~"http://other.org, http://example.com");
+        let allowed = performance.resource_entry(&other, ~"img", &timing, &page).resource.get();
+        assert allowed.response_start == 4.0 && allowed.encoded_body_size == 100;
+        assert timing_allowed(&other, &page, &Some(~"*"));
+        assert !timing_allowed(&other, &page, &Some(~"http://example.com:8000"));
+    }
+}
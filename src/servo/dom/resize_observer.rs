@@ -0,0 +1,222 @@
+/*!
+`ResizeObserver`: tells scripts when the boxes of the elements they observe
+change size. After each layout the content task gathers the entries for the
+observations whose size changed and calls each observer's callback.
+
+An observer keeps its callback and the objects of the elements it observes
+rooted while it's observing anything, and the window only keeps the
+observers that are.
+*/
+
+use dom::node::Node;
+use dom::bindings::rooting::RootedValues;
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use js::jsapi::{JSContext, JSVal};
+
+/// Which box an observation watches.
+pub enum ResizeObserverBox {
+    ContentBox,
+    BorderBox,
+    DevicePixelContentBox
+}
+
+impl ResizeObserverBox {
+    static fn from_str(s: &str) -> Option<ResizeObserverBox> {
+        match s {
+            "content-box" => Some(ContentBox),
+            "border-box" => Some(BorderBox),
+            "device-pixel-content-box" => Some(DevicePixelContentBox),
+            _ => None
+        }
+    }
+}
+
+/// The sizes of an element's boxes, in px.
+pub struct BoxSizes {
+    // Relative to the border box
+    //TODO: relative to the padding box, once layout knows about it
+    content_rect: Rect<int>,
+    content_box: Size2D<int>,
+    border_box: Size2D<int>,
+    device_pixel_content_box: Size2D<int>,
+}
+
+//TODO: scale device pixel sizes once there's a device pixel ratio
+pub fn BoxSizes(content_box: Rect<int>, border_box: Rect<int>) -> BoxSizes {
+    BoxSizes {
+        content_rect: Rect(Point2D(content_box.origin.x - border_box.origin.x,
+                                   content_box.origin.y - border_box.origin.y),
+                           content_box.size),
+        content_box: content_box.size,
+        border_box: border_box.size,
+        device_pixel_content_box: content_box.size,
+    }
+}
+
+/// The sizes of an element that isn't rendered.
+pub fn empty_box_sizes() -> BoxSizes {
+    let empty = Rect(Point2D(0, 0), Size2D(0, 0));
+    BoxSizes(empty, empty)
+}
+
+impl BoxSizes {
+    fn size_of(&self, observed_box: ResizeObserverBox) -> Size2D<int> {
+        match observed_box {
+            ContentBox => self.content_box,
+            BorderBox => self.border_box,
+            DevicePixelContentBox => self.device_pixel_content_box
+        }
+    }
+}
+
+pub struct ResizeObserverEntry {
+    // The JS object for the element
+    target: JSVal,
+    sizes: BoxSizes,
+}
+
+struct Observation {
+    target: Node,
+    // The key of the element's JS object in the observer's roots
+    target_key: uint,
+    observed_box: ResizeObserverBox,
+    // Starts at 0x0, so that rendered elements are reported once observed
+    last_reported_size: Size2D<int>,
+}
+
+pub struct ResizeObserver {
+    callback: JSVal,
+    priv mut observations: ~[Observation],
+    priv roots: RootedValues,
+    // The key of the callback in `roots`, while there are observations
+    priv mut callback_key: Option<uint>,
+}
+
+pub fn ResizeObserver(cx: *JSContext, callback: JSVal) -> ResizeObserver {
+    ResizeObserver {
+        callback: callback,
+        observations: ~[],
+        roots: RootedValues(cx),
+        callback_key: None,
+    }
+}
+
+impl ResizeObserver {
+    /// Starts observing `target`, or changes the box observed if it's
+    /// already being observed.
+    fn observe(&self, target: Node, target_obj: JSVal, observed_box: ResizeObserverBox) {
+        self.unobserve(target);
+        if self.callback_key.is_none() {
+            self.callback_key = Some(self.roots.add(self.callback));
+        }
+        self.observations.push(Observation {
+            target: target,
+            target_key: self.roots.add(target_obj),
+            observed_box: observed_box,
+            last_reported_size: Size2D(0, 0),
+        });
+    }
+
+    fn unobserve(&self, target: Node) {
+        let (removed, kept) = vec::partition(core::util::replace(&mut self.observations, ~[]),
+                                             |o| o.target == target);
+        for removed.each |observation| {
+            self.roots.remove(observation.target_key);
+        }
+        self.observations = move kept;
+        if self.observations.is_empty() {
+            self.disconnect();
+        }
+    }
+
+    /// Stops observing everything, and unroots the callback.
+    fn disconnect(&self) {
+        self.observations = ~[];
+        self.roots.clear();
+        self.callback_key = None;
+    }
+
+    fn is_observing(&self) -> bool {
+        !self.observations.is_empty()
+    }
+
+    /**
+    The entries for the observed elements whose size changed since they
+    were last reported. `sizes_of` gives the current sizes from layout.
+    */
+    fn gather_entries(&self, sizes_of: fn(Node) -> BoxSizes) -> ~[ResizeObserverEntry] {
+        let mut entries = ~[];
+        for uint::range(0, self.observations.len()) |i| {
+            let observation = self.observations[i];
+            let sizes = sizes_of(observation.target);
+            let size = sizes.size_of(observation.observed_box);
+            if size != observation.last_reported_size {
+                self.observations[i].last_reported_size = size;
+                entries.push(ResizeObserverEntry {
+                    target: self.roots.get(observation.target_key),
+                    sizes: move sizes,
+                });
+            }
+        }
+        move entries
+    }
+}
+
+#[cfg(test)]
+mod resize_observer_tests {
+    use dom::element::{ElementData, HTMLDivElement};
+    use dom::node::NodeScope;
+    use js::JSVAL_NULL;
+
+    fn sizes(width: int, height: int) -> BoxSizes {
+        let rect = Rect(Point2D(0, 0), Size2D(width, height));
+        BoxSizes(rect, rect)
+    }
+
+    #[test]
+    fn test_entries_only_for_changes() {
+        let scope = NodeScope();
+        let node = scope.new_node(dom::node::Element(ElementData(~"div", ~HTMLDivElement)));
+        let observer = ResizeObserver(ptr::null(), JSVAL_NULL);
+        observer.observe(node, JSVAL_NULL, ContentBox);
+
+        assert observer.gather_entries(|_n| sizes(10, 10)).len() == 1;
+        assert observer.gather_entries(|_n| sizes(10, 10)).is_empty();
+        assert observer.gather_entries(|_n| sizes(20, 10)).len() == 1;
+
+        // An element that was never rendered isn't reported
+        let other = scope.new_node(dom::node::Element(ElementData(~"div", ~HTMLDivElement)));
+        observer.observe(other, JSVAL_NULL, BorderBox);
+        assert observer.gather_entries(|n| if n == node { sizes(20, 10) }
+                                           else { empty_box_sizes() }).is_empty();
+    }
+
+    #[test]
+    fn test_unobserve_and_disconnect() {
+        let scope = NodeScope();
+        let a = scope.new_node(dom::node::Element(ElementData(~"div", ~HTMLDivElement)));
+        let b = scope.new_node(dom::node::Element(ElementData(~"div", ~HTMLDivElement)));
+        let observer = ResizeObserver(ptr::null(), JSVAL_NULL);
+        observer.observe(a, JSVAL_NULL, ContentBox);
+        observer.observe(b, JSVAL_NULL, ContentBox);
+        observer.observe(b, JSVAL_NULL, BorderBox);
+        // The callback, and one object for each element
+        assert observer.roots.len() == 3;
+
+        observer.unobserve(a);
+        assert observer.roots.len() == 2;
+        assert observer.gather_entries(|_n| sizes(5, 5)).len() == 1;
+
+        // Unobserving the last element lets go of the callback too
+        observer.unobserve(b);
+        assert !observer.is_observing();
+        assert observer.roots.len() == 0;
+
+        observer.observe(a, JSVAL_NULL, ContentBox);
+        observer.disconnect();
+        assert !observer.is_observing();
+        assert observer.roots.len() == 0;
+    }
+}
@@ -0,0 +1,147 @@
+/*!
+Scroll offsets: how far the viewport and each scroll container have been
+scrolled, in px.
+*/
+
+use dom::element::ElementData;
+use dom::node::{Node, NodeScope, Element};
+use dvec::DVec;
+use geom::point::Point2D;
+use geom::size::Size2D;
+use util::tree;
+
+pub struct ScrollState {
+    mut viewport: Point2D<int>,
+    priv elements: DVec<(Node, Point2D<int>)>,
+}
+
+pub fn ScrollState() -> ScrollState {
+    ScrollState {
+        viewport: Point2D(0, 0),
+        elements: DVec(),
+    }
+}
+
+impl ScrollState {
+    fn element_offset(&self, node: Node) -> Point2D<int> {
+        for self.elements.each |entry| {
+            let (n, offset) = *entry;
+            if n == node {
+                return offset;
+            }
+        }
+        Point2D(0, 0)
+    }
+
+    /// Scrolls `node` to `offset`, returning whether it moved. Offsets can't
+    /// be negative.
+    //TODO: clamp to the scrollable overflow too, once layout computes it
+    fn set_element_offset(&self, node: Node, offset: Point2D<int>) -> bool {
+        let offset = Point2D(int::max(offset.x, 0), int::max(offset.y, 0));
+        if offset == self.element_offset(node) {
+            return false;
+        }
+        match self.elements.position(|entry| { let (n, _) = *entry; n == node }) {
+            Some(i) => self.elements.set_elt(i, (node, offset)),
+            None => self.elements.push((node, offset))
+        }
+        true
+    }
+
+    /// The offset of each element that has been scrolled.
+    fn element_offsets(&self) -> ~[(Node, Point2D<int>)] {
+        self.elements.get()
+    }
+}
+
+/// Clamps a scroll offset so that a `viewport` sized view stays within
+/// content of `content_size`.
+pub pure fn clamp_offset(offset: Point2D<int>, content_size: Size2D<int>,
+                         viewport: Size2D<int>) -> Point2D<int> {
+    let max_x = int::max(content_size.width - viewport.width, 0);
+    let max_y = int::max(content_size.height - viewport.height, 0);
+    Point2D(int::min(int::max(offset.x, 0), max_x),
+            int::min(int::max(offset.y, 0), max_y))
+}
+
+/**
+Whether `node` scrolls its contents, having `overflow: auto` or
+`overflow: scroll`.
+
+TODO: the style system doesn't know about `overflow` yet, so this only sees
+it in the element's `style` attribute.
+*/
+pub fn is_scroll_container(scope: &NodeScope, node: Node) -> bool {
+    do scope.read(&node) |n| {
+        match n.kind {
+            ~Element(ref e) => match e.get_style_property("overflow") {
+                Some(~"auto") | Some(~"scroll") => true,
+                _ => false
+            },
+            _ => false
+        }
+    }
+}
+
+/// The scroll container that a scroll gesture over `node` scrolls: the
+/// node itself or its nearest ancestor that scrolls. None means the
+/// viewport scrolls.
+pub fn scroll_container_for(scope: &NodeScope, node: Node) -> Option<Node> {
+    let mut current = Some(node);
+    while current.is_some() {
+        let node = current.get();
+        if is_scroll_container(scope, node) {
+            return Some(node);
+        }
+        current = tree::parent(scope, &node);
+    }
+    None
+}
+
+#[cfg(test)]
+mod scroll_tests {
+    use dom::element::{Attr, HTMLDivElement, HTMLSpanElement, HTMLParagraphElement};
+    use dom::node::NodeScopeExtensions;
+
+    #[test]
+    fn test_clamp_offset() {
+        let content = Size2D(800, 2000);
+        let viewport = Size2D(800, 600);
+        assert clamp_offset(Point2D(10, 500), content, viewport) == Point2D(0, 500);
+        assert clamp_offset(Point2D(0, 5000), content, viewport) == Point2D(0, 1400);
+        assert clamp_offset(Point2D(0, -20), content, viewport) == Point2D(0, 0);
+        // Content smaller than the viewport can't scroll
+        assert clamp_offset(Point2D(0, 50), Size2D(100, 100), viewport) == Point2D(0, 0);
+    }
+
+    #[test]
+    fn test_element_offsets() {
+        let scope = NodeScope();
+        let div = scope.new_node(Element(ElementData(~"div", ~HTMLDivElement)));
+        let state = ScrollState();
+        assert state.element_offset(div) == Point2D(0, 0);
+        assert state.set_element_offset(div, Point2D(0, 40));
+        assert !state.set_element_offset(div, Point2D(0, 40));
+        assert state.set_element_offset(div, Point2D(-5, 10));
+        assert state.element_offset(div) == Point2D(0, 10);
+    }
+
+    #[test]
+    fn test_scroll_container() {
+        let scope = NodeScope();
+        let data = ElementData(~"div", ~HTMLDivElement);
+        data.attrs.push(~Attr(~"style", ~"height: 10px; OVERFLOW : Auto"));
+        let scroller = scope.new_node(Element(move data));
+        let plain = scope.new_node(Element(ElementData(~"div", ~HTMLDivElement)));
+        assert is_scroll_container(&scope, scroller);
+        assert !is_scroll_container(&scope, plain);
+
+        let inner = scope.new_node(Element(ElementData(~"span", ~HTMLSpanElement)));
+        scope.add_child(scroller, plain);
+        scope.add_child(plain, inner);
+        assert scroll_container_for(&scope, inner) == Some(scroller);
+        assert scroll_container_for(&scope, scroller) == Some(scroller);
+        assert scroll_container_for(&scope, scope.new_node(Element(
+            ElementData(~"p", ~HTMLParagraphElement)))).is_none();
+    }
+}
@@ -0,0 +1,381 @@
+/*!
+Service workers: scripts that sit between a page and the network. A page
+registers one with `navigator.serviceWorker.register`, for a scope, and
+pages whose URLs start with that scope are controlled by it from their
+next load. The worker's script runs on a task of its own, in a JS runtime
+of its own. It gets `install` and `activate` events when it's registered,
+and then a `fetch` event for each load a controlled page makes. If its
+handler calls `respondWith`, the page gets that response; otherwise the
+load goes to the network.
+
+Registrations last as long as the content task that made them, and a
+worker is never updated or stopped while it has a registration.
+*/
+
+use comm::{Chan, Port};
+use task::{spawn, spawn_listener, task, SingleThreaded};
+use std::future;
+use std::future::Future;
+use std::net::url::Url;
+use util::url::url_to_str;
+use resource::resource_task;
+use resource::resource_task::{ResourceTask, ControlMsg, Load, LoadFor, ContentType, Header, Payload, Done,
+                              timed};
+use dom::cache_storage::CachedResponse;
+use dom::bindings::service_worker::WorkerGlobal;
+
+pub enum WorkerState {
+    Activated,
+    // It failed to install, so it will never run
+    Redundant
+}
+
+impl WorkerState {
+    /// The state as `ServiceWorker.state` gives it.
+    pure fn name(&self) -> &static/str {
+        match *self {
+            Activated => "activated",
+            Redundant => "redundant"
+        }
+    }
+}
+
+impl WorkerState : cmp::Eq {
+    pure fn eq(&self, other: &WorkerState) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &WorkerState) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// What a worker's fetch handler made of a load.
+pub enum FetchOutcome {
+    // It didn't call respondWith, so the load goes to the network
+    FallThrough,
+    Respond(CachedResponse),
+    // respondWith was given something other than a Response, or a promise
+    // that wasn't fulfilled with one
+    NetworkError
+}
+
+pub enum WorkerMsg {
+    /// Dispatches `install`, then `activate` if it installed, sending back
+    /// the state the worker ends up in
+    Install(Chan<WorkerState>),
+    /// A load made by a page the worker controls. The reply is a pipe, so
+    /// that it's closed rather than left waiting if the worker has died
+    Fetch(Url, pipes::Chan<FetchOutcome>),
+    Stop
+}
+
+/// Handle to a service worker's task
+pub type ServiceWorker = Chan<WorkerMsg>;
+
+/// Runs `source`, the script at `script_url`, as a service worker on a task
+/// of its own.
+pub fn ServiceWorker(script_url: Url, source: ~str) -> ServiceWorker {
+    // SpiderMonkey runtimes have to stay on the thread they were made on
+    do task().sched_mode(SingleThreaded).spawn_listener |port: Port<WorkerMsg>,
+                                                         move script_url, move source| {
+        let global = WorkerGlobal(&script_url, source);
+        loop {
+            match port.recv() {
+                Install(reply) => reply.send(global.install()),
+                Fetch(move url, reply) => reply.send(global.fetch(move url)),
+                Stop => break
+            }
+        }
+        global.close();
+    }
+}
+
+/**
+Fetches the script at `script_url` and runs it as a service worker, until
+it's installed and activated. Errs if the script couldn't be loaded, or the
+worker failed to install.
+*/
+pub fn install(script_url: Url, resource_task: ResourceTask)
+    -> Future<Result<ServiceWorker, ~str>> {
+    do future::spawn |move script_url, move resource_task| {
+        match fetch_script(copy script_url, resource_task) {
+            Ok(move source) => {
+                let worker = ServiceWorker(copy script_url, move source);
+                let state = Port();
+                worker.send(Install(state.chan()));
+                if state.recv() == Activated {
+                    Ok(worker)
+                } else {
+                    worker.send(Stop);
+                    Err(fmt!("The service worker at %s failed to install",
+                             url_to_str(copy script_url)))
+                }
+            }
+            Err(move e) => Err(move e)
+        }
+    }
+}
+
+fn fetch_script(url: Url, resource_task: ResourceTask) -> Result<~str, ~str> {
+    let response_port = Port();
+    resource_task.send(Load(copy url, response_port.chan()));
+    let mut source = ~[];
+    loop {
+        match response_port.recv() {
+            Payload(data) => source += data,
+            Done(Ok(*)) => break,
+            Done(Err(*)) => return Err(fmt!("Couldn't load %s", url_to_str(copy url))),
+            _ => ()
+        }
+    }
+    if str::is_utf8(source) {
+        Ok(str::from_bytes(source))
+    } else {
+        Err(fmt!("%s isn't UTF-8", url_to_str(copy url)))
+    }
+}
+
+/**
+A resource task for the loads of a page `worker` controls. Each load is
+offered to the worker's fetch handler first, and only goes on to
+`resource_task` if the handler leaves it, or the worker has died. Other
+messages are passed straight on. Sending it `Exit` stops it, but not `resource_task`.
+*/
+pub fn intercept(resource_task: ResourceTask, worker: ServiceWorker) -> ResourceTask {
+    do spawn_listener |from_page: Port<ControlMsg>| {
+        loop {
+            match from_page.recv() {
+                Load(move url, progress_chan) => {
+                    // The worker handles its fetches one at a time, but
+                    // the network can take them all at once
+                    do spawn |move url| {
+                        respond(copy url, move url, progress_chan, resource_task, worker);
+                    }
+                }
+                LoadFor(move url, move top_level_url, progress_chan) => {
+                    do spawn |move url, move top_level_url| {
+                        respond(move url, move top_level_url, progress_chan, resource_task,
+                                worker);
+                    }
+                }
+                resource_task::Exit => break,
+                move msg => resource_task.send(move msg)
+            }
+        }
+    }
+}
+
+fn respond(url: Url, top_level_url: Url, progress_chan: Chan<resource_task::ProgressMsg>,
+           resource_task: ResourceTask, worker: ServiceWorker) {
+    let (outcome_chan, outcome_port) = pipes::stream();
+    worker.send(Fetch(copy url, move outcome_chan));
+    // None if the worker's task is gone, and the reply channel with it
+    match outcome_port.try_recv() {
+        Some(FallThrough) | None => {
+            resource_task.send(LoadFor(move url, move top_level_url, progress_chan))
+        }
+        Some(Respond(move response)) => {
+            let CachedResponse { headers: move headers, body: move body, _ } = move response;
+            let progress_chan = timed(progress_chan);
+            for headers.each |header| {
+                let (ref name, ref value) = *header;
+                if *name == ~"content-type" {
+                    progress_chan.send(ContentType(copy *value));
+                }
+                progress_chan.send(Header(copy *name, copy *value));
+            }
+            progress_chan.send(Payload(move body));
+            progress_chan.send(Done(Ok(())));
+        }
+        Some(NetworkError) => progress_chan.send(Done(Err(())))
+    }
+}
+
+// A URL without its query and fragment
+fn without_query(url: &str) -> ~str {
+    match str::find(url, |c| c == '?' || c == '#') {
+        Some(i) => url.slice(0, i),
+        None => url.to_str()
+    }
+}
+
+/// The scope a worker gets if it's registered without one: the directory
+/// its script is in.
+pub fn default_scope(script_url: &str) -> ~str {
+    let url = without_query(script_url);
+    match str::rfind_char(url, '/') {
+        Some(i) => url.slice(0, i + 1),
+        None => move url
+    }
+}
+
+/// Whether a worker whose script is at `script_url` may control `scope`.
+/// It can't reach outside its script's directory.
+pub fn scope_allowed(scope: &str, script_url: &str) -> bool {
+    str::starts_with(scope, default_scope(script_url))
+}
+
+pub struct Registration {
+    scope: ~str,
+    script_url: ~str,
+    worker: ServiceWorker,
+}
+
+/// The service workers registered by the pages of a content task.
+pub struct ServiceWorkerRegistry {
+    priv mut registrations: ~[@Registration],
+}
+
+pub fn ServiceWorkerRegistry() -> ServiceWorkerRegistry {
+    ServiceWorkerRegistry { registrations: ~[] }
+}
+
+impl ServiceWorkerRegistry {
+    /// Adds `registration`, stopping the worker of any there was for its
+    /// scope.
+    fn add(&self, registration: @Registration) {
+        for self.get(registration.scope).each |old| {
+            old.worker.send(Stop);
+        }
+        self.registrations = do self.registrations.filtered |r| {
+            r.scope != registration.scope
+        };
+        self.registrations.push(registration);
+    }
+
+    fn get(&self, scope: &str) -> Option<@Registration> {
+        self.registrations.find(|r| str::eq_slice(r.scope, scope))
+    }
+
+    /// The registration that controls a page at `url`: the one with the
+    /// longest scope the URL starts with.
+    fn controller_for(&self, url: &str) -> Option<@Registration> {
+        let mut controller = None;
+        for self.registrations.each |registration| {
+            if !str::starts_with(url, registration.scope) {
+                loop;
+            }
+            let longer = match controller {
+                Some(current) => registration.scope.len() > current.scope.len(),
+                None => true
+            };
+            if longer {
+                controller = Some(*registration);
+            }
+        }
+        controller
+    }
+
+    /// Stops every worker.
+    fn stop_all(&self) {
+        for self.registrations.each |registration| {
+            registration.worker.send(Stop);
+        }
+        self.registrations = ~[];
+    }
+}
+
+#[cfg(test)]
+mod service_worker_tests {
+    use std::net::url;
+    use resource::resource_task::{create_resource_task_with_loaders, Exit};
+
+    fn registration(scope: &str, worker: ServiceWorker) -> @Registration {
+        @Registration {
+            scope: scope.to_str(),
+            script_url: scope.to_str() + "sw.js",
+            worker: worker
+        }
+    }
+
+    #[test]
+    fn test_scopes() {
+        assert default_scope("http://example.com/app/sw.js?v=2") == ~"http://example.com/app/";
+        assert default_scope("http://example.com/sw.js") == ~"http://example.com/";
+        assert scope_allowed("http://example.com/app/page/", "http://example.com/app/sw.js");
+        assert !scope_allowed("http://example.com/", "http://example.com/app/sw.js");
+    }
+
+    #[test]
+    fn test_controller() {
+        let port: Port<WorkerMsg> = Port();
+        let registry = ServiceWorkerRegistry();
+        registry.add(registration("http://example.com/", port.chan()));
+        registry.add(registration("http://example.com/app/", port.chan()));
+        let controller = |url| registry.controller_for(url).map(|r| copy r.scope);
+        assert controller("http://example.com/app/index.html") == Some(~"http://example.com/app/");
+        assert controller("http://example.com/other.html") == Some(~"http://example.com/");
+        assert controller("http://example.org/").is_none();
+
+        // Registering a scope again replaces its worker, which is stopped
+        registry.add(registration("http://example.com/app/", port.chan()));
+        match port.recv() {
+            Stop => (),
+            _ => fail
+        }
+        registry.stop_all();
+        assert registry.controller_for("http://example.com/").is_none();
+    }
+
+    #[test]
+    #[allow(non_implicitly_copyable_typarams)]
+    fn test_intercept() {
+        // A worker that answers for /cached and leaves everything else
+        let worker = do spawn_listener |port: Port<WorkerMsg>| {
+            loop {
+                match port.recv() {
+                    Fetch(move url, reply) => {
+                        if url.path == ~"/cached" {
+                            reply.send(Respond(CachedResponse {
+                                status: 200,
+                                headers: ~[(~"content-type", ~"text/plain")],
+                                body: str::to_bytes("from the worker")
+                            }));
+                        } else {
+                            reply.send(FallThrough);
+                        }
+                    }
+                    Install(reply) => reply.send(Activated),
+                    Stop => break
+                }
+            }
+        };
+        let loader_factory = fn~(_url: Url, _headers: ~[(~str, ~str)],
+                                 progress: Chan<resource_task::ProgressMsg>) {
+            progress.send(Payload(str::to_bytes("from the network")));
+            progress.send(Done(Ok(())));
+        };
+        let network = create_resource_task_with_loaders(~[(~"http", move loader_factory)]);
+        let loads = intercept(network, worker);
+
+        let body = |url: ~str| {
+            let progress = Port();
+            loads.send(Load(url::from_str(url).get(), progress.chan()));
+            let mut body = ~[];
+            let mut content_type = None;
+            loop {
+                match progress.recv() {
+                    ContentType(move t) => content_type = Some(move t),
+                    Payload(data) => body += data,
+                    Done(result) => {
+                        assert result.is_ok();
+                        break;
+                    }
+                    _ => ()
+                }
+            }
+            (str::from_bytes(body), content_type)
+        };
+        assert body(~"http://example.com/cached") ==
+            (~"from the worker", Some(~"text/plain"));
+        assert body(~"http://example.com/other") == (~"from the network", None);
+
+        // Once the worker has stopped, loads go to the network rather than
+        // waiting on it
+        worker.send(Stop);
+        assert body(~"http://example.com/cached") == (~"from the network", None);
+
+        loads.send(Exit);
+        network.send(Exit);
+    }
+}
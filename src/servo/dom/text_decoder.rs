@@ -0,0 +1,330 @@
+/*!
+`TextDecoder` and `TextEncoder`, which turn bytes into text and back. The
+decoder knows UTF-8, UTF-16LE, UTF-16BE and ISO-8859-1, and follows the
+Encoding spec's decoders: a malformed sequence becomes U+FFFD, or fails the
+whole decode if the decoder is `fatal`. The encoder only writes UTF-8, as
+in the spec.
+
+Text from JS comes as UTF-16 code units, which can hold lone surrogates
+that Rust strings can't; those become U+FFFD on the way in.
+*/
+
+const REPLACEMENT: uint = 0xfffd;
+
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding : cmp::Eq {
+    pure fn eq(&self, other: &Encoding) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &Encoding) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+impl Encoding {
+    /// The name `TextDecoder.encoding` gives.
+    pure fn name(&self) -> &static/str {
+        match *self {
+            Utf8 => "utf-8",
+            Utf16Le => "utf-16le",
+            Utf16Be => "utf-16be",
+            Latin1 => "iso-8859-1"
+        }
+    }
+}
+
+/// The encoding a label names, ignoring case and surrounding whitespace.
+pub fn encoding_for_label(label: &str) -> Option<Encoding> {
+    match str::to_lower(str::trim(label)) {
+        ~"utf-8" | ~"utf8" | ~"unicode-1-1-utf-8" | ~"unicode11utf8" | ~"unicode20utf8" |
+        ~"x-unicode20utf8" => Some(Utf8),
+        ~"utf-16le" | ~"utf-16" | ~"unicode" | ~"ucs-2" | ~"csunicode" | ~"iso-10646-ucs-2" |
+        ~"unicodefeff" => Some(Utf16Le),
+        ~"utf-16be" | ~"unicodefffe" => Some(Utf16Be),
+        ~"iso-8859-1" | ~"iso8859-1" | ~"iso_8859-1" | ~"latin1" | ~"l1" | ~"cp819" |
+        ~"ibm819" | ~"csisolatin1" | ~"iso-ir-100" | ~"ascii" | ~"us-ascii" => Some(Latin1),
+        _ => None
+    }
+}
+
+/// What a fatal decoder throws on malformed input.
+pub struct TypeError {
+    message: &static/str,
+}
+
+pub struct TextDecoder {
+    encoding: Encoding,
+    // Fail on malformed input, rather than replacing it
+    fatal: bool,
+    // Keep a byte order mark at the start, rather than skipping it
+    ignore_bom: bool,
+}
+
+/// A decoder for the encoding `label` names, or None if it names none.
+pub fn TextDecoder(label: &str, fatal: bool, ignore_bom: bool) -> Option<TextDecoder> {
+    do encoding_for_label(label).map |encoding| {
+        TextDecoder { encoding: *encoding, fatal: fatal, ignore_bom: ignore_bom }
+    }
+}
+
+// Adds `code_point` to `result`, or U+FFFD if it isn't a scalar value
+fn push_code_point(result: &mut ~str, code_point: uint) {
+    let valid = code_point < 0xd800 || (code_point > 0xdfff && code_point <= 0x10ffff);
+    str::push_char(result, (if valid { code_point } else { REPLACEMENT }) as char);
+}
+
+// Notes a malformed sequence
+fn error(result: &mut ~str, fatal: bool) -> Result<(), TypeError> {
+    if fatal {
+        return Err(TypeError { message: "the data isn't valid in the decoder's encoding" });
+    }
+    push_code_point(result, REPLACEMENT);
+    Ok(())
+}
+
+fn decode_utf8(bytes: &[u8], fatal: bool) -> Result<~str, TypeError> {
+    let mut result = ~"";
+    let mut code_point = 0;
+    let mut needed = 0;
+    let mut seen = 0;
+    let mut lower = 0x80u8;
+    let mut upper = 0xbfu8;
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if needed == 0 {
+            if b <= 0x7f {
+                push_code_point(&mut result, b as uint);
+            } else if b >= 0xc2 && b <= 0xdf {
+                needed = 1;
+                code_point = (b & 0x1f) as uint;
+            } else if b >= 0xe0 && b <= 0xef {
+                if b == 0xe0 { lower = 0xa0; }
+                if b == 0xed { upper = 0x9f; }
+                needed = 2;
+                code_point = (b & 0xf) as uint;
+            } else if b >= 0xf0 && b <= 0xf4 {
+                if b == 0xf0 { lower = 0x90; }
+                if b == 0xf4 { upper = 0x8f; }
+                needed = 3;
+                code_point = (b & 0x7) as uint;
+            } else {
+                match error(&mut result, fatal) { Err(e) => return Err(e), Ok(()) => () }
+            }
+            i += 1;
+            loop;
+        }
+        if b < lower || b > upper {
+            // The sequence ends early; this byte starts whatever is next
+            code_point = 0;
+            needed = 0;
+            seen = 0;
+            lower = 0x80;
+            upper = 0xbf;
+            match error(&mut result, fatal) { Err(e) => return Err(e), Ok(()) => () }
+            loop;
+        }
+        lower = 0x80;
+        upper = 0xbf;
+        code_point = (code_point << 6) | (b & 0x3f) as uint;
+        seen += 1;
+        if seen == needed {
+            push_code_point(&mut result, code_point);
+            code_point = 0;
+            needed = 0;
+            seen = 0;
+        }
+        i += 1;
+    }
+    if needed != 0 {
+        match error(&mut result, fatal) { Err(e) => return Err(e), Ok(()) => () }
+    }
+    Ok(move result)
+}
+
+/// Text from UTF-16 code units, with each lone surrogate a malformed
+/// sequence.
+pub fn decode_utf16_units(units: &[u16], fatal: bool) -> Result<~str, TypeError> {
+    let mut result = ~"";
+    let mut i = 0;
+    while i < units.len() {
+        let u = units[i] as uint;
+        if u >= 0xd800 && u <= 0xdbff && i + 1 < units.len() &&
+           units[i + 1] >= 0xdc00 && units[i + 1] <= 0xdfff {
+            let trail = units[i + 1] as uint;
+            push_code_point(&mut result, 0x10000 + ((u - 0xd800) << 10) + (trail - 0xdc00));
+            i += 2;
+        } else if u >= 0xd800 && u <= 0xdfff {
+            match error(&mut result, fatal) { Err(e) => return Err(e), Ok(()) => () }
+            i += 1;
+        } else {
+            push_code_point(&mut result, u);
+            i += 1;
+        }
+    }
+    Ok(move result)
+}
+
+fn decode_utf16(bytes: &[u8], big_endian: bool, fatal: bool) -> Result<~str, TypeError> {
+    let units = do vec::from_fn(bytes.len() / 2) |i| {
+        let (hi, lo) = if big_endian { (bytes[2 * i], bytes[2 * i + 1]) }
+                       else { (bytes[2 * i + 1], bytes[2 * i]) };
+        ((hi as u16) << 8) | lo as u16
+    };
+    let mut result = match decode_utf16_units(units, fatal) {
+        Ok(move result) => move result,
+        Err(e) => return Err(e)
+    };
+    // A byte left over is half a code unit
+    if bytes.len() % 2 == 1 {
+        match error(&mut result, fatal) { Err(e) => return Err(e), Ok(()) => () }
+    }
+    Ok(move result)
+}
+
+impl TextDecoder {
+    /// Decodes `buffer`, skipping the encoding's byte order mark at the
+    /// start unless told not to.
+    fn decode(&self, buffer: &[u8]) -> Result<~str, TypeError> {
+        let bom: &[u8] = match self.encoding {
+            Utf8 => &[0xef, 0xbb, 0xbf],
+            Utf16Le => &[0xff, 0xfe],
+            Utf16Be => &[0xfe, 0xff],
+            Latin1 => &[]
+        };
+        let bytes = if !self.ignore_bom && !bom.is_empty() && buffer.len() >= bom.len() &&
+                       vec::view(buffer, 0, bom.len()) == bom {
+            vec::view(buffer, bom.len(), buffer.len())
+        } else {
+            vec::view(buffer, 0, buffer.len())
+        };
+        match self.encoding {
+            Utf8 => decode_utf8(bytes, self.fatal),
+            Utf16Le => decode_utf16(bytes, false, self.fatal),
+            Utf16Be => decode_utf16(bytes, true, self.fatal),
+            Latin1 => {
+                let mut result = ~"";
+                for bytes.each |b| {
+                    push_code_point(&mut result, *b as uint);
+                }
+                Ok(move result)
+            }
+        }
+    }
+}
+
+/// The UTF-8 of text given as UTF-16 code units.
+pub fn encode(units: &[u16]) -> ~[u8] {
+    match decode_utf16_units(units, false) {
+        Ok(move text) => str::to_bytes(text),
+        Err(_) => fail ~"decoding without fatal can't fail"
+    }
+}
+
+/**
+Writes the UTF-8 of as much of `units` as fits into `dest`, stopping
+before a character that doesn't fit whole. Returns how many code units
+were read and how many bytes were written.
+*/
+pub fn encode_into(units: &[u16], dest: &mut [u8]) -> (uint, uint) {
+    let mut read = 0;
+    let mut written = 0;
+    while read < units.len() {
+        let pair = read + 1 < units.len() && units[read] >= 0xd800 && units[read] <= 0xdbff &&
+            units[read + 1] >= 0xdc00 && units[read + 1] <= 0xdfff;
+        let len = if pair { 2 } else { 1 };
+        let bytes = encode(vec::view(units, read, read + len));
+        if written + bytes.len() > dest.len() {
+            break;
+        }
+        for bytes.eachi |i, b| {
+            dest[written + i] = *b;
+        }
+        read += len;
+        written += bytes.len();
+    }
+    (read, written)
+}
+
+#[cfg(test)]
+mod text_decoder_tests {
+    fn decode(label: &str, fatal: bool, bytes: &[u8]) -> Result<~str, TypeError> {
+        TextDecoder(label, fatal, false).get().decode(bytes)
+    }
+
+    fn lossy(label: &str, bytes: &[u8]) -> ~str {
+        result::unwrap(decode(label, false, bytes))
+    }
+
+    #[test]
+    fn test_labels() {
+        assert encoding_for_label(" UTF8 ") == Some(Utf8);
+        assert encoding_for_label("utf-16") == Some(Utf16Le);
+        assert encoding_for_label("latin1") == Some(Latin1);
+        assert encoding_for_label("klingon").is_none();
+        assert TextDecoder("unicodefffe", false, false).get().encoding.name() == "utf-16be";
+    }
+
+    #[test]
+    fn test_decode_utf8() {
+        assert lossy("utf-8", [0x68, 0xc3, 0xa9, 0xe2, 0x82, 0xac, 0xf0, 0x9f, 0x98, 0x80]) ==
+            ~"hé€\U0001f600";
+        // The BOM is skipped, unless it's to be kept
+        assert lossy("utf-8", [0xef, 0xbb, 0xbf, 0x61]) == ~"a";
+        let keep_bom = TextDecoder("utf-8", false, true).get();
+        assert result::unwrap(keep_bom.decode([0xef, 0xbb, 0xbf, 0x61])) == ~"\ufeffa";
+    }
+
+    #[test]
+    fn test_decode_utf8_malformed() {
+        // A truncated sequence is one U+FFFD, and the next byte starts over
+        assert lossy("utf-8", [0xe2, 0x82, 0x61]) == ~"\ufffda";
+        assert lossy("utf-8", [0xff, 0x61, 0xc3]) == ~"\ufffda\ufffd";
+        // Surrogates and overlong forms are malformed
+        assert lossy("utf-8", [0xed, 0xa0, 0x80]) == ~"\ufffd\ufffd\ufffd";
+        assert lossy("utf-8", [0xc0, 0x80]) == ~"\ufffd\ufffd";
+        assert decode("utf-8", true, [0x61, 0xc3]).is_err();
+        assert decode("utf-8", true, [0x61, 0x62]).is_ok();
+    }
+
+    #[test]
+    fn test_decode_utf16() {
+        assert lossy("utf-16le", [0xff, 0xfe, 0x68, 0x00, 0x3d, 0xd8, 0x00, 0xde]) ==
+            ~"h\U0001f600";
+        assert lossy("utf-16be", [0x00, 0x68, 0x20, 0xac]) == ~"h€";
+        // A lone surrogate, and an odd byte at the end
+        assert lossy("utf-16le", [0x3d, 0xd8, 0x68, 0x00, 0x61]) == ~"\ufffdh\ufffd";
+        assert decode("utf-16le", true, [0x3d, 0xd8]).is_err();
+        assert decode("utf-16be", true, [0x00]).is_err();
+    }
+
+    #[test]
+    fn test_decode_latin1() {
+        assert lossy("iso-8859-1", [0x63, 0x61, 0x66, 0xe9, 0xff]) == ~"caféÿ";
+    }
+
+    #[test]
+    fn test_encode() {
+        assert encode(str::to_utf16("h€\U0001f600")) == str::to_bytes("h€\U0001f600");
+        // A lone surrogate becomes U+FFFD
+        assert encode([0xd83d, 0x61]) == ~[0xef, 0xbf, 0xbd, 0x61];
+    }
+
+    #[test]
+    fn test_encode_into() {
+        let units = str::to_utf16("a€\U0001f600");
+        let mut dest = vec::from_elem(5, 0u8);
+        // The emoji's four bytes don't fit after the first four
+        assert encode_into(units, dest) == (2, 4);
+        assert vec::slice(dest, 0, 4) == ~[0x61, 0xe2, 0x82, 0xac];
+
+        let mut dest = vec::from_elem(8, 0u8);
+        assert encode_into(units, dest) == (4, 8);
+    }
+}
@@ -1,16 +1,63 @@
 use comm::{Port, Chan};
-use content::content_task::{ControlMsg, Timer, ExitMsg};
-use js::jsapi::JSVal;
+use content::content_task::{ControlMsg, Timer, ExitMsg, FireEvent, Callback, ParseMsg, PostMsg,
+                            DeliverPerformanceEntries, task_from_context};
+use dom::geolocation::Geolocation;
+use dom::history::History;
+use dom::resize_observer::ResizeObserver;
+use css::property_registry::PropertyRegistry;
+use dom::performance_observer::{Performance, PerformanceEntry, LongTaskEntry, LONG_TASK_MS};
+use dom::document::Document;
+use dom::event_target::EventListeners;
+use dom::events::pointer_event::PointerCaptures;
+use dom::focus::{FocusedElement, tab_order, next_focus};
+use dom::node::Node;
+use dom::scroll::ScrollState;
+use dom::bindings::utils::{new_event, new_related_event};
+use dom::bindings::node;
+use dom::bindings::rooting::RootedValues;
+use dom::notification::{Notification, NotificationPermission, PermissionDefault, PermissionGranted,
+                        PermissionDenied};
+use opts::PermissionPrompt;
+use js::JSVAL_NULL;
+use js::jsapi::{JSContext, JSVal};
+use js::glue::bindgen::RUST_OBJECT_TO_JSVAL;
+use geom::point::Point2D;
+use std::net::url::Url;
 use dvec::DVec;
+use std::time::precise_time_ns;
 
 enum TimerControlMsg {
     TimerMessage_Fire(~TimerData),
     TimerMessage_Close,
+    // The values are keys in the content task's `posted` roots
+    TimerMessage_FireEvent(uint, ~str, uint),
+    TimerMessage_Callback(uint, uint),
+    TimerMessage_Navigate(Url),
+    // A POST of a body, of a Content-Type, for a form
+    TimerMessage_NavigatePost(Url, ~[u8], ~str),
+    TimerMessage_DeliverPerformanceEntries,
     TimerMessage_TriggerExit //XXXjdm this is just a quick hack to talk to the content task
 }
 
 struct Window {
     timer_chan: Chan<TimerControlMsg>,
+    permission_prompt: PermissionPrompt,
+    mut notification_permission: NotificationPermission,
+    // The notifications showing, whose JS objects are rooted
+    notifications: DVec<@Notification>,
+    geolocation: @Geolocation,
+    history: @History,
+    resize_observers: DVec<@ResizeObserver>,
+    performance: @Performance,
+    // The custom properties registered with CSS.registerProperty
+    property_registry: @PropertyRegistry,
+    mut focused: Option<FocusedElement>,
+    // The JS object of the focused element
+    focused_root: RootedValues,
+    scroll: ScrollState,
+    pointers: PointerCaptures,
+    event_listeners: EventListeners,
+    cx: *JSContext,
 
     drop {
         self.timer_chan.send(TimerMessage_Close);
@@ -23,12 +70,15 @@ struct Window {
 pub struct TimerData {
     funval: JSVal,
     args: DVec<JSVal>,
+    // The monotonic time, in ns, the timer may fire at
+    fire_at: u64,
 }
 
-pub fn TimerData(argc: libc::c_uint, argv: *JSVal) -> TimerData unsafe {
+pub fn TimerData(argc: libc::c_uint, argv: *JSVal, fire_at: u64) -> TimerData unsafe {
     let data = TimerData {
         funval : *argv,
         args : DVec(),
+        fire_at : fire_at,
     };
 
     let mut i = 2;
@@ -52,18 +102,203 @@ impl Window {
         self.timer_chan.send(TimerMessage_TriggerExit);
     }
 
+    /// Keeps `observer` in the window's list while it's observing something,
+    /// and takes it out when it stops.
+    fn update_resize_observer(observer: @ResizeObserver) {
+        let listed = self.resize_observers.any(|o| core::box::ptr_eq(*o, observer));
+        if observer.is_observing() && !listed {
+            self.resize_observers.push(observer);
+        } else if !observer.is_observing() && listed {
+            do self.resize_observers.swap |observers| {
+                vec::filter(observers, |o| !core::box::ptr_eq(*o, observer))
+            }
+        }
+    }
+
+    /// Lets go of the JS values the window keeps rooted, as it does when it's
+    /// replaced by the next page's or the content task exits.
+    fn unroot_all() {
+        self.event_listeners.clear();
+        for self.resize_observers.each |observer| {
+            observer.disconnect();
+        }
+        self.resize_observers.set(~[]);
+        self.performance.disconnect_observers();
+        for self.notifications.each |notification| {
+            notification.close();
+        }
+        self.notifications.set(~[]);
+        self.history.unroot_states();
+        self.focused = None;
+        self.focused_root.clear();
+    }
+
+    /// Asks the user for permission to show notifications, unless they've
+    /// already answered.
+    fn request_notification_permission() -> NotificationPermission {
+        if self.notification_permission == PermissionDefault {
+            self.notification_permission = if self.permission_prompt.grants() {
+                PermissionGranted
+            } else {
+                PermissionDenied
+            };
+        }
+        self.notification_permission
+    }
+
+    /// Queues a `kind` event (e.g. "show") on a JS object. It's dispatched
+    /// once the script that's running now has finished, and the target and
+    /// event are kept rooted until then.
+    fn post_event(target: JSVal, kind: ~str, event: JSVal) {
+        let posted = unsafe { &(*task_from_context(self.cx)).posted };
+        self.timer_chan.send(TimerMessage_FireEvent(posted.add(target), move kind,
+                                                    posted.add(event)));
+    }
+
+    /// Calls `funval` with `arg` once the script that's running now has finished.
+    /// Both are kept rooted until then.
+    fn post_callback(funval: JSVal, arg: JSVal) {
+        let posted = unsafe { &(*task_from_context(self.cx)).posted };
+        self.timer_chan.send(TimerMessage_Callback(posted.add(funval), posted.add(arg)));
+    }
+
+    /// Like `post_callback`, but the call is queued when the function this
+    /// returns is called, which can be done on any task. Both values are
+    /// kept rooted until then.
+    fn callback_sender(funval: JSVal, arg: JSVal) -> fn~() {
+        let posted = unsafe { &(*task_from_context(self.cx)).posted };
+        let funval_key = posted.add(funval);
+        let arg_key = posted.add(arg);
+        let timer_chan = self.timer_chan;
+        fn~() { timer_chan.send(TimerMessage_Callback(funval_key, arg_key)) }
+    }
+
+    /// Records `entry` in the page's timeline, queueing a call to the
+    /// PerformanceObservers of its type.
+    fn record_performance_entry(entry: PerformanceEntry) {
+        if self.performance.record(move entry) {
+            self.timer_chan.send(TimerMessage_DeliverPerformanceEntries);
+        }
+    }
+
+    /// Records a `longtask` entry if a task that ran from `start` (in ns)
+    /// until now took too long.
+    fn note_task(start: u64) {
+        let start_ms = self.performance.to_ms(start);
+        let duration = self.performance.now() - start_ms;
+        if duration > LONG_TASK_MS {
+            self.record_performance_entry(PerformanceEntry(~"self", LongTaskEntry, start_ms,
+                                                           duration));
+        }
+    }
+
+    /// Loads `url` in place of the current document, once the events queued
+    /// before it have been dispatched.
+    fn navigate(url: Url) {
+        self.timer_chan.send(TimerMessage_Navigate(move url));
+    }
+
+    /// Navigates to what POSTing `body`, whose type is `content_type`, to
+    /// `url` gets.
+    fn navigate_post(url: Url, body: ~[u8], content_type: ~str) {
+        self.timer_chan.send(TimerMessage_NavigatePost(move url, move body, move content_type));
+    }
+
+    /**
+    Moves focus to `target`, or nowhere. The element losing focus gets
+    `blur` and then `focusout`; the one gaining it then gets `focus` and
+    `focusin`. The events are dispatched before this returns, as they are
+    for `element.focus()`.
+    */
+    fn set_focus(cx: *JSContext, target: Option<FocusedElement>) unsafe {
+        let old = self.focused;
+        match (old, target) {
+            (Some(ref a), Some(ref b)) if a.node == b.node => return,
+            (None, None) => return,
+            _ => ()
+        }
+        self.focused = target;
+
+        // The old element's object stays rooted until its events are done
+        let old_keys = self.focused_root.keys();
+        match target {
+            Some(ref f) => { self.focused_root.add(f.obj); }
+            None => ()
+        }
+
+        let content = task_from_context(cx);
+        let old_obj = old.map_default(JSVAL_NULL, |f| f.obj);
+        let new_obj = target.map_default(JSVAL_NULL, |f| f.obj);
+        if old.is_some() {
+            for [~"blur", ~"focusout"].each |kind| {
+                let event = new_related_event(cx, *kind, old_obj, new_obj);
+                (*content).dispatch_event(old_obj, *kind, RUST_OBJECT_TO_JSVAL(event));
+            }
+        }
+        for old_keys.each |key| {
+            self.focused_root.remove(*key);
+        }
+        if target.is_some() {
+            for [~"focus", ~"focusin"].each |kind| {
+                let event = new_related_event(cx, *kind, new_obj, old_obj);
+                (*content).dispatch_event(new_obj, *kind, RUST_OBJECT_TO_JSVAL(event));
+            }
+        }
+    }
+
+    /// Moves focus to the next element in tab order, as Tab does.
+    fn focus_next(cx: *JSContext, document: &Document) {
+        self.cycle_focus(cx, document, true)
+    }
+
+    /// Moves focus to the previous element in tab order, as Shift+Tab does.
+    fn focus_previous(cx: *JSContext, document: &Document) {
+        self.cycle_focus(cx, document, false)
+    }
+
+    priv fn cycle_focus(cx: *JSContext, document: &Document, forward: bool) unsafe {
+        let current = self.focused.map(|f| f.node);
+        match next_focus(tab_order(document), current, forward) {
+            Some(node) => {
+                let obj = node::create(cx, node, document.scope);
+                self.set_focus(cx, Some(FocusedElement {
+                    node: node,
+                    obj: RUST_OBJECT_TO_JSVAL(obj),
+                }));
+            }
+            None => ()
+        }
+    }
+
+    /// Scrolls an element to `offset`, queueing a `scroll` event at its JS
+    /// object `obj` if it moved. Returns whether it moved.
+    fn scroll_element_to(cx: *JSContext, node: Node, obj: JSVal, offset: Point2D<int>) -> bool unsafe {
+        if !self.scroll.set_element_offset(node, offset) {
+            return false;
+        }
+        let event = new_event(cx, "scroll", obj);
+        self.post_event(obj, ~"scroll", RUST_OBJECT_TO_JSVAL(event));
+        true
+    }
+
     fn setTimeout(&self, timeout: int, argc: libc::c_uint, argv: *JSVal) {
         let timeout = int::max(0, timeout) as uint;
+        let fire_at = precise_time_ns() + (timeout as u64) * 1000000;
 
         // Post a delayed message to the per-window timer task; it will dispatch it
         // to the relevant content handler that will deal with it.
         std::timer::delayed_send(std::uv_global_loop::get(),
                                  timeout, self.timer_chan,
-                                 TimerMessage_Fire(~TimerData(argc, argv)));
+                                 TimerMessage_Fire(~TimerData(argc, argv, fire_at)));
     }
 }
 
-fn Window(content_chan: pipes::SharedChan<ControlMsg>) -> Window {
+fn Window(content_chan: pipes::SharedChan<ControlMsg>,
+          permission_prompt: PermissionPrompt,
+          url: Url,
+          // When navigation to `url` started, in ns
+          navigation_start: u64,
+          cx: *JSContext) -> Window {
         
     Window {
         timer_chan: do task::spawn_listener |timer_port: Port<TimerControlMsg>,
@@ -74,9 +309,36 @@ fn Window(content_chan: pipes::SharedChan<ControlMsg>) -> Window {
                     TimerMessage_Fire(move td) => {
                         content_chan.send(Timer(move td));
                     }
+                    TimerMessage_FireEvent(target, move kind, event) => {
+                        content_chan.send(FireEvent(target, move kind, event));
+                    }
+                    TimerMessage_Callback(funval, arg) => {
+                        content_chan.send(Callback(funval, arg));
+                    }
+                    TimerMessage_Navigate(move url) => content_chan.send(ParseMsg(move url)),
+                    TimerMessage_NavigatePost(move url, move body, move content_type) => {
+                        content_chan.send(PostMsg(move url, move body, move content_type));
+                    }
+                    TimerMessage_DeliverPerformanceEntries => {
+                        content_chan.send(DeliverPerformanceEntries);
+                    }
                     TimerMessage_TriggerExit => content_chan.send(ExitMsg)
                 }
             }
-        }
+        },
+        permission_prompt: permission_prompt,
+        notification_permission: PermissionDefault,
+        notifications: DVec(),
+        geolocation: @Geolocation(permission_prompt),
+        history: @History(cx, move url),
+        resize_observers: DVec(),
+        performance: @Performance(navigation_start),
+        property_registry: @PropertyRegistry(),
+        focused: None,
+        focused_root: RootedValues(cx),
+        scroll: ScrollState(),
+        pointers: PointerCaptures(),
+        event_listeners: EventListeners(cx),
+        cx: cx
     }
 }
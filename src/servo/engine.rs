@@ -5,7 +5,8 @@ use task::spawn_listener;
 use layout::layout_task;
 use layout_task::LayoutTask;
 use mod content::content_task;
-use content::content_task::{ContentTask, ExecuteMsg, ParseMsg, ExitMsg};
+use content::content_task::{ContentTask, ExecuteMsg, ParseMsg, ExitMsg, CollectGarbage,
+                            AttachDevtools, DevtoolsCommand, DetachDevtools, Print};
 use resource::resource_task;
 use resource::resource_task::ResourceTask;
 use std::net::url::Url;
@@ -14,25 +15,48 @@ use image_cache_task::{ImageCacheTask, image_cache_task, ImageCacheTaskClient};
 use pipes::{Port, Chan};
 use dom::event::Event;
 use std::cell::Cell;
+use opts::Opts;
+use memory_watchdog::MemoryWatchdog;
+use WatchdogExitMsg = memory_watchdog::ExitMsg;
+use util::url::make_url;
+use gfx::pdf_writer::{write_pdf, TrueTypeFont};
+use text::font_cache::{test_font_bin, TEST_FONT_SIZE};
 
 pub type EngineTask = comm::Chan<Msg>;
 
 pub enum Msg {
     LoadURLMsg(Url),
+    // Loads the page and prints it to a PDF file at the path, saying when
+    // it's written
+    PrintToPdfMsg(Url, ~str, Chan<()>),
+    // From the memory watchdog
+    CollectGarbageMsg,
+    OutOfMemoryMsg,
+    // From the devtools server: a client connected, and where to send it
+    // messages; one of its messages; and that it went away
+    AttachDevtoolsMsg(pipes::SharedChan<~str>),
+    DevtoolsCommandMsg(~str),
+    DetachDevtoolsMsg,
     ExitMsg(Chan<()>)
 }
 
 struct Engine<C:Compositor Send Copy> {
     request_port: comm::Port<Msg>,
     compositor: C,
+    opts: Opts,
     render_task: RenderTask,
     resource_task: ResourceTask,
     image_cache_task: ImageCacheTask,
-    layout_task: LayoutTask,
-    content_task: ContentTask
+    // The layout and content tasks are replaced if they run out of memory
+    mut layout_task: LayoutTask,
+    mut content_task: ContentTask,
+    dom_event_chan: pipes::SharedChan<Event>,
+    event_forwarder: EventForwarder,
+    memory_watchdog: Option<MemoryWatchdog>
 }
 
 fn Engine<C:Compositor Send Copy>(compositor: C,
+                                  opts: Opts,
                                   dom_event_port: pipes::Port<Event>,
                                   dom_event_chan: pipes::SharedChan<Event>,
                                   resource_task: ResourceTask,
@@ -42,25 +66,60 @@ fn Engine<C:Compositor Send Copy>(compositor: C,
     let dom_event_chan = Cell(move dom_event_chan);
 
     do spawn_listener::<Msg> |request, move dom_event_port, move dom_event_chan,
-                              move image_cache_task| {
+                              move image_cache_task, move opts| {
         let render_task = RenderTask(compositor);
-        let layout_task = LayoutTask(render_task, image_cache_task.clone());
-        let content_task = ContentTask(layout_task,
-                                       dom_event_port.take(), dom_event_chan.take(),
+        let layout_task = LayoutTask(render_task, image_cache_task.clone(), copy opts);
+        let dom_event_chan = dom_event_chan.take();
+        let event_forwarder = EventForwarder(dom_event_port.take());
+        let (content_event_chan, content_event_port) = pipes::stream();
+        event_forwarder.send(move content_event_chan);
+        let content_task = ContentTask(layout_task, copy opts,
+                                       move content_event_port, dom_event_chan.clone(),
                                        resource_task, image_cache_task.clone());
+        let memory_watchdog = do opts.memory_limit.map |limit| {
+            MemoryWatchdog(*limit * 1024 * 1024, comm::Chan(&request))
+        };
 
         Engine {
             request_port: request,
             compositor: compositor,
+            opts: copy opts,
             render_task: render_task,
             resource_task: resource_task,
             image_cache_task: image_cache_task.clone(),
             layout_task: move layout_task,
-            content_task: move content_task
+            content_task: move content_task,
+            dom_event_chan: move dom_event_chan,
+            event_forwarder: event_forwarder,
+            memory_watchdog: move memory_watchdog
         }.run();
     }
 }
 
+/**
+Passes events from the platform on to the current content task. Sending it
+a new channel redirects the events that come after.
+*/
+type EventForwarder = comm::Chan<pipes::Chan<Event>>;
+
+fn EventForwarder(dom_event_port: pipes::Port<Event>) -> EventForwarder {
+    do spawn_listener |target_port: comm::Port<pipes::Chan<Event>>, move dom_event_port| {
+        let mut target = target_port.recv();
+        loop {
+            match dom_event_port.try_recv() {
+                Some(move event) => {
+                    while target_port.peek() {
+                        target = target_port.recv();
+                    }
+                    // The content task may have gone away; drop the event
+                    target.try_send(move event);
+                }
+                None => break
+            }
+        }
+    }
+}
+
 impl<C: Compositor Copy Send> Engine<C> {
     fn run() {
         while self.handle_request(self.request_port.recv()) {
@@ -71,7 +130,11 @@ impl<C: Compositor Copy Send> Engine<C> {
     fn handle_request(request: Msg) -> bool {
         match move request {
           LoadURLMsg(move url) => {
-            if url.path.ends_with(".js") {
+            if url.scheme == ~"about" && url.path == ~"imagecache" {
+                let page = write_page(~"servo-imagecache.html",
+                                      self.image_cache_task.report().to_html());
+                self.content_task.send(ParseMsg(move page));
+            } else if url.path.ends_with(".js") {
                 self.content_task.send(ExecuteMsg(move url))
             } else {
                 self.content_task.send(ParseMsg(move url))
@@ -79,7 +142,52 @@ impl<C: Compositor Copy Send> Engine<C> {
             return true;
           }
 
+          PrintToPdfMsg(move url, move path, move printed) => {
+            self.content_task.send(ParseMsg(move url));
+            let (pages_chan, pages_port) = pipes::stream();
+            // PDF is measured in points, 72 to the inch
+            self.content_task.send(Print(72, move pages_chan));
+            let pages = pages_port.recv();
+            debug!("engine: writing %u pages to %s", pages.len(), path);
+            // Text runs are all in the test font for now
+            let pdf = write_pdf(pages, &TrueTypeFont(test_font_bin()), TEST_FONT_SIZE);
+            match io::file_writer(&Path(path), ~[io::Create, io::Truncate]) {
+                Ok(writer) => writer.write(pdf),
+                Err(e) => error!("engine: unable to write %s: %s", path, e)
+            }
+            printed.send(());
+            return true;
+          }
+
+          CollectGarbageMsg => {
+            self.content_task.send(CollectGarbage);
+            return true;
+          }
+
+          OutOfMemoryMsg => {
+            self.restart_renderer();
+            return true;
+          }
+
+          AttachDevtoolsMsg(move chan) => {
+            self.content_task.send(AttachDevtools(move chan));
+            return true;
+          }
+
+          DevtoolsCommandMsg(move message) => {
+            self.content_task.send(DevtoolsCommand(move message));
+            return true;
+          }
+
+          DetachDevtoolsMsg => {
+            self.content_task.send(DetachDevtools);
+            return true;
+          }
+
           ExitMsg(move sender) => {
+            for self.memory_watchdog.each |watchdog| {
+                watchdog.send(WatchdogExitMsg);
+            }
             self.content_task.send(content_task::ExitMsg);
             self.layout_task.send(layout_task::ExitMsg);
             
@@ -96,5 +204,42 @@ impl<C: Compositor Copy Send> Engine<C> {
           }
         }
     }
+
+    /// Kills the content and layout tasks and starts new ones showing an
+    /// error page.
+    fn restart_renderer() {
+        warn!("engine: renderer ran out of memory, restarting it");
+        self.content_task.send(content_task::ExitMsg);
+
+        let layout_task = LayoutTask(self.render_task, self.image_cache_task.clone(),
+                                     copy self.opts);
+        let (content_event_chan, content_event_port) = pipes::stream();
+        self.event_forwarder.send(move content_event_chan);
+        let content_task = ContentTask(layout_task, copy self.opts,
+                                       move content_event_port, self.dom_event_chan.clone(),
+                                       self.resource_task, self.image_cache_task.clone());
+        content_task.send(ParseMsg(out_of_memory_page()));
+
+        self.layout_task = move layout_task;
+        self.content_task = move content_task;
+    }
 }
 
+fn out_of_memory_page() -> Url {
+    write_page(~"servo-out-of-memory.html",
+               ~"<html><body><h1>Out of memory</h1>" +
+               ~"<p>This page used more memory than " +
+               ~"--memory-limit allows, so it was stopped.</p>" +
+               ~"</body></html>")
+}
+
+// There's no way to load a page from memory yet, so pages the engine makes
+// itself are written to temporary files
+fn write_page(name: ~str, html: ~str) -> Url {
+    let path = os::tmpdir().push(name);
+    match io::file_writer(&path, ~[io::Create, io::Truncate]) {
+        Ok(writer) => writer.write_str(html),
+        Err(e) => warn!("engine: unable to write %s: %s", path.to_str(), e)
+    }
+    make_url(path.to_str(), None)
+}
@@ -0,0 +1,246 @@
+/*!
+The content task's scheduler. Messages for the task wait here in one queue
+per priority, and each turn of the event loop runs one task from the most
+urgent queue that has one ready, so a page that is busy with timers and
+network callbacks still answers input and relayouts promptly.
+
+A less urgent task isn't starved by a steady stream of more urgent ones:
+once its priority has had a ready task passed over `MAX_PASSED_OVER`
+times, it goes first.
+
+A task can be held back until a time, as timers are: it stays queued, and
+others behind it can run, until the monotonic clock has reached it.
+*/
+
+use std::time::precise_time_ns;
+
+pub enum Priority {
+    // Input, relayout and repaint; what the user is waiting to see
+    UserVisible,
+    // Timers and network callbacks
+    Normal,
+    // Work nobody is waiting on, like collecting garbage
+    Background,
+}
+
+impl Priority : cmp::Eq {
+    pure fn eq(&self, other: &Priority) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &Priority) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+// Most urgent first
+const PRIORITIES: [Priority * 3] = [UserVisible, Normal, Background];
+
+/// How many tasks of more urgent priorities may run while a priority has
+/// one ready, before that one runs anyway.
+pub const MAX_PASSED_OVER: uint = 8;
+
+struct QueuedTask<T> {
+    // The monotonic time, in ns, before which the task mustn't run
+    not_before: u64,
+    task: T,
+}
+
+/// The tasks of one priority, in the order they were posted.
+pub struct TaskQueue<T> {
+    priv mut tasks: ~[QueuedTask<T>],
+}
+
+pub fn TaskQueue<T: Owned>() -> TaskQueue<T> {
+    TaskQueue { tasks: ~[] }
+}
+
+impl<T: Owned> TaskQueue<T> {
+    fn push(&self, task: T, not_before: u64) {
+        self.tasks.push(QueuedTask { not_before: not_before, task: move task });
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    fn len(&self) -> uint {
+        self.tasks.len()
+    }
+
+    fn has_ready(&self, now: u64) -> bool {
+        vec::any(self.tasks, |queued| queued.not_before <= now)
+    }
+
+    /// Takes the oldest task that may run at `now`, skipping any that are
+    /// held back until later.
+    fn take_ready(&self, now: u64) -> Option<T> {
+        match vec::position(self.tasks, |queued| queued.not_before <= now) {
+            Some(i) => {
+                let QueuedTask { task: move task, _ } = vec::remove(&mut self.tasks, i);
+                Some(move task)
+            }
+            None => None
+        }
+    }
+
+    /// When the soonest held back task may run.
+    fn next_due(&self) -> Option<u64> {
+        let mut due = None;
+        for self.tasks.each |queued| {
+            if due.map_default(true, |d| queued.not_before < *d) {
+                due = Some(queued.not_before);
+            }
+        }
+        due
+    }
+}
+
+pub struct Scheduler<T> {
+    // One for each priority, most urgent first
+    priv queues: ~[TaskQueue<T>],
+    // How many times each priority has had a ready task passed over for a
+    // more urgent one since it last ran one
+    priv mut passed_over: ~[uint],
+}
+
+pub fn Scheduler<T: Owned>() -> Scheduler<T> {
+    Scheduler {
+        queues: vec::from_fn(PRIORITIES.len(), |_i| TaskQueue()),
+        passed_over: vec::from_elem(PRIORITIES.len(), 0),
+    }
+}
+
+impl<T: Owned> Scheduler<T> {
+    /// Queues a task to run as soon as its priority allows.
+    fn post(&self, priority: Priority, task: T) {
+        self.queues[priority as uint].push(move task, 0);
+    }
+
+    /// Queues a task that mustn't run before the monotonic time
+    /// `not_before`, in ns.
+    fn post_at(&self, priority: Priority, task: T, not_before: u64) {
+        self.queues[priority as uint].push(move task, not_before);
+    }
+
+    fn is_empty(&self) -> bool {
+        vec::all(self.queues, |queue| queue.is_empty())
+    }
+
+    fn len(&self, priority: Priority) -> uint {
+        self.queues[priority as uint].len()
+    }
+
+    /// Whether any task may run at `now`.
+    fn has_ready(&self, now: u64) -> bool {
+        vec::any(self.queues, |queue| queue.has_ready(now))
+    }
+
+    /**
+    Takes the task to run at `now`: the oldest ready one of the most urgent
+    priority that has one, unless a less urgent priority has been passed
+    over too often. None if nothing is queued, or everything queued is held
+    back.
+    */
+    fn next_task(&self, now: u64) -> Option<T> {
+        let starved = PRIORITIES.filter(|p| self.passed_over[*p as uint] >= MAX_PASSED_OVER);
+        let rest = PRIORITIES.filter(|p| !starved.contains(p));
+        for vec::append(starved, rest).each |priority| {
+            match self.queues[*priority as uint].take_ready(now) {
+                Some(move task) => {
+                    self.passed_over[*priority as uint] = 0;
+                    for PRIORITIES.each |other| {
+                        if (*other as uint) > (*priority as uint) &&
+                                self.queues[*other as uint].has_ready(now) {
+                            self.passed_over[*other as uint] += 1;
+                        }
+                    }
+                    return Some(move task);
+                }
+                None => ()
+            }
+        }
+        None
+    }
+
+    /// When the soonest held back task may run, if any are queued.
+    fn next_due(&self) -> Option<u64> {
+        let mut due = None;
+        for self.queues.each |queue| {
+            match queue.next_due() {
+                Some(d) if due.map_default(true, |due| d < *due) => due = Some(d),
+                _ => ()
+            }
+        }
+        due
+    }
+
+    /**
+    Runs the next task with `run`, checking the clock first so no task runs
+    before its time. Returns whether there was one to run.
+    */
+    fn run_one_task(&self, run: fn(T)) -> bool {
+        match self.next_task(precise_time_ns()) {
+            Some(move task) => {
+                run(move task);
+                true
+            }
+            None => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod scheduler_tests {
+    #[test]
+    fn test_most_urgent_first() {
+        let scheduler = Scheduler();
+        scheduler.post(Background, 1);
+        scheduler.post(Normal, 2);
+        scheduler.post(UserVisible, 3);
+        scheduler.post(Normal, 4);
+        assert scheduler.len(Normal) == 2;
+
+        let mut order = ~[];
+        while scheduler.run_one_task(|task| order.push(task)) {}
+        assert order == ~[3, 2, 4, 1];
+        assert scheduler.is_empty();
+    }
+
+    #[test]
+    fn test_background_is_not_starved() {
+        let scheduler = Scheduler();
+        scheduler.post(Background, 0);
+        for uint::range(1, MAX_PASSED_OVER + 2) |i| {
+            scheduler.post(UserVisible, i);
+        }
+
+        let mut order = ~[];
+        for uint::range(0, MAX_PASSED_OVER + 1) |_i| {
+            scheduler.run_one_task(|task| order.push(task));
+        }
+        // the background task goes once it's been passed over enough
+        assert order.len() == MAX_PASSED_OVER + 1;
+        assert order[MAX_PASSED_OVER] == 0;
+        assert scheduler.len(Background) == 0;
+        assert scheduler.len(UserVisible) == 1;
+    }
+
+    #[test]
+    fn test_held_back_until_due() {
+        let scheduler = Scheduler();
+        scheduler.post_at(UserVisible, 1, 100);
+        scheduler.post(Background, 2);
+        scheduler.post_at(Normal, 3, 50);
+        assert scheduler.next_due() == Some(0);
+
+        // Tasks that aren't due don't block the others
+        assert scheduler.next_task(10) == Some(2);
+        assert !scheduler.has_ready(10);
+        assert scheduler.next_task(10).is_none();
+        assert scheduler.next_due() == Some(50);
+        assert scheduler.next_task(50) == Some(3);
+        assert scheduler.next_task(99).is_none();
+        assert scheduler.next_task(100) == Some(1);
+        assert scheduler.next_due().is_none();
+    }
+}
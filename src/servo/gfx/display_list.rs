@@ -30,7 +30,7 @@ pub enum DisplayItem {
     // TODO: need to provide spacing data for text run.
     // (i.e, to support rendering of CSS 'word-spacing' and 'letter-spacing')
     // TODO: don't copy text runs, ever.
-    Text(DisplayItemData, ~SendableTextRun, Range),
+    Text(DisplayItemData, ~SendableTextRun, Range, u8, u8, u8),
     Image(DisplayItemData, ARC<~image::base::Image>),
     Border(DisplayItemData, Au, u8, u8, u8)
 }
@@ -39,7 +39,7 @@ impl DisplayItem {
     pure fn d(&self) -> &self/DisplayItemData {
         match *self {
             SolidColor(ref d, _, _, _) => d,
-            Text(ref d, _, _) => d,
+            Text(ref d, _, _, _, _, _) => d,
             Image(ref d, _) => d,
             Border(ref d, _, _, _, _) => d
         }
@@ -48,12 +48,12 @@ impl DisplayItem {
     fn draw_into_context(&self, ctx: &RenderContext) {
         match *self {
             SolidColor(_, r,g,b) => ctx.draw_solid_color(&self.d().bounds, r, g, b),
-            Text(_, run, range) => {
+            Text(_, ref run, range, r, g, b) => {
                 let new_run = @run.deserialize(ctx.font_cache);
                 let font = new_run.font;
                 let origin = self.d().bounds.origin;
                 let baseline_origin = Point2D(origin.x, origin.y + font.metrics.ascent);
-                font.draw_text_into_context(ctx, new_run, range, baseline_origin);
+                font.draw_text_into_context(ctx, new_run, range, baseline_origin, r, g, b);
             },
             Image(_, ref img) => ctx.draw_image(self.d().bounds, clone_arc(img)),
             Border(_, width, r, g, b) => ctx.draw_border(&self.d().bounds, width, r, g, b),
@@ -72,8 +72,9 @@ impl DisplayItem {
         Border(DisplayItemData::new(bounds), width, r, g, b)
     }
 
-    static pure fn new_Text(bounds: &Rect<Au>, run: ~SendableTextRun, range: Range) -> DisplayItem {
-        Text(DisplayItemData::new(bounds), move run, range)
+    static pure fn new_Text(bounds: &Rect<Au>, run: ~SendableTextRun, range: Range,
+                            r: u8, g: u8, b: u8) -> DisplayItem {
+        Text(DisplayItemData::new(bounds), move run, range, r, g, b)
     }
 
     // ARC should be cloned into ImageData, but Images are not sendable
@@ -0,0 +1,427 @@
+/*!
+PDF output, for `--print-to-pdf`. Each printed page's display list is
+drawn into the content stream of a PDF page, in the px it was laid out
+in, scaled to the points, 72 to the inch, that PDF measures in. Streams
+are written uncompressed.
+
+Text is drawn by glyph in an embedded TrueType font, subset to the glyphs
+the document uses. A font that can't be subset, or one of the standard
+14 that every reader has, draws text from its characters instead.
+*/
+
+use au = gfx::geometry;
+use au::Au;
+use geom::rect::Rect;
+use gfx::display_list::{DisplayItem, SolidColor, Text, Image, Border};
+use layout::print::PrintedPage;
+use text::subset::subset_glyphs;
+use text::variable::{u16_at, sfnt_table, tag};
+use std::arc;
+
+const PT_PER_PX: float = 0.75;
+
+// Subset fonts are named with six capitals and a plus; the rest is free
+const SUBSET_FONT_NAME: &static/str = "SERVOA+EmbeddedFont";
+
+/// A font to draw text in.
+pub enum PdfFont {
+    // One of the standard 14, by name, such as Helvetica
+    StandardFont(~str),
+    // The data of a TrueType font
+    TrueTypeFont(~[u8]),
+}
+
+/// A PDF being put together, one object at a time. Objects are numbered
+/// from 1, in the order they're reserved.
+pub struct PdfWriter {
+    priv mut objects: ~[Option<~[u8]>],
+}
+
+pub fn PdfWriter() -> PdfWriter {
+    PdfWriter { objects: ~[] }
+}
+
+impl PdfWriter {
+    /// A number for an object that's written later.
+    fn reserve(&self) -> uint {
+        self.objects.push(None);
+        self.objects.len()
+    }
+
+    fn set(&self, id: uint, body: ~[u8]) {
+        self.objects[id - 1] = Some(move body);
+    }
+
+    fn add(&self, body: &str) -> uint {
+        let id = self.reserve();
+        self.set(id, str::to_bytes(body));
+        id
+    }
+
+    /// Adds a stream of `data`, whose dictionary has `entries` as well as
+    /// its length.
+    fn add_stream(&self, entries: &str, data: &[u8]) -> uint {
+        let mut body = str::to_bytes(fmt!("<< %s /Length %u >>\nstream\n", entries, data.len()));
+        body.push_all(data);
+        body.push_all(str::to_bytes("\nendstream"));
+        let id = self.reserve();
+        self.set(id, move body);
+        id
+    }
+
+    /// The file, with the cross-reference table that says where each
+    /// object is and a trailer naming the `catalog`.
+    fn finish(&self, catalog: uint) -> ~[u8] {
+        let mut out = str::to_bytes("%PDF-1.4\n");
+        // A comment of high bytes, so that the file is taken as binary
+        out.push_all([0x25, 0xe2, 0xe3, 0xcf, 0xd3, 0x0a]);
+
+        let mut offsets = ~[];
+        for self.objects.eachi |i, object| {
+            offsets.push(out.len());
+            out.push_all(str::to_bytes(fmt!("%u 0 obj\n", i + 1)));
+            match *object {
+                Some(ref body) => out.push_all(*body),
+                None => fail fmt!("PDF object %u was reserved but never written", i + 1)
+            }
+            out.push_all(str::to_bytes("\nendobj\n"));
+        }
+
+        let xref = out.len();
+        out.push_all(str::to_bytes(fmt!("xref\n0 %u\n0000000000 65535 f \n",
+                                        self.objects.len() + 1)));
+        for offsets.each |offset| {
+            out.push_all(str::to_bytes(padded(*offset, 10) + ~" 00000 n \n"));
+        }
+        out.push_all(str::to_bytes(fmt!("trailer\n<< /Size %u /Root %u 0 R >>\nstartxref\n%u\n%%%%EOF\n",
+                                        self.objects.len() + 1, catalog, xref)));
+        move out
+    }
+}
+
+// `n` in decimal, with zeros in front to make it `width` digits
+fn padded(n: uint, width: uint) -> ~str {
+    let digits = n.to_str();
+    let mut result = ~"";
+    for uint::range(digits.len(), width) |_i| {
+        str::push_char(&mut result, '0');
+    }
+    result + digits
+}
+
+fn num(n: float) -> ~str {
+    float::to_str(n, 3)
+}
+
+fn color(r: u8, g: u8, b: u8) -> ~str {
+    fmt!("%s %s %s", num(r as float / 255.0), num(g as float / 255.0), num(b as float / 255.0))
+}
+
+fn px(length: Au) -> ~str {
+    num(au::to_frac_px(length))
+}
+
+fn rect(bounds: &Rect<Au>) -> ~str {
+    fmt!("%s %s %s %s re", px(bounds.origin.x), px(bounds.origin.y),
+         px(bounds.size.width), px(bounds.size.height))
+}
+
+// A glyph id as a hex string, two bytes as Identity-H takes them
+fn glyph_string(glyph: u16) -> ~str {
+    let hex = uint::to_str(glyph as uint, 16);
+    ~"<" + str::from_chars(vec::from_elem(4 - hex.len(), '0')) + hex + ~">"
+}
+
+// `text` as a literal string in WinAnsiEncoding, which is close enough to
+// Latin-1; anything outside it becomes a question mark
+fn literal_string(text: &str) -> ~str {
+    let mut result = ~"(";
+    for str::each_char(text) |c| {
+        match c {
+            '(' | ')' | '\\' => {
+                str::push_char(&mut result, '\\');
+                str::push_char(&mut result, c);
+            }
+            ' '..'~' => str::push_char(&mut result, c),
+            '\xa0'..'\xff' => result += ~"\\" + uint::to_str(c as uint, 8),
+            _ => str::push_char(&mut result, '?')
+        }
+    }
+    result + ~")"
+}
+
+// A font added to the document, and how text is drawn in it
+struct DocumentFont {
+    id: uint,
+    // How far the baseline is below the top of the text, as a fraction of
+    // the font size
+    ascent: float,
+    // Whether text is drawn by glyph rather than by character
+    by_glyph: bool,
+}
+
+// The ascents of the standard fonts, from their metrics files
+fn standard_ascent(name: &str) -> float {
+    if name.starts_with("Times") {
+        0.683
+    } else if name.starts_with("Courier") {
+        0.629
+    } else {
+        0.718
+    }
+}
+
+fn add_standard_font(writer: &PdfWriter, name: &str) -> DocumentFont {
+    let id = writer.add(fmt!("<< /Type /Font /Subtype /Type1 /BaseFont /%s \
+                              /Encoding /WinAnsiEncoding >>", name));
+    DocumentFont { id: id, ascent: standard_ascent(name), by_glyph: false }
+}
+
+/**
+Embeds the TrueType font `data` with only `glyphs` in it, as a composite
+font whose character codes are glyph ids. None if it isn't a TrueType
+font.
+*/
+fn add_truetype_font(writer: &PdfWriter, data: &[u8], glyphs: &[u16]) -> Option<DocumentFont> {
+    let subset = subset_glyphs(data, glyphs);
+    let (head, hhea, hmtx) = match (sfnt_table(data, tag("head")), sfnt_table(data, tag("hhea")),
+                                    sfnt_table(data, tag("hmtx"))) {
+        (Some(move head), Some(move hhea), Some(move hmtx)) => (move head, move hhea, move hmtx),
+        _ => return None
+    };
+    if subset.is_none() || u16_at(head, 18) == 0 {
+        return None;
+    }
+    let subset = option::unwrap(move subset);
+
+    // Font units, in thousandths of the font size
+    let units_per_em = u16_at(head, 18) as float;
+    let scaled = |units: u16| num((units as i16) as float * 1000.0 / units_per_em);
+    let advance = |glyph: u16| {
+        let num_hmetrics = uint::max(u16_at(hhea, 34) as uint, 1);
+        let advance = u16_at(hmtx, uint::min(glyph as uint, num_hmetrics - 1) * 4);
+        num(advance as float * 1000.0 / units_per_em)
+    };
+
+    let file = writer.add_stream(fmt!("/Length1 %u", subset.len()), subset);
+    let descriptor = writer.add(fmt!(
+        "<< /Type /FontDescriptor /FontName /%s /Flags 4 /FontBBox [%s %s %s %s] \
+         /ItalicAngle 0 /Ascent %s /Descent %s /CapHeight %s /StemV 80 /FontFile2 %u 0 R >>",
+        SUBSET_FONT_NAME, scaled(u16_at(head, 36)), scaled(u16_at(head, 38)),
+        scaled(u16_at(head, 40)), scaled(u16_at(head, 42)), scaled(u16_at(hhea, 4)),
+        scaled(u16_at(hhea, 6)), scaled(u16_at(hhea, 4)), file));
+    let widths = str::concat(glyphs.map(|g| fmt!("%u [%s] ", *g as uint, advance(*g))));
+    let cid_font = writer.add(fmt!(
+        "<< /Type /Font /Subtype /CIDFontType2 /BaseFont /%s \
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> \
+         /FontDescriptor %u 0 R /CIDToGIDMap /Identity /W [%s] >>",
+        SUBSET_FONT_NAME, descriptor, widths));
+    let id = writer.add(fmt!("<< /Type /Font /Subtype /Type0 /BaseFont /%s \
+                              /Encoding /Identity-H /DescendantFonts [%u 0 R] >>",
+                             SUBSET_FONT_NAME, cid_font));
+    let ascent = (u16_at(hhea, 4) as i16) as float / units_per_em;
+    Some(DocumentFont { id: id, ascent: ascent, by_glyph: true })
+}
+
+fn add_font(writer: &PdfWriter, font: &PdfFont, glyphs: &[u16]) -> DocumentFont {
+    match *font {
+        StandardFont(ref name) => add_standard_font(writer, *name),
+        TrueTypeFont(ref data) => match add_truetype_font(writer, *data, glyphs) {
+            Some(move font) => move font,
+            None => {
+                warn!("pdf: the font can't be embedded; drawing text in Helvetica instead");
+                add_standard_font(writer, "Helvetica")
+            }
+        }
+    }
+}
+
+// Every glyph drawn on `pages`, in order, once each
+fn glyphs_used(pages: &[PrintedPage]) -> ~[u16] {
+    let mut glyphs = ~[];
+    for pages.each |page| {
+        for page.display_list.list.each |item| {
+            match **item {
+                Text(_, ref run, range, _, _, _) => {
+                    do run.glyphs().iter_glyphs_for_range(range) |_i, glyph| {
+                        glyphs.push(glyph.index() as u16);
+                    }
+                }
+                _ => ()
+            }
+        }
+    }
+    sort::quick_sort3(glyphs);
+    let mut unique = ~[];
+    for glyphs.each |glyph| {
+        if unique.is_empty() || unique.last() != *glyph {
+            unique.push(*glyph);
+        }
+    }
+    move unique
+}
+
+// An image XObject of a BGRA image. Its alpha goes in a soft mask, which
+// says that the colors have been multiplied by it
+fn add_image(writer: &PdfWriter, image: &image::base::Image) -> uint {
+    let pixels = image.width * image.height;
+    let mut rgb = vec::with_capacity(pixels * 3);
+    let mut alpha = vec::with_capacity(pixels);
+    for uint::range(0, pixels) |i| {
+        rgb.push(image.data[i * 4 + 2]);
+        rgb.push(image.data[i * 4 + 1]);
+        rgb.push(image.data[i * 4]);
+        alpha.push(image.data[i * 4 + 3]);
+    }
+    let smask = if alpha.all(|a| *a == 255) {
+        ~""
+    } else {
+        let mask = writer.add_stream(fmt!("/Type /XObject /Subtype /Image /Width %u /Height %u \
+                                           /ColorSpace /DeviceGray /BitsPerComponent 8 \
+                                           /Matte [0 0 0]", image.width, image.height), alpha);
+        fmt!(" /SMask %u 0 R", mask)
+    };
+    writer.add_stream(fmt!("/Type /XObject /Subtype /Image /Width %u /Height %u \
+                            /ColorSpace /DeviceRGB /BitsPerComponent 8%s",
+                           image.width, image.height, smask), rgb)
+}
+
+/**
+The content stream of `page`, and the images it draws, which it names
+`/Im0`, `/Im1` and so on. It's drawn in px, down from the top of the
+page, and clipped to the page's content area.
+*/
+fn page_content(writer: &PdfWriter, page: &PrintedPage, font: &DocumentFont,
+                font_size: float) -> (~str, ~[uint]) {
+    let height = au::to_frac_px(page.size.height) * PT_PER_PX;
+    let mut content = fmt!("q %s 0 0 %s 0 %s cm\n%s W n\n", num(PT_PER_PX), num(-PT_PER_PX),
+                           num(height), rect(&page.content_area));
+    let mut images = ~[];
+    for page.display_list.list.each |item| {
+        let bounds = &item.d().bounds;
+        match **item {
+            SolidColor(_, r, g, b) => {
+                content += fmt!("%s rg %s f\n", color(r, g, b), rect(bounds));
+            }
+            Border(_, width, r, g, b) => {
+                content += fmt!("%s RG %s w %s S\n", color(r, g, b), px(width), rect(bounds));
+            }
+            Image(_, ref image) => {
+                let image = arc::get(image);
+                content += fmt!("q %s 0 0 %s %s %s cm /Im%u Do Q\n", px(bounds.size.width),
+                                num(-au::to_frac_px(bounds.size.height)), px(bounds.origin.x),
+                                px(bounds.origin.y + bounds.size.height), images.len());
+                images.push(add_image(writer, &**image));
+            }
+            Text(_, ref run, range, r, g, b) => {
+                // Text is drawn the right way up in the flipped page
+                let baseline = au::to_frac_px(bounds.origin.y) + font.ascent * font_size;
+                content += fmt!("BT /F1 %s Tf %s rg\n", num(font_size), color(r, g, b));
+                if font.by_glyph {
+                    let mut x = bounds.origin.x;
+                    do run.glyphs().iter_glyphs_for_range(range) |_i, glyph| {
+                        let offset = glyph.offset().get_default(au::zero_point());
+                        content += fmt!("1 0 0 -1 %s %s Tm %s Tj\n", px(x + offset.x),
+                                        num(baseline + au::to_frac_px(offset.y)),
+                                        glyph_string(glyph.index() as u16));
+                        x = x + glyph.advance();
+                    }
+                } else {
+                    let text = str::slice(run.text, range.begin(), range.end());
+                    content += fmt!("1 0 0 -1 %s %s Tm %s Tj\n", px(bounds.origin.x),
+                                    num(baseline), literal_string(text));
+                }
+                content += ~"ET\n";
+            }
+        }
+    }
+    content += ~"Q\n";
+    (move content, move images)
+}
+
+/// A PDF of `pages`, with their text in `font` at `font_size` px.
+pub fn write_pdf(pages: &[PrintedPage], font: &PdfFont, font_size: float) -> ~[u8] {
+    let writer = PdfWriter();
+    let catalog = writer.reserve();
+    let page_tree = writer.reserve();
+    let font = add_font(&writer, font, glyphs_used(pages));
+
+    let mut kids = ~[];
+    for pages.each |page| {
+        let (content, images) = page_content(&writer, page, &font, font_size);
+        let contents = writer.add_stream("", str::to_bytes(content));
+        let xobjects = str::concat(do images.mapi |i, id| { fmt!(" /Im%u %u 0 R", i, *id) });
+        kids.push(writer.add(fmt!(
+            "<< /Type /Page /Parent %u 0 R /MediaBox [0 0 %s %s] \
+             /Resources << /Font << /F1 %u 0 R >> /XObject <<%s >> >> /Contents %u 0 R >>",
+            page_tree, num(au::to_frac_px(page.size.width) * PT_PER_PX),
+            num(au::to_frac_px(page.size.height) * PT_PER_PX), font.id, xobjects, contents)));
+    }
+    let kid_refs = str::connect(kids.map(|id| fmt!("%u 0 R", *id)), " ");
+    writer.set(page_tree, str::to_bytes(fmt!("<< /Type /Pages /Kids [%s] /Count %u >>",
+                                             kid_refs, kids.len())));
+    writer.set(catalog, str::to_bytes(fmt!("<< /Type /Catalog /Pages %u 0 R >>", page_tree)));
+    writer.finish(catalog)
+}
+
+#[cfg(test)]
+mod pdf_writer_tests {
+    use geom::point::Point2D;
+    use geom::size::Size2D;
+    use gfx::display_list::DisplayList;
+
+    // The file as text, with its binary comment made readable
+    fn as_text(pdf: &[u8]) -> ~str {
+        str::from_chars(pdf.map(|b| *b as char))
+    }
+
+    #[test]
+    fn test_cross_references() {
+        let writer = PdfWriter();
+        let catalog = writer.reserve();
+        let pages = writer.add("<< /Type /Pages /Kids [] /Count 0 >>");
+        writer.set(catalog, str::to_bytes(fmt!("<< /Type /Catalog /Pages %u 0 R >>", pages)));
+        let pdf = as_text(writer.finish(catalog));
+
+        assert pdf.starts_with("%PDF-1.4");
+        assert pdf.ends_with("%%EOF\n");
+        let xref = str::find_str(pdf, "xref\n0 3\n").get();
+        assert str::find_str(pdf, fmt!("startxref\n%u\n", xref)).is_some();
+        // Each entry points at its object
+        for uint::range(1, 3) |n| {
+            let entry = xref + 9 + 20 * n;
+            let offset = uint::from_str(str::slice(pdf, entry, entry + 10)).get();
+            assert str::slice(pdf, offset, pdf.len()).starts_with(fmt!("%u 0 obj", n));
+        }
+    }
+
+    #[test]
+    fn test_write_pdf() {
+        let mut list = DisplayList::new();
+        let bounds = Rect(Point2D(au::from_px(10), au::from_px(20)),
+                          Size2D(au::from_px(30), au::from_px(40)));
+        list.append_item(~DisplayItem::new_SolidColor(&bounds, 255, 0, 0));
+        let page = PrintedPage {
+            size: Size2D(au::from_px(800), au::from_px(400)),
+            content_area: Rect(Point2D(au::from_px(0), au::from_px(0)),
+                               Size2D(au::from_px(800), au::from_px(400))),
+            dpi: 72,
+            display_list: move list
+        };
+        let pdf = as_text(write_pdf([move page], &StandardFont(~"Helvetica"), 16.0));
+
+        assert str::find_str(pdf, "/Type /Pages /Kids [").is_some();
+        assert str::find_str(pdf, "/Count 1").is_some();
+        assert str::find_str(pdf, "/MediaBox [0 0 600 300]").is_some();
+        assert str::find_str(pdf, "/BaseFont /Helvetica").is_some();
+        assert str::find_str(pdf, "0.75 0 0 -0.75 0 300 cm").is_some();
+        assert str::find_str(pdf, "1 0 0 rg 10 20 30 40 re f").is_some();
+    }
+
+    #[test]
+    fn test_literal_string() {
+        assert literal_string("a (b) \\") == ~"(a \\(b\\) \\\\)";
+        assert literal_string("café ☺") == ~"(caf\\351 ?)";
+        assert glyph_string(0x2a) == ~"<002a>";
+    }
+}
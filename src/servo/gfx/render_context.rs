@@ -76,6 +76,26 @@ impl RenderContext  {
                                      draw_surface_options, draw_options);
     }
 
+    // Draws premultiplied BGRA pixels unscaled, with their top left at `origin`
+    pub fn draw_bitmap(&self, origin: Point2D<int>, size: Size2D<int>, data: &[u8]) {
+        if size.width <= 0 || size.height <= 0 {
+            return;
+        }
+        let draw_target_ref = &self.canvas.draw_target;
+        let surface_size = Size2D(size.width as i32, size.height as i32);
+        let azure_surface = draw_target_ref.create_source_surface_from_data(data, surface_size,
+                                                                            (size.width * 4) as i32,
+                                                                            B8G8R8A8);
+        let source_rect = Rect(Point2D(0 as AzFloat, 0 as AzFloat),
+                               Size2D(size.width as AzFloat, size.height as AzFloat));
+        let dest_rect = Rect(Point2D(origin.x as AzFloat, origin.y as AzFloat),
+                             Size2D(size.width as AzFloat, size.height as AzFloat));
+        let draw_surface_options = DrawSurfaceOptions(Linear, true);
+        let draw_options = DrawOptions(1.0f as AzFloat, 0);
+        draw_target_ref.draw_surface(move azure_surface, dest_rect, source_rect,
+                                     draw_surface_options, draw_options);
+    }
+
     fn clear(&self) {
         let pattern = ColorPattern(Color(1f as AzFloat, 1f as AzFloat, 1f as AzFloat, 1f as AzFloat));
         let rect = Rect(Point2D(self.canvas.rect.origin.x as AzFloat,
@@ -4,35 +4,106 @@ Some little helpers for hooking up the HTML parser with the CSS parser
 
 use std::net::url::Url;
 use std::cell::Cell;
-use resource::resource_task::{ResourceTask, ProgressMsg, Load, Payload, Done};
+use resource::resource_task::{ResourceTask, ProgressMsg, LoadFor, ContentType, Header, Payload,
+                              Timing, Done, TimedFetch};
 use newcss::values::Stylesheet;
 use newcss::util::{DataStream, DataStreamFactory};
+use text::font_display::{FontDisplay, font_face_rules, font_face_url};
+use util::url::make_url;
 
-pub fn spawn_css_parser(url: Url, resource_task: ResourceTask) -> comm::Port<Stylesheet> {
+/// The font of an `@font-face` rule, to be loaded from `url`.
+pub struct WebFontFace {
+    family: ~str,
+    url: Url,
+    display: FontDisplay,
+}
+
+/**
+Parses the style sheet at `url`, loaded by the page at `page_url`, sending
+how long it took to load to `timing_chan`. The CSS library doesn't keep `@font-face` rules, so the
+faces are read from the source as it goes past, and come with the sheet.
+*/
+pub fn spawn_css_parser(url: Url, page_url: Url, resource_task: ResourceTask,
+                        timing_chan: comm::Chan<TimedFetch>)
+    -> comm::Port<(Stylesheet, ~[WebFontFace])> {
     let result_port = comm::Port();
     let result_chan = comm::Chan(&result_port);
-    do task::spawn |move url, copy resource_task| {
-        let sheet = newcss::parser::parse_stylesheet(copy url, data_stream_factory(copy url, resource_task));
-        result_chan.send(move sheet);
+    do task::spawn |move url, move page_url, copy resource_task| {
+        let source_port = comm::Port();
+        let source_chan = comm::Chan(&source_port);
+        let sheet = newcss::parser::parse_stylesheet(copy url, data_stream_factory(copy url,
+                                                                                   copy page_url,
+                                                                                   resource_task,
+                                                                                   timing_chan,
+                                                                                   source_chan));
+        // The parser has read the whole sheet by the time it's done
+        let mut source = ~[];
+        while source_port.peek() {
+            source.push_all_move(source_port.recv());
+        }
+        let faces = web_font_faces(str::from_bytes(source), &url);
+        result_chan.send((move sheet, move faces));
     }
 
     return result_port;
 }
 
-fn data_stream_factory(url: Url, resource_task: ResourceTask) -> DataStreamFactory {
+/// The faces of the `@font-face` rules in `css`, with their URLs resolved
+/// against the sheet's.
+pub fn web_font_faces(css: &str, sheet_url: &Url) -> ~[WebFontFace] {
+    do font_face_rules(css).filter_map |rule| {
+        do font_face_url(rule.src).map |url| {
+            WebFontFace {
+                family: copy rule.family,
+                url: make_url(copy *url, Some(copy *sheet_url)),
+                display: rule.display
+            }
+        }
+    }
+}
+
+fn data_stream_factory(url: Url, page_url: Url, resource_task: ResourceTask,
+                       timing_chan: comm::Chan<TimedFetch>,
+                       source_chan: comm::Chan<~[u8]>) -> DataStreamFactory {
     let url = Cell(move url);
-    return |move url| {
+    return |move url, move page_url| {
+        let url = url.take();
         let input_port = Port();
-        resource_task.send(Load(url.take(), input_port.chan()));
-        resource_port_to_data_stream(input_port)
+        resource_task.send(LoadFor(copy url, copy page_url, input_port.chan()));
+        resource_port_to_data_stream(input_port, move url, timing_chan, source_chan)
     }
 }
 
-fn resource_port_to_data_stream(input_port: comm::Port<ProgressMsg>) -> DataStream {
-    return || {
-        match input_port.recv() {
-            Payload(move data) => Some(move data),
-            Done(*) => None
+fn resource_port_to_data_stream(input_port: comm::Port<ProgressMsg>, url: Url,
+                                timing_chan: comm::Chan<TimedFetch>,
+                                source_chan: comm::Chan<~[u8]>) -> DataStream {
+    let url = Cell(move url);
+    return |move url| {
+        loop {
+            match input_port.recv() {
+                ContentType(*) | Header(*) => (),
+                Payload(move data) => {
+                    source_chan.send(copy data);
+                    return Some(move data);
+                }
+                Timing(move timing) => {
+                    timing_chan.send(TimedFetch { url: url.take(), initiator_type: ~"link",
+                                                  timing: move timing });
+                }
+                Done(*) => return None
+            }
         }
     }
 }
+
+#[test]
+fn test_web_font_faces() {
+    let sheet_url = make_url(~"http://example.com/css/site.css", None);
+    let faces = web_font_faces("@font-face { font-family: Josefin; src: url(fonts/j.woff2);
+                                              font-display: swap }
+                                @font-face { font-family: System; src: local(Arial) }",
+                               &sheet_url);
+    assert faces.len() == 1;
+    assert faces[0].family == ~"Josefin";
+    assert faces[0].url.path == ~"/css/fonts/j.woff2";
+}
@@ -6,17 +6,26 @@ use dom::element::*;
 use dom::event::{Event, ReflowEvent};
 use dom::node::{Comment, Doctype, DoctypeData, Text,
                 Element, Node, NodeScope};
+use resource::buffer_pool::BufferPool;
 use resource::image_cache_task::ImageCacheTask;
 use resource::image_cache_task;
-use resource::resource_task::{Done, Load, Payload, ResourceTask};
+use resource::resource_task::{ContentType, Done, Header, Load, LoadFor, Payload, Post, ResourceTask,
+                              Timing, TimedFetch};
 
 use hubbub::Attribute;
 
 use comm::{Chan, Port};
 use std::net::url::Url;
-use cssparse::spawn_css_parser;
+use cssparse::{spawn_css_parser, WebFontFace};
 
-type JSResult = ~[~[u8]];
+/// A script the page runs, in document order.
+pub enum Script {
+    ClassicScript(~[u8]),
+    // Fetched along with its imports by the content task
+    ModuleScript(Url),
+}
+
+type JSResult = ~[Script];
 
 enum CSSMessage {
     CSSTaskNewFile(Url),
@@ -25,13 +34,20 @@ enum CSSMessage {
 
 enum JSMessage {
     JSTaskNewFile(Url),
+    JSTaskNewModule(Url),
     JSTaskExit
 }
 
 struct HtmlParserResult {
     root: Node,
-    style_port: comm::Port<Stylesheet>,
+    // The page's style sheets, parsed on other tasks, and the fonts of
+    // their `@font-face` rules. This can be sent, so they can be waited for
+    // off the content task
+    style_port: pipes::Port<(Stylesheet, ~[WebFontFace])>,
     js_port: comm::Port<JSResult>,
+    // How long each script and style sheet took to load. All of them have
+    // been sent by the time the scripts and style sheets have
+    timing_port: comm::Port<TimedFetch>,
 }
 
 /**
@@ -45,18 +61,23 @@ spawned, collates them, and sends them to the given result channel.
 
 # Arguments
 
-* `to_parent` - A channel on which to send back the full set of rules.
+* `to_parent` - A channel on which to send back the full set of rules, and
+  the faces of the `@font-face` rules.
 * `from_parent` - A port on which to receive new links.
+* `page_url` - The URL of the page the links are in, which loads them.
+* `timing_chan` - A channel on which to send how long each load took.
 
 */
-fn css_link_listener(to_parent : comm::Chan<Stylesheet>, from_parent : comm::Port<CSSMessage>,
-                     resource_task: ResourceTask) {
+fn css_link_listener(to_parent : pipes::Chan<(Stylesheet, ~[WebFontFace])>,
+                     from_parent : comm::Port<CSSMessage>, page_url: Url,
+                     resource_task: ResourceTask, timing_chan: comm::Chan<TimedFetch>) {
     let mut result_vec = ~[];
 
     loop {
         match from_parent.recv() {
             CSSTaskNewFile(move url) => {
-                result_vec.push(spawn_css_parser(move url, copy resource_task));
+                result_vec.push(spawn_css_parser(move url, copy page_url, copy resource_task,
+                                                 timing_chan));
             }
             CSSTaskExit => {
                 break;
@@ -64,13 +85,21 @@ fn css_link_listener(to_parent : comm::Chan<Stylesheet>, from_parent : comm::Por
         }
     }
 
-    let css_rules = vec::flat_map(result_vec, |result_port| { result_port.recv() });
+    let mut css_rules = ~[];
+    let mut faces = ~[];
+    for result_vec.each |result_port| {
+        let (move sheet, move sheet_faces) = result_port.recv();
+        css_rules.push_all_move(move sheet);
+        faces.push_all_move(move sheet_faces);
+    }
     
-    to_parent.send(move css_rules);
+    to_parent.send((move css_rules, move faces));
 }
 
-fn js_script_listener(to_parent : comm::Chan<~[~[u8]]>, from_parent : comm::Port<JSMessage>,
-                      resource_task: ResourceTask) {
+fn js_script_listener(to_parent : comm::Chan<JSResult>, from_parent : comm::Port<JSMessage>,
+                      page_url: Url, resource_task: ResourceTask,
+                      timing_chan: comm::Chan<TimedFetch>,
+                      buffer_pool: BufferPool) {
     let mut result_vec = ~[];
 
     loop {
@@ -78,19 +107,30 @@ fn js_script_listener(to_parent : comm::Chan<~[~[u8]]>, from_parent : comm::Port
             JSTaskNewFile(move url) => {
                 let result_port = comm::Port();
                 let result_chan = comm::Chan(&result_port);
-                do task::spawn |move url| {
+                let buffer_pool = buffer_pool.clone();
+                let page_url = copy page_url;
+                do task::spawn |move url, move page_url, move buffer_pool| {
                     let input_port = Port();
                     // TODO: change copy to move once we can move into closures
-                    resource_task.send(Load(copy url, input_port.chan()));
+                    resource_task.send(LoadFor(copy url, copy page_url, input_port.chan()));
 
-                    let mut buf = ~[];
+                    // The body is copied into a buffer from the pool once
+                    // it's all in, which the content task gives back after
+                    // running the script
+                    let mut chunks = ~[];
                     loop {
                         match input_port.recv() {
+                            ContentType(*) | Header(*) => (),
                             Payload(move data) => {
-                                buf += data;
+                                chunks.push(move data);
+                            }
+                            Timing(move timing) => {
+                                timing_chan.send(TimedFetch { url: copy url,
+                                                              initiator_type: ~"script",
+                                                              timing: move timing });
                             }
                             Done(Ok(*)) => {
-                                result_chan.send(move buf);
+                                result_chan.send(ClassicScript(buffer_pool.copy_chunks(chunks)));
                                 break;
                             }
                             Done(Err(*)) => {
@@ -101,6 +141,11 @@ fn js_script_listener(to_parent : comm::Chan<~[~[u8]]>, from_parent : comm::Port
                 }
                 vec::push(&mut result_vec, result_port);
             }
+            JSTaskNewModule(move url) => {
+                let result_port = comm::Port();
+                comm::Chan(&result_port).send(ModuleScript(move url));
+                vec::push(&mut result_vec, result_port);
+            }
             JSTaskExit => {
                 break;
             }
@@ -118,6 +163,7 @@ fn build_element_kind(tag: &str) -> ~ElementKind {
     else if tag == ~"br" { ~HTMLBRElement }
     else if tag == ~"body" { ~HTMLBodyElement }
     else if tag == ~"bold" { ~HTMLBoldElement }
+    else if tag == ~"button" { ~HTMLButtonElement }
     else if tag == ~"div" { ~HTMLDivElement }
     else if tag == ~"font" { ~HTMLFontElement }
     else if tag == ~"form" { ~HTMLFormElement }
@@ -131,7 +177,7 @@ fn build_element_kind(tag: &str) -> ~ElementKind {
     else if tag == ~"h6" { ~HTMLHeadingElement(Heading6) }
     else if tag == ~"html" { ~HTMLHtmlElement }
     else if tag == ~"img" { ~HTMLImageElement(HTMLImageData()) }
-    else if tag == ~"input" { ~HTMLInputElement }
+    else if tag == ~"input" { ~HTMLInputElement(HTMLInputData()) }
     else if tag == ~"i" { ~HTMLItalicElement }
     else if tag == ~"link" { ~HTMLLinkElement }
     else if tag == ~"li" { ~HTMLListItemElement }
@@ -139,6 +185,10 @@ fn build_element_kind(tag: &str) -> ~ElementKind {
     else if tag == ~"ol" { ~HTMLOListElement }
     else if tag == ~"option" { ~HTMLOptionElement }
     else if tag == ~"p" { ~HTMLParagraphElement }
+    else if tag == ~"rb" { ~HTMLRubyBaseElement }
+    else if tag == ~"rp" { ~HTMLRubyParenthesisElement }
+    else if tag == ~"rt" { ~HTMLRubyTextElement }
+    else if tag == ~"ruby" { ~HTMLRubyElement }
     else if tag == ~"script" { ~HTMLScriptElement }
     else if tag == ~"section" { ~HTMLSectionElement }
     else if tag == ~"select" { ~HTMLSelectElement }
@@ -148,6 +198,7 @@ fn build_element_kind(tag: &str) -> ~ElementKind {
     else if tag == ~"tbody" { ~HTMLTableBodyElement }
     else if tag == ~"td" { ~HTMLTableCellElement }
     else if tag == ~"table" { ~HTMLTableElement }
+    else if tag == ~"textarea" { ~HTMLTextAreaElement }
     else if tag == ~"tr" { ~HTMLTableRowElement }
     else if tag == ~"title" { ~HTMLTitleElement }
     else if tag == ~"ul" { ~HTMLUListElement }
@@ -156,20 +207,30 @@ fn build_element_kind(tag: &str) -> ~ElementKind {
 
 pub fn parse_html(scope: NodeScope,
                   url: Url,
+                  body: Option<(~[u8], ~str)>,
                   resource_task: ResourceTask,
-                  image_cache_task: ImageCacheTask) -> HtmlParserResult unsafe {
+                  image_cache_task: ImageCacheTask,
+                  buffer_pool: BufferPool) -> HtmlParserResult unsafe {
+    let timing_port = comm::Port();
+    let timing_chan = comm::Chan(&timing_port);
+
     // Spawn a CSS parser to receive links to CSS style sheets.
-    let (css_port, css_chan): (comm::Port<Stylesheet>, comm::Chan<CSSMessage>) =
-            do task::spawn_conversation |css_port: comm::Port<CSSMessage>,
-                                         css_chan: comm::Chan<Stylesheet>| {
-        css_link_listener(css_chan, css_port, resource_task);
+    let (style_chan, style_port) = pipes::stream();
+    let page_url = copy url;
+    let css_chan: comm::Chan<CSSMessage> =
+            do task::spawn_listener |css_port: comm::Port<CSSMessage>, move style_chan,
+                                     move page_url| {
+        css_link_listener(style_chan, css_port, copy page_url, resource_task, timing_chan);
     };
 
     // Spawn a JS parser to receive JavaScript.
+    let page_url = copy url;
     let (js_port, js_chan): (comm::Port<JSResult>, comm::Chan<JSMessage>) =
             do task::spawn_conversation |js_port: comm::Port<JSMessage>,
-                                         js_chan: comm::Chan<JSResult>| {
-        js_script_listener(js_chan, js_port, resource_task);
+                                         js_chan: comm::Chan<JSResult>, move buffer_pool,
+                                         move page_url| {
+        js_script_listener(js_chan, js_port, copy page_url, resource_task, timing_chan,
+                           move buffer_pool);
     };
 
     let (scope, url) = (@copy scope, @move url);
@@ -232,12 +293,16 @@ pub fn parse_html(scope: NodeScope,
                     }
                 },
                 ~HTMLImageElement(copy d) => {  // FIXME: Bad copy.
+                    d.loading = ImageLoading::from_attr(elem.get_attr(~"loading"));
                     do elem.get_attr(~"src").iter |img_url_str| {
                         let img_url = make_url(copy *img_url_str, Some(copy *url));
                         d.image = Some(copy img_url);
                         // inform the image cache to load this, but don't store a handle.
+                        // Lazy images wait until layout finds them near the viewport.
                         // TODO (Issue #84): don't prefetch if we are within a <noscript> tag.
-                        image_cache_task.send(image_cache_task::Prefetch(move img_url));
+                        if !d.loading.is_lazy() {
+                            image_cache_task.send(image_cache_task::Prefetch(move img_url));
+                        }
                     }
                 }
                 //TODO (Issue #86): handle inline styles ('style' attr)
@@ -305,11 +370,19 @@ pub fn parse_html(scope: NodeScope,
                 do scope.read(&cow::wrap(cast::transmute(script))) |node_contents| {
                     match *node_contents.kind {
                         Element(element) if element.tag_name == ~"script" => {
+                            let is_module = match element.get_attr(~"type") {
+                                Some(move kind) => str::trim(kind).to_lower() == ~"module",
+                                None => false
+                            };
                             match element.get_attr(~"src") {
                                 Some(move src) => {
                                     debug!("found script: %s", src);
                                     let new_url = make_url(move src, Some(copy *url));
-                                    js_chan.send(JSTaskNewFile(move new_url));
+                                    if is_module {
+                                        js_chan.send(JSTaskNewModule(move new_url));
+                                    } else {
+                                        js_chan.send(JSTaskNewFile(move new_url));
+                                    }
                                 }
                                 None => {}
                             }
@@ -324,11 +397,19 @@ pub fn parse_html(scope: NodeScope,
     });
     debug!("set tree handler");
 
+    // A submitted form can POST a body to get the page
     let input_port = Port();
-    resource_task.send(Load(copy *url, input_port.chan()));
+    match move body {
+        Some((move body, move content_type)) => {
+            resource_task.send(Post(copy *url, move body, move content_type, input_port.chan()))
+        }
+        None => resource_task.send(Load(copy *url, input_port.chan()))
+    }
     debug!("loaded page");
     loop {
         match input_port.recv() {
+            // the page's own load is its navigation entry
+            ContentType(*) | Header(*) | Timing(*) => (),
             Payload(data) => {
                 debug!("received data");
                 parser.parse_chunk(data);
@@ -342,6 +423,7 @@ pub fn parse_html(scope: NodeScope,
     css_chan.send(CSSTaskExit);
     js_chan.send(JSTaskExit);
 
-    return HtmlParserResult { root: root, style_port: css_port, js_port: js_port };
+    return HtmlParserResult { root: root, style_port: move style_port, js_port: js_port,
+                              timing_port: timing_port };
 }
 
@@ -1,10 +1,22 @@
 export Image;
+export Frame;
+export LoopCount, LoopForever, LoopTimes;
+export ImageFormat, PNG, JPEG, GIF, WebP, AVIF;
 
 export load;
 export load_from_memory;
+export load_frames;
+export sniff_format;
+export format_for_mime;
+export premultiply;
 export test_image_bin;
 
 use stb_image = stb_image::image;
+use image::decoders::gif;
+#[cfg(avif)]
+use image::decoders::avif;
+#[cfg(webp)]
+use image::decoders::webp;
 
 // FIXME: Images must not be copied every frame. Instead we should atomically
 // reference count them.
@@ -21,7 +33,163 @@ fn test_image_bin() -> ~[u8] {
     return vec::from_fn(4962, |i| TEST_IMAGE[i]);
 }
 
+/// One frame of an image, with how long it's shown for if it's animated.
+pub struct Frame {
+    image: Image,
+    // In ms, or 0 for a still image
+    duration: uint,
+}
+
+/// How many times an animation plays.
+pub enum LoopCount {
+    LoopForever,
+    LoopTimes(uint)
+}
+
+impl LoopCount : cmp::Eq {
+    pure fn eq(&self, other: &LoopCount) -> bool {
+        match (*self, *other) {
+            (LoopForever, LoopForever) => true,
+            (LoopTimes(a), LoopTimes(b)) => a == b,
+            _ => false
+        }
+    }
+    pure fn ne(&self, other: &LoopCount) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// The formats that have decoders of their own. Anything else is given to
+/// stb_image, which also knows BMP and a few others.
+pub enum ImageFormat {
+    PNG,
+    JPEG,
+    GIF,
+    WebP,
+    AVIF
+}
+
+impl ImageFormat : cmp::Eq {
+    pure fn eq(&self, other: &ImageFormat) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &ImageFormat) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+fn starts_with(data: &[u8], offset: uint, prefix: &[u8]) -> bool {
+    data.len() >= offset + prefix.len() &&
+        vec::all2(vec::view(data, offset, offset + prefix.len()), prefix, |a, b| *a == *b)
+}
+
+/// The format of `data`, going by its magic bytes.
+pub fn sniff_format(data: &[u8]) -> Option<ImageFormat> {
+    if starts_with(data, 0, [0x89, 'P' as u8, 'N' as u8, 'G' as u8, 0x0d, 0x0a, 0x1a, 0x0a]) {
+        Some(PNG)
+    } else if starts_with(data, 0, [0xff, 0xd8, 0xff]) {
+        Some(JPEG)
+    } else if gif::is_gif(data) {
+        Some(GIF)
+    } else if starts_with(data, 0, str::to_bytes("RIFF")) &&
+              starts_with(data, 8, str::to_bytes("WEBP")) {
+        Some(WebP)
+    } else if starts_with(data, 4, str::to_bytes("ftyp")) &&
+              (starts_with(data, 8, str::to_bytes("avif")) ||
+               starts_with(data, 8, str::to_bytes("avis"))) {
+        Some(AVIF)
+    } else {
+        None
+    }
+}
+
+/// The format a MIME type names, ignoring any parameters.
+pub fn format_for_mime(mime_type: &str) -> Option<ImageFormat> {
+    let essence = str::trim(str::split_char(mime_type, ';')[0]).to_lower();
+    match essence {
+        ~"image/png" => Some(PNG),
+        ~"image/jpeg" | ~"image/jpg" | ~"image/pjpeg" => Some(JPEG),
+        ~"image/gif" => Some(GIF),
+        ~"image/webp" => Some(WebP),
+        ~"image/avif" => Some(AVIF),
+        _ => None
+    }
+}
+
+/**
+Decodes every frame of `data`. The format comes from the magic bytes, as
+servers often get image types wrong, and only from `mime_type` if they're
+not recognised. Pixels are premultiplied BGRA.
+*/
+pub fn load_frames(data: &[u8], mime_type: Option<&str>) -> Option<~[Frame]> {
+    let format = match sniff_format(data) {
+        Some(format) => Some(format),
+        None => match mime_type {
+            Some(mime_type) => format_for_mime(mime_type),
+            None => None
+        }
+    };
+    match format {
+        Some(GIF) => match gif::decode(data) {
+            Some((move frames, _)) => Some(move frames),
+            None => None
+        },
+        Some(WebP) => decode_webp(data),
+        Some(AVIF) => decode_avif(data),
+        Some(PNG) | Some(JPEG) | None => {
+            do load_with_stb(data).map |image| {
+                ~[Frame { image: copy *image, duration: 0 }]
+            }
+        }
+    }
+}
+
+// WebP and AVIF need libraries that configure may not have found, and
+// without them images in those formats don't load
+#[cfg(webp)]
+fn decode_webp(data: &[u8]) -> Option<~[Frame]> {
+    webp::decode(data)
+}
+
+#[cfg(not(webp))]
+fn decode_webp(_data: &[u8]) -> Option<~[Frame]> {
+    debug!("image: built without libwebp, so WebP can't be decoded");
+    None
+}
+
+#[cfg(avif)]
+fn decode_avif(data: &[u8]) -> Option<~[Frame]> {
+    avif::decode(data)
+}
+
+#[cfg(not(avif))]
+fn decode_avif(_data: &[u8]) -> Option<~[Frame]> {
+    debug!("image: built without libavif, so AVIF can't be decoded");
+    None
+}
+
+/// Decodes `data`, or its first frame if it's animated.
 pub fn load_from_memory(buffer: &[u8]) -> Option<Image> {
+    match load_frames(buffer, None) {
+        Some(move frames) => {
+            let mut frames = move frames;
+            if frames.is_empty() { None } else { Some(vec::shift(&mut frames).image) }
+        }
+        None => None
+    }
+}
+
+/// Premultiplies BGRA or RGBA pixels by their alpha.
+pub fn premultiply(data: &mut [u8]) {
+    for uint::range(0, data.len() / 4) |i| {
+        let alpha = data[i * 4 + 3] as uint;
+        for uint::range(0, 3) |c| {
+            data[i * 4 + c] = ((data[i * 4 + c] as uint * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+fn load_with_stb(buffer: &[u8]) -> Option<Image> {
     do stb_image::load_from_memory(buffer).map |image| {
 
         assert image.depth == 4;
@@ -43,3 +211,35 @@ pub fn load_from_memory(buffer: &[u8]) -> Option<Image> {
        Image(image.width, image.height, image.depth, move data)
     }
 }
+
+#[test]
+fn test_sniff_format() {
+    assert sniff_format(test_image_bin()) == Some(JPEG);
+    assert sniff_format(str::to_bytes("GIF89a\x01\x00")) == Some(GIF);
+    assert sniff_format(str::to_bytes("RIFF\x24\x00\x00\x00WEBPVP8 ")) == Some(WebP);
+    assert sniff_format(str::to_bytes("\x00\x00\x00\x1cftypavif")) == Some(AVIF);
+    assert sniff_format(str::to_bytes("\x00\x00\x00\x1cftypmp42")).is_none();
+    assert sniff_format(str::to_bytes("RIFF")).is_none();
+}
+
+#[test]
+fn test_format_for_mime() {
+    assert format_for_mime("image/webp") == Some(WebP);
+    assert format_for_mime("Image/AVIF; q=1") == Some(AVIF);
+    assert format_for_mime("image/jpg") == Some(JPEG);
+    assert format_for_mime("text/html").is_none();
+}
+
+#[test]
+fn test_premultiply() {
+    let mut pixels = ~[200u8, 100, 50, 255, 200, 100, 50, 128, 200, 100, 50, 0];
+    premultiply(pixels);
+    assert pixels == ~[200u8, 100, 50, 255, 100, 50, 25, 128, 0, 0, 0, 0];
+}
+
+#[test]
+fn test_load_jpeg() {
+    let frames = load_frames(test_image_bin(), Some("image/webp")).get();
+    assert frames.len() == 1;
+    assert frames[0].duration == 0;
+}
@@ -0,0 +1,274 @@
+/*!
+Decoded images, kept for as long as they fit in a budget of pixel bytes.
+When they don't, the least recently used are evicted first. The image cache
+task holds on to the encoded data of every image it has decoded, so an
+evicted image is only decoded again, not fetched again.
+*/
+
+use clone_arc = std::arc::clone;
+use geom::size::Size2D;
+use image::base::Image;
+use std::arc::{ARC, get};
+use std::net::url::Url;
+
+/// How much decoded pixel data is kept by default: 64MB.
+pub const DEFAULT_BUDGET: uint = 64 * 1024 * 1024;
+
+/// The resolution an image was decoded at. Everything is decoded at its
+/// natural size for now, but a downscaled decode of the same url would be
+/// an entry of its own.
+pub enum DecodeSize {
+    NaturalSize,
+    ScaledSize(Size2D<uint>)
+}
+
+impl DecodeSize : cmp::Eq {
+    pure fn eq(&self, other: &DecodeSize) -> bool {
+        match (*self, *other) {
+            (NaturalSize, NaturalSize) => true,
+            (ScaledSize(a), ScaledSize(b)) => a.width == b.width && a.height == b.height,
+            _ => false
+        }
+    }
+    pure fn ne(&self, other: &DecodeSize) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+impl DecodeSize : ToStr {
+    pure fn to_str() -> ~str {
+        match self {
+            NaturalSize => ~"natural",
+            ScaledSize(size) => fmt!("%ux%u", size.width, size.height)
+        }
+    }
+}
+
+struct Entry {
+    url: Url,
+    size: DecodeSize,
+    image: ARC<~Image>,
+    bytes: uint,
+}
+
+pub struct ImageCache {
+    // Least recently used first
+    priv mut entries: ~[Entry],
+    priv budget: uint,
+    priv mut bytes: uint,
+    priv mut hits: uint,
+    priv mut misses: uint,
+}
+
+pub fn ImageCache(budget: uint) -> ImageCache {
+    ImageCache {
+        entries: ~[],
+        budget: budget,
+        bytes: 0,
+        hits: 0,
+        misses: 0
+    }
+}
+
+/// What `about:imagecache` shows.
+pub struct ImageCacheReport {
+    // Each entry's url, size and pixel bytes, most recently used first
+    entries: ~[(~str, DecodeSize, uint)],
+    bytes: uint,
+    budget: uint,
+    hits: uint,
+    misses: uint,
+}
+
+impl ImageCache {
+    /// The image for `url` at `size`, if it's cached, which makes it the
+    /// most recently used.
+    fn find(&self, url: &Url, size: DecodeSize) -> Option<ARC<~Image>> {
+        match self.position(url, size) {
+            Some(i) => {
+                self.hits += 1;
+                let entry = vec::remove(&mut self.entries, i);
+                let image = clone_arc(&entry.image);
+                self.entries.push(move entry);
+                Some(move image)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /**
+    Caches `image` for `url` at `size`, then evicts the least recently used
+    images until the cache is within its budget. The image just cached is
+    never evicted, even if it's bigger than the budget on its own, or it
+    would be decoded again every time it was asked for.
+    */
+    fn insert(&self, url: Url, size: DecodeSize, image: ARC<~Image>) {
+        match self.position(&url, size) {
+            Some(i) => {
+                let old = vec::remove(&mut self.entries, i);
+                self.bytes -= old.bytes;
+            }
+            None => ()
+        }
+
+        let bytes = get(&image).data.len();
+        self.entries.push(Entry {
+            url: move url,
+            size: size,
+            image: move image,
+            bytes: bytes
+        });
+        self.bytes += bytes;
+
+        while self.bytes > self.budget && self.entries.len() > 1 {
+            let evicted = vec::shift(&mut self.entries);
+            #debug("image cache: evicting %s", evicted.url.to_str());
+            self.bytes -= evicted.bytes;
+        }
+    }
+
+    fn len(&self) -> uint {
+        self.entries.len()
+    }
+
+    /// The pixel bytes of every cached image.
+    fn bytes(&self) -> uint {
+        self.bytes
+    }
+
+    /// The bytes of the decoded images.
+    fn heap_size(&self) -> uint {
+        self.bytes
+    }
+
+    fn report(&self) -> ImageCacheReport {
+        let mut entries = ~[];
+        for self.entries.each_reverse |entry| {
+            entries.push((entry.url.to_str(), entry.size, entry.bytes));
+        }
+        ImageCacheReport {
+            entries: move entries,
+            bytes: self.bytes,
+            budget: self.budget,
+            hits: self.hits,
+            misses: self.misses
+        }
+    }
+
+    priv fn position(&self, url: &Url, size: DecodeSize) -> Option<uint> {
+        do self.entries.position |entry| {
+            entry.url == *url && entry.size == size
+        }
+    }
+}
+
+impl ImageCacheReport {
+    /// The share of lookups that found their image, as a percentage.
+    pure fn hit_rate() -> float {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            (self.hits as float) * 100.0 / (lookups as float)
+        }
+    }
+
+    /// The `about:imagecache` page.
+    fn to_html() -> ~str {
+        let mut html = ~"<html><head><title>about:imagecache</title></head><body>";
+        html += ~"<h1>Image cache</h1>";
+        html += fmt!("<p>%u images, %u of %u KB. %u hits and %u misses: a hit rate of %s%%.</p>",
+                     self.entries.len(), self.bytes / 1024, self.budget / 1024,
+                     self.hits, self.misses, float::to_str(self.hit_rate(), 1));
+        html += ~"<table><tr><th>URL</th><th>Resolution</th><th>KB</th></tr>";
+        for self.entries.each |entry| {
+            let (url, size, bytes) = copy *entry;
+            html += fmt!("<tr><td>%s</td><td>%s</td><td>%u</td></tr>",
+                         escape_html(url), size.to_str(), bytes / 1024);
+        }
+        html += ~"</table></body></html>";
+        move html
+    }
+}
+
+fn escape_html(s: &str) -> ~str {
+    let mut escaped = ~"";
+    for str::each_char(s) |c| {
+        match c {
+            '&' => escaped += ~"&amp;",
+            '<' => escaped += ~"&lt;",
+            '>' => escaped += ~"&gt;",
+            '"' => escaped += ~"&quot;",
+            c => str::push_char(&mut escaped, c)
+        }
+    }
+    move escaped
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use image::base::Image;
+    use util::url::make_url;
+
+    // A 10x10 image: 400 bytes
+    fn image() -> ARC<~Image> {
+        ARC(~Image(10, 10, 4, vec::from_elem(400, 0)))
+    }
+
+    #[test]
+    fn test_hits_and_misses() {
+        let cache = ImageCache(DEFAULT_BUDGET);
+        let url = make_url(~"http://example.com/a.png", None);
+        assert cache.find(&url, NaturalSize).is_none();
+        cache.insert(copy url, NaturalSize, image());
+        assert cache.find(&url, NaturalSize).is_some();
+        assert cache.find(&url, ScaledSize(Size2D(5u, 5u))).is_none();
+
+        // Caching a url again replaces its image
+        cache.insert(copy url, NaturalSize, image());
+        assert cache.len() == 1;
+        assert cache.bytes() == 400;
+
+        let report = cache.report();
+        assert report.hits == 1;
+        assert report.misses == 2;
+        assert float::abs(report.hit_rate() - 100.0 / 3.0) < 0.001;
+    }
+
+    #[test]
+    fn test_eviction() {
+        let cache = ImageCache(1000);
+        let a = make_url(~"http://example.com/a.png", None);
+        let b = make_url(~"http://example.com/b.png", None);
+        let c = make_url(~"http://example.com/c.png", None);
+        cache.insert(copy a, NaturalSize, image());
+        cache.insert(copy b, NaturalSize, image());
+        // Using a makes b the least recently used
+        assert cache.find(&a, NaturalSize).is_some();
+        cache.insert(copy c, NaturalSize, image());
+        assert cache.bytes() == 800;
+        assert cache.find(&b, NaturalSize).is_none();
+        assert cache.find(&a, NaturalSize).is_some();
+        assert cache.find(&c, NaturalSize).is_some();
+
+        // An image over budget on its own is still kept
+        let small = ImageCache(100);
+        small.insert(copy a, NaturalSize, image());
+        small.insert(copy b, NaturalSize, image());
+        assert small.len() == 1;
+        assert small.find(&b, NaturalSize).is_some();
+    }
+
+    #[test]
+    fn test_report() {
+        let cache = ImageCache(DEFAULT_BUDGET);
+        cache.insert(make_url(~"http://example.com/a.png?x=1&y=<2>", None),
+                     NaturalSize, image());
+        let html = cache.report().to_html();
+        assert str::contains(html, "a.png?x=1&amp;y=&lt;2&gt;");
+        assert str::contains(html, "1 images");
+    }
+}
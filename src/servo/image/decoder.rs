@@ -0,0 +1,94 @@
+/*!
+The one entry point for decoding an image, whatever its format. The image
+cache calls it on a task of its own for each image, so decoding never
+holds up content or layout.
+
+PNG and JPEG are decoded with stb_image, which is what the tree links for
+them; WebP, GIF and AVIF have decoders of their own, picked by
+`load_frames`.
+*/
+
+use image::base::{Image, Frame, ImageFormat, PNG, JPEG, GIF, WebP, AVIF, sniff_format,
+                  format_for_mime, load_frames};
+
+/// The frames of a decoded image, in premultiplied BGRA.
+pub struct DecodedImage {
+    format: ImageFormat,
+    width: uint,
+    height: uint,
+    // One for a still image
+    frames: ~[Frame],
+}
+
+impl DecodedImage {
+    /// The first frame, which is what a still image shows.
+    fn first_frame(&self) -> Image {
+        copy self.frames[0].image
+    }
+}
+
+pub enum DecodeError {
+    // Neither the bytes nor the MIME type say what the image is
+    UnknownFormat,
+    // The data isn't a valid image of the format it looks like
+    CorruptImage(ImageFormat),
+}
+
+impl DecodeError {
+    fn message(&self) -> ~str {
+        match *self {
+            UnknownFormat => ~"unknown image format",
+            CorruptImage(format) => fmt!("corrupt %s image", match format {
+                PNG => "PNG",
+                JPEG => "JPEG",
+                GIF => "GIF",
+                WebP => "WebP",
+                AVIF => "AVIF"
+            })
+        }
+    }
+}
+
+/**
+Decodes `bytes`. The format comes from the magic bytes, as servers often
+get image types wrong, and only from `mime` when they're not recognised.
+*/
+pub fn decode_image(bytes: &[u8], mime: &str) -> Result<DecodedImage, DecodeError> {
+    let format = match sniff_format(bytes) {
+        Some(format) => format,
+        None => match format_for_mime(mime) {
+            Some(format) => format,
+            None => return Err(UnknownFormat)
+        }
+    };
+    match load_frames(bytes, Some(mime)) {
+        Some(move frames) if !frames.is_empty() => {
+            let (width, height) = (frames[0].image.width, frames[0].image.height);
+            Ok(DecodedImage { format: format, width: width, height: height,
+                              frames: move frames })
+        }
+        _ => Err(CorruptImage(format))
+    }
+}
+
+#[cfg(test)]
+mod decoder_tests {
+    use image::base::test_image_bin;
+
+    #[test]
+    fn test_decode_image() {
+        let decoded = result::unwrap(decode_image(test_image_bin(), "image/png"));
+        assert decoded.format == JPEG;
+        assert decoded.frames.len() == 1;
+        assert decoded.width == decoded.first_frame().width;
+
+        match decode_image(str::to_bytes("not an image"), "text/plain") {
+            Err(UnknownFormat) => (),
+            _ => fail
+        }
+        match decode_image(str::to_bytes("RIFF\x24\x00\x00\x00WEBPVP8 "), "") {
+            Err(CorruptImage(WebP)) => (),
+            _ => fail
+        }
+    }
+}
@@ -0,0 +1,110 @@
+/*!
+AVIF decoding with libavif. The image is decoded to YUV, then converted to
+premultiplied BGRA, keeping its alpha plane if it has one; without one
+every pixel is opaque. Only the first frame of an image sequence is read.
+*/
+
+use image::base::{Image, Frame};
+use libc::{c_int, size_t};
+use ptr::null;
+
+const AVIF_RESULT_OK: c_int = 0;
+// avifRGBFormat
+const AVIF_RGB_FORMAT_BGRA: c_int = 4;
+
+pub fn decode(data: &[u8]) -> Option<~[Frame]> unsafe {
+    let decoder = avif::avifDecoderCreate();
+    if decoder.is_null() {
+        return None;
+    }
+    let image = avif::avifImageCreateEmpty();
+    let result = do vec::as_imm_buf(data) |buf, len| {
+        avif::avifDecoderReadMemory(decoder, image, buf, len as size_t)
+    };
+    let frames = if result == AVIF_RESULT_OK {
+        match to_bgra(image) {
+            Some(move image) => Some(~[Frame { image: move image, duration: 0 }]),
+            None => None
+        }
+    } else {
+        #debug("avif: unable to decode: %d", result as int);
+        None
+    };
+    avif::avifImageDestroy(image);
+    avif::avifDecoderDestroy(decoder);
+    move frames
+}
+
+unsafe fn to_bgra(image: *AvifImage) -> Option<Image> {
+    let rgb = AvifRGBImage {
+        width: 0,
+        height: 0,
+        depth: 0,
+        format: 0,
+        chroma_upsampling: 0,
+        chroma_downsampling: 0,
+        avoid_lib_yuv: 0,
+        ignore_alpha: 0,
+        alpha_premultiplied: 0,
+        is_float: 0,
+        max_threads: 0,
+        pixels: null(),
+        row_bytes: 0
+    };
+    avif::avifRGBImageSetDefaults(ptr::to_unsafe_ptr(&rgb), image);
+    rgb.format = AVIF_RGB_FORMAT_BGRA;
+    // 10 and 12 bit images come down to 8 bits
+    rgb.depth = 8;
+    rgb.alpha_premultiplied = 1;
+    if avif::avifRGBImageAllocatePixels(ptr::to_unsafe_ptr(&rgb)) != AVIF_RESULT_OK {
+        return None;
+    }
+
+    let result = if avif::avifImageYUVToRGB(image, ptr::to_unsafe_ptr(&rgb)) == AVIF_RESULT_OK {
+        let (width, height) = (rgb.width as uint, rgb.height as uint);
+        let mut bytes = vec::with_capacity(width * height * 4);
+        for uint::range(0, height) |y| {
+            let row = ptr::offset(rgb.pixels, y * (rgb.row_bytes as uint));
+            bytes.push_all(vec::raw::from_buf_raw(row, width * 4));
+        }
+        Some(Image(width, height, 4, move bytes))
+    } else {
+        None
+    };
+    avif::avifRGBImageFreePixels(ptr::to_unsafe_ptr(&rgb));
+    move result
+}
+
+enum AvifDecoder {}
+enum AvifImage {}
+
+// As of libavif 1.0. It's part of the ABI, which only changes with the
+// major version, so configure only takes libavif 1.x
+struct AvifRGBImage {
+    mut width: u32,
+    mut height: u32,
+    mut depth: u32,
+    mut format: c_int,
+    mut chroma_upsampling: c_int,
+    mut chroma_downsampling: c_int,
+    mut avoid_lib_yuv: c_int,
+    mut ignore_alpha: c_int,
+    mut alpha_premultiplied: c_int,
+    mut is_float: c_int,
+    mut max_threads: c_int,
+    mut pixels: *u8,
+    mut row_bytes: u32,
+}
+
+extern mod avif {
+    fn avifDecoderCreate() -> *AvifDecoder;
+    fn avifDecoderDestroy(decoder: *AvifDecoder);
+    fn avifDecoderReadMemory(decoder: *AvifDecoder, image: *AvifImage, data: *u8,
+                             size: size_t) -> c_int;
+    fn avifImageCreateEmpty() -> *AvifImage;
+    fn avifImageDestroy(image: *AvifImage);
+    fn avifRGBImageSetDefaults(rgb: *AvifRGBImage, image: *AvifImage);
+    fn avifRGBImageAllocatePixels(rgb: *AvifRGBImage) -> c_int;
+    fn avifRGBImageFreePixels(rgb: *AvifRGBImage);
+    fn avifImageYUVToRGB(image: *AvifImage, rgb: *AvifRGBImage) -> c_int;
+}
@@ -0,0 +1,465 @@
+/*!
+GIF decoding, with every frame of an animation. Frames are composited onto
+the canvas as the disposal methods say, so each is a whole picture, and the
+canvas starts out transparent rather than the background colour, as other
+browsers do. Frame delays come from the graphics control extensions and the
+loop count from the `NETSCAPE2.0` application extension.
+
+TODO: the image cache keeps one image per URL, so only the first frame is
+painted. Playing the frames needs the cache to hold all of them and a
+timer to repaint with.
+*/
+
+use image::base::{Image, Frame, LoopCount, LoopForever, LoopTimes};
+
+const EXTENSION: u8 = 0x21;
+const IMAGE_DESCRIPTOR: u8 = 0x2c;
+const TRAILER: u8 = 0x3b;
+const GRAPHIC_CONTROL: u8 = 0xf9;
+const APPLICATION: u8 = 0xff;
+
+// Disposal methods
+const RESTORE_BACKGROUND: u8 = 2;
+const RESTORE_PREVIOUS: u8 = 3;
+
+const MAX_CODES: uint = 4096;
+
+// The biggest canvas or frame that's decoded, in pixels, and the most
+// memory all of the frames can take; the sizes come from the file
+const MAX_PIXELS: uint = 32 * 1024 * 1024;
+const MAX_FRAMES_SIZE: uint = 256 * 1024 * 1024;
+
+// Delays this short are taken to mean "as fast as possible", which browsers
+// have long slowed down to 10 frames a second
+const MIN_DELAY: uint = 20;
+const DEFAULT_DELAY: uint = 100;
+
+pub fn is_gif(data: &[u8]) -> bool {
+    data.len() >= 6 && data[0] == 'G' as u8 && data[1] == 'I' as u8 && data[2] == 'F' as u8 &&
+        data[3] == '8' as u8 && (data[4] == '7' as u8 || data[4] == '9' as u8) &&
+        data[5] == 'a' as u8
+}
+
+// The graphics control extension before an image
+struct GraphicControl {
+    disposal: u8,
+    transparent: Option<u8>,
+    // In ms
+    delay: uint,
+}
+
+fn GraphicControl() -> GraphicControl {
+    GraphicControl { disposal: 0, transparent: None, delay: 0 }
+}
+
+// Readers for the data at `*pos`, which move past what they read
+fn read_byte(data: &[u8], pos: &mut uint) -> Option<u8> {
+    if *pos < data.len() {
+        *pos += 1;
+        Some(data[*pos - 1])
+    } else {
+        None
+    }
+}
+
+fn read_u16(data: &[u8], pos: &mut uint) -> Option<uint> {
+    if *pos + 2 <= data.len() {
+        *pos += 2;
+        Some(data[*pos - 2] as uint | (data[*pos - 1] as uint << 8))
+    } else {
+        None
+    }
+}
+
+fn read_bytes(data: &[u8], pos: &mut uint, len: uint) -> Option<~[u8]> {
+    if *pos + len <= data.len() {
+        *pos += len;
+        Some(vec::slice(data, *pos - len, *pos))
+    } else {
+        None
+    }
+}
+
+// A run of sub-blocks, up to the empty one that ends it, joined up
+fn read_sub_blocks(data: &[u8], pos: &mut uint) -> Option<~[u8]> {
+    let mut joined = ~[];
+    loop {
+        let len = match read_byte(data, pos) {
+            Some(len) => len as uint,
+            None => return None
+        };
+        if len == 0 {
+            return Some(move joined);
+        }
+        match read_bytes(data, pos, len) {
+            Some(move block) => joined.push_all(block),
+            None => return None
+        }
+    }
+}
+
+// A colour table, as BGRA
+fn read_color_table(data: &[u8], pos: &mut uint, flags: u8) -> Option<~[u8]> {
+    if flags & 0x80 == 0 {
+        return Some(~[]);
+    }
+    let entries = 2 << (flags & 0x07) as uint;
+    do read_bytes(data, pos, entries * 3).map |rgb| {
+        do vec::from_fn(entries * 4) |i| {
+            match i % 4 {
+                3 => 0xff,
+                c => rgb[(i / 4) * 3 + 2 - c]
+            }
+        }
+    }
+}
+
+// Pushes the string for `code` to `out`, returning its first index
+fn push_string(out: &mut ~[u8], prefix: &[u16], suffix: &[u8], code: uint, clear: uint) -> u8 {
+    let mut string = ~[];
+    let mut code = code;
+    while code > clear && string.len() < MAX_CODES {
+        string.push(suffix[code]);
+        code = prefix[code] as uint;
+    }
+    let first = code as u8;
+    out.push(first);
+    while !string.is_empty() {
+        out.push(string.pop());
+    }
+    first
+}
+
+/// Decompresses an image's LZW data to colour indices, `len` of them at most.
+pub fn lzw_decode(min_code_size: uint, data: &[u8], len: uint) -> ~[u8] {
+    let mut out = vec::with_capacity(len);
+    if min_code_size < 1 || min_code_size > 11 {
+        return move out;
+    }
+    let clear = 1 << min_code_size;
+    let end = clear + 1;
+    let mut prefix = vec::from_elem(MAX_CODES, 0u16);
+    let mut suffix = vec::from_fn(MAX_CODES, |i| i as u8);
+    let mut code_size = min_code_size + 1;
+    let mut next = clear + 2;
+    let mut prev: Option<uint> = None;
+
+    let mut bits = 0u;
+    let mut bit_count = 0;
+    let mut pos = 0;
+    while out.len() < len {
+        while bit_count < code_size && pos < data.len() {
+            bits |= (data[pos] as uint) << bit_count;
+            bit_count += 8;
+            pos += 1;
+        }
+        if bit_count < code_size {
+            break;
+        }
+        let code = bits & ((1 << code_size) - 1);
+        bits >>= code_size;
+        bit_count -= code_size;
+
+        if code == clear {
+            code_size = min_code_size + 1;
+            next = clear + 2;
+            prev = None;
+            loop;
+        }
+        if code == end {
+            break;
+        }
+        match prev {
+            None => {
+                if code > clear {
+                    break;
+                }
+                out.push(code as u8);
+            }
+            Some(prev) => {
+                let first = if code < next {
+                    push_string(&mut out, prefix, suffix, code, clear)
+                } else if code == next {
+                    let first = push_string(&mut out, prefix, suffix, prev, clear);
+                    out.push(first);
+                    first
+                } else {
+                    break;
+                };
+                if next < MAX_CODES {
+                    prefix[next] = prev as u16;
+                    suffix[next] = first;
+                    next += 1;
+                    if next == 1 << code_size && code_size < 12 {
+                        code_size += 1;
+                    }
+                }
+            }
+        }
+        prev = Some(code);
+    }
+    out.truncate(len);
+    move out
+}
+
+// The rows of an interlaced image in the order they're stored
+fn interlaced_rows(height: uint) -> ~[uint] {
+    let mut rows = ~[];
+    for [(0u, 8u), (4, 8), (2, 4), (1, 2)].each |pass| {
+        let (start, step) = *pass;
+        let mut row = start;
+        while row < height {
+            rows.push(row);
+            row += step;
+        }
+    }
+    move rows
+}
+
+/**
+Decodes every frame of a GIF, with how many times to play them. An image
+that's cut off keeps the frames that arrived whole; one that doesn't have
+a frame at all fails.
+*/
+pub fn decode(data: &[u8]) -> Option<(~[Frame], LoopCount)> {
+    if !is_gif(data) {
+        return None;
+    }
+    let mut pos = 6;
+    let (width, height, flags) = match (read_u16(data, &mut pos), read_u16(data, &mut pos),
+                                         read_byte(data, &mut pos)) {
+        (Some(width), Some(height), Some(flags)) => (width, height, flags),
+        _ => return None
+    };
+    // The background colour and pixel aspect ratio aren't used
+    pos += 2;
+    let global_colors = match read_color_table(data, &mut pos, flags) {
+        Some(move colors) => move colors,
+        None => return None
+    };
+
+    // Both are 16 bits, so the area can't wrap around
+    if width * height > MAX_PIXELS {
+        return None;
+    }
+    let mut canvas = vec::from_elem(width * height * 4, 0u8);
+    let mut frames = ~[];
+    let mut loop_count = LoopTimes(1);
+    let mut control = GraphicControl();
+    loop {
+        match read_byte(data, &mut pos) {
+            Some(EXTENSION) => {
+                let label = match read_byte(data, &mut pos) {
+                    Some(label) => label,
+                    None => break
+                };
+                let body = match read_sub_blocks(data, &mut pos) {
+                    Some(move body) => move body,
+                    None => break
+                };
+                if label == GRAPHIC_CONTROL && body.len() >= 4 {
+                    let delay = (body[1] as uint | (body[2] as uint << 8)) * 10;
+                    control = GraphicControl {
+                        disposal: (body[0] >> 2) & 0x07,
+                        transparent: if body[0] & 0x01 != 0 { Some(body[3]) } else { None },
+                        delay: if delay < MIN_DELAY { DEFAULT_DELAY } else { delay }
+                    };
+                } else if label == APPLICATION && body.len() >= 14 &&
+                          vec::slice(body, 0, 11) == str::to_bytes("NETSCAPE2.0") &&
+                          body[11] == 0x01 {
+                    // The number of times to repeat, after the first play
+                    loop_count = match body[12] as uint | (body[13] as uint << 8) {
+                        0 => LoopForever,
+                        repeats => LoopTimes(repeats + 1)
+                    };
+                }
+            }
+            Some(IMAGE_DESCRIPTOR) => {
+                let (left, top, frame_width, frame_height, flags) =
+                    match (read_u16(data, &mut pos), read_u16(data, &mut pos), read_u16(data, &mut pos),
+                           read_u16(data, &mut pos), read_byte(data, &mut pos)) {
+                        (Some(l), Some(t), Some(w), Some(h), Some(f)) => (l, t, w, h, f),
+                        _ => break
+                    };
+                let local_colors = match read_color_table(data, &mut pos, flags) {
+                    Some(move colors) => move colors,
+                    None => break
+                };
+                let min_code_size = match read_byte(data, &mut pos) {
+                    Some(size) => size as uint,
+                    None => break
+                };
+                let compressed = match read_sub_blocks(data, &mut pos) {
+                    Some(move compressed) => move compressed,
+                    None => break
+                };
+                // Later frames are dropped once they'd take too much memory
+                if frame_width * frame_height > MAX_PIXELS ||
+                   (frames.len() + 1) * canvas.len() > MAX_FRAMES_SIZE {
+                    break;
+                }
+                let colors = if local_colors.is_empty() { &global_colors } else { &local_colors };
+                let indices = lzw_decode(min_code_size, compressed, frame_width * frame_height);
+                let rows = if flags & 0x40 != 0 {
+                    interlaced_rows(frame_height)
+                } else {
+                    vec::from_fn(frame_height, |row| row)
+                };
+
+                let previous = if control.disposal == RESTORE_PREVIOUS {
+                    Some(copy canvas)
+                } else {
+                    None
+                };
+                for indices.eachi |i, index| {
+                    let (x, y) = (left + i % frame_width, top + rows[i / frame_width]);
+                    if x >= width || y >= height || control.transparent == Some(*index) ||
+                       (*index as uint) * 4 >= colors.len() {
+                        loop;
+                    }
+                    let (src, dst) = ((*index as uint) * 4, (y * width + x) * 4);
+                    for uint::range(0, 4) |c| {
+                        canvas[dst + c] = colors[src + c];
+                    }
+                }
+                frames.push(Frame {
+                    image: Image(width, height, 4, copy canvas),
+                    duration: control.delay
+                });
+
+                match previous {
+                    Some(move previous) => canvas = move previous,
+                    None if control.disposal == RESTORE_BACKGROUND => {
+                        for uint::range(top, uint::min(top + frame_height, height)) |y| {
+                            for uint::range(left, uint::min(left + frame_width, width)) |x| {
+                                for uint::range(0, 4) |c| {
+                                    canvas[(y * width + x) * 4 + c] = 0;
+                                }
+                            }
+                        }
+                    }
+                    None => ()
+                }
+                control = GraphicControl();
+            }
+            Some(TRAILER) | None => break,
+            Some(_) => break
+        }
+    }
+
+    if frames.is_empty() {
+        return None;
+    }
+    if frames.len() == 1 {
+        // A still image
+        frames[0].duration = 0;
+    }
+    Some((move frames, loop_count))
+}
+
+#[cfg(test)]
+mod gif_tests {
+    // Compresses `indices` without any actual compression: a clear code
+    // before every two indices keeps the codes three bits long.
+    fn lzw(indices: &[u8]) -> ~[u8] {
+        let mut codes = ~[];
+        for indices.eachi |i, index| {
+            if i % 2 == 0 {
+                codes.push(4u);
+            }
+            codes.push(*index as uint);
+        }
+        codes.push(5);
+
+        let mut bytes = ~[];
+        let mut bits = 0u;
+        let mut bit_count = 0;
+        for codes.each |code| {
+            bits |= *code << bit_count;
+            bit_count += 3;
+            while bit_count >= 8 {
+                bytes.push((bits & 0xff) as u8);
+                bits >>= 8;
+                bit_count -= 8;
+            }
+        }
+        if bit_count > 0 {
+            bytes.push(bits as u8);
+        }
+        move bytes
+    }
+
+    fn image(indices: &[u8]) -> ~[u8] {
+        let data = lzw(indices);
+        let mut bytes = ~[0x2c, 0, 0, 0, 0, 2, 0, 1, 0, 0, 2, data.len() as u8];
+        bytes.push_all(data);
+        bytes.push(0);
+        move bytes
+    }
+
+    // 2x1, with four colours: black, red, green and blue. The first frame is
+    // red and green for 50ms; the second makes the green pixel blue, with
+    // black transparent, and has no delay.
+    fn animation() -> ~[u8] {
+        let mut gif = str::to_bytes("GIF89a");
+        gif.push_all([2, 0, 1, 0, 0x81, 0, 0]);
+        gif.push_all([0, 0, 0, 0xff, 0, 0, 0, 0xff, 0, 0, 0, 0xff]);
+        gif.push_all([0x21, 0xff, 11]);
+        gif.push_all(str::to_bytes("NETSCAPE2.0"));
+        gif.push_all([3, 1, 0, 0, 0]);
+        gif.push_all([0x21, 0xf9, 4, 0x00, 5, 0, 0, 0]);
+        gif.push_all(image([1, 2]));
+        gif.push_all([0x21, 0xf9, 4, 0x01, 0, 0, 0, 0]);
+        gif.push_all(image([0, 3]));
+        gif.push(0x3b);
+        move gif
+    }
+
+    #[test]
+    fn test_lzw_decode() {
+        assert lzw_decode(2, lzw([1, 2, 3, 0, 1]), 5) == ~[1, 2, 3, 0, 1];
+        // A repeated run, coded as clear, 0, 6 (0 0), 7 (0 0 0), end
+        assert lzw_decode(2, [0x84, 0x5f], 6) == ~[0, 0, 0, 0, 0, 0];
+        // Stops at the number of pixels
+        assert lzw_decode(2, lzw([1, 2, 3]), 2) == ~[1, 2];
+    }
+
+    #[test]
+    fn test_interlaced_rows() {
+        assert interlaced_rows(10) == ~[0, 8, 4, 2, 6, 1, 3, 5, 7, 9];
+    }
+
+    #[test]
+    fn test_decode_animation() {
+        let (frames, loop_count) = decode(animation()).get();
+        assert loop_count == LoopForever;
+        assert frames.len() == 2;
+        assert frames[0].duration == 50;
+        assert frames[0].image.data == ~[0, 0, 0xff, 0xff, 0, 0xff, 0, 0xff];
+        // Too short a delay is slowed down
+        assert frames[1].duration == 100;
+        assert frames[1].image.data == ~[0, 0, 0xff, 0xff, 0xff, 0, 0, 0xff];
+    }
+
+    #[test]
+    fn test_decode_still() {
+        // The smallest GIF there is: one transparent pixel
+        let gif = ~[0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00,
+                    0x00, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x21, 0xf9, 0x04, 0x01, 0x00,
+                    0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00,
+                    0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b];
+        let (frames, loop_count) = decode(gif).get();
+        assert loop_count == LoopTimes(1);
+        assert frames.len() == 1;
+        assert frames[0].duration == 0;
+        assert frames[0].image.data == ~[0, 0, 0, 0];
+
+        assert decode(vec::slice(gif, 0, 20)).is_none();
+        assert decode(str::to_bytes("GIF90a")).is_none();
+
+        // 65535x65535 is too big to decode
+        let huge = ~[0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00,
+                     0x00, 0x3b];
+        assert decode(huge).is_none();
+    }
+}
@@ -0,0 +1,184 @@
+/*!
+Progressive JPEG display. A progressive JPEG sends the whole picture in a
+series of scans, each adding detail, so a useful image can be shown long
+before the last byte arrives. As the data comes in, a `ProgressiveDecoder`
+finds the scans that are complete and decodes everything up to the last of
+them, as if the file ended there.
+*/
+
+use image::base::{Image, load_from_memory};
+
+const SOI: u8 = 0xd8;
+const EOI: u8 = 0xd9;
+const SOS: u8 = 0xda;
+// Start of frame, progressive DCT with Huffman coding
+const SOF2: u8 = 0xc2;
+const TEM: u8 = 0x01;
+
+pure fn is_rst(marker: u8) -> bool {
+    marker >= 0xd0 && marker <= 0xd7
+}
+
+// Where the entropy-coded data of a scan starting at `start` ends: the next
+// marker that isn't a restart or a stuffed 0xff byte
+fn entropy_end(data: &[u8], start: uint) -> Option<uint> {
+    let mut i = start;
+    while i + 1 < data.len() {
+        if data[i] == 0xff && data[i + 1] != 0 && data[i + 1] != 0xff && !is_rst(data[i + 1]) {
+            return Some(i);
+        }
+        i += 1;
+    }
+    None
+}
+
+/**
+The markers of `data` that have arrived in full, with the offset just past
+each. For a scan that's past the end of its entropy-coded data, not just
+its header.
+*/
+pub fn segments(data: &[u8]) -> ~[(u8, uint)] {
+    let mut segments = ~[];
+    if data.len() < 2 || data[0] != 0xff || data[1] != SOI {
+        return move segments;
+    }
+    let mut i = 2;
+    while i + 1 < data.len() {
+        if data[i] != 0xff {
+            // Not a marker; the data is broken
+            break;
+        }
+        let marker = data[i + 1];
+        if marker == 0xff {
+            // Fill byte
+            i += 1;
+        } else if marker == SOI || marker == TEM || is_rst(marker) {
+            i += 2;
+            segments.push((marker, i));
+        } else if marker == EOI {
+            segments.push((marker, i + 2));
+            break;
+        } else {
+            if i + 3 >= data.len() {
+                break;
+            }
+            let end = i + 2 + ((data[i + 2] as uint << 8) | data[i + 3] as uint);
+            if end > data.len() {
+                break;
+            }
+            if marker == SOS {
+                match entropy_end(data, end) {
+                    Some(scan_end) => {
+                        segments.push((marker, scan_end));
+                        i = scan_end;
+                    }
+                    None => break
+                }
+            } else {
+                segments.push((marker, end));
+                i = end;
+            }
+        }
+    }
+    move segments
+}
+
+pub fn is_progressive(data: &[u8]) -> bool {
+    segments(data).any(|segment| segment.first() == SOF2)
+}
+
+/// Where each complete scan of `data` ends.
+pub fn scan_ends(data: &[u8]) -> ~[uint] {
+    let mut ends = ~[];
+    for segments(data).each |segment| {
+        let (marker, end) = *segment;
+        if marker == SOS {
+            ends.push(end);
+        }
+    }
+    move ends
+}
+
+/// Decodes a progressive JPEG a scan at a time, as its data arrives.
+pub struct ProgressiveDecoder {
+    priv mut data: ~[u8],
+    priv mut scans_shown: uint,
+}
+
+pub fn ProgressiveDecoder() -> ProgressiveDecoder {
+    ProgressiveDecoder {
+        data: ~[],
+        scans_shown: 0
+    }
+}
+
+impl ProgressiveDecoder {
+    /**
+    Adds the next bytes of the image. If they complete a scan, returns the
+    image as it stands, at its full size. Data that isn't a progressive
+    JPEG never gives an image, nor does the data that ends the last scan,
+    as the whole image is decoded then anyway.
+    */
+    fn push(&self, bytes: &[u8]) -> Option<Image> {
+        self.data.push_all(bytes);
+        let segments = segments(self.data);
+        if !segments.any(|segment| segment.first() == SOF2) ||
+           segments.any(|segment| segment.first() == EOI) {
+            return None;
+        }
+        let ends = scan_ends(self.data);
+        if ends.len() <= self.scans_shown {
+            return None;
+        }
+        self.scans_shown = ends.len();
+
+        // The scans so far, then the end of the image
+        let mut partial = vec::slice(self.data, 0, ends.last());
+        partial.push_all([0xff, EOI]);
+        load_from_memory(partial)
+    }
+}
+
+#[cfg(test)]
+mod jpeg_tests {
+    use image::base::test_image_bin;
+
+    // SOI, a progressive frame header, two scans and EOI. The scans' data
+    // has a stuffed byte and a restart marker.
+    fn progressive() -> ~[u8] {
+        ~[0xff, 0xd8,
+          0xff, 0xc2, 0x00, 0x04, 0x08, 0x00,
+          0xff, 0xda, 0x00, 0x03, 0x01, 0x12, 0xff, 0x00, 0x34, 0xff, 0xd0, 0x56,
+          0xff, 0xda, 0x00, 0x03, 0x02, 0x78,
+          0xff, 0xd9]
+    }
+
+    #[test]
+    fn test_scan_ends() {
+        let data = progressive();
+        assert is_progressive(data);
+        assert scan_ends(data) == ~[20, 26];
+        // The second scan isn't complete until the next marker arrives
+        assert scan_ends(vec::slice(data, 0, 27)) == ~[20];
+        assert segments(vec::slice(data, 0, 10)).len() == 1;
+        assert !is_progressive([0xff, 0xd8, 0xff, 0xc0, 0x00, 0x02, 0xff, 0xd9]);
+        assert segments([0x89, 'P' as u8]).is_empty();
+    }
+
+    #[test]
+    fn test_progressive_decoder() {
+        // Nothing comes of data that isn't a progressive JPEG
+        let baseline = ProgressiveDecoder();
+        assert baseline.push(test_image_bin()).is_none();
+
+        // Nor of a progressive one until a scan is complete, nor once the
+        // whole image is there
+        let data = progressive();
+        let decoder = ProgressiveDecoder();
+        assert decoder.push(vec::slice(data, 0, 14)).is_none();
+        decoder.push(vec::slice(data, 14, 22));
+        assert decoder.scans_shown == 1;
+        assert decoder.push(vec::slice(data, 22, data.len())).is_none();
+        assert decoder.scans_shown == 1;
+    }
+}
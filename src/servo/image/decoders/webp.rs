@@ -0,0 +1,161 @@
+/*!
+WebP decoding with libwebp. Still images are decoded with `WebPDecodeBGRA`.
+Animated ones go through the demux library's `WebPAnimDecoder`, which
+composites each frame onto the canvas, so every frame is a whole picture.
+*/
+
+use image::base::{Image, Frame, premultiply};
+use libc::{c_int, size_t, c_void};
+use ptr::null;
+
+// WEBP_CSP_MODE: premultiplied BGRA
+const MODE_PREMULTIPLIED_BGRA: c_int = 8;
+const WEBP_DEMUX_ABI_VERSION: c_int = 0x0107;
+
+// The VP8X chunk's flag for an animation
+const ANIMATION_FLAG: u8 = 0x02;
+
+/// Whether `data` is an animated WebP: one with a VP8X chunk right after
+/// the RIFF header that says it has an animation.
+pub fn is_animated(data: &[u8]) -> bool {
+    data.len() > 20 && data[12] == 'V' as u8 && data[13] == 'P' as u8 &&
+        data[14] == '8' as u8 && data[15] == 'X' as u8 &&
+        data[20] & ANIMATION_FLAG != 0
+}
+
+pub fn decode(data: &[u8]) -> Option<~[Frame]> {
+    if is_animated(data) {
+        decode_animation(data)
+    } else {
+        do decode_still(data).map |image| {
+            ~[Frame { image: copy *image, duration: 0 }]
+        }
+    }
+}
+
+fn decode_still(data: &[u8]) -> Option<Image> unsafe {
+    let width: c_int = 0;
+    let height: c_int = 0;
+    let pixels = do vec::as_imm_buf(data) |buf, len| {
+        webp::WebPDecodeBGRA(buf, len as size_t, ptr::to_unsafe_ptr(&width),
+                             ptr::to_unsafe_ptr(&height))
+    };
+    if pixels.is_null() {
+        return None;
+    }
+    let mut bytes = vec::raw::from_buf_raw(pixels, (width * height * 4) as uint);
+    webp::WebPFree(pixels as *c_void);
+    premultiply(bytes);
+    Some(Image(width as uint, height as uint, 4, move bytes))
+}
+
+fn decode_animation(data: &[u8]) -> Option<~[Frame]> unsafe {
+    let mut options = WebPAnimDecoderOptions {
+        color_mode: 0,
+        use_threads: 0,
+        padding: [0, ..7]
+    };
+    if webpdemux::WebPAnimDecoderOptionsInitInternal(ptr::to_unsafe_ptr(&options),
+                                                     WEBP_DEMUX_ABI_VERSION) == 0 {
+        return None;
+    }
+    options.color_mode = MODE_PREMULTIPLIED_BGRA;
+
+    do vec::as_imm_buf(data) |buf, len| {
+        let webp_data = WebPData { bytes: buf, size: len as size_t };
+        let decoder = webpdemux::WebPAnimDecoderNewInternal(ptr::to_unsafe_ptr(&webp_data),
+                                                            ptr::to_unsafe_ptr(&options),
+                                                            WEBP_DEMUX_ABI_VERSION);
+        if decoder.is_null() {
+            None
+        } else {
+            let frames = read_frames(decoder);
+            webpdemux::WebPAnimDecoderDelete(decoder);
+            move frames
+        }
+    }
+}
+
+unsafe fn read_frames(decoder: *WebPAnimDecoder) -> Option<~[Frame]> {
+    let info = WebPAnimInfo {
+        canvas_width: 0,
+        canvas_height: 0,
+        loop_count: 0,
+        bgcolor: 0,
+        frame_count: 0,
+        pad: [0, ..4]
+    };
+    if webpdemux::WebPAnimDecoderGetInfo(decoder, ptr::to_unsafe_ptr(&info)) == 0 {
+        return None;
+    }
+    let (width, height) = (info.canvas_width as uint, info.canvas_height as uint);
+
+    let mut frames = ~[];
+    // Each frame's timestamp is when it ends
+    let mut last_timestamp = 0;
+    while webpdemux::WebPAnimDecoderHasMoreFrames(decoder) != 0 {
+        let pixels: *u8 = null();
+        let timestamp: c_int = 0;
+        if webpdemux::WebPAnimDecoderGetNext(decoder, ptr::to_unsafe_ptr(&pixels),
+                                             ptr::to_unsafe_ptr(&timestamp)) == 0 {
+            return None;
+        }
+        // The canvas belongs to the decoder, and is reused for the next frame
+        let bytes = vec::raw::from_buf_raw(pixels, width * height * 4);
+        frames.push(Frame {
+            image: Image(width, height, 4, move bytes),
+            duration: (timestamp - last_timestamp) as uint
+        });
+        last_timestamp = timestamp;
+    }
+    Some(move frames)
+}
+
+struct WebPData {
+    bytes: *u8,
+    size: size_t,
+}
+
+struct WebPAnimDecoderOptions {
+    color_mode: c_int,
+    use_threads: c_int,
+    padding: [u32 * 7],
+}
+
+struct WebPAnimInfo {
+    canvas_width: u32,
+    canvas_height: u32,
+    loop_count: u32,
+    bgcolor: u32,
+    frame_count: u32,
+    pad: [u32 * 4],
+}
+
+enum WebPAnimDecoder {}
+
+extern mod webp {
+    fn WebPDecodeBGRA(data: *u8, data_size: size_t, width: *c_int, height: *c_int) -> *u8;
+    fn WebPFree(ptr: *c_void);
+}
+
+extern mod webpdemux {
+    fn WebPAnimDecoderOptionsInitInternal(options: *WebPAnimDecoderOptions,
+                                          abi_version: c_int) -> c_int;
+    fn WebPAnimDecoderNewInternal(data: *WebPData, options: *WebPAnimDecoderOptions,
+                                  abi_version: c_int) -> *WebPAnimDecoder;
+    fn WebPAnimDecoderGetInfo(decoder: *WebPAnimDecoder, info: *WebPAnimInfo) -> c_int;
+    fn WebPAnimDecoderHasMoreFrames(decoder: *WebPAnimDecoder) -> c_int;
+    fn WebPAnimDecoderGetNext(decoder: *WebPAnimDecoder, buf: **u8, timestamp: *c_int) -> c_int;
+    fn WebPAnimDecoderDelete(decoder: *WebPAnimDecoder);
+}
+
+#[test]
+fn test_is_animated() {
+    let mut header = str::to_bytes("RIFF\x00\x00\x00\x00WEBPVP8X\x0a\x00\x00\x00");
+    header.push(0x12);
+    header.push_all([0, 0, 0]);
+    assert is_animated(header);
+    header[20] = 0x10;
+    assert !is_animated(header);
+    assert !is_animated(str::to_bytes("RIFF\x00\x00\x00\x00WEBPVP8 \x0a\x00\x00\x00\x00"));
+}
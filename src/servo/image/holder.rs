@@ -1,7 +1,8 @@
 use core::util::replace;
 use std::net::url::Url;
 use std::arc::{ARC, clone, get};
-use resource::image_cache_task::{ImageCacheTask, ImageReady, ImageNotReady, ImageFailed};
+use resource::image_cache_task::{ImageCacheTask, ImageReady, ImageProgress, ImageNotReady,
+                                 ImageFailed};
 use mod resource::image_cache_task;
 use resource::local_image_cache::LocalImageCache;
 use geom::size::Size2D;
@@ -15,6 +16,8 @@ pub struct ImageHolder {
     mut image : Option<ARC<~Image>>,
     mut cached_size: Size2D<int>,
     local_image_cache: @LocalImageCache,
+    // Set for `loading="lazy"` images that haven't been asked for yet
+    mut deferred: bool,
 }
 
 fn ImageHolder(url : Url, local_image_cache: @LocalImageCache) -> ImageHolder {
@@ -24,6 +27,7 @@ fn ImageHolder(url : Url, local_image_cache: @LocalImageCache) -> ImageHolder {
         image : None,
         cached_size : Size2D(0,0),
         local_image_cache: local_image_cache,
+        deferred: false,
     };
 
     // Tell the image cache we're going to be interested in this url
@@ -37,6 +41,28 @@ fn ImageHolder(url : Url, local_image_cache: @LocalImageCache) -> ImageHolder {
     move holder
 }
 
+/** Like ImageHolder(), but for `loading="lazy"` images. Nothing is
+    requested until `load` is called, unless an earlier layout already
+    started loading this url.
+ */
+fn LazyImageHolder(url : Url, local_image_cache: @LocalImageCache) -> ImageHolder {
+    debug!("LazyImageHolder() %?", url.to_str());
+    let deferred = !local_image_cache.has_prefetched(&url);
+    let holder = ImageHolder {
+        url : move url,
+        image : None,
+        cached_size : Size2D(0,0),
+        local_image_cache: local_image_cache,
+        deferred: deferred,
+    };
+
+    if !deferred {
+        local_image_cache.decode(&holder.url);
+    }
+
+    move holder
+}
+
 impl ImageHolder {
     /**
     This version doesn't perform any computation, but may be stale w.r.t.
@@ -63,9 +89,32 @@ impl ImageHolder {
         }
     }
 
+    pure fn is_deferred() -> bool {
+        self.deferred
+    }
+
+    /// Whether the whole image has arrived, so it's painted as it is.
+    pure fn is_loaded() -> bool {
+        self.image.is_some()
+    }
+
+    /// Starts loading a deferred image. Does nothing if it's already loading.
+    fn load() {
+        if self.deferred {
+            debug!("load() %?", self.url);
+            self.local_image_cache.prefetch(&self.url);
+            self.local_image_cache.decode(&self.url);
+            self.deferred = false;
+        }
+    }
+
     fn get_image() -> Option<ARC<~Image>> {
         debug!("get_image() %?", self.url);
 
+        if self.deferred {
+            return None;
+        }
+
         // If this is the first time we've called this function, load
         // the image and store it for the future
         if self.image.is_none() {
@@ -73,6 +122,12 @@ impl ImageHolder {
                 ImageReady(move image) => {
                     self.image = Some(move image);
                 }
+                ImageProgress(move image) => {
+                    // Not kept, so the next call asks for more of it. It's
+                    // the size the whole image will be, so nothing moves
+                    // when it's replaced.
+                    return Some(move image);
+                }
                 ImageNotReady => {
                     debug!("image not ready for %s", self.url.to_str());
                 }
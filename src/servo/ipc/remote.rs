@@ -0,0 +1,173 @@
+/*!
+The `--ipc-socket` control socket. A browser process connects to it and
+drives the engine with `RemoteMsg`s, one connection at a time. Every 100 ms
+the control task also checks whether it's been told to exit. A bare socket
+name is put in the user's runtime directory, and only that user can connect.
+*/
+
+use comm::{Port, Chan};
+use engine::{EngineTask, LoadURLMsg, PrintToPdfMsg, CollectGarbageMsg};
+use ipc::unix_socket::{IpcMessage, UnixSocketChannel, ConnectionClosed, listen, runtime_path,
+                       push_str, read_str};
+use task::{task, SingleThreaded};
+use util::url::make_url;
+
+const POLL_INTERVAL_MS: uint = 100;
+
+/// What the browser process can ask of the engine, and what the engine
+/// says back.
+pub enum RemoteMsg {
+    RemoteLoadURL(~str),
+    // Loads the URL and prints it to a PDF at the path, answered with
+    // `RemotePrinted` when it's written
+    RemotePrintToPdf(~str, ~str),
+    RemotePrinted(~str),
+    RemoteCollectGarbage
+}
+
+impl RemoteMsg : IpcMessage {
+    fn to_bytes(&self) -> ~[u8] {
+        let mut bytes = ~[];
+        match *self {
+            RemoteLoadURL(ref url) => {
+                bytes.push(0);
+                push_str(&mut bytes, *url);
+            }
+            RemotePrintToPdf(ref url, ref path) => {
+                bytes.push(1);
+                push_str(&mut bytes, *url);
+                push_str(&mut bytes, *path);
+            }
+            RemotePrinted(ref path) => {
+                bytes.push(2);
+                push_str(&mut bytes, *path);
+            }
+            RemoteCollectGarbage => bytes.push(3)
+        }
+        move bytes
+    }
+
+    static fn from_bytes(bytes: &[u8]) -> Option<RemoteMsg> {
+        if bytes.is_empty() {
+            return None;
+        }
+        match bytes[0] {
+            0 => match read_str(bytes, 1) {
+                Some((move url, end)) if end == bytes.len() => Some(RemoteLoadURL(move url)),
+                _ => None
+            },
+            1 => match read_str(bytes, 1) {
+                Some((move url, end)) => match read_str(bytes, end) {
+                    Some((move path, end)) if end == bytes.len() => {
+                        Some(RemotePrintToPdf(move url, move path))
+                    }
+                    _ => None
+                },
+                None => None
+            },
+            2 => match read_str(bytes, 1) {
+                Some((move path, end)) if end == bytes.len() => Some(RemotePrinted(move path)),
+                _ => None
+            },
+            3 if bytes.len() == 1 => Some(RemoteCollectGarbage),
+            _ => None
+        }
+    }
+}
+
+pub enum Msg {
+    ExitMsg
+}
+
+pub type RemoteControl = Chan<Msg>;
+
+/// Listens on `path`, passing what connections ask for to `engine`. Fails
+/// if the socket can't be made.
+pub fn RemoteControl(path: ~str, engine: EngineTask) -> RemoteControl {
+    let path = runtime_path(path);
+    let listener = match listen(path) {
+        Ok(move listener) => move listener,
+        Err(_) => fail fmt!("can't listen on --ipc-socket %s", path)
+    };
+    let control_port = Port();
+    let control_chan = Chan(&control_port);
+    // Polling the socket blocks, so the task gets a thread of its own
+    do task().sched_mode(SingleThreaded).spawn |move listener, move control_port| {
+        let mut connection: Option<UnixSocketChannel<RemoteMsg>> = None;
+        while !control_port.peek() {
+            let closed = match connection {
+                None => {
+                    if listener.poll(POLL_INTERVAL_MS) {
+                        match listener.accept() {
+                            Ok(move channel) => connection = Some(move channel),
+                            Err(_) => #error("remote control: can't accept a connection")
+                        }
+                    }
+                    false
+                }
+                Some(ref channel) => {
+                    if channel.poll(POLL_INTERVAL_MS) {
+                        match channel.recv() {
+                            Ok(RemoteLoadURL(move url)) => {
+                                #debug("remote control: loading %s", url);
+                                engine.send(LoadURLMsg(make_url(move url, None)));
+                                false
+                            }
+                            Ok(RemotePrintToPdf(move url, move path)) => {
+                                #debug("remote control: printing %s to %s", url, path);
+                                let (printed_chan, printed_port) = pipes::stream();
+                                engine.send(PrintToPdfMsg(make_url(move url, None), copy path,
+                                                          move printed_chan));
+                                printed_port.recv();
+                                channel.send(&RemotePrinted(move path)).is_err()
+                            }
+                            Ok(RemoteCollectGarbage) => {
+                                engine.send(CollectGarbageMsg);
+                                false
+                            }
+                            Ok(RemotePrinted(*)) => {
+                                #error("remote control: unexpected message, dropping the connection");
+                                true
+                            }
+                            Err(ConnectionClosed) => true,
+                            Err(_) => {
+                                #error("remote control: bad message, dropping the connection");
+                                true
+                            }
+                        }
+                    } else {
+                        false
+                    }
+                }
+            };
+            if closed {
+                connection = None;
+            }
+        }
+    }
+    move control_chan
+}
+
+#[test]
+fn test_remote_msg_bytes() {
+    let bytes = RemoteLoadURL(~"http://example.com/").to_bytes();
+    match from_bytes(bytes) {
+        Some(RemoteLoadURL(url)) => assert url == ~"http://example.com/",
+        _ => fail
+    }
+    match from_bytes(RemotePrintToPdf(~"http://example.com/", ~"out.pdf").to_bytes()) {
+        Some(RemotePrintToPdf(url, path)) => {
+            assert url == ~"http://example.com/";
+            assert path == ~"out.pdf";
+        }
+        _ => fail
+    }
+    match from_bytes(RemoteCollectGarbage.to_bytes()) {
+        Some(RemoteCollectGarbage) => (),
+        _ => fail
+    }
+    let bad: Option<RemoteMsg> = from_bytes(~[0, 0, 0, 0, 9]);
+    assert bad.is_none();
+    let unknown: Option<RemoteMsg> = from_bytes(~[7]);
+    assert unknown.is_none();
+}
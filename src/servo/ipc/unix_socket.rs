@@ -0,0 +1,437 @@
+/*!
+Typed messages over a Unix domain socket, for talking to servo from another
+process. Each message is framed as a 4-byte big-endian length followed by
+that many bytes of the message's own encoding.
+*/
+
+use libc::{c_int, c_char, c_short, c_void, size_t, ssize_t, mode_t, uid_t};
+
+const AF_UNIX: c_int = 1;
+const SOCK_STREAM: c_int = 1;
+const POLLIN: c_short = 1;
+const EINTR: int = 4;
+// Larger frames are taken to mean the stream is out of step
+const MAX_FRAME_LEN: uint = 16 * 1024 * 1024;
+
+/// Something that can be sent over a `UnixSocketChannel`.
+pub trait IpcMessage {
+    fn to_bytes(&self) -> ~[u8];
+    static fn from_bytes(bytes: &[u8]) -> Option<self>;
+}
+
+pub enum IpcError {
+    ConnectionClosed,
+    MalformedMessage,
+    OsError(int)
+}
+
+impl IpcError : cmp::Eq {
+    pure fn eq(&self, other: &IpcError) -> bool {
+        match (*self, *other) {
+            (ConnectionClosed, ConnectionClosed) => true,
+            (MalformedMessage, MalformedMessage) => true,
+            (OsError(a), OsError(b)) => a == b,
+            _ => false
+        }
+    }
+    pure fn ne(&self, other: &IpcError) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// Appends `n` to a message, big-endian.
+pub fn push_u32(bytes: &mut ~[u8], n: u32) {
+    bytes.push((n >> 24) as u8);
+    bytes.push((n >> 16) as u8);
+    bytes.push((n >> 8) as u8);
+    bytes.push(n as u8);
+}
+
+/// Reads a big-endian u32 at `pos`.
+pub fn read_u32(bytes: &[u8], pos: uint) -> Option<u32> {
+    if pos + 4 > bytes.len() {
+        return None;
+    }
+    Some((bytes[pos] as u32 << 24) | (bytes[pos + 1] as u32 << 16) |
+         (bytes[pos + 2] as u32 << 8) | bytes[pos + 3] as u32)
+}
+
+/// Appends a string to a message, prefixed with its length.
+pub fn push_str(bytes: &mut ~[u8], s: &str) {
+    push_u32(bytes, s.len() as u32);
+    bytes.push_all(str::to_bytes(s));
+}
+
+/// Reads a string written by `push_str` at `pos`, returning it and the
+/// position after it.
+pub fn read_str(bytes: &[u8], pos: uint) -> Option<(~str, uint)> {
+    do read_u32(bytes, pos).chain |len| {
+        let start = pos + 4;
+        let end = start + len as uint;
+        if end > bytes.len() || !str::is_utf8(vec::view(bytes, start, end)) {
+            None
+        } else {
+            Some((str::from_bytes(vec::view(bytes, start, end)), end))
+        }
+    }
+}
+
+/// One end of a connection. Messages of type `M` are sent and received
+/// whole; the socket is closed when the channel is dropped.
+pub struct UnixSocketChannel<M: IpcMessage> {
+    priv fd: c_int,
+
+    drop {
+        unsafe { libc::close(self.fd); }
+    }
+}
+
+fn UnixSocketChannel<M: IpcMessage>(fd: c_int) -> UnixSocketChannel<M> {
+    UnixSocketChannel { fd: fd }
+}
+
+impl<M: IpcMessage> UnixSocketChannel<M> {
+    fn send(&self, msg: &M) -> Result<(), IpcError> {
+        let body = msg.to_bytes();
+        let mut frame = vec::with_capacity(body.len() + 4);
+        push_u32(&mut frame, body.len() as u32);
+        frame.push_all(body);
+        write_all(self.fd, frame)
+    }
+
+    /// Waits for the next message.
+    fn recv(&self) -> Result<M, IpcError> {
+        let header = match read_exact(self.fd, 4) {
+            Ok(move header) => move header,
+            Err(e) => return Err(e)
+        };
+        let len = read_u32(header, 0).get() as uint;
+        if len > MAX_FRAME_LEN {
+            return Err(MalformedMessage);
+        }
+        let body = match read_exact(self.fd, len) {
+            Ok(move body) => move body,
+            Err(e) => return Err(e)
+        };
+        match from_bytes(body) {
+            Some(move msg) => Ok(move msg),
+            None => Err(MalformedMessage)
+        }
+    }
+
+    /// Whether there's something to receive within `ms` milliseconds.
+    fn poll(&self, ms: uint) -> bool {
+        poll_readable(self.fd, ms)
+    }
+}
+
+/// A socket bound to a path, waiting for connections. The path is removed
+/// when the listener is dropped.
+pub struct UnixListener {
+    priv fd: c_int,
+    priv path: ~str,
+
+    drop {
+        unsafe {
+            libc::close(self.fd);
+            do str::as_c_str(self.path) |path| { libc::unlink(path); }
+        }
+    }
+}
+
+impl UnixListener {
+    fn accept<M: IpcMessage>(&self) -> Result<UnixSocketChannel<M>, IpcError> {
+        loop {
+            let fd = c_accept(self.fd, ptr::null(), ptr::null());
+            if fd >= 0 {
+                return Ok(UnixSocketChannel(fd));
+            }
+            if os::errno() != EINTR {
+                return Err(OsError(os::errno()));
+            }
+        }
+    }
+
+    /// Whether a connection arrives within `ms` milliseconds.
+    fn poll(&self, ms: uint) -> bool {
+        poll_readable(self.fd, ms)
+    }
+}
+
+/// Listens on `path`, replacing any socket left there by an earlier run.
+/// Only the user running servo can connect to it.
+pub fn listen(path: &str) -> Result<UnixListener, IpcError> {
+    let addr = match sockaddr(path) {
+        Some(move addr) => move addr,
+        None => return Err(OsError(ENAMETOOLONG))
+    };
+    let fd = socket(AF_UNIX, SOCK_STREAM, 0);
+    if fd < 0 {
+        return Err(OsError(os::errno()));
+    }
+    unsafe {
+        do str::as_c_str(path) |path| { libc::unlink(path); }
+    }
+    let len = sys::size_of::<sockaddr_un>() as u32;
+    // Nothing can connect before listen(), so the mode is set in time
+    if bind(fd, ptr::to_unsafe_ptr(&addr), len) != 0 ||
+            do str::as_c_str(path) |path| { chmod(path, 0o600) != 0 } ||
+            c_listen(fd, 8) != 0 {
+        let errno = os::errno();
+        unsafe { libc::close(fd); }
+        return Err(OsError(errno));
+    }
+    Ok(UnixListener { fd: fd, path: path.to_str() })
+}
+
+/// Where a socket named `name` goes. A name with a `/` in it is a path
+/// already; a bare name goes in the user's runtime directory, which is
+/// `$XDG_RUNTIME_DIR` or else a `servo-<uid>` directory in `/tmp` that
+/// only the user can get into. Fails if that directory belongs to someone
+/// else.
+pub fn runtime_path(name: &str) -> ~str {
+    if name.contains("/") {
+        return name.to_str();
+    }
+    let dir = match os::getenv("XDG_RUNTIME_DIR") {
+        Some(move dir) if !dir.is_empty() => Path(dir),
+        _ => {
+            let dir = Path(fmt!("/tmp/servo-%u", getuid() as uint));
+            os::make_dir(&dir, 0o700);
+            // Only the owner can change the mode, and an existing directory
+            // may have been made with a looser one
+            if !do str::as_c_str(dir.to_str()) |path| { chmod(path, 0o700) == 0 } {
+                fail fmt!("%s isn't ours to put a socket in", dir.to_str());
+            }
+            move dir
+        }
+    };
+    dir.push(name).to_str()
+}
+
+/// Connects to a socket another process is listening on.
+pub fn connect<M: IpcMessage>(path: &str) -> Result<UnixSocketChannel<M>, IpcError> {
+    let addr = match sockaddr(path) {
+        Some(move addr) => move addr,
+        None => return Err(OsError(ENAMETOOLONG))
+    };
+    let fd = socket(AF_UNIX, SOCK_STREAM, 0);
+    if fd < 0 {
+        return Err(OsError(os::errno()));
+    }
+    let channel = UnixSocketChannel(fd);
+    let len = sys::size_of::<sockaddr_un>() as u32;
+    if c_connect(fd, ptr::to_unsafe_ptr(&addr), len) != 0 {
+        return Err(OsError(os::errno()));
+    }
+    Ok(move channel)
+}
+
+/// Two connected channels, as if one had connected to the other.
+pub fn pair<M: IpcMessage>() -> Result<(UnixSocketChannel<M>, UnixSocketChannel<M>), IpcError> {
+    let fds = [-1 as c_int, -1];
+    if socketpair(AF_UNIX, SOCK_STREAM, 0, vec::raw::to_ptr(fds)) != 0 {
+        return Err(OsError(os::errno()));
+    }
+    Ok((UnixSocketChannel(fds[0]), UnixSocketChannel(fds[1])))
+}
+
+/// Writes all of `bytes` to `fd`, retrying after interruptions.
+pub fn write_all(fd: c_int, bytes: &[u8]) -> Result<(), IpcError> {
+    let mut written = 0;
+    while written < bytes.len() {
+        let n = do vec::as_imm_buf(vec::view(bytes, written, bytes.len())) |buf, len| {
+            unsafe { libc::write(fd, buf as *c_void, len as size_t) }
+        };
+        if n < 0 {
+            if os::errno() == EINTR {
+                loop;
+            }
+            return Err(OsError(os::errno()));
+        }
+        written += n as uint;
+    }
+    Ok(())
+}
+
+/// Reads exactly `len` bytes from `fd`.
+pub fn read_exact(fd: c_int, len: uint) -> Result<~[u8], IpcError> {
+    let mut bytes = vec::from_elem(len, 0u8);
+    let mut read = 0;
+    while read < len {
+        let n: ssize_t = do vec::as_mut_buf(bytes) |buf, _| {
+            unsafe {
+                libc::read(fd, ptr::mut_offset(buf, read) as *mut c_void,
+                           (len - read) as size_t)
+            }
+        };
+        if n == 0 {
+            return Err(ConnectionClosed);
+        }
+        if n < 0 {
+            if os::errno() == EINTR {
+                loop;
+            }
+            return Err(OsError(os::errno()));
+        }
+        read += n as uint;
+    }
+    Ok(move bytes)
+}
+
+/// Whether `fd` has something to read within `ms` milliseconds.
+pub fn poll_readable(fd: c_int, ms: uint) -> bool {
+    let fds = [pollfd { fd: fd, events: POLLIN, revents: 0 }];
+    poll(vec::raw::to_ptr(fds), 1, ms as c_int) > 0
+}
+
+struct pollfd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+#[cfg(target_os = "linux")]
+const ENAMETOOLONG: int = 36;
+
+#[cfg(target_os = "linux")]
+struct sockaddr_un {
+    sun_family: u16,
+    sun_path: [c_char * 108],
+}
+
+#[cfg(target_os = "linux")]
+fn sockaddr(path: &str) -> Option<sockaddr_un> {
+    let mut addr = sockaddr_un { sun_family: AF_UNIX as u16, sun_path: [0, ..108] };
+    // The path needs a terminating NUL
+    if path.len() >= addr.sun_path.len() {
+        return None;
+    }
+    for str::byte_slice(path) |bytes| {
+        for bytes.eachi |i, b| { addr.sun_path[i] = *b as c_char; }
+    }
+    Some(move addr)
+}
+
+#[cfg(target_os = "linux")]
+extern {
+    fn poll(fds: *pollfd, nfds: libc::c_ulong, timeout: c_int) -> c_int;
+}
+
+#[cfg(target_os = "macos")]
+const ENAMETOOLONG: int = 63;
+
+#[cfg(target_os = "macos")]
+struct sockaddr_un {
+    sun_len: u8,
+    sun_family: u8,
+    sun_path: [c_char * 104],
+}
+
+#[cfg(target_os = "macos")]
+fn sockaddr(path: &str) -> Option<sockaddr_un> {
+    let mut addr = sockaddr_un {
+        sun_len: sys::size_of::<sockaddr_un>() as u8,
+        sun_family: AF_UNIX as u8,
+        sun_path: [0, ..104]
+    };
+    if path.len() >= addr.sun_path.len() {
+        return None;
+    }
+    for str::byte_slice(path) |bytes| {
+        for bytes.eachi |i, b| { addr.sun_path[i] = *b as c_char; }
+    }
+    Some(move addr)
+}
+
+#[cfg(target_os = "macos")]
+extern {
+    fn poll(fds: *pollfd, nfds: libc::c_uint, timeout: c_int) -> c_int;
+}
+
+extern {
+    fn socket(domain: c_int, ty: c_int, protocol: c_int) -> c_int;
+    fn socketpair(domain: c_int, ty: c_int, protocol: c_int, fds: *c_int) -> c_int;
+    fn bind(fd: c_int, addr: *sockaddr_un, len: u32) -> c_int;
+    // Renamed so as not to clash with the functions above
+    #[link_name = "listen"]
+    fn c_listen(fd: c_int, backlog: c_int) -> c_int;
+    #[link_name = "accept"]
+    fn c_accept(fd: c_int, addr: *sockaddr_un, len: *u32) -> c_int;
+    #[link_name = "connect"]
+    fn c_connect(fd: c_int, addr: *sockaddr_un, len: u32) -> c_int;
+    fn chmod(path: *c_char, mode: mode_t) -> c_int;
+    fn getuid() -> uid_t;
+}
+
+#[cfg(test)]
+mod unix_socket_tests {
+    enum TestMsg {
+        Text(~str),
+        Number(u32)
+    }
+
+    impl TestMsg : IpcMessage {
+        fn to_bytes(&self) -> ~[u8] {
+            let mut bytes = ~[];
+            match *self {
+                Text(ref s) => { bytes.push(0); push_str(&mut bytes, *s); }
+                Number(n) => { bytes.push(1); push_u32(&mut bytes, n); }
+            }
+            move bytes
+        }
+
+        static fn from_bytes(bytes: &[u8]) -> Option<TestMsg> {
+            if bytes.is_empty() {
+                return None;
+            }
+            match bytes[0] {
+                0 => read_str(bytes, 1).map(|r| { let (s, _) = copy *r; Text(move s) }),
+                1 => read_u32(bytes, 1).map(|n| Number(*n)),
+                _ => None
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (a, b) = pair::<TestMsg>().get();
+        a.send(&Text(~"http://example.com/")).get();
+        a.send(&Number(0xdeadbeef)).get();
+        assert b.poll(1000);
+        match b.recv() {
+            Ok(Text(s)) => assert s == ~"http://example.com/",
+            _ => fail
+        }
+        match b.recv() {
+            Ok(Number(n)) => assert n == 0xdeadbeef,
+            _ => fail
+        }
+        assert !b.poll(0);
+    }
+
+    #[test]
+    fn test_closed_and_malformed() {
+        let (a, b) = pair::<TestMsg>().get();
+        // A frame whose body no TestMsg decodes from
+        write_all(a.fd, ~[0, 0, 0, 1, 7]).get();
+        assert b.recv().get_err() == MalformedMessage;
+        { let _a = move a; }
+        assert b.recv().get_err() == ConnectionClosed;
+    }
+
+    #[test]
+    fn test_read_str_bounds() {
+        let mut bytes = ~[];
+        push_str(&mut bytes, "abc");
+        assert read_str(bytes, 0) == Some((~"abc", 7));
+        assert read_str(vec::view(bytes, 0, 6), 0).is_none();
+        assert sockaddr(str::from_chars(vec::from_elem(200, 'a'))).is_none();
+    }
+
+    #[test]
+    fn test_runtime_path() {
+        assert runtime_path("/var/run/servo.sock") == ~"/var/run/servo.sock";
+        assert runtime_path("servo.sock").ends_with("/servo.sock");
+    }
+}
@@ -1,27 +1,73 @@
 use au = gfx::geometry;
+use css::values::aspect_ratio::height_for_width;
 use newcss::values::*;
 use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
 use gfx::display_list::{DisplayList, DisplayListBuilder};
 use gfx::geometry::Au;
-use layout::box::{RenderBox};
+use layout::box::{RenderBox, aspect_ratio};
 use layout::context::LayoutContext;
-use layout::flow::{FlowContext, FlowTree, InlineBlockFlow, BlockFlow, RootFlow};
+use layout::float::{FloatSide, ClearSide, ClearNone, FloatContext, PlacedFloat};
+use layout::grid::GridContext;
+use layout::intrinsic::{Definite, intrinsic_width, space_for};
+use layout::masonry::MasonryContext;
+use layout::flow::{FlowContext, FlowTree, InlineBlockFlow, BlockFlow, InlineFlow, RootFlow};
+use layout::multi_column::{MultiColumnContext, spans_all_columns, specified_height};
+use layout::shape_outside::{ShapeSource, NoShape};
+use layout::list_marker::{list_indent, place_outside_marker};
+use layout::table::{TableContext, TablePart, RowGroup, HeaderGroup, FooterGroup, Row};
 use util::tree;
 
 struct BlockFlowData {
-    mut box: Option<@RenderBox>
+    mut box: Option<@RenderBox>,
+    // the side the block floats to, if it's floated
+    mut float: Option<FloatSide>,
+    mut clear: ClearSide,
+    // what lines beside a float wrap around, if not its box
+    mut shape_outside: ShapeSource,
+    // floats placed among the children, kept so that the parent can take
+    // the ones hanging out of the bottom of the block
+    mut floats: FloatContext,
+    // the columns children are laid out in, for a multi-column block
+    mut columns: Option<@MultiColumnContext>,
+    // the columns children are stacked in, for a masonry block
+    mut masonry: Option<@MasonryContext>,
+    // the tracks children are placed in, for a grid
+    mut grid: Option<@GridContext>,
+    // the grid children are laid out in, for a table
+    mut table: Option<@TableContext>,
+    // what part of a table the block is, if it's in one; see layout::table
+    mut table_part: Option<TablePart>,
+    // an outside list marker, which hangs to the left of the first line
+    mut marker: Option<@RenderBox>,
 }
 
 fn BlockFlowData() -> BlockFlowData {
     BlockFlowData {
-        box: None
+        box: None,
+        float: None,
+        clear: ClearNone,
+        shape_outside: NoShape,
+        floats: FloatContext(),
+        columns: None,
+        masonry: None,
+        grid: None,
+        table: None,
+        table_part: None,
+        marker: None,
     }
 }
 
 trait BlockLayout {
     pure fn starts_block_flow() -> bool;
+    pure fn is_float() -> bool;
+    pure fn columns() -> Option<@MultiColumnContext>;
+    pure fn masonry() -> Option<@MasonryContext>;
+    pure fn grid() -> Option<@GridContext>;
+    pure fn table() -> Option<@TableContext>;
+    pure fn is_table_track() -> bool;
+    pure fn marker() -> Option<@RenderBox>;
     pure fn with_block_box(@self, fn(box: &@RenderBox) -> ()) -> ();
 
     fn bubble_widths_block(@self, ctx: &LayoutContext);
@@ -40,6 +86,60 @@ impl FlowContext : BlockLayout {
         }
     }
 
+    pure fn is_float() -> bool {
+        match self {
+            BlockFlow(_, ref data) => data.float.is_some(),
+            _ => false
+        }
+    }
+
+    pure fn columns() -> Option<@MultiColumnContext> {
+        match self {
+            BlockFlow(_, ref data) => data.columns,
+            _ => None
+        }
+    }
+
+    pure fn masonry() -> Option<@MasonryContext> {
+        match self {
+            BlockFlow(_, ref data) => data.masonry,
+            _ => None
+        }
+    }
+
+    pure fn grid() -> Option<@GridContext> {
+        match self {
+            BlockFlow(_, ref data) => data.grid,
+            _ => None
+        }
+    }
+
+    pure fn table() -> Option<@TableContext> {
+        match self {
+            BlockFlow(_, ref data) => data.table,
+            _ => None
+        }
+    }
+
+    pure fn marker() -> Option<@RenderBox> {
+        match self {
+            BlockFlow(_, ref data) => data.marker,
+            _ => None
+        }
+    }
+
+    /// Whether this is a row or row group of a table, whose children the
+    /// table places.
+    pure fn is_table_track() -> bool {
+        match self {
+            BlockFlow(_, ref data) => match data.table_part {
+                Some(Row) | Some(RowGroup) | Some(HeaderGroup) | Some(FooterGroup) => true,
+                _ => false
+            },
+            _ => false
+        }
+    }
+
     /* Get the current flow's corresponding block box, if it exists, and do something with it. 
        This works on both BlockFlow and RootFlow, since they are mostly the same. */
     pure fn with_block_box(@self, cb: fn(box: &@RenderBox) -> ()) -> () {
@@ -71,12 +171,26 @@ impl FlowContext : BlockLayout {
         let mut min_width = Au(0);
         let mut pref_width = Au(0);
 
-        /* find max width from child block contexts */
-        for FlowTree.each_child(self) |child_ctx| {
-            assert child_ctx.starts_block_flow() || child_ctx.starts_inline_flow();
+        match self.table() {
+            // a table's columns are side by side
+            Some(table) => {
+                let (table_min, table_pref) = table.bubble_widths(self);
+                min_width = table_min;
+                pref_width = table_pref;
+            }
+            // as are a grid's
+            None if self.grid().is_some() => {
+                let (grid_min, grid_pref) = self.grid().get().bubble_widths(self);
+                min_width = grid_min;
+                pref_width = grid_pref;
+            }
+            /* find max width from child block contexts */
+            None => for FlowTree.each_child(self) |child_ctx| {
+                assert child_ctx.starts_block_flow() || child_ctx.starts_inline_flow();
 
-            min_width  = au::max(min_width, child_ctx.d().min_width);
-            pref_width = au::max(pref_width, child_ctx.d().pref_width);
+                min_width  = au::max(min_width, child_ctx.d().min_width);
+                pref_width = au::max(pref_width, child_ctx.d().pref_width);
+            }
         }
 
         /* if not an anonymous block context, add in block box's widths.
@@ -84,6 +198,9 @@ impl FlowContext : BlockLayout {
         do self.with_block_box |box| {
             min_width = min_width.add(&box.get_min_width(ctx));
             pref_width = pref_width.add(&box.get_pref_width(ctx));
+            let indent = list_indent(box.d().node);
+            min_width += indent;
+            pref_width += indent;
         }
 
         self.d().min_width = min_width;
@@ -100,6 +217,11 @@ impl FlowContext : BlockLayout {
     fn assign_widths_block(@self, _ctx: &LayoutContext) { 
         assert self.starts_block_flow();
 
+        // a table is only as wide as it needs, and no narrower
+        for self.table().each |table| {
+            self.d().position.size.width = table.used_width(self, self.d().position.size.width);
+        }
+
         let mut remaining_width = self.d().position.size.width;
         let mut _right_used = Au(0);
         let mut left_used = Au(0);
@@ -112,21 +234,183 @@ impl FlowContext : BlockLayout {
             remaining_width -= left_used.add(&right_used);
         }
 
+        do self.with_block_box |box| {
+            let indent = list_indent(box.d().node);
+            left_used += indent;
+            remaining_width -= indent;
+        }
+
+        // a table sizes its parts itself
+        match self.table() {
+            Some(table) => {
+                table.assign_widths(left_used, remaining_width);
+                return;
+            }
+            None if self.is_table_track() => return,
+            None => ()
+        }
+
+        // and a grid places its children in its tracks
+        match self.grid() {
+            Some(grid) => {
+                grid.assign_widths(self, left_used, remaining_width);
+                return;
+            }
+            None => ()
+        }
+
+        // children go in columns, unless they span them all
+        let column_width = match self.columns() {
+            Some(columns) => columns.assign_width(remaining_width),
+            None => remaining_width
+        };
+        for self.masonry().each |masonry| {
+            masonry.assign_widths(self, left_used, remaining_width);
+        }
+
         for FlowTree.each_child(self) |child_ctx| {
             assert child_ctx.starts_block_flow() || child_ctx.starts_inline_flow();
+            let spans = match *child_ctx {
+                BlockFlow(_, ref data) => {
+                    data.box.map_default(false, |b| spans_all_columns(b.d().node))
+                }
+                _ => false
+            };
+            let remaining_width = match self.masonry() {
+                Some(masonry) => masonry.item_width(child_ctx),
+                None if spans => remaining_width,
+                None => column_width
+            };
+            let intrinsic = match *child_ctx {
+                BlockFlow(_, ref data) => data.box.chain(|b| intrinsic_width(b.d().node)),
+                _ => None
+            };
+            let min_content = child_ctx.d().min_width;
+            let max_content = child_ctx.d().pref_width;
             child_ctx.d().position.origin.x = left_used;
-            child_ctx.d().position.size.width = remaining_width;
+            child_ctx.d().position.size.width = match intrinsic {
+                // width: min-content, max-content or fit-content()
+                Some(ref size) => space_for(size, remaining_width).fit(min_content, max_content),
+                // floats shrink to fit their contents
+                None if child_ctx.is_float() => {
+                    Definite(remaining_width).fit(min_content, max_content)
+                }
+                None => remaining_width
+            };
+            // lines are first broken as if there were no floats, since
+            // floats are only placed once heights are known
+            if child_ctx.starts_inline_flow() {
+                child_ctx.inline().floats = FloatContext();
+            }
         }
     }
 
-    fn assign_height_block(@self, _ctx: &LayoutContext) {
+    /* Stacks the children, and places the floats among them. Lines
+    beside floats are broken again to wrap around them. */
+    fn assign_height_block(@self, ctx: &LayoutContext) {
         assert self.starts_block_flow();
 
         let mut cur_y = Au(0);
+        let width = self.d().position.size.width;
+        let floats = FloatContext();
 
-        for FlowTree.each_child(self) |child_ctx| {
-            child_ctx.d().position.origin.y = cur_y;
-            cur_y += child_ctx.d().position.size.height;
+        match self.columns() {
+            Some(columns) => {
+                do self.with_block_box |box| {
+                    columns.max_height = specified_height(box.d().node);
+                }
+                cur_y = columns.layout(self);
+            }
+            None if self.masonry().is_some() => {
+                cur_y = self.masonry().get().layout(self);
+            }
+            None if self.table().is_some() => {
+                cur_y = self.table().get().layout();
+            }
+            None if self.grid().is_some() => {
+                let mut height = None;
+                do self.with_block_box |box| {
+                    height = specified_height(box.d().node);
+                }
+                cur_y = self.grid().get().layout(self, height);
+            }
+            None => {
+                for FlowTree.each_child(self) |child_ctx| {
+                    match *child_ctx {
+                        BlockFlow(_, ref data) if data.float.is_some() => {
+                            let side = data.float.get();
+                            let size = copy child_ctx.d().position.size;
+                            let bounds = floats.place(side, size, cur_y, width);
+                            child_ctx.d().position.origin = copy bounds.origin;
+                            let shape = data.shape_outside.resolve(&size);
+                            floats.add(PlacedFloat { side: side, bounds: bounds, shape: shape });
+                            // out of the flow, so it takes no room
+                            loop;
+                        }
+                        BlockFlow(_, ref data) => {
+                            cur_y = floats.clearance(data.clear, cur_y);
+                            child_ctx.d().position.origin.y = cur_y;
+                            // floats hanging out of the child push on what follows it
+                            let child_origin = copy child_ctx.d().position.origin;
+                            let child_height = child_ctx.d().position.size.height;
+                            floats.add_all(&data.floats.translated_below(child_height, &child_origin));
+                        }
+                        InlineFlow(*) if floats.bottom() > cur_y => {
+                            child_ctx.d().position.origin.y = cur_y;
+                            let child_origin = copy child_ctx.d().position.origin;
+                            let to_child = Point2D(Au(0) - child_origin.x, Au(0) - child_origin.y);
+                            child_ctx.inline().floats = floats.translated_below(cur_y, &to_child);
+                            child_ctx.assign_widths_inline(ctx);
+                            child_ctx.assign_height_inline(ctx);
+                        }
+                        _ => {
+                            child_ctx.d().position.origin.y = cur_y;
+                        }
+                    }
+                    cur_y += child_ctx.d().position.size.height;
+                }
+            }
+        }
+
+        // The root and floats contain their floats. Other blocks let them
+        // hang out of the bottom, into their parent.
+        let contains_floats = match *self {
+            RootFlow(*) => true,
+            _ => self.is_float()
+        };
+        if contains_floats {
+            cur_y = au::max(cur_y, floats.bottom());
+        }
+        match *self {
+            BlockFlow(_, ref data) => data.floats = move floats,
+            _ => ()
+        }
+
+        // a list item is never shorter than its marker
+        for self.marker().each |marker| {
+            cur_y = au::max(cur_y, place_outside_marker(*marker));
+        }
+
+        // replaced content, like a floated image, has a height of its own
+        do self.with_block_box |box| {
+            if box.is_replaced() {
+                cur_y = au::max(cur_y, box.get_replaced_size().height);
+            }
+        }
+
+        // with aspect-ratio, an auto height comes from the width, unless the
+        // content needs more
+        match *self {
+            BlockFlow(*) => do self.with_block_box |box| {
+                let node = box.d().node;
+                if !box.is_replaced() && specified_height(node).is_none() {
+                    match aspect_ratio(node).chain(|r| r.used_ratio(None)) {
+                        Some(ratio) => cur_y = au::max(cur_y, height_for_width(width, ratio)),
+                        None => ()
+                    }
+                }
+            },
+            _ => ()
         }
 
         self.d().position.size.height = cur_y;
@@ -150,12 +434,30 @@ impl FlowContext : BlockLayout {
         do self.with_block_box |box| {
             box.build_display_list(builder, dirty, offset, list)
         }
+        for self.marker().each |marker| {
+            marker.build_display_list(builder, dirty, offset, list)
+        }
 
         // TODO: handle any out-of-flow elements
 
+        // A scroll container's contents move up and left as it's scrolled.
+        // TODO: clip them to the container
+        let child_offset = match self.d().node {
+            Some(node) => {
+                let scrolled = builder.ctx.element_scroll_offset(node);
+                Point2D(offset.x - scrolled.x, offset.y - scrolled.y)
+            }
+            None => copy *offset
+        };
+
         // go deeper into the flow tree
         for FlowTree.each_child(self) |child| {
-            self.build_display_list_for_child(builder, child, dirty, offset, list)
+            self.build_display_list_for_child(builder, child, dirty, &child_offset, list)
+        }
+
+        match self.columns() {
+            Some(columns) => columns.build_display_list(offset, list),
+            None => ()
         }
     }
 }
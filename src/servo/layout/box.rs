@@ -9,8 +9,11 @@ use core::dvec::DVec;
 use core::to_str::ToStr;
 use core::rand;
 use css::styles::SpecifiedStyle;
+use css::values::color_scheme::control_background;
 use newcss::values::{BoxSizing, Length, Px, CSSDisplay, Specified, BgColor, BgColorTransparent};
-use newcss::values::{BdrColor, PosAbsolute};
+use newcss::values::{BdrColor, TextColor, PosAbsolute, CSSValue, BoxLength};
+use css::values::aspect_ratio::{AspectRatio, parse_aspect_ratio, height_for_width,
+                                width_for_height};
 use newcss::color::{Color, rgba};
 use dom::element::{ElementKind, HTMLDivElement, HTMLImageElement};
 use dom::node::{Element, Node, NodeData, NodeKind, NodeTree};
@@ -19,6 +22,7 @@ use geom::size::Size2D;
 use geom::point::Point2D;
 use gfx::display_list::{DisplayItem, DisplayList, DisplayListBuilder};
 use image::{Image, ImageHolder};
+use layout::color_scheme::{is_form_control, used_color_scheme};
 use layout::context::LayoutContext;
 use layout::debug::BoxedDebugMethods;
 use layout::flow::FlowContext;
@@ -78,7 +82,10 @@ struct RenderBoxData {
     mut position : Rect<Au>,
     font_size : Length,
     /* TODO (Issue #87): debug only */
-    mut id: int
+    mut id: int,
+    /* generated content, like a list marker, rather than the node's own;
+       it has none of the node's size, position, background or border */
+    generated: bool
 }
 
 enum RenderBoxType {
@@ -116,6 +123,8 @@ trait RenderBoxMethods {
     fn split_to_width(@self, &LayoutContext, Au, starts_line: bool) -> SplitBoxResult;
     fn get_min_width(&LayoutContext) -> Au;
     fn get_pref_width(&LayoutContext) -> Au;
+    fn get_pref_line_widths(&LayoutContext) -> ~[Au];
+    fn get_replaced_size() -> Size2D<Au>;
     fn get_used_width() -> (Au, Au);
     fn get_used_height() -> (Au, Au);
     fn build_display_list(@self, &DisplayListBuilder, dirty: &Rect<Au>, 
@@ -128,7 +137,19 @@ fn RenderBoxData(node: Node, ctx: @FlowContext, id: int) -> RenderBoxData {
         mut ctx  : ctx,
         mut position : au::zero_rect(),
         font_size: Px(0.0),
-        id : id
+        id : id,
+        generated: false
+    }
+}
+
+fn GeneratedBoxData(node: Node, ctx: @FlowContext, id: int) -> RenderBoxData {
+    RenderBoxData {
+        node : node,
+        mut ctx  : ctx,
+        mut position : au::zero_rect(),
+        font_size: Px(0.0),
+        id : id,
+        generated: true
     }
 }
 
@@ -259,21 +280,31 @@ impl RenderBox : RenderBoxMethods {
             GenericBox(*) => Au(0),
             // TODO: consult CSS 'width', margin, border.
             // TODO: If image isn't available, consult 'width'.
-            ImageBox(_,i) => au::from_px(i.get_size().get_default(Size2D(0,0)).width),
+            ImageBox(*) => self.get_replaced_size().width,
             TextBox(_,d) => d.run.min_width_for_range(d.range),
             UnscannedTextBox(*) => fail ~"Shouldn't see unscanned boxes here."
         }
     }
 
-    fn get_pref_width(_ctx: &LayoutContext) -> Au {
+    fn get_pref_width(ctx: &LayoutContext) -> Au {
+        let mut max_line_width = Au(0);
+        for self.get_pref_line_widths(ctx).each |width| {
+            max_line_width = au::max(max_line_width, *width);
+        }
+        max_line_width
+    }
+
+    /* The widths of the box's lines, when they're broken only where they
+    must be. Only text can have more than one. */
+    fn get_pref_line_widths(_ctx: &LayoutContext) -> ~[Au] {
         match self {
             // TODO: this should account for min/pref widths of the
             // box element in isolation. That includes
             // border/margin/padding but not child widths. The block
             // FlowContext will combine the width of this element and
             // that of its children to arrive at the context width.
-            GenericBox(*) => Au(0),
-            ImageBox(_,i) => au::from_px(i.get_size().get_default(Size2D(0,0)).width),
+            GenericBox(*) => ~[Au(0)],
+            ImageBox(*) => ~[self.get_replaced_size().width],
 
             // a text box cannot span lines, so assume that this is an unsplit text box.
 
@@ -282,21 +313,41 @@ impl RenderBox : RenderBoxMethods {
             // maybe text boxes should report nothing, and the parent flow could
             // factor in min/pref widths of any text runs that it owns.
             TextBox(_,d) => {
-                let mut max_line_width: Au = Au(0);
+                let mut line_widths = ~[];
                 for d.run.iter_natural_lines_for_range(d.range) |line_range| {
                     let mut line_width: Au = Au(0);
                     for d.run.glyphs.iter_glyphs_for_range(line_range) |_char_i, glyph| {
                         line_width += glyph.advance()
                     }
-                    max_line_width = au::max(max_line_width, line_width);
+                    line_widths.push(line_width);
                 }
-
-                max_line_width
+                if line_widths.is_empty() {
+                    line_widths.push(Au(0));
+                }
+                move line_widths
             },
             UnscannedTextBox(*) => fail ~"Shouldn't see unscanned boxes here."
         }
     }
 
+    /* The size replaced content, like an image, is laid out at. Other
+    boxes are the size layout made them. */
+    fn get_replaced_size() -> Size2D<Au> {
+        match self {
+            ImageBox(_,i) => {
+                let natural = i.get_size().map(|size| Size2D(au::from_px(size.width),
+                                                                au::from_px(size.height)));
+                if self.d().generated {
+                    return replaced_size(natural, None, None, None);
+                }
+                let node = self.d().node;
+                let (width, height) = specified_size(node);
+                replaced_size(natural, width, height, aspect_ratio(node))
+            }
+            _ => copy self.d().position.size
+        }
+    }
+
     /* Returns the amount of left, right "fringe" used by this
     box. This should be based on margin, border, padding, width. */
     fn get_used_width() -> (Au, Au) {
@@ -386,7 +437,7 @@ impl RenderBox : RenderBoxMethods {
 
         let style = self.d().node.style();
         let box_bounds : Rect<Au> = match style.position {
-            Specified(PosAbsolute) => {
+            Specified(PosAbsolute) if !self.d().generated => {
                 let x_offset = match style.left {
                     Specified(Px(px)) => au::from_frac_px(px),
                     _ => self.d().position.origin.x
@@ -406,6 +457,20 @@ impl RenderBox : RenderBoxMethods {
         debug!("RenderBox::build_display_list at rel=%?, abs=%?: %s", 
                box_bounds, abs_box_bounds, self.debug_str());
         debug!("RenderBox::build_display_list: dirty=%?, offset=%?", dirty, offset);
+
+        // Lazy images start loading once their box comes near the viewport.
+        // The image cache triggers a reflow when the data arrives.
+        match *self {
+            ImageBox(_,i) if i.is_deferred() => {
+                // The display list is in the viewport; the bounds are in the page
+                let page_bounds = abs_box_bounds.translate(&builder.ctx.scroll_offset);
+                if touches(&page_bounds, &builder.ctx.image_load_bounds) {
+                    i.load();
+                }
+            }
+            _ => ()
+        }
+
         if abs_box_bounds.intersects(dirty) {
             debug!("RenderBox::build_display_list: intersected. Adding display item...");
         } else {
@@ -413,14 +478,20 @@ impl RenderBox : RenderBoxMethods {
             return;
         }
 
-        self.add_bgcolor_to_list(list, &abs_box_bounds); 
+        if !self.d().generated {
+            self.add_bgcolor_to_list(builder.ctx, list, &abs_box_bounds); 
+        }
 
         match *self {
             UnscannedTextBox(*) => fail ~"Shouldn't see unscanned boxes here.",
+            // Text waiting for its web font takes up room, but isn't drawn
+            TextBox(_,d) if d.run.invisible => (),
             TextBox(_,d) => {
+                let color = self.text_color();
                 list.append_item(~DisplayItem::new_Text(&abs_box_bounds, 
                                                         ~d.run.serialize(builder.ctx.font_cache),
-                                                        d.range));
+                                                        d.range, color.red, color.green,
+                                                        color.blue));
                 // debug frames for text box bounds
                 debug!("%?", { 
                     list.append_item(~DisplayItem::new_Border(&abs_box_bounds, au::from_px(1), 0, 0, 200))
@@ -438,15 +509,21 @@ impl RenderBox : RenderBoxMethods {
             }
         }
 
-        self.add_border_to_list(list, &abs_box_bounds);
+        if !self.d().generated {
+            self.add_border_to_list(list, &abs_box_bounds);
+        }
     }
 
-    fn add_bgcolor_to_list(list: &mut DisplayList, abs_bounds: &Rect<Au>) {
+    fn add_bgcolor_to_list(ctx: &LayoutContext, list: &mut DisplayList, abs_bounds: &Rect<Au>) {
         use std::cmp::FuzzyEq;
         // TODO: shouldn't need to unbox CSSValue by now
-        let boxed_bgcolor = self.d().node.style().background_color;
+        let node = self.d().node;
+        let boxed_bgcolor = node.style().background_color;
         let bgcolor = match boxed_bgcolor {
             Specified(BgColor(c)) => c,
+            // Form controls are filled even when the page doesn't say how,
+            // in the colors of their scheme
+            _ if is_form_control(node) => control_background(used_color_scheme(ctx, node)),
             Specified(BgColorTransparent) | _ => rgba(0,0,0,0.0)
         };
         if !bgcolor.alpha.fuzzy_eq(&0.0) {
@@ -454,6 +531,22 @@ impl RenderBox : RenderBoxMethods {
         }
     }
 
+    // color is inherited, so text is drawn in the color of the nearest node
+    // above it that gives one
+    fn text_color() -> Color {
+        let mut cur = Some(self.d().node);
+        loop {
+            match cur {
+                Some(n) if n.has_aux() => match n.style().text_color {
+                    Specified(TextColor(c)) => return c,
+                    _ => cur = n.read(|n| n.tree.parent)
+                },
+                Some(n) => cur = n.read(|n| n.tree.parent),
+                None => return rgb(0, 0, 0)
+            }
+        }
+    }
+
     fn add_border_to_list(list: &mut DisplayList, abs_bounds: &Rect<Au>) {
         let style = self.d().node.style();
         match style.border_width {
@@ -482,6 +575,66 @@ impl RenderBox : RenderBoxMethods {
     }
 }
 
+// The `width` and `height` of `node`, if they're given in px
+fn specified_size(node: Node) -> (Option<Au>, Option<Au>) {
+    let style = node.style();
+    let length = |value: CSSValue<BoxSizing>| match value {
+        Specified(BoxLength(Px(px))) => Some(au::from_frac_px(px)),
+        _ => None
+    };
+    (length(style.width), length(style.height))
+}
+
+/**
+The `aspect-ratio` of `node`.
+
+TODO: the style system doesn't know about `aspect-ratio` yet, so this only
+sees it in the element's `style` attribute.
+*/
+pub fn aspect_ratio(node: Node) -> Option<AspectRatio> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_style_property("aspect-ratio").chain(|v| parse_aspect_ratio(v)),
+            _ => None
+        }
+    }
+}
+
+/**
+The size of replaced content (CSS 2.1 Section 10.3.2 and 10.6.2, with
+`aspect-ratio`). A `width` or `height` that's given is used; one that's
+auto comes from the other and the aspect ratio. With neither, it's the
+natural size, with the height from the ratio if `aspect-ratio` overrides
+the natural one. An image that hasn't loaded has no natural size, so only
+`aspect-ratio` can fill in for it.
+*/
+pub pure fn replaced_size(natural: Option<Size2D<Au>>, width: Option<Au>, height: Option<Au>,
+                          aspect_ratio: Option<AspectRatio>) -> Size2D<Au> {
+    let natural_ratio = match natural {
+        Some(ref size) if size.height > Au(0) => {
+            Some(au::to_frac_px(size.width) / au::to_frac_px(size.height))
+        }
+        _ => None
+    };
+    let ratio = match aspect_ratio {
+        Some(ref aspect_ratio) => aspect_ratio.used_ratio(natural_ratio),
+        None => natural_ratio
+    };
+    let natural_width = natural.map_default(Au(0), |size| size.width);
+    let natural_height = natural.map_default(Au(0), |size| size.height);
+    match (width, height, ratio) {
+        (Some(width), Some(height), _) => Size2D(width, height),
+        (Some(width), None, Some(ratio)) => Size2D(width, height_for_width(width, ratio)),
+        (None, Some(height), Some(ratio)) => Size2D(width_for_height(height, ratio), height),
+        (Some(width), None, None) => Size2D(width, natural_height),
+        (None, Some(height), None) => Size2D(natural_width, height),
+        (None, None, Some(ratio)) if natural.is_some() => {
+            Size2D(natural_width, height_for_width(natural_width, ratio))
+        }
+        (None, None, _) => Size2D(natural_width, natural_height)
+    }
+}
+
 impl RenderBox : BoxedDebugMethods {
     fn dump(@self) {
         self.dump_indent(0u);
@@ -509,3 +662,53 @@ impl RenderBox : BoxedDebugMethods {
         fmt!("box b%?: %?", self.d().id, repr)
     }
 }
+
+// Whether `a` and `b` overlap or meet. A box without a size, like an image
+// that hasn't loaded and has no width or height given, still touches the
+// rects around its position.
+pure fn touches(a: &Rect<Au>, b: &Rect<Au>) -> bool {
+    a.origin.x <= b.origin.x + b.size.width && b.origin.x <= a.origin.x + a.size.width &&
+        a.origin.y <= b.origin.y + b.size.height && b.origin.y <= a.origin.y + a.size.height
+}
+
+#[cfg(test)]
+mod box_tests {
+    use css::values::aspect_ratio::AspectRatio;
+
+    fn px(n: int) -> Au { au::from_px(n) }
+
+    #[test]
+    fn test_replaced_size() {
+        let natural = Some(Size2D(px(200), px(100)));
+        // the natural size and ratio, filling in whatever isn't given
+        assert replaced_size(natural, None, None, None) == Size2D(px(200), px(100));
+        assert replaced_size(natural, Some(px(100)), None, None) == Size2D(px(100), px(50));
+        assert replaced_size(natural, None, Some(px(50)), None) == Size2D(px(100), px(50));
+        assert replaced_size(natural, Some(px(10)), Some(px(10)), None) == Size2D(px(10), px(10));
+
+        // aspect-ratio overrides the natural ratio, unless it's `auto`
+        let square = Some(AspectRatio { ratio: Some(1.0), auto: false });
+        assert replaced_size(natural, None, None, square) == Size2D(px(200), px(200));
+        assert replaced_size(natural, Some(px(100)), None, square) == Size2D(px(100), px(100));
+        let auto_square = Some(AspectRatio { ratio: Some(1.0), auto: true });
+        assert replaced_size(natural, Some(px(100)), None, auto_square) ==
+            Size2D(px(100), px(50));
+
+        // before the image loads, only aspect-ratio gives a ratio
+        let wide = Some(AspectRatio { ratio: Some(2.0), auto: true });
+        assert replaced_size(None, Some(px(160)), None, wide) == Size2D(px(160), px(80));
+        assert replaced_size(None, Some(px(160)), None, None) == Size2D(px(160), px(0));
+        assert replaced_size(None, None, None, wide) == Size2D(px(0), px(0));
+    }
+
+    #[test]
+    fn test_touches() {
+        let load_bounds = Rect(Point2D(px(0), px(600)), Size2D(px(800), px(1200)));
+        // a tall image starting above the bounds reaches into them
+        assert touches(&Rect(Point2D(px(10), px(100)), Size2D(px(50), px(600))), &load_bounds);
+        assert !touches(&Rect(Point2D(px(10), px(100)), Size2D(px(50), px(400))), &load_bounds);
+        // one without a size, far down the page
+        assert touches(&Rect(Point2D(px(10), px(1800)), Size2D(px(0), px(0))), &load_bounds);
+        assert !touches(&Rect(Point2D(px(10), px(1801)), Size2D(px(0), px(0))), &load_bounds);
+    }
+}
@@ -3,13 +3,25 @@ use au = gfx::geometry;
 use core::dvec::DVec;
 use css::styles::{SpecifiedStyle, empty_style_for_node_kind};
 use newcss::values::{CSSDisplay, DisplayBlock, DisplayInline, DisplayInlineBlock, DisplayNone};
+use newcss::values::{DisplayTable, DisplayTableRowGroup, DisplayTableHeaderGroup};
+use newcss::values::{DisplayTableFooterGroup, DisplayTableRow, DisplayTableCell};
+use newcss::values::{DisplayTableCaption, DisplayTableColumn, DisplayTableColumnGroup};
+use newcss::values::DisplayListItem;
+use css::values::list_style::{Inside, Outside};
 use newcss::values::{Inherit, Initial, Specified};
 use dom::element::*;
 use dom::node::{Comment, Doctype, Element, Text, Node, LayoutData};
-use image::holder::ImageHolder;
+use image::holder::{ImageHolder, LazyImageHolder};
 use layout::box::*;
 use layout::block::BlockFlowData;
 use layout::context::LayoutContext;
+use layout::float;
+use layout::grid::GridContext;
+use layout::shape_outside::ShapeSource;
+use layout::masonry::MasonryContext;
+use layout::multi_column::MultiColumnContext;
+use layout::list_marker::{make_marker_box, marker_style};
+use layout::table::{TableContext, TableBox, is_table_display, part_in_table};
 use layout::flow::*;
 use layout::inline::InlineFlowData;
 use layout::root::RootFlowData;
@@ -53,6 +65,12 @@ priv fn simulate_UA_display_rules(node: Node) -> CSSDisplay {
         }
     };
     if (resolved == DisplayNone) { return resolved; }
+    // columns only say how wide cells are; they have no boxes of their own
+    if (resolved == DisplayTableColumn || resolved == DisplayTableColumnGroup) {
+        return DisplayNone;
+    }
+    // floats are blocks, whatever their display (CSS 2.1 Section 9.7)
+    if float::float_side(node).is_some() { return DisplayBlock; }
 
     do node.read |n| {
         match n.kind {
@@ -68,12 +86,60 @@ priv fn simulate_UA_display_rules(node: Node) -> CSSDisplay {
                 ~HTMLHtmlElement(*) => DisplayBlock,
                 ~HTMLUListElement(*) => DisplayBlock,
                 ~HTMLOListElement(*) => DisplayBlock,
+                ~HTMLListItemElement(*) => DisplayListItem,
+                ~HTMLTableElement(*) => DisplayTable,
+                ~HTMLTableBodyElement(*) => DisplayTableRowGroup,
+                ~HTMLTableRowElement(*) => DisplayTableRow,
+                ~HTMLTableCellElement(*) => DisplayTableCell,
+                // Kept in the DOM, so screen readers still read it out
+                ~HTMLRubyParenthesisElement(*) => DisplayNone,
+                // table parts the parser has no element kinds for
+                ~UnknownElement => match e.tag_name {
+                    ~"th" => DisplayTableCell,
+                    ~"thead" => DisplayTableHeaderGroup,
+                    ~"tfoot" => DisplayTableFooterGroup,
+                    ~"caption" => DisplayTableCaption,
+                    ~"col" | ~"colgroup" => DisplayNone,
+                    _ => resolved
+                },
                 _ => resolved
             }
         }
     }
 }
 
+/**
+Whether `node` is `display: contents`, and so has no box of its own: its
+children are laid out as if they were its parent's, and its own background
+and border are never drawn. newcss doesn't know the value yet, so it's read
+from the element's style.
+*/
+priv fn has_display_contents(node: Node) -> bool {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => {
+                e.get_style_property("display").map(|v| str::trim(*v)) == Some(~"contents")
+            }
+            _ => false
+        }
+    }
+}
+
+// Replaced elements have no children to lay out in their place, so
+// `display: contents` hides them, like `display: none` (CSS Display 3,
+// Appendix B)
+priv fn is_replaced(node: Node) -> bool {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => match e.kind {
+                ~HTMLImageElement(*) | ~HTMLInputElement(*) => true,
+                _ => false
+            },
+            _ => false
+        }
+    }
+}
+
 impl BoxGenerator {
     static pure fn new(flow: @FlowContext) -> BoxGenerator {
         unsafe { debug!("Creating box generator for flow: %s", flow.debug_str()); }
@@ -127,6 +193,30 @@ impl BoxGenerator {
                 let new_box = builder.make_box(ctx, box_type, node, self.flow);
                 assert self.flow.block().box.is_none();
                 self.flow.block().box = Some(new_box);
+                self.flow.block().float = float::float_side(node);
+                self.flow.block().clear = float::clear_side(node);
+                if self.flow.block().float.is_some() {
+                    self.flow.block().shape_outside = ShapeSource(ctx, node);
+                }
+                self.flow.block().columns = match MultiColumnContext(node) {
+                    Some(move columns) => Some(@move columns),
+                    None => None
+                };
+                self.flow.block().grid = match GridContext(node) {
+                    Some(move grid) => Some(@move grid),
+                    None => None
+                };
+                if ctx.enable_masonry {
+                    self.flow.block().masonry = match MasonryContext(node) {
+                        Some(move masonry) => Some(@move masonry),
+                        None => None
+                    };
+                }
+                let part = part_in_table(simulated_display, tree::parent(&FlowTree, &self.flow));
+                self.flow.block().table_part = part;
+                if part == Some(TableBox) {
+                    self.flow.block().table = Some(@TableContext(node));
+                }
             },
             @RootFlow(*) => {
                 let new_box = builder.make_box(ctx, box_type, node, self.flow);
@@ -221,6 +311,12 @@ impl BuilderContext {
 
     fn containing_context_for_display(display: CSSDisplay,
                                       builder: &LayoutTreeBuilder) -> BuilderContext {
+        // every part of a table is a block; the table arranges them
+        let display = if is_table_display(display) || display == DisplayListItem {
+            DisplayBlock
+        } else {
+            display
+        };
         match (display, self.default_collector.flow) { 
             (DisplayBlock, @RootFlow(*)) => self.create_child_flow_of_type(Flow_Block, builder),
             (DisplayBlock, @BlockFlow(*)) => {
@@ -246,9 +342,20 @@ impl LayoutTreeBuilder {
     fn construct_recursively(layout_ctx: &LayoutContext, cur_node: Node, parent_ctx: &BuilderContext) {
         // DEBUG
         debug!("Considering node: %?", fmt!("%?", cur_node.read(|n| copy n.kind )));
+        layout_ctx.styler.ensure_style(cur_node);
+
+        if has_display_contents(cur_node) {
+            if is_replaced(cur_node) {
+                return;
+            }
+            for tree::each_child(&NodeTree, &cur_node) |child_node| {
+                self.construct_recursively(layout_ctx, *child_node, parent_ctx);
+            }
+            return;
+        }
 
         // TODO: remove this once UA styles work
-        // TODO: handle interactions with 'float', 'position' (CSS 2.1, Section 9.7)
+        // TODO: handle interactions with 'position' (CSS 2.1, Section 9.7)
         let simulated_display = match simulate_UA_display_rules(cur_node) {
             DisplayNone => return, // tree ends here if 'display: none'
             v => v
@@ -256,6 +363,9 @@ impl LayoutTreeBuilder {
 
         let this_ctx = parent_ctx.containing_context_for_display(simulated_display, &self);
         this_ctx.default_collector.push_node(layout_ctx, &self, cur_node);
+        if simulated_display == DisplayListItem {
+            self.make_list_marker(layout_ctx, cur_node, &this_ctx);
+        }
 
         // recurse on child nodes.
         for tree::each_child(&NodeTree, &cur_node) |child_node| {
@@ -370,6 +480,31 @@ impl LayoutTreeBuilder {
        disambiguate between different methods here instead of inlining, since each
        case has very different complexity 
     */
+    /** Gives a list item its marker: on the item's block flow if it's
+    outside, or as the first inline box of the item if it's inside. */
+    fn make_list_marker(layout_ctx: &LayoutContext, node: Node, item_ctx: &BuilderContext) {
+        match item_ctx.default_collector.flow {
+            @BlockFlow(*) => (),
+            // a list item in an inline gets no marker
+            _ => return
+        }
+        let style = marker_style(node);
+        match style.position {
+            Outside => {
+                let flow = item_ctx.default_collector.flow;
+                flow.block().marker = make_marker_box(layout_ctx, node, &style, flow,
+                                                      self.next_box_id());
+            }
+            Inside => {
+                let collector = item_ctx.get_inline_collector(&self).default_collector;
+                let flow = collector.flow;
+                for make_marker_box(layout_ctx, node, &style, flow, self.next_box_id()).each |box| {
+                    flow.inline().boxes.push(*box);
+                }
+            }
+        }
+    }
+
     fn make_box(layout_ctx: &LayoutContext, ty: RenderBoxType, node: Node, ctx: @FlowContext) -> @RenderBox {
         let ret = match ty {
             RenderBox_Generic => self.make_generic_box(layout_ctx, node, ctx),
@@ -392,8 +527,13 @@ impl LayoutTreeBuilder {
                         // TODO: this could be written as a pattern guard, but it triggers
                         // an ICE (mozilla/rust issue #3601)
                         if d.image.is_some() {
-                            let holder = ImageHolder({copy *d.image.get_ref()},
-                                                     layout_ctx.image_cache);
+                            let holder = if d.loading.is_lazy() {
+                                LazyImageHolder({copy *d.image.get_ref()},
+                                                layout_ctx.image_cache)
+                            } else {
+                                ImageHolder({copy *d.image.get_ref()},
+                                            layout_ctx.image_cache)
+                            };
 
                             @ImageBox(RenderBoxData(node, ctx, self.next_box_id()), move holder)
                         } else {
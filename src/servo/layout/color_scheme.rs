@@ -0,0 +1,100 @@
+/*!
+Works out which color scheme each part of the page is drawn in, from the
+`color-scheme` property, the page's `<meta name="color-scheme">` and what
+the user prefers. Only the colors the browser picks itself change with it;
+the page's own colors are left alone.
+*/
+
+use css::values::color_scheme::{ColorScheme, ColorSchemes, parse_color_scheme, resolve};
+use dom::element::{HTMLMetaElement, HTMLInputElement, HTMLSelectElement, HTMLTextAreaElement};
+use dom::node::{Element, Node};
+use layout::context::LayoutContext;
+
+// The `color-scheme` an element sets, if it sets a valid one
+fn specified_schemes(node: Node) -> Option<ColorSchemes> {
+    let value = do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_style_property("color-scheme"),
+            _ => None
+        }
+    };
+    match value {
+        Some(ref value) => parse_color_scheme(*value),
+        None => None
+    }
+}
+
+// The content of the first `<meta name="color-scheme">` under `root` that
+// is valid
+fn meta_schemes(root: Node) -> Option<ColorSchemes> {
+    let mut found = None;
+    do root.traverse_preorder |node| {
+        if found.is_none() {
+            let content = do node.read |n| {
+                match n.kind {
+                    ~Element(ref e) => match e.kind {
+                        ~HTMLMetaElement
+                            if e.get_attr("name").map_default(false, |name|
+                                   str::to_lower(str::trim(*name)) == ~"color-scheme") => {
+                            e.get_attr("content")
+                        }
+                        _ => None
+                    },
+                    _ => None
+                }
+            };
+            match content {
+                Some(ref content) => found = parse_color_scheme(*content),
+                None => ()
+            }
+        }
+    }
+    move found
+}
+
+/**
+The scheme of the page as a whole, which its canvas and the window's own
+controls are drawn in. The root element's `color-scheme` wins over the
+meta tag, as the meta tag only sets what the root gets by default.
+*/
+pub fn page_color_scheme(root: Node, preferred: ColorScheme) -> ColorScheme {
+    let schemes = match specified_schemes(root) {
+        Some(move schemes) => Some(move schemes),
+        None => meta_schemes(root)
+    };
+    match schemes {
+        Some(ref schemes) => resolve(schemes, preferred),
+        None => resolve(&ColorSchemes { schemes: ~[], only: false }, preferred)
+    }
+}
+
+/// The scheme `node` is drawn in. `color-scheme` inherits, so it's the
+/// nearest one set on the node or above it, or else the page's.
+pub fn used_color_scheme(ctx: &LayoutContext, node: Node) -> ColorScheme {
+    let mut cur = Some(node);
+    loop {
+        match cur {
+            Some(n) => {
+                match specified_schemes(n) {
+                    Some(ref schemes) => return resolve(schemes, ctx.preferred_color_scheme),
+                    None => cur = n.read(|n| n.tree.parent)
+                }
+            }
+            None => return ctx.color_scheme
+        }
+    }
+}
+
+/// Whether `node` is a form control, whose default colors the browser
+/// picks.
+pub fn is_form_control(node: Node) -> bool {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => match e.kind {
+                ~HTMLInputElement(*) | ~HTMLSelectElement | ~HTMLTextAreaElement => true,
+                _ => false
+            },
+            _ => false
+        }
+    }
+}
@@ -1,14 +1,50 @@
+use css::styles::Styler;
+use css::values::color_scheme::ColorScheme;
+use dom::node::Node;
 use resource::local_image_cache::LocalImageCache;
 use servo_text::font_cache::FontCache;
 use std::net::url::Url;
+use geom::point::Point2D;
 use geom::rect::Rect;
 use gfx::geometry::Au;
 
 /* Represents layout task context. */
 
 struct LayoutContext {
+    // Styles nodes as they're first looked at
+    styler: @Styler,
     font_cache: @FontCache,
     image_cache: @LocalImageCache,
     doc_url: Url,
-    screen_size: Rect<Au>
+    screen_size: Rect<Au>,
+    // How far the viewport is scrolled; the display list is shifted up and
+    // left by it
+    scroll_offset: Point2D<Au>,
+    // How far the scroll containers that have been scrolled are
+    element_scroll_offsets: ~[(Node, Point2D<Au>)],
+    // The viewport grown by the lazy image margin, in the page; deferred
+    // images that intersect it are loaded during display list building
+    image_load_bounds: Rect<Au>,
+    // Whether `display: masonry` is laid out, with --enable-masonry
+    enable_masonry: bool,
+    // The scheme the user prefers, and the one the page is drawn in
+    preferred_color_scheme: ColorScheme,
+    color_scheme: ColorScheme,
+    // Whether colors are replaced with the high contrast palette
+    forced_colors: bool,
+    // When this layout started, in ms, for the `font-display` of web fonts
+    font_time: uint
+}
+
+impl LayoutContext {
+    // How far `node`'s contents are scrolled, if it's a scroll container
+    fn element_scroll_offset(&self, node: Node) -> Point2D<Au> {
+        for self.element_scroll_offsets.each |entry| {
+            let (n, offset) = *entry;
+            if n == node {
+                return offset;
+            }
+        }
+        Point2D(Au(0), Au(0))
+    }
 }
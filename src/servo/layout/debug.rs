@@ -1,3 +1,11 @@
+use au = gfx::geometry;
+use au::Au;
+use dom::node::{Node, Doctype, Comment, Element, Text};
+use geom::rect::Rect;
+use layout::box::{RenderBox, GenericBox, ImageBox, TextBox, UnscannedTextBox};
+use layout::flow::{FlowContext, FlowTree, AbsoluteFlow, BlockFlow, FloatFlow, InlineBlockFlow,
+                   InlineFlow, RootFlow};
+
 trait BoxedDebugMethods {
     fn dump(@self);
     fn dump_indent(@self, ident: uint);
@@ -9,3 +17,122 @@ trait DebugMethods {
     fn dump_indent(&self, ident: uint);
     fn debug_str(&self) -> ~str;
 }
+
+// The tag name of an element, or what kind of node the others are
+fn node_name(node: Node) -> ~str {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => copy e.tag_name,
+            ~Text(*) => ~"#text",
+            ~Comment(*) => ~"#comment",
+            ~Doctype(*) => ~"#doctype"
+        }
+    }
+}
+
+/// One line of a layout dump: `depth` levels in, what `kind` of flow or box
+/// it is, the node it's for, and where it is, in px.
+pub fn dump_line(depth: uint, kind: &str, node_name: Option<~str>, position: &Rect<Au>) -> ~str {
+    let mut line = str::repeat("  ", depth) + kind;
+    for node_name.each |name| {
+        line += fmt!(" <%s>", *name);
+    }
+    line + fmt!(" at (%d, %d) size %dx%d\n",
+                au::to_px(position.origin.x), au::to_px(position.origin.y),
+                au::to_px(position.size.width), au::to_px(position.size.height))
+}
+
+fn dump_box(box: @RenderBox, depth: uint) -> ~str {
+    let kind = match box {
+        @GenericBox(*) => ~"GenericBox",
+        @ImageBox(*) => ~"ImageBox",
+        @TextBox(_, d) => {
+            fmt!("TextBox %?", str::substr(d.run.text, d.range.begin(), d.range.length()))
+        }
+        @UnscannedTextBox(_, ref s) => fmt!("UnscannedTextBox %?", *s)
+    };
+    dump_line(depth, kind, Some(node_name(box.d().node)), &box.d().position)
+}
+
+fn dump_flow(flow: @FlowContext, depth: uint) -> ~str {
+    let kind = match *flow {
+        AbsoluteFlow(*) => "AbsoluteFlow",
+        BlockFlow(*) => "BlockFlow",
+        FloatFlow(*) => "FloatFlow",
+        InlineBlockFlow(*) => "InlineBlockFlow",
+        InlineFlow(*) => "InlineFlow",
+        RootFlow(*) => "RootFlow"
+    };
+    let name = flow.d().node.map(|node| node_name(*node));
+    let mut dump = dump_line(depth, kind, move name, &flow.d().position);
+    match *flow {
+        BlockFlow(*) | InlineFlow(*) | RootFlow(*) => {
+            for flow.iter_all_boxes |box| {
+                dump += dump_box(box, depth + 1);
+            }
+        }
+        _ => ()
+    }
+    for FlowTree.each_child(flow) |child| {
+        dump += dump_flow(child, depth + 1);
+    }
+    move dump
+}
+
+/**
+Dumps the flow tree under `root` as text, for debugging and for checking
+layout against golden files: a line for each flow and then each of its
+boxes, indented under its parent, with the tag of the node it's for and its
+position in px. Flows are placed relative to their parent flow and boxes
+relative to their flow.
+*/
+pub fn dump_layout_tree(root: @FlowContext) -> ~str {
+    dump_flow(root, 0)
+}
+
+/// Compares a layout dump with what it should be, saying where the first
+/// line that differs is. None if they're the same.
+pub fn diff_layout_dumps(expected: &str, actual: &str) -> Option<~str> {
+    let expected = str::lines(expected);
+    let actual = str::lines(actual);
+    for uint::range(0, uint::max(expected.len(), actual.len())) |i| {
+        let want = if i < expected.len() { copy expected[i] } else { ~"(nothing)" };
+        let got = if i < actual.len() { copy actual[i] } else { ~"(nothing)" };
+        if want != got {
+            return Some(fmt!("line %u: expected `%s`, got `%s`", i + 1, want, got));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod debug_tests {
+    use geom::point::Point2D;
+    use geom::size::Size2D;
+
+    fn rect(x: int, y: int, width: int, height: int) -> Rect<Au> {
+        Rect(Point2D(au::from_px(x), au::from_px(y)),
+             Size2D(au::from_px(width), au::from_px(height)))
+    }
+
+    #[test]
+    fn test_dump_line() {
+        assert dump_line(0, "RootFlow", None, &rect(0, 0, 800, 600))
+            == ~"RootFlow at (0, 0) size 800x600\n";
+        assert dump_line(2, "GenericBox", Some(~"div"), &rect(8, 16, 784, 20))
+            == ~"    GenericBox <div> at (8, 16) size 784x20\n";
+    }
+
+    #[test]
+    fn test_diff_layout_dumps() {
+        let golden = "RootFlow at (0, 0) size 800x600\n  BlockFlow <body> at (0, 0) size 800x20\n";
+        assert diff_layout_dumps(golden, golden).is_none();
+        assert diff_layout_dumps(golden, "RootFlow at (0, 0) size 800x600\n  BlockFlow <body> \
+                                          at (0, 0) size 800x40\n")
+            == Some(~"line 2: expected `  BlockFlow <body> at (0, 0) size 800x20`, \
+                      got `  BlockFlow <body> at (0, 0) size 800x40`");
+        assert diff_layout_dumps(golden, "RootFlow at (0, 0) size 800x600\n")
+            == Some(~"line 2: expected `  BlockFlow <body> at (0, 0) size 800x20`, \
+                      got `(nothing)`");
+    }
+}
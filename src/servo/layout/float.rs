@@ -0,0 +1,357 @@
+/*!
+Floats: boxes taken out of the flow and pushed to the left or right of
+their block, with the lines after them wrapping around. `clear` moves a
+block down past the floats on one side or both.
+
+A block lays out the floats among its children. Floats that hang out of
+the bottom of a block are handed to its parent, so that the block's later
+siblings wrap around them too.
+
+TODO: the style system doesn't know about `float` and `clear` yet, so this
+only sees them in the element's `style` attribute.
+*/
+
+use au = gfx::geometry;
+use dom::node::{Node, Element};
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use gfx::geometry::Au;
+use layout::shape_outside::Shape;
+
+pub enum FloatSide {
+    FloatLeft,
+    FloatRight,
+}
+
+impl FloatSide : cmp::Eq {
+    pure fn eq(&self, other: &FloatSide) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &FloatSide) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+pub enum ClearSide {
+    ClearNone,
+    ClearLeft,
+    ClearRight,
+    ClearBoth,
+}
+
+impl ClearSide : cmp::Eq {
+    pure fn eq(&self, other: &ClearSide) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &ClearSide) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+impl ClearSide {
+    pure fn clears(side: FloatSide) -> bool {
+        match (self, side) {
+            (ClearBoth, _) | (ClearLeft, FloatLeft) | (ClearRight, FloatRight) => true,
+            _ => false
+        }
+    }
+}
+
+/// The side `node` floats to, if it's a floated element.
+pub fn float_side(node: Node) -> Option<FloatSide> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => match e.get_style_property("float") {
+                Some(~"left") => Some(FloatLeft),
+                Some(~"right") => Some(FloatRight),
+                _ => None
+            },
+            _ => None
+        }
+    }
+}
+
+/// The sides of floats that `node` is moved down past.
+pub fn clear_side(node: Node) -> ClearSide {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => match e.get_style_property("clear") {
+                Some(~"left") => ClearLeft,
+                Some(~"right") => ClearRight,
+                Some(~"both") => ClearBoth,
+                _ => ClearNone
+            },
+            _ => ClearNone
+        }
+    }
+}
+
+pub struct PlacedFloat {
+    side: FloatSide,
+    bounds: Rect<Au>,
+    // what lines wrap around, in the float's coordinates, if not its box
+    shape: Option<@Shape>,
+}
+
+impl PlacedFloat {
+    /**
+    The part of the band from `band_top` to `band_bottom` that the float
+    keeps lines out of, from left to right. None if its shape doesn't reach
+    the band. With `shapes` false it's the float's box, which is what other
+    floats go beside.
+    */
+    pure fn exclusion(&self, band_top: Au, band_bottom: Au, shapes: bool) -> Option<(Au, Au)> {
+        if self.bounds.origin.y >= band_bottom || bottom(&self.bounds) <= band_top {
+            return None;
+        }
+        let left = self.bounds.origin.x;
+        let right = left + self.bounds.size.width;
+        match self.shape {
+            Some(shape) if shapes => {
+                let y = self.bounds.origin.y;
+                do shape.extent(band_top - y, band_bottom - y).map |extent| {
+                    // cut to the float's box
+                    let (l, r) = *extent;
+                    (au::max(left + l, left), au::min(left + r, right))
+                }
+            }
+            _ => Some((left, right))
+        }
+    }
+}
+
+pure fn bottom(bounds: &Rect<Au>) -> Au {
+    bounds.origin.y + bounds.size.height
+}
+
+/// The floats placed so far in a block, in its coordinates.
+pub struct FloatContext {
+    mut floats: ~[PlacedFloat],
+}
+
+pub fn FloatContext() -> FloatContext {
+    FloatContext { floats: ~[] }
+}
+
+impl FloatContext {
+    pure fn is_empty(&self) -> bool {
+        self.floats.is_empty()
+    }
+
+    fn add(&self, float: PlacedFloat) {
+        self.floats.push(float);
+    }
+
+    /// The bottom of the lowest float, or 0 if there are none.
+    pure fn bottom(&self) -> Au {
+        self.floats.foldl(Au(0), |b, f| au::max(*b, bottom(&f.bounds)))
+    }
+
+    /**
+    The part of a block `width` wide that floats leave for content, from
+    left to right, across the band from `y` down `height`. A band of no
+    height is taken to be the line at `y`.
+    */
+    pure fn available(&self, y: Au, height: Au, width: Au) -> (Au, Au) {
+        self.available_beside(y, height, width, true)
+    }
+
+    // The same, going by the floats' boxes or, with `shapes`, their shapes
+    priv pure fn available_beside(&self, y: Au, height: Au, width: Au,
+                                  shapes: bool) -> (Au, Au) {
+        let band_bottom = y + au::max(height, Au(1));
+        let mut left = Au(0);
+        let mut right = width;
+        for self.floats.each |f| {
+            match (f.side, f.exclusion(y, band_bottom, shapes)) {
+                (FloatLeft, Some((_, r))) => left = au::max(left, r),
+                (FloatRight, Some((l, _))) => right = au::min(right, l),
+                (_, None) => ()
+            }
+        }
+        (left, right)
+    }
+
+    /// The first place below `y` where a float ends, so that the space
+    /// left for content may change.
+    pure fn next_edge_below(&self, y: Au) -> Option<Au> {
+        let mut edge = None;
+        for self.floats.each |f| {
+            let b = bottom(&f.bounds);
+            if b > y && edge.map_default(true, |e| b < *e) {
+                edge = Some(b);
+            }
+        }
+        edge
+    }
+
+    /**
+    Like `next_edge_below`, but no more than `step` down while `y` is beside
+    a float with a shape, as the space beside a shape changes all the way
+    down it.
+    */
+    pure fn next_line_edge_below(&self, y: Au, step: Au) -> Option<Au> {
+        let beside_shape = do self.floats.any |f| {
+            f.shape.is_some() && f.bounds.origin.y <= y && bottom(&f.bounds) > y
+        };
+        match self.next_edge_below(y) {
+            Some(edge) if beside_shape => Some(au::min(edge, y + step)),
+            edge => edge
+        }
+    }
+
+    /// How far down `clear` moves something that would be at `y`.
+    pure fn clearance(&self, clear: ClearSide, y: Au) -> Au {
+        self.floats.foldl(y, |cleared, f| {
+            if clear.clears(f.side) { au::max(*cleared, bottom(&f.bounds)) } else { *cleared }
+        })
+    }
+
+    /**
+    Where a float of `size` goes on `side` of a block `width` wide: no
+    higher than `y` or the top of any float before it, then as high as it
+    fits beside the others, then as far to its side as it goes. A float
+    too wide to fit anywhere goes below all the others.
+    */
+    pure fn place(&self, side: FloatSide, size: Size2D<Au>, y: Au, width: Au) -> Rect<Au> {
+        let mut y = self.floats.foldl(y, |top, f| au::max(*top, f.bounds.origin.y));
+        loop {
+            let (left, right) = self.available_beside(y, size.height, width, false);
+            if right - left >= size.width {
+                let x = match side {
+                    FloatLeft => left,
+                    FloatRight => right - size.width
+                };
+                return Rect(Point2D(x, y), size);
+            }
+            match self.next_edge_below(y) {
+                Some(edge) => y = edge,
+                None => {
+                    let x = match side {
+                        FloatLeft => Au(0),
+                        FloatRight => width - size.width
+                    };
+                    return Rect(Point2D(x, y), size);
+                }
+            }
+        }
+    }
+
+    /**
+    The floats that reach below `y`, moved by `offset`. A block hands its
+    parent the ones hanging out of its bottom this way, and a parent gives
+    a child the ones beside it.
+    */
+    pure fn translated_below(&self, y: Au, offset: &Point2D<Au>) -> FloatContext {
+        let floats = do vec::filter_map(self.floats) |f| {
+            if bottom(&f.bounds) > y {
+                Some(PlacedFloat { side: f.side, bounds: f.bounds.translate(offset),
+                                   shape: f.shape })
+            } else {
+                None
+            }
+        };
+        FloatContext { floats: move floats }
+    }
+
+    fn add_all(&self, other: &FloatContext) {
+        for other.floats.each |f| {
+            self.add(copy *f);
+        }
+    }
+}
+
+#[cfg(test)]
+mod float_tests {
+    use layout::shape_outside::ShapePolygon;
+
+    fn px(n: int) -> Au { au::from_px(n) }
+
+    fn context(floats: &[(FloatSide, int, int, int, int)]) -> FloatContext {
+        let context = FloatContext();
+        for floats.each |f| {
+            let (side, x, y, w, h) = *f;
+            context.add(PlacedFloat {
+                side: side,
+                bounds: Rect(Point2D(px(x), px(y)), Size2D(px(w), px(h))),
+                shape: None
+            });
+        }
+        move context
+    }
+
+    #[test]
+    fn test_available() {
+        let floats = context([(FloatLeft, 0, 0, 100, 50), (FloatRight, 300, 20, 100, 50)]);
+        assert floats.available(px(0), px(10), px(400)) == (px(100), px(400));
+        assert floats.available(px(10), px(20), px(400)) == (px(100), px(300));
+        assert floats.available(px(50), px(10), px(400)) == (px(0), px(300));
+        assert floats.available(px(70), px(10), px(400)) == (px(0), px(400));
+        assert floats.next_edge_below(px(0)) == Some(px(50));
+        assert floats.next_edge_below(px(50)) == Some(px(70));
+        assert floats.next_edge_below(px(70)) == None;
+    }
+
+    #[test]
+    fn test_place() {
+        let floats = FloatContext();
+        let first = floats.place(FloatLeft, Size2D(px(100), px(50)), px(0), px(400));
+        assert first == Rect(Point2D(px(0), px(0)), Size2D(px(100), px(50)));
+        floats.add(PlacedFloat { side: FloatLeft, bounds: first, shape: None });
+
+        // Beside the first, and no higher than it
+        let second = floats.place(FloatRight, Size2D(px(100), px(30)), px(0), px(400));
+        assert second == Rect(Point2D(px(300), px(0)), Size2D(px(100), px(30)));
+        floats.add(PlacedFloat { side: FloatRight, bounds: second, shape: None });
+
+        // Too wide to go beside them until the right one ends
+        let third = floats.place(FloatLeft, Size2D(px(250), px(10)), px(0), px(400));
+        assert third == Rect(Point2D(px(100), px(30)), Size2D(px(250), px(10)));
+
+        // Wider than the block, so below everything
+        let wide = floats.place(FloatRight, Size2D(px(500), px(10)), px(0), px(400));
+        assert wide.origin.y == px(50);
+    }
+
+    #[test]
+    fn test_clearance() {
+        let floats = context([(FloatLeft, 0, 0, 100, 50), (FloatRight, 300, 0, 100, 80)]);
+        assert floats.clearance(ClearNone, px(10)) == px(10);
+        assert floats.clearance(ClearLeft, px(10)) == px(50);
+        assert floats.clearance(ClearRight, px(10)) == px(80);
+        assert floats.clearance(ClearBoth, px(10)) == px(80);
+        assert floats.clearance(ClearBoth, px(100)) == px(100);
+    }
+
+    #[test]
+    fn test_shapes() {
+        // a triangle pointing right, widest halfway down
+        let triangle = ShapePolygon(~[Point2D(px(0), px(0)), Point2D(px(100), px(50)),
+                                      Point2D(px(0), px(100))]);
+        let floats = FloatContext();
+        floats.add(PlacedFloat {
+            side: FloatLeft,
+            bounds: Rect(Point2D(px(0), px(0)), Size2D(px(100), px(100))),
+            shape: Some(@move triangle)
+        });
+        assert floats.available(px(0), px(10), px(400)) == (px(20), px(400));
+        assert floats.available(px(45), px(10), px(400)) == (px(100), px(400));
+        // other floats go by the box
+        let beside = floats.place(FloatLeft, Size2D(px(50), px(10)), px(0), px(400));
+        assert beside.origin.x == px(100);
+        // lines move down a step at a time beside the shape
+        assert floats.next_line_edge_below(px(0), px(20)) == Some(px(20));
+        assert floats.next_line_edge_below(px(90), px(20)) == Some(px(100));
+    }
+
+    #[test]
+    fn test_translated_below() {
+        // A 60px float in a 40px block hangs 20px into what follows
+        let floats = context([(FloatLeft, 0, 0, 100, 60), (FloatLeft, 100, 0, 50, 30)]);
+        let hanging = floats.translated_below(px(40), &Point2D(px(0), px(200)));
+        assert hanging.bottom() == px(260);
+        assert hanging.available(px(250), px(5), px(400)) == (px(100), px(400));
+        assert hanging.available(px(210), px(5), px(400)) == (px(100), px(400));
+    }
+}
@@ -0,0 +1,829 @@
+/*!
+Grid track sizing (CSS Grid Level 2, Section 11), along one axis at a time:
+columns are sized from items' min- and max-content widths, rows from their
+heights.
+
+A subgrid (`grid-template-columns: subgrid`) has no tracks of its own. Its
+items are sized as if they were the parent's, in the parent tracks the
+subgrid spans, so sizing takes two passes: `size_tracks` sizes the
+parent's tracks with the subgrid's items among its own, and then
+`subgrid_layout` gives the subgrid those tracks to place its items in.
+
+A block with `display: grid` is laid out by a `GridContext`. Its children
+are placed in cells by `grid-column` and `grid-row` or, without them,
+automatically, row by row. Columns are then sized from the children's
+widths, and rows from their heights once those are known.
+
+TODO: newcss doesn't know `display: grid` or these properties, so this
+only sees them in the element's `style` attribute. Children aren't
+stretched to the height of their rows, inline content directly in the
+grid is one item rather than being wrapped per run, and
+`grid-template-rows: subgrid` is taken as no rows.
+*/
+
+use au = gfx::geometry;
+use css::values::grid_template::{TrackSize, FixedTrack, FlexTrack, AutoTrack};
+use css::values::grid_template::{TrackList, Tracks, Subgrid, parse_track_list};
+use dom::node::{Node, Element};
+use gfx::geometry::Au;
+use layout::flow::{FlowContext, FlowTree, BlockFlow};
+
+/// An item's place in a grid along one axis, and its content's sizes
+/// along it.
+pub struct GridItem {
+    // The first track it's in, counting from 0
+    start: uint,
+    span: uint,
+    min_content: Au,
+    max_content: Au,
+    // A subgrid's own items, placed in the tracks it spans
+    subgrid_items: Option<~[GridItem]>,
+}
+
+/// What an item asks of the tracks it spans.
+pub struct Contribution {
+    start: uint,
+    span: uint,
+    min_content: Au,
+    max_content: Au,
+}
+
+/// The sized tracks of a grid along one axis.
+pub struct GridLayout {
+    sizes: ~[Au],
+    // Where each track starts, from the grid's content edge
+    offsets: ~[Au],
+}
+
+// A subgrid has no implicit tracks, so items placed past its end go in its
+// last track
+pure fn clamp(start: uint, span: uint, track_count: uint) -> (uint, uint) {
+    let start = uint::min(start, track_count - 1);
+    (start, uint::max(1, uint::min(span, track_count - start)))
+}
+
+/**
+What the items ask of a grid's tracks, `offset` tracks in. A subgrid
+counts for nothing itself; its items count as the grid's.
+*/
+pub fn contributions(items: &[GridItem], offset: uint) -> ~[Contribution] {
+    let mut result = ~[];
+    for items.each |item| {
+        match item.subgrid_items {
+            Some(ref subitems) => {
+                for contributions(*subitems, 0).each |c| {
+                    let (start, span) = clamp(c.start, c.span, item.span);
+                    result.push(Contribution { start: offset + item.start + start, span: span,
+                                               min_content: c.min_content,
+                                               max_content: c.max_content });
+                }
+            }
+            None => {
+                result.push(Contribution { start: offset + item.start, span: item.span,
+                                           min_content: item.min_content,
+                                           max_content: item.max_content });
+            }
+        }
+    }
+    move result
+}
+
+pure fn is_auto(track: &TrackSize) -> bool {
+    match *track { AutoTrack => true, _ => false }
+}
+
+pure fn scale(au: Au, n: uint) -> Au { Au(*au * n as i32) }
+pure fn divide(au: Au, n: uint) -> Au { Au(*au / n as i32) }
+
+// What's left of `available` once `used` is taken from it
+pure fn free_space(available: Au, used: Au) -> Au {
+    if available > used { available - used } else { Au(0) }
+}
+
+// Grows the auto tracks among `start..end` by `extra` between them, in
+// equal shares
+fn grow_auto_tracks(tracks: &[TrackSize], sizes: &mut ~[Au], start: uint, end: uint,
+                    extra: Au) {
+    let autos = vec::filter(vec::from_fn(end - start, |i| start + i), |i| is_auto(&tracks[*i]));
+    if autos.is_empty() {
+        return;
+    }
+    let share = divide(extra, autos.len());
+    for autos.each |i| {
+        sizes[*i] += share;
+    }
+}
+
+/**
+Sizes `tracks` for items asking `items` of them, in `available` space
+with `gap` between tracks:
+
+1. Fixed tracks are their size.
+2. Auto tracks are at least the min-content size of the items in them
+   alone, and grow up to the items' max-content sizes. Items spanning
+   several tracks grow the auto ones among them, in equal shares, by what
+   the tracks don't already give them.
+3. Space left over grows auto tracks up to their limits, then goes to the
+   `fr` tracks, by their shares. Without `fr` tracks, auto tracks stretch
+   to fill it.
+*/
+pub fn size_tracks(tracks: &[TrackSize], items: &[Contribution], available: Au,
+                   gap: Au) -> ~[Au] {
+    let count = tracks.len();
+    if count == 0 {
+        return ~[];
+    }
+    let mut base = vec::from_elem(count, Au(0));
+    let mut limit = vec::from_elem(count, Au(0));
+    for tracks.eachi |i, track| {
+        match *track {
+            FixedTrack(size) => { base[i] = size; limit[i] = size; }
+            _ => ()
+        }
+    }
+
+    // Items in one track
+    for items.each |item| {
+        if item.span == 1 && item.start < count && !is_fixed(&tracks[item.start]) {
+            base[item.start] = au::max(base[item.start], item.min_content);
+            limit[item.start] = au::max(limit[item.start], item.max_content);
+        }
+    }
+
+    // Items spanning several, narrowest spans first
+    let spanning = std::sort::merge_sort(
+        vec::filter(items, |item| item.span > 1 && item.start < count),
+        |a, b| a.span <= b.span);
+    for spanning.each |item| {
+        let end = uint::min(item.start + item.span, count);
+        let gaps = scale(gap, end - item.start - 1);
+        let mut have = gaps;
+        let mut have_limit = gaps;
+        for uint::range(item.start, end) |i| {
+            have += base[i];
+            have_limit += au::max(base[i], limit[i]);
+        }
+        if item.min_content > have {
+            grow_auto_tracks(tracks, &mut base, item.start, end, item.min_content - have);
+        }
+        if item.max_content > have_limit {
+            grow_auto_tracks(tracks, &mut limit, item.start, end, item.max_content - have_limit);
+        }
+    }
+    for uint::range(0, count) |i| {
+        limit[i] = au::max(limit[i], base[i]);
+    }
+
+    let gaps = scale(gap, count - 1);
+    let used = |base: &~[Au]| base.foldl(gaps, |total, size| *total + *size);
+
+    // Grow auto tracks towards their limits, in equal shares
+    loop {
+        let free = free_space(available, used(&base));
+        let growing = vec::filter(vec::from_fn(count, |i| i),
+                                  |i| is_auto(&tracks[*i]) && base[*i] < limit[*i]);
+        if free == Au(0) || growing.is_empty() {
+            break;
+        }
+        let share = au::max(divide(free, growing.len()), Au(1));
+        let mut grew = false;
+        for growing.each |i| {
+            let growth = au::min(share, limit[*i] - base[*i]);
+            if growth > Au(0) && used(&base) + growth <= available {
+                base[*i] += growth;
+                grew = true;
+            }
+        }
+        if !grew {
+            break;
+        }
+    }
+
+    let flex_total = tracks.foldl(0.0, |total, track| match *track {
+        FlexTrack(fr) => *total + fr,
+        _ => *total
+    });
+    if flex_total > 0.0 {
+        // What one fr is: the space the other tracks leave, shared out
+        let mut inflexible = gaps;
+        for tracks.eachi |i, track| {
+            if !is_flex(track) {
+                inflexible += base[i];
+            }
+        }
+        let leftover = free_space(available, inflexible);
+        for tracks.eachi |i, track| {
+            match *track {
+                FlexTrack(fr) => {
+                    let size = Au(float::floor(*leftover as float * fr / flex_total) as i32);
+                    base[i] = au::max(base[i], size);
+                }
+                _ => ()
+            }
+        }
+    } else {
+        let free = free_space(available, used(&base));
+        grow_auto_tracks(tracks, &mut base, 0, count, free);
+    }
+    move base
+}
+
+pure fn is_fixed(track: &TrackSize) -> bool {
+    match *track { FixedTrack(*) => true, _ => false }
+}
+
+pure fn is_flex(track: &TrackSize) -> bool {
+    match *track { FlexTrack(*) => true, _ => false }
+}
+
+/// Where tracks of `sizes` start, with `gap` between them.
+pub fn track_offsets(sizes: &[Au], gap: Au) -> ~[Au] {
+    let mut offsets = ~[];
+    let mut offset = Au(0);
+    for sizes.each |size| {
+        offsets.push(offset);
+        offset += *size + gap;
+    }
+    move offsets
+}
+
+/// Sizes and places a grid's tracks along one axis. Subgrids among `items`
+/// have their items sized in this grid's tracks.
+pub fn layout_grid(tracks: &[TrackSize], items: &[GridItem], available: Au,
+                   gap: Au) -> GridLayout {
+    let sizes = size_tracks(tracks, contributions(items, 0), available, gap);
+    let offsets = track_offsets(sizes, gap);
+    GridLayout { sizes: move sizes, offsets: move offsets }
+}
+
+/// The tracks of `subgrid`, an item of a grid laid out as `parent`: the
+/// parent's tracks it spans, from its own edge.
+pub fn subgrid_layout(parent: &GridLayout, subgrid: &GridItem) -> GridLayout {
+    let count = parent.sizes.len();
+    let (start, span) = clamp(subgrid.start, subgrid.span, count);
+    let sizes = vec::slice(parent.sizes, start, start + span);
+    let origin = parent.offsets[start];
+    let offsets = vec::map(vec::view(parent.offsets, start, start + span), |o| *o - origin);
+    GridLayout { sizes: move sizes, offsets: move offsets }
+}
+
+impl GridLayout {
+    /// The offset and size of the area of an item in `span` tracks from
+    /// `start`, gaps between them included.
+    fn area(start: uint, span: uint) -> (Au, Au) {
+        let (start, span) = clamp(start, span, self.sizes.len());
+        let end = start + span - 1;
+        (self.offsets[start], self.offsets[end] + self.sizes[end] - self.offsets[start])
+    }
+}
+
+/// Where an item asks to be along one axis: the track it starts in, if
+/// it says, and how many it spans.
+pub struct GridPlacement {
+    start: Option<uint>,
+    span: uint,
+}
+
+/// Where an item is: the first column and row it's in, counting from 0,
+/// and how many of each it spans.
+pub struct GridArea {
+    column: uint,
+    column_span: uint,
+    row: uint,
+    row_span: uint,
+}
+
+// A line number, counting from 1, as the track it starts
+fn parse_line(value: &str) -> Option<uint> {
+    match int::from_str(value) {
+        Some(n) if n > 0 => Some(n as uint - 1),
+        _ => None
+    }
+}
+
+// `span <n>`
+fn parse_span(value: &str) -> Option<uint> {
+    let words = str::words(value);
+    if words.len() != 2 || words[0] != ~"span" {
+        return None;
+    }
+    match uint::from_str(words[1]) {
+        Some(n) if n > 0 => Some(n),
+        _ => None
+    }
+}
+
+/**
+Parses `grid-column` or `grid-row`: `<line>`, `<line> / <line>`,
+`span <n>` or `<line> / span <n>`, with lines counted from 1. Negative
+and named lines aren't supported.
+*/
+pub fn parse_grid_placement(value: &str) -> Option<GridPlacement> {
+    let parts = str::split_char(value, '/').map(|p| str::trim(*p));
+    match parts.len() {
+        1 => match (parse_line(parts[0]), parse_span(parts[0])) {
+            (Some(start), _) => Some(GridPlacement { start: Some(start), span: 1 }),
+            (None, Some(span)) => Some(GridPlacement { start: None, span: span }),
+            _ => None
+        },
+        2 => match (parse_line(parts[0]), parse_line(parts[1]), parse_span(parts[1])) {
+            (Some(start), Some(end), _) if end > start => {
+                Some(GridPlacement { start: Some(start), span: end - start })
+            }
+            (Some(start), None, Some(span)) => Some(GridPlacement { start: Some(start), span: span }),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// A length in px, or 0.
+pub fn parse_length(value: &str) -> Option<Au> {
+    if value == "0" {
+        return Some(Au(0));
+    }
+    if !value.ends_with("px") {
+        return None;
+    }
+    float::from_str(value.slice(0, value.len() - 2)).map(|px| au::from_frac_px(*px))
+}
+
+// Whether the cells of `area` are free in a grid `columns` wide, `taken`
+// a row at a time. Rows past the end are empty.
+pure fn is_free(taken: &[bool], columns: uint, area: &GridArea) -> bool {
+    for uint::range(area.row, area.row + area.row_span) |row| {
+        for uint::range(area.column, area.column + area.column_span) |column| {
+            let cell = row * columns + column;
+            if cell < taken.len() && taken[cell] {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn take(taken: &mut ~[bool], columns: uint, area: &GridArea) {
+    while taken.len() < (area.row + area.row_span) * columns {
+        taken.push(false);
+    }
+    for uint::range(area.row, area.row + area.row_span) |row| {
+        for uint::range(area.column, area.column + area.column_span) |column| {
+            taken[row * columns + column] = true;
+        }
+    }
+}
+
+/**
+Places items asking for `placements`, of their column and row, in a grid
+of `column_count` columns, as auto-placement without `dense` does (CSS
+Grid Section 8.5):
+
+1. Items asking for a column and a row go there.
+2. Items asking for only a row go in its first columns they fit in.
+3. The rest go, in order, in the first place they fit after the previous
+   one, row by row. Those asking for a column move down to where it's
+   free.
+
+Rows are added as they're needed. Spans are cut to fit the columns.
+*/
+pub fn place_items(placements: &[(GridPlacement, GridPlacement)],
+                   column_count: uint) -> ~[GridArea] {
+    let column_count = uint::max(column_count, 1);
+    let mut taken = ~[];
+    let mut areas = vec::from_elem(placements.len(), GridArea { column: 0, column_span: 1,
+                                                                row: 0, row_span: 1 });
+    let mut placed = vec::from_elem(placements.len(), false);
+    let column_area = |column: &GridPlacement, start: uint, row: uint, row_span: uint| {
+        let start = uint::min(start, column_count - 1);
+        GridArea { column: start, column_span: uint::min(column.span, column_count - start),
+                   row: row, row_span: row_span }
+    };
+
+    for placements.eachi |i, placement| {
+        let (column, row) = *placement;
+        match (column.start, row.start) {
+            (Some(c), Some(r)) => {
+                areas[i] = column_area(&column, c, r, row.span);
+                take(&mut taken, column_count, &areas[i]);
+                placed[i] = true;
+            }
+            _ => ()
+        }
+    }
+
+    for placements.eachi |i, placement| {
+        let (column, row) = *placement;
+        match (column.start, row.start) {
+            (None, Some(r)) => {
+                let mut area = column_area(&column, 0, r, row.span);
+                while !is_free(taken, column_count, &area) &&
+                        area.column + area.column_span < column_count {
+                    area.column += 1;
+                }
+                areas[i] = area;
+                take(&mut taken, column_count, &areas[i]);
+                placed[i] = true;
+            }
+            _ => ()
+        }
+    }
+
+    let mut cursor_row = 0;
+    let mut cursor_column = 0;
+    for placements.eachi |i, placement| {
+        if placed[i] {
+            loop;
+        }
+        let (column, row) = *placement;
+        let mut area = match column.start {
+            Some(c) => {
+                let mut area = column_area(&column, c, cursor_row, row.span);
+                if area.column < cursor_column {
+                    area.row += 1;
+                }
+                area
+            }
+            None => GridArea { column: cursor_column,
+                               column_span: uint::min(column.span, column_count),
+                               row: cursor_row, row_span: row.span }
+        };
+        loop {
+            if column.start.is_none() && area.column + area.column_span > column_count {
+                area.row += 1;
+                area.column = 0;
+            } else if is_free(taken, column_count, &area) {
+                break;
+            } else if column.start.is_some() {
+                area.row += 1;
+            } else {
+                area.column += 1;
+            }
+        }
+        areas[i] = area;
+        take(&mut taken, column_count, &areas[i]);
+        cursor_row = area.row;
+        cursor_column = area.column + area.column_span;
+    }
+    move areas
+}
+
+fn property(node: Node, name: &str) -> Option<~str> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_style_property(name),
+            _ => None
+        }
+    }
+}
+
+// The children of `flow`, as items of a grid
+fn children_of(flow: @FlowContext) -> ~[@FlowContext] {
+    let mut children = ~[];
+    for FlowTree.each_child(flow) |child| {
+        children.push(child);
+    }
+    move children
+}
+
+// Where `child` asks to be along the axis `name` is for
+fn child_placement(child: @FlowContext, name: &str) -> GridPlacement {
+    let auto = GridPlacement { start: None, span: 1 };
+    match *child {
+        BlockFlow(_, ref data) => {
+            data.box.chain(|b| property(b.d().node, name).chain(|v| parse_grid_placement(v)))
+                .get_default(auto)
+        }
+        _ => auto
+    }
+}
+
+// The grid `child` lays out its own children in, if it's a subgrid
+fn subgrid_of(child: @FlowContext) -> Option<@GridContext> {
+    match *child {
+        BlockFlow(_, ref data) => match data.grid {
+            Some(grid) if grid.is_subgrid() => Some(grid),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/**
+Places `children` in a grid of `column_count` columns, or more if they
+ask for columns past those and `grows`. Returns where each goes, and how
+many columns the grid then has.
+*/
+fn place_children(children: &[@FlowContext], column_count: uint,
+                  grows: bool) -> (~[GridArea], uint) {
+    let placements = do children.map |child| {
+        (child_placement(*child, "grid-column"), child_placement(*child, "grid-row"))
+    };
+    let mut count = uint::max(column_count, 1);
+    if grows {
+        for placements.each |placement| {
+            let (column, _) = *placement;
+            for column.start.each |start| {
+                count = uint::max(count, *start + column.span);
+            }
+        }
+    }
+    (place_items(placements, count), count)
+}
+
+pub struct GridContext {
+    columns: TrackList,
+    rows: ~[TrackSize],
+    column_gap: Au,
+    row_gap: Au,
+    // where each child goes, once widths are assigned
+    mut areas: ~[GridArea],
+    // the parent grid's columns a subgrid spans, from its own edge
+    mut parent_columns: Option<GridLayout>,
+}
+
+/// The grid `node` lays its children out in, or None if it isn't
+/// `display: grid`.
+pub fn GridContext(node: Node) -> Option<GridContext> {
+    if property(node, "display").map(|v| str::trim(*v)) != Some(~"grid") {
+        return None;
+    }
+    let columns = match property(node, "grid-template-columns").chain(|v| parse_track_list(v)) {
+        Some(move columns) => move columns,
+        None => Tracks(~[])
+    };
+    let rows = match property(node, "grid-template-rows").chain(|v| parse_track_list(v)) {
+        Some(Tracks(move rows)) => move rows,
+        _ => ~[]
+    };
+    // `gap` is the row gap and then the column gap, or one for both
+    let gap = property(node, "gap").map_default(~[], |v| str::words(*v));
+    let gap_at = |i: uint| if i < gap.len() { parse_length(gap[i]) } else { None };
+    let row_gap = match property(node, "row-gap").chain(|v| parse_length(v)) {
+        Some(gap) => gap,
+        None => gap_at(0).get_default(Au(0))
+    };
+    let column_gap = match (property(node, "column-gap").chain(|v| parse_length(v)), gap_at(1)) {
+        (Some(gap), _) | (None, Some(gap)) => gap,
+        (None, None) => gap_at(0).get_default(Au(0))
+    };
+    Some(GridContext {
+        columns: move columns,
+        rows: move rows,
+        column_gap: column_gap,
+        row_gap: row_gap,
+        areas: ~[],
+        parent_columns: None,
+    })
+}
+
+impl GridContext {
+    pure fn is_subgrid(&self) -> bool {
+        match self.columns { Subgrid => true, _ => false }
+    }
+
+    // The grid's columns when there are `count` of them: the template's,
+    // then auto columns for items placed past its end
+    fn column_tracks(&self, count: uint) -> ~[TrackSize] {
+        let explicit = match self.columns {
+            Tracks(ref tracks) => copy *tracks,
+            Subgrid => ~[]
+        };
+        let implicit = vec::from_elem(count - uint::min(count, explicit.len()), AutoTrack);
+        explicit + implicit
+    }
+
+    // How many columns the template has. A subgrid outside a grid has
+    // none of its own, so its columns are all implicit.
+    fn explicit_column_count(&self) -> uint {
+        match self.columns {
+            Tracks(ref tracks) => tracks.len(),
+            Subgrid => 0
+        }
+    }
+
+    // The children of a grid as items of it, in `areas`. A subgrid's
+    // children are placed in the columns it spans.
+    fn column_items(&self, children: &[@FlowContext], areas: &[GridArea]) -> ~[GridItem] {
+        do vec::from_fn(children.len()) |i| {
+            let child = children[i];
+            let area = areas[i];
+            let subgrid_items = do subgrid_of(child).map |subgrid| {
+                let grandchildren = children_of(child);
+                let (sub_areas, _) = place_children(grandchildren, area.column_span, false);
+                subgrid.column_items(grandchildren, sub_areas)
+            };
+            GridItem { start: area.column, span: area.column_span,
+                       min_content: child.d().min_width, max_content: child.d().pref_width,
+                       subgrid_items: move subgrid_items }
+        }
+    }
+
+    /// The min- and max-content widths of the grid of `flow`'s children.
+    fn bubble_widths(&self, flow: @FlowContext) -> (Au, Au) {
+        let children = children_of(flow);
+        let (areas, count) = place_children(children, self.explicit_column_count(), true);
+        let tracks = self.column_tracks(count);
+        let asked = contributions(self.column_items(children, areas), 0);
+        let width = |sizes: ~[Au]| sizes.foldl(scale(self.column_gap, count - 1),
+                                               |total, size| *total + *size);
+        let min = size_tracks(tracks, asked.map(|c| {
+            Contribution { start: c.start, span: c.span, min_content: c.min_content,
+                           max_content: c.min_content }
+        }), Au(0), self.column_gap);
+        let pref = size_tracks(tracks, asked.map(|c| {
+            Contribution { start: c.start, span: c.span, min_content: c.max_content,
+                           max_content: c.max_content }
+        }), Au(0), self.column_gap);
+        (width(move min), width(move pref))
+    }
+
+    /// Places the children of `flow` in the grid, and sizes the columns
+    /// across `available` from `left`. Each child is as wide as the
+    /// columns it spans, and a subgrid among them is given those columns.
+    fn assign_widths(&self, flow: @FlowContext, left: Au, available: Au) {
+        let children = children_of(flow);
+        let given = if self.is_subgrid() { copy self.parent_columns } else { None };
+        let columns = match move given {
+            Some(move columns) => {
+                let (areas, _) = place_children(children, columns.sizes.len(), false);
+                self.areas = move areas;
+                move columns
+            }
+            None => {
+                let (areas, count) = place_children(children, self.explicit_column_count(), true);
+                let columns = layout_grid(self.column_tracks(count),
+                                          self.column_items(children, areas), available,
+                                          self.column_gap);
+                self.areas = move areas;
+                move columns
+            }
+        };
+
+        for children.eachi |i, child| {
+            let area = self.areas[i];
+            let (x, width) = columns.area(area.column, area.column_span);
+            child.d().position.origin.x = left + x;
+            child.d().position.size.width = width;
+            for subgrid_of(*child).each |subgrid| {
+                let item = GridItem { start: area.column, span: area.column_span,
+                                      min_content: Au(0), max_content: Au(0),
+                                      subgrid_items: None };
+                subgrid.parent_columns = Some(subgrid_layout(&columns, &item));
+            }
+        }
+    }
+
+    /// Sizes the rows for the heights of `flow`'s children, in `height` if
+    /// the grid has one, and places the children in them. Returns the
+    /// height the rows take.
+    fn layout(&self, flow: @FlowContext, height: Option<Au>) -> Au {
+        let children = children_of(flow);
+        let row_count = self.areas.foldl(self.rows.len(), |count, area| {
+            uint::max(*count, area.row + area.row_span)
+        });
+        if row_count == 0 {
+            return Au(0);
+        }
+        let implicit = vec::from_elem(row_count - self.rows.len(), AutoTrack);
+        let tracks = self.rows + implicit;
+        let items = do vec::from_fn(children.len()) |i| {
+            let child_height = children[i].d().position.size.height;
+            Contribution { start: self.areas[i].row, span: self.areas[i].row_span,
+                           min_content: child_height, max_content: child_height }
+        };
+        let sizes = size_tracks(tracks, items, height.get_default(Au(0)), self.row_gap);
+        let offsets = track_offsets(sizes, self.row_gap);
+        let rows = GridLayout { sizes: move sizes, offsets: move offsets };
+
+        for children.eachi |i, child| {
+            let (y, _) = rows.area(self.areas[i].row, self.areas[i].row_span);
+            child.d().position.origin.y = y;
+        }
+        let (_, total) = rows.area(0, row_count);
+        total
+    }
+}
+
+#[cfg(test)]
+mod grid_tests {
+    fn px(n: int) -> Au { au::from_px(n) }
+
+    fn item(start: uint, span: uint, min: int, max: int) -> GridItem {
+        GridItem { start: start, span: span, min_content: px(min), max_content: px(max),
+                   subgrid_items: None }
+    }
+
+    #[test]
+    fn test_fixed_auto_flex() {
+        let tracks = ~[FixedTrack(px(100)), AutoTrack, FlexTrack(1.0), FlexTrack(2.0)];
+        let layout = layout_grid(tracks, ~[item(1, 1, 40, 60)], px(460), px(0));
+        // the auto track grows to its max-content size, and the fr tracks
+        // share the 300px left, 1:2
+        assert layout.sizes == ~[px(100), px(60), px(100), px(200)];
+        assert layout.offsets == ~[px(0), px(100), px(160), px(260)];
+    }
+
+    #[test]
+    fn test_auto_tracks_stretch() {
+        let tracks = ~[AutoTrack, AutoTrack];
+        let layout = layout_grid(tracks, ~[item(0, 1, 50, 50), item(1, 1, 30, 30)], px(200),
+                                 px(20));
+        // 100px left over, 50px each
+        assert layout.sizes == ~[px(100), px(80)];
+        assert layout.area(0, 2) == (px(0), px(200));
+    }
+
+    #[test]
+    fn test_spanning_items() {
+        let tracks = ~[AutoTrack, FixedTrack(px(50)), AutoTrack];
+        // needs 150px across the three; the fixed track gives 50px, and the
+        // auto tracks share the rest
+        let sizes = size_tracks(tracks, contributions(~[item(0, 3, 150, 150)], 0), px(0),
+                                px(0));
+        assert sizes == ~[px(50), px(50), px(50)];
+    }
+
+    #[test]
+    fn test_subgrid() {
+        let tracks = ~[AutoTrack, AutoTrack, AutoTrack];
+        // a subgrid over the last two columns, with an item in each
+        let subgrid = GridItem { start: 1, span: 2, min_content: Au(0), max_content: Au(0),
+                                 subgrid_items: Some(~[item(0, 1, 80, 80),
+                                                       item(1, 1, 40, 40)]) };
+        let items = ~[item(0, 1, 50, 50), move subgrid];
+
+        // pass one: the subgrid's items size the parent's tracks
+        let c = contributions(items, 0);
+        assert c.len() == 3;
+        assert c[1].start == 1 && c[2].start == 2;
+        let parent = layout_grid(tracks, items, px(200), px(0));
+        assert parent.sizes == ~[px(60), px(90), px(50)];
+
+        // pass two: the subgrid places its items in those tracks
+        let sub = subgrid_layout(&parent, &items[1]);
+        assert sub.sizes == ~[px(90), px(50)];
+        assert sub.offsets == ~[px(0), px(90)];
+        assert sub.area(1, 1) == (px(90), px(50));
+    }
+
+    fn auto() -> GridPlacement { GridPlacement { start: None, span: 1 } }
+
+    fn at(line: uint, span: uint) -> GridPlacement { GridPlacement { start: Some(line), span: span } }
+
+    fn cell(area: &GridArea) -> (uint, uint, uint, uint) {
+        (area.column, area.column_span, area.row, area.row_span)
+    }
+
+    #[test]
+    fn test_parse_grid_placement() {
+        match parse_grid_placement("2") {
+            Some(GridPlacement { start: Some(1), span: 1 }) => (),
+            _ => fail
+        }
+        match parse_grid_placement(" 1 / 3 ") {
+            Some(GridPlacement { start: Some(0), span: 2 }) => (),
+            _ => fail
+        }
+        match parse_grid_placement("span 3") {
+            Some(GridPlacement { start: None, span: 3 }) => (),
+            _ => fail
+        }
+        match parse_grid_placement("2 / span 2") {
+            Some(GridPlacement { start: Some(1), span: 2 }) => (),
+            _ => fail
+        }
+        assert parse_grid_placement("0").is_none();
+        assert parse_grid_placement("3 / 2").is_none();
+        assert parse_grid_placement("-1").is_none();
+        assert parse_grid_placement("header").is_none();
+    }
+
+    #[test]
+    fn test_auto_placement() {
+        // three columns. The third item asks for the second column, which
+        // is behind the cursor, so it goes a row down; the fourth spans two
+        // columns, which don't fit after it, so it goes another row down.
+        let placements = ~[(auto(), auto()), (auto(), auto()), (at(1, 2), auto()),
+                           (GridPlacement { start: None, span: 2 }, auto())];
+        let areas = place_items(placements, 3);
+        assert areas.map(|a| cell(a)) == ~[(0, 1, 0, 1), (1, 1, 0, 1), (1, 2, 1, 1),
+                                           (0, 2, 2, 1)];
+    }
+
+    #[test]
+    fn test_definite_items_placed_first() {
+        // the last item asks for the top left cell, so the others go round it
+        let placements = ~[(auto(), auto()), (auto(), at(0, 1)), (at(0, 1), at(0, 1))];
+        let areas = place_items(placements, 2);
+        assert areas.map(|a| cell(a)) == ~[(0, 1, 1, 1), (1, 1, 0, 1), (0, 1, 0, 1)];
+    }
+
+    #[test]
+    fn test_subgrid_clamps_items() {
+        let subgrid = GridItem { start: 0, span: 2, min_content: Au(0), max_content: Au(0),
+                                 subgrid_items: Some(~[item(5, 3, 70, 70)]) };
+        let c = contributions(~[move subgrid], 0);
+        // past the subgrid's end, so in its last track
+        assert c[0].start == 1 && c[0].span == 1;
+    }
+}
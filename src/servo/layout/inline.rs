@@ -2,7 +2,7 @@ use au = gfx::geometry;
 use core::dlist::DList;
 use core::dvec::DVec;
 use newcss::values::{BoxAuto, BoxLength, Px};
-use dom::node::Node;
+use dom::node::{Node, NodeTree, Element};
 use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
@@ -10,9 +10,15 @@ use gfx::display_list::{DisplayList, DisplayListBuilder};
 use gfx::geometry::Au;
 use layout::box::*;
 use layout::context::LayoutContext;
+use layout::float::FloatContext;
 use layout::flow::{FlowContext, InlineFlow};
+use layout::intrinsic::max_content_of_lines;
+use layout::ruby;
 use layout::text::TextBoxData;
 use num::Num;
+use servo_text::bidi;
+use servo_text::font::Font;
+use servo_text::font_cache::font_families;
 use servo_text::text_run::TextRun;
 use servo_text::util::*;
 use std::arc;
@@ -236,12 +242,12 @@ impl TextRunScanner {
                 // TODO(Issue #115): use actual CSS 'white-space' property of relevant style.
                 let compression = CompressWhitespaceNewline;
                 let transformed_text = transform_text(text, compression);
-                // TODO(Issue #116): use actual font for corresponding DOM node to create text run.
-                let run = @TextRun::new(ctx.font_cache.get_test_font(), move transformed_text);
+                let (font, visible) = font_for_node(ctx, in_boxes[self.clump.begin()].d().node);
+                let mut run = TextRun::new(font, move transformed_text);
+                run.invisible = !visible;
+                let run = @move run;
                 debug!("TextRunScanner: pushing single text box in range: %?", self.clump);
-                let new_box = layout::text::adapt_textbox_with_range(in_boxes[self.clump.begin()].d(), run,
-                                                                     Range(0, run.text.len()));
-                out_boxes.push(new_box);
+                push_text_boxes(in_boxes[self.clump.begin()], run, Range(0, run.text.len()), out_boxes);
             },
             (false, true) => {
                 // TODO(Issue #115): use actual CSS 'white-space' property of relevant style.
@@ -269,8 +275,12 @@ impl TextRunScanner {
 
                 // create the run, then make new boxes with the run and adjusted text indices
 
-                // TODO(Issue #116): use actual font for corresponding DOM node to create text run.
-                let run = @TextRun::new(ctx.font_cache.get_test_font(), move run_str);
+                // TODO(Issue #116): boxes whose nodes have different fonts
+                // are still shaped together, in the font of the first.
+                let (font, visible) = font_for_node(ctx, in_boxes[self.clump.begin()].d().node);
+                let mut run = TextRun::new(font, move run_str);
+                run.invisible = !visible;
+                let run = @move run;
                 debug!("TextRunScanner: pushing box(es) in range: %?", self.clump);
                 for self.clump.eachi |i| {
                     let range = new_ranges[i - self.clump.begin()];
@@ -279,8 +289,7 @@ impl TextRunScanner {
                               in_boxes[i].debug_str());
                         loop
                     }
-                    let new_box = layout::text::adapt_textbox_with_range(in_boxes[i].d(), run, range);
-                    out_boxes.push(new_box);
+                    push_text_boxes(in_boxes[i], run, range, out_boxes);
                 }
             }
         } /* /match */
@@ -307,12 +316,67 @@ impl TextRunScanner {
     } /* /fn flush_clump_to_list */
 }
 
+// The font `node`'s text is shaped in, and whether it's drawn yet. The
+// style system doesn't know about `font-family` yet, so it's looked for in
+// the `style` attributes of the node and its ancestors.
+fn font_for_node(ctx: &LayoutContext, node: Node) -> (@Font, bool) {
+    let mut current = Some(node);
+    while current.is_some() {
+        let node = current.get();
+        let family = do node.read |n| {
+            match n.kind {
+                ~Element(ref e) => e.get_style_property("font-family"),
+                _ => None
+            }
+        };
+        match move family {
+            Some(move family) => {
+                return ctx.font_cache.get_font_for_families(font_families(family),
+                                                            ctx.font_time);
+            }
+            None => ()
+        }
+        current = tree::parent(&NodeTree, &node);
+    }
+    (ctx.font_cache.get_test_font(), true)
+}
+
+// Pushes text boxes for `range` of `run`: one per bidi level run, so that
+// lines can be reordered box-wise, or one per character for ruby text, so
+// that it can be spaced out.
+fn push_text_boxes(in_box: @RenderBox, run: @TextRun, range: Range, out_boxes: &DVec<@RenderBox>) {
+    let per_character = match ruby::ruby_role(in_box.d().node) {
+        ruby::NotRuby => false,
+        _ => true
+    };
+    for run.iter_level_runs_for_range(range) |level_range| {
+        if per_character {
+            let mut i = level_range.begin();
+            while i < level_range.end() {
+                let next = str::char_range_at(run.text, i).next;
+                out_boxes.push(layout::text::adapt_textbox_with_range(in_box.d(), run,
+                                                                      Range(i, next - i)));
+                i = next;
+            }
+        } else {
+            out_boxes.push(layout::text::adapt_textbox_with_range(in_box.d(), run, level_range));
+        }
+    }
+}
+
+// TODO: get from CSS 'line-height' property
+pure fn line_height() -> Au {
+    au::from_px(20)
+}
+
 struct LineboxScanner {
     flow: @FlowContext,
     new_boxes: DVec<@RenderBox>,
     work_list: DList<@RenderBox>,
-    pending_line: {range: MutableRange, mut width: Au},
+    pending_line: {range: MutableRange, mut width: Au, mut left: Au, mut right: Au},
     line_spans: DVec<Range>,
+    line_tops: DVec<Au>,
+    mut cur_y: Au,
 }
 
 fn LineboxScanner(inline: @FlowContext) -> LineboxScanner {
@@ -322,8 +386,11 @@ fn LineboxScanner(inline: @FlowContext) -> LineboxScanner {
         flow: inline,
         new_boxes: DVec(),
         work_list: DList(),
-        pending_line: {range: util::range::empty_mut(), mut width: Au(0)},
-        line_spans: DVec()
+        pending_line: {range: util::range::empty_mut(), mut width: Au(0),
+                       mut left: Au(0), mut right: Au(0)},
+        line_spans: DVec(),
+        line_tops: DVec(),
+        cur_y: Au(0)
     }
 }
 
@@ -331,6 +398,8 @@ impl LineboxScanner {
     priv fn reset_scanner() {
         debug!("Resetting line box scanner's state for flow f%d.", self.flow.d().id);
         self.line_spans.set(~[]);
+        self.line_tops.set(~[]);
+        self.cur_y = Au(0);
         self.new_boxes.set(~[]);
         self.reset_linebox();
     }
@@ -338,6 +407,16 @@ impl LineboxScanner {
     priv fn reset_linebox() {
         self.pending_line.range.reset(0,0);
         self.pending_line.width = Au(0);
+        self.fit_line_to_floats();
+    }
+
+    // narrows the pending line to the space left beside any floats.
+    // TODO: lines taller than line_height() can run into a float below
+    priv fn fit_line_to_floats() {
+        let (left, right) = self.flow.inline().floats.available(self.cur_y, line_height(),
+                                                                self.flow.d().position.size.width);
+        self.pending_line.left = left;
+        self.pending_line.right = right;
     }
 
     pub fn scan_for_lines(ctx: &LayoutContext) {
@@ -393,6 +472,10 @@ impl LineboxScanner {
             self.flow.inline().lines.set(move boxes);
             ~[]
         };
+        do self.line_tops.swap |tops| {
+            self.flow.inline().line_tops.set(move tops);
+            ~[]
+        };
     }
 
     priv fn flush_current_line() {
@@ -400,28 +483,86 @@ impl LineboxScanner {
                self.line_spans.len(), self.pending_line);
         // set box horizontal offsets
         let line_range = self.pending_line.range.as_immutable();
-        let mut offset_x = Au(0);
+        let mut offset_x = self.pending_line.left;
         // TODO: interpretation of CSS 'text-direction' and 'text-align' 
         // will change from which side we start laying out the line.
         debug!("LineboxScanner: Setting horizontal offsets for boxes in line %u range: %?",
                self.line_spans.len(), line_range);
-        for line_range.eachi |i| {
-            let box_data = &self.new_boxes[i].d();
-            box_data.position.origin.x = offset_x;
-            offset_x += box_data.position.size.width;
+        // Boxes stay in logical order in the box list, but are placed on
+        // the line in visual order of their bidi levels.
+        let levels = vec::from_fn(line_range.length(), |i| {
+            match self.new_boxes[line_range.begin() + i] {
+                @TextBox(_, data) => data.run.level_at(data.range.begin()),
+                _ => 0u8
+            }
+        });
+        // A ruby base and its annotation are placed together, as wide as
+        // the wider of the two.
+        do self.new_boxes.borrow |boxes| {
+            let line_boxes = vec::view(boxes, line_range.begin(), line_range.end());
+            let pairs = ruby::ruby_pairs(line_boxes);
+            let mut pair_of = vec::from_elem(line_boxes.len(), None);
+            for pairs.eachi |p, pair| {
+                for pair.base.eachi |i| { pair_of[i] = Some(p); }
+                for pair.annotation.eachi |i| { pair_of[i] = Some(p); }
+            }
+            let mut placed = vec::from_elem(pairs.len(), false);
+
+            for bidi::visual_order(levels).each |i| {
+                match pair_of[*i] {
+                    Some(p) if placed[p] => (),
+                    Some(p) => {
+                        offset_x += ruby::place_pair(line_boxes, &pairs[p], offset_x);
+                        placed[p] = true;
+                    }
+                    None => {
+                        let box_data = &line_boxes[*i].d();
+                        box_data.position.origin.x = offset_x;
+                        offset_x += box_data.position.size.width;
+                    }
+                }
+            }
         }
 
         // clear line and add line mapping
         debug!("LineboxScanner: Saving information for flushed line %u.", self.line_spans.len());
+        // assign_height_inline finds the real line height; this is enough
+        // to tell which floats the next line is beside
+        let mut height = line_height();
+        for line_range.eachi |i| {
+            height = au::max(height, self.new_boxes[i].d().position.size.height);
+        }
+        self.line_tops.push(self.cur_y);
+        self.cur_y += height;
         self.line_spans.push(move line_range);
         self.reset_linebox();
     }
 
     // return value: whether any box was appended.
     priv fn try_append_to_line(ctx: &LayoutContext, in_box: @RenderBox) -> bool {
-        let remaining_width = self.flow.d().position.size.width - self.pending_line.width;
-        let in_box_width = in_box.d().position.size.width;
         let line_is_empty: bool = self.pending_line.range.length() == 0;
+        // a line too narrow beside floats for the box moves down past them
+        if line_is_empty {
+            let min_width = in_box.get_min_width(ctx);
+            let full_width = self.flow.d().position.size.width;
+            while self.pending_line.right - self.pending_line.left < min_width &&
+                  self.pending_line.right - self.pending_line.left < full_width {
+                match self.flow.inline().floats.next_line_edge_below(self.cur_y,
+                                                                     line_height()) {
+                    Some(y) => {
+                        self.cur_y = y;
+                        self.fit_line_to_floats();
+                    }
+                    None => break
+                }
+            }
+        }
+
+        // Ruby annotations are counted as if they were set on the line, which
+        // is more than a base and annotation take together
+        let line_width = self.pending_line.right - self.pending_line.left;
+        let remaining_width = line_width - self.pending_line.width;
+        let in_box_width = in_box.d().position.size.width;
 
         debug!("LineboxScanner: Trying to append box to line %u (box width: %?, remaining width: %?): %s",
                self.line_spans.len(), in_box_width, remaining_width, in_box.debug_str());
@@ -521,7 +662,13 @@ struct InlineFlowData {
     // vec of ranges into boxes that represent elements. These ranges
     // must be well-nested, and are only related to the content of
     // boxes (not lines). Ranges are only kept for non-leaf elements.
-    elems: ElementMapping
+    elems: ElementMapping,
+    // where each line starts. Lines are moved down past floats too
+    // narrow to fit beside.
+    line_tops: DVec<Au>,
+    // floats beside the flow, in its coordinates. The parent block sets
+    // these once it has placed them.
+    mut floats: FloatContext,
 }
 
 fn InlineFlowData() -> InlineFlowData {
@@ -529,6 +676,8 @@ fn InlineFlowData() -> InlineFlowData {
         boxes: DVec(),
         lines: DVec(),
         elems: ElementMapping::new(),
+        line_tops: DVec(),
+        floats: FloatContext(),
     }
 }
 
@@ -552,16 +701,17 @@ impl FlowContext : InlineLayout {
         scanner.scan_for_runs(ctx);
 
         let mut min_width = Au(0);
-        let mut pref_width = Au(0);
+        let mut box_lines = ~[];
 
         for self.inline().boxes.each |box| {
             debug!("FlowContext[%d]: measuring %s", self.d().id, box.debug_str());
             min_width = au::max(min_width, box.get_min_width(ctx));
-            pref_width = au::max(pref_width, box.get_pref_width(ctx));
+            box_lines.push(box.get_pref_line_widths(ctx));
         }
 
+        // boxes share lines, so their max-content widths add up
         self.d().min_width = min_width;
-        self.d().pref_width = pref_width;
+        self.d().pref_width = max_content_of_lines(box_lines);
     }
 
     /* Recursively (top-down) determines the actual width of child
@@ -575,7 +725,7 @@ impl FlowContext : InlineLayout {
         // over the box list, and/or put into RenderBox.
         for self.inline().boxes.each |box| {
             box.d().position.size.width = match *box {
-                @ImageBox(_,img) => au::from_px(img.get_size().get_default(Size2D(0,0)).width),
+                @ImageBox(*) => box.get_replaced_size().width,
                 @TextBox(*) => { /* text boxes are initialized with dimensions */
                                    box.d().position.size.width
                 },
@@ -597,21 +747,25 @@ impl FlowContext : InlineLayout {
     }
 
     fn assign_height_inline(@self, _ctx: &LayoutContext) {
-        // TODO: get from CSS 'line-height' property
-        let line_height = au::from_px(20);
+        let line_height = line_height();
         let mut cur_y = Au(0);
 
         for self.inline().lines.eachi |i, line_span| {
             debug!("assign_height_inline: processing line %u with box span: %?", i, line_span);
+            if i < self.inline().line_tops.len() {
+                cur_y = au::max(cur_y, self.inline().line_tops[i]);
+            }
             // coords relative to left baseline
             let mut linebox_bounding_box = au::zero_rect();
+            let mut over_height = Au(0);
+            let mut under_height = Au(0);
             let boxes = &self.inline().boxes;
             for line_span.eachi |box_i| {
                 let cur_box = boxes[box_i];
 
                 // compute box height.
                 cur_box.d().position.size.height = match cur_box {
-                    @ImageBox(_,img) => au::from_px(img.size().height),
+                    @ImageBox(*) => cur_box.get_replaced_size().height,
                     @TextBox(*) => { /* text boxes are initialized with dimensions */
                         cur_box.d().position.size.height
                     },
@@ -637,13 +791,31 @@ impl FlowContext : InlineLayout {
                     },
                     _ => fail fmt!("Tried to compute bounding box of unknown Box variant: %s", cur_box.debug_str())
                 };
-                cur_box.d().position.origin.y = cur_y;
                 debug!("assign_height_inline: bounding box for box b%d = %?", cur_box.d().id, bounding_box);
-                linebox_bounding_box = linebox_bounding_box.union(&bounding_box);
+                // ruby annotations go in a band over or under the line
+                match ruby::annotation_position(cur_box) {
+                    Some(ruby::RubyOver) => {
+                        over_height = au::max(over_height, bounding_box.size.height);
+                    }
+                    Some(ruby::RubyUnder) => {
+                        under_height = au::max(under_height, bounding_box.size.height);
+                    }
+                    None => {
+                        linebox_bounding_box = linebox_bounding_box.union(&bounding_box);
+                    }
+                }
                 debug!("assign_height_inline: linebox bounding box = %?", linebox_bounding_box);
             }
-            let linebox_height = linebox_bounding_box.size.height;
-            cur_y += au::max(line_height, linebox_height);
+            let linebox_height = au::max(line_height, linebox_bounding_box.size.height);
+            for line_span.eachi |box_i| {
+                let cur_box = boxes[box_i];
+                cur_box.d().position.origin.y = match ruby::annotation_position(cur_box) {
+                    Some(ruby::RubyOver) => cur_y,
+                    Some(ruby::RubyUnder) => cur_y + over_height + linebox_height,
+                    None => cur_y + over_height
+                };
+            }
+            cur_y += over_height + linebox_height + under_height;
         } // /lines.each |line_span|
 
         self.d().position.size.height = cur_y;
@@ -0,0 +1,128 @@
+/*!
+Intrinsic widths. A flow's min-content width (`min_width`) is the narrowest
+it can be without overflowing, breaking lines wherever it may; its
+max-content width (`pref_width`) is its width with lines broken only where
+they must be. Widths like `width: min-content`, and floats, which shrink
+to fit, are sized from these.
+*/
+
+use au = gfx::geometry;
+use css::values::intrinsic_size::{IntrinsicSize, MinContent, MaxContent, FitContent,
+                                  parse_intrinsic_size};
+use dom::node::{Node, Element};
+use gfx::geometry::Au;
+
+/// The room to size content in along the inline axis.
+pub enum AvailableSpace {
+    Definite(Au),
+    // As narrow as the content can be
+    MinContentSpace,
+    // As wide as the content wants to be
+    MaxContentSpace,
+}
+
+impl AvailableSpace {
+    /// The width of content with these intrinsic widths, sized in this
+    /// space. In a definite space, that's as much of it as the content
+    /// will take, but no less than its min-content width.
+    pure fn fit(min_content: Au, max_content: Au) -> Au {
+        match self {
+            Definite(width) => fit_content(min_content, max_content, width),
+            MinContentSpace => min_content,
+            MaxContentSpace => max_content
+        }
+    }
+}
+
+/// `fit-content(limit)`: `min(max-content, max(min-content, limit))`.
+pub pure fn fit_content(min_content: Au, max_content: Au, limit: Au) -> Au {
+    au::min(max_content, au::max(min_content, limit))
+}
+
+/// The space a box with `width: size` sizes its content in, when its
+/// containing block is `containing_width` wide.
+pub pure fn space_for(size: &IntrinsicSize, containing_width: Au) -> AvailableSpace {
+    match *size {
+        MinContent => MinContentSpace,
+        MaxContent => MaxContentSpace,
+        FitContent(limit) => Definite(limit.resolve(containing_width))
+    }
+}
+
+/**
+The max-content width of a run of inline boxes, given the widths of each
+box's lines. A box has more than one line only where a line must break in
+it, so the boxes are laid end to end, and a new line starts at each of
+those breaks.
+*/
+pub pure fn max_content_of_lines(boxes: &[~[Au]]) -> Au {
+    let mut widest = Au(0);
+    let mut line = Au(0);
+    for boxes.each |lines| {
+        for lines.eachi |i, width| {
+            if i > 0 {
+                widest = au::max(widest, line);
+                line = Au(0);
+            }
+            line += *width;
+        }
+    }
+    au::max(widest, line)
+}
+
+/**
+The intrinsic sizing keyword `node` has for its `width`, if any.
+
+TODO: the style system doesn't know about these keywords yet, so this only
+sees them in the element's `style` attribute.
+*/
+pub fn intrinsic_width(node: Node) -> Option<IntrinsicSize> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_style_property("width").chain(|v| parse_intrinsic_size(v)),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod intrinsic_tests {
+    use css::values::intrinsic_size::{LimitAu, LimitPercent};
+
+    fn px(n: int) -> Au { au::from_px(n) }
+
+    #[test]
+    fn test_fit() {
+        // min-content 50px, max-content 200px
+        assert MinContentSpace.fit(px(50), px(200)) == px(50);
+        assert MaxContentSpace.fit(px(50), px(200)) == px(200);
+        assert Definite(px(120)).fit(px(50), px(200)) == px(120);
+        // never wider than the content wants, nor narrower than it can be
+        assert Definite(px(500)).fit(px(50), px(200)) == px(200);
+        assert Definite(px(10)).fit(px(50), px(200)) == px(50);
+    }
+
+    #[test]
+    fn test_fit_content() {
+        assert fit_content(px(50), px(200), px(100)) == px(100);
+        assert fit_content(px(50), px(200), px(300)) == px(200);
+        assert fit_content(px(50), px(200), px(20)) == px(50);
+
+        let space = space_for(&FitContent(LimitAu(px(100))), px(400));
+        assert space.fit(px(50), px(200)) == px(100);
+        let space = space_for(&FitContent(LimitPercent(25.0)), px(400));
+        assert space.fit(px(50), px(200)) == px(100);
+        assert space_for(&MinContent, px(400)).fit(px(50), px(200)) == px(50);
+        assert space_for(&MaxContent, px(400)).fit(px(50), px(200)) == px(200);
+    }
+
+    #[test]
+    fn test_max_content_of_lines() {
+        // boxes on one line add up
+        assert max_content_of_lines(~[~[px(30)], ~[px(40)], ~[px(20)]]) == px(90);
+        // a forced break in the middle box starts a new line
+        assert max_content_of_lines(~[~[px(30)], ~[px(40), px(100)], ~[px(20)]]) == px(120);
+        assert max_content_of_lines(~[~[px(30)], ~[px(40), px(10)], ~[px(20)]]) == px(70);
+        assert max_content_of_lines(~[]) == Au(0);
+    }
+}
@@ -7,10 +7,10 @@ use au = gfx::geometry;
 use au::Au;
 use content::content_task;
 use core::dvec::DVec;
-use css::styles::apply_style;
+use css::styles::{Styler, SpecifiedStyle, apply_style};
 use newcss::values::Stylesheet;
 use dl = gfx::display_list;
-use dom::event::{Event, ReflowEvent};
+use dom::event::{Event, ReflowEvent, PaintedEvent};
 use dom::node::{Node, LayoutData};
 use geom::point::Point2D;
 use geom::rect::Rect;
@@ -20,13 +20,22 @@ use gfx::render_task;
 use gfx::render_layers::RenderLayer;
 use layout::box::RenderBox;
 use layout::box_builder::LayoutTreeBuilder;
+use layout::color_scheme::page_color_scheme;
 use layout::context::LayoutContext;
+use layout::debug::dump_layout_tree;
+use layout::flow::{RootFlow, BlockFlow, InlineFlow};
+use layout::paint_timing::{ContentfulPaint, find_contentful_paint};
+use layout::print::{PrintContext, PrintedPage, page_rule_for, find_breaks, paginate,
+                    build_page_display_lists};
 use opt = core::option;
+use platform::appearance::{preferred_color_scheme, set_native_color_scheme,
+                           forced_colors_active};
 use render_task::RenderTask;
 use resource::image_cache_task::{ImageCacheTask, ImageResponseMsg};
 use resource::local_image_cache::LocalImageCache;
 use servo_text::font_context::FontContext;
 use servo_text::font_cache::FontCache;
+use servo_text::font_display::FontDisplay;
 use servo_text::font_matcher::FontMatcher;
 use std::arc::ARC;
 use std::net::url::Url;
@@ -36,24 +45,47 @@ use std::cell::Cell;
 use layout::traverse::*;
 use comm::*;
 use task::*;
-use core::mutable::Mut;
+use opts::Opts;
 
 pub type LayoutTask = comm::Chan<Msg>;
 
 pub enum LayoutQuery {
-    ContentBox(Node)
+    ContentBox(Node),
+    // The content and border boxes, in px
+    Boxes(Node),
+    // The innermost node under a point in the page, in px
+    HitTest(Node, Point2D<int>),
+    // The flow tree, as text; see layout::debug
+    DumpLayout,
+    // The text and images the last frame showed; see layout::paint_timing
+    Contentful,
+    // How much memory layout is using
+    HeapSize
 }
 
 pub type LayoutQueryResponse = Result<LayoutQueryResponse_, ()>;
 
 enum LayoutQueryResponse_ {
-    ContentSize(Size2D<int>)
+    ContentSize(Size2D<int>),
+    NodeBoxes(Rect<int>, Rect<int>),
+    HitNode(Node),
+    LayoutDump(~str),
+    PaintedContent(ContentfulPaint),
+    // The bytes of the layout data and flow tree, of the styles, and of
+    // the font cache
+    LayoutHeapSize(uint, uint, uint)
 }
 
 pub enum Msg {
     AddStylesheet(Stylesheet),
+    // A family's `@font-face` font has started loading
+    WebFontRequested(~str, FontDisplay),
+    // The data of an `@font-face` rule's font for a family, or Err if it
+    // couldn't be loaded
+    WebFontLoaded(~str, Result<~[u8], ()>),
     BuildMsg(BuildData),
     QueryMsg(LayoutQuery, comm::Chan<LayoutQueryResponse>),
+    PrintMsg(PrintData),
     ExitMsg
 }
 
@@ -62,13 +94,35 @@ struct BuildData {
     url: Url,
     dom_event_chan: pipes::SharedChan<Event>,
     window_size: Size2D<uint>,
+    // How far the viewport is scrolled, in px
+    scroll_offset: Point2D<int>,
+    // How far each scroll container that has been scrolled is, in px
+    element_scroll_offsets: ~[(Node, Point2D<int>)],
+    // Whether content wants a PaintedEvent for the frame, for paint timing
+    report_paint: bool,
     content_join_chan: pipes::Chan<()>
 }
 
+struct PrintData {
+    node: Node,
+    url: Url,
+    dom_event_chan: pipes::SharedChan<Event>,
+    // The printer's resolution
+    dpi: uint,
+    pages_chan: pipes::Chan<~[PrintedPage]>,
+    content_join_chan: pipes::Chan<()>
+}
+
+// The time in ms, as `font-display` periods are measured
+fn now_ms() -> uint {
+    (std::time::precise_time_ns() / 1000000) as uint
+}
+
 fn LayoutTask(render_task: RenderTask,
-              img_cache_task: ImageCacheTask) -> LayoutTask {
-    do spawn_listener::<Msg> |from_content, move img_cache_task| {
-        Layout(render_task, img_cache_task.clone(), from_content).start();
+              img_cache_task: ImageCacheTask,
+              opts: Opts) -> LayoutTask {
+    do spawn_listener::<Msg> |from_content, move img_cache_task, move opts| {
+        Layout(render_task, img_cache_task.clone(), from_content, &opts).start();
     }
 }
 
@@ -82,12 +136,23 @@ struct Layout {
     font_matcher: @FontMatcher,
     // This is used to root auxilliary RCU reader data
     layout_refs: DVec<@LayoutData>,
-    stylesheet: Mut<Option<Stylesheet>>
+    styler: @Styler,
+    lazy_image_margin: float,
+    enable_masonry: bool,
+    // Forced colors mode whether or not the platform asks for it
+    forced_colors: bool,
+    // The flow tree built by the last layout
+    mut layout_root: Option<@FlowContext>,
+    // The part of the page the last layout showed
+    mut viewport: Rect<Au>,
+    // When, in ms, a relayout is due for text waiting on a web font
+    mut font_relayout_at: Option<uint>
 }
 
 fn Layout(render_task: RenderTask, 
          image_cache_task: ImageCacheTask,
-         from_content: comm::Port<Msg>) -> Layout {
+         from_content: comm::Port<Msg>,
+         opts: &Opts) -> Layout {
 
     let fctx = @FontContext::new();
 
@@ -99,7 +164,13 @@ fn Layout(render_task: RenderTask,
         font_matcher: @FontMatcher::new(fctx),
         font_cache: @FontCache::new(fctx),
         layout_refs: DVec(),
-        stylesheet: Mut(None)
+        styler: @Styler(),
+        lazy_image_margin: opts.lazy_image_margin,
+        enable_masonry: opts.enable_masonry,
+        forced_colors: opts.forced_colors,
+        layout_root: None,
+        viewport: au::zero_rect(),
+        font_relayout_at: None
     }
 }
 
@@ -117,6 +188,12 @@ impl Layout {
             AddStylesheet(move sheet) => {
                 self.handle_add_stylesheet(move sheet);
             }
+            WebFontRequested(move family, display) => {
+                self.font_cache.start_web_font_load(family, display, now_ms());
+            }
+            WebFontLoaded(move family, move data) => {
+                self.handle_web_font(move family, move data);
+            }
             BuildMsg(move data) => {
                 let data = Cell(move data);
 
@@ -130,6 +207,13 @@ impl Layout {
                     self.handle_query(query, chan)
                 }
             }
+            PrintMsg(move data) => {
+                let data = Cell(move data);
+
+                do time("layout: printing") {
+                    self.handle_print(data.take());
+                }
+            }
             ExitMsg => {
                 debug!("layout: ExitMsg received");
                 return false
@@ -140,16 +224,45 @@ impl Layout {
     }
 
     fn handle_add_stylesheet(sheet: Stylesheet) {
-        let sheet = Cell(move sheet);
-        do self.stylesheet.borrow_mut |mysheet| {
-            assert mysheet.is_none(); // FIXME: Support multiple sheets
-            *mysheet = Some(sheet.take());
+        // Nodes are restyled with it at the next build
+        self.styler.set_stylesheet(move sheet);
+    }
+
+    fn handle_web_font(family: ~str, data: Result<~[u8], ()>) {
+        // Text is shaped with it from the next build
+        match move data {
+            Ok(move data) => {
+                if self.font_cache.add_web_font(family, move data, now_ms()).is_err() {
+                    debug!("layout: couldn't use the web font for %s", family);
+                }
+            }
+            Err(()) => {
+                debug!("layout: no web font for %s", family);
+                self.font_cache.web_font_failed(family);
+            }
+        }
+    }
+
+    // Lays the page out again when text waiting for a web font should be
+    // drawn in its fallback, unless that's already on its way
+    fn schedule_font_relayout(now: uint, dom_event_chan: pipes::SharedChan<Event>) {
+        if self.font_relayout_at.map_default(false, |at| *at <= now) {
+            self.font_relayout_at = None;
+        }
+        match self.font_cache.next_font_change(now) {
+            Some(change) if self.font_relayout_at.map_default(true, |at| change < *at) => {
+                self.font_relayout_at = Some(change);
+                do spawn |move dom_event_chan| {
+                    std::timer::sleep(std::uv_global_loop::get(), change - now);
+                    dom_event_chan.send(ReflowEvent);
+                }
+            }
+            _ => ()
         }
     }
 
     fn handle_build(data: BuildData) {
 
-        let node = &data.node;
         // FIXME: Bad copy
         let doc_url = copy data.url;
         // FIXME: Bad clone
@@ -157,43 +270,156 @@ impl Layout {
 
         debug!("layout: received layout request for: %s", doc_url.to_str());
         debug!("layout: parsed Node tree");
-        debug!("%?", node.dump());
+        debug!("%?", data.node.dump());
+
+        let screen_size = Size2D(au::from_px(data.window_size.width as int),
+                                 au::from_px(data.window_size.height as int));
+        let scroll_point = Point2D(au::from_px(data.scroll_offset.x),
+                                   au::from_px(data.scroll_offset.y));
+        let element_offsets = do data.element_scroll_offsets.map |entry| {
+            let (node, offset) = *entry;
+            (node, Point2D(au::from_px(offset.x), au::from_px(offset.y)))
+        };
+        let (layout_root, layout_ctx) = self.lay_out(data.node, move doc_url,
+                                                     move dom_event_chan, screen_size,
+                                                     scroll_point, move element_offsets);
+
+        self.layout_root = Some(layout_root);
+        self.viewport = Rect(scroll_point, screen_size);
+        self.schedule_font_relayout(layout_ctx.font_time, data.dom_event_chan.clone());
+        if data.report_paint {
+            data.dom_event_chan.send(PaintedEvent(find_contentful_paint(layout_root,
+                                                                        &self.viewport)));
+        }
+
+        do time("layout: display list building") {
+            let builder = dl::DisplayListBuilder {
+                ctx: &layout_ctx,
+            };
+            let mut render_layer = RenderLayer {
+                display_list: DisplayList::new(),
+                size: Size2D(au::to_px(screen_size.width) as uint,
+                             au::to_px(screen_size.height) as uint)
+            };
+
+            // Shift the page up and left by the scroll offset, so that the
+            // scrolled-to part lands in the viewport
+            let scroll_offset = Point2D(au::from_px(-data.scroll_offset.x),
+                                        au::from_px(-data.scroll_offset.y));
+
+            // TODO: set options on the builder before building
+            // TODO: be smarter about what needs painting
+            layout_root.build_display_list_recurse(&builder, &copy layout_root.d().position,
+                                                   &scroll_offset,
+                                                   &mut render_layer.display_list);
+            self.render_task.send(render_task::RenderMsg(move render_layer));
+        } // time(layout: display list building)
+
+        // Tell content we're done
+        data.content_join_chan.send(());
+
+    }
+
+    /**
+    Lays the document out again as wide as the printed page, cuts it into
+    pages and sends content their display lists. The flow tree the screen
+    showed is kept for queries, and nodes are pointed back at its flows.
+    */
+    fn handle_print(data: PrintData) {
+        // Building the printed flows points nodes at them
+        let screen_flows = DVec();
+        do data.node.traverse_preorder |node| {
+            let flow = if node.has_aux() { node.aux(|d| d.flow) } else { None };
+            screen_flows.push((node, flow));
+        }
+
+        let print = PrintContext(&page_rule_for(data.node), data.dpi);
+        let area = print.content_area();
+        let (layout_root, layout_ctx) = self.lay_out(data.node, copy data.url,
+                                                     data.dom_event_chan.clone(),
+                                                     copy area.size, au::zero_point(), ~[]);
+
+        let pages = paginate(&find_breaks(layout_root, &print),
+                             layout_root.d().position.size.height, area.size.height);
+        debug!("layout: printing %u pages", pages.len());
+        let builder = dl::DisplayListBuilder {
+            ctx: &layout_ctx,
+        };
+        let lists = build_page_display_lists(layout_root, &builder, &print, pages);
+        let printed = do vec::map_consume(move lists) |list| {
+            PrintedPage {
+                size: copy print.page_size,
+                content_area: copy area,
+                dpi: print.dpi,
+                display_list: move list
+            }
+        };
+        data.pages_chan.send(move printed);
+
+        for screen_flows.each |entry| {
+            let (node, flow) = *entry;
+            if node.has_aux() {
+                do node.aux |d| { d.flow = flow }
+            }
+        }
+        data.content_join_chan.send(());
+    }
+
+    // Styles the document and lays it out in a viewport `screen_size` big,
+    // scrolled to `scroll_offset`, with the scroll containers in
+    // `element_scroll_offsets` scrolled as far as they say
+    fn lay_out(node: Node, doc_url: Url, dom_event_chan: pipes::SharedChan<Event>,
+               screen_size: Size2D<Au>, scroll_offset: Point2D<Au>,
+               element_scroll_offsets: ~[(Node, Point2D<Au>)]) -> (@FlowContext, LayoutContext) {
+        let node = &node;
 
         // Reset the image cache
         self.local_image_cache.next_round(self.make_on_image_available_cb(move dom_event_chan));
 
-        let screen_size = Size2D(au::from_px(data.window_size.width as int),
-                                 au::from_px(data.window_size.height as int));
+        // Lazy images are loaded once they come within this rect, the
+        // viewport grown by the margin on all sides
+        let image_load_size = Size2D(au::from_frac_px(au::to_frac_px(screen_size.width) * self.lazy_image_margin),
+                                     au::from_frac_px(au::to_frac_px(screen_size.height) * self.lazy_image_margin));
+        let image_load_origin = Point2D(scroll_offset.x - (image_load_size.width - screen_size.width) / Au(2),
+                                        scroll_offset.y - (image_load_size.height - screen_size.height) / Au(2));
+
+        let preferred = preferred_color_scheme();
+        let color_scheme = page_color_scheme(*node, preferred);
+        set_native_color_scheme(color_scheme);
 
         let layout_ctx = LayoutContext {
+            styler: self.styler,
             image_cache: self.local_image_cache,
             font_cache: self.font_cache,
             doc_url: move doc_url,
-            screen_size: Rect(Point2D(Au(0), Au(0)), screen_size)
+            screen_size: Rect(Point2D(Au(0), Au(0)), screen_size),
+            scroll_offset: scroll_offset,
+            element_scroll_offsets: move element_scroll_offsets,
+            image_load_bounds: Rect(image_load_origin, image_load_size),
+            enable_masonry: self.enable_masonry,
+            preferred_color_scheme: preferred,
+            color_scheme: color_scheme,
+            forced_colors: self.forced_colors || forced_colors_active(),
+            font_time: now_ms()
         };
 
         let layout_root: @FlowContext = do time("layout: tree construction") {
             // TODO: this is dumb. we don't need 3 separate traversals.
             node.initialize_style_for_subtree(&layout_ctx, &self.layout_refs);
-            do self.stylesheet.borrow_imm |sheet| {
-                match *sheet {
-                    Some(ref sheet) => {
-                        unsafe {
-                            node.recompute_style_for_subtree(&layout_ctx, sheet);
-                        }
-                    } 
-                    None => ()
-                }
-            }
+            self.styler.set_forced_colors(layout_ctx.forced_colors);
             /* resolve styles (convert relative values) down the node tree */
             apply_style(&layout_ctx, *node);
             
+            // Nodes are styled as the builder gets to them
             let builder = LayoutTreeBuilder::new();
             let layout_root: @FlowContext = match builder.construct_trees(&layout_ctx,
                                                                           *node) {
                 Ok(root) => root,
                 Err(*) => fail ~"Root flow should always exist"
             };
+            let (entries, hits, misses) = self.styler.match_cache_stats();
+            debug!("layout: match cache has %u entries, %u hits, %u misses",
+                   entries, hits, misses);
 
             debug!("layout: constructed Flow tree");
             debug!("%?", layout_root.dump());
@@ -208,26 +434,7 @@ impl Layout {
             do layout_root.traverse_postorder |f| { f.assign_height(&layout_ctx) }
         }
 
-        do time("layout: display list building") {
-            let builder = dl::DisplayListBuilder {
-                ctx: &layout_ctx,
-            };
-            let mut render_layer = RenderLayer {
-                display_list: DisplayList::new(),
-                size: Size2D(au::to_px(screen_size.width) as uint,
-                             au::to_px(screen_size.height) as uint)
-            };
-
-            // TODO: set options on the builder before building
-            // TODO: be smarter about what needs painting
-            layout_root.build_display_list(&builder, &copy layout_root.d().position,
-                                           &mut render_layer.display_list);
-            self.render_task.send(render_task::RenderMsg(move render_layer));
-        } // time(layout: display list building)
-
-        // Tell content we're done
-        data.content_join_chan.send(());
-
+        (layout_root, move layout_ctx)
     }
 
 
@@ -235,31 +442,108 @@ impl Layout {
                     reply_chan: comm::Chan<LayoutQueryResponse>) {
         match query {
             ContentBox(node) => {
-                // TODO: extract me to a method when I get sibling arms
-                let response = match node.aux(|a| copy *a).flow {
+                let response = match self.node_rect(node, |box| box.content_box()) {
                     None => Err(()),
-                    Some(flow) => {
-                        let start_val : Option<Rect<Au>> = None;
-                        let rect = do flow.foldl_boxes_for_node(node, start_val) |acc, box| {
-                            match acc {
-                                Some(acc) => Some(acc.union(&box.content_box())),
-                                None => Some(box.content_box())
-                            }
-                        };
-                        
-                        match rect {
-                            None => Err(()),
-                            Some(rect) => {
-                                let size = Size2D(au::to_px(rect.size.width),
-                                                  au::to_px(rect.size.height));
-                                Ok(ContentSize(move size))
-                            }
+                    Some(rect) => Ok(ContentSize(move rect.size))
+                };
+
+                reply_chan.send(response)
+            }
+            Boxes(node) => {
+                let content_box = self.node_rect(node, |box| box.content_box());
+                let border_box = self.node_rect(node, |box| box.border_box());
+                let response = match (content_box, border_box) {
+                    (Some(content_box), Some(border_box)) => {
+                        Ok(NodeBoxes(move content_box, move border_box))
+                    }
+                    _ => Err(())
+                };
+
+                reply_chan.send(response)
+            }
+            HitTest(root, point) => {
+                // Nodes later in tree order are painted on top, so the last
+                // hit is the innermost one
+                let mut hit = None;
+                do root.traverse_preorder |node| {
+                    match self.node_rect(node, |box| box.border_box()) {
+                        Some(rect) if point.x >= rect.origin.x && point.y >= rect.origin.y &&
+                                      point.x < rect.origin.x + rect.size.width &&
+                                      point.y < rect.origin.y + rect.size.height => {
+                            hit = Some(node)
                         }
+                        _ => ()
                     }
+                }
+                let response = match hit {
+                    Some(node) => Ok(HitNode(node)),
+                    None => Err(())
+                };
+
+                reply_chan.send(response)
+            }
+            DumpLayout => {
+                let response = match self.layout_root {
+                    Some(root) => Ok(LayoutDump(dump_layout_tree(root))),
+                    None => Err(())
+                };
+
+                reply_chan.send(response)
+            }
+            Contentful => {
+                let response = match self.layout_root {
+                    Some(root) => Ok(PaintedContent(find_contentful_paint(root, &self.viewport))),
+                    None => Err(())
                 };
 
                 reply_chan.send(response)
             }
+            HeapSize => {
+                let (layout_data, style_data) = self.heap_size();
+                reply_chan.send(Ok(LayoutHeapSize(layout_data, style_data,
+                                                  self.font_cache.heap_size())))
+            }
+        }
+    }
+
+    // The bytes of the nodes' layout data with the flow tree and its
+    // boxes, and the bytes of the nodes' styles
+    fn heap_size() -> (uint, uint) {
+        let nodes = self.layout_refs.len();
+        let mut layout_data = nodes * sys::size_of::<LayoutData>();
+        for self.layout_root.each |root| {
+            do root.traverse_preorder |flow| {
+                let boxes = match *flow {
+                    RootFlow(*) | BlockFlow(*) | InlineFlow(*) => {
+                        flow.foldl_all_boxes(0, |n, _box| n + 1)
+                    }
+                    _ => 0
+                };
+                layout_data += sys::size_of::<FlowContext>() +
+                    boxes * sys::size_of::<RenderBox>();
+            }
+        }
+        (layout_data, nodes * sys::size_of::<SpecifiedStyle>())
+    }
+
+    // The union of the given box of each of the node's render boxes, in px
+    fn node_rect(node: Node, get_box: pure fn(@RenderBox) -> Rect<Au>) -> Option<Rect<int>> {
+        match node.aux(|a| copy *a).flow {
+            None => None,
+            Some(flow) => {
+                let start_val : Option<Rect<Au>> = None;
+                let rect = do flow.foldl_boxes_for_node(node, start_val) |acc, box| {
+                    match acc {
+                        Some(acc) => Some(acc.union(&get_box(box))),
+                        None => Some(get_box(box))
+                    }
+                };
+
+                do rect.map |rect| {
+                    Rect(Point2D(au::to_px(rect.origin.x), au::to_px(rect.origin.y)),
+                         Size2D(au::to_px(rect.size.width), au::to_px(rect.size.height)))
+                }
+            }
         }
     }
 
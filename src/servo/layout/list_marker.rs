@@ -0,0 +1,351 @@
+/*!
+List item markers. A `display: list-item` element gets a marker: a bullet,
+a number or an image, generated from its `list-style`, with no DOM node of
+its own. An outside marker hangs to the left of the item's first line,
+and belongs to the item's block flow; an inside one is the first box on
+that line.
+
+TODO: the style system doesn't know about the list-style properties yet,
+so this only sees them in the `style` attributes of the item and the
+elements around it. An image marker that fails to load shows nothing
+rather than falling back to the list-style-type. Counters, `::marker` and
+right-to-left lists aren't supported.
+*/
+
+use au = gfx::geometry;
+use css::values::list_style::{ListStyleType, ListStylePosition, Disc, Circle, Square,
+                              Decimal, DecimalLeadingZero, LowerAlpha, UpperAlpha, LowerGreek,
+                              LowerRoman, UpperRoman, NoListStyle, Outside, Inside,
+                              parse_list_style, parse_list_style_type,
+                              parse_list_style_position, parse_list_style_image,
+                              list_style_type_for_attr};
+use dom::element::{HTMLListItemElement, HTMLOListElement, HTMLUListElement};
+use dom::node::{Node, NodeTree, Element};
+use geom::point::Point2D;
+use gfx::geometry::Au;
+use image::holder::ImageHolder;
+use layout::box::{RenderBox, RenderBoxMethods, GeneratedBoxData, ImageBox, UnscannedTextBox};
+use layout::context::LayoutContext;
+use layout::flow::FlowContext;
+use layout::text::adapt_textbox_with_range;
+use newcss::values::{DisplayListItem, Specified};
+use servo_text::text_run::TextRun;
+use util::range::Range;
+use util::tree;
+use util::url::make_url;
+
+// between an outside image marker and the item; text markers end in a space
+const IMAGE_MARKER_GAP: int = 8;
+// the HTML UA style sheet's padding-left for lists
+const LIST_INDENT: int = 40;
+
+/// The list style an item's marker is drawn with.
+pub struct MarkerStyle {
+    style_type: ListStyleType,
+    position: ListStylePosition,
+    // unresolved
+    image: Option<~str>,
+}
+
+enum ListKind {
+    OrderedList,
+    UnorderedList,
+}
+
+fn list_kind(node: Node) -> Option<ListKind> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => match e.kind {
+                ~HTMLOListElement => Some(OrderedList),
+                ~HTMLUListElement => Some(UnorderedList),
+                _ => None
+            },
+            _ => None
+        }
+    }
+}
+
+fn attr(node: Node, name: &str) -> Option<~str> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_attr(name),
+            _ => None
+        }
+    }
+}
+
+fn property(node: Node, name: &str) -> Option<~str> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_style_property(name),
+            _ => None
+        }
+    }
+}
+
+/**
+How far `<ol>` and `<ul>` indent their items, so that outside markers
+have room. It stands in for the UA style sheet's padding, which boxes
+don't get yet.
+*/
+pub fn list_indent(node: Node) -> Au {
+    match list_kind(node) {
+        Some(_) => au::from_px(LIST_INDENT),
+        None => Au(0)
+    }
+}
+
+/// Whether `node` is a list item, and so counts towards its siblings'
+/// numbers.
+pub fn is_list_item(node: Node) -> bool {
+    let is_li = do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => match e.kind {
+                ~HTMLListItemElement => true,
+                _ => false
+            },
+            _ => false
+        }
+    };
+    is_li || node.has_aux() && do node.aux |nd| {
+        match nd.style.display_type {
+            Specified(DisplayListItem) => true,
+            _ => false
+        }
+    }
+}
+
+/**
+The list style of `item`. The list-style properties are inherited, so the
+nearest element that sets one wins. Without one, `<ol>` items are numbered
+and `<ul>` items get a disc, or a circle or square when the list is nested
+in others.
+*/
+pub fn marker_style(item: Node) -> MarkerStyle {
+    let mut style_type = None;
+    let mut position = None;
+    let mut image = None;
+    let mut kind = None;
+    let mut depth = 0;
+
+    let mut current = Some(item);
+    while current.is_some() {
+        let node = current.get();
+        let node_kind = list_kind(node);
+        if node_kind.is_some() {
+            if kind.is_none() {
+                kind = node_kind;
+            }
+            depth += 1;
+        }
+
+        match property(node, "list-style").chain(|v| parse_list_style(v)) {
+            Some(ref shorthand) => {
+                if style_type.is_none() { style_type = shorthand.style_type; }
+                if position.is_none() { position = shorthand.position; }
+                if image.is_none() { image = copy shorthand.image; }
+            }
+            None => ()
+        }
+        if style_type.is_none() {
+            style_type = property(node, "list-style-type").chain(|v| parse_list_style_type(v));
+        }
+        if position.is_none() {
+            position = property(node, "list-style-position")
+                .chain(|v| parse_list_style_position(v));
+        }
+        if image.is_none() {
+            image = property(node, "list-style-image").chain(|v| parse_list_style_image(v));
+        }
+        // the HTML `type` attribute on lists and items
+        if style_type.is_none() && (node == item || node_kind.is_some()) {
+            style_type = attr(node, "type").chain(|v| list_style_type_for_attr(v));
+        }
+
+        current = tree::parent(&NodeTree, &node);
+    }
+
+    let default_type = match kind {
+        Some(OrderedList) => Decimal,
+        _ if depth <= 1 => Disc,
+        _ if depth == 2 => Circle,
+        _ => Square
+    };
+    MarkerStyle {
+        style_type: style_type.get_default(default_type),
+        position: position.get_default(Outside),
+        image: image.get_default(None),
+    }
+}
+
+/**
+The number of `item` in its list. `<ol start>` sets the first item's
+number, and `<ol reversed>` counts down, from the number of items unless
+it has a start. An item's `value` sets its own number, and the items
+after it count on from there.
+*/
+pub fn ordinal(item: Node) -> int {
+    let parent = match tree::parent(&NodeTree, &item) {
+        Some(parent) => parent,
+        None => return 1
+    };
+    let ordered = match list_kind(parent) {
+        Some(OrderedList) => true,
+        _ => false
+    };
+    let reversed = ordered && attr(parent, "reversed").is_some();
+    let step = if reversed { -1 } else { 1 };
+    let start = match attr(parent, "start").chain(|v| int::from_str(str::trim(v))) {
+        Some(start) if ordered => start,
+        _ if reversed => {
+            let mut count = 0;
+            for tree::each_child(&NodeTree, &parent) |child| {
+                if is_list_item(*child) { count += 1; }
+            }
+            count
+        }
+        _ => 1
+    };
+
+    let mut number = start - step;
+    for tree::each_child(&NodeTree, &parent) |child| {
+        if !is_list_item(*child) { loop; }
+        number = match attr(*child, "value").chain(|v| int::from_str(str::trim(v))) {
+            Some(value) => value,
+            None => number + step
+        };
+        if *child == item { break; }
+    }
+    number
+}
+
+// `ordinal` in a bijective base of `letters`: a to z, then aa, ab, ...
+// Only for positive numbers.
+fn alphabetic(ordinal: int, letters: &[~str]) -> Option<~str> {
+    if ordinal < 1 {
+        return None;
+    }
+    let base = letters.len() as int;
+    let mut n = ordinal;
+    let mut digits = ~[];
+    while n > 0 {
+        n -= 1;
+        digits.push(copy letters[n % base]);
+        n /= base;
+    }
+    vec::reverse(digits);
+    Some(str::concat(digits))
+}
+
+// `ordinal` in roman numerals, for 1 to 3999
+fn roman(ordinal: int) -> Option<~str> {
+    if ordinal < 1 || ordinal > 3999 {
+        return None;
+    }
+    let values = [(1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+                  (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i")];
+    let mut n = ordinal;
+    let mut result = ~"";
+    for values.each |pair| {
+        let (value, numeral) = *pair;
+        while n >= value {
+            result += numeral;
+            n -= value;
+        }
+    }
+    Some(move result)
+}
+
+/**
+The text of a marker for the item numbered `ordinal`, including the space
+after it. Numbers an alphabetic or roman style can't show are decimal.
+None for `list-style-type: none`.
+*/
+pub fn marker_text(style_type: ListStyleType, ordinal: int) -> Option<~str> {
+    let latin = vec::from_fn(26, |i| str::from_char(('a' as uint + i) as char));
+    // alpha to omega, without the final sigma
+    let greek = vec::filter_map(vec::from_fn(25, |i| 0x3b1 + i), |c| {
+        if *c == 0x3c2 { None } else { Some(str::from_char(*c as char)) }
+    });
+    let decimal = int::str(ordinal);
+    let number = match style_type {
+        Disc => return Some(~"• "),
+        Circle => return Some(~"◦ "),
+        Square => return Some(~"▪ "),
+        NoListStyle => return None,
+        Decimal => move decimal,
+        DecimalLeadingZero if ordinal >= 0 && ordinal < 10 => ~"0" + decimal,
+        DecimalLeadingZero if ordinal < 0 && ordinal > -10 => ~"-0" + int::str(-ordinal),
+        DecimalLeadingZero => move decimal,
+        LowerAlpha => alphabetic(ordinal, latin).get_default(decimal),
+        UpperAlpha => alphabetic(ordinal, latin).map_default(decimal, |s| str::to_upper(*s)),
+        LowerGreek => alphabetic(ordinal, greek).get_default(decimal),
+        LowerRoman => roman(ordinal).get_default(decimal),
+        UpperRoman => roman(ordinal).map_default(decimal, |s| str::to_upper(*s))
+    };
+    Some(number + ~". ")
+}
+
+/**
+The marker box for `item`, in `flow`. An inside text marker is left for
+the text run scanner, like the item's own text; an outside one is shaped
+now, since it isn't on a line.
+*/
+pub fn make_marker_box(ctx: &LayoutContext, item: Node, style: &MarkerStyle,
+                       flow: @FlowContext, id: int) -> Option<@RenderBox> {
+    let data = GeneratedBoxData(item, flow, id);
+    match copy style.image {
+        Some(move url) => {
+            let url = make_url(move url, Some(copy ctx.doc_url));
+            return Some(@ImageBox(move data, ImageHolder(move url, ctx.image_cache)));
+        }
+        None => ()
+    }
+    do marker_text(style.style_type, ordinal(item)).map |text| {
+        match style.position {
+            Inside => @UnscannedTextBox(copy data, copy *text),
+            Outside => {
+                let len = text.len();
+                let run = @TextRun::new(ctx.font_cache.get_test_font(), copy *text);
+                adapt_textbox_with_range(&data, run, Range(0, len))
+            }
+        }
+    }
+}
+
+/**
+Puts an outside marker to the left of its item's first line, which starts
+at the top of the item. Returns how tall the marker is, since an item with
+nothing else on its first line is still as tall as its marker.
+*/
+pub fn place_outside_marker(marker: @RenderBox) -> Au {
+    let (size, gap) = match marker {
+        @ImageBox(*) => (marker.get_replaced_size(), au::from_px(IMAGE_MARKER_GAP)),
+        _ => (copy marker.d().position.size, Au(0))
+    };
+    marker.d().position.size = copy size;
+    marker.d().position.origin = Point2D(Au(0) - size.width - gap, Au(0));
+    size.height
+}
+
+#[cfg(test)]
+mod list_marker_tests {
+    #[test]
+    fn test_marker_text() {
+        assert marker_text(Decimal, 3) == Some(~"3. ");
+        assert marker_text(DecimalLeadingZero, 7) == Some(~"07. ");
+        assert marker_text(DecimalLeadingZero, -7) == Some(~"-07. ");
+        assert marker_text(LowerAlpha, 1) == Some(~"a. ");
+        assert marker_text(LowerAlpha, 26) == Some(~"z. ");
+        assert marker_text(UpperAlpha, 28) == Some(~"AB. ");
+        // no letter for zero
+        assert marker_text(LowerAlpha, 0) == Some(~"0. ");
+        assert marker_text(LowerGreek, 2) == Some(~"β. ");
+        assert marker_text(LowerGreek, 18) == Some(~"σ. ");
+        assert marker_text(LowerRoman, 1994) == Some(~"mcmxciv. ");
+        assert marker_text(UpperRoman, 4) == Some(~"IV. ");
+        assert marker_text(UpperRoman, 4000) == Some(~"4000. ");
+        assert marker_text(Disc, 5) == Some(~"• ");
+        assert marker_text(NoListStyle, 1).is_none();
+    }
+}
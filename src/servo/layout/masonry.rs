@@ -0,0 +1,282 @@
+/*!
+Masonry layout (CSS Grid Level 3), behind `--enable-masonry` while the
+spec is still moving. A block with `display: masonry` has columns, sized
+like grid tracks from `grid-template-columns`, and its children are
+stacked in them: each goes in the column with the least in it so far, so
+items of varying heights pack together without rows.
+
+`masonry-auto-flow` picks how items are placed:
+
+* `pack` (the default): in the shortest column, the first of those tied.
+* `next`: in the column after the previous item's, whatever the heights.
+* `definite-first` (the default) places items with a `grid-column` of
+  their own before the others; `ordered` places every item in order.
+
+TODO: newcss doesn't know `display: masonry` or these properties, so this
+only sees them in the element's `style` attribute. Auto-placed items are
+sized before their column is known, so when the columns differ in width
+they're all as wide as the narrowest.
+*/
+
+use au = gfx::geometry;
+use css::values::grid_template::{TrackSize, AutoTrack, Tracks, parse_track_list};
+use dom::node::{Node, Element};
+use geom::point::Point2D;
+use gfx::geometry::Au;
+use layout::flow::{FlowContext, FlowTree, BlockFlow};
+use layout::grid::{Contribution, parse_length, size_tracks, track_offsets};
+
+pub enum MasonryPlacement {
+    MasonryPack,
+    MasonryNext,
+}
+
+pub struct MasonryAutoFlow {
+    placement: MasonryPlacement,
+    definite_first: bool,
+}
+
+/// An item to place: the column it asks for, if any, and its height.
+pub struct MasonryItem {
+    track: Option<uint>,
+    height: Au,
+}
+
+pub struct MasonryContext {
+    tracks: ~[TrackSize],
+    gap: Au,
+    auto_flow: MasonryAutoFlow,
+    // the sizes and offsets of the columns, once widths are assigned
+    mut sizes: ~[Au],
+    mut offsets: ~[Au],
+    // where the columns start, past the block's left border and padding
+    mut left: Au,
+}
+
+fn property(node: Node, name: &str) -> Option<~str> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_style_property(name),
+            _ => None
+        }
+    }
+}
+
+/// Parses `masonry-auto-flow`: `pack` or `next`, and `definite-first` or
+/// `ordered`, in either order.
+pub fn parse_masonry_auto_flow(value: &str) -> Option<MasonryAutoFlow> {
+    let mut placement = None;
+    let mut definite_first = None;
+    for str::words(value).each |word| {
+        match (word.to_str(), placement, definite_first) {
+            (~"pack", None, _) => placement = Some(MasonryPack),
+            (~"next", None, _) => placement = Some(MasonryNext),
+            (~"definite-first", _, None) => definite_first = Some(true),
+            (~"ordered", _, None) => definite_first = Some(false),
+            _ => return None
+        }
+    }
+    if placement.is_none() && definite_first.is_none() {
+        return None;
+    }
+    Some(MasonryAutoFlow { placement: placement.get_default(MasonryPack),
+                           definite_first: definite_first.get_default(true) })
+}
+
+/// The columns `node` stacks its children in, or None if it isn't
+/// `display: masonry`.
+pub fn MasonryContext(node: Node) -> Option<MasonryContext> {
+    if property(node, "display").map(|v| str::trim(*v)) != Some(~"masonry") {
+        return None;
+    }
+    let tracks = match property(node, "grid-template-columns").chain(|v| parse_track_list(v)) {
+        Some(Tracks(move tracks)) => move tracks,
+        // a masonry container has no parent tracks to use
+        _ => ~[AutoTrack]
+    };
+    let gap = match property(node, "column-gap").chain(|v| parse_length(v)) {
+        Some(gap) => gap,
+        None => property(node, "gap").chain(|v| parse_length(v)).get_default(Au(0))
+    };
+    let auto_flow = property(node, "masonry-auto-flow").chain(|v| parse_masonry_auto_flow(v))
+        .get_default(MasonryAutoFlow { placement: MasonryPack, definite_first: true });
+    Some(MasonryContext {
+        tracks: move tracks,
+        gap: gap,
+        auto_flow: auto_flow,
+        sizes: ~[],
+        offsets: ~[],
+        left: Au(0),
+    })
+}
+
+/// The column `node` asks for with `grid-column: <n>`, counting from 0.
+pub fn definite_track(node: Node) -> Option<uint> {
+    match property(node, "grid-column").chain(|v| int::from_str(str::trim(v))) {
+        Some(n) if n > 0 => Some(n as uint - 1),
+        _ => None
+    }
+}
+
+// The shortest of `heights`, the first of those tied
+pure fn shortest(heights: &[Au]) -> uint {
+    let mut best = 0;
+    for heights.eachi |i, h| {
+        if *h < heights[best] {
+            best = i;
+        }
+    }
+    best
+}
+
+/**
+Places `items` in `track_count` columns with `gap` between items stacked
+in one. Returns the column and top of each item, and the height the
+columns take.
+*/
+pub pure fn place_items(items: &[MasonryItem], track_count: uint, gap: Au,
+                        auto_flow: MasonryAutoFlow) -> (~[(uint, Au)], Au) {
+    let track_count = uint::max(track_count, 1);
+    let mut heights = vec::from_elem(track_count, Au(0));
+    let mut placed = vec::from_elem(items.len(), (0u, Au(0)));
+    let order = if auto_flow.definite_first {
+        vec::filter(vec::from_fn(items.len(), |i| i), |i| items[*i].track.is_some()) +
+            vec::filter(vec::from_fn(items.len(), |i| i), |i| items[*i].track.is_none())
+    } else {
+        vec::from_fn(items.len(), |i| i)
+    };
+
+    let mut next = 0;
+    for order.each |i| {
+        let item = &items[*i];
+        let track = match item.track {
+            Some(track) => uint::min(track, track_count - 1),
+            None => match auto_flow.placement {
+                MasonryPack => shortest(heights),
+                MasonryNext => next
+            }
+        };
+        placed[*i] = (track, heights[track]);
+        heights[track] += item.height + gap;
+        next = (track + 1) % track_count;
+    }
+
+    let tallest = heights.foldl(Au(0), |m, h| au::max(*m, *h));
+    (move placed, if items.is_empty() { Au(0) } else { tallest - gap })
+}
+
+impl MasonryContext {
+    /// Sizes the columns across `available`, from `left`, for the children
+    /// of `flow`. Each child's intrinsic widths count towards its own
+    /// column, or towards all of them if it hasn't one.
+    fn assign_widths(&self, flow: @FlowContext, left: Au, available: Au) {
+        let count = self.tracks.len();
+        let mut items = ~[];
+        for FlowTree.each_child(flow) |child| {
+            let tracks = match item_track(child) {
+                Some(track) => ~[uint::min(track, count - 1)],
+                None => vec::from_fn(count, |i| i)
+            };
+            for tracks.each |track| {
+                items.push(Contribution { start: *track, span: 1,
+                                          min_content: child.d().min_width,
+                                          max_content: child.d().pref_width });
+            }
+        }
+        self.sizes = size_tracks(self.tracks, items, available, self.gap);
+        self.offsets = track_offsets(self.sizes, self.gap);
+        self.left = left;
+    }
+
+    /// The width of `child`: that of its column, or of the narrowest if
+    /// it's placed automatically.
+    fn item_width(&self, child: @FlowContext) -> Au {
+        match item_track(child) {
+            Some(track) => self.sizes[uint::min(track, self.sizes.len() - 1)],
+            None => self.sizes.foldl(self.sizes[0], |m, s| au::min(*m, *s))
+        }
+    }
+
+    /// Places the children of `flow` in the columns, once their heights
+    /// are known, and returns the height the columns take.
+    fn layout(&self, flow: @FlowContext) -> Au {
+        let mut children = ~[];
+        for FlowTree.each_child(flow) |child| {
+            children.push(child);
+        }
+        let items = do children.map |child| {
+            MasonryItem { track: item_track(*child), height: child.d().position.size.height }
+        };
+        let (placed, height) = place_items(items, self.sizes.len(), self.gap, self.auto_flow);
+        for children.eachi |i, child| {
+            let (track, y) = placed[i];
+            child.d().position.origin = Point2D(self.left + self.offsets[track], y);
+        }
+        height
+    }
+}
+
+// The column a child asks for, if it's a block with a `grid-column`
+fn item_track(child: @FlowContext) -> Option<uint> {
+    match *child {
+        BlockFlow(_, ref data) => data.box.chain(|b| definite_track(b.d().node)),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod masonry_tests {
+    fn px(n: int) -> Au { au::from_px(n) }
+
+    fn auto(height: int) -> MasonryItem { MasonryItem { track: None, height: px(height) } }
+
+    fn pack() -> MasonryAutoFlow { MasonryAutoFlow { placement: MasonryPack, definite_first: true } }
+
+    #[test]
+    fn test_pack() {
+        // the grid of test-masonry.html: three columns, 10px gaps
+        let items = ~[auto(100), auto(50), auto(80), auto(30), auto(60), auto(40)];
+        let (placed, height) = place_items(items, 3, px(10), pack());
+        assert placed == ~[(0, px(0)), (1, px(0)), (2, px(0)),
+                           // each under whichever column is shortest by then
+                           (1, px(60)), (2, px(90)), (1, px(100))];
+        assert height == px(150);
+    }
+
+    #[test]
+    fn test_next() {
+        let items = ~[auto(100), auto(50), auto(80), auto(30)];
+        let auto_flow = MasonryAutoFlow { placement: MasonryNext, definite_first: true };
+        let (placed, height) = place_items(items, 3, px(10), auto_flow);
+        // round the columns, whatever their heights
+        assert placed == ~[(0, px(0)), (1, px(0)), (2, px(0)), (0, px(110))];
+        assert height == px(130);
+    }
+
+    #[test]
+    fn test_definite_first() {
+        let items = ~[auto(100), MasonryItem { track: Some(0), height: px(50) }, auto(20)];
+        let (placed, _) = place_items(items, 2, px(0), pack());
+        // the item asking for the first column goes there before the others
+        assert placed == ~[(1, px(0)), (0, px(0)), (0, px(50))];
+
+        let ordered = MasonryAutoFlow { placement: MasonryPack, definite_first: false };
+        let (placed, _) = place_items(items, 2, px(0), ordered);
+        assert placed == ~[(0, px(0)), (0, px(100)), (1, px(0))];
+    }
+
+    #[test]
+    fn test_parse_masonry_auto_flow() {
+        match parse_masonry_auto_flow("next") {
+            Some(MasonryAutoFlow { placement: MasonryNext, definite_first: true }) => (),
+            _ => fail
+        }
+        match parse_masonry_auto_flow("ordered pack") {
+            Some(MasonryAutoFlow { placement: MasonryPack, definite_first: false }) => (),
+            _ => fail
+        }
+        assert parse_masonry_auto_flow("").is_none();
+        assert parse_masonry_auto_flow("pack next").is_none();
+        assert parse_masonry_auto_flow("dense").is_none();
+    }
+}
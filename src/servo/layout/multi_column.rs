@@ -0,0 +1,444 @@
+/*!
+Multi-column layout. A block with `column-count` or `column-width` lays
+its children out in columns side by side, with `column-gap` between them
+and an optional `column-rule` down the middle of each gap.
+
+Children are fragmented across the columns of a row: an inline flow can
+break between any two of its lines, and a block child goes whole into one
+column. A child with `column-span: all` ends the row, spans every column,
+and starts a new row below it. With `column-fill: balance`, the default,
+the columns of a row are made as even as they can be. With
+`column-fill: auto` and a `height`, columns are filled to that height one
+after another, and any left over run off to the side.
+
+TODO: the style system doesn't know about the column properties yet, so
+this only sees them in the element's `style` attribute.
+*/
+
+use au = gfx::geometry;
+use dom::node::{Node, Element};
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use gfx::display_list::{DisplayItem, DisplayList};
+use gfx::geometry::Au;
+use layout::box::RenderBoxMethods;
+use layout::flow::{FlowContext, FlowTree, BlockFlow, InlineFlow};
+use newcss::values::{Specified, BoxLength, Px};
+
+pub enum ColumnFill {
+    ColumnFillBalance,
+    ColumnFillAuto,
+}
+
+impl ColumnFill : cmp::Eq {
+    pure fn eq(&self, other: &ColumnFill) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &ColumnFill) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+pub struct ColumnRule {
+    width: Au,
+    color: (u8, u8, u8),
+}
+
+// A row of columns, between elements that span them all
+struct ColumnRow {
+    y: Au,
+    height: Au,
+    columns: uint,
+}
+
+pub struct MultiColumnContext {
+    count: Option<uint>,
+    width: Option<Au>,
+    gap: Au,
+    rule: Option<ColumnRule>,
+    fill: ColumnFill,
+    // for column-fill: auto, the height to fill columns to
+    mut max_height: Option<Au>,
+    // the number and width of columns, once widths are assigned
+    mut used_count: uint,
+    mut used_width: Au,
+    mut rows: ~[ColumnRow],
+}
+
+/// The columns `node` lays out its children in, or None if it isn't a
+/// multi-column element.
+pub fn MultiColumnContext(node: Node) -> Option<MultiColumnContext> {
+    let property = |name: &str| {
+        do node.read |n| {
+            match n.kind {
+                ~Element(ref e) => e.get_style_property(name),
+                _ => None
+            }
+        }
+    };
+    let count = match property("column-count").chain(|v| uint::from_str(v)) {
+        Some(0) | None => None,
+        count => count
+    };
+    let width = match property("column-width").chain(|v| parse_length(v)) {
+        Some(w) if w > Au(0) => Some(w),
+        _ => None
+    };
+    if count.is_none() && width.is_none() {
+        return None;
+    }
+    Some(MultiColumnContext {
+        count: count,
+        width: width,
+        // 'normal' is 1em
+        gap: property("column-gap").chain(|v| parse_length(v)).get_default(au::from_px(16)),
+        rule: property("column-rule").chain(|v| parse_rule(v)),
+        fill: match property("column-fill") {
+            Some(~"auto") => ColumnFillAuto,
+            _ => ColumnFillBalance
+        },
+        max_height: None,
+        used_count: 1,
+        used_width: Au(0),
+        rows: ~[],
+    })
+}
+
+/// Whether `node` spans all the columns of its multi-column parent.
+pub fn spans_all_columns(node: Node) -> bool {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_style_property("column-span") == Some(~"all"),
+            _ => false
+        }
+    }
+}
+
+// A length in px, or 0
+fn parse_length(value: &str) -> Option<Au> {
+    if value == "0" {
+        return Some(Au(0));
+    }
+    if !value.ends_with("px") {
+        return None;
+    }
+    float::from_str(value.slice(0, value.len() - 2)).map(|px| au::from_frac_px(*px))
+}
+
+// A color given as #rgb, #rrggbb or one of a few names
+fn parse_color(value: &str) -> Option<(u8, u8, u8)> {
+    let hex = |s: &str| uint::from_str_radix(s, 16).map(|v| *v as u8);
+    match value.to_str() {
+        ~"black" => Some((0, 0, 0)),
+        ~"gray" | ~"grey" => Some((128, 128, 128)),
+        ~"silver" => Some((192, 192, 192)),
+        ~"white" => Some((255, 255, 255)),
+        ~"red" => Some((255, 0, 0)),
+        _ if value.starts_with("#") && value.len() == 7 => {
+            match (hex(value.slice(1, 3)), hex(value.slice(3, 5)), hex(value.slice(5, 7))) {
+                (Some(r), Some(g), Some(b)) => Some((r, g, b)),
+                _ => None
+            }
+        }
+        _ if value.starts_with("#") && value.len() == 4 => {
+            match (hex(value.slice(1, 2)), hex(value.slice(2, 3)), hex(value.slice(3, 4))) {
+                (Some(r), Some(g), Some(b)) => Some((r * 17, g * 17, b * 17)),
+                _ => None
+            }
+        }
+        _ => None
+    }
+}
+
+// The `column-rule` shorthand: a width, a style and a color, in any order.
+// Rules of every visible style are drawn solid.
+fn parse_rule(value: &str) -> Option<ColumnRule> {
+    let mut width = au::from_px(3);
+    let mut color = (0u8, 0u8, 0u8);
+    let mut visible = false;
+    for str::split_char_nonempty(value, ' ').each |part| {
+        match *part {
+            ~"none" | ~"hidden" => return None,
+            ~"solid" | ~"dotted" | ~"dashed" | ~"double" => visible = true,
+            ~"thin" => width = au::from_px(1),
+            ~"medium" => width = au::from_px(3),
+            ~"thick" => width = au::from_px(5),
+            _ => match (parse_length(*part), parse_color(*part)) {
+                (Some(w), _) => width = w,
+                (None, Some(c)) => color = c,
+                (None, None) => return None
+            }
+        }
+    }
+    if visible && width > Au(0) {
+        Some(ColumnRule { width: width, color: color })
+    } else {
+        None
+    }
+}
+
+/**
+The number and width of columns across `available`. With both
+`column-count` and `column-width`, whichever gives more columns is used.
+*/
+pub pure fn resolve_columns(count: Option<uint>, width: Option<Au>, gap: Au,
+                            available: Au) -> (uint, Au) {
+    let from_width = match width {
+        // as many columns at least `w` wide as fit
+        Some(w) => uint::max(1, *((available + gap) / (w + gap)) as uint),
+        None => 0
+    };
+    let count = uint::max(uint::max(count.get_default(0), from_width), 1);
+    let width = (available - gap * Au(count as i32 - 1)) / Au(count as i32);
+    (count, au::max(width, Au(0)))
+}
+
+/**
+Which column each of a row of pieces of `heights` goes in, filling
+columns of `column_height` in order. Also returns the least a column
+would have to grow by to take the first piece of the next column, if any
+piece was pushed to the next column.
+*/
+pub pure fn pack(heights: &[Au], column_height: Au) -> (~[uint], Option<Au>) {
+    let mut column = 0;
+    let mut used = Au(0);
+    let mut stretch = None;
+    let columns = do heights.map |h| {
+        if used > Au(0) && used + *h > column_height {
+            let needed = used + *h - column_height;
+            if stretch.map_default(true, |s| needed < *s) {
+                stretch = Some(needed);
+            }
+            column += 1;
+            used = Au(0);
+        }
+        used += *h;
+        column
+    };
+    (move columns, stretch)
+}
+
+/// The least column height that fits pieces of `heights` into `count`
+/// columns. A piece taller than that gets a column of its own.
+pub pure fn balance(heights: &[Au], count: uint) -> Au {
+    let total = heights.foldl(Au(0), |sum, h| *sum + *h);
+    let tallest = heights.foldl(Au(0), |m, h| au::max(*m, *h));
+    let count = uint::max(count, 1);
+    let per_column = (total + Au(count as i32 - 1)) / Au(count as i32);
+    let mut column_height = au::max(tallest, per_column);
+    loop {
+        let (columns, stretch) = pack(heights, column_height);
+        if columns.is_empty() || columns.last() < count {
+            return column_height;
+        }
+        match stretch {
+            Some(s) => column_height += s,
+            None => return column_height
+        }
+    }
+}
+
+// A child, or a line of one, to put in a column
+struct Piece {
+    flow: @FlowContext,
+    // for a line of an inline flow, its index and top in the flow
+    line: Option<(uint, Au)>,
+    height: Au,
+}
+
+fn pieces_of(flow: @FlowContext) -> ~[Piece] {
+    match *flow {
+        InlineFlow(*) => {
+            let lines = &flow.inline().lines;
+            let boxes = &flow.inline().boxes;
+            let tops = do vec::from_fn(lines.len()) |i| {
+                let line = lines[i];
+                let mut top = flow.d().position.size.height;
+                for line.eachi |b| {
+                    top = au::min(top, boxes[b].d().position.origin.y);
+                }
+                top
+            };
+            do vec::from_fn(lines.len()) |i| {
+                let bottom = if i + 1 < lines.len() {
+                    tops[i + 1]
+                } else {
+                    flow.d().position.size.height
+                };
+                Piece { flow: flow, line: Some((i, tops[i])), height: bottom - tops[i] }
+            }
+        }
+        _ => ~[Piece { flow: flow, line: None, height: flow.d().position.size.height }]
+    }
+}
+
+impl MultiColumnContext {
+    /// Works out the columns across `available`, and returns their width.
+    fn assign_width(&self, available: Au) -> Au {
+        let (count, width) = resolve_columns(self.count, self.width, self.gap, available);
+        self.used_count = count;
+        self.used_width = width;
+        width
+    }
+
+    priv fn column_x(&self, column: uint) -> Au {
+        (self.used_width + self.gap) * Au(column as i32)
+    }
+
+    /**
+    Places the children of `flow` in rows of columns, and returns the
+    height they take. Inline children are left where their first line
+    was, with their boxes moved to the columns their lines went to.
+    */
+    fn layout(&self, flow: @FlowContext) -> Au {
+        self.rows = ~[];
+        let mut y = Au(0);
+        let mut row = ~[];
+        for FlowTree.each_child(flow) |child| {
+            let spans = match *child {
+                BlockFlow(*) => {
+                    let mut spans = false;
+                    do child.with_block_box |box| { spans = spans_all_columns(box.d().node) }
+                    spans
+                }
+                _ => false
+            };
+            if spans {
+                y = self.layout_row(row, y);
+                row = ~[];
+                child.d().position.origin = Point2D(Au(0), y);
+                y += child.d().position.size.height;
+            } else {
+                row.push_all(pieces_of(child));
+            }
+        }
+        self.layout_row(row, y)
+    }
+
+    priv fn layout_row(&self, pieces: &[Piece], y: Au) -> Au {
+        if pieces.is_empty() {
+            return y;
+        }
+        let heights = pieces.map(|p| p.height);
+        let column_height = match (self.fill, self.max_height) {
+            (ColumnFillAuto, Some(height)) => height,
+            _ => balance(heights, self.used_count)
+        };
+        let (columns, _) = pack(heights, column_height);
+
+        let mut offset = Au(0);
+        for pieces.eachi |i, piece| {
+            if i > 0 && columns[i] != columns[i - 1] {
+                offset = Au(0);
+            }
+            let x = self.column_x(columns[i]);
+            match piece.line {
+                None => piece.flow.d().position.origin = Point2D(x, y + offset),
+                Some((line, top)) => {
+                    if line == 0 {
+                        piece.flow.d().position.origin = Point2D(Au(0), y);
+                    }
+                    // the flow is at the top of the row, so the line moves
+                    // from its top in the flow to its place in the column
+                    let boxes = &piece.flow.inline().boxes;
+                    for piece.flow.inline().lines[line].eachi |b| {
+                        boxes[b].d().position.origin.x += x;
+                        boxes[b].d().position.origin.y += offset - top;
+                    }
+                }
+            }
+            offset += piece.height;
+        }
+
+        // inline flows now cover the row, for painting
+        for pieces.each |piece| {
+            if piece.line.is_some() {
+                piece.flow.d().position.size = Size2D(self.column_x(self.used_count) - self.gap,
+                                                      column_height);
+            }
+        }
+        self.rows.push(ColumnRow { y: y, height: column_height, columns: columns.last() + 1 });
+        y + column_height
+    }
+
+    /// Adds the column rules between the columns of each row.
+    fn build_display_list(&self, offset: &Point2D<Au>, list: &mut DisplayList) {
+        match self.rule {
+            Some(rule) => {
+                let (r, g, b) = rule.color;
+                for self.rows.each |row| {
+                    for uint::range(1, row.columns) |column| {
+                        let x = self.column_x(column) - (self.gap + rule.width) / Au(2);
+                        let bounds = Rect(Point2D(x, row.y), Size2D(rule.width, row.height));
+                        list.append_item(~DisplayItem::new_SolidColor(&bounds.translate(offset),
+                                                                      r, g, b));
+                    }
+                }
+            }
+            None => ()
+        }
+    }
+}
+
+/// The `height` of `node`, which `column-fill: auto` fills columns to.
+pub fn specified_height(node: Node) -> Option<Au> {
+    match node.style().height {
+        Specified(BoxLength(Px(px))) => Some(au::from_frac_px(px)),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod multi_column_tests {
+    fn px(n: int) -> Au { au::from_px(n) }
+
+    #[test]
+    fn test_resolve_columns() {
+        // 3 columns with 20px gaps in 340px are 100px each
+        assert resolve_columns(Some(3), None, px(20), px(340)) == (3, px(100));
+        // As many 100px columns as fit, which is 3, stretched to fill
+        assert resolve_columns(None, Some(px(100)), px(20), px(400)) == (3, px(120));
+        // Whichever gives more columns
+        assert resolve_columns(Some(2), Some(px(100)), px(20), px(340)) == (3, px(100));
+        assert resolve_columns(Some(4), Some(px(100)), px(20), px(340)).first() == 4;
+        // Always at least one column
+        assert resolve_columns(None, Some(px(500)), px(20), px(340)) == (1, px(340));
+    }
+
+    #[test]
+    fn test_pack() {
+        let (columns, stretch) = pack([px(20), px(20), px(20), px(20)], px(40));
+        assert columns == ~[0, 0, 1, 1];
+        assert stretch == Some(px(20));
+        // A piece taller than a column gets one to itself
+        let (columns, _) = pack([px(100), px(20)], px(40));
+        assert columns == ~[0, 1];
+    }
+
+    #[test]
+    fn test_balance() {
+        // Six even lines in three columns are two to a column
+        let lines = [px(20), px(20), px(20), px(20), px(20), px(20)];
+        assert balance(lines, 3) == px(40);
+        // A block can't break, so it sets the least height
+        assert balance([px(20), px(90), px(20)], 3) == px(90);
+        assert balance([px(20), px(90), px(20)], 2) == px(110);
+        // Seven lines in three columns need three to a column
+        assert balance([px(20), px(20), px(20), px(20), px(20), px(20), px(20)], 3) == px(60);
+        // Fewer pieces than columns
+        assert balance([px(20)], 3) == px(20);
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        let rule = parse_rule("1px solid #ccc").get();
+        assert rule.width == px(1);
+        assert rule.color == (204, 204, 204);
+        let rule = parse_rule("gray thick dashed").get();
+        assert rule.width == px(5);
+        assert rule.color == (128, 128, 128);
+        assert parse_rule("1px none red").is_none();
+        assert parse_rule("2px red").is_none();
+    }
+}
@@ -0,0 +1,124 @@
+/*!
+What a frame shows, for paint timing. A frame is contentful once it has
+text or a loaded image in it, and its largest contentful element is the
+image or block of text covering the most of the viewport. A block of text
+is all the text of one element, however many boxes it takes.
+*/
+
+use au = gfx::geometry;
+use au::Au;
+use dom::node::Node;
+use geom::point::Point2D;
+use geom::rect::Rect;
+use layout::box::{RenderBox, ImageBox, TextBox};
+use layout::flow::{FlowContext, FlowTree, BlockFlow, InlineFlow, RootFlow};
+
+/// An image or block of text, and how much of the viewport it covers, in
+/// square px.
+pub struct ContentfulElement {
+    node: Node,
+    area: uint,
+}
+
+pub struct ContentfulPaint {
+    contentful: bool,
+    largest: Option<ContentfulElement>,
+}
+
+/// How many square px of `rect` are inside `viewport`.
+pub pure fn visible_area(rect: &Rect<Au>, viewport: &Rect<Au>) -> uint {
+    let left = au::max(rect.origin.x, viewport.origin.x);
+    let top = au::max(rect.origin.y, viewport.origin.y);
+    let right = au::min(rect.origin.x + rect.size.width,
+                        viewport.origin.x + viewport.size.width);
+    let bottom = au::min(rect.origin.y + rect.size.height,
+                         viewport.origin.y + viewport.size.height);
+    if right <= left || bottom <= top {
+        return 0;
+    }
+    (au::to_px(right - left) * au::to_px(bottom - top)) as uint
+}
+
+// The element a box counts towards, if it shows content: an image's own,
+// or the element a run of text is in
+fn contentful_node(box: @RenderBox) -> Option<Node> {
+    match box {
+        @ImageBox(ref d, ref image) if image.is_loaded() => Some(d.node),
+        @TextBox(ref d, ref text) => {
+            let s = str::substr(text.run.text, text.range.begin(), text.range.length());
+            if s.is_whitespace() {
+                None
+            } else {
+                d.node.read(|n| n.tree.parent)
+            }
+        }
+        _ => None
+    }
+}
+
+// Adds the contentful boxes of `flow`, which is at `offset` in the page,
+// and of the flows under it to `elements`, by element
+fn gather_elements(flow: @FlowContext, offset: &Point2D<Au>,
+                   elements: &mut ~[(Node, Rect<Au>)]) {
+    match *flow {
+        BlockFlow(*) | InlineFlow(*) | RootFlow(*) => (),
+        // floats and positioned flows aren't painted yet
+        _ => return
+    }
+    for flow.iter_all_boxes |box| {
+        match contentful_node(box) {
+            Some(node) => {
+                let rect = box.d().position.translate(offset);
+                match elements.position(|e| { let (n, _) = *e; n == node }) {
+                    Some(i) => {
+                        let (_, union) = elements[i];
+                        elements[i] = (node, union.union(&rect));
+                    }
+                    None => elements.push((node, rect))
+                }
+            }
+            None => ()
+        }
+    }
+    for FlowTree.each_child(flow) |child| {
+        gather_elements(child, &offset.add(&child.d().position.origin), elements);
+    }
+}
+
+/// What the frame laid out from `root` shows in `viewport`, in page
+/// coordinates.
+pub fn find_contentful_paint(root: @FlowContext, viewport: &Rect<Au>) -> ContentfulPaint {
+    let mut elements = ~[];
+    gather_elements(root, &Point2D(Au(0), Au(0)), &mut elements);
+
+    let mut largest: Option<ContentfulElement> = None;
+    for elements.each |element| {
+        let (node, rect) = *element;
+        let area = visible_area(&rect, viewport);
+        if area > largest.map_default(0, |e| e.area) {
+            largest = Some(ContentfulElement { node: node, area: area });
+        }
+    }
+    ContentfulPaint { contentful: !elements.is_empty(), largest: largest }
+}
+
+#[cfg(test)]
+mod paint_timing_tests {
+    use geom::size::Size2D;
+
+    fn rect(x: int, y: int, width: int, height: int) -> Rect<Au> {
+        Rect(Point2D(au::from_px(x), au::from_px(y)),
+             Size2D(au::from_px(width), au::from_px(height)))
+    }
+
+    #[test]
+    fn test_visible_area() {
+        let viewport = rect(0, 100, 800, 600);
+        assert visible_area(&rect(10, 200, 100, 50), &viewport) == 5000;
+        // only the part scrolled into view counts
+        assert visible_area(&rect(0, 50, 100, 100), &viewport) == 5000;
+        assert visible_area(&rect(700, 650, 200, 100), &viewport) == 5000;
+        assert visible_area(&rect(0, 0, 100, 100), &viewport) == 0;
+        assert visible_area(&rect(900, 200, 100, 100), &viewport) == 0;
+    }
+}
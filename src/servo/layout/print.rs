@@ -0,0 +1,408 @@
+/*!
+Printing. The document is laid out once, as one long page as wide as the
+paper's printable area, and then cut into pages. Cuts go where a block
+starts or ends, or between lines of a paragraph that leave at least
+`orphans` lines above and `widows` below, so that a block that fits on a
+page isn't split across two; a block taller than what's left of the page
+is cut where the page ends.
+
+The break properties move the cuts: a page always ends where
+`break-before` or `break-after` force it to, and a block with
+`break-inside: avoid` goes whole onto the next page rather than being
+split, if it fits on one.
+
+`@media print` rules don't apply yet, as the style sheet parser doesn't
+hand media rules on; the page is printed with its screen styles.
+*/
+
+use au = gfx::geometry;
+use au::Au;
+use css::values::breaks::{BreakBetween, BreakAuto, BreakAvoid, BreakPage, BreakInside,
+                          InsideAuto, InsideAvoid, parse_break_between,
+                          parse_page_break_between, parse_break_inside, parse_page_break_inside};
+use css::values::page::{PageRule, parse_page_rules};
+use dom::element::HTMLStyleElement;
+use dom::node::{Element, Node, NodeTree, Text};
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use gfx::display_list::{DisplayList, DisplayListBuilder};
+use layout::flow::{FlowContext, FlowTree, BlockFlow, InlineFlow, RootFlow};
+use layout::block::BlockLayout;
+
+/// How pages are printed: the `@page` rules applied to the printer's defaults.
+pub struct PrintContext {
+    page_size: Size2D<Au>,
+    // Top, right, bottom and left
+    margins: (Au, Au, Au, Au),
+    // The fewest lines of a paragraph left at the bottom and top of a page
+    orphans: uint,
+    widows: uint,
+    // The dots per inch pages are rendered at
+    dpi: uint,
+}
+
+// US letter, with 1cm margins, as the printer would have it
+fn default_page_size() -> Size2D<Au> {
+    Size2D(au::from_frac_px(8.5 * 96.0), au::from_frac_px(11.0 * 96.0))
+}
+
+fn default_margin() -> Au {
+    au::from_frac_px(96.0 / 2.54)
+}
+
+pub fn PrintContext(rule: &PageRule, dpi: uint) -> PrintContext {
+    let m = default_margin();
+    PrintContext {
+        page_size: rule.size.get_default(default_page_size()),
+        margins: rule.margins.get_default((m, m, m, m)),
+        orphans: rule.orphans.get_default(2),
+        widows: rule.widows.get_default(2),
+        dpi: dpi
+    }
+}
+
+impl PrintContext {
+    /// The part of a page that's printed on, inside the margins.
+    fn content_area(&self) -> Rect<Au> {
+        let (top, right, bottom, left) = self.margins;
+        let width = au::max(Au(0), self.page_size.width - left - right);
+        let height = au::max(Au(0), self.page_size.height - top - bottom);
+        Rect(Point2D(left, top), Size2D(width, height))
+    }
+
+    /// The size of a page in the printer's dots.
+    fn device_size(&self) -> Size2D<uint> {
+        let dots = |length: Au| (au::to_frac_px(length) * (self.dpi as float) / 96.0) as uint;
+        Size2D(dots(self.page_size.width), dots(self.page_size.height))
+    }
+}
+
+/// The `@page` rules in the document's style elements.
+pub fn page_rule_for(root: Node) -> PageRule {
+    let mut rule = PageRule();
+    do root.traverse_preorder |node| {
+        let is_style = do node.read |n| {
+            match n.kind {
+                ~Element(ref e) => match e.kind {
+                    ~HTMLStyleElement => true,
+                    _ => false
+                },
+                _ => false
+            }
+        };
+        if is_style {
+            for NodeTree.each_child(&node) |child| {
+                do child.read |n| {
+                    match n.kind {
+                        ~Text(ref css) => parse_page_rules(*css, &mut rule),
+                        _ => ()
+                    }
+                }
+            }
+        }
+    }
+    move rule
+}
+
+/// A page ready for the printer. The display list is in px, at 96 to the
+/// inch, and is scaled to the printer's resolution as it's drawn.
+pub struct PrintedPage {
+    size: Size2D<Au>,
+    // Inside the margins, which nothing is drawn past
+    content_area: Rect<Au>,
+    dpi: uint,
+    display_list: DisplayList,
+}
+
+/// The slice of the laid out document that goes on one page.
+pub struct Page {
+    top: Au,
+    height: Au,
+}
+
+impl Page : cmp::Eq {
+    pure fn eq(&self, other: &Page) -> bool {
+        self.top == other.top && self.height == other.height
+    }
+    pure fn ne(&self, other: &Page) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// Where the document may, must and mustn't be cut between pages, each
+/// from the top of the document.
+pub struct Breaks {
+    // Where a page may end, in order
+    points: ~[Au],
+    // Where a page must end, in order
+    forced: ~[Au],
+    // The tops and bottoms of blocks that shouldn't be split
+    avoid: ~[(Au, Au)],
+}
+
+pub fn Breaks() -> Breaks {
+    Breaks { points: ~[], forced: ~[], avoid: ~[] }
+}
+
+// A break property of `node`, going by the older `page-break-*` one if
+// the newer isn't set
+fn read_break<T: Copy>(node: Node, name: &str, legacy_name: &str,
+                       parse: fn(&str) -> Option<T>,
+                       parse_legacy: fn(&str) -> Option<T>) -> Option<T> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => {
+                let value = match e.get_style_property(name) {
+                    Some(ref value) => parse(*value),
+                    None => None
+                };
+                if value.is_some() {
+                    value
+                } else {
+                    match e.get_style_property(legacy_name) {
+                        Some(ref value) => parse_legacy(*value),
+                        None => None
+                    }
+                }
+            }
+            _ => None
+        }
+    }
+}
+
+// The breaks before, after and inside the block `flow`
+fn block_breaks(flow: @FlowContext) -> (BreakBetween, BreakBetween, BreakInside) {
+    match flow.d().node {
+        Some(node) => {
+            let before = read_break(node, "break-before", "page-break-before",
+                                    parse_break_between, parse_page_break_between);
+            let after = read_break(node, "break-after", "page-break-after",
+                                   parse_break_between, parse_page_break_between);
+            let inside = read_break(node, "break-inside", "page-break-inside",
+                                    parse_break_inside, parse_page_break_inside);
+            (before.get_default(BreakAuto), after.get_default(BreakAuto),
+             inside.get_default(InsideAuto))
+        }
+        None => (BreakAuto, BreakAuto, InsideAuto)
+    }
+}
+
+// Where each line of the inline flow starts, in the flow's coordinates
+fn line_tops(flow: @FlowContext) -> ~[Au] {
+    let mut tops = ~[];
+    let boxes = &flow.inline().boxes;
+    for flow.inline().lines.each |line_span| {
+        let mut top = None;
+        for line_span.eachi |box_i| {
+            let y = boxes[box_i].d().position.origin.y;
+            top = Some(match top {
+                Some(t) => au::min(t, y),
+                None => y
+            });
+        }
+        tops.push(top.get_default(Au(0)));
+    }
+    move tops
+}
+
+/**
+Where a paragraph of lines starting at `tops` may be cut, which is before
+any line with at least `orphans` lines above it and `widows` below.
+*/
+pub fn line_break_points(tops: &[Au], orphans: uint, widows: uint) -> ~[Au] {
+    let mut points = ~[];
+    for uint::range(uint::max(orphans, 1), tops.len()) |i| {
+        if tops.len() - i >= widows {
+            points.push(tops[i]);
+        }
+    }
+    move points
+}
+
+// Adds the breaks around and inside each block and paragraph under
+// `flow`, which is `top` down the document
+fn gather_breaks(flow: @FlowContext, top: Au, print: &PrintContext, breaks: &mut Breaks,
+                 avoided: &mut ~[Au]) {
+    for FlowTree.each_child(flow) |child| {
+        let child_top = top + child.d().position.origin.y;
+        let child_bottom = child_top + child.d().position.size.height;
+        match *child {
+            BlockFlow(*) if child.is_float() => (),
+            BlockFlow(*) => {
+                let (before, after, inside) = block_breaks(child);
+                match before {
+                    BreakPage => breaks.forced.push(child_top),
+                    BreakAvoid => avoided.push(child_top),
+                    BreakAuto => ()
+                }
+                if inside == InsideAvoid {
+                    breaks.avoid.push((child_top, child_bottom));
+                }
+                breaks.points.push(child_top);
+                gather_breaks(child, child_top, print, breaks, avoided);
+                breaks.points.push(child_bottom);
+                match after {
+                    BreakPage => breaks.forced.push(child_bottom),
+                    BreakAvoid => avoided.push(child_bottom),
+                    BreakAuto => ()
+                }
+            }
+            InlineFlow(*) => {
+                breaks.points.push(child_top);
+                let tops = line_tops(child).map(|t| child_top + *t);
+                breaks.points.push_all(line_break_points(tops, print.orphans, print.widows));
+                breaks.points.push(child_bottom);
+            }
+            _ => ()
+        }
+    }
+}
+
+/// Where the document laid out under `root` may, must and mustn't be cut.
+pub fn find_breaks(root: @FlowContext, print: &PrintContext) -> Breaks {
+    let mut breaks = Breaks();
+    let mut avoided = ~[];
+    gather_breaks(root, Au(0), print, &mut breaks, &mut avoided);
+    // `break-*: avoid` takes a point away, unless a break is forced there
+    let mut points = do breaks.points.filter |point| {
+        !vec::contains(avoided, point) || vec::contains(breaks.forced, point)
+    };
+    sort::quick_sort3(points);
+    sort::quick_sort3(breaks.forced);
+    breaks.points = move points;
+    move breaks
+}
+
+/**
+Cuts a document `height` tall into pages of `page_height`. Each page ends
+at the first forced break on it, or else at the last break point that
+fits on it and doesn't split a block that should be kept whole, or where
+the page ends if there's none.
+*/
+pub fn paginate(breaks: &Breaks, height: Au, page_height: Au) -> ~[Page] {
+    let mut pages = ~[];
+    if page_height <= Au(0) {
+        return move pages;
+    }
+    let mut top = Au(0);
+    while top < height {
+        let limit = top + page_height;
+        let forced = do breaks.forced.find |point| {
+            *point > top && *point <= limit && *point < height
+        };
+        let mut bottom = limit;
+        if forced.is_some() {
+            bottom = forced.get();
+        } else if limit < height {
+            for breaks.points.each |point| {
+                if *point > top && *point <= limit && !splits_kept_block(breaks, top, *point,
+                                                                          page_height) {
+                    bottom = *point;
+                }
+            }
+        }
+        pages.push(Page { top: top, height: au::min(bottom, height) - top });
+        top = bottom;
+    }
+    // An empty document still prints a page
+    if pages.is_empty() {
+        pages.push(Page { top: Au(0), height: Au(0) });
+    }
+    move pages
+}
+
+// Whether cutting at `point` would split a block that should be kept
+// whole, and could be, as it starts on the page from `top` and is no
+// taller than a page
+fn splits_kept_block(breaks: &Breaks, top: Au, point: Au, page_height: Au) -> bool {
+    do breaks.avoid.any |span| {
+        let (start, end) = *span;
+        start >= top && start < point && point < end && end - start <= page_height
+    }
+}
+
+/// The display list of each page, placed inside the page's margins.
+pub fn build_page_display_lists(root: @FlowContext, builder: &DisplayListBuilder,
+                                print: &PrintContext, pages: &[Page]) -> ~[DisplayList] {
+    let area = print.content_area();
+    do pages.map |page| {
+        let mut list = DisplayList::new();
+        let offset = Point2D(area.origin.x, area.origin.y - page.top);
+        let dirty = Rect(copy area.origin, Size2D(area.size.width, page.height));
+        match *root {
+            RootFlow(*) => root.build_display_list_recurse(builder, &dirty, &offset, &mut list),
+            _ => fail ~"pages are built from the root flow"
+        }
+        move list
+    }
+}
+
+#[cfg(test)]
+mod print_tests {
+    fn px(n: int) -> Au { au::from_px(n) }
+
+    fn breaks_at(points: ~[Au]) -> Breaks {
+        Breaks { points: move points, forced: ~[], avoid: ~[] }
+    }
+
+    #[test]
+    fn test_paginate() {
+        // Blocks from 0 to 40, 40 to 90, 90 to 130 and 130 to 200
+        let breaks = breaks_at(~[px(0), px(40), px(90), px(130), px(200)]);
+        let pages = paginate(&breaks, px(200), px(100));
+        assert pages == ~[Page { top: px(0), height: px(90) },
+                          Page { top: px(90), height: px(40) },
+                          Page { top: px(130), height: px(70) }];
+
+        // A block too tall for a page is cut where the page ends
+        assert paginate(&breaks_at(~[px(0)]), px(250), px(100)).len() == 3;
+        assert paginate(&breaks_at(~[]), px(0), px(100)) == ~[Page { top: Au(0), height: Au(0) }];
+    }
+
+    #[test]
+    fn test_paginate_forced_breaks() {
+        let mut breaks = breaks_at(~[px(0), px(40), px(90), px(130), px(200)]);
+        breaks.forced = ~[px(0), px(40)];
+        let pages = paginate(&breaks, px(200), px(100));
+        // A break before the first block doesn't leave an empty page
+        assert pages == ~[Page { top: px(0), height: px(40) },
+                          Page { top: px(40), height: px(90) },
+                          Page { top: px(130), height: px(70) }];
+    }
+
+    #[test]
+    fn test_paginate_avoid_inside() {
+        // The block from 40 to 130 is kept whole
+        let mut breaks = breaks_at(~[px(0), px(40), px(90), px(130), px(200)]);
+        breaks.avoid = ~[(px(40), px(130))];
+        let pages = paginate(&breaks, px(200), px(100));
+        assert pages == ~[Page { top: px(0), height: px(40) },
+                          Page { top: px(40), height: px(90) },
+                          Page { top: px(130), height: px(70) }];
+
+        // One taller than a page is split anyway
+        breaks.avoid = ~[(px(40), px(150))];
+        assert paginate(&breaks, px(200), px(100))[0] == Page { top: px(0), height: px(90) };
+    }
+
+    #[test]
+    fn test_line_break_points() {
+        let tops = [px(0), px(20), px(40), px(60), px(80)];
+        assert line_break_points(tops, 2, 2) == ~[px(40), px(60)];
+        assert line_break_points(tops, 1, 1) == ~[px(20), px(40), px(60), px(80)];
+        // Too few lines to split at all
+        assert line_break_points(tops, 3, 3).is_empty();
+    }
+
+    #[test]
+    fn test_content_area() {
+        let mut rule = PageRule();
+        rule.size = Some(Size2D(px(400), px(600)));
+        rule.margins = Some((px(10), px(20), px(30), px(40)));
+        let print = PrintContext(&rule, 192);
+        assert print.content_area() == Rect(Point2D(px(40), px(10)), Size2D(px(340), px(560)));
+        assert print.device_size() == Size2D(800, 1200);
+        assert print.orphans == 2;
+    }
+}
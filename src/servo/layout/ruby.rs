@@ -0,0 +1,223 @@
+/*!
+Ruby: short annotations, like the readings of CJK characters, set over or
+under the base text they go with. In
+
+    <ruby>漢<rp>(</rp><rt>kan</rt><rp>)</rp>字<rt>ji</rt></ruby>
+
+each `<rt>` annotates the base text since the one before it. `<rp>` is for
+browsers without ruby, and isn't displayed.
+
+The text run scanner gives ruby text a box per character, so that a base
+or annotation narrower than its partner can be spaced out to its width.
+*/
+
+use au = gfx::geometry;
+use dom::element::{ElementData, HTMLRubyElement, HTMLRubyTextElement};
+use dom::node::{Node, NodeTree, Element};
+use gfx::geometry::Au;
+use layout::box::{RenderBox, RenderBoxMethods};
+use util::range::Range;
+use util::tree;
+
+pub enum RubyPosition {
+    RubyOver,
+    RubyUnder,
+}
+
+impl RubyPosition : cmp::Eq {
+    pure fn eq(&self, other: &RubyPosition) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &RubyPosition) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/**
+The `ruby-position` of `element`, if it sets one.
+
+TODO: the style system doesn't know about `ruby-position` yet, so this only
+sees it in the element's `style` attribute.
+*/
+pub fn ruby_position(element: &ElementData) -> Option<RubyPosition> {
+    match element.get_style_property("ruby-position") {
+        Some(~"over") => Some(RubyOver),
+        Some(~"under") => Some(RubyUnder),
+        _ => None
+    }
+}
+
+/// What a box's content is to the `<ruby>` it's in, if any.
+pub enum RubyRole {
+    NotRuby,
+    RubyBase(Node),
+    RubyAnnotation(Node, RubyPosition),
+}
+
+/// The role in ruby layout of the boxes for `node`.
+pub fn ruby_role(node: Node) -> RubyRole {
+    let mut in_annotation = false;
+    // ruby-position is inherited, so the nearest one wins
+    let mut position = None;
+    let mut current = Some(node);
+    while current.is_some() {
+        let n = current.get();
+        let (is_ruby, is_annotation, n_position) = do n.read |nd| {
+            match nd.kind {
+                ~Element(ref e) => match e.kind {
+                    ~HTMLRubyElement => (true, false, ruby_position(e)),
+                    ~HTMLRubyTextElement => (false, true, ruby_position(e)),
+                    _ => (false, false, ruby_position(e))
+                },
+                _ => (false, false, None)
+            }
+        };
+        if position.is_none() {
+            position = n_position;
+        }
+        in_annotation = in_annotation || is_annotation;
+        if is_ruby {
+            return if in_annotation {
+                RubyAnnotation(n, position.get_default(RubyOver))
+            } else {
+                RubyBase(n)
+            };
+        }
+        current = tree::parent(&NodeTree, &n);
+    }
+    NotRuby
+}
+
+/// Whether a box is an annotation, and where it goes if so.
+pub fn annotation_position(box: @RenderBox) -> Option<RubyPosition> {
+    match ruby_role(box.d().node) {
+        RubyAnnotation(_, position) => Some(position),
+        _ => None
+    }
+}
+
+/// Base text and the annotation that goes with it, as ranges of a line's
+/// boxes. The base is empty for an `<rt>` with nothing before it.
+pub struct RubyPair {
+    base: Range,
+    annotation: Range,
+}
+
+/// The ruby pairs in a line of `boxes`, in logical order.
+pub fn ruby_pairs(boxes: &[@RenderBox]) -> ~[RubyPair] {
+    let mut pairs = ~[];
+    let mut ruby = None;
+    let mut base_begin = 0;
+    let mut i = 0;
+    while i < boxes.len() {
+        match ruby_role(boxes[i].d().node) {
+            NotRuby => {
+                ruby = None;
+                i += 1;
+            }
+            RubyBase(r) => {
+                if ruby != Some(r) {
+                    ruby = Some(r);
+                    base_begin = i;
+                }
+                i += 1;
+            }
+            RubyAnnotation(r, _) => {
+                if ruby != Some(r) {
+                    ruby = Some(r);
+                    base_begin = i;
+                }
+                let annotation_begin = i;
+                while i < boxes.len() && is_annotation_of(boxes[i], r) {
+                    i += 1;
+                }
+                pairs.push(RubyPair {
+                    base: Range(base_begin, annotation_begin - base_begin),
+                    annotation: Range(annotation_begin, i - annotation_begin),
+                });
+                base_begin = i;
+            }
+        }
+    }
+    move pairs
+}
+
+fn is_annotation_of(box: @RenderBox, ruby: Node) -> bool {
+    match ruby_role(box.d().node) {
+        RubyAnnotation(r, _) => r == ruby,
+        _ => false
+    }
+}
+
+/**
+Where to put boxes of `widths` across `width`, with the space left over
+shared out equally around each, as `ruby-align: space-around` does. The
+offsets are from the start of the span; boxes wider than it together are
+placed edge to edge.
+*/
+pub fn space_around(widths: &[Au], width: Au) -> ~[Au] {
+    let total = widths.foldl(Au(0), |sum, w| *sum + *w);
+    let gap = if total < width && widths.len() > 0 {
+        (width - total) / Au(widths.len() as i32)
+    } else {
+        Au(0)
+    };
+    let mut x = gap / Au(2);
+    do widths.map |w| {
+        let offset = x;
+        x += *w + gap;
+        offset
+    }
+}
+
+/**
+Places `pair` at `x` on its line, its base and annotation spaced out to
+the width of the wider of the two, and returns that width. The
+annotation is moved over or under the base when line heights are known.
+*/
+pub fn place_pair(boxes: &[@RenderBox], pair: &RubyPair, x: Au) -> Au {
+    let widths_of = |range: &Range| {
+        vec::from_fn(range.length(), |i| boxes[range.begin() + i].d().position.size.width)
+    };
+    let base_widths = widths_of(&pair.base);
+    let annotation_widths = widths_of(&pair.annotation);
+    let sum = |widths: &[Au]| widths.foldl(Au(0), |sum, w| *sum + *w);
+    let width = au::max(sum(base_widths), sum(annotation_widths));
+
+    for [(pair.base, base_widths), (pair.annotation, annotation_widths)].each |part| {
+        let (range, widths) = copy *part;
+        let offsets = space_around(widths, width);
+        for range.eachi |i| {
+            boxes[i].d().position.origin.x = x + offsets[i - range.begin()];
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod ruby_tests {
+    use dom::element::{ElementData, HTMLRubyElement};
+
+    #[test]
+    fn test_space_around() {
+        // Two 10px characters under a 60px annotation get 20px around each
+        let offsets = space_around(~[Au(10), Au(10)], Au(60));
+        assert offsets == ~[Au(10), Au(40)];
+        // Nothing to share out when they're as wide as the span
+        assert space_around(~[Au(30), Au(30)], Au(60)) == ~[Au(0), Au(30)];
+        assert space_around(~[Au(40), Au(40)], Au(60)) == ~[Au(0), Au(40)];
+        assert space_around(~[], Au(60)) == ~[];
+    }
+
+    #[test]
+    fn test_ruby_position() {
+        let ruby = ElementData(~"ruby", ~HTMLRubyElement);
+        assert ruby_position(&ruby).is_none();
+        ruby.set_attr("style", ~"color: red; Ruby-Position: Under");
+        assert ruby_position(&ruby) == Some(RubyUnder);
+        ruby.set_attr("style", ~"ruby-position: under; ruby-position: over");
+        assert ruby_position(&ruby) == Some(RubyOver);
+        ruby.set_attr("style", ~"ruby-position: alternate");
+        assert ruby_position(&ruby).is_none();
+    }
+}
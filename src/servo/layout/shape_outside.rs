@@ -0,0 +1,324 @@
+/*!
+Float shapes. With `shape-outside`, the lines beside a float wrap around a
+circle, ellipse, polygon or the opaque part of an image instead of the
+float's box. The shape is cut to the box, and only moves lines; other
+floats still go beside the box.
+
+TODO: the style system doesn't know about `shape-outside` yet, so this only
+sees it in the element's `style` attribute, where urls are lowercased along
+with everything else. `shape-margin` and `shape-image-threshold` aren't
+supported.
+*/
+
+use au = gfx::geometry;
+use css::values::basic_shape::{BasicShape, Circle, Ellipse, Inset, Polygon, ShapeRadius,
+                               RadiusLength, ClosestSide, FarthestSide, ShapeNone, ShapeBasic,
+                               ShapeImage, parse_shape_outside};
+use dom::node::{Node, Element};
+use geom::point::Point2D;
+use geom::size::Size2D;
+use gfx::geometry::Au;
+use image::base::Image;
+use image::holder::ImageHolder;
+use layout::context::LayoutContext;
+use std::arc::get;
+use util::url::make_url;
+
+/// A float's shape, in its box's coordinates.
+pub enum Shape {
+    // center and radii
+    ShapeEllipse(Point2D<Au>, Au, Au),
+    ShapePolygon(~[Point2D<Au>]),
+    // the opaque span of each row of an image, and the rows' height
+    ShapeMask(~[Option<(Au, Au)>], Au),
+}
+
+impl Shape {
+    /**
+    The part of the shape from `top` to `bottom`, as the leftmost and
+    rightmost points it reaches there. None if it doesn't reach that band
+    at all, so that lines there go by the float as if it weren't there.
+    */
+    pure fn extent(top: Au, bottom: Au) -> Option<(Au, Au)> {
+        match self {
+            ShapeEllipse(ref center, rx, ry) => ellipse_extent(center, rx, ry, top, bottom),
+            ShapePolygon(ref points) => polygon_extent(*points, top, bottom),
+            ShapeMask(ref rows, row_height) => mask_extent(*rows, row_height, top, bottom)
+        }
+    }
+}
+
+// `au` times `factor`, rounded rather than cut short
+pure fn scale(au: Au, factor: float) -> Au {
+    Au(float::floor(*au as float * factor + 0.5) as i32)
+}
+
+pure fn union(extent: Option<(Au, Au)>, left: Au, right: Au) -> Option<(Au, Au)> {
+    match extent {
+        Some((l, r)) => Some((au::min(l, left), au::max(r, right))),
+        None => Some((left, right))
+    }
+}
+
+pure fn ellipse_extent(center: &Point2D<Au>, rx: Au, ry: Au, top: Au,
+                       bottom: Au) -> Option<(Au, Au)> {
+    if rx <= Au(0) || ry <= Au(0) || bottom <= center.y - ry || top >= center.y + ry {
+        return None;
+    }
+    // widest at the point of the band nearest the center
+    let dy = if top <= center.y && center.y <= bottom {
+        0.0
+    } else {
+        float::fmin(float::abs(au::to_frac_px(top - center.y)),
+                   float::abs(au::to_frac_px(bottom - center.y)))
+    };
+    let ratio = dy / au::to_frac_px(ry);
+    let half_width = scale(rx, float::sqrt(1.0 - ratio * ratio));
+    Some((center.x - half_width, center.x + half_width))
+}
+
+pure fn polygon_extent(points: &[Point2D<Au>], top: Au, bottom: Au) -> Option<(Au, Au)> {
+    let mut extent = None;
+    for uint::range(0, points.len()) |i| {
+        let p = points[i];
+        let q = points[(i + 1) % points.len()];
+        if au::max(p.y, q.y) < top || au::min(p.y, q.y) > bottom {
+            loop;
+        }
+        if p.y == q.y {
+            extent = union(extent, au::min(p.x, q.x), au::max(p.x, q.x));
+            loop;
+        }
+        // where the edge crosses into and out of the band
+        let low = au::max(au::min(p.y, q.y), top);
+        let high = au::min(au::max(p.y, q.y), bottom);
+        let (a, b) = (x_on_edge(&p, &q, low), x_on_edge(&p, &q, high));
+        extent = union(extent, au::min(a, b), au::max(a, b));
+    }
+    extent
+}
+
+pure fn x_on_edge(p: &Point2D<Au>, q: &Point2D<Au>, y: Au) -> Au {
+    let t = au::to_frac_px(y - p.y) / au::to_frac_px(q.y - p.y);
+    p.x + scale(q.x - p.x, t)
+}
+
+pure fn mask_extent(rows: &[Option<(Au, Au)>], row_height: Au, top: Au,
+                    bottom: Au) -> Option<(Au, Au)> {
+    if row_height <= Au(0) {
+        return None;
+    }
+    let mut extent = None;
+    for rows.eachi |i, row| {
+        let row_top = row_height * Au(i as i32);
+        if row_top + row_height <= top || row_top >= bottom {
+            loop;
+        }
+        match *row {
+            Some((left, right)) => extent = union(extent, left, right),
+            None => ()
+        }
+    }
+    extent
+}
+
+// The radius `radius` resolves to for a center `offset` into a box `length`
+// wide, in the axis it's for
+fn resolve_radius(radius: ShapeRadius, offset: Au, length: Au, reference: Au) -> Au {
+    let abs = |a: Au| au::max(a, Au(0) - a);
+    match radius {
+        RadiusLength(length) => length.resolve(reference),
+        ClosestSide => au::min(abs(offset), abs(length - offset)),
+        FarthestSide => au::max(abs(offset), abs(length - offset))
+    }
+}
+
+/// `shape` on a box of `size`.
+pub fn resolve_shape(shape: &BasicShape, size: &Size2D<Au>) -> Shape {
+    let (w, h) = (size.width, size.height);
+    match *shape {
+        Circle(radius, position) => {
+            let center = Point2D(position.x.resolve(w), position.y.resolve(h));
+            // percentages are of the box's diagonal over √2
+            let diagonal = au::from_frac_px(float::sqrt((au::to_frac_px(w) * au::to_frac_px(w) +
+                                                         au::to_frac_px(h) * au::to_frac_px(h))
+                                                        / 2.0));
+            let r = match radius {
+                ClosestSide => au::min(resolve_radius(ClosestSide, center.x, w, w),
+                                       resolve_radius(ClosestSide, center.y, h, h)),
+                FarthestSide => au::max(resolve_radius(FarthestSide, center.x, w, w),
+                                        resolve_radius(FarthestSide, center.y, h, h)),
+                _ => resolve_radius(radius, center.x, w, diagonal)
+            };
+            ShapeEllipse(center, r, r)
+        }
+        Ellipse(rx, ry, position) => {
+            let center = Point2D(position.x.resolve(w), position.y.resolve(h));
+            ShapeEllipse(center, resolve_radius(rx, center.x, w, w),
+                         resolve_radius(ry, center.y, h, h))
+        }
+        Inset(top, right, bottom, left) => {
+            let (l, t) = (left.resolve(w), top.resolve(h));
+            let (r, b) = (w - right.resolve(w), h - bottom.resolve(h));
+            ShapePolygon(~[Point2D(l, t), Point2D(r, t), Point2D(r, b), Point2D(l, b)])
+        }
+        Polygon(ref points) => ShapePolygon(do points.map |point| {
+            let (x, y) = *point;
+            Point2D(x.resolve(w), y.resolve(h))
+        })
+    }
+}
+
+/// The shape of the opaque pixels of `image`, stretched over a box of
+/// `size`.
+pub fn mask_shape(image: &Image, size: &Size2D<Au>) -> Shape {
+    let (width, height) = (image.width, image.height);
+    let scale_x = if width == 0 { 0.0 } else { au::to_frac_px(size.width) / (width as float) };
+    let rows = do vec::from_fn(height) |y| {
+        let mut opaque = None;
+        for uint::range(0, width) |x| {
+            if image.data[(y * width + x) * 4 + 3] > 0 {
+                opaque = match opaque {
+                    Some((first, _)) => Some((first, x)),
+                    None => Some((x, x))
+                };
+            }
+        }
+        do opaque.map |span| {
+            let (first, last) = *span;
+            (au::from_frac_px(first as float * scale_x),
+             au::from_frac_px((last + 1) as float * scale_x))
+        }
+    };
+    let row_height = if height == 0 { Au(0) } else { size.height / Au(height as i32) };
+    ShapeMask(move rows, row_height)
+}
+
+/// Where a float's shape comes from.
+pub enum ShapeSource {
+    NoShape,
+    BasicShapeSource(BasicShape),
+    ImageShapeSource(@ImageHolder),
+}
+
+/// The `shape-outside` of `node`. An image is asked for now, so it may be
+/// ready by the time the float is placed.
+pub fn ShapeSource(ctx: &LayoutContext, node: Node) -> ShapeSource {
+    let value = do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_style_property_as_written("shape-outside"),
+            _ => None
+        }
+    };
+    match value.map(|v| parse_shape_outside(*v)) {
+        Some(ShapeBasic(move shape)) => BasicShapeSource(move shape),
+        Some(ShapeImage(move url)) => {
+            let url = make_url(move url, Some(copy ctx.doc_url));
+            ImageShapeSource(@ImageHolder(move url, ctx.image_cache))
+        }
+        Some(ShapeNone) | None => NoShape
+    }
+}
+
+impl ShapeSource {
+    /// The shape of a float of `size`, or None if it's the float's box,
+    /// as it is until an image shape has loaded.
+    fn resolve(&self, size: &Size2D<Au>) -> Option<@Shape> {
+        match *self {
+            NoShape => None,
+            BasicShapeSource(ref shape) => Some(@resolve_shape(shape, size)),
+            ImageShapeSource(holder) => match holder.get_image() {
+                Some(image) => Some(@mask_shape(*get(&image), size)),
+                None => None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod shape_outside_tests {
+    use css::values::basic_shape::{ShapeBasic, ShapeImage, ShapeNone, parse_shape_outside};
+
+    fn px(n: int) -> Au { au::from_px(n) }
+
+    fn shape(value: &str, w: int, h: int) -> Shape {
+        match parse_shape_outside(value) {
+            ShapeBasic(ref shape) => resolve_shape(shape, &Size2D(px(w), px(h))),
+            _ => fail fmt!("%s didn't parse", value)
+        }
+    }
+
+    #[test]
+    fn test_circle() {
+        let circle = shape("circle(50% at 50% 50%)", 100, 100);
+        assert circle.extent(px(50), px(50)) == Some((px(0), px(100)));
+        // the band's widest point counts
+        assert circle.extent(px(10), px(60)) == Some((px(0), px(100)));
+        assert circle.extent(px(90), px(100)) == Some((px(20), px(80)));
+        assert circle.extent(px(100), px(120)).is_none();
+
+        // closest-side is the default, and the center defaults to the middle
+        let default = shape("circle()", 100, 60);
+        assert default.extent(px(30), px(30)) == Some((px(20), px(80)));
+    }
+
+    #[test]
+    fn test_ellipse_and_inset() {
+        let ellipse = shape("ellipse(50px 20px at left top)", 100, 100);
+        assert ellipse.extent(px(0), px(0)) == Some((px(-50), px(50)));
+        assert ellipse.extent(px(20), px(30)).is_none();
+
+        let inset = shape("inset(10px 20px)", 100, 100);
+        assert inset.extent(px(0), px(5)).is_none();
+        assert inset.extent(px(0), px(20)) == Some((px(20), px(80)));
+        assert shape("inset(10px round 5px)", 100, 100).extent(px(50), px(60)) ==
+            Some((px(10), px(90)));
+    }
+
+    #[test]
+    fn test_url() {
+        let url = |value: &str| match parse_shape_outside(value) {
+            ShapeImage(move url) => move url,
+            _ => fail fmt!("%s isn't a url", value)
+        };
+        assert url("url(Shapes/Foo.PNG)") == ~"Shapes/Foo.PNG";
+        assert url("URL( \"a b.png\" ) margin-box") == ~"a b.png";
+        assert url("url('data:image/png;base64,AbC=')") == ~"data:image/png;base64,AbC=";
+        assert url("url(\"x).png\")") == ~"x).png";
+        match parse_shape_outside("url('unclosed)") {
+            ShapeNone => (),
+            _ => fail
+        }
+    }
+
+    #[test]
+    fn test_polygon() {
+        // a triangle pointing right, widest halfway down
+        let triangle = shape("polygon(0 0, 100% 50%, 0 100%)", 100, 100);
+        assert triangle.extent(px(0), px(10)) == Some((px(0), px(20)));
+        assert triangle.extent(px(40), px(60)) == Some((px(0), px(100)));
+        assert triangle.extent(px(90), px(100)) == Some((px(0), px(20)));
+        assert triangle.extent(px(101), px(110)).is_none();
+    }
+
+    #[test]
+    fn test_mask() {
+        // a 2x2 image with one opaque pixel, at the bottom right
+        let image = Image(2, 2, 4, ~[0, 0, 0, 0,   0, 0, 0, 0,
+                                     0, 0, 0, 0,   0, 0, 0, 255]);
+        let mask = mask_shape(&image, &Size2D(px(40), px(40)));
+        assert mask.extent(px(0), px(10)).is_none();
+        assert mask.extent(px(10), px(30)) == Some((px(20), px(40)));
+    }
+
+    #[test]
+    fn test_unparsed() {
+        assert match parse_shape_outside("none") { ShapeNone => true, _ => false };
+        assert match parse_shape_outside("circle(big)") { ShapeNone => true, _ => false };
+        assert match parse_shape_outside("polygon(0 0, 10px 0)") { ShapeNone => true, _ => false };
+        assert match parse_shape_outside("url('shape.png')") {
+            ShapeImage(ref url) => *url == ~"shape.png",
+            _ => false
+        };
+    }
+}
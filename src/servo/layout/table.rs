@@ -0,0 +1,1061 @@
+/*!
+Table layout (CSS 2.1 Section 17). A table and its parts are block flows:
+the table's children are its captions, row groups and rows, a row's
+children are its cells. Once the cells know their intrinsic widths and
+their content heights, the table sizes its columns and rows and places
+every part itself, which block layout leaves alone.
+
+Parts that are missing are made up without flows of their own: cells
+directly in a table or row group share an anonymous row, and content that
+isn't a cell where a cell belongs is laid out as one. Rows and row groups
+outside a table are laid out as plain blocks.
+
+Columns are sized by the auto layout algorithm, or by the fixed one for
+`table-layout: fixed` with a width: from the `<col>`s and the first row
+only, so that the rest of the table needn't be looked at. `colspan` and
+`rowspan` cells take their share of the columns and rows they span.
+
+TODO: `caption-side: bottom`, baseline alignment (baseline cells are
+aligned to the top) and `inline-table`, which is laid out as a block. In
+the collapsing border model, borders are compared by width and by which
+part they're from, since border styles aren't read yet.
+*/
+
+use au = gfx::geometry;
+use dom::node::{Node, Element};
+use geom::point::Point2D;
+use geom::size::Size2D;
+use gfx::geometry::Au;
+use layout::box::{TextBox, UnscannedTextBox};
+use layout::flow::{FlowContext, FlowTree, BlockFlow, InlineFlow};
+use newcss::values::*;
+use util::tree;
+
+pub enum TablePart {
+    TableBox,
+    RowGroup,
+    HeaderGroup,
+    FooterGroup,
+    Row,
+    Cell,
+    Caption,
+}
+
+impl TablePart : cmp::Eq {
+    pure fn eq(&self, other: &TablePart) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &TablePart) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// The part of a table an element with `display` is, if any.
+pub pure fn table_part(display: CSSDisplay) -> Option<TablePart> {
+    match display {
+        DisplayTable | DisplayInlineTable => Some(TableBox),
+        DisplayTableRowGroup => Some(RowGroup),
+        DisplayTableHeaderGroup => Some(HeaderGroup),
+        DisplayTableFooterGroup => Some(FooterGroup),
+        DisplayTableRow => Some(Row),
+        DisplayTableCell => Some(Cell),
+        DisplayTableCaption => Some(Caption),
+        _ => None
+    }
+}
+
+/// Whether `display` makes a table's parts. Columns have no boxes.
+pub pure fn is_table_display(display: CSSDisplay) -> bool {
+    table_part(display).is_some()
+}
+
+/// What an element is a part of a table as, given the `display` the UA
+/// rules gave it, and the flow its own flow is a child of. Parts other
+/// than tables are only parts inside a table or one of its row groups or
+/// rows.
+pub fn part_in_table(display: CSSDisplay, parent: Option<@FlowContext>) -> Option<TablePart> {
+    let part = table_part(display);
+    if part == Some(TableBox) {
+        return part;
+    }
+    match parent {
+        Some(@BlockFlow(_, ref data)) if data.table.is_some() => part,
+        Some(@BlockFlow(_, ref data)) => match data.table_part {
+            Some(RowGroup) | Some(HeaderGroup) | Some(FooterGroup) | Some(Row) => part,
+            _ => None
+        },
+        _ => None
+    }
+}
+
+pub enum TableLayout {
+    AutoLayout,
+    FixedLayout,
+}
+
+pub enum TableWidth {
+    AutoWidth,
+    FixedWidth(Au),
+    PercentWidth(float),
+}
+
+pub enum CellAlign {
+    AlignTop,
+    AlignMiddle,
+    AlignBottom,
+    AlignBaseline,
+}
+
+// Where a border comes from, from the one whose borders win ties when
+// they collapse to the one that loses them
+pub enum BorderOrigin {
+    CellBorder,
+    RowBorder,
+    RowGroupBorder,
+    TableBorder,
+}
+
+/// A border in the collapsing border model.
+pub struct CollapsedBorder {
+    width: Au,
+    origin: BorderOrigin,
+}
+
+/// The border drawn where `a` and `b` meet: the wider, or the one from
+/// the part nearer the cell (CSS 2.1 Section 17.6.2.1).
+pub pure fn collapse_borders(a: CollapsedBorder, b: CollapsedBorder) -> CollapsedBorder {
+    if a.width > b.width {
+        a
+    } else if b.width > a.width {
+        b
+    } else if (a.origin as uint) <= (b.origin as uint) {
+        a
+    } else {
+        b
+    }
+}
+
+/// How much a cell spanning columns or rows needs from them.
+pub struct Contribution {
+    start: uint,
+    span: uint,
+    min: Au,
+    max: Au,
+}
+
+fn sum(sizes: &[Au]) -> Au {
+    sizes.foldl(Au(0), |total, size| *total + *size)
+}
+
+// Spreads `extra` over `sizes[start]` to `sizes[end - 1]`, in proportion
+// to `weights`, one for each, or evenly if they're all 0
+fn spread(sizes: &mut ~[Au], start: uint, end: uint, weights: &[Au], extra: Au) {
+    let total = sum(weights);
+    let count = end - start;
+    let mut given = Au(0);
+    for uint::range(0, count) |i| {
+        let share = if i == count - 1 {
+            // the last takes what rounding left over
+            extra - given
+        } else if total > Au(0) {
+            Au(((*extra as i64) * (*weights[i] as i64) / (*total as i64)) as i32)
+        } else {
+            Au(*extra / (count as i32))
+        };
+        sizes[start + i] += share;
+        given += share;
+    }
+}
+
+/**
+The minimum and maximum widths of `count` columns, from the cells in them.
+Cells in one column count first, then those spanning more, which widen the
+columns they span if those aren't wide enough between them, `spacing`
+included.
+*/
+pub fn column_extents(count: uint, cells: &[Contribution],
+                      spacing: Au) -> (~[Au], ~[Au]) {
+    let mut mins = vec::from_elem(count, Au(0));
+    let mut maxes = vec::from_elem(count, Au(0));
+    for cells.each |cell| {
+        if cell.span == 1 {
+            mins[cell.start] = au::max(mins[cell.start], cell.min);
+            maxes[cell.start] = au::max(maxes[cell.start], au::max(cell.min, cell.max));
+        }
+    }
+    // the narrower spans first, as the wider ones may span them
+    let widest = cells.foldl(1, |w, cell| uint::max(*w, cell.span));
+    for uint::range(2, widest + 1) |span| {
+        for cells.each |cell| {
+            if cell.span != span {
+                loop;
+            }
+            let end = uint::min(cell.start + cell.span, count);
+            let gaps = Au(*spacing * ((end - cell.start) as i32 - 1));
+            let weights = vec::slice(maxes, cell.start, end);
+            let min_extra = cell.min - gaps - sum(vec::slice(mins, cell.start, end));
+            if min_extra > Au(0) {
+                spread(&mut mins, cell.start, end, weights, min_extra);
+            }
+            let max_extra = au::max(cell.min, cell.max) - gaps - sum(weights);
+            if max_extra > Au(0) {
+                spread(&mut maxes, cell.start, end, weights, max_extra);
+            }
+            for uint::range(cell.start, end) |i| {
+                maxes[i] = au::max(maxes[i], mins[i]);
+            }
+        }
+    }
+    (move mins, move maxes)
+}
+
+/**
+Widths for columns with minimum widths `mins` and maximum widths `maxes`
+that add up to `target`. Between the two, each column gets the same share
+of the way from its minimum to its maximum; past the maximums, the extra
+is shared in proportion to them. Short of the minimums, the columns are
+as narrow as they go.
+*/
+pub fn distribute_width(mins: &[Au], maxes: &[Au], target: Au) -> ~[Au] {
+    let min_total = sum(mins);
+    let max_total = sum(maxes);
+    let count = mins.len();
+    if target <= min_total || count == 0 {
+        return vec::from_fn(count, |i| mins[i]);
+    }
+    if target <= max_total {
+        let mut widths = vec::from_fn(count, |i| mins[i]);
+        let room = vec::from_fn(count, |i| maxes[i] - mins[i]);
+        spread(&mut widths, 0, count, room, target - min_total);
+        move widths
+    } else {
+        let mut widths = vec::from_fn(count, |i| maxes[i]);
+        spread(&mut widths, 0, count, maxes, target - max_total);
+        move widths
+    }
+}
+
+/**
+The widths of the columns of a `table-layout: fixed` table whose columns
+take `target` between them. Columns with a width of their own keep it, and
+the others share what's left evenly, or if there are none, the extra is
+shared in proportion to the widths.
+*/
+pub fn fixed_column_widths(specified: &[Option<Au>], target: Au) -> ~[Au] {
+    let fixed = sum(specified.map(|w| w.get_default(Au(0))));
+    let auto_count = specified.foldl(0, |n, w| if w.is_none() { *n + 1 } else { *n });
+    let left = au::max(target - fixed, Au(0));
+    let mut widths = do specified.map |w| {
+        match *w {
+            Some(width) => width,
+            None => Au(*left / (auto_count as i32))
+        }
+    };
+    if auto_count == 0 && left > Au(0) && !widths.is_empty() {
+        let weights = copy widths;
+        spread(&mut widths, 0, weights.len(), weights, left);
+    }
+    move widths
+}
+
+/**
+The heights of `count` rows with `spacing` between them, from the heights
+of the cells in them: as tall as the tallest cell in one row, and then
+taller if a cell spanning rows needs more, which is shared between them.
+*/
+pub fn row_heights(count: uint, cells: &[Contribution], spacing: Au) -> ~[Au] {
+    let mut heights = vec::from_elem(count, Au(0));
+    for cells.each |cell| {
+        if cell.span == 1 {
+            heights[cell.start] = au::max(heights[cell.start], cell.min);
+        }
+    }
+    for cells.each |cell| {
+        let end = uint::min(cell.start + cell.span, count);
+        if cell.span > 1 && end > cell.start {
+            let gaps = Au(*spacing * ((end - cell.start) as i32 - 1));
+            let extra = cell.min - gaps - sum(vec::slice(heights, cell.start, end));
+            if extra > Au(0) {
+                let weights = vec::from_elem(end - cell.start, Au(0));
+                spread(&mut heights, cell.start, end, weights, extra);
+            }
+        }
+    }
+    move heights
+}
+
+/**
+Finds the slot each cell starts in, given the `(colspan, rowspan)` of the
+cells of each row. A cell goes in the first column of its row that no
+cell above spans down into. Returns the `(row, column)` of each cell in
+order, and how many columns there are.
+*/
+pub fn place_cells(rows: &[~[(uint, uint)]]) -> (~[(uint, uint)], uint) {
+    // how many more rows each column is taken for, by cells above
+    let mut taken: ~[uint] = ~[];
+    let mut slots = ~[];
+    for rows.eachi |r, row| {
+        let mut col = 0;
+        for row.each |span| {
+            let (colspan, rowspan) = *span;
+            while col < taken.len() && taken[col] > 0 {
+                col += 1;
+            }
+            slots.push((r, col));
+            while taken.len() < col + colspan {
+                taken.push(0);
+            }
+            for uint::range(col, col + colspan) |c| {
+                taken[c] = rowspan;
+            }
+            col += colspan;
+        }
+        for uint::range(0, taken.len()) |c| {
+            if taken[c] > 0 {
+                taken[c] -= 1;
+            }
+        }
+    }
+    (move slots, taken.len())
+}
+
+/// How far down a cell's content goes in a cell `cell_height` tall.
+pub pure fn align_offset(align: CellAlign, content_height: Au, cell_height: Au) -> Au {
+    let room = au::max(cell_height - content_height, Au(0));
+    match align {
+        AlignTop | AlignBaseline => Au(0),
+        AlignMiddle => Au(*room / 2),
+        AlignBottom => room
+    }
+}
+
+fn attr(node: Node, name: &str) -> Option<~str> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_attr(name),
+            _ => None
+        }
+    }
+}
+
+fn property(node: Node, name: &str) -> Option<~str> {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => e.get_style_property(name),
+            _ => None
+        }
+    }
+}
+
+fn tag_name(node: Node) -> ~str {
+    do node.read |n| {
+        match n.kind {
+            ~Element(ref e) => copy e.tag_name,
+            _ => ~""
+        }
+    }
+}
+
+// A length in px, or 0
+fn parse_length(value: &str) -> Option<Au> {
+    let value = str::trim(value);
+    if value == ~"0" {
+        return Some(Au(0));
+    }
+    if !value.ends_with("px") {
+        return None;
+    }
+    float::from_str(value.slice(0, value.len() - 2)).map(|px| au::from_frac_px(*px))
+}
+
+/// Parses a width: a length, a percentage, or an HTML width attribute's
+/// bare number of px.
+pub fn parse_width(value: &str) -> TableWidth {
+    let value = str::trim(value);
+    if value.ends_with("%") {
+        return match float::from_str(value.slice(0, value.len() - 1)) {
+            Some(percent) => PercentWidth(percent / 100.0),
+            None => AutoWidth
+        };
+    }
+    match parse_length(value) {
+        Some(width) => FixedWidth(width),
+        None => match float::from_str(value) {
+            Some(px) => FixedWidth(au::from_frac_px(px)),
+            None => AutoWidth
+        }
+    }
+}
+
+// The `width` of `node`, from its style or its width attribute
+fn specified_width(node: Node) -> TableWidth {
+    match property(node, "width").map(|v| parse_width(*v)) {
+        Some(FixedWidth(w)) => FixedWidth(w),
+        Some(PercentWidth(p)) => PercentWidth(p),
+        _ => attr(node, "width").map_default(AutoWidth, |v| parse_width(*v))
+    }
+}
+
+// The most columns a cell or <col> can span, and rows a cell can, as HTML
+// clamps them
+const MAX_COLSPAN: uint = 1000;
+const MAX_ROWSPAN: uint = 65534;
+
+// A `span`, `colspan` or `rowspan` value, clamped to `max`
+fn parse_span(value: &str, max: uint) -> Option<uint> {
+    uint::from_str(str::trim(value)).map(|span| uint::min(*span, max))
+}
+
+fn span_attr(node: Node, name: &str, max: uint) -> Option<uint> {
+    attr(node, name).chain(|v| parse_span(v, max))
+}
+
+fn cell_align(node: Node) -> CellAlign {
+    let value = match property(node, "vertical-align") {
+        Some(move value) => Some(move value),
+        None => attr(node, "valign")
+    };
+    match value.map(|v| str::to_lower(str::trim(*v))) {
+        Some(~"top") => AlignTop,
+        Some(~"bottom") => AlignBottom,
+        Some(~"baseline") => AlignBaseline,
+        // the HTML UA sheet has rows and row groups middle aligned
+        _ => AlignMiddle
+    }
+}
+
+fn border_width(node: Node) -> Au {
+    match node.style().border_width {
+        Specified(Px(px)) => au::from_frac_px(px),
+        _ => Au(0)
+    }
+}
+
+// The node a block flow's box is for
+fn flow_node(flow: @FlowContext) -> Option<Node> {
+    match *flow {
+        BlockFlow(_, ref data) => data.box.map(|b| b.d().node),
+        _ => None
+    }
+}
+
+fn flow_part(flow: @FlowContext) -> Option<TablePart> {
+    match *flow {
+        BlockFlow(_, ref data) => data.table_part,
+        _ => None
+    }
+}
+
+// Whether `flow` is only the whitespace between a table's parts
+fn is_whitespace_flow(flow: @FlowContext) -> bool {
+    match *flow {
+        InlineFlow(*) => (),
+        _ => return false
+    }
+    let mut whitespace = true;
+    for flow.inline().boxes.each |box| {
+        let blank = match *box {
+            @UnscannedTextBox(_, ref s) => s.is_whitespace(),
+            @TextBox(_, ref d) => {
+                str::substr(d.run.text, d.range.begin(), d.range.length()).is_whitespace()
+            }
+            _ => false
+        };
+        whitespace = whitespace && blank;
+    }
+    whitespace
+}
+
+/// A cell, and the slots it takes.
+struct TableCell {
+    flow: @FlowContext,
+    row: uint,
+    col: uint,
+    colspan: uint,
+    rowspan: uint,
+}
+
+/// A row, which may be anonymous, and the flow its cells are children of.
+struct TableRow {
+    flow: Option<@FlowContext>,
+    parent: @FlowContext,
+    // the row group it's in, by index
+    group: Option<uint>,
+}
+
+struct TableGroup {
+    flow: @FlowContext,
+    first_row: uint,
+    row_count: uint,
+}
+
+pub struct TableContext {
+    layout: TableLayout,
+    width: TableWidth,
+    collapse: bool,
+    // space between cells, across and down, in the separated border model
+    spacing: Size2D<Au>,
+    // widths given by <col>s, one for each column they stand for
+    column_widths: ~[Option<Au>],
+    // the width of the table's own border
+    border: Au,
+    // in the collapsing border model, the border around the cells, half
+    // of which is inside the table
+    mut edge_border: Au,
+
+    mut captions: ~[@FlowContext],
+    mut rows: ~[TableRow],
+    mut groups: ~[TableGroup],
+    mut cells: ~[TableCell],
+    mut column_count: uint,
+    mut mins: ~[Au],
+    mut maxes: ~[Au],
+    // once widths are assigned, where the columns are and how wide
+    mut widths: ~[Au],
+    mut left: Au,
+}
+
+// The widths of the <col>s of `table`, one for each column they span
+fn column_widths(table: Node) -> ~[Option<Au>] {
+    let mut widths = ~[];
+    let add = |node: Node, widths: &mut ~[Option<Au>]| {
+        let width = match specified_width(node) {
+            FixedWidth(w) => Some(w),
+            _ => None
+        };
+        for uint::range(0, span_attr(node, "span", MAX_COLSPAN).get_default(1)) |_i| {
+            widths.push(width);
+        }
+    };
+    for tree::each_child(&dom::node::NodeTree, &table) |child| {
+        match tag_name(*child) {
+            ~"col" => add(*child, &mut widths),
+            ~"colgroup" => {
+                let mut cols = 0;
+                for tree::each_child(&dom::node::NodeTree, child) |col| {
+                    if tag_name(*col) == ~"col" {
+                        add(*col, &mut widths);
+                        cols += 1;
+                    }
+                }
+                if cols == 0 {
+                    add(*child, &mut widths);
+                }
+            }
+            _ => ()
+        }
+    }
+    move widths
+}
+
+/// The table `node` lays out.
+pub fn TableContext(node: Node) -> TableContext {
+    let collapse = property(node, "border-collapse") == Some(~"collapse");
+    let spacing = match property(node, "border-spacing") {
+        Some(ref value) => {
+            let lengths = str::words(*value).map(|v| parse_length(*v).get_default(Au(0)));
+            match lengths.len() {
+                0 => Size2D(Au(0), Au(0)),
+                1 => Size2D(lengths[0], lengths[0]),
+                _ => Size2D(lengths[0], lengths[1])
+            }
+        }
+        None => {
+            // the HTML UA sheet's 2px, unless cellspacing says otherwise
+            let html = tag_name(node) == ~"table";
+            let default = if html { au::from_px(2) } else { Au(0) };
+            let spacing = attr(node, "cellspacing").chain(|v| float::from_str(str::trim(v)))
+                .map_default(default, |px| au::from_frac_px(*px));
+            Size2D(spacing, spacing)
+        }
+    };
+    let width = specified_width(node);
+    let layout = match (property(node, "table-layout"), width) {
+        // a fixed layout needs a width to fit the columns to
+        (Some(~"fixed"), AutoWidth) => AutoLayout,
+        (Some(~"fixed"), _) => FixedLayout,
+        _ => AutoLayout
+    };
+    TableContext {
+        layout: layout,
+        width: width,
+        collapse: collapse,
+        spacing: if collapse { Size2D(Au(0), Au(0)) } else { spacing },
+        column_widths: column_widths(node),
+        border: border_width(node),
+        edge_border: Au(0),
+        captions: ~[],
+        rows: ~[],
+        groups: ~[],
+        cells: ~[],
+        column_count: 0,
+        mins: ~[],
+        maxes: ~[],
+        widths: ~[],
+        left: Au(0),
+    }
+}
+
+impl TableContext {
+    // Adds the rows among `children`, which are in `parent`, a table or
+    // row group, making up anonymous rows for cells and other content
+    // that aren't in one
+    fn gather_rows(&self, children: &[@FlowContext], parent: @FlowContext, group: Option<uint>,
+                   rows: &mut ~[TableRow], cells: &mut ~[~[@FlowContext]]) {
+        let mut anonymous = false;
+        for children.each |child| {
+            match flow_part(*child) {
+                Some(Row) => {
+                    rows.push(TableRow { flow: Some(*child), parent: *child, group: group });
+                    let mut row_cells = ~[];
+                    for FlowTree.each_child(*child) |cell| {
+                        if !is_whitespace_flow(cell) {
+                            row_cells.push(cell);
+                        }
+                    }
+                    cells.push(move row_cells);
+                    anonymous = false;
+                }
+                _ => {
+                    if !anonymous {
+                        rows.push(TableRow { flow: None, parent: parent, group: group });
+                        cells.push(~[]);
+                        anonymous = true;
+                    }
+                    cells[cells.len() - 1].push(*child);
+                }
+            }
+        }
+    }
+
+    /// Finds the table's captions, row groups, rows and cells, and the
+    /// columns the cells are in, under `flow`, the table's own flow.
+    fn gather(&self, flow: @FlowContext) {
+        let mut captions = ~[];
+        let mut headers = ~[];
+        let mut footers = ~[];
+        // the row groups in order, with None where the rows and cells
+        // directly in the table are, which all go where the first was
+        let mut bodies = ~[];
+        let mut loose = ~[];
+        for FlowTree.each_child(flow) |child| {
+            if is_whitespace_flow(child) {
+                loop;
+            }
+            match flow_part(child) {
+                Some(Caption) => captions.push(child),
+                Some(HeaderGroup) if headers.is_empty() => headers.push(Some(child)),
+                Some(FooterGroup) if footers.is_empty() => footers.push(Some(child)),
+                Some(RowGroup) | Some(HeaderGroup) | Some(FooterGroup) => bodies.push(Some(child)),
+                _ => {
+                    if loose.is_empty() {
+                        bodies.push(None);
+                    }
+                    loose.push(child);
+                }
+            }
+        }
+
+        // a header group is laid out first and a footer group last,
+        // wherever they are in the table
+        let mut rows = ~[];
+        let mut row_cells = ~[];
+        let mut groups = ~[];
+        for (headers + bodies + footers).each |section| {
+            match *section {
+                Some(group) => {
+                    let mut children = ~[];
+                    for FlowTree.each_child(group) |child| {
+                        if !is_whitespace_flow(child) {
+                            children.push(child);
+                        }
+                    }
+                    let first_row = rows.len();
+                    self.gather_rows(children, group, Some(groups.len()), &mut rows,
+                                     &mut row_cells);
+                    groups.push(TableGroup { flow: group, first_row: first_row,
+                                             row_count: rows.len() - first_row });
+                }
+                None => self.gather_rows(loose, flow, None, &mut rows, &mut row_cells)
+            }
+        }
+
+        // rowspan="0" and spans past the end of a row group stop at its end
+        let spans = do row_cells.mapi |r, cells| {
+            let group_end = match rows[r].group {
+                Some(g) => groups[g].first_row + groups[g].row_count,
+                None => row_cells.len()
+            };
+            do cells.map |cell| {
+                let node = flow_node(*cell);
+                let colspan = node.chain(|n| span_attr(n, "colspan", MAX_COLSPAN)).get_default(1);
+                let rowspan = node.chain(|n| span_attr(n, "rowspan", MAX_ROWSPAN)).get_default(1);
+                let rowspan = if rowspan == 0 { group_end - r } else { rowspan };
+                (uint::max(colspan, 1), uint::max(uint::min(rowspan, group_end - r), 1))
+            }
+        };
+        let (slots, column_count) = place_cells(spans);
+        let column_count = uint::max(column_count, self.column_widths.len());
+        let mut cells = ~[];
+        let mut i = 0;
+        for row_cells.eachi |r, row| {
+            for row.eachi |c, cell| {
+                let (row_index, col) = slots[i];
+                let (colspan, rowspan) = spans[r][c];
+                cells.push(TableCell { flow: *cell, row: row_index, col: col,
+                                       colspan: colspan, rowspan: rowspan });
+                i += 1;
+            }
+        }
+
+        // with collapsing borders, the table's border is the widest of its
+        // own and those of the cells along its edges
+        if self.collapse {
+            let mut edge = CollapsedBorder { width: self.border, origin: TableBorder };
+            for cells.each |cell| {
+                if cell.row == 0 || cell.row + cell.rowspan >= rows.len() ||
+                   cell.col == 0 || cell.col + cell.colspan >= column_count {
+                    let width = flow_node(cell.flow).map_default(Au(0), |n| border_width(*n));
+                    edge = collapse_borders(edge, CollapsedBorder { width: width,
+                                                                    origin: CellBorder });
+                }
+            }
+            self.edge_border = edge.width;
+        }
+
+        self.captions = move captions;
+        self.rows = move rows;
+        self.groups = move groups;
+        self.cells = move cells;
+        self.column_count = column_count;
+    }
+
+    // Room taken by the spacing between and around `count` columns, or
+    // in the collapsing model by the table's own border
+    pure fn spacing_across(count: uint) -> Au {
+        if self.collapse {
+            self.edge_border
+        } else {
+            Au(*self.spacing.width * (count as i32 + 1))
+        }
+    }
+
+    /// The table's minimum and preferred widths, from its cells, which
+    /// have theirs already. Called for the table's flow, `flow`.
+    fn bubble_widths(&self, flow: @FlowContext) -> (Au, Au) {
+        self.gather(flow);
+        let contributions = do self.cells.map |cell| {
+            let mut min = cell.flow.d().min_width;
+            let mut max = cell.flow.d().pref_width;
+            match flow_node(cell.flow).map(|n| specified_width(*n)) {
+                Some(FixedWidth(width)) => {
+                    min = au::max(min, width);
+                    max = au::max(min, width);
+                }
+                _ => ()
+            }
+            Contribution { start: cell.col, span: cell.colspan, min: min, max: max }
+        };
+        let (mins, maxes) = column_extents(self.column_count, contributions, self.spacing.width);
+        let mut mins = move mins;
+        let mut maxes = move maxes;
+        for self.column_widths.eachi |i, width| {
+            for width.each |w| {
+                mins[i] = au::max(mins[i], *w);
+                maxes[i] = au::max(maxes[i], *w);
+            }
+        }
+        let spacing = self.spacing_across(self.column_count);
+        let mut min = sum(mins) + spacing;
+        let mut max = sum(maxes) + spacing;
+        for self.captions.each |caption| {
+            min = au::max(min, caption.d().min_width);
+            max = au::max(max, caption.d().pref_width);
+        }
+        match self.width {
+            FixedWidth(width) => {
+                max = au::max(min, width);
+                match self.layout {
+                    FixedLayout => min = max,
+                    AutoLayout => ()
+                }
+            }
+            _ => ()
+        }
+        self.mins = move mins;
+        self.maxes = move maxes;
+        (min, max)
+    }
+
+    /// The width of the table given `available` beside it: its own width,
+    /// or else as wide as its contents want, but no wider than it has room
+    /// for unless its contents can't be narrower.
+    fn used_width(&self, flow: @FlowContext, available: Au) -> Au {
+        let min = flow.d().min_width;
+        let max = flow.d().pref_width;
+        match self.width {
+            FixedWidth(width) => au::max(width, min),
+            PercentWidth(p) => au::max(au::from_frac_px(au::to_frac_px(available) * p), min),
+            AutoWidth => au::max(au::min(max, available), min)
+        }
+    }
+
+    fn first_row_widths(&self) -> ~[Option<Au>] {
+        let mut widths = vec::from_fn(self.column_count, |i| {
+            if i < self.column_widths.len() { self.column_widths[i] } else { None }
+        });
+        for self.cells.each |cell| {
+            if cell.row != 0 {
+                loop;
+            }
+            match flow_node(cell.flow).map(|n| specified_width(*n)) {
+                Some(FixedWidth(width)) => {
+                    let share = Au(*width / (cell.colspan as i32));
+                    for uint::range(cell.col, cell.col + cell.colspan) |c| {
+                        if widths[c].is_none() {
+                            widths[c] = Some(share);
+                        }
+                    }
+                }
+                _ => ()
+            }
+        }
+        move widths
+    }
+
+    /// Sizes the columns to fit `width`, the table's content width, from
+    /// `left`, and makes the parts in the table as wide as what they span.
+    fn assign_widths(&self, left: Au, width: Au) {
+        let target = au::max(width - self.spacing_across(self.column_count), Au(0));
+        self.widths = match self.layout {
+            FixedLayout => fixed_column_widths(self.first_row_widths(), target),
+            AutoLayout => distribute_width(self.mins, self.maxes, target)
+        };
+        self.left = left + if self.collapse { Au(*self.edge_border / 2) } else { Au(0) };
+
+        let inner = sum(self.widths) + Au(*self.spacing.width *
+                                            (uint::max(self.column_count, 1) as i32 - 1));
+        for self.captions.each |caption| {
+            caption.d().position.origin.x = left;
+            caption.d().position.size.width = width;
+        }
+        for self.groups.each |group| {
+            group.flow.d().position.size.width = inner;
+        }
+        for self.rows.each |row| {
+            for row.flow.each |flow| {
+                flow.d().position.size.width = inner;
+            }
+        }
+        for self.cells.each |cell| {
+            cell.flow.d().position.size.width = self.span_width(cell.col, cell.colspan);
+        }
+    }
+
+    pure fn column_x(col: uint) -> Au {
+        let mut x = self.left + self.spacing.width;
+        for uint::range(0, col) |c| {
+            x += self.widths[c] + self.spacing.width;
+        }
+        x
+    }
+
+    pure fn span_width(col: uint, span: uint) -> Au {
+        let end = uint::min(col + span, self.widths.len());
+        let mut width = Au(*self.spacing.width * (span as i32 - 1));
+        for uint::range(col, end) |c| {
+            width += self.widths[c];
+        }
+        width
+    }
+
+    /// Sizes the rows to fit the cells, once their contents are laid out,
+    /// and places every part of the table. Returns the table's height.
+    fn layout(&self) -> Au {
+        let mut y = if self.collapse { Au(*self.edge_border / 2) } else { Au(0) };
+        for self.captions.each |caption| {
+            caption.d().position.origin.y = y;
+            y += caption.d().position.size.height;
+        }
+
+        let contributions = do self.cells.map |cell| {
+            let height = cell.flow.d().position.size.height;
+            Contribution { start: cell.row, span: cell.rowspan, min: height, max: height }
+        };
+        let heights = row_heights(self.rows.len(), contributions, self.spacing.height);
+        let mut row_y = ~[];
+        y += self.spacing.height;
+        for heights.each |height| {
+            row_y.push(y);
+            y += *height + self.spacing.height;
+        }
+        if self.rows.is_empty() {
+            y -= self.spacing.height;
+        }
+        let grid_left = self.column_x(0);
+
+        // where each flow in the table is, from the table's top left
+        let mut origins: ~[(@FlowContext, Point2D<Au>)] = ~[];
+        let origin_of = |flow: @FlowContext, origins: &~[(@FlowContext, Point2D<Au>)]| {
+            match origins.find(|o| { let (f, _) = *o; core::box::ptr_eq(f, flow) }) {
+                Some((_, origin)) => origin,
+                None => Point2D(Au(0), Au(0))
+            }
+        };
+        for self.groups.each |group| {
+            let top = if group.row_count > 0 { row_y[group.first_row] } else { y };
+            let mut bottom = top;
+            for uint::range(group.first_row, group.first_row + group.row_count) |r| {
+                bottom = row_y[r] + heights[r];
+            }
+            group.flow.d().position.origin = Point2D(grid_left, top);
+            group.flow.d().position.size.height = bottom - top;
+            do group.flow.with_block_box |box| {
+                box.d().position.size.height = bottom - top;
+            }
+            origins.push((group.flow, Point2D(grid_left, top)));
+        }
+        for self.rows.eachi |r, row| {
+            for row.flow.each |flow| {
+                let parent = match row.group {
+                    Some(g) => origin_of(self.groups[g].flow, &origins),
+                    None => Point2D(Au(0), Au(0))
+                };
+                flow.d().position.origin = Point2D(grid_left - parent.x, row_y[r] - parent.y);
+                flow.d().position.size.height = heights[r];
+                do flow.with_block_box |box| {
+                    box.d().position.size.height = heights[r];
+                }
+                origins.push((*flow, Point2D(grid_left, row_y[r])));
+            }
+        }
+
+        for self.cells.each |cell| {
+            let row = &self.rows[cell.row];
+            let parent = match row.flow {
+                Some(_) => origin_of(row.parent, &origins),
+                None => match row.group {
+                    Some(g) => origin_of(self.groups[g].flow, &origins),
+                    None => Point2D(Au(0), Au(0))
+                }
+            };
+            let end = uint::min(cell.row + cell.rowspan, heights.len());
+            let height = row_y[end - 1] + heights[end - 1] - row_y[cell.row];
+            let content_height = cell.flow.d().position.size.height;
+            cell.flow.d().position.origin = Point2D(self.column_x(cell.col) - parent.x,
+                                                    row_y[cell.row] - parent.y);
+            cell.flow.d().position.size.height = height;
+
+            match *cell.flow {
+                BlockFlow(*) => {
+                    let align = flow_node(cell.flow).map_default(AlignMiddle, |n| cell_align(*n));
+                    let offset = align_offset(align, content_height, height);
+                    for FlowTree.each_child(cell.flow) |child| {
+                        child.d().position.origin.y += offset;
+                    }
+                    do cell.flow.with_block_box |box| {
+                        box.d().position.size.height = height;
+                    }
+                }
+                _ => ()
+            }
+        }
+        y + if self.collapse { Au(*self.edge_border / 2) } else { Au(0) }
+    }
+}
+
+#[cfg(test)]
+mod table_tests {
+    fn px(n: int) -> Au { au::from_px(n) }
+
+    fn cell(start: uint, span: uint, min: int, max: int) -> Contribution {
+        Contribution { start: start, span: span, min: px(min), max: px(max) }
+    }
+
+    #[test]
+    fn test_place_cells() {
+        // | a (rowspan 2) | b (colspan 2) |
+        // |               | c     | d     |
+        // | e     | f     | g     |
+        let rows = ~[~[(1, 2), (2, 1)], ~[(1, 1), (1, 1)], ~[(1, 1), (1, 1), (1, 1)]];
+        let (slots, columns) = place_cells(rows);
+        assert slots == ~[(0, 0), (0, 1), (1, 1), (1, 2), (2, 0), (2, 1), (2, 2)];
+        assert columns == 3;
+    }
+
+    #[test]
+    fn test_column_extents() {
+        let cells = ~[cell(0, 1, 50, 100), cell(1, 1, 20, 50),
+                      // needs 30px more than the two columns and the gap give
+                      // it, and 150px more at most
+                      cell(0, 2, 110, 310)];
+        let (mins, maxes) = column_extents(2, cells, px(10));
+        // the extra is shared in proportion to the columns' maxes
+        assert mins == ~[px(70), px(30)];
+        assert maxes == ~[px(200), px(100)];
+    }
+
+    #[test]
+    fn test_distribute_width() {
+        let mins = ~[px(50), px(20)];
+        let maxes = ~[px(100), px(40)];
+        assert distribute_width(mins, maxes, px(60)) == mins;
+        // halfway between the minimums and the maximums
+        assert distribute_width(mins, maxes, px(105)) == ~[px(75), px(30)];
+        assert distribute_width(mins, maxes, px(280)) == ~[px(200), px(80)];
+    }
+
+    #[test]
+    fn test_fixed_column_widths() {
+        let widths = fixed_column_widths(~[Some(px(100)), None, None], px(300));
+        assert widths == ~[px(100), px(100), px(100)];
+        // no auto columns: the extra is shared out
+        assert fixed_column_widths(~[Some(px(100)), Some(px(50))], px(300)) ==
+            ~[px(200), px(100)];
+        // too narrow: the table overflows
+        assert fixed_column_widths(~[Some(px(200)), None], px(100)) == ~[px(200), px(0)];
+    }
+
+    #[test]
+    fn test_row_heights() {
+        let cells = ~[cell(0, 1, 20, 20), cell(1, 1, 30, 30), cell(0, 2, 79, 79)];
+        // the spanning cell needs 24px more, shared by its rows
+        assert row_heights(2, cells, px(5)) == ~[px(32), px(42)];
+    }
+
+    #[test]
+    fn test_collapse_borders() {
+        let cell = CollapsedBorder { width: px(1), origin: CellBorder };
+        let table = CollapsedBorder { width: px(3), origin: TableBorder };
+        assert collapse_borders(cell, table).width == px(3);
+        let thin_table = CollapsedBorder { width: px(1), origin: TableBorder };
+        match collapse_borders(thin_table, cell).origin {
+            CellBorder => (),
+            _ => fail
+        }
+    }
+
+    #[test]
+    fn test_align_offset() {
+        assert align_offset(AlignTop, px(20), px(50)) == px(0);
+        assert align_offset(AlignMiddle, px(20), px(50)) == px(15);
+        assert align_offset(AlignBottom, px(20), px(50)) == px(30);
+        assert align_offset(AlignBottom, px(60), px(50)) == px(0);
+    }
+
+    #[test]
+    fn test_parse_span() {
+        assert parse_span(" 3 ", MAX_COLSPAN) == Some(3);
+        assert parse_span("2147483647", MAX_COLSPAN) == Some(1000);
+        assert parse_span("2147483647", MAX_ROWSPAN) == Some(65534);
+        assert parse_span("wide", MAX_COLSPAN).is_none();
+    }
+
+    #[test]
+    fn test_parse_width() {
+        match parse_width("50%") { PercentWidth(p) => assert p == 0.5, _ => fail }
+        match parse_width("120") { FixedWidth(w) => assert w == px(120), _ => fail }
+        match parse_width("80px") { FixedWidth(w) => assert w == px(80), _ => fail }
+        match parse_width("auto") { AutoWidth => (), _ => fail }
+    }
+}
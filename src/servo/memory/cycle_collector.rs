@@ -0,0 +1,430 @@
+/*!
+Collection of cycles between the DOM and JS. A node's listeners are held
+from Rust, and its wrapper is rooted for as long as the node is around, so
+a listener that refers to its own element keeps the element, the wrapper
+and itself alive. Neither the GC nor the DOM can see that nothing else
+does.
+
+After a GC, the collector marks what is alive: whatever the engine's roots
+reach, leaving out the roots of node wrappers; every node in the document;
+and the listeners of targets that aren't nodes. A node that is alive keeps
+its wrapper, its listeners and the rest of its tree alive, and reaching a
+node's wrapper keeps the node alive. The nodes with listeners that are left
+are only held by garbage. Tarjan's algorithm splits them, with the JS
+objects between them, into strongly connected components, and the cycles
+among those are broken by dropping their nodes' listeners, and the
+wrappers of every node that isn't alive are unrooted, after which the GC
+can take the rest.
+
+A busy page can GC often, so cycles are collected at most once every
+`MIN_INTERVAL_NS`.
+*/
+
+use dom::bindings::rooting::NodeWrappers;
+use dom::document::Document;
+use dom::event_target::NodeTarget;
+use dom::node::{Node, NodeTree};
+use dom::window::Window;
+use content::content_task::{Content, CollectCycles};
+use js::{JSGC_END, JSTRACE_OBJECT};
+use js::jsapi::{JSContext, JSObject, JSRuntime, JSTracer, JSGCStatus, JSGCTraceKind};
+use js::jsapi::bindgen::{JS_SetGCCallback, JS_GetRuntime, JS_TracerInit, JS_TraceRuntime,
+                         JS_TraceChildren};
+use js::glue::bindgen::{RUST_JSVAL_IS_OBJECT, RUST_JSVAL_TO_OBJECT};
+use core::libc::types::os::arch::c95::size_t;
+use libc::{c_uint, c_void};
+use std::map::HashMap;
+use std::time::precise_time_ns;
+use util::tree;
+
+/// The least time, in ns, between two collections.
+pub const MIN_INTERVAL_NS: u64 = 1000000000;
+
+// Not yet visited by `strongly_connected_components`
+const UNVISITED: uint = uint::max_value;
+
+/**
+The strongly connected components of a graph, given as the vertices each
+vertex has edges to, by Tarjan's algorithm. Components come out in reverse
+topological order. The recursion is kept on a stack of its own, as chains
+of nodes and objects can be long.
+*/
+pub fn strongly_connected_components(edges: &[~[uint]]) -> ~[~[uint]] {
+    let n = edges.len();
+    let mut index = vec::from_elem(n, UNVISITED);
+    let mut lowlink = vec::from_elem(n, 0);
+    let mut on_stack = vec::from_elem(n, false);
+    let mut stack = ~[];
+    let mut components = ~[];
+    let mut next_index = 0;
+
+    for uint::range(0, n) |root| {
+        if index[root] != UNVISITED {
+            loop;
+        }
+        // Each vertex being visited, and the next of its edges to follow
+        let mut calls = ~[(root, 0)];
+        index[root] = next_index;
+        lowlink[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while !calls.is_empty() {
+            let (v, i) = calls.last();
+            if i < edges[v].len() {
+                calls[calls.len() - 1] = (v, i + 1);
+                let w = edges[v][i];
+                if index[w] == UNVISITED {
+                    index[w] = next_index;
+                    lowlink[w] = next_index;
+                    next_index += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    calls.push((w, 0));
+                } else if on_stack[w] {
+                    lowlink[v] = uint::min(lowlink[v], index[w]);
+                }
+            } else {
+                calls.pop();
+                if !calls.is_empty() {
+                    let (caller, _) = calls.last();
+                    lowlink[caller] = uint::min(lowlink[caller], lowlink[v]);
+                }
+                if lowlink[v] == index[v] {
+                    let mut component = ~[];
+                    loop {
+                        let w = stack.pop();
+                        on_stack[w] = false;
+                        component.push(w);
+                        if w == v {
+                            break;
+                        }
+                    }
+                    components.push(move component);
+                }
+            }
+        }
+    }
+    move components
+}
+
+/// Whether a component is a cycle: more than one vertex, or one with an
+/// edge to itself.
+pub fn is_cycle(component: &[uint], edges: &[~[uint]]) -> bool {
+    component.len() > 1 || edges[component[0]].contains(&component[0])
+}
+
+// A tracer, followed by where the objects it's shown go, so that the
+// callback can get from one to the other
+struct Tracer {
+    trc: JSTracer,
+    found: *mut ~[*JSObject],
+}
+
+extern fn note_object(trc: *JSTracer, thingp: **c_void, kind: JSGCTraceKind) unsafe {
+    if kind == JSTRACE_OBJECT {
+        let tracer: *Tracer = cast::reinterpret_cast(&trc);
+        (*(*tracer).found).push(*thingp as *JSObject);
+    }
+}
+
+// The objects `f` shows a tracer
+unsafe fn traced_objects(rt: *JSRuntime, f: fn(*JSTracer)) -> ~[*JSObject] {
+    let mut found = ~[];
+    let tracer: *mut Tracer = cast::reinterpret_cast(
+        &libc::calloc(1 as size_t, sys::size_of::<Tracer>() as size_t));
+    assert tracer.is_not_null();
+    (*tracer).found = ptr::to_mut_unsafe_ptr(&mut found);
+    let trc: *JSTracer = cast::reinterpret_cast(&tracer);
+    JS_TracerInit(trc, rt, note_object);
+    f(trc);
+    libc::free(tracer as *c_void);
+    move found
+}
+
+// The objects `obj` refers to
+unsafe fn children(rt: *JSRuntime, obj: *JSObject) -> ~[*JSObject] {
+    traced_objects(rt, |trc| JS_TraceChildren(trc, obj as *c_void, JSTRACE_OBJECT))
+}
+
+fn node_key(node: Node) -> uint unsafe {
+    dom::cow::unwrap(node) as uint
+}
+
+// The objects a node's listeners are
+fn listener_objects(window: &Window, node: Node) -> ~[*JSObject] {
+    do window.event_listeners.callbacks(NodeTarget(node)).filter_map |callback| {
+        if RUST_JSVAL_IS_OBJECT(*callback) == 1 {
+            let obj = RUST_JSVAL_TO_OBJECT(*callback);
+            if obj.is_not_null() { Some(obj) } else { None }
+        } else {
+            None
+        }
+    }
+}
+
+// The nodes next to `node` in its tree, which it keeps alive
+fn tree_neighbours(node: Node) -> ~[Node] {
+    let mut neighbours = ~[];
+    for tree::parent(&NodeTree, &node).each |parent| {
+        neighbours.push(*parent);
+    }
+    for NodeTree.each_child(&node) |child| {
+        neighbours.push(*child);
+    }
+    move neighbours
+}
+
+// What's alive, as the keys of nodes and the addresses of objects
+struct Marks {
+    nodes: HashMap<uint, ()>,
+    objects: HashMap<uint, ()>,
+}
+
+unsafe fn mark_live(document: &Document, window: &Window, wrappers: &NodeWrappers,
+                    rt: *JSRuntime) -> Marks {
+    let marks = Marks { nodes: HashMap(), objects: HashMap() };
+    let mut nodes = ~[];
+    let mut objects = ~[];
+
+    for traced_objects(rt, |trc| JS_TraceRuntime(trc)).each |obj| {
+        if wrappers.node_for(*obj).is_none() {
+            objects.push(*obj);
+        }
+    }
+    do document.root.traverse_preorder |node| {
+        nodes.push(node);
+    }
+    for window.event_listeners.targets().each |target| {
+        match *target {
+            NodeTarget(_) => (),
+            _ => for window.event_listeners.callbacks(*target).each |callback| {
+                if RUST_JSVAL_IS_OBJECT(*callback) == 1 {
+                    objects.push(RUST_JSVAL_TO_OBJECT(*callback));
+                }
+            }
+        }
+    }
+
+    while !nodes.is_empty() || !objects.is_empty() {
+        if !nodes.is_empty() {
+            let node = nodes.pop();
+            if marks.nodes.insert(node_key(node), ()) {
+                for wrappers.find(node).each |wrapper| {
+                    objects.push(*wrapper);
+                }
+                objects.push_all(listener_objects(window, node));
+                nodes.push_all(tree_neighbours(node));
+            }
+        } else {
+            let obj = objects.pop();
+            if obj.is_not_null() && marks.objects.insert(obj as uint, ()) {
+                for wrappers.node_for(obj).each |node| {
+                    nodes.push(*node);
+                }
+                objects.push_all(children(rt, obj));
+            }
+        }
+    }
+    move marks
+}
+
+// A vertex of the garbage graph
+enum Vertex {
+    NodeVertex(Node),
+    ObjectVertex(*JSObject),
+}
+
+/**
+Finds the cycles between nodes and JS objects that nothing alive refers
+to, and breaks them by removing the listeners of their nodes, then
+unroots the wrappers of the nodes that aren't alive. Returns how many
+cycles were broken.
+*/
+pub fn detect_cycles(document: &Document, window: &Window, wrappers: &NodeWrappers,
+                     cx: *JSContext) -> uint unsafe {
+    let rt = JS_GetRuntime(cx);
+    let marks = mark_live(document, window, wrappers, rt);
+
+    // Everything the dead nodes with listeners reach, which is all dead too
+    let mut vertices = ~[];
+    let mut edges: ~[~[uint]] = ~[];
+    let node_vertices = HashMap();
+    let object_vertices = HashMap();
+    let mut pending = ~[];
+
+    fn vertex(v: Vertex, vertices: &mut ~[Vertex], edges: &mut ~[~[uint]],
+              node_vertices: HashMap<uint, uint>, object_vertices: HashMap<uint, uint>,
+              pending: &mut ~[uint]) -> uint {
+        let (map, key) = match v {
+            NodeVertex(node) => (node_vertices, node_key(node)),
+            ObjectVertex(obj) => (object_vertices, obj as uint)
+        };
+        match map.find(key) {
+            Some(i) => i,
+            None => {
+                let i = vertices.len();
+                vertices.push(v);
+                edges.push(~[]);
+                map.insert(key, i);
+                pending.push(i);
+                i
+            }
+        }
+    }
+
+    for window.event_listeners.targets().each |target| {
+        match *target {
+            NodeTarget(node) if !marks.nodes.contains_key(node_key(node)) => {
+                vertex(NodeVertex(node), &mut vertices, &mut edges, node_vertices,
+                       object_vertices, &mut pending);
+            }
+            _ => ()
+        }
+    }
+
+    while !pending.is_empty() {
+        let i = pending.pop();
+        let mut next = ~[];
+        match vertices[i] {
+            NodeVertex(node) => {
+                for wrappers.find(node).each |wrapper| {
+                    next.push(ObjectVertex(*wrapper));
+                }
+                for listener_objects(window, node).each |obj| {
+                    next.push(ObjectVertex(*obj));
+                }
+                for tree_neighbours(node).each |neighbour| {
+                    next.push(NodeVertex(*neighbour));
+                }
+            }
+            ObjectVertex(obj) => {
+                for wrappers.node_for(obj).each |node| {
+                    next.push(NodeVertex(*node));
+                }
+                for children(rt, obj).each |child| {
+                    if child.is_not_null() && !marks.objects.contains_key(*child as uint) {
+                        next.push(ObjectVertex(*child));
+                    }
+                }
+            }
+        }
+        for next.each |v| {
+            let j = vertex(*v, &mut vertices, &mut edges, node_vertices, object_vertices,
+                           &mut pending);
+            edges[i].push(j);
+        }
+    }
+
+    let mut broken = 0;
+    for strongly_connected_components(edges).each |component| {
+        if !is_cycle(*component, edges) {
+            loop;
+        }
+        let mut has_node = false;
+        for component.each |i| {
+            match vertices[*i] {
+                NodeVertex(node) => {
+                    window.event_listeners.remove_target(NodeTarget(node));
+                    has_node = true;
+                }
+                ObjectVertex(_) => ()
+            }
+        }
+        if has_node {
+            broken += 1;
+        }
+    }
+
+    let mut unrooted = 0;
+    for wrappers.nodes().each |node| {
+        if !marks.nodes.contains_key(node_key(*node)) {
+            wrappers.remove(*node);
+            unrooted += 1;
+        }
+    }
+    debug!("cycle collector: %u vertices, %u cycles broken, %u wrappers unrooted",
+           vertices.len(), broken, unrooted);
+    broken
+}
+
+/// Whether a collection may start at `now`, given when the last one
+/// finished, both in ns.
+pub pure fn interval_elapsed(last: Option<u64>, now: u64) -> bool {
+    match last {
+        Some(last) => now < last || now - last >= MIN_INTERVAL_NS,
+        None => true
+    }
+}
+
+// Asks for cycles to be collected after a GC, unless they were collected
+// too recently. Nothing can be changed while the GC is running, so the
+// content task does it when it gets round to it, once however many GCs
+// have happened since.
+extern fn gc_callback(_cx: *JSContext, status: JSGCStatus, _reason: c_uint,
+                      data: *c_void) unsafe {
+    let content: *Content = cast::reinterpret_cast(&data);
+    if status == JSGC_END && !(*content).cycle_collection_pending &&
+            interval_elapsed((*content).last_cycle_collection, precise_time_ns()) {
+        (*content).cycle_collection_pending = true;
+        (*content).control_chan.send(CollectCycles);
+    }
+}
+
+/// Collects cycles on `content`, the content task of `cx`, after GCs.
+pub fn init(cx: *JSContext, content: *Content) {
+    JS_SetGCCallback(cx, gc_callback, content as *c_void);
+}
+
+#[cfg(test)]
+mod cycle_collector_tests {
+    fn sorted(components: ~[~[uint]]) -> ~[~[uint]] {
+        let mut result = components.map(|c| {
+            let mut c = copy *c;
+            sort::quick_sort3(c);
+            move c
+        });
+        sort::quick_sort3(result);
+        move result
+    }
+
+    #[test]
+    fn test_strongly_connected_components() {
+        // 0 -> 1 -> 2 -> 0 is a cycle, 3 leads into it, 4 loops on itself
+        let edges = ~[~[1], ~[2], ~[0, 3], ~[4], ~[4], ~[]];
+        let components = strongly_connected_components(edges);
+        assert sorted(copy components) == ~[~[0, 1, 2], ~[3], ~[4], ~[5]];
+
+        let cycles = components.filter(|c| is_cycle(*c, edges));
+        assert sorted(move cycles) == ~[~[0, 1, 2], ~[4]];
+    }
+
+    #[test]
+    fn test_components_in_reverse_topological_order() {
+        let edges = ~[~[1], ~[0, 2], ~[3], ~[2]];
+        let components = strongly_connected_components(edges);
+        assert components.len() == 2;
+        assert sorted(~[copy components[0]]) == ~[~[2, 3]];
+        assert sorted(~[copy components[1]]) == ~[~[0, 1]];
+    }
+
+    #[test]
+    fn test_interval_elapsed() {
+        assert interval_elapsed(None, 0);
+        assert !interval_elapsed(Some(10), 10 + MIN_INTERVAL_NS - 1);
+        assert interval_elapsed(Some(10), 10 + MIN_INTERVAL_NS);
+        // the clock can't be trusted to only go forwards
+        assert interval_elapsed(Some(10), 5);
+    }
+
+    #[test]
+    fn test_long_chain() {
+        // Deeper than the recursion would go on a task's stack
+        let n = 100000;
+        let edges = vec::from_fn(n, |i| ~[(i + 1) % n]);
+        let components = strongly_connected_components(edges);
+        assert components.len() == 1;
+        assert components[0].len() == n;
+    }
+}
@@ -0,0 +1,60 @@
+/*!
+How much memory each part of servo is using, for
+`__servoDebug.memoryReport()`. Each subsystem counts its own with a
+`heap_size()`: the DOM its node scope, layout its layout data and flows,
+the image cache task its decoded and encoded images, and the font cache its
+font and glyphs. The JS heap is what the GC says it has allocated.
+
+Sizes are in bytes, and are what the structures hold, not what malloc has
+given them, so they come out a little low.
+*/
+
+use content::content_task::Content;
+use dom::document::Document;
+use layout::layout_task;
+use resource::image_cache_task::ImageCacheTaskClient;
+use js::JSGC_BYTES;
+use js::jsapi::JSContext;
+use js::jsapi::bindgen::{JS_GetGCParameter, JS_GetRuntime};
+
+pub struct MemoryReport {
+    dom_nodes: uint,
+    // Each node's layout data, and the flow tree with its boxes
+    layout_data: uint,
+    style_data: uint,
+    js_heap: uint,
+    // Decoded images
+    image_cache: uint,
+    // Fetched images, kept encoded. There is no other network cache
+    network_cache: uint,
+    font_cache: uint,
+}
+
+/// The bytes the GC has allocated for `cx`'s runtime.
+pub fn js_heap_size(cx: *JSContext) -> uint {
+    JS_GetGCParameter(JS_GetRuntime(cx), JSGC_BYTES) as uint
+}
+
+/**
+Asks each subsystem what it's using. Layout has to finish laying out
+`document` to answer, and the image cache task answers from its own task,
+so this waits on both.
+*/
+pub fn collect_report(document: &Document, content: &Content) -> MemoryReport {
+    let (layout_data, style_data, font_cache) = match content.query_layout(layout_task::HeapSize) {
+        Ok(layout_task::LayoutHeapSize(layout_data, style_data, font_cache)) => {
+            (layout_data, style_data, font_cache)
+        }
+        _ => (0, 0, 0)
+    };
+    let (image_cache, network_cache) = content.image_cache_task.heap_size();
+    MemoryReport {
+        dom_nodes: document.scope.heap_size(),
+        layout_data: layout_data,
+        style_data: style_data,
+        js_heap: js_heap_size(content.cx.ptr),
+        image_cache: image_cache,
+        network_cache: network_cache,
+        font_cache: font_cache
+    }
+}
@@ -0,0 +1,144 @@
+/*!
+Keeps the renderer under the `--memory-limit`. Every 500 ms the watchdog
+checks the current resident set size; when it's over the limit it asks
+for a garbage collection, waits a second, and if that didn't help tells
+the engine that the renderer is out of memory. The engine then tells the
+content task to exit, which stops any script it's running from the
+runtime's operation callback, and starts a new one.
+*/
+
+use engine::{EngineTask, CollectGarbageMsg, OutOfMemoryMsg};
+use comm::{Port, Chan};
+use task::spawn_listener;
+use std::timer::recv_timeout;
+use std::uv_global_loop;
+
+const POLL_INTERVAL_MS: uint = 500;
+const GC_GRACE_MS: uint = 1000;
+
+pub enum Msg {
+    ExitMsg
+}
+
+pub type MemoryWatchdog = Chan<Msg>;
+
+/// Starts watching. `limit` is in bytes.
+pub fn MemoryWatchdog(limit: uint, engine: EngineTask) -> MemoryWatchdog {
+    do spawn_listener |port: Port<Msg>| {
+        loop {
+            if wait(&port, POLL_INTERVAL_MS) {
+                break;
+            }
+            if resident_set_size() <= limit {
+                loop;
+            }
+
+            debug!("memory watchdog: over the limit, collecting garbage");
+            engine.send(CollectGarbageMsg);
+            if wait(&port, GC_GRACE_MS) {
+                break;
+            }
+            let rss = resident_set_size();
+            if rss > limit {
+                warn!("memory watchdog: %u bytes resident, over the %u byte limit", rss, limit);
+                engine.send(OutOfMemoryMsg);
+                // Give the new renderer a chance before checking again
+                if wait(&port, GC_GRACE_MS) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Waits `ms` milliseconds, returning true if the watchdog should exit
+fn wait(port: &Port<Msg>, ms: uint) -> bool {
+    match recv_timeout(uv_global_loop::get(), ms, port) {
+        Some(ExitMsg) => true,
+        None => false
+    }
+}
+
+/// The current resident set size of this process in bytes.
+#[cfg(target_os = "linux")]
+pub fn resident_set_size() -> uint {
+    // getrusage only reports the peak, which won't go down after a
+    // collection, so use the current size from /proc
+    match io::read_whole_file_str(&Path("/proc/self/statm")) {
+        Ok(statm) => {
+            let fields = str::split_char(statm, ' ');
+            if fields.len() < 2 {
+                return 0;
+            }
+            let pages = uint::from_str(fields[1]).get_default(0);
+            pages * (unsafe { sysconf(_SC_PAGESIZE) } as uint)
+        }
+        Err(_) => 0
+    }
+}
+
+/// The current resident set size of this process in bytes.
+#[cfg(target_os = "macos")]
+pub fn resident_set_size() -> uint {
+    // getrusage only reports the peak here too, so ask the kernel for the
+    // task's current size
+    let info = mach_task_basic_info {
+        virtual_size: 0,
+        resident_size: 0,
+        resident_size_max: 0,
+        user_time: time_value { seconds: 0, microseconds: 0 },
+        system_time: time_value { seconds: 0, microseconds: 0 },
+        policy: 0,
+        suspend_count: 0,
+    };
+    let count = MACH_TASK_BASIC_INFO_COUNT;
+    unsafe {
+        if task_info(task_self_trap(), MACH_TASK_BASIC_INFO, ptr::to_unsafe_ptr(&info),
+                     ptr::to_unsafe_ptr(&count)) != KERN_SUCCESS {
+            return 0;
+        }
+    }
+    info.resident_size as uint
+}
+
+#[cfg(target_os = "linux")]
+const _SC_PAGESIZE: libc::c_int = 30;
+
+#[cfg(target_os = "linux")]
+extern {
+    fn sysconf(name: libc::c_int) -> libc::c_long;
+}
+
+#[cfg(target_os = "macos")]
+const MACH_TASK_BASIC_INFO: libc::c_int = 20;
+
+// The size of mach_task_basic_info, in natural_t's
+#[cfg(target_os = "macos")]
+const MACH_TASK_BASIC_INFO_COUNT: u32 = 12;
+
+#[cfg(target_os = "macos")]
+const KERN_SUCCESS: libc::c_int = 0;
+
+#[cfg(target_os = "macos")]
+struct time_value {
+    seconds: i32,
+    microseconds: i32,
+}
+
+#[cfg(target_os = "macos")]
+struct mach_task_basic_info {
+    virtual_size: u64,
+    resident_size: u64,
+    resident_size_max: u64,
+    user_time: time_value,
+    system_time: time_value,
+    policy: i32,
+    suspend_count: i32,
+}
+
+#[cfg(target_os = "macos")]
+extern {
+    fn task_self_trap() -> u32;
+    fn task_info(task: u32, flavor: libc::c_int, info: *mach_task_basic_info,
+                 count: *u32) -> libc::c_int;
+}
@@ -3,22 +3,108 @@
 
 pub type Opts = {
     urls: ~[~str],
-    render_mode: RenderMode
+    render_mode: RenderMode,
+    // How far beyond the viewport `loading="lazy"` images start loading,
+    // as a multiple of the viewport size: `--lazy-image-margin`, or
+    // DEFAULT_LAZY_IMAGE_MARGIN
+    lazy_image_margin: float,
+    // How to answer pages asking for permissions (geolocation, notifications)
+    permission_prompt: PermissionPrompt,
+    // The most memory, in MiB, the renderer may use before it's restarted
+    memory_limit: Option<uint>,
+    // The percentage of a CPU scripts may use before they're slowed down
+    cpu_limit: Option<uint>,
+    // False with --disable-javascript: pages are parsed and styled, but
+    // their scripts never run
+    javascript_enabled: bool,
+    // Refuse cookies set for other sites than the page's, unless they're
+    // SameSite=Strict or SameSite=Lax
+    block_third_party_cookies: bool,
+    // The DNS over HTTPS server to resolve hosts with, instead of the OS.
+    // It can be a plain http server on this machine, like a local DoH proxy
+    dns_over_https: Option<~str>,
+    // The HTTP proxy to load http and https URLs through
+    proxy: Option<~str>,
+    // Comma-separated hosts to load without the proxy
+    no_proxy: ~str,
+    // A file of SPKI hashes to pin hosts to
+    spki_hash_list: Option<~str>,
+    // A Unix socket to listen on for another process to drive servo through
+    ipc_socket: Option<~str>,
+    // The port to serve the Chrome DevTools Protocol on, for debuggers
+    devtools_port: Option<uint>,
+    // Lays out `display: masonry`, which is still experimental
+    enable_masonry: bool,
+    // Defines a global gc() function, for tests
+    expose_gc: bool,
+    // Defines element.computedAccessibleName(), and logs the accessibility
+    // tree when there's no platform API to give it to, for debugging
+    enable_accessibility_debug: bool,
+    // Defines notification.debugClick(), since nothing can click a
+    // notification that's only logged
+    enable_notification_debug: bool,
+    // Draws pages in the high contrast palette, whatever the platform says
+    forced_colors: bool,
+    // Where sites' IndexedDB databases and caches are kept. They go in the
+    // user's data directory if it isn't given
+    storage_dir: Option<~str>
 };
 
+// Lazy images load once they're within a viewport grown by half, a quarter
+// of its size on each side
+pub const DEFAULT_LAZY_IMAGE_MARGIN: float = 1.5;
+
 pub enum RenderMode {
     Screen,
-    Png(~str)
+    Png(~str),
+    // Prints the page to a PDF file, then exits
+    Pdf(~str)
+}
+
+/// There's no UI to ask the user for permissions yet, so they're all answered
+/// the same way: denied, unless `--permissions grant` says otherwise.
+pub enum PermissionPrompt {
+    PromptGrant,
+    PromptDeny
+}
+
+impl PermissionPrompt {
+    pure fn grants() -> bool {
+        match self {
+            PromptGrant => true,
+            PromptDeny => false
+        }
+    }
 }
 
 #[allow(non_implicitly_copyable_typarams)]
 pub fn from_cmdline_args(args: &[~str]) -> Opts {
     use std::getopts;
+    use devtools::websocket::is_loopback_host;
 
     let args = args.tail();
 
     let opts = ~[
-        getopts::optopt(~"o")
+        getopts::optopt(~"o"),
+        getopts::optopt(~"print-to-pdf"),
+        getopts::optopt(~"lazy-image-margin"),
+        getopts::optopt(~"permissions"),
+        getopts::optopt(~"memory-limit"),
+        getopts::optopt(~"cpu-limit"),
+        getopts::optflag(~"disable-javascript"),
+        getopts::optflag(~"block-third-party-cookies"),
+        getopts::optopt(~"dns-over-https"),
+        getopts::optopt(~"proxy"),
+        getopts::optopt(~"no-proxy"),
+        getopts::optopt(~"spki-hash-list"),
+        getopts::optopt(~"ipc-socket"),
+        getopts::optopt(~"devtools-port"),
+        getopts::optflag(~"enable-masonry"),
+        getopts::optflag(~"expose-gc"),
+        getopts::optflag(~"enable-accessibility-debug"),
+        getopts::optflag(~"enable-notification-debug"),
+        getopts::optflag(~"forced-colors"),
+        getopts::optopt(~"storage-dir")
     ];
 
     let opt_match = match getopts::getopts(args, opts) {
@@ -32,13 +118,113 @@ pub fn from_cmdline_args(args: &[~str]) -> Opts {
         copy opt_match.free
     };
 
-    let render_mode = match getopts::opt_maybe_str(move opt_match, ~"o") {
-      Some(move output_file) => { Png(move output_file) }
-      None => { Screen }
+    let render_mode = match (getopts::opt_maybe_str(copy opt_match, ~"print-to-pdf"),
+                             getopts::opt_maybe_str(copy opt_match, ~"o")) {
+      (Some(move pdf_file), _) => { Pdf(move pdf_file) }
+      (None, Some(move output_file)) => { Png(move output_file) }
+      (None, None) => { Screen }
+    };
+
+    let lazy_image_margin = match getopts::opt_maybe_str(copy opt_match, ~"lazy-image-margin") {
+      Some(move margin_str) => match float::from_str(margin_str) {
+        Some(margin) if margin >= 1.0 => margin,
+        _ => fail ~"--lazy-image-margin must be a number no smaller than 1"
+      },
+      None => DEFAULT_LAZY_IMAGE_MARGIN
+    };
+
+    let memory_limit = match getopts::opt_maybe_str(copy opt_match, ~"memory-limit") {
+      Some(move limit_str) => match uint::from_str(limit_str) {
+        Some(limit) if limit > 0 => Some(limit),
+        _ => fail ~"--memory-limit must be a positive number of MiB"
+      },
+      None => None
+    };
+
+    let cpu_limit = match getopts::opt_maybe_str(copy opt_match, ~"cpu-limit") {
+      Some(move limit_str) => match uint::from_str(limit_str) {
+        Some(limit) if limit > 0 && limit <= 100 => Some(limit),
+        _ => fail ~"--cpu-limit must be a percentage between 1 and 100"
+      },
+      None => None
+    };
+
+    let javascript_enabled = !getopts::opt_present(copy opt_match, ~"disable-javascript");
+
+    let block_third_party_cookies =
+        getopts::opt_present(copy opt_match, ~"block-third-party-cookies");
+
+    let dns_over_https = match getopts::opt_maybe_str(copy opt_match, ~"dns-over-https") {
+      Some(move server) => match std::net::url::from_str(server) {
+        Ok(url) if url.scheme == ~"https" => Some(move server),
+        Ok(url) if url.scheme == ~"http" && is_loopback_host(url.host) => Some(move server),
+        _ => fail ~"--dns-over-https must be an https URL, or an http one on this machine"
+      },
+      None => None
+    };
+
+    let proxy = match getopts::opt_maybe_str(copy opt_match, ~"proxy") {
+      Some(move proxy) => match std::net::url::from_str(proxy) {
+        Ok(url) if url.scheme == ~"http" && !url.host.is_empty() => Some(move proxy),
+        _ => fail ~"--proxy must be an http URL, like http://proxy.example:3128"
+      },
+      None => None
+    };
+
+    let no_proxy = getopts::opt_maybe_str(copy opt_match, ~"no-proxy").get_default(~"");
+
+    let spki_hash_list = getopts::opt_maybe_str(copy opt_match, ~"spki-hash-list");
+
+    let ipc_socket = getopts::opt_maybe_str(copy opt_match, ~"ipc-socket");
+
+    let devtools_port = match getopts::opt_maybe_str(copy opt_match, ~"devtools-port") {
+      Some(move port_str) => match uint::from_str(port_str) {
+        Some(port) if port > 0 && port < 65536 => Some(port),
+        _ => fail ~"--devtools-port must be a port number, like 9222"
+      },
+      None => None
+    };
+
+    let enable_masonry = getopts::opt_present(copy opt_match, ~"enable-masonry");
+
+    let expose_gc = getopts::opt_present(copy opt_match, ~"expose-gc");
+
+    let enable_accessibility_debug =
+        getopts::opt_present(copy opt_match, ~"enable-accessibility-debug");
+
+    let enable_notification_debug =
+        getopts::opt_present(copy opt_match, ~"enable-notification-debug");
+
+    let forced_colors = getopts::opt_present(copy opt_match, ~"forced-colors");
+
+    let storage_dir = getopts::opt_maybe_str(copy opt_match, ~"storage-dir");
+
+    let permission_prompt = match getopts::opt_maybe_str(move opt_match, ~"permissions") {
+      Some(~"grant") => PromptGrant,
+      Some(~"deny") | None => PromptDeny,
+      Some(_) => fail ~"--permissions must be `grant` or `deny`"
     };
 
     {
         urls: move urls,
-        render_mode: move render_mode
+        render_mode: move render_mode,
+        lazy_image_margin: lazy_image_margin,
+        permission_prompt: permission_prompt,
+        memory_limit: memory_limit,
+        cpu_limit: cpu_limit,
+        javascript_enabled: javascript_enabled,
+        block_third_party_cookies: block_third_party_cookies,
+        dns_over_https: move dns_over_https,
+        proxy: move proxy,
+        no_proxy: move no_proxy,
+        spki_hash_list: move spki_hash_list,
+        ipc_socket: move ipc_socket,
+        devtools_port: devtools_port,
+        enable_masonry: enable_masonry,
+        expose_gc: expose_gc,
+        enable_accessibility_debug: enable_accessibility_debug,
+        enable_notification_debug: enable_notification_debug,
+        forced_colors: forced_colors,
+        storage_dir: move storage_dir
     }
 }
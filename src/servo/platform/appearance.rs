@@ -0,0 +1,47 @@
+/*!
+The platform's appearance: whether the user prefers light or dark, or a
+high contrast palette, and which scheme the window's native parts, like
+its scroll bars, are drawn in.
+*/
+
+use css::values::color_scheme::{ColorScheme, Light};
+
+/// The scheme the user has picked for their desktop.
+#[cfg(target_os = "macos")]
+pub fn preferred_color_scheme() -> ColorScheme {
+    //TODO: read NSApp.effectiveAppearance
+    Light
+}
+
+#[cfg(target_os = "linux")]
+pub fn preferred_color_scheme() -> ColorScheme {
+    //TODO: read gtk-application-prefer-dark-theme
+    Light
+}
+
+/// Whether the user has turned on a high contrast mode, whose palette
+/// pages are drawn in.
+#[cfg(target_os = "macos")]
+pub fn forced_colors_active() -> bool {
+    //TODO: read accessibilityDisplayShouldIncreaseContrast
+    false
+}
+
+#[cfg(target_os = "linux")]
+pub fn forced_colors_active() -> bool {
+    //TODO: check for the HighContrast GTK theme
+    false
+}
+
+/// Draws the window's native controls in `scheme`, the page's.
+#[cfg(target_os = "macos")]
+pub fn set_native_color_scheme(scheme: ColorScheme) {
+    //TODO: set the window's NSAppearance to NSAppearanceNameDarkAqua or Aqua
+    debug!("appearance: native controls in %?", scheme);
+}
+
+#[cfg(target_os = "linux")]
+pub fn set_native_color_scheme(scheme: ColorScheme) {
+    //TODO: set gtk-application-prefer-dark-theme on the window's settings
+    debug!("appearance: native controls in %?", scheme);
+}
@@ -4,7 +4,9 @@ use azure::azure_hl::DrawTarget;
 use cairo::cairo_hl::ImageSurface;
 use cairo::cairo_surface_t;
 use core::util::replace;
-use dom::event::{Event, ResizeEvent};
+use dom::event::{Event, ResizeEvent, ScrollEvent, KeyEvent, PointerInputEvent};
+use dom::events::pointer_event::{PointerCoalescer, PointerInput, PointerDown, PointerMove,
+                                 PointerUp, mouse_sample, button_bit};
 use dvec::DVec;
 use geom::matrix::{Matrix4, identity};
 use geom::point::Point2D;
@@ -16,6 +18,7 @@ use pipes::Chan;
 use resize_rate_limiter::ResizeRateLimiter;
 use std::cell::Cell;
 use std::cmp::FuzzyEq;
+use std::time::precise_time_ns;
 use task::TaskBuilder;
 use vec::push;
 
@@ -109,6 +112,14 @@ fn mainloop(mode: Mode, po: comm::Port<Msg>, dom_event_chan: pipes::SharedChan<E
                                       identity(0.0f32));
 
     let done = @mut false;
+    let scroll_chan = dom_event_chan.clone();
+    let key_chan = dom_event_chan.clone();
+    let pointer_chan = dom_event_chan.clone();
+    let pointer_queue = @PointerCoalescer();
+    // When queued pointer events were last sent, in ns
+    let last_pointer_flush = @mut 0u64;
+    // The mouse buttons held down, as in MouseEvent.buttons
+    let mouse_buttons = @mut 0u16;
     let resize_rate_limiter = @ResizeRateLimiter(move dom_event_chan);
     let check_for_messages = fn@() {
 
@@ -195,8 +206,26 @@ fn mainloop(mode: Mode, po: comm::Port<Msg>, dom_event_chan: pipes::SharedChan<E
         *size = Size2D(window_width as f32, window_height as f32);
     };
 
+    // Sends the pointer events queued since the last frame
+    let flush_pointer_events: fn@() = || {
+        for pointer_queue.take().each |input| {
+            pointer_chan.send(PointerInputEvent(copy *input));
+        }
+        *last_pointer_flush = precise_time_ns();
+    };
+
+    // Sends queued moves once they've waited a frame, whether or not
+    // anything is drawn
+    let flush_pointer_events_if_due: fn@() = || {
+        if !pointer_queue.is_empty() &&
+                precise_time_ns() - *last_pointer_flush >= POINTER_FLUSH_INTERVAL_NS {
+            flush_pointer_events();
+        }
+    };
+
     let composite: fn@() = || {
         //#debug("osmain: drawing to screen");
+        flush_pointer_events();
 
         do util::time::time(~"compositing") {
             adjust_for_window_resizing();
@@ -216,6 +245,47 @@ fn mainloop(mode: Mode, po: comm::Port<Msg>, dom_event_chan: pipes::SharedChan<E
                 //composite();
             }
 
+            do glut::mouse_func |button, state, x, y| {
+                let point = Point2D(x as int, y as int);
+                // GLUT reports wheel notches as presses of buttons 3 to 6
+                match scroll_delta(button) {
+                    Some(delta) => {
+                        if state == glut::MOUSE_DOWN {
+                            scroll_chan.send(ScrollEvent(point, delta))
+                        }
+                    }
+                    None => {
+                        let button = dom_button(button);
+                        let phase = if state == glut::MOUSE_DOWN {
+                            *mouse_buttons |= button_bit(button);
+                            PointerDown
+                        } else {
+                            *mouse_buttons &= !button_bit(button);
+                            PointerUp
+                        };
+                        // Presses and releases aren't held back for the next frame
+                        pointer_queue.push(PointerInput(phase,
+                                                        mouse_sample(point, button,
+                                                                     *mouse_buttons)));
+                        flush_pointer_events();
+                    }
+                }
+            }
+
+            do glut::motion_func |x, y| {
+                let sample = mouse_sample(Point2D(x as int, y as int), -1, *mouse_buttons);
+                pointer_queue.push(PointerInput(PointerMove, sample));
+            }
+
+            do glut::passive_motion_func |x, y| {
+                let sample = mouse_sample(Point2D(x as int, y as int), -1, *mouse_buttons);
+                pointer_queue.push(PointerInput(PointerMove, sample));
+            }
+
+            do glut::keyboard_func |key, _x, _y| {
+                key_chan.send(KeyEvent(key as char))
+            }
+
             do glut::display_func() {
                 //debug!("osmain: display func");
                 check_for_messages();
@@ -225,6 +295,7 @@ fn mainloop(mode: Mode, po: comm::Port<Msg>, dom_event_chan: pipes::SharedChan<E
             while !*done {
                 //#debug("osmain: running GLUT check loop");
                 glut::check_loop();
+                flush_pointer_events_if_due();
             }
         }
         ShareWindow(share_context) => {
@@ -258,6 +329,32 @@ struct SurfaceSet {
     mut back: Surface,
 }
 
+// How far one wheel notch scrolls, in px
+const SCROLL_STEP: int = 40;
+
+// How long moves are held back to be coalesced when nothing is drawn: a
+// frame at 60Hz
+const POINTER_FLUSH_INTERVAL_NS: u64 = 16666667;
+
+// The scroll delta for a press of the given GLUT mouse button, if it's a
+// wheel button
+fn scroll_delta(button: libc::c_int) -> Option<Point2D<int>> {
+    match button {
+        3 => Some(Point2D(0, -SCROLL_STEP)),
+        4 => Some(Point2D(0, SCROLL_STEP)),
+        5 => Some(Point2D(-SCROLL_STEP, 0)),
+        6 => Some(Point2D(SCROLL_STEP, 0)),
+        _ => None
+    }
+}
+
+// The MouseEvent.button number of a GLUT mouse button. GLUT numbers the
+// left, middle and right buttons as the DOM does, and the back and forward
+// buttons after the wheel's
+fn dom_button(button: libc::c_int) -> i16 {
+    if button < 3 { button as i16 } else { (button - 4) as i16 }
+}
+
 fn lend_surface(surfaces: &SurfaceSet, receiver: pipes::Chan<LayerBufferSet>) {
     // We are in a position to lend out the surface?
     assert surfaces.front.have;
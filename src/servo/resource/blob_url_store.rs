@@ -0,0 +1,101 @@
+/*!
+The Blobs behind the `blob:` URLs made by `URL.createObjectURL`. The resource
+manager owns the store and serves loads of those URLs from it.
+*/
+
+use core::send_map::linear::LinearMap;
+use comm::Chan;
+use std::net::url::Url;
+use resource_task::{ProgressMsg, Payload, ContentType, Done};
+use dom::blob::Blob;
+
+pub struct BlobURLStore {
+    // Keyed by the UUID at the end of the URL
+    priv blobs: LinearMap<~str, Blob>,
+}
+
+pub fn BlobURLStore() -> BlobURLStore {
+    BlobURLStore { blobs: LinearMap() }
+}
+
+impl BlobURLStore {
+    fn insert(&mut self, url: &str, blob: Blob) {
+        self.blobs.insert(blob_id(url), move blob);
+    }
+
+    fn remove(&mut self, url: &str) {
+        self.blobs.remove(&blob_id(url));
+    }
+
+    fn find(&self, url: &str) -> Option<Blob> {
+        match self.blobs.find_ref(&blob_id(url)) {
+            Some(blob) => Some(blob.clone()),
+            None => None
+        }
+    }
+
+    /// Sends the Blob for `url` to `progress_chan`, or an error if it has
+    /// been revoked.
+    fn load(&self, url: &Url, progress_chan: Chan<ProgressMsg>) {
+        // The URL parser doesn't know about `blob:` URLs, so the UUID ends
+        // up at the end of the path
+        match self.find(url.path) {
+            Some(blob) => {
+                if !blob.content_type.is_empty() {
+                    progress_chan.send(ContentType(copy blob.content_type));
+                }
+                progress_chan.send(Payload(do blob.with_bytes |bytes| {
+                    vec::from_slice(bytes)
+                }));
+                progress_chan.send(Done(Ok(())));
+            }
+            None => progress_chan.send(Done(Err(())))
+        }
+    }
+}
+
+/// A fresh `blob:` URL for a page with the given origin.
+pub fn new_blob_url(origin: &str) -> ~str {
+    fmt!("blob:%s/%s", origin, uuid())
+}
+
+// A random (version 4) UUID
+fn uuid() -> ~str {
+    let rng = rand::Rng();
+    let mut bytes = rng.gen_bytes(16);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = str::concat(bytes.map(|b| fmt!("%02x", *b as uint)));
+    fmt!("%s-%s-%s-%s-%s", hex.slice(0, 8), hex.slice(8, 12), hex.slice(12, 16),
+         hex.slice(16, 20), hex.slice(20, 32))
+}
+
+fn blob_id(url: &str) -> ~str {
+    match str::rfind_char(url, '/') {
+        Some(i) => str::slice(url, i + 1, url.len()),
+        None => str::from_slice(url)
+    }
+}
+
+#[test]
+fn test_new_blob_url() {
+    let url = new_blob_url("http://example.com");
+    assert url.starts_with("blob:http://example.com/");
+    let id = blob_id(url);
+    assert id.len() == 36;
+    assert id.char_at(14) == '4';
+    assert new_blob_url("http://example.com") != url;
+}
+
+#[test]
+fn test_insert_and_remove() {
+    let mut store = BlobURLStore();
+    let url = new_blob_url("null");
+    store.insert(url, Blob(~[1u8, 2], "text/plain"));
+    match store.find(url) {
+        Some(blob) => assert blob.size() == 2,
+        None => fail
+    }
+    store.remove(url);
+    assert store.find(url).is_none();
+}
@@ -0,0 +1,186 @@
+/*!
+Buffers for response bodies, reused rather than allocated for each
+response. A page loading many small resources at once would otherwise
+allocate, grow and free a vector for every one of them.
+
+The pool keeps buffers in buckets of a few common sizes. A finished body
+is copied into a buffer from the smallest bucket it fits, and whoever is
+done with the body gives the buffer back. Bodies bigger than the biggest
+bucket get a buffer of their own, which isn't kept. Handles to the pool
+can be sent to other tasks, so that buffers can be given back wherever
+the body ends up.
+*/
+
+use std::arc::RWARC;
+use std::cell::Cell;
+
+// The sizes of the buckets, smallest first
+pub const BUCKET_SIZES: &static/[uint] = &[4 * 1024, 16 * 1024, 64 * 1024, 256 * 1024,
+                                           1024 * 1024];
+
+// How many free buffers each bucket keeps
+pub const MAX_FREE_PER_BUCKET: uint = 8;
+
+struct PoolState {
+    // The free buffers of each size, all empty
+    buckets: ~[~[~[u8]]],
+    // How many buffers had to be allocated, and how many were reused
+    allocated: uint,
+    reused: uint,
+}
+
+pub struct BufferPool {
+    priv state: RWARC<PoolState>,
+}
+
+pub fn BufferPool() -> BufferPool {
+    BufferPool {
+        state: RWARC(PoolState {
+            buckets: BUCKET_SIZES.map(|_| ~[]),
+            allocated: 0,
+            reused: 0
+        })
+    }
+}
+
+// The smallest bucket whose buffers hold `len` bytes
+fn bucket_for(len: uint) -> Option<uint> {
+    BUCKET_SIZES.position(|size| *size >= len)
+}
+
+fn with_capacity(len: uint) -> ~[u8] {
+    let mut buf = ~[];
+    vec::reserve(&mut buf, len);
+    move buf
+}
+
+impl BufferPool {
+    /// Another handle to the same pool.
+    fn clone(&self) -> BufferPool {
+        BufferPool { state: self.state.clone() }
+    }
+
+    /// An empty buffer that holds `len` bytes without growing.
+    fn acquire(&self, len: uint) -> ~[u8] {
+        match bucket_for(len) {
+            None => with_capacity(len),
+            Some(i) => do self.state.write |state| {
+                if state.buckets[i].is_empty() {
+                    state.allocated += 1;
+                    with_capacity(BUCKET_SIZES[i])
+                } else {
+                    state.reused += 1;
+                    state.buckets[i].pop()
+                }
+            }
+        }
+    }
+
+    /// A buffer from the pool with `chunks` copied into it, one after
+    /// the other, as a body is once it has all arrived.
+    fn copy_chunks(&self, chunks: &[~[u8]]) -> ~[u8] {
+        let len = chunks.foldl(0, |len, chunk| *len + chunk.len());
+        let mut buf = self.acquire(len);
+        for chunks.each |chunk| {
+            buf.push_all(*chunk);
+        }
+        move buf
+    }
+
+    /**
+    Gives back a buffer once what's in it is done with. It goes in the
+    bucket of its size, unless that bucket is full. Buffers of any other
+    size, such as those for bodies too big for the pool, aren't kept.
+    */
+    fn release(&self, buf: ~[u8]) {
+        let capacity = vec::capacity(&buf);
+        let bucket = match BUCKET_SIZES.position(|size| *size == capacity) {
+            Some(bucket) => bucket,
+            None => return
+        };
+        let buf = Cell(move buf);
+        do self.state.write |state| {
+            if state.buckets[bucket].len() < MAX_FREE_PER_BUCKET {
+                let mut buf = buf.take();
+                vec::truncate(&mut buf, 0);
+                state.buckets[bucket].push(move buf);
+            }
+        }
+    }
+
+    /// How many buffers the pool has allocated, and how many times one
+    /// was reused instead.
+    fn stats(&self) -> (uint, uint) {
+        do self.state.read |state| { (state.allocated, state.reused) }
+    }
+
+    /// The free buffers in each bucket.
+    fn free_buffers(&self) -> ~[uint] {
+        do self.state.read |state| { state.buckets.map(|bucket| bucket.len()) }
+    }
+}
+
+#[cfg(test)]
+mod buffer_pool_tests {
+    #[test]
+    fn test_acquire_sizes() {
+        let pool = BufferPool();
+        assert vec::capacity(&pool.acquire(10)) >= 4 * 1024;
+        assert vec::capacity(&pool.acquire(5000)) >= 16 * 1024;
+        assert pool.acquire(5000).is_empty();
+        assert pool.stats() == (2, 0);
+
+        // Too big for any bucket: not from the pool
+        let big = pool.acquire(2 * 1024 * 1024);
+        assert vec::capacity(&big) >= 2 * 1024 * 1024;
+        assert pool.stats() == (3, 0);
+    }
+
+    #[test]
+    fn test_release_and_reuse() {
+        let pool = BufferPool();
+        let buf = pool.copy_chunks([~[1, 2], ~[3]]);
+        assert buf == ~[1, 2, 3];
+        pool.release(move buf);
+        assert pool.free_buffers() == ~[1, 0, 0, 0, 0];
+
+        // The buffer comes back empty
+        let again = pool.acquire(100);
+        assert again.is_empty();
+        assert pool.stats() == (1, 1);
+        assert pool.free_buffers() == ~[0, 0, 0, 0, 0];
+    }
+
+    #[test]
+    fn test_release_limits() {
+        let pool = BufferPool();
+        // Too small for a bucket
+        pool.release(~[1, 2, 3]);
+        assert pool.free_buffers() == ~[0, 0, 0, 0, 0];
+
+        for uint::range(0, MAX_FREE_PER_BUCKET + 2) |_| {
+            pool.release(with_capacity(64 * 1024));
+        }
+        assert pool.free_buffers() == ~[0, 0, MAX_FREE_PER_BUCKET, 0, 0];
+
+        // Between bucket sizes, or bigger than the biggest: not kept either
+        pool.release(with_capacity(5000));
+        pool.release(pool.acquire(50 * 1024 * 1024));
+        assert pool.free_buffers() == ~[0, 0, MAX_FREE_PER_BUCKET, 0, 0];
+    }
+
+    #[test]
+    fn test_pool_shared_between_tasks() {
+        let pool = BufferPool();
+        let other = pool.clone();
+        let (chan, port) = pipes::stream();
+        do task::spawn |move other, move chan| {
+            let buf = other.copy_chunks([~[7, 8, 9]]);
+            chan.send(move buf);
+        }
+        let buf = port.recv();
+        assert buf == ~[7, 8, 9];
+        pool.release(move buf);
+        assert pool.free_buffers()[0] == 1;
+    }
+}
@@ -0,0 +1,376 @@
+/*!
+Cookies set by `Set-Cookie` headers, and the policy deciding which of them
+are accepted.
+*/
+
+use core::send_map::linear::LinearMap;
+use std::net::url::Url;
+
+pub enum SameSite {
+    SameSiteStrict,
+    SameSiteLax,
+    SameSiteNone
+}
+
+impl SameSite : cmp::Eq {
+    pure fn eq(&self, other: &SameSite) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &SameSite) -> bool {
+        !self.eq(other)
+    }
+}
+
+pub struct Cookie {
+    name: ~str,
+    value: ~str,
+    // Without a leading dot
+    domain: ~str,
+    path: ~str,
+    secure: bool,
+    http_only: bool,
+    // None when the header didn't say
+    same_site: Option<SameSite>,
+    // Set by the `Partitioned` attribute: the cookie is only sent under the
+    // top-level site it was set under
+    partitioned: bool,
+}
+
+pub enum CookieError {
+    // The header had no name=value pair
+    MalformedCookie,
+    // The Domain attribute doesn't cover the host that set it
+    DomainMismatch,
+    // The Domain attribute is a public suffix, like `com` or `co.uk`, that
+    // isn't the host itself
+    PublicSuffixDomain,
+    // SameSite=None without Secure
+    InsecureSameSiteNone,
+    // A third-party cookie that isn't SameSite=Strict or SameSite=Lax
+    ThirdPartyBlocked,
+    // Partitioned without Secure
+    InsecurePartitioned
+}
+
+impl CookieError : cmp::Eq {
+    pure fn eq(&self, other: &CookieError) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &CookieError) -> bool {
+        !self.eq(other)
+    }
+}
+
+/// Parses a `Set-Cookie` header sent in the response for `url`.
+pub fn parse_set_cookie(header: &str, url: &Url) -> Result<Cookie, CookieError> {
+    let parts = str::split_char(header, ';');
+    let (name, value) = match str::find_char(parts[0], '=') {
+        Some(i) => (str::trim(str::slice(parts[0], 0, i)),
+                    str::trim(str::slice(parts[0], i + 1, parts[0].len()))),
+        None => return Err(MalformedCookie)
+    };
+    if name.is_empty() {
+        return Err(MalformedCookie);
+    }
+
+    let mut cookie = Cookie {
+        name: move name,
+        value: move value,
+        domain: str::to_lower(url.host),
+        path: default_path(url.path),
+        secure: false,
+        http_only: false,
+        same_site: None,
+        partitioned: false,
+    };
+    for vec::view(parts, 1, parts.len()).each |part| {
+        let (attr, attr_value) = match str::find_char(*part, '=') {
+            Some(i) => (str::trim(str::slice(*part, 0, i)),
+                        str::trim(str::slice(*part, i + 1, part.len()))),
+            None => (str::trim(*part), ~"")
+        };
+        match str::to_lower(attr) {
+            ~"domain" if !attr_value.is_empty() => {
+                let domain = str::to_lower(str::trim_left_chars(attr_value, ~['.']));
+                if !domain_matches(cookie.domain, domain) {
+                    return Err(DomainMismatch);
+                }
+                // A host that is itself a public suffix can still set a
+                // cookie for just itself
+                if is_public_suffix(domain) && domain != cookie.domain {
+                    return Err(PublicSuffixDomain);
+                }
+                cookie.domain = move domain;
+            }
+            ~"path" if attr_value.starts_with("/") => cookie.path = move attr_value,
+            ~"secure" => cookie.secure = true,
+            ~"httponly" => cookie.http_only = true,
+            ~"partitioned" => cookie.partitioned = true,
+            ~"samesite" => {
+                cookie.same_site = match str::to_lower(attr_value) {
+                    ~"strict" => Some(SameSiteStrict),
+                    ~"lax" => Some(SameSiteLax),
+                    ~"none" => Some(SameSiteNone),
+                    _ => None
+                }
+            }
+            // Expiry isn't supported yet, so every cookie lasts the session
+            _ => ()
+        }
+    }
+    Ok(move cookie)
+}
+
+// The directory of the request path
+fn default_path(path: &str) -> ~str {
+    match str::rfind_char(path, '/') {
+        Some(0) | None => ~"/",
+        Some(i) => str::slice(path, 0, i)
+    }
+}
+
+// Whether `host` is `domain` or a subdomain of it
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(~"." + domain)
+}
+
+// Whether a request for `path` goes to the cookie path `cookie_path`: it's
+// the same path, or one under it, not just one starting with the same text
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    let path = if path.is_empty() { "/" } else { path };
+    path == cookie_path ||
+        (path.starts_with(cookie_path) &&
+         (cookie_path.ends_with("/") || path.char_at(cookie_path.len()) == '/'))
+}
+
+/**
+The suffixes under which anyone can register a name, beyond the single
+labels like `com` that all are.
+
+TODO: use the whole public suffix list.
+*/
+const PUBLIC_SUFFIXES: &static/[&static/str] = &[
+    "co.uk", "org.uk", "ac.uk", "gov.uk", "me.uk",
+    "com.au", "net.au", "org.au", "co.nz", "co.jp", "ne.jp",
+    "com.br", "com.cn", "co.in", "co.kr", "com.mx"
+];
+
+/// Whether `domain`, lowercased, is a suffix that sites are registered under.
+pub fn is_public_suffix(domain: &str) -> bool {
+    !str::contains_char(domain, '.') || PUBLIC_SUFFIXES.any(|suffix| *suffix == domain)
+}
+
+/**
+The part of a host that a site registers, like `example.co.uk` for
+`www.example.co.uk`: the public suffix and the label before it.
+*/
+pub fn registrable_domain(host: &str) -> ~str {
+    let host = str::to_lower(host);
+    let labels = str::split_char(host, '.');
+    if labels.all(|label| uint::from_str(*label).is_some()) {
+        // IP addresses are their own site
+        return move host;
+    }
+    let mut n = 2;
+    while n < labels.len() &&
+          is_public_suffix(str::connect(vec::slice(labels, labels.len() - n, labels.len()), ".")) {
+        n += 1;
+    }
+    if labels.len() <= n {
+        // Short names are their own site
+        return move host;
+    }
+    str::connect(vec::slice(labels, labels.len() - n, labels.len()), ".")
+}
+
+pub struct CookieJar {
+    // Set by --block-third-party-cookies
+    block_third_party_cookies: bool,
+    priv mut cookies: ~[Cookie],
+    // Partitioned cookies, keyed by the registrable domain of the top-level
+    // site they were set under
+    priv mut partitions: LinearMap<~str, ~[Cookie]>,
+}
+
+pub fn CookieJar(block_third_party_cookies: bool) -> CookieJar {
+    CookieJar {
+        block_third_party_cookies: block_third_party_cookies,
+        cookies: ~[],
+        partitions: LinearMap(),
+    }
+}
+
+impl CookieJar {
+    /**
+    Stores the cookie from a `Set-Cookie` header in the response for `url`,
+    made by a document at `top_level_url`, replacing any cookie with the
+    same name, domain and path.
+    */
+    fn set_cookie(&self, header: &str, url: &Url, top_level_url: &Url)
+        -> Result<(), CookieError> {
+        let cookie = match parse_set_cookie(header, url) {
+            Ok(move cookie) => move cookie,
+            Err(e) => return Err(e)
+        };
+
+        if cookie.partitioned {
+            // A partitioned cookie can't be used to track across sites, so
+            // it's allowed even when third-party cookies are blocked
+            if !cookie.secure {
+                return Err(InsecurePartitioned);
+            }
+            let key = registrable_domain(top_level_url.host);
+            let partition = match self.partitions.pop(&key) {
+                Some(move cookies) => move cookies,
+                None => ~[]
+            };
+            self.partitions.insert(move key, replace_cookie(move partition, move cookie));
+            return Ok(());
+        }
+
+        if self.block_third_party_cookies {
+            if cookie.same_site == Some(SameSiteNone) && !cookie.secure {
+                return Err(InsecureSameSiteNone);
+            }
+            let third_party = registrable_domain(cookie.domain) !=
+                registrable_domain(top_level_url.host);
+            let same_site = cookie.same_site == Some(SameSiteStrict) ||
+                cookie.same_site == Some(SameSiteLax);
+            if third_party && !same_site {
+                return Err(ThirdPartyBlocked);
+            }
+        }
+
+        let mut cookies = ~[];
+        cookies <-> self.cookies;
+        self.cookies = replace_cookie(move cookies, move cookie);
+        Ok(())
+    }
+
+    /**
+    The value of the `Cookie` header for a request to `url` made by a
+    document at `top_level_url`. Partitioned cookies are only included
+    under the top-level site that set them.
+    */
+    fn cookie_header(&self, url: &Url, top_level_url: &Url) -> Option<~str> {
+        let mut pairs = matching_pairs(self.cookies, url);
+        match self.partitions.find(&registrable_domain(top_level_url.host)) {
+            Some(ref partition) => pairs.push_all(matching_pairs(*partition, url)),
+            None => ()
+        }
+        if pairs.is_empty() { None } else { Some(str::connect(pairs, "; ")) }
+    }
+}
+
+// Adds `cookie` to `cookies`, dropping any with the same name, domain and path
+fn replace_cookie(cookies: ~[Cookie], cookie: Cookie) -> ~[Cookie] {
+    let mut cookies = do cookies.filter |c| {
+        c.name != cookie.name || c.domain != cookie.domain || c.path != cookie.path
+    };
+    cookies.push(move cookie);
+    move cookies
+}
+
+// The name=value pairs of the cookies that go with a request to `url`
+fn matching_pairs(cookies: &[Cookie], url: &Url) -> ~[~str] {
+    let host = str::to_lower(url.host);
+    do cookies.filter_map |cookie| {
+        if domain_matches(host, cookie.domain) &&
+           path_matches(url.path, cookie.path) &&
+           (!cookie.secure || url.scheme == ~"https") {
+            Some(fmt!("%s=%s", cookie.name, cookie.value))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+fn url(s: &str) -> Url {
+    std::net::url::from_str(s).get()
+}
+
+#[test]
+fn test_parse_set_cookie() {
+    let cookie = parse_set_cookie("id=42; Path=/app; Secure; SameSite=Lax",
+                                  &url("https://www.example.com/app/page")).get();
+    assert cookie.name == ~"id" && cookie.value == ~"42";
+    assert cookie.domain == ~"www.example.com";
+    assert cookie.path == ~"/app";
+    assert cookie.secure;
+    assert cookie.same_site == Some(SameSiteLax);
+
+    assert parse_set_cookie("id=1; Domain=other.com", &url("http://example.com/")) ==
+        Err(DomainMismatch);
+    assert parse_set_cookie("novalue", &url("http://example.com/")) == Err(MalformedCookie);
+    assert parse_set_cookie("id=1; Domain=com", &url("http://example.com/")) ==
+        Err(PublicSuffixDomain);
+    assert parse_set_cookie("id=1; Domain=.co.uk", &url("http://shop.example.co.uk/")) ==
+        Err(PublicSuffixDomain);
+    assert parse_set_cookie("id=1; Domain=example.co.uk", &url("http://shop.example.co.uk/"))
+        .get().domain == ~"example.co.uk";
+}
+
+#[test]
+fn test_path_matches() {
+    assert path_matches("/foo", "/foo");
+    assert path_matches("/foo/bar", "/foo");
+    assert path_matches("/foo/bar", "/foo/");
+    assert path_matches("/anything", "/");
+    assert path_matches("", "/");
+    assert !path_matches("/foobar", "/foo");
+    assert !path_matches("/fo", "/foo");
+
+    let jar = CookieJar(false);
+    let page = url("http://example.com/foo/page");
+    assert jar.set_cookie("a=1; Path=/foo", &page, &page) == Ok(());
+    assert jar.cookie_header(&url("http://example.com/foo"), &page) == Some(~"a=1");
+    assert jar.cookie_header(&url("http://example.com/foobar"), &page).is_none();
+}
+
+#[test]
+fn test_registrable_domain() {
+    assert registrable_domain("www.example.com") == ~"example.com";
+    assert registrable_domain("example.com") == ~"example.com";
+    assert registrable_domain("127.0.0.1") == ~"127.0.0.1";
+    assert registrable_domain("www.example.co.uk") == ~"example.co.uk";
+    assert registrable_domain("example.co.uk") == ~"example.co.uk";
+    assert registrable_domain("co.uk") == ~"co.uk";
+}
+
+#[test]
+fn test_block_third_party_cookies() {
+    let page = url("http://news.example.com/");
+    let tracker = url("https://ads.tracker.com/pixel");
+
+    let jar = CookieJar(true);
+    assert jar.set_cookie("a=1", &tracker, &page) == Err(ThirdPartyBlocked);
+    assert jar.set_cookie("a=1; SameSite=None; Secure", &tracker, &page) ==
+        Err(ThirdPartyBlocked);
+    assert jar.set_cookie("a=1; SameSite=None", &tracker, &page) == Err(InsecureSameSiteNone);
+    assert jar.set_cookie("a=1; SameSite=Lax", &tracker, &page) == Ok(());
+    assert jar.set_cookie("b=2", &url("http://static.example.com/"), &page) == Ok(());
+
+    let permissive = CookieJar(false);
+    assert permissive.set_cookie("a=1", &tracker, &page) == Ok(());
+    assert permissive.cookie_header(&tracker, &page) == Some(~"a=1");
+    assert permissive.cookie_header(&url("http://ads.tracker.com/"), &page).is_none();
+}
+
+#[test]
+fn test_partitioned_cookies_are_isolated() {
+    let news = url("https://news.example/");
+    let shop = url("https://shop.example/");
+    let widget = url("https://widget.embed.com/frame");
+
+    let jar = CookieJar(true);
+    assert jar.set_cookie("chat=news; Secure; Partitioned", &widget, &news) == Ok(());
+    assert jar.set_cookie("chat=shop; Secure; Partitioned", &widget, &shop) == Ok(());
+    assert jar.set_cookie("id=1; Partitioned", &widget, &news) == Err(InsecurePartitioned);
+
+    assert jar.cookie_header(&widget, &news) == Some(~"chat=news");
+    assert jar.cookie_header(&widget, &shop) == Some(~"chat=shop");
+    assert jar.cookie_header(&widget, &url("https://other.example/")).is_none();
+    // Partitions are per top-level site, not per page
+    assert jar.cookie_header(&widget, &url("https://www.news.example/")) == Some(~"chat=news");
+}
@@ -0,0 +1,392 @@
+/*!
+DNS over HTTPS (RFC 8484). Lookups are sent as binary DNS messages to a
+DoH server, so the network can't see which hosts are being resolved.
+Answers are cached for as long as their TTL allows.
+*/
+
+use comm::Port;
+use core::send_map::linear::LinearMap;
+use std::net::url::Url;
+use std::time::precise_time_ns;
+use resource_task::{Payload, Done};
+use util::url::format_ipv6;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+// The longest a label, and a whole name, can be
+const MAX_LABEL_LEN: uint = 63;
+const MAX_NAME_LEN: uint = 253;
+
+pub enum DnsError {
+    // The server says the name doesn't exist
+    NameNotFound,
+    // The server couldn't answer, with this RCODE
+    ServerFailure(uint),
+    // The response wasn't a DNS message answering our query
+    MalformedResponse,
+    // The request to the DoH server failed
+    TransportError,
+    // The host can't be a DNS name: a label is empty or longer than 63
+    // bytes, or the name is longer than 253
+    InvalidName
+}
+
+impl DnsError : cmp::Eq {
+    pure fn eq(&self, other: &DnsError) -> bool {
+        match (*self, *other) {
+            (NameNotFound, NameNotFound) => true,
+            (ServerFailure(a), ServerFailure(b)) => a == b,
+            (MalformedResponse, MalformedResponse) => true,
+            (TransportError, TransportError) => true,
+            (InvalidName, InvalidName) => true,
+            (NameNotFound, _) | (ServerFailure(*), _) | (MalformedResponse, _) |
+            (TransportError, _) | (InvalidName, _) => false
+        }
+    }
+    pure fn ne(&self, other: &DnsError) -> bool {
+        !self.eq(other)
+    }
+}
+
+/// An address record from a DNS answer.
+pub struct DnsRecord {
+    // In the usual text form, like `192.0.2.1` or `2001:db8::1`
+    address: ~str,
+    ttl: uint,
+}
+
+/// Serializes a query for the `qtype` records of `name`. DoH queries use
+/// an ID of 0, so that they cache well in HTTP caches.
+pub fn encode_query(name: &str, qtype: u16) -> Result<~[u8], DnsError> {
+    let name = str::trim_right_chars(name, ~['.']);
+    let labels = str::split_char(name, '.');
+    if name.len() > MAX_NAME_LEN ||
+       labels.any(|label| label.is_empty() || label.len() > MAX_LABEL_LEN) {
+        return Err(InvalidName);
+    }
+
+    let mut message = ~[];
+    push_u16(&mut message, 0);          // ID
+    push_u16(&mut message, 0x0100);     // Flags: recursion desired
+    push_u16(&mut message, 1);          // QDCOUNT
+    push_u16(&mut message, 0);          // ANCOUNT
+    push_u16(&mut message, 0);          // NSCOUNT
+    push_u16(&mut message, 0);          // ARCOUNT
+    for labels.each |label| {
+        message.push(label.len() as u8);
+        message.push_all(str::to_bytes(*label));
+    }
+    message.push(0);
+    push_u16(&mut message, qtype);
+    push_u16(&mut message, CLASS_IN);
+    Ok(move message)
+}
+
+fn push_u16(message: &mut ~[u8], n: u16) {
+    message.push((n >> 8) as u8);
+    message.push(n as u8);
+}
+
+fn read_u16(message: &[u8], pos: uint) -> Result<u16, DnsError> {
+    if pos + 2 > message.len() {
+        return Err(MalformedResponse);
+    }
+    Ok((message[pos] as u16 << 8) | message[pos + 1] as u16)
+}
+
+fn read_u32(message: &[u8], pos: uint) -> Result<uint, DnsError> {
+    let high = match read_u16(message, pos) { Ok(n) => n, Err(e) => return Err(e) };
+    let low = match read_u16(message, pos + 2) { Ok(n) => n, Err(e) => return Err(e) };
+    Ok((high as uint << 16) | low as uint)
+}
+
+// The position just after the (possibly compressed) name at `pos`
+fn skip_name(message: &[u8], pos: uint) -> Result<uint, DnsError> {
+    let mut pos = pos;
+    loop {
+        if pos >= message.len() {
+            return Err(MalformedResponse);
+        }
+        let len = message[pos] as uint;
+        if len == 0 {
+            return Ok(pos + 1);
+        }
+        if len & 0xc0 == 0xc0 {
+            // A pointer ends the name
+            return Ok(pos + 2);
+        }
+        pos += len + 1;
+    }
+}
+
+fn format_address(rdata: &[u8]) -> ~str {
+    if rdata.len() == 4 {
+        fmt!("%u.%u.%u.%u", rdata[0] as uint, rdata[1] as uint, rdata[2] as uint,
+             rdata[3] as uint)
+    } else {
+        format_ipv6(vec::from_fn(8, |i| (rdata[2 * i] as u16 << 8) | rdata[2 * i + 1] as u16))
+    }
+}
+
+/// Parses a DNS response, returning its A and AAAA records. Other records,
+/// like the CNAMEs leading to them, are skipped.
+pub fn decode_response(message: &[u8]) -> Result<~[DnsRecord], DnsError> {
+    let flags = match read_u16(message, 2) { Ok(n) => n, Err(e) => return Err(e) };
+    if flags & 0x8000 == 0 {
+        // Not a response
+        return Err(MalformedResponse);
+    }
+    match (flags & 0xf) as uint {
+        0 => (),
+        3 => return Err(NameNotFound),
+        rcode => return Err(ServerFailure(rcode))
+    }
+    let questions = match read_u16(message, 4) { Ok(n) => n, Err(e) => return Err(e) };
+    let answers = match read_u16(message, 6) { Ok(n) => n, Err(e) => return Err(e) };
+
+    let mut pos = 12;
+    for uint::range(0, questions as uint) |_i| {
+        pos = match skip_name(message, pos) { Ok(p) => p + 4, Err(e) => return Err(e) };
+    }
+
+    let mut records = ~[];
+    for uint::range(0, answers as uint) |_i| {
+        pos = match skip_name(message, pos) { Ok(p) => p, Err(e) => return Err(e) };
+        let rtype = match read_u16(message, pos) { Ok(n) => n, Err(e) => return Err(e) };
+        let ttl = match read_u32(message, pos + 4) { Ok(n) => n, Err(e) => return Err(e) };
+        let len = match read_u16(message, pos + 8) { Ok(n) => n as uint, Err(e) => return Err(e) };
+        let start = pos + 10;
+        if start + len > message.len() {
+            return Err(MalformedResponse);
+        }
+        if (rtype == TYPE_A && len == 4) || (rtype == TYPE_AAAA && len == 16) {
+            records.push(DnsRecord {
+                address: format_address(vec::view(message, start, start + len)),
+                ttl: ttl,
+            });
+        }
+        pos = start + len;
+    }
+    Ok(move records)
+}
+
+struct CacheEntry {
+    addresses: ~[~str],
+    expires_ns: u64,
+}
+
+/// Resolved addresses, kept until their TTL runs out.
+pub struct DnsCache {
+    priv entries: LinearMap<~str, CacheEntry>,
+}
+
+pub fn DnsCache() -> DnsCache {
+    DnsCache { entries: LinearMap() }
+}
+
+impl DnsCache {
+    fn find(&mut self, host: &str, now_ns: u64) -> Option<~[~str]> {
+        let host = str::to_lower(host);
+        let expired = match self.entries.find_ref(&host) {
+            Some(entry) if entry.expires_ns > now_ns => return Some(copy entry.addresses),
+            Some(_) => true,
+            None => false
+        };
+        if expired {
+            self.entries.remove(&host);
+        }
+        None
+    }
+
+    /// Caches the addresses in `records` for the shortest of their TTLs.
+    fn insert(&mut self, host: &str, records: &[DnsRecord], now_ns: u64) {
+        if records.is_empty() {
+            return;
+        }
+        let ttl = records.foldl(uint::max_value, |ttl, record| uint::min(*ttl, record.ttl));
+        self.entries.insert(str::to_lower(host), CacheEntry {
+            addresses: records.map(|record| copy record.address),
+            expires_ns: now_ns + (ttl as u64) * 1000000000,
+        });
+    }
+}
+
+/**
+Sends a DNS message to a DoH server and returns the server's answer. This
+is a parameter so that the resolver can be tested without a network.
+*/
+pub type DohTransport = fn@(server: &Url, query: ~[u8]) -> Result<~[u8], ()>;
+
+/**
+POSTs `query` to `server` as `application/dns-message`.
+
+There's no TLS implementation to reach an https server with, so only plain
+http servers work: a DoH proxy on the local machine, which `--dns-over-https`
+allows as the only kind of http server.
+*/
+pub fn post_transport(server: &Url, query: ~[u8]) -> Result<~[u8], ()> {
+    if server.scheme != ~"http" {
+        #error("dns: can't reach DoH server %s: https isn't supported",
+               std::net::url::to_str(copy *server));
+        return Err(());
+    }
+    let progress = Port();
+    http_loader::post(copy *server, move query, ~"application/dns-message",
+                      ~[(~"Accept", ~"application/dns-message")], &None, progress.chan());
+    let mut response = ~[];
+    loop {
+        match progress.recv() {
+            Payload(move data) => response.push_all_move(move data),
+            Done(Ok(())) => return Ok(move response),
+            Done(Err(())) => return Err(()),
+            _ => ()
+        }
+    }
+}
+
+pub struct DohResolver {
+    server: Url,
+    priv transport: DohTransport,
+    priv mut cache: DnsCache,
+}
+
+pub fn DohResolver(server: Url, transport: DohTransport) -> DohResolver {
+    DohResolver {
+        server: move server,
+        transport: move transport,
+        cache: DnsCache(),
+    }
+}
+
+impl DohResolver {
+    /**
+    The IPv4 and IPv6 addresses of `host`. If only one of the two lookups
+    fails, the other's addresses are still used.
+    */
+    fn resolve(&self, host: &str) -> Result<~[~str], DnsError> {
+        let now = precise_time_ns();
+        match self.cache.find(host, now) {
+            Some(move addresses) => return Ok(move addresses),
+            None => ()
+        }
+
+        let mut records = ~[];
+        let mut error = None;
+        for [TYPE_A, TYPE_AAAA].each |qtype| {
+            match self.query(host, *qtype) {
+                Ok(move answers) => records.push_all_move(move answers),
+                Err(e) => if error.is_none() { error = Some(e) }
+            }
+        }
+        match error {
+            Some(e) if records.is_empty() => return Err(e),
+            _ => ()
+        }
+        self.cache.insert(host, records, now);
+        Ok(records.map(|record| copy record.address))
+    }
+
+    // The `qtype` records of `host`
+    priv fn query(&self, host: &str, qtype: u16) -> Result<~[DnsRecord], DnsError> {
+        let query = match encode_query(host, qtype) {
+            Ok(move query) => move query,
+            Err(e) => return Err(e)
+        };
+        match (self.transport)(&self.server, move query) {
+            Ok(move response) => decode_response(response),
+            Err(()) => Err(TransportError)
+        }
+    }
+}
+
+#[cfg(test)]
+fn response(rcode: u8, answers: &[(u16, ~[u8], uint)]) -> ~[u8] {
+    let mut message = ~[0, 0, 0x81, 0x80 | rcode, 0, 1, 0, answers.len() as u8, 0, 0, 0, 0];
+    message.push_all(vec::view(encode_query("example.com", TYPE_A).get(), 12, 29));
+    for answers.each |answer| {
+        let (rtype, ref rdata, ttl) = *answer;
+        // A pointer back to the question's name
+        message.push_all(~[0xc0, 12]);
+        push_u16(&mut message, rtype);
+        push_u16(&mut message, CLASS_IN);
+        push_u16(&mut message, (ttl >> 16) as u16);
+        push_u16(&mut message, ttl as u16);
+        push_u16(&mut message, rdata.len() as u16);
+        message.push_all(*rdata);
+    }
+    move message
+}
+
+#[test]
+fn test_encode_query() {
+    let query = encode_query("example.com.", TYPE_AAAA).get();
+    assert vec::view(query, 0, 12) == ~[0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    assert vec::view(query, 12, query.len()) ==
+        ~[7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 28, 0, 1];
+
+    assert encode_query(str::from_chars(vec::from_elem(63, 'a')) + ".com", TYPE_A).is_ok();
+    assert encode_query(str::from_chars(vec::from_elem(64, 'a')) + ".com", TYPE_A) ==
+        Err(InvalidName);
+    assert encode_query("a..com", TYPE_A) == Err(InvalidName);
+    let long_name = str::connect(vec::from_elem(64, ~"abc"), ".");
+    assert encode_query(long_name, TYPE_A) == Err(InvalidName);
+}
+
+#[test]
+fn test_decode_response() {
+    let v6 = ~[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    let records = decode_response(response(0, ~[(TYPE_A, ~[192, 0, 2, 1], 300),
+                                                (5, ~[0xc0, 12], 60),
+                                                (TYPE_AAAA, move v6, 120)])).get();
+    assert records.len() == 2;
+    assert records[0].address == ~"192.0.2.1" && records[0].ttl == 300;
+    assert records[1].address == ~"2001:db8::1";
+
+    assert decode_response(response(3, ~[])) == Err(NameNotFound);
+    assert decode_response(response(2, ~[])) == Err(ServerFailure(2));
+    assert decode_response(~[0, 0, 0x81]) == Err(MalformedResponse);
+}
+
+#[test]
+fn test_cache_expires() {
+    let mut cache = DnsCache();
+    cache.insert("Example.com", ~[DnsRecord { address: ~"192.0.2.1", ttl: 60 },
+                                  DnsRecord { address: ~"192.0.2.2", ttl: 30 }], 0);
+    assert cache.find("example.com", 29000000000) == Some(~[~"192.0.2.1", ~"192.0.2.2"]);
+    assert cache.find("example.com", 30000000000).is_none();
+}
+
+#[test]
+fn test_resolver_uses_cache() {
+    let queries = @mut 0;
+    let resolver = DohResolver(std::net::url::from_str("https://dns.example/dns-query").get(),
+                               |_server, query| {
+        *queries += 1;
+        if query[query.len() - 3] == TYPE_A as u8 {
+            Ok(response(0, ~[(TYPE_A, ~[192, 0, 2, 1], 300)]))
+        } else {
+            Ok(response(0, ~[]))
+        }
+    });
+    assert resolver.resolve("example.com") == Ok(~[~"192.0.2.1"]);
+    assert resolver.resolve("example.com") == Ok(~[~"192.0.2.1"]);
+    assert *queries == 2;
+}
+
+#[test]
+fn test_resolver_falls_back_to_a_records() {
+    let resolver = DohResolver(std::net::url::from_str("https://dns.example/dns-query").get(),
+                               |_server, query| {
+        if query[query.len() - 3] == TYPE_A as u8 {
+            Ok(response(0, ~[(TYPE_A, ~[192, 0, 2, 1], 300)]))
+        } else {
+            Err(())
+        }
+    });
+    assert resolver.resolve("example.com") == Ok(~[~"192.0.2.1"]);
+
+    let unreachable = DohResolver(std::net::url::from_str("https://dns.example/dns-query").get(),
+                                  |_server, _query| Err(()));
+    assert unreachable.resolve("example.com") == Err(TransportError);
+}
@@ -0,0 +1,182 @@
+/*!
+Happy Eyeballs (RFC 8305). A host's addresses are tried in turn, alternating
+between IPv6 and IPv4 and starting with IPv6 if this machine can reach the
+IPv6 internet. A new attempt starts whenever the last one fails or has
+been waiting for 250 ms, and the first connection made is used.
+*/
+
+use comm::{Port, Chan};
+use std::net::ip;
+use std::net::ip::{IpAddr, Ipv6};
+use std::net::tcp;
+use std::net::tcp::TcpSocket;
+use std::timer::recv_timeout;
+use std::uv_global_loop;
+use util::url::bare_host;
+
+// The "Connection Attempt Delay" of RFC 8305
+const CONNECTION_ATTEMPT_DELAY_MS: uint = 250;
+
+/**
+Whether the contents of `/proc/net/if_inet6` list a global IPv6 address.
+Each line is an address, its interface index, prefix length, scope, flags
+and interface name; global addresses have a scope of 0.
+*/
+pub fn has_global_ipv6(if_inet6: &str) -> bool {
+    do str::lines_any(if_inet6).any |line| {
+        let fields = str::words(*line);
+        fields.len() >= 4 && fields[3] == ~"00"
+    }
+}
+
+/// Whether this machine has an IPv6 address it can reach the internet with.
+/// Where that can't be found out, it's assumed not to.
+pub fn has_ipv6_connectivity() -> bool {
+    match io::read_whole_file_str(&Path("/proc/net/if_inet6")) {
+        Ok(ref contents) => has_global_ipv6(*contents),
+        Err(_) => false
+    }
+}
+
+/**
+Orders addresses for connection attempts: alternately one of each family,
+starting with IPv6 if `prefer_ipv6` is set. Within a family the resolver's
+order is kept.
+*/
+pub fn interleave<T: Copy>(addresses: &[T], is_ipv6: fn(&T) -> bool, prefer_ipv6: bool)
+    -> ~[T] {
+    let (preferred, other) = vec::partition(vec::from_slice(addresses),
+                                            |a| is_ipv6(a) == prefer_ipv6);
+    let mut ordered = ~[];
+    for uint::range(0, uint::max(preferred.len(), other.len())) |i| {
+        if i < preferred.len() {
+            ordered.push(copy preferred[i]);
+        }
+        if i < other.len() {
+            ordered.push(copy other[i]);
+        }
+    }
+    move ordered
+}
+
+/// Whether an address in text form is an IPv6 one.
+pub fn is_ipv6_str(addr: &~str) -> bool {
+    str::contains_char(*addr, ':')
+}
+
+fn is_ipv6(addr: &IpAddr) -> bool {
+    match *addr {
+        Ipv6(*) => true,
+        _ => false
+    }
+}
+
+/// What to do with the connection that wins the race. It's copied into the
+/// task of each attempt, and runs in the task of the one that connects first.
+pub trait OnConnect {
+    fn on_connect(&self, socket: TcpSocket) -> Result<(), ()>;
+}
+
+enum AttemptMsg {
+    // An attempt has connected, and asks whether to use its connection
+    Connected(pipes::Chan<bool>),
+    Failed,
+    // The winning connection has been used
+    Finished(Result<(), ()>)
+}
+
+fn start_attempt<H: OnConnect Copy Send>(addr: IpAddr, port: uint, handler: H,
+                                        messages: Chan<AttemptMsg>) {
+    do task::spawn |move addr, move handler| {
+        match tcp::connect(move addr, port, uv_global_loop::get()) {
+            Ok(move socket) => {
+                let (reply_chan, reply_port) = pipes::stream();
+                messages.send(Connected(move reply_chan));
+                // Losers close their connections by dropping them
+                match reply_port.try_recv() {
+                    Some(true) => messages.send(Finished(handler.on_connect(move socket))),
+                    Some(false) | None => ()
+                }
+            }
+            Err(_) => messages.send(Failed)
+        }
+    }
+}
+
+/**
+Connects to `host` on `port` and hands the connection to `handler`,
+returning what it does. Fails if the host can't be resolved or none of its
+addresses can be connected to.
+*/
+pub fn connect<H: OnConnect Copy Send>(host: &str, port: uint, handler: H) -> Result<(), ()> {
+    let iotask = uv_global_loop::get();
+    let addresses = match ip::get_addr(bare_host(host), iotask) {
+        Ok(move addresses) => interleave(addresses, is_ipv6, has_ipv6_connectivity()),
+        Err(_) => {
+            #error("happy_eyeballs: can't resolve %s", host);
+            return Err(());
+        }
+    };
+    if addresses.is_empty() {
+        return Err(());
+    }
+
+    let messages = Port();
+    let messages_chan = Chan(&messages);
+    let mut started = 0;
+    let mut failed = 0;
+    let mut connected = false;
+    start_attempt(copy addresses[0], port, copy handler, messages_chan);
+    started += 1;
+    loop {
+        let msg = if !connected && started < addresses.len() {
+            recv_timeout(iotask, CONNECTION_ATTEMPT_DELAY_MS, &messages)
+        } else {
+            Some(messages.recv())
+        };
+        match move msg {
+            Some(Connected(move reply)) => {
+                reply.send(!connected);
+                connected = true;
+            }
+            Some(Finished(result)) => return result,
+            Some(Failed) => {
+                failed += 1;
+                if !connected && failed == addresses.len() {
+                    #error("happy_eyeballs: can't connect to %s", host);
+                    return Err(());
+                }
+                // A failure starts the next attempt straight away
+                if !connected && started < addresses.len() {
+                    start_attempt(copy addresses[started], port, copy handler, messages_chan);
+                    started += 1;
+                }
+            }
+            None => {
+                #debug("happy_eyeballs: %s is slow to connect, trying another address", host);
+                start_attempt(copy addresses[started], port, copy handler, messages_chan);
+                started += 1;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_has_global_ipv6() {
+    let loopback = ~"00000000000000000000000000000001 01 80 10 80       lo\n";
+    let link_local = ~"fe800000000000000000000000000001 02 40 20 80     eth0\n";
+    let global = ~"20010db8000000000000000000000001 02 40 00 00     eth0\n";
+    assert !has_global_ipv6(loopback + link_local);
+    assert has_global_ipv6(loopback + global);
+    assert !has_global_ipv6("");
+}
+
+#[test]
+fn test_interleave() {
+    let addresses = ~[~"192.0.2.1", ~"192.0.2.2", ~"192.0.2.3", ~"2001:db8::1", ~"2001:db8::2"];
+    assert interleave(addresses, is_ipv6_str, true) ==
+        ~[~"2001:db8::1", ~"192.0.2.1", ~"2001:db8::2", ~"192.0.2.2", ~"192.0.2.3"];
+    assert interleave(addresses, is_ipv6_str, false) ==
+        ~[~"192.0.2.1", ~"2001:db8::1", ~"192.0.2.2", ~"2001:db8::2", ~"192.0.2.3"];
+    assert interleave(~[~"192.0.2.1"], is_ipv6_str, true) == ~[~"192.0.2.1"];
+}
@@ -1,38 +1,300 @@
-export factory;
+export factory, proxied_factory, post;
 
 use comm::Chan;
 use task::spawn;
-use resource_task::{ProgressMsg, Payload, Done};
+use resource_task::{ProgressMsg, Header, Payload, Done, LoaderTaskFactory};
+use proxy::{ProxyConfig, port_of, host_header, proxied_target, connect_request,
+            parse_response_head, check_connect_response};
+use resource::happy_eyeballs;
+use happy_eyeballs::OnConnect;
+use std::net::tcp::TcpSocket;
 use std::net::url::Url;
-use http_client::{uv_http_request};
+use util::url::url_to_str;
 
-pub fn factory(url: Url, progress_chan: Chan<ProgressMsg>) {
+/// Loads an http URL directly, connecting with `happy_eyeballs` so that
+/// both the IPv6 and IPv4 addresses of the host are tried.
+pub fn factory(url: Url, headers: ~[(~str, ~str)], progress_chan: Chan<ProgressMsg>) {
+    load_directly(move url, move headers, None, progress_chan)
+}
+
+// Connects to the host of `url`, and asks for it with a GET, or a POST of
+// `body` if there is one
+fn load_directly(url: Url, headers: ~[(~str, ~str)], body: Option<(~[u8], ~str)>,
+                 progress_chan: Chan<ProgressMsg>) {
     assert url.scheme == ~"http";
 
-    do spawn |move url| {
-        #debug("http_loader: requesting via http: %?", copy url);
-        let request = uv_http_request(copy url);
-        let errored = @mut false;
-        do request.begin |event, copy url| {
-            let url = copy url;
-            match event {
-                http_client::Status(*) => { }
-                http_client::Payload(data) => {
-                    #debug("http_loader: got data from %?", url);
-                    let mut junk = None;
-                    *data <-> junk;
-                    progress_chan.send(Payload(option::unwrap(move junk)));
-                }
-                http_client::Error(*) => {
-                    #debug("http_loader: error loading %?", url);
-                    *errored = true;
-                    progress_chan.send(Done(Err(())));
+    let request = DirectRequest {
+        url: move url,
+        headers: move headers,
+        body: move body,
+        progress_chan: progress_chan,
+    };
+    do spawn |move request| {
+        #debug("http_loader: requesting via http: %s", url_to_str(copy request.url));
+        let result = happy_eyeballs::connect(request.url.host, port_of(&request.url),
+                                             copy request);
+        if result.is_err() {
+            #debug("http_loader: error loading %s", url_to_str(copy request.url));
+        }
+        progress_chan.send(Done(result));
+    }
+}
+
+/// A loader for http and https URLs that goes through the proxy in `config`,
+/// except for the hosts it bypasses.
+pub fn proxied_factory(config: ProxyConfig) -> LoaderTaskFactory {
+    fn~(url: Url, headers: ~[(~str, ~str)], progress_chan: Chan<ProgressMsg>, move config) {
+        assert url.scheme == ~"http" || url.scheme == ~"https";
+
+        if config.bypasses(&url) {
+            if url.scheme == ~"http" {
+                factory(move url, move headers, progress_chan);
+            } else {
+                #error("http_loader: can't load %s directly: https isn't supported",
+                       url_to_str(move url));
+                progress_chan.send(Done(Err(())));
+            }
+            return;
+        }
+
+        load_through_proxy(move url, move headers, None, &config, progress_chan);
+    }
+}
+
+/// POSTs `body`, whose type is `content_type`, to an http URL, with the
+/// extra request `headers`, through `proxy` unless it bypasses the URL's host.
+pub fn post(url: Url, body: ~[u8], content_type: ~str, headers: ~[(~str, ~str)],
+            proxy: &Option<ProxyConfig>, progress_chan: Chan<ProgressMsg>) {
+    let body = Some((move body, move content_type));
+    match *proxy {
+        Some(ref config) if !config.bypasses(&url) => {
+            load_through_proxy(move url, move headers, move body, config, progress_chan)
+        }
+        _ => load_directly(move url, move headers, move body, progress_chan)
+    }
+}
+
+// Connects to the proxy in `config` and asks it for `url`, with a GET, or a
+// POST of `body` if there is one
+fn load_through_proxy(url: Url, headers: ~[(~str, ~str)], body: Option<(~[u8], ~str)>,
+                      config: &ProxyConfig, progress_chan: Chan<ProgressMsg>) {
+    let request = ProxyRequest {
+        url: move url,
+        headers: move headers,
+        body: move body,
+        progress_chan: progress_chan,
+    };
+    let proxy = copy config.proxy;
+    do spawn |move request, move proxy| {
+        #debug("http_loader: requesting %s via proxy %s", url_to_str(copy request.url),
+               url_to_str(copy proxy));
+        let result = happy_eyeballs::connect(proxy.host, port_of(&proxy), copy request);
+        if result.is_err() {
+            #error("http_loader: can't load %s via proxy %s", url_to_str(copy request.url),
+                   url_to_str(copy proxy));
+        }
+        progress_chan.send(Done(result));
+    }
+}
+
+// A request to make over a connection to the host itself
+struct DirectRequest {
+    url: Url,
+    // Added to the request, after Host
+    headers: ~[(~str, ~str)],
+    // What to POST, and its Content-Type; GET if there's nothing
+    body: Option<(~[u8], ~str)>,
+    progress_chan: Chan<ProgressMsg>,
+}
+
+impl DirectRequest : OnConnect {
+    fn on_connect(&self, socket: TcpSocket) -> Result<(), ()> {
+        let request = http_request(request_target(&self.url), &self.url, self.headers,
+                                   &self.body);
+        if socket.write(move request).is_err() {
+            return Err(());
+        }
+        read_response(socket, self.progress_chan)
+    }
+}
+
+// A request to make over a connection to the proxy
+struct ProxyRequest {
+    url: Url,
+    headers: ~[(~str, ~str)],
+    body: Option<(~[u8], ~str)>,
+    progress_chan: Chan<ProgressMsg>,
+}
+
+impl ProxyRequest : OnConnect {
+    fn on_connect(&self, socket: TcpSocket) -> Result<(), ()> {
+        if self.url.scheme == ~"http" {
+            let request = http_request(proxied_target(&self.url), &self.url, self.headers,
+                                       &self.body);
+            if socket.write(move request).is_err() {
+                return Err(());
+            }
+            read_response(socket, self.progress_chan)
+        } else {
+            tunnel_through_proxy(socket, &self.url)
+        }
+    }
+}
+
+/**
+A request for `url`, asking for `target` with `headers`: a GET, or a POST
+of `body` if there is one.
+
+The request is HTTP/1.0, so that the response is never chunked and ends
+when the server closes the connection.
+*/
+fn http_request(target: &str, url: &Url, headers: &[(~str, ~str)],
+                body: &Option<(~[u8], ~str)>) -> ~[u8] {
+    let method = if body.is_some() { "POST" } else { "GET" };
+    let mut head = fmt!("%s %s HTTP/1.0\r\nHost: %s\r\n", method, target, host_header(url));
+    for headers.each |header| {
+        let (ref name, ref value) = *header;
+        head += fmt!("%s: %s\r\n", *name, *value);
+    }
+    match *body {
+        None => str::to_bytes(head + "\r\n"),
+        Some((ref body, ref content_type)) => {
+            head += fmt!("Content-Type: %s\r\nContent-Length: %u\r\n\r\n", *content_type,
+                         body.len());
+            str::to_bytes(head) + *body
+        }
+    }
+}
+
+// The headers in a response head, not counting the status line
+fn response_headers(head: &[u8]) -> ~[(~str, ~str)] {
+    let head = str::from_bytes(head);
+    let lines = str::split_char(head, '\n');
+    do vec::view(lines, 1, lines.len()).filter_map |line| {
+        match str::find_char(*line, ':') {
+            Some(i) => Some((str::trim(str::slice(*line, 0, i)),
+                             str::trim(str::slice(*line, i + 1, line.len())))),
+            None => None
+        }
+    }
+}
+
+/**
+The path and query of `url`, which is what's asked for when talking to the
+host directly.
+*/
+fn request_target(url: &Url) -> ~str {
+    let mut target = copy *url;
+    target.fragment = None;
+    let target = url_to_str(move target);
+    let authority_start = match str::find_str(target, "://") {
+        Some(i) => i + 3,
+        None => 0
+    };
+    match str::find_from(target, authority_start, |c| c == '/' || c == '?') {
+        Some(i) if target.char_at(i) == '/' => target.slice(i, target.len()),
+        Some(i) => ~"/" + target.slice(i, target.len()),
+        None => ~"/"
+    }
+}
+
+// Reads an HTTP/1.0 response, streaming its body to `progress_chan`
+fn read_response(socket: TcpSocket, progress_chan: Chan<ProgressMsg>) -> Result<(), ()> {
+    let reader = match socket.read_start() {
+        Ok(reader) => reader,
+        Err(_) => return Err(())
+    };
+
+    let mut head = ~[];
+    let mut in_body = false;
+    loop {
+        match reader.recv() {
+            Ok(move data) if in_body => progress_chan.send(Payload(move data)),
+            Ok(move data) => {
+                head.push_all_move(move data);
+                match parse_response_head(head) {
+                    Some(Ok((_status, body_start))) => {
+                        in_body = true;
+                        for response_headers(vec::view(head, 0, body_start)).each |header| {
+                            let (ref name, ref value) = *header;
+                            progress_chan.send(Header(copy *name, copy *value));
+                        }
+                        if body_start < head.len() {
+                            progress_chan.send(Payload(vec::slice(head, body_start, head.len())));
+                        }
+                    }
+                    Some(Err(_)) => return Err(()),
+                    None => ()
                 }
             }
+            // The response ends when the server closes the connection
+            Err(ref err) if err.err_name == ~"EOF" => break,
+            Err(_) => return Err(())
         }
+    }
+    if in_body { Ok(()) } else { Err(()) }
+}
+
+/**
+Asks the proxy to open a tunnel to the host of an https URL.
 
-        if !*errored {
-            progress_chan.send(Done(Ok(())));
+TODO: there's no TLS implementation to make the handshake through the
+tunnel with, so the load fails once the tunnel is open.
+*/
+fn tunnel_through_proxy(socket: TcpSocket, url: &Url) -> Result<(), ()> {
+    if socket.write(str::to_bytes(connect_request(url))).is_err() {
+        return Err(());
+    }
+    let reader = match socket.read_start() {
+        Ok(reader) => reader,
+        Err(_) => return Err(())
+    };
+
+    let mut response = ~[];
+    loop {
+        match reader.recv() {
+            Ok(move data) => response.push_all_move(move data),
+            Err(_) => return Err(())
+        }
+        match check_connect_response(response) {
+            Some(Ok(())) => break,
+            Some(Err(err)) => {
+                #error("http_loader: proxy refused to tunnel to %s: %?", url.host, err);
+                return Err(());
+            }
+            None => ()
         }
     }
+
+    #error("http_loader: tunnel to %s is open, but TLS isn't supported", url.host);
+    Err(())
+}
+
+#[test]
+fn test_request_target() {
+    let url = |s: &str| std::net::url::from_str(s).get();
+    assert request_target(&url("http://example.com:8000/a/b?c=d#frag")) == ~"/a/b?c=d";
+    assert request_target(&url("http://example.com")) == ~"/";
+    assert request_target(&url("http://example.com?q=1")) == ~"/?q=1";
+}
+
+#[test]
+fn test_http_request() {
+    let url = std::net::url::from_str("http://example.com:8000/form").get();
+    assert http_request("/form", &url, [], &None) ==
+        str::to_bytes("GET /form HTTP/1.0\r\nHost: example.com:8000\r\n\r\n");
+    let body = Some((str::to_bytes("a=1&b=2"), ~"application/x-www-form-urlencoded"));
+    assert http_request("/form", &url, [(~"Cookie", ~"id=1")], &body) ==
+        str::to_bytes(~"POST /form HTTP/1.0\r\nHost: example.com:8000\r\n" +
+                      "Cookie: id=1\r\n" +
+                      "Content-Type: application/x-www-form-urlencoded\r\n" +
+                      "Content-Length: 7\r\n\r\na=1&b=2");
+}
+
+#[test]
+fn test_response_headers() {
+    let head = str::to_bytes("HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n" +
+                             "Set-Cookie: id=1; Path=/\r\n\r\n");
+    assert response_headers(head) == ~[(~"Content-Type", ~"text/html"),
+                                       (~"Set-Cookie", ~"id=1; Path=/")];
 }
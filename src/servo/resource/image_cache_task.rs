@@ -1,11 +1,15 @@
 use core::util::replace;
 use image::base::{Image, load_from_memory, test_image_bin};
+use image::decoder::decode_image;
+use image::cache::{NaturalSize, ImageCacheReport, DEFAULT_BUDGET};
+use image::decoders::jpeg::ProgressiveDecoder;
+use DecodedImages = image::cache::ImageCache;
 use std::net::url::Url;
 use util::url::{make_url, UrlMap, url_map};
 use pipes::{stream, SharedChan, Chan, Port};
 use task::{spawn, spawn_listener};
 use resource::resource_task;
-use resource_task::ResourceTask;
+use resource_task::{ResourceTask, FetchTiming};
 use std::arc::ARC;
 use clone_arc = std::arc::clone;
 use std::cell::Cell;
@@ -26,6 +30,10 @@ pub enum Msg {
     /// Used by the decoder tasks to post decoded images back to the cache
     priv StoreImage(Url, Option<ARC<~Image>>),
 
+    /// Used by the prefetch tasks to post partly loaded progressive images
+    /// back to the cache, after each scan
+    priv ImageUpdate(Url, ARC<~Image>),
+
     /// Request an Image object for a URL. If the image is not is not immediately
     /// available then ImageNotReady is returned.
     pub GetImage(Url, Chan<ImageResponseMsg>),
@@ -33,6 +41,28 @@ pub enum Msg {
     /// Wait for an image to become available (or fail to load).
     pub WaitForImage(Url, Chan<ImageResponseMsg>),
 
+    /// Wait for more of an image to become available: a progressive image
+    /// with another scan, or the whole image (or its failure).
+    pub WaitForUpdate(Url, Chan<ImageResponseMsg>),
+
+    /// Describe the decoded images that are cached, for `about:imagecache`
+    pub GetReport(Chan<ImageCacheReport>),
+
+    /// Ask how many bytes the decoded images take, and the fetched data
+    /// that is kept encoded
+    pub GetHeapSize(Chan<(uint, uint)>),
+
+    /// Used by the prefetch tasks to post how long the fetch took
+    priv StoreTiming(Url, FetchTiming),
+
+    /// Ask how long the fetch of an image took, for resource timing. None
+    /// until the fetch is done.
+    pub GetTiming(Url, Chan<Option<FetchTiming>>),
+
+    /// Ask whether an image is done with: decoded, or failed to load or
+    /// decode
+    pub IsComplete(Url, Chan<bool>),
+
     /// For testing
     priv OnMsg(fn~(msg: &Msg)),
 
@@ -42,6 +72,9 @@ pub enum Msg {
 
 pub enum ImageResponseMsg {
     ImageReady(ARC<~Image>),
+    /// A progressive image that's still loading, as it stands so far. It's
+    /// the same size as the image will be.
+    ImageProgress(ARC<~Image>),
     ImageNotReady,
     ImageFailed
 }
@@ -50,6 +83,7 @@ impl ImageResponseMsg {
     pure fn clone() -> ImageResponseMsg {
         match self {
           ImageReady(img) => ImageReady(unsafe { clone_arc(&img) }),
+          ImageProgress(img) => ImageProgress(unsafe { clone_arc(&img) }),
           ImageNotReady => ImageNotReady,
           ImageFailed => ImageFailed
         }
@@ -61,10 +95,12 @@ impl ImageResponseMsg: cmp::Eq {
         // FIXME: Bad copies
         match (self.clone(), other.clone()) {
           (ImageReady(*), ImageReady(*)) => fail ~"unimplemented comparison",
+          (ImageProgress(*), ImageProgress(*)) => fail ~"unimplemented comparison",
           (ImageNotReady, ImageNotReady) => true,
           (ImageFailed, ImageFailed) => true,
 
           (ImageReady(*), _)
+          | (ImageProgress(*), _)
           | (ImageNotReady, _)
           | (ImageFailed, _) => false
         }
@@ -101,6 +137,11 @@ pub fn ImageCacheTask_(resource_task: ResourceTask, decoder_factory: DecoderFact
             chan: chan_cell.take(),
             state_map: url_map(),
             wait_map: url_map(),
+            update_map: url_map(),
+            partial_map: url_map(),
+            encoded_map: url_map(),
+            timing_map: url_map(),
+            decoded: DecodedImages(DEFAULT_BUDGET),
             need_exit: None
         }.run();
     }
@@ -148,6 +189,17 @@ struct ImageCache {
     state_map: UrlMap<ImageState>,
     /// List of clients waiting on a WaitForImage response
     wait_map: UrlMap<@mut ~[Chan<ImageResponseMsg>]>,
+    /// List of clients waiting on a WaitForUpdate response
+    update_map: UrlMap<@mut ~[Chan<ImageResponseMsg>]>,
+    /// The latest partial image of each progressive image still loading
+    partial_map: UrlMap<@ARC<~Image>>,
+    /// The encoded data of each image that has been decoded, to decode
+    /// again if it's evicted
+    encoded_map: UrlMap<@~[u8]>,
+    /// How long each finished fetch took
+    timing_map: UrlMap<@FetchTiming>,
+    /// The decoded images, evicted when they don't fit in the budget
+    decoded: DecodedImages,
     mut need_exit: Option<Chan<()>>,
 }
 
@@ -156,7 +208,8 @@ enum ImageState {
     Prefetching(AfterPrefetch),
     Prefetched(@Cell<~[u8]>),
     Decoding,
-    Decoded(@ARC<~Image>),
+    /// Decoded, but maybe since evicted from `decoded`
+    Decoded,
     Failed
 }
 
@@ -186,10 +239,28 @@ impl ImageCache {
                 }
                 Decode(move url) => self.decode(move url),
                 StoreImage(move url, move image) => self.store_image(move url, move image),
+                ImageUpdate(move url, move image) => self.update_image(move url, move image),
                 GetImage(move url, move response) => self.get_image(move url, move response),
                 WaitForImage(move url, move response) => {
                     self.wait_for_image(move url, move response)
                 }
+                WaitForUpdate(move url, move response) => {
+                    self.wait_for_update(move url, move response)
+                }
+                GetReport(move response) => response.send(self.decoded.report()),
+                GetHeapSize(move response) => {
+                    response.send((self.decoded.heap_size(), self.encoded_heap_size()))
+                }
+                StoreTiming(move url, move timing) => self.timing_map.insert(move url, @move timing),
+                GetTiming(move url, move response) => {
+                    response.send(self.timing_map.find(move url).map(|timing| copy **timing))
+                }
+                IsComplete(move url, move response) => {
+                    response.send(match self.get_state(move url) {
+                        Decoded | Failed => true,
+                        _ => false
+                    })
+                }
                 OnMsg(move handler) => msg_handlers += [move handler],
                 Exit(move response) => {
                     assert self.need_exit.is_none();
@@ -211,7 +282,7 @@ impl ImageCache {
 
                       Init
                       | Prefetched(*)
-                      | Decoded(*)
+                      | Decoded
                       | Failed => ()
                     }
                 }
@@ -228,6 +299,23 @@ impl ImageCache {
         }
     }
 
+    // The bytes of fetched images, kept until they're decoded and after
+    priv fn encoded_heap_size() -> uint {
+        let mut size = 0;
+        for self.encoded_map.each_value |data| {
+            size += data.len();
+        }
+        for self.state_map.each_value |state| {
+            match state {
+                Prefetched(data_cell) if !data_cell.is_empty() => {
+                    size += data_cell.with_ref(|data| data.len());
+                }
+                _ => ()
+            }
+        }
+        size
+    }
+
     priv fn get_state(url: Url) -> ImageState {
         match move self.state_map.find(move url) {
             Some(move state) => move state,
@@ -250,7 +338,18 @@ impl ImageCache {
                 let url = url_cell.take();
                 #debug("image_cache_task: started fetch for %s", url.to_str());
 
-                let image = load_image_data(copy url, resource_task);
+                let progressive = ProgressiveDecoder();
+                let (image, timing) = do load_image_data(copy url, resource_task) |data| {
+                    match progressive.push(data) {
+                        Some(move partial) => {
+                            to_cache.send(ImageUpdate(copy url, ARC(~move partial)));
+                        }
+                        None => ()
+                    }
+                };
+                for timing.each |timing| {
+                    to_cache.send(StoreTiming(copy url, copy *timing));
+                }
 
                 let result = if image.is_ok() {
                     Ok(Cell(result::unwrap(move image)))
@@ -267,7 +366,7 @@ impl ImageCache {
           Prefetching(*)
           | Prefetched(*)
           | Decoding
-          | Decoded(*)
+          | Decoded
           | Failed => {
             // We've already begun working on this image
           }
@@ -296,7 +395,7 @@ impl ImageCache {
           Init
           | Prefetched(*)
           | Decoding
-          | Decoded(*)
+          | Decoded
           | Failed => {
             fail ~"wrong state for storing prefetched image"
           }
@@ -319,40 +418,60 @@ impl ImageCache {
             Prefetched(data_cell) => {
                 assert !data_cell.is_empty();
 
-                let data = data_cell.take();
-                let to_cache = self.chan.clone();
-                let url_cell = Cell(copy url);
-                let decode = self.decoder_factory();
-
-                do spawn |move url_cell, move decode, move data, move to_cache| {
-                    let url = url_cell.take();
-                    #debug("image_cache_task: started image decode for %s", url.to_str());
-                    let image = decode(data);
-                    let image = if image.is_some() {
-                        Some(ARC(~option::unwrap(move image)))
-                    } else {
-                        None
-                    };
-                    to_cache.send(StoreImage(copy url, move image));
-                    #debug("image_cache_task: ended image decode for %s", url.to_str());
-                }
-
-                self.set_state(move url, Decoding);
+                let data = @data_cell.take();
+                self.encoded_map.insert(copy url, data);
+                self.start_decode(move url, copy *data);
             }
 
-            Decoding | Decoded(*) | Failed => {
+            Decoding | Decoded | Failed => {
                 // We've already begun decoding
             }
         }
     }
 
+    priv fn start_decode(url: Url, data: ~[u8]) {
+        let to_cache = self.chan.clone();
+        let url_cell = Cell(copy url);
+        let decode = self.decoder_factory();
+
+        do spawn |move url_cell, move decode, move data, move to_cache| {
+            let url = url_cell.take();
+            #debug("image_cache_task: started image decode for %s", url.to_str());
+            let image = decode(data);
+            let image = if image.is_some() {
+                Some(ARC(~option::unwrap(move image)))
+            } else {
+                None
+            };
+            to_cache.send(StoreImage(copy url, move image));
+            #debug("image_cache_task: ended image decode for %s", url.to_str());
+        }
+
+        self.set_state(move url, Decoding);
+    }
+
+    /// The decoded image for a url in the Decoded state. If it's been
+    /// evicted, it's decoded again, and None is returned.
+    priv fn find_decoded(url: Url) -> Option<ARC<~Image>> {
+        match self.decoded.find(&url, NaturalSize) {
+            Some(move image) => Some(move image),
+            None => {
+                #debug("image_cache_task: decoding evicted image %s", url.to_str());
+                let data = self.encoded_map.get(copy url);
+                self.start_decode(move url, copy *data);
+                None
+            }
+        }
+    }
+
     priv fn store_image(url: Url, image: Option<ARC<~Image>>) {
 
         match self.get_state(copy url) {
           Decoding => {
             match image {
               Some(image) => {
-                self.set_state(copy url, Decoded(@clone_arc(&image)));
+                self.decoded.insert(copy url, NaturalSize, clone_arc(&image));
+                self.set_state(copy url, Decoded);
                 self.purge_waiters(move url, || ImageReady(clone_arc(&image)) );
               }
               None => {
@@ -365,7 +484,7 @@ impl ImageCache {
           Init
           | Prefetching(*)
           | Prefetched(*)
-          | Decoded(*)
+          | Decoded
           | Failed => {
             fail ~"incorrect state in store_image"
           }
@@ -373,7 +492,31 @@ impl ImageCache {
 
     }
 
+    priv fn update_image(url: Url, image: ARC<~Image>) {
+        match self.get_state(copy url) {
+          Prefetching(DoDecode) => {
+            self.partial_map.insert(copy url, @clone_arc(&image));
+            self.purge_update_waiters(move url, || ImageProgress(clone_arc(&image)));
+          }
+
+          Prefetching(DoNotDecode) => {
+            // Nobody has asked to see it yet
+          }
+
+          Init
+          | Prefetched(*)
+          | Decoding
+          | Decoded
+          | Failed => {
+            fail ~"incorrect state in update_image"
+          }
+        }
+    }
+
+    // Sends the whole image, or its failure, to every client waiting on it
     priv fn purge_waiters(url: Url, f: fn() -> ImageResponseMsg) {
+        self.partial_map.remove(copy url);
+        self.purge_update_waiters(copy url, f);
         match self.wait_map.find(copy url) {
           Some(@waiters) => {
             for waiters.each |response| {
@@ -385,6 +528,26 @@ impl ImageCache {
         }
     }
 
+    priv fn purge_update_waiters(url: Url, f: fn() -> ImageResponseMsg) {
+        match self.update_map.find(copy url) {
+          Some(@waiters) => {
+            for waiters.each |response| {
+                response.send(f());
+            }
+            self.update_map.remove(move url);
+          }
+          None => ()
+        }
+    }
+
+    // What's there so far of an image that's loading
+    priv fn progress(url: Url) -> ImageResponseMsg {
+        match self.partial_map.find(move url) {
+            Some(image) => ImageProgress(clone_arc(image)),
+            None => ImageNotReady
+        }
+    }
+
 
     priv fn get_image(url: Url, response: Chan<ImageResponseMsg>) {
 
@@ -392,18 +555,21 @@ impl ImageCache {
           Init => fail ~"request for image before prefetch",
 
           Prefetching(DoDecode) => {
-            response.send(ImageNotReady);
+            response.send(self.progress(move url));
           }
 
           Prefetching(DoNotDecode)
           | Prefetched(*) => fail ~"request for image before decode",
 
           Decoding => {
-            response.send(ImageNotReady)
+            response.send(self.progress(move url))
           }
 
-          Decoded(image) => {
-            response.send(ImageReady(clone_arc(image)));
+          Decoded => {
+            match self.find_decoded(move url) {
+                Some(move image) => response.send(ImageReady(move image)),
+                None => response.send(ImageNotReady)
+            }
           }
 
           Failed => {
@@ -420,22 +586,50 @@ impl ImageCache {
 
             Prefetching(DoDecode) | Decoding => {
                 // We don't have this image yet
-                match self.wait_map.find(copy url) {
+                self.add_waiter(move url, move response);
+            }
+
+            Decoded => {
+                match self.find_decoded(copy url) {
+                    Some(move image) => response.send(ImageReady(move image)),
+                    None => self.add_waiter(move url, move response)
+                }
+            }
+
+            Failed => {
+                response.send(ImageFailed);
+            }
+        }
+    }
+
+    priv fn wait_for_update(url: Url, response: Chan<ImageResponseMsg>) {
+        match self.get_state(copy url) {
+            Init => fail ~"request for image before prefetch",
+
+            Prefetching(DoNotDecode) | Prefetched(*) => fail ~"request for image before decode",
+
+            Prefetching(DoDecode) | Decoding => {
+                match self.update_map.find(copy url) {
                     Some(waiters) => {
                         vec::push(&mut *waiters, move response);
                     }
                     None => {
-                        self.wait_map.insert(move url, @mut ~[move response]);
+                        self.update_map.insert(move url, @mut ~[move response]);
                     }
                 }
             }
 
-            Decoded(image) => {
-                response.send(ImageReady(clone_arc(image)));
-            }
+            Decoded | Failed => self.wait_for_image(move url, move response)
+        }
+    }
 
-            Failed => {
-                response.send(ImageFailed);
+    priv fn add_waiter(url: Url, response: Chan<ImageResponseMsg>) {
+        match self.wait_map.find(copy url) {
+            Some(waiters) => {
+                vec::push(&mut *waiters, move response);
+            }
+            None => {
+                self.wait_map.insert(move url, @mut ~[move response]);
             }
         }
     }
@@ -445,6 +639,10 @@ impl ImageCache {
 
 trait ImageCacheTaskClient {
     fn exit();
+    fn report() -> ImageCacheReport;
+    fn heap_size() -> (uint, uint);
+    fn timing(url: Url) -> Option<FetchTiming>;
+    fn is_complete(url: Url) -> bool;
 }
 
 impl ImageCacheTask: ImageCacheTaskClient {
@@ -455,31 +653,71 @@ impl ImageCacheTask: ImageCacheTaskClient {
         response_port.recv();
     }
 
+    fn report() -> ImageCacheReport {
+        let (response_chan, response_port) = stream();
+        self.send(GetReport(move response_chan));
+        response_port.recv()
+    }
+
+    fn heap_size() -> (uint, uint) {
+        let (response_chan, response_port) = stream();
+        self.send(GetHeapSize(move response_chan));
+        response_port.recv()
+    }
+
+    fn timing(url: Url) -> Option<FetchTiming> {
+        let (response_chan, response_port) = stream();
+        self.send(GetTiming(move url, move response_chan));
+        response_port.recv()
+    }
+
+    fn is_complete(url: Url) -> bool {
+        let (response_chan, response_port) = stream();
+        self.send(IsComplete(move url, move response_chan));
+        response_port.recv()
+    }
+
 }
 
-fn load_image_data(url: Url, resource_task: ResourceTask) -> Result<~[u8], ()> {
+/// Fetches an image, calling `on_data` with each piece as it arrives.
+/// Returns the image with how long the fetch took.
+fn load_image_data(url: Url, resource_task: ResourceTask,
+                   on_data: fn(&[u8])) -> (Result<~[u8], ()>, Option<FetchTiming>) {
     let response_port = Port();
     resource_task.send(resource_task::Load(move url, response_port.chan()));
 
     let mut image_data = ~[];
+    let mut timing = None;
 
     loop {
         match response_port.recv() {
+            resource_task::ContentType(*) | resource_task::Header(*) => (),
             resource_task::Payload(data) => {
+                on_data(data);
                 image_data += data;
             }
+            resource_task::Timing(move t) => timing = Some(move t),
             resource_task::Done(result::Ok(*)) => {
-                return Ok(move image_data);
+                return (Ok(move image_data), move timing);
             }
             resource_task::Done(result::Err(*)) => {
-                return Err(());
+                return (Err(()), move timing);
             }
         }
     }
 }
 
 fn default_decoder_factory() -> ~fn(&[u8]) -> Option<Image> {
-    fn~(data: &[u8]) -> Option<Image> { load_from_memory(data) }
+    fn~(data: &[u8]) -> Option<Image> {
+        // The content type isn't kept, so this goes by the magic bytes
+        match decode_image(data, "") {
+            Ok(ref decoded) => Some(decoded.first_frame()),
+            Err(ref err) => {
+                #debug("image_cache_task: %s", err.message());
+                None
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -490,7 +728,8 @@ fn mock_resource_task(on_load: ~fn(resource: comm::Chan<resource_task::ProgressM
               resource_task::Load(_, response) => {
                 on_load(response);
               }
-              resource_task::Exit => break
+              resource_task::Exit => break,
+              _ => ()
             }
         }
     }
@@ -722,6 +961,7 @@ fn should_not_request_image_from_resource_task_if_image_is_already_available() {
                     resource_task_exited_chan.send(());
                     break
                 }
+                _ => ()
             }
         }
     };
@@ -767,6 +1007,7 @@ fn should_not_request_image_from_resource_task_if_image_fetch_already_failed() {
                     resource_task_exited_chan.send(());
                     break
                 }
+                _ => ()
             }
         }
     };
@@ -1098,3 +1339,61 @@ fn sync_cache_should_wait_for_images() {
     image_cache_task.exit();
     mock_resource_task.send(resource_task::Exit);
 }
+
+#[test]
+fn should_report_decoded_images() {
+
+    let mock_resource_task = do mock_resource_task |response| {
+        response.send(resource_task::Payload(test_image_bin()));
+        response.send(resource_task::Done(result::Ok(())));
+    };
+
+    let image_cache_task = ImageCacheTask(mock_resource_task);
+    let url = make_url(~"file", None);
+
+    image_cache_task.send(Prefetch(copy url));
+    image_cache_task.send(Decode(copy url));
+
+    let (response_chan, response_port) = stream();
+    image_cache_task.send(WaitForImage(copy url, move response_chan));
+    response_port.recv();
+
+    let (response_chan, response_port) = stream();
+    image_cache_task.send(GetImage(copy url, move response_chan));
+    response_port.recv();
+
+    let report = image_cache_task.report();
+    assert report.entries.len() == 1;
+    let (entry_url, _, _) = copy report.entries[0];
+    assert entry_url == url.to_str();
+    assert report.hits == 1;
+
+    image_cache_task.exit();
+    mock_resource_task.send(resource_task::Exit);
+}
+
+#[test]
+fn should_return_image_on_wait_for_update_if_image_is_not_progressive() {
+
+    let mock_resource_task = do mock_resource_task |response| {
+        response.send(resource_task::Payload(test_image_bin()));
+        response.send(resource_task::Done(result::Ok(())));
+    };
+
+    let image_cache_task = ImageCacheTask(mock_resource_task);
+    let url = make_url(~"file", None);
+
+    image_cache_task.send(Prefetch(copy url));
+    image_cache_task.send(Decode(copy url));
+
+    // A baseline JPEG has no partial images, so the whole image comes next
+    let (response_chan, response_port) = stream();
+    image_cache_task.send(WaitForUpdate(move url, move response_chan));
+    match response_port.recv() {
+      ImageReady(*) => (),
+      _ => fail
+    }
+
+    image_cache_task.exit();
+    mock_resource_task.send(resource_task::Exit);
+}
@@ -7,7 +7,7 @@ multiple times and thus triggering reflows multiple times.
 use clone_arc = std::arc::clone;
 use std::net::url::Url;
 use pipes::{Port, Chan, stream};
-use image_cache_task::{ImageCacheTask, ImageResponseMsg, Prefetch, Decode, GetImage, WaitForImage, ImageReady, ImageNotReady, ImageFailed};
+use image_cache_task::{ImageCacheTask, ImageResponseMsg, Prefetch, Decode, GetImage, WaitForUpdate, ImageReady, ImageProgress, ImageNotReady, ImageFailed};
 use util::url::{UrlMap, url_map};
 
 pub fn LocalImageCache(image_cache_task: ImageCacheTask) -> LocalImageCache {
@@ -51,6 +51,13 @@ pub impl LocalImageCache {
         }
     }
 
+    pub fn has_prefetched(url: &Url) -> bool {
+        match self.state_map.find(copy *url) {
+            Some(state) => state.prefetched,
+            None => false
+        }
+    }
+
     pub fn decode(url: &Url) {
         let state = self.get_state(url);
         if !state.decoded {
@@ -77,6 +84,18 @@ pub impl LocalImageCache {
                     return move port;
                 }
             }
+            ImageProgress(ref image) => {
+                if last_round == self.round_number {
+                    // FIXME: appease borrowck
+                    unsafe {
+                        let (chan, port) = pipes::stream();
+                        chan.send(ImageProgress(clone_arc(image)));
+                        return move port;
+                    }
+                } else {
+                    // There may be more of it by now
+                }
+            }
             ImageNotReady => {
                 if last_round == self.round_number {
                     let (chan, port) = pipes::stream();
@@ -99,8 +118,9 @@ pub impl LocalImageCache {
 
         let response = response_port.recv();
         match response {
-            ImageNotReady => {
-                // Need to reflow when the image is available
+            ImageNotReady | ImageProgress(*) => {
+                // Need to reflow when the image, or more of a progressive
+                // one, is available
                 // FIXME: Instead we should be just passing a Future
                 // to the caller, then to the display list. Finally,
                 // the compositor should be resonsible for waiting
@@ -111,7 +131,7 @@ pub impl LocalImageCache {
                 let url = copy *url;
                 do task::spawn |move url, move on_image_available, move image_cache_task| {
                     let (response_chan, response_port) = pipes::stream();
-                    image_cache_task.send(WaitForImage(copy url, move response_chan));
+                    image_cache_task.send(WaitForUpdate(copy url, move response_chan));
                     on_image_available(response_port.recv());
                 }
             }
@@ -121,6 +141,7 @@ pub impl LocalImageCache {
         // Put a copy of the response in the cache
         let response_copy = match response {
             ImageReady(ref image) => ImageReady(clone_arc(image)),
+            ImageProgress(ref image) => ImageProgress(clone_arc(image)),
             ImageNotReady => ImageNotReady,
             ImageFailed => ImageFailed
         };
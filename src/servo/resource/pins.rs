@@ -0,0 +1,282 @@
+/*!
+HTTP Public Key Pinning (RFC 7469). A host can be pinned to the SHA-256
+hashes of the SubjectPublicKeyInfo of certificates in its chain, either by
+a `Public-Key-Pins` header or in the file given by `--spki-hash-list`.
+Connections to a pinned host whose chain has none of the pinned keys are
+refused.
+
+Pins from headers are noted as responses arrive, but no https load gets as
+far as a handshake yet: there's no TLS implementation, so `CheckPins` has
+no caller, and the pins only guard connections once there is one.
+*/
+
+use core::send_map::linear::LinearMap;
+use std::base64::ToBase64;
+use std::net::url::Url;
+use util::sha256::sha256;
+
+pub enum PinError {
+    // Like Chromium's net::ERR_SSL_PINNED_KEY_NOT_IN_CERT_CHAIN: none of the
+    // certificates has a pinned key
+    PinnedKeyNotInCertChain,
+    // A Public-Key-Pins header that can't be used
+    MalformedPins,
+    // A Public-Key-Pins header received over plain http
+    InsecurePins
+}
+
+impl PinError : cmp::Eq {
+    pure fn eq(&self, other: &PinError) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &PinError) -> bool {
+        !self.eq(other)
+    }
+}
+
+impl PinError : ToStr {
+    pure fn to_str() -> ~str {
+        match self {
+            PinnedKeyNotInCertChain => ~"net::ERR_SSL_PINNED_KEY_NOT_IN_CERT_CHAIN",
+            MalformedPins => ~"malformed Public-Key-Pins header",
+            InsecurePins => ~"Public-Key-Pins header sent over http"
+        }
+    }
+}
+
+/// The keys a host is pinned to.
+pub struct PinSet {
+    // Base64 SHA-256 digests of SubjectPublicKeyInfos
+    hashes: ~[~str],
+    include_subdomains: bool,
+    // When a pin from a header stops applying; static pins never expire
+    expires_ns: Option<u64>,
+}
+
+// A base64 SHA-256 digest is 44 characters, ending with one `=` of padding
+fn is_pin_hash(s: &str) -> bool {
+    s.len() == 44 && s.ends_with("=") && !s.ends_with("==")
+}
+
+/**
+Parses a `Public-Key-Pins` header. It must have a `max-age` and at least two
+`pin-sha256` hashes, one of them a backup that isn't in the current chain.
+A `max-age` of 0 gives an empty pin set, which removes the host's pins.
+*/
+pub fn parse_public_key_pins(header: &str, now_ns: u64) -> Result<PinSet, PinError> {
+    let mut hashes = ~[];
+    let mut max_age = None;
+    let mut include_subdomains = false;
+    for str::split_char(header, ';').each |directive| {
+        let (name, value) = match str::find_char(*directive, '=') {
+            Some(i) => (str::trim(directive.slice(0, i)),
+                        str::trim(directive.slice(i + 1, directive.len()))),
+            None => (str::trim(*directive), ~"")
+        };
+        let value = str::trim_chars(value, ~['"']);
+        match str::to_lower(name) {
+            ~"pin-sha256" if is_pin_hash(value) => hashes.push(move value),
+            ~"pin-sha256" => return Err(MalformedPins),
+            ~"max-age" => match u64::from_str(value) {
+                Some(age) => max_age = Some(age),
+                None => return Err(MalformedPins)
+            },
+            ~"includesubdomains" => include_subdomains = true,
+            // Including report-uri: reports aren't sent yet
+            _ => ()
+        }
+    }
+
+    match max_age {
+        Some(0) => Ok(PinSet { hashes: ~[], include_subdomains: false, expires_ns: Some(now_ns) }),
+        Some(age) if hashes.len() >= 2 => Ok(PinSet {
+            hashes: move hashes,
+            include_subdomains: include_subdomains,
+            expires_ns: Some(now_ns + age * 1000000000),
+        }),
+        _ => Err(MalformedPins)
+    }
+}
+
+/**
+Parses a pin file. Each line is a host, then `includeSubDomains` if the pins
+cover its subdomains too, then one or more base64 SHA-256 hashes, each
+optionally prefixed with `sha256/`. Blank lines and lines starting with `#`
+are skipped. Returns the first line that can't be parsed on failure.
+*/
+pub fn parse_pin_file(contents: &str) -> Result<~[(~str, PinSet)], uint> {
+    let mut pins = ~[];
+    let mut line_number = 0;
+    for str::lines_any(contents).each |line| {
+        line_number += 1;
+        let line = str::trim(*line);
+        if line.is_empty() || line.starts_with("#") {
+            loop;
+        }
+        let words = str::words(line);
+        let mut include_subdomains = false;
+        let mut hashes = ~[];
+        for vec::view(words, 1, words.len()).each |word| {
+            let hash = if word.starts_with("sha256/") {
+                word.slice(7, word.len())
+            } else {
+                copy *word
+            };
+            if str::to_lower(*word) == ~"includesubdomains" {
+                include_subdomains = true;
+            } else if is_pin_hash(hash) {
+                hashes.push(move hash);
+            } else {
+                return Err(line_number);
+            }
+        }
+        if hashes.is_empty() {
+            return Err(line_number);
+        }
+        pins.push((str::to_lower(words[0]), PinSet {
+            hashes: move hashes,
+            include_subdomains: include_subdomains,
+            expires_ns: None,
+        }));
+    }
+    Ok(move pins)
+}
+
+pub struct PinStore {
+    // From --spki-hash-list
+    priv static_pins: LinearMap<~str, PinSet>,
+    // From Public-Key-Pins headers, which override static pins
+    priv mut dynamic_pins: LinearMap<~str, PinSet>,
+}
+
+pub fn PinStore(static_pins: ~[(~str, PinSet)]) -> PinStore {
+    let mut map = LinearMap();
+    do vec::consume(move static_pins) |_i, pin| {
+        let (host, pins) = move pin;
+        map.insert(move host, move pins);
+    }
+    PinStore {
+        static_pins: move map,
+        dynamic_pins: LinearMap(),
+    }
+}
+
+impl PinStore {
+    /// Notes a `Public-Key-Pins` header from the response for `url`. Pins
+    /// are only taken from https responses.
+    fn note_header(&self, header: &str, url: &Url, now_ns: u64) -> Result<(), PinError> {
+        if url.scheme != ~"https" {
+            return Err(InsecurePins);
+        }
+        match parse_public_key_pins(header, now_ns) {
+            Ok(move pins) => {
+                let host = str::to_lower(url.host);
+                if pins.hashes.is_empty() {
+                    self.dynamic_pins.remove(&host);
+                } else {
+                    self.dynamic_pins.insert(move host, move pins);
+                }
+                Ok(())
+            }
+            Err(e) => Err(e)
+        }
+    }
+
+    // The pins covering `host`: its own, or those of the nearest ancestor
+    // domain that includes subdomains
+    priv fn pins_for(&self, host: &str, now_ns: u64) -> Option<~[~str]> {
+        let labels = str::split_char(str::to_lower(host), '.');
+        for uint::range(0, labels.len()) |i| {
+            let domain = str::connect(vec::slice(labels, i, labels.len()), ".");
+            for [&self.dynamic_pins, &self.static_pins].each |pins| {
+                match pins.find_ref(&domain) {
+                    Some(pin_set) if pin_set.expires_ns.map_default(true, |e| *e > now_ns) &&
+                                     (i == 0 || pin_set.include_subdomains) => {
+                        return Some(copy pin_set.hashes);
+                    }
+                    _ => ()
+                }
+            }
+        }
+        None
+    }
+
+    /**
+    Checks the certificate chain a TLS server for `host` presented, given as
+    the DER SubjectPublicKeyInfo of each certificate. This is to be called
+    before the handshake completes.
+    */
+    fn check_chain(&self, host: &str, spki_chain: &[~[u8]], now_ns: u64)
+        -> Result<(), PinError> {
+        match self.pins_for(host, now_ns) {
+            None => Ok(()),
+            Some(hashes) => {
+                if spki_chain.any(|spki| hashes.contains(&sha256(*spki).to_base64())) {
+                    Ok(())
+                } else {
+                    Err(PinnedKeyNotInCertChain)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn pin(spki: &str) -> ~str {
+    sha256(str::to_bytes(spki)).to_base64()
+}
+
+#[test]
+fn test_parse_public_key_pins() {
+    let header = fmt!("pin-sha256=\"%s\"; pin-sha256=\"%s\"; max-age=60; includeSubDomains; \
+                       report-uri=\"https://example.com/report\"", pin("a"), pin("b"));
+    let pins = parse_public_key_pins(header, 0).get();
+    assert pins.hashes == ~[pin("a"), pin("b")];
+    assert pins.include_subdomains;
+    assert pins.expires_ns == Some(60000000000);
+
+    // No backup pin
+    assert parse_public_key_pins(fmt!("pin-sha256=\"%s\"; max-age=60", pin("a")), 0).is_err();
+    assert parse_public_key_pins("pin-sha256=\"short=\"; max-age=60", 0).is_err();
+    assert parse_public_key_pins(fmt!("pin-sha256=\"%s\"; pin-sha256=\"%s\"", pin("a"), pin("b")),
+                                 0).is_err();
+}
+
+#[test]
+fn test_parse_pin_file() {
+    let file = fmt!("# Pins\n\nexample.com includeSubDomains sha256/%s %s\nbad.com nothash\n",
+                    pin("a"), pin("b"));
+    assert parse_pin_file(file) == Err(4);
+
+    let pins = parse_pin_file(fmt!("Example.com %s\n", pin("a"))).get();
+    assert pins.len() == 1;
+    let (ref host, ref pin_set) = pins[0];
+    assert *host == ~"example.com";
+    assert pin_set.hashes == ~[pin("a")] && !pin_set.include_subdomains;
+}
+
+#[test]
+fn test_check_chain() {
+    let store = PinStore(~[(~"example.com", PinSet {
+        hashes: ~[pin("leaf")],
+        include_subdomains: true,
+        expires_ns: None,
+    })]);
+    let good = ~[str::to_bytes("leaf"), str::to_bytes("root")];
+    let bad = ~[str::to_bytes("evil"), str::to_bytes("root")];
+    assert store.check_chain("example.com", good, 0) == Ok(());
+    assert store.check_chain("www.example.com", bad, 0) == Err(PinnedKeyNotInCertChain);
+    assert store.check_chain("other.com", bad, 0) == Ok(());
+
+    // A header overrides the static pins, until it expires
+    let url = std::net::url::from_str("https://example.com/").get();
+    let header = fmt!("pin-sha256=\"%s\"; pin-sha256=\"%s\"; max-age=10", pin("evil"), pin("backup"));
+    assert store.note_header(header, &url, 0) == Ok(());
+    assert store.check_chain("example.com", bad, 0) == Ok(());
+    assert store.check_chain("example.com", bad, 10000000000) == Err(PinnedKeyNotInCertChain);
+    // The header didn't include subdomains
+    assert store.check_chain("www.example.com", bad, 0) == Err(PinnedKeyNotInCertChain);
+
+    let http = std::net::url::from_str("http://example.com/").get();
+    assert store.note_header(header, &http, 0) == Err(InsecurePins);
+}
@@ -0,0 +1,189 @@
+/*!
+Loading through an HTTP proxy, set with `--proxy`. Plain http requests are
+sent to the proxy with the whole URL as the request target; https requests
+go through a tunnel the proxy opens with `CONNECT`. Hosts listed with
+`--no-proxy` are loaded directly.
+*/
+
+use std::net::url::Url;
+use util::url::{url_host, url_to_str};
+
+pub enum ProxyError {
+    // The proxy's response didn't start with an HTTP status line
+    MalformedProxyResponse,
+    // The proxy refused the request, with this status
+    ProxyRefused(uint)
+}
+
+impl ProxyError : cmp::Eq {
+    pure fn eq(&self, other: &ProxyError) -> bool {
+        match (*self, *other) {
+            (MalformedProxyResponse, MalformedProxyResponse) => true,
+            (ProxyRefused(a), ProxyRefused(b)) => a == b,
+            (MalformedProxyResponse, _) | (ProxyRefused(*), _) => false
+        }
+    }
+    pure fn ne(&self, other: &ProxyError) -> bool {
+        !self.eq(other)
+    }
+}
+
+pub struct ProxyConfig {
+    proxy: Url,
+    // Hosts loaded without the proxy: `*` for all of them, or domain names,
+    // each covering its subdomains too, optionally with a `:port`
+    no_proxy: ~[~str],
+}
+
+/// A proxy config from `--proxy` and the comma-separated `--no-proxy` list.
+pub fn ProxyConfig(proxy: Url, no_proxy: &str) -> ProxyConfig {
+    let no_proxy = do str::split_char(no_proxy, ',').filter_map |rule| {
+        let rule = str::to_lower(str::trim(*rule));
+        if rule.is_empty() { None } else { Some(move rule) }
+    };
+    ProxyConfig {
+        proxy: move proxy,
+        no_proxy: move no_proxy,
+    }
+}
+
+/// The port a URL is loaded from, whether or not it says so.
+pub fn port_of(url: &Url) -> uint {
+    match url.port {
+        Some(ref port) => uint::from_str(*port).get_default(0),
+        None if url.scheme == ~"https" => 443,
+        None => 80
+    }
+}
+
+impl ProxyConfig {
+    /// Whether `url` should be loaded directly rather than through the proxy.
+    fn bypasses(&self, url: &Url) -> bool {
+        let host = str::to_lower(url.host);
+        let port = port_of(url);
+        for self.no_proxy.each |rule| {
+            if *rule == ~"*" {
+                return true;
+            }
+            let (domain, rule_port) = match str::rfind_char(*rule, ':') {
+                Some(i) => (rule.slice(0, i), uint::from_str(rule.slice(i + 1, rule.len()))),
+                None => (copy *rule, None)
+            };
+            let domain = str::trim_left_chars(domain, ~['.']);
+            let host_matches = host == domain || host.ends_with(~"." + domain);
+            if host_matches && rule_port.map_default(true, |p| *p == port) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// The Host header for `url`, with its port if it has one
+pub fn host_header(url: &Url) -> ~str {
+    match url.port {
+        Some(ref port) => fmt!("%s:%s", url_host(url.host), *port),
+        None => url_host(url.host)
+    }
+}
+
+/// The request target of a request for `url` sent to the proxy: the whole
+/// URL, so that the proxy knows where to forward it.
+pub fn proxied_target(url: &Url) -> ~str {
+    let mut target = copy *url;
+    target.fragment = None;
+    url_to_str(move target)
+}
+
+/// The request asking the proxy to open a tunnel to `url`'s host.
+pub fn connect_request(url: &Url) -> ~str {
+    let authority = fmt!("%s:%u", url_host(url.host), port_of(url));
+    fmt!("CONNECT %s HTTP/1.1\r\nHost: %s\r\n\r\n", authority, authority)
+}
+
+/**
+Finds the end of the response head in the data received so far. Returns
+the status code and where the body starts, or None if the head hasn't all
+arrived yet.
+*/
+pub fn parse_response_head(data: &[u8]) -> Option<Result<(uint, uint), ProxyError>> {
+    let blank_line = str::to_bytes("\r\n\r\n");
+    let mut i = 0;
+    while i + 4 <= data.len() && vec::view(data, i, i + 4) != blank_line {
+        i += 1;
+    }
+    if i + 4 > data.len() {
+        return None;
+    }
+    let end = i + 4;
+
+    let head = str::from_bytes(vec::slice(data, 0, end));
+    let status_line = str::split_char(head, '\n')[0];
+    let words = str::split_char(str::trim(status_line), ' ');
+    if words.len() < 2 || !words[0].starts_with("HTTP/1.") {
+        return Some(Err(MalformedProxyResponse));
+    }
+    match uint::from_str(words[1]) {
+        Some(status) => Some(Ok((status, end))),
+        None => Some(Err(MalformedProxyResponse))
+    }
+}
+
+/// Checks the proxy's answer to a CONNECT request: anything but a 2xx
+/// status means there's no tunnel.
+pub fn check_connect_response(data: &[u8]) -> Option<Result<(), ProxyError>> {
+    do parse_response_head(data).map |result| {
+        match *result {
+            Ok((status, _)) if status >= 200 && status < 300 => Ok(()),
+            Ok((status, _)) => Err(ProxyRefused(status)),
+            Err(e) => Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+fn url(s: &str) -> Url {
+    std::net::url::from_str(s).get()
+}
+
+#[test]
+fn test_no_proxy_rules() {
+    let config = ProxyConfig(url("http://proxy.corp:3128"), " .internal.corp, Localhost ,example.com:8080,");
+    assert config.no_proxy == ~[~".internal.corp", ~"localhost", ~"example.com:8080"];
+    assert config.bypasses(&url("http://wiki.internal.corp/"));
+    assert config.bypasses(&url("http://internal.corp/"));
+    assert config.bypasses(&url("http://localhost/"));
+    assert config.bypasses(&url("http://example.com:8080/"));
+    assert !config.bypasses(&url("http://example.com/"));
+    assert !config.bypasses(&url("http://notinternal.corp/"));
+
+    assert ProxyConfig(url("http://proxy.corp:3128"), "*").bypasses(&url("https://a.b/"));
+}
+
+#[test]
+fn test_requests() {
+    assert proxied_target(&url("http://example.com:8000/a/b?c=d#frag")) ==
+        ~"http://example.com:8000/a/b?c=d";
+    assert connect_request(&url("https://example.com/secure")) ==
+        ~"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n";
+}
+
+#[test]
+fn test_parse_response_head() {
+    let response = str::to_bytes("HTTP/1.1 200 Connection established\r\n\r\n");
+    assert check_connect_response(response) == Some(Ok(()));
+    assert check_connect_response(vec::view(response, 0, 20)).is_none();
+    assert check_connect_response(str::to_bytes("HTTP/1.0 407 Proxy Authentication Required\r\n\r\n")) ==
+        Some(Err(ProxyRefused(407)));
+    assert check_connect_response(str::to_bytes("SSH-2.0\r\n\r\n")) ==
+        Some(Err(MalformedProxyResponse));
+
+    let response = str::to_bytes("HTTP/1.0 404 Not Found\r\nServer: x\r\n\r\nbody");
+    match parse_response_head(response) {
+        Some(Ok((status, body_start))) => {
+            assert status == 404;
+            assert vec::view(response, body_start, response.len()) == str::to_bytes("body");
+        }
+        _ => fail
+    }
+}
@@ -6,19 +6,57 @@ A task that takes a URL and streams back the binary data
 
 use comm::{Chan, Port};
 use task::{spawn, spawn_listener};
-use std::net::url;
+use std::net::{ip, url};
+use std::uv_global_loop;
 use std::net::url::{Url, to_str};
+use blob_url_store::BlobURLStore;
+use cookie_jar::CookieJar;
+use dns::{DnsError, NameNotFound, DohResolver, post_transport};
+use happy_eyeballs::{interleave, is_ipv6_str, has_ipv6_connectivity};
+use pins::{PinError, PinSet, PinStore};
+use proxy::ProxyConfig;
+use std::time::precise_time_ns;
+use dom::blob::Blob;
 
 pub enum ControlMsg {
-    /// Request the data associated with a particular URL
+    /// Request the data associated with a particular URL, as a top-level
+    /// document would
     Load(Url, Chan<ProgressMsg>),
+    /// Request the data for the first URL on behalf of the document at the
+    /// second, whose site decides which cookies go with it
+    LoadFor(Url, Url, Chan<ProgressMsg>),
+    /// POST a body, with its Content-Type, to an http URL, as submitting a
+    /// form does
+    Post(Url, ~[u8], ~str, Chan<ProgressMsg>),
+    /// Make a `blob:` URL load the given Blob
+    RegisterBlobURL(~str, Blob),
+    RevokeBlobURL(~str),
+    /// A `Set-Cookie` header from the response for the first URL, made by
+    /// the document at the second
+    SetCookie(~str, Url, Url),
+    /// Request the value of the `Cookie` header for the first URL, loaded by
+    /// the document at the second
+    GetCookies(Url, Url, Chan<Option<~str>>),
+    /// Look up the addresses of a host, over DNS over HTTPS if it's enabled
+    Resolve(~str, Chan<Result<~[~str], DnsError>>),
+    /// A `Public-Key-Pins` header from the response for a URL
+    SetPublicKeyPins(~str, Url),
+    /// Check the SubjectPublicKeyInfos of a TLS server's certificate chain
+    /// against the keys its host is pinned to
+    CheckPins(~str, ~[~[u8]], Chan<Result<(), PinError>>),
     Exit
 }
 
 /// Messages sent in response to a `Load` message
 pub enum ProgressMsg {
+    /// The MIME type of the data, when the loader knows it
+    ContentType(~str),
+    /// A header of the response, for loaders that see them
+    Header(~str, ~str),
     /// Binary data - there may be multiple of these
     Payload(~[u8]),
+    /// How long the load took, sent just before `Done`
+    Timing(FetchTiming),
     /// Indicates loading is complete, either successfully or not
     Done(Result<(), ()>)
 }
@@ -26,10 +64,16 @@ pub enum ProgressMsg {
 impl ProgressMsg: cmp::Eq {
     pure fn eq(other: &ProgressMsg) -> bool {
         match (copy self, copy *other) {
+          (ContentType(a), ContentType(b)) => a == b,
+          (Header(a, b), Header(c, d)) => a == c && b == d,
           (Payload(a), Payload(b)) => a == b,
+          (Timing(a), Timing(b)) => a == b,
           (Done(a), Done(b)) => a == b,
 
-          (Payload(*), _)
+          (ContentType(*), _)
+          | (Header(*), _)
+          | (Payload(*), _)
+          | (Timing(*), _)
           | (Done(*), _) => false
         }
     }
@@ -38,6 +82,114 @@ impl ProgressMsg: cmp::Eq {
     }
 }
 
+/**
+When each step of a load happened, in ns, as resource timing reports them.
+Steps that didn't happen, like redirects, are 0. Connections are made out
+of sight of the resource task, by the http client, so looking up the host
+and connecting are taken to happen instantly at the start of the fetch,
+as they do for a connection that's reused.
+*/
+pub struct FetchTiming {
+    fetch_start: u64,
+    redirect_start: u64,
+    redirect_end: u64,
+    domain_lookup_start: u64,
+    domain_lookup_end: u64,
+    connect_start: u64,
+    connect_end: u64,
+    secure_connection_start: u64,
+    request_start: u64,
+    response_start: u64,
+    response_end: u64,
+    /// Bytes over the network: the body, with a nominal 300 for headers
+    transfer_size: uint,
+    /// The body as sent, and after any content coding is undone. Content
+    /// codings aren't supported, so they're the same
+    encoded_body_size: uint,
+    decoded_body_size: uint,
+    /// The `Timing-Allow-Origin` header, if the loader saw one
+    timing_allow_origin: Option<~str>,
+}
+
+/// The timing of a fetch just started at `fetch_start`.
+pub fn FetchTiming(fetch_start: u64) -> FetchTiming {
+    FetchTiming {
+        fetch_start: fetch_start,
+        redirect_start: 0,
+        redirect_end: 0,
+        domain_lookup_start: fetch_start,
+        domain_lookup_end: fetch_start,
+        connect_start: fetch_start,
+        connect_end: fetch_start,
+        secure_connection_start: 0,
+        request_start: fetch_start,
+        response_start: 0,
+        response_end: 0,
+        transfer_size: 0,
+        encoded_body_size: 0,
+        decoded_body_size: 0,
+        timing_allow_origin: None,
+    }
+}
+
+// Nominal size of a response's headers, counted in its transfer size
+const HEADER_SIZE: uint = 300;
+
+impl FetchTiming {
+    // Notes a message from the loader, received at `time`
+    fn note(&mut self, msg: &ProgressMsg, time: u64) {
+        match *msg {
+            Done(*) | Timing(*) => (),
+            _ if self.response_start == 0 => self.response_start = time,
+            _ => ()
+        }
+        match *msg {
+            Header(ref name, ref value) => {
+                if str::to_lower(*name) == ~"timing-allow-origin" {
+                    self.timing_allow_origin = Some(copy *value);
+                }
+            }
+            Payload(ref data) => self.encoded_body_size += data.len(),
+            Done(*) => {
+                self.response_end = time;
+                self.decoded_body_size = self.encoded_body_size;
+                self.transfer_size = self.encoded_body_size + HEADER_SIZE;
+            }
+            _ => ()
+        }
+    }
+}
+
+impl FetchTiming: cmp::Eq {
+    pure fn eq(other: &FetchTiming) -> bool {
+        self.fetch_start == other.fetch_start &&
+            self.redirect_start == other.redirect_start &&
+            self.redirect_end == other.redirect_end &&
+            self.domain_lookup_start == other.domain_lookup_start &&
+            self.domain_lookup_end == other.domain_lookup_end &&
+            self.connect_start == other.connect_start &&
+            self.connect_end == other.connect_end &&
+            self.secure_connection_start == other.secure_connection_start &&
+            self.request_start == other.request_start &&
+            self.response_start == other.response_start &&
+            self.response_end == other.response_end &&
+            self.transfer_size == other.transfer_size &&
+            self.encoded_body_size == other.encoded_body_size &&
+            self.decoded_body_size == other.decoded_body_size &&
+            self.timing_allow_origin == other.timing_allow_origin
+    }
+    pure fn ne(other: &FetchTiming) -> bool {
+        return !self.eq(other);
+    }
+}
+
+/// A finished load, and what asked for it: "script", "img", "css", ...
+pub struct TimedFetch {
+    url: Url,
+    initiator_type: ~str,
+    timing: FetchTiming,
+}
+
 /// Handle to a resource task
 type ResourceTask = Chan<ControlMsg>;
 
@@ -45,38 +197,96 @@ type ResourceTask = Chan<ControlMsg>;
 Creates a task to load a specific resource
 
 The ResourceManager delegates loading to a different type of loader task for
-each URL scheme
+each URL scheme. It's given the headers to add to the request, like
+`Cookie`, which loaders that don't make requests can ignore
 */
-type LoaderTaskFactory = fn~(url: Url, Chan<ProgressMsg>);
+type LoaderTaskFactory = fn~(url: Url, headers: ~[(~str, ~str)], Chan<ProgressMsg>);
 
 /// Create a ResourceTask with the default loaders
 fn ResourceTask() -> ResourceTask {
-    let loaders = ~[
-        (~"file", file_loader::factory),
-        (~"http", http_loader::factory)
-    ];
-    create_resource_task_with_loaders(move loaders)
+    create_resource_task_with_policy(false, None, None, ~[])
+}
+
+/**
+Create a ResourceTask with the default loaders, which refuses third-party
+cookies if `block_third_party_cookies` is set, resolves hosts with the
+DNS over HTTPS server `doh_server` if there is one, loads http and https
+URLs through `proxy` if there is one, and pins hosts to the keys in `pins`
+*/
+fn create_resource_task_with_policy(block_third_party_cookies: bool,
+                                    doh_server: Option<Url>,
+                                    proxy: Option<ProxyConfig>,
+                                    pins: ~[(~str, PinSet)]) -> ResourceTask {
+    let loaders = match copy proxy {
+        Some(move proxy) => ~[
+            (~"file", file_loader::factory),
+            (~"http", http_loader::proxied_factory(copy proxy)),
+            (~"https", http_loader::proxied_factory(move proxy))
+        ],
+        None => ~[
+            (~"file", file_loader::factory),
+            (~"http", http_loader::factory)
+        ]
+    };
+    spawn_resource_manager(move loaders, block_third_party_cookies, move doh_server,
+                           move proxy, move pins)
 }
 
 fn create_resource_task_with_loaders(loaders: ~[(~str, LoaderTaskFactory)]) -> ResourceTask {
-    do spawn_listener |from_client, move loaders| {
+    spawn_resource_manager(move loaders, false, None, None, ~[])
+}
+
+fn spawn_resource_manager(loaders: ~[(~str, LoaderTaskFactory)],
+                          block_third_party_cookies: bool,
+                          doh_server: Option<Url>,
+                          proxy: Option<ProxyConfig>,
+                          pins: ~[(~str, PinSet)]) -> ResourceTask {
+    do spawn_listener |from_client: Port<ControlMsg>, move loaders, move doh_server, move proxy,
+                       move pins| {
         // TODO: change copy to move once we can move out of closures
-        ResourceManager(from_client, copy loaders).start()
+        let resolver = do doh_server.map |server| {
+            DohResolver(copy *server, post_transport)
+        };
+        let to_self = from_client.chan();
+        ResourceManager(from_client, to_self, copy loaders, block_third_party_cookies,
+                        move resolver, copy proxy, PinStore(copy pins)).start()
     }
 }
 
 pub struct ResourceManager {
     from_client: Port<ControlMsg>,
+    /// For the `Set-Cookie` headers of responses to come back to the jar
+    to_self: Chan<ControlMsg>,
     /// Per-scheme resource loaders
     loaders: ~[(~str, LoaderTaskFactory)],
+    /// The Blobs that `blob:` URLs load
+    mut blob_urls: BlobURLStore,
+    cookie_jar: CookieJar,
+    /// Set by --dns-over-https; otherwise hosts are resolved by the OS
+    resolver: Option<DohResolver>,
+    /// Set by --proxy; POSTs go through it as loads of http URLs do
+    proxy: Option<ProxyConfig>,
+    /// Keys hosts are pinned to, by --spki-hash-list and Public-Key-Pins
+    pins: PinStore,
 }
 
 
 pub fn ResourceManager(from_client: Port<ControlMsg>, 
-                       loaders: ~[(~str, LoaderTaskFactory)]) -> ResourceManager {
+                       to_self: Chan<ControlMsg>,
+                       loaders: ~[(~str, LoaderTaskFactory)],
+                       block_third_party_cookies: bool,
+                       resolver: Option<DohResolver>,
+                       proxy: Option<ProxyConfig>,
+                       pins: PinStore) -> ResourceManager {
     ResourceManager {
         from_client : move from_client,
+        to_self : to_self,
         loaders : move loaders,
+        blob_urls : BlobURLStore(),
+        cookie_jar : CookieJar(block_third_party_cookies),
+        resolver : move resolver,
+        proxy : move proxy,
+        pins : move pins,
     }
 }
 
@@ -85,8 +295,42 @@ impl ResourceManager {
     fn start() {
         loop {
             match self.from_client.recv() {
-              Load(url, progress_chan) => {
-                self.load(copy url, progress_chan)
+              Load(move url, progress_chan) => {
+                self.load(copy url, move url, progress_chan)
+              }
+              LoadFor(move url, move top_level_url, progress_chan) => {
+                self.load(move url, move top_level_url, progress_chan)
+              }
+              Post(move url, move body, move content_type, progress_chan) => {
+                self.post(move url, move body, move content_type, progress_chan)
+              }
+              RegisterBlobURL(move url, move blob) => {
+                self.blob_urls.insert(url, move blob)
+              }
+              RevokeBlobURL(move url) => {
+                self.blob_urls.remove(url)
+              }
+              SetCookie(move header, move url, move top_level_url) => {
+                match self.cookie_jar.set_cookie(header, &url, &top_level_url) {
+                  Ok(()) => (),
+                  Err(_) => #debug("resource_task: rejected cookie from %s", to_str(copy url))
+                }
+              }
+              GetCookies(move url, move top_level_url, response_chan) => {
+                response_chan.send(self.cookie_jar.cookie_header(&url, &top_level_url))
+              }
+              Resolve(move host, response_chan) => {
+                response_chan.send(self.resolve(host))
+              }
+              SetPublicKeyPins(move header, move url) => {
+                match self.pins.note_header(header, &url, precise_time_ns()) {
+                  Ok(()) => (),
+                  Err(e) => #debug("resource_task: ignored pins from %s: %s",
+                                   to_str(copy url), e.to_str())
+                }
+              }
+              CheckPins(move host, move spki_chain, response_chan) => {
+                response_chan.send(self.pins.check_chain(host, spki_chain, precise_time_ns()))
               }
               Exit => {
                 break
@@ -95,12 +339,19 @@ impl ResourceManager {
         }
     }
 
-    fn load(url: Url, progress_chan: Chan<ProgressMsg>) {
+    fn load(url: Url, top_level_url: Url, progress_chan: Chan<ProgressMsg>) {
+        if url.scheme == ~"blob" {
+            #debug("resource_task: loading blob url: %s", to_str(copy url));
+            return self.blob_urls.load(&url, timed(progress_chan));
+        }
 
         match self.get_loader_factory(&url) {
             Some(loader_factory) => {
                 #debug("resource_task: loading url: %s", to_str(copy url));
-                loader_factory(move url, progress_chan);
+                let headers = self.request_headers(&url, &top_level_url);
+                let progress_chan = noting_headers(copy url, move top_level_url, self.to_self,
+                                                   timed(progress_chan));
+                loader_factory(move url, move headers, progress_chan);
             }
             None => {
                 #debug("resource_task: no loader for scheme %s", url.scheme);
@@ -109,6 +360,44 @@ impl ResourceManager {
         }
     }
 
+    // Only http URLs can be posted to
+    fn post(url: Url, body: ~[u8], content_type: ~str, progress_chan: Chan<ProgressMsg>) {
+        if url.scheme != ~"http" {
+            #debug("resource_task: can't POST to a %s url", url.scheme);
+            return progress_chan.send(Done(Err(())));
+        }
+        #debug("resource_task: posting to url: %s", to_str(copy url));
+        // Submitting a form navigates, so the page posted to is the top level
+        let headers = self.request_headers(&url, &url);
+        let progress_chan = noting_headers(copy url, copy url, self.to_self,
+                                           timed(progress_chan));
+        http_loader::post(move url, move body, move content_type, move headers, &self.proxy,
+                          progress_chan);
+    }
+
+    // The headers that go with a request for `url` by the document at
+    // `top_level_url`
+    fn request_headers(url: &Url, top_level_url: &Url) -> ~[(~str, ~str)] {
+        match self.cookie_jar.cookie_header(url, top_level_url) {
+            Some(move cookies) => ~[(~"Cookie", move cookies)],
+            None => ~[]
+        }
+    }
+
+    // The addresses of `host`, in the order to try connecting to them
+    fn resolve(host: &str) -> Result<~[~str], DnsError> {
+        let addresses = match self.resolver {
+            Some(ref resolver) => resolver.resolve(host),
+            None => match ip::get_addr(host, uv_global_loop::get()) {
+                Ok(move addrs) => Ok(addrs.map(|addr| ip::format_addr(addr))),
+                Err(_) => Err(NameNotFound)
+            }
+        };
+        do addresses.map |addresses| {
+            interleave(*addresses, is_ipv6_str, has_ipv6_connectivity())
+        }
+    }
+
     fn get_loader_factory(url: &Url) -> Option<LoaderTaskFactory> {
         for self.loaders.each |scheme_loader| {
             let (scheme, loader_factory) = copy *scheme_loader;
@@ -120,6 +409,61 @@ impl ResourceManager {
     }
 }
 
+/**
+A chan for a loader to send to, which passes what it's sent on to
+`progress_chan`, timing it, and adds a `Timing` before the `Done`.
+*/
+fn timed(progress_chan: Chan<ProgressMsg>) -> Chan<ProgressMsg> {
+    let fetch_start = precise_time_ns();
+    do spawn_listener |from_loader: Port<ProgressMsg>| {
+        let mut timing = FetchTiming(fetch_start);
+        loop {
+            let msg = from_loader.recv();
+            timing.note(&msg, precise_time_ns());
+            let done = match msg { Done(*) => true, _ => false };
+            if done {
+                progress_chan.send(Timing(copy timing));
+            }
+            progress_chan.send(move msg);
+            if done {
+                break;
+            }
+        }
+    }
+}
+
+/**
+A chan for a loader to send to, which passes what it's sent on to
+`progress_chan`, after sending the `Set-Cookie` and `Public-Key-Pins`
+headers of the response for `url`, made by the document at `top_level_url`,
+to the resource task.
+*/
+fn noting_headers(url: Url, top_level_url: Url, resource_task: ResourceTask,
+                  progress_chan: Chan<ProgressMsg>) -> Chan<ProgressMsg> {
+    do spawn_listener |from_loader: Port<ProgressMsg>, move url, move top_level_url| {
+        loop {
+            let msg = from_loader.recv();
+            match msg {
+                Header(ref name, ref value) => match str::to_lower(*name) {
+                    ~"set-cookie" => {
+                        resource_task.send(SetCookie(copy *value, copy url, copy top_level_url))
+                    }
+                    ~"public-key-pins" => {
+                        resource_task.send(SetPublicKeyPins(copy *value, copy url))
+                    }
+                    _ => ()
+                },
+                _ => ()
+            }
+            let done = match msg { Done(*) => true, _ => false };
+            progress_chan.send(move msg);
+            if done {
+                break;
+            }
+        }
+    }
+}
+
 #[test]
 fn test_exit() {
     let resource_task = ResourceTask();
@@ -139,11 +483,29 @@ fn test_bad_scheme() {
     resource_task.send(Exit);
 }
 
+#[test]
+#[allow(non_implicitly_copyable_typarams)]
+fn test_third_party_cookies_blocked() {
+    let resource_task = create_resource_task_with_policy(true, None, None, ~[]);
+    let page = url::from_str(~"http://example.com/").get();
+    let tracker = url::from_str(~"http://tracker.com/").get();
+    resource_task.send(SetCookie(~"id=1", copy tracker, copy page));
+    resource_task.send(SetCookie(~"session=2", copy page, copy page));
+
+    let response = Port();
+    resource_task.send(GetCookies(move tracker, copy page, response.chan()));
+    assert response.recv().is_none();
+    resource_task.send(GetCookies(copy page, move page, response.chan()));
+    assert response.recv() == Some(~"session=2");
+    resource_task.send(Exit);
+}
+
 #[test]
 #[allow(non_implicitly_copyable_typarams)]
 fn should_delegate_to_scheme_loader() {
     let payload = ~[1, 2, 3];
-    let loader_factory = fn~(_url: Url, progress_chan: Chan<ProgressMsg>, copy payload) {
+    let loader_factory = fn~(_url: Url, _headers: ~[(~str, ~str)],
+                             progress_chan: Chan<ProgressMsg>, copy payload) {
         progress_chan.send(Payload(copy payload));
         progress_chan.send(Done(Ok(())));
     };
@@ -152,6 +514,87 @@ fn should_delegate_to_scheme_loader() {
     let progress = Port();
     resource_task.send(Load(url::from_str(~"snicklefritz://heya").get(), progress.chan()));
     assert progress.recv() == Payload(move payload);
+    match progress.recv() {
+      Timing(timing) => {
+        assert timing.encoded_body_size == 3;
+        assert timing.transfer_size == 303;
+        assert timing.response_start >= timing.fetch_start;
+        assert timing.response_end >= timing.response_start;
+      }
+      _ => fail
+    }
     assert progress.recv() == Done(Ok(()));
     resource_task.send(Exit);
 }
+
+#[test]
+#[allow(non_implicitly_copyable_typarams)]
+fn test_loads_send_and_store_cookies() {
+    // Sets a cookie on the first load, and sends back the Cookie header it's
+    // given on the others
+    let loader_factory = fn~(_url: Url, headers: ~[(~str, ~str)],
+                             progress_chan: Chan<ProgressMsg>) {
+        if headers.is_empty() {
+            progress_chan.send(Header(~"Set-Cookie", ~"id=1; Path=/app"));
+        }
+        for headers.each |header| {
+            let (ref name, ref value) = *header;
+            progress_chan.send(Header(copy *name, copy *value));
+        }
+        progress_chan.send(Done(Ok(())));
+    };
+    let resource_task = create_resource_task_with_loaders(~[(~"http", move loader_factory)]);
+    let sent_headers = |url: &str| {
+        let progress = Port();
+        resource_task.send(LoadFor(url::from_str(url).get(),
+                                   url::from_str("http://example.com/").get(),
+                                   progress.chan()));
+        let mut headers = ~[];
+        loop {
+            match progress.recv() {
+                Header(move name, move value) => headers.push((move name, move value)),
+                Done(_) => break,
+                _ => ()
+            }
+        }
+        move headers
+    };
+
+    assert sent_headers("http://example.com/app/login") ==
+        ~[(~"Set-Cookie", ~"id=1; Path=/app")];
+    assert sent_headers("http://example.com/app/page") == ~[(~"Cookie", ~"id=1")];
+    let response = Port();
+    resource_task.send(GetCookies(url::from_str(~"http://example.com/application").get(),
+                                  url::from_str(~"http://example.com/").get(),
+                                  response.chan()));
+    assert response.recv().is_none();
+    resource_task.send(Exit);
+}
+
+#[test]
+#[allow(non_implicitly_copyable_typarams)]
+fn test_loads_note_public_key_pins() {
+    use std::base64::ToBase64;
+    use util::sha256::sha256;
+    use pins::PinnedKeyNotInCertChain;
+
+    let pin = |spki: &str| sha256(str::to_bytes(spki)).to_base64();
+    let header = fmt!("pin-sha256=\"%s\"; pin-sha256=\"%s\"; max-age=60", pin("leaf"),
+                      pin("backup"));
+    let loader_factory = fn~(_url: Url, _headers: ~[(~str, ~str)],
+                             progress_chan: Chan<ProgressMsg>, move header) {
+        progress_chan.send(Header(~"Public-Key-Pins", copy header));
+        progress_chan.send(Done(Ok(())));
+    };
+    let resource_task = create_resource_task_with_loaders(~[(~"https", move loader_factory)]);
+    let progress = Port();
+    resource_task.send(Load(url::from_str(~"https://example.com/").get(), progress.chan()));
+    while progress.recv() != Done(Ok(())) {}
+
+    let response = Port();
+    resource_task.send(CheckPins(~"example.com", ~[str::to_bytes("evil")], response.chan()));
+    assert response.recv() == Err(PinnedKeyNotInCertChain);
+    resource_task.send(CheckPins(~"example.com", ~[str::to_bytes("leaf")], response.chan()));
+    assert response.recv() == Ok(());
+    resource_task.send(Exit);
+}
@@ -2,10 +2,15 @@ use comm::*;
 use option::swap_unwrap;
 use platform::osmain;
 use osmain::{OSMain, AddKeyHandler};
-use opts::{Opts, Screen, Png};
-use engine::{Engine, ExitMsg, LoadURLMsg};
+use opts::{Opts, Screen, Png, Pdf};
+use engine::{Engine, ExitMsg, LoadURLMsg, PrintToPdfMsg};
+use ipc::remote::RemoteControl;
+use devtools::cdp_server::CdpServer;
 use resource::image_cache_task::ImageCacheTask;
-use resource::resource_task::ResourceTask;
+use resource::resource_task::{ResourceTask, create_resource_task_with_policy};
+use resource::pins::{PinSet, parse_pin_file};
+use resource::proxy::ProxyConfig;
+use std::net::url;
 
 use util::url::make_url;
 
@@ -19,7 +24,7 @@ fn main() {
 #[allow(non_implicitly_copyable_typarams)]
 fn run(opts: &Opts) {
     match opts.render_mode {
-      Screen => run_pipeline_screen(opts.urls),
+      Screen => run_pipeline_screen(opts),
       Png(outfile) => {
         assert opts.urls.is_not_empty();
         if opts.urls.len() > 1u {
@@ -27,10 +32,31 @@ fn run(opts: &Opts) {
         }
         run_pipeline_png(opts.urls.head(), outfile)
       }
+      Pdf(outfile) => {
+        if opts.urls.len() > 1u {
+            fail ~"servo asks that you stick to a single URL when printing to PDF"
+        }
+        run_pipeline_pdf(opts, outfile)
+      }
+    }
+}
+
+// The pins in the file given by --spki-hash-list
+fn read_pin_file(path: &str) -> ~[(~str, PinSet)] {
+    let contents = match io::read_whole_file_str(&Path(path)) {
+        Ok(move contents) => move contents,
+        Err(move msg) => fail fmt!("can't read --spki-hash-list %s: %s", path, msg)
+    };
+    match parse_pin_file(contents) {
+        Ok(move pins) => {
+            warn!("--spki-hash-list: https isn't supported yet, so no connection is checked");
+            move pins
+        }
+        Err(line) => fail fmt!("%s:%u: expected a host and SPKI hashes", path, line)
     }
 }
 
-fn run_pipeline_screen(urls: &[~str]) {
+fn run_pipeline_screen(opts: &Opts) {
 
     let (dom_event_chan, dom_event_port) = pipes::stream();
     let dom_event_chan = pipes::SharedChan(move dom_event_chan);
@@ -43,12 +69,18 @@ fn run_pipeline_screen(urls: &[~str]) {
     osmain.send(AddKeyHandler(move keypress_to_engine));
 
     // Create a servo instance
-    let resource_task = ResourceTask();
+    let resource_task = create_resource_task_with_policy(
+        opts.block_third_party_cookies,
+        opts.dns_over_https.map(|server| url::from_str(*server).get()),
+        opts.proxy.map(|proxy| ProxyConfig(url::from_str(*proxy).get(), opts.no_proxy)),
+        opts.spki_hash_list.map_default(~[], |path| read_pin_file(*path)));
     let image_cache_task = ImageCacheTask(copy resource_task);
-    let engine_task = Engine(osmain, move dom_event_port, move dom_event_chan, move resource_task,
-                             move image_cache_task);
+    let engine_task = Engine(osmain, copy *opts, move dom_event_port, move dom_event_chan,
+                             move resource_task, move image_cache_task);
+    let remote_control = opts.ipc_socket.map(|path| RemoteControl(copy *path, engine_task));
+    let devtools_server = opts.devtools_port.map(|port| CdpServer(*port, engine_task));
 
-    for urls.each |filename| {
+    for opts.urls.each |filename| {
         let url = make_url(copy *filename, None);
         #debug["master: Sending url `%s`", url.to_str()];
         engine_task.send(LoadURLMsg(move url));
@@ -62,6 +94,41 @@ fn run_pipeline_screen(urls: &[~str]) {
 
     // Shut everything down
     #debug["master: Shut down"];
+    for remote_control.each |control| {
+        control.send(ipc::remote::ExitMsg);
+    }
+    for devtools_server.each |server| {
+        server.send(devtools::cdp_server::ExitMsg);
+    }
+    let (exit_chan, exit_response_from_engine) = pipes::stream();
+    engine_task.send(engine::ExitMsg(move exit_chan));
+    exit_response_from_engine.recv();
+
+    osmain.send(osmain::Exit);
+}
+
+// There's no compositor that draws off screen yet, so the page is shown in
+// a window while it's printed
+fn run_pipeline_pdf(opts: &Opts, outfile: &str) {
+    let (dom_event_chan, dom_event_port) = pipes::stream();
+    let dom_event_chan = pipes::SharedChan(move dom_event_chan);
+
+    let osmain = OSMain(dom_event_chan.clone());
+    let resource_task = create_resource_task_with_policy(
+        opts.block_third_party_cookies,
+        opts.dns_over_https.map(|server| url::from_str(*server).get()),
+        opts.proxy.map(|proxy| ProxyConfig(url::from_str(*proxy).get(), opts.no_proxy)),
+        opts.spki_hash_list.map_default(~[], |path| read_pin_file(*path)));
+    let image_cache_task = ImageCacheTask(copy resource_task);
+    let engine_task = Engine(osmain, copy *opts, move dom_event_port, move dom_event_chan,
+                             move resource_task, move image_cache_task);
+
+    let url = make_url(copy opts.urls.head(), None);
+    #debug["master: Printing `%s` to %s", url.to_str(), outfile];
+    let (printed_chan, printed_port) = pipes::stream();
+    engine_task.send(PrintToPdfMsg(move url, outfile.to_str(), move printed_chan));
+    printed_port.recv();
+
     let (exit_chan, exit_response_from_engine) = pipes::stream();
     engine_task.send(engine::ExitMsg(move exit_chan));
     exit_response_from_engine.recv();
@@ -0,0 +1,433 @@
+/*!
+The Unicode Bidirectional Algorithm (UAX #9), applied to a single
+paragraph of text.
+
+Levels are resolved per character: even levels are left-to-right and
+odd levels are right-to-left. `reorder_visual` turns resolved levels
+into the order in which characters should be displayed.
+
+TODO: isolates (LRI, RLI, FSI, PDI) and bracket pairs (N0) from
+Unicode 6.3 are not implemented; the class table only covers the
+common scripts.
+*/
+
+// The deepest explicit embedding level allowed by rule X1.
+const MAX_DEPTH: u8 = 61u8;
+
+pub enum BidiClass {
+    L,   // Left-to-right
+    R,   // Right-to-left
+    AL,  // Arabic letter
+    EN,  // European number
+    ES,  // European separator
+    ET,  // European terminator
+    AN,  // Arabic number
+    CS,  // Common number separator
+    NSM, // Nonspacing mark
+    BN,  // Boundary neutral
+    B,   // Paragraph separator
+    S,   // Segment separator
+    WS,  // Whitespace
+    ON,  // Other neutral
+    LRE,
+    LRO,
+    RLE,
+    RLO,
+    PDF,
+}
+
+impl BidiClass : cmp::Eq {
+    pure fn eq(other: &BidiClass) -> bool {
+        (self as uint) == (*other as uint)
+    }
+    pure fn ne(other: &BidiClass) -> bool {
+        !self.eq(other)
+    }
+}
+
+pub pure fn is_rtl(level: u8) -> bool {
+    level & 1u8 == 1u8
+}
+
+/// The bidi class of a character. Unlisted characters are treated as L.
+pub pure fn bidi_class(ch: char) -> BidiClass {
+    match ch as uint {
+        0x0009 | 0x000B | 0x001F => S,
+        0x000A | 0x000D | 0x001C .. 0x001E | 0x0085 | 0x2029 => B,
+        0x000C | 0x0020 | 0x2000 .. 0x200A | 0x2028 | 0x205F | 0x3000 => WS,
+        0x0000 .. 0x0008 | 0x000E .. 0x001B | 0x007F .. 0x0084 | 0x0086 .. 0x009F |
+        0x00AD | 0x200B .. 0x200D | 0x2060 .. 0x2064 | 0xFEFF => BN,
+        0x0030 .. 0x0039 | 0x00B2 | 0x00B3 | 0x00B9 | 0x06F0 .. 0x06F9 |
+        0x2070 .. 0x2079 | 0x2080 .. 0x2089 | 0xFF10 .. 0xFF19 => EN,
+        0x002B | 0x002D | 0x207A | 0x207B | 0x208A | 0x208B | 0x2212 => ES,
+        0x0023 .. 0x0025 | 0x00A2 .. 0x00A5 | 0x00B0 | 0x00B1 | 0x066A |
+        0x2030 .. 0x2034 | 0x20A0 .. 0x20CF => ET,
+        0x002C | 0x002E | 0x002F | 0x003A | 0x00A0 | 0x060C | 0x202F | 0x2044 => CS,
+        0x0600 .. 0x0605 | 0x0660 .. 0x0669 | 0x066B | 0x066C | 0x06DD => AN,
+        0x0300 .. 0x036F | 0x0483 .. 0x0489 | 0x0591 .. 0x05BD | 0x05BF | 0x05C1 | 0x05C2 |
+        0x05C4 | 0x05C5 | 0x05C7 | 0x0610 .. 0x061A | 0x064B .. 0x065F | 0x0670 |
+        0x06D6 .. 0x06DC | 0x06DF .. 0x06E4 | 0x06E7 | 0x06E8 | 0x06EA .. 0x06ED |
+        0x20D0 .. 0x20F0 | 0xFE00 .. 0xFE0F | 0xFE20 .. 0xFE2F => NSM,
+        0x200F | 0x05BE | 0x05C0 | 0x05C3 | 0x05C6 | 0x05D0 .. 0x05FF |
+        0x07C0 .. 0x085F | 0xFB1D .. 0xFB4F | 0x10800 .. 0x10FFF => R,
+        0x0608 | 0x060B | 0x060D | 0x061B .. 0x064A | 0x066D .. 0x06D5 | 0x06E5 | 0x06E6 |
+        0x06EE | 0x06EF | 0x06FA .. 0x07BF | 0x0860 .. 0x08FF | 0xFB50 .. 0xFDFF |
+        0xFE70 .. 0xFEFE => AL,
+        0x202A => LRE,
+        0x202B => RLE,
+        0x202C => PDF,
+        0x202D => LRO,
+        0x202E => RLO,
+        0x0041 .. 0x005A | 0x0061 .. 0x007A => L,
+        0x0021 .. 0x007E | 0x00A1 .. 0x00BF | 0x00D7 | 0x00F7 | 0x2010 .. 0x2027 |
+        0x2035 .. 0x205E | 0x2190 .. 0x2BFF | 0x3001 .. 0x3004 => ON,
+        _ => L
+    }
+}
+
+/**
+Rules P2 and P3: the paragraph embedding level is set by the first
+strong character, ignoring characters inside explicit embeddings.
+Paragraphs without strong characters are left-to-right.
+*/
+pub fn paragraph_level(text: &str) -> u8 {
+    let mut depth = 0u;
+    for str::each_char(text) |ch| {
+        match bidi_class(ch) {
+            LRE | LRO | RLE | RLO => depth += 1,
+            PDF => if depth > 0 { depth -= 1 },
+            L if depth == 0 => return 0u8,
+            R | AL if depth == 0 => return 1u8,
+            _ => ()
+        }
+    }
+    0u8
+}
+
+/// Resolves the embedding level of each character in `text`, which is
+/// taken to be one paragraph at embedding level `para_level`.
+pub fn resolve_levels(text: &str, para_level: u8) -> ~[u8] {
+    let initial = vec::map(str::chars(text), |ch| bidi_class(*ch));
+    let mut classes = copy initial;
+    let mut levels = vec::from_elem(initial.len(), para_level);
+
+    // X1-X8: explicit embeddings and overrides.
+    let mut stack: ~[(u8, Option<BidiClass>)] = ~[];
+    let mut level = para_level;
+    let mut override_class: Option<BidiClass> = None;
+    let mut overflow = 0u;
+    for uint::range(0, initial.len()) |i| {
+        match initial[i] {
+            RLE | RLO | LRE | LRO => {
+                let new_level = match initial[i] {
+                    RLE | RLO => (level + 1u8) | 1u8,
+                    _ => (level + 2u8) & !1u8
+                };
+                if new_level <= MAX_DEPTH && overflow == 0 {
+                    stack.push((level, override_class));
+                    level = new_level;
+                    override_class = match initial[i] {
+                        RLO => Some(R),
+                        LRO => Some(L),
+                        _ => None
+                    };
+                } else {
+                    overflow += 1;
+                }
+                levels[i] = level;
+                classes[i] = BN;
+            }
+            PDF => {
+                if overflow > 0 {
+                    overflow -= 1;
+                } else if stack.len() > 0 {
+                    let (old_level, old_override) = stack.pop();
+                    level = old_level;
+                    override_class = old_override;
+                }
+                levels[i] = level;
+                classes[i] = BN;
+            }
+            B => levels[i] = para_level,
+            BN => levels[i] = level,
+            _ => {
+                levels[i] = level;
+                match override_class {
+                    Some(class) => classes[i] = class,
+                    None => ()
+                }
+            }
+        }
+    }
+
+    // X9: boundary neutrals and embedding controls take no further part.
+    let mut kept: ~[uint] = ~[];
+    for uint::range(0, classes.len()) |i| {
+        if classes[i] != BN { kept.push(i) }
+    }
+
+    // X10: resolve each level run on its own.
+    let mut start = 0u;
+    while start < kept.len() {
+        let mut end = start + 1;
+        while end < kept.len() && levels[kept[end]] == levels[kept[start]] {
+            end += 1;
+        }
+
+        let run_level = levels[kept[start]];
+        let prev_level = if start == 0 { para_level } else { levels[kept[start - 1]] };
+        let next_level = if end == kept.len() { para_level } else { levels[kept[end]] };
+        let sor = if is_rtl(uint::max(prev_level as uint, run_level as uint) as u8) { R } else { L };
+        let eor = if is_rtl(uint::max(next_level as uint, run_level as uint) as u8) { R } else { L };
+
+        let run = vec::slice(kept, start, end);
+        resolve_weak_types(&mut classes, run, sor);
+        resolve_neutral_types(&mut classes, run, sor, eor, run_level);
+        resolve_implicit_levels(classes, &mut levels, run);
+
+        start = end;
+    }
+
+    // L1: separators, and whitespace before them or at the end of the
+    // line, go back to the paragraph level.
+    let mut trailing = true;
+    let mut i = initial.len();
+    while i > 0 {
+        i -= 1;
+        match initial[i] {
+            S | B => { levels[i] = para_level; trailing = true; }
+            WS | BN | LRE | LRO | RLE | RLO | PDF => {
+                if trailing { levels[i] = para_level }
+            }
+            _ => trailing = false
+        }
+    }
+
+    move levels
+}
+
+// W1-W7, on the characters at `run` indices.
+fn resolve_weak_types(classes: &mut ~[BidiClass], run: &[uint], sor: BidiClass) {
+    // W1: nonspacing marks take the class of what they attach to.
+    let mut prev = sor;
+    for run.each |i| {
+        if classes[*i] == NSM { classes[*i] = prev }
+        prev = classes[*i];
+    }
+
+    // W2 and W3: European numbers after Arabic letters become Arabic
+    // numbers, then Arabic letters become R.
+    let mut last_strong = sor;
+    for run.each |i| {
+        match classes[*i] {
+            L | R => last_strong = classes[*i],
+            AL => { last_strong = AL; classes[*i] = R }
+            EN if last_strong == AL => classes[*i] = AN,
+            _ => ()
+        }
+    }
+
+    // W4: a single separator between two numbers of the same type.
+    for uint::range(1, run.len()) |j| {
+        if j + 1 < run.len() {
+            let (before, here, after) = (classes[run[j - 1]], classes[run[j]], classes[run[j + 1]]);
+            match (before, here, after) {
+                (EN, ES, EN) | (EN, CS, EN) => classes[run[j]] = EN,
+                (AN, CS, AN) => classes[run[j]] = AN,
+                _ => ()
+            }
+        }
+    }
+
+    // W5: terminators next to European numbers become numbers.
+    let mut j = 0u;
+    while j < run.len() {
+        if classes[run[j]] == ET {
+            let mut end = j;
+            while end < run.len() && classes[run[end]] == ET { end += 1 }
+            let touches_en = (j > 0 && classes[run[j - 1]] == EN) ||
+                             (end < run.len() && classes[run[end]] == EN);
+            if touches_en {
+                for uint::range(j, end) |k| { classes[run[k]] = EN }
+            }
+            j = end;
+        } else {
+            j += 1;
+        }
+    }
+
+    // W6 and W7: leftover separators are neutral, and European numbers
+    // in a left-to-right context are treated as L.
+    let mut last_strong = sor;
+    for run.each |i| {
+        match classes[*i] {
+            ES | ET | CS => classes[*i] = ON,
+            L | R => last_strong = classes[*i],
+            EN if last_strong == L => classes[*i] = L,
+            _ => ()
+        }
+    }
+}
+
+// N1 and N2: neutrals between two characters of the same direction take
+// that direction; the rest take the embedding direction.
+fn resolve_neutral_types(classes: &mut ~[BidiClass], run: &[uint], sor: BidiClass,
+                         eor: BidiClass, level: u8) {
+    pure fn is_neutral(class: BidiClass) -> bool {
+        match class { B | S | WS | ON => true, _ => false }
+    }
+    pure fn strong_direction(class: BidiClass) -> BidiClass {
+        match class { L => L, _ => R }
+    }
+
+    let embedding = if is_rtl(level) { R } else { L };
+    let mut j = 0u;
+    while j < run.len() {
+        if !is_neutral(classes[run[j]]) {
+            j += 1;
+            loop;
+        }
+
+        let mut end = j;
+        while end < run.len() && is_neutral(classes[run[end]]) { end += 1 }
+        let before = if j == 0 { sor } else { strong_direction(classes[run[j - 1]]) };
+        let after = if end == run.len() { eor } else { strong_direction(classes[run[end]]) };
+        let resolved = if before == after { before } else { embedding };
+        for uint::range(j, end) |k| { classes[run[k]] = resolved }
+        j = end;
+    }
+}
+
+// I1 and I2.
+fn resolve_implicit_levels(classes: &[BidiClass], levels: &mut ~[u8], run: &[uint]) {
+    for run.each |i| {
+        let level = levels[*i];
+        levels[*i] = match (is_rtl(level), classes[*i]) {
+            (false, R) => level + 1u8,
+            (false, AN) | (false, EN) => level + 2u8,
+            (true, L) | (true, EN) | (true, AN) => level + 1u8,
+            _ => level
+        };
+    }
+}
+
+/**
+Rule L2: given the resolved level of each character of `text`, returns
+the character indices in display order (left to right).
+*/
+pub fn reorder_visual(text: &str, levels: &[u8]) -> ~[uint] {
+    assert str::char_len(text) == levels.len();
+    visual_order(levels)
+}
+
+/// L2 for anything with levels, such as the boxes of a line.
+pub fn visual_order(levels: &[u8]) -> ~[uint] {
+    let mut order = vec::from_fn(levels.len(), |i| i);
+    if levels.is_empty() { return move order }
+
+    let mut highest = 0u8;
+    let mut lowest_odd = MAX_DEPTH + 1u8;
+    for levels.each |level| {
+        if *level > highest { highest = *level }
+        if is_rtl(*level) && *level < lowest_odd { lowest_odd = *level }
+    }
+
+    // Reverse every maximal run at or above each level, from the
+    // highest level down to the lowest odd one.
+    let mut level = highest;
+    while level >= lowest_odd {
+        let mut i = 0u;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                loop;
+            }
+            let mut end = i;
+            while end < order.len() && levels[order[end]] >= level { end += 1 }
+            let (mut a, mut b) = (i, end - 1);
+            while a < b {
+                order[a] <-> order[b];
+                a += 1;
+                b -= 1;
+            }
+            i = end;
+        }
+        level -= 1u8;
+    }
+
+    move order
+}
+
+/// Spreads per-character levels over the UTF-8 bytes of `text`, so they
+/// can be looked up with the byte ranges used by text runs.
+pub fn byte_levels(text: &str, char_levels: &[u8]) -> ~[u8] {
+    assert str::char_len(text) == char_levels.len();
+    let mut levels = vec::with_capacity(text.len());
+    let mut char_i = 0u;
+    for str::each_char(text) |ch| {
+        for uint::range(0, str::from_char(ch).len()) |_i| {
+            levels.push(char_levels[char_i]);
+        }
+        char_i += 1;
+    }
+    move levels
+}
+
+#[test]
+fn test_paragraph_level() {
+    assert paragraph_level("hello") == 0u8;
+    assert paragraph_level("\u05d0\u05d1 abc") == 1u8;
+    assert paragraph_level("123 \u0627") == 1u8;
+    assert paragraph_level("\u202babc\u202c \u05d0") == 1u8;
+    assert paragraph_level("123 ...") == 0u8;
+}
+
+#[test]
+fn test_resolve_levels_mixed() {
+    // abc, space, then three Hebrew letters
+    let text = "abc \u05d0\u05d1\u05d2";
+    assert resolve_levels(text, 0u8) == ~[0u8, 0u8, 0u8, 0u8, 1u8, 1u8, 1u8];
+}
+
+#[test]
+fn test_resolve_levels_numbers_in_rtl() {
+    let text = "\u05d0\u05d1 12";
+    assert resolve_levels(text, 1u8) == ~[1u8, 1u8, 1u8, 2u8, 2u8];
+}
+
+#[test]
+fn test_resolve_levels_arabic_numbers() {
+    // European digits after an Arabic letter are Arabic numbers
+    let text = "\u0627 1";
+    assert resolve_levels(text, 1u8) == ~[1u8, 1u8, 2u8];
+}
+
+#[test]
+fn test_reorder_ltr_is_identity() {
+    let text = "abc def";
+    let levels = resolve_levels(text, paragraph_level(text));
+    assert reorder_visual(text, levels) == ~[0u, 1u, 2u, 3u, 4u, 5u, 6u];
+}
+
+#[test]
+fn test_reorder_mixed() {
+    let text = "abc \u05d0\u05d1\u05d2";
+    let levels = resolve_levels(text, paragraph_level(text));
+    assert reorder_visual(text, levels) == ~[0u, 1u, 2u, 3u, 6u, 5u, 4u];
+
+    let text = "\u05d0\u05d1 12";
+    let levels = resolve_levels(text, paragraph_level(text));
+    assert reorder_visual(text, levels) == ~[3u, 4u, 2u, 1u, 0u];
+}
+
+#[test]
+fn test_trailing_whitespace_at_paragraph_level() {
+    let text = "\u05d0  ";
+    assert resolve_levels(text, 0u8) == ~[1u8, 0u8, 0u8];
+}
+
+#[test]
+fn test_byte_levels() {
+    let text = "a\u05d0";
+    assert byte_levels(text, ~[0u8, 1u8]) == ~[0u8, 1u8, 1u8];
+}
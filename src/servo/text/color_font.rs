@@ -0,0 +1,1231 @@
+/*!
+Color fonts, from the `COLR` and `CPAL` tables. A `COLR` version 0 glyph is
+a stack of outline glyphs, each filled with one palette colour. Version 1
+glyphs are graphs of paints instead: solid fills and gradients, clipped to
+outlines, transformed, and composited onto each other, which is how emoji
+fonts such as Noto Color Emoji draw their shading.
+
+Outlines are read from `glyf` and filled here rather than by the native
+font, since a paint's transform can skew and rotate them. Pixels are kept
+as premultiplied floats until the glyph is done.
+*/
+
+use float::{fmin, fmax};
+use variable::{tag, sfnt_table, u8_at, u16_at, u24_at, u32_at, fixed_at, f2dot14_at};
+
+// The palette index that means the text colour
+const FOREGROUND: u16 = 0xffff;
+
+// Paint graphs can refer to themselves through PaintColrGlyph and layers
+const MAX_DEPTH: uint = 64;
+// Paints drawn for one glyph. A graph whose paints share children can
+// reach far more paints than it has.
+const MAX_PAINTS: uint = 1024;
+// The largest bitmap a colour glyph is drawn into, whatever its clip box says
+const MAX_GLYPH_PIXELS: uint = 512 * 512;
+
+// Vertical samples per pixel row when filling outlines
+const SUBSAMPLES: uint = 4;
+// Line segments per quadratic curve
+const CURVE_STEPS: uint = 8;
+
+// Composite modes
+const COMPOSITE_CLEAR: u8 = 0;
+const COMPOSITE_SRC_OVER: u8 = 3;
+const COMPOSITE_XOR: u8 = 11;
+const COMPOSITE_PLUS: u8 = 12;
+const COMPOSITE_SCREEN: u8 = 13;
+const COMPOSITE_MULTIPLY: u8 = 23;
+
+// Simple glyph flags
+const ON_CURVE: u8 = 0x01;
+const X_SHORT: u8 = 0x02;
+const Y_SHORT: u8 = 0x04;
+const REPEAT: u8 = 0x08;
+const X_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+// Composite glyph flags
+const ARGS_ARE_WORDS: u16 = 0x0001;
+const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+const HAVE_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const HAVE_X_AND_Y_SCALE: u16 = 0x0040;
+const HAVE_TWO_BY_TWO: u16 = 0x0080;
+
+// A signed distance in font units
+fn fword_at(data: &[u8], pos: uint) -> float {
+    (u16_at(data, pos) as i16) as float
+}
+
+/// A colour with its alpha multiplied in, each part from 0 to 1.
+pub struct Rgba {
+    r: float,
+    g: float,
+    b: float,
+    a: float,
+}
+
+pub fn rgba(r: u8, g: u8, b: u8, a: u8) -> Rgba {
+    let a = a as float / 255f;
+    Rgba { r: r as float / 255f * a, g: g as float / 255f * a, b: b as float / 255f * a, a: a }
+}
+
+pure fn transparent() -> Rgba {
+    Rgba { r: 0f, g: 0f, b: 0f, a: 0f }
+}
+
+impl Rgba {
+    pure fn scale(&self, k: float) -> Rgba {
+        Rgba { r: self.r * k, g: self.g * k, b: self.b * k, a: self.a * k }
+    }
+
+    pure fn lerp(&self, other: &Rgba, t: float) -> Rgba {
+        Rgba {
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+            a: self.a + (other.a - self.a) * t
+        }
+    }
+}
+
+/// The palettes in a `CPAL` table. Colours are stored as BGRA.
+pub fn parse_cpal(cpal: &[u8]) -> ~[~[Rgba]] {
+    let num_entries = u16_at(cpal, 2) as uint;
+    let num_palettes = u16_at(cpal, 4) as uint;
+    let records = u32_at(cpal, 8) as uint;
+    do vec::from_fn(num_palettes) |i| {
+        let first = u16_at(cpal, 12 + 2 * i) as uint;
+        do vec::from_fn(num_entries) |j| {
+            let record = records + 4 * (first + j);
+            rgba(u8_at(cpal, record + 2), u8_at(cpal, record + 1), u8_at(cpal, record),
+                 u8_at(cpal, record + 3))
+        }
+    }
+}
+
+/// An affine transform, mapping (x, y) to (xx·x + xy·y + dx, yx·x + yy·y + dy).
+pub struct Matrix {
+    xx: float,
+    yx: float,
+    xy: float,
+    yy: float,
+    dx: float,
+    dy: float,
+}
+
+pure fn identity() -> Matrix {
+    Matrix { xx: 1f, yx: 0f, xy: 0f, yy: 1f, dx: 0f, dy: 0f }
+}
+
+pure fn translation(dx: float, dy: float) -> Matrix {
+    Matrix { xx: 1f, yx: 0f, xy: 0f, yy: 1f, dx: dx, dy: dy }
+}
+
+pure fn scaling(sx: float, sy: float) -> Matrix {
+    Matrix { xx: sx, yx: 0f, xy: 0f, yy: sy, dx: 0f, dy: 0f }
+}
+
+impl Matrix {
+    pure fn apply(&self, x: float, y: float) -> (float, float) {
+        (self.xx * x + self.xy * y + self.dx, self.yx * x + self.yy * y + self.dy)
+    }
+
+    /// This transform after `other`.
+    pure fn multiply(&self, other: &Matrix) -> Matrix {
+        Matrix {
+            xx: self.xx * other.xx + self.xy * other.yx,
+            yx: self.yx * other.xx + self.yy * other.yx,
+            xy: self.xx * other.xy + self.xy * other.yy,
+            yy: self.yx * other.xy + self.yy * other.yy,
+            dx: self.xx * other.dx + self.xy * other.dy + self.dx,
+            dy: self.yx * other.dx + self.yy * other.dy + self.dy
+        }
+    }
+
+    pure fn invert(&self) -> Option<Matrix> {
+        let det = self.xx * self.yy - self.xy * self.yx;
+        if det == 0f {
+            return None;
+        }
+        let xx = self.yy / det;
+        let xy = -self.xy / det;
+        let yx = -self.yx / det;
+        let yy = self.xx / det;
+        Some(Matrix {
+            xx: xx, yx: yx, xy: xy, yy: yy,
+            dx: -(xx * self.dx + xy * self.dy),
+            dy: -(yx * self.dx + yy * self.dy)
+        })
+    }
+
+    // This transform, about (cx, cy) rather than the origin
+    pure fn around(&self, cx: float, cy: float) -> Matrix {
+        translation(cx, cy).multiply(self).multiply(&translation(-cx, -cy))
+    }
+}
+
+enum Extend {
+    ExtendPad,
+    ExtendRepeat,
+    ExtendReflect,
+}
+
+// Colours along a gradient, with the stops sorted by offset
+struct ColorLine {
+    extend: Extend,
+    stops: ~[(float, Rgba)],
+}
+
+impl ColorLine {
+    fn at(&self, t: float) -> Rgba {
+        let n = self.stops.len();
+        if n == 0 {
+            return transparent();
+        }
+        let (first, first_color) = self.stops[0];
+        let (last, last_color) = self.stops[n - 1];
+        let span = last - first;
+        let t = if span <= 0f {
+            t
+        } else {
+            match self.extend {
+                ExtendPad => t,
+                ExtendRepeat => {
+                    let u = (t - first) / span;
+                    first + (u - float::floor(u)) * span
+                }
+                ExtendReflect => {
+                    let u = (t - first) / span;
+                    let u = u - 2f * float::floor(u / 2f);
+                    first + (if u > 1f { 2f - u } else { u }) * span
+                }
+            }
+        };
+        if t <= first {
+            return first_color;
+        }
+        if t >= last {
+            return last_color;
+        }
+        for uint::range(1, n) |i| {
+            let (offset, color) = self.stops[i];
+            if t <= offset {
+                let (prev_offset, prev_color) = self.stops[i - 1];
+                let f = if offset > prev_offset {
+                    (t - prev_offset) / (offset - prev_offset)
+                } else {
+                    1f
+                };
+                return prev_color.lerp(&color, f);
+            }
+        }
+        last_color
+    }
+}
+
+// Where a point falls along a gradient, in glyph space
+enum Gradient {
+    // The start, and the direction the colour line runs in
+    Linear(float, float, float, float),
+    // Two circles, as centre and radius
+    Radial(float, float, float, float, float, float),
+    // The centre, and the start and end angles in degrees
+    Sweep(float, float, float, float),
+}
+
+/**
+A linear gradient from its three points. The colour line runs from p0
+towards p1, but lines of equal colour are parallel to p0p2, so the
+direction is p0p1 projected onto the normal of p0p2.
+*/
+fn linear_gradient(x0: float, y0: float, x1: float, y1: float,
+                   x2: float, y2: float) -> Gradient {
+    let nx = y2 - y0;
+    let ny = x0 - x2;
+    let len2 = nx * nx + ny * ny;
+    if len2 == 0f {
+        return Linear(x0, y0, x1 - x0, y1 - y0);
+    }
+    let k = ((x1 - x0) * nx + (y1 - y0) * ny) / len2;
+    Linear(x0, y0, nx * k, ny * k)
+}
+
+fn gradient_t(gradient: &Gradient, x: float, y: float) -> Option<float> {
+    match *gradient {
+        Linear(x0, y0, dx, dy) => {
+            let len2 = dx * dx + dy * dy;
+            if len2 == 0f { None } else { Some(((x - x0) * dx + (y - y0) * dy) / len2) }
+        }
+        Radial(x0, y0, r0, x1, y1, r1) => {
+            // The largest t for which the point is on the circle between the two at t
+            let (cdx, cdy, dr) = (x1 - x0, y1 - y0, r1 - r0);
+            let (pdx, pdy) = (x - x0, y - y0);
+            let a = cdx * cdx + cdy * cdy - dr * dr;
+            let b = pdx * cdx + pdy * cdy + r0 * dr;
+            let c = pdx * pdx + pdy * pdy - r0 * r0;
+            if float::abs(a) < 1e-9 {
+                if b == 0f {
+                    return None;
+                }
+                let t = c / (2f * b);
+                return if r0 + t * dr >= 0f { Some(t) } else { None };
+            }
+            let discriminant = b * b - a * c;
+            if discriminant < 0f {
+                return None;
+            }
+            let root = float::sqrt(discriminant);
+            let (t1, t2) = ((b + root) / a, (b - root) / a);
+            let (larger, smaller) = if t1 > t2 { (t1, t2) } else { (t2, t1) };
+            if r0 + larger * dr >= 0f {
+                Some(larger)
+            } else if r0 + smaller * dr >= 0f {
+                Some(smaller)
+            } else {
+                None
+            }
+        }
+        Sweep(cx, cy, start, end) => {
+            if start == end {
+                return None;
+            }
+            let angle = float::atan2(y - cy, x - cx) * 180f / float::consts::pi;
+            let angle = if angle < 0f { angle + 360f } else { angle };
+            Some((angle - start) / (end - start))
+        }
+    }
+}
+
+fn blend(mode: u8, cs: float, cb: float) -> float {
+    fn hard_light(cs: float, cb: float) -> float {
+        if cs <= 0.5f {
+            cb * 2f * cs
+        } else {
+            let s = 2f * cs - 1f;
+            cb + s - cb * s
+        }
+    }
+
+    match mode {
+        COMPOSITE_SCREEN => cs + cb - cs * cb,
+        // Overlay
+        14 => hard_light(cb, cs),
+        // Darken and lighten
+        15 => fmin(cs, cb),
+        16 => fmax(cs, cb),
+        // Colour dodge
+        17 => {
+            if cb <= 0f { 0f } else if cs >= 1f { 1f } else { fmin(1f, cb / (1f - cs)) }
+        }
+        // Colour burn
+        18 => {
+            if cb >= 1f { 1f } else if cs <= 0f { 0f } else { 1f - fmin(1f, (1f - cb) / cs) }
+        }
+        19 => hard_light(cs, cb),
+        // Soft light
+        20 => {
+            if cs <= 0.5f {
+                cb - (1f - 2f * cs) * cb * (1f - cb)
+            } else {
+                let d = if cb <= 0.25f {
+                    ((16f * cb - 12f) * cb + 4f) * cb
+                } else {
+                    float::sqrt(cb)
+                };
+                cb + (2f * cs - 1f) * (d - cb)
+            }
+        }
+        // Difference and exclusion
+        21 => float::abs(cs - cb),
+        22 => cs + cb - 2f * cs * cb,
+        _ => cs * cb
+    }
+}
+
+/**
+`src` composited onto `dst` with one of `COLR`'s composite modes. Modes
+0 to 12 are Porter-Duff operators and 13 to 23 separable blend modes. The
+hue, saturation, colour and luminosity modes aren't supported, and draw
+the source over.
+*/
+pub fn composite(mode: u8, src: Rgba, dst: Rgba) -> Rgba {
+    let (fa, fb) = match mode {
+        COMPOSITE_CLEAR => (0f, 0f),
+        // Source, destination
+        1 => (1f, 0f),
+        2 => (0f, 1f),
+        // Destination over
+        4 => (1f - dst.a, 1f),
+        // Source in, destination in, source out, destination out
+        5 => (dst.a, 0f),
+        6 => (0f, src.a),
+        7 => (1f - dst.a, 0f),
+        8 => (0f, 1f - src.a),
+        // Source atop, destination atop
+        9 => (dst.a, 1f - src.a),
+        10 => (1f - dst.a, src.a),
+        COMPOSITE_XOR => (1f - dst.a, 1f - src.a),
+        COMPOSITE_PLUS => {
+            return Rgba {
+                r: fmin(1f, src.r + dst.r),
+                g: fmin(1f, src.g + dst.g),
+                b: fmin(1f, src.b + dst.b),
+                a: fmin(1f, src.a + dst.a)
+            };
+        }
+        13 .. 23 => {
+            let channel = |s: float, d: float| {
+                let cs = if src.a > 0f { s / src.a } else { 0f };
+                let cb = if dst.a > 0f { d / dst.a } else { 0f };
+                (1f - dst.a) * s + (1f - src.a) * d + src.a * dst.a * blend(mode, cs, cb)
+            };
+            return Rgba {
+                r: channel(src.r, dst.r),
+                g: channel(src.g, dst.g),
+                b: channel(src.b, dst.b),
+                a: src.a + dst.a - src.a * dst.a
+            };
+        }
+        _ => (1f, 1f - src.a)
+    };
+    Rgba {
+        r: src.r * fa + dst.r * fb,
+        g: src.g * fa + dst.g * fb,
+        b: src.b * fa + dst.b * fb,
+        a: src.a * fa + dst.a * fb
+    }
+}
+
+// Pixels being painted, in device space
+struct Layer {
+    width: uint,
+    height: uint,
+    pixels: ~[Rgba],
+}
+
+fn Layer(width: uint, height: uint) -> Layer {
+    Layer { width: width, height: height, pixels: vec::from_elem(width * height, transparent()) }
+}
+
+fn fill_gradient(layer: &mut Layer, transform: &Matrix, line: &ColorLine, gradient: &Gradient) {
+    let inverse = match transform.invert() {
+        Some(inverse) => inverse,
+        None => return
+    };
+    for uint::range(0, layer.height) |y| {
+        for uint::range(0, layer.width) |x| {
+            let (gx, gy) = inverse.apply(x as float + 0.5f, y as float + 0.5f);
+            match gradient_t(gradient, gx, gy) {
+                Some(t) => {
+                    let i = y * layer.width + x;
+                    layer.pixels[i] = composite(COMPOSITE_SRC_OVER, line.at(t), layer.pixels[i]);
+                }
+                None => ()
+            }
+        }
+    }
+}
+
+/**
+A contour of quadratic curves as a polygon. Points are (x, y, on curve),
+and between two points off the curve there's one on it, half way.
+*/
+fn flatten(contour: &[(float, float, bool)]) -> ~[(float, float)] {
+    let n = contour.len();
+    let mut points = ~[];
+    for uint::range(0, n) |i| {
+        let (x, y, on) = contour[i];
+        let (next_x, next_y, next_on) = contour[(i + 1) % n];
+        points.push((x, y, on));
+        if !on && !next_on {
+            points.push(((x + next_x) / 2f, (y + next_y) / 2f, true));
+        }
+    }
+    let first = match vec::position(points, |p| { let (_, _, on) = *p; on }) {
+        Some(first) => first,
+        None => return ~[]
+    };
+    let count = points.len();
+    let (start_x, start_y, _) = points[first];
+    let mut polygon = ~[(start_x, start_y)];
+    let mut i = 1;
+    while i <= count {
+        let (x, y, on) = points[(first + i) % count];
+        if on {
+            polygon.push((x, y));
+            i += 1;
+        } else {
+            let (end_x, end_y, _) = points[(first + i + 1) % count];
+            let (from_x, from_y) = polygon.last();
+            for uint::range(1, CURVE_STEPS + 1) |step| {
+                let t = step as float / CURVE_STEPS as float;
+                let u = 1f - t;
+                polygon.push((u * u * from_x + 2f * u * t * x + t * t * end_x,
+                              u * u * from_y + 2f * u * t * y + t * t * end_y));
+            }
+            i += 2;
+        }
+    }
+    move polygon
+}
+
+/// How much of each pixel of a `width` by `height` grid the polygons cover,
+/// by the non-zero winding rule.
+fn fill_path(polygons: &[~[(float, float)]], width: uint, height: uint) -> ~[float] {
+    let mut coverage = vec::from_elem(width * height, 0f);
+    for uint::range(0, height) |row| {
+        for uint::range(0, SUBSAMPLES) |sample| {
+            let sy = row as float + (sample as float + 0.5f) / SUBSAMPLES as float;
+            let mut crossings = ~[];
+            for polygons.each |polygon| {
+                let n = polygon.len();
+                for uint::range(0, n) |i| {
+                    let (x0, y0) = polygon[i];
+                    let (x1, y1) = polygon[(i + 1) % n];
+                    if (y0 <= sy && sy < y1) || (y1 <= sy && sy < y0) {
+                        let x = x0 + (sy - y0) * (x1 - x0) / (y1 - y0);
+                        crossings.push((x, if y1 > y0 { 1 } else { -1 }));
+                    }
+                }
+            }
+            std::sort::quick_sort(crossings, |a, b| {
+                let (xa, _) = *a;
+                let (xb, _) = *b;
+                xa <= xb
+            });
+
+            let mut winding = 0;
+            let mut span_start = 0f;
+            for crossings.each |crossing| {
+                let (x, direction) = *crossing;
+                let was_inside = winding != 0;
+                winding += direction;
+                if !was_inside && winding != 0 {
+                    span_start = x;
+                } else if was_inside && winding == 0 {
+                    // Partly covered pixels at the ends get what they overlap
+                    let from = fmax(span_start, 0f);
+                    let to = fmin(x, width as float);
+                    if to > from {
+                        for uint::range(float::floor(from) as uint, float::ceil(to) as uint) |px| {
+                            let overlap = fmin(to, (px + 1) as float) - fmax(from, px as float);
+                            coverage[row * width + px] += overlap / SUBSAMPLES as float;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    move coverage
+}
+
+// What a colour glyph is drawn from
+enum ColorGlyphRoot {
+    // A version 1 paint, by its offset in COLR
+    PaintRoot(uint),
+    // Version 0 layer records, by the first and the count
+    LayerRoot(uint, uint),
+}
+
+struct PaintContext {
+    palette: ~[Rgba],
+    foreground: Rgba,
+    // How many paints have been drawn so far
+    mut painted: uint,
+    // The paints being drawn, from the root down
+    mut active: ~[uint],
+}
+
+impl PaintContext {
+    fn color(&self, index: u16, alpha: float) -> Rgba {
+        let color = if index == FOREGROUND {
+            self.foreground
+        } else if (index as uint) < self.palette.len() {
+            self.palette[index]
+        } else {
+            transparent()
+        };
+        color.scale(alpha)
+    }
+}
+
+/// A glyph drawn in colour, as premultiplied BGRA.
+pub struct ColorGlyph {
+    bitmap: ~[u8],
+    width: u32,
+    height: u32,
+    // offset from the pen position to the top left of the bitmap
+    bearing_x: i32,
+    bearing_y: i32,
+}
+
+/// Draws the glyphs of a font that has a `COLR` table, version 0 or 1.
+pub struct ColrV1Renderer {
+    priv colr: ~[u8],
+    priv palettes: ~[~[Rgba]],
+    priv glyf: ~[u8],
+    priv loca: ~[u8],
+    priv long_loca: bool,
+    priv units_per_em: float,
+    // The bounding box of every glyph in the font, from head
+    priv font_bounds: (float, float, float, float),
+}
+
+/// A renderer for `font`, or None if it has no colour glyphs.
+pub fn ColrV1Renderer(font: &[u8]) -> Option<ColrV1Renderer> {
+    let colr = match sfnt_table(font, tag("COLR")) {
+        Some(move colr) => move colr,
+        None => return None
+    };
+    let palettes = match sfnt_table(font, tag("CPAL")) {
+        Some(move cpal) => parse_cpal(cpal),
+        None => ~[]
+    };
+    let head = sfnt_table(font, tag("head")).get_default(~[]);
+    Some(renderer_from_tables(move colr, move palettes, head,
+                              sfnt_table(font, tag("glyf")).get_default(~[]),
+                              sfnt_table(font, tag("loca")).get_default(~[])))
+}
+
+fn renderer_from_tables(colr: ~[u8], palettes: ~[~[Rgba]], head: &[u8], glyf: ~[u8],
+                        loca: ~[u8]) -> ColrV1Renderer {
+    let units_per_em = u16_at(head, 18);
+    ColrV1Renderer {
+        colr: move colr,
+        palettes: move palettes,
+        glyf: move glyf,
+        loca: move loca,
+        long_loca: u16_at(head, 50) != 0,
+        units_per_em: if units_per_em == 0 { 1000f } else { units_per_em as float },
+        font_bounds: (fword_at(head, 36), fword_at(head, 38),
+                      fword_at(head, 40), fword_at(head, 42))
+    }
+}
+
+impl ColrV1Renderer {
+    priv fn version(&self) -> u16 {
+        u16_at(self.colr, 0)
+    }
+
+    // The paint for `glyph` in BaseGlyphList, whose records are sorted by glyph
+    priv fn base_paint(&self, glyph: u16) -> Option<uint> {
+        let colr: &[u8] = self.colr;
+        if self.version() < 1 || u32_at(colr, 14) == 0 {
+            return None;
+        }
+        let list = u32_at(colr, 14) as uint;
+        let (mut low, mut high) = (0, u32_at(colr, list) as uint);
+        while low < high {
+            let mid = (low + high) / 2;
+            let record = list + 4 + 6 * mid;
+            let record_glyph = u16_at(colr, record);
+            if record_glyph == glyph {
+                return Some(list + u32_at(colr, record + 2) as uint);
+            } else if record_glyph < glyph {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        None
+    }
+
+    // The layers for `glyph` in the version 0 base glyph records
+    priv fn base_layers(&self, glyph: u16) -> Option<(uint, uint)> {
+        let colr: &[u8] = self.colr;
+        let records = u32_at(colr, 4) as uint;
+        let (mut low, mut high) = (0, u16_at(colr, 2) as uint);
+        while low < high {
+            let mid = (low + high) / 2;
+            let record = records + 6 * mid;
+            let record_glyph = u16_at(colr, record);
+            if record_glyph == glyph {
+                return Some((u16_at(colr, record + 2) as uint, u16_at(colr, record + 4) as uint));
+            } else if record_glyph < glyph {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        None
+    }
+
+    priv fn root(&self, glyph: u16) -> Option<ColorGlyphRoot> {
+        match self.base_paint(glyph) {
+            Some(paint) => Some(PaintRoot(paint)),
+            None => match self.base_layers(glyph) {
+                Some((first, count)) => Some(LayerRoot(first, count)),
+                None => None
+            }
+        }
+    }
+
+    /// Whether the font has a colour version of `glyph`.
+    pub fn has_color_glyph(&self, glyph: u16) -> bool {
+        self.root(glyph).is_some()
+    }
+
+    // The paint at `index` in LayerList
+    priv fn layer_paint(&self, index: uint) -> Option<uint> {
+        let colr: &[u8] = self.colr;
+        let list = u32_at(colr, 18) as uint;
+        if list == 0 || index >= u32_at(colr, list) as uint {
+            return None;
+        }
+        Some(list + u32_at(colr, list + 4 + 4 * index) as uint)
+    }
+
+    // The clip box ClipList gives `glyph`, if any
+    priv fn clip_box(&self, glyph: u16) -> Option<(float, float, float, float)> {
+        let colr: &[u8] = self.colr;
+        if self.version() < 1 || u32_at(colr, 22) == 0 {
+            return None;
+        }
+        let list = u32_at(colr, 22) as uint;
+        for uint::range(0, u32_at(colr, list + 1) as uint) |i| {
+            let record = list + 5 + 7 * i;
+            if u16_at(colr, record) <= glyph && glyph <= u16_at(colr, record + 2) {
+                let clip = list + u24_at(colr, record + 4) as uint;
+                return Some((fword_at(colr, clip + 1), fword_at(colr, clip + 3),
+                             fword_at(colr, clip + 5), fword_at(colr, clip + 7)));
+            }
+        }
+        None
+    }
+
+    // Where `glyph`'s outline is in glyf, if it has one
+    priv fn glyph_range(&self, glyph: u16) -> Option<(uint, uint)> {
+        let loca: &[u8] = self.loca;
+        let i = glyph as uint;
+        let (start, end) = if self.long_loca {
+            (u32_at(loca, 4 * i) as uint, u32_at(loca, 4 * i + 4) as uint)
+        } else {
+            (2 * u16_at(loca, 2 * i) as uint, 2 * u16_at(loca, 2 * i + 2) as uint)
+        };
+        if start >= end || end > self.glyf.len() { None } else { Some((start, end)) }
+    }
+
+    priv fn glyph_bounds(&self, glyph: u16) -> Option<(float, float, float, float)> {
+        let glyf: &[u8] = self.glyf;
+        do self.glyph_range(glyph).map |range| {
+            let (start, _) = *range;
+            (fword_at(glyf, start + 2), fword_at(glyf, start + 4),
+             fword_at(glyf, start + 6), fword_at(glyf, start + 8))
+        }
+    }
+
+    /// Adds the contours of `glyph` under `transform` to `contours`, going
+    /// through the components of composite glyphs.
+    priv fn contours(&self, glyph: u16, transform: &Matrix, depth: uint,
+                     contours: &mut ~[~[(float, float, bool)]]) {
+        let glyf: &[u8] = self.glyf;
+        let start = match self.glyph_range(glyph) {
+            Some((start, _)) => start,
+            None => return
+        };
+        if depth > MAX_DEPTH {
+            return;
+        }
+        let num_contours = u16_at(glyf, start) as i16;
+        if num_contours < 0 {
+            let mut pos = start + 10;
+            loop {
+                let flags = u16_at(glyf, pos);
+                let component = u16_at(glyf, pos + 2);
+                pos += 4;
+                let (arg1, arg2) = if flags & ARGS_ARE_WORDS != 0 {
+                    pos += 4;
+                    (fword_at(glyf, pos - 4), fword_at(glyf, pos - 2))
+                } else {
+                    pos += 2;
+                    ((u8_at(glyf, pos - 2) as i8) as float, (u8_at(glyf, pos - 1) as i8) as float)
+                };
+                let mut m = identity();
+                if flags & HAVE_SCALE != 0 {
+                    let scale = f2dot14_at(glyf, pos);
+                    m = scaling(scale, scale);
+                    pos += 2;
+                } else if flags & HAVE_X_AND_Y_SCALE != 0 {
+                    m = scaling(f2dot14_at(glyf, pos), f2dot14_at(glyf, pos + 2));
+                    pos += 4;
+                } else if flags & HAVE_TWO_BY_TWO != 0 {
+                    m.xx = f2dot14_at(glyf, pos);
+                    m.yx = f2dot14_at(glyf, pos + 2);
+                    m.xy = f2dot14_at(glyf, pos + 4);
+                    m.yy = f2dot14_at(glyf, pos + 6);
+                    pos += 8;
+                }
+                // TODO: components placed by matching points
+                if flags & ARGS_ARE_XY_VALUES != 0 {
+                    m.dx = arg1;
+                    m.dy = arg2;
+                }
+                self.contours(component, &transform.multiply(&m), depth + 1, contours);
+                if flags & MORE_COMPONENTS == 0 {
+                    break;
+                }
+            }
+            return;
+        }
+
+        let num_contours = num_contours as uint;
+        let end_points = vec::from_fn(num_contours, |i| u16_at(glyf, start + 10 + 2 * i) as uint);
+        let num_points = if num_contours == 0 { 0 } else { end_points.last() + 1 };
+        let instructions = u16_at(glyf, start + 10 + 2 * num_contours) as uint;
+        let mut pos = start + 12 + 2 * num_contours + instructions;
+
+        let mut flags = ~[];
+        while flags.len() < num_points {
+            let flag = u8_at(glyf, pos);
+            pos += 1;
+            flags.push(flag);
+            if flag & REPEAT != 0 {
+                for (u8_at(glyf, pos) as uint).times {
+                    flags.push(flag);
+                }
+                pos += 1;
+            }
+        }
+
+        let mut xs = ~[];
+        let mut x = 0f;
+        for uint::range(0, num_points) |i| {
+            let flag = flags[i];
+            if flag & X_SHORT != 0 {
+                let delta = u8_at(glyf, pos) as float;
+                pos += 1;
+                x += if flag & X_SAME_OR_POSITIVE != 0 { delta } else { -delta };
+            } else if flag & X_SAME_OR_POSITIVE == 0 {
+                x += fword_at(glyf, pos);
+                pos += 2;
+            }
+            xs.push(x);
+        }
+        let mut ys = ~[];
+        let mut y = 0f;
+        for uint::range(0, num_points) |i| {
+            let flag = flags[i];
+            if flag & Y_SHORT != 0 {
+                let delta = u8_at(glyf, pos) as float;
+                pos += 1;
+                y += if flag & Y_SAME_OR_POSITIVE != 0 { delta } else { -delta };
+            } else if flag & Y_SAME_OR_POSITIVE == 0 {
+                y += fword_at(glyf, pos);
+                pos += 2;
+            }
+            ys.push(y);
+        }
+
+        let mut first = 0;
+        for end_points.each |end| {
+            let mut contour = ~[];
+            for uint::range(first, *end + 1) |i| {
+                let (px, py) = transform.apply(xs[i], ys[i]);
+                contour.push((px, py, flags[i] & ON_CURVE != 0));
+            }
+            contours.push(move contour);
+            first = *end + 1;
+        }
+    }
+
+    // How much of each pixel of `layer` the outline of `glyph` covers
+    priv fn glyph_coverage(&self, glyph: u16, transform: &Matrix, layer: &Layer) -> ~[float] {
+        let mut contours = ~[];
+        self.contours(glyph, transform, 0, &mut contours);
+        let polygons = contours.map(|contour| flatten(*contour));
+        fill_path(polygons, layer.width, layer.height)
+    }
+
+    priv fn color_line(&self, ctx: &PaintContext, offset: uint, variable: bool) -> ColorLine {
+        let colr: &[u8] = self.colr;
+        let extend = match u8_at(colr, offset) {
+            1 => ExtendRepeat,
+            2 => ExtendReflect,
+            _ => ExtendPad
+        };
+        // Variable stops have a variation index after them
+        let stop_size = if variable { 10 } else { 6 };
+        let mut stops = do vec::from_fn(u16_at(colr, offset + 1) as uint) |i| {
+            let stop = offset + 3 + stop_size * i;
+            (f2dot14_at(colr, stop), ctx.color(u16_at(colr, stop + 2), f2dot14_at(colr, stop + 4)))
+        };
+        std::sort::quick_sort(stops, |a, b| {
+            let (offset_a, _) = *a;
+            let (offset_b, _) = *b;
+            offset_a <= offset_b
+        });
+        ColorLine { extend: extend, stops: move stops }
+    }
+
+    /**
+    Paints the paint at `offset` in COLR onto `layer`, with `transform`
+    taking glyph space to the layer. Variable paints are drawn at the
+    font's default instance.
+    */
+    priv fn paint(&self, ctx: &PaintContext, offset: uint, transform: &Matrix, layer: &mut Layer,
+                  depth: uint) {
+        let colr: &[u8] = self.colr;
+        if depth > MAX_DEPTH || offset >= colr.len() || ctx.painted >= MAX_PAINTS {
+            return;
+        }
+        // A paint that contains itself is drawn once
+        if ctx.active.contains(&offset) {
+            debug!("COLR paint at %u refers to itself", offset);
+            return;
+        }
+        ctx.painted += 1;
+        ctx.active.push(offset);
+        self.paint_format(ctx, offset, transform, layer, depth);
+        ctx.active.pop();
+    }
+
+    priv fn paint_format(&self, ctx: &PaintContext, offset: uint, transform: &Matrix,
+                         layer: &mut Layer, depth: uint) {
+        let colr: &[u8] = self.colr;
+        let format = u8_at(colr, offset);
+        // Each variable format is one after the format it varies
+        let variable = format % 2 == 1 && format > 1 && format != 11 && format < 32;
+        let base = if variable { format - 1 } else { format };
+        let child = offset + u24_at(colr, offset + 1) as uint;
+
+        match base {
+            // PaintColrLayers
+            1 => {
+                let first = u32_at(colr, offset + 2) as uint;
+                for uint::range(first, first + u8_at(colr, offset + 1) as uint) |i| {
+                    match self.layer_paint(i) {
+                        Some(paint) => self.paint(ctx, paint, transform, layer, depth + 1),
+                        None => ()
+                    }
+                }
+            }
+            // PaintSolid
+            2 => {
+                let color = ctx.color(u16_at(colr, offset + 1), f2dot14_at(colr, offset + 3));
+                for uint::range(0, layer.pixels.len()) |i| {
+                    layer.pixels[i] = composite(COMPOSITE_SRC_OVER, color, layer.pixels[i]);
+                }
+            }
+            // PaintLinearGradient, PaintRadialGradient, PaintSweepGradient
+            4 | 6 | 8 => {
+                let line = self.color_line(ctx, child, variable);
+                let p = |i: uint| fword_at(colr, offset + 4 + 2 * i);
+                let gradient = match base {
+                    4 => linear_gradient(p(0), p(1), p(2), p(3), p(4), p(5)),
+                    6 => {
+                        Radial(p(0), p(1), u16_at(colr, offset + 8) as float,
+                               p(3), p(4), u16_at(colr, offset + 14) as float)
+                    }
+                    _ => {
+                        Sweep(p(0), p(1), f2dot14_at(colr, offset + 8) * 180f,
+                              f2dot14_at(colr, offset + 10) * 180f)
+                    }
+                };
+                fill_gradient(layer, transform, &line, &gradient);
+            }
+            // PaintGlyph: the child, clipped to an outline
+            10 => {
+                let coverage = self.glyph_coverage(u16_at(colr, offset + 4), transform, layer);
+                let mut content = Layer(layer.width, layer.height);
+                self.paint(ctx, child, transform, &mut content, depth + 1);
+                for uint::range(0, layer.pixels.len()) |i| {
+                    let src = content.pixels[i].scale(fmin(coverage[i], 1f));
+                    layer.pixels[i] = composite(COMPOSITE_SRC_OVER, src, layer.pixels[i]);
+                }
+            }
+            // PaintColrGlyph
+            11 => {
+                match self.base_paint(u16_at(colr, offset + 1)) {
+                    Some(paint) => self.paint(ctx, paint, transform, layer, depth + 1),
+                    None => ()
+                }
+            }
+            // PaintTransform and its shorthands
+            12 | 14 | 16 | 18 | 20 | 22 | 24 | 26 | 28 | 30 => {
+                let m = transform.multiply(&paint_transform(colr, offset, base));
+                self.paint(ctx, child, &m, layer, depth + 1);
+            }
+            // PaintComposite
+            32 => {
+                let backdrop_paint = offset + u24_at(colr, offset + 5) as uint;
+                let mut backdrop = Layer(layer.width, layer.height);
+                self.paint(ctx, backdrop_paint, transform, &mut backdrop, depth + 1);
+                let mut source = Layer(layer.width, layer.height);
+                self.paint(ctx, child, transform, &mut source, depth + 1);
+                let mode = u8_at(colr, offset + 4);
+                for uint::range(0, layer.pixels.len()) |i| {
+                    let result = composite(mode, source.pixels[i], backdrop.pixels[i]);
+                    layer.pixels[i] = composite(COMPOSITE_SRC_OVER, result, layer.pixels[i]);
+                }
+            }
+            _ => debug!("unknown COLR paint format %u", format as uint)
+        }
+    }
+
+    // The bounds of `glyph` in glyph space
+    priv fn bounds(&self, glyph: u16, root: &ColorGlyphRoot) -> (float, float, float, float) {
+        match self.clip_box(glyph) {
+            Some(bounds) => return bounds,
+            None => ()
+        }
+        match *root {
+            PaintRoot(_) => self.font_bounds,
+            LayerRoot(first, count) => {
+                let records = u32_at(self.colr, 8) as uint;
+                let mut bounds = None;
+                for uint::range(first, first + count) |i| {
+                    match self.glyph_bounds(u16_at(self.colr, records + 4 * i)) {
+                        Some((x0, y0, x1, y1)) => {
+                            bounds = match bounds {
+                                Some((bx0, by0, bx1, by1)) => {
+                                    Some((fmin(x0, bx0), fmin(y0, by0),
+                                          fmax(x1, bx1), fmax(y1, by1)))
+                                }
+                                None => Some((x0, y0, x1, y1))
+                            };
+                        }
+                        None => ()
+                    }
+                }
+                bounds.get_default((0f, 0f, 0f, 0f))
+            }
+        }
+    }
+
+    /**
+    Draws `glyph` at `pt_size` in the colours of palette `palette`, with
+    `foreground` where the font asks for the text colour. None if the font
+    has no colour version of the glyph.
+    */
+    pub fn render(&self, glyph: u16, pt_size: float, palette: uint,
+                  foreground: Rgba) -> Option<ColorGlyph> {
+        let root = match self.root(glyph) {
+            Some(root) => root,
+            None => return None
+        };
+        let (x_min, y_min, x_max, y_max) = self.bounds(glyph, &root);
+        let scale = pt_size / self.units_per_em;
+        let left = float::floor(x_min * scale);
+        let top = float::ceil(y_max * scale);
+        let width = fmax(float::ceil(x_max * scale) - left, 0f);
+        let height = fmax(top - float::floor(y_min * scale), 0f);
+        if width * height > MAX_GLYPH_PIXELS as float {
+            debug!("colour glyph %u is too big to draw: %? by %?", glyph as uint, width, height);
+            return None;
+        }
+        let (width, height) = (width as uint, height as uint);
+        // Glyph space has y going up
+        let transform = Matrix { xx: scale, yx: 0f, xy: 0f, yy: -scale, dx: -left, dy: top };
+
+        let ctx = PaintContext {
+            palette: if palette < self.palettes.len() { copy self.palettes[palette] } else { ~[] },
+            foreground: foreground,
+            painted: 0,
+            active: ~[]
+        };
+        let mut layer = Layer(width, height);
+        match root {
+            PaintRoot(paint) => self.paint(&ctx, paint, &transform, &mut layer, 0),
+            LayerRoot(first, count) => {
+                let records = u32_at(self.colr, 8) as uint;
+                for uint::range(first, first + count) |i| {
+                    let record = records + 4 * i;
+                    let color = ctx.color(u16_at(self.colr, record + 2), 1f);
+                    let coverage = self.glyph_coverage(u16_at(self.colr, record), &transform,
+                                                       &layer);
+                    for uint::range(0, layer.pixels.len()) |j| {
+                        let src = color.scale(fmin(coverage[j], 1f));
+                        layer.pixels[j] = composite(COMPOSITE_SRC_OVER, src, layer.pixels[j]);
+                    }
+                }
+            }
+        }
+
+        let to_u8 = |v: float| (fmin(fmax(v, 0f), 1f) * 255f + 0.5f) as u8;
+        let mut bitmap = ~[];
+        for layer.pixels.each |pixel| {
+            bitmap.push_all(~[to_u8(pixel.b), to_u8(pixel.g), to_u8(pixel.r), to_u8(pixel.a)]);
+        }
+        Some(ColorGlyph {
+            bitmap: move bitmap,
+            width: width as u32,
+            height: height as u32,
+            bearing_x: left as i32,
+            bearing_y: top as i32
+        })
+    }
+}
+
+// The transform a transforming paint of format `base` applies to its child
+fn paint_transform(colr: &[u8], offset: uint, base: u8) -> Matrix {
+    let f2dot14 = |i: uint| f2dot14_at(colr, offset + 4 + 2 * i);
+    let fword = |i: uint| fword_at(colr, offset + 4 + 2 * i);
+    let rotation = |turns: float| {
+        let angle = turns * float::consts::pi;
+        let (cos, sin) = (float::cos(angle), float::sin(angle));
+        Matrix { xx: cos, yx: sin, xy: -sin, yy: cos, dx: 0f, dy: 0f }
+    };
+    let skew = |x: float, y: float| {
+        Matrix {
+            xx: 1f, yx: float::tan(y * float::consts::pi),
+            xy: -float::tan(x * float::consts::pi), yy: 1f,
+            dx: 0f, dy: 0f
+        }
+    };
+    match base {
+        // Affine2x3, in 16.16 fixed point
+        12 => {
+            let affine = offset + u24_at(colr, offset + 4) as uint;
+            Matrix {
+                xx: fixed_at(colr, affine), yx: fixed_at(colr, affine + 4),
+                xy: fixed_at(colr, affine + 8), yy: fixed_at(colr, affine + 12),
+                dx: fixed_at(colr, affine + 16), dy: fixed_at(colr, affine + 20)
+            }
+        }
+        14 => translation(fword(0), fword(1)),
+        16 => scaling(f2dot14(0), f2dot14(1)),
+        18 => scaling(f2dot14(0), f2dot14(1)).around(fword(2), fword(3)),
+        20 => scaling(f2dot14(0), f2dot14(0)),
+        22 => scaling(f2dot14(0), f2dot14(0)).around(fword(1), fword(2)),
+        24 => rotation(f2dot14(0)),
+        26 => rotation(f2dot14(0)).around(fword(1), fword(2)),
+        28 => skew(f2dot14(0), f2dot14(1)),
+        30 => skew(f2dot14(0), f2dot14(1)).around(fword(2), fword(3)),
+        _ => identity()
+    }
+}
+
+#[cfg(test)]
+mod color_font_tests {
+    fn close(a: float, b: float) -> bool {
+        float::abs(a - b) < 0.01f
+    }
+
+    fn be16(v: u16) -> ~[u8] {
+        ~[(v >> 8) as u8, v as u8]
+    }
+
+    fn be32(v: u32) -> ~[u8] {
+        be16((v >> 16) as u16) + be16(v as u16)
+    }
+
+    #[test]
+    fn test_parse_cpal() {
+        let cpal = be16(0) + be16(2) + be16(1) + be16(2) + be32(14) + be16(0) +
+                   ~[0, 0, 255, 255, 255, 0, 0, 128];
+        let palettes = parse_cpal(cpal);
+        assert palettes.len() == 1;
+        assert palettes[0].len() == 2;
+        let red = palettes[0][0];
+        assert close(red.r, 1f) && close(red.g, 0f) && close(red.a, 1f);
+        // Premultiplied
+        let blue = palettes[0][1];
+        assert close(blue.b, 0.5f) && close(blue.a, 0.5f) && close(blue.r, 0f);
+    }
+
+    #[test]
+    fn test_fill_path() {
+        let square = ~[(1f, 1f), (3f, 1f), (3f, 3f), (1f, 3f)];
+        let coverage = fill_path(~[copy square], 4, 4);
+        assert close(coverage[0], 0f);
+        assert close(coverage[1 * 4 + 1], 1f);
+        assert close(coverage[2 * 4 + 2], 1f);
+        assert close(coverage[3 * 4 + 3], 0f);
+
+        let half = ~[(0.5f, 0f), (2f, 0f), (2f, 1f), (0.5f, 1f)];
+        let coverage = fill_path(~[half], 2, 1);
+        assert close(coverage[0], 0.5f);
+        assert close(coverage[1], 1f);
+
+        // Wound the same way twice, it's still only covered once
+        let coverage = fill_path(~[copy square, copy square], 4, 4);
+        assert close(coverage[1 * 4 + 1], 1f);
+    }
+
+    #[test]
+    fn test_color_line() {
+        let stops = ~[(0f, rgba(0, 0, 0, 255)), (1f, rgba(255, 255, 255, 255))];
+        let pad = ColorLine { extend: ExtendPad, stops: copy stops };
+        assert close(pad.at(0.5f).r, 0.5f);
+        assert close(pad.at(1.5f).r, 1f);
+        assert close(pad.at(-1f).r, 0f);
+        let repeat = ColorLine { extend: ExtendRepeat, stops: copy stops };
+        assert close(repeat.at(1.25f).r, 0.25f);
+        let reflect = ColorLine { extend: ExtendReflect, stops: copy stops };
+        assert close(reflect.at(1.25f).r, 0.75f);
+        assert close(reflect.at(-0.25f).r, 0.25f);
+    }
+
+    #[test]
+    fn test_composite() {
+        let red = rgba(255, 0, 0, 255);
+        let half_blue = rgba(0, 0, 255, 128);
+        let src_in = composite(5, red, half_blue);
+        assert close(src_in.r, 0.5f) && close(src_in.a, 0.5f) && close(src_in.b, 0f);
+        let xor = composite(COMPOSITE_XOR, red, rgba(0, 255, 0, 255));
+        assert close(xor.a, 0f);
+        let white = rgba(255, 255, 255, 255);
+        let multiply = composite(COMPOSITE_MULTIPLY, rgba(255, 128, 0, 255), white);
+        assert close(multiply.r, 1f) && close(multiply.g, 0.5f) && close(multiply.b, 0f);
+    }
+
+    #[test]
+    fn test_gradients() {
+        // Along x, whatever p1's y, since p0p2 is vertical
+        let linear = linear_gradient(0f, 0f, 10f, 5f, 0f, 10f);
+        assert close(gradient_t(&linear, 5f, 100f).get(), 0.5f);
+        // Circles at the same centre, radius 0 to 10
+        let radial = Radial(0f, 0f, 0f, 0f, 0f, 10f);
+        assert close(gradient_t(&radial, 0f, 5f).get(), 0.5f);
+        let sweep = Sweep(0f, 0f, 0f, 360f);
+        assert close(gradient_t(&sweep, 0f, 1f).get(), 0.25f);
+    }
+
+    // A font of one square glyph, 100 units across, painted red in COLR v1
+    fn square_renderer() -> ColrV1Renderer {
+        // PaintGlyph of glyph 1, then PaintSolid of palette entry 0
+        renderer_with_paints(~[10, 0, 0, 6] + be16(1) + ~[2] + be16(0) + be16(0x4000))
+    }
+
+    // The square font, with glyph 1 drawn by `paints`
+    fn renderer_with_paints(paints: ~[u8]) -> ColrV1Renderer {
+        let glyf = be16(1) + be16(0) + be16(0) + be16(100) + be16(100) + be16(3) + be16(0) +
+                   ~[0x31, 0x33, 0x35, 0x23, 100, 100, 100, 0];
+        let loca = be16(0) + be16(0) + be16(11);
+        let mut head = vec::from_elem(54, 0u8);
+        head[19] = 100;
+        head[41] = 100;
+        head[43] = 100;
+        let header = be16(1) + be16(0) + be32(0) + be32(0) + be16(0) + be32(34) + be32(0) +
+                     be32(0) + be32(0) + be32(0);
+        // BaseGlyphList, with glyph 1's paint 10 bytes in
+        let base_glyphs = be32(1) + be16(1) + be32(10);
+        let palettes = ~[~[rgba(255, 0, 0, 255)]];
+        renderer_from_tables(header + base_glyphs + paints, palettes, head, glyf, loca)
+    }
+
+    #[test]
+    fn test_render_paint_glyph() {
+        let renderer = square_renderer();
+        assert renderer.has_color_glyph(1);
+        assert !renderer.has_color_glyph(0);
+
+        let glyph = renderer.render(1, 10f, 0, rgba(0, 0, 0, 255)).get();
+        assert glyph.width == 10 && glyph.height == 10;
+        assert glyph.bearing_x == 0 && glyph.bearing_y == 10;
+        assert vec::slice(glyph.bitmap, 0, 4) == ~[0, 0, 255, 255];
+        assert vec::slice(glyph.bitmap, 396, 400) == ~[0, 0, 255, 255];
+    }
+
+    #[test]
+    fn test_render_limits() {
+        // PaintColrGlyph of glyph 1, which is this paint again
+        let renderer = renderer_with_paints(~[11] + be16(1));
+        let glyph = renderer.render(1, 10f, 0, rgba(0, 0, 0, 255)).get();
+        assert glyph.bitmap.all(|v| *v == 0);
+
+        assert square_renderer().render(1, 10000f, 0, rgba(0, 0, 0, 255)).is_none();
+    }
+}
@@ -0,0 +1,231 @@
+/*!
+Emoji presentation. Many characters can be drawn either as text or as an
+emoji, and a variation selector after one asks for a presentation: VS15
+for text and VS16 for emoji. Without one, characters with the Unicode
+`Emoji_Presentation` property are emoji and everything else is text.
+
+A font that has both glyphs for a character maps the variation sequences
+to them in its `cmap` format 14 subtable.
+*/
+
+use glyph::GlyphIndex;
+use variable::{tag, sfnt_table, u8_at, u16_at, u24_at, u32_at};
+
+pub const TEXT_SELECTOR: char = '\uFE0E';
+pub const EMOJI_SELECTOR: char = '\uFE0F';
+
+pub enum Presentation {
+    TextPresentation,
+    EmojiPresentation,
+}
+
+impl Presentation : cmp::Eq {
+    pure fn eq(&self, other: &Presentation) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &Presentation) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// Whether `ch` has the `Emoji_Presentation` property, as of Unicode 15.
+pub pure fn is_emoji_presentation(ch: char) -> bool {
+    match ch as uint {
+        0x231A .. 0x231B | 0x23E9 .. 0x23EC | 0x23F0 | 0x23F3 | 0x25FD .. 0x25FE |
+        0x2614 .. 0x2615 | 0x2648 .. 0x2653 | 0x267F | 0x2693 | 0x26A1 | 0x26AA .. 0x26AB |
+        0x26BD .. 0x26BE | 0x26C4 .. 0x26C5 | 0x26CE | 0x26D4 | 0x26EA | 0x26F2 .. 0x26F3 |
+        0x26F5 | 0x26FA | 0x26FD | 0x2705 | 0x270A .. 0x270B | 0x2728 | 0x274C | 0x274E |
+        0x2753 .. 0x2755 | 0x2757 | 0x2795 .. 0x2797 | 0x27B0 | 0x27BF | 0x2B1B .. 0x2B1C |
+        0x2B50 | 0x2B55 => true,
+        0x1F004 | 0x1F0CF | 0x1F18E | 0x1F191 .. 0x1F19A | 0x1F1E6 .. 0x1F1FF | 0x1F201 |
+        0x1F21A | 0x1F22F | 0x1F232 .. 0x1F236 | 0x1F238 .. 0x1F23A | 0x1F250 .. 0x1F251 |
+        0x1F300 .. 0x1F320 | 0x1F32D .. 0x1F335 | 0x1F337 .. 0x1F37C | 0x1F37E .. 0x1F393 |
+        0x1F3A0 .. 0x1F3CA | 0x1F3CF .. 0x1F3D3 | 0x1F3E0 .. 0x1F3F0 | 0x1F3F4 |
+        0x1F3F8 .. 0x1F43E | 0x1F440 | 0x1F442 .. 0x1F4FC | 0x1F4FF .. 0x1F53D |
+        0x1F54B .. 0x1F54E | 0x1F550 .. 0x1F567 | 0x1F57A | 0x1F595 .. 0x1F596 | 0x1F5A4 |
+        0x1F5FB .. 0x1F64F | 0x1F680 .. 0x1F6C5 | 0x1F6CC | 0x1F6D0 .. 0x1F6D2 |
+        0x1F6D5 .. 0x1F6D7 | 0x1F6DC .. 0x1F6DF | 0x1F6EB .. 0x1F6EC | 0x1F6F4 .. 0x1F6FC |
+        0x1F7E0 .. 0x1F7EB | 0x1F7F0 | 0x1F90C .. 0x1F93A | 0x1F93C .. 0x1F945 |
+        0x1F947 .. 0x1F9FF | 0x1FA70 .. 0x1FA7C | 0x1FA80 .. 0x1FA88 | 0x1FA90 .. 0x1FABD |
+        0x1FABF .. 0x1FAC5 | 0x1FACE .. 0x1FADB | 0x1FAE0 .. 0x1FAE8 | 0x1FAF0 .. 0x1FAF8 => true,
+        _ => false
+    }
+}
+
+/// How `base` is presented, given the variation selector after it, if any.
+pub pure fn presentation(base: char, selector: Option<char>) -> Presentation {
+    match selector {
+        Some(TEXT_SELECTOR) => TextPresentation,
+        Some(EMOJI_SELECTOR) => EmojiPresentation,
+        _ => if is_emoji_presentation(base) { EmojiPresentation } else { TextPresentation }
+    }
+}
+
+/// The variation selector that asks for `presentation`.
+pub pure fn selector_for(presentation: Presentation) -> char {
+    match presentation {
+        TextPresentation => TEXT_SELECTOR,
+        EmojiPresentation => EMOJI_SELECTOR
+    }
+}
+
+/// The presentation of the character at byte `i` of `text`, looking at
+/// the character after it.
+pub fn presentation_at(text: &str, i: uint) -> Presentation {
+    let {ch, next} = str::char_range_at(text, i);
+    let selector = if next < text.len() { Some(str::char_at(text, next)) } else { None };
+    presentation(ch, selector)
+}
+
+/// What a font maps a variation sequence to.
+pub enum VariantGlyph {
+    // The glyph the character has on its own
+    DefaultVariant,
+    Variant(GlyphIndex),
+    // The font doesn't know the sequence
+    NoVariant,
+}
+
+impl VariantGlyph : cmp::Eq {
+    pure fn eq(&self, other: &VariantGlyph) -> bool {
+        match (copy *self, copy *other) {
+            (DefaultVariant, DefaultVariant) | (NoVariant, NoVariant) => true,
+            (Variant(a), Variant(b)) => a == b,
+            _ => false
+        }
+    }
+    pure fn ne(&self, other: &VariantGlyph) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// The variation sequences of a font, from its `cmap` format 14 subtable.
+pub struct VariationSequences {
+    priv subtable: ~[u8],
+}
+
+/// The variation sequences in `font`, or None if it has none.
+pub fn VariationSequences(font: &[u8]) -> Option<VariationSequences> {
+    let cmap = match sfnt_table(font, tag("cmap")) {
+        Some(move cmap) => move cmap,
+        None => return None
+    };
+    // Format 14 is always Unicode platform, encoding 5
+    for uint::range(0, u16_at(cmap, 2) as uint) |i| {
+        let record = 4 + 8 * i;
+        if u16_at(cmap, record) == 0 && u16_at(cmap, record + 2) == 5 {
+            let offset = u32_at(cmap, record + 4) as uint;
+            if u16_at(cmap, offset) != 14 {
+                return None;
+            }
+            let end = uint::min(offset + u32_at(cmap, offset + 2) as uint, cmap.len());
+            return Some(VariationSequences { subtable: vec::slice(cmap, offset, end) });
+        }
+    }
+    None
+}
+
+impl VariationSequences {
+    /// The glyph the font has for `base` followed by `selector`.
+    fn lookup(&self, base: char, selector: char) -> VariantGlyph {
+        let data: &[u8] = self.subtable;
+        let base = base as u32;
+        let selector = selector as u32;
+        // Records of a selector and two tables, sorted by selector
+        let record = match binary_search(u32_at(data, 6) as uint, |i| {
+            u24_at(data, 10 + 11 * i)
+        }, selector) {
+            Some(i) => 10 + 11 * i,
+            None => return NoVariant
+        };
+
+        // Ranges of characters whose sequence gives their usual glyph
+        let default_uvs = u32_at(data, record + 3) as uint;
+        if default_uvs != 0 {
+            let count = u32_at(data, default_uvs) as uint;
+            let mut low = 0;
+            let mut high = count;
+            while low < high {
+                let mid = (low + high) / 2;
+                let range = default_uvs + 4 + 4 * mid;
+                let start = u24_at(data, range);
+                if base < start {
+                    high = mid;
+                } else if base > start + u8_at(data, range + 3) as u32 {
+                    low = mid + 1;
+                } else {
+                    return DefaultVariant;
+                }
+            }
+        }
+
+        let non_default_uvs = u32_at(data, record + 7) as uint;
+        if non_default_uvs != 0 {
+            match binary_search(u32_at(data, non_default_uvs) as uint, |i| {
+                u24_at(data, non_default_uvs + 4 + 5 * i)
+            }, base) {
+                Some(i) => {
+                    let glyph = u16_at(data, non_default_uvs + 4 + 5 * i + 3);
+                    return Variant(glyph as GlyphIndex);
+                }
+                None => ()
+            }
+        }
+        NoVariant
+    }
+}
+
+// The index among `count` sorted keys of the one equal to `key`
+fn binary_search(count: uint, key_at: fn(uint) -> u32, key: u32) -> Option<uint> {
+    let mut low = 0;
+    let mut high = count;
+    while low < high {
+        let mid = (low + high) / 2;
+        let mid_key = key_at(mid);
+        if mid_key == key {
+            return Some(mid);
+        } else if mid_key < key {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    None
+}
+
+#[test]
+fn test_presentation() {
+    // U+2764 HEAVY BLACK HEART is text unless asked for as emoji
+    assert presentation('\u2764', None) == TextPresentation;
+    assert presentation('\u2764', Some(EMOJI_SELECTOR)) == EmojiPresentation;
+    // U+1F600 GRINNING FACE is emoji unless asked for as text
+    assert presentation('\U0001F600', None) == EmojiPresentation;
+    assert presentation('\U0001F600', Some(TEXT_SELECTOR)) == TextPresentation;
+    assert presentation('a', None) == TextPresentation;
+
+    let text = "\u2764\uFE0F \u231A\uFE0E \u231A";
+    assert presentation_at(text, 0) == EmojiPresentation;
+    let watch = str::find_char(text, '\u231A').get();
+    assert presentation_at(text, watch) == TextPresentation;
+    assert presentation_at(text, str::rfind_char(text, '\u231A').get()) == EmojiPresentation;
+}
+
+#[test]
+fn test_variation_sequences() {
+    fn be16(v: uint) -> ~[u8] { ~[(v >> 8) as u8, v as u8] }
+    fn be24(v: uint) -> ~[u8] { ~[(v >> 16) as u8] + be16(v & 0xffff) }
+    fn be32(v: uint) -> ~[u8] { be16(v >> 16) + be16(v & 0xffff) }
+
+    // VS15: U+2600 to U+2603 keep their usual glyphs. VS16: U+2764 has glyph 7
+    let subtable = be16(14) + be32(49) + be32(2) +
+                   be24(0xFE0E) + be32(32) + be32(0) +
+                   be24(0xFE0F) + be32(0) + be32(40) +
+                   be32(1) + be24(0x2600) + ~[3] +
+                   be32(1) + be24(0x2764) + be16(7);
+    let sequences = VariationSequences { subtable: move subtable };
+    assert sequences.lookup('\u2601', TEXT_SELECTOR) == DefaultVariant;
+    assert sequences.lookup('\u2604', TEXT_SELECTOR) == NoVariant;
+    assert sequences.lookup('\u2764', EMOJI_SELECTOR) == Variant(7);
+    assert sequences.lookup('\u2765', EMOJI_SELECTOR) == NoVariant;
+    assert sequences.lookup('\u2764', '\uFE00') == NoVariant;
+}
@@ -2,6 +2,8 @@ pub use font_cache::FontCache;
 
 use au = gfx::geometry;
 use au::Au;
+use color_font::{ColrV1Renderer, ColorGlyph, Rgba, rgba};
+use emoji::{VariationSequences, DefaultVariant, Variant, NoVariant};
 use azure::{
     AzFloat,
     AzScaledFontRef,
@@ -12,10 +14,12 @@ use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
 use glyph::{GlyphStore, GlyphIndex};
+use glyph_cache::{FontId, GlyphCache, GlyphKey, RasterizedGlyph};
 use native_font::NativeFont;
 use servo_util::range::Range;
 use shaper::Shaper;
 use text::text_run::TextRun;
+use std::arc::ARC;
 
 // Used to abstract over the shaper's choice of fixed int representation.
 type FractionalPixel = float;
@@ -47,8 +51,12 @@ enum CSSFontWeight {
 struct FontStyle {
     pt_size: float,
     weight: CSSFontWeight,
+    // font-stretch, as a percentage
+    stretch: float,
     italic: bool,
     oblique: bool,
+    // font-variation-settings, as axis tags and values
+    variations: ~[(u32, float)],
 }
 
 struct FontFaceProperties {
@@ -82,6 +90,12 @@ struct Font {
     priv native_font: NativeFont,
     priv mut azure_font: Option<AzScaledFontRef>,
     priv mut shaper: Option<@Shaper>,
+    priv glyph_cache: GlyphCache,
+    // for fonts with a COLR table
+    priv color_renderer: Option<@ColrV1Renderer>,
+    // from the cmap format 14 subtable, if there is one
+    priv variation_sequences: Option<@VariationSequences>,
+    id: FontId,
     style: FontStyle,
     metrics: FontMetrics,
 
@@ -93,19 +107,38 @@ struct Font {
 
 impl Font {
     // TODO: who should own fontbuf?
-    static fn new(fontbuf: @~[u8], native_font: NativeFont, style: FontStyle) -> Font {
+    static fn new(fontbuf: @~[u8], native_font: NativeFont, style: FontStyle,
+                  id: FontId, glyph_cache: GlyphCache) -> Font {
         let metrics = native_font.get_metrics();
+        let color_renderer = match ColrV1Renderer(*fontbuf) {
+            Some(move renderer) => Some(@move renderer),
+            None => None
+        };
+        let variation_sequences = match VariationSequences(*fontbuf) {
+            Some(move sequences) => Some(@move sequences),
+            None => None
+        };
 
         Font {
             fontbuf : fontbuf,
             native_font : move native_font,
             azure_font: None,
             shaper: None,
+            glyph_cache: move glyph_cache,
+            color_renderer: color_renderer,
+            variation_sequences: variation_sequences,
+            id: id,
             style: move style,
             metrics: move metrics,
         }
     }
 
+    /// The bytes of the font file. Glyphs are in the glyph cache, which
+    /// fonts share.
+    fn heap_size() -> uint {
+        self.fontbuf.len()
+    }
+
     priv fn get_shaper(@self) -> @Shaper {
         // fast path: already created a shaper
         match self.shaper {
@@ -216,19 +249,28 @@ impl Font {
 
 // Public API
 pub trait FontMethods {
-    fn draw_text_into_context(rctx: &RenderContext, run: &TextRun, range: Range, baseline_origin: Point2D<Au>);
+    fn draw_text_into_context(rctx: &RenderContext, run: &TextRun, range: Range, baseline_origin: Point2D<Au>,
+                              r: u8, g: u8, b: u8);
     fn measure_text(&TextRun, Range) -> RunMetrics;
-    fn shape_text(@self, &str) -> GlyphStore;
+    fn shape_text(@self, &str, levels: &[u8]) -> GlyphStore;
 
     fn buf(&self) -> @~[u8];
     // these are used to get glyphs and advances in the case that the
     // shaper can't figure it out.
     fn glyph_index(char) -> Option<GlyphIndex>;
+    // the glyph for a character followed by a variation selector
+    fn variant_glyph_index(char, selector: char) -> Option<GlyphIndex>;
     fn glyph_h_advance(GlyphIndex) -> FractionalPixel;
+    // rasterizes a glyph at this font's size, or finds it in the glyph cache
+    fn rasterize_glyph(GlyphIndex) -> Option<ARC<RasterizedGlyph>>;
+    // rasterizes a glyph from the font's color table, using `foreground`
+    // where the font asks for the text color
+    fn rasterize_color_glyph(GlyphIndex, foreground: Rgba) -> Option<ColorGlyph>;
 }
 
 pub impl Font : FontMethods {
-    fn draw_text_into_context(rctx: &RenderContext, run: &TextRun, range: Range, baseline_origin: Point2D<Au>) {
+    fn draw_text_into_context(rctx: &RenderContext, run: &TextRun, range: Range, baseline_origin: Point2D<Au>,
+                              r: u8, g: u8, b: u8) {
         use libc::types::common::c99::{uint16_t, uint32_t};
         use azure::{AzDrawOptions,
                     AzGlyph,
@@ -240,9 +282,9 @@ pub impl Font : FontMethods {
         let target = rctx.get_draw_target();
         let azfont = self.get_azure_font();
         let color = {
-            r: 0f as AzFloat,
-            g: 0f as AzFloat,
-            b: 0f as AzFloat,
+            r: (r.to_float() / 255f) as AzFloat,
+            g: (g.to_float() / 255f) as AzFloat,
+            b: (b.to_float() / 255f) as AzFloat,
             a: 1f as AzFloat
         };
         let pattern = AzCreateColorPattern(ptr::to_unsafe_ptr(&color));
@@ -256,20 +298,39 @@ pub impl Font : FontMethods {
         let mut origin = copy baseline_origin;
         let azglyphs = DVec();
         azglyphs.reserve(range.length());
+        // Glyphs from the colour table and the glyph cache, drawn as
+        // bitmaps after the rest
+        let color_glyphs = DVec();
+        let cached_glyphs = DVec();
 
         do run.glyphs.iter_glyphs_for_range(range) |_i, glyph| {
             let glyph_advance = glyph.advance();
             let glyph_offset = glyph.offset().get_default(au::zero_point());
+            let position = Point2D(origin.x + glyph_offset.x, origin.y + glyph_offset.y);
 
-            let azglyph: AzGlyph = {
-                mIndex: glyph.index() as uint32_t,
-                mPosition: {
-                    x: au::to_px(origin.x + glyph_offset.x) as AzFloat,
-                    y: au::to_px(origin.y + glyph_offset.y) as AzFloat
-                }
+            // TODO: keep colour glyphs in the glyph cache too
+            let color_glyph = match self.color_renderer {
+                Some(_) => self.rasterize_color_glyph(glyph.index(), rgba(r, g, b, 255)),
+                None => None
             };
+            match move color_glyph {
+                Some(move color_glyph) => color_glyphs.push((position, move color_glyph)),
+                None => match self.rasterize_glyph(glyph.index()) {
+                    Some(move cached) => cached_glyphs.push((position, move cached)),
+                    // Backends that can't rasterize leave the glyph to Azure
+                    None => {
+                        let azglyph: AzGlyph = {
+                            mIndex: glyph.index() as uint32_t,
+                            mPosition: {
+                                x: au::to_px(position.x) as AzFloat,
+                                y: au::to_px(position.y) as AzFloat
+                            }
+                        };
+                        azglyphs.push(move azglyph)
+                    }
+                }
+            }
             origin = Point2D(origin.x + glyph_advance, origin.y);
-            azglyphs.push(move azglyph)
         };
 
         let azglyph_buf_len = azglyphs.len();
@@ -284,6 +345,23 @@ pub impl Font : FontMethods {
                                ptr::to_unsafe_ptr(&glyphbuf), pattern, ptr::to_unsafe_ptr(&options), ptr::null());
 
         AzReleaseColorPattern(pattern);
+
+        for color_glyphs.each |entry| {
+            let (ref position, ref glyph) = *entry;
+            let x = au::to_px(position.x) + glyph.bearing_x as int;
+            let y = au::to_px(position.y) - glyph.bearing_y as int;
+            rctx.draw_bitmap(Point2D(x, y), Size2D(glyph.width as int, glyph.height as int),
+                             glyph.bitmap);
+        }
+
+        for cached_glyphs.each |entry| {
+            let (ref position, ref glyph) = *entry;
+            let glyph = std::arc::get(glyph);
+            let x = au::to_px(position.x) + glyph.bearing_x as int;
+            let y = au::to_px(position.y) - glyph.bearing_y as int;
+            rctx.draw_bitmap(Point2D(x, y), Size2D(glyph.width as int, glyph.height as int),
+                             coverage_to_bgra(glyph.bitmap, r, g, b));
+        }
     }
 
     fn measure_text(run: &TextRun, range: Range) -> RunMetrics {
@@ -313,10 +391,10 @@ pub impl Font : FontMethods {
         return metrics;
     }
 
-    fn shape_text(@self, text: &str) -> GlyphStore {
+    fn shape_text(@self, text: &str, levels: &[u8]) -> GlyphStore {
         let store = GlyphStore(text.len());
         let shaper = self.get_shaper();
-        shaper.shape_text(text, &store);
+        shaper.shape_text(text, levels, &store);
         return move store;
     }
 
@@ -328,12 +406,62 @@ pub impl Font : FontMethods {
         self.native_font.glyph_index(codepoint)
     }
 
+    fn variant_glyph_index(codepoint: char, selector: char) -> Option<GlyphIndex> {
+        let variant = match self.variation_sequences {
+            Some(sequences) => sequences.lookup(codepoint, selector),
+            None => NoVariant
+        };
+        match variant {
+            Variant(glyph) => Some(glyph),
+            // A font that doesn't know the sequence still has the character
+            DefaultVariant | NoVariant => self.glyph_index(codepoint)
+        }
+    }
+
     fn glyph_h_advance(glyph: GlyphIndex) -> FractionalPixel {
         match self.native_font.glyph_h_advance(glyph) {
           Some(adv) => adv,
           None => /* FIXME: Need fallback strategy */ 10f as FractionalPixel
         }
     }
+
+    fn rasterize_glyph(glyph: GlyphIndex) -> Option<ARC<RasterizedGlyph>> {
+        let key = GlyphKey {
+            font: self.id,
+            glyph: glyph,
+            // fonts are created at 72 DPI, so points and pixels agree
+            px_size: self.style.pt_size as uint
+        };
+        do self.glyph_cache.get_or_rasterize(move key) {
+            self.native_font.rasterize_glyph(glyph)
+        }
+    }
+
+    fn rasterize_color_glyph(glyph: GlyphIndex, foreground: Rgba) -> Option<ColorGlyph> {
+        match self.color_renderer {
+            Some(renderer) => renderer.render(glyph as u16, self.style.pt_size, 0, foreground),
+            None => None
+        }
+    }
+}
+
+// Cached glyphs are only coverage, so they're colored as they're drawn: the
+// coverage is the alpha, and the text color is premultiplied by it
+fn coverage_to_bgra(coverage: &[u8], r: u8, g: u8, b: u8) -> ~[u8] {
+    let premultiply = |c: u8, alpha: u8| ((c as uint * alpha as uint + 127) / 255) as u8;
+    let mut pixels = vec::with_capacity(coverage.len() * 4);
+    for coverage.each |alpha| {
+        pixels.push_all(~[premultiply(b, *alpha), premultiply(g, *alpha), premultiply(r, *alpha), *alpha]);
+    }
+    move pixels
+}
+
+fn should_color_coverage_with_text_color() {
+    #[test];
+
+    assert coverage_to_bgra(~[0, 128, 255], 0, 0, 0) == ~[0, 0, 0, 0, 0, 0, 0, 128, 0, 0, 0, 255];
+    assert coverage_to_bgra(~[0, 128, 255], 255, 0, 200)
+        == ~[0, 0, 0, 0, 100, 0, 128, 128, 200, 0, 255, 255];
 }
 
 fn should_destruct_on_fail_without_leaking() {
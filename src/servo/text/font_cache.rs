@@ -1,60 +1,325 @@
 use font::{Font, FontStyle, FontWeight300};
 use native_font::NativeFont;
 use font_context::FontContext;
+use glyph_cache::{FontId, GlyphCache};
+use woff2;
+use variable::variation_coords;
+use font_display::{FontDisplay, FontFaceLoad, UseFace, InvisibleFallback, VisibleFallback};
+use core::box::ptr_eq;
+use core::dvec::DVec;
+use std::arc::{ARC, clone, get};
+
+// Bytes of rasterized glyphs kept by each FontCache.
+const GLYPH_CACHE_BUDGET: uint = 4 * 1024 * 1024;
 
 // TODO(Issue #164): delete, and get default font from NativeFontMatcher
 const TEST_FONT: [u8 * 33004] = #include_bin("JosefinSans-SemiBold.ttf");
 
-fn test_font_bin() -> ~[u8] {
+pub fn test_font_bin() -> ~[u8] {
     return vec::from_fn(33004, |i| TEST_FONT[i]);
 }
 
+// The size every run is shaped at, until runs know their font
+pub const TEST_FONT_SIZE: float = 40f;
+
+/// Names the font of a SendableTextRun: a web font is sent with its data,
+/// so that the cache of the task the run goes to can load it too. None is
+/// the test font.
+pub struct FontDescriptor {
+    // Lowercased, since families match case-insensitively
+    family: ~str,
+    data: ARC<~[u8]>,
+}
+
+// A font loaded from a page's `@font-face` rule
+struct WebFont {
+    family: ~str,
+    data: ARC<~[u8]>,
+    font: @Font,
+}
+
 // Dummy font cache.
 
 struct FontCache {
     fctx: @FontContext,
-    mut cached_font: Option<@Font>
+    mut cached_font: Option<@Font>,
+    glyph_cache: GlyphCache,
+    priv web_fonts: DVec<WebFont>,
+    // The `font-display` timing of each family's web font, by family
+    priv web_font_loads: DVec<(~str, FontFaceLoad)>,
+    priv mut next_font_id: FontId
+}
+
+/// The family names in a `font-family` value, unquoted and lowercased.
+pub fn font_families(value: &str) -> ~[~str] {
+    do str::split_char(value, ',').filter_map |family| {
+        let family = str::trim(*family);
+        let family = if family.len() >= 2 &&
+                        (family.starts_with("\"") && family.ends_with("\"") ||
+                         family.starts_with("'") && family.ends_with("'")) {
+            family.slice(1, family.len() - 1)
+        } else {
+            family
+        };
+        if family.is_empty() { None } else { Some(str::to_lower(family)) }
+    }
+}
+
+fn default_style() -> FontStyle {
+    FontStyle {
+        pt_size: TEST_FONT_SIZE,
+        weight: FontWeight300,
+        stretch: 100f,
+        italic: false,
+        oblique: false,
+        variations: ~[]
+    }
 }
 
 impl FontCache {
     static pub fn new(fctx: @FontContext) -> FontCache {
         FontCache { 
             fctx: fctx,
-            cached_font: None
+            cached_font: None,
+            glyph_cache: GlyphCache::new(GLYPH_CACHE_BUDGET),
+            web_fonts: DVec(),
+            web_font_loads: DVec(),
+            next_font_id: 0
         }
     }
     
     pub fn get_test_font(@self) -> @Font {
-        let dummy_style = FontStyle {
-            pt_size: 40f,
-            weight: FontWeight300,
-            italic: false,
-            oblique: false
-        };
-
         return match self.cached_font {
             Some(font) => font,
-            None => match self.get_font(&dummy_style) {
+            None => match self.get_font(&default_style()) {
                 Ok(font) => { self.cached_font = Some(font); font }
                 Err(*) => /* FIXME */ fail
             }
         }
     }
 
+    /// The bytes of the cached fonts and the rasterized glyphs.
+    pub fn heap_size(&self) -> uint {
+        let mut fonts = match self.cached_font {
+            Some(font) => font.heap_size(),
+            None => 0
+        };
+        for self.web_fonts.each |web_font| {
+            fonts += web_font.font.heap_size();
+        }
+        fonts + self.glyph_cache.size()
+    }
+
     // TODO: maybe FontStyle should be canonicalized when used in FontCache?
     priv fn create_font(style: &FontStyle) -> Result<@Font, ()> {
-        let font_bin = @test_font_bin();
+        self.create_font_from(@test_font_bin(), style)
+    }
+
+    priv fn create_font_from(font_bin: @~[u8], style: &FontStyle) -> Result<@Font, ()> {
         let native_font = NativeFont::new(self.fctx, font_bin, style.pt_size);
         let native_font = if native_font.is_ok() {
             result::unwrap(move native_font)
         } else {
             return Err(native_font.get_err());
         };
+        // Variable fonts are drawn at the style's position on their axes
+        let coords = variation_coords(*font_bin, style);
+        if !coords.is_empty() {
+            native_font.set_variation_coords(coords);
+        }
 
-        return Ok(@Font::new(font_bin, move native_font, copy *style));
+        let font_id = self.next_font_id;
+        self.next_font_id += 1;
+        return Ok(@Font::new(font_bin, move native_font, copy *style,
+                             font_id, self.glyph_cache.clone()));
     }
 
     pub fn get_font(@self, style: &FontStyle) -> Result<@Font, ()> {
         self.create_font(style)
     }
-}
\ No newline at end of file
+
+    /// Starts timing the load of a family's `@font-face` font, at `now` in
+    /// ms. Its text is drawn as `display` says until the font arrives.
+    pub fn start_web_font_load(&self, family: &str, display: FontDisplay, now: uint) {
+        let family = str::to_lower(family);
+        for self.web_font_loads.each |entry| {
+            let (ref name, _) = *entry;
+            if *name == family {
+                return;
+            }
+        }
+        self.web_font_loads.push((move family, FontFaceLoad(display, now)));
+    }
+
+    /**
+    Adds the font of an `@font-face` rule, from downloaded data that can be
+    WOFF2 as well as TrueType or OpenType, at `now` in ms. The first font
+    loaded for a family is the one it keeps.
+    */
+    pub fn add_web_font(@self, family: &str, data: ~[u8], now: uint) -> Result<@Font, ()> {
+        let family = str::to_lower(family);
+        match self.find_web_font(family) {
+            Some(font) => return Ok(font),
+            None => ()
+        }
+        let font_bin = if woff2::is_woff2(data) {
+            match woff2::decode(data) {
+                Ok(move sfnt) => move sfnt,
+                Err(move err) => {
+                    debug!("couldn't decode WOFF2 font: %?", err);
+                    self.web_font_failed(family);
+                    return Err(());
+                }
+            }
+        } else {
+            move data
+        };
+        match self.add_font_data(copy family, ARC(move font_bin)) {
+            Ok(font) => {
+                for self.web_font_loads.each |entry| {
+                    let (ref name, ref load) = *entry;
+                    if *name == family {
+                        load.loaded(now);
+                    }
+                }
+                Ok(font)
+            }
+            Err(()) => {
+                self.web_font_failed(family);
+                Err(())
+            }
+        }
+    }
+
+    /// Gives up on a family's web font, so its text is drawn in the fallback.
+    pub fn web_font_failed(&self, family: &str) {
+        let family = str::to_lower(family);
+        for self.web_font_loads.each |entry| {
+            let (ref name, ref load) = *entry;
+            if *name == family {
+                load.failed();
+            }
+        }
+    }
+
+    /**
+    The font for text in `families` at `now` in ms, and whether the text is
+    drawn. The first family with an `@font-face` rule picks it: its web font
+    once `font-display` lets it be used, and until then the test font, which
+    is invisible in the block period. Without one it's the test font.
+    */
+    pub fn get_font_for_families(@self, families: &[~str], now: uint) -> (@Font, bool) {
+        for families.each |family| {
+            let mut rendering = None;
+            for self.web_font_loads.each |entry| {
+                let (ref name, ref load) = *entry;
+                if *name == *family {
+                    rendering = Some(load.rendering(now));
+                }
+            }
+            match rendering {
+                Some(UseFace) => match self.find_web_font(*family) {
+                    Some(font) => return (font, true),
+                    None => return (self.get_test_font(), true)
+                },
+                Some(InvisibleFallback) => return (self.get_test_font(), false),
+                Some(VisibleFallback) => return (self.get_test_font(), true),
+                None => ()
+            }
+        }
+        (self.get_test_font(), true)
+    }
+
+    /// When, in ms, text might next change font without a font arriving.
+    pub fn next_font_change(&self, now: uint) -> Option<uint> {
+        let mut next = None;
+        for self.web_font_loads.each |entry| {
+            let (_, ref load) = *entry;
+            match (load.next_change(now), next) {
+                (Some(change), Some(soonest)) if change >= soonest => (),
+                (Some(change), _) => next = Some(change),
+                (None, _) => ()
+            }
+        }
+        next
+    }
+
+    /// What another task needs to draw with `font`.
+    pub fn descriptor_for(&self, font: @Font) -> Option<FontDescriptor> {
+        for self.web_fonts.each |web_font| {
+            if ptr_eq(web_font.font, font) {
+                return Some(FontDescriptor {
+                    family: copy web_font.family,
+                    data: clone(&web_font.data)
+                });
+            }
+        }
+        None
+    }
+
+    /// The font a run's descriptor names, loading it the first time.
+    pub fn get_font_for_descriptor(@self, descriptor: &Option<FontDescriptor>) -> @Font {
+        match *descriptor {
+            None => self.get_test_font(),
+            Some(ref descriptor) => match self.find_web_font(descriptor.family) {
+                Some(font) => font,
+                None => {
+                    let data = clone(&descriptor.data);
+                    match self.add_font_data(copy descriptor.family, move data) {
+                        Ok(font) => font,
+                        Err(*) => self.get_test_font()
+                    }
+                }
+            }
+        }
+    }
+
+    priv fn find_web_font(family: &str) -> Option<@Font> {
+        for self.web_fonts.each |web_font| {
+            if web_font.family == family.to_str() {
+                return Some(web_font.font);
+            }
+        }
+        None
+    }
+
+    priv fn add_font_data(family: ~str, data: ARC<~[u8]>) -> Result<@Font, ()> {
+        let font = match self.create_font_from(@copy *get(&data), &default_style()) {
+            Ok(font) => font,
+            Err(*) => return Err(())
+        };
+        self.web_fonts.push(WebFont {
+            family: move family,
+            data: move data,
+            font: font
+        });
+        Ok(font)
+    }
+}
+
+#[test]
+fn test_font_families() {
+    assert font_families("\"Josefin Sans\", 'Open Sans' , Serif,") ==
+        ~[~"josefin sans", ~"open sans", ~"serif"];
+    assert font_families("").is_empty();
+}
+
+#[test]
+fn test_web_font_display() {
+    use font_display::FontDisplayFallback;
+
+    let cache = @FontCache::new(@FontContext::new());
+    let families = ~[~"josefin", ~"serif"];
+    cache.start_web_font_load("Josefin", FontDisplayFallback, 0);
+    let (font, visible) = cache.get_font_for_families(families, 50);
+    assert ptr_eq(font, cache.get_test_font()) && !visible;
+    assert cache.next_font_change(50) == Some(100);
+    let (_, visible) = cache.get_font_for_families(families, 150);
+    assert visible;
+
+    let web_font = cache.add_web_font("Josefin", test_font_bin(), 200).get();
+    let (font, visible) = cache.get_font_for_families(families, 250);
+    assert ptr_eq(font, web_font) && visible;
+    assert cache.descriptor_for(web_font).get().family == ~"josefin";
+    assert cache.descriptor_for(cache.get_test_font()).is_none();
+}
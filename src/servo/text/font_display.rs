@@ -0,0 +1,342 @@
+/*!
+`font-display`: what text in a web font looks like while the font loads.
+
+Each value splits the load into periods. In the block period the text is
+laid out with a fallback font's metrics but not drawn; in the swap period
+it's drawn with the fallback; after that, a font that still hasn't loaded
+is given up on and the fallback stays. A font that loads before then is
+swapped in, all at once, so a run is never drawn in two fonts.
+
+The CSS library doesn't hand `@font-face` rules on, so they're read from
+the stylesheet source here.
+*/
+
+// The spec's recommended periods, in ms
+const SHORT_BLOCK: uint = 100;
+const LONG_BLOCK: uint = 3000;
+const SHORT_SWAP: uint = 3000;
+
+pub enum FontDisplay {
+    FontDisplayAuto,
+    FontDisplayBlock,
+    FontDisplaySwap,
+    FontDisplayFallback,
+    FontDisplayOptional,
+}
+
+impl FontDisplay : cmp::Eq {
+    pure fn eq(&self, other: &FontDisplay) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &FontDisplay) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+pub fn parse_font_display(value: &str) -> Option<FontDisplay> {
+    match str::trim(value).to_lower() {
+        ~"auto" => Some(FontDisplayAuto),
+        ~"block" => Some(FontDisplayBlock),
+        ~"swap" => Some(FontDisplaySwap),
+        ~"fallback" => Some(FontDisplayFallback),
+        ~"optional" => Some(FontDisplayOptional),
+        _ => None
+    }
+}
+
+impl FontDisplay {
+    /// How long text is invisible for, in ms.
+    pure fn block_period(&self) -> uint {
+        match *self {
+            // Browsers treat auto as block
+            FontDisplayAuto | FontDisplayBlock => LONG_BLOCK,
+            FontDisplaySwap => 0,
+            FontDisplayFallback | FontDisplayOptional => SHORT_BLOCK
+        }
+    }
+
+    /// How long after the block period the font can still be swapped in,
+    /// in ms, or None if it always can.
+    pure fn swap_period(&self) -> Option<uint> {
+        match *self {
+            FontDisplayAuto | FontDisplayBlock | FontDisplaySwap => None,
+            FontDisplayFallback => Some(SHORT_SWAP),
+            FontDisplayOptional => Some(0)
+        }
+    }
+}
+
+/// What to do with text in a face, some time into its load.
+pub enum FaceRendering {
+    // Lay out with the fallback, but don't draw
+    InvisibleFallback,
+    VisibleFallback,
+    UseFace,
+}
+
+impl FaceRendering : cmp::Eq {
+    pure fn eq(&self, other: &FaceRendering) -> bool {
+        (*self as uint) == (*other as uint)
+    }
+    pure fn ne(&self, other: &FaceRendering) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// The load of one `@font-face`, timed from when the page asked for it.
+pub struct FontFaceLoad {
+    display: FontDisplay,
+    started: uint,
+    // When the font arrived, or None if it hasn't (or never will)
+    priv mut loaded_at: Option<uint>,
+    priv mut failed: bool,
+}
+
+pub fn FontFaceLoad(display: FontDisplay, started: uint) -> FontFaceLoad {
+    FontFaceLoad {
+        display: display,
+        started: started,
+        loaded_at: None,
+        failed: false
+    }
+}
+
+impl FontFaceLoad {
+    fn loaded(&self, now: uint) {
+        if self.loaded_at.is_none() {
+            self.loaded_at = Some(now);
+        }
+    }
+
+    fn failed(&self) {
+        self.failed = true;
+    }
+
+    // How long the load had gone on for at `time`, which a clock that went
+    // backwards can put before the start
+    priv fn elapsed(&self, time: uint) -> uint {
+        if time > self.started { time - self.started } else { 0 }
+    }
+
+    // Whether `time` is before the swap period ends
+    priv fn can_swap_at(&self, time: uint) -> bool {
+        match self.display.swap_period() {
+            None => true,
+            Some(swap) => self.elapsed(time) < self.display.block_period() + swap
+        }
+    }
+
+    /**
+    How text in this face is drawn at `now`. A font that arrives after its
+    swap period is never used, even once it's there, so that the text
+    doesn't change under a reader who has started on it.
+    */
+    fn rendering(&self, now: uint) -> FaceRendering {
+        match self.loaded_at {
+            Some(loaded_at) if now >= loaded_at && self.can_swap_at(loaded_at) => {
+                return UseFace;
+            }
+            _ => ()
+        }
+        if !self.failed && self.elapsed(now) < self.display.block_period() {
+            InvisibleFallback
+        } else {
+            VisibleFallback
+        }
+    }
+
+    /// When the rendering might next change without the font arriving, so
+    /// layout knows when to look again. None if it won't.
+    fn next_change(&self, now: uint) -> Option<uint> {
+        if self.rendering(now) != InvisibleFallback {
+            None
+        } else {
+            Some(self.started + self.display.block_period())
+        }
+    }
+}
+
+/// The descriptors of an `@font-face` rule that matter for loading.
+pub struct FontFaceRule {
+    family: ~str,
+    // The src descriptor as written, for the loader to pick a source from
+    src: ~str,
+    display: FontDisplay,
+}
+
+// The text of the block starting at `open`, and the index after it
+fn block_at(css: &str, open: uint) -> Option<(~str, uint)> {
+    let mut depth = 0;
+    let mut i = open;
+    while i < css.len() {
+        match css[i] as char {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((css.slice(open + 1, i), i + 1));
+                }
+            }
+            _ => ()
+        }
+        i += 1;
+    }
+    None
+}
+
+fn unquote(value: &str) -> ~str {
+    let value = str::trim(value);
+    if value.len() >= 2 && (value.starts_with("\"") && value.ends_with("\"") ||
+                            value.starts_with("'") && value.ends_with("'")) {
+        value.slice(1, value.len() - 1)
+    } else {
+        value
+    }
+}
+
+/// Reads the descriptors of one `@font-face` block. Without a family and a
+/// source there's no face, and an unknown `font-display` counts as `auto`.
+pub fn parse_font_face(block: &str) -> Option<FontFaceRule> {
+    let mut family = None;
+    let mut src = None;
+    let mut display = FontDisplayAuto;
+    for str::split_char(block, ';').each |declaration| {
+        match str::find_char(*declaration, ':') {
+            Some(colon) => {
+                let value = str::trim(declaration.slice(colon + 1, declaration.len()));
+                match str::trim(declaration.slice(0, colon)).to_lower() {
+                    ~"font-family" => family = Some(unquote(value)),
+                    ~"src" => src = Some(value),
+                    ~"font-display" => {
+                        display = parse_font_display(value).get_default(FontDisplayAuto);
+                    }
+                    _ => ()
+                }
+            }
+            None => ()
+        }
+    }
+    match (move family, move src) {
+        (Some(move family), Some(move src)) => {
+            if family.is_empty() {
+                None
+            } else {
+                Some(FontFaceRule { family: move family, src: move src, display: display })
+            }
+        }
+        _ => None
+    }
+}
+
+/**
+The URL of the first `url()` in a `src` descriptor. Fonts that come with
+the system, named with `local()`, aren't looked for.
+*/
+pub fn font_face_url(src: &str) -> Option<~str> {
+    let start = match str::find_str(src, "url(") {
+        Some(i) => i + 4,
+        None => return None
+    };
+    match str::find_char_from(src, ')', start) {
+        Some(end) => {
+            let url = unquote(src.slice(start, end));
+            if url.is_empty() { None } else { Some(move url) }
+        }
+        None => None
+    }
+}
+
+/// The `@font-face` rules in a stylesheet's source.
+pub fn font_face_rules(css: &str) -> ~[FontFaceRule] {
+    let mut rules = ~[];
+    let mut from = 0;
+    loop {
+        let at = match str::find_str_from(css, "@font-face", from) {
+            Some(at) => at,
+            None => break
+        };
+        let open = match str::find_char_from(css, '{', at) {
+            Some(open) => open,
+            None => break
+        };
+        match block_at(css, open) {
+            Some((move block, end)) => {
+                match parse_font_face(block) {
+                    Some(move rule) => rules.push(move rule),
+                    None => ()
+                }
+                from = end;
+            }
+            None => break
+        }
+    }
+    move rules
+}
+
+#[test]
+fn test_periods() {
+    let load = FontFaceLoad(FontDisplayBlock, 1000);
+    assert load.rendering(1000) == InvisibleFallback;
+    assert load.next_change(1000) == Some(4000);
+    assert load.rendering(4000) == VisibleFallback;
+    // Swapped in whenever it arrives
+    load.loaded(10000);
+    assert load.rendering(10000) == UseFace;
+
+    let load = FontFaceLoad(FontDisplaySwap, 0);
+    assert load.rendering(0) == VisibleFallback;
+    assert load.next_change(0).is_none();
+
+    let load = FontFaceLoad(FontDisplayFallback, 0);
+    assert load.rendering(50) == InvisibleFallback;
+    assert load.rendering(150) == VisibleFallback;
+    load.loaded(2000);
+    assert load.rendering(2000) == UseFace;
+
+    // Too late: the fallback stays
+    let load = FontFaceLoad(FontDisplayFallback, 0);
+    load.loaded(3100);
+    assert load.rendering(4000) == VisibleFallback;
+
+    let load = FontFaceLoad(FontDisplayOptional, 0);
+    load.loaded(50);
+    assert load.rendering(60) == UseFace;
+    let load = FontFaceLoad(FontDisplayOptional, 0);
+    load.loaded(150);
+    assert load.rendering(200) == VisibleFallback;
+
+    // A clock that went backwards is still in the block period
+    let load = FontFaceLoad(FontDisplayFallback, 1000);
+    assert load.rendering(900) == InvisibleFallback;
+    load.loaded(500);
+    assert load.rendering(900) == UseFace;
+
+    // A failed load shows the fallback straight away
+    let load = FontFaceLoad(FontDisplayBlock, 0);
+    load.failed();
+    assert load.rendering(10) == VisibleFallback;
+}
+
+#[test]
+fn test_font_face_rules() {
+    let css = "body { color: red }
+               @font-face { font-family: \"Josefin Sans\"; src: url(josefin.woff2);
+                            font-display: Swap }
+               @font-face { font-family: Plain; src: url(plain.ttf); font-display: never }
+               @font-face { src: url(nameless.ttf) }";
+    let rules = font_face_rules(css);
+    assert rules.len() == 2;
+    assert rules[0].family == ~"Josefin Sans";
+    assert rules[0].src == ~"url(josefin.woff2)";
+    assert rules[0].display == FontDisplaySwap;
+    assert rules[1].family == ~"Plain";
+    assert rules[1].display == FontDisplayAuto;
+
+    assert font_face_url(rules[0].src) == Some(~"josefin.woff2");
+    assert font_face_url("local(Josefin), url('a b.woff2') format(\"woff2\")") ==
+        Some(~"a b.woff2");
+    assert font_face_url("local(Josefin)").is_none();
+
+    assert parse_font_display(" optional ") == Some(FontDisplayOptional);
+    assert parse_font_display("fast").is_none();
+}
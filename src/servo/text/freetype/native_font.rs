@@ -10,9 +10,10 @@ use vec_as_buf = vec::as_imm_buf;
 use ptr::{addr_of, null};
 use cast::reinterpret_cast;
 use glyph::GlyphIndex;
+use glyph_cache::RasterizedGlyph;
 
 use freetype::{ FT_Error, FT_Library, FT_Face, FT_Long, FT_ULong, FT_Size, FT_SizeRec,
-               FT_UInt, FT_GlyphSlot, FT_Size_Metrics, FT_FaceRec, FT_F26Dot6 };
+               FT_UInt, FT_GlyphSlot, FT_Size_Metrics, FT_FaceRec, FT_F26Dot6, FT_Fixed };
 use freetype::bindgen::{
     FT_Init_FreeType,
     FT_Done_FreeType,
@@ -20,9 +21,13 @@ use freetype::bindgen::{
     FT_Done_Face,
     FT_Get_Char_Index,
     FT_Load_Glyph,
-    FT_Set_Char_Size
+    FT_Set_Char_Size,
+    FT_Set_Var_Blend_Coordinates
 };
 
+// FT_LOAD_RENDER, from freetype.h
+const FT_LOAD_RENDER: i32 = 1 << 2;
+
 fn float_to_fixed_ft(f: float) -> i32 {
     float_to_fixed(6, f)
 }
@@ -66,6 +71,20 @@ pub impl FreeTypeNativeFont {
         })
     }
 
+    /// Moves a variable font to `coords`, normalized as in variable.rs,
+    /// one for each of its axes. FreeType applies the glyph and metrics
+    /// variations as it loads glyphs from then on.
+    pub fn set_variation_coords(coords: &[float]) {
+        assert self.face.is_not_null();
+        let fixed = coords.map(|coord| float_to_fixed(16, *coord) as FT_Fixed);
+        let res = do vec_as_buf(fixed) |buf, len| {
+            FT_Set_Var_Blend_Coordinates(self.face, len as FT_UInt, buf)
+        };
+        if !res.succeeded() {
+            debug!("Unable to set variation coordinates %?. reason: %?", coords, res);
+        }
+    }
+
     pub fn glyph_index(codepoint: char) -> Option<GlyphIndex> {
         assert self.face.is_not_null();
         let idx = FT_Get_Char_Index(self.face, codepoint as FT_ULong);
@@ -97,6 +116,44 @@ pub impl FreeTypeNativeFont {
         }
     }
 
+    /// Renders a glyph to an 8-bit coverage bitmap. The glyph cache is
+    /// the only expected caller; everything else should go through it.
+    pub fn rasterize_glyph(glyph: GlyphIndex) -> Option<RasterizedGlyph> {
+        assert self.face.is_not_null();
+        let res = FT_Load_Glyph(self.face, glyph as FT_UInt, FT_LOAD_RENDER);
+        if !res.succeeded() {
+            debug!("Unable to render glyph %?. reason: %?", glyph, res);
+            return None;
+        }
+
+        unsafe {
+            let void_glyph = (*self.face).glyph;
+            let slot: FT_GlyphSlot = reinterpret_cast(&void_glyph);
+            assert slot.is_not_null();
+            let bitmap = &(*slot).bitmap;
+            let width = bitmap.width as uint;
+            let height = bitmap.rows as uint;
+
+            // Copy out row by row, since rows may be padded out to the
+            // pitch. A negative pitch means the rows are stored bottom-up.
+            let stride = int::abs(bitmap.pitch as int) as uint;
+            let mut pixels = vec::with_capacity(width * height);
+            for uint::range(0, height) |row| {
+                let stored_row = if bitmap.pitch < 0 { height - 1 - row } else { row };
+                let row_start = ptr::offset(bitmap.buffer, stored_row * stride);
+                pixels.push_all(vec::raw::from_buf_raw(row_start, width));
+            }
+
+            Some(RasterizedGlyph {
+                bitmap: move pixels,
+                width: width as u32,
+                height: height as u32,
+                bearing_x: (*slot).bitmap_left as i32,
+                bearing_y: (*slot).bitmap_top as i32,
+            })
+        }
+    }
+
     pub fn get_metrics() -> FontMetrics {
         /* TODO(Issue #76): complete me */
         let face = self.get_face_rec();
@@ -0,0 +1,294 @@
+/*!
+A cache of rasterized glyph bitmaps, shared between tasks.
+
+Glyphs are keyed by font, glyph index and pixel size, and are only
+rasterized the first time they are asked for. The cache holds at most
+`budget` bytes of bitmap data; when it is full the least recently used
+glyphs are evicted. The entries are linked in the order they were used,
+so finding the one to evict doesn't mean looking at them all. Cloning a
+GlyphCache gives another handle to the same cache, so a task
+pre-rasterizing glyphs can fill it for the renderer.
+*/
+
+use core::send_map::linear::LinearMap;
+use core::to_bytes::{Cb, IterBytes};
+use glyph::GlyphIndex;
+use std::arc::{ARC, RWARC, clone, get};
+
+/// Identifies a Font within a FontCache.
+pub type FontId = uint;
+
+pub struct GlyphKey {
+    font: FontId,
+    glyph: GlyphIndex,
+    px_size: uint,
+}
+
+impl GlyphKey : cmp::Eq {
+    pure fn eq(other: &GlyphKey) -> bool {
+        self.font == other.font && self.glyph == other.glyph && self.px_size == other.px_size
+    }
+    pure fn ne(other: &GlyphKey) -> bool {
+        !self.eq(other)
+    }
+}
+
+impl GlyphKey : IterBytes {
+    pure fn iter_bytes(lsb0: bool, f: Cb) {
+        self.font.iter_bytes(lsb0, f);
+        (self.glyph as uint).iter_bytes(lsb0, f);
+        self.px_size.iter_bytes(lsb0, f);
+    }
+}
+
+/// An 8-bit coverage bitmap for one glyph, with `width` bytes per row.
+pub struct RasterizedGlyph {
+    bitmap: ~[u8],
+    width: u32,
+    height: u32,
+    // offset from the pen position to the top left of the bitmap
+    bearing_x: i32,
+    bearing_y: i32,
+}
+
+// The entries make a doubly linked list, from the most recently used to
+// the least, through the keys of their neighbours. core::dlist's nodes are
+// managed boxes, which can't be shared between tasks
+priv struct CacheEntry {
+    glyph: ARC<RasterizedGlyph>,
+    mut newer: Option<GlyphKey>,
+    mut older: Option<GlyphKey>,
+}
+
+priv struct GlyphCacheState {
+    entries: LinearMap<GlyphKey, CacheEntry>,
+    budget: uint,
+    mut size: uint,
+    // The ends of the list
+    mut newest: Option<GlyphKey>,
+    mut oldest: Option<GlyphKey>,
+}
+
+pub struct GlyphCache {
+    priv state: RWARC<GlyphCacheState>,
+}
+
+pub impl GlyphCache {
+    static pub fn new(budget: uint) -> GlyphCache {
+        GlyphCache {
+            state: RWARC(GlyphCacheState {
+                entries: LinearMap(),
+                budget: budget,
+                size: 0,
+                newest: None,
+                oldest: None,
+            })
+        }
+    }
+
+    /// Another handle to the same cache.
+    pub fn clone(&self) -> GlyphCache {
+        GlyphCache { state: self.state.clone() }
+    }
+
+    pub fn find(&self, key: &GlyphKey) -> Option<ARC<RasterizedGlyph>> {
+        do self.state.write |state| {
+            let glyph = match state.entries.find(key) {
+                Some(entry) => Some(clone(&entry.glyph)),
+                None => None
+            };
+            if glyph.is_some() {
+                unlink(state, key);
+                link_newest(state, key);
+            }
+            move glyph
+        }
+    }
+
+    /// Adds a glyph, evicting old ones to stay within the budget. A glyph
+    /// bigger than the whole budget is handed back without being stored.
+    pub fn insert(&self, key: GlyphKey, glyph: RasterizedGlyph) -> ARC<RasterizedGlyph> {
+        let glyph_size = glyph.bitmap.len();
+        let glyph = ARC(move glyph);
+
+        do self.state.write |state| {
+            if glyph_size <= state.budget {
+                // Another task may have cached it since this one looked
+                if state.entries.contains_key(&key) {
+                    remove(state, &key);
+                }
+                while state.size + glyph_size > state.budget {
+                    match state.oldest {
+                        Some(oldest) => remove(state, &oldest),
+                        None => fail ~"glyph cache is over budget with nothing to evict"
+                    }
+                }
+                let entry = CacheEntry { glyph: clone(&glyph), newer: None, older: None };
+                state.entries.insert(copy key, move entry);
+                link_newest(state, &key);
+                state.size += glyph_size;
+            }
+        }
+
+        move glyph
+    }
+
+    /// Returns the cached glyph, or rasterizes and caches it on a miss.
+    pub fn get_or_rasterize(&self, key: GlyphKey,
+                            rasterize: fn() -> Option<RasterizedGlyph>) -> Option<ARC<RasterizedGlyph>> {
+        match self.find(&key) {
+            Some(move glyph) => Some(move glyph),
+            None => match rasterize() {
+                Some(move glyph) => Some(self.insert(move key, move glyph)),
+                None => None
+            }
+        }
+    }
+
+    /// The number of bitmap bytes currently cached.
+    pub fn size(&self) -> uint {
+        do self.state.read |state| { state.size }
+    }
+
+    pub fn len(&self) -> uint {
+        do self.state.read |state| { state.entries.len() }
+    }
+}
+
+// Takes an entry out of the list, leaving it in the map
+priv fn unlink(state: &mut GlyphCacheState, key: &GlyphKey) {
+    let (newer, older) = {
+        let entry = state.entries.get(key);
+        (entry.newer, entry.older)
+    };
+    match newer {
+        Some(ref newer) => state.entries.get(newer).older = older,
+        None => state.newest = older
+    }
+    match older {
+        Some(ref older) => state.entries.get(older).newer = newer,
+        None => state.oldest = newer
+    }
+}
+
+// Puts an entry that's in the map, but not the list, at the list's head
+priv fn link_newest(state: &mut GlyphCacheState, key: &GlyphKey) {
+    let entry = state.entries.get(key);
+    entry.newer = None;
+    entry.older = state.newest;
+    match state.newest {
+        Some(ref newest) => state.entries.get(newest).newer = Some(*key),
+        None => state.oldest = Some(*key)
+    }
+    state.newest = Some(*key);
+}
+
+priv fn remove(state: &mut GlyphCacheState, key: &GlyphKey) {
+    unlink(state, key);
+    let size = get(&state.entries.get(key).glyph).bitmap.len();
+    state.entries.remove(key);
+    state.size -= size;
+}
+
+#[cfg(test)]
+fn test_glyph(size: uint) -> RasterizedGlyph {
+    RasterizedGlyph {
+        bitmap: vec::from_elem(size, 0u8),
+        width: size as u32,
+        height: 1,
+        bearing_x: 0,
+        bearing_y: 0,
+    }
+}
+
+#[cfg(test)]
+fn test_key(glyph: GlyphIndex) -> GlyphKey {
+    GlyphKey { font: 0, glyph: glyph, px_size: 12 }
+}
+
+#[test]
+fn test_insert_and_find() {
+    let cache = GlyphCache::new(100);
+    cache.insert(test_key(1), test_glyph(10));
+    assert cache.find(&test_key(1)).is_some();
+    assert cache.find(&test_key(2)).is_none();
+    assert cache.size() == 10;
+}
+
+#[test]
+fn test_evicts_least_recently_used() {
+    let cache = GlyphCache::new(30);
+    cache.insert(test_key(1), test_glyph(10));
+    cache.insert(test_key(2), test_glyph(10));
+    cache.insert(test_key(3), test_glyph(10));
+    // touch 1, so 2 is now the oldest
+    cache.find(&test_key(1));
+    cache.insert(test_key(4), test_glyph(10));
+
+    assert cache.find(&test_key(1)).is_some();
+    assert cache.find(&test_key(2)).is_none();
+    assert cache.find(&test_key(3)).is_some();
+    assert cache.find(&test_key(4)).is_some();
+    assert cache.size() == 30;
+}
+
+#[test]
+fn test_evicts_in_order_of_use() {
+    let cache = GlyphCache::new(30);
+    for uint::range(1, 4) |i| {
+        cache.insert(test_key(i as GlyphIndex), test_glyph(10));
+    }
+    cache.find(&test_key(2));
+    cache.find(&test_key(1));
+    // 3, then 2, are the least recently used
+    cache.insert(test_key(4), test_glyph(20));
+    assert cache.find(&test_key(3)).is_none();
+    assert cache.find(&test_key(2)).is_none();
+    assert cache.find(&test_key(1)).is_some();
+    assert cache.size() == 30;
+}
+
+#[test]
+fn test_inserting_again_replaces() {
+    let cache = GlyphCache::new(30);
+    cache.insert(test_key(1), test_glyph(10));
+    cache.insert(test_key(1), test_glyph(20));
+    assert cache.len() == 1;
+    assert cache.size() == 20;
+    assert get(&option::unwrap(cache.find(&test_key(1)))).bitmap.len() == 20;
+}
+
+#[test]
+fn test_oversized_glyph_is_not_cached() {
+    let cache = GlyphCache::new(5);
+    let glyph = cache.insert(test_key(1), test_glyph(10));
+    assert get(&glyph).bitmap.len() == 10;
+    assert cache.len() == 0;
+}
+
+#[test]
+fn test_rasterizes_once() {
+    let cache = GlyphCache::new(100);
+    let mut calls = 0;
+    for 3.times {
+        let glyph = do cache.get_or_rasterize(test_key(7)) {
+            calls += 1;
+            Some(test_glyph(4))
+        };
+        assert glyph.is_some();
+    }
+    assert calls == 1;
+}
+
+#[test]
+fn test_shared_between_tasks() {
+    let cache = GlyphCache::new(100);
+    let other = cache.clone();
+    let (chan, port) = pipes::stream();
+    do task::spawn |move other, move chan| {
+        other.insert(test_key(1), test_glyph(10));
+        chan.send(());
+    }
+    port.recv();
+    assert cache.find(&test_key(1)).is_some();
+}
@@ -12,9 +12,12 @@ use libc::{c_uint, c_int, c_void, c_char};
 use ptr::{null, to_unsafe_ptr, offset};
 use std::arc;
 use util::*;
+use bidi;
+use emoji;
 
 use harfbuzz::{HB_MEMORY_MODE_READONLY,
-                  HB_DIRECTION_LTR};
+                  HB_DIRECTION_LTR,
+                  HB_DIRECTION_RTL};
 use harfbuzz::{hb_blob_t, hb_face_t, hb_font_t, hb_font_funcs_t, hb_buffer_t,
                   hb_codepoint_t, hb_bool_t, hb_glyph_position_t,
 		  hb_glyph_info_t, hb_var_int_t, hb_position_t};
@@ -99,22 +102,37 @@ pub impl HarfbuzzShaper {
     Calculate the layout metrics associated with a some given text
     when rendered in a specific font.
     */
-    pub fn shape_text(text: &str, glyphs: &GlyphStore) {
+    pub fn shape_text(text: &str, levels: &[u8], glyphs: &GlyphStore) {
         debug!("shaping text '%s'", text);
+        assert levels.len() == text.len();
+
+        // Each run of one bidi level is shaped separately, in its own direction.
+        let mut begin = 0u;
+        while begin < text.len() {
+            let mut end = begin + 1;
+            while end < text.len() && levels[end] == levels[begin] {
+                end += 1;
+            }
+            self.shape_segment(text, begin, end - begin, bidi::is_rtl(levels[begin]), glyphs);
+            begin = end;
+        }
+    }
 
+    priv fn shape_segment(text: &str, segment_begin: uint, length: uint, rtl: bool, glyphs: &GlyphStore) {
         // TODO(Issue #94): harfbuzz fonts and faces should be cached on the 
         // Shaper object, which is owned by the Font instance.
 
         let hb_buffer: *hb_buffer_t = hb_buffer_create();
-        hb_buffer_set_direction(hb_buffer, HB_DIRECTION_LTR);
+        hb_buffer_set_direction(hb_buffer, if rtl { HB_DIRECTION_RTL } else { HB_DIRECTION_LTR });
 
-        // Using as_buf because it never does a copy - we don't need the trailing null
+        // Using as_buf because it never does a copy - we don't need the trailing null.
+        // The whole text is passed so harfbuzz can see the context around the segment.
         str::as_buf(text, |ctext: *u8, _l: uint| {
             hb_buffer_add_utf8(hb_buffer, 
                                ctext as *c_char,
                                text.len() as c_int,
-                               0 as c_uint,
-                               text.len() as c_int);
+                               segment_begin as c_uint,
+                               length as c_int);
         });
 
         hb_shape(self.hb_font, hb_buffer, null(), 0 as c_uint);
@@ -133,7 +151,7 @@ pub impl HarfbuzzShaper {
             let hb_pos: hb_glyph_position_t = *offset(pos_buf, i);
             let codepoint = hb_info.codepoint as GlyphIndex;
             let advance: Au = au::from_frac_px(HarfbuzzShaper::fixed_to_float(hb_pos.x_advance));
-            let offset = match (hb_pos.x_offset, hb_pos.y_offset) {
+            let glyph_offset = match (hb_pos.x_offset, hb_pos.y_offset) {
                 (0, 0) => None,
                 (x, y) => Some(Point2D(au::from_frac_px(HarfbuzzShaper::fixed_to_float(x)),
                                        au::from_frac_px(HarfbuzzShaper::fixed_to_float(y))))
@@ -141,10 +159,12 @@ pub impl HarfbuzzShaper {
             // TODO: convert pos.y_advance into offset adjustment
             // TODO: handle multiple glyphs per char, ligatures, etc.
             // NB. this debug statement is commented out, as it must be checked for every shaped char.
-            //debug!("glyph %?: index %?, advance %?, offset %?", i, codepoint, advance, offset);
+            //debug!("glyph %?: index %?, advance %?, offset %?", i, codepoint, advance, glyph_offset);
 
-            let data = GlyphData(codepoint, advance, offset, false, false, false);
-            glyphs.add_glyph_for_index(i, &data);
+            // RTL glyphs come out in visual order; clusters map them back to the text.
+            let char_index = if rtl { hb_info.cluster as uint } else { segment_begin + i };
+            let data = GlyphData(codepoint, advance, glyph_offset, false, false, false);
+            glyphs.add_glyph_for_index(char_index, &data);
         } /* unsafe */ }
 
         hb_buffer_destroy(hb_buffer);
@@ -167,12 +187,22 @@ pub impl HarfbuzzShaper {
 extern fn glyph_func(_font: *hb_font_t,
                      font_data: *c_void,
                      unicode: hb_codepoint_t,
-                     _variant_selector: hb_codepoint_t,
+                     variant_selector: hb_codepoint_t,
                      glyph: *mut hb_codepoint_t,
                      _user_data: *c_void) -> hb_bool_t unsafe {
     let font: *Font = font_data as *Font;
     assert font.is_not_null();
-    return match (*font).glyph_index(unicode as char) {
+    let selector = if variant_selector == 0 { None } else { Some(variant_selector as char) };
+    // Selectors other than VS15 and VS16, such as the ideographic ones, are
+    // looked up as they are. Otherwise the font is asked for the glyph of
+    // the presentation the character should have.
+    let selector = match selector {
+        Some(selector) if selector != emoji::TEXT_SELECTOR && selector != emoji::EMOJI_SELECTOR => {
+            selector
+        }
+        _ => emoji::selector_for(emoji::presentation(unicode as char, selector))
+    };
+    return match (*font).variant_glyph_index(unicode as char, selector) {
         Some(g) => { *glyph = g as hb_codepoint_t; true },
         None => false
     } as hb_bool_t;
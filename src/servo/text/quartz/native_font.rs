@@ -8,7 +8,8 @@ use font_context::QuartzFontContext;
 use au = gfx::geometry;
 use cast::transmute;
 use glyph::GlyphIndex;
-use libc::size_t;
+use glyph_cache::RasterizedGlyph;
+use libc::{c_void, size_t};
 use ptr::null;
 
 use cf = core_foundation;
@@ -32,7 +33,7 @@ use cg::font::{
     CGFontRelease,
     CGGlyph,
 };
-use cg::geometry::CGRect;
+use cg::geometry::{CGPoint, CGRect};
 
 use ct = core_text;
 use ct::font::{
@@ -48,6 +49,7 @@ use ct::font::{
     CTFontGetUnderlinePosition,
     CTFontGetUnderlineThickness,
     CTFontGetXHeight,
+    CTFontOrientation,
     kCTFontDefaultOrientation,
 };
 
@@ -91,6 +93,10 @@ pub impl QuartzNativeFont {
         })
     }
 
+    fn set_variation_coords(_coords: &[float]) {
+        // TODO: copy the CTFont with kCTFontVariationAttribute
+    }
+
     fn glyph_index(codepoint: char) -> Option<GlyphIndex> {
         assert self.ctfont.is_not_null();
 
@@ -124,6 +130,65 @@ pub impl QuartzNativeFont {
         return Some(advance as FractionalPixel);
     }
 
+    fn rasterize_glyph(glyph: GlyphIndex) -> Option<RasterizedGlyph> {
+        assert self.ctfont.is_not_null();
+
+        let glyphs = ~[glyph as CGGlyph];
+        let bounds = do vec::as_imm_buf(glyphs) |glyph_buf, _l| {
+            bitmap_context::CTFontGetBoundingRectsForGlyphs(self.ctfont, kCTFontDefaultOrientation,
+                                                            glyph_buf, null(), 1)
+        };
+
+        // Whole pixels around the glyph, with y going up from the baseline
+        let left = float::floor(bounds.origin.x as float) as int;
+        let bottom = float::floor(bounds.origin.y as float) as int;
+        let right = float::ceil((bounds.origin.x + bounds.size.width) as float) as int;
+        let top = float::ceil((bounds.origin.y + bounds.size.height) as float) as int;
+        let width = (right - left) as uint;
+        let height = (top - bottom) as uint;
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        // Draw the glyph in white on black into a grey bitmap, which leaves
+        // its coverage in the bytes. Bitmap contexts store rows top-down.
+        let mut pixels = vec::from_elem(width * height, 0u8);
+        let drawn = do vec::as_mut_buf(pixels) |pixel_buf, _l| {
+            let space = bitmap_context::CGColorSpaceCreateDeviceGray();
+            let context = bitmap_context::CGBitmapContextCreate(pixel_buf as *c_void,
+                                                                width as size_t, height as size_t,
+                                                                8, width as size_t, space,
+                                                                kCGImageAlphaNone);
+            bitmap_context::CGColorSpaceRelease(space);
+            if context.is_null() {
+                false
+            } else {
+                bitmap_context::CGContextSetGrayFillColor(context, 1.0 as CGFloat, 1.0 as CGFloat);
+                let positions = ~[CGPoint { x: -left as CGFloat, y: -bottom as CGFloat }];
+                do vec::as_imm_buf(glyphs) |glyph_buf, _l| {
+                    do vec::as_imm_buf(positions) |position_buf, _l| {
+                        bitmap_context::CTFontDrawGlyphs(self.ctfont, glyph_buf, position_buf, 1,
+                                                         context);
+                    }
+                }
+                bitmap_context::CGContextRelease(context);
+                true
+            }
+        };
+        if !drawn {
+            debug!("Unable to render glyph %?", glyph);
+            return None;
+        }
+
+        Some(RasterizedGlyph {
+            bitmap: move pixels,
+            width: width as u32,
+            height: height as u32,
+            bearing_x: left as i32,
+            bearing_y: top as i32,
+        })
+    }
+
     fn get_metrics() -> FontMetrics {
         let ctfont = self.ctfont;
         assert ctfont.is_not_null();
@@ -155,4 +220,28 @@ fn ctfont_from_cgfont(cgfont: CGFontRef, pt_size: float) -> CTFontRef {
     assert cgfont.is_not_null();
 
     CTFontCreateWithGraphicsFont(cgfont, pt_size as CGFloat, null(), null())
-}
\ No newline at end of file
+}
+
+// Glyph drawing bindings, which rust-core-graphics and rust-core-text don't have yet. The
+// frameworks are already linked through core_graphics and core_text.
+enum CGColorSpace {}
+type CGColorSpaceRef = *CGColorSpace;
+enum CGContext {}
+type CGContextRef = *CGContext;
+
+const kCGImageAlphaNone: u32 = 0;
+
+#[nolink]
+extern mod bitmap_context {
+    fn CGColorSpaceCreateDeviceGray() -> CGColorSpaceRef;
+    fn CGColorSpaceRelease(space: CGColorSpaceRef);
+    fn CGBitmapContextCreate(data: *c_void, width: size_t, height: size_t,
+                             bits_per_component: size_t, bytes_per_row: size_t,
+                             space: CGColorSpaceRef, bitmap_info: u32) -> CGContextRef;
+    fn CGContextRelease(context: CGContextRef);
+    fn CGContextSetGrayFillColor(context: CGContextRef, gray: CGFloat, alpha: CGFloat);
+    fn CTFontGetBoundingRectsForGlyphs(font: CTFontRef, orientation: CTFontOrientation,
+                                       glyphs: *CGGlyph, rects: *CGRect, count: CFIndex) -> CGRect;
+    fn CTFontDrawGlyphs(font: CTFontRef, glyphs: *CGGlyph, positions: *CGPoint, count: size_t,
+                        context: CGContextRef);
+}
@@ -0,0 +1,183 @@
+/*!
+Subsetting of TrueType fonts, for embedding them in documents. Glyphs
+that aren't used are emptied rather than taken out, so that glyph ids
+stay the same and text shaped with the whole font can be drawn with the
+subset. The tables that are only needed to install the font, or to map
+characters to glyphs, are left out.
+
+Fonts with CFF outlines have no `glyf` table, and can't be subset.
+*/
+
+use variable::{u16_at, u32_at, tag, sfnt_table};
+use woff2::{build_sfnt, push_u32};
+
+// Composite glyph flags
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+// What a TrueType font embedded in a PDF needs
+const KEPT_TABLES: &static/[&static/str] = &["head", "hhea", "hmtx", "maxp", "cvt ", "fpgm",
+                                             "prep", "glyf", "loca"];
+
+// Where each glyph starts in glyf, and where the last ends
+fn read_loca(loca: &[u8], num_glyphs: uint, long: bool) -> ~[uint] {
+    do vec::from_fn(num_glyphs + 1) |i| {
+        if long { u32_at(loca, i * 4) as uint } else { u16_at(loca, i * 2) as uint * 2 }
+    }
+}
+
+// Whether each glyph's data is within glyf, and doesn't end before it starts
+fn valid_offsets(offsets: &[uint], glyf_len: uint) -> bool {
+    for uint::range(0, offsets.len() - 1) |g| {
+        if offsets[g] > offsets[g + 1] || offsets[g + 1] > glyf_len {
+            return false;
+        }
+    }
+    true
+}
+
+fn glyph_data(glyf: &r/[u8], offsets: &[uint], g: uint) -> &r/[u8] {
+    vec::view(glyf, offsets[g], offsets[g + 1])
+}
+
+// The glyphs a composite glyph is made of
+fn components(glyph: &[u8]) -> ~[u16] {
+    let mut result = ~[];
+    if glyph.len() < 10 || (u16_at(glyph, 0) as i16) >= 0 {
+        return move result;
+    }
+    let mut pos = 10;
+    loop {
+        let flags = u16_at(glyph, pos);
+        result.push(u16_at(glyph, pos + 2));
+        pos += 4;
+        pos += if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            pos += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            pos += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            pos += 8;
+        }
+        if flags & MORE_COMPONENTS == 0 || pos >= glyph.len() {
+            return move result;
+        }
+    }
+}
+
+/**
+`font` with only `glyphs`, the glyphs they're made of and `.notdef` left
+in it. None if it isn't a TrueType font.
+*/
+pub fn subset_glyphs(font: &[u8], glyphs: &[u16]) -> Option<~[u8]> {
+    let (head, maxp, loca, glyf) = match (sfnt_table(font, tag("head")),
+                                          sfnt_table(font, tag("maxp")),
+                                          sfnt_table(font, tag("loca")),
+                                          sfnt_table(font, tag("glyf"))) {
+        (Some(move head), Some(move maxp), Some(move loca), Some(move glyf)) => {
+            (move head, move maxp, move loca, move glyf)
+        }
+        _ => return None
+    };
+    if head.len() < 54 {
+        return None;
+    }
+    let num_glyphs = u16_at(maxp, 4) as uint;
+    let offsets = read_loca(loca, num_glyphs, u16_at(head, 50) != 0);
+    if !valid_offsets(offsets, glyf.len()) {
+        return None;
+    }
+
+    let mut keep = vec::from_elem(num_glyphs, false);
+    let mut pending = ~[0u16];
+    pending.push_all(glyphs);
+    while !pending.is_empty() {
+        let g = pending.pop() as uint;
+        if g < num_glyphs && !keep[g] {
+            keep[g] = true;
+            pending.push_all(components(glyph_data(glyf, offsets, g)));
+        }
+    }
+
+    let mut new_glyf = ~[];
+    let mut new_loca = ~[];
+    for uint::range(0, num_glyphs) |g| {
+        push_u32(&mut new_loca, new_glyf.len() as u32);
+        if keep[g] {
+            new_glyf.push_all(glyph_data(glyf, offsets, g));
+            while new_glyf.len() % 4 != 0 {
+                new_glyf.push(0);
+            }
+        }
+    }
+    push_u32(&mut new_loca, new_glyf.len() as u32);
+
+    // loca is always written with long offsets
+    let mut new_head = move head;
+    new_head[50] = 0;
+    new_head[51] = 1;
+
+    let mut tables = ~[];
+    for KEPT_TABLES.each |name| {
+        let t = tag(*name);
+        let data = if t == tag("head") {
+            Some(copy new_head)
+        } else if t == tag("glyf") {
+            Some(copy new_glyf)
+        } else if t == tag("loca") {
+            Some(copy new_loca)
+        } else {
+            sfnt_table(font, t)
+        };
+        match move data {
+            Some(move data) => tables.push((t, move data)),
+            None => ()
+        }
+    }
+    Some(build_sfnt(0x00010000, move tables))
+}
+
+#[cfg(test)]
+mod subset_tests {
+    use font_cache::test_font_bin;
+
+    #[test]
+    fn test_subset_glyphs() {
+        let font = test_font_bin();
+        let subset = subset_glyphs(font, [36, 68]).get();
+        assert subset.len() < font.len();
+
+        let num_glyphs = u16_at(sfnt_table(font, tag("maxp")).get(), 4) as uint;
+        let loca = sfnt_table(subset, tag("loca")).get();
+        let head = sfnt_table(subset, tag("head")).get();
+        assert loca.len() == (num_glyphs + 1) * 4;
+        assert u16_at(head, 50) == 1;
+        assert sfnt_table(subset, tag("name")).is_none();
+
+        // Kept glyphs are the same as in the whole font; others are empty
+        let old = read_loca(sfnt_table(font, tag("loca")).get(), num_glyphs,
+                            u16_at(sfnt_table(font, tag("head")).get(), 50) != 0);
+        let new = read_loca(loca, num_glyphs, true);
+        let old_glyf = sfnt_table(font, tag("glyf")).get();
+        let new_glyf = sfnt_table(subset, tag("glyf")).get();
+        assert vec::slice(new_glyf, new[68], new[68] + old[69] - old[68]) ==
+            vec::slice(old_glyf, old[68], old[69]);
+        assert new[51] == new[52];
+    }
+
+    #[test]
+    fn test_valid_offsets() {
+        assert valid_offsets([0, 4, 4, 12], 12);
+        // A glyph that ends before it starts, or after glyf does
+        assert !valid_offsets([0, 8, 4, 12], 12);
+        assert !valid_offsets([0, 4, 16], 12);
+    }
+
+    #[test]
+    fn test_subset_needs_truetype() {
+        assert subset_glyphs([0, 1, 0, 0, 0, 0], [1]).is_none();
+    }
+}
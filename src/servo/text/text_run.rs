@@ -1,8 +1,9 @@
 use arc = std::arc;
 use arc::ARC;
 use au = gfx::geometry;
+use bidi;
 use font::{RunMetrics, Font};
-use font_cache::FontCache;
+use font_cache::{FontCache, FontDescriptor};
 use geom::point::Point2D;
 use geom::size::Size2D;
 use gfx::geometry::Au;
@@ -16,52 +17,87 @@ use servo_util::range::{Range, MutableRange};
 pub struct TextRun {
     text: ~str,
     font: @Font,
+    // Laid out but not drawn, while its web font is in its block period
+    invisible: bool,
     priv glyphs: GlyphStore,
+    // bidi embedding level of each byte of text
+    priv levels: ~[u8],
 }
 
 // This is a hack until TextRuns are normally sendable, or
 // we instead use ARC<TextRun> everywhere.
 pub struct SendableTextRun {
     text: ~str,
-    font_descriptor: (),
+    font_descriptor: Option<FontDescriptor>,
     priv glyphs: GlyphStore,
+    priv levels: ~[u8],
 }
 
 impl SendableTextRun {
     pub fn deserialize(&self, cache: @FontCache) -> TextRun {
         TextRun {
             text: copy self.text,
-            // TODO: actually deserialize a font descriptor thingy
-            font: cache.get_test_font(),
-            glyphs: copy self.glyphs
+            font: cache.get_font_for_descriptor(&self.font_descriptor),
+            invisible: false,
+            glyphs: copy self.glyphs,
+            levels: copy self.levels
         }
     }
+
+    pure fn glyphs(&self) -> &self/GlyphStore { &self.glyphs }
 }
 
 impl TextRun {
     static fn new(font: @Font, text: ~str) -> TextRun {
         use shaper::Shaper;
 
-        let glyph_store = font.shape_text(text);
+        // TODO: the paragraph level should come from CSS 'direction'
+        // and the enclosing block, rather than from the run's own text.
+        let char_levels = bidi::resolve_levels(text, bidi::paragraph_level(text));
+        let levels = bidi::byte_levels(text, char_levels);
+        let glyph_store = font.shape_text(text, levels);
         let run = TextRun {
             text: move text,
             font: font,
+            invisible: false,
             glyphs: move glyph_store,
+            levels: move levels,
         };
         return move run;
     }
 
-    pub pure fn serialize(&self, _cache: @FontCache) -> SendableTextRun {
+    pub fn serialize(&self, cache: @FontCache) -> SendableTextRun {
         SendableTextRun {
             text: copy self.text,
-            // TODO: actually serialize a font descriptor thingy
-            font_descriptor: (),
+            font_descriptor: cache.descriptor_for(self.font),
             glyphs: copy self.glyphs,
+            levels: copy self.levels,
         }
     }
 
     pure fn glyphs(&self) -> &self/GlyphStore { &self.glyphs }
 
+    /// The bidi embedding level of the character starting at byte `i`.
+    pure fn level_at(&self, i: uint) -> u8 { self.levels[i] }
+
+    /// Splits `range` into maximal pieces whose characters share a bidi level.
+    fn iter_level_runs_for_range(&self, range: Range, f: fn(Range) -> bool) {
+        assert range.is_valid_for_string(self.text);
+
+        let clump = MutableRange(range.begin(), 0);
+        for range.eachi |i| {
+            if clump.length() > 0 && self.levels[i] != self.levels[clump.begin()] {
+                if !f(clump.as_immutable()) { return }
+                clump.reset(i, 0);
+            }
+            clump.extend_by(1);
+        }
+
+        if clump.length() > 0 {
+            f(clump.as_immutable());
+        }
+    }
+
     pure fn range_is_trimmable_whitespace(&self, range: Range) -> bool {
         let mut i = range.begin();
         while i < range.end() {
@@ -0,0 +1,655 @@
+/*!
+Variable fonts. A variable font has design axes, such as weight and width,
+listed in its `fvar` table; a position on each axis is normalized to
+between -1 and 1, remapped by `avar`, and the glyph outlines are moved by
+the deltas `gvar` gives for that position.
+
+Axis values come from `font-weight`, `font-stretch` and `font-style`, and
+`font-variation-settings` overrides them.
+*/
+
+use font::FontStyle;
+
+// Axis tags
+const WGHT: u32 = 0x77676874;
+const WDTH: u32 = 0x77647468;
+const ITAL: u32 = 0x6974616c;
+const SLNT: u32 = 0x736c6e74;
+
+// Table tags
+const FVAR: u32 = 0x66766172;
+const AVAR: u32 = 0x61766172;
+
+// What an `oblique` with no angle leans by, in degrees
+const DEFAULT_OBLIQUE_ANGLE: float = 14f;
+
+// Tuple variation header flags
+const EMBEDDED_PEAK_TUPLE: u16 = 0x8000;
+const INTERMEDIATE_REGION: u16 = 0x4000;
+const PRIVATE_POINT_NUMBERS: u16 = 0x2000;
+const TUPLE_INDEX_MASK: u16 = 0x0fff;
+const SHARED_POINT_NUMBERS: u16 = 0x8000;
+const TUPLE_COUNT_MASK: u16 = 0x0fff;
+
+// Packed point number and delta run flags
+const POINTS_ARE_WORDS: u8 = 0x80;
+const DELTAS_ARE_ZERO: u8 = 0x80;
+const DELTAS_ARE_WORDS: u8 = 0x40;
+
+// Big-endian reads, which give zero past the end of `data`
+pub fn u8_at(data: &[u8], pos: uint) -> u8 {
+    if pos < data.len() { data[pos] } else { 0 }
+}
+
+pub fn u16_at(data: &[u8], pos: uint) -> u16 {
+    (u8_at(data, pos) as u16 << 8) | u8_at(data, pos + 1) as u16
+}
+
+pub fn u24_at(data: &[u8], pos: uint) -> u32 {
+    (u8_at(data, pos) as u32 << 16) | u16_at(data, pos + 1) as u32
+}
+
+pub fn u32_at(data: &[u8], pos: uint) -> u32 {
+    (u16_at(data, pos) as u32 << 16) | u16_at(data, pos + 2) as u32
+}
+
+// 16.16 fixed point
+pub fn fixed_at(data: &[u8], pos: uint) -> float {
+    (u32_at(data, pos) as i32) as float / 65536f
+}
+
+pub fn f2dot14_at(data: &[u8], pos: uint) -> float {
+    (u16_at(data, pos) as i16) as float / 16384f
+}
+
+/// The tag for a four-character name, such as "wght".
+pub fn tag(name: &str) -> u32 {
+    let mut tag = 0u32;
+    for uint::range(0, 4) |i| {
+        tag = (tag << 8) | if i < name.len() { name[i] as u32 } else { ' ' as u32 };
+    }
+    tag
+}
+
+/// The table tagged `tag` in an sfnt font.
+pub fn sfnt_table(font: &[u8], tag: u32) -> Option<~[u8]> {
+    let num_tables = u16_at(font, 4) as uint;
+    for uint::range(0, num_tables) |i| {
+        let record = 12 + 16 * i;
+        if u32_at(font, record) == tag {
+            let offset = u32_at(font, record + 8) as uint;
+            let length = u32_at(font, record + 12) as uint;
+            if offset + length > font.len() {
+                return None;
+            }
+            return Some(vec::slice(font, offset, offset + length));
+        }
+    }
+    None
+}
+
+pub struct Axis {
+    tag: u32,
+    min: float,
+    default: float,
+    max: float,
+}
+
+impl Axis {
+    /// Where `value` is on the axis: -1 at the minimum, 0 at the default
+    /// and 1 at the maximum. Values outside the axis are clamped to it.
+    pure fn normalize(&self, value: float) -> float {
+        let value = if value < self.min {
+            self.min
+        } else if value > self.max {
+            self.max
+        } else {
+            value
+        };
+        if value < self.default && self.default > self.min {
+            (value - self.default) / (self.default - self.min)
+        } else if value > self.default && self.max > self.default {
+            (value - self.default) / (self.max - self.default)
+        } else {
+            0f
+        }
+    }
+}
+
+/// The axes in an `fvar` table.
+pub fn parse_fvar(data: &[u8]) -> Option<~[Axis]> {
+    if data.len() < 16 || u16_at(data, 0) != 1 {
+        return None;
+    }
+    let axes_offset = u16_at(data, 4) as uint;
+    let count = u16_at(data, 8) as uint;
+    let size = u16_at(data, 10) as uint;
+    if size < 20 || axes_offset + count * size > data.len() {
+        return None;
+    }
+    Some(do vec::from_fn(count) |i| {
+        let record = axes_offset + i * size;
+        Axis {
+            tag: u32_at(data, record),
+            min: fixed_at(data, record + 4),
+            default: fixed_at(data, record + 8),
+            max: fixed_at(data, record + 12)
+        }
+    })
+}
+
+/// How `avar` remaps one axis's normalized values, as pairs of from and to
+/// coordinates in ascending order.
+pub type SegmentMap = ~[(float, float)];
+
+/// The segment maps in an `avar` table, one for each axis.
+pub fn parse_avar(data: &[u8]) -> Option<~[SegmentMap]> {
+    if data.len() < 8 || u16_at(data, 0) != 1 {
+        return None;
+    }
+    let axis_count = u16_at(data, 6) as uint;
+    let mut pos = 8;
+    let mut maps = ~[];
+    for axis_count.times {
+        let count = u16_at(data, pos) as uint;
+        pos += 2;
+        if pos + count * 4 > data.len() {
+            return None;
+        }
+        maps.push(do vec::from_fn(count) |i| {
+            (f2dot14_at(data, pos + i * 4), f2dot14_at(data, pos + i * 4 + 2))
+        });
+        pos += count * 4;
+    }
+    Some(move maps)
+}
+
+/// A normalized value moved by an axis's segment map: interpolated between
+/// the pairs either side of it, and shifted by the nearest one outside them.
+pub pure fn apply_segment_map(map: &[(float, float)], value: float) -> float {
+    if map.is_empty() {
+        return value;
+    }
+    let (first_from, first_to) = map[0];
+    if value <= first_from {
+        return value + first_to - first_from;
+    }
+    for uint::range(1, map.len()) |i| {
+        let (from, to) = map[i];
+        if value <= from {
+            let (prev_from, prev_to) = map[i - 1];
+            if from == prev_from {
+                return to;
+            }
+            return prev_to + (value - prev_from) * (to - prev_to) / (from - prev_from);
+        }
+    }
+    let (last_from, last_to) = map[map.len() - 1];
+    value + last_to - last_from
+}
+
+/**
+The normalized coordinates for `values`, given as axis tags and values, in
+the order of `axes`. An axis with no value is at its default, and where a
+tag is given more than once the last value counts.
+*/
+pub fn normalized_coords(axes: &[Axis], avar: &[SegmentMap],
+                         values: &[(u32, float)]) -> ~[float] {
+    do vec::from_fn(axes.len()) |i| {
+        let axis = &axes[i];
+        let mut value = axis.default;
+        for values.each |setting| {
+            let (tag, setting_value) = *setting;
+            if tag == axis.tag {
+                value = setting_value;
+            }
+        }
+        let coord = axis.normalize(value);
+        if i < avar.len() {
+            apply_segment_map(avar[i], coord)
+        } else {
+            coord
+        }
+    }
+}
+
+/// Parses `font-variation-settings`: `normal`, or a list of quoted four
+/// character axis tags, each followed by a number.
+pub fn parse_font_variation_settings(value: &str) -> Option<~[(u32, float)]> {
+    let value = str::trim(value);
+    if value.to_lower() == ~"normal" {
+        return Some(~[]);
+    }
+    let mut settings = ~[];
+    for str::split_char(value, ',').each |item| {
+        let item = str::trim(*item);
+        if item.len() < 6 || !(item[0] == '"' as u8 || item[0] == '\'' as u8) ||
+           item[5] != item[0] {
+            return None;
+        }
+        let name = item.slice(1, 5);
+        if !str::is_ascii(name) || str::any(name, |c| c < ' ' || c > '~') {
+            return None;
+        }
+        match float::from_str(str::trim(item.slice(6, item.len()))) {
+            Some(number) => settings.push((tag(name), number)),
+            None => return None
+        }
+    }
+    Some(move settings)
+}
+
+/**
+The axis values a font style asks for. `font-weight` sets `wght`,
+`font-stretch` sets `wdth`, and `italic` or `oblique` set `ital` or `slnt`;
+the style's `font-variation-settings` come last, so they win.
+*/
+pub fn style_variations(style: &FontStyle) -> ~[(u32, float)] {
+    let mut values = ~[(WGHT, (style.weight as uint + 1) as float * 100f),
+                       (WDTH, style.stretch)];
+    if style.italic {
+        values.push((ITAL, 1f));
+    } else if style.oblique {
+        // slnt leans the other way
+        values.push((SLNT, -DEFAULT_OBLIQUE_ANGLE));
+    }
+    values.push_all(style.variations);
+    move values
+}
+
+/// The normalized coordinates to draw the sfnt font `font` at for `style`,
+/// or none if it isn't a variable font.
+pub fn variation_coords(font: &[u8], style: &FontStyle) -> ~[float] {
+    let axes = match sfnt_table(font, FVAR) {
+        Some(move fvar) => parse_fvar(fvar).get_default(~[]),
+        None => ~[]
+    };
+    if axes.is_empty() {
+        return ~[];
+    }
+    let avar = match sfnt_table(font, AVAR) {
+        Some(move avar) => parse_avar(avar).get_default(~[]),
+        None => ~[]
+    };
+    normalized_coords(axes, avar, style_variations(style))
+}
+
+/// The glyph variations in a `gvar` table.
+pub struct Gvar {
+    priv data: ~[u8],
+    priv axis_count: uint,
+    priv shared_tuples: ~[~[float]],
+    // Where each glyph's variation data starts, and the last one ends
+    priv offsets: ~[uint],
+}
+
+pub fn parse_gvar(data: ~[u8]) -> Option<Gvar> {
+    if data.len() < 20 || u16_at(data, 0) != 1 {
+        return None;
+    }
+    let axis_count = u16_at(data, 4) as uint;
+    let shared_count = u16_at(data, 6) as uint;
+    let shared_offset = u32_at(data, 8) as uint;
+    let glyph_count = u16_at(data, 12) as uint;
+    let long_offsets = u16_at(data, 14) & 1 != 0;
+    let array_offset = u32_at(data, 16) as uint;
+
+    let shared_tuples = do vec::from_fn(shared_count) |i| {
+        let tuple = shared_offset + i * axis_count * 2;
+        vec::from_fn(axis_count, |axis| f2dot14_at(data, tuple + axis * 2))
+    };
+    let offsets = do vec::from_fn(glyph_count + 1) |i| {
+        if long_offsets {
+            array_offset + u32_at(data, 20 + i * 4) as uint
+        } else {
+            array_offset + u16_at(data, 20 + i * 2) as uint * 2
+        }
+    };
+    if offsets[glyph_count] > data.len() {
+        return None;
+    }
+    Some(Gvar {
+        data: move data,
+        axis_count: axis_count,
+        shared_tuples: move shared_tuples,
+        offsets: move offsets
+    })
+}
+
+// How much a tuple variation applies at `coords`, from 0 to 1
+pure fn tuple_scalar(peak: &[float], region: &Option<(~[float], ~[float])>,
+                     coords: &[float]) -> float {
+    let mut scalar = 1f;
+    for peak.eachi |i, peak| {
+        let peak = *peak;
+        let coord = if i < coords.len() { coords[i] } else { 0f };
+        if peak == 0f || coord == peak {
+            loop;
+        }
+        match *region {
+            Some((ref start, ref end)) => {
+                let (start, end) = (start[i], end[i]);
+                if coord <= start || coord >= end {
+                    return 0f;
+                }
+                scalar *= if coord < peak {
+                    (coord - start) / (peak - start)
+                } else {
+                    (end - coord) / (end - peak)
+                };
+            }
+            None => {
+                let (low, high) = if peak < 0f { (peak, 0f) } else { (0f, peak) };
+                if coord == 0f || coord < low || coord > high {
+                    return 0f;
+                }
+                scalar *= coord / peak;
+            }
+        }
+    }
+    scalar
+}
+
+// Packed point numbers at `pos`, or None for all of the glyph's points,
+// and where they end
+fn unpack_points(data: &[u8], pos: uint) -> (Option<~[uint]>, uint) {
+    let mut pos = pos;
+    let first = u8_at(data, pos) as uint;
+    pos += 1;
+    if first == 0 {
+        return (None, pos);
+    }
+    let count = if first & 0x80 != 0 {
+        pos += 1;
+        ((first & 0x7f) << 8) | u8_at(data, pos - 1) as uint
+    } else {
+        first
+    };
+    let mut points = ~[];
+    let mut point = 0;
+    while points.len() < count && pos < data.len() {
+        let control = u8_at(data, pos);
+        pos += 1;
+        for ((control & 0x7f) as uint + 1).times {
+            if control & POINTS_ARE_WORDS != 0 {
+                point += u16_at(data, pos) as uint;
+                pos += 2;
+            } else {
+                point += u8_at(data, pos) as uint;
+                pos += 1;
+            }
+            points.push(point);
+        }
+    }
+    points.truncate(count);
+    (Some(move points), pos)
+}
+
+// `count` packed deltas at `pos`, and where they end
+fn unpack_deltas(data: &[u8], pos: uint, count: uint) -> (~[float], uint) {
+    let mut pos = pos;
+    let mut deltas = ~[];
+    while deltas.len() < count && pos < data.len() {
+        let control = u8_at(data, pos);
+        pos += 1;
+        for ((control & 0x3f) as uint + 1).times {
+            if control & DELTAS_ARE_ZERO != 0 {
+                deltas.push(0f);
+            } else if control & DELTAS_ARE_WORDS != 0 {
+                deltas.push((u16_at(data, pos) as i16) as float);
+                pos += 2;
+            } else {
+                deltas.push((u8_at(data, pos) as i8) as float);
+                pos += 1;
+            }
+        }
+    }
+    deltas.truncate(count);
+    while deltas.len() < count {
+        deltas.push(0f);
+    }
+    (move deltas, pos)
+}
+
+// The delta for a point at `c` between two touched points, which moves with
+// them if it's between them and with the nearer one if it's not
+pure fn interpolate(c: float, a: float, b: float, delta_a: float, delta_b: float) -> float {
+    if a == b {
+        return if delta_a == delta_b { delta_a } else { 0f };
+    }
+    let (c1, d1, c2, d2) = if a < b { (a, delta_a, b, delta_b) } else { (b, delta_b, a, delta_a) };
+    if c <= c1 {
+        d1
+    } else if c >= c2 {
+        d2
+    } else {
+        d1 + (c - c1) * (d2 - d1) / (c2 - c1)
+    }
+}
+
+/**
+Fills in the deltas of the points a tuple didn't give any for, from the
+touched points either side of them on the same contour. A contour with no
+touched points doesn't move, and neither do untouched phantom points.
+*/
+fn interpolate_untouched(points: &[(float, float)], touched: &[Option<(float, float)>],
+                         contour_ends: &[uint]) -> ~[(float, float)] {
+    let mut deltas = do touched.map |delta| { delta.get_default((0f, 0f)) };
+    let mut start = 0;
+    for contour_ends.each |end| {
+        let end = *end;
+        if end < start || end >= points.len() {
+            break;
+        }
+        let touched_points = do vec::filter(vec::from_fn(end - start + 1, |i| start + i)) |i| {
+            touched[*i].is_some()
+        };
+        if !touched_points.is_empty() {
+            for uint::range(start, end + 1) |i| {
+                if touched[i].is_some() {
+                    loop;
+                }
+                let next = match touched_points.find(|t| *t > i) {
+                    Some(next) => next,
+                    None => touched_points[0]
+                };
+                let prev = match vec::rfind(touched_points, |t| *t < i) {
+                    Some(prev) => prev,
+                    None => touched_points[touched_points.len() - 1]
+                };
+                let (x, y) = points[i];
+                let (prev_x, prev_y) = points[prev];
+                let (next_x, next_y) = points[next];
+                let (prev_dx, prev_dy) = deltas[prev];
+                let (next_dx, next_dy) = deltas[next];
+                deltas[i] = (interpolate(x, prev_x, next_x, prev_dx, next_dx),
+                             interpolate(y, prev_y, next_y, prev_dy, next_dy));
+            }
+        }
+        start = end + 1;
+    }
+    move deltas
+}
+
+impl Gvar {
+    /**
+    How far each point of glyph `glyph` moves at the normalized coordinates
+    `coords`. `points` are the glyph's points from `glyf`, followed by its
+    four phantom points, the last but two of which moves with the advance
+    width. `contour_ends` gives the last point of each contour.
+    */
+    fn glyph_deltas(&self, glyph: uint, coords: &[float], points: &[(float, float)],
+                    contour_ends: &[uint]) -> ~[(float, float)] {
+        let mut deltas = vec::from_elem(points.len(), (0f, 0f));
+        if glyph + 1 >= self.offsets.len() || self.offsets[glyph] >= self.offsets[glyph + 1] {
+            return move deltas;
+        }
+        let data = vec::view(self.data, self.offsets[glyph], self.offsets[glyph + 1]);
+
+        let tuple_count = u16_at(data, 0) & TUPLE_COUNT_MASK;
+        let mut serialized = u16_at(data, 2) as uint;
+        let shared_points = if u16_at(data, 0) & SHARED_POINT_NUMBERS != 0 {
+            let (shared_points, next) = unpack_points(data, serialized);
+            serialized = next;
+            move shared_points
+        } else {
+            None
+        };
+
+        let mut header = 4;
+        for (tuple_count as uint).times {
+            let size = u16_at(data, header) as uint;
+            let index = u16_at(data, header + 2);
+            header += 4;
+            let read_tuple = |at: uint| {
+                vec::from_fn(self.axis_count, |axis| f2dot14_at(data, at + axis * 2))
+            };
+            let peak = if index & EMBEDDED_PEAK_TUPLE != 0 {
+                header += self.axis_count * 2;
+                read_tuple(header - self.axis_count * 2)
+            } else if ((index & TUPLE_INDEX_MASK) as uint) < self.shared_tuples.len() {
+                copy self.shared_tuples[(index & TUPLE_INDEX_MASK) as uint]
+            } else {
+                return move deltas;
+            };
+            let region = if index & INTERMEDIATE_REGION != 0 {
+                header += self.axis_count * 4;
+                Some((read_tuple(header - self.axis_count * 4),
+                      read_tuple(header - self.axis_count * 2)))
+            } else {
+                None
+            };
+
+            let tuple_data = serialized;
+            serialized += size;
+            let scalar = tuple_scalar(peak, &region, coords);
+            if scalar == 0f {
+                loop;
+            }
+
+            let (tuple_points, pos) = if index & PRIVATE_POINT_NUMBERS != 0 {
+                unpack_points(data, tuple_data)
+            } else {
+                (copy shared_points, tuple_data)
+            };
+            let count = match tuple_points {
+                Some(ref tuple_points) => tuple_points.len(),
+                None => points.len()
+            };
+            let (xs, pos) = unpack_deltas(data, pos, count);
+            let (ys, _) = unpack_deltas(data, pos, count);
+
+            let tuple_deltas = match tuple_points {
+                Some(ref tuple_points) => {
+                    let mut touched = vec::from_elem(points.len(), None);
+                    for tuple_points.eachi |i, point| {
+                        if *point < points.len() {
+                            touched[*point] = Some((xs[i], ys[i]));
+                        }
+                    }
+                    interpolate_untouched(points, touched, contour_ends)
+                }
+                None => vec::from_fn(points.len(), |i| (xs[i], ys[i]))
+            };
+            for uint::range(0, points.len()) |i| {
+                let (dx, dy) = deltas[i];
+                let (tuple_dx, tuple_dy) = tuple_deltas[i];
+                deltas[i] = (dx + tuple_dx * scalar, dy + tuple_dy * scalar);
+            }
+        }
+        move deltas
+    }
+}
+
+#[cfg(test)]
+mod variable_tests {
+    use font::FontWeight700;
+
+    fn weight_axis() -> Axis {
+        Axis { tag: tag("wght"), min: 100f, default: 400f, max: 900f }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let axis = weight_axis();
+        assert axis.normalize(400f) == 0f;
+        assert axis.normalize(100f) == -1f;
+        assert axis.normalize(650f) == 0.5f;
+        assert axis.normalize(1000f) == 1f;
+        assert axis.normalize(250f) == -0.5f;
+
+        let map = ~[(-1f, -1f), (0f, 0f), (0.5f, 0.75f), (1f, 1f)];
+        assert apply_segment_map(map, 0.25f) == 0.375f;
+        assert apply_segment_map(map, 0.75f) == 0.875f;
+        assert apply_segment_map(map, -0.5f) == -0.5f;
+
+        let width = Axis { tag: tag("wdth"), min: 75f, default: 100f, max: 100f };
+        let coords = normalized_coords([weight_axis(), width], [map],
+                                       [(tag("wght"), 500f), (tag("wdth"), 50f),
+                                        (tag("wght"), 650f)]);
+        assert coords == ~[0.875f, -1f];
+    }
+
+    #[test]
+    fn test_font_variation_settings() {
+        assert parse_font_variation_settings("normal") == Some(~[]);
+        assert parse_font_variation_settings("\"wght\" 700, 'wdth' 87.5") ==
+            Some(~[(tag("wght"), 700f), (tag("wdth"), 87.5f)]);
+        assert parse_font_variation_settings("\"wght\"").is_none();
+        assert parse_font_variation_settings("wght 700").is_none();
+        assert parse_font_variation_settings("\"wgh\" 700").is_none();
+
+        let style = FontStyle {
+            pt_size: 12f,
+            weight: FontWeight700,
+            stretch: 100f,
+            italic: false,
+            oblique: true,
+            variations: ~[(tag("wght"), 550f)]
+        };
+        assert style_variations(&style) == ~[(tag("wght"), 700f), (tag("wdth"), 100f),
+                                              (tag("slnt"), -14f), (tag("wght"), 550f)];
+    }
+
+    #[test]
+    fn test_parse_fvar() {
+        let mut data = ~[0, 1, 0, 0, 0, 16, 0, 2, 0, 1, 0, 20, 0, 0, 0, 0];
+        data.push_all(str::to_bytes("wght"));
+        data.push_all([0, 100, 0, 0, 1, 0x90, 0, 0, 3, 0x84, 0, 0, 0, 0, 1, 0]);
+        let axes = parse_fvar(data).get();
+        assert axes.len() == 1;
+        assert axes[0].tag == tag("wght");
+        assert axes[0].min == 100f && axes[0].default == 400f && axes[0].max == 900f;
+        assert parse_fvar(vec::slice(data, 0, 30)).is_none();
+    }
+
+    #[test]
+    fn test_glyph_deltas() {
+        // One axis and a glyph of one contour, with a tuple that peaks at
+        // the axis's maximum and moves points 0 and 2
+        let mut data = ~[0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 20, 0, 1, 0, 0, 0, 0, 0, 24];
+        data.push_all([0, 0, 0, 10]); // glyph offsets, in words
+        data.push_all([0, 1, 0, 10]); // one tuple, with its data at 10
+        data.push_all([0, 8, 0xa0, 0x00, 0x40, 0x00]); // embedded peak of 1, private points
+        data.push_all([2, 1, 0, 2]); // points 0 and 2
+        data.push_all([1, 10, 30]); // x deltas
+        data.push_all([0x81]); // y deltas, both zero
+        data.push_all([0, 0]);
+
+        let gvar = parse_gvar(move data).get();
+        let points = ~[(0f, 0f), (50f, 0f), (100f, 0f), (100f, 100f),
+                       (0f, 0f), (100f, 0f), (0f, 0f), (0f, 0f)];
+        let deltas = gvar.glyph_deltas(0, [0.5f], points, [3]);
+        // Point 1 is halfway between points 0 and 2; point 3 is past
+        // point 2, so moves with it
+        assert deltas[0] == (5f, 0f);
+        assert deltas[1] == (10f, 0f);
+        assert deltas[2] == (15f, 0f);
+        assert deltas[3] == (15f, 0f);
+        // Phantom points aren't interpolated
+        assert deltas[5] == (0f, 0f);
+
+        assert gvar.glyph_deltas(0, [0f], points, [3])[2] == (0f, 0f);
+        assert gvar.glyph_deltas(0, [-0.5f], points, [3])[2] == (0f, 0f);
+    }
+}
@@ -0,0 +1,843 @@
+/*!
+WOFF2 decoding. A WOFF2 file is an OpenType font with its tables
+Brotli-compressed together, and `glyf` and `loca` (sometimes `hmtx` too)
+transformed beforehand so that they compress better. Decoding undoes both
+and lays the tables out again as a plain sfnt, which is what FreeType and
+Core Text load.
+
+Font collections, with a `ttcf` flavor, aren't supported. The Brotli
+decompression needs libbrotlidec, which is only linked with `--cfg woff2`
+(configure turns it off if the library isn't found); without it every
+WOFF2 font fails to decompress.
+*/
+
+#[cfg(woff2)]
+use libc::{c_int, size_t};
+
+// Tags, as big-endian numbers
+const WOFF2_SIGNATURE: u32 = 0x774f4632; // wOF2
+const TTCF: u32 = 0x74746366;
+const GLYF: u32 = 0x676c7966;
+const LOCA: u32 = 0x6c6f6361;
+const HMTX: u32 = 0x686d7478;
+const HHEA: u32 = 0x68686561;
+const MAXP: u32 = 0x6d617870;
+const HEAD: u32 = 0x68656164;
+
+// The tables a directory entry can name by index rather than spelling the
+// tag out, four characters each
+const KNOWN_TAGS: &static/str = "cmapheadhheahmtxmaxpnameOS/2postcvt fpgmglyflocaprepCFF VORG\
+EBDTEBLCgasphdmxkernLTSHPCLTVDMXvheavmtxBASEGDEFGPOSGSUBEBSCJSTFMATHCBDTCBLCCOLRCPALSVG sbix\
+acntavarbdatblocbslncvarfdscfeatfmtxfvargvarhstyjustlcarmortmorxopbdproptrakZapfSilfGlatGloc\
+FeatSill";
+const ARBITRARY_TAG: u8 = 63;
+
+// Simple glyph flags
+const ON_CURVE: u8 = 0x01;
+const X_SHORT: u8 = 0x02;
+const Y_SHORT: u8 = 0x04;
+// With X_SHORT, that x is positive; without, that it's unchanged
+const X_SAME: u8 = 0x10;
+const Y_SAME: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+// Composite glyph flags
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+// The whole font's checksum comes out as this, once head says how to adjust it
+const CHECKSUM_MAGIC: u32 = 0xb1b0afba;
+
+// The most decompressed table data a font can ask for, whatever its header
+// says, since the lengths come from the file
+const MAX_SFNT_SIZE: uint = 30 * 1024 * 1024;
+
+// BROTLI_DECODER_RESULT_SUCCESS
+#[cfg(woff2)]
+const BROTLI_SUCCESS: c_int = 1;
+
+pub enum Woff2Error {
+    NotWoff2,
+    FontCollection,
+    DecompressionFailed,
+    // Names the table, or the part of the file, that didn't make sense
+    MalformedFont(~str),
+}
+
+impl Woff2Error : cmp::Eq {
+    pure fn eq(&self, other: &Woff2Error) -> bool {
+        match (copy *self, copy *other) {
+            (NotWoff2, NotWoff2) => true,
+            (FontCollection, FontCollection) => true,
+            (DecompressionFailed, DecompressionFailed) => true,
+            (MalformedFont(a), MalformedFont(b)) => a == b,
+            _ => false
+        }
+    }
+    pure fn ne(&self, other: &Woff2Error) -> bool {
+        !(*self).eq(other)
+    }
+}
+
+/// Big-endian reads from a table, or part of one. Reading past the end
+/// gives zeroes and leaves the stream bad, to be checked once after.
+struct Stream {
+    priv data: ~[u8],
+    priv mut pos: uint,
+    priv mut bad: bool,
+}
+
+fn Stream(data: ~[u8]) -> Stream {
+    Stream { data: move data, pos: 0, bad: false }
+}
+
+impl Stream {
+    pure fn is_ok(&self) -> bool {
+        !self.bad
+    }
+
+    pure fn position(&self) -> uint {
+        self.pos
+    }
+
+    fn u8(&self) -> u8 {
+        if self.pos < self.data.len() {
+            self.pos += 1;
+            self.data[self.pos - 1]
+        } else {
+            self.bad = true;
+            0
+        }
+    }
+
+    fn u16(&self) -> u16 {
+        let high = self.u8() as u16;
+        (high << 8) | self.u8() as u16
+    }
+
+    fn i16(&self) -> i16 {
+        self.u16() as i16
+    }
+
+    fn u32(&self) -> u32 {
+        let high = self.u16() as u32;
+        (high << 16) | self.u16() as u32
+    }
+
+    fn bytes(&self, len: uint) -> ~[u8] {
+        if self.pos + len <= self.data.len() {
+            self.pos += len;
+            vec::slice(self.data, self.pos - len, self.pos)
+        } else {
+            self.bad = true;
+            self.pos = self.data.len();
+            vec::from_elem(len, 0)
+        }
+    }
+
+    fn skip(&self, len: uint) {
+        self.bytes(len);
+    }
+
+    /// A UIntBase128: seven bits a byte, most significant first, in at most
+    /// five bytes and with no leading zeroes.
+    fn base128(&self) -> u32 {
+        let mut n = 0u32;
+        for 5.times {
+            let byte = self.u8();
+            if n == 0 && byte == 0x80 || n & 0xfe000000 != 0 {
+                self.bad = true;
+                return 0;
+            }
+            n = (n << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return n;
+            }
+        }
+        self.bad = true;
+        0
+    }
+
+    /// A 255UInt16, which is one byte for numbers below 253.
+    fn u255(&self) -> u16 {
+        match self.u8() {
+            253 => self.u16(),
+            254 => self.u8() as u16 + 506,
+            255 => self.u8() as u16 + 253,
+            code => code as u16
+        }
+    }
+}
+
+pub fn push_u16(out: &mut ~[u8], n: u16) {
+    out.push((n >> 8) as u8);
+    out.push(n as u8);
+}
+
+pub fn push_u32(out: &mut ~[u8], n: u32) {
+    push_u16(out, (n >> 16) as u16);
+    push_u16(out, n as u16);
+}
+
+fn known_tag(index: uint) -> u32 {
+    let mut tag = 0u32;
+    for uint::range(index * 4, index * 4 + 4) |i| {
+        tag = (tag << 8) | KNOWN_TAGS[i] as u32;
+    }
+    tag
+}
+
+pub fn is_woff2(data: &[u8]) -> bool {
+    data.len() >= 4 && data[0] == 'w' as u8 && data[1] == 'O' as u8 &&
+        data[2] == 'F' as u8 && data[3] == '2' as u8
+}
+
+struct TableEntry {
+    tag: u32,
+    transform: u8,
+    orig_length: uint,
+    // Its length in the decompressed data, which differs if it's transformed
+    stored_length: uint,
+}
+
+impl TableEntry {
+    pure fn is_transformed(&self) -> bool {
+        // For glyf and loca, version 0 is the transform and 3 is none
+        if self.tag == GLYF || self.tag == LOCA {
+            self.transform == 0
+        } else {
+            self.transform != 0
+        }
+    }
+}
+
+/**
+The TrueType or OpenType font in a WOFF2 file. Tables come out in tag
+order, each with its checksum, and head's checksum adjustment is redone
+for the new layout.
+*/
+pub fn decode(data: &[u8]) -> Result<~[u8], Woff2Error> {
+    let header = Stream(vec::from_slice(data));
+    if header.u32() != WOFF2_SIGNATURE {
+        return Err(NotWoff2);
+    }
+    let flavor = header.u32();
+    if flavor == TTCF {
+        return Err(FontCollection);
+    }
+    header.u32(); // length
+    let num_tables = header.u16() as uint;
+    header.u16(); // reserved
+    let total_sfnt_size = header.u32() as uint;
+    let compressed_length = header.u32() as uint;
+    // The version, then the metadata and private blocks, which fonts don't need
+    header.skip(24);
+
+    let mut entries = ~[];
+    for num_tables.times {
+        let flags = header.u8();
+        let tag = if flags & 0x3f == ARBITRARY_TAG {
+            header.u32()
+        } else {
+            known_tag((flags & 0x3f) as uint)
+        };
+        let mut entry = TableEntry {
+            tag: tag,
+            transform: flags >> 6,
+            orig_length: header.base128() as uint,
+            stored_length: 0
+        };
+        entry.stored_length = if entry.is_transformed() {
+            header.base128() as uint
+        } else {
+            entry.orig_length
+        };
+        entries.push(move entry);
+    }
+    if !header.is_ok() || header.position() + compressed_length > data.len() {
+        return Err(MalformedFont(~"table directory"));
+    }
+
+    // Checked as it's added up, so that it can't wrap around
+    let mut total = 0;
+    for entries.each |entry| {
+        total += entry.stored_length;
+        if entry.stored_length > MAX_SFNT_SIZE || total > total_sfnt_size ||
+                total > MAX_SFNT_SIZE {
+            return Err(MalformedFont(~"table directory"));
+        }
+    }
+    let compressed = vec::view(data, header.position(), header.position() + compressed_length);
+    let decompressed = match decompress(compressed, total) {
+        Some(move decompressed) => move decompressed,
+        None => return Err(DecompressionFailed)
+    };
+
+    // The tables come one after another, in directory order
+    let mut stored = ~[];
+    let mut offset = 0;
+    for entries.each |entry| {
+        stored.push(vec::slice(decompressed, offset, offset + entry.stored_length));
+        offset += entry.stored_length;
+    }
+
+    // glyf and loca come back together, and hmtx can need the glyphs'
+    // bounding boxes, so those three are done first
+    let mut glyf = None;
+    let mut x_mins = ~[];
+    match (find_table(entries, GLYF), find_table(entries, LOCA)) {
+        (Some(g), Some(l)) if entries[g].is_transformed() => {
+            if !entries[l].is_transformed() {
+                return Err(MalformedFont(~"loca"));
+            }
+            match reconstruct_glyf(stored[g]) {
+                Ok((move glyf_data, move loca_data, move mins)) => {
+                    if loca_data.len() != entries[l].orig_length {
+                        return Err(MalformedFont(~"loca"));
+                    }
+                    glyf = Some((move glyf_data, move loca_data));
+                    x_mins = move mins;
+                }
+                Err(move err) => return Err(move err)
+            }
+        }
+        _ => ()
+    }
+    let mut hmtx = None;
+    match find_table(entries, HMTX) {
+        Some(h) if entries[h].is_transformed() => {
+            let tables = (find_table(entries, MAXP), find_table(entries, HHEA));
+            let (num_glyphs, num_hmetrics) = match tables {
+                (Some(maxp), Some(hhea)) if stored[maxp].len() >= 6 && stored[hhea].len() >= 36 => {
+                    (Stream(vec::slice(stored[maxp], 4, 6)).u16() as uint,
+                     Stream(vec::slice(stored[hhea], 34, 36)).u16() as uint)
+                }
+                _ => return Err(MalformedFont(~"hmtx"))
+            };
+            match reconstruct_hmtx(stored[h], num_glyphs, num_hmetrics, x_mins) {
+                Ok(move hmtx_data) => hmtx = Some(move hmtx_data),
+                Err(move err) => return Err(move err)
+            }
+        }
+        _ => ()
+    }
+
+    let mut tables = ~[];
+    for entries.eachi |i, entry| {
+        let data = if entry.tag == GLYF && glyf.is_some() {
+            let (ref glyf_data, _) = *glyf.get_ref();
+            copy *glyf_data
+        } else if entry.tag == LOCA && glyf.is_some() {
+            let (_, ref loca_data) = *glyf.get_ref();
+            copy *loca_data
+        } else if entry.tag == HMTX && hmtx.is_some() {
+            copy *hmtx.get_ref()
+        } else if entry.is_transformed() {
+            // Nothing else has a transform yet
+            return Err(MalformedFont(~"table transform"));
+        } else {
+            copy stored[i]
+        };
+        tables.push((entry.tag, move data));
+    }
+    Ok(build_sfnt(flavor, move tables))
+}
+
+fn find_table(entries: &[TableEntry], tag: u32) -> Option<uint> {
+    entries.position(|entry| entry.tag == tag)
+}
+
+#[cfg(woff2)]
+fn decompress(data: &[u8], size: uint) -> Option<~[u8]> unsafe {
+    let mut out = vec::from_elem(size, 0u8);
+    let decoded_size = size as size_t;
+    let result = do vec::as_imm_buf(data) |buf, len| {
+        do vec::as_mut_buf(out) |out_buf, _len| {
+            brotlidec::BrotliDecoderDecompress(len as size_t, buf,
+                                               ptr::to_unsafe_ptr(&decoded_size),
+                                               out_buf as *u8)
+        }
+    };
+    if result == BROTLI_SUCCESS && decoded_size as uint == size {
+        Some(move out)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(woff2))]
+fn decompress(_data: &[u8], _size: uint) -> Option<~[u8]> {
+    debug!("woff2: built without libbrotlidec, so tables can't be decompressed");
+    None
+}
+
+// A point's move from the last, as a glyph stream triplet encodes it
+fn decode_triplet(flag: u8, data: &[u8]) -> (int, int) {
+    let byte = |i: uint| if i < data.len() { data[i] as int } else { 0 };
+    let (b0, b1, b2, b3) = (byte(0), byte(1), byte(2), byte(3));
+    if flag < 10 {
+        (0, with_sign(flag, (((flag & 14) as int) << 7) + b0))
+    } else if flag < 20 {
+        (with_sign(flag, ((((flag - 10) & 14) as int) << 7) + b0), 0)
+    } else if flag < 84 {
+        let b = (flag - 20) as int;
+        (with_sign(flag, 1 + (b & 0x30) + (b0 >> 4)),
+         with_sign(flag >> 1, 1 + ((b & 0x0c) << 2) + (b0 & 0x0f)))
+    } else if flag < 120 {
+        let b = (flag - 84) as int;
+        (with_sign(flag, 1 + ((b / 12) << 8) + b0),
+         with_sign(flag >> 1, 1 + (((b % 12) >> 2) << 8) + b1))
+    } else if flag < 124 {
+        (with_sign(flag, (b0 << 4) + (b1 >> 4)),
+         with_sign(flag >> 1, ((b1 & 0x0f) << 8) + b2))
+    } else {
+        (with_sign(flag, (b0 << 8) + b1), with_sign(flag >> 1, (b2 << 8) + b3))
+    }
+}
+
+pure fn with_sign(flag: u8, n: int) -> int {
+    if flag & 1 != 0 { n } else { -n }
+}
+
+// How many bytes of the glyph stream a triplet with this flag takes
+pure fn triplet_length(flag: u8) -> uint {
+    if flag < 84 { 1 } else if flag < 120 { 2 } else if flag < 124 { 3 } else { 4 }
+}
+
+// A simple glyph's flags and coordinates as TrueType has them, each point
+// relative to the last and in as few bytes as it'll go
+fn encode_points(out: &mut ~[u8], points: &[(int, int, bool)], overlap: bool) {
+    let mut flags = ~[];
+    let mut xs = ~[];
+    let mut ys = ~[];
+    let mut last = (0, 0);
+    for points.eachi |i, point| {
+        let (x, y, on_curve) = *point;
+        let (last_x, last_y) = last;
+        let mut flag = if on_curve { ON_CURVE } else { 0 };
+        if i == 0 && overlap {
+            flag |= OVERLAP_SIMPLE;
+        }
+        flag |= encode_delta(&mut xs, x - last_x, X_SHORT, X_SAME);
+        flag |= encode_delta(&mut ys, y - last_y, Y_SHORT, Y_SAME);
+        flags.push(flag);
+        last = (x, y);
+    }
+    out.push_all(flags);
+    out.push_all(xs);
+    out.push_all(ys);
+}
+
+fn encode_delta(out: &mut ~[u8], delta: int, short: u8, same: u8) -> u8 {
+    if delta == 0 {
+        same
+    } else if delta > -256 && delta < 256 {
+        out.push(int::abs(delta) as u8);
+        if delta > 0 { short | same } else { short }
+    } else {
+        push_u16(out, delta as u16);
+        0
+    }
+}
+
+// Whether glyph `i` has its bit set in `bitmap`, most significant bit first
+pure fn bit_set(bitmap: &[u8], i: uint) -> bool {
+    bitmap[i / 8] & (0x80u >> (i % 8)) as u8 != 0
+}
+
+/**
+glyf and loca from a transformed glyf table, which splits the glyphs'
+contour counts, point counts, flags, coordinates, composite descriptions,
+bounding boxes and instructions into separate streams. Also gives each
+glyph's xMin, for hmtx.
+*/
+fn reconstruct_glyf(data: &[u8]) -> Result<(~[u8], ~[u8], ~[i16]), Woff2Error> {
+    let header = Stream(vec::from_slice(data));
+    header.u16(); // version
+    let option_flags = header.u16();
+    let num_glyphs = header.u16() as uint;
+    let index_format = header.u16();
+    let mut offset = 36;
+    let mut streams = ~[];
+    for 7.times {
+        let length = header.u32() as uint;
+        if offset + length > data.len() {
+            return Err(MalformedFont(~"glyf"));
+        }
+        streams.push(Stream(vec::slice(data, offset, offset + length)));
+        offset += length;
+    }
+    if !header.is_ok() {
+        return Err(MalformedFont(~"glyf"));
+    }
+    let streams = move streams;
+    let (n_contours, n_points, flag_stream, glyphs, composites, bboxes, instructions) =
+        (&streams[0], &streams[1], &streams[2], &streams[3], &streams[4], &streams[5],
+         &streams[6]);
+
+    let bbox_bitmap = bboxes.bytes(4 * ((num_glyphs + 31) / 32));
+    let overlap_bitmap = if option_flags & 1 != 0 {
+        let length = (num_glyphs + 7) / 8;
+        if offset + length > data.len() {
+            return Err(MalformedFont(~"glyf"));
+        }
+        Some(vec::slice(data, offset, offset + length))
+    } else {
+        None
+    };
+
+    let mut glyf = ~[];
+    let mut loca_offsets = ~[];
+    let mut x_mins = ~[];
+    for uint::range(0, num_glyphs) |i| {
+        loca_offsets.push(glyf.len());
+        let explicit_bbox = bit_set(bbox_bitmap, i);
+        let contours = n_contours.i16();
+        if contours == 0 {
+            // Empty
+            if explicit_bbox {
+                return Err(MalformedFont(~"glyf"));
+            }
+            x_mins.push(0);
+            loop;
+        }
+
+        if contours < 0 {
+            // Composite, which must say its bounding box
+            if !explicit_bbox {
+                return Err(MalformedFont(~"glyf"));
+            }
+            let bbox = bboxes.bytes(8);
+            push_u16(&mut glyf, contours as u16);
+            glyf.push_all(bbox);
+            x_mins.push(Stream(move bbox).i16());
+
+            let mut has_instructions = false;
+            loop {
+                let flags = composites.u16();
+                push_u16(&mut glyf, flags);
+                push_u16(&mut glyf, composites.u16()); // glyph index
+                let mut length = if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+                if flags & WE_HAVE_A_SCALE != 0 {
+                    length += 2;
+                } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                    length += 4;
+                } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                    length += 8;
+                }
+                glyf.push_all(composites.bytes(length));
+                if flags & WE_HAVE_INSTRUCTIONS != 0 {
+                    has_instructions = true;
+                }
+                if flags & MORE_COMPONENTS == 0 || !composites.is_ok() {
+                    break;
+                }
+            }
+            if has_instructions {
+                let length = glyphs.u255();
+                push_u16(&mut glyf, length);
+                glyf.push_all(instructions.bytes(length as uint));
+            }
+        } else {
+            let mut end_points = ~[];
+            let mut total = 0;
+            for (contours as uint).times {
+                total += n_points.u255() as uint;
+                end_points.push((total - 1) as u16);
+            }
+            let flags = flag_stream.bytes(total);
+            let mut points = ~[];
+            let (mut x, mut y) = (0, 0);
+            for flags.each |flag| {
+                // The top bit is set for points off the curve
+                let triplet_flag = *flag & 0x7f;
+                let (dx, dy) = decode_triplet(triplet_flag,
+                                              glyphs.bytes(triplet_length(triplet_flag)));
+                x += dx;
+                y += dy;
+                points.push((x, y, *flag & 0x80 == 0));
+            }
+            let instruction_length = glyphs.u255();
+
+            let bbox = if explicit_bbox {
+                let bbox = Stream(bboxes.bytes(8));
+                (bbox.i16() as int, bbox.i16() as int, bbox.i16() as int, bbox.i16() as int)
+            } else {
+                bounding_box(points)
+            };
+            let (x_min, y_min, x_max, y_max) = bbox;
+            push_u16(&mut glyf, contours as u16);
+            push_u16(&mut glyf, x_min as u16);
+            push_u16(&mut glyf, y_min as u16);
+            push_u16(&mut glyf, x_max as u16);
+            push_u16(&mut glyf, y_max as u16);
+            for end_points.each |end| {
+                push_u16(&mut glyf, *end);
+            }
+            push_u16(&mut glyf, instruction_length);
+            glyf.push_all(instructions.bytes(instruction_length as uint));
+            let overlap = match overlap_bitmap {
+                Some(ref bitmap) => bit_set(*bitmap, i),
+                None => false
+            };
+            encode_points(&mut glyf, points, overlap);
+            x_mins.push(x_min as i16);
+        }
+
+        // Glyphs start on four-byte boundaries
+        while glyf.len() % 4 != 0 {
+            glyf.push(0);
+        }
+    }
+    loca_offsets.push(glyf.len());
+
+    for streams.each |stream| {
+        if !stream.is_ok() {
+            return Err(MalformedFont(~"glyf"));
+        }
+    }
+
+    let mut loca = ~[];
+    for loca_offsets.each |offset| {
+        if index_format == 0 {
+            push_u16(&mut loca, (*offset / 2) as u16);
+        } else {
+            push_u32(&mut loca, *offset as u32);
+        }
+    }
+    Ok((move glyf, move loca, move x_mins))
+}
+
+fn bounding_box(points: &[(int, int, bool)]) -> (int, int, int, int) {
+    if points.is_empty() {
+        return (0, 0, 0, 0);
+    }
+    let (x, y, _) = points[0];
+    let mut bbox = (x, y, x, y);
+    for points.each |point| {
+        let (x, y, _) = *point;
+        let (x_min, y_min, x_max, y_max) = bbox;
+        bbox = (int::min(x_min, x), int::min(y_min, y), int::max(x_max, x), int::max(y_max, y));
+    }
+    bbox
+}
+
+/**
+hmtx from its transformed form, which leaves out the left side bearings
+that are the same as the glyphs' xMins. Flag bit 0 says the proportional
+glyphs' are left out, and bit 1 the monospaced ones' at the end.
+*/
+fn reconstruct_hmtx(data: &[u8], num_glyphs: uint, num_hmetrics: uint,
+                    x_mins: &[i16]) -> Result<~[u8], Woff2Error> {
+    let stream = Stream(vec::from_slice(data));
+    let flags = stream.u8();
+    if num_hmetrics == 0 || num_hmetrics > num_glyphs || x_mins.len() < num_glyphs {
+        return Err(MalformedFont(~"hmtx"));
+    }
+    let advances = do vec::from_fn(num_hmetrics) |_i| { stream.u16() };
+
+    let mut hmtx = ~[];
+    for uint::range(0, num_glyphs) |i| {
+        let omitted = if i < num_hmetrics {
+            push_u16(&mut hmtx, advances[i]);
+            flags & 1 != 0
+        } else {
+            flags & 2 != 0
+        };
+        let side_bearing = if omitted { x_mins[i] } else { stream.i16() };
+        push_u16(&mut hmtx, side_bearing as u16);
+    }
+    if stream.is_ok() {
+        Ok(move hmtx)
+    } else {
+        Err(MalformedFont(~"hmtx"))
+    }
+}
+
+// The sum of `data` as big-endian u32s, as the table directory has it
+fn checksum(data: &[u8]) -> u32 {
+    let mut sum = 0u32;
+    let mut i = 0;
+    while i < data.len() {
+        let mut word = 0u32;
+        for uint::range(i, i + 4) |j| {
+            word = (word << 8) | if j < data.len() { data[j] as u32 } else { 0 };
+        }
+        sum += word;
+        i += 4;
+    }
+    sum
+}
+
+/// An sfnt font of `tables`, given as tags and data.
+pub fn build_sfnt(flavor: u32, tables: ~[(u32, ~[u8])]) -> ~[u8] {
+    let mut tables = move tables;
+    std::sort::quick_sort(tables, |a, b| {
+        let (tag_a, _) = *a;
+        let (tag_b, _) = *b;
+        tag_a <= tag_b
+    });
+
+    let num_tables = tables.len();
+    let mut entry_selector = 0;
+    while 1u << (entry_selector + 1) <= num_tables {
+        entry_selector += 1;
+    }
+    let search_range = (1u << entry_selector) * 16;
+
+    let mut sfnt = ~[];
+    push_u32(&mut sfnt, flavor);
+    push_u16(&mut sfnt, num_tables as u16);
+    push_u16(&mut sfnt, search_range as u16);
+    push_u16(&mut sfnt, entry_selector as u16);
+    push_u16(&mut sfnt, (num_tables * 16 - search_range) as u16);
+
+    let mut offset = 12 + 16 * num_tables;
+    let mut head_offset = None;
+    for tables.each |table| {
+        let (tag, ref data) = *table;
+        let mut sum = checksum(*data);
+        if tag == HEAD && data.len() >= 12 {
+            // checkSumAdjustment counts as zero, in head's checksum and the font's
+            sum -= checksum(vec::view(*data, 8, 12));
+            head_offset = Some(offset);
+        }
+        push_u32(&mut sfnt, tag);
+        push_u32(&mut sfnt, sum);
+        push_u32(&mut sfnt, offset as u32);
+        push_u32(&mut sfnt, data.len() as u32);
+        offset += (data.len() + 3) & !3;
+    }
+    for tables.each |table| {
+        let (_, ref data) = *table;
+        sfnt.push_all(*data);
+        while sfnt.len() % 4 != 0 {
+            sfnt.push(0);
+        }
+    }
+
+    match head_offset {
+        Some(head) => {
+            for uint::range(8, 12) |i| {
+                sfnt[head + i] = 0;
+            }
+            let adjustment = CHECKSUM_MAGIC - checksum(sfnt);
+            for uint::range(0, 4) |i| {
+                sfnt[head + 8 + i] = (adjustment >> (24 - 8 * i)) as u8;
+            }
+        }
+        None => ()
+    }
+    move sfnt
+}
+
+#[cfg(woff2)]
+extern mod brotlidec {
+    fn BrotliDecoderDecompress(encoded_size: size_t, encoded_buffer: *u8,
+                               decoded_size: *size_t, decoded_buffer: *u8) -> c_int;
+}
+
+#[cfg(test)]
+mod woff2_tests {
+    #[test]
+    fn test_numbers() {
+        assert Stream(~[0x3f]).base128() == 63;
+        assert Stream(~[0x81, 0x00]).base128() == 128;
+        let leading_zero = Stream(~[0x80, 0x01]);
+        leading_zero.base128();
+        assert !leading_zero.is_ok();
+
+        assert Stream(~[252]).u255() == 252;
+        assert Stream(~[253, 0x01, 0x00]).u255() == 256;
+        assert Stream(~[255, 3]).u255() == 256;
+        assert Stream(~[254, 0]).u255() == 506;
+
+        let short = Stream(~[1]);
+        short.u16();
+        assert !short.is_ok();
+    }
+
+    #[test]
+    fn test_known_tags() {
+        assert known_tag(0) == 0x636d6170; // cmap
+        assert known_tag(10) == GLYF;
+        assert known_tag(11) == LOCA;
+        assert known_tag(62) == 0x53696c6c; // Sill
+    }
+
+    #[test]
+    fn test_reconstruct_glyf() {
+        // An empty glyph, then a triangle: (0, 0), (100, 0), (50, 100)
+        let mut data = ~[0, 0, 0, 0, 0, 2, 0, 0];
+        for [4u32, 1, 3, 5, 0, 4, 0].each |length| {
+            push_u32(&mut data, *length);
+        }
+        data.push_all([0, 0, 0, 1]); // contours
+        data.push_all([3]); // points
+        data.push_all([1, 11, 86]); // flags
+        data.push_all([0, 100, 49, 99, 0]); // triplets and instruction length
+        data.push_all([0, 0, 0, 0]); // bbox bitmap
+
+        let (glyf, loca, x_mins) = reconstruct_glyf(data).get();
+        assert glyf == ~[0, 1, 0, 0, 0, 0, 0, 100, 0, 100, 0, 2, 0, 0,
+                         0x31, 0x33, 0x27, 100, 50, 100];
+        assert loca == ~[0, 0, 0, 0, 0, 10];
+        assert x_mins == ~[0, 0];
+
+        // The bounding boxes must be there, so it's short
+        assert reconstruct_glyf(vec::slice(data, 0, 50)).is_err();
+    }
+
+    #[test]
+    fn test_reconstruct_hmtx() {
+        // One advance, and both kinds of side bearing left out
+        let hmtx = reconstruct_hmtx([3, 0x01, 0xf4], 2, 1, [-5, 7]).get();
+        assert hmtx == ~[0x01, 0xf4, 0xff, 0xfb, 0x00, 0x07];
+
+        let hmtx = reconstruct_hmtx([2, 0x01, 0xf4, 0x00, 0x02], 2, 1, [-5, 7]).get();
+        assert hmtx == ~[0x01, 0xf4, 0x00, 0x02, 0x00, 0x07];
+    }
+
+    #[test]
+    fn test_table_size_limits() {
+        // A header for one cmap table of `length` bytes, and no data
+        let woff2 = |length: &[u8], total_sfnt_size: u32| {
+            let mut data = ~[];
+            push_u32(&mut data, WOFF2_SIGNATURE);
+            push_u32(&mut data, 0x00010000);
+            push_u32(&mut data, 0);
+            push_u16(&mut data, 1);
+            push_u16(&mut data, 0);
+            push_u32(&mut data, total_sfnt_size);
+            push_u32(&mut data, 0);
+            data.push_all(vec::from_elem(24, 0u8));
+            data.push(0);
+            data.push_all(length);
+            move data
+        };
+        let too_big = Err(MalformedFont(~"table directory"));
+        // 0xffffffff bytes
+        assert decode(woff2([0x8f, 0xff, 0xff, 0xff, 0x7f], 0xffffffff)) == too_big;
+        // More than the header says the font is
+        assert decode(woff2([0x81, 0x00], 100)) == too_big;
+    }
+
+    #[test]
+    fn test_build_sfnt() {
+        let mut head = vec::from_elem(54, 0u8);
+        head[8] = 0xff;
+        let sfnt = build_sfnt(0x00010000, ~[(MAXP, ~[0, 0, 0x50, 0, 0, 2]), (HEAD, move head)]);
+        assert sfnt.len() == 12 + 32 + 56 + 8;
+        // head sorts first, then maxp
+        assert Stream(vec::slice(sfnt, 12, 16)).u32() == HEAD;
+        assert Stream(vec::slice(sfnt, 28, 32)).u32() == MAXP;
+        assert Stream(vec::slice(sfnt, 4, 6)).u16() == 2;
+        assert checksum(sfnt) == CHECKSUM_MAGIC;
+
+        assert decode(str::to_bytes("wOFF")).get_err() == NotWoff2;
+    }
+}
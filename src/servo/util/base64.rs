@@ -0,0 +1,128 @@
+/*!
+Base64 as `btoa()` and `atob()` want it. Decoding is the HTML spec's
+"forgiving" base64: whitespace is skipped and the padding is optional, but
+anything else out of place fails. Input is JS string code units.
+*/
+
+const ALPHABET: &static/str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// `bytes` in base64, padded with `=` to a multiple of four characters.
+pub fn encode(bytes: &[u8]) -> ~str {
+    let mut result = ~"";
+    let mut i = 0;
+    while i < bytes.len() {
+        let n = uint::min(3, bytes.len() - i);
+        let b0 = bytes[i] as uint;
+        let b1 = if n > 1 { bytes[i + 1] as uint } else { 0 };
+        let b2 = if n > 2 { bytes[i + 2] as uint } else { 0 };
+        let group = (b0 << 16) | (b1 << 8) | b2;
+        for uint::range(0, 4) |j| {
+            if j <= n {
+                str::push_char(&mut result, ALPHABET[(group >> (18 - 6 * j)) & 63] as char);
+            } else {
+                str::push_char(&mut result, '=');
+            }
+        }
+        i += 3;
+    }
+    move result
+}
+
+// The six bits `c` stands for
+pure fn value_of(c: u16) -> Option<uint> {
+    let c = c as uint;
+    if c >= 'A' as uint && c <= 'Z' as uint {
+        Some(c - 'A' as uint)
+    } else if c >= 'a' as uint && c <= 'z' as uint {
+        Some(c - 'a' as uint + 26)
+    } else if c >= '0' as uint && c <= '9' as uint {
+        Some(c - '0' as uint + 52)
+    } else if c == '+' as uint {
+        Some(62)
+    } else if c == '/' as uint {
+        Some(63)
+    } else {
+        None
+    }
+}
+
+pure fn is_ascii_whitespace(c: u16) -> bool {
+    c == 0x09 || c == 0x0a || c == 0x0c || c == 0x0d || c == 0x20
+}
+
+/// The bytes `input` stands for, or None if it isn't base64.
+pub fn decode(input: &[u16]) -> Option<~[u8]> {
+    let mut chars = input.filter(|c| !is_ascii_whitespace(*c));
+    if chars.len() % 4 == 0 {
+        for uint::range(0, 2) |_| {
+            if !chars.is_empty() && chars.last() == '=' as u16 {
+                chars.pop();
+            }
+        }
+    }
+    if chars.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut result = ~[];
+    let mut buffer = 0u;
+    let mut bits = 0;
+    for chars.each |c| {
+        match value_of(*c) {
+            Some(value) => {
+                buffer = (buffer << 6) | value;
+                bits += 6;
+                if bits >= 8 {
+                    bits -= 8;
+                    result.push(((buffer >> bits) & 0xff) as u8);
+                }
+            }
+            None => return None
+        }
+    }
+    // Whatever bits are left over past the last byte are dropped
+    Some(move result)
+}
+
+#[cfg(test)]
+fn units(s: &str) -> ~[u16] {
+    str::to_bytes(s).map(|b| *b as u16)
+}
+
+#[test]
+fn test_encode() {
+    assert encode([]) == ~"";
+    assert encode(str::to_bytes("f")) == ~"Zg==";
+    assert encode(str::to_bytes("fo")) == ~"Zm8=";
+    assert encode(str::to_bytes("foo")) == ~"Zm9v";
+    assert encode(str::to_bytes("foobar")) == ~"Zm9vYmFy";
+    assert encode([0xff, 0xfe]) == ~"//4=";
+}
+
+#[test]
+fn test_decode() {
+    assert decode([]) == Some(~[]);
+    assert decode(units("Zm9vYmFy")) == Some(str::to_bytes("foobar"));
+    // Padding is optional, and whitespace is skipped
+    assert decode(units("Zg==")) == Some(~[0x66]);
+    assert decode(units("Zg")) == Some(~[0x66]);
+    assert decode(units(" Zm 9v\n")) == Some(str::to_bytes("foo"));
+}
+
+#[test]
+fn test_decode_malformed() {
+    assert decode(units("Z")).is_none();
+    assert decode(units("Zg=")).is_none();
+    assert decode(units("Zg===")).is_none();
+    assert decode(units("Z=g=")).is_none();
+    assert decode(units("Zm9v!")).is_none();
+    assert decode(~[0x5a, 0x100, 0x67, 0x3d]).is_none();
+}
+
+#[test]
+fn test_all_bytes_round_trip() {
+    let bytes = vec::from_fn(256, |i| i as u8);
+    let encoded = encode(bytes);
+    assert encoded.len() == 344;
+    assert decode(units(encoded)) == Some(move bytes);
+}
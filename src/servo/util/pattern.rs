@@ -0,0 +1,313 @@
+/*!
+A small backtracking regular expression matcher, for the `pattern`
+attribute of form controls. It supports the common subset of ECMAScript
+syntax: literals, `.`, the `\d \w \s` classes and their negations,
+bracketed classes with ranges, groups with `|`, and the `* + ? {n,m}`
+quantifiers. Patterns always match the whole string.
+*/
+
+enum Atom {
+    Literal(char),
+    AnyChar,
+    // Inclusive ranges, and whether the class is negated
+    Class(~[(char, char)], bool),
+    Group(~[~[Piece]])
+}
+
+struct Piece {
+    atom: Atom,
+    min: uint,
+    max: uint,
+}
+
+pub struct Pattern {
+    priv alternatives: ~[~[Piece]],
+}
+
+// The ranges of the `\d`, `\w` and `\s` classes
+fn escape_class(c: char) -> Option<(~[(char, char)], bool)> {
+    let digit = ~[('0', '9')];
+    let word = ~[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+    let space = ~[(' ', ' '), ('\t', '\r')];
+    match c {
+        'd' => Some((move digit, false)),
+        'D' => Some((move digit, true)),
+        'w' => Some((move word, false)),
+        'W' => Some((move word, true)),
+        's' => Some((move space, false)),
+        'S' => Some((move space, true)),
+        _ => None
+    }
+}
+
+struct Parser {
+    chars: ~[char],
+    mut pos: uint,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        if self.pos < self.chars.len() { Some(self.chars[self.pos]) } else { None }
+    }
+
+    fn next(&self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn alternatives(&self) -> Result<~[~[Piece]], ()> {
+        let mut alternatives = ~[];
+        loop {
+            match self.sequence() {
+                Ok(move sequence) => alternatives.push(move sequence),
+                Err(()) => return Err(())
+            }
+            if self.peek() == Some('|') {
+                self.pos += 1;
+            } else {
+                return Ok(move alternatives);
+            }
+        }
+    }
+
+    fn sequence(&self) -> Result<~[Piece], ()> {
+        let mut pieces = ~[];
+        loop {
+            match self.peek() {
+                None | Some('|') | Some(')') => return Ok(move pieces),
+                _ => ()
+            }
+            let atom = match self.atom() {
+                Ok(move atom) => move atom,
+                Err(()) => return Err(())
+            };
+            let (min, max) = match self.quantifier() {
+                Ok(bounds) => bounds,
+                Err(()) => return Err(())
+            };
+            pieces.push(Piece { atom: move atom, min: min, max: max });
+        }
+    }
+
+    fn atom(&self) -> Result<Atom, ()> {
+        match self.next() {
+            Some('.') => Ok(AnyChar),
+            Some('(') => {
+                // Non-capturing groups are the same as groups here
+                if self.peek() == Some('?') {
+                    self.pos += 1;
+                    if self.next() != Some(':') {
+                        return Err(());
+                    }
+                }
+                let alternatives = match self.alternatives() {
+                    Ok(move alternatives) => move alternatives,
+                    Err(()) => return Err(())
+                };
+                if self.next() != Some(')') {
+                    return Err(());
+                }
+                Ok(Group(move alternatives))
+            }
+            Some('[') => self.class(),
+            Some('\\') => match self.next() {
+                Some(c) => match escape_class(c) {
+                    Some((move ranges, negated)) => Ok(Class(move ranges, negated)),
+                    None => Ok(Literal(c))
+                },
+                None => Err(())
+            },
+            Some('*') | Some('+') | Some('?') | Some('{') | Some(')') | None => Err(()),
+            Some(c) => Ok(Literal(c))
+        }
+    }
+
+    fn class(&self) -> Result<Atom, ()> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = ~[];
+        loop {
+            let low = match self.next() {
+                Some(']') => return Ok(Class(move ranges, negated)),
+                Some('\\') => match self.next() {
+                    Some(c) => match escape_class(c) {
+                        Some((move class_ranges, false)) => {
+                            ranges.push_all_move(move class_ranges);
+                            loop;
+                        }
+                        // Negated classes inside brackets aren't supported
+                        Some(_) => return Err(()),
+                        None => c
+                    },
+                    None => return Err(())
+                },
+                Some(c) => c,
+                None => return Err(())
+            };
+            if self.peek() == Some('-') && self.pos + 1 < self.chars.len() &&
+               self.chars[self.pos + 1] != ']' {
+                self.pos += 1;
+                let high = self.next().get();
+                if high < low {
+                    return Err(());
+                }
+                ranges.push((low, high));
+            } else {
+                ranges.push((low, low));
+            }
+        }
+    }
+
+    fn number(&self) -> Option<uint> {
+        let start = self.pos;
+        while self.peek().map_default(false, |c| char::is_digit(*c)) {
+            self.pos += 1;
+        }
+        uint::from_str(str::from_chars(vec::slice(self.chars, start, self.pos)))
+    }
+
+    fn quantifier(&self) -> Result<(uint, uint), ()> {
+        match self.peek() {
+            Some('*') => { self.pos += 1; Ok((0, uint::max_value)) }
+            Some('+') => { self.pos += 1; Ok((1, uint::max_value)) }
+            Some('?') => { self.pos += 1; Ok((0, 1)) }
+            Some('{') => {
+                self.pos += 1;
+                let min = match self.number() {
+                    Some(min) => min,
+                    None => return Err(())
+                };
+                let max = if self.peek() == Some(',') {
+                    self.pos += 1;
+                    if self.peek() == Some('}') {
+                        uint::max_value
+                    } else {
+                        match self.number() {
+                            Some(max) if max >= min => max,
+                            _ => return Err(())
+                        }
+                    }
+                } else {
+                    min
+                };
+                if self.next() != Some('}') {
+                    return Err(());
+                }
+                Ok((min, max))
+            }
+            _ => Ok((1, 1))
+        }
+    }
+}
+
+/// Compiles a pattern, or fails if it uses syntax that isn't supported.
+pub fn Pattern(pattern: &str) -> Result<Pattern, ()> {
+    let parser = Parser { chars: str::chars(pattern), pos: 0 };
+    match parser.alternatives() {
+        Ok(move alternatives) if parser.pos == parser.chars.len() => {
+            Ok(Pattern { alternatives: move alternatives })
+        }
+        _ => Err(())
+    }
+}
+
+fn match_alternatives(alternatives: &[~[Piece]], chars: &[char], pos: uint,
+                      k: fn&(uint) -> bool) -> bool {
+    alternatives.any(|sequence| match_sequence(*sequence, 0, chars, pos, k))
+}
+
+fn match_sequence(sequence: &[Piece], i: uint, chars: &[char], pos: uint,
+                  k: fn&(uint) -> bool) -> bool {
+    if i == sequence.len() {
+        return k(pos);
+    }
+    match_piece(&sequence[i], 0, chars, pos, |next| match_sequence(sequence, i + 1, chars, next, k))
+}
+
+// Greedily matches `piece` as many more times as it can, then backtracks
+fn match_piece(piece: &Piece, count: uint, chars: &[char], pos: uint,
+               k: fn&(uint) -> bool) -> bool {
+    if count < piece.max {
+        let matched_more = do match_atom(&piece.atom, chars, pos) |next| {
+            // An empty match can't make progress, so stop repeating it
+            (next != pos || count < piece.min) && match_piece(piece, count + 1, chars, next, k)
+        };
+        if matched_more {
+            return true;
+        }
+    }
+    count >= piece.min && k(pos)
+}
+
+fn match_atom(atom: &Atom, chars: &[char], pos: uint, k: fn&(uint) -> bool) -> bool {
+    match *atom {
+        Literal(c) => pos < chars.len() && chars[pos] == c && k(pos + 1),
+        AnyChar => pos < chars.len() && chars[pos] != '\n' && k(pos + 1),
+        Class(ref ranges, negated) => {
+            pos < chars.len() &&
+                ranges.any(|r| { let (low, high) = *r; chars[pos] >= low && chars[pos] <= high })
+                    != negated &&
+                k(pos + 1)
+        }
+        Group(ref alternatives) => match_alternatives(*alternatives, chars, pos, k)
+    }
+}
+
+impl Pattern {
+    /// Whether the pattern matches all of `s`.
+    fn matches(&self, s: &str) -> bool {
+        let chars = str::chars(s);
+        match_alternatives(self.alternatives, chars, 0, |end| end == chars.len())
+    }
+}
+
+#[cfg(test)]
+fn matches(pattern: &str, s: &str) -> bool {
+    Pattern(pattern).get().matches(s)
+}
+
+#[test]
+fn test_literals_and_classes() {
+    assert matches("abc", "abc");
+    assert !matches("abc", "abcd");
+    assert matches("a.c", "axc");
+    assert matches("[A-Z]{3}", "ABC");
+    assert !matches("[A-Z]{3}", "AbC");
+    assert matches("[^0-9]+", "abc");
+    assert !matches("[^0-9]+", "a1");
+    assert matches("\\d{3}-\\d{4}", "555-1234");
+    assert matches("[\\w.]+", "a_b.c");
+    assert matches("\\.", ".");
+    assert !matches("\\.", "x");
+}
+
+#[test]
+fn test_quantifiers_backtrack() {
+    assert matches("a*ab", "aaab");
+    assert matches("(ab)+", "ababab");
+    assert !matches("(ab)+", "aba");
+    assert matches("colou?r", "color");
+    assert matches("x{2,}", "xxxx");
+    assert !matches("x{2,3}", "xxxx");
+    assert matches("(a*)*b", "aab");
+}
+
+#[test]
+fn test_alternatives() {
+    assert matches("cat|dog", "dog");
+    assert !matches("cat|dog", "catdog");
+    assert matches("(?:https?|ftp)://.+", "https://example.com");
+}
+
+#[test]
+fn test_unsupported_syntax() {
+    assert Pattern("(abc").is_err();
+    assert Pattern("a{2").is_err();
+    assert Pattern("*a").is_err();
+    assert Pattern("[z-a]").is_err();
+}
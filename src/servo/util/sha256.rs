@@ -0,0 +1,90 @@
+/*!
+SHA-256 (FIPS 180-4), for the places that need a digest of some bytes.
+*/
+
+const K: [u32 * 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2
+];
+
+pure fn rotr(x: u32, n: u32) -> u32 {
+    (x >> n) | (x << (32 - n))
+}
+
+/// The 32 byte SHA-256 digest of `data`.
+pub fn sha256(data: &[u8]) -> ~[u8] {
+    let mut h: ~[u32] = ~[0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+                          0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+
+    // Pad with a 1 bit, zeroes and the length in bits to a multiple of 64 bytes
+    let mut message = vec::from_slice(data);
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    let bits = (data.len() as u64) * 8;
+    for uint::range(0, 8) |i| {
+        message.push((bits >> ((56 - 8 * i) as u64)) as u8);
+    }
+
+    let mut w = vec::from_elem(64, 0u32);
+    let mut chunk = 0;
+    while chunk < message.len() {
+        for uint::range(0, 16) |i| {
+            let at = chunk + 4 * i;
+            w[i] = (message[at] as u32 << 24) | (message[at + 1] as u32 << 16) |
+                   (message[at + 2] as u32 << 8) | message[at + 3] as u32;
+        }
+        for uint::range(16, 64) |i| {
+            let s0 = rotr(w[i - 15], 7) ^ rotr(w[i - 15], 18) ^ (w[i - 15] >> 3);
+            let s1 = rotr(w[i - 2], 17) ^ rotr(w[i - 2], 19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16] + s0 + w[i - 7] + s1;
+        }
+
+        let (mut a, mut b, mut c, mut d) = (h[0], h[1], h[2], h[3]);
+        let (mut e, mut f, mut g, mut hh) = (h[4], h[5], h[6], h[7]);
+        for uint::range(0, 64) |i| {
+            let s1 = rotr(e, 6) ^ rotr(e, 11) ^ rotr(e, 25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh + s1 + ch + K[i] + w[i];
+            let s0 = rotr(a, 2) ^ rotr(a, 13) ^ rotr(a, 22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0 + maj;
+            hh = g; g = f; f = e; e = d + t1;
+            d = c; c = b; b = a; a = t1 + t2;
+        }
+        h[0] += a; h[1] += b; h[2] += c; h[3] += d;
+        h[4] += e; h[5] += f; h[6] += g; h[7] += hh;
+        chunk += 64;
+    }
+
+    let mut digest = ~[];
+    for h.each |word| {
+        for [24u32, 16, 8, 0].each |shift| {
+            digest.push((*word >> *shift) as u8);
+        }
+    }
+    move digest
+}
+
+#[cfg(test)]
+fn hex(digest: &[u8]) -> ~str {
+    str::concat(digest.map(|b| fmt!("%02x", *b as uint)))
+}
+
+#[test]
+fn test_sha256() {
+    assert hex(sha256(~[])) ==
+        ~"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    assert hex(sha256(str::to_bytes("abc"))) ==
+        ~"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    // Two blocks once padded
+    assert hex(sha256(str::to_bytes("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"))) ==
+        ~"248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1";
+}
@@ -0,0 +1,238 @@
+/*!
+A small wrapper over SQLite, for what pages keep on disk: the Cache API's
+responses and IndexedDB's databases. Errors are SQLite's messages.
+
+SQLite is only linked with `--cfg sqlite` (configure turns it off if the
+library isn't found); without it every connection fails to open, so
+pages can't keep anything.
+*/
+
+use libc::{c_char, c_int, c_void};
+#[cfg(not(sqlite))]
+use util::sqlite::unlinked::*;
+
+// How long to wait for another connection's transaction to finish
+const BUSY_TIMEOUT_MS: c_int = 5000;
+
+pub struct Connection {
+    priv db: *sqlite3,
+
+    drop {
+        sqlite3_close(self.db);
+    }
+}
+
+/// Opens (or creates) a database. ":memory:" opens one that's never
+/// written to disk.
+#[cfg(sqlite)]
+pub fn Connection(path: &str) -> Result<Connection, ~str> {
+    let db: *sqlite3 = ptr::null();
+    let rc = do str::as_c_str(path) |path| {
+        sqlite3_open(path, ptr::to_unsafe_ptr(&db))
+    };
+    if db.is_null() {
+        return Err(~"out of memory");
+    }
+    let conn = Connection { db: db };
+    if rc != SQLITE_OK {
+        return Err(conn.error());
+    }
+    sqlite3_busy_timeout(db, BUSY_TIMEOUT_MS);
+    Ok(move conn)
+}
+
+#[cfg(not(sqlite))]
+pub fn Connection(_path: &str) -> Result<Connection, ~str> {
+    Err(~"servo was built without SQLite")
+}
+
+impl Connection {
+    /**
+    Runs `f` in a transaction, which is rolled back if it fails. The write
+    lock is taken up front, so that two writers can't deadlock upgrading
+    their read locks.
+    */
+    fn transaction<T>(&self, f: fn() -> Result<T, ~str>) -> Result<T, ~str> {
+        match self.exec("BEGIN IMMEDIATE") {
+            Ok(()) => (),
+            Err(move e) => return Err(move e)
+        }
+        match f() {
+            Ok(move result) => self.exec("COMMIT").map(|_| move result),
+            Err(move e) => {
+                self.exec("ROLLBACK");
+                Err(move e)
+            }
+        }
+    }
+
+    fn exec(&self, sql: &str) -> Result<(), ~str> {
+        let rc = do str::as_c_str(sql) |sql| {
+            sqlite3_exec(self.db, sql, ptr::null(), ptr::null(), ptr::null())
+        };
+        if rc == SQLITE_OK { Ok(()) } else { Err(self.error()) }
+    }
+
+    fn prepare(&self, sql: &str) -> Result<Statement, ~str> {
+        let stmt: *sqlite3_stmt = ptr::null();
+        let rc = do str::as_c_str(sql) |sql| {
+            sqlite3_prepare_v2(self.db, sql, -1, ptr::to_unsafe_ptr(&stmt), ptr::null())
+        };
+        if rc == SQLITE_OK {
+            Ok(Statement { stmt: stmt, db: self.db })
+        } else {
+            Err(self.error())
+        }
+    }
+
+    /// The rowid of the last row inserted.
+    fn last_insert_rowid(&self) -> i64 {
+        sqlite3_last_insert_rowid(self.db)
+    }
+
+    /// How many rows the last statement changed.
+    fn changes(&self) -> uint {
+        sqlite3_changes(self.db) as uint
+    }
+
+    fn error(&self) -> ~str {
+        unsafe { str::raw::from_c_str(sqlite3_errmsg(self.db)) }
+    }
+}
+
+pub struct Statement {
+    priv stmt: *sqlite3_stmt,
+    priv db: *sqlite3,
+
+    drop {
+        sqlite3_finalize(self.stmt);
+    }
+}
+
+impl Statement {
+    // Text and blobs are copied by SQLite, so needn't outlive the call
+    fn bind_text(&self, index: int, text: &str) {
+        do str::as_c_str(text) |s| {
+            sqlite3_bind_text(self.stmt, index as c_int, s, -1, SQLITE_TRANSIENT());
+        }
+    }
+
+    fn bind_blob(&self, index: int, blob: &[u8]) {
+        do vec::as_imm_buf(blob) |buf, len| {
+            sqlite3_bind_blob(self.stmt, index as c_int, buf as *c_void, len as c_int,
+                              SQLITE_TRANSIENT());
+        }
+    }
+
+    fn bind_int(&self, index: int, n: int) {
+        sqlite3_bind_int64(self.stmt, index as c_int, n as i64);
+    }
+
+    fn bind_null(&self, index: int) {
+        sqlite3_bind_null(self.stmt, index as c_int);
+    }
+
+    /// Runs the statement until its next row, returning whether there was one.
+    fn step(&self) -> Result<bool, ~str> {
+        match sqlite3_step(self.stmt) {
+            SQLITE_ROW => Ok(true),
+            SQLITE_DONE => Ok(false),
+            _ => unsafe { Err(str::raw::from_c_str(sqlite3_errmsg(self.db))) }
+        }
+    }
+
+    fn column_int(&self, column: int) -> int {
+        sqlite3_column_int64(self.stmt, column as c_int) as int
+    }
+
+    fn column_text(&self, column: int) -> ~str {
+        let text = sqlite3_column_text(self.stmt, column as c_int);
+        if text.is_null() { ~"" } else { unsafe { str::raw::from_c_str(text) } }
+    }
+
+    fn column_blob(&self, column: int) -> ~[u8] {
+        let blob = sqlite3_column_blob(self.stmt, column as c_int);
+        let len = sqlite3_column_bytes(self.stmt, column as c_int) as uint;
+        if blob.is_null() {
+            ~[]
+        } else {
+            unsafe { vec::from_buf(blob as *u8, len) }
+        }
+    }
+
+    fn column_is_null(&self, column: int) -> bool {
+        sqlite3_column_type(self.stmt, column as c_int) == SQLITE_NULL
+    }
+}
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_NULL: c_int = 5;
+
+// Tells SQLite to copy what's bound
+fn SQLITE_TRANSIENT() -> *c_void {
+    unsafe { cast::reinterpret_cast(&-1) }
+}
+
+enum sqlite3 {}
+enum sqlite3_stmt {}
+
+#[cfg(sqlite)]
+extern mod sqlite3 {
+    fn sqlite3_open(filename: *c_char, db: **sqlite3) -> c_int;
+    fn sqlite3_close(db: *sqlite3) -> c_int;
+    fn sqlite3_busy_timeout(db: *sqlite3, ms: c_int) -> c_int;
+    fn sqlite3_errmsg(db: *sqlite3) -> *c_char;
+    fn sqlite3_exec(db: *sqlite3, sql: *c_char, callback: *c_void, arg: *c_void,
+                    errmsg: **c_char) -> c_int;
+    fn sqlite3_last_insert_rowid(db: *sqlite3) -> i64;
+    fn sqlite3_changes(db: *sqlite3) -> c_int;
+    fn sqlite3_prepare_v2(db: *sqlite3, sql: *c_char, len: c_int, stmt: **sqlite3_stmt,
+                          tail: **c_char) -> c_int;
+    fn sqlite3_finalize(stmt: *sqlite3_stmt) -> c_int;
+    fn sqlite3_step(stmt: *sqlite3_stmt) -> c_int;
+    fn sqlite3_bind_text(stmt: *sqlite3_stmt, index: c_int, text: *c_char, len: c_int,
+                         destructor: *c_void) -> c_int;
+    fn sqlite3_bind_blob(stmt: *sqlite3_stmt, index: c_int, blob: *c_void, len: c_int,
+                         destructor: *c_void) -> c_int;
+    fn sqlite3_bind_int64(stmt: *sqlite3_stmt, index: c_int, n: i64) -> c_int;
+    fn sqlite3_bind_null(stmt: *sqlite3_stmt, index: c_int) -> c_int;
+    fn sqlite3_column_int64(stmt: *sqlite3_stmt, column: c_int) -> i64;
+    fn sqlite3_column_text(stmt: *sqlite3_stmt, column: c_int) -> *c_char;
+    fn sqlite3_column_blob(stmt: *sqlite3_stmt, column: c_int) -> *c_void;
+    fn sqlite3_column_bytes(stmt: *sqlite3_stmt, column: c_int) -> c_int;
+    fn sqlite3_column_type(stmt: *sqlite3_stmt, column: c_int) -> c_int;
+}
+
+// Stand-ins for the above, which nothing reaches, since no Connection can
+// be opened
+#[cfg(not(sqlite))]
+mod unlinked {
+    use libc::{c_char, c_int, c_void};
+    use util::sqlite::{sqlite3, sqlite3_stmt};
+
+    pub fn sqlite3_open(_filename: *c_char, _db: **sqlite3) -> c_int { fail }
+    pub fn sqlite3_close(_db: *sqlite3) -> c_int { fail }
+    pub fn sqlite3_busy_timeout(_db: *sqlite3, _ms: c_int) -> c_int { fail }
+    pub fn sqlite3_errmsg(_db: *sqlite3) -> *c_char { fail }
+    pub fn sqlite3_exec(_db: *sqlite3, _sql: *c_char, _callback: *c_void, _arg: *c_void,
+                        _errmsg: **c_char) -> c_int { fail }
+    pub fn sqlite3_last_insert_rowid(_db: *sqlite3) -> i64 { fail }
+    pub fn sqlite3_changes(_db: *sqlite3) -> c_int { fail }
+    pub fn sqlite3_prepare_v2(_db: *sqlite3, _sql: *c_char, _len: c_int,
+                              _stmt: **sqlite3_stmt, _tail: **c_char) -> c_int { fail }
+    pub fn sqlite3_finalize(_stmt: *sqlite3_stmt) -> c_int { fail }
+    pub fn sqlite3_step(_stmt: *sqlite3_stmt) -> c_int { fail }
+    pub fn sqlite3_bind_text(_stmt: *sqlite3_stmt, _index: c_int, _text: *c_char, _len: c_int,
+                             _destructor: *c_void) -> c_int { fail }
+    pub fn sqlite3_bind_blob(_stmt: *sqlite3_stmt, _index: c_int, _blob: *c_void, _len: c_int,
+                             _destructor: *c_void) -> c_int { fail }
+    pub fn sqlite3_bind_int64(_stmt: *sqlite3_stmt, _index: c_int, _n: i64) -> c_int { fail }
+    pub fn sqlite3_bind_null(_stmt: *sqlite3_stmt, _index: c_int) -> c_int { fail }
+    pub fn sqlite3_column_int64(_stmt: *sqlite3_stmt, _column: c_int) -> i64 { fail }
+    pub fn sqlite3_column_text(_stmt: *sqlite3_stmt, _column: c_int) -> *c_char { fail }
+    pub fn sqlite3_column_blob(_stmt: *sqlite3_stmt, _column: c_int) -> *c_void { fail }
+    pub fn sqlite3_column_bytes(_stmt: *sqlite3_stmt, _column: c_int) -> c_int { fail }
+    pub fn sqlite3_column_type(_stmt: *sqlite3_stmt, _column: c_int) -> c_int { fail }
+}
@@ -1,4 +1,5 @@
-export make_url, UrlMap, url_map;
+export make_url, origin, same_origin, UrlMap, url_map, parse_ipv4, parse_ipv6, format_ipv6,
+       ipv6_host, bare_host, url_host, url_to_str;
 
 use std::net::url;
 use std::net::url::Url;
@@ -25,14 +26,22 @@ fn make_url(str_url: ~str, current_url: Option<Url>) -> Url {
         } else {
             let current_url = current_url.get();
             #debug("make_url: current_url: %?", current_url);
-            if current_url.path.is_empty() || current_url.path.ends_with("/") {
-                current_url.scheme + "://" + current_url.host + "/" + str_url
+            if str_url.starts_with("?") || str_url.starts_with("#") {
+                // Only the query and fragment, or just the fragment, change
+                let mut base = move current_url;
+                base.fragment = None;
+                if str_url.starts_with("?") {
+                    base.query = ~[];
+                }
+                url_to_str(move base) + str_url
+            } else if current_url.path.is_empty() || current_url.path.ends_with("/") {
+                current_url.scheme + "://" + url_host(current_url.host) + "/" + str_url
             } else {
                 let path = str::split_char(current_url.path, '/');
                 let path = path.init();
                 let path = str::connect(path + ~[move str_url], "/");
 
-                current_url.scheme + "://" + current_url.host + path
+                current_url.scheme + "://" + url_host(current_url.host) + path
             }
         }
     } else {
@@ -40,7 +49,168 @@ fn make_url(str_url: ~str, current_url: Option<Url>) -> Url {
     };
 
     // FIXME: Need to handle errors
-    url::from_str(str_url).get()
+    let mut url = url::from_str(str_url).get();
+    // IPv6 hosts are stored in canonical form, without their brackets, so
+    // that they can be resolved as they are
+    match ipv6_host(url.host) {
+        Some(groups) => url.host = format_ipv6(groups),
+        None => ()
+    }
+    move url
+}
+
+/// Parses a dotted-decimal IPv4 address.
+fn parse_ipv4(s: &str) -> Option<~[u8]> {
+    let parts = str::split_char(s, '.');
+    if parts.len() != 4 {
+        return None;
+    }
+    let mut bytes = ~[];
+    for parts.each |part| {
+        if part.is_empty() || part.len() > 3 || !str::all(*part, char::is_digit) {
+            return None;
+        }
+        match uint::from_str(*part) {
+            Some(n) if n <= 255 => bytes.push(n as u8),
+            _ => return None
+        }
+    }
+    Some(move bytes)
+}
+
+// Parses colon-separated hex groups. The last may be an IPv4 address, which
+// counts as two groups, if `ipv4_tail` is set.
+fn parse_groups(s: &str, ipv4_tail: bool) -> Option<~[u16]> {
+    if s.is_empty() {
+        return Some(~[]);
+    }
+    let parts = str::split_char(s, ':');
+    let mut groups = ~[];
+    for parts.eachi |i, part| {
+        if ipv4_tail && i == parts.len() - 1 && str::contains_char(*part, '.') {
+            match parse_ipv4(*part) {
+                Some(bytes) => {
+                    groups.push((bytes[0] as u16 << 8) | bytes[1] as u16);
+                    groups.push((bytes[2] as u16 << 8) | bytes[3] as u16);
+                }
+                None => return None
+            }
+        } else if part.is_empty() || part.len() > 4 ||
+                  !str::all(*part, |c| char::is_digit_radix(c, 16)) {
+            return None;
+        } else {
+            groups.push(uint::from_str_radix(*part, 16).get() as u16);
+        }
+    }
+    Some(move groups)
+}
+
+/**
+Parses the text form of an IPv6 address (RFC 4291) into its eight 16-bit
+groups. A `::` stands for one or more groups of zeroes, and the last 32
+bits may be written as an IPv4 address.
+*/
+fn parse_ipv6(s: &str) -> Option<~[u16]> {
+    match str::find_str(s, "::") {
+        Some(i) => {
+            let tail = s.slice(i + 2, s.len());
+            if str::find_str(tail, "::").is_some() {
+                return None;
+            }
+            match (parse_groups(s.slice(0, i), false), parse_groups(tail, true)) {
+                (Some(head), Some(tail)) if head.len() + tail.len() < 8 => {
+                    Some(head + vec::from_elem(8 - head.len() - tail.len(), 0u16) + tail)
+                }
+                _ => None
+            }
+        }
+        None => match parse_groups(s, true) {
+            Some(move groups) if groups.len() == 8 => Some(move groups),
+            _ => None
+        }
+    }
+}
+
+/// Formats an IPv6 address canonically (RFC 5952): lowercase hex without
+/// leading zeroes, with the longest run of two or more zero groups as `::`.
+fn format_ipv6(groups: &[u16]) -> ~str {
+    let mut (best_start, best_len) = (0, 0);
+    let mut i = 0;
+    while i < groups.len() {
+        if groups[i] == 0 {
+            let start = i;
+            while i < groups.len() && groups[i] == 0 {
+                i += 1;
+            }
+            if i - start > best_len {
+                best_start = start;
+                best_len = i - start;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    let hex = |groups: &[u16]| str::connect(groups.map(|g| fmt!("%x", *g as uint)), ":");
+    if best_len < 2 {
+        hex(groups)
+    } else {
+        hex(vec::view(groups, 0, best_start)) + "::" +
+            hex(vec::view(groups, best_start + best_len, groups.len()))
+    }
+}
+
+/// The address of a host written as an IPv6 literal, like `[::1]`.
+fn ipv6_host(host: &str) -> Option<~[u16]> {
+    if host.starts_with("[") && host.ends_with("]") {
+        parse_ipv6(host.slice(1, host.len() - 1))
+    } else {
+        None
+    }
+}
+
+/// A URL's host as it's resolved or connected to: without the brackets
+/// around an IPv6 literal.
+fn bare_host(host: &str) -> ~str {
+    if host.starts_with("[") && host.ends_with("]") {
+        host.slice(1, host.len() - 1)
+    } else {
+        host.to_str()
+    }
+}
+
+/// A host as it's written in a URL: an IPv6 address goes in brackets.
+fn url_host(host: &str) -> ~str {
+    if str::contains_char(host, ':') && !host.starts_with("[") {
+        fmt!("[%s]", host)
+    } else {
+        host.to_str()
+    }
+}
+
+/// Serializes a URL, putting back the brackets `make_url` took off an IPv6
+/// host.
+fn url_to_str(url: Url) -> ~str {
+    let mut url = move url;
+    url.host = url_host(url.host);
+    url::to_str(move url)
+}
+
+/// The origin of a URL as a string, e.g. `http://example.com:8000`. URLs
+/// that don't have a host, like `file:` ones, are opaque origins.
+fn origin(url: &Url) -> ~str {
+    if url.host.is_empty() {
+        return ~"null";
+    }
+    match url.port {
+        Some(ref port) => fmt!("%s://%s:%s", url.scheme, url_host(url.host), *port),
+        None => fmt!("%s://%s", url.scheme, url_host(url.host))
+    }
+}
+
+/// Whether two URLs have the same origin. An opaque origin is unique, so
+/// it isn't the same as any other, not even another opaque one.
+fn same_origin(a: &Url, b: &Url) -> bool {
+    !a.host.is_empty() && a.scheme == b.scheme && a.host == b.host && a.port == b.port
 }
 
 mod make_url_tests {
@@ -98,6 +268,179 @@ mod make_url_tests {
         assert new_url.path == ~"/snarf/crumpet.html";
     }
 
+    #[test]
+    fn should_keep_the_path_for_a_query_or_fragment() {
+        let old_url = make_url(~"http://example.com/snarf/index.html?a=b#c", None);
+        let new_url = make_url(~"?d=e", Some(copy old_url));
+        assert new_url.path == ~"/snarf/index.html";
+        assert new_url.query == ~[(~"d", ~"e")];
+        assert new_url.fragment.is_none();
+        let new_url = make_url(~"#f", Some(move old_url));
+        assert new_url.path == ~"/snarf/index.html";
+        assert new_url.query == ~[(~"a", ~"b")];
+        assert new_url.fragment == Some(~"f");
+    }
+
+    #[test]
+    fn should_canonicalize_ipv6_hosts() {
+        let url = make_url(~"http://[2001:DB8:0:0:0:0:0:1]:8080/index.html", None);
+        assert url.host == ~"2001:db8::1";
+        assert url.port == Some(~"8080");
+        assert origin(&url) == ~"http://[2001:db8::1]:8080";
+        assert url_to_str(copy url) == ~"http://[2001:db8::1]:8080/index.html";
+        let relative = make_url(~"other.html", Some(move url));
+        assert relative.host == ~"2001:db8::1";
+    }
+}
+
+mod ipv6_tests {
+
+    #[test]
+    fn should_parse_ipv6_addresses() {
+        assert parse_ipv6("::") == Some(~[0, 0, 0, 0, 0, 0, 0, 0]);
+        assert parse_ipv6("::1") == Some(~[0, 0, 0, 0, 0, 0, 0, 1]);
+        assert parse_ipv6("2001:db8::8:800:200c:417a") ==
+            Some(~[0x2001, 0xdb8, 0, 0, 8, 0x800, 0x200c, 0x417a]);
+        assert parse_ipv6("fe80:0:0:0:0:0:0:1") == Some(~[0xfe80, 0, 0, 0, 0, 0, 0, 1]);
+        assert parse_ipv6("::ffff:192.0.2.128") == Some(~[0, 0, 0, 0, 0, 0xffff, 0xc000, 0x280]);
+    }
+
+    #[test]
+    fn should_reject_bad_ipv6_addresses() {
+        assert parse_ipv6("").is_none();
+        assert parse_ipv6("1:2:3:4:5:6:7").is_none();
+        assert parse_ipv6("1:2:3:4:5:6:7:8:9").is_none();
+        assert parse_ipv6("1::2::3").is_none();
+        assert parse_ipv6("1:2:3:4:5:6:7::8").is_none();
+        assert parse_ipv6("12345::").is_none();
+        assert parse_ipv6("g::").is_none();
+        assert parse_ipv6("1.2.3.4::").is_none();
+        assert parse_ipv6("::256.0.0.1").is_none();
+        assert ipv6_host("::1").is_none();
+    }
+
+    #[test]
+    fn should_format_ipv6_canonically() {
+        assert format_ipv6(~[0, 0, 0, 0, 0, 0, 0, 0]) == ~"::";
+        assert format_ipv6(~[0x2001, 0xdb8, 0, 0, 0, 0, 0, 1]) == ~"2001:db8::1";
+        // A lone zero group isn't compressed
+        assert format_ipv6(~[0x2001, 0xdb8, 0, 1, 1, 1, 1, 1]) == ~"2001:db8:0:1:1:1:1:1";
+        // The first of two equally long runs is
+        assert format_ipv6(~[1, 0, 0, 1, 0, 0, 1, 1]) == ~"1::1:0:0:1:1";
+        assert format_ipv6(~[1, 0, 0, 1, 0, 0, 0, 1]) == ~"1:0:0:1::1";
+    }
+}
+
+// Randomized tests of the URL parser, from a fixed seed so that failures
+// can be reproduced
+mod url_property_tests {
+    use std::net::url::{Url, UserInfo, from_str, to_str};
+
+    const CASES: uint = 100000;
+
+    fn test_rng() -> rand::Rng {
+        rand::seeded_rng(&~[0x73u8, 0x65, 0x72, 0x76, 0x6f])
+    }
+
+    fn random_string(rng: rand::Rng, chars: &str, max_len: uint) -> ~str {
+        let mut s = ~"";
+        for rng.gen_uint_range(0, max_len + 1).times {
+            str::push_char(&mut s, rng.gen_char_from(chars));
+        }
+        move s
+    }
+
+    // A path segment, sometimes with a percent-encoded byte in it. Only
+    // bytes the encoder escapes itself can round-trip: others come back
+    // decoded, and those past ASCII on their own aren't UTF-8
+    fn random_segment(rng: rand::Rng) -> ~str {
+        let mut segment = random_string(rng, "abcXYZ019-._~!$&'()*+,;=:@", 6);
+        if rng.gen_bool() {
+            segment += fmt!("%%%02X", rng.gen_char_from("\"<>\\^`{|}") as uint);
+        }
+        move segment
+    }
+
+    // Any (scheme, host, port, path, query, fragment), with any of the
+    // optional parts left out or empty
+    fn random_url(rng: rand::Rng) -> Url {
+        let scheme = random_string(rng, "abcdefghijklmnopqrstuvwxyz", 1) +
+                     random_string(rng, "abcxyz019+.-", 5);
+        let host = random_string(rng, "abcxyz019.-", 12);
+        let port = if rng.gen_bool() {
+            Some(uint::to_str(rng.gen_uint_range(0, 65536), 10))
+        } else {
+            None
+        };
+        let mut path = ~"";
+        for rng.gen_uint_range(0, 4).times {
+            path += ~"/" + random_segment(rng);
+        }
+        let mut query = ~[];
+        for rng.gen_uint_range(0, 3).times {
+            query.push((random_segment(rng), random_segment(rng)));
+        }
+        let fragment = if rng.gen_bool() { Some(random_segment(rng)) } else { None };
+
+        Url(move scheme, None::<UserInfo>, move host, move port, move path, move query,
+            move fragment)
+    }
+
+    #[test]
+    fn href_round_trips() {
+        let rng = test_rng();
+        for CASES.times {
+            let href = to_str(random_url(rng));
+            match from_str(href) {
+                Ok(move url) => {
+                    let reparsed = to_str(move url);
+                    if reparsed != href {
+                        fail fmt!("%s reparsed as %s", href, reparsed);
+                    }
+                }
+                Err(move e) => fail fmt!("%s failed to parse: %s", href, e)
+            }
+        }
+    }
+
+    #[test]
+    fn origin_never_fails() {
+        let rng = test_rng();
+        for CASES.times {
+            let s = if rng.gen_bool() {
+                to_str(random_url(rng))
+            } else {
+                random_string(rng, "abc:/?#@%[]019.", 24)
+            };
+            match from_str(s) {
+                Ok(url) => { origin(&url); }
+                Err(_) => ()
+            }
+        }
+    }
+
+    #[test]
+    fn same_origin_ignores_path_query_and_fragment() {
+        let a = from_str(~"http://example.com:8000/a?b=c#d").get();
+        let b = from_str(~"http://example.com:8000/e").get();
+        let c = from_str(~"http://example.com/a?b=c#d").get();
+        assert same_origin(&a, &b);
+        assert !same_origin(&a, &c);
+        assert origin(&a) == ~"http://example.com:8000";
+        assert origin(&c) == ~"http://example.com";
+    }
+
+    #[test]
+    fn opaque_origins_are_never_the_same() {
+        let file = from_str(~"file:///tmp/a.html").get();
+        let data = from_str(~"data:text/html,hi").get();
+        let http = from_str(~"http://example.com/").get();
+        assert !same_origin(&file, &file);
+        assert !same_origin(&data, &file);
+        assert !same_origin(&http, &file);
+        assert !same_origin(&file, &http);
+        assert same_origin(&http, &http);
+    }
 }
 
 type UrlMap<T: Copy> = HashMap<Url, T>;
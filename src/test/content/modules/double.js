@@ -0,0 +1,6 @@
+export var counter = 0;
+counter++;
+
+export function double(x) {
+  return x * 2;
+}
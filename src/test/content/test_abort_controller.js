@@ -0,0 +1,32 @@
+var controller = new AbortController();
+var signal = controller.signal;
+is(signal.aborted, false);
+is(signal.reason, undefined);
+
+var heard = 0;
+signal.addEventListener("abort", function() { heard++; });
+
+// The reason is only held by the signal, and has to outlive a collection
+controller.abort({why: "stopped"});
+gc();
+is(signal.aborted, true);
+is(signal.reason.why, "stopped");
+
+// Aborting again changes nothing
+controller.abort("again");
+is(heard, 1);
+is(signal.reason.why, "stopped");
+
+var thrown = null;
+try {
+  signal.throwIfAborted();
+} catch (e) {
+  thrown = e;
+}
+is(thrown.why, "stopped");
+
+// Without a reason, it's an AbortError
+var aborted = AbortSignal.abort();
+is(aborted.aborted, true);
+is(aborted.reason.name, "AbortError");
+finish();
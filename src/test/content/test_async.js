@@ -0,0 +1,32 @@
+var steps = [];
+
+function later(value) {
+  return new Promise(function(resolve) {
+    window.setTimeout(function() { resolve(value); }, 0);
+  });
+}
+
+async function run() {
+  steps.push("start");
+  var first = await Promise.resolve(1);
+  // Resumed from the microtask queue, after the script finished
+  steps.push("resumed " + first);
+  var second = await later(2);
+  steps.push("after timeout " + second);
+  try {
+    await Promise.reject(new Error("no"));
+  } catch (e) {
+    steps.push("caught " + e.message);
+  }
+  return first + second;
+}
+
+var result = run();
+steps.push("returned");
+is(result instanceof Promise, true);
+
+result.then(function(sum) {
+  is(steps.join(", "), "start, returned, resumed 1, after timeout 2, caught no");
+  is(sum, 3);
+  finish();
+});
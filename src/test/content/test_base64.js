@@ -0,0 +1,30 @@
+is(btoa(""), "");
+is(atob(""), "");
+is(btoa("foobar"), "Zm9vYmFy");
+is(btoa("fo"), "Zm8=");
+is(atob("Zm8="), "fo");
+is(atob("Zm8"), "fo");
+is(atob(" Zm9v\nYmFy "), "foobar");
+
+// Every byte value survives the round trip
+var all = "";
+for (var i = 0; i < 256; i++) {
+  all += String.fromCharCode(i);
+}
+is(btoa(all).length, 344);
+is(atob(btoa(all)), all);
+
+function throwsName(f) {
+  try {
+    f();
+  } catch (e) {
+    return e.name;
+  }
+  return "nothing";
+}
+
+is(throwsName(() => btoa("Ā")), "InvalidCharacterError");
+is(throwsName(() => atob("Z")), "InvalidCharacterError");
+is(throwsName(() => atob("Zm9v!")), "InvalidCharacterError");
+is(throwsName(() => atob("Zg===")), "InvalidCharacterError");
+finish();
@@ -0,0 +1,61 @@
+// A new cache each run, since they're kept on disk
+var name = "test_cache_storage_" + Date.now();
+var url = "http://example.com/data.json";
+
+var response = new Response("{\"a\": 1}",
+                            {status: 200, headers: {"Content-Type": "application/json"}});
+is(response.status, 200);
+is(response.ok, true);
+is(response.headers["content-type"], "application/json");
+
+var request = new Request(url, {method: "get"});
+is(request.method, "GET");
+is(request.url, url);
+
+var cache;
+caches.open(name).then(function(opened) {
+  cache = opened;
+  return cache.put(request, response);
+}).then(function() {
+  // A URL stands for a GET of it, and the fragment is ignored
+  return cache.match(url + "#top");
+}).then(function(found) {
+  is(found.status, 200);
+  is(found.headers["content-type"], "application/json");
+  return found.text();
+}).then(function(text) {
+  is(text, "{\"a\": 1}");
+  return cache.match(new Request(url, {method: "POST"}));
+}).then(function(found) {
+  is(found, undefined);
+  return cache.put(new Request(url, {method: "POST"}), new Response(""));
+}).then(null, function(error) {
+  is(error.name, "TypeError");
+  return cache.keys();
+}).then(function(requests) {
+  is(requests.length, 1);
+  is(requests[0].url, url);
+  return caches.match(url);
+}).then(function(found) {
+  is(found.status, 200);
+  return caches.keys();
+}).then(function(names) {
+  is(names.indexOf(name) >= 0, true);
+  return cache.delete(url);
+}).then(function(deleted) {
+  is(deleted, true);
+  return cache.match(url);
+}).then(function(found) {
+  is(found, undefined);
+  return caches.delete(name);
+}).then(function(deleted) {
+  is(deleted, true);
+  return caches.has(name);
+}).then(function(has) {
+  is(has, false);
+  finish();
+});
+
+// Only the content task holds on to the promise caches.open gave until the
+// cache is open, and the chain above has to survive a collection meanwhile
+gc();
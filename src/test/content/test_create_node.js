@@ -0,0 +1,23 @@
+// Nodes made by the document are wrapped like the parser's
+var div = document.createElement("DIV");
+is(div.tagName, "div");
+is(div.nodeType, 1);
+is(div instanceof HTMLDivElement, true);
+is(div.getAttribute("id"), null);
+
+var text = document.createTextNode("hello");
+is(text.nodeType, 3);
+is(text.data, "hello");
+is(text instanceof Text, true);
+is(text instanceof Node, true);
+
+// Each node has one wrapper, and many nodes of each kind can be made
+var texts = [];
+for (var i = 0; i < 1000; i++) {
+  texts.push(document.createTextNode("t" + i));
+  document.createElement("span");
+}
+is(texts[999].data, "t999");
+is(texts[0].data, "t0");
+
+finish();
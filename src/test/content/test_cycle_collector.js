@@ -0,0 +1,18 @@
+// A listener that refers to its own element makes a cycle through the
+// element's wrapper. The cycle collector runs after GCs, and must leave
+// it be while the element is in the document.
+var clicks = 0;
+(function() {
+  var element = document.documentElement;
+  element.addEventListener("click", () => { element; clicks++; });
+})();
+
+gc();
+window.setTimeout(function() {
+  gc();
+  window.setTimeout(function() {
+    document.documentElement.dispatchEvent({type: "click"});
+    is(clicks, 1);
+    finish();
+  }, 0);
+}, 0);
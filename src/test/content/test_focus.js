@@ -0,0 +1,37 @@
+function buttons(node, found) {
+  for (var child = node.firstChild; child; child = child.nextSibling) {
+    if (child.nodeType == 1) {
+      if (child.tagName.toUpperCase() == "BUTTON") {
+        found.push(child);
+      }
+      buttons(child, found);
+    }
+  }
+  return found;
+}
+
+var found = buttons(document.documentElement, []);
+var first = found[0];
+var second = found[1];
+var heard = [];
+["focus", "focusin", "blur", "focusout"].forEach(function(kind) {
+  first.addEventListener(kind, function() { heard.push("first " + kind); });
+  second.addEventListener(kind, function() { heard.push("second " + kind); });
+});
+
+// The events are dispatched before focus() returns
+first.focus();
+is(heard.join(), "first focus,first focusin");
+
+// The focused element is only held by the window, and has to outlive a
+// collection
+first = null;
+found = null;
+gc();
+second.focus();
+is(heard.join(), "first focus,first focusin,first blur,first focusout,second focus,second focusin");
+
+second.blur();
+is(heard.length, 8);
+is(heard[7], "second focusout");
+finish();
@@ -0,0 +1,111 @@
+// A new database each run, since they're kept on disk
+var name = "test_indexeddb_" + Date.now();
+
+var request = indexedDB.open(name, 1);
+var upgraded = false;
+request.onupgradeneeded = function(event) {
+  upgraded = true;
+  is(event.oldVersion, 0);
+  is(event.newVersion, 1);
+  var db = request.result;
+  var people = db.createObjectStore("people", {keyPath: "id"});
+  is(people.keyPath, "id");
+  var byEmail = people.createIndex("by_email", "email", {unique: true});
+  is(byEmail.name, "by_email");
+  is(byEmail.keyPath, "email");
+  is(byEmail.unique, true);
+  is(byEmail.objectStore, people);
+  db.createObjectStore("notes");
+};
+request.onsuccess = function() {
+  is(upgraded, true);
+  var db = request.result;
+  is(db.version, 1);
+  write(db);
+};
+
+function write(db) {
+  var tx = db.transaction(["people", "notes"], "readwrite");
+  var people = tx.objectStore("people");
+  people.add({id: 1, name: "Ann", email: "ann@example.com"});
+  people.put({id: 2, name: "Bob", email: "bob@example.com"});
+  var duplicate = people.add({id: 1, name: "Eve", email: "eve@example.com"});
+  duplicate.onerror = function(event) {
+    is(duplicate.error.name, "ConstraintError");
+    // Otherwise the whole transaction would abort
+    event.preventDefault();
+  };
+  var note = tx.objectStore("notes").add("remember the milk", "milk");
+  note.onsuccess = function() {
+    is(note.result, "milk");
+  };
+  var error = null;
+  try {
+    tx.objectStore("notes").add("no key");
+  } catch (e) {
+    error = e.name;
+  }
+  is(error, "DataError");
+  tx.oncomplete = function() {
+    read(db);
+  };
+}
+
+function read(db) {
+  var tx = db.transaction("people");
+  var people = tx.objectStore("people");
+  var all = people.getAll();
+  all.onsuccess = function() {
+    is(all.result.map(function(p) { return p.name; }).join(","), "Ann,Bob");
+  };
+  // Requests only run once the script that made them has finished
+  is(all.readyState, "pending");
+  var bob = people.get(2);
+  bob.onsuccess = function() {
+    is(bob.result.email, "bob@example.com");
+  };
+  var missing = people.get(3);
+  missing.onsuccess = function() {
+    is(missing.result, undefined);
+  };
+  var error = null;
+  try {
+    people.put({id: 4});
+  } catch (e) {
+    error = e.name;
+  }
+  is(error, "ReadOnlyError");
+  tx.oncomplete = function() {
+    rollBack(db);
+  };
+}
+
+function rollBack(db) {
+  var tx = db.transaction("people", "readwrite");
+  var people = tx.objectStore("people");
+  people.put({id: 3, name: "Cy"});
+  // Fails, and isn't cancelled, so the put is undone too
+  people.add({id: 1, name: "Ann again"});
+  tx.onabort = function() {
+    is(tx.error.name, "ConstraintError");
+    var check = db.transaction("people").objectStore("people").getAll();
+    check.onsuccess = function() {
+      is(check.result.length, 2);
+      inactive(db);
+    };
+  };
+}
+
+function inactive(db) {
+  var people = db.transaction("people").objectStore("people");
+  window.setTimeout(function() {
+    var error = null;
+    try {
+      people.get(1);
+    } catch (e) {
+      error = e.name;
+    }
+    is(error, "TransactionInactiveError");
+    finish();
+  }, 0);
+}
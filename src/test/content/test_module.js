@@ -0,0 +1,8 @@
+import { double, counter } from "./modules/double.js";
+import "./modules/double.js";
+
+is(double(21), 42);
+// Imported twice, but only run once
+is(counter, 1);
+is(import.meta.url.indexOf("test_module.js") != -1, true);
+finish();
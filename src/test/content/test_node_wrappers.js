@@ -0,0 +1,13 @@
+// A node has one wrapper, which the GC leaves alone while the node is around
+var root = document.documentElement;
+is(root === document.documentElement, true);
+is(root.firstChild === document.documentElement.firstChild, true);
+
+root.expando = "kept";
+root = null;
+
+window.setTimeout(function() {
+  gc();
+  is(document.documentElement.expando, "kept");
+  finish();
+}, 0);
@@ -0,0 +1,27 @@
+// contenttest runs this with --permissions grant and
+// --enable-notification-debug
+is(Notification.permission, "granted");
+
+var events = [];
+var notification = new Notification("Hello", {body: "world"});
+is(notification.title, "Hello");
+is(notification.body, "world");
+
+notification.onshow = function() {
+  events.push("show");
+  notification.debugClick();
+};
+notification.onclick = function(event) {
+  events.push("click");
+  is(event.target, notification);
+  notification.close();
+};
+notification.onclose = function() {
+  events.push("close");
+  // Once it's closed, there's nothing to click
+  notification.debugClick();
+  window.setTimeout(function() {
+    is(events.join(","), "show,click,close");
+    finish();
+  }, 0);
+};
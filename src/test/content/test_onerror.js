@@ -0,0 +1,26 @@
+is(window.onerror, null);
+
+var errors = [];
+window.onerror = function(error) {
+  errors.push(error);
+  // Keeps it out of the log
+  return true;
+};
+
+window.setTimeout(function() {
+  throw new Error("boom");
+}, 0);
+
+// The handler runs once the microtask has returned, before the next task
+queueMicrotask(function() {
+  throw new Error("from a microtask");
+});
+
+window.setTimeout(function() {
+  is(errors.length, 2);
+  is(/from a microtask/.test(errors[0]), true);
+  // file:line:column: message
+  is(/test_onerror\.js:\d+:\d+: .*boom/.test(errors[1]), true);
+  window.onerror = null;
+  finish();
+}, 0);
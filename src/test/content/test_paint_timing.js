@@ -0,0 +1,16 @@
+// Paint entries are only recorded once the page is shown, after this runs
+is(performance.getEntriesByType("paint").length, 0);
+
+var observer = new PerformanceObserver(function(list) {
+  var paints = list.getEntries();
+  is(paints.length, 1);
+  is(paints[0] instanceof PerformancePaintTiming, true);
+  is(paints[0].name, "first-contentful-paint");
+  is(paints[0].startTime > 0, true);
+  is(performance.getEntriesByName("first-contentful-paint", "paint").length, 1);
+  // The largest contentful paint waits for the user to interact
+  is(performance.getEntriesByType("largest-contentful-paint").length, 0);
+  observer.disconnect();
+  finish();
+});
+observer.observe({entryTypes: ["paint", "largest-contentful-paint"]});
@@ -0,0 +1,35 @@
+var start = performance.mark("start");
+is(start instanceof PerformanceMark, true);
+is(start instanceof PerformanceEntry, true);
+is(start.entryType, "mark");
+is(start.duration, 0);
+is(performance.now() >= start.startTime, true);
+
+var failed = false;
+try {
+  performance.measure("broken", "no such mark");
+} catch (e) {
+  failed = true;
+}
+is(failed, true);
+
+var observer = new PerformanceObserver(function(list, obs) {
+  is(obs, observer);
+  var names = list.getEntries().map(function(e) { return e.name; });
+  // Both entries come in one call, after the script that made them
+  is(names.join(", "), "end, start to end");
+  is(list.getEntriesByType("measure").length, 1);
+  is(list.getEntriesByName("end")[0].entryType, "mark");
+  is(observer.takeRecords().length, 0);
+  observer.disconnect();
+  finish();
+});
+observer.observe({entryTypes: ["mark", "measure", "no-such-type"]});
+
+performance.mark("end");
+var measure = performance.measure("start to end", "start", "end");
+is(measure instanceof PerformanceMeasure, true);
+is(measure.startTime, start.startTime);
+is(performance.getEntriesByType("mark").length, 2);
+performance.clearMarks("start");
+is(performance.getEntriesByName("start").length, 0);
@@ -0,0 +1,54 @@
+is(typeof Proxy, "function");
+
+var log = [];
+var target = { a: 1 };
+var proxy = new Proxy(target, {
+  get: function(t, name) {
+    log.push("get " + String(name));
+    return name in t ? t[name] : "missing " + String(name);
+  },
+  set: function(t, name, value) {
+    t[name] = value * 2;
+    return true;
+  },
+  has: function(t, name) {
+    return name == "virtual" || name in t;
+  },
+  deleteProperty: function(t, name) {
+    log.push("delete " + name);
+    return delete t[name];
+  },
+  ownKeys: function(t) {
+    return Object.keys(t).concat(["virtual"]);
+  },
+  getOwnPropertyDescriptor: function(t, name) {
+    if (name == "virtual") {
+      return { value: "v", enumerable: true, configurable: true };
+    }
+    return Object.getOwnPropertyDescriptor(t, name);
+  }
+});
+
+is(proxy.a, 1);
+is(proxy.b, "missing b");
+proxy.c = 5;
+is(target.c, 10);
+is("virtual" in proxy, true);
+is("nothing" in proxy, false);
+delete proxy.a;
+is("a" in target, false);
+is(Object.keys(proxy).join(), "c,virtual");
+is(log.join(), "get a,get b,delete a");
+
+// A revoked proxy throws on any use
+var revocable = Proxy.revocable({}, {});
+revocable.revoke();
+var threw = false;
+try {
+  revocable.proxy.x;
+} catch (e) {
+  threw = e instanceof TypeError;
+}
+is(threw, true);
+
+finish();
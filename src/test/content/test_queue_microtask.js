@@ -0,0 +1,34 @@
+var steps = [];
+
+window.setTimeout(function() {
+  steps.push("timeout");
+  is(steps.join(", "),
+     "sync, microtask 1, promise, microtask 2, kept, nested, nested again, timeout");
+  finish();
+}, 0);
+
+queueMicrotask(function() {
+  steps.push("microtask 1");
+  // Queued during the checkpoint: runs at the end of the same one
+  queueMicrotask(function() {
+    steps.push("nested");
+    queueMicrotask(function() { steps.push("nested again"); });
+  });
+});
+Promise.resolve().then(function() { steps.push("promise"); });
+queueMicrotask(function() { steps.push("microtask 2"); });
+// Only the queue holds on to this one, and it has to outlive a collection
+(function() {
+  var word = "kept";
+  queueMicrotask(function() { steps.push(word); });
+})();
+gc();
+steps.push("sync");
+
+var thrown = "nothing";
+try {
+  queueMicrotask("not a function");
+} catch (e) {
+  thrown = e.name;
+}
+is(thrown, "TypeError");
@@ -0,0 +1,85 @@
+is(typeof Reflect, "object");
+
+// Each function against the object operation it stands for
+function Point(x, y) {
+  this.x = x;
+  this.y = y;
+}
+Point.prototype.sum = function(extra) { return this.x + this.y + extra; };
+
+var p = Reflect.construct(Point, [1, 2]);
+is(p instanceof Point, true);
+is(p.x, new Point(1, 2).x);
+is(Reflect.apply(Point.prototype.sum, p, [3]), p.sum(3));
+
+var o = {};
+is(Reflect.defineProperty(o, "a", { value: 1, writable: true, configurable: true }), true);
+is(o.a, 1);
+Object.freeze(o);
+is(Reflect.defineProperty(o, "b", { value: 2 }), false);
+is("b" in o, false);
+
+var d = { a: 1 };
+is(Reflect.deleteProperty(d, "a"), true);
+is("a" in d, false);
+Object.defineProperty(d, "fixed", { value: 1, configurable: false });
+is(Reflect.deleteProperty(d, "fixed"), false);
+
+// get and set take a receiver, so getters and setters see it as this
+var withAccessor = {
+  get double() { return this.n * 2; },
+  set double(v) { this.n = v / 2; }
+};
+var receiver = { n: 5 };
+is(Reflect.get(withAccessor, "double", receiver), 10);
+is(Reflect.get({ a: 1 }, "a"), ({ a: 1 }).a);
+is(Reflect.set(withAccessor, "double", 8, receiver), true);
+is(receiver.n, 4);
+var frozen = Object.freeze({ a: 1 });
+is(Reflect.set(frozen, "a", 2), false);
+is(frozen.a, 1);
+
+var desc = Reflect.getOwnPropertyDescriptor({ a: 1 }, "a");
+var expected = Object.getOwnPropertyDescriptor({ a: 1 }, "a");
+is(desc.value, expected.value);
+is(desc.writable, expected.writable);
+is(desc.enumerable, expected.enumerable);
+is(desc.configurable, expected.configurable);
+is(Reflect.getOwnPropertyDescriptor({}, "a"), undefined);
+
+is(Reflect.getPrototypeOf(p), Object.getPrototypeOf(p));
+is(Reflect.has(p, "sum"), "sum" in p);
+is(Reflect.has(p, "nothing"), false);
+
+var e = {};
+is(Reflect.isExtensible(e), Object.isExtensible(e));
+is(Reflect.preventExtensions(e), true);
+is(Reflect.isExtensible(e), false);
+is(Object.isExtensible(e), false);
+
+var s = Symbol("s");
+var keyed = { b: 1, a: 2 };
+keyed[s] = 3;
+Object.defineProperty(keyed, "hidden", { value: 4, enumerable: false });
+var keys = Reflect.ownKeys(keyed);
+is(keys.length, 4);
+is(keys.slice(0, 3).join(), Object.getOwnPropertyNames(keyed).join());
+is(keys[3], Object.getOwnPropertySymbols(keyed)[0]);
+
+var proto = { inherited: true };
+var child = {};
+is(Reflect.setPrototypeOf(child, proto), true);
+is(Object.getPrototypeOf(child), proto);
+is(child.inherited, true);
+is(Reflect.setPrototypeOf(Object.preventExtensions({}), proto), false);
+
+// Non-objects are a TypeError, unlike the Object functions that coerce
+var threw = false;
+try {
+  Reflect.getPrototypeOf(1);
+} catch (err) {
+  threw = err instanceof TypeError;
+}
+is(threw, true);
+
+finish();
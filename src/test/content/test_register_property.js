@@ -0,0 +1,39 @@
+function throwsNamed(f) {
+  try {
+    f();
+  } catch (e) {
+    return e.name;
+  }
+  return null;
+}
+
+CSS.registerProperty({name: "--size", syntax: "<length>", inherits: false,
+                      initialValue: "0px"});
+CSS.registerProperty({name: "--anything", inherits: true});
+
+is(throwsNamed(function() {
+  CSS.registerProperty({name: "--size", syntax: "<length>", inherits: false,
+                        initialValue: "0px"});
+}), "InvalidModificationError");
+is(throwsNamed(function() {
+  CSS.registerProperty({name: "size", inherits: false});
+}), "SyntaxError");
+is(throwsNamed(function() {
+  CSS.registerProperty({name: "--tint", syntax: "<color>", inherits: false});
+}), "SyntaxError");
+is(throwsNamed(function() {
+  CSS.registerProperty({name: "--tint", syntax: "<color>", inherits: false,
+                        initialValue: "10px"});
+}), "SyntaxError");
+is(throwsNamed(function() {
+  CSS.registerProperty({name: "--tint", syntax: "<colour>", inherits: false,
+                        initialValue: "red"});
+}), "SyntaxError");
+is(throwsNamed(function() {
+  CSS.registerProperty({name: "--tint"});
+}), "TypeError");
+
+// Changing a registered property with a transition starts one
+var box = document.getElementById("box");
+box.setAttribute("style", "--size: 20px; transition: --size 1s");
+finish();
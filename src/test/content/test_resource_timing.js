@@ -0,0 +1,23 @@
+var entries = performance.getEntriesByType("resource");
+var scripts = entries.filter(function(e) {
+  return e.name.indexOf("test_resource_timing.js") != -1;
+});
+is(scripts.length, 1);
+
+var script = scripts[0];
+is(script instanceof PerformanceResourceTiming, true);
+is(script.entryType, "resource");
+is(script.initiatorType, "script");
+is(script.startTime, script.fetchStart);
+is(script.redirectStart, 0);
+// The script comes from the same origin, so nothing is hidden
+is(script.requestStart >= script.fetchStart, true);
+is(script.responseStart >= script.requestStart, true);
+is(script.responseEnd >= script.responseStart, true);
+is(script.duration, script.responseEnd - script.startTime);
+is(script.encodedBodySize > 0, true);
+is(script.decodedBodySize, script.encodedBodySize);
+is(script.transferSize > script.encodedBodySize, true);
+
+is(entries.filter(function(e) { return e.initiatorType == "script"; }).length, 2);
+finish();
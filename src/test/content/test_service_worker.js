@@ -0,0 +1,23 @@
+// The page was loaded before anything was registered, so nothing controls it
+is(navigator.serviceWorker.controller, null);
+
+var href = window.location.href;
+var directory = href.slice(0, href.lastIndexOf("/") + 1);
+
+navigator.serviceWorker.register("test_service_worker_sw.js").then(function(registration) {
+  // Without a scope, the worker gets its script's directory
+  is(registration.scope, directory);
+  is(registration.active.scriptURL, directory + "test_service_worker_sw.js");
+  is(registration.active.state, "activated");
+  // A worker can't reach outside its directory
+  return navigator.serviceWorker.register("test_service_worker_sw.js", {scope: "/"});
+}).then(function() {
+  is(true, false);
+}, function(error) {
+  is(error.name, "SecurityError");
+  return navigator.serviceWorker.register("no_such_worker.js");
+}).then(function() {
+  is(true, false);
+}, function(error) {
+  is(error.name, "TypeError");
+}).then(finish);
@@ -0,0 +1,10 @@
+// Waits on a promise while it installs, and answers for /cached.txt itself
+addEventListener("install", function(event) {
+  event.waitUntil(Promise.resolve());
+});
+
+addEventListener("fetch", function(event) {
+  if (event.request.url.slice(-"/cached.txt".length) == "/cached.txt") {
+    event.respondWith(new Response("from the worker", {headers: {"Content-Type": "text/plain"}}));
+  }
+});
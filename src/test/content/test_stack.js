@@ -0,0 +1,45 @@
+function inner() {
+  return new Error("deep");
+}
+function outer() {
+  return inner();
+}
+
+var e = outer();
+var lines = e.stack.split("\n");
+is(lines[0], "Error: deep");
+is(/^    at inner \(.*test_stack\.js:2:\d+\)$/.test(lines[1]), true);
+is(/^    at outer \(.*test_stack\.js:5:\d+\)$/.test(lines[2]), true);
+is(Object.keys(e).indexOf("stack"), -1);
+
+// stack can be replaced on one error
+e.stack = "replaced";
+is(e.stack, "replaced");
+is(new Error("other").stack.split("\n")[0], "Error: other");
+
+is(typeof Error.captureStackTrace, "function");
+
+var plain = { toString: function() { return "Plain"; } };
+function capture() {
+  Error.captureStackTrace(plain);
+}
+capture();
+is(plain.stack.split("\n")[0], "Plain");
+is(/^    at capture \(/.test(plain.stack.split("\n")[1]), true);
+is(Object.getOwnPropertyDescriptor(plain, "stack").enumerable, false);
+
+// Frames from the constructor up are left out
+function MyError(message) {
+  this.message = message;
+  Error.captureStackTrace(this, MyError);
+}
+MyError.prototype.toString = function() { return "MyError: " + this.message; };
+function make() {
+  return new MyError("custom");
+}
+var custom = make();
+var customLines = custom.stack.split("\n");
+is(customLines[0], "MyError: custom");
+is(/^    at make \(/.test(customLines[1]), true);
+
+finish();
@@ -0,0 +1,61 @@
+is(typeof Symbol, "function");
+
+var s = Symbol("desc");
+is(typeof s, "symbol");
+is(s.toString(), "Symbol(desc)");
+is(s === Symbol("desc"), false);
+is(Symbol.for("shared"), Symbol.for("shared"));
+
+var wellKnown = ["iterator", "toPrimitive", "toStringTag", "hasInstance", "species"];
+for (var i = 0; i < wellKnown.length; i++) {
+  is(typeof Symbol[wellKnown[i]], "symbol");
+}
+
+// A user-defined [Symbol.iterator] is what for-of calls
+var calls = 0;
+var range = {};
+range[Symbol.iterator] = function() {
+  calls++;
+  var n = 0;
+  return { next: function() { return n < 3 ? { value: n++, done: false } : { done: true }; } };
+};
+var seen = [];
+for (var x of range) {
+  seen.push(x);
+}
+is(calls, 1);
+is(seen.join(","), "0,1,2");
+
+var money = {};
+money[Symbol.toPrimitive] = function(hint) { return hint == "number" ? 42 : "forty-two"; };
+is(+money, 42);
+is(`${money}`, "forty-two");
+
+var tagged = {};
+tagged[Symbol.toStringTag] = "Tagged";
+is(Object.prototype.toString.call(tagged), "[object Tagged]");
+
+function Even() {}
+Object.defineProperty(Even, Symbol.hasInstance, { value: function(n) { return n % 2 == 0; } });
+is(2 instanceof Even, true);
+is(3 instanceof Even, false);
+
+class MyArray extends Array {
+  static get [Symbol.species]() { return Array; }
+}
+var mapped = new MyArray(1, 2, 3).map(function(n) { return n * 2; });
+is(mapped instanceof MyArray, false);
+is(mapped instanceof Array, true);
+
+// Bindings with [Symbol.iterator] work with for-of
+var form = new FormData();
+form.append("a", "1");
+form.append("b", "2");
+is(typeof form[Symbol.iterator], "function");
+var pairs = [];
+for (var entry of form) {
+  pairs.push(entry[0] + "=" + entry[1]);
+}
+is(pairs.join("&"), "a=1&b=2");
+
+finish();
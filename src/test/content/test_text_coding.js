@@ -0,0 +1,51 @@
+var encoder = new TextEncoder();
+is(encoder.encoding, "utf-8");
+var bytes = encoder.encode("hé€😀");
+is(bytes.length, 10);
+is(bytes[1], 0xc3);
+is(bytes[6], 0xf0);
+// A lone surrogate is written as U+FFFD
+is(encoder.encode("\ud83d").join(), "239,191,189");
+
+var decoder = new TextDecoder();
+is(decoder.encoding, "utf-8");
+is(decoder.fatal, false);
+is(decoder.ignoreBOM, false);
+is(decoder.decode(bytes), "hé€😀");
+is(decoder.decode(bytes.buffer), "hé€😀");
+is(decoder.decode(), "");
+is(decoder.decode(new Uint8Array([0xef, 0xbb, 0xbf, 0x61])), "a");
+is(decoder.decode(new Uint8Array([0x61, 0xff, 0x62])), "a\ufffdb");
+is(new TextDecoder("utf-8", {ignoreBOM: true}).decode(new Uint8Array([0xef, 0xbb, 0xbf])),
+   "\ufeff");
+
+is(new TextDecoder("utf-16le").decode(new Uint8Array([0x68, 0x00, 0x3d, 0xd8, 0x00, 0xde])),
+   "h😀");
+is(new TextDecoder("UTF-16BE").decode(new Uint8Array([0x20, 0xac])), "€");
+var latin1 = new TextDecoder("latin1");
+is(latin1.encoding, "iso-8859-1");
+is(latin1.decode(new Uint8Array([0x63, 0xe9])), "cé");
+
+var dest = new Uint8Array(5);
+var result = encoder.encodeInto("a€😀", dest);
+is(result.read, 2);
+is(result.written, 4);
+is(dest[4], 0);
+
+function throwsName(f) {
+  try {
+    f();
+  } catch (e) {
+    return e.name;
+  }
+  return "nothing";
+}
+
+var fatal = new TextDecoder("utf-8", {fatal: true});
+is(fatal.fatal, true);
+is(throwsName(() => fatal.decode(new Uint8Array([0x61, 0xc3]))), "TypeError");
+is(throwsName(() => new TextDecoder("utf-16le", {fatal: true}).decode(new Uint8Array([0x00]))),
+   "TypeError");
+is(throwsName(() => new TextDecoder("klingon")), "RangeError");
+is(throwsName(() => encoder.encodeInto("a", [])), "TypeError");
+finish();
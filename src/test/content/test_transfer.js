@@ -0,0 +1,38 @@
+var buffer = new Uint8Array([1, 2, 3, 4]).buffer;
+var view = new Uint8Array(buffer);
+var clone = structuredClone({data: buffer}, {transfer: [buffer]});
+
+// The original is detached, and its views see nothing
+is(buffer.byteLength, 0);
+is(view.length, 0);
+is(view[0], undefined);
+
+// The copy has the bytes, and doesn't share them with anything
+is(clone.data.byteLength, 4);
+var moved = new Uint8Array(clone.data);
+is(moved.join(), "1,2,3,4");
+moved[0] = 9;
+is(new Uint8Array(clone.data)[0], 9);
+is(view[0], undefined);
+
+// Without a transfer list the original stays as it was
+var kept = new Uint8Array([5, 6]).buffer;
+var copy = structuredClone(kept);
+new Uint8Array(copy)[0] = 7;
+is(new Uint8Array(kept)[0], 5);
+
+function throwsName(f) {
+  try {
+    f();
+  } catch (e) {
+    return e.name;
+  }
+  return "nothing";
+}
+
+is(throwsName(() => structuredClone(buffer, {transfer: [buffer]})), "DataCloneError");
+var twice = new ArrayBuffer(8);
+is(throwsName(() => structuredClone(twice, {transfer: [twice, twice]})), "DataCloneError");
+is(twice.byteLength, 8);
+is(throwsName(() => structuredClone(1, {transfer: [view]})), "DataCloneError");
+finish();
@@ -0,0 +1,32 @@
+var constructors = [
+  [Int8Array, 1], [Uint8Array, 1], [Uint8ClampedArray, 1],
+  [Int16Array, 2], [Uint16Array, 2], [Int32Array, 4], [Uint32Array, 4],
+  [Float32Array, 4], [Float64Array, 8], [BigInt64Array, 8], [BigUint64Array, 8]
+];
+constructors.forEach(function(entry) {
+  var array = new entry[0](4);
+  is(array.length, 4);
+  is(array.BYTES_PER_ELEMENT, entry[1]);
+  is(array.buffer.byteLength, 4 * entry[1]);
+});
+
+var clamped = new Uint8ClampedArray([300, -5, 1.5]);
+is(clamped.join(), "255,0,2");
+is(new Int8Array([200])[0], -56);
+is(new BigInt64Array([-1n])[0], -1n);
+
+var buffer = new ArrayBuffer(8);
+var view = new DataView(buffer);
+view.setUint16(0, 0x1234);
+is(new Uint8Array(buffer, 0, 2).join(), "18,52");
+view.setFloat32(4, 1.5, true);
+is(new Float32Array(buffer, 4, 1)[0], 1.5);
+
+// Blobs take typed arrays and DataViews as well as ArrayBuffers, and only
+// the bytes a view covers
+var bytes = new Uint8Array([1, 2, 3, 4, 5, 6]);
+is(new Blob([bytes]).size, 6);
+is(new Blob([bytes.subarray(2)]).size, 4);
+is(new Blob([new DataView(buffer, 1, 3), buffer]).size, 11);
+
+finish();
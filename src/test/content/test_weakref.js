@@ -0,0 +1,23 @@
+var target = { name: "target" };
+var ref = new WeakRef(target);
+var cleaned = [];
+var registry = new FinalizationRegistry(function(held) {
+  cleaned.push(held);
+});
+registry.register(target, "held value");
+
+is(ref.deref() === target, true);
+target = null;
+
+// deref() keeps the target alive until the end of this task, so collect it
+// in the next one
+window.setTimeout(function() {
+  gc();
+  is(ref.deref(), undefined);
+
+  // The cleanup callback runs as a task of its own, after the collection
+  window.setTimeout(function() {
+    is(cleaned.join(), "held value");
+    finish();
+  }, 0);
+}, 0);
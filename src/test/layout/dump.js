@@ -0,0 +1,3 @@
+// Prints the page's layout for layouttest to compare with the golden file
+window.alert(window.__servoDebug.dumpLayout());
+window.close();
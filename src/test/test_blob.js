@@ -0,0 +1,18 @@
+var blob = new Blob(["hello ", "world"], {type: "Text/Plain"});
+window.alert("size: " + blob.size + ", type: " + blob.type);
+var file = new File([blob.slice(0, 5)], "hello.txt", {lastModified: 0});
+window.alert("name: " + file.name + ", size: " + file.size + ", lastModified: " + file.lastModified);
+
+var reader = new FileReader();
+reader.onloadend = function(event) {
+    window.alert("loadend: " + reader.result + " (readyState " + reader.readyState + ")");
+};
+reader.readAsText(file);
+
+var dataReader = new FileReader();
+dataReader.onload = function(event) { window.alert("data url: " + dataReader.result); };
+dataReader.readAsDataURL(blob);
+
+var data = new FormData();
+data.append("upload", blob, "greeting.txt");
+window.alert("form data file: " + data.get("upload").name);
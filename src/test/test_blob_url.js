@@ -0,0 +1,4 @@
+var blob = new Blob(["<p>from a blob</p>"], {type: "text/html"});
+var url = URL.createObjectURL(blob);
+window.alert("blob url: " + url);
+URL.revokeObjectURL(url);
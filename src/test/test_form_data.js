@@ -0,0 +1,9 @@
+var data = new FormData();
+data.append("name", "servo");
+data.append("tag", "a");
+data.append("tag", "b");
+window.alert("get: " + data.get("name") + ", getAll: " + data.getAll("tag"));
+data.set("tag", "c");
+data.delete("name");
+window.alert("has name: " + data.has("name") + ", keys: " + data.keys() +
+             ", values: " + data.values());
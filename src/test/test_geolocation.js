@@ -0,0 +1,13 @@
+function success(position) {
+  window.alert("position: " + position.coords.latitude + ", " + position.coords.longitude +
+               " (" + position.coords.accuracy + "m) at " + position.timestamp);
+}
+
+function error(e) {
+  window.alert("geolocation error " + e.code + ": " + e.message);
+}
+
+navigator.geolocation.getCurrentPosition(success, error);
+var id = navigator.geolocation.watchPosition(success, error);
+window.alert("watching: " + id);
+navigator.geolocation.clearWatch(id);
@@ -0,0 +1,13 @@
+window.onpopstate = function(e) {
+  window.alert("popstate: " + e.state + " at " + window.location.href);
+};
+
+window.alert("start: " + window.location.href);
+window.history.pushState(1, "one", "?one");
+window.history.pushState(2, "two", "?two");
+window.alert("pushed: " + window.location.href + ", length " + window.history.length);
+window.history.replaceState(3, "three", "?three");
+window.alert("replaced: " + window.location.href + ", state " + window.history.state);
+window.history.back();
+window.history.go(-1);
+window.history.forward();
@@ -0,0 +1,12 @@
+window.alert("permission: " + Notification.permission);
+window.alert("requested: " + Notification.requestPermission());
+
+var n = new Notification("Hello", {body: "from servo", tag: "greeting", vibrate: [100, 50, 100]});
+window.alert("title: " + n.title + ", body: " + n.body + ", tag: " + n.tag);
+n.onshow = function() {
+  window.alert("shown");
+  n.close();
+};
+n.onclose = function() {
+  window.alert("closed");
+};
@@ -0,0 +1,9 @@
+var div = document.documentElement.firstChild;
+var observer = new ResizeObserver(function(entries) {
+    for (var i = 0; i < entries.length; i++) {
+        var rect = entries[i].contentRect;
+        window.alert("resized: " + rect.width + "x" + rect.height +
+                     ", border box inline size: " + entries[i].borderBoxSize[0].inlineSize);
+    }
+});
+observer.observe(div, {box: "border-box"});
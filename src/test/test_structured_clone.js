@@ -0,0 +1,10 @@
+var original = {name: "servo", tags: ["a", "b"], nested: {n: 1}};
+var clone = window.structuredClone(original);
+clone.nested.n = 2;
+window.alert("original: " + original.nested.n + ", clone: " + clone.nested.n);
+
+try {
+    window.structuredClone({f: function() {}});
+} catch (e) {
+    window.alert("function: " + e.name);
+}
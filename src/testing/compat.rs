@@ -0,0 +1,247 @@
+/*!
+Runs the web-platform-tests DOM suite and reports how much of it passes.
+
+The tests are loaded from a web-platform-tests checkout, either as files or,
+with `--wpt-url`, from a `wptserve` serving that checkout so that absolute
+paths like `/resources/testharness.js` resolve. Either way the checkout's
+`resources/testharnessreport.js` has to be replaced with the one next to
+this file, which reports each result on the console; `make check-compat`
+does that in a copy of the checkout, not in the checkout itself. `--servo`
+is the binary to run the tests with, `./servo` by default.
+
+The report is JSON, with the files that fail the most tests first. Given
+the report from a previous run with `--baseline`, the runner fails if more
+than `--max-regressions` tests that passed then don't pass now.
+*/
+
+extern mod std;
+
+use std::getopts::{getopts, reqopt, optopt, opt_str, opt_maybe_str, fail_str};
+use std::json;
+use std::sort;
+use std::json::{Json, Object, List, String, Number, Boolean};
+use core::send_map::linear::LinearMap;
+
+const DEFAULT_MAX_REGRESSIONS: uint = 5;
+
+/// The results of one test file.
+struct FileResult {
+    file: ~str,
+    passing: ~[~str],
+    failing: ~[~str],
+    // The file didn't finish, so the tests it didn't get to are missing
+    crashed: bool,
+}
+
+struct CompatRunner {
+    wpt_dir: ~str,
+    wpt_url: Option<~str>,
+    servo: ~str,
+    filter: Option<~str>,
+}
+
+impl CompatRunner {
+    /// The HTML files under `dom/`, relative to the checkout, in order.
+    fn find_tests(&self) -> ~[~str] {
+        let dom_dir = Path(self.wpt_dir).push(~"dom");
+        let prefix_len = relative_prefix_len(self.wpt_dir);
+        let mut tests = ~[];
+        for os::walk_dir(&dom_dir) |path| {
+            let file = path.to_str();
+            let relative = str::slice(file, prefix_len, file.len());
+            let wanted = match self.filter {
+                Some(ref filter) => relative.contains(*filter),
+                None => true
+            };
+            if file.ends_with(".html") && wanted {
+                tests.push(move relative);
+            }
+            true
+        }
+        sort::quick_sort3(tests);
+        move tests
+    }
+
+    fn test_url(&self, file: &str) -> ~str {
+        match self.wpt_url {
+            Some(ref base) => fmt!("%s/%s", *base, file),
+            None => ~"file://" + os::make_absolute(&Path(self.wpt_dir).push_rel(&Path(file)))
+                .to_str()
+        }
+    }
+
+    fn run_test(&self, file: &str) -> FileResult {
+        let res = run::program_output(self.servo, ~[self.test_url(file)]);
+        let mut result = FileResult {
+            file: str::from_slice(file),
+            passing: ~[],
+            failing: ~[],
+            crashed: res.status != 0,
+        };
+        for str::lines(res.out).each |line| {
+            match parse_result_line(*line) {
+                Some((true, move name)) => result.passing.push(move name),
+                Some((false, move name)) => result.failing.push(move name),
+                None => ()
+            }
+        }
+        move result
+    }
+
+    fn run(&self) -> ~[FileResult] {
+        do self.find_tests().map |file| {
+            io::println(fmt!("compat: %s", *file));
+            self.run_test(*file)
+        }
+    }
+}
+
+/// Parses the lines printed by testharnessreport.js, giving whether the
+/// test passed and its name.
+fn parse_result_line(line: &str) -> Option<(bool, ~str)> {
+    let pass = "TEST-PASS | ";
+    let fail = "TEST-UNEXPECTED-FAIL | ";
+    match str::find_str(line, pass) {
+        Some(i) => return Some((true, str::slice(line, i + pass.len(), line.len()))),
+        None => ()
+    }
+    match str::find_str(line, fail) {
+        Some(i) => Some((false, str::slice(line, i + fail.len(), line.len()))),
+        None => None
+    }
+}
+
+fn strings_to_json(strings: &[~str]) -> Json {
+    List(strings.map(|s| String(copy *s)))
+}
+
+fn report_to_json(results: &[FileResult]) -> Json {
+    // The files with the most failures first
+    let mut sorted = vec::from_fn(results.len(), |i| i);
+    sort::quick_sort(sorted, |a, b| {
+        let (a, b) = (&results[*a], &results[*b]);
+        a.failing.len() > b.failing.len() ||
+            (a.failing.len() == b.failing.len() && a.file <= b.file)
+    });
+
+    let mut passed = 0, failed = 0;
+    let tests = do sorted.map |i| {
+        let result = &results[*i];
+        passed += result.passing.len();
+        failed += result.failing.len();
+        let mut obj = ~LinearMap();
+        obj.insert(~"file", String(copy result.file));
+        obj.insert(~"passed", Number(result.passing.len() as float));
+        obj.insert(~"failed", Number(result.failing.len() as float));
+        obj.insert(~"crashed", Boolean(result.crashed));
+        obj.insert(~"passing", strings_to_json(result.passing));
+        obj.insert(~"failing", strings_to_json(result.failing));
+        Object(move obj)
+    };
+
+    let mut summary = ~LinearMap();
+    summary.insert(~"files", Number(results.len() as float));
+    summary.insert(~"passed", Number(passed as float));
+    summary.insert(~"failed", Number(failed as float));
+
+    let mut report = ~LinearMap();
+    report.insert(~"summary", Object(move summary));
+    report.insert(~"tests", List(move tests));
+    Object(move report)
+}
+
+// How much of a path under `dir` to cut off to make it relative
+fn relative_prefix_len(dir: &str) -> uint {
+    if dir.ends_with("/") { dir.len() } else { dir.len() + 1 }
+}
+
+// Each passing test in a report, as "file | test name"
+fn passing_tests(report: &Json) -> ~[~str] {
+    let mut passing = ~[];
+    let tests = match *report {
+        Object(ref report) => report.find_ref(&~"tests"),
+        _ => None
+    };
+    match tests {
+        Some(&List(ref tests)) => for tests.each |test| {
+            match *test {
+                Object(ref test) => match (test.find_ref(&~"file"), test.find_ref(&~"passing")) {
+                    (Some(&String(ref file)), Some(&List(ref names))) => for names.each |name| {
+                        match *name {
+                            String(ref name) => passing.push(fmt!("%s | %s", *file, *name)),
+                            _ => ()
+                        }
+                    },
+                    _ => ()
+                },
+                _ => ()
+            }
+        },
+        _ => ()
+    }
+    move passing
+}
+
+/// The tests that pass in `baseline` but not in `current`.
+fn regressions(baseline: &Json, current: &Json) -> ~[~str] {
+    let now_passing = passing_tests(current);
+    passing_tests(baseline).filter(|test| !now_passing.contains(test))
+}
+
+fn main() {
+    let args = os::args();
+    let opts = ~[reqopt(~"wpt-dir"), optopt(~"wpt-url"), optopt(~"servo"), optopt(~"report"),
+                 optopt(~"baseline"), optopt(~"max-regressions")];
+    let matches = match getopts(args.tail(), opts) {
+      Ok(m) => m,
+      Err(f) => fail fail_str(f)
+    };
+
+    let runner = CompatRunner {
+        wpt_dir: opt_str(matches, ~"wpt-dir"),
+        wpt_url: opt_maybe_str(matches, ~"wpt-url"),
+        servo: match opt_maybe_str(matches, ~"servo") {
+            Some(move servo) => move servo,
+            None => ~"./servo"
+        },
+        filter: if matches.free.is_empty() { None } else { Some(matches.free.head()) },
+    };
+    let max_regressions = match opt_maybe_str(matches, ~"max-regressions") {
+        Some(ref n) => uint::from_str(*n).expect(~"--max-regressions needs a number"),
+        None => DEFAULT_MAX_REGRESSIONS
+    };
+
+    let report = report_to_json(runner.run());
+    let report_str = json::to_pretty_str(&report);
+    match opt_maybe_str(matches, ~"report") {
+        Some(ref path) => {
+            match io::file_writer(&Path(*path), ~[io::Create, io::Truncate]) {
+                Ok(writer) => writer.write_str(report_str),
+                Err(e) => fail fmt!("unable to write %s: %s", *path, e)
+            }
+        }
+        None => io::println(report_str)
+    }
+
+    match opt_maybe_str(matches, ~"baseline") {
+        Some(ref path) => {
+            let baseline = match io::read_whole_file_str(&Path(*path)) {
+                Ok(data) => match json::from_str(data) {
+                    Ok(move json) => move json,
+                    Err(e) => fail fmt!("%s isn't a report: %s", *path, e.msg)
+                },
+                Err(e) => fail fmt!("unable to read %s: %s", *path, e)
+            };
+            let regressed = regressions(&baseline, &report);
+            for regressed.each |test| {
+                io::println(fmt!("TEST-REGRESSION | %s", *test));
+            }
+            if regressed.len() > max_regressions {
+                io::println(fmt!("%u tests regressed, more than the %u allowed",
+                                 regressed.len(), max_regressions));
+                os::set_exit_status(1);
+            }
+        }
+        None => ()
+    }
+}
@@ -0,0 +1,12 @@
+// Replaces resources/testharnessreport.js in a web-platform-tests checkout
+// so that compat.rs can read the results from the console
+add_completion_callback(function(tests, harness_status) {
+    for (var i = 0; i < tests.length; i++) {
+        var prefix = tests[i].status == tests[i].PASS ? "TEST-PASS" : "TEST-UNEXPECTED-FAIL";
+        window.alert(prefix + " | " + tests[i].name);
+    }
+    if (harness_status.status != harness_status.OK) {
+        window.alert("TEST-UNEXPECTED-FAIL | harness: " + harness_status.message);
+    }
+    window.close();
+});